@@ -0,0 +1,247 @@
+// Copyright (c) 2016-2017 Bruce Stenning. All rights reserved.
+//
+// Redistribution and use in source and binary forms, with or without
+// modification, are permitted provided that the following conditions
+// are met:
+//
+// 1. Redistributions of source code must retain the above copyright
+//    notice, this list of conditions and the following disclaimer.
+//
+// 2. Redistributions in binary form must reproduce the above copyright
+//    notice, this list of conditions and the following disclaimer in the
+//    documentation and/or other materials provided with the distribution.
+//
+// 3. Neither the name of the copyright holder nor the names of its
+//    contributors may be used to endorse or promote products derived
+//    from this software without specific prior written permission.
+//
+// THIS SOFTWARE IS PROVIDED BY THE COPYRIGHT HOLDERS AND CONTRIBUTORS
+// AS IS" AND ANY EXPRESS OR IMPLIED WARRANTIES, INCLUDING, BUT NOT
+// LIMITED TO, THE IMPLIED WARRANTIES OF MERCHANTABILITY AND FITNESS
+// FOR A PARTICULAR PURPOSE ARE DISCLAIMED. IN NO EVENT SHALL THE
+// COPYRIGHT HOLDER OR CONTRIBUTORS BE LIABLE FOR ANY DIRECT, INDIRECT,
+// INCIDENTAL, SPECIAL, EXEMPLARY, OR CONSEQUENTIAL DAMAGES (INCLUDING,
+// BUT NOT LIMITED TO, PROCUREMENT OF SUBSTITUTE GOODS OR SERVICES; LOSS
+// OF USE, DATA, OR PROFITS; OR BUSINESS INTERRUPTION) HOWEVER CAUSED
+// AND ON ANY THEORY OF LIABILITY, WHETHER IN CONTRACT, STRICT LIABILITY,
+// OR TORT (INCLUDING NEGLIGENCE OR OTHERWISE) ARISING IN ANY WAY OUT OF
+// THE USE OF THIS SOFTWARE, EVEN IF ADVISED OF THE POSSIBILITY OF SUCH
+// DAMAGE.
+
+#![allow(dead_code)]
+
+use algebra::matrix::Mat4;
+use algebra::quaternion::Quaternion;
+use algebra::vector::Vec3;
+
+/// The semi-major axis of the WGS84 ellipsoid in metres
+pub const WGS84_SEMI_MAJOR_AXIS: f64 = 6378137.0;
+
+/// The flattening of the WGS84 ellipsoid
+pub const WGS84_FLATTENING: f64 = 1.0 / 298.257223563;
+
+/// Return the square of the first eccentricity of the WGS84 ellipsoid
+fn wgs84_eccentricity_squared() -> f64 {
+    WGS84_FLATTENING * (2.0 - WGS84_FLATTENING)
+}
+
+/// Convert a geodetic position on the WGS84 ellipsoid to Earth-centred, Earth-fixed coordinates
+///
+/// latitude: The geodetic latitude in degrees
+/// longitude: The longitude in degrees
+/// height: The height above the ellipsoid in metres
+pub fn geodetic_to_ecef(latitude: f64, longitude: f64, height: f64) -> Vec3<f64> {
+    let (sin_lat, cos_lat) = latitude.to_radians().sin_cos();
+    let (sin_lon, cos_lon) = longitude.to_radians().sin_cos();
+    let e2 = wgs84_eccentricity_squared();
+
+    // The radius of curvature in the prime vertical
+    let n = WGS84_SEMI_MAJOR_AXIS / (1.0 - e2 * sin_lat * sin_lat).sqrt();
+
+    Vec3 {
+        x: (n + height) * cos_lat * cos_lon,
+        y: (n + height) * cos_lat * sin_lon,
+        z: (n * (1.0 - e2) + height) * sin_lat,
+    }
+}
+
+/// Convert Earth-centred, Earth-fixed coordinates to a geodetic position on the WGS84 ellipsoid
+///
+/// Returns the latitude and longitude in degrees and the height above the ellipsoid in metres.
+/// The latitude is refined iteratively, which converges to well under a millimetre near the
+/// surface.
+///
+/// position: The ECEF position in metres
+pub fn ecef_to_geodetic(position: &Vec3<f64>) -> (f64, f64, f64) {
+    let e2 = wgs84_eccentricity_squared();
+    let p = (position.x * position.x + position.y * position.y).sqrt();
+    let longitude = position.y.atan2(position.x);
+
+    let mut latitude = position.z.atan2(p * (1.0 - e2));
+    let mut height = 0.0;
+    for _ in 0..8 {
+        let sin_lat = latitude.sin();
+        let n = WGS84_SEMI_MAJOR_AXIS / (1.0 - e2 * sin_lat * sin_lat).sqrt();
+
+        // Near the poles p vanishes, so the height is measured along z instead
+        height = if latitude.cos().abs() > 1.0e-9 {
+            p / latitude.cos() - n
+        } else {
+            position.z.abs() - n * (1.0 - e2)
+        };
+        latitude = position.z.atan2(p * (1.0 - e2 * n / (n + height)));
+    }
+
+    (latitude.to_degrees(), longitude.to_degrees(), height)
+}
+
+/// A local east, north, up tangent frame at a point on the ellipsoid
+#[derive(Clone, Copy)]
+pub struct EnuFrame {
+    pub origin: Vec3<f64>,
+    pub east: Vec3<f64>,
+    pub north: Vec3<f64>,
+    pub up: Vec3<f64>,
+}
+
+impl EnuFrame {
+    /// Construct the tangent frame at a geodetic position
+    ///
+    /// latitude: The geodetic latitude in degrees
+    /// longitude: The longitude in degrees
+    /// height: The height above the ellipsoid in metres
+    pub fn new(latitude: f64, longitude: f64, height: f64) -> EnuFrame {
+        let (sin_lat, cos_lat) = latitude.to_radians().sin_cos();
+        let (sin_lon, cos_lon) = longitude.to_radians().sin_cos();
+
+        EnuFrame {
+            origin: geodetic_to_ecef(latitude, longitude, height),
+            east: Vec3 {
+                x: -sin_lon,
+                y: cos_lon,
+                z: 0.0,
+            },
+            north: Vec3 {
+                x: -sin_lat * cos_lon,
+                y: -sin_lat * sin_lon,
+                z: cos_lat,
+            },
+            up: Vec3 {
+                x: cos_lat * cos_lon,
+                y: cos_lat * sin_lon,
+                z: sin_lat,
+            },
+        }
+    }
+
+    /// Convert an ECEF position to east, north and up offsets from the origin of the frame
+    ///
+    /// position: The ECEF position in metres
+    pub fn ecef_to_enu(&self, position: &Vec3<f64>) -> Vec3<f64> {
+        let offset = position - &self.origin;
+
+        Vec3 {
+            x: offset.dot(&self.east),
+            y: offset.dot(&self.north),
+            z: offset.dot(&self.up),
+        }
+    }
+
+    /// Convert east, north and up offsets from the origin of the frame to an ECEF position
+    ///
+    /// enu: The offsets in metres
+    pub fn enu_to_ecef(&self, enu: &Vec3<f64>) -> Vec3<f64> {
+        Vec3 {
+            x: self.origin.x + enu.x * self.east.x + enu.y * self.north.x + enu.z * self.up.x,
+            y: self.origin.y + enu.x * self.east.y + enu.y * self.north.y + enu.z * self.up.y,
+            z: self.origin.z + enu.x * self.east.z + enu.y * self.north.z + enu.z * self.up.z,
+        }
+    }
+}
+
+/// The double-precision world-to-camera pre-transform for camera-relative rendering
+///
+/// Single-precision world coordinates lose centimetre precision a few hundred kilometres from
+/// the origin, far short of planetary coordinates.  Instead, geometry is split into chunks, each
+/// with a double-precision origin and single-precision residuals relative to it.  Every frame the
+/// offset from the camera to each chunk origin is computed here in double precision, and only the
+/// small result is rounded to single precision for the chunk's model matrix, while the view
+/// matrix places the camera at the origin.
+pub struct CameraRelativeTransform {
+    camera_position: Vec3<f64>,
+}
+
+impl CameraRelativeTransform {
+    /// Construct a pre-transform for a camera at the specified position
+    ///
+    /// camera_position: The world position of the camera
+    pub fn new(camera_position: Vec3<f64>) -> CameraRelativeTransform {
+        CameraRelativeTransform { camera_position: camera_position }
+    }
+
+    /// Move the camera, which is done once a frame before the chunks are transformed
+    ///
+    /// camera_position: The world position of the camera
+    pub fn set_camera_position(&mut self, camera_position: Vec3<f64>) {
+        self.camera_position = camera_position;
+    }
+
+    /// Return the world position of the camera
+    pub fn get_camera_position(&self) -> Vec3<f64> {
+        self.camera_position
+    }
+
+    /// Return the offset from the camera to a chunk origin, rounded to single precision
+    ///
+    /// chunk_origin: The world position of the chunk's origin
+    pub fn chunk_offset(&self, chunk_origin: &Vec3<f64>) -> Vec3<f32> {
+        let offset = chunk_origin - &self.camera_position;
+
+        Vec3 {
+            x: offset.x as f32,
+            y: offset.y as f32,
+            z: offset.z as f32,
+        }
+    }
+
+    /// Return the model matrix of a chunk, translating its residuals to be relative to the camera
+    ///
+    /// chunk_origin: The world position of the chunk's origin
+    pub fn chunk_matrix(&self, chunk_origin: &Vec3<f64>) -> Mat4<f32> {
+        let offset = self.chunk_offset(chunk_origin);
+        Mat4::translate(offset.x, offset.y, offset.z)
+    }
+
+    /// Return the view matrix for the camera's orientation, with the camera at the origin
+    ///
+    /// orientation: The view orientation, as a quaternion
+    pub fn view_matrix(&self, orientation: &Quaternion<f32>) -> Mat4<f32> {
+        let origin = Vec3 {
+            x: 0.0f32,
+            y: 0.0f32,
+            z: 0.0f32,
+        };
+
+        Mat4::modelview_quaternion(&origin, orientation)
+    }
+
+    /// Convert world positions to single-precision residuals relative to a chunk origin
+    ///
+    /// This is done once when a chunk is built, not every frame.  The chunk should be small
+    /// enough, a few kilometres across, for its residuals to keep centimetre precision.
+    ///
+    /// chunk_origin: The world position of the chunk's origin
+    /// positions: The world positions of the chunk's vertices
+    pub fn residuals(chunk_origin: &Vec3<f64>, positions: &[Vec3<f64>]) -> Vec<Vec3<f32>> {
+        positions.iter()
+            .map(|position| {
+                let residual = position - chunk_origin;
+                Vec3 {
+                    x: residual.x as f32,
+                    y: residual.y as f32,
+                    z: residual.z as f32,
+                }
+            })
+            .collect()
+    }
+}
@@ -47,6 +47,51 @@ pub struct Mat4<T: Copy> {
     pub m: [[T; 4]; 4],
 }
 
+/// The clip space and normalised device coordinate conventions of the backends
+///
+/// OpenGL has y pointing up the screen and depth from -1 to 1.  Vulkan has y pointing down the
+/// screen and depth from 0 to 1, unless the viewport is given a negative height, which points y
+/// back up while keeping Vulkan's depth range.
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub enum ClipSpaceConvention {
+    OpenGl,
+    Vulkan,
+    VulkanFlippedViewport,
+}
+
+impl ClipSpaceConvention {
+    /// Return whether y must be negated in clip space for the image to appear the right way up
+    pub fn flips_y(&self) -> bool {
+        *self == ClipSpaceConvention::Vulkan
+    }
+
+    /// Return whether clip space depth runs from 0 to 1 rather than from -1 to 1
+    pub fn half_z(&self) -> bool {
+        *self != ClipSpaceConvention::OpenGl
+    }
+
+    /// Map normalised device coordinates to window coordinates as the rasteriser does
+    ///
+    /// The window coordinates have their origin at the top left of the displayed image, with
+    /// depth from 0 to 1, so the same point gives the same result under every convention.
+    ///
+    /// ndc: The normalised device coordinates
+    /// width: The width of the viewport
+    /// height: The height of the viewport
+    pub fn ndc_to_window(&self, ndc: &Vec3<f32>, width: f32, height: f32) -> Vec3<f32> {
+        let x = (ndc.x + 1.0f32) * 0.5f32 * width;
+        let (y, z) = match *self {
+            // The default framebuffer has its origin at the bottom left
+            ClipSpaceConvention::OpenGl => (height - (ndc.y + 1.0f32) * 0.5f32 * height, (ndc.z + 1.0f32) * 0.5f32),
+            ClipSpaceConvention::Vulkan => ((ndc.y + 1.0f32) * 0.5f32 * height, ndc.z),
+            // The viewport has y = height and a height of -height
+            ClipSpaceConvention::VulkanFlippedViewport => (height - (ndc.y + 1.0f32) * 0.5f32 * height, ndc.z),
+        };
+
+        Vec3 { x: x, y: y, z: z }
+    }
+}
+
 impl<T: Add<T, Output = T> + AddAssign<T> + Mul<T, Output = T> + Zero + Copy> Mul for Mat4<T> {
     type Output = Mat4<T>;
 
@@ -134,6 +179,21 @@ impl<T: Zero + One + Copy> Mat4<T> {
     }
 }
 
+impl<T: Copy> Mat4<T> {
+    /// Return the transpose of the matrix
+    pub fn transpose(&self) -> Self {
+        let mut matrix = *self;
+
+        for col in 0..4 {
+            for row in 0..4 {
+                matrix.m[col][row] = self.m[row][col];
+            }
+        }
+
+        matrix
+    }
+}
+
 impl Mat4<f32> {
     /// 4x4 matrix by 4-component vector multiply
     ///
@@ -363,6 +423,174 @@ impl Mat4<f32> {
         }
     }
 
+    /// Construct a new projection matrix for a backend's clip space convention
+    ///
+    /// fovy: The field-of-view angle, in degrees, of the vertical axis of the viewport
+    /// aspect: The aspect ratio (width divided by height) of the viewport
+    /// znear: The distance from the camera to the near clip plane
+    /// zfar: The distance from the camera to the far clip plane
+    /// convention: The clip space convention of the backend, from Renderer::clip_space_convention
+    pub fn projection_for(fovy: f32, aspect: f32, znear: f32, zfar: f32, convention: ClipSpaceConvention) -> Self {
+        Mat4::projection(fovy,
+                         aspect,
+                         znear,
+                         zfar,
+                         convention.flips_y(),
+                         convention.half_z())
+    }
+
+    /// Construct a new perspective projection matrix in OpenGL's clip space convention
+    ///
+    /// This is the matrix of gluPerspective, scaling by the cotangent of half the field of view;
+    /// premultiply it by clip_space_correction for other backends.  As with projection, znear
+    /// and zfar are positive distances down the negative Z axis.
+    ///
+    /// fovy: The field-of-view angle, in degrees, of the vertical axis of the viewport
+    /// aspect: The aspect ratio (width divided by height) of the viewport
+    /// znear: The distance from the camera to the near clip plane
+    /// zfar: The distance from the camera to the far clip plane
+    pub fn perspective(fovy: f32, aspect: f32, znear: f32, zfar: f32) -> Self {
+        let mut matrix = Mat4::new();
+
+        let f = 1.0f32 / degrees_to_radians(fovy / 2.0f32).tan();
+
+        matrix.m[0][0] = f / aspect;
+        matrix.m[1][1] = f;
+        matrix.m[2][2] = (zfar + znear) / (znear - zfar);
+        matrix.m[3][2] = (2.0 * zfar * znear) / (znear - zfar);
+        matrix.m[2][3] = -1.0f32;
+
+        matrix
+    }
+
+    /// Construct a new orthographic projection matrix in OpenGL's clip space convention
+    ///
+    /// This is the matrix of glOrtho; premultiply it by clip_space_correction for other backends.
+    ///
+    /// left: The X coordinate of the left clip plane
+    /// right: The X coordinate of the right clip plane
+    /// bottom: The Y coordinate of the bottom clip plane
+    /// top: The Y coordinate of the top clip plane
+    /// znear: The distance from the camera to the near clip plane
+    /// zfar: The distance from the camera to the far clip plane
+    pub fn ortho(left: f32, right: f32, bottom: f32, top: f32, znear: f32, zfar: f32) -> Self {
+        let mut matrix = Mat4::newidentity();
+
+        matrix.m[0][0] = 2.0f32 / (right - left);
+        matrix.m[1][1] = 2.0f32 / (top - bottom);
+        matrix.m[2][2] = -2.0f32 / (zfar - znear);
+        matrix.m[3][0] = -(right + left) / (right - left);
+        matrix.m[3][1] = -(top + bottom) / (top - bottom);
+        matrix.m[3][2] = -(zfar + znear) / (zfar - znear);
+
+        matrix
+    }
+
+    /// Construct a new view matrix for a camera at a position looking at a target
+    ///
+    /// This is the matrix of gluLookAt.  The up vector need not be unit length or orthogonal to
+    /// the view direction, but must not be parallel to it.
+    ///
+    /// eye: The position of the camera
+    /// target: The point the camera looks at
+    /// up: The direction up from the camera
+    pub fn look_at(eye: &Vec3<f32>, target: &Vec3<f32>, up: &Vec3<f32>) -> Self {
+        let forward = (*target - *eye).normalise();
+        let right = Vec3::cross(&forward, up).normalise();
+        let true_up = Vec3::cross(&right, &forward);
+
+        // The camera looks down its negative Z axis
+        let back = Vec3 {
+            x: -forward.x,
+            y: -forward.y,
+            z: -forward.z,
+        };
+
+        Mat4::modelview(eye, &back, &right, &true_up)
+    }
+
+    /// Construct a new transformation matrix from a translation, rotation and scale
+    ///
+    /// The scale is applied first and the translation last, so this is the inverse of decompose.
+    ///
+    /// translation: The translation
+    /// rotation: The rotation, as a unit quaternion
+    /// scale: The scale along each axis
+    pub fn compose(translation: &Vec3<f32>, rotation: &Quaternion<f32>, scale: &Vec3<f32>) -> Self {
+        let mut matrix = rotation.to_mat4();
+
+        for row in 0..3 {
+            matrix.m[0][row] *= scale.x;
+            matrix.m[1][row] *= scale.y;
+            matrix.m[2][row] *= scale.z;
+        }
+        matrix.m[3][0] = translation.x;
+        matrix.m[3][1] = translation.y;
+        matrix.m[3][2] = translation.z;
+
+        matrix
+    }
+
+    /// Split an affine transformation matrix into its translation, rotation and scale
+    ///
+    /// The matrix must have no shear or projection.  A reflection is returned as a negative
+    /// scale along X.  Returns None if the matrix has no scale along some axis, as the rotation
+    /// is then undefined.
+    pub fn decompose(&self) -> Option<(Vec3<f32>, Quaternion<f32>, Vec3<f32>)> {
+        let translation = Vec3 {
+            x: self.m[3][0],
+            y: self.m[3][1],
+            z: self.m[3][2],
+        };
+
+        let column = |c: usize| {
+            Vec3 {
+                x: self.m[c][0],
+                y: self.m[c][1],
+                z: self.m[c][2],
+            }
+        };
+        let (x_axis, y_axis, z_axis) = (column(0), column(1), column(2));
+
+        let mut scale = Vec3 {
+            x: x_axis.magnitude(),
+            y: y_axis.magnitude(),
+            z: z_axis.magnitude(),
+        };
+        if scale.x < f32::EPSILON || scale.y < f32::EPSILON || scale.z < f32::EPSILON {
+            return None;
+        }
+        if Vec3::dot(&Vec3::cross(&x_axis, &y_axis), &z_axis) < 0.0f32 {
+            scale.x = -scale.x;
+        }
+
+        let mut rotation = Mat4::newidentity();
+        for row in 0..3 {
+            rotation.m[0][row] = self.m[0][row] / scale.x;
+            rotation.m[1][row] = self.m[1][row] / scale.y;
+            rotation.m[2][row] = self.m[2][row] / scale.z;
+        }
+
+        Some((translation, Quaternion::from_mat4(&rotation), scale))
+    }
+
+    /// Construct a matrix converting OpenGL clip coordinates to those of another convention
+    ///
+    /// Premultiplying an OpenGL-style projection matrix by this adapts it to the backend.
+    ///
+    /// convention: The clip space convention to convert to
+    pub fn clip_space_correction(convention: ClipSpaceConvention) -> Self {
+        let mut matrix = Mat4::newidentity();
+        if convention.flips_y() {
+            matrix.m[1][1] = -1.0f32;
+        }
+        if convention.half_z() {
+            matrix.m[2][2] = 0.5f32;
+            matrix.m[3][2] = 0.5f32;
+        }
+        matrix
+    }
+
     /// Construct a new model view matrix from a set of unit basis vectors and a position vector
     ///
     /// The basis vectors must be unit length and mutually orthogonal.
@@ -429,6 +657,49 @@ impl Mat4<f32> {
 
         basis * translation
     }
+
+    /// Return the inverse of the matrix, or None if it is singular
+    ///
+    /// This is a Gauss-Jordan elimination with partial pivoting.  Since the inverse of the
+    /// transpose is the transpose of the inverse, it works directly on the column-major storage.
+    pub fn inverse(&self) -> Option<Mat4<f32>> {
+        let mut a = self.m;
+        let mut output = Mat4::newidentity();
+
+        for column in 0..4 {
+            let mut pivot = column;
+            for row in column + 1..4 {
+                if a[row][column].abs() > a[pivot][column].abs() {
+                    pivot = row;
+                }
+            }
+
+            if a[pivot][column].abs() < ::std::f32::EPSILON {
+                return None;
+            }
+
+            a.swap(column, pivot);
+            output.m.swap(column, pivot);
+
+            let scale = 1.0f32 / a[column][column];
+            for x in 0..4 {
+                a[column][x] *= scale;
+                output.m[column][x] *= scale;
+            }
+
+            for row in 0..4 {
+                if row != column {
+                    let factor = a[row][column];
+                    for x in 0..4 {
+                        a[row][x] -= factor * a[column][x];
+                        output.m[row][x] -= factor * output.m[column][x];
+                    }
+                }
+            }
+        }
+
+        Some(output)
+    }
 }
 
 /// How to display a 4x4-component matrix
@@ -28,6 +28,7 @@
 // THE USE OF THIS SOFTWARE, EVEN IF ADVISED OF THE POSSIBILITY OF SUCH
 // DAMAGE.
 
+pub mod geospatial;
 pub mod matrix;
 pub mod quaternion;
 pub mod vector;
@@ -36,6 +36,7 @@ use std::mem;
 use std::ops::*;
 use num::*;
 
+use algebra::matrix::Mat4;
 use algebra::vector::Vec3;
 use misc::conversions::degrees_to_radians;
 
@@ -191,6 +192,144 @@ impl Quaternion<f32> {
     pub fn rotate(vector: &Vec3<f32>, rotation: &Quaternion<f32>) -> Vec3<f32> {
         rotation.multiply(&vector.to_quaternion()).multiply(&rotation.conjugate()).vector()
     }
+
+    /// Calculate the dot product of two quaternions
+    ///
+    /// other: The RHS of the product
+    pub fn dot(&self, other: &Quaternion<f32>) -> f32 {
+        self.x * other.x + self.y * other.y + self.z * other.z + self.w * other.w
+    }
+
+    /// Calculate the magnitude of a quaternion
+    pub fn magnitude(&self) -> f32 {
+        self.dot(self).sqrt()
+    }
+
+    /// Return the quaternion scaled to unit length, or the identity if it has no length
+    pub fn normalise(&self) -> Quaternion<f32> {
+        let magnitude = self.magnitude();
+        if magnitude < f32::EPSILON {
+            return Quaternion::identity();
+        }
+
+        Quaternion::<f32> {
+            x: self.x / magnitude,
+            y: self.y / magnitude,
+            z: self.z / magnitude,
+            w: self.w / magnitude,
+        }
+    }
+
+    /// Spherically interpolate between two unit quaternions
+    ///
+    /// The interpolation takes the shorter way round, and falls back to a normalised linear
+    /// interpolation where the quaternions are too close for the angle between them to be
+    /// calculated accurately.
+    ///
+    /// a: The rotation at t = 0
+    /// b: The rotation at t = 1
+    /// t: The interpolation factor
+    pub fn slerp(a: &Quaternion<f32>, b: &Quaternion<f32>, t: f32) -> Quaternion<f32> {
+        // q and -q are the same rotation, so flip b onto a's hemisphere
+        let mut cos_theta = a.dot(b);
+        let b = if cos_theta < 0.0f32 {
+            cos_theta = -cos_theta;
+            Quaternion::<f32> {
+                x: -b.x,
+                y: -b.y,
+                z: -b.z,
+                w: -b.w,
+            }
+        } else {
+            *b
+        };
+
+        let (wa, wb) = if cos_theta > 0.9995f32 {
+            (1.0f32 - t, t)
+        } else {
+            let theta = cos_theta.acos();
+            let sin_theta = theta.sin();
+            (((1.0f32 - t) * theta).sin() / sin_theta, (t * theta).sin() / sin_theta)
+        };
+
+        let blended = Quaternion::<f32> {
+            x: wa * a.x + wb * b.x,
+            y: wa * a.y + wb * b.y,
+            z: wa * a.z + wb * b.z,
+            w: wa * a.w + wb * b.w,
+        };
+
+        blended.normalise()
+    }
+
+    /// Convert a unit quaternion to a rotation matrix
+    ///
+    /// The matrix rotates vectors as Quaternion::rotate does.
+    pub fn to_mat4(&self) -> Mat4<f32> {
+        let (x, y, z, w) = (self.x, self.y, self.z, self.w);
+        let mut matrix = Mat4::newidentity();
+
+        matrix.m[0][0] = 1.0f32 - 2.0f32 * (y * y + z * z);
+        matrix.m[0][1] = 2.0f32 * (x * y + w * z);
+        matrix.m[0][2] = 2.0f32 * (x * z - w * y);
+
+        matrix.m[1][0] = 2.0f32 * (x * y - w * z);
+        matrix.m[1][1] = 1.0f32 - 2.0f32 * (x * x + z * z);
+        matrix.m[1][2] = 2.0f32 * (y * z + w * x);
+
+        matrix.m[2][0] = 2.0f32 * (x * z + w * y);
+        matrix.m[2][1] = 2.0f32 * (y * z - w * x);
+        matrix.m[2][2] = 1.0f32 - 2.0f32 * (x * x + y * y);
+
+        matrix
+    }
+
+    /// Convert the rotation held in the upper 3x3 of a matrix to a unit quaternion
+    ///
+    /// The upper 3x3 must be orthonormal, with no scale or shear.  The largest component is
+    /// found first, so that it is never divided by a value near zero.
+    ///
+    /// matrix: The rotation matrix
+    pub fn from_mat4(matrix: &Mat4<f32>) -> Quaternion<f32> {
+        let m = &matrix.m;
+        let trace = m[0][0] + m[1][1] + m[2][2];
+
+        let quaternion = if trace > 0.0f32 {
+            let s = (trace + 1.0f32).sqrt() * 2.0f32; // 4w
+            Quaternion::<f32> {
+                x: (m[1][2] - m[2][1]) / s,
+                y: (m[2][0] - m[0][2]) / s,
+                z: (m[0][1] - m[1][0]) / s,
+                w: 0.25f32 * s,
+            }
+        } else if m[0][0] > m[1][1] && m[0][0] > m[2][2] {
+            let s = (1.0f32 + m[0][0] - m[1][1] - m[2][2]).sqrt() * 2.0f32; // 4x
+            Quaternion::<f32> {
+                x: 0.25f32 * s,
+                y: (m[1][0] + m[0][1]) / s,
+                z: (m[2][0] + m[0][2]) / s,
+                w: (m[1][2] - m[2][1]) / s,
+            }
+        } else if m[1][1] > m[2][2] {
+            let s = (1.0f32 + m[1][1] - m[0][0] - m[2][2]).sqrt() * 2.0f32; // 4y
+            Quaternion::<f32> {
+                x: (m[1][0] + m[0][1]) / s,
+                y: 0.25f32 * s,
+                z: (m[2][1] + m[1][2]) / s,
+                w: (m[2][0] - m[0][2]) / s,
+            }
+        } else {
+            let s = (1.0f32 + m[2][2] - m[0][0] - m[1][1]).sqrt() * 2.0f32; // 4z
+            Quaternion::<f32> {
+                x: (m[2][0] + m[0][2]) / s,
+                y: (m[2][1] + m[1][2]) / s,
+                z: 0.25f32 * s,
+                w: (m[0][1] - m[1][0]) / s,
+            }
+        };
+
+        quaternion.normalise()
+    }
 }
 
 /// Equivalence operator for quaternions
@@ -0,0 +1,373 @@
+// Copyright (c) 2016-2017 Bruce Stenning. All rights reserved.
+//
+// Redistribution and use in source and binary forms, with or without
+// modification, are permitted provided that the following conditions
+// are met:
+//
+// 1. Redistributions of source code must retain the above copyright
+//    notice, this list of conditions and the following disclaimer.
+//
+// 2. Redistributions in binary form must reproduce the above copyright
+//    notice, this list of conditions and the following disclaimer in the
+//    documentation and/or other materials provided with the distribution.
+//
+// 3. Neither the name of the copyright holder nor the names of its
+//    contributors may be used to endorse or promote products derived
+//    from this software without specific prior written permission.
+//
+// THIS SOFTWARE IS PROVIDED BY THE COPYRIGHT HOLDERS AND CONTRIBUTORS
+// AS IS" AND ANY EXPRESS OR IMPLIED WARRANTIES, INCLUDING, BUT NOT
+// LIMITED TO, THE IMPLIED WARRANTIES OF MERCHANTABILITY AND FITNESS
+// FOR A PARTICULAR PURPOSE ARE DISCLAIMED. IN NO EVENT SHALL THE
+// COPYRIGHT HOLDER OR CONTRIBUTORS BE LIABLE FOR ANY DIRECT, INDIRECT,
+// INCIDENTAL, SPECIAL, EXEMPLARY, OR CONSEQUENTIAL DAMAGES (INCLUDING,
+// BUT NOT LIMITED TO, PROCUREMENT OF SUBSTITUTE GOODS OR SERVICES; LOSS
+// OF USE, DATA, OR PROFITS; OR BUSINESS INTERRUPTION) HOWEVER CAUSED
+// AND ON ANY THEORY OF LIABILITY, WHETHER IN CONTRACT, STRICT LIABILITY,
+// OR TORT (INCLUDING NEGLIGENCE OR OTHERWISE) ARISING IN ANY WAY OUT OF
+// THE USE OF THIS SOFTWARE, EVEN IF ADVISED OF THE POSSIBILITY OF SUCH
+// DAMAGE.
+
+use std::collections::HashMap;
+
+use graphics::renderer::*;
+use graphics::texture::*;
+use graphics::texturegl::*;
+use graphics::texturevk::*;
+
+/// The default width and height of an atlas page in texels
+pub const DEFAULT_ATLAS_PAGE_SIZE: u32 = 2048;
+
+/// The default gutter around each image, filled by extending the image's edge texels so that
+/// bilinear filtering does not sample from neighbouring images
+pub const DEFAULT_ATLAS_PADDING: u32 = 1;
+
+// RGBA, one unsigned byte per channel
+const BYTES_PER_TEXEL: usize = 4;
+
+/// The location of an image within an atlas
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub struct AtlasRect {
+    /// The page holding the image
+    pub page: usize,
+    /// The texel position and size of the image within the page, excluding the gutter
+    pub x: u32,
+    pub y: u32,
+    pub width: u32,
+    pub height: u32,
+}
+
+impl AtlasRect {
+    /// Return the normalised texture coordinates of the image as [u, v, width, height]
+    ///
+    /// page_size: The width and height of the atlas pages
+    pub fn uv(&self, page_size: u32) -> [f32; 4] {
+        let scale = 1.0f32 / page_size as f32;
+        [self.x as f32 * scale, self.y as f32 * scale, self.width as f32 * scale, self.height as f32 * scale]
+    }
+
+    /// Return whether two rectangles on the same page overlap
+    ///
+    /// other: The rectangle to test against
+    pub fn overlaps(&self, other: &AtlasRect) -> bool {
+        self.page == other.page && self.x < other.x + other.width && other.x < self.x + self.width &&
+        self.y < other.y + other.height && other.y < self.y + self.height
+    }
+}
+
+struct Shelf {
+    y: u32,
+    height: u32,
+    used_width: u32,
+}
+
+/// A shelf rectangle packer
+///
+/// Rectangles are placed left to right along horizontal shelves.  Each rectangle goes on the
+/// shortest open shelf it fits on, and a new shelf is opened beneath the others when none fit.
+/// Nothing is ever moved, so rectangles can be added at any time without disturbing earlier ones.
+pub struct ShelfPacker {
+    width: u32,
+    height: u32,
+    shelves: Vec<Shelf>,
+}
+
+impl ShelfPacker {
+    /// Create a new, empty packer
+    ///
+    /// width: The width of the area to pack into
+    /// height: The height of the area to pack into
+    pub fn new(width: u32, height: u32) -> ShelfPacker {
+        ShelfPacker {
+            width: width,
+            height: height,
+            shelves: vec![],
+        }
+    }
+
+    /// Find a place for a rectangle, returning its position or None if the area is too full
+    ///
+    /// width: The width of the rectangle
+    /// height: The height of the rectangle
+    pub fn pack(&mut self, width: u32, height: u32) -> Option<(u32, u32)> {
+        if width > self.width || height > self.height {
+            return None;
+        }
+
+        let mut best: Option<usize> = None;
+        for (i, shelf) in self.shelves.iter().enumerate() {
+            if shelf.height >= height && self.width - shelf.used_width >= width {
+                match best {
+                    Some(b) if self.shelves[b].height <= shelf.height => {}
+                    _ => best = Some(i),
+                }
+            }
+        }
+
+        if let Some(b) = best {
+            let shelf = &mut self.shelves[b];
+            let x = shelf.used_width;
+            shelf.used_width += width;
+            return Some((x, shelf.y));
+        }
+
+        let top = match self.shelves.last() {
+            Some(shelf) => shelf.y + shelf.height,
+            None => 0,
+        };
+        if self.height - top < height {
+            return None;
+        }
+
+        self.shelves.push(Shelf {
+            y: top,
+            height: height,
+            used_width: width,
+        });
+        Some((0, top))
+    }
+}
+
+struct AtlasPage {
+    packer: ShelfPacker,
+    data: Vec<u8>,
+    dirty: bool,
+}
+
+/// Packs many small RGBA images into as few large textures as possible
+///
+/// Sprites, UI elements and glyphs drawn from one atlas page share a single texture bind.  Images
+/// may be added at any time; only the pages they land on are regenerated by update_textures.
+pub struct TextureAtlas {
+    page_size: u32,
+    padding: u32,
+    pages: Vec<AtlasPage>,
+    entries: HashMap<String, AtlasRect>,
+}
+
+impl TextureAtlas {
+    /// Create a new, empty atlas
+    ///
+    /// page_size: The width and height of each page in texels
+    /// padding: The gutter around each image in texels
+    pub fn new(page_size: u32, padding: u32) -> TextureAtlas {
+        TextureAtlas {
+            page_size: page_size,
+            padding: padding,
+            pages: vec![],
+            entries: HashMap::new(),
+        }
+    }
+
+    /// Return the width and height of each page in texels
+    pub fn get_page_size(&self) -> u32 {
+        self.page_size
+    }
+
+    /// Return the number of pages
+    pub fn page_count(&self) -> usize {
+        self.pages.len()
+    }
+
+    /// Return the RGBA pixel data of a page
+    ///
+    /// page: The index of the page
+    pub fn get_page_data(&self, page: usize) -> &Vec<u8> {
+        &self.pages[page].data
+    }
+
+    /// Return the location of a named image
+    ///
+    /// name: The name the image was added under
+    pub fn get(&self, name: &str) -> Option<&AtlasRect> {
+        self.entries.get(name)
+    }
+
+    /// Return the normalised texture coordinates of a named image as [u, v, width, height]
+    ///
+    /// name: The name the image was added under
+    pub fn get_uv(&self, name: &str) -> Option<[f32; 4]> {
+        self.entries.get(name).map(|r| r.uv(self.page_size))
+    }
+
+    /// Return the indices of the pages changed since their textures were last updated
+    pub fn dirty_pages(&self) -> Vec<usize> {
+        (0..self.pages.len()).filter(|&p| self.pages[p].dirty).collect()
+    }
+
+    /// Add an image to the atlas, returning where it was placed
+    ///
+    /// An image added again under the same name with the same size is overwritten in place,
+    /// otherwise it is packed afresh.  Returns None if the image is empty or does not fit on a page.
+    ///
+    /// name: The name to look the image up by
+    /// width: The image width
+    /// height: The image height
+    /// data: The RGBA pixel data, one unsigned byte per channel
+    pub fn add_image(&mut self, name: &str, width: u32, height: u32, data: &Vec<u8>) -> Option<AtlasRect> {
+        if width == 0 || height == 0 {
+            return None;
+        }
+        assert!(data.len() >= width as usize * height as usize * BYTES_PER_TEXEL);
+
+        let existing = match self.entries.get(name) {
+            Some(r) if r.width == width && r.height == height => Some(*r),
+            _ => None,
+        };
+
+        let rect = match existing {
+            Some(r) => r,
+            None => {
+                match self.allocate(width, height) {
+                    Some(r) => r,
+                    None => return None,
+                }
+            }
+        };
+
+        self.blit(&rect, data);
+        self.entries.insert(name.to_string(), rect);
+        Some(rect)
+    }
+
+    /// Add a set of images, placing the tallest first for a tighter packing
+    ///
+    /// Returns the names of the images that did not fit on a page.
+    ///
+    /// images: The names, widths, heights and RGBA pixel data of the images
+    pub fn add_images(&mut self, images: &Vec<(String, u32, u32, Vec<u8>)>) -> Vec<String> {
+        let mut order: Vec<usize> = (0..images.len()).collect();
+        order.sort_by(|&a, &b| images[b].2.cmp(&images[a].2));
+
+        let mut rejected = vec![];
+        for i in order {
+            let (ref name, width, height, ref data) = images[i];
+            if self.add_image(name, width, height, data).is_none() {
+                rejected.push(name.clone());
+            }
+        }
+        rejected
+    }
+
+    /// Create a texture for each new page and recreate the textures of changed pages
+    ///
+    /// renderer: The renderer object
+    /// textures: The page textures, indexed by page
+    pub fn update_textures(&mut self, renderer: &mut Box<Renderer>, textures: &mut Vec<Box<Texture>>) {
+        for page in 0..self.pages.len() {
+            if page < textures.len() && !self.pages[page].dirty {
+                continue;
+            }
+
+            let texture = self.create_page_texture(renderer, page);
+            if page < textures.len() {
+                textures[page] = texture;
+            } else {
+                textures.push(texture);
+            }
+            self.pages[page].dirty = false;
+        }
+    }
+
+    /// Create a texture from a page
+    ///
+    /// There are no mipmaps, as the smaller levels would blend neighbouring images together.
+    ///
+    /// renderer: The renderer object
+    /// page: The index of the page
+    pub fn create_page_texture(&self, renderer: &mut Box<Renderer>, page: usize) -> Box<Texture> {
+        let data = &self.pages[page].data;
+        match renderer.renderer_type() {
+            RendererType::RendererGl => {
+                Box::new(TextureGl::new_ubyte_rgba(renderer, self.page_size, self.page_size, data, false))
+            }
+            RendererType::RendererVk => {
+                Box::new(TextureVk::new_ubyte_rgba(renderer, self.page_size, self.page_size, data, false))
+            }
+        }
+    }
+
+    /// Reserve space for an image and its gutter on the first page with room, adding a page if none has
+    ///
+    /// width: The image width
+    /// height: The image height
+    fn allocate(&mut self, width: u32, height: u32) -> Option<AtlasRect> {
+        let padded_width = width + self.padding * 2;
+        let padded_height = height + self.padding * 2;
+        if padded_width > self.page_size || padded_height > self.page_size {
+            return None;
+        }
+
+        for (page, atlas_page) in self.pages.iter_mut().enumerate() {
+            if let Some((x, y)) = atlas_page.packer.pack(padded_width, padded_height) {
+                return Some(AtlasRect {
+                    page: page,
+                    x: x + self.padding,
+                    y: y + self.padding,
+                    width: width,
+                    height: height,
+                });
+            }
+        }
+
+        let mut packer = ShelfPacker::new(self.page_size, self.page_size);
+        let (x, y) = packer.pack(padded_width, padded_height).unwrap();
+        self.pages.push(AtlasPage {
+            packer: packer,
+            data: vec![0; self.page_size as usize * self.page_size as usize * BYTES_PER_TEXEL],
+            dirty: true,
+        });
+        Some(AtlasRect {
+            page: self.pages.len() - 1,
+            x: x + self.padding,
+            y: y + self.padding,
+            width: width,
+            height: height,
+        })
+    }
+
+    /// Copy an image into its rectangle, extending the edge texels across the gutter
+    ///
+    /// rect: The rectangle reserved for the image
+    /// data: The RGBA pixel data
+    fn blit(&mut self, rect: &AtlasRect, data: &Vec<u8>) {
+        let page_size = self.page_size as i64;
+        let padding = self.padding as i64;
+        let page = &mut self.pages[rect.page];
+
+        for y in -padding..(rect.height as i64 + padding) {
+            let src_y = y.max(0).min(rect.height as i64 - 1) as usize;
+            let dst_y = rect.y as i64 + y;
+            for x in -padding..(rect.width as i64 + padding) {
+                let src_x = x.max(0).min(rect.width as i64 - 1) as usize;
+                let dst_x = rect.x as i64 + x;
+
+                let src = (src_y * rect.width as usize + src_x) * BYTES_PER_TEXEL;
+                let dst = ((dst_y * page_size + dst_x) as usize) * BYTES_PER_TEXEL;
+                page.data[dst..dst + BYTES_PER_TEXEL].copy_from_slice(&data[src..src + BYTES_PER_TEXEL]);
+            }
+        }
+
+        page.dirty = true;
+    }
+}
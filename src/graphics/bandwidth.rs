@@ -0,0 +1,146 @@
+// Copyright (c) 2016-2017 Bruce Stenning. All rights reserved.
+//
+// Redistribution and use in source and binary forms, with or without
+// modification, are permitted provided that the following conditions
+// are met:
+//
+// 1. Redistributions of source code must retain the above copyright
+//    notice, this list of conditions and the following disclaimer.
+//
+// 2. Redistributions in binary form must reproduce the above copyright
+//    notice, this list of conditions and the following disclaimer in the
+//    documentation and/or other materials provided with the distribution.
+//
+// 3. Neither the name of the copyright holder nor the names of its
+//    contributors may be used to endorse or promote products derived
+//    from this software without specific prior written permission.
+//
+// THIS SOFTWARE IS PROVIDED BY THE COPYRIGHT HOLDERS AND CONTRIBUTORS
+// AS IS" AND ANY EXPRESS OR IMPLIED WARRANTIES, INCLUDING, BUT NOT
+// LIMITED TO, THE IMPLIED WARRANTIES OF MERCHANTABILITY AND FITNESS
+// FOR A PARTICULAR PURPOSE ARE DISCLAIMED. IN NO EVENT SHALL THE
+// COPYRIGHT HOLDER OR CONTRIBUTORS BE LIABLE FOR ANY DIRECT, INDIRECT,
+// INCIDENTAL, SPECIAL, EXEMPLARY, OR CONSEQUENTIAL DAMAGES (INCLUDING,
+// BUT NOT LIMITED TO, PROCUREMENT OF SUBSTITUTE GOODS OR SERVICES; LOSS
+// OF USE, DATA, OR PROFITS; OR BUSINESS INTERRUPTION) HOWEVER CAUSED
+// AND ON ANY THEORY OF LIABILITY, WHETHER IN CONTRACT, STRICT LIABILITY,
+// OR TORT (INCLUDING NEGLIGENCE OR OTHERWISE) ARISING IN ANY WAY OUT OF
+// THE USE OF THIS SOFTWARE, EVEN IF ADVISED OF THE POSSIBILITY OF SUCH
+// DAMAGE.
+
+use std::mem;
+
+/// The bytes fetched per pixel from each texture a pass samples, assuming four-byte texels and
+/// that the texture cache absorbs the neighbouring taps of filtering
+pub const ASSUMED_TEXEL_BYTES: u64 = 4;
+
+/// The colour and depth attachments a pass renders to
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub struct PassTargetDesc {
+    pub width: u32,
+    pub height: u32,
+    pub samples: u32,
+    pub colour_bytes_per_sample: u32,
+    pub depth_bytes_per_sample: u32, // Zero when there is no depth attachment
+    pub loads_colour: bool, // Whether the previous colour contents are read at the start of the pass
+    pub resolves: bool, // Whether multisampled colour is resolved into a single-sampled image
+}
+
+/// A first-order estimate of the memory traffic of a pass
+///
+/// The estimate assumes every pixel of the target is covered once, so it is most accurate for
+/// full-screen passes and a lower bound for passes with overdraw.  It ignores framebuffer
+/// compression and, on tiling GPUs, the traffic kept on-chip, so it is most useful for spotting
+/// which passes are likely to be bandwidth-bound on integrated GPUs.
+#[derive(Clone, Debug, PartialEq)]
+pub struct PassBandwidth {
+    pub shader_name: &'static str,
+    pub attachment_read_bytes: u64,
+    pub attachment_write_bytes: u64,
+    pub texture_read_bytes: u64,
+    pub gpu_ms: Option<f32>, // The GPU time of the pass, where it was measured
+}
+
+impl PassBandwidth {
+    /// Estimate the memory traffic of a pass
+    ///
+    /// shader_name: The shader the pass is drawn with
+    /// target: The attachments the pass renders to
+    /// sampled_textures: The number of textures the shader samples
+    pub fn estimate(shader_name: &'static str, target: &PassTargetDesc, sampled_textures: u32) -> PassBandwidth {
+        let pixels = target.width as u64 * target.height as u64;
+        let samples = pixels * target.samples.max(1) as u64;
+
+        let colour_bytes = samples * target.colour_bytes_per_sample as u64;
+        let depth_bytes = samples * target.depth_bytes_per_sample as u64;
+
+        let mut reads = depth_bytes; // The depth test reads what it then writes
+        let mut writes = colour_bytes + depth_bytes;
+        if target.loads_colour {
+            reads += colour_bytes;
+        }
+        if target.resolves && target.samples > 1 {
+            reads += colour_bytes;
+            writes += pixels * target.colour_bytes_per_sample as u64;
+        }
+
+        PassBandwidth {
+            shader_name: shader_name,
+            attachment_read_bytes: reads,
+            attachment_write_bytes: writes,
+            texture_read_bytes: pixels * sampled_textures as u64 * ASSUMED_TEXEL_BYTES,
+            gpu_ms: None,
+        }
+    }
+
+    /// Return the total bytes read and written by the pass
+    pub fn total_bytes(&self) -> u64 {
+        self.attachment_read_bytes + self.attachment_write_bytes + self.texture_read_bytes
+    }
+
+    /// Return the bandwidth the pass achieved in gigabytes per second, where its GPU time is known
+    pub fn gigabytes_per_second(&self) -> Option<f32> {
+        match self.gpu_ms {
+            Some(ms) if ms > 0.0f32 => Some(self.total_bytes() as f32 / (ms * 1.0e6f32)),
+            _ => None,
+        }
+    }
+}
+
+/// The bandwidth estimates of the passes of the frame being recorded and of the last frame
+pub struct BandwidthStats {
+    current_frame: Vec<PassBandwidth>,
+    last_frame: Vec<PassBandwidth>,
+}
+
+impl BandwidthStats {
+    pub fn new() -> BandwidthStats {
+        BandwidthStats {
+            current_frame: vec![],
+            last_frame: vec![],
+        }
+    }
+
+    /// Record the estimate for a pass of the frame being recorded
+    ///
+    /// pass: The estimate for the pass
+    pub fn record_pass(&mut self, pass: PassBandwidth) {
+        self.current_frame.push(pass);
+    }
+
+    /// Finish the frame being recorded, making its estimates those of the last frame
+    pub fn end_frame(&mut self) {
+        self.last_frame.clear();
+        mem::swap(&mut self.current_frame, &mut self.last_frame);
+    }
+
+    /// Return the estimates for the passes of the last frame, in the order they were drawn
+    pub fn last_frame(&self) -> &Vec<PassBandwidth> {
+        &self.last_frame
+    }
+
+    /// Return the total estimated bytes read and written by the last frame
+    pub fn last_frame_total_bytes(&self) -> u64 {
+        self.last_frame.iter().map(|pass| pass.total_bytes()).sum()
+    }
+}
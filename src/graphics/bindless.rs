@@ -0,0 +1,311 @@
+// Copyright (c) 2016-2017 Bruce Stenning. All rights reserved.
+//
+// Redistribution and use in source and binary forms, with or without
+// modification, are permitted provided that the following conditions
+// are met:
+//
+// 1. Redistributions of source code must retain the above copyright
+//    notice, this list of conditions and the following disclaimer.
+//
+// 2. Redistributions in binary form must reproduce the above copyright
+//    notice, this list of conditions and the following disclaimer in the
+//    documentation and/or other materials provided with the distribution.
+//
+// 3. Neither the name of the copyright holder nor the names of its
+//    contributors may be used to endorse or promote products derived
+//    from this software without specific prior written permission.
+//
+// THIS SOFTWARE IS PROVIDED BY THE COPYRIGHT HOLDERS AND CONTRIBUTORS
+// AS IS" AND ANY EXPRESS OR IMPLIED WARRANTIES, INCLUDING, BUT NOT
+// LIMITED TO, THE IMPLIED WARRANTIES OF MERCHANTABILITY AND FITNESS
+// FOR A PARTICULAR PURPOSE ARE DISCLAIMED. IN NO EVENT SHALL THE
+// COPYRIGHT HOLDER OR CONTRIBUTORS BE LIABLE FOR ANY DIRECT, INDIRECT,
+// INCIDENTAL, SPECIAL, EXEMPLARY, OR CONSEQUENTIAL DAMAGES (INCLUDING,
+// BUT NOT LIMITED TO, PROCUREMENT OF SUBSTITUTE GOODS OR SERVICES; LOSS
+// OF USE, DATA, OR PROFITS; OR BUSINESS INTERRUPTION) HOWEVER CAUSED
+// AND ON ANY THEORY OF LIABILITY, WHETHER IN CONTRACT, STRICT LIABILITY,
+// OR TORT (INCLUDING NEGLIGENCE OR OTHERWISE) ARISING IN ANY WAY OUT OF
+// THE USE OF THIS SOFTWARE, EVEN IF ADVISED OF THE POSSIBILITY OF SUCH
+// DAMAGE.
+use std::io;
+
+use graphics::renderer::*;
+use graphics::resources::*;
+use misc::fileutils::*;
+
+/// The descriptor set of the bindless texture array
+///
+/// Shaders that use bindless textures have their own blocks and textures in the sets before
+/// this one.  Missing sets are filled with empty set layouts.
+pub const BINDLESS_TEXTURE_SET: u32 = 2;
+
+/// The binding of the bindless texture array in BINDLESS_TEXTURE_SET
+pub const BINDLESS_TEXTURE_BINDING: u32 = 0;
+
+/// The number of descriptors in the bindless texture array
+pub const BINDLESS_MAX_TEXTURES: u32 = 4096;
+
+/// The texture index of a material record that has no texture in that slot
+pub const BINDLESS_NO_TEXTURE: u32 = 0xffffffff;
+
+/// The name of the storage block holding the material records
+///
+/// The block is declared in GLSL by BINDLESS_LIBRARY_FILENAME.  Shaders find their record with
+/// an index of their own, such as a push constant or an instance attribute.
+///
+/// layout(std430, set = 0, binding = 8) readonly buffer material_buffer {
+///     MaterialRecord material_records[MATERIAL_MAX_RECORDS];
+/// };
+pub const MATERIAL_BLOCK: &'static str = "material_buffer";
+
+/// The most material records in MATERIAL_BLOCK
+pub const MATERIAL_MAX_RECORDS: usize = 1024;
+
+/// The number of floats a packed material record takes, following the std430 layout
+pub const MATERIAL_RECORD_FLOATS: usize = 12;
+
+/// The GLSL library declaring the bindless texture array and the material records
+///
+/// It enables GL_EXT_nonuniform_qualifier, so must be the first library of a shader.
+pub const BINDLESS_LIBRARY_FILENAME: &'static str = "bindless.glsl";
+
+const BINDLESS_LIBRARY_SOURCE: &'static str = include_str!("shaders/bindless.glsl");
+
+/// Allocates the indices of the bindless texture array
+///
+/// Released indices are kept back for a number of frames, so that frames still in flight never
+/// see a slot rewritten underneath them.  The lowest free index is reused first so that the
+/// array stays compact.
+pub struct BindlessSlots {
+    capacity: u32,
+    next: u32,
+    free: Vec<u32>,
+    retiring: Vec<(u32, u32)>, // Index and frames until it may be reused
+    retire_frames: u32,
+}
+
+impl BindlessSlots {
+    /// Create an allocator with every index free
+    ///
+    /// capacity: The number of indices
+    /// retire_frames: The number of frames a released index is held back for
+    pub fn new(capacity: u32, retire_frames: u32) -> BindlessSlots {
+        BindlessSlots {
+            capacity: capacity,
+            next: 0,
+            free: vec![],
+            retiring: vec![],
+            retire_frames: retire_frames,
+        }
+    }
+
+    /// Allocate an index, returning None if they are all in use or retiring
+    pub fn allocate(&mut self) -> Option<u32> {
+        if !self.free.is_empty() {
+            return self.free.pop();
+        }
+
+        if self.next < self.capacity {
+            self.next += 1;
+            Some(self.next - 1)
+        } else {
+            None
+        }
+    }
+
+    /// Release an index, which may be allocated again after retire_frames calls to advance_frame
+    ///
+    /// index: The index to release
+    pub fn release(&mut self, index: u32) {
+        debug_assert!(index < self.next);
+        debug_assert!(!self.free.contains(&index) && !self.retiring.iter().any(|&(i, _)| i == index));
+
+        if self.retire_frames == 0 {
+            self.return_index(index);
+        } else {
+            self.retiring.push((index, self.retire_frames));
+        }
+    }
+
+    /// Count down the frames of the retiring indices, freeing those that have finished
+    pub fn advance_frame(&mut self) {
+        let mut finished = vec![];
+        for entry in self.retiring.iter_mut() {
+            entry.1 -= 1;
+            if entry.1 == 0 {
+                finished.push(entry.0);
+            }
+        }
+
+        self.retiring.retain(|&(_, frames)| frames > 0);
+        for index in finished {
+            self.return_index(index);
+        }
+    }
+
+    /// Return the number of indices allocated and not released
+    pub fn in_use(&self) -> u32 {
+        self.next - self.free.len() as u32 - self.retiring.len() as u32
+    }
+
+    /// Return the number of indices
+    pub fn capacity(&self) -> u32 {
+        self.capacity
+    }
+
+    /// Add an index to the free list, which is kept in descending order
+    fn return_index(&mut self, index: u32) {
+        let position = self.free.iter().position(|&i| i < index).unwrap_or(self.free.len());
+        self.free.insert(position, index);
+    }
+}
+
+/// The textures and factors of a material, as read from MATERIAL_BLOCK by bindless shaders
+///
+/// Each texture is an index into the bindless texture array, or BINDLESS_NO_TEXTURE.
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub struct MaterialRecord {
+    pub albedo_texture: u32,
+    pub normal_texture: u32,
+    pub metallic_roughness_texture: u32,
+    pub emissive_texture: u32,
+    pub base_colour: [f32; 4],
+    pub metallic: f32,
+    pub roughness: f32,
+    pub normal_scale: f32,
+    pub emissive_strength: f32,
+}
+
+impl Default for MaterialRecord {
+    fn default() -> MaterialRecord {
+        MaterialRecord {
+            albedo_texture: BINDLESS_NO_TEXTURE,
+            normal_texture: BINDLESS_NO_TEXTURE,
+            metallic_roughness_texture: BINDLESS_NO_TEXTURE,
+            emissive_texture: BINDLESS_NO_TEXTURE,
+            base_colour: [1.0, 1.0, 1.0, 1.0],
+            metallic: 0.0,
+            roughness: 1.0,
+            normal_scale: 1.0,
+            emissive_strength: 0.0,
+        }
+    }
+}
+
+impl MaterialRecord {
+    /// Append the record to a vector of floats in its std430 layout
+    ///
+    /// The bit patterns of the texture indices are copied into the floats unchanged.
+    ///
+    /// out: The vector to append to
+    pub fn pack(&self, out: &mut Vec<f32>) {
+        out.push(f32::from_bits(self.albedo_texture));
+        out.push(f32::from_bits(self.normal_texture));
+        out.push(f32::from_bits(self.metallic_roughness_texture));
+        out.push(f32::from_bits(self.emissive_texture));
+        out.extend_from_slice(&self.base_colour);
+        out.push(self.metallic);
+        out.push(self.roughness);
+        out.push(self.normal_scale);
+        out.push(self.emissive_strength);
+    }
+}
+
+/// The material records uploaded to MATERIAL_BLOCK, indexed by the shaders that draw them
+pub struct MaterialTable {
+    records: Vec<MaterialRecord>,
+}
+
+impl MaterialTable {
+    /// Create an empty table
+    pub fn new() -> MaterialTable {
+        MaterialTable { records: vec![] }
+    }
+
+    /// Add a record, returning its index or None if the table is full
+    ///
+    /// record: The record to add
+    pub fn add(&mut self, record: MaterialRecord) -> Option<u32> {
+        if self.records.len() >= MATERIAL_MAX_RECORDS {
+            return None;
+        }
+
+        self.records.push(record);
+        Some(self.records.len() as u32 - 1)
+    }
+
+    /// Replace a record
+    ///
+    /// index: The index returned when the record was added
+    /// record: The new record
+    pub fn set(&mut self, index: u32, record: MaterialRecord) {
+        self.records[index as usize] = record;
+    }
+
+    /// Return a record
+    ///
+    /// index: The index returned when the record was added
+    pub fn get(&self, index: u32) -> &MaterialRecord {
+        &self.records[index as usize]
+    }
+
+    /// Return the number of records
+    pub fn len(&self) -> usize {
+        self.records.len()
+    }
+
+    /// Return whether there are no records
+    pub fn is_empty(&self) -> bool {
+        self.records.is_empty()
+    }
+
+    /// Return the records packed for upload to MATERIAL_BLOCK
+    pub fn packed(&self) -> Vec<f32> {
+        let mut out = Vec::with_capacity(self.records.len() * MATERIAL_RECORD_FLOATS);
+        for record in self.records.iter() {
+            record.pack(&mut out);
+        }
+
+        out
+    }
+}
+
+/// Write the source of the bindless library to the working directory
+///
+/// Shaders listing BINDLESS_LIBRARY_FILENAME include the file while they build during setup, so
+/// this must be called before the renderer is set up.
+pub fn install_bindless_shader_sources() -> Result<(), io::Error> {
+    write_entire_file(BINDLESS_LIBRARY_SOURCE, BINDLESS_LIBRARY_FILENAME)?;
+
+    Ok(())
+}
+
+/// Add the block specification of the material records
+///
+/// Shaders using bindless textures list BINDLESS_LIBRARY_FILENAME first in their library files,
+/// MATERIAL_BLOCK in their uniform block names, and set bindless_textures in their specification.
+///
+/// resource_manager: The resource manager to add the specification to
+pub fn register_material_block(resource_manager: &mut ResourceManager) {
+    resource_manager.uniform_block_specs.insert(MATERIAL_BLOCK,
+                                                UniformBlockSpec {
+                                                    set: 0,
+                                                    block_type: UniformType::StorageBuffer,
+                                                    uniforms: vec![BlockUniformSpec {
+                                                                       name: "material_records",
+                                                                       ..Default::default()
+                                                                   }],
+                                                    ..Default::default()
+                                                });
+}
+
+/// Upload the material records for the shaders drawn from now on
+///
+/// This must be called at a pass boundary.
+///
+/// renderer: The renderer to upload with
+/// table: The material records
+pub fn upload_material_table<Rend: Renderer + ?Sized>(renderer: &mut Rend, table: &MaterialTable) {
+    renderer.set_uniform_buffer_float_vector(MATERIAL_BLOCK, "material_records", &table.packed());
+    renderer.synchronise_uniform_buffer(MATERIAL_BLOCK);
+}
@@ -0,0 +1,393 @@
+// Copyright (c) 2016-2017 Bruce Stenning. All rights reserved.
+//
+// Redistribution and use in source and binary forms, with or without
+// modification, are permitted provided that the following conditions
+// are met:
+//
+// 1. Redistributions of source code must retain the above copyright
+//    notice, this list of conditions and the following disclaimer.
+//
+// 2. Redistributions in binary form must reproduce the above copyright
+//    notice, this list of conditions and the following disclaimer in the
+//    documentation and/or other materials provided with the distribution.
+//
+// 3. Neither the name of the copyright holder nor the names of its
+//    contributors may be used to endorse or promote products derived
+//    from this software without specific prior written permission.
+//
+// THIS SOFTWARE IS PROVIDED BY THE COPYRIGHT HOLDERS AND CONTRIBUTORS
+// AS IS" AND ANY EXPRESS OR IMPLIED WARRANTIES, INCLUDING, BUT NOT
+// LIMITED TO, THE IMPLIED WARRANTIES OF MERCHANTABILITY AND FITNESS
+// FOR A PARTICULAR PURPOSE ARE DISCLAIMED. IN NO EVENT SHALL THE
+// COPYRIGHT HOLDER OR CONTRIBUTORS BE LIABLE FOR ANY DIRECT, INDIRECT,
+// INCIDENTAL, SPECIAL, EXEMPLARY, OR CONSEQUENTIAL DAMAGES (INCLUDING,
+// BUT NOT LIMITED TO, PROCUREMENT OF SUBSTITUTE GOODS OR SERVICES; LOSS
+// OF USE, DATA, OR PROFITS; OR BUSINESS INTERRUPTION) HOWEVER CAUSED
+// AND ON ANY THEORY OF LIABILITY, WHETHER IN CONTRACT, STRICT LIABILITY,
+// OR TORT (INCLUDING NEGLIGENCE OR OTHERWISE) ARISING IN ANY WAY OUT OF
+// THE USE OF THIS SOFTWARE, EVEN IF ADVISED OF THE POSSIBILITY OF SUCH
+// DAMAGE.
+
+use std::collections::HashMap;
+use std::io;
+
+use algebra::vector::Vec3;
+use graphics::renderer::*;
+use graphics::resources::*;
+use graphics::shader::*;
+use misc::fileutils::*;
+
+/// The name the terrain brush shader is registered under
+pub const BRUSH_SHADER_NAME: &'static str = "terrain_brush";
+
+/// The name of the uniform block holding the camera matrices and viewport size
+pub const BRUSH_CAMERA_BLOCK: &'static str = "brush_camera";
+
+/// The name of the texture the scene depth buffer is sampled from
+pub const BRUSH_DEPTH_TEXTURE: &'static str = "brush_depth_texture";
+
+/// The name of the fragment output of the brush shader
+pub const BRUSH_FRAGMENT_OUT: &'static str = "brush_colour";
+
+const BRUSH_VERTEX_FILENAME: &'static str = "terrain_brush.vert";
+const BRUSH_FRAGMENT_FILENAME: &'static str = "terrain_brush.frag";
+
+const BRUSH_VERTEX_SOURCE: &'static str = include_str!("shaders/terrain_brush.vert");
+const BRUSH_FRAGMENT_SOURCE: &'static str = include_str!("shaders/terrain_brush.frag");
+
+// The corners and triangles of the box around a brush, from -1 to 1 on each axis before scaling
+const BRUSH_BOX_CORNERS: [[f32; 3]; 8] = [[-1.0, -1.0, -1.0],
+                                          [1.0, -1.0, -1.0],
+                                          [1.0, 1.0, -1.0],
+                                          [-1.0, 1.0, -1.0],
+                                          [-1.0, -1.0, 1.0],
+                                          [1.0, -1.0, 1.0],
+                                          [1.0, 1.0, 1.0],
+                                          [-1.0, 1.0, 1.0]];
+const BRUSH_BOX_TRIANGLES: [[usize; 3]; 12] = [[0, 2, 1], [0, 3, 2], [4, 5, 6], [4, 6, 7], [0, 1, 5], [0, 5, 4], [3, 6, 2],
+                                               [3, 7, 6], [0, 4, 7], [0, 7, 3], [1, 2, 6], [1, 6, 5]];
+
+/// Identifies a brush added to a brush overlay
+pub type BrushHandle = u32;
+
+/// How a brush is drawn on the terrain
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub enum BrushShape {
+    /// An outline at the radius, for showing where the brush is
+    Ring,
+    /// A filled disc fading out over the falloff, for previewing the strength of an edit
+    Disc,
+}
+
+/// A brush projected straight down onto the terrain
+///
+/// The brush covers the terrain within its radius of the centre, measured in the horizontal
+/// plane, and within its height above or below the centre.
+#[derive(Clone, Copy)]
+pub struct Brush {
+    pub shape: BrushShape,
+    pub centre: Vec3<f32>,
+    pub radius: f32,
+    pub falloff: f32, // The fraction of the radius over which the edge of a disc fades, from 0 to 1
+    pub ring_width: f32, // The width of a ring in world units, ignored by a disc
+    pub height: f32, // How far above and below the centre the terrain is drawn on
+    pub colour: Vec3<f32>,
+    pub opacity: f32,
+    pub visible: bool,
+}
+
+impl Brush {
+    /// Create a visible brush with a soft edge, reaching as far vertically as horizontally
+    ///
+    /// shape: How the brush is drawn
+    /// centre: The point on the terrain the brush is centred on
+    /// radius: The radius of the brush
+    /// colour: The colour of the brush
+    pub fn new(shape: BrushShape, centre: Vec3<f32>, radius: f32, colour: Vec3<f32>) -> Brush {
+        Brush {
+            shape: shape,
+            centre: centre,
+            radius: radius,
+            falloff: 0.25f32,
+            ring_width: radius * 0.05f32,
+            height: radius,
+            colour: colour,
+            opacity: 0.75f32,
+            visible: true,
+        }
+    }
+
+    /// Return the opacity the brush is drawn with at a horizontal distance from its centre
+    ///
+    /// This matches the brush shader, and suits sizing edits to what the preview shows.
+    ///
+    /// distance: The horizontal distance from the centre
+    pub fn coverage(&self, distance: f32) -> f32 {
+        if self.radius <= 0.0f32 || distance > self.radius {
+            return 0.0f32;
+        }
+
+        let from_edge = self.radius - distance;
+        match self.shape {
+            BrushShape::Disc => {
+                let edge = self.radius * self.falloff.max(0.0f32).min(1.0f32);
+                if edge > 0.0f32 {
+                    self.opacity * (from_edge / edge).min(1.0f32)
+                } else {
+                    self.opacity
+                }
+            }
+            BrushShape::Ring => {
+                if from_edge <= self.ring_width {
+                    self.opacity
+                } else {
+                    0.0f32
+                }
+            }
+        }
+    }
+}
+
+/// Holds the editor brushes and draws them over the terrain after the opaque geometry
+pub struct BrushOverlay {
+    brushes: HashMap<BrushHandle, Brush>,
+    next_handle: BrushHandle,
+}
+
+impl BrushOverlay {
+    pub fn new() -> BrushOverlay {
+        BrushOverlay {
+            brushes: HashMap::new(),
+            next_handle: 0,
+        }
+    }
+
+    /// Add a brush, returning the handle it may be updated or removed with
+    ///
+    /// brush: The brush to add
+    pub fn add_brush(&mut self, brush: Brush) -> BrushHandle {
+        let handle = self.next_handle;
+        self.next_handle += 1;
+
+        self.brushes.insert(handle, brush);
+
+        handle
+    }
+
+    /// Remove a brush, returning it if the handle was present
+    ///
+    /// handle: The handle returned when the brush was added
+    pub fn remove_brush(&mut self, handle: BrushHandle) -> Option<Brush> {
+        self.brushes.remove(&handle)
+    }
+
+    /// Move a brush, e.g. to follow the cursor over the terrain each frame
+    ///
+    /// handle: The handle returned when the brush was added
+    /// centre: The new centre of the brush
+    pub fn set_brush_position(&mut self, handle: BrushHandle, centre: &Vec3<f32>) {
+        match self.brushes.get_mut(&handle) {
+            Some(brush) => brush.centre = *centre,
+            None => log_warning!("Unknown brush handle {}", handle),
+        }
+    }
+
+    /// Show or hide a brush without removing it
+    ///
+    /// handle: The handle returned when the brush was added
+    /// visible: Whether the brush should be drawn
+    pub fn set_brush_visible(&mut self, handle: BrushHandle, visible: bool) {
+        match self.brushes.get_mut(&handle) {
+            Some(brush) => brush.visible = visible,
+            None => log_warning!("Unknown brush handle {}", handle),
+        }
+    }
+
+    /// Return the brush with the specified handle
+    ///
+    /// handle: The handle returned when the brush was added
+    pub fn get(&self, handle: BrushHandle) -> Option<&Brush> {
+        self.brushes.get(&handle)
+    }
+
+    /// Return the brush with the specified handle for changing its settings
+    ///
+    /// handle: The handle returned when the brush was added
+    pub fn get_mut(&mut self, handle: BrushHandle) -> Option<&mut Brush> {
+        self.brushes.get_mut(&handle)
+    }
+
+    /// Return the handles of the brushes that are drawn, in sorted order
+    pub fn visible_brushes(&self) -> Vec<BrushHandle> {
+        let mut handles: Vec<BrushHandle> = self.brushes
+            .iter()
+            .filter(|&(_, brush)| brush.visible && brush.radius > 0.0f32 && brush.opacity > 0.0f32)
+            .map(|(handle, _)| *handle)
+            .collect();
+        handles.sort();
+        handles
+    }
+
+    /// Draw the visible brushes as a pass of their own
+    ///
+    /// As with decals, this must follow the opaque geometry, with the scene depth buffer
+    /// available as the texture named by BRUSH_DEPTH_TEXTURE and the brush_camera block
+    /// synchronised.  Each brush is one flush of the twelve triangles of its box.
+    ///
+    /// renderer: The renderer to draw with
+    pub fn render<Rend: Renderer + ?Sized>(&self, renderer: &mut Rend) {
+        let handles = self.visible_brushes();
+        if handles.is_empty() {
+            return;
+        }
+
+        renderer.begin_pass(BRUSH_SHADER_NAME);
+
+        let threaddata_arc = renderer.get_threaddata(0);
+        let mut threaddata = threaddata_arc.lock().unwrap();
+        threaddata.vertex_array_type = VertexArrayType::F3;
+
+        for handle in handles {
+            let brush = &self.brushes[&handle];
+
+            renderer.set_push_constant_vec3(0, "brush_centre", &brush.centre);
+            renderer.set_push_constant_float(0, "brush_radius", brush.radius);
+            renderer.set_push_constant_vec3(0, "brush_tint", &brush.colour);
+            renderer.set_push_constant_float(0, "brush_opacity", brush.opacity);
+            renderer.set_push_constant_float(0, "brush_falloff", brush.falloff);
+            renderer.set_push_constant_float(0, "brush_ring_width", match brush.shape {
+                BrushShape::Ring => brush.ring_width.max(f32::MIN_POSITIVE), // Zero would draw a disc
+                BrushShape::Disc => 0.0f32,
+            });
+            renderer.set_push_constant_float(0, "brush_height", brush.height);
+
+            let corners: Vec<Vec3<f32>> = BRUSH_BOX_CORNERS.iter()
+                .map(|c| {
+                    Vec3 {
+                        x: brush.centre.x + c[0] * brush.radius,
+                        y: brush.centre.y + c[1] * brush.height,
+                        z: brush.centre.z + c[2] * brush.radius,
+                    }
+                })
+                .collect();
+            for triangle in BRUSH_BOX_TRIANGLES.iter() {
+                threaddata.add_triangle_st_n3(&corners[triangle[0]], &corners[triangle[1]], &corners[triangle[2]]);
+            }
+
+            threaddata.check_flush_st(true, renderer);
+        }
+
+        renderer.end_pass();
+    }
+}
+
+/// Write the sources of the terrain brush shader to the working directory
+///
+/// The brush shader is built during setup even when no brush is drawn until later, so this must be
+/// called before the renderer is set up.
+pub fn install_brush_shader_sources() -> Result<(), io::Error> {
+    write_entire_file(BRUSH_VERTEX_SOURCE, BRUSH_VERTEX_FILENAME)?;
+    write_entire_file(BRUSH_FRAGMENT_SOURCE, BRUSH_FRAGMENT_FILENAME)?;
+
+    Ok(())
+}
+
+/// Add the uniform block and the shader specification of the terrain brush shader
+///
+/// The shader blends over the colour target without writing depth, so the pass should use the
+/// render target of the opaque geometry with the depth buffer bound as a texture.
+///
+/// resource_manager: The resource manager to add the specifications to
+/// pass_name: The pass the shader is used in
+pub fn register_brush_shaders(resource_manager: &mut ResourceManager, pass_name: &'static str) {
+    resource_manager.uniform_block_specs.insert(BRUSH_CAMERA_BLOCK,
+                                                UniformBlockSpec {
+                                                    set: 0,
+                                                    block_type: UniformType::UniformBuffer,
+                                                    uniforms: ["brush_view_projection",
+                                                               "brush_inverse_view_projection",
+                                                               "brush_viewport"]
+                                                        .iter()
+                                                        .map(|name| {
+                                                            BlockUniformSpec {
+                                                                name: *name,
+                                                                ..Default::default()
+                                                            }
+                                                        })
+                                                        .collect(),
+                                                    ..Default::default()
+                                                });
+
+    let shader_spec = ShaderSpec {
+        name: BRUSH_SHADER_NAME,
+        library_files: vec![],
+        shader_files: vec![ShaderFilesSpecification {
+                               filename: BRUSH_VERTEX_FILENAME,
+                               shader_stage: ShaderStage::VertexShader,
+                               spirv_out: "terrain_brush.vert.spv",
+                               reflect_out: "terrain_brush.vert.rfl",
+                               entry_point: DEFAULT_ENTRY_POINT,
+                           },
+                           ShaderFilesSpecification {
+                               filename: BRUSH_FRAGMENT_FILENAME,
+                               shader_stage: ShaderStage::FragmentShader,
+                               spirv_out: "terrain_brush.frag.spv",
+                               reflect_out: "terrain_brush.frag.rfl",
+                               entry_point: DEFAULT_ENTRY_POINT,
+                           }],
+        fallback_shader_files: vec![],
+        uniform_block_names: vec![BRUSH_CAMERA_BLOCK],
+        uniform_specs: vec![UniformSpec {
+                                name: BRUSH_DEPTH_TEXTURE,
+                                set: 1,
+                                binding: 0,
+                                uniform_type: UniformType::CombinedImageSampler,
+                            }],
+        push_constants: vec![PushConstantSpec {
+                                 name: "brush_centre",
+                                 offset: 0,
+                                 size: 12,
+                             },
+                             PushConstantSpec {
+                                 name: "brush_radius",
+                                 offset: 12,
+                                 size: 4,
+                             },
+                             PushConstantSpec {
+                                 name: "brush_tint",
+                                 offset: 16,
+                                 size: 12,
+                             },
+                             PushConstantSpec {
+                                 name: "brush_opacity",
+                                 offset: 28,
+                                 size: 4,
+                             },
+                             PushConstantSpec {
+                                 name: "brush_falloff",
+                                 offset: 32,
+                                 size: 4,
+                             },
+                             PushConstantSpec {
+                                 name: "brush_ring_width",
+                                 offset: 36,
+                                 size: 4,
+                             },
+                             PushConstantSpec {
+                                 name: "brush_height",
+                                 offset: 40,
+                                 size: 4,
+                             }],
+        vertex_array_type: VertexArrayType::F3,
+        primitive: PrimitiveType::PrimitiveTriangles,
+        instance_layout: None,
+        attributes: vec!["position"],
+        fragment_out: BRUSH_FRAGMENT_OUT,
+        extra_fragment_outs: vec![],
+        render_state: RenderState::alpha_blended(),
+        stencil: None,
+        bindless_textures: false,
+        pass_name: pass_name,
+    };
+    resource_manager.shader_specs.insert(BRUSH_SHADER_NAME, shader_spec);
+}
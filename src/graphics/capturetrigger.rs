@@ -0,0 +1,306 @@
+// Copyright (c) 2016-2017 Bruce Stenning. All rights reserved.
+//
+// Redistribution and use in source and binary forms, with or without
+// modification, are permitted provided that the following conditions
+// are met:
+//
+// 1. Redistributions of source code must retain the above copyright
+//    notice, this list of conditions and the following disclaimer.
+//
+// 2. Redistributions in binary form must reproduce the above copyright
+//    notice, this list of conditions and the following disclaimer in the
+//    documentation and/or other materials provided with the distribution.
+//
+// 3. Neither the name of the copyright holder nor the names of its
+//    contributors may be used to endorse or promote products derived
+//    from this software without specific prior written permission.
+//
+// THIS SOFTWARE IS PROVIDED BY THE COPYRIGHT HOLDERS AND CONTRIBUTORS
+// AS IS" AND ANY EXPRESS OR IMPLIED WARRANTIES, INCLUDING, BUT NOT
+// LIMITED TO, THE IMPLIED WARRANTIES OF MERCHANTABILITY AND FITNESS
+// FOR A PARTICULAR PURPOSE ARE DISCLAIMED. IN NO EVENT SHALL THE
+// COPYRIGHT HOLDER OR CONTRIBUTORS BE LIABLE FOR ANY DIRECT, INDIRECT,
+// INCIDENTAL, SPECIAL, EXEMPLARY, OR CONSEQUENTIAL DAMAGES (INCLUDING,
+// BUT NOT LIMITED TO, PROCUREMENT OF SUBSTITUTE GOODS OR SERVICES; LOSS
+// OF USE, DATA, OR PROFITS; OR BUSINESS INTERRUPTION) HOWEVER CAUSED
+// AND ON ANY THEORY OF LIABILITY, WHETHER IN CONTRACT, STRICT LIABILITY,
+// OR TORT (INCLUDING NEGLIGENCE OR OTHERWISE) ARISING IN ANY WAY OUT OF
+// THE USE OF THIS SOFTWARE, EVEN IF ADVISED OF THE POSSIBILITY OF SUCH
+// DAMAGE.
+
+use std::ffi::{CStr, CString};
+#[cfg(unix)]
+use std::mem;
+use std::os::raw::{c_char, c_int, c_void};
+use std::ptr;
+#[cfg(unix)]
+use libc;
+
+/// The version of RenderDoc's in-application API requested, 1.1.2 as RENDERDOC_GetAPI encodes it
+const RENDERDOC_API_VERSION: c_int = 10102;
+
+/// The library RenderDoc injects into the applications it launches
+#[cfg(unix)]
+const RENDERDOC_LIBRARY: &'static str = "librenderdoc.so";
+
+type PfnGetApi = extern "C" fn(version: c_int, out_api_pointers: *mut *mut c_void) -> c_int;
+type PfnSetCaptureFilePathTemplate = extern "C" fn(path_template: *const c_char);
+type PfnGetNumCaptures = extern "C" fn() -> u32;
+type PfnGetCapture = extern "C" fn(index: u32, filename: *mut c_char, path_length: *mut u32, timestamp: *mut u64) -> u32;
+type PfnStartFrameCapture = extern "C" fn(device: *mut c_void, window: *mut c_void);
+type PfnIsFrameCapturing = extern "C" fn() -> u32;
+type PfnEndFrameCapture = extern "C" fn(device: *mut c_void, window: *mut c_void) -> u32;
+
+/// The entry points of RENDERDOC_API_1_1_2, in order, of which only those for capturing are called
+#[repr(C)]
+struct RenderDocApiTable {
+    get_api_version: *const c_void,
+    set_capture_option_u32: *const c_void,
+    set_capture_option_f32: *const c_void,
+    get_capture_option_u32: *const c_void,
+    get_capture_option_f32: *const c_void,
+    set_focus_toggle_keys: *const c_void,
+    set_capture_keys: *const c_void,
+    get_overlay_bits: *const c_void,
+    mask_overlay_bits: *const c_void,
+    remove_hooks: *const c_void,
+    unload_crash_handler: *const c_void,
+    set_capture_file_path_template: PfnSetCaptureFilePathTemplate,
+    get_capture_file_path_template: *const c_void,
+    get_num_captures: PfnGetNumCaptures,
+    get_capture: PfnGetCapture,
+    trigger_capture: *const c_void,
+    is_target_control_connected: *const c_void,
+    launch_replay_ui: *const c_void,
+    set_active_window: *const c_void,
+    start_frame_capture: PfnStartFrameCapture,
+    is_frame_capturing: PfnIsFrameCapturing,
+    end_frame_capture: PfnEndFrameCapture,
+    trigger_multi_frame_capture: *const c_void,
+}
+
+/// RenderDoc's in-application API, from the library loaded into the process
+struct RenderDocApi {
+    table: *const RenderDocApiTable,
+}
+
+impl RenderDocApi {
+    /// Find the API of the RenderDoc library, returning None if it is not in the process
+    ///
+    /// RenderDoc hooks the graphics API as it is loaded, so the library must be loaded before
+    /// the Vulkan instance or OpenGL context is created for frames to be captured.
+    ///
+    /// load: Whether to load the library if the application was not launched from RenderDoc
+    #[cfg(unix)]
+    fn new(load: bool) -> Option<RenderDocApi> {
+        let library_name = CString::new(RENDERDOC_LIBRARY).unwrap();
+        let flags = if load {
+            libc::RTLD_NOW
+        } else {
+            libc::RTLD_NOW | libc::RTLD_NOLOAD
+        };
+
+        unsafe {
+            let library = libc::dlopen(library_name.as_ptr(), flags);
+            if library.is_null() {
+                return None;
+            }
+
+            let entrypoint_name = CString::new("RENDERDOC_GetAPI").unwrap();
+            let get_api = libc::dlsym(library, entrypoint_name.as_ptr());
+            if get_api.is_null() {
+                return None;
+            }
+
+            let get_api = mem::transmute::<*mut c_void, PfnGetApi>(get_api);
+            let mut table: *mut c_void = ptr::null_mut();
+            if get_api(RENDERDOC_API_VERSION, &mut table) != 1 || table.is_null() {
+                log_warning!("RenderDoc does not offer version 1.1.2 of its API");
+                return None;
+            }
+
+            Some(RenderDocApi { table: table as *const RenderDocApiTable })
+        }
+    }
+
+    /// Find the API of the RenderDoc library, which is only looked for on Unix-like systems
+    #[cfg(not(unix))]
+    fn new(_: bool) -> Option<RenderDocApi> {
+        None
+    }
+
+    fn table(&self) -> &RenderDocApiTable {
+        unsafe { &*self.table }
+    }
+}
+
+/// The frames requested to be captured, each started at begin_frame and ended at end_frame
+#[derive(Clone, Copy, Debug, Default, PartialEq)]
+pub struct CaptureSchedule {
+    requested: u32, // Frames yet to be started
+    capturing: bool, // Whether the current frame is being captured
+    completed: u32,
+}
+
+impl CaptureSchedule {
+    pub fn new() -> CaptureSchedule {
+        CaptureSchedule::default()
+    }
+
+    /// Request that the coming frames be captured, each in a capture of its own
+    ///
+    /// frames: The number of frames to capture
+    pub fn request(&mut self, frames: u32) {
+        self.requested += frames;
+    }
+
+    /// Begin a frame, returning whether its capture is to be started
+    pub fn begin_frame(&mut self) -> bool {
+        if self.capturing || self.requested == 0 {
+            return false;
+        }
+
+        self.requested -= 1;
+        self.capturing = true;
+
+        true
+    }
+
+    /// End a frame, returning whether its capture is to be ended
+    pub fn end_frame(&mut self) -> bool {
+        if !self.capturing {
+            return false;
+        }
+
+        self.capturing = false;
+        self.completed += 1;
+
+        true
+    }
+
+    /// Return whether the current frame is being captured
+    pub fn is_capturing(&self) -> bool {
+        self.capturing
+    }
+
+    /// Return the number of frames requested whose capture has not yet started
+    pub fn pending(&self) -> u32 {
+        self.requested
+    }
+
+    /// Return the number of frames whose capture has ended
+    pub fn completed(&self) -> u32 {
+        self.completed
+    }
+}
+
+/// Captures frames in RenderDoc when asked to, as automated tests do on detecting a visual
+/// regression
+///
+/// Each renderer owns one, starting and ending the captures it schedules in begin_frame and
+/// end_frame.  Without RenderDoc in the process no capture is scheduled.
+pub struct CaptureTrigger {
+    api: Option<RenderDocApi>,
+    schedule: CaptureSchedule,
+}
+
+unsafe impl Send for CaptureTrigger {}
+unsafe impl Sync for CaptureTrigger {}
+
+impl CaptureTrigger {
+    /// Create a capture trigger, attached to RenderDoc if it is in the process
+    ///
+    /// load: Whether to load RenderDoc if the application was not launched from it, which must
+    ///     be before the Vulkan instance or OpenGL context is created
+    pub fn new(load: bool) -> CaptureTrigger {
+        CaptureTrigger {
+            api: RenderDocApi::new(load),
+            schedule: CaptureSchedule::new(),
+        }
+    }
+
+    /// Return whether RenderDoc is attached, so that frames can be captured
+    pub fn is_available(&self) -> bool {
+        self.api.is_some()
+    }
+
+    /// Return the frames requested to be captured
+    pub fn schedule(&self) -> &CaptureSchedule {
+        &self.schedule
+    }
+
+    /// Request that the coming frames be captured, returning false if RenderDoc is not attached
+    ///
+    /// frames: The number of frames to capture
+    pub fn request(&mut self, frames: u32) -> bool {
+        if self.api.is_none() {
+            return false;
+        }
+
+        self.schedule.request(frames);
+
+        true
+    }
+
+    /// Start capturing the frame being begun if it was requested
+    ///
+    /// device: The device pointer RenderDoc identifies the API by, or null for any
+    pub fn begin_frame(&mut self, device: *mut c_void) {
+        if let Some(ref api) = self.api {
+            if self.schedule.begin_frame() {
+                (api.table().start_frame_capture)(device, ptr::null_mut());
+            }
+        }
+    }
+
+    /// End the capture of the frame being ended, if it is being captured
+    ///
+    /// device: The device pointer RenderDoc identifies the API by, or null for any
+    pub fn end_frame(&mut self, device: *mut c_void) {
+        if let Some(ref api) = self.api {
+            if self.schedule.end_frame() {
+                let table = api.table();
+                if (table.is_frame_capturing)() == 0 || (table.end_frame_capture)(device, ptr::null_mut()) == 0 {
+                    log_warning!("RenderDoc failed to capture the frame");
+                }
+            }
+        }
+    }
+
+    /// Set the path the captures are written to, to which RenderDoc appends the frame number
+    ///
+    /// path_template: The path and the start of the filename
+    pub fn set_capture_path_template(&self, path_template: &str) {
+        if let Some(ref api) = self.api {
+            let path = CString::new(path_template).unwrap();
+            (api.table().set_capture_file_path_template)(path.as_ptr());
+        }
+    }
+
+    /// Return the path of the most recent capture, or None if none has been written
+    pub fn last_capture_path(&self) -> Option<String> {
+        let api = match self.api {
+            Some(ref a) => a,
+            None => return None,
+        };
+
+        let table = api.table();
+        let count = (table.get_num_captures)();
+        if count == 0 {
+            return None;
+        }
+
+        let mut length: u32 = 0;
+        if (table.get_capture)(count - 1, ptr::null_mut(), &mut length, ptr::null_mut()) == 0 || length == 0 {
+            return None;
+        }
+
+        let mut filename: Vec<c_char> = vec![0; length as usize];
+        if (table.get_capture)(count - 1, filename.as_mut_ptr(), &mut length, ptr::null_mut()) == 0 {
+            return None;
+        }
+
+        let path = unsafe { CStr::from_ptr(filename.as_ptr()) };
+        Some(path.to_string_lossy().into_owned())
+    }
+}
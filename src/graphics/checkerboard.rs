@@ -0,0 +1,279 @@
+// Copyright (c) 2016-2017 Bruce Stenning. All rights reserved.
+//
+// Redistribution and use in source and binary forms, with or without
+// modification, are permitted provided that the following conditions
+// are met:
+//
+// 1. Redistributions of source code must retain the above copyright
+//    notice, this list of conditions and the following disclaimer.
+//
+// 2. Redistributions in binary form must reproduce the above copyright
+//    notice, this list of conditions and the following disclaimer in the
+//    documentation and/or other materials provided with the distribution.
+//
+// 3. Neither the name of the copyright holder nor the names of its
+//    contributors may be used to endorse or promote products derived
+//    from this software without specific prior written permission.
+//
+// THIS SOFTWARE IS PROVIDED BY THE COPYRIGHT HOLDERS AND CONTRIBUTORS
+// AS IS" AND ANY EXPRESS OR IMPLIED WARRANTIES, INCLUDING, BUT NOT
+// LIMITED TO, THE IMPLIED WARRANTIES OF MERCHANTABILITY AND FITNESS
+// FOR A PARTICULAR PURPOSE ARE DISCLAIMED. IN NO EVENT SHALL THE
+// COPYRIGHT HOLDER OR CONTRIBUTORS BE LIABLE FOR ANY DIRECT, INDIRECT,
+// INCIDENTAL, SPECIAL, EXEMPLARY, OR CONSEQUENTIAL DAMAGES (INCLUDING,
+// BUT NOT LIMITED TO, PROCUREMENT OF SUBSTITUTE GOODS OR SERVICES; LOSS
+// OF USE, DATA, OR PROFITS; OR BUSINESS INTERRUPTION) HOWEVER CAUSED
+// AND ON ANY THEORY OF LIABILITY, WHETHER IN CONTRACT, STRICT LIABILITY,
+// OR TORT (INCLUDING NEGLIGENCE OR OTHERWISE) ARISING IN ANY WAY OUT OF
+// THE USE OF THIS SOFTWARE, EVEN IF ADVISED OF THE POSSIBILITY OF SUCH
+// DAMAGE.
+
+use std::io;
+
+use algebra::matrix::Mat4;
+use graphics::fullscreen::draw_fullscreen_quad;
+use graphics::renderer::*;
+use graphics::resources::*;
+use graphics::shader::*;
+use graphics::uniformlayout::BlockMemberType;
+use misc::fileutils::*;
+
+/// The name the checkerboard resolve shader is registered under
+pub const CHECKERBOARD_RESOLVE_SHADER_NAME: &'static str = "checkerboard_resolve";
+
+/// The name of the uniform block holding the resolve settings
+pub const CHECKERBOARD_SETTINGS_BLOCK: &'static str = "checkerboard_settings";
+
+/// The name of the texture the half-width image of this frame is sampled from
+pub const CHECKERBOARD_CURRENT_TEXTURE: &'static str = "checkerboard_current_texture";
+
+/// The name of the texture the previous frame's resolve is sampled from
+pub const CHECKERBOARD_HISTORY_TEXTURE: &'static str = "checkerboard_history_texture";
+
+/// The name of the texture the velocity buffer is sampled from, see motionblur
+pub const CHECKERBOARD_VELOCITY_TEXTURE: &'static str = "checkerboard_velocity_texture";
+
+const CHECKERBOARD_RESOLVE_VERTEX_FILENAME: &'static str = "checkerboard_resolve.vert";
+const CHECKERBOARD_RESOLVE_FRAGMENT_FILENAME: &'static str = "checkerboard_resolve.frag";
+
+const CHECKERBOARD_RESOLVE_VERTEX_SOURCE: &'static str = include_str!("shaders/checkerboard_resolve.vert");
+const CHECKERBOARD_RESOLVE_FRAGMENT_SOURCE: &'static str = include_str!("shaders/checkerboard_resolve.frag");
+
+/// The state of checkerboard rendering, which renders half of the columns of the output each frame
+///
+/// The scene is drawn into a half-width target with the projection from jitter_projection, so
+/// that on alternate frames it covers the even and odd columns of the output.  The resolve then
+/// fills the columns not rendered from the previous resolve, reprojected along the motion vectors
+/// and clamped to the rendered neighbours.  The resolves alternate between two full-size targets,
+/// each the history of the next.  Checkerboarding can be switched on and off between frames.
+pub struct Checkerboard {
+    enabled: bool,
+    frame: u64, // Of the frames rendered with checkerboarding, whose parity picks the columns
+    history_valid: bool, // Whether the history target holds the resolve of the previous frame
+}
+
+impl Checkerboard {
+    /// Create the state, with no history
+    ///
+    /// enabled: Whether to start with checkerboarding enabled
+    pub fn new(enabled: bool) -> Checkerboard {
+        Checkerboard {
+            enabled: enabled,
+            frame: 0,
+            history_valid: false,
+        }
+    }
+
+    /// Return whether checkerboarding is enabled
+    pub fn is_enabled(&self) -> bool {
+        self.enabled
+    }
+
+    /// Enable or disable checkerboarding from the next frame
+    ///
+    /// The history is discarded, as it is missing or stale after frames rendered in full.
+    ///
+    /// enabled: Whether to render half of the columns each frame
+    pub fn set_enabled(&mut self, enabled: bool) {
+        if enabled != self.enabled {
+            self.enabled = enabled;
+            self.history_valid = false;
+        }
+    }
+
+    /// Discard the history, so that the next resolve fills the columns not rendered spatially
+    ///
+    /// Call this on camera cuts and when the output is resized, where reprojection would bring
+    /// in unrelated colour.
+    pub fn invalidate_history(&mut self) {
+        self.history_valid = false;
+    }
+
+    /// Start a frame, switching to the other half of the columns
+    pub fn begin_frame(&mut self) {
+        if self.enabled {
+            self.frame += 1;
+        }
+    }
+
+    /// Return the columns of the output rendered this frame: even for 0 and odd for 1
+    pub fn parity(&self) -> i32 {
+        (self.frame & 1) as i32
+    }
+
+    /// Return the size to render the scene at for an output of the size given
+    ///
+    /// The output width must be even for the columns to line up.
+    ///
+    /// width: The width of the output in pixels
+    /// height: The height of the output in pixels
+    pub fn render_size(&self, width: u32, height: u32) -> (u32, u32) {
+        if self.enabled {
+            ((width / 2).max(1), height)
+        } else {
+            (width, height)
+        }
+    }
+
+    /// Return a projection matrix moving the pixel centres of the half-width target onto the
+    /// output columns rendered this frame
+    ///
+    /// Texel x of the half-width target then covers output column 2x + parity.  The motion
+    /// vectors must be computed from the unjittered matrices, so that they hold the motion
+    /// of the scene alone.
+    ///
+    /// projection: The projection matrix for the output
+    /// width: The width of the output in pixels
+    pub fn jitter_projection(&self, projection: &Mat4<f32>, width: u32) -> Mat4<f32> {
+        if !self.enabled {
+            return *projection;
+        }
+        let offset = (1 - 2 * self.parity()) as f32 / width as f32; // In normalised device coordinates
+        Mat4::translate(offset, 0.0f32, 0.0f32) * *projection
+    }
+
+    /// Return which of the two full-size targets the resolve draws into this frame
+    pub fn resolve_target_index(&self) -> usize {
+        (self.frame & 1) as usize
+    }
+
+    /// Return which of the two full-size targets holds the previous resolve, to be sampled
+    pub fn history_target_index(&self) -> usize {
+        1 - self.resolve_target_index()
+    }
+}
+
+/// Write the sources of the checkerboard resolve shader to the working directory
+///
+/// The resolve shader is built from these files when the renderer is set up, so this must be
+/// called before then even if checkerboarding is only enabled later.
+pub fn install_checkerboard_shader_sources() -> Result<(), io::Error> {
+    write_entire_file(CHECKERBOARD_RESOLVE_VERTEX_SOURCE, CHECKERBOARD_RESOLVE_VERTEX_FILENAME)?;
+    write_entire_file(CHECKERBOARD_RESOLVE_FRAGMENT_SOURCE, CHECKERBOARD_RESOLVE_FRAGMENT_FILENAME)?;
+
+    Ok(())
+}
+
+/// Add the uniform block and shader specification of the checkerboard resolve shader
+///
+/// The shader draws F2F2 full-screen triangles at the output size, sampling the half-width
+/// image, the previous resolve and the velocity buffer written by the velocity shader.
+///
+/// resource_manager: The resource manager to add the specifications to
+/// pass_name: The pass the resolve shader is used in
+pub fn register_checkerboard_shader(resource_manager: &mut ResourceManager, pass_name: &'static str) {
+    let block_spec = UniformBlockSpec {
+        set: 0,
+        block_type: UniformType::UniformBuffer,
+        uniforms: vec![BlockUniformSpec::typed("checkerboard_viewport", BlockMemberType::Vec4),
+                       BlockUniformSpec::typed("checkerboard_parity", BlockMemberType::Int),
+                       BlockUniformSpec::typed("checkerboard_history_valid", BlockMemberType::Int)],
+        ..Default::default()
+    };
+    resource_manager.uniform_block_specs.insert(CHECKERBOARD_SETTINGS_BLOCK, block_spec);
+
+    let resolve_spec = ShaderSpec {
+        name: CHECKERBOARD_RESOLVE_SHADER_NAME,
+        library_files: vec![],
+        shader_files: vec![ShaderFilesSpecification {
+                               filename: CHECKERBOARD_RESOLVE_VERTEX_FILENAME,
+                               shader_stage: ShaderStage::VertexShader,
+                               spirv_out: "checkerboard_resolve.vert.spv",
+                               reflect_out: "checkerboard_resolve.vert.rfl",
+                               entry_point: DEFAULT_ENTRY_POINT,
+                           },
+                           ShaderFilesSpecification {
+                               filename: CHECKERBOARD_RESOLVE_FRAGMENT_FILENAME,
+                               shader_stage: ShaderStage::FragmentShader,
+                               spirv_out: "checkerboard_resolve.frag.spv",
+                               reflect_out: "checkerboard_resolve.frag.rfl",
+                               entry_point: DEFAULT_ENTRY_POINT,
+                           }],
+        fallback_shader_files: vec![],
+        uniform_block_names: vec![CHECKERBOARD_SETTINGS_BLOCK],
+        uniform_specs: vec![UniformSpec {
+                                name: CHECKERBOARD_CURRENT_TEXTURE,
+                                set: 1,
+                                binding: 0,
+                                uniform_type: UniformType::CombinedImageSampler,
+                            },
+                            UniformSpec {
+                                name: CHECKERBOARD_HISTORY_TEXTURE,
+                                set: 1,
+                                binding: 1,
+                                uniform_type: UniformType::CombinedImageSampler,
+                            },
+                            UniformSpec {
+                                name: CHECKERBOARD_VELOCITY_TEXTURE,
+                                set: 1,
+                                binding: 2,
+                                uniform_type: UniformType::CombinedImageSampler,
+                            }],
+        push_constants: vec![],
+        vertex_array_type: VertexArrayType::F2F2,
+        primitive: PrimitiveType::PrimitiveTriangles,
+        instance_layout: None,
+        attributes: vec!["position", "texcoord"],
+        fragment_out: "checkerboard_colour",
+        extra_fragment_outs: vec![],
+        render_state: RenderState::default(),
+        stencil: None,
+        bindless_textures: false,
+        pass_name: pass_name,
+    };
+    resource_manager.shader_specs.insert(CHECKERBOARD_RESOLVE_SHADER_NAME, resolve_spec);
+}
+
+/// Draw the checkerboard resolve over the whole of the current render target
+///
+/// The render target must be the full-size target named by resolve_target_index, and the
+/// half-width image, the other full-size target and the velocity buffer must be available as
+/// the textures named by CHECKERBOARD_CURRENT_TEXTURE, CHECKERBOARD_HISTORY_TEXTURE and
+/// CHECKERBOARD_VELOCITY_TEXTURE.  The resolve becomes the history of the next frame.
+///
+/// renderer: The renderer to draw with
+/// checkerboard: The checkerboard state, which must be enabled
+/// width: The width of the output in pixels
+/// height: The height of the output in pixels
+pub fn render_checkerboard_resolve<Rend: Renderer + ?Sized>(renderer: &mut Rend,
+                                                            checkerboard: &mut Checkerboard,
+                                                            width: u32,
+                                                            height: u32) {
+    let (render_width, _) = checkerboard.render_size(width, height);
+    renderer.set_uniform_buffer_float_vector(CHECKERBOARD_SETTINGS_BLOCK,
+                                             "checkerboard_viewport",
+                                             &vec![width as f32, height as f32, render_width as f32, 0.0f32]);
+    renderer.set_uniform_buffer_int(CHECKERBOARD_SETTINGS_BLOCK, "checkerboard_parity", checkerboard.parity());
+    renderer.set_uniform_buffer_int(CHECKERBOARD_SETTINGS_BLOCK,
+                                    "checkerboard_history_valid",
+                                    checkerboard.history_valid as i32);
+    renderer.synchronise_uniform_buffer(CHECKERBOARD_SETTINGS_BLOCK);
+
+    renderer.begin_pass(CHECKERBOARD_RESOLVE_SHADER_NAME);
+
+    draw_fullscreen_quad(renderer);
+
+    renderer.end_pass();
+
+    checkerboard.history_valid = true;
+}
@@ -0,0 +1,246 @@
+// Copyright (c) 2016-2017 Bruce Stenning. All rights reserved.
+//
+// Redistribution and use in source and binary forms, with or without
+// modification, are permitted provided that the following conditions
+// are met:
+//
+// 1. Redistributions of source code must retain the above copyright
+//    notice, this list of conditions and the following disclaimer.
+//
+// 2. Redistributions in binary form must reproduce the above copyright
+//    notice, this list of conditions and the following disclaimer in the
+//    documentation and/or other materials provided with the distribution.
+//
+// 3. Neither the name of the copyright holder nor the names of its
+//    contributors may be used to endorse or promote products derived
+//    from this software without specific prior written permission.
+//
+// THIS SOFTWARE IS PROVIDED BY THE COPYRIGHT HOLDERS AND CONTRIBUTORS
+// AS IS" AND ANY EXPRESS OR IMPLIED WARRANTIES, INCLUDING, BUT NOT
+// LIMITED TO, THE IMPLIED WARRANTIES OF MERCHANTABILITY AND FITNESS
+// FOR A PARTICULAR PURPOSE ARE DISCLAIMED. IN NO EVENT SHALL THE
+// COPYRIGHT HOLDER OR CONTRIBUTORS BE LIABLE FOR ANY DIRECT, INDIRECT,
+// INCIDENTAL, SPECIAL, EXEMPLARY, OR CONSEQUENTIAL DAMAGES (INCLUDING,
+// BUT NOT LIMITED TO, PROCUREMENT OF SUBSTITUTE GOODS OR SERVICES; LOSS
+// OF USE, DATA, OR PROFITS; OR BUSINESS INTERRUPTION) HOWEVER CAUSED
+// AND ON ANY THEORY OF LIABILITY, WHETHER IN CONTRACT, STRICT LIABILITY,
+// OR TORT (INCLUDING NEGLIGENCE OR OTHERWISE) ARISING IN ANY WAY OUT OF
+// THE USE OF THIS SOFTWARE, EVEN IF ADVISED OF THE POSSIBILITY OF SUCH
+// DAMAGE.
+
+use std::collections::HashMap;
+
+/// Identifies a paced compute job
+pub type ComputeJobHandle = u32;
+
+/// The push constant through which a slice receives the index of its first workgroup in x
+///
+/// A paced compute shader declares a uint of this name in its push constant block and adds it
+/// to gl_WorkGroupID.x.  Shaders without it are dispatched whole, as they cannot be sliced.
+pub const WORKGROUP_OFFSET_PUSH_CONSTANT: &'static str = "workgroup_offset";
+
+/// The default GPU time given over to paced compute work each frame
+pub const DEFAULT_COMPUTE_BUDGET_MS: f32 = 2.0;
+
+/// The most slices submitted in a frame, which bounds the timestamp queries needed
+pub const MAX_COMPUTE_SLICES_PER_FRAME: usize = 16;
+
+/// The assumed cost of a workgroup of a shader before any slice of it has been timed
+const INITIAL_WORKGROUP_COST_MS: f32 = 0.001;
+
+/// The weight given to each new timing in the running estimate of a shader's cost
+const WORKGROUP_COST_SMOOTHING: f32 = 0.25;
+
+/// A part of a paced dispatch, covering a range of workgroups in x and every workgroup in y and z
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub struct ComputeSlice {
+    pub job: ComputeJobHandle,
+    pub shader_name: &'static str,
+    pub first_x: u32,
+    pub x: u32,
+    pub y: u32,
+    pub z: u32,
+}
+
+impl ComputeSlice {
+    /// Return the number of workgroups in the slice
+    pub fn workgroups(&self) -> u64 {
+        self.x as u64 * self.y as u64 * self.z as u64
+    }
+}
+
+struct ComputeJob {
+    handle: ComputeJobHandle,
+    shader_name: &'static str,
+    x: u32,
+    y: u32,
+    z: u32,
+    sliceable: bool,
+    next_x: u32, // The first workgroup not yet submitted
+    completed_x: u32, // The number of workgroups whose slices have completed
+}
+
+/// Splits large compute dispatches into slices spread across frames within a GPU time budget
+///
+/// Each frame the renderer plans the slices to submit, and reports back the GPU time each one
+/// took once it has completed.  The cost of a workgroup of each shader is estimated from those
+/// timings, so the slices grow or shrink to fill the budget.  At least one slice is submitted
+/// every frame while there is work, so that jobs always make progress.
+pub struct ComputePacer {
+    budget_ms: f32,
+    jobs: Vec<ComputeJob>,
+    next_handle: ComputeJobHandle,
+    workgroup_cost_ms: HashMap<&'static str, f32>,
+}
+
+impl ComputePacer {
+    /// Create a pacer with the specified per-frame budget
+    ///
+    /// budget_ms: The GPU time to spend on paced compute work each frame, in milliseconds
+    pub fn new(budget_ms: f32) -> ComputePacer {
+        ComputePacer {
+            budget_ms: budget_ms,
+            jobs: vec![],
+            next_handle: 0,
+            workgroup_cost_ms: HashMap::new(),
+        }
+    }
+
+    /// Change the per-frame budget
+    ///
+    /// budget_ms: The GPU time to spend on paced compute work each frame, in milliseconds
+    pub fn set_budget_ms(&mut self, budget_ms: f32) {
+        self.budget_ms = budget_ms;
+    }
+
+    /// Return the per-frame budget in milliseconds
+    pub fn get_budget_ms(&self) -> f32 {
+        self.budget_ms
+    }
+
+    /// Queue a dispatch to be made in slices over the coming frames
+    ///
+    /// shader_name: The name of the compute shader
+    /// x: The number of workgroups in the x dimension
+    /// y: The number of workgroups in the y dimension
+    /// z: The number of workgroups in the z dimension
+    /// sliceable: Whether the shader takes the workgroup offset, without which it is dispatched whole
+    pub fn queue(&mut self, shader_name: &'static str, x: u32, y: u32, z: u32, sliceable: bool) -> ComputeJobHandle {
+        let handle = self.next_handle;
+        self.next_handle = self.next_handle.wrapping_add(1);
+
+        self.jobs.push(ComputeJob {
+            handle: handle,
+            shader_name: shader_name,
+            x: x,
+            y: y,
+            z: z,
+            sliceable: sliceable,
+            next_x: 0,
+            completed_x: 0,
+        });
+
+        handle
+    }
+
+    /// Return the estimated cost of a workgroup of a shader in milliseconds
+    ///
+    /// shader_name: The name of the compute shader
+    pub fn get_workgroup_cost_ms(&self, shader_name: &str) -> f32 {
+        *self.workgroup_cost_ms.get(shader_name).unwrap_or(&INITIAL_WORKGROUP_COST_MS)
+    }
+
+    /// Choose the slices to submit this frame, in the order the jobs were queued
+    pub fn plan_frame(&mut self) -> Vec<ComputeSlice> {
+        let mut slices = vec![];
+        let mut remaining_ms = self.budget_ms;
+
+        for job in self.jobs.iter_mut() {
+            if slices.len() == MAX_COMPUTE_SLICES_PER_FRAME || (remaining_ms <= 0.0 && !slices.is_empty()) {
+                break;
+            }
+            if job.next_x == job.x {
+                continue; // Everything submitted, waiting on completion
+            }
+
+            let columns_left = job.x - job.next_x;
+            let columns = if job.sliceable {
+                let cost = *self.workgroup_cost_ms.get(job.shader_name).unwrap_or(&INITIAL_WORKGROUP_COST_MS);
+                let column_cost_ms = (cost * job.y as f32 * job.z as f32).max(1.0e-6);
+                let affordable = (remaining_ms / column_cost_ms).floor().max(0.0) as u32;
+
+                // A frame always makes some progress, however expensive a column is
+                affordable.max(if slices.is_empty() { 1 } else { 0 }).min(columns_left)
+            } else {
+                columns_left
+            };
+            if columns == 0 {
+                break;
+            }
+
+            let slice = ComputeSlice {
+                job: job.handle,
+                shader_name: job.shader_name,
+                first_x: job.next_x,
+                x: columns,
+                y: job.y,
+                z: job.z,
+            };
+            remaining_ms -= self.workgroup_cost_ms.get(job.shader_name).unwrap_or(&INITIAL_WORKGROUP_COST_MS) *
+                            slice.workgroups() as f32;
+            job.next_x += columns;
+            slices.push(slice);
+        }
+
+        slices
+    }
+
+    /// Record that a slice has completed, refining the cost estimate if it was timed
+    ///
+    /// slice: The slice, as returned by plan_frame
+    /// gpu_ms: The GPU time the slice took, if known
+    pub fn slice_completed(&mut self, slice: &ComputeSlice, gpu_ms: Option<f32>) {
+        if let Some(ms) = gpu_ms {
+            if slice.workgroups() > 0 {
+                let sample = ms / slice.workgroups() as f32;
+                let estimate = match self.workgroup_cost_ms.get(slice.shader_name) {
+                    Some(&cost) => cost + (sample - cost) * WORKGROUP_COST_SMOOTHING,
+                    None => sample,
+                };
+                self.workgroup_cost_ms.insert(slice.shader_name, estimate);
+            }
+        }
+
+        if let Some(job) = self.jobs.iter_mut().find(|j| j.handle == slice.job) {
+            job.completed_x += slice.x;
+        }
+        self.jobs.retain(|j| j.completed_x < j.x);
+    }
+
+    /// Return the fraction of a job that has completed, from 0 to 1
+    ///
+    /// Jobs are forgotten once complete, so an unknown handle is reported as complete.
+    ///
+    /// handle: The handle returned by queue
+    pub fn progress(&self, handle: ComputeJobHandle) -> f32 {
+        match self.jobs.iter().find(|j| j.handle == handle) {
+            Some(job) => job.completed_x as f32 / job.x.max(1) as f32,
+            None => 1.0,
+        }
+    }
+
+    /// Return the fraction of all queued work that has completed, from 0 to 1, for loading screens
+    pub fn overall_progress(&self) -> f32 {
+        let total: u64 = self.jobs.iter().map(|j| j.x as u64 * j.y as u64 * j.z as u64).sum();
+        if total == 0 {
+            return 1.0;
+        }
+        let completed: u64 = self.jobs.iter().map(|j| j.completed_x as u64 * j.y as u64 * j.z as u64).sum();
+        completed as f32 / total as f32
+    }
+
+    /// Is there no paced work left to submit or complete?
+    pub fn is_idle(&self) -> bool {
+        self.jobs.is_empty()
+    }
+}
@@ -0,0 +1,157 @@
+// Copyright (c) 2016-2017 Bruce Stenning. All rights reserved.
+//
+// Redistribution and use in source and binary forms, with or without
+// modification, are permitted provided that the following conditions
+// are met:
+//
+// 1. Redistributions of source code must retain the above copyright
+//    notice, this list of conditions and the following disclaimer.
+//
+// 2. Redistributions in binary form must reproduce the above copyright
+//    notice, this list of conditions and the following disclaimer in the
+//    documentation and/or other materials provided with the distribution.
+//
+// 3. Neither the name of the copyright holder nor the names of its
+//    contributors may be used to endorse or promote products derived
+//    from this software without specific prior written permission.
+//
+// THIS SOFTWARE IS PROVIDED BY THE COPYRIGHT HOLDERS AND CONTRIBUTORS
+// AS IS" AND ANY EXPRESS OR IMPLIED WARRANTIES, INCLUDING, BUT NOT
+// LIMITED TO, THE IMPLIED WARRANTIES OF MERCHANTABILITY AND FITNESS
+// FOR A PARTICULAR PURPOSE ARE DISCLAIMED. IN NO EVENT SHALL THE
+// COPYRIGHT HOLDER OR CONTRIBUTORS BE LIABLE FOR ANY DIRECT, INDIRECT,
+// INCIDENTAL, SPECIAL, EXEMPLARY, OR CONSEQUENTIAL DAMAGES (INCLUDING,
+// BUT NOT LIMITED TO, PROCUREMENT OF SUBSTITUTE GOODS OR SERVICES; LOSS
+// OF USE, DATA, OR PROFITS; OR BUSINESS INTERRUPTION) HOWEVER CAUSED
+// AND ON ANY THEORY OF LIABILITY, WHETHER IN CONTRACT, STRICT LIABILITY,
+// OR TORT (INCLUDING NEGLIGENCE OR OTHERWISE) ARISING IN ANY WAY OUT OF
+// THE USE OF THIS SOFTWARE, EVEN IF ADVISED OF THE POSSIBILITY OF SUCH
+// DAMAGE.
+use graphics::pbr::*;
+use graphics::renderer::*;
+use graphics::resources::*;
+
+/// The name of the uniform block holding the contact shadow settings, declared by the PBR shader
+pub const CONTACT_SHADOW_BLOCK: &'static str = "pbr_contact_shadows";
+
+/// The name of the texture the scene depth is sampled from by the PBR shader
+///
+/// The lighting pass cannot sample the depth buffer it writes, so this is a depth drawn before
+/// it, such as level 0 of the Hi-Z pyramid built after a depth prepass.
+pub const CONTACT_SHADOW_DEPTH_TEXTURE: &'static str = "pbr_contact_shadow_depth";
+
+/// The most steps a contact shadow ray is marched
+pub const CONTACT_SHADOW_MAX_STEPS: i32 = 64;
+
+/// Presets trading the accuracy of the contact shadows for their cost
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub enum ContactShadowQuality {
+    Low,
+    Medium,
+    High,
+}
+
+/// Settings for the short-range shadows the PBR shader marches towards each light
+#[derive(Clone, Copy, Debug)]
+pub struct ContactShadowSettings {
+    pub enabled: bool,
+    /// The distance each ray is marched towards the light, in world units
+    pub length: f32,
+    /// The number of steps the ray is marched in
+    pub steps: i32,
+    /// How far behind the depth a ray may pass and still be shadowed, in view space units
+    pub thickness: f32,
+    /// How dark the shadows are, from 0 for none to 1 for black
+    pub intensity: f32,
+}
+
+impl ContactShadowSettings {
+    /// Return the settings of a quality preset
+    ///
+    /// quality: The preset
+    pub fn from_quality(quality: ContactShadowQuality) -> ContactShadowSettings {
+        let steps = match quality {
+            ContactShadowQuality::Low => 8,
+            ContactShadowQuality::Medium => 16,
+            ContactShadowQuality::High => 32,
+        };
+
+        ContactShadowSettings {
+            enabled: true,
+            length: 0.5f32,
+            steps: steps,
+            thickness: 0.1f32,
+            intensity: 0.8f32,
+        }
+    }
+
+    /// Return the number of steps the shader marches, limited to CONTACT_SHADOW_MAX_STEPS and
+    /// none when disabled
+    pub fn step_count(&self) -> i32 {
+        if self.enabled {
+            self.steps.max(0).min(CONTACT_SHADOW_MAX_STEPS)
+        } else {
+            0
+        }
+    }
+}
+
+impl Default for ContactShadowSettings {
+    fn default() -> ContactShadowSettings {
+        ContactShadowSettings::from_quality(ContactShadowQuality::Medium)
+    }
+}
+
+/// Add the uniform block of the contact shadow settings, whose members are filled in by the
+/// reflection data
+///
+/// This is registered along with the PBR shader.
+///
+/// resource_manager: The resource manager to add the specification to
+pub fn register_contact_shadow_block(resource_manager: &mut ResourceManager) {
+    let block_spec = UniformBlockSpec {
+        set: 0,
+        block_type: UniformType::UniformBuffer,
+        uniforms: ["pbr_contact_shadow_length",
+                   "pbr_contact_shadow_thickness",
+                   "pbr_contact_shadow_intensity",
+                   "pbr_contact_shadow_steps",
+                   "pbr_contact_shadow_light_mask"]
+            .iter()
+            .map(|name| {
+                BlockUniformSpec {
+                    name: *name,
+                    ..Default::default()
+                }
+            })
+            .collect(),
+        ..Default::default()
+    };
+    resource_manager.uniform_block_specs.insert(CONTACT_SHADOW_BLOCK, block_spec);
+}
+
+/// Set the contact shadow settings, and the lights casting them, in their uniform block
+///
+/// This should be called whenever the settings change or a light's contact shadows are toggled.
+///
+/// renderer: The renderer owning the uniform buffer
+/// settings: The contact shadow settings
+/// lights: The lights of the scene, whose contact_shadows flags select the lights casting them
+pub fn synchronise_contact_shadows<Rend: Renderer + ?Sized>(renderer: &Rend,
+                                                            settings: &ContactShadowSettings,
+                                                            lights: &LightManager) {
+    let light_mask = if settings.enabled {
+        lights.contact_shadow_mask()
+    } else {
+        0
+    };
+
+    renderer.set_uniform_buffer_float(CONTACT_SHADOW_BLOCK, "pbr_contact_shadow_length", settings.length);
+    renderer.set_uniform_buffer_float(CONTACT_SHADOW_BLOCK, "pbr_contact_shadow_thickness", settings.thickness);
+    renderer.set_uniform_buffer_float(CONTACT_SHADOW_BLOCK,
+                                      "pbr_contact_shadow_intensity",
+                                      settings.intensity.max(0.0f32).min(1.0f32));
+    renderer.set_uniform_buffer_int(CONTACT_SHADOW_BLOCK, "pbr_contact_shadow_steps", settings.step_count());
+    renderer.set_uniform_buffer_int(CONTACT_SHADOW_BLOCK, "pbr_contact_shadow_light_mask", light_mask);
+    renderer.synchronise_uniform_buffer(CONTACT_SHADOW_BLOCK);
+}
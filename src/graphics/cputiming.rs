@@ -0,0 +1,334 @@
+// Copyright (c) 2016-2017 Bruce Stenning. All rights reserved.
+//
+// Redistribution and use in source and binary forms, with or without
+// modification, are permitted provided that the following conditions
+// are met:
+//
+// 1. Redistributions of source code must retain the above copyright
+//    notice, this list of conditions and the following disclaimer.
+//
+// 2. Redistributions in binary form must reproduce the above copyright
+//    notice, this list of conditions and the following disclaimer in the
+//    documentation and/or other materials provided with the distribution.
+//
+// 3. Neither the name of the copyright holder nor the names of its
+//    contributors may be used to endorse or promote products derived
+//    from this software without specific prior written permission.
+//
+// THIS SOFTWARE IS PROVIDED BY THE COPYRIGHT HOLDERS AND CONTRIBUTORS
+// AS IS" AND ANY EXPRESS OR IMPLIED WARRANTIES, INCLUDING, BUT NOT
+// LIMITED TO, THE IMPLIED WARRANTIES OF MERCHANTABILITY AND FITNESS
+// FOR A PARTICULAR PURPOSE ARE DISCLAIMED. IN NO EVENT SHALL THE
+// COPYRIGHT HOLDER OR CONTRIBUTORS BE LIABLE FOR ANY DIRECT, INDIRECT,
+// INCIDENTAL, SPECIAL, EXEMPLARY, OR CONSEQUENTIAL DAMAGES (INCLUDING,
+// BUT NOT LIMITED TO, PROCUREMENT OF SUBSTITUTE GOODS OR SERVICES; LOSS
+// OF USE, DATA, OR PROFITS; OR BUSINESS INTERRUPTION) HOWEVER CAUSED
+// AND ON ANY THEORY OF LIABILITY, WHETHER IN CONTRACT, STRICT LIABILITY,
+// OR TORT (INCLUDING NEGLIGENCE OR OTHERWISE) ARISING IN ANY WAY OUT OF
+// THE USE OF THIS SOFTWARE, EVEN IF ADVISED OF THE POSSIBILITY OF SUCH
+// DAMAGE.
+
+use std::cell::RefCell;
+use std::collections::VecDeque;
+use std::io;
+use std::sync::Mutex;
+use std::sync::atomic::{AtomicBool, Ordering};
+
+use time::precise_time_ns;
+
+use misc::fileutils::*;
+
+/// The number of frames of scope timings kept
+pub const CPU_TIMING_HISTORY_FRAMES: usize = 32;
+
+/// The most scopes a thread records in a frame, after which scopes are counted but not timed
+pub const MAX_CPU_SCOPES_PER_THREAD: usize = 4096;
+
+// Profiling is switched on and off for every thread at once, as the scopes have no access to
+// the renderer
+static CPU_PROFILING_ENABLED: AtomicBool = AtomicBool::new(false);
+
+thread_local!(static CPU_SCOPES: RefCell<CpuScopeRecorder> = RefCell::new(CpuScopeRecorder::new()));
+
+/// The CPU time of one scope
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub struct CpuScope {
+    pub name: &'static str,
+    pub thread: usize, // The rendering thread, or the number of rendering threads for the thread that ends the frame
+    pub depth: u32, // The number of scopes it is nested in
+    pub start_ns: u64, // Of the system's monotonic clock
+    pub duration_ns: u64,
+}
+
+impl CpuScope {
+    /// Return the duration in milliseconds
+    pub fn ms(&self) -> f32 {
+        self.duration_ns as f32 / 1.0e6
+    }
+}
+
+/// Records the scopes timed on one thread, in the order they began
+pub struct CpuScopeRecorder {
+    scopes: Vec<CpuScope>,
+    open: Vec<Option<usize>>, // The index of each scope not yet ended, None for those not recorded
+    dropped: u32,
+}
+
+impl CpuScopeRecorder {
+    pub fn new() -> CpuScopeRecorder {
+        CpuScopeRecorder {
+            scopes: vec![],
+            open: vec![],
+            dropped: 0,
+        }
+    }
+
+    /// Begin a scope, nested in any that have begun and not ended
+    ///
+    /// name: The name of the scope
+    /// now_ns: The time the scope began
+    pub fn begin(&mut self, name: &'static str, now_ns: u64) {
+        if self.scopes.len() >= MAX_CPU_SCOPES_PER_THREAD {
+            self.dropped += 1;
+            self.open.push(None);
+            return;
+        }
+
+        self.scopes.push(CpuScope {
+            name: name,
+            thread: 0,
+            depth: self.open.len() as u32,
+            start_ns: now_ns,
+            duration_ns: 0,
+        });
+        self.open.push(Some(self.scopes.len() - 1));
+    }
+
+    /// End the most recently begun scope
+    ///
+    /// now_ns: The time the scope ended
+    pub fn end(&mut self, now_ns: u64) {
+        if let Some(Some(index)) = self.open.pop() {
+            let ref mut scope = self.scopes[index];
+            scope.duration_ns = now_ns.saturating_sub(scope.start_ns);
+        }
+    }
+
+    /// Take the scopes recorded and the number dropped, leaving the recorder empty
+    ///
+    /// Scopes that have not ended are taken with no duration.
+    pub fn take(&mut self) -> (Vec<CpuScope>, u32) {
+        let dropped = self.dropped;
+        self.dropped = 0;
+        self.open.clear();
+
+        (self.scopes.drain(..).collect(), dropped)
+    }
+}
+
+/// Times the scope it lives in on the current thread, from its creation until it is dropped
+///
+/// Bind it to a named variable, as `let _ = profile_scope(..)` drops it straight away.
+pub struct CpuScopeTimer {
+    recording: bool,
+}
+
+impl Drop for CpuScopeTimer {
+    fn drop(&mut self) {
+        if self.recording {
+            CPU_SCOPES.with(|scopes| scopes.borrow_mut().end(precise_time_ns()));
+        }
+    }
+}
+
+/// Begin timing a scope on the current thread, which costs next to nothing while profiling is off
+///
+/// The scopes of the rendering threads are gathered when mt_render_harness returns, and those
+/// of the thread ending the frame by end_frame, see Renderer::cpu_profiler.
+///
+/// name: The name of the scope
+pub fn profile_scope(name: &'static str) -> CpuScopeTimer {
+    let recording = CPU_PROFILING_ENABLED.load(Ordering::Relaxed);
+    if recording {
+        CPU_SCOPES.with(|scopes| scopes.borrow_mut().begin(name, precise_time_ns()));
+    }
+
+    CpuScopeTimer { recording: recording }
+}
+
+/// Return whether scopes are being timed
+pub fn cpu_profiling_enabled() -> bool {
+    CPU_PROFILING_ENABLED.load(Ordering::Relaxed)
+}
+
+/// The CPU times of the scopes of one frame, across all of the threads
+#[derive(Clone, Debug, PartialEq)]
+pub struct CpuFrameTimings {
+    pub frame: u64, // The frame number the scopes were timed in
+    pub scopes: Vec<CpuScope>, // In the order they began
+    pub dropped: u32, // The scopes beyond MAX_CPU_SCOPES_PER_THREAD, which are left out
+}
+
+impl CpuFrameTimings {
+    /// Return the total time of the scopes of a name, across all of the threads
+    ///
+    /// name: The name of the scopes
+    pub fn scope_ms(&self, name: &str) -> f32 {
+        self.scopes.iter().filter(|scope| scope.name == name).map(|scope| scope.ms()).sum()
+    }
+
+    /// Return the time a thread spent in scopes that are not nested in others
+    ///
+    /// thread: The thread
+    pub fn thread_ms(&self, thread: usize) -> f32 {
+        self.scopes.iter().filter(|scope| scope.thread == thread && scope.depth == 0).map(|scope| scope.ms()).sum()
+    }
+
+    /// Return the threads that timed scopes, in ascending order
+    pub fn threads(&self) -> Vec<usize> {
+        let mut threads: Vec<usize> = self.scopes.iter().map(|scope| scope.thread).collect();
+        threads.sort();
+        threads.dedup();
+        threads
+    }
+}
+
+/// Gathers the scopes timed on each thread into the timings of recent frames
+pub struct CpuProfiler {
+    submitted: Mutex<(Vec<CpuScope>, u32)>, // This frame's scopes and the number dropped
+    frames: VecDeque<CpuFrameTimings>,
+    capacity: usize,
+}
+
+impl CpuProfiler {
+    /// Create a profiler with an empty history
+    ///
+    /// capacity: The number of frames kept, after which the oldest are discarded
+    pub fn new(capacity: usize) -> CpuProfiler {
+        CpuProfiler {
+            submitted: Mutex::new((vec![], 0)),
+            frames: VecDeque::with_capacity(capacity),
+            capacity: capacity,
+        }
+    }
+
+    /// Switch the timing of scopes on or off, on every thread
+    ///
+    /// enabled: Whether scopes are timed
+    pub fn set_enabled(&self, enabled: bool) {
+        CPU_PROFILING_ENABLED.store(enabled, Ordering::Relaxed);
+    }
+
+    /// Return whether scopes are being timed
+    pub fn is_enabled(&self) -> bool {
+        cpu_profiling_enabled()
+    }
+
+    /// Add scopes timed on a thread to those of the current frame
+    ///
+    /// thread: The thread the scopes were timed on
+    /// scopes: The scopes
+    /// dropped: The number of scopes that were not recorded
+    pub fn submit(&self, thread: usize, scopes: Vec<CpuScope>, dropped: u32) {
+        let mut submitted = self.submitted.lock().unwrap();
+        submitted.0.extend(scopes.into_iter().map(|scope| CpuScope { thread: thread, ..scope }));
+        submitted.1 += dropped;
+    }
+
+    /// Add the scopes timed on the current thread since they were last gathered
+    ///
+    /// thread: The number to give the current thread
+    pub fn submit_current_thread(&self, thread: usize) {
+        let (scopes, dropped) = CPU_SCOPES.with(|recorder| recorder.borrow_mut().take());
+        if !scopes.is_empty() || dropped > 0 {
+            self.submit(thread, scopes, dropped);
+        }
+    }
+
+    /// Gather the scopes of the frame into its timings, discarding the oldest frame if the history is full
+    ///
+    /// Nothing is kept for a frame without scopes, so the history holds the frames last profiled.
+    ///
+    /// frame: The frame number
+    /// thread: The number to give the thread ending the frame
+    pub fn end_frame(&mut self, frame: u64, thread: usize) {
+        self.submit_current_thread(thread);
+
+        let (mut scopes, dropped) = {
+            let mut submitted = self.submitted.lock().unwrap();
+            let dropped = submitted.1;
+            submitted.1 = 0;
+            (submitted.0.drain(..).collect::<Vec<CpuScope>>(), dropped)
+        };
+        if scopes.is_empty() && dropped == 0 {
+            return;
+        }
+        scopes.sort_by_key(|scope| (scope.start_ns, scope.depth));
+
+        if self.frames.len() == self.capacity {
+            self.frames.pop_front();
+        }
+        self.frames.push_back(CpuFrameTimings {
+            frame: frame,
+            scopes: scopes,
+            dropped: dropped,
+        });
+    }
+
+    /// Return the timings of the most recent frame profiled
+    pub fn latest(&self) -> Option<&CpuFrameTimings> {
+        self.frames.back()
+    }
+
+    /// Return the frames kept, oldest first
+    pub fn frames(&self) -> &VecDeque<CpuFrameTimings> {
+        &self.frames
+    }
+
+    /// Return the average time per frame of the scopes of a name, over the frames kept
+    ///
+    /// name: The name of the scopes
+    pub fn average_scope_ms(&self, name: &str) -> Option<f32> {
+        let times: Vec<f32> = self.frames
+            .iter()
+            .filter(|frame| frame.scopes.iter().any(|scope| scope.name == name))
+            .map(|frame| frame.scope_ms(name))
+            .collect();
+
+        if times.is_empty() {
+            None
+        } else {
+            Some(times.iter().sum::<f32>() / times.len() as f32)
+        }
+    }
+
+    /// Write the frames kept as a trace that chrome://tracing can load
+    ///
+    /// filename: The file to write
+    pub fn write_chrome_trace(&self, filename: &str) -> Result<(), io::Error> {
+        write_entire_file(&chrome_trace_json(self.frames.iter()), filename)
+    }
+}
+
+/// Format the scopes of frames as a trace in the Trace Event Format of chrome://tracing
+///
+/// Each scope becomes a complete event on the track of its thread, with times in microseconds
+/// from the start of the first scope.
+///
+/// frames: The frames to include
+pub fn chrome_trace_json<'a, I: Iterator<Item = &'a CpuFrameTimings>>(frames: I) -> String {
+    let scopes: Vec<&CpuScope> = frames.flat_map(|frame| frame.scopes.iter()).collect();
+    let origin_ns = scopes.iter().map(|scope| scope.start_ns).min().unwrap_or(0);
+
+    let events: Vec<String> = scopes.iter()
+        .map(|scope| {
+            format!("{{\"name\":\"{}\",\"cat\":\"cpu\",\"ph\":\"X\",\"ts\":{:.3},\"dur\":{:.3},\"pid\":0,\"tid\":{}}}",
+                    scope.name.replace('\\', "\\\\").replace('"', "\\\""),
+                    (scope.start_ns - origin_ns) as f64 / 1.0e3,
+                    scope.duration_ns as f64 / 1.0e3,
+                    scope.thread)
+        })
+        .collect();
+
+    format!("{{\"traceEvents\":[\n{}\n],\"displayTimeUnit\":\"ms\"}}\n", events.join(",\n"))
+}
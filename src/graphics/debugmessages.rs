@@ -0,0 +1,181 @@
+// Copyright (c) 2016-2017 Bruce Stenning. All rights reserved.
+//
+// Redistribution and use in source and binary forms, with or without
+// modification, are permitted provided that the following conditions
+// are met:
+//
+// 1. Redistributions of source code must retain the above copyright
+//    notice, this list of conditions and the following disclaimer.
+//
+// 2. Redistributions in binary form must reproduce the above copyright
+//    notice, this list of conditions and the following disclaimer in the
+//    documentation and/or other materials provided with the distribution.
+//
+// 3. Neither the name of the copyright holder nor the names of its
+//    contributors may be used to endorse or promote products derived
+//    from this software without specific prior written permission.
+//
+// THIS SOFTWARE IS PROVIDED BY THE COPYRIGHT HOLDERS AND CONTRIBUTORS
+// AS IS" AND ANY EXPRESS OR IMPLIED WARRANTIES, INCLUDING, BUT NOT
+// LIMITED TO, THE IMPLIED WARRANTIES OF MERCHANTABILITY AND FITNESS
+// FOR A PARTICULAR PURPOSE ARE DISCLAIMED. IN NO EVENT SHALL THE
+// COPYRIGHT HOLDER OR CONTRIBUTORS BE LIABLE FOR ANY DIRECT, INDIRECT,
+// INCIDENTAL, SPECIAL, EXEMPLARY, OR CONSEQUENTIAL DAMAGES (INCLUDING,
+// BUT NOT LIMITED TO, PROCUREMENT OF SUBSTITUTE GOODS OR SERVICES; LOSS
+// OF USE, DATA, OR PROFITS; OR BUSINESS INTERRUPTION) HOWEVER CAUSED
+// AND ON ANY THEORY OF LIABILITY, WHETHER IN CONTRACT, STRICT LIABILITY,
+// OR TORT (INCLUDING NEGLIGENCE OR OTHERWISE) ARISING IN ANY WAY OUT OF
+// THE USE OF THIS SOFTWARE, EVEN IF ADVISED OF THE POSSIBILITY OF SUCH
+// DAMAGE.
+
+use misc::logging::*;
+
+/// The bit of the Vulkan debug mask enabling informational messages
+pub const DEBUG_MASK_INFO: u32 = 1;
+
+/// The bit of the Vulkan debug mask enabling warnings
+pub const DEBUG_MASK_WARNING: u32 = 2;
+
+/// The bit of the Vulkan debug mask enabling performance warnings
+pub const DEBUG_MASK_PERFORMANCE: u32 = 4;
+
+/// The bit of the Vulkan debug mask enabling errors
+pub const DEBUG_MASK_ERROR: u32 = 8;
+
+/// The bit of the Vulkan debug mask enabling verbose diagnostics of the loader and layers
+pub const DEBUG_MASK_DEBUG: u32 = 16;
+
+// VkDebugUtilsMessageSeverityFlagBitsEXT
+pub const VK_DEBUG_UTILS_MESSAGE_SEVERITY_VERBOSE_BIT_EXT: u32 = 0x0001;
+pub const VK_DEBUG_UTILS_MESSAGE_SEVERITY_INFO_BIT_EXT: u32 = 0x0010;
+pub const VK_DEBUG_UTILS_MESSAGE_SEVERITY_WARNING_BIT_EXT: u32 = 0x0100;
+pub const VK_DEBUG_UTILS_MESSAGE_SEVERITY_ERROR_BIT_EXT: u32 = 0x1000;
+
+// VkDebugUtilsMessageTypeFlagBitsEXT
+pub const VK_DEBUG_UTILS_MESSAGE_TYPE_GENERAL_BIT_EXT: u32 = 0x1;
+pub const VK_DEBUG_UTILS_MESSAGE_TYPE_VALIDATION_BIT_EXT: u32 = 0x2;
+pub const VK_DEBUG_UTILS_MESSAGE_TYPE_PERFORMANCE_BIT_EXT: u32 = 0x4;
+
+/// The severity of a message from the Vulkan loader or layers
+#[derive(Clone, Copy, Debug, PartialEq, Eq, PartialOrd, Ord)]
+pub enum DebugMessageSeverity {
+    Verbose,
+    Info,
+    Warning,
+    Error,
+}
+
+impl DebugMessageSeverity {
+    /// Return the severity of a set of VkDebugUtilsMessageSeverityFlagBitsEXT, taking the highest
+    ///
+    /// bits: The severity bits
+    pub fn from_vk(bits: u32) -> DebugMessageSeverity {
+        if (bits & VK_DEBUG_UTILS_MESSAGE_SEVERITY_ERROR_BIT_EXT) != 0 {
+            DebugMessageSeverity::Error
+        } else if (bits & VK_DEBUG_UTILS_MESSAGE_SEVERITY_WARNING_BIT_EXT) != 0 {
+            DebugMessageSeverity::Warning
+        } else if (bits & VK_DEBUG_UTILS_MESSAGE_SEVERITY_INFO_BIT_EXT) != 0 {
+            DebugMessageSeverity::Info
+        } else {
+            DebugMessageSeverity::Verbose
+        }
+    }
+
+    /// Return the bit of the Vulkan debug mask enabling messages of this severity
+    pub fn debug_mask_bit(&self) -> u32 {
+        match *self {
+            DebugMessageSeverity::Verbose => DEBUG_MASK_DEBUG,
+            DebugMessageSeverity::Info => DEBUG_MASK_INFO,
+            DebugMessageSeverity::Warning => DEBUG_MASK_WARNING,
+            DebugMessageSeverity::Error => DEBUG_MASK_ERROR,
+        }
+    }
+}
+
+/// A message from the Vulkan loader or layers, such as the validation layer
+#[derive(Clone, Debug, PartialEq)]
+pub struct DebugMessage {
+    pub severity: DebugMessageSeverity,
+    pub validation: bool, // Whether the message reports a violation of the specification
+    pub performance: bool, // Whether the message reports a potentially non-optimal use of the API
+    pub id_name: String, // The validation layer's identifier of the check, if any
+    pub id_number: i32,
+    pub message: String,
+}
+
+impl DebugMessage {
+    /// Return the short tag printed before the message
+    pub fn prefix(&self) -> &'static str {
+        if self.performance {
+            return "PERF";
+        }
+
+        match self.severity {
+            DebugMessageSeverity::Verbose => "DEBG",
+            DebugMessageSeverity::Info => "INFO",
+            DebugMessageSeverity::Warning => "WARN",
+            DebugMessageSeverity::Error => "ERRR",
+        }
+    }
+}
+
+/// A function the messages of the Vulkan loader and layers are passed to
+pub type DebugMessageCallback = Box<Fn(&DebugMessage) + Send>;
+
+/// The callback messages are passed to unless the application sets another, which logs them
+///
+/// They are logged as errors or warnings, as vk_debug_mask has already selected them.
+///
+/// message: The message
+pub fn log_debug_message(message: &DebugMessage) {
+    let level = if message.severity == DebugMessageSeverity::Error {
+        LogLevel::Error
+    } else {
+        LogLevel::Warning
+    };
+    log(level, format_args!("{}: @[{}]: {}", message.prefix(), message.id_name, message.message));
+}
+
+/// Return the severities and types of message to ask the messenger for, given the debug mask
+///
+/// These are a superset of the messages wanted, as performance warnings can only be asked for
+/// with warnings, so messages are checked again with debug_message_wanted.
+///
+/// debug_mask: The Vulkan debug mask, made from the DEBUG_MASK bits
+pub fn messenger_filter(debug_mask: u32) -> (u32, u32) {
+    let mut severities = 0;
+    if (debug_mask & DEBUG_MASK_DEBUG) != 0 {
+        severities |= VK_DEBUG_UTILS_MESSAGE_SEVERITY_VERBOSE_BIT_EXT;
+    }
+    if (debug_mask & DEBUG_MASK_INFO) != 0 {
+        severities |= VK_DEBUG_UTILS_MESSAGE_SEVERITY_INFO_BIT_EXT;
+    }
+    if (debug_mask & (DEBUG_MASK_WARNING | DEBUG_MASK_PERFORMANCE)) != 0 {
+        severities |= VK_DEBUG_UTILS_MESSAGE_SEVERITY_WARNING_BIT_EXT;
+    }
+    if (debug_mask & DEBUG_MASK_ERROR) != 0 {
+        severities |= VK_DEBUG_UTILS_MESSAGE_SEVERITY_ERROR_BIT_EXT;
+    }
+
+    let mut types = VK_DEBUG_UTILS_MESSAGE_TYPE_GENERAL_BIT_EXT | VK_DEBUG_UTILS_MESSAGE_TYPE_VALIDATION_BIT_EXT;
+    if (debug_mask & DEBUG_MASK_PERFORMANCE) != 0 {
+        types |= VK_DEBUG_UTILS_MESSAGE_TYPE_PERFORMANCE_BIT_EXT;
+    }
+
+    (severities, types)
+}
+
+/// Return whether the debug mask asks for a message
+///
+/// Performance warnings are wanted when the performance bit is set, and other messages when
+/// the bit of their severity is.
+///
+/// debug_mask: The Vulkan debug mask, made from the DEBUG_MASK bits
+/// message: The message
+pub fn debug_message_wanted(debug_mask: u32, message: &DebugMessage) -> bool {
+    if message.performance && !message.validation {
+        (debug_mask & DEBUG_MASK_PERFORMANCE) != 0
+    } else {
+        (debug_mask & message.severity.debug_mask_bit()) != 0
+    }
+}
@@ -0,0 +1,398 @@
+// Copyright (c) 2016-2017 Bruce Stenning. All rights reserved.
+//
+// Redistribution and use in source and binary forms, with or without
+// modification, are permitted provided that the following conditions
+// are met:
+//
+// 1. Redistributions of source code must retain the above copyright
+//    notice, this list of conditions and the following disclaimer.
+//
+// 2. Redistributions in binary form must reproduce the above copyright
+//    notice, this list of conditions and the following disclaimer in the
+//    documentation and/or other materials provided with the distribution.
+//
+// 3. Neither the name of the copyright holder nor the names of its
+//    contributors may be used to endorse or promote products derived
+//    from this software without specific prior written permission.
+//
+// THIS SOFTWARE IS PROVIDED BY THE COPYRIGHT HOLDERS AND CONTRIBUTORS
+// AS IS" AND ANY EXPRESS OR IMPLIED WARRANTIES, INCLUDING, BUT NOT
+// LIMITED TO, THE IMPLIED WARRANTIES OF MERCHANTABILITY AND FITNESS
+// FOR A PARTICULAR PURPOSE ARE DISCLAIMED. IN NO EVENT SHALL THE
+// COPYRIGHT HOLDER OR CONTRIBUTORS BE LIABLE FOR ANY DIRECT, INDIRECT,
+// INCIDENTAL, SPECIAL, EXEMPLARY, OR CONSEQUENTIAL DAMAGES (INCLUDING,
+// BUT NOT LIMITED TO, PROCUREMENT OF SUBSTITUTE GOODS OR SERVICES; LOSS
+// OF USE, DATA, OR PROFITS; OR BUSINESS INTERRUPTION) HOWEVER CAUSED
+// AND ON ANY THEORY OF LIABILITY, WHETHER IN CONTRACT, STRICT LIABILITY,
+// OR TORT (INCLUDING NEGLIGENCE OR OTHERWISE) ARISING IN ANY WAY OUT OF
+// THE USE OF THIS SOFTWARE, EVEN IF ADVISED OF THE POSSIBILITY OF SUCH
+// DAMAGE.
+
+use std::collections::HashMap;
+use std::io;
+
+use algebra::matrix::Mat4;
+use algebra::vector::Vec3;
+use graphics::renderer::*;
+use graphics::resources::*;
+use graphics::shader::*;
+use graphics::texture::*;
+use misc::fileutils::*;
+
+/// The name the built-in decal shader is registered under
+pub const DECAL_SHADER_NAME: &'static str = "decal";
+
+/// The name of the uniform block holding the camera matrices, viewport size and light direction
+pub const DECAL_CAMERA_BLOCK: &'static str = "decal_camera";
+
+/// The name of the texture the scene depth buffer is sampled from
+pub const DECAL_DEPTH_TEXTURE: &'static str = "decal_depth_texture";
+
+/// The names of the albedo and normal atlas textures
+pub const DECAL_ATLAS_TEXTURE_NAMES: [&'static str; 2] = ["decal_albedo_atlas", "decal_normal_atlas"];
+
+/// The name of the fragment output of the decal shader
+pub const DECAL_FRAGMENT_OUT: &'static str = "decal_colour";
+
+const DECAL_VERTEX_FILENAME: &'static str = "decal.vert";
+const DECAL_FRAGMENT_FILENAME: &'static str = "decal.frag";
+
+const DECAL_VERTEX_SOURCE: &'static str = include_str!("shaders/decal.vert");
+const DECAL_FRAGMENT_SOURCE: &'static str = include_str!("shaders/decal.frag");
+
+// The corners and triangles of the unit box a decal projects through, before its transform
+const DECAL_BOX_CORNERS: [[f32; 3]; 8] = [[-0.5, -0.5, -0.5],
+                                          [0.5, -0.5, -0.5],
+                                          [0.5, 0.5, -0.5],
+                                          [-0.5, 0.5, -0.5],
+                                          [-0.5, -0.5, 0.5],
+                                          [0.5, -0.5, 0.5],
+                                          [0.5, 0.5, 0.5],
+                                          [-0.5, 0.5, 0.5]];
+const DECAL_BOX_TRIANGLES: [[usize; 3]; 12] = [[0, 2, 1], [0, 3, 2], [4, 5, 6], [4, 6, 7], [0, 1, 5], [0, 5, 4], [3, 6, 2],
+                                               [3, 7, 6], [0, 4, 7], [0, 7, 3], [1, 2, 6], [1, 6, 5]];
+
+/// Identifies a decal added to a decal manager
+pub type DecalHandle = u32;
+
+/// A box decal
+///
+/// The decal is projected through the unit box from -0.5 to 0.5 on each axis, placed in the
+/// world by the transform.  The texture is projected down the local y axis, with local x and z
+/// mapping to u and v.
+#[derive(Clone, Copy)]
+pub struct Decal {
+    pub transform: Mat4<f32>,
+    pub atlas: &'static str,
+    pub atlas_rect: [f32; 4],
+    pub albedo_opacity: f32,
+    pub normal_strength: f32,
+}
+
+/// A pair of albedo and normal textures holding the images of a batch of decals
+pub struct DecalAtlas<'a> {
+    pub albedo: &'a Box<Texture>,
+    pub normal: &'a Box<Texture>,
+}
+
+/// Holds the decals in a scene and renders them after the opaque geometry
+pub struct DecalManager {
+    decals: HashMap<DecalHandle, (Decal, Mat4<f32>)>,
+    next_handle: DecalHandle,
+}
+
+impl DecalManager {
+    pub fn new() -> DecalManager {
+        DecalManager {
+            decals: HashMap::new(),
+            next_handle: 0,
+        }
+    }
+
+    /// Add a decal, returning the handle it may be updated or removed with
+    ///
+    /// Returns None, without adding the decal, if its transform cannot be inverted.
+    ///
+    /// decal: The decal to add
+    pub fn add_decal(&mut self, decal: Decal) -> Option<DecalHandle> {
+        let inverse = match decal.transform.inverse() {
+            Some(inverse) => inverse,
+            None => {
+                log_warning!("Skipping decal whose transform is not invertible");
+                return None;
+            }
+        };
+
+        let handle = self.next_handle;
+        self.next_handle += 1;
+        self.decals.insert(handle, (decal, inverse));
+
+        Some(handle)
+    }
+
+    /// Remove a decal, returning it if the handle was present
+    ///
+    /// handle: The handle returned when the decal was added
+    pub fn remove_decal(&mut self, handle: DecalHandle) -> Option<Decal> {
+        self.decals.remove(&handle).map(|(decal, _)| decal)
+    }
+
+    /// Move a decal
+    ///
+    /// A transform that cannot be inverted is ignored, leaving the decal where it was.
+    ///
+    /// handle: The handle returned when the decal was added
+    /// transform: The new transform of the unit box into the world
+    pub fn update_decal_transform(&mut self, handle: DecalHandle, transform: &Mat4<f32>) {
+        match self.decals.get_mut(&handle) {
+            Some(entry) => {
+                match transform.inverse() {
+                    Some(inverse) => {
+                        entry.0.transform = *transform;
+                        entry.1 = inverse;
+                    }
+                    None => log_warning!("Not moving decal {}, as its new transform is not invertible", handle),
+                }
+            }
+            None => log_warning!("Unknown decal handle {}", handle),
+        }
+    }
+
+    /// Change the image a decal draws
+    ///
+    /// handle: The handle returned when the decal was added
+    /// atlas: The name of the atlas holding the image
+    /// atlas_rect: The offset and size of the image within the atlas, in texture coordinates
+    pub fn update_decal_texture(&mut self, handle: DecalHandle, atlas: &'static str, atlas_rect: [f32; 4]) {
+        match self.decals.get_mut(&handle) {
+            Some(entry) => {
+                entry.0.atlas = atlas;
+                entry.0.atlas_rect = atlas_rect;
+            }
+            None => log_warning!("Unknown decal handle {}", handle),
+        }
+    }
+
+    /// Return the decal with the specified handle
+    ///
+    /// handle: The handle returned when the decal was added
+    pub fn get(&self, handle: DecalHandle) -> Option<&Decal> {
+        self.decals.get(&handle).map(|entry| &entry.0)
+    }
+
+    /// Return the number of decals
+    pub fn len(&self) -> usize {
+        self.decals.len()
+    }
+
+    /// Return whether there are no decals
+    pub fn is_empty(&self) -> bool {
+        self.decals.is_empty()
+    }
+
+    /// Return the decal handles grouped by atlas, with the atlases and handles in sorted order
+    pub fn batches(&self) -> Vec<(&'static str, Vec<DecalHandle>)> {
+        let mut batches: HashMap<&'static str, Vec<DecalHandle>> = HashMap::new();
+        for (handle, entry) in self.decals.iter() {
+            batches.entry(entry.0.atlas).or_insert_with(Vec::new).push(*handle);
+        }
+
+        let mut batches: Vec<(&'static str, Vec<DecalHandle>)> = batches.into_iter().collect();
+        batches.sort_by(|a, b| a.0.cmp(&b.0));
+        for batch in batches.iter_mut() {
+            batch.1.sort();
+        }
+
+        batches
+    }
+
+    /// Draw the decals as a pass of their own
+    ///
+    /// This must follow the opaque geometry, with the scene depth buffer available as the
+    /// texture named by DECAL_DEPTH_TEXTURE, and the decal_camera block synchronised.  Each
+    /// decal is one flush of twelve triangles, since its push constants differ from the next.
+    ///
+    /// On Vulkan textures are bound to the pipeline by name when the renderer is set up, so all
+    /// batches sample the textures named in DECAL_ATLAS_TEXTURE_NAMES and a scene should use a
+    /// single atlas there.  On OpenGL each batch binds its own atlas.
+    ///
+    /// renderer: The renderer to draw with
+    /// atlases: The albedo and normal textures of each atlas
+    pub fn render<Rend: Renderer + ?Sized>(&self, renderer: &mut Rend, atlases: &HashMap<&'static str, DecalAtlas>) {
+        if self.decals.is_empty() {
+            return;
+        }
+
+        renderer.begin_pass(DECAL_SHADER_NAME);
+
+        let threaddata_arc = renderer.get_threaddata(0);
+        let mut threaddata = threaddata_arc.lock().unwrap();
+        threaddata.vertex_array_type = VertexArrayType::F3;
+
+        for (atlas_name, handles) in self.batches() {
+            match atlases.get(atlas_name) {
+                Some(atlas) => {
+                    atlas.albedo.bind(1);
+                    atlas.normal.bind(2);
+                }
+                None => {
+                    log_warning!("Skipping decals using unknown atlas {}", atlas_name);
+                    continue;
+                }
+            }
+
+            for handle in handles {
+                let (ref decal, ref inverse) = self.decals[&handle];
+
+                renderer.set_push_constant_matrix(0, "decal_inverse_model", inverse);
+                renderer.set_push_constant_float(0, "decal_atlas_u", decal.atlas_rect[0]);
+                renderer.set_push_constant_float(0, "decal_atlas_v", decal.atlas_rect[1]);
+                renderer.set_push_constant_float(0, "decal_atlas_width", decal.atlas_rect[2]);
+                renderer.set_push_constant_float(0, "decal_atlas_height", decal.atlas_rect[3]);
+                renderer.set_push_constant_float(0, "decal_albedo_opacity", decal.albedo_opacity);
+                renderer.set_push_constant_float(0, "decal_normal_strength", decal.normal_strength);
+
+                let corners: Vec<Vec3<f32>> = DECAL_BOX_CORNERS.iter()
+                    .map(|c| {
+                        decal.transform
+                            .mul_by_vec3(Vec3 {
+                                x: c[0],
+                                y: c[1],
+                                z: c[2],
+                            })
+                            .project()
+                    })
+                    .collect();
+                for triangle in DECAL_BOX_TRIANGLES.iter() {
+                    threaddata.add_triangle_st_n3(&corners[triangle[0]], &corners[triangle[1]], &corners[triangle[2]]);
+                }
+
+                threaddata.check_flush_st(true, renderer);
+            }
+        }
+
+        renderer.end_pass();
+    }
+}
+
+/// Write the sources of the built-in decal shader to the working directory
+///
+/// The decal shader is built along with the scene shaders it draws over, so this must be called
+/// before the renderer is set up.
+pub fn install_decal_shader_sources() -> Result<(), io::Error> {
+    write_entire_file(DECAL_VERTEX_SOURCE, DECAL_VERTEX_FILENAME)?;
+    write_entire_file(DECAL_FRAGMENT_SOURCE, DECAL_FRAGMENT_FILENAME)?;
+
+    Ok(())
+}
+
+/// Add the uniform block and the shader specification of the built-in decal shader
+///
+/// The shader blends over the colour target without writing depth, so the pass should use the
+/// render target of the opaque geometry with the depth buffer bound as a texture.
+///
+/// resource_manager: The resource manager to add the specifications to
+/// pass_name: The pass the shader is used in
+pub fn register_decal_shaders(resource_manager: &mut ResourceManager, pass_name: &'static str) {
+    resource_manager.uniform_block_specs.insert(DECAL_CAMERA_BLOCK,
+                                                UniformBlockSpec {
+                                                    set: 0,
+                                                    block_type: UniformType::UniformBuffer,
+                                                    uniforms: ["decal_view_projection",
+                                                               "decal_inverse_view_projection",
+                                                               "decal_viewport",
+                                                               "decal_light_direction"]
+                                                        .iter()
+                                                        .map(|name| {
+                                                            BlockUniformSpec {
+                                                                name: *name,
+                                                                ..Default::default()
+                                                            }
+                                                        })
+                                                        .collect(),
+                                                    ..Default::default()
+                                                });
+
+    let mut uniform_specs = vec![UniformSpec {
+                                     name: DECAL_DEPTH_TEXTURE,
+                                     set: 1,
+                                     binding: 0,
+                                     uniform_type: UniformType::CombinedImageSampler,
+                                 }];
+    for (binding, name) in DECAL_ATLAS_TEXTURE_NAMES.iter().enumerate() {
+        uniform_specs.push(UniformSpec {
+            name: *name,
+            set: 1,
+            binding: binding as u32 + 1,
+            uniform_type: UniformType::CombinedImageSampler,
+        });
+    }
+
+    let shader_spec = ShaderSpec {
+        name: DECAL_SHADER_NAME,
+        library_files: vec![],
+        shader_files: vec![ShaderFilesSpecification {
+                               filename: DECAL_VERTEX_FILENAME,
+                               shader_stage: ShaderStage::VertexShader,
+                               spirv_out: "decal.vert.spv",
+                               reflect_out: "decal.vert.rfl",
+                               entry_point: DEFAULT_ENTRY_POINT,
+                           },
+                           ShaderFilesSpecification {
+                               filename: DECAL_FRAGMENT_FILENAME,
+                               shader_stage: ShaderStage::FragmentShader,
+                               spirv_out: "decal.frag.spv",
+                               reflect_out: "decal.frag.rfl",
+                               entry_point: DEFAULT_ENTRY_POINT,
+                           }],
+        fallback_shader_files: vec![],
+        uniform_block_names: vec![DECAL_CAMERA_BLOCK],
+        uniform_specs: uniform_specs,
+        push_constants: vec![PushConstantSpec {
+                                 name: "decal_inverse_model",
+                                 offset: 0,
+                                 size: 64,
+                             },
+                             PushConstantSpec {
+                                 name: "decal_atlas_u",
+                                 offset: 64,
+                                 size: 4,
+                             },
+                             PushConstantSpec {
+                                 name: "decal_atlas_v",
+                                 offset: 68,
+                                 size: 4,
+                             },
+                             PushConstantSpec {
+                                 name: "decal_atlas_width",
+                                 offset: 72,
+                                 size: 4,
+                             },
+                             PushConstantSpec {
+                                 name: "decal_atlas_height",
+                                 offset: 76,
+                                 size: 4,
+                             },
+                             PushConstantSpec {
+                                 name: "decal_albedo_opacity",
+                                 offset: 80,
+                                 size: 4,
+                             },
+                             PushConstantSpec {
+                                 name: "decal_normal_strength",
+                                 offset: 84,
+                                 size: 4,
+                             }],
+        vertex_array_type: VertexArrayType::F3,
+        primitive: PrimitiveType::PrimitiveTriangles,
+        instance_layout: None,
+        attributes: vec!["position"],
+        fragment_out: DECAL_FRAGMENT_OUT,
+        extra_fragment_outs: vec![],
+        render_state: RenderState::alpha_blended(),
+        stencil: None,
+        bindless_textures: false,
+        pass_name: pass_name,
+    };
+    resource_manager.shader_specs.insert(DECAL_SHADER_NAME, shader_spec);
+}
@@ -0,0 +1,280 @@
+// Copyright (c) 2016-2017 Bruce Stenning. All rights reserved.
+//
+// Redistribution and use in source and binary forms, with or without
+// modification, are permitted provided that the following conditions
+// are met:
+//
+// 1. Redistributions of source code must retain the above copyright
+//    notice, this list of conditions and the following disclaimer.
+//
+// 2. Redistributions in binary form must reproduce the above copyright
+//    notice, this list of conditions and the following disclaimer in the
+//    documentation and/or other materials provided with the distribution.
+//
+// 3. Neither the name of the copyright holder nor the names of its
+//    contributors may be used to endorse or promote products derived
+//    from this software without specific prior written permission.
+//
+// THIS SOFTWARE IS PROVIDED BY THE COPYRIGHT HOLDERS AND CONTRIBUTORS
+// AS IS" AND ANY EXPRESS OR IMPLIED WARRANTIES, INCLUDING, BUT NOT
+// LIMITED TO, THE IMPLIED WARRANTIES OF MERCHANTABILITY AND FITNESS
+// FOR A PARTICULAR PURPOSE ARE DISCLAIMED. IN NO EVENT SHALL THE
+// COPYRIGHT HOLDER OR CONTRIBUTORS BE LIABLE FOR ANY DIRECT, INDIRECT,
+// INCIDENTAL, SPECIAL, EXEMPLARY, OR CONSEQUENTIAL DAMAGES (INCLUDING,
+// BUT NOT LIMITED TO, PROCUREMENT OF SUBSTITUTE GOODS OR SERVICES; LOSS
+// OF USE, DATA, OR PROFITS; OR BUSINESS INTERRUPTION) HOWEVER CAUSED
+// AND ON ANY THEORY OF LIABILITY, WHETHER IN CONTRACT, STRICT LIABILITY,
+// OR TORT (INCLUDING NEGLIGENCE OR OTHERWISE) ARISING IN ANY WAY OUT OF
+// THE USE OF THIS SOFTWARE, EVEN IF ADVISED OF THE POSSIBILITY OF SUCH
+// DAMAGE.
+
+use graphics::shader::ShaderStage;
+
+/// Device capabilities that an application may depend on, beyond what every device offers
+///
+/// Vulkan devices are created with only the features requested, and both renderers report the
+/// features granted so that callers can skip the passes that need a missing one.
+#[derive(Clone, Copy, Debug, Default, PartialEq)]
+pub struct DeviceFeatures {
+    pub tessellation_shader: bool,
+    pub geometry_shader: bool,
+    pub fill_mode_non_solid: bool, // Wireframe and point polygon modes
+    pub wide_lines: bool, // Line widths other than 1.0
+    pub large_points: bool, // Point sizes greater than 1.0
+    pub sampler_anisotropy: bool,
+    pub depth_clamp: bool,
+    pub independent_blend: bool, // A separate blend state for each colour attachment
+    pub multi_draw_indirect: bool,
+    pub texture_compression_bc: bool,
+    pub pipeline_statistics_query: bool,
+    pub vertex_pipeline_stores_and_atomics: bool,
+    pub fragment_stores_and_atomics: bool,
+    pub shader_float64: bool,
+}
+
+impl DeviceFeatures {
+    /// Return a feature set with every feature cleared
+    pub fn none() -> DeviceFeatures {
+        DeviceFeatures::default()
+    }
+
+    /// Return a feature set with every feature set
+    pub fn all() -> DeviceFeatures {
+        DeviceFeatures::from_flags(&[true; FEATURE_COUNT])
+    }
+
+    /// Return the name and state of each feature, in declaration order
+    pub fn flags(&self) -> [(&'static str, bool); FEATURE_COUNT] {
+        [("tessellation_shader", self.tessellation_shader),
+         ("geometry_shader", self.geometry_shader),
+         ("fill_mode_non_solid", self.fill_mode_non_solid),
+         ("wide_lines", self.wide_lines),
+         ("large_points", self.large_points),
+         ("sampler_anisotropy", self.sampler_anisotropy),
+         ("depth_clamp", self.depth_clamp),
+         ("independent_blend", self.independent_blend),
+         ("multi_draw_indirect", self.multi_draw_indirect),
+         ("texture_compression_bc", self.texture_compression_bc),
+         ("pipeline_statistics_query", self.pipeline_statistics_query),
+         ("vertex_pipeline_stores_and_atomics", self.vertex_pipeline_stores_and_atomics),
+         ("fragment_stores_and_atomics", self.fragment_stores_and_atomics),
+         ("shader_float64", self.shader_float64)]
+    }
+
+    /// Build a feature set from the states of its features, in declaration order
+    ///
+    /// flags: The state of each feature
+    pub fn from_flags(flags: &[bool; FEATURE_COUNT]) -> DeviceFeatures {
+        DeviceFeatures {
+            tessellation_shader: flags[0],
+            geometry_shader: flags[1],
+            fill_mode_non_solid: flags[2],
+            wide_lines: flags[3],
+            large_points: flags[4],
+            sampler_anisotropy: flags[5],
+            depth_clamp: flags[6],
+            independent_blend: flags[7],
+            multi_draw_indirect: flags[8],
+            texture_compression_bc: flags[9],
+            pipeline_statistics_query: flags[10],
+            vertex_pipeline_stores_and_atomics: flags[11],
+            fragment_stores_and_atomics: flags[12],
+            shader_float64: flags[13],
+        }
+    }
+
+    /// Return the features set in either feature set
+    ///
+    /// other: The other feature set
+    pub fn union(&self, other: &DeviceFeatures) -> DeviceFeatures {
+        self.combine(other, |a, b| a || b)
+    }
+
+    /// Return the features set in both feature sets
+    ///
+    /// other: The other feature set
+    pub fn intersection(&self, other: &DeviceFeatures) -> DeviceFeatures {
+        self.combine(other, |a, b| a && b)
+    }
+
+    /// Return the names of the features set here but not in the available features
+    ///
+    /// available: The features a device offers
+    pub fn missing_from(&self, available: &DeviceFeatures) -> Vec<&'static str> {
+        self.flags()
+            .iter()
+            .zip(available.flags().iter())
+            .filter(|&(&(_, wanted), &(_, offered))| wanted && !offered)
+            .map(|(&(name, _), _)| name)
+            .collect()
+    }
+
+    /// Return whether shaders of a stage can be used
+    ///
+    /// stage: The shader stage
+    pub fn supports_stage(&self, stage: ShaderStage) -> bool {
+        match stage {
+            ShaderStage::TessControlShader | ShaderStage::TessEvalShader => self.tessellation_shader,
+            ShaderStage::GeometryShader => self.geometry_shader,
+            _ => true,
+        }
+    }
+
+    /// Return the stages of a shader that cannot be used, each listed once
+    ///
+    /// stages: The stages of the shader
+    pub fn missing_stages<I: Iterator<Item = ShaderStage>>(&self, stages: I) -> Vec<ShaderStage> {
+        let mut missing: Vec<ShaderStage> = vec![];
+        for stage in stages {
+            if !self.supports_stage(stage) && !missing.contains(&stage) {
+                missing.push(stage);
+            }
+        }
+        missing
+    }
+
+    /// Return the features that only decide which shader stages can be used
+    pub fn shader_stages() -> DeviceFeatures {
+        DeviceFeatures {
+            tessellation_shader: true,
+            geometry_shader: true,
+            ..DeviceFeatures::none()
+        }
+    }
+
+    /// Return the number of features set
+    pub fn count(&self) -> u32 {
+        self.flags().iter().filter(|&&(_, set)| set).count() as u32
+    }
+
+    /// Combine each feature with the same feature of another set
+    fn combine<F: Fn(bool, bool) -> bool>(&self, other: &DeviceFeatures, f: F) -> DeviceFeatures {
+        let mut flags = [false; FEATURE_COUNT];
+        for (i, (&(_, a), &(_, b))) in self.flags().iter().zip(other.flags().iter()).enumerate() {
+            flags[i] = f(a, b);
+        }
+
+        DeviceFeatures::from_flags(&flags)
+    }
+}
+
+/// The number of features in DeviceFeatures
+pub const FEATURE_COUNT: usize = 14;
+
+/// The device features an application asks for
+///
+/// A device lacking any of the required features is never chosen, while the optional features
+/// are enabled wherever they are available.  Devices are scored by the optional features they
+/// offer, after the preference for discrete GPUs, except for the shader stages: shaders declare
+/// fallback stage sets for devices without them, so their absence is not held against a device.
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub struct FeatureRequest {
+    pub required: DeviceFeatures,
+    pub optional: DeviceFeatures,
+}
+
+impl Default for FeatureRequest {
+    /// Ask for the tessellation and geometry shaders the terrain shaders are built on, which fall
+    /// back to other stages without them, and the features the renderers use when they are present
+    fn default() -> FeatureRequest {
+        FeatureRequest {
+            required: DeviceFeatures::none(),
+            optional: DeviceFeatures {
+                tessellation_shader: true,
+                geometry_shader: true,
+                sampler_anisotropy: true,
+                fill_mode_non_solid: true,
+                vertex_pipeline_stores_and_atomics: true,
+                fragment_stores_and_atomics: true,
+                ..DeviceFeatures::none()
+            },
+        }
+    }
+}
+
+impl FeatureRequest {
+    /// Return the features to enable on a device, or the names of the required features it lacks
+    ///
+    /// available: The features the device offers
+    pub fn resolve(&self, available: &DeviceFeatures) -> Result<DeviceFeatures, Vec<&'static str>> {
+        let missing = self.required.missing_from(available);
+        if !missing.is_empty() {
+            return Err(missing);
+        }
+
+        Ok(self.required.union(&self.optional.intersection(available)))
+    }
+
+    /// Return the score a device earns for the optional features it offers
+    ///
+    /// available: The features the device offers
+    pub fn optional_score(&self, available: &DeviceFeatures) -> i32 {
+        let offered = self.optional.intersection(available);
+        (offered.count() - offered.intersection(&DeviceFeatures::shader_stages()).count()) as i32
+    }
+}
+
+/// Device limits that an application may size its resources by
+///
+/// Vulkan reports these from VkPhysicalDeviceLimits, and OpenGL from the equivalent
+/// implementation-dependent values.  Limits that OpenGL does not report, such as the compute
+/// limits of a context without compute shaders, are zero.
+#[derive(Clone, Copy, Debug, Default, PartialEq)]
+pub struct DeviceLimits {
+    pub max_image_dimension_2d: u32,
+    pub max_image_dimension_3d: u32,
+    pub max_image_dimension_cube: u32,
+    pub max_image_array_layers: u32,
+    pub max_framebuffer_width: u32,
+    pub max_framebuffer_height: u32,
+    pub max_colour_attachments: u32,
+    pub max_uniform_buffer_range: u32, // In bytes
+    pub min_uniform_buffer_offset_alignment: u64, // In bytes
+    pub max_storage_buffer_range: u32, // In bytes
+    pub max_push_constants_size: u32, // In bytes, of the plain uniforms standing in for them on OpenGL
+    pub max_vertex_input_attributes: u32,
+    pub max_compute_work_group_count: [u32; 3],
+    pub max_compute_work_group_size: [u32; 3],
+    pub max_compute_work_group_invocations: u32,
+    pub max_sampler_anisotropy: f32, // 1.0 where anisotropic filtering is unavailable
+}
+
+impl DeviceLimits {
+    /// Return whether a two-dimensional image of a size can be created
+    ///
+    /// width: The width of the image
+    /// height: The height of the image
+    pub fn fits_image_2d(&self, width: u32, height: u32) -> bool {
+        width <= self.max_image_dimension_2d && height <= self.max_image_dimension_2d
+    }
+
+    /// Return whether a compute dispatch fits within the workgroup count limits
+    ///
+    /// x: The number of workgroups in the x dimension
+    /// y: The number of workgroups in the y dimension
+    /// z: The number of workgroups in the z dimension
+    pub fn fits_dispatch(&self, x: u32, y: u32, z: u32) -> bool {
+        x <= self.max_compute_work_group_count[0] && y <= self.max_compute_work_group_count[1] &&
+        z <= self.max_compute_work_group_count[2]
+    }
+}
@@ -0,0 +1,73 @@
+// Copyright (c) 2016-2017 Bruce Stenning. All rights reserved.
+//
+// Redistribution and use in source and binary forms, with or without
+// modification, are permitted provided that the following conditions
+// are met:
+//
+// 1. Redistributions of source code must retain the above copyright
+//    notice, this list of conditions and the following disclaimer.
+//
+// 2. Redistributions in binary form must reproduce the above copyright
+//    notice, this list of conditions and the following disclaimer in the
+//    documentation and/or other materials provided with the distribution.
+//
+// 3. Neither the name of the copyright holder nor the names of its
+//    contributors may be used to endorse or promote products derived
+//    from this software without specific prior written permission.
+//
+// THIS SOFTWARE IS PROVIDED BY THE COPYRIGHT HOLDERS AND CONTRIBUTORS
+// AS IS" AND ANY EXPRESS OR IMPLIED WARRANTIES, INCLUDING, BUT NOT
+// LIMITED TO, THE IMPLIED WARRANTIES OF MERCHANTABILITY AND FITNESS
+// FOR A PARTICULAR PURPOSE ARE DISCLAIMED. IN NO EVENT SHALL THE
+// COPYRIGHT HOLDER OR CONTRIBUTORS BE LIABLE FOR ANY DIRECT, INDIRECT,
+// INCIDENTAL, SPECIAL, EXEMPLARY, OR CONSEQUENTIAL DAMAGES (INCLUDING,
+// BUT NOT LIMITED TO, PROCUREMENT OF SUBSTITUTE GOODS OR SERVICES; LOSS
+// OF USE, DATA, OR PROFITS; OR BUSINESS INTERRUPTION) HOWEVER CAUSED
+// AND ON ANY THEORY OF LIABILITY, WHETHER IN CONTRACT, STRICT LIABILITY,
+// OR TORT (INCLUDING NEGLIGENCE OR OTHERWISE) ARISING IN ANY WAY OUT OF
+// THE USE OF THIS SOFTWARE, EVEN IF ADVISED OF THE POSSIBILITY OF SUCH
+// DAMAGE.
+
+use graphics::renderer::*;
+
+/// The colour drawn by the error shader, chosen to be obvious on screen
+pub const ERROR_SHADER_COLOUR: [f32; 4] = [1.0f32, 0.0f32, 1.0f32, 1.0f32];
+
+/// The name of the fragment output of the error shader
+pub const ERROR_SHADER_FRAGMENT_OUT: &'static str = "error_colour";
+
+/// Return the GLSL source of the error vertex shader for a vertex layout
+///
+/// The error shader is substituted for a shader or pipeline that fails to build, so that the
+/// pass remains functional and the geometry that would have been drawn shows up in magenta.
+/// It has no knowledge of the uniforms of the shader it replaces, so the positions are passed
+/// through untransformed.  The source has no #version directive, as both back ends add one.
+///
+/// vertex_array_type: The vertex layout of the shader being replaced
+///
+/// Returns: The source of the vertex shader
+pub fn error_vertex_shader_source(vertex_array_type: VertexArrayType) -> String {
+    let position = match vertex_array_type {
+        VertexArrayType::F3 | VertexArrayType::F3F3F3 | VertexArrayType::F3F3 => {
+            "layout(location = 0) in vec3 position;\n\nvoid main() {\n    gl_Position = vec4(position, 1.0);\n}\n"
+        }
+        VertexArrayType::F2F2 => {
+            "layout(location = 0) in vec2 position;\n\nvoid main() {\n    gl_Position = vec4(position, 0.0, 1.0);\n}\n"
+        }
+    };
+
+    position.to_string()
+}
+
+/// Return the GLSL source of the error fragment shader
+///
+/// Returns: The source of the fragment shader
+pub fn error_fragment_shader_source() -> String {
+    format!("layout(location = 0) out vec4 {};\n\nvoid main() {{\n    {} = vec4({:.1}, {:.1}, {:.1}, {:.1});\n}}\n",
+            ERROR_SHADER_FRAGMENT_OUT,
+            ERROR_SHADER_FRAGMENT_OUT,
+            ERROR_SHADER_COLOUR[0],
+            ERROR_SHADER_COLOUR[1],
+            ERROR_SHADER_COLOUR[2],
+            ERROR_SHADER_COLOUR[3])
+}
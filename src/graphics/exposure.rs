@@ -0,0 +1,443 @@
+// Copyright (c) 2016-2017 Bruce Stenning. All rights reserved.
+//
+// Redistribution and use in source and binary forms, with or without
+// modification, are permitted provided that the following conditions
+// are met:
+//
+// 1. Redistributions of source code must retain the above copyright
+//    notice, this list of conditions and the following disclaimer.
+//
+// 2. Redistributions in binary form must reproduce the above copyright
+//    notice, this list of conditions and the following disclaimer in the
+//    documentation and/or other materials provided with the distribution.
+//
+// 3. Neither the name of the copyright holder nor the names of its
+//    contributors may be used to endorse or promote products derived
+//    from this software without specific prior written permission.
+//
+// THIS SOFTWARE IS PROVIDED BY THE COPYRIGHT HOLDERS AND CONTRIBUTORS
+// AS IS" AND ANY EXPRESS OR IMPLIED WARRANTIES, INCLUDING, BUT NOT
+// LIMITED TO, THE IMPLIED WARRANTIES OF MERCHANTABILITY AND FITNESS
+// FOR A PARTICULAR PURPOSE ARE DISCLAIMED. IN NO EVENT SHALL THE
+// COPYRIGHT HOLDER OR CONTRIBUTORS BE LIABLE FOR ANY DIRECT, INDIRECT,
+// INCIDENTAL, SPECIAL, EXEMPLARY, OR CONSEQUENTIAL DAMAGES (INCLUDING,
+// BUT NOT LIMITED TO, PROCUREMENT OF SUBSTITUTE GOODS OR SERVICES; LOSS
+// OF USE, DATA, OR PROFITS; OR BUSINESS INTERRUPTION) HOWEVER CAUSED
+// AND ON ANY THEORY OF LIABILITY, WHETHER IN CONTRACT, STRICT LIABILITY,
+// OR TORT (INCLUDING NEGLIGENCE OR OTHERWISE) ARISING IN ANY WAY OUT OF
+// THE USE OF THIS SOFTWARE, EVEN IF ADVISED OF THE POSSIBILITY OF SUCH
+// DAMAGE.
+use std::io;
+
+use algebra::vector::Vec2;
+use graphics::frametime::FRAME_BLOCK;
+use graphics::image::Image;
+use graphics::fullscreen::draw_fullscreen_quad;
+use graphics::readback::ReadbackHandle;
+use graphics::renderer::*;
+use graphics::rendertarget::*;
+use graphics::resources::*;
+use graphics::shader::*;
+use misc::fileutils::*;
+
+/// The name the exposure metering shader is registered under
+pub const EXPOSURE_METERING_SHADER_NAME: &'static str = "exposure_metering";
+
+/// The name of the texture the HDR scene is sampled from for metering
+pub const EXPOSURE_SOURCE_TEXTURE: &'static str = "exposure_source_texture";
+
+/// The member of the FRAME_BLOCK uniform block the exposure is published in, once registered
+/// with register_frame_exposure
+pub const FRAME_EXPOSURE: &'static str = "frame_exposure";
+
+/// The width and height of the render target the scene is downsampled into for metering
+pub const EXPOSURE_METERING_SIZE: u32 = 64;
+
+/// The lowest log2 luminance metered, below which the scene is treated as black
+pub const EXPOSURE_MIN_LOG_LUMINANCE: f32 = -12.0;
+
+/// The highest log2 luminance metered
+pub const EXPOSURE_MAX_LOG_LUMINANCE: f32 = 12.0;
+
+/// The number of bins in the luminance histogram
+pub const EXPOSURE_HISTOGRAM_BINS: usize = 64;
+
+const EXPOSURE_METERING_VERTEX_FILENAME: &'static str = "exposure_metering.vert";
+const EXPOSURE_METERING_FRAGMENT_FILENAME: &'static str = "exposure_metering.frag";
+
+const EXPOSURE_METERING_VERTEX_SOURCE: &'static str = include_str!("shaders/exposure_metering.vert");
+const EXPOSURE_METERING_FRAGMENT_SOURCE: &'static str = include_str!("shaders/exposure_metering.frag");
+
+/// How the parts of the scene are weighted when metering
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub enum MeteringMode {
+    /// The whole scene counts equally
+    Average,
+    /// The centre of the scene counts most, falling off towards the edges
+    CentreWeighted,
+    /// Only a small circle at the centre of the scene counts
+    Spot,
+}
+
+impl MeteringMode {
+    /// Return the weight of a point of the scene
+    ///
+    /// uv: The position of the point, from (0, 0) to (1, 1) across the scene
+    pub fn weight(&self, uv: Vec2<f32>) -> f32 {
+        let dx = uv.x - 0.5f32;
+        let dy = uv.y - 0.5f32;
+        let distance_squared = dx * dx + dy * dy;
+        match *self {
+            MeteringMode::Average => 1.0f32,
+            MeteringMode::CentreWeighted => (-distance_squared * 8.0f32).exp(),
+            MeteringMode::Spot => if distance_squared < 0.01f32 { 1.0f32 } else { 0.0f32 },
+        }
+    }
+}
+
+/// How the exposure is metered and how quickly it adapts
+#[derive(Clone, Copy, Debug)]
+pub struct ExposureSettings {
+    pub metering_mode: MeteringMode,
+    /// The rate at which the exposure adapts to a brighter scene, per second
+    pub speed_up: f32,
+    /// The rate at which the exposure adapts to a darker scene, per second
+    pub speed_down: f32,
+    /// The luminance the metered average is exposed to, conventionally middle grey
+    pub key_value: f32,
+    /// An adjustment to the metered exposure, in stops
+    pub compensation_ev: f32,
+    pub min_exposure: f32,
+    pub max_exposure: f32,
+    /// The fraction of the darkest metered weight ignored, so small black areas do not count
+    pub low_percentile: f32,
+    /// The fraction of the metered weight below which the brightest parts are ignored, so small
+    /// highlights do not count
+    pub high_percentile: f32,
+}
+
+impl Default for ExposureSettings {
+    fn default() -> ExposureSettings {
+        ExposureSettings {
+            metering_mode: MeteringMode::CentreWeighted,
+            speed_up: 3.0f32,
+            speed_down: 1.0f32,
+            key_value: 0.18f32,
+            compensation_ev: 0.0f32,
+            min_exposure: 1.0e-3f32,
+            max_exposure: 1.0e3f32,
+            low_percentile: 0.5f32,
+            high_percentile: 0.95f32,
+        }
+    }
+}
+
+/// A histogram of the log2 luminance of the scene, weighted by the metering mode
+pub struct LuminanceHistogram {
+    bins: [f32; EXPOSURE_HISTOGRAM_BINS],
+    total: f32,
+}
+
+impl LuminanceHistogram {
+    pub fn new() -> LuminanceHistogram {
+        LuminanceHistogram {
+            bins: [0.0f32; EXPOSURE_HISTOGRAM_BINS],
+            total: 0.0f32,
+        }
+    }
+
+    /// Build the histogram from a readback of the metering render target
+    ///
+    /// Each pixel's red channel holds its log2 luminance, scaled from the metered range to [0, 1].
+    ///
+    /// image: The RGB readback of the metering render target
+    /// metering_mode: How to weight the pixels
+    pub fn from_metering_image(image: &Image, metering_mode: MeteringMode) -> LuminanceHistogram {
+        let mut histogram = LuminanceHistogram::new();
+        let (width, height) = (image.get_width().max(1), image.get_height().max(1));
+        for (i, pixel) in image.get_data().chunks(3).enumerate() {
+            let uv = Vec2 {
+                x: ((i as u32 % width) as f32 + 0.5f32) / width as f32,
+                y: ((i as u32 / width) as f32 + 0.5f32) / height as f32,
+            };
+            let log_luminance = EXPOSURE_MIN_LOG_LUMINANCE +
+                                pixel[0] as f32 / 255.0f32 * (EXPOSURE_MAX_LOG_LUMINANCE - EXPOSURE_MIN_LOG_LUMINANCE);
+            histogram.add(log_luminance, metering_mode.weight(uv));
+        }
+        histogram
+    }
+
+    /// Add a metered sample
+    ///
+    /// log_luminance: The log2 luminance of the sample
+    /// weight: The weight of the sample
+    pub fn add(&mut self, log_luminance: f32, weight: f32) {
+        if weight <= 0.0f32 {
+            return;
+        }
+        let range = EXPOSURE_MAX_LOG_LUMINANCE - EXPOSURE_MIN_LOG_LUMINANCE;
+        let position = (log_luminance - EXPOSURE_MIN_LOG_LUMINANCE) / range * EXPOSURE_HISTOGRAM_BINS as f32;
+        let bin = (position.max(0.0f32) as usize).min(EXPOSURE_HISTOGRAM_BINS - 1);
+        self.bins[bin] += weight;
+        self.total += weight;
+    }
+
+    /// Return the weighted average log2 luminance between two percentiles of the weight, or None if
+    /// nothing was metered
+    ///
+    /// low_percentile: The fraction of the weight ignored at the dark end
+    /// high_percentile: The fraction of the weight above which the bright end is ignored
+    pub fn average_log_luminance(&self, low_percentile: f32, high_percentile: f32) -> Option<f32> {
+        if self.total <= 0.0f32 {
+            return None;
+        }
+
+        let low = self.total * low_percentile.max(0.0f32).min(1.0f32);
+        let high = self.total * high_percentile.max(low_percentile).min(1.0f32);
+        let bin_width = (EXPOSURE_MAX_LOG_LUMINANCE - EXPOSURE_MIN_LOG_LUMINANCE) / EXPOSURE_HISTOGRAM_BINS as f32;
+
+        let mut below = 0.0f32;
+        let mut sum = 0.0f32;
+        let mut weight = 0.0f32;
+        for (i, bin) in self.bins.iter().enumerate() {
+            // The part of this bin's weight that lies between the percentiles
+            let included = (below + bin).min(high) - below.max(low);
+            if included > 0.0f32 {
+                let centre = EXPOSURE_MIN_LOG_LUMINANCE + (i as f32 + 0.5f32) * bin_width;
+                sum += centre * included;
+                weight += included;
+            }
+            below += *bin;
+        }
+
+        if weight > 0.0f32 {
+            Some(sum / weight)
+        } else {
+            // The percentiles met within a single bin
+            let bin = self.bins.iter().position(|b| *b > 0.0f32).unwrap_or(0);
+            Some(EXPOSURE_MIN_LOG_LUMINANCE + (bin as f32 + 0.5f32) * bin_width)
+        }
+    }
+}
+
+/// Meters the HDR scene and adapts the exposure to it over time, as the eye does
+///
+/// Each frame, render_exposure_metering downsamples the scene into a small render target, and
+/// update reads it back, meters it and moves the exposure towards the metered value.  The
+/// readbacks are asynchronous, so the exposure trails the scene by a couple of frames, which
+/// adaptation hides.  The exposure is published in FRAME_BLOCK for the tonemap pass to apply.
+pub struct AutoExposure {
+    settings: ExposureSettings,
+    manual_exposure: Option<f32>,
+    target_log_luminance: Option<f32>,
+    adapted_log_luminance: Option<f32>,
+    pending_readback: Option<ReadbackHandle>,
+}
+
+impl AutoExposure {
+    /// Create the metering and adaptation with the settings specified
+    ///
+    /// settings: How the exposure is metered and how quickly it adapts
+    pub fn new(settings: ExposureSettings) -> AutoExposure {
+        AutoExposure {
+            settings: settings,
+            manual_exposure: None,
+            target_log_luminance: None,
+            adapted_log_luminance: None,
+            pending_readback: None,
+        }
+    }
+
+    /// Return the metering and adaptation settings
+    pub fn get_settings(&self) -> &ExposureSettings {
+        &self.settings
+    }
+
+    /// Change the metering and adaptation settings
+    ///
+    /// settings: How the exposure is metered and how quickly it adapts
+    pub fn set_settings(&mut self, settings: ExposureSettings) {
+        self.settings = settings;
+    }
+
+    /// Fix the exposure, overriding metering, or return to automatic exposure
+    ///
+    /// exposure: The exposure to use, or None to meter the scene
+    pub fn set_manual_exposure(&mut self, exposure: Option<f32>) {
+        self.manual_exposure = exposure;
+    }
+
+    /// Meter a histogram of the scene, setting the luminance that the exposure adapts towards
+    ///
+    /// histogram: The histogram of the scene's luminance
+    pub fn meter(&mut self, histogram: &LuminanceHistogram) {
+        if let Some(average) = histogram.average_log_luminance(self.settings.low_percentile,
+                                                               self.settings.high_percentile) {
+            self.target_log_luminance = Some(average);
+        }
+    }
+
+    /// Adapt the exposure towards the last metered luminance
+    ///
+    /// The first metering is adopted immediately, so that a scene does not start out of exposure.
+    ///
+    /// delta: The time since the last frame in seconds, normally the real time delta of the frame clock
+    pub fn adapt(&mut self, delta: f32) {
+        let target = match self.target_log_luminance {
+            Some(target) => target,
+            None => return,
+        };
+
+        self.adapted_log_luminance = Some(match self.adapted_log_luminance {
+            Some(adapted) => {
+                let speed = if target > adapted {
+                    self.settings.speed_up
+                } else {
+                    self.settings.speed_down
+                };
+                adapted + (target - adapted) * (1.0f32 - (-delta.max(0.0f32) * speed).exp())
+            }
+            None => target,
+        });
+    }
+
+    /// Return the exposure to apply to the scene, the manual one if set
+    pub fn exposure(&self) -> f32 {
+        if let Some(exposure) = self.manual_exposure {
+            return exposure;
+        }
+
+        let adapted = match self.adapted_log_luminance {
+            Some(adapted) => adapted,
+            None => return 1.0f32,
+        };
+        let exposure = self.settings.key_value / adapted.exp2() * self.settings.compensation_ev.exp2();
+        exposure.max(self.settings.min_exposure).min(self.settings.max_exposure)
+    }
+
+    /// Read back the last metering, meter it, adapt and publish the exposure
+    ///
+    /// This should be called once per frame, after render_exposure_metering.  A new readback is
+    /// only requested once the previous one has completed.
+    ///
+    /// renderer: The renderer that drew the metering pass
+    /// metering_target: The render target the metering pass drew into
+    /// delta: The time since the last frame in seconds
+    pub fn update<Rend: Renderer + ?Sized>(&mut self, renderer: &mut Rend, metering_target: &RenderTarget, delta: f32) {
+        if let Some(handle) = self.pending_readback {
+            if let Some(image) = renderer.poll_readback(handle) {
+                let histogram = LuminanceHistogram::from_metering_image(&image, self.settings.metering_mode);
+                self.meter(&histogram);
+                self.pending_readback = None;
+            }
+        }
+        if self.pending_readback.is_none() && self.manual_exposure.is_none() {
+            self.pending_readback = Some(renderer.request_readback(metering_target));
+        }
+
+        self.adapt(delta);
+        self.publish(renderer);
+    }
+
+    /// Set the exposure in the FRAME_BLOCK uniform block and synchronise it
+    ///
+    /// renderer: The renderer owning the uniform buffer
+    pub fn publish<Rend: Renderer + ?Sized>(&self, renderer: &Rend) {
+        renderer.set_uniform_buffer_float(FRAME_BLOCK, FRAME_EXPOSURE, self.exposure());
+        renderer.synchronise_uniform_buffer(FRAME_BLOCK);
+    }
+}
+
+/// Write the sources of the exposure metering shader to the working directory
+///
+/// The metering shader is built from these files when the renderer is set up, so this must be
+/// called before then.  The tone-mapping shader only reads the metered exposure and needs none of them.
+pub fn install_exposure_shader_sources() -> Result<(), io::Error> {
+    write_entire_file(EXPOSURE_METERING_VERTEX_SOURCE, EXPOSURE_METERING_VERTEX_FILENAME)?;
+    write_entire_file(EXPOSURE_METERING_FRAGMENT_SOURCE, EXPOSURE_METERING_FRAGMENT_FILENAME)?;
+
+    Ok(())
+}
+
+/// Add the FRAME_EXPOSURE member to the FRAME_BLOCK uniform block, registering the block if it
+/// has not been already
+///
+/// Every shader declaring the block must then declare the member, after the frame times.
+///
+/// resource_manager: The resource manager to add the specification to
+pub fn register_frame_exposure(resource_manager: &mut ResourceManager) {
+    if !resource_manager.uniform_block_specs.contains_key(FRAME_BLOCK) {
+        ::graphics::frametime::register_frame_block(resource_manager);
+    }
+
+    let block_spec = resource_manager.uniform_block_specs.get_mut(FRAME_BLOCK).unwrap();
+    if !block_spec.uniforms.iter().any(|u| u.name == FRAME_EXPOSURE) {
+        block_spec.uniforms.push(BlockUniformSpec {
+            name: FRAME_EXPOSURE,
+            ..Default::default()
+        });
+    }
+}
+
+/// Add the shader specification of the exposure metering shader
+///
+/// The metering shader draws F2F2 full-screen triangles into a render target of
+/// EXPOSURE_METERING_SIZE square, averaging the log2 luminance of the part of the scene each
+/// pixel covers.
+///
+/// resource_manager: The resource manager to add the specification to
+/// pass_name: The pass the metering shader is used in, which draws to an offscreen target
+pub fn register_exposure_metering_shader(resource_manager: &mut ResourceManager, pass_name: &'static str) {
+    let metering_spec = ShaderSpec {
+        name: EXPOSURE_METERING_SHADER_NAME,
+        library_files: vec![],
+        shader_files: vec![ShaderFilesSpecification {
+                               filename: EXPOSURE_METERING_VERTEX_FILENAME,
+                               shader_stage: ShaderStage::VertexShader,
+                               spirv_out: "exposure_metering.vert.spv",
+                               reflect_out: "exposure_metering.vert.rfl",
+                               entry_point: DEFAULT_ENTRY_POINT,
+                           },
+                           ShaderFilesSpecification {
+                               filename: EXPOSURE_METERING_FRAGMENT_FILENAME,
+                               shader_stage: ShaderStage::FragmentShader,
+                               spirv_out: "exposure_metering.frag.spv",
+                               reflect_out: "exposure_metering.frag.rfl",
+                               entry_point: DEFAULT_ENTRY_POINT,
+                           }],
+        fallback_shader_files: vec![],
+        uniform_block_names: vec![],
+        uniform_specs: vec![UniformSpec {
+                                name: EXPOSURE_SOURCE_TEXTURE,
+                                set: 1,
+                                binding: 0,
+                                uniform_type: UniformType::CombinedImageSampler,
+                            }],
+        push_constants: vec![],
+        vertex_array_type: VertexArrayType::F2F2,
+        primitive: PrimitiveType::PrimitiveTriangles,
+        instance_layout: None,
+        attributes: vec!["position", "texcoord"],
+        fragment_out: "exposure_log_luminance",
+        extra_fragment_outs: vec![],
+        render_state: RenderState::default(),
+        stencil: None,
+        bindless_textures: false,
+        pass_name: pass_name,
+    };
+    resource_manager.shader_specs.insert(EXPOSURE_METERING_SHADER_NAME, metering_spec);
+}
+
+/// Downsample the HDR scene into the current render target for metering
+///
+/// The metering render target of EXPOSURE_METERING_SIZE square must be selected, and the HDR
+/// scene must be available as the texture named by EXPOSURE_SOURCE_TEXTURE.
+///
+/// renderer: The renderer to draw with
+pub fn render_exposure_metering<Rend: Renderer + ?Sized>(renderer: &mut Rend) {
+    renderer.begin_pass(EXPOSURE_METERING_SHADER_NAME);
+
+    draw_fullscreen_quad(renderer);
+
+    renderer.end_pass();
+}
@@ -0,0 +1,203 @@
+// Copyright (c) 2016-2017 Bruce Stenning. All rights reserved.
+//
+// Redistribution and use in source and binary forms, with or without
+// modification, are permitted provided that the following conditions
+// are met:
+//
+// 1. Redistributions of source code must retain the above copyright
+//    notice, this list of conditions and the following disclaimer.
+//
+// 2. Redistributions in binary form must reproduce the above copyright
+//    notice, this list of conditions and the following disclaimer in the
+//    documentation and/or other materials provided with the distribution.
+//
+// 3. Neither the name of the copyright holder nor the names of its
+//    contributors may be used to endorse or promote products derived
+//    from this software without specific prior written permission.
+//
+// THIS SOFTWARE IS PROVIDED BY THE COPYRIGHT HOLDERS AND CONTRIBUTORS
+// AS IS" AND ANY EXPRESS OR IMPLIED WARRANTIES, INCLUDING, BUT NOT
+// LIMITED TO, THE IMPLIED WARRANTIES OF MERCHANTABILITY AND FITNESS
+// FOR A PARTICULAR PURPOSE ARE DISCLAIMED. IN NO EVENT SHALL THE
+// COPYRIGHT HOLDER OR CONTRIBUTORS BE LIABLE FOR ANY DIRECT, INDIRECT,
+// INCIDENTAL, SPECIAL, EXEMPLARY, OR CONSEQUENTIAL DAMAGES (INCLUDING,
+// BUT NOT LIMITED TO, PROCUREMENT OF SUBSTITUTE GOODS OR SERVICES; LOSS
+// OF USE, DATA, OR PROFITS; OR BUSINESS INTERRUPTION) HOWEVER CAUSED
+// AND ON ANY THEORY OF LIABILITY, WHETHER IN CONTRACT, STRICT LIABILITY,
+// OR TORT (INCLUDING NEGLIGENCE OR OTHERWISE) ARISING IN ANY WAY OUT OF
+// THE USE OF THIS SOFTWARE, EVEN IF ADVISED OF THE POSSIBILITY OF SUCH
+// DAMAGE.
+use std::boxed::Box;
+use std::sync::{Mutex, Once};
+use std::sync::atomic::{AtomicBool, Ordering};
+
+/// The kind of failure to inject into a call, which the renderer maps onto an API result
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub enum InjectedFailure {
+    OutOfDate,
+    DeviceLost,
+    OutOfMemory,
+    Generic,
+}
+
+impl InjectedFailure {
+    /// Return the failure most typical of a call, for failures injected at random
+    ///
+    /// call_name: The name of the API call
+    pub fn typical_of(call_name: &str) -> InjectedFailure {
+        if call_name.contains("Acquire") || call_name.contains("Present") {
+            InjectedFailure::OutOfDate
+        } else if call_name.contains("Submit") || call_name.contains("Wait") {
+            InjectedFailure::DeviceLost
+        } else if call_name.contains("Allocate") {
+            InjectedFailure::OutOfMemory
+        } else {
+            InjectedFailure::Generic
+        }
+    }
+}
+
+/// A failure of a named call, injected once a number of calls to it have been let through
+struct TargetedFailure {
+    call_name: String,
+    calls_to_skip: u32,
+    failures_remaining: u32,
+    failure: InjectedFailure,
+}
+
+/// A plan of the API calls to fail, for exercising the error handling paths in tests
+///
+/// Calls fail either because they are targeted by name or at random with a fixed probability.
+/// The random failures come from a seeded generator, so that a failing run can be repeated.
+pub struct FailurePlan {
+    probability: f32,
+    random_state: u32,
+    targets: Vec<TargetedFailure>,
+    injected: u64,
+}
+
+impl FailurePlan {
+    /// Construct a plan that fails no calls
+    pub fn new() -> FailurePlan {
+        FailurePlan {
+            probability: 0.0,
+            random_state: 1,
+            targets: vec![],
+            injected: 0,
+        }
+    }
+
+    /// Fail every call with a probability, with the failure typical of the call
+    ///
+    /// probability: The probability of each call failing, from 0 to 1
+    /// seed: The seed of the random failures
+    pub fn fail_randomly(&mut self, probability: f32, seed: u32) {
+        self.probability = probability.max(0.0).min(1.0);
+        self.random_state = if seed == 0 { 1 } else { seed };
+    }
+
+    /// Fail calls of a name, once a number of calls to it have been let through
+    ///
+    /// call_name: The name of the API call, as reported in error messages, e.g. "vkQueueSubmit"
+    /// calls_to_skip: The number of calls to let through first
+    /// failures: The number of calls to fail after that
+    /// failure: The failure to inject
+    pub fn fail_call(&mut self, call_name: &str, calls_to_skip: u32, failures: u32, failure: InjectedFailure) {
+        self.targets.push(TargetedFailure {
+            call_name: call_name.to_string(),
+            calls_to_skip: calls_to_skip,
+            failures_remaining: failures,
+            failure: failure,
+        });
+    }
+
+    /// Decide whether a call fails, returning the failure to inject if it does
+    ///
+    /// call_name: The name of the API call
+    pub fn next_failure(&mut self, call_name: &str) -> Option<InjectedFailure> {
+        let mut failure = None;
+        for target in self.targets.iter_mut().filter(|target| target.call_name == call_name) {
+            if target.calls_to_skip > 0 {
+                target.calls_to_skip -= 1;
+            } else if target.failures_remaining > 0 && failure.is_none() {
+                target.failures_remaining -= 1;
+                failure = Some(target.failure);
+            }
+        }
+
+        if failure.is_none() && self.probability > 0.0 && self.next_random() < self.probability {
+            failure = Some(InjectedFailure::typical_of(call_name));
+        }
+
+        if failure.is_some() {
+            self.injected += 1;
+        }
+
+        failure
+    }
+
+    /// Return the number of failures injected
+    pub fn injected(&self) -> u64 {
+        self.injected
+    }
+
+    /// Return the next number from the xorshift generator, from 0 up to but excluding 1
+    fn next_random(&mut self) -> f32 {
+        let mut x = self.random_state;
+        x ^= x << 13;
+        x ^= x >> 17;
+        x ^= x << 5;
+        self.random_state = x;
+
+        (x >> 8) as f32 / (1u32 << 24) as f32
+    }
+}
+
+static PLAN_ENABLED: AtomicBool = AtomicBool::new(false);
+static PLAN_INIT: Once = Once::new();
+static mut PLAN: *const Mutex<Option<FailurePlan>> = 0 as *const Mutex<Option<FailurePlan>>;
+
+/// Return the process-wide failure plan, which API calls on any thread consult
+fn plan() -> &'static Mutex<Option<FailurePlan>> {
+    unsafe {
+        PLAN_INIT.call_once(|| {
+            PLAN = Box::into_raw(Box::new(Mutex::new(None)));
+        });
+        &*PLAN
+    }
+}
+
+/// Install a plan of the calls to fail, replacing any previous plan
+///
+/// Failures are only injected in debug builds.
+///
+/// failure_plan: The plan, or None to stop injecting failures
+pub fn set_failure_plan(failure_plan: Option<FailurePlan>) {
+    let mut slot = plan().lock().unwrap();
+    PLAN_ENABLED.store(failure_plan.is_some(), Ordering::SeqCst);
+    *slot = failure_plan;
+}
+
+/// Return the number of failures injected by the current plan
+pub fn failures_injected() -> u64 {
+    match *plan().lock().unwrap() {
+        Some(ref failure_plan) => failure_plan.injected(),
+        None => 0,
+    }
+}
+
+/// Decide whether an API call is to fail, returning the failure to inject if it is
+///
+/// This costs a single atomic load when no plan is installed.
+///
+/// call_name: The name of the API call
+pub fn injected_failure(call_name: &str) -> Option<InjectedFailure> {
+    if !cfg!(debug_assertions) || !PLAN_ENABLED.load(Ordering::Relaxed) {
+        return None;
+    }
+
+    match *plan().lock().unwrap() {
+        Some(ref mut failure_plan) => failure_plan.next_failure(call_name),
+        None => None,
+    }
+}
@@ -0,0 +1,218 @@
+// Copyright (c) 2016-2017 Bruce Stenning. All rights reserved.
+//
+// Redistribution and use in source and binary forms, with or without
+// modification, are permitted provided that the following conditions
+// are met:
+//
+// 1. Redistributions of source code must retain the above copyright
+//    notice, this list of conditions and the following disclaimer.
+//
+// 2. Redistributions in binary form must reproduce the above copyright
+//    notice, this list of conditions and the following disclaimer in the
+//    documentation and/or other materials provided with the distribution.
+//
+// 3. Neither the name of the copyright holder nor the names of its
+//    contributors may be used to endorse or promote products derived
+//    from this software without specific prior written permission.
+//
+// THIS SOFTWARE IS PROVIDED BY THE COPYRIGHT HOLDERS AND CONTRIBUTORS
+// AS IS" AND ANY EXPRESS OR IMPLIED WARRANTIES, INCLUDING, BUT NOT
+// LIMITED TO, THE IMPLIED WARRANTIES OF MERCHANTABILITY AND FITNESS
+// FOR A PARTICULAR PURPOSE ARE DISCLAIMED. IN NO EVENT SHALL THE
+// COPYRIGHT HOLDER OR CONTRIBUTORS BE LIABLE FOR ANY DIRECT, INDIRECT,
+// INCIDENTAL, SPECIAL, EXEMPLARY, OR CONSEQUENTIAL DAMAGES (INCLUDING,
+// BUT NOT LIMITED TO, PROCUREMENT OF SUBSTITUTE GOODS OR SERVICES; LOSS
+// OF USE, DATA, OR PROFITS; OR BUSINESS INTERRUPTION) HOWEVER CAUSED
+// AND ON ANY THEORY OF LIABILITY, WHETHER IN CONTRACT, STRICT LIABILITY,
+// OR TORT (INCLUDING NEGLIGENCE OR OTHERWISE) ARISING IN ANY WAY OUT OF
+// THE USE OF THIS SOFTWARE, EVEN IF ADVISED OF THE POSSIBILITY OF SUCH
+// DAMAGE.
+
+use graphics::renderer::*;
+use graphics::resources::*;
+use misc::timer::Timer;
+
+/// The name of the uniform block the frame times are published in
+///
+/// The block is declared in GLSL as follows, at whatever set and binding suit the shader:
+///
+/// layout(set = 0, binding = 0) uniform frame_block {
+///     float frame_time;
+///     float frame_delta_time;
+///     float frame_real_time;
+///     float frame_real_delta_time;
+///     float frame_time_scale;
+/// };
+///
+/// Once registered with register_frame_exposure, a float frame_exposure follows the times.
+pub const FRAME_BLOCK: &'static str = "frame_block";
+
+/// The longest step engine time advances by in one frame, so that a hitch or a stop in the
+/// debugger does not make animations jump
+pub const DEFAULT_MAX_DELTA_TIME: f32 = 0.25f32;
+
+/// Pausable and scalable engine time, alongside the unscaled real time
+///
+/// The renderer ticks its clock at the start of each frame and publishes both times in the
+/// FRAME_BLOCK uniform block, when one is registered.  Shader animations and particles should
+/// use engine time, as should any CPU-side animation such as a transition, so that all of them
+/// stop together when paused and slow down together under a time scale.  Real time keeps
+/// running for things like UI that must respond while the game is paused.
+pub struct FrameClock {
+    timer: Timer,
+    last_real_ns: u64,
+    real_time: f64,
+    real_delta: f32,
+    time: f64,
+    delta: f32,
+    time_scale: f32,
+    max_delta: f32,
+    paused: bool,
+    pending_step: Option<f32>,
+}
+
+impl FrameClock {
+    pub fn new() -> FrameClock {
+        FrameClock {
+            timer: Timer::new(),
+            last_real_ns: 0,
+            real_time: 0.0f64,
+            real_delta: 0.0f32,
+            time: 0.0f64,
+            delta: 0.0f32,
+            time_scale: 1.0f32,
+            max_delta: DEFAULT_MAX_DELTA_TIME,
+            paused: false,
+            pending_step: None,
+        }
+    }
+
+    /// Advance the clock by the real time since it was last ticked
+    pub fn tick(&mut self) {
+        let now_ns = self.timer.read_ns();
+        let real_delta = (now_ns - self.last_real_ns) as f32 / 1.0e9f32;
+        self.last_real_ns = now_ns;
+
+        self.advance(real_delta);
+    }
+
+    /// Advance the clock by the specified real time
+    ///
+    /// This is what tick does with the measured time, and is exposed for fixed-step replays.
+    ///
+    /// real_delta: The real time that has passed in seconds
+    pub fn advance(&mut self, real_delta: f32) {
+        let real_delta = real_delta.max(0.0f32);
+        self.real_delta = real_delta;
+        self.real_time += real_delta as f64;
+
+        self.delta = match self.pending_step.take() {
+            Some(step) => step,
+            None if self.paused => 0.0f32,
+            None => real_delta.min(self.max_delta) * self.time_scale,
+        };
+        self.time += self.delta as f64;
+    }
+
+    /// Stop engine time, leaving real time running
+    pub fn pause(&mut self) {
+        self.paused = true;
+    }
+
+    /// Restart engine time after a pause
+    pub fn resume(&mut self) {
+        self.paused = false;
+    }
+
+    /// Return whether engine time is paused
+    pub fn is_paused(&self) -> bool {
+        self.paused
+    }
+
+    /// Advance engine time by a single step on the next tick, e.g. to step through frames while
+    /// paused.  The step is not scaled.
+    ///
+    /// step: The time to advance by in seconds
+    pub fn step(&mut self, step: f32) {
+        self.pending_step = Some(step.max(0.0f32));
+    }
+
+    /// Set the rate engine time runs at relative to real time, e.g. 0.25 for slow motion
+    ///
+    /// scale: The time scale, clamped to be non-negative
+    pub fn set_time_scale(&mut self, scale: f32) {
+        self.time_scale = scale.max(0.0f32);
+    }
+
+    /// Return the rate engine time runs at relative to real time
+    pub fn get_time_scale(&self) -> f32 {
+        self.time_scale
+    }
+
+    /// Set the longest step engine time advances by in one frame
+    ///
+    /// max_delta: The longest step in seconds, before scaling
+    pub fn set_max_delta(&mut self, max_delta: f32) {
+        self.max_delta = max_delta.max(0.0f32);
+    }
+
+    /// Return the engine time in seconds
+    pub fn get_time(&self) -> f64 {
+        self.time
+    }
+
+    /// Return how far engine time advanced in the last tick, in seconds
+    pub fn get_delta(&self) -> f32 {
+        self.delta
+    }
+
+    /// Return the real time since the clock was created, in seconds
+    pub fn get_real_time(&self) -> f64 {
+        self.real_time
+    }
+
+    /// Return how far real time advanced in the last tick, in seconds
+    pub fn get_real_delta(&self) -> f32 {
+        self.real_delta
+    }
+
+    /// Set the members of the FRAME_BLOCK uniform block and synchronise it
+    ///
+    /// The times are given to shaders in single precision, so lose precision after some hours.
+    ///
+    /// renderer: The renderer owning the uniform buffer
+    pub fn publish<Rend: Renderer + ?Sized>(&self, renderer: &Rend) {
+        renderer.set_uniform_buffer_float(FRAME_BLOCK, "frame_time", self.time as f32);
+        renderer.set_uniform_buffer_float(FRAME_BLOCK, "frame_delta_time", self.delta);
+        renderer.set_uniform_buffer_float(FRAME_BLOCK, "frame_real_time", self.real_time as f32);
+        renderer.set_uniform_buffer_float(FRAME_BLOCK, "frame_real_delta_time", self.real_delta);
+        renderer.set_uniform_buffer_float(FRAME_BLOCK, "frame_time_scale", self.time_scale);
+        renderer.synchronise_uniform_buffer(FRAME_BLOCK);
+    }
+}
+
+/// Add the specification of the FRAME_BLOCK uniform block, so the renderer publishes the frame
+/// times each frame
+///
+/// resource_manager: The resource manager to add the specification to
+pub fn register_frame_block(resource_manager: &mut ResourceManager) {
+    resource_manager.uniform_block_specs.insert(FRAME_BLOCK,
+                                                UniformBlockSpec {
+                                                    set: 0,
+                                                    block_type: UniformType::UniformBuffer,
+                                                    uniforms: ["frame_time",
+                                                               "frame_delta_time",
+                                                               "frame_real_time",
+                                                               "frame_real_delta_time",
+                                                               "frame_time_scale"]
+                                                        .iter()
+                                                        .map(|name| {
+                                                            BlockUniformSpec {
+                                                                name: *name,
+                                                                ..Default::default()
+                                                            }
+                                                        })
+                                                        .collect(),
+                                                    ..Default::default()
+                                                });
+}
@@ -0,0 +1,56 @@
+// Copyright (c) 2016-2017 Bruce Stenning. All rights reserved.
+//
+// Redistribution and use in source and binary forms, with or without
+// modification, are permitted provided that the following conditions
+// are met:
+//
+// 1. Redistributions of source code must retain the above copyright
+//    notice, this list of conditions and the following disclaimer.
+//
+// 2. Redistributions in binary form must reproduce the above copyright
+//    notice, this list of conditions and the following disclaimer in the
+//    documentation and/or other materials provided with the distribution.
+//
+// 3. Neither the name of the copyright holder nor the names of its
+//    contributors may be used to endorse or promote products derived
+//    from this software without specific prior written permission.
+//
+// THIS SOFTWARE IS PROVIDED BY THE COPYRIGHT HOLDERS AND CONTRIBUTORS
+// AS IS" AND ANY EXPRESS OR IMPLIED WARRANTIES, INCLUDING, BUT NOT
+// LIMITED TO, THE IMPLIED WARRANTIES OF MERCHANTABILITY AND FITNESS
+// FOR A PARTICULAR PURPOSE ARE DISCLAIMED. IN NO EVENT SHALL THE
+// COPYRIGHT HOLDER OR CONTRIBUTORS BE LIABLE FOR ANY DIRECT, INDIRECT,
+// INCIDENTAL, SPECIAL, EXEMPLARY, OR CONSEQUENTIAL DAMAGES (INCLUDING,
+// BUT NOT LIMITED TO, PROCUREMENT OF SUBSTITUTE GOODS OR SERVICES; LOSS
+// OF USE, DATA, OR PROFITS; OR BUSINESS INTERRUPTION) HOWEVER CAUSED
+// AND ON ANY THEORY OF LIABILITY, WHETHER IN CONTRACT, STRICT LIABILITY,
+// OR TORT (INCLUDING NEGLIGENCE OR OTHERWISE) ARISING IN ANY WAY OUT OF
+// THE USE OF THIS SOFTWARE, EVEN IF ADVISED OF THE POSSIBILITY OF SUCH
+// DAMAGE.
+
+use algebra::vector::Vec2;
+use graphics::renderer::*;
+
+/// Draw a quad covering the render target with the first thread data
+///
+/// The corners span the clip space and the texture coordinates run from 0 to 1 across them, as
+/// the full-screen passes sample their source textures.  The quad is flushed before returning.
+///
+/// renderer: The renderer to draw with, inside a pass whose shader takes F2F2 vertices
+pub fn draw_fullscreen_quad<Rend: Renderer + ?Sized>(renderer: &mut Rend) {
+    let corners = [Vec2 { x: -1.0f32, y: -1.0f32 },
+                   Vec2 { x: 1.0f32, y: -1.0f32 },
+                   Vec2 { x: 1.0f32, y: 1.0f32 },
+                   Vec2 { x: -1.0f32, y: 1.0f32 }];
+    let texcoords = [Vec2 { x: 0.0f32, y: 0.0f32 },
+                     Vec2 { x: 1.0f32, y: 0.0f32 },
+                     Vec2 { x: 1.0f32, y: 1.0f32 },
+                     Vec2 { x: 0.0f32, y: 1.0f32 }];
+
+    let threaddata_arc = renderer.get_threaddata(0);
+    let mut threaddata = threaddata_arc.lock().unwrap();
+    threaddata.vertex_array_type = VertexArrayType::F2F2;
+    threaddata.add_triangle_st_f2f2(&corners[0], &texcoords[0], &corners[1], &texcoords[1], &corners[2], &texcoords[2]);
+    threaddata.add_triangle_st_f2f2(&corners[0], &texcoords[0], &corners[2], &texcoords[2], &corners[3], &texcoords[3]);
+    threaddata.check_flush_st(true, renderer);
+}
@@ -0,0 +1,309 @@
+// Copyright (c) 2016-2017 Bruce Stenning. All rights reserved.
+//
+// Redistribution and use in source and binary forms, with or without
+// modification, are permitted provided that the following conditions
+// are met:
+//
+// 1. Redistributions of source code must retain the above copyright
+//    notice, this list of conditions and the following disclaimer.
+//
+// 2. Redistributions in binary form must reproduce the above copyright
+//    notice, this list of conditions and the following disclaimer in the
+//    documentation and/or other materials provided with the distribution.
+//
+// 3. Neither the name of the copyright holder nor the names of its
+//    contributors may be used to endorse or promote products derived
+//    from this software without specific prior written permission.
+//
+// THIS SOFTWARE IS PROVIDED BY THE COPYRIGHT HOLDERS AND CONTRIBUTORS
+// AS IS" AND ANY EXPRESS OR IMPLIED WARRANTIES, INCLUDING, BUT NOT
+// LIMITED TO, THE IMPLIED WARRANTIES OF MERCHANTABILITY AND FITNESS
+// FOR A PARTICULAR PURPOSE ARE DISCLAIMED. IN NO EVENT SHALL THE
+// COPYRIGHT HOLDER OR CONTRIBUTORS BE LIABLE FOR ANY DIRECT, INDIRECT,
+// INCIDENTAL, SPECIAL, EXEMPLARY, OR CONSEQUENTIAL DAMAGES (INCLUDING,
+// BUT NOT LIMITED TO, PROCUREMENT OF SUBSTITUTE GOODS OR SERVICES; LOSS
+// OF USE, DATA, OR PROFITS; OR BUSINESS INTERRUPTION) HOWEVER CAUSED
+// AND ON ANY THEORY OF LIABILITY, WHETHER IN CONTRACT, STRICT LIABILITY,
+// OR TORT (INCLUDING NEGLIGENCE OR OTHERWISE) ARISING IN ANY WAY OUT OF
+// THE USE OF THIS SOFTWARE, EVEN IF ADVISED OF THE POSSIBILITY OF SUCH
+// DAMAGE.
+
+use std::fs::File;
+use std::io;
+use std::io::prelude::*;
+use std::io::BufWriter;
+use std::sync::*;
+
+use graphics::renderer::*;
+use misc::fileutils::*;
+
+// Identifies a geometry capture file, followed by the format version
+const CAPTURE_MAGIC: &'static [u8; 4] = b"WYGC";
+const CAPTURE_VERSION: u32 = 1;
+
+/// One flushed batch of triangles, with the frame and thread it was generated on
+#[derive(Clone)]
+pub struct GeometryStream {
+    pub frame: u64,
+    pub thread: u32,
+    pub vertex_array_type: VertexArrayType,
+    pub primitive: PrimitiveType,
+    pub triangles: u32,
+    pub data: Vec<f32>,
+}
+
+impl GeometryStream {
+    /// Take a copy of the batch held in a thread's data
+    ///
+    /// frame: The frame the batch belongs to
+    /// threaddata: The thread data holding the batch
+    pub fn from_threaddata(frame: u64, threaddata: &ThreadData) -> GeometryStream {
+        let components = threaddata.index * VertexArrayType::components_per_vertex(threaddata.vertex_array_type) *
+                         PrimitiveType::vertices_per_primitive(threaddata.primitive);
+        GeometryStream {
+            frame: frame,
+            thread: threaddata.thr as u32,
+            vertex_array_type: threaddata.vertex_array_type,
+            primitive: threaddata.primitive,
+            triangles: threaddata.index as u32,
+            data: threaddata.data[0..components].to_vec(),
+        }
+    }
+
+    /// Append the little-endian binary encoding of the stream
+    ///
+    /// bytes: The buffer to append to
+    pub fn encode(&self, bytes: &mut Vec<u8>) {
+        push_u32(bytes, self.frame as u32);
+        push_u32(bytes, (self.frame >> 32) as u32);
+        push_u32(bytes, self.thread);
+        push_u32(bytes, self.vertex_array_type as u32);
+        push_u32(bytes, self.primitive as u32);
+        push_u32(bytes, self.triangles);
+        for value in self.data.iter() {
+            push_u32(bytes, value.to_bits());
+        }
+    }
+
+    /// Decode a stream, returning it and the offset just past it
+    ///
+    /// bytes: The encoded capture
+    /// offset: The offset of the stream within the capture
+    pub fn decode(bytes: &Vec<u8>, offset: usize) -> Result<(GeometryStream, usize), io::Error> {
+        let mut offset = offset;
+        let frame_low = read_u32(bytes, &mut offset)? as u64;
+        let frame_high = read_u32(bytes, &mut offset)? as u64;
+        let thread = read_u32(bytes, &mut offset)?;
+        let vertex_array_type = read_u32(bytes, &mut offset)?;
+        let primitive = read_u32(bytes, &mut offset)?;
+        let triangles = read_u32(bytes, &mut offset)?;
+
+        if vertex_array_type > VERTEX_ARRAY_TYPE_END_RANGE || primitive > PRIMITIVE_TYPE_END_RANGE ||
+           triangles as usize > TRIANGLE_ARRAY_MAX_SIZE {
+            return Err(io::Error::new(io::ErrorKind::InvalidData, "Corrupt geometry stream header"));
+        }
+
+        let vertex_array_type = VertexArrayType::from_u32(vertex_array_type);
+        let primitive = PrimitiveType::from_u32(primitive);
+        let components = triangles as usize * VertexArrayType::components_per_vertex(vertex_array_type) *
+                         PrimitiveType::vertices_per_primitive(primitive);
+        let mut data = Vec::with_capacity(components);
+        for _ in 0..components {
+            data.push(f32::from_bits(read_u32(bytes, &mut offset)?));
+        }
+
+        Ok((GeometryStream {
+                frame: frame_low | (frame_high << 32),
+                thread: thread,
+                vertex_array_type: vertex_array_type,
+                primitive: primitive,
+                triangles: triangles,
+                data: data,
+            },
+            offset))
+    }
+}
+
+/// Writes every batch of triangles flushed while it is attached to a binary file
+///
+/// Each batch is recorded with its frame number, thread, vertex array type and primitive, so
+/// that GeometryReplay can feed the same triangles to either backend later.
+pub struct GeometryCapture {
+    writer: Option<BufWriter<File>>,
+    frame: u64,
+    streams: usize,
+}
+
+impl GeometryCapture {
+    /// Create a capture file, ready to be attached to a renderer
+    ///
+    /// filename: The file to write the capture to
+    pub fn create(filename: &str) -> Result<Arc<Mutex<GeometryCapture>>, io::Error> {
+        let mut writer = BufWriter::new(File::create(filename)?);
+
+        let mut header = CAPTURE_MAGIC.to_vec();
+        push_u32(&mut header, CAPTURE_VERSION);
+        writer.write_all(&header)?;
+
+        Ok(Arc::new(Mutex::new(GeometryCapture {
+            writer: Some(writer),
+            frame: 0,
+            streams: 0,
+        })))
+    }
+
+    /// Start capturing the batches flushed by every thread
+    ///
+    /// capture: The capture to write to
+    /// renderer: The renderer object
+    pub fn attach<Rend: Renderer + ?Sized>(capture: &Arc<Mutex<GeometryCapture>>, renderer: &Rend) {
+        for thr in 0..renderer.get_maxthreads() {
+            let threaddata_arc = renderer.get_threaddata(thr);
+            threaddata_arc.lock().unwrap().capture = Some(capture.clone());
+        }
+    }
+
+    /// Stop capturing, leaving the capture file open until the capture is dropped or finished
+    ///
+    /// renderer: The renderer object
+    pub fn detach<Rend: Renderer + ?Sized>(renderer: &Rend) {
+        for thr in 0..renderer.get_maxthreads() {
+            let threaddata_arc = renderer.get_threaddata(thr);
+            threaddata_arc.lock().unwrap().capture = None;
+        }
+    }
+
+    /// Mark the start of the next frame, which subsequent batches are recorded against
+    pub fn begin_frame(&mut self) {
+        self.frame += 1;
+    }
+
+    /// Return the number of batches recorded so far
+    pub fn stream_count(&self) -> usize {
+        self.streams
+    }
+
+    /// Record the batch held in a thread's data
+    ///
+    /// A failed write is reported and ends the capture, rather than interrupting rendering.
+    ///
+    /// threaddata: The thread data holding the batch
+    pub fn record(&mut self, threaddata: &ThreadData) {
+        let mut bytes = vec![];
+        GeometryStream::from_threaddata(self.frame, threaddata).encode(&mut bytes);
+
+        let failed = match self.writer {
+            Some(ref mut writer) => {
+                match writer.write_all(&bytes) {
+                    Ok(_) => false,
+                    Err(e) => {
+                        log_warning!("Geometry capture failed: {}", e);
+                        true
+                    }
+                }
+            }
+            None => return,
+        };
+
+        if failed {
+            self.writer = None;
+        } else {
+            self.streams += 1;
+        }
+    }
+
+    /// Flush and close the capture file
+    pub fn finish(&mut self) -> Result<(), io::Error> {
+        match self.writer.take() {
+            Some(mut writer) => writer.flush(),
+            None => Ok(()),
+        }
+    }
+}
+
+/// The batches of a geometry capture, loaded for replay
+pub struct GeometryReplay {
+    streams: Vec<GeometryStream>,
+}
+
+impl GeometryReplay {
+    /// Load a capture file
+    ///
+    /// filename: The capture file written by GeometryCapture
+    pub fn load(filename: &str) -> Result<GeometryReplay, io::Error> {
+        let bytes = read_binary_file(filename, false)?;
+        GeometryReplay::decode(&bytes)
+    }
+
+    /// Decode a capture held in memory
+    ///
+    /// bytes: The contents of the capture file
+    pub fn decode(bytes: &Vec<u8>) -> Result<GeometryReplay, io::Error> {
+        if bytes.len() < CAPTURE_MAGIC.len() || &bytes[0..CAPTURE_MAGIC.len()] != &CAPTURE_MAGIC[..] {
+            return Err(io::Error::new(io::ErrorKind::InvalidData, "Not a geometry capture"));
+        }
+
+        let mut offset = CAPTURE_MAGIC.len();
+        let version = read_u32(bytes, &mut offset)?;
+        if version != CAPTURE_VERSION {
+            return Err(io::Error::new(io::ErrorKind::InvalidData,
+                                      format!("Unsupported geometry capture version {}", version)));
+        }
+
+        let mut streams = vec![];
+        while offset < bytes.len() {
+            let (stream, next) = GeometryStream::decode(bytes, offset)?;
+            streams.push(stream);
+            offset = next;
+        }
+
+        Ok(GeometryReplay { streams: streams })
+    }
+
+    /// Return all of the batches in the order they were flushed
+    pub fn get_streams(&self) -> &Vec<GeometryStream> {
+        &self.streams
+    }
+
+    /// Return the distinct frame numbers in the capture, in order
+    pub fn frames(&self) -> Vec<u64> {
+        let mut frames: Vec<u64> = self.streams.iter().map(|s| s.frame).collect();
+        frames.dedup();
+        frames
+    }
+
+    /// Flush the batches of a frame to the renderer through thread 0, in their original order
+    ///
+    /// Batches larger than the thread data holds are split, which breaks a line strip where they
+    /// are split.  The triangles are drawn with whatever shader is selected, so the caller sets up the same
+    /// shaders and uniforms as the captured application did.
+    ///
+    /// renderer: The renderer object
+    /// frame: The frame to replay
+    pub fn replay_frame<Rend: Renderer + ?Sized>(&self, renderer: &mut Rend, frame: u64) {
+        let threaddata_arc = renderer.get_threaddata(0);
+        let mut threaddata = threaddata_arc.lock().unwrap();
+
+        // The replayed batches are not captured again
+        let capture = threaddata.capture.take();
+        let vertex_array_type = threaddata.vertex_array_type;
+        let primitive = threaddata.primitive;
+
+        for stream in self.streams.iter().filter(|s| s.frame == frame) {
+            threaddata.vertex_array_type = stream.vertex_array_type;
+            threaddata.primitive = stream.primitive;
+
+            let components = VertexArrayType::components_per_vertex(stream.vertex_array_type) *
+                             PrimitiveType::vertices_per_primitive(stream.primitive);
+            for batch in stream.data.chunks(threaddata.capacity() * components) {
+                threaddata.data[0..batch.len()].copy_from_slice(batch);
+                threaddata.index = batch.len() / components;
+                threaddata.check_flush_st(true, renderer);
+            }
+        }
+
+        threaddata.capture = capture;
+        threaddata.vertex_array_type = vertex_array_type;
+        threaddata.primitive = primitive;
+    }
+}
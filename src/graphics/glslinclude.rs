@@ -0,0 +1,248 @@
+// Copyright (c) 2016-2017 Bruce Stenning. All rights reserved.
+//
+// Redistribution and use in source and binary forms, with or without
+// modification, are permitted provided that the following conditions
+// are met:
+//
+// 1. Redistributions of source code must retain the above copyright
+//    notice, this list of conditions and the following disclaimer.
+//
+// 2. Redistributions in binary form must reproduce the above copyright
+//    notice, this list of conditions and the following disclaimer in the
+//    documentation and/or other materials provided with the distribution.
+//
+// 3. Neither the name of the copyright holder nor the names of its
+//    contributors may be used to endorse or promote products derived
+//    from this software without specific prior written permission.
+//
+// THIS SOFTWARE IS PROVIDED BY THE COPYRIGHT HOLDERS AND CONTRIBUTORS
+// AS IS" AND ANY EXPRESS OR IMPLIED WARRANTIES, INCLUDING, BUT NOT
+// LIMITED TO, THE IMPLIED WARRANTIES OF MERCHANTABILITY AND FITNESS
+// FOR A PARTICULAR PURPOSE ARE DISCLAIMED. IN NO EVENT SHALL THE
+// COPYRIGHT HOLDER OR CONTRIBUTORS BE LIABLE FOR ANY DIRECT, INDIRECT,
+// INCIDENTAL, SPECIAL, EXEMPLARY, OR CONSEQUENTIAL DAMAGES (INCLUDING,
+// BUT NOT LIMITED TO, PROCUREMENT OF SUBSTITUTE GOODS OR SERVICES; LOSS
+// OF USE, DATA, OR PROFITS; OR BUSINESS INTERRUPTION) HOWEVER CAUSED
+// AND ON ANY THEORY OF LIABILITY, WHETHER IN CONTRACT, STRICT LIABILITY,
+// OR TORT (INCLUDING NEGLIGENCE OR OTHERWISE) ARISING IN ANY WAY OUT OF
+// THE USE OF THIS SOFTWARE, EVEN IF ADVISED OF THE POSSIBILITY OF SUCH
+// DAMAGE.
+
+use std::fs;
+use std::io;
+
+use misc::embeddedresources::*;
+
+/// The deepest nesting of includes accepted, beyond which a cycle is assumed to have been missed
+pub const MAX_INCLUDE_DEPTH: usize = 32;
+
+/// A GLSL preprocessor that resolves #include directives
+///
+/// Each file is given a source string number, the index of its entry in the files read, and the
+/// expanded source carries #line directives naming it so compilation logs point at the original
+/// file and line.  Included paths are resolved relative to the directory of the including file.
+///
+/// Directives are recognised on any line starting with #include, including those in inactive
+/// conditional blocks, as the preprocessor does not evaluate conditions.
+pub struct GlslPreprocessor {
+    files: Vec<String>, // Every file read, indexed by its source string number
+    stack: Vec<String>, // The chain of files currently being expanded, for detecting cycles
+}
+
+impl GlslPreprocessor {
+    pub fn new() -> GlslPreprocessor {
+        GlslPreprocessor {
+            files: vec![],
+            stack: vec![],
+        }
+    }
+
+    /// Return every file read so far, indexed by source string number
+    ///
+    /// This includes the files read before a failed expansion, so they can still be watched for
+    /// modification.
+    pub fn files(&self) -> &Vec<String> {
+        &self.files
+    }
+
+    /// Return the source string number of a file, recording it if it is new
+    ///
+    /// filename: The resolved name of the file
+    fn source_number(&mut self, filename: &str) -> usize {
+        match self.files.iter().position(|f| f == filename) {
+            Some(index) => index,
+            None => {
+                self.files.push(filename.to_string());
+                self.files.len() - 1
+            }
+        }
+    }
+
+    /// Expand a file and everything it includes
+    ///
+    /// filename: The name of the file to expand
+    /// read: Returns the contents of a file, or None if it does not exist
+    ///
+    /// Returns: The expanded source, starting with a #line directive for the file
+    pub fn expand(&mut self, filename: &str, read: &mut FnMut(&str) -> Option<String>) -> Result<String, io::Error> {
+        if self.stack.iter().any(|f| f == filename) {
+            let mut chain = self.stack.clone();
+            chain.push(filename.to_string());
+            return Err(io::Error::new(io::ErrorKind::InvalidData,
+                                      format!("Cyclic include: {}", chain.join(" -> "))));
+        }
+        if self.stack.len() >= MAX_INCLUDE_DEPTH {
+            return Err(io::Error::new(io::ErrorKind::InvalidData,
+                                      format!("Includes nested more than {} deep at {}", MAX_INCLUDE_DEPTH, filename)));
+        }
+
+        let number = self.source_number(filename);
+        let contents = match read(filename) {
+            Some(c) => c,
+            None => {
+                let message = match self.stack.last() {
+                    Some(including) => format!("Unable to read {}, included from {}", filename, including),
+                    None => format!("Unable to read {}", filename),
+                };
+                return Err(io::Error::new(io::ErrorKind::NotFound, message));
+            }
+        };
+
+        self.stack.push(filename.to_string());
+
+        let mut expanded = format!("#line 1 {}\n", number);
+        for (index, line) in contents.lines().enumerate() {
+            match parse_include(line) {
+                Some(included) => {
+                    let path = resolve_include_path(filename, included);
+                    let source = match self.expand(&path, read) {
+                        Ok(s) => s,
+                        Err(e) => {
+                            self.stack.pop();
+                            return Err(e);
+                        }
+                    };
+                    expanded.push_str(&source);
+                    if !source.ends_with('\n') {
+                        expanded.push('\n');
+                    }
+                    // Resume numbering at the line after the directive
+                    expanded.push_str(&format!("#line {} {}\n", index + 2, number));
+                }
+                None => {
+                    expanded.push_str(line);
+                    expanded.push('\n');
+                }
+            }
+        }
+
+        self.stack.pop();
+
+        Ok(expanded)
+    }
+}
+
+/// Return the path named by an #include directive, or None if the line is not one
+///
+/// Both the quoted and the angle bracket forms are accepted, and whitespace is allowed around
+/// the #.
+///
+/// line: A line of GLSL source
+pub fn parse_include(line: &str) -> Option<&str> {
+    let trimmed = line.trim_start();
+    if !trimmed.starts_with('#') {
+        return None;
+    }
+    let directive = trimmed[1..].trim_start();
+    if !directive.starts_with("include") {
+        return None;
+    }
+    let argument = directive["include".len()..].trim();
+
+    let close = match argument.chars().next() {
+        Some('"') => '"',
+        Some('<') => '>',
+        _ => return None,
+    };
+    match argument[1..].find(close) {
+        Some(end) if end > 0 => Some(&argument[1..end + 1]),
+        _ => None,
+    }
+}
+
+/// Resolve an included path relative to the directory of the including file
+///
+/// The result is normalised, so the same file reached by different routes has the same name for
+/// cycle detection and modification checking.  Absolute included paths are used as they are.
+///
+/// including: The name of the including file
+/// included: The path named by the #include directive
+pub fn resolve_include_path(including: &str, included: &str) -> String {
+    let joined = if included.starts_with('/') {
+        included.to_string()
+    } else {
+        match including.rfind(|c| c == '/' || c == '\\') {
+            Some(end) => including[..end + 1].to_string() + included,
+            None => included.to_string(),
+        }
+    };
+
+    let mut components: Vec<&str> = vec![];
+    for component in joined.split(|c| c == '/' || c == '\\') {
+        match component {
+            "" | "." => (),
+            ".." => {
+                match components.last() {
+                    Some(&last) if last != ".." => {
+                        components.pop();
+                    }
+                    _ => components.push(".."),
+                }
+            }
+            _ => components.push(component),
+        }
+    }
+
+    let normalised = components.join("/");
+    if joined.starts_with('/') {
+        "/".to_string() + &normalised
+    } else {
+        normalised
+    }
+}
+
+/// Read a shader source file, from the embedded resources if they are in use
+///
+/// autos: The automatically generated resources object
+/// filename: The name of the file
+pub fn read_shader_source(autos: Option<&EmbeddedResources>, filename: &str) -> Option<String> {
+    match autos {
+        Some(ref autos) if autos.use_me() => autos.resource(filename).map(|s| String::from_utf8_lossy(&s).into_owned()),
+        _ => fs::read_to_string(filename).ok(),
+    }
+}
+
+/// Expand the library files and source of a shader stage with their includes resolved
+///
+/// The library files are expanded in order ahead of the source, each with its own source string
+/// number.
+///
+/// preprocessor: The preprocessor, which records the files read
+/// autos: The automatically generated resources object
+/// library_files: The library files of the shader
+/// filename: The source file of the stage
+pub fn preprocess_glsl(preprocessor: &mut GlslPreprocessor,
+                       autos: Option<&EmbeddedResources>,
+                       library_files: &Vec<&'static str>,
+                       filename: &str)
+                       -> Result<String, io::Error> {
+    let mut read = |name: &str| read_shader_source(autos, name);
+
+    let mut source = String::new();
+    for library_file in library_files.iter() {
+        source.push_str(&preprocessor.expand(library_file, &mut read)?);
+    }
+    source.push_str(&preprocessor.expand(filename, &mut read)?);
+
+    Ok(source)
+}
@@ -0,0 +1,492 @@
+// Copyright (c) 2016-2017 Bruce Stenning. All rights reserved.
+//
+// Redistribution and use in source and binary forms, with or without
+// modification, are permitted provided that the following conditions
+// are met:
+//
+// 1. Redistributions of source code must retain the above copyright
+//    notice, this list of conditions and the following disclaimer.
+//
+// 2. Redistributions in binary form must reproduce the above copyright
+//    notice, this list of conditions and the following disclaimer in the
+//    documentation and/or other materials provided with the distribution.
+//
+// 3. Neither the name of the copyright holder nor the names of its
+//    contributors may be used to endorse or promote products derived
+//    from this software without specific prior written permission.
+//
+// THIS SOFTWARE IS PROVIDED BY THE COPYRIGHT HOLDERS AND CONTRIBUTORS
+// AS IS" AND ANY EXPRESS OR IMPLIED WARRANTIES, INCLUDING, BUT NOT
+// LIMITED TO, THE IMPLIED WARRANTIES OF MERCHANTABILITY AND FITNESS
+// FOR A PARTICULAR PURPOSE ARE DISCLAIMED. IN NO EVENT SHALL THE
+// COPYRIGHT HOLDER OR CONTRIBUTORS BE LIABLE FOR ANY DIRECT, INDIRECT,
+// INCIDENTAL, SPECIAL, EXEMPLARY, OR CONSEQUENTIAL DAMAGES (INCLUDING,
+// BUT NOT LIMITED TO, PROCUREMENT OF SUBSTITUTE GOODS OR SERVICES; LOSS
+// OF USE, DATA, OR PROFITS; OR BUSINESS INTERRUPTION) HOWEVER CAUSED
+// AND ON ANY THEORY OF LIABILITY, WHETHER IN CONTRACT, STRICT LIABILITY,
+// OR TORT (INCLUDING NEGLIGENCE OR OTHERWISE) ARISING IN ANY WAY OUT OF
+// THE USE OF THIS SOFTWARE, EVEN IF ADVISED OF THE POSSIBILITY OF SUCH
+// DAMAGE.
+use std::io;
+
+use graphics::renderer::*;
+use graphics::resources::*;
+use graphics::shader::*;
+use misc::fileutils::*;
+
+/// The name of the storage block holding the key/value pairs being sorted
+///
+/// The block is declared in GLSL by SORT_LIBRARY_FILENAME.  Shaders that produce keys, such as
+/// particle depths or fragment list entries, may include the library and write the pairs
+/// themselves, and shaders that consume the sorted order read the values back from it.
+///
+/// layout(set = 0, binding = 0) buffer sort_buffer {
+///     uint sort_keys[SORT_MAX_ELEMENTS];
+///     uint sort_values[SORT_MAX_ELEMENTS];
+///     uint sort_scratch_keys[SORT_MAX_ELEMENTS];
+///     uint sort_scratch_values[SORT_MAX_ELEMENTS];
+///     uint sort_histogram[RADIX_BUCKETS * SORT_MAX_WORKGROUPS];
+/// };
+pub const SORT_BLOCK: &'static str = "sort_buffer";
+
+/// The name of the uniform block holding the parameters of each sorting dispatch
+pub const SORT_PARAMS_BLOCK: &'static str = "sort_params";
+
+/// The GLSL library declaring the sort blocks
+pub const SORT_LIBRARY_FILENAME: &'static str = "sort_common.glsl";
+
+/// The names the sorting compute shaders are registered under
+pub const BITONIC_SORT_SHADER_NAME: &'static str = "bitonic_sort";
+pub const RADIX_COUNT_SHADER_NAME: &'static str = "radix_sort_count";
+pub const RADIX_SCAN_SHADER_NAME: &'static str = "radix_sort_scan";
+pub const RADIX_SCATTER_SHADER_NAME: &'static str = "radix_sort_scatter";
+
+/// The most pairs that can be sorted at once
+pub const SORT_MAX_ELEMENTS: usize = 65536;
+
+/// The number of invocations in a workgroup of each sorting shader
+pub const SORT_WORKGROUP_SIZE: usize = 256;
+
+/// The most workgroups a radix sort pass is dispatched with
+pub const SORT_MAX_WORKGROUPS: usize = SORT_MAX_ELEMENTS / SORT_WORKGROUP_SIZE;
+
+/// The number of key bits each radix sort pass sorts by
+pub const RADIX_BITS: u32 = 4;
+
+/// The number of digit values of each radix sort pass
+pub const RADIX_BUCKETS: usize = 1 << RADIX_BITS;
+
+/// The most pairs sorted with a bitonic sort, above which the radix sort is quicker
+pub const BITONIC_MAX_ELEMENTS: usize = 8192;
+
+/// The most pairs sorted on the CPU when they are supplied from the CPU anyway
+pub const CPU_SORT_MAX_ELEMENTS: usize = 1024;
+
+/// The key the bitonic sort pads the pairs to a power of two with, which sorts after all others
+///
+/// Keys to be sorted by the bitonic sort should be less than this.
+pub const SORT_PADDING_KEY: u32 = 0xffffffff;
+
+const SORT_LIBRARY_SOURCE: &'static str = include_str!("shaders/sort_common.glsl");
+const BITONIC_SORT_FILENAME: &'static str = "bitonic_sort.comp";
+const BITONIC_SORT_SOURCE: &'static str = include_str!("shaders/bitonic_sort.comp");
+const RADIX_COUNT_FILENAME: &'static str = "radix_sort_count.comp";
+const RADIX_COUNT_SOURCE: &'static str = include_str!("shaders/radix_sort_count.comp");
+const RADIX_SCAN_FILENAME: &'static str = "radix_sort_scan.comp";
+const RADIX_SCAN_SOURCE: &'static str = include_str!("shaders/radix_sort_scan.comp");
+const RADIX_SCATTER_FILENAME: &'static str = "radix_sort_scatter.comp";
+const RADIX_SCATTER_SOURCE: &'static str = include_str!("shaders/radix_sort_scatter.comp");
+
+/// The way a set of pairs is sorted
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub enum SortMethod {
+    /// Sorted on the CPU and uploaded, when there is no compute support or few pairs
+    Cpu,
+    /// Sorted by a bitonic network of compare and exchange dispatches, which is not stable
+    Bitonic,
+    /// Sorted stably by counting, scanning and scattering RADIX_BITS of the key at a time
+    Radix,
+}
+
+/// Choose how to sort pairs supplied from the CPU
+///
+/// count: The number of pairs
+/// compute_available: Whether the renderer can dispatch compute shaders
+pub fn choose_sort_method(count: usize, compute_available: bool) -> SortMethod {
+    if !compute_available || count <= CPU_SORT_MAX_ELEMENTS {
+        SortMethod::Cpu
+    } else {
+        choose_gpu_sort_method(count)
+    }
+}
+
+/// Choose how to sort pairs that are already in SORT_BLOCK
+///
+/// count: The number of pairs
+pub fn choose_gpu_sort_method(count: usize) -> SortMethod {
+    if count <= BITONIC_MAX_ELEMENTS {
+        SortMethod::Bitonic
+    } else {
+        SortMethod::Radix
+    }
+}
+
+/// Sort pairs by key on the CPU, keeping pairs with equal keys in order
+///
+/// keys: The keys to sort by
+/// values: The values to be kept with their keys
+pub fn sort_pairs_cpu(keys: &mut [u32], values: &mut [u32]) {
+    debug_assert!(keys.len() == values.len());
+
+    let mut pairs: Vec<(u32, u32)> = keys.iter().cloned().zip(values.iter().cloned()).collect();
+    pairs.sort_by_key(|pair| pair.0);
+    for (i, pair) in pairs.into_iter().enumerate() {
+        keys[i] = pair.0;
+        values[i] = pair.1;
+    }
+}
+
+/// A dispatch of one of the sorting shaders, with the SORT_PARAMS_BLOCK contents it runs with
+#[derive(Clone, Debug, PartialEq)]
+pub struct SortDispatch {
+    pub shader_name: &'static str,
+    /// The number of pairs being sorted
+    pub count: u32,
+    /// The number of pairs rounded up to a power of two, for the bitonic sort
+    pub padded_count: u32,
+    /// The size of the bitonic sequences being merged, or zero to pad the pairs
+    pub stage: u32,
+    /// The distance between the pairs compared by a bitonic merge step
+    pub step: u32,
+    /// The lowest key bit of the radix sort pass's digit
+    pub shift: u32,
+    /// Whether the radix sort pass reads from the scratch arrays rather than the pairs
+    pub flip: bool,
+    /// The number of workgroups in x
+    pub workgroups: u32,
+}
+
+/// The dispatches that sort pairs in SORT_BLOCK with the bitonic or radix sort
+#[derive(Clone, Debug)]
+pub struct SortPlan {
+    pub method: SortMethod,
+    pub count: usize,
+    pub dispatches: Vec<SortDispatch>,
+}
+
+impl SortPlan {
+    /// Plan the dispatches of a sort
+    ///
+    /// An even number of radix sort passes cover the key, so that the pairs end up back in
+    /// sort_keys and sort_values.
+    ///
+    /// count: The number of pairs, at most SORT_MAX_ELEMENTS
+    /// method: The bitonic or radix sort
+    pub fn new(count: usize, method: SortMethod) -> SortPlan {
+        debug_assert!(count <= SORT_MAX_ELEMENTS);
+        let count = count.min(SORT_MAX_ELEMENTS);
+        let padded_count = count.next_power_of_two();
+        let groups = |n: usize| ((n + SORT_WORKGROUP_SIZE - 1) / SORT_WORKGROUP_SIZE).max(1) as u32;
+        let dispatch = SortDispatch {
+            shader_name: BITONIC_SORT_SHADER_NAME,
+            count: count as u32,
+            padded_count: padded_count as u32,
+            stage: 0,
+            step: 0,
+            shift: 0,
+            flip: false,
+            workgroups: groups(padded_count),
+        };
+
+        let mut dispatches = vec![];
+        match method {
+            SortMethod::Cpu => {}
+            SortMethod::Bitonic => {
+                if padded_count > count {
+                    dispatches.push(dispatch.clone());
+                }
+                let mut stage = 2;
+                while stage <= padded_count {
+                    let mut step = stage / 2;
+                    while step > 0 {
+                        dispatches.push(SortDispatch {
+                            stage: stage as u32,
+                            step: step as u32,
+                            ..dispatch.clone()
+                        });
+                        step /= 2;
+                    }
+                    stage *= 2;
+                }
+            }
+            SortMethod::Radix => {
+                for pass in 0..(32 / RADIX_BITS) {
+                    let pass_dispatch = SortDispatch {
+                        shift: pass * RADIX_BITS,
+                        flip: pass % 2 == 1,
+                        workgroups: groups(count),
+                        ..dispatch.clone()
+                    };
+                    dispatches.push(SortDispatch { shader_name: RADIX_COUNT_SHADER_NAME, ..pass_dispatch.clone() });
+                    dispatches.push(SortDispatch {
+                        shader_name: RADIX_SCAN_SHADER_NAME,
+                        workgroups: 1,
+                        ..pass_dispatch.clone()
+                    });
+                    dispatches.push(SortDispatch { shader_name: RADIX_SCATTER_SHADER_NAME, ..pass_dispatch });
+                }
+            }
+        }
+
+        SortPlan {
+            method: method,
+            count: count,
+            dispatches: dispatches,
+        }
+    }
+
+    /// Run the dispatches on the CPU as the shaders run them, returning the sorted pairs
+    ///
+    /// This is the reference the sorting shaders are checked against.
+    ///
+    /// keys: The keys to sort by
+    /// values: The values to be kept with their keys
+    pub fn emulate(&self, keys: &[u32], values: &[u32]) -> (Vec<u32>, Vec<u32>) {
+        let mut keys_out = keys[..self.count].to_vec();
+        let mut values_out = values[..self.count].to_vec();
+        if self.method == SortMethod::Cpu {
+            sort_pairs_cpu(&mut keys_out, &mut values_out);
+            return (keys_out, values_out);
+        }
+
+        let padded_count = self.count.next_power_of_two();
+        keys_out.resize(padded_count, 0);
+        values_out.resize(padded_count, 0);
+        let mut scratch_keys = vec![0; padded_count];
+        let mut scratch_values = vec![0; padded_count];
+        let mut histogram = vec![0u32; RADIX_BUCKETS * SORT_MAX_WORKGROUPS];
+
+        for dispatch in self.dispatches.iter() {
+            let invocations = dispatch.workgroups as usize * SORT_WORKGROUP_SIZE;
+            let count = dispatch.count as usize;
+            let groups = (count + SORT_WORKGROUP_SIZE - 1) / SORT_WORKGROUP_SIZE;
+            let digit = |key: u32| ((key >> dispatch.shift) as usize) & (RADIX_BUCKETS - 1);
+
+            if dispatch.shader_name == BITONIC_SORT_SHADER_NAME && dispatch.stage == 0 {
+                for i in count..padded_count {
+                    keys_out[i] = SORT_PADDING_KEY;
+                    values_out[i] = SORT_PADDING_KEY;
+                }
+            } else if dispatch.shader_name == BITONIC_SORT_SHADER_NAME {
+                for i in 0..invocations.min(padded_count) {
+                    let partner = i ^ dispatch.step as usize;
+                    if partner > i {
+                        let ascending = i & dispatch.stage as usize == 0;
+                        if (keys_out[i] > keys_out[partner]) == ascending && keys_out[i] != keys_out[partner] {
+                            keys_out.swap(i, partner);
+                            values_out.swap(i, partner);
+                        }
+                    }
+                }
+            } else {
+                let (source_keys, source_values, dest_keys, dest_values) = if dispatch.flip {
+                    (&mut scratch_keys, &mut scratch_values, &mut keys_out, &mut values_out)
+                } else {
+                    (&mut keys_out, &mut values_out, &mut scratch_keys, &mut scratch_values)
+                };
+
+                if dispatch.shader_name == RADIX_COUNT_SHADER_NAME {
+                    for group in 0..groups {
+                        for bucket in 0..RADIX_BUCKETS {
+                            histogram[bucket * groups + group] = 0;
+                        }
+                        for i in (group * SORT_WORKGROUP_SIZE)..((group + 1) * SORT_WORKGROUP_SIZE).min(count) {
+                            histogram[digit(source_keys[i]) * groups + group] += 1;
+                        }
+                    }
+                } else if dispatch.shader_name == RADIX_SCAN_SHADER_NAME {
+                    let mut sum = 0;
+                    for entry in histogram[..RADIX_BUCKETS * groups].iter_mut() {
+                        let entry_count = *entry;
+                        *entry = sum;
+                        sum += entry_count;
+                    }
+                } else {
+                    for i in 0..count {
+                        let group = i / SORT_WORKGROUP_SIZE;
+                        let d = digit(source_keys[i]);
+                        let rank = ((group * SORT_WORKGROUP_SIZE)..i).filter(|&j| digit(source_keys[j]) == d).count();
+                        let destination = histogram[d * groups + group] as usize + rank;
+                        dest_keys[destination] = source_keys[i];
+                        dest_values[destination] = source_values[i];
+                    }
+                }
+            }
+        }
+
+        keys_out.truncate(self.count);
+        values_out.truncate(self.count);
+        (keys_out, values_out)
+    }
+}
+
+/// Write the sources of the sorting shaders to the working directory
+///
+/// The compute shaders include the sort library and are all built during setup, before the first
+/// dispatch, so this must be called before the renderer is set up.
+pub fn install_sort_shader_sources() -> Result<(), io::Error> {
+    write_entire_file(SORT_LIBRARY_SOURCE, SORT_LIBRARY_FILENAME)?;
+    write_entire_file(BITONIC_SORT_SOURCE, BITONIC_SORT_FILENAME)?;
+    write_entire_file(RADIX_COUNT_SOURCE, RADIX_COUNT_FILENAME)?;
+    write_entire_file(RADIX_SCAN_SOURCE, RADIX_SCAN_FILENAME)?;
+    write_entire_file(RADIX_SCATTER_SOURCE, RADIX_SCATTER_FILENAME)?;
+
+    Ok(())
+}
+
+/// Return a block specification whose members are filled in by the reflection data
+fn sort_block_spec(block_type: UniformType, names: &[&'static str]) -> UniformBlockSpec {
+    UniformBlockSpec {
+        set: 0,
+        block_type: block_type,
+        uniforms: names.iter()
+            .map(|name| {
+                BlockUniformSpec {
+                    name: *name,
+                    ..Default::default()
+                }
+            })
+            .collect(),
+        ..Default::default()
+    }
+}
+
+/// Return the specification of one of the sorting compute shaders
+fn sort_shader_spec(name: &'static str,
+                    filename: &'static str,
+                    spirv_out: &'static str,
+                    reflect_out: &'static str)
+                    -> ShaderSpec {
+    ShaderSpec {
+        name: name,
+        library_files: vec![SORT_LIBRARY_FILENAME],
+        shader_files: vec![ShaderFilesSpecification {
+                               filename: filename,
+                               shader_stage: ShaderStage::ComputeShader,
+                               spirv_out: spirv_out,
+                               reflect_out: reflect_out,
+                               entry_point: DEFAULT_ENTRY_POINT,
+                           }],
+        fallback_shader_files: vec![],
+        uniform_block_names: vec![SORT_BLOCK, SORT_PARAMS_BLOCK],
+        uniform_specs: vec![],
+        push_constants: vec![],
+        vertex_array_type: VertexArrayType::F3,
+        primitive: PrimitiveType::PrimitiveTriangles,
+        instance_layout: None,
+        attributes: vec![],
+        fragment_out: "",
+        extra_fragment_outs: vec![],
+        render_state: RenderState::default(),
+        stencil: None,
+        bindless_textures: false,
+        pass_name: "", // Compute shaders draw in no render pass
+    }
+}
+
+/// Add the blocks and compute shader specifications of the bitonic and radix sorts
+///
+/// resource_manager: The resource manager to add the specifications to
+pub fn register_sort_shaders(resource_manager: &mut ResourceManager) {
+    resource_manager.uniform_block_specs.insert(SORT_BLOCK,
+                                                sort_block_spec(UniformType::StorageBuffer,
+                                                                &["sort_keys",
+                                                                  "sort_values",
+                                                                  "sort_scratch_keys",
+                                                                  "sort_scratch_values",
+                                                                  "sort_histogram"]));
+    resource_manager.uniform_block_specs.insert(SORT_PARAMS_BLOCK,
+                                                sort_block_spec(UniformType::UniformBuffer,
+                                                                &["sort_count",
+                                                                  "sort_padded_count",
+                                                                  "sort_stage",
+                                                                  "sort_step",
+                                                                  "sort_shift",
+                                                                  "sort_flip"]));
+
+    for &(name, filename, spirv_out, reflect_out) in
+        [(BITONIC_SORT_SHADER_NAME, BITONIC_SORT_FILENAME, "bitonic_sort.comp.spv", "bitonic_sort.comp.rfl"),
+         (RADIX_COUNT_SHADER_NAME, RADIX_COUNT_FILENAME, "radix_sort_count.comp.spv", "radix_sort_count.comp.rfl"),
+         (RADIX_SCAN_SHADER_NAME, RADIX_SCAN_FILENAME, "radix_sort_scan.comp.spv", "radix_sort_scan.comp.rfl"),
+         (RADIX_SCATTER_SHADER_NAME, RADIX_SCATTER_FILENAME, "radix_sort_scatter.comp.spv", "radix_sort_scatter.comp.rfl")]
+            .iter() {
+        resource_manager.shader_specs.insert(name, sort_shader_spec(name, filename, spirv_out, reflect_out));
+    }
+}
+
+/// Run the dispatches of a sort plan on the pairs in SORT_BLOCK
+fn run_sort_plan<Rend: Renderer + ?Sized>(renderer: &mut Rend, plan: &SortPlan) {
+    for dispatch in plan.dispatches.iter() {
+        renderer.set_uniform_buffer_int(SORT_PARAMS_BLOCK, "sort_count", dispatch.count as i32);
+        renderer.set_uniform_buffer_int(SORT_PARAMS_BLOCK, "sort_padded_count", dispatch.padded_count as i32);
+        renderer.set_uniform_buffer_int(SORT_PARAMS_BLOCK, "sort_stage", dispatch.stage as i32);
+        renderer.set_uniform_buffer_int(SORT_PARAMS_BLOCK, "sort_step", dispatch.step as i32);
+        renderer.set_uniform_buffer_int(SORT_PARAMS_BLOCK, "sort_shift", dispatch.shift as i32);
+        renderer.set_uniform_buffer_int(SORT_PARAMS_BLOCK, "sort_flip", dispatch.flip as i32);
+        renderer.synchronise_uniform_buffer(SORT_PARAMS_BLOCK);
+
+        renderer.dispatch_compute(dispatch.shader_name, dispatch.workgroups, 1, 1);
+    }
+}
+
+/// Sort key/value pairs, leaving them in sort_keys and sort_values of SORT_BLOCK
+///
+/// The pairs are sorted on the CPU when the renderer has no compute shaders or there are few
+/// of them, and otherwise uploaded and sorted on the GPU.  This must be called at a pass
+/// boundary, and returns the method used.
+///
+/// renderer: The renderer to sort with
+/// keys: The keys to sort by
+/// values: The values to be kept with their keys
+/// count: The number of pairs, at most SORT_MAX_ELEMENTS
+pub fn sort_buffer<Rend: Renderer + ?Sized>(renderer: &mut Rend, keys: &[u32], values: &[u32], count: usize) -> SortMethod {
+    let count = count.min(keys.len()).min(values.len()).min(SORT_MAX_ELEMENTS);
+    let method = choose_sort_method(count, renderer.supports_compute());
+
+    let mut keys = keys[..count].to_vec();
+    let mut values = values[..count].to_vec();
+    if method == SortMethod::Cpu {
+        sort_pairs_cpu(&mut keys, &mut values);
+    }
+
+    // The bit patterns of the uints are copied into the block unchanged
+    renderer.set_uniform_buffer_float_vector(SORT_BLOCK,
+                                             "sort_keys",
+                                             &keys.iter().map(|k| f32::from_bits(*k)).collect());
+    renderer.set_uniform_buffer_float_vector(SORT_BLOCK,
+                                             "sort_values",
+                                             &values.iter().map(|v| f32::from_bits(*v)).collect());
+    renderer.synchronise_uniform_buffer(SORT_BLOCK);
+
+    run_sort_plan(renderer, &SortPlan::new(count, method));
+
+    method
+}
+
+/// Sort the key/value pairs already written to SORT_BLOCK by other shaders
+///
+/// This must be called at a pass boundary.  Nothing is sorted if the renderer has no compute
+/// shaders, since the pairs cannot be read back to be sorted on the CPU.
+///
+/// renderer: The renderer to sort with
+/// count: The number of pairs, at most SORT_MAX_ELEMENTS
+pub fn sort_resident_buffer<Rend: Renderer + ?Sized>(renderer: &mut Rend, count: usize) -> Option<SortMethod> {
+    if !renderer.supports_compute() {
+        return None;
+    }
+
+    let method = choose_gpu_sort_method(count);
+    run_sort_plan(renderer, &SortPlan::new(count, method));
+
+    Some(method)
+}
@@ -0,0 +1,359 @@
+// Copyright (c) 2016-2017 Bruce Stenning. All rights reserved.
+//
+// Redistribution and use in source and binary forms, with or without
+// modification, are permitted provided that the following conditions
+// are met:
+//
+// 1. Redistributions of source code must retain the above copyright
+//    notice, this list of conditions and the following disclaimer.
+//
+// 2. Redistributions in binary form must reproduce the above copyright
+//    notice, this list of conditions and the following disclaimer in the
+//    documentation and/or other materials provided with the distribution.
+//
+// 3. Neither the name of the copyright holder nor the names of its
+//    contributors may be used to endorse or promote products derived
+//    from this software without specific prior written permission.
+//
+// THIS SOFTWARE IS PROVIDED BY THE COPYRIGHT HOLDERS AND CONTRIBUTORS
+// AS IS" AND ANY EXPRESS OR IMPLIED WARRANTIES, INCLUDING, BUT NOT
+// LIMITED TO, THE IMPLIED WARRANTIES OF MERCHANTABILITY AND FITNESS
+// FOR A PARTICULAR PURPOSE ARE DISCLAIMED. IN NO EVENT SHALL THE
+// COPYRIGHT HOLDER OR CONTRIBUTORS BE LIABLE FOR ANY DIRECT, INDIRECT,
+// INCIDENTAL, SPECIAL, EXEMPLARY, OR CONSEQUENTIAL DAMAGES (INCLUDING,
+// BUT NOT LIMITED TO, PROCUREMENT OF SUBSTITUTE GOODS OR SERVICES; LOSS
+// OF USE, DATA, OR PROFITS; OR BUSINESS INTERRUPTION) HOWEVER CAUSED
+// AND ON ANY THEORY OF LIABILITY, WHETHER IN CONTRACT, STRICT LIABILITY,
+// OR TORT (INCLUDING NEGLIGENCE OR OTHERWISE) ARISING IN ANY WAY OUT OF
+// THE USE OF THIS SOFTWARE, EVEN IF ADVISED OF THE POSSIBILITY OF SUCH
+// DAMAGE.
+
+use std::io;
+
+use graphics::renderer::*;
+use graphics::resources::*;
+use graphics::shader::*;
+use graphics::texture::*;
+use misc::fileutils::*;
+
+/// The name the GPU text shader is registered under
+pub const GPU_TEXT_SHADER_NAME: &'static str = "gpu_text";
+
+/// The name of the storage block holding the glyph records
+pub const GLYPH_BLOCK: &'static str = "glyph_buffer";
+
+/// The name of the uniform block holding the viewport size and the atlas grid
+pub const GPU_TEXT_SETTINGS_BLOCK: &'static str = "gpu_text_settings";
+
+/// The name of the texture the glyphs are sampled from
+pub const GPU_TEXT_ATLAS_TEXTURE: &'static str = "gpu_text_atlas";
+
+/// The name of the fragment output of the GPU text shader
+pub const GPU_TEXT_FRAGMENT_OUT: &'static str = "gpu_text_colour";
+
+/// The most glyphs drawn in one batch, which must agree with gpu_text.vert
+pub const GPU_TEXT_MAX_GLYPHS: usize = 262144;
+
+/// The number of floats each glyph record occupies in GLYPH_BLOCK
+pub const GLYPH_INSTANCE_FLOATS: usize = 4;
+
+// Each glyph is a quad of two triangles, expanded from its record by the vertex shader
+const VERTICES_PER_GLYPH: u32 = 6;
+
+const GPU_TEXT_VERTEX_FILENAME: &'static str = "gpu_text.vert";
+const GPU_TEXT_FRAGMENT_FILENAME: &'static str = "gpu_text.frag";
+
+const GPU_TEXT_VERTEX_SOURCE: &'static str = include_str!("shaders/gpu_text.vert");
+const GPU_TEXT_FRAGMENT_SOURCE: &'static str = include_str!("shaders/gpu_text.frag");
+
+/// Pack a colour into RGBA8, with red in the lowest byte as unpackUnorm4x8 expects
+///
+/// colour: The red, green, blue and alpha components, clamped to [0, 1]
+pub fn pack_colour(colour: [f32; 4]) -> u32 {
+    colour.iter()
+        .enumerate()
+        .map(|(i, c)| ((c.max(0.0f32).min(1.0f32) * 255.0f32).round() as u32) << (8 * i))
+        .fold(0, |packed, byte| packed | byte)
+}
+
+/// One glyph to draw, as read by the vertex shader from GLYPH_BLOCK
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub struct GlyphInstance {
+    pub x: f32, // The top-left corner in pixels, with y increasing down the screen
+    pub y: f32,
+    pub glyph: u32, // The cell of the glyph in the atlas grid, counted along the rows
+    pub colour: u32, // RGBA8, from pack_colour
+}
+
+impl GlyphInstance {
+    /// Append the record to a vector of floats in its std430 layout
+    ///
+    /// The bit patterns of the glyph and colour are copied into the floats unchanged.
+    ///
+    /// out: The vector to append to
+    pub fn pack(&self, out: &mut Vec<f32>) {
+        out.push(self.x);
+        out.push(self.y);
+        out.push(f32::from_bits(self.glyph));
+        out.push(f32::from_bits(self.colour));
+    }
+}
+
+/// A monospaced glyph atlas, laid out as a grid of equally sized cells in character order
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub struct GlyphGrid {
+    pub columns: u32,
+    pub rows: u32,
+    pub first_character: u32, // The character in the top-left cell, e.g. 32 for printable ASCII
+    pub glyph_width: f32, // The size a glyph is drawn at in pixels, which is also the advance
+    pub glyph_height: f32,
+}
+
+impl GlyphGrid {
+    /// Return the cell of a character, or None if the atlas does not hold it
+    ///
+    /// c: The character
+    pub fn glyph_of(&self, c: char) -> Option<u32> {
+        let code = c as u32;
+        if code >= self.first_character && code - self.first_character < self.columns * self.rows {
+            Some(code - self.first_character)
+        } else {
+            None
+        }
+    }
+}
+
+/// Glyph records gathered over a frame and drawn with a single draw call
+///
+/// The quads are generated on the GPU, so adding a label costs only a record per glyph, which
+/// keeps overlays of many thousands of labels off the CPU.
+pub struct GpuTextBatch {
+    grid: GlyphGrid,
+    glyphs: Vec<GlyphInstance>,
+}
+
+impl GpuTextBatch {
+    /// Create an empty batch
+    ///
+    /// grid: The layout of the atlas the glyphs are drawn from
+    pub fn new(grid: GlyphGrid) -> GpuTextBatch {
+        GpuTextBatch {
+            grid: grid,
+            glyphs: vec![],
+        }
+    }
+
+    /// Add a glyph record, returning false if the batch is full
+    ///
+    /// glyph: The record to add
+    pub fn add_glyph(&mut self, glyph: GlyphInstance) -> bool {
+        if self.glyphs.len() >= GPU_TEXT_MAX_GLYPHS {
+            return false;
+        }
+
+        self.glyphs.push(glyph);
+        true
+    }
+
+    /// Lay out a string from a top-left corner, returning the number of glyphs added
+    ///
+    /// Newlines return to the starting column on the next line.  Characters missing from the
+    /// atlas, including spaces when it starts after them, advance without adding a glyph.  The
+    /// string is cut short if the batch fills.
+    ///
+    /// x: The left edge in pixels
+    /// y: The top edge in pixels
+    /// text: The string to draw
+    /// colour: The red, green, blue and alpha components of the text
+    pub fn add_text(&mut self, x: f32, y: f32, text: &str, colour: [f32; 4]) -> usize {
+        let colour = pack_colour(colour);
+        let mut pen_x = x;
+        let mut pen_y = y;
+        let mut added = 0;
+        for c in text.chars() {
+            if c == '\n' {
+                pen_x = x;
+                pen_y += self.grid.glyph_height;
+                continue;
+            }
+
+            if let Some(glyph) = self.grid.glyph_of(c) {
+                if !self.add_glyph(GlyphInstance {
+                    x: pen_x,
+                    y: pen_y,
+                    glyph: glyph,
+                    colour: colour,
+                }) {
+                    break;
+                }
+                added += 1;
+            }
+            pen_x += self.grid.glyph_width;
+        }
+
+        added
+    }
+
+    /// Remove every glyph, typically at the start of a frame
+    pub fn clear(&mut self) {
+        self.glyphs.clear();
+    }
+
+    /// Return the number of glyphs
+    pub fn len(&self) -> usize {
+        self.glyphs.len()
+    }
+
+    /// Return whether there are no glyphs
+    pub fn is_empty(&self) -> bool {
+        self.glyphs.is_empty()
+    }
+
+    /// Return the layout of the atlas the glyphs are drawn from
+    pub fn grid(&self) -> &GlyphGrid {
+        &self.grid
+    }
+
+    /// Return the glyph records packed for upload to GLYPH_BLOCK
+    pub fn packed(&self) -> Vec<f32> {
+        let mut out = Vec::with_capacity(self.glyphs.len() * GLYPH_INSTANCE_FLOATS);
+        for glyph in self.glyphs.iter() {
+            glyph.pack(&mut out);
+        }
+
+        out
+    }
+}
+
+/// Write the sources of the GPU text shader to the working directory
+///
+/// The glyph grid is uploaded at run time, but the shader itself is built during setup, so this
+/// must be called before the renderer is set up.
+pub fn install_gpu_text_shader_sources() -> Result<(), io::Error> {
+    write_entire_file(GPU_TEXT_VERTEX_SOURCE, GPU_TEXT_VERTEX_FILENAME)?;
+    write_entire_file(GPU_TEXT_FRAGMENT_SOURCE, GPU_TEXT_FRAGMENT_FILENAME)?;
+
+    Ok(())
+}
+
+/// Add the blocks and shader specification of the GPU text shader
+///
+/// The shader takes no vertex data: it blends the glyphs over the colour target without testing
+/// depth, so the pass normally comes last.
+///
+/// resource_manager: The resource manager to add the specifications to
+/// pass_name: The pass the shader is used in
+pub fn register_gpu_text_shader(resource_manager: &mut ResourceManager, pass_name: &'static str) {
+    resource_manager.uniform_block_specs.insert(GLYPH_BLOCK,
+                                                UniformBlockSpec {
+                                                    set: 0,
+                                                    block_type: UniformType::StorageBuffer,
+                                                    uniforms: vec![BlockUniformSpec {
+                                                                       name: "glyph_instances",
+                                                                       ..Default::default()
+                                                                   }],
+                                                    ..Default::default()
+                                                });
+    resource_manager.uniform_block_specs.insert(GPU_TEXT_SETTINGS_BLOCK,
+                                                UniformBlockSpec {
+                                                    set: 0,
+                                                    block_type: UniformType::UniformBuffer,
+                                                    uniforms: ["text_viewport", "text_glyph_grid"]
+                                                        .iter()
+                                                        .map(|name| {
+                                                            BlockUniformSpec {
+                                                                name: *name,
+                                                                ..Default::default()
+                                                            }
+                                                        })
+                                                        .collect(),
+                                                    ..Default::default()
+                                                });
+
+    let shader_spec = ShaderSpec {
+        name: GPU_TEXT_SHADER_NAME,
+        library_files: vec![],
+        shader_files: vec![ShaderFilesSpecification {
+                               filename: GPU_TEXT_VERTEX_FILENAME,
+                               shader_stage: ShaderStage::VertexShader,
+                               spirv_out: "gpu_text.vert.spv",
+                               reflect_out: "gpu_text.vert.rfl",
+                               entry_point: DEFAULT_ENTRY_POINT,
+                           },
+                           ShaderFilesSpecification {
+                               filename: GPU_TEXT_FRAGMENT_FILENAME,
+                               shader_stage: ShaderStage::FragmentShader,
+                               spirv_out: "gpu_text.frag.spv",
+                               reflect_out: "gpu_text.frag.rfl",
+                               entry_point: DEFAULT_ENTRY_POINT,
+                           }],
+        fallback_shader_files: vec![],
+        uniform_block_names: vec![GLYPH_BLOCK, GPU_TEXT_SETTINGS_BLOCK],
+        uniform_specs: vec![UniformSpec {
+                                name: GPU_TEXT_ATLAS_TEXTURE,
+                                set: 1,
+                                binding: 0,
+                                uniform_type: UniformType::CombinedImageSampler,
+                            }],
+        push_constants: vec![],
+        vertex_array_type: VertexArrayType::F3,
+        primitive: PrimitiveType::PrimitiveTriangles,
+        instance_layout: None,
+        attributes: vec![],
+        fragment_out: GPU_TEXT_FRAGMENT_OUT,
+        extra_fragment_outs: vec![],
+        render_state: RenderState::alpha_blended(),
+        stencil: None,
+        bindless_textures: false,
+        pass_name: pass_name,
+    };
+    resource_manager.shader_specs.insert(GPU_TEXT_SHADER_NAME, shader_spec);
+}
+
+/// Draw the glyphs of a batch over the current render target with one draw call
+///
+/// This must be called at a pass boundary, as the glyph records are uploaded first.  On Vulkan
+/// the atlas is bound to the pipeline by name when the renderer is set up, so it must be the
+/// texture named by GPU_TEXT_ATLAS_TEXTURE.
+///
+/// renderer: The renderer to draw with
+/// batch: The glyphs to draw
+/// atlas: The texture holding the glyphs, with their coverage in the red channel
+/// width: The width of the render target in pixels
+/// height: The height of the render target in pixels
+pub fn render_gpu_text<Rend: Renderer + ?Sized>(renderer: &mut Rend,
+                                                batch: &GpuTextBatch,
+                                                atlas: &Box<Texture>,
+                                                width: u32,
+                                                height: u32) {
+    if batch.is_empty() {
+        return;
+    }
+
+    // Clip space y points down the screen on Vulkan but up it on OpenGL
+    let y_sign = if renderer.renderer_type() == RendererType::RendererVk {
+        1.0f32
+    } else {
+        -1.0f32
+    };
+
+    let grid = batch.grid();
+    renderer.set_uniform_buffer_float_vector(GLYPH_BLOCK, "glyph_instances", &batch.packed());
+    renderer.synchronise_uniform_buffer(GLYPH_BLOCK);
+    renderer.set_uniform_buffer_float_vector(GPU_TEXT_SETTINGS_BLOCK,
+                                             "text_viewport",
+                                             &vec![width as f32, height as f32, y_sign, 0.0f32]);
+    renderer.set_uniform_buffer_float_vector(GPU_TEXT_SETTINGS_BLOCK,
+                                             "text_glyph_grid",
+                                             &vec![grid.glyph_width, grid.glyph_height, grid.columns as f32, grid.rows as f32]);
+    renderer.synchronise_uniform_buffer(GPU_TEXT_SETTINGS_BLOCK);
+
+    renderer.begin_pass(GPU_TEXT_SHADER_NAME);
+    atlas.bind(0);
+
+    let threaddata_arc = renderer.get_threaddata(0);
+    let mut threaddata = threaddata_arc.lock().unwrap();
+    renderer.draw_generated(&mut threaddata, VERTICES_PER_GLYPH, batch.len() as u32);
+
+    renderer.end_pass();
+}
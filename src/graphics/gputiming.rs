@@ -0,0 +1,162 @@
+// Copyright (c) 2016-2017 Bruce Stenning. All rights reserved.
+//
+// Redistribution and use in source and binary forms, with or without
+// modification, are permitted provided that the following conditions
+// are met:
+//
+// 1. Redistributions of source code must retain the above copyright
+//    notice, this list of conditions and the following disclaimer.
+//
+// 2. Redistributions in binary form must reproduce the above copyright
+//    notice, this list of conditions and the following disclaimer in the
+//    documentation and/or other materials provided with the distribution.
+//
+// 3. Neither the name of the copyright holder nor the names of its
+//    contributors may be used to endorse or promote products derived
+//    from this software without specific prior written permission.
+//
+// THIS SOFTWARE IS PROVIDED BY THE COPYRIGHT HOLDERS AND CONTRIBUTORS
+// AS IS" AND ANY EXPRESS OR IMPLIED WARRANTIES, INCLUDING, BUT NOT
+// LIMITED TO, THE IMPLIED WARRANTIES OF MERCHANTABILITY AND FITNESS
+// FOR A PARTICULAR PURPOSE ARE DISCLAIMED. IN NO EVENT SHALL THE
+// COPYRIGHT HOLDER OR CONTRIBUTORS BE LIABLE FOR ANY DIRECT, INDIRECT,
+// INCIDENTAL, SPECIAL, EXEMPLARY, OR CONSEQUENTIAL DAMAGES (INCLUDING,
+// BUT NOT LIMITED TO, PROCUREMENT OF SUBSTITUTE GOODS OR SERVICES; LOSS
+// OF USE, DATA, OR PROFITS; OR BUSINESS INTERRUPTION) HOWEVER CAUSED
+// AND ON ANY THEORY OF LIABILITY, WHETHER IN CONTRACT, STRICT LIABILITY,
+// OR TORT (INCLUDING NEGLIGENCE OR OTHERWISE) ARISING IN ANY WAY OUT OF
+// THE USE OF THIS SOFTWARE, EVEN IF ADVISED OF THE POSSIBILITY OF SUCH
+// DAMAGE.
+
+use std::collections::VecDeque;
+
+/// The most passes timed in a frame, after which passes go untimed
+pub const MAX_TIMED_PASSES: usize = 64;
+
+/// The number of frames of pass timings kept
+pub const GPU_TIMING_HISTORY_FRAMES: usize = 32;
+
+/// The GPU time of one pass
+#[derive(Clone, Debug, PartialEq)]
+pub struct PassTiming {
+    pub shader_name: &'static str,
+    pub gpu_ms: f32,
+}
+
+/// The GPU times of the passes of one frame, in the order they were drawn
+#[derive(Clone, Debug, PartialEq)]
+pub struct FrameTimings {
+    pub frame: u64, // The frame number the passes were drawn in
+    pub passes: Vec<PassTiming>,
+    pub unavailable: u32, // The passes without results, which are left out
+}
+
+impl FrameTimings {
+    /// Pair the timestamps written at the start and end of each pass into pass timings
+    ///
+    /// Passes whose timestamps are not both available are counted rather than waited for.
+    ///
+    /// frame: The frame number the passes were drawn in
+    /// shader_names: The shader of each timed pass
+    /// timestamps: The start and end timestamp of each pass in ticks, or None where unavailable
+    /// period_ns: The length of a tick in nanoseconds
+    pub fn from_timestamps(frame: u64,
+                           shader_names: &[&'static str],
+                           timestamps: &[Option<u64>],
+                           period_ns: f32)
+                           -> FrameTimings {
+        debug_assert!(timestamps.len() >= 2 * shader_names.len());
+
+        let mut passes = Vec::with_capacity(shader_names.len());
+        let mut unavailable = 0;
+        for (i, shader_name) in shader_names.iter().enumerate() {
+            match (timestamps[2 * i], timestamps[2 * i + 1]) {
+                (Some(start), Some(end)) => {
+                    passes.push(PassTiming {
+                        shader_name: *shader_name,
+                        gpu_ms: end.saturating_sub(start) as f32 * period_ns / 1.0e6,
+                    })
+                }
+                _ => unavailable += 1,
+            }
+        }
+
+        FrameTimings {
+            frame: frame,
+            passes: passes,
+            unavailable: unavailable,
+        }
+    }
+
+    /// Return the sum of the GPU times of the passes
+    pub fn total_ms(&self) -> f32 {
+        self.passes.iter().map(|pass| pass.gpu_ms).sum()
+    }
+}
+
+/// The pass timings of the most recent frames whose results have been harvested
+///
+/// Results are harvested when a frame's queries are about to be reused, and only where the
+/// queries report them available, so timing never makes the CPU wait on the GPU.
+pub struct GpuTimingHistory {
+    frames: VecDeque<FrameTimings>,
+    capacity: usize,
+    unavailable: u64,
+}
+
+impl GpuTimingHistory {
+    /// Create an empty history
+    ///
+    /// capacity: The number of frames kept, after which the oldest are discarded
+    pub fn new(capacity: usize) -> GpuTimingHistory {
+        GpuTimingHistory {
+            frames: VecDeque::with_capacity(capacity),
+            capacity: capacity,
+            unavailable: 0,
+        }
+    }
+
+    /// Add the timings of a frame, discarding the oldest frame if the history is full
+    ///
+    /// timings: The timings of the frame
+    pub fn record(&mut self, timings: FrameTimings) {
+        self.unavailable += timings.unavailable as u64;
+        if self.frames.len() == self.capacity {
+            self.frames.pop_front();
+        }
+        self.frames.push_back(timings);
+    }
+
+    /// Return the timings of the most recent frame harvested
+    pub fn latest(&self) -> Option<&FrameTimings> {
+        self.frames.back()
+    }
+
+    /// Return the frames kept, oldest first
+    pub fn frames(&self) -> &VecDeque<FrameTimings> {
+        &self.frames
+    }
+
+    /// Return the average GPU time of the passes drawn with a shader, over the frames kept
+    ///
+    /// shader_name: The shader of the passes
+    pub fn average_pass_ms(&self, shader_name: &str) -> Option<f32> {
+        let times: Vec<f32> = self.frames
+            .iter()
+            .flat_map(|frame| frame.passes.iter())
+            .filter(|pass| pass.shader_name == shader_name)
+            .map(|pass| pass.gpu_ms)
+            .collect();
+
+        if times.is_empty() {
+            None
+        } else {
+            Some(times.iter().sum::<f32>() / times.len() as f32)
+        }
+    }
+
+    /// Return the number of passes since creation whose results were not available in time
+    pub fn unavailable(&self) -> u64 {
+        self.unavailable
+    }
+}
@@ -0,0 +1,409 @@
+// Copyright (c) 2016-2017 Bruce Stenning. All rights reserved.
+//
+// Redistribution and use in source and binary forms, with or without
+// modification, are permitted provided that the following conditions
+// are met:
+//
+// 1. Redistributions of source code must retain the above copyright
+//    notice, this list of conditions and the following disclaimer.
+//
+// 2. Redistributions in binary form must reproduce the above copyright
+//    notice, this list of conditions and the following disclaimer in the
+//    documentation and/or other materials provided with the distribution.
+//
+// 3. Neither the name of the copyright holder nor the names of its
+//    contributors may be used to endorse or promote products derived
+//    from this software without specific prior written permission.
+//
+// THIS SOFTWARE IS PROVIDED BY THE COPYRIGHT HOLDERS AND CONTRIBUTORS
+// AS IS" AND ANY EXPRESS OR IMPLIED WARRANTIES, INCLUDING, BUT NOT
+// LIMITED TO, THE IMPLIED WARRANTIES OF MERCHANTABILITY AND FITNESS
+// FOR A PARTICULAR PURPOSE ARE DISCLAIMED. IN NO EVENT SHALL THE
+// COPYRIGHT HOLDER OR CONTRIBUTORS BE LIABLE FOR ANY DIRECT, INDIRECT,
+// INCIDENTAL, SPECIAL, EXEMPLARY, OR CONSEQUENTIAL DAMAGES (INCLUDING,
+// BUT NOT LIMITED TO, PROCUREMENT OF SUBSTITUTE GOODS OR SERVICES; LOSS
+// OF USE, DATA, OR PROFITS; OR BUSINESS INTERRUPTION) HOWEVER CAUSED
+// AND ON ANY THEORY OF LIABILITY, WHETHER IN CONTRACT, STRICT LIABILITY,
+// OR TORT (INCLUDING NEGLIGENCE OR OTHERWISE) ARISING IN ANY WAY OUT OF
+// THE USE OF THIS SOFTWARE, EVEN IF ADVISED OF THE POSSIBILITY OF SUCH
+// DAMAGE.
+use std::io;
+
+use graphics::fullscreen::draw_fullscreen_quad;
+use graphics::renderer::*;
+use graphics::rendergraph::*;
+use graphics::rendertarget::*;
+use graphics::resources::*;
+use graphics::shader::*;
+use misc::fileutils::*;
+
+/// The most levels a pyramid has, enough for a depth buffer of 8192 pixels across
+pub const HIZ_MAX_LEVELS: usize = 14;
+
+/// The names the downsample shader of each level is registered under, which are also the names
+/// of the render graph passes building the levels
+pub const HIZ_DOWNSAMPLE_SHADER_NAMES: [&'static str; HIZ_MAX_LEVELS] = ["hiz_downsample_0",
+                                                                         "hiz_downsample_1",
+                                                                         "hiz_downsample_2",
+                                                                         "hiz_downsample_3",
+                                                                         "hiz_downsample_4",
+                                                                         "hiz_downsample_5",
+                                                                         "hiz_downsample_6",
+                                                                         "hiz_downsample_7",
+                                                                         "hiz_downsample_8",
+                                                                         "hiz_downsample_9",
+                                                                         "hiz_downsample_10",
+                                                                         "hiz_downsample_11",
+                                                                         "hiz_downsample_12",
+                                                                         "hiz_downsample_13"];
+
+/// The names of the textures, and render graph attachments, holding each level of the pyramid
+///
+/// Later passes sample the levels they need under these names.
+pub const HIZ_LEVEL_TEXTURES: [&'static str; HIZ_MAX_LEVELS] = ["hiz_level_0",
+                                                                "hiz_level_1",
+                                                                "hiz_level_2",
+                                                                "hiz_level_3",
+                                                                "hiz_level_4",
+                                                                "hiz_level_5",
+                                                                "hiz_level_6",
+                                                                "hiz_level_7",
+                                                                "hiz_level_8",
+                                                                "hiz_level_9",
+                                                                "hiz_level_10",
+                                                                "hiz_level_11",
+                                                                "hiz_level_12",
+                                                                "hiz_level_13"];
+
+/// The name of the texture the scene depth buffer is sampled from, by the first level
+pub const HIZ_DEPTH_TEXTURE: &'static str = "hiz_depth_texture";
+
+/// The push constants holding the size of the level being read, and of the level being written
+pub const HIZ_SOURCE_WIDTH: &'static str = "hiz_source_width";
+pub const HIZ_SOURCE_HEIGHT: &'static str = "hiz_source_height";
+pub const HIZ_TARGET_WIDTH: &'static str = "hiz_target_width";
+pub const HIZ_TARGET_HEIGHT: &'static str = "hiz_target_height";
+
+/// The push constant selecting the reduction, 0 for the minimum and 1 for the maximum
+pub const HIZ_REDUCTION: &'static str = "hiz_reduction";
+
+const HIZ_VERTEX_FILENAME: &'static str = "hiz_downsample.vert";
+const HIZ_FRAGMENT_FILENAME: &'static str = "hiz_downsample.frag";
+
+const HIZ_VERTEX_SOURCE: &'static str = include_str!("shaders/hiz_downsample.vert");
+const HIZ_FRAGMENT_SOURCE: &'static str = include_str!("shaders/hiz_downsample.frag");
+
+/// How the depths a texel of a level covers are combined
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub enum HiZReduction {
+    /// The nearest depth, for tracing rays that must not step over thin geometry
+    Min,
+    /// The furthest depth, for conservative occlusion tests
+    Max,
+}
+
+impl HiZReduction {
+    /// Combine two depths
+    ///
+    /// a: The first depth
+    /// b: The second depth
+    pub fn reduce(&self, a: f32, b: f32) -> f32 {
+        match *self {
+            HiZReduction::Min => a.min(b),
+            HiZReduction::Max => a.max(b),
+        }
+    }
+}
+
+/// Return the number of levels of a full pyramid, down to a single texel
+///
+/// width: The width of the depth buffer
+/// height: The height of the depth buffer
+pub fn hiz_level_count(width: u32, height: u32) -> usize {
+    let largest = width.max(height).max(1);
+    (32 - largest.leading_zeros()) as usize
+}
+
+/// Return the range of source texels along one axis that a texel of a level covers
+///
+/// Where the source has an odd size, the texels covering it take in the texel between them as
+/// well, so that no depth is lost to the reduction.
+///
+/// texel: The texel of the level being written
+/// target_size: The size along the axis of the level being written
+/// source_size: The size along the axis of the level being read
+///
+/// Returns: The first source texel covered, and the one past the last
+pub fn hiz_footprint(texel: u32, target_size: u32, source_size: u32) -> (u32, u32) {
+    let target_size = target_size.max(1);
+    let first = texel * source_size / target_size;
+    let end = ((texel + 1) * source_size + target_size - 1) / target_size;
+    (first, end.max(first + 1).min(source_size.max(1)))
+}
+
+/// The size of a level of the pyramid, and of the level it is reduced from
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub struct HiZLevel {
+    pub width: u32,
+    pub height: u32,
+    pub source_width: u32,
+    pub source_height: u32,
+}
+
+/// The levels of a depth pyramid built from a depth buffer
+///
+/// Level 0 is a copy of the depth buffer at full size, and each further level halves the one
+/// before, rounding down, until a single texel remains.  Each level is rendered into a render
+/// target of its own, with the colour format of the render targets, by a fragment pass on both
+/// renderers.  The textures of the level render targets are then sampled by later passes under
+/// the names in HIZ_LEVEL_TEXTURES.
+pub struct HiZPyramid {
+    reduction: HiZReduction,
+    levels: Vec<HiZLevel>,
+}
+
+impl HiZPyramid {
+    /// Lay out the pyramid of a depth buffer
+    ///
+    /// width: The width of the depth buffer
+    /// height: The height of the depth buffer
+    /// reduction: How the depths covered by each texel are combined
+    pub fn new(width: u32, height: u32, reduction: HiZReduction) -> HiZPyramid {
+        let mut levels = vec![];
+        let (mut source_width, mut source_height) = (width.max(1), height.max(1));
+        for level in 0..hiz_level_count(width, height).min(HIZ_MAX_LEVELS) {
+            let (level_width, level_height) = if level == 0 {
+                (source_width, source_height)
+            } else {
+                ((source_width / 2).max(1), (source_height / 2).max(1))
+            };
+            levels.push(HiZLevel {
+                width: level_width,
+                height: level_height,
+                source_width: source_width,
+                source_height: source_height,
+            });
+            source_width = level_width;
+            source_height = level_height;
+        }
+
+        HiZPyramid {
+            reduction: reduction,
+            levels: levels,
+        }
+    }
+
+    /// Return how the depths covered by each texel are combined
+    pub fn reduction(&self) -> HiZReduction {
+        self.reduction
+    }
+
+    /// Return the levels, from the full size copy of the depth buffer down to a single texel
+    pub fn levels(&self) -> &[HiZLevel] {
+        &self.levels
+    }
+
+    /// Return the number of levels
+    pub fn level_count(&self) -> usize {
+        self.levels.len()
+    }
+
+    /// Build the pyramid on the CPU, as the downsample shader does
+    ///
+    /// This is the reference the shader follows, and suits depth buffers that have been read back.
+    ///
+    /// depth: The depths of the depth buffer, row by row
+    ///
+    /// Returns: The depths of each level, row by row
+    pub fn build(&self, depth: &[f32]) -> Vec<Vec<f32>> {
+        let mut built: Vec<Vec<f32>> = Vec::with_capacity(self.levels.len());
+        for level in self.levels.iter() {
+            let mut texels = Vec::with_capacity((level.width * level.height) as usize);
+            {
+                let source = match built.last() {
+                    Some(previous) => previous.as_slice(),
+                    None => depth,
+                };
+                for y in 0..level.height {
+                    let (first_y, end_y) = hiz_footprint(y, level.height, level.source_height);
+                    for x in 0..level.width {
+                        let (first_x, end_x) = hiz_footprint(x, level.width, level.source_width);
+                        let mut reduced = source[(first_y * level.source_width + first_x) as usize];
+                        for sy in first_y..end_y {
+                            for sx in first_x..end_x {
+                                reduced = self.reduction.reduce(reduced, source[(sy * level.source_width + sx) as usize]);
+                            }
+                        }
+                        texels.push(reduced);
+                    }
+                }
+            }
+            built.push(texels);
+        }
+        built
+    }
+}
+
+/// Declare the render graph passes building the levels of a pyramid
+///
+/// Each level's pass samples the level before, the first sampling the depth attachment, so the
+/// passes are scheduled after the opaque pass writing the depth and before any pass sampling a
+/// level.  The render target of each level should be bound to its attachment in
+/// HIZ_LEVEL_TEXTURES, so that the renderer transitions a level between being drawn and sampled.
+///
+/// graph: The render graph to add the passes to
+/// depth_attachment: The attachment the opaque pass writes depth in
+/// pass_name: The render pass the downsample shaders are registered with
+/// level_count: The number of levels of the pyramid
+pub fn add_hiz_graph_passes(graph: &mut RenderGraph, depth_attachment: &'static str, pass_name: &'static str, level_count: usize) {
+    for level in 0..level_count.min(HIZ_MAX_LEVELS) {
+        let source = if level == 0 {
+            depth_attachment
+        } else {
+            HIZ_LEVEL_TEXTURES[level - 1]
+        };
+        graph.add_pass(RenderGraphPass::new(HIZ_DOWNSAMPLE_SHADER_NAMES[level], pass_name)
+            .reads(source)
+            .writes(HIZ_LEVEL_TEXTURES[level]));
+    }
+}
+
+/// Write the sources of the downsample shader to the working directory
+///
+/// The shader of every level is built from these two files when the renderer is set up, so this
+/// must be called before then, whatever the number of levels.
+pub fn install_hiz_shader_sources() -> Result<(), io::Error> {
+    write_entire_file(HIZ_VERTEX_SOURCE, HIZ_VERTEX_FILENAME)?;
+    write_entire_file(HIZ_FRAGMENT_SOURCE, HIZ_FRAGMENT_FILENAME)?;
+
+    Ok(())
+}
+
+/// Add the shader specifications of the downsample shader of each level
+///
+/// The levels share the one shader, and differ in the texture they sample: the first level
+/// samples HIZ_DEPTH_TEXTURE, and each level after samples the level before it.  The shaders
+/// draw F2F2 full-screen triangles.
+///
+/// resource_manager: The resource manager to add the specifications to
+/// pass_name: The pass the downsample shaders are used in, which draws to an offscreen target
+/// level_count: The number of levels of the pyramid, from HiZPyramid::level_count
+pub fn register_hiz_shaders(resource_manager: &mut ResourceManager, pass_name: &'static str, level_count: usize) {
+    for level in 0..level_count.min(HIZ_MAX_LEVELS) {
+        let source_texture = if level == 0 {
+            HIZ_DEPTH_TEXTURE
+        } else {
+            HIZ_LEVEL_TEXTURES[level - 1]
+        };
+
+        let downsample_spec = ShaderSpec {
+            name: HIZ_DOWNSAMPLE_SHADER_NAMES[level],
+            library_files: vec![],
+            shader_files: vec![ShaderFilesSpecification {
+                                   filename: HIZ_VERTEX_FILENAME,
+                                   shader_stage: ShaderStage::VertexShader,
+                                   spirv_out: "hiz_downsample.vert.spv",
+                                   reflect_out: "hiz_downsample.vert.rfl",
+                                   entry_point: DEFAULT_ENTRY_POINT,
+                               },
+                               ShaderFilesSpecification {
+                                   filename: HIZ_FRAGMENT_FILENAME,
+                                   shader_stage: ShaderStage::FragmentShader,
+                                   spirv_out: "hiz_downsample.frag.spv",
+                                   reflect_out: "hiz_downsample.frag.rfl",
+                                   entry_point: DEFAULT_ENTRY_POINT,
+                               }],
+            fallback_shader_files: vec![],
+            uniform_block_names: vec![],
+            uniform_specs: vec![UniformSpec {
+                                    name: source_texture,
+                                    set: 1,
+                                    binding: 0,
+                                    uniform_type: UniformType::CombinedImageSampler,
+                                }],
+            push_constants: vec![PushConstantSpec {
+                                     name: HIZ_SOURCE_WIDTH,
+                                     offset: 0,
+                                     size: 4,
+                                 },
+                                 PushConstantSpec {
+                                     name: HIZ_SOURCE_HEIGHT,
+                                     offset: 4,
+                                     size: 4,
+                                 },
+                                 PushConstantSpec {
+                                     name: HIZ_TARGET_WIDTH,
+                                     offset: 8,
+                                     size: 4,
+                                 },
+                                 PushConstantSpec {
+                                     name: HIZ_TARGET_HEIGHT,
+                                     offset: 12,
+                                     size: 4,
+                                 },
+                                 PushConstantSpec {
+                                     name: HIZ_REDUCTION,
+                                     offset: 16,
+                                     size: 4,
+                                 }],
+            vertex_array_type: VertexArrayType::F2F2,
+            primitive: PrimitiveType::PrimitiveTriangles,
+            instance_layout: None,
+            attributes: vec!["position", "texcoord"],
+            fragment_out: "hiz_depth",
+            extra_fragment_outs: vec![],
+            render_state: RenderState::default(),
+            stencil: None,
+            bindless_textures: false,
+            pass_name: pass_name,
+        };
+        resource_manager.shader_specs.insert(HIZ_DOWNSAMPLE_SHADER_NAMES[level], downsample_spec);
+    }
+}
+
+/// Rebuild the pyramid from the depth buffer, which should be done each frame after the opaque pass
+///
+/// The depth buffer must be available as the texture named by HIZ_DEPTH_TEXTURE, and each level's
+/// render target as the texture of its name in HIZ_LEVEL_TEXTURES.  Where the passes were declared
+/// with add_hiz_graph_passes, each is begun as a graph pass.  No render target is selected after.
+///
+/// renderer: The renderer to draw with
+/// pyramid: The levels of the pyramid
+/// targets: The render target of each level, sized as the level
+pub fn render_hiz_pyramid<Rend: Renderer + ?Sized>(renderer: &mut Rend,
+                                                   pyramid: &HiZPyramid,
+                                                   targets: &mut [Box<RenderTarget>]) {
+    debug_assert!(targets.len() >= pyramid.level_count());
+
+    for (level, hiz_level) in pyramid.levels().iter().enumerate().take(targets.len()) {
+        let shader_name = HIZ_DOWNSAMPLE_SHADER_NAMES[level];
+        if renderer.render_graph_schedule().find(shader_name).is_some() {
+            renderer.begin_graph_pass(shader_name);
+        }
+
+        // OpenGL samples the previous level from the texture unit of its binding, so the level
+        // being drawn is bound to another unit
+        if level > 0 {
+            targets[level - 1].bind_texture(0);
+        }
+        renderer.select_render_target(1, &mut *targets[level]);
+        renderer.begin_pass(shader_name);
+
+        renderer.set_push_constant_int(0, HIZ_SOURCE_WIDTH, hiz_level.source_width as i32);
+        renderer.set_push_constant_int(0, HIZ_SOURCE_HEIGHT, hiz_level.source_height as i32);
+        renderer.set_push_constant_int(0, HIZ_TARGET_WIDTH, hiz_level.width as i32);
+        renderer.set_push_constant_int(0, HIZ_TARGET_HEIGHT, hiz_level.height as i32);
+        renderer.set_push_constant_int(0,
+                                       HIZ_REDUCTION,
+                                       match pyramid.reduction() {
+                                           HiZReduction::Min => 0,
+                                           HiZReduction::Max => 1,
+                                       });
+
+        draw_fullscreen_quad(renderer);
+
+        renderer.end_pass();
+    }
+
+    renderer.deselect_render_target();
+}
@@ -32,6 +32,8 @@ use std::path::Path;
 use image;
 use image::*;
 
+use misc::embeddedresources::*;
+
 pub struct Image {
     width: u32,
     height: u32,
@@ -58,7 +60,32 @@ impl Image {
     ///
     /// filename: The name of the PNG file to load into the image
     pub fn load_from_png(filename: &str) -> Image {
-        let img = image::open(&Path::new(filename)).unwrap();
+        Image::from_rgb_image(image::open(&Path::new(filename)).unwrap())
+    }
+
+    /// Create an Image object from a PNG file, from the embedded resources if they are in use
+    ///
+    /// Compressed embedded resources are decompressed on first access.
+    ///
+    /// embedded: The embedded resources object
+    /// filename: The name of the PNG file to load into the image
+    pub fn load_png(embedded: Option<&EmbeddedResources>, filename: &str) -> Image {
+        match embedded {
+            Some(ref embedded) if embedded.use_me() => {
+                let bytes = match embedded.resource(filename) {
+                    Some(b) => b,
+                    None => panic!("No embedded resource {}", filename),
+                };
+                Image::from_rgb_image(image::load_from_memory(&bytes).unwrap())
+            }
+            _ => Image::load_from_png(filename),
+        }
+    }
+
+    /// Create an Image object from a decoded RGB image
+    ///
+    /// img: The decoded image
+    fn from_rgb_image(img: DynamicImage) -> Image {
         let (width, height) = img.dimensions();
 
         // Assume the input does not have an alpha channel and add one
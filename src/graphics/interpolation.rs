@@ -0,0 +1,333 @@
+// Copyright (c) 2016-2017 Bruce Stenning. All rights reserved.
+//
+// Redistribution and use in source and binary forms, with or without
+// modification, are permitted provided that the following conditions
+// are met:
+//
+// 1. Redistributions of source code must retain the above copyright
+//    notice, this list of conditions and the following disclaimer.
+//
+// 2. Redistributions in binary form must reproduce the above copyright
+//    notice, this list of conditions and the following disclaimer in the
+//    documentation and/or other materials provided with the distribution.
+//
+// 3. Neither the name of the copyright holder nor the names of its
+//    contributors may be used to endorse or promote products derived
+//    from this software without specific prior written permission.
+//
+// THIS SOFTWARE IS PROVIDED BY THE COPYRIGHT HOLDERS AND CONTRIBUTORS
+// AS IS" AND ANY EXPRESS OR IMPLIED WARRANTIES, INCLUDING, BUT NOT
+// LIMITED TO, THE IMPLIED WARRANTIES OF MERCHANTABILITY AND FITNESS
+// FOR A PARTICULAR PURPOSE ARE DISCLAIMED. IN NO EVENT SHALL THE
+// COPYRIGHT HOLDER OR CONTRIBUTORS BE LIABLE FOR ANY DIRECT, INDIRECT,
+// INCIDENTAL, SPECIAL, EXEMPLARY, OR CONSEQUENTIAL DAMAGES (INCLUDING,
+// BUT NOT LIMITED TO, PROCUREMENT OF SUBSTITUTE GOODS OR SERVICES; LOSS
+// OF USE, DATA, OR PROFITS; OR BUSINESS INTERRUPTION) HOWEVER CAUSED
+// AND ON ANY THEORY OF LIABILITY, WHETHER IN CONTRACT, STRICT LIABILITY,
+// OR TORT (INCLUDING NEGLIGENCE OR OTHERWISE) ARISING IN ANY WAY OUT OF
+// THE USE OF THIS SOFTWARE, EVEN IF ADVISED OF THE POSSIBILITY OF SUCH
+// DAMAGE.
+use std::collections::HashMap;
+use std::io;
+
+use algebra::matrix::Mat4;
+use algebra::quaternion::Quaternion;
+use algebra::vector::Vec3;
+use graphics::renderer::*;
+use graphics::resources::*;
+use misc::fileutils::*;
+
+/// The name of the storage block the interpolated transforms are published in
+///
+/// The block is declared in GLSL as follows, at whatever set and binding suit the shader, and
+/// holds six vec4s per object: the previous position, rotation and scale followed by the
+/// current ones.
+///
+/// layout(set = 0, binding = 0) buffer object_transforms {
+///     float object_transform_alpha;
+///     vec4 object_transform_data[];
+/// };
+pub const TRANSFORM_BLOCK: &'static str = "object_transforms";
+
+/// The name of the library of GLSL functions blending the published transforms
+pub const INTERPOLATION_LIBRARY_FILENAME: &'static str = "interpolation_common.glsl";
+
+/// The number of vec4s published for each object
+pub const TRANSFORM_VEC4S_PER_OBJECT: usize = 6;
+
+const INTERPOLATION_LIBRARY_SOURCE: &'static str = include_str!("shaders/interpolation_common.glsl");
+
+/// The position, rotation and scale of an object at the end of a simulation step
+#[derive(Clone, Copy)]
+pub struct ObjectTransform {
+    pub position: Vec3<f32>,
+    pub rotation: Quaternion<f32>,
+    pub scale: Vec3<f32>,
+}
+
+impl ObjectTransform {
+    /// Construct a transform that leaves an object where it is
+    pub fn identity() -> ObjectTransform {
+        ObjectTransform {
+            position: Vec3 {
+                x: 0.0f32,
+                y: 0.0f32,
+                z: 0.0f32,
+            },
+            rotation: Quaternion::identity(),
+            scale: Vec3 {
+                x: 1.0f32,
+                y: 1.0f32,
+                z: 1.0f32,
+            },
+        }
+    }
+
+    /// Blend two transforms, lerping position and scale and taking the shorter way round between
+    /// the rotations
+    ///
+    /// previous: The transform at alpha 0
+    /// current: The transform at alpha 1
+    /// alpha: How far to blend from the previous transform to the current one
+    pub fn interpolate(previous: &ObjectTransform, current: &ObjectTransform, alpha: f32) -> ObjectTransform {
+        let lerp = |a: f32, b: f32| a + (b - a) * alpha;
+
+        let (p, c) = (previous.rotation, current.rotation);
+        let sign = if p.x * c.x + p.y * c.y + p.z * c.z + p.w * c.w < 0.0 {
+            -1.0f32
+        } else {
+            1.0f32
+        };
+        let mut rotation = Quaternion {
+            x: lerp(p.x, sign * c.x),
+            y: lerp(p.y, sign * c.y),
+            z: lerp(p.z, sign * c.z),
+            w: lerp(p.w, sign * c.w),
+        };
+        let length = (rotation.x * rotation.x + rotation.y * rotation.y + rotation.z * rotation.z +
+                      rotation.w * rotation.w)
+            .sqrt();
+        if length > 0.0 {
+            rotation.x /= length;
+            rotation.y /= length;
+            rotation.z /= length;
+            rotation.w /= length;
+        }
+
+        ObjectTransform {
+            position: Vec3 {
+                x: lerp(previous.position.x, current.position.x),
+                y: lerp(previous.position.y, current.position.y),
+                z: lerp(previous.position.z, current.position.z),
+            },
+            rotation: rotation,
+            scale: Vec3 {
+                x: lerp(previous.scale.x, current.scale.x),
+                y: lerp(previous.scale.y, current.scale.y),
+                z: lerp(previous.scale.z, current.scale.z),
+            },
+        }
+    }
+
+    /// Return the model matrix that scales, then rotates, then translates
+    pub fn model_matrix(&self) -> Mat4<f32> {
+        let q = self.rotation;
+        let mut matrix = Mat4::newidentity();
+
+        // Columns are the rotated basis vectors, scaled
+        matrix.m[0][0] = (1.0 - 2.0 * (q.y * q.y + q.z * q.z)) * self.scale.x;
+        matrix.m[0][1] = 2.0 * (q.x * q.y + q.z * q.w) * self.scale.x;
+        matrix.m[0][2] = 2.0 * (q.x * q.z - q.y * q.w) * self.scale.x;
+        matrix.m[1][0] = 2.0 * (q.x * q.y - q.z * q.w) * self.scale.y;
+        matrix.m[1][1] = (1.0 - 2.0 * (q.x * q.x + q.z * q.z)) * self.scale.y;
+        matrix.m[1][2] = 2.0 * (q.y * q.z + q.x * q.w) * self.scale.y;
+        matrix.m[2][0] = 2.0 * (q.x * q.z + q.y * q.w) * self.scale.z;
+        matrix.m[2][1] = 2.0 * (q.y * q.z - q.x * q.w) * self.scale.z;
+        matrix.m[2][2] = (1.0 - 2.0 * (q.x * q.x + q.y * q.y)) * self.scale.z;
+        matrix.m[3][0] = self.position.x;
+        matrix.m[3][1] = self.position.y;
+        matrix.m[3][2] = self.position.z;
+
+        matrix
+    }
+}
+
+/// Holds the previous and current transforms of objects moved by a fixed-timestep simulation,
+/// and blends between them for rendering
+///
+/// Call begin_step at the start of each simulation step, then set_transform for each object it
+/// moves.  Before each frame, set the alpha to the fraction of a step that the accumulated time
+/// is past the last completed step.  The renderer publishes the transforms to TRANSFORM_BLOCK
+/// in begin_frame when the block is registered, so that vertex shaders can blend them with the
+/// functions in INTERPOLATION_LIBRARY_FILENAME; otherwise model_matrix blends them on the CPU.
+pub struct TransformInterpolator {
+    objects: HashMap<u32, usize>,
+    slots: Vec<Option<(ObjectTransform, ObjectTransform)>>,
+    alpha: f32,
+}
+
+impl TransformInterpolator {
+    pub fn new() -> TransformInterpolator {
+        TransformInterpolator {
+            objects: HashMap::new(),
+            slots: vec![],
+            alpha: 1.0f32,
+        }
+    }
+
+    /// Start a simulation step, making the current transforms the previous ones
+    pub fn begin_step(&mut self) {
+        for entry in self.slots.iter_mut() {
+            if let Some(ref mut transforms) = *entry {
+                transforms.0 = transforms.1;
+            }
+        }
+    }
+
+    /// Record the transform of an object at the end of the current simulation step
+    ///
+    /// An object seen for the first time does not move until the next step.
+    ///
+    /// object: The caller's identifier for the object
+    /// transform: The transform of the object
+    pub fn set_transform(&mut self, object: u32, transform: &ObjectTransform) {
+        let slot = self.slot_of(object, transform);
+        if let Some(ref mut transforms) = self.slots[slot] {
+            transforms.1 = *transform;
+        }
+    }
+
+    /// Move an object without blending from where it was, e.g. when it respawns
+    ///
+    /// object: The caller's identifier for the object
+    /// transform: The transform of the object
+    pub fn teleport(&mut self, object: u32, transform: &ObjectTransform) {
+        let slot = self.slot_of(object, transform);
+        self.slots[slot] = Some((*transform, *transform));
+    }
+
+    /// Forget an object, freeing its slot in TRANSFORM_BLOCK for another
+    ///
+    /// object: The caller's identifier for the object
+    pub fn remove_object(&mut self, object: u32) {
+        if let Some(slot) = self.objects.remove(&object) {
+            self.slots[slot] = None;
+        }
+    }
+
+    /// Set how far between the previous and current transforms to render
+    ///
+    /// alpha: The fraction of a simulation step, from 0 to 1
+    pub fn set_alpha(&mut self, alpha: f32) {
+        self.alpha = alpha.max(0.0).min(1.0);
+    }
+
+    /// Return how far between the previous and current transforms is rendered
+    pub fn get_alpha(&self) -> f32 {
+        self.alpha
+    }
+
+    /// Return the index of an object's transforms in TRANSFORM_BLOCK, for its draws' push constants
+    ///
+    /// object: The caller's identifier for the object
+    pub fn object_index(&self, object: u32) -> Option<usize> {
+        self.objects.get(&object).cloned()
+    }
+
+    /// Return the blended transform of an object
+    ///
+    /// object: The caller's identifier for the object
+    pub fn transform(&self, object: u32) -> Option<ObjectTransform> {
+        self.objects.get(&object).and_then(|slot| self.slots[*slot].as_ref()).map(|transforms| {
+            ObjectTransform::interpolate(&transforms.0, &transforms.1, self.alpha)
+        })
+    }
+
+    /// Return the blended model matrix of an object, computed on the CPU
+    ///
+    /// object: The caller's identifier for the object
+    pub fn model_matrix(&self, object: u32) -> Option<Mat4<f32>> {
+        self.transform(object).map(|transform| transform.model_matrix())
+    }
+
+    /// Return the previous and current transforms of every slot, as TRANSFORM_VEC4S_PER_OBJECT
+    /// vec4s each, with free slots left as identity transforms
+    pub fn transform_data(&self) -> Vec<f32> {
+        let identity = ObjectTransform::identity();
+        let mut data = Vec::with_capacity(self.slots.len() * TRANSFORM_VEC4S_PER_OBJECT * 4);
+        for entry in self.slots.iter() {
+            let (previous, current) = match *entry {
+                Some((ref previous, ref current)) => (previous, current),
+                None => (&identity, &identity),
+            };
+            for transform in [previous, current].iter() {
+                data.extend_from_slice(&[transform.position.x, transform.position.y, transform.position.z, 0.0]);
+                data.extend_from_slice(&[transform.rotation.x,
+                                         transform.rotation.y,
+                                         transform.rotation.z,
+                                         transform.rotation.w]);
+                data.extend_from_slice(&[transform.scale.x, transform.scale.y, transform.scale.z, 0.0]);
+            }
+        }
+        data
+    }
+
+    /// Set the members of the TRANSFORM_BLOCK storage block and synchronise it
+    ///
+    /// renderer: The renderer owning the storage buffer
+    pub fn publish<Rend: Renderer + ?Sized>(&self, renderer: &Rend) {
+        renderer.set_uniform_buffer_float(TRANSFORM_BLOCK, "object_transform_alpha", self.alpha);
+        renderer.set_uniform_buffer_float_vector(TRANSFORM_BLOCK, "object_transform_data", &self.transform_data());
+        renderer.synchronise_uniform_buffer(TRANSFORM_BLOCK);
+    }
+
+    /// Return the slot of an object, giving it the first free one if it has none
+    fn slot_of(&mut self, object: u32, transform: &ObjectTransform) -> usize {
+        if let Some(slot) = self.objects.get(&object) {
+            return *slot;
+        }
+
+        let slot = match self.slots.iter().position(|entry| entry.is_none()) {
+            Some(slot) => slot,
+            None => {
+                self.slots.push(None);
+                self.slots.len() - 1
+            }
+        };
+        self.slots[slot] = Some((*transform, *transform));
+        self.objects.insert(object, slot);
+        slot
+    }
+}
+
+/// Write the source of the transform interpolation library to the working directory
+///
+/// The library is only included by other shaders, which fail to build during setup when the file
+/// is missing, so this must be called before the renderer is set up.
+pub fn install_interpolation_shader_sources() -> Result<(), io::Error> {
+    write_entire_file(INTERPOLATION_LIBRARY_SOURCE, INTERPOLATION_LIBRARY_FILENAME)?;
+
+    Ok(())
+}
+
+/// Add the specification of the TRANSFORM_BLOCK storage block, so the renderer publishes the
+/// interpolated transforms each frame
+///
+/// resource_manager: The resource manager to add the specification to
+pub fn register_transform_block(resource_manager: &mut ResourceManager) {
+    resource_manager.uniform_block_specs.insert(TRANSFORM_BLOCK,
+                                                UniformBlockSpec {
+                                                    set: 0,
+                                                    block_type: UniformType::StorageBuffer,
+                                                    uniforms: ["object_transform_alpha", "object_transform_data"]
+                                                        .iter()
+                                                        .map(|name| {
+                                                            BlockUniformSpec {
+                                                                name: *name,
+                                                                ..Default::default()
+                                                            }
+                                                        })
+                                                        .collect(),
+                                                    ..Default::default()
+                                                });
+}
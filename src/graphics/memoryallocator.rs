@@ -0,0 +1,458 @@
+// Copyright (c) 2016-2017 Bruce Stenning. All rights reserved.
+//
+// Redistribution and use in source and binary forms, with or without
+// modification, are permitted provided that the following conditions
+// are met:
+//
+// 1. Redistributions of source code must retain the above copyright
+//    notice, this list of conditions and the following disclaimer.
+//
+// 2. Redistributions in binary form must reproduce the above copyright
+//    notice, this list of conditions and the following disclaimer in the
+//    documentation and/or other materials provided with the distribution.
+//
+// 3. Neither the name of the copyright holder nor the names of its
+//    contributors may be used to endorse or promote products derived
+//    from this software without specific prior written permission.
+//
+// THIS SOFTWARE IS PROVIDED BY THE COPYRIGHT HOLDERS AND CONTRIBUTORS
+// AS IS" AND ANY EXPRESS OR IMPLIED WARRANTIES, INCLUDING, BUT NOT
+// LIMITED TO, THE IMPLIED WARRANTIES OF MERCHANTABILITY AND FITNESS
+// FOR A PARTICULAR PURPOSE ARE DISCLAIMED. IN NO EVENT SHALL THE
+// COPYRIGHT HOLDER OR CONTRIBUTORS BE LIABLE FOR ANY DIRECT, INDIRECT,
+// INCIDENTAL, SPECIAL, EXEMPLARY, OR CONSEQUENTIAL DAMAGES (INCLUDING,
+// BUT NOT LIMITED TO, PROCUREMENT OF SUBSTITUTE GOODS OR SERVICES; LOSS
+// OF USE, DATA, OR PROFITS; OR BUSINESS INTERRUPTION) HOWEVER CAUSED
+// AND ON ANY THEORY OF LIABILITY, WHETHER IN CONTRACT, STRICT LIABILITY,
+// OR TORT (INCLUDING NEGLIGENCE OR OTHERWISE) ARISING IN ANY WAY OUT OF
+// THE USE OF THIS SOFTWARE, EVEN IF ADVISED OF THE POSSIBILITY OF SUCH
+// DAMAGE.
+
+use std::collections::HashMap;
+
+/// The size of the blocks of device memory that resources are sub-allocated from
+pub const DEFAULT_MEMORY_BLOCK_SIZE: u64 = 64 * 1024 * 1024;
+
+/// The layout of the resources a pool holds
+///
+/// Linear and optimally tiled resources are kept in separate pools, so that they never share a
+/// page of the device's buffer-image granularity.
+#[derive(Clone, Copy, Debug, PartialEq, Eq, Hash)]
+pub enum MemoryResourceKind {
+    Linear, // Buffers and linearly tiled images
+    Optimal, // Optimally tiled images
+}
+
+/// Usage statistics of device memory, in bytes
+#[derive(Clone, Copy, Debug, Default, PartialEq)]
+pub struct MemoryStats {
+    /// The blocks of device memory allocated, including those dedicated to a single resource
+    pub blocks: usize,
+    /// The blocks dedicated to a single resource too large to sub-allocate
+    pub dedicated_blocks: usize,
+    /// The resources the memory is allocated to
+    pub allocations: usize,
+    /// The memory allocated from the device
+    pub bytes_reserved: u64,
+    /// The memory of the blocks in use by resources
+    pub bytes_used: u64,
+    /// The free ranges of the blocks resources are sub-allocated from
+    pub free_ranges: usize,
+    /// The largest of the free ranges, the biggest resource that fits without a new block
+    pub largest_free_range: u64,
+    /// The allocations moved to fuller blocks by defragmentation, see MemoryAllocator::relocate
+    ///
+    /// Only the buffers of meshes are moved.  Images keep the memory they were created in.
+    pub relocations: usize,
+    /// The memory of the allocations moved by defragmentation
+    pub bytes_relocated: u64,
+}
+
+impl MemoryStats {
+    /// Add the statistics of a block
+    fn add_block(&mut self, block: &MemoryBlockRanges, dedicated: bool) {
+        self.blocks += 1;
+        if dedicated {
+            self.dedicated_blocks += 1;
+        }
+        self.allocations += block.allocations;
+        self.bytes_reserved += block.size;
+        self.bytes_used += block.used;
+        self.free_ranges += block.free.len();
+        for &(_, size) in block.free.iter() {
+            self.largest_free_range = self.largest_free_range.max(size);
+        }
+    }
+
+    /// Return how scattered the free memory is, from 0 when it is all in one range towards 1
+    /// when it is split into many small ones
+    pub fn fragmentation(&self) -> f32 {
+        let free = self.bytes_reserved - self.bytes_used;
+        if free == 0 {
+            0.0f32
+        } else {
+            1.0f32 - self.largest_free_range as f32 / free as f32
+        }
+    }
+}
+
+/// The free list of a block of memory
+///
+/// The free ranges are kept in order of offset, and are merged with their neighbours when freed
+/// so that the list never holds adjacent ranges.
+#[derive(Clone, Debug, PartialEq)]
+pub struct MemoryBlockRanges {
+    size: u64,
+    free: Vec<(u64, u64)>, // Offset and size of each free range
+    used: u64, // Bytes allocated
+    allocations: usize,
+}
+
+impl MemoryBlockRanges {
+    /// Construct the free list of an empty block
+    ///
+    /// size: The size of the block
+    pub fn new(size: u64) -> MemoryBlockRanges {
+        MemoryBlockRanges {
+            size: size,
+            free: vec![(0, size)],
+            used: 0,
+            allocations: 0,
+        }
+    }
+
+    /// Allocate a range from the first free range it fits in, returning its offset
+    ///
+    /// Returns None if there is no free range large enough once aligned.
+    ///
+    /// size: The size of the range
+    /// alignment: The alignment of the offset, a power of two
+    pub fn allocate(&mut self, size: u64, alignment: u64) -> Option<u64> {
+        debug_assert!(alignment.is_power_of_two());
+
+        for i in 0..self.free.len() {
+            let (offset, length) = self.free[i];
+            let aligned = (offset + alignment - 1) & !(alignment - 1);
+            let padding = aligned - offset;
+            if padding + size > length {
+                continue;
+            }
+
+            // Split off what is left either side of the allocation
+            let mut remains = Vec::with_capacity(2);
+            if padding > 0 {
+                remains.push((offset, padding));
+            }
+            if padding + size < length {
+                remains.push((aligned + size, length - padding - size));
+            }
+            self.free.splice(i..i + 1, remains);
+
+            self.used += size;
+            self.allocations += 1;
+            return Some(aligned);
+        }
+
+        None
+    }
+
+    /// Return a range allocated from the block to the free list
+    ///
+    /// offset: The offset of the range
+    /// size: The size the range was allocated with
+    pub fn free(&mut self, offset: u64, size: u64) {
+        let i = match self.free.binary_search_by_key(&offset, |&(o, _)| o) {
+            Ok(_) => panic!("Memory at offset {} freed twice", offset),
+            Err(i) => i,
+        };
+        self.free.insert(i, (offset, size));
+
+        // Merge with the following range, then the preceding one
+        if i + 1 < self.free.len() && offset + size == self.free[i + 1].0 {
+            self.free[i].1 += self.free[i + 1].1;
+            self.free.remove(i + 1);
+        }
+        if i > 0 && self.free[i - 1].0 + self.free[i - 1].1 == offset {
+            self.free[i - 1].1 += self.free[i].1;
+            self.free.remove(i);
+        }
+
+        self.used -= size;
+        self.allocations -= 1;
+    }
+
+    /// Return whether nothing is allocated from the block
+    pub fn is_empty(&self) -> bool {
+        self.allocations == 0
+    }
+
+    /// Return the free ranges, as offsets and sizes
+    pub fn free_ranges(&self) -> &Vec<(u64, u64)> {
+        &self.free
+    }
+}
+
+/// An allocation of device memory for a resource
+#[derive(Clone, Copy, Debug)]
+pub struct MemoryAllocation<M: Copy> {
+    pub memory: M, // The block the resource is bound to
+    pub offset: u64, // The offset of the resource in the block
+    pub size: u64,
+    alignment: u64,
+    memory_type: u32,
+    kind: MemoryResourceKind,
+    block: usize, // The index of the block in its pool
+}
+
+/// A block of device memory in a pool
+struct MemoryPoolBlock<M: Copy> {
+    memory: M,
+    ranges: MemoryBlockRanges,
+    dedicated: bool,
+}
+
+/// A sub-allocator of device memory
+///
+/// Resources are allocated from large blocks, with a pool of blocks per memory type and resource
+/// kind, which keeps the number of device allocations well within the device's limit.  Resources
+/// larger than half a block are given a block of their own.  The allocator is generic over the
+/// handle of a block of memory, which is allocated and released by the caller, so that it can be
+/// used without a device.
+pub struct MemoryAllocator<M: Copy> {
+    block_size: u64,
+    pools: HashMap<(u32, MemoryResourceKind), Vec<Option<MemoryPoolBlock<M>>>>, // Released blocks leave a hole
+    relocations: usize,
+    bytes_relocated: u64,
+}
+
+impl<M: Copy> MemoryAllocator<M> {
+    /// Construct an allocator with no blocks
+    ///
+    /// block_size: The size of the blocks resources are sub-allocated from
+    pub fn new(block_size: u64) -> MemoryAllocator<M> {
+        MemoryAllocator {
+            block_size: block_size,
+            pools: HashMap::new(),
+            relocations: 0,
+            bytes_relocated: 0,
+        }
+    }
+
+    /// Allocate memory for a resource, allocating a new block if none of the pool's has room
+    ///
+    /// Returns None if a new block was needed and could not be allocated.
+    ///
+    /// memory_type: The index of the memory type
+    /// kind: The layout of the resource
+    /// size: The size of the resource
+    /// alignment: The alignment of the resource, a power of two
+    /// allocate_block: Allocate a block of memory of the given size from the memory type
+    pub fn allocate(&mut self,
+                    memory_type: u32,
+                    kind: MemoryResourceKind,
+                    size: u64,
+                    alignment: u64,
+                    allocate_block: &mut FnMut(u64) -> Option<M>)
+                    -> Option<MemoryAllocation<M>> {
+        let block_size = self.block_size;
+        let pool = self.pools.entry((memory_type, kind)).or_insert_with(|| vec![]);
+
+        let dedicated = size > block_size / 2;
+        if !dedicated {
+            for (i, entry) in pool.iter_mut().enumerate() {
+                if let Some(ref mut block) = *entry {
+                    if block.dedicated {
+                        continue;
+                    }
+                    if let Some(offset) = block.ranges.allocate(size, alignment) {
+                        return Some(MemoryAllocation {
+                            memory: block.memory,
+                            offset: offset,
+                            size: size,
+                            alignment: alignment,
+                            memory_type: memory_type,
+                            kind: kind,
+                            block: i,
+                        });
+                    }
+                }
+            }
+        }
+
+        let new_size = if dedicated { size } else { block_size };
+        let memory = match allocate_block(new_size) {
+            Some(memory) => memory,
+            None => return None,
+        };
+        let mut ranges = MemoryBlockRanges::new(new_size);
+        let offset = ranges.allocate(size, alignment).unwrap();
+
+        let block = MemoryPoolBlock {
+            memory: memory,
+            ranges: ranges,
+            dedicated: dedicated,
+        };
+        let i = match pool.iter().position(|b| b.is_none()) {
+            Some(i) => {
+                pool[i] = Some(block);
+                i
+            }
+            None => {
+                pool.push(Some(block));
+                pool.len() - 1
+            }
+        };
+
+        Some(MemoryAllocation {
+            memory: memory,
+            offset: offset,
+            size: size,
+            alignment: alignment,
+            memory_type: memory_type,
+            kind: kind,
+            block: i,
+        })
+    }
+
+    /// Reserve a new place for an allocation in a fuller block of its pool, so that its own
+    /// block drains
+    ///
+    /// This is a step of incremental defragmentation: the caller copies the resource to the
+    /// allocation returned, and frees the original once the copy has completed and nothing still
+    /// in flight uses it.  Blocks only ever take allocations from emptier ones, so repeated steps
+    /// settle.  No block is allocated, and dedicated allocations never move.  Returns None if no
+    /// fuller block has room.  The move is only counted in the statistics once it is committed.
+    ///
+    /// allocation: The allocation to move
+    pub fn relocate(&mut self, allocation: &MemoryAllocation<M>) -> Option<MemoryAllocation<M>> {
+        let pool = match self.pools.get_mut(&(allocation.memory_type, allocation.kind)) {
+            Some(pool) => pool,
+            None => return None,
+        };
+        let source_used = match pool.get(allocation.block) {
+            Some(&Some(ref block)) if !block.dedicated => block.ranges.used,
+            _ => return None,
+        };
+
+        // Try the fullest blocks first, leaving the emptiest to drain
+        let mut targets: Vec<(u64, usize)> = pool.iter()
+            .enumerate()
+            .filter_map(|(i, b)| match *b {
+                Some(ref b) if i != allocation.block && !b.dedicated && b.ranges.used > source_used => {
+                    Some((b.ranges.used, i))
+                }
+                _ => None,
+            })
+            .collect();
+        targets.sort_by(|a, b| b.cmp(a));
+
+        for &(_, i) in targets.iter() {
+            let block = pool[i].as_mut().unwrap();
+            if let Some(offset) = block.ranges.allocate(allocation.size, allocation.alignment) {
+                return Some(MemoryAllocation {
+                    memory: block.memory,
+                    offset: offset,
+                    size: allocation.size,
+                    alignment: allocation.alignment,
+                    memory_type: allocation.memory_type,
+                    kind: allocation.kind,
+                    block: i,
+                });
+            }
+        }
+
+        None
+    }
+
+    /// Count a relocation in the statistics, once the resource has replaced the original
+    ///
+    /// Moves that are abandoned, freeing the allocation from relocate unused, are not counted.
+    ///
+    /// allocation: The allocation returned by relocate
+    pub fn commit_relocation(&mut self, allocation: &MemoryAllocation<M>) {
+        self.relocations += 1;
+        self.bytes_relocated += allocation.size;
+    }
+
+    /// Free the memory of a resource, returning the block if it is no longer needed
+    ///
+    /// Dedicated blocks are released once their resource is freed, and other blocks once they
+    /// are empty and the pool has another empty block on hand.  The caller releases the block
+    /// returned.  Memory freed after its block was drained is ignored.
+    ///
+    /// allocation: The allocation to free
+    pub fn free(&mut self, allocation: &MemoryAllocation<M>) -> Option<M> {
+        let pool = match self.pools.get_mut(&(allocation.memory_type, allocation.kind)) {
+            Some(pool) => pool,
+            None => return None,
+        };
+        if pool.get(allocation.block).map_or(true, |b| b.is_none()) {
+            return None;
+        }
+
+        let release = {
+            let block = pool[allocation.block].as_mut().unwrap();
+            block.ranges.free(allocation.offset, allocation.size);
+            block.ranges.is_empty()
+        };
+        if !release {
+            return None;
+        }
+
+        let spare = pool.iter()
+            .enumerate()
+            .any(|(i, b)| {
+                i != allocation.block &&
+                match *b {
+                    Some(ref b) => !b.dedicated && b.ranges.is_empty(),
+                    None => false,
+                }
+            });
+        let dedicated = pool[allocation.block].as_ref().unwrap().dedicated;
+        if dedicated || spare {
+            pool[allocation.block].take().map(|b| b.memory)
+        } else {
+            None
+        }
+    }
+
+    /// Remove every block, returning them for the caller to release
+    ///
+    /// Any resources still allocated from them must already have been destroyed.
+    pub fn drain_blocks(&mut self) -> Vec<M> {
+        self.pools
+            .drain()
+            .flat_map(|(_, pool)| pool.into_iter().filter_map(|b| b.map(|b| b.memory)))
+            .collect()
+    }
+
+    /// Return the usage statistics of every pool
+    pub fn stats(&self) -> MemoryStats {
+        let mut stats = MemoryStats {
+            relocations: self.relocations,
+            bytes_relocated: self.bytes_relocated,
+            ..Default::default()
+        };
+        for pool in self.pools.values() {
+            for block in pool.iter().filter_map(|b| b.as_ref()) {
+                stats.add_block(&block.ranges, block.dedicated);
+            }
+        }
+        stats
+    }
+
+    /// Return the usage statistics of the pools of a memory type
+    ///
+    /// memory_type: The index of the memory type
+    pub fn memory_type_stats(&self, memory_type: u32) -> MemoryStats {
+        let mut stats = MemoryStats::default();
+        for (&(ty, _), pool) in self.pools.iter() {
+            if ty == memory_type {
+                for block in pool.iter().filter_map(|b| b.as_ref()) {
+                    stats.add_block(&block.ranges, block.dedicated);
+                }
+            }
+        }
+        stats
+    }
+}
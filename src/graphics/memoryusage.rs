@@ -0,0 +1,156 @@
+// Copyright (c) 2016-2017 Bruce Stenning. All rights reserved.
+//
+// Redistribution and use in source and binary forms, with or without
+// modification, are permitted provided that the following conditions
+// are met:
+//
+// 1. Redistributions of source code must retain the above copyright
+//    notice, this list of conditions and the following disclaimer.
+//
+// 2. Redistributions in binary form must reproduce the above copyright
+//    notice, this list of conditions and the following disclaimer in the
+//    documentation and/or other materials provided with the distribution.
+//
+// 3. Neither the name of the copyright holder nor the names of its
+//    contributors may be used to endorse or promote products derived
+//    from this software without specific prior written permission.
+//
+// THIS SOFTWARE IS PROVIDED BY THE COPYRIGHT HOLDERS AND CONTRIBUTORS
+// AS IS" AND ANY EXPRESS OR IMPLIED WARRANTIES, INCLUDING, BUT NOT
+// LIMITED TO, THE IMPLIED WARRANTIES OF MERCHANTABILITY AND FITNESS
+// FOR A PARTICULAR PURPOSE ARE DISCLAIMED. IN NO EVENT SHALL THE
+// COPYRIGHT HOLDER OR CONTRIBUTORS BE LIABLE FOR ANY DIRECT, INDIRECT,
+// INCIDENTAL, SPECIAL, EXEMPLARY, OR CONSEQUENTIAL DAMAGES (INCLUDING,
+// BUT NOT LIMITED TO, PROCUREMENT OF SUBSTITUTE GOODS OR SERVICES; LOSS
+// OF USE, DATA, OR PROFITS; OR BUSINESS INTERRUPTION) HOWEVER CAUSED
+// AND ON ANY THEORY OF LIABILITY, WHETHER IN CONTRACT, STRICT LIABILITY,
+// OR TORT (INCLUDING NEGLIGENCE OR OTHERWISE) ARISING IN ANY WAY OUT OF
+// THE USE OF THIS SOFTWARE, EVEN IF ADVISED OF THE POSSIBILITY OF SUCH
+// DAMAGE.
+
+use std::collections::HashMap;
+
+/// What the memory of a buffer or image is used for
+#[derive(Clone, Copy, Debug, PartialEq, Eq, Hash)]
+pub enum MemoryCategory {
+    VertexBuffers, // Vertex, index and instance data
+    Uniforms, // Uniform buffers
+    StorageBuffers, // Storage buffers read and written by shaders
+    Staging, // Host-visible buffers the transfers and readbacks go through
+    Textures, // Sampled images
+    RenderTargets, // Colour and depth attachments
+}
+
+/// Every category, in the order usage is reported in
+pub const MEMORY_CATEGORIES: [MemoryCategory; 6] = [MemoryCategory::VertexBuffers,
+                                                    MemoryCategory::Uniforms,
+                                                    MemoryCategory::StorageBuffers,
+                                                    MemoryCategory::Staging,
+                                                    MemoryCategory::Textures,
+                                                    MemoryCategory::RenderTargets];
+
+/// The memory of the resources of one category
+#[derive(Clone, Copy, Debug, Default, PartialEq)]
+pub struct CategoryUsage {
+    pub resources: usize,
+    pub bytes: u64,
+}
+
+/// The use of one of the device's memory heaps
+#[derive(Clone, Copy, Debug, Default, PartialEq)]
+pub struct HeapUsage {
+    pub size: u64,
+    pub device_local: bool, // Whether the heap is the GPU's own memory rather than system memory
+    pub allocated: u64, // The memory the renderer has allocated from the heap
+    pub budget: Option<u64>, // How much the process may use before risking eviction, where the driver reports it
+    pub usage: Option<u64>, // How much the process uses, including memory the renderer did not allocate
+}
+
+impl HeapUsage {
+    /// Return how much more the process may use before exceeding the budget, where it is known
+    pub fn available(&self) -> Option<u64> {
+        match (self.budget, self.usage) {
+            (Some(budget), Some(usage)) => Some(budget.saturating_sub(usage)),
+            _ => None,
+        }
+    }
+}
+
+/// The memory used by a renderer, by heap and by what it is used for
+#[derive(Clone, Debug, Default, PartialEq)]
+pub struct MemoryUsage {
+    pub heaps: Vec<HeapUsage>, // Empty where the driver does not describe its heaps
+    pub categories: Vec<(MemoryCategory, CategoryUsage)>, // In the order of MEMORY_CATEGORIES
+}
+
+impl MemoryUsage {
+    /// Return the usage of one category
+    ///
+    /// category: The category
+    pub fn category(&self, category: MemoryCategory) -> CategoryUsage {
+        self.categories
+            .iter()
+            .find(|&&(c, _)| c == category)
+            .map_or(CategoryUsage::default(), |&(_, usage)| usage)
+    }
+
+    /// Return the memory of the resources of every category
+    pub fn total_bytes(&self) -> u64 {
+        self.categories.iter().map(|&(_, usage)| usage.bytes).sum()
+    }
+}
+
+/// Tallies the memory of buffers and images by category as they are created and destroyed
+///
+/// Resources are recorded under a key of the kind of object, such as a VkObjectType or an
+/// OpenGL object identifier, and its handle.  Recording a resource again replaces its record, so
+/// storage that is respecified under the same handle is not counted twice.
+#[derive(Clone, Debug, Default)]
+pub struct MemoryUsageTracker {
+    resources: HashMap<(u32, u64), (MemoryCategory, u64)>,
+}
+
+impl MemoryUsageTracker {
+    /// Construct a tracker with no resources
+    pub fn new() -> MemoryUsageTracker {
+        MemoryUsageTracker::default()
+    }
+
+    /// Record the memory of a resource, replacing any earlier record of it
+    ///
+    /// key: The kind of object and its handle
+    /// category: What the memory is used for
+    /// bytes: The size of the resource's memory
+    pub fn record(&mut self, key: (u32, u64), category: MemoryCategory, bytes: u64) {
+        self.resources.insert(key, (category, bytes));
+    }
+
+    /// Change the size of a recorded resource, keeping its category
+    ///
+    /// key: The kind of object and its handle
+    /// bytes: The new size of the resource's memory
+    pub fn resize(&mut self, key: (u32, u64), bytes: u64) {
+        if let Some(record) = self.resources.get_mut(&key) {
+            record.1 = bytes;
+        }
+    }
+
+    /// Forget a resource that has been destroyed
+    ///
+    /// key: The kind of object and its handle
+    pub fn release(&mut self, key: (u32, u64)) {
+        self.resources.remove(&key);
+    }
+
+    /// Return the usage of every category, in the order of MEMORY_CATEGORIES
+    pub fn categories(&self) -> Vec<(MemoryCategory, CategoryUsage)> {
+        let mut categories: Vec<(MemoryCategory, CategoryUsage)> =
+            MEMORY_CATEGORIES.iter().map(|c| (*c, CategoryUsage::default())).collect();
+        for &(category, bytes) in self.resources.values() {
+            let usage = &mut categories.iter_mut().find(|&&mut (c, _)| c == category).unwrap().1;
+            usage.resources += 1;
+            usage.bytes += bytes;
+        }
+        categories
+    }
+}
@@ -0,0 +1,244 @@
+// Copyright (c) 2016-2017 Bruce Stenning. All rights reserved.
+//
+// Redistribution and use in source and binary forms, with or without
+// modification, are permitted provided that the following conditions
+// are met:
+//
+// 1. Redistributions of source code must retain the above copyright
+//    notice, this list of conditions and the following disclaimer.
+//
+// 2. Redistributions in binary form must reproduce the above copyright
+//    notice, this list of conditions and the following disclaimer in the
+//    documentation and/or other materials provided with the distribution.
+//
+// 3. Neither the name of the copyright holder nor the names of its
+//    contributors may be used to endorse or promote products derived
+//    from this software without specific prior written permission.
+//
+// THIS SOFTWARE IS PROVIDED BY THE COPYRIGHT HOLDERS AND CONTRIBUTORS
+// AS IS" AND ANY EXPRESS OR IMPLIED WARRANTIES, INCLUDING, BUT NOT
+// LIMITED TO, THE IMPLIED WARRANTIES OF MERCHANTABILITY AND FITNESS
+// FOR A PARTICULAR PURPOSE ARE DISCLAIMED. IN NO EVENT SHALL THE
+// COPYRIGHT HOLDER OR CONTRIBUTORS BE LIABLE FOR ANY DIRECT, INDIRECT,
+// INCIDENTAL, SPECIAL, EXEMPLARY, OR CONSEQUENTIAL DAMAGES (INCLUDING,
+// BUT NOT LIMITED TO, PROCUREMENT OF SUBSTITUTE GOODS OR SERVICES; LOSS
+// OF USE, DATA, OR PROFITS; OR BUSINESS INTERRUPTION) HOWEVER CAUSED
+// AND ON ANY THEORY OF LIABILITY, WHETHER IN CONTRACT, STRICT LIABILITY,
+// OR TORT (INCLUDING NEGLIGENCE OR OTHERWISE) ARISING IN ANY WAY OUT OF
+// THE USE OF THIS SOFTWARE, EVEN IF ADVISED OF THE POSSIBILITY OF SUCH
+// DAMAGE.
+
+use std::collections::HashMap;
+use std::mem;
+
+use graphics::renderer::*;
+
+/// Identifies a mesh created by Renderer::create_mesh
+pub type MeshHandle = u32;
+
+/// The vertices, and optionally the indices, of a mesh to upload once and draw many times
+///
+/// The vertices are interleaved in the layout of the vertex array type, which must match that
+/// of the shaders the mesh is drawn with.  Without indices, each three vertices are a triangle.
+pub struct MeshData {
+    pub vertex_array_type: VertexArrayType,
+    pub vertices: Vec<f32>,
+    pub indices: Option<Vec<u32>>, // Three per triangle, indexing the vertices
+}
+
+impl MeshData {
+    /// Create the data of a mesh drawn as a list of triangles
+    ///
+    /// vertex_array_type: The layout of the vertices
+    /// vertices: The interleaved vertex components, three vertices per triangle
+    pub fn new(vertex_array_type: VertexArrayType, vertices: Vec<f32>) -> MeshData {
+        MeshData {
+            vertex_array_type: vertex_array_type,
+            vertices: vertices,
+            indices: None,
+        }
+    }
+
+    /// Create the data of a mesh whose triangles index shared vertices
+    ///
+    /// vertex_array_type: The layout of the vertices
+    /// vertices: The interleaved vertex components
+    /// indices: The indices of the vertices, three per triangle
+    pub fn indexed(vertex_array_type: VertexArrayType, vertices: Vec<f32>, indices: Vec<u32>) -> MeshData {
+        MeshData {
+            vertex_array_type: vertex_array_type,
+            vertices: vertices,
+            indices: Some(indices),
+        }
+    }
+
+    /// Return the number of vertices
+    pub fn vertex_count(&self) -> usize {
+        self.vertices.len() / VertexArrayType::components_per_vertex(self.vertex_array_type)
+    }
+
+    /// Return the number of vertices a draw of the mesh processes, which is the number of indices
+    /// of an indexed mesh
+    pub fn draw_count(&self) -> usize {
+        match self.indices {
+            Some(ref indices) => indices.len(),
+            None => self.vertex_count(),
+        }
+    }
+
+    /// Return the size of the vertex data in bytes
+    pub fn vertex_bytes(&self) -> usize {
+        self.vertices.len() * mem::size_of::<f32>()
+    }
+
+    /// Return the size of the index data in bytes, zero for a mesh without indices
+    pub fn index_bytes(&self) -> usize {
+        match self.indices {
+            Some(ref indices) => indices.len() * mem::size_of::<u32>(),
+            None => 0,
+        }
+    }
+
+    /// Check that the data describes whole triangles of whole vertices, returning why not if it
+    /// doesn't
+    pub fn validate(&self) -> Result<(), String> {
+        let components = VertexArrayType::components_per_vertex(self.vertex_array_type);
+        if self.vertices.is_empty() {
+            return Err("the mesh has no vertices".to_string());
+        }
+        if self.vertices.len() % components != 0 {
+            return Err(format!("{} components do not make whole vertices of {} components",
+                               self.vertices.len(),
+                               components));
+        }
+
+        let vertex_count = self.vertex_count();
+        match self.indices {
+            Some(ref indices) => {
+                if indices.is_empty() || indices.len() % 3 != 0 {
+                    return Err(format!("{} indices do not make whole triangles", indices.len()));
+                }
+                if let Some(index) = indices.iter().find(|&&i| i as usize >= vertex_count) {
+                    return Err(format!("index {} is beyond the {} vertices", index, vertex_count));
+                }
+            }
+            None => {
+                if vertex_count % 3 != 0 {
+                    return Err(format!("{} vertices do not make whole triangles", vertex_count));
+                }
+            }
+        }
+
+        Ok(())
+    }
+}
+
+/// The meshes a renderer holds, by handle
+///
+/// Handles are never reused, so a destroyed mesh's handle can't draw another mesh.  Destroyed
+/// meshes are kept for a number of frames, so that their buffers outlive the frames in flight
+/// that still draw from them.
+pub struct MeshRegistry<T> {
+    meshes: HashMap<MeshHandle, T>,
+    next_handle: MeshHandle,
+    retiring: Vec<(T, u32)>, // Mesh and frames until it may be dropped
+    retire_frames: u32,
+}
+
+impl<T> MeshRegistry<T> {
+    /// Create an empty registry
+    ///
+    /// retire_frames: The number of frames a destroyed mesh is kept for
+    pub fn new(retire_frames: u32) -> MeshRegistry<T> {
+        MeshRegistry {
+            meshes: HashMap::new(),
+            next_handle: 1,
+            retiring: vec![],
+            retire_frames: retire_frames,
+        }
+    }
+
+    /// Add a mesh, returning its handle
+    ///
+    /// mesh: The mesh
+    pub fn insert(&mut self, mesh: T) -> MeshHandle {
+        let handle = self.next_handle;
+        self.next_handle += 1;
+        self.meshes.insert(handle, mesh);
+
+        handle
+    }
+
+    /// Return the mesh of a handle, if it has not been destroyed
+    ///
+    /// handle: The handle of the mesh
+    pub fn get(&self, handle: MeshHandle) -> Option<&T> {
+        self.meshes.get(&handle)
+    }
+
+    /// Remove a mesh, which is dropped after retire_frames calls to advance_frame, returning
+    /// whether there was one
+    ///
+    /// handle: The handle of the mesh
+    pub fn remove(&mut self, handle: MeshHandle) -> bool {
+        match self.meshes.remove(&handle) {
+            Some(mesh) => {
+                if self.retire_frames > 0 {
+                    self.retiring.push((mesh, self.retire_frames));
+                }
+                true
+            }
+            None => false,
+        }
+    }
+
+    /// Replace the mesh of a handle, retiring the previous one as remove does, returning
+    /// whether there was one
+    ///
+    /// This keeps the handle valid when a mesh is moved, such as by defragmentation.  With no
+    /// mesh for the handle the replacement is dropped.
+    ///
+    /// handle: The handle of the mesh
+    /// mesh: The replacement
+    pub fn replace(&mut self, handle: MeshHandle, mesh: T) -> bool {
+        match self.meshes.get_mut(&handle) {
+            Some(existing) => {
+                let previous = mem::replace(existing, mesh);
+                if self.retire_frames > 0 {
+                    self.retiring.push((previous, self.retire_frames));
+                }
+                true
+            }
+            None => false,
+        }
+    }
+
+    /// Return the handles of the meshes that have not been destroyed
+    pub fn handles(&self) -> Vec<MeshHandle> {
+        self.meshes.keys().cloned().collect()
+    }
+
+    /// Count down the frames of the retiring meshes, dropping those that have finished
+    pub fn advance_frame(&mut self) {
+        for entry in self.retiring.iter_mut() {
+            entry.1 -= 1;
+        }
+        self.retiring.retain(|&(_, frames)| frames > 0);
+    }
+
+    /// Return the number of meshes that have not been destroyed
+    pub fn len(&self) -> usize {
+        self.meshes.len()
+    }
+
+    /// Return the number of destroyed meshes still being kept
+    pub fn retiring(&self) -> usize {
+        self.retiring.len()
+    }
+
+    /// Drop every mesh, including those retiring, once the device is idle
+    pub fn clear(&mut self) {
+        self.meshes.clear();
+        self.retiring.clear();
+    }
+}
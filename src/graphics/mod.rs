@@ -32,8 +32,24 @@ pub mod renderer;
 pub mod renderergl;
 pub mod renderervk;
 pub mod shader;
+pub mod errorshader;
+pub mod pbr;
+pub mod contactshadows;
+pub mod decals;
+pub mod brushes;
+pub mod motionblur;
+pub mod fullscreen;
+pub mod transition;
+pub mod atlas;
+pub mod perdraw;
+pub mod glslinclude;
 pub mod shaderglsl;
 pub mod shaderspirv;
+pub mod spirvreflect;
+pub mod uniformlayout;
+pub mod shaderhlsl;
+pub mod shadervariants;
+pub mod pipelinebuild;
 pub mod texture;
 pub mod texturegl;
 pub mod texturevk;
@@ -41,4 +57,43 @@ pub mod rendertarget;
 pub mod rendertargetgl;
 pub mod rendertargetvk;
 pub mod image;
+pub mod readback;
+pub mod geometrycapture;
+pub mod workpartition;
+pub mod computepacing;
+pub mod frametime;
+pub mod bandwidth;
+pub mod versioning;
+pub mod renderthread;
+pub mod vertexpool;
+pub mod mesh;
+pub mod memoryallocator;
+pub mod memoryusage;
+pub mod failureinjection;
+pub mod interpolation;
+pub mod exposure;
+pub mod colourspace;
+pub mod tonemap;
+pub mod posteffects;
+pub mod ssr;
+pub mod checkerboard;
+pub mod hiz;
+pub mod rendergraph;
+pub mod renderpasses;
+pub mod streambuffer;
+pub mod gpusort;
+pub mod gputext;
+pub mod text;
+pub mod overlay;
+pub mod gputiming;
+pub mod cputiming;
+pub mod texturefeedback;
+pub mod bindless;
+pub mod devicefeatures;
+pub mod parity;
+pub mod capturetrigger;
+pub mod debugmessages;
+pub mod rendererevents;
+pub mod stereo;
+pub mod xr;
 pub mod resources;
@@ -0,0 +1,370 @@
+// Copyright (c) 2016-2017 Bruce Stenning. All rights reserved.
+//
+// Redistribution and use in source and binary forms, with or without
+// modification, are permitted provided that the following conditions
+// are met:
+//
+// 1. Redistributions of source code must retain the above copyright
+//    notice, this list of conditions and the following disclaimer.
+//
+// 2. Redistributions in binary form must reproduce the above copyright
+//    notice, this list of conditions and the following disclaimer in the
+//    documentation and/or other materials provided with the distribution.
+//
+// 3. Neither the name of the copyright holder nor the names of its
+//    contributors may be used to endorse or promote products derived
+//    from this software without specific prior written permission.
+//
+// THIS SOFTWARE IS PROVIDED BY THE COPYRIGHT HOLDERS AND CONTRIBUTORS
+// AS IS" AND ANY EXPRESS OR IMPLIED WARRANTIES, INCLUDING, BUT NOT
+// LIMITED TO, THE IMPLIED WARRANTIES OF MERCHANTABILITY AND FITNESS
+// FOR A PARTICULAR PURPOSE ARE DISCLAIMED. IN NO EVENT SHALL THE
+// COPYRIGHT HOLDER OR CONTRIBUTORS BE LIABLE FOR ANY DIRECT, INDIRECT,
+// INCIDENTAL, SPECIAL, EXEMPLARY, OR CONSEQUENTIAL DAMAGES (INCLUDING,
+// BUT NOT LIMITED TO, PROCUREMENT OF SUBSTITUTE GOODS OR SERVICES; LOSS
+// OF USE, DATA, OR PROFITS; OR BUSINESS INTERRUPTION) HOWEVER CAUSED
+// AND ON ANY THEORY OF LIABILITY, WHETHER IN CONTRACT, STRICT LIABILITY,
+// OR TORT (INCLUDING NEGLIGENCE OR OTHERWISE) ARISING IN ANY WAY OUT OF
+// THE USE OF THIS SOFTWARE, EVEN IF ADVISED OF THE POSSIBILITY OF SUCH
+// DAMAGE.
+
+use std::collections::HashMap;
+use std::io;
+
+use algebra::matrix::Mat4;
+use graphics::fullscreen::draw_fullscreen_quad;
+use graphics::renderer::*;
+use graphics::resources::*;
+use graphics::shader::*;
+use misc::fileutils::*;
+
+/// The name the velocity shader is registered under
+pub const VELOCITY_SHADER_NAME: &'static str = "velocity";
+
+/// The name the motion blur shader is registered under
+pub const MOTION_BLUR_SHADER_NAME: &'static str = "motion_blur";
+
+/// The name of the uniform block holding the current and previous view-projection matrices
+pub const VELOCITY_CAMERA_BLOCK: &'static str = "velocity_camera";
+
+/// The name of the uniform block holding the motion blur settings
+pub const MOTION_BLUR_SETTINGS_BLOCK: &'static str = "motion_blur_settings";
+
+/// The name of the texture the scene colour is sampled from by the motion blur
+pub const MOTION_BLUR_COLOUR_TEXTURE: &'static str = "motion_blur_colour_texture";
+
+/// The name of the texture the velocity buffer is sampled from by the motion blur
+pub const MOTION_BLUR_VELOCITY_TEXTURE: &'static str = "motion_blur_velocity_texture";
+
+/// The GLSL library that computes motion vectors, for shaders that write velocity themselves
+pub const VELOCITY_LIBRARY_FILENAME: &'static str = "velocity_common.glsl";
+
+const VELOCITY_VERTEX_FILENAME: &'static str = "velocity.vert";
+const VELOCITY_FRAGMENT_FILENAME: &'static str = "velocity.frag";
+const MOTION_BLUR_VERTEX_FILENAME: &'static str = "motion_blur.vert";
+const MOTION_BLUR_FRAGMENT_FILENAME: &'static str = "motion_blur.frag";
+
+const VELOCITY_LIBRARY_SOURCE: &'static str = include_str!("shaders/velocity_common.glsl");
+const VELOCITY_VERTEX_SOURCE: &'static str = include_str!("shaders/velocity.vert");
+const VELOCITY_FRAGMENT_SOURCE: &'static str = include_str!("shaders/velocity.frag");
+const MOTION_BLUR_VERTEX_SOURCE: &'static str = include_str!("shaders/motion_blur.vert");
+const MOTION_BLUR_FRAGMENT_SOURCE: &'static str = include_str!("shaders/motion_blur.frag");
+
+/// Tracks the current and previous frame transforms needed to produce motion vectors
+///
+/// Call begin_frame once per frame before setting any transforms, then set_model for each
+/// object as it is drawn.  An object seen for the first time has no motion.
+pub struct MotionVectorTracker {
+    view_projection: Mat4<f32>,
+    previous_view_projection: Mat4<f32>,
+    models: HashMap<u32, (Mat4<f32>, Mat4<f32>)>,
+    started: bool,
+}
+
+impl MotionVectorTracker {
+    pub fn new() -> MotionVectorTracker {
+        MotionVectorTracker {
+            view_projection: Mat4::newidentity(),
+            previous_view_projection: Mat4::newidentity(),
+            models: HashMap::new(),
+            started: false,
+        }
+    }
+
+    /// Start a new frame, making the transforms of the last frame the previous ones
+    ///
+    /// view_projection: The view-projection matrix of the new frame
+    pub fn begin_frame(&mut self, view_projection: &Mat4<f32>) {
+        self.previous_view_projection = if self.started {
+            self.view_projection
+        } else {
+            *view_projection
+        };
+        self.view_projection = *view_projection;
+        self.started = true;
+
+        for entry in self.models.values_mut() {
+            entry.1 = entry.0;
+        }
+    }
+
+    /// Record the model matrix of an object for the current frame
+    ///
+    /// object: The caller's identifier for the object
+    /// model: The model matrix of the object this frame
+    pub fn set_model(&mut self, object: u32, model: &Mat4<f32>) {
+        let entry = self.models.entry(object).or_insert((*model, *model));
+        entry.0 = *model;
+    }
+
+    /// Forget an object, so that it no longer has a previous transform
+    ///
+    /// object: The caller's identifier for the object
+    pub fn remove_object(&mut self, object: u32) {
+        self.models.remove(&object);
+    }
+
+    /// Return the model matrix of an object in the previous frame
+    ///
+    /// object: The caller's identifier for the object
+    pub fn previous_model(&self, object: u32) -> Option<&Mat4<f32>> {
+        self.models.get(&object).map(|entry| &entry.1)
+    }
+
+    /// Return the view-projection matrix of the current frame
+    pub fn view_projection(&self) -> &Mat4<f32> {
+        &self.view_projection
+    }
+
+    /// Return the view-projection matrix of the previous frame, the current one on the first frame
+    pub fn previous_view_projection(&self) -> &Mat4<f32> {
+        &self.previous_view_projection
+    }
+
+    /// Update the velocity_camera block from the current and previous view-projections
+    ///
+    /// renderer: The renderer owning the uniform buffer
+    pub fn synchronise_camera<Rend: Renderer + ?Sized>(&self, renderer: &Rend) {
+        renderer.set_uniform_buffer_matrix(VELOCITY_CAMERA_BLOCK, "velocity_view_projection", &self.view_projection);
+        renderer.set_uniform_buffer_matrix(VELOCITY_CAMERA_BLOCK,
+                                           "velocity_previous_view_projection",
+                                           &self.previous_view_projection);
+        renderer.synchronise_uniform_buffer(VELOCITY_CAMERA_BLOCK);
+    }
+
+    /// Set the push constants of the velocity shader for the draws of an object
+    ///
+    /// The object's model matrix for this frame must already have been set.
+    ///
+    /// renderer: The renderer to set the push constants on
+    /// thr: The thread the object is drawn from
+    /// object: The caller's identifier for the object
+    pub fn set_object_constants<Rend: Renderer + ?Sized>(&self, renderer: &Rend, thr: usize, object: u32) {
+        match self.models.get(&object) {
+            Some(&(ref model, ref previous_model)) => {
+                renderer.set_push_constant_matrix(thr, "velocity_model", model);
+                renderer.set_push_constant_matrix(thr, "velocity_previous_model", previous_model);
+            }
+            None => log_warning!("No transform recorded for object {}", object),
+        }
+    }
+}
+
+/// Settings for the motion blur post effect
+#[derive(Clone, Copy, Debug)]
+pub struct MotionBlurSettings {
+    pub intensity: f32,
+    pub max_samples: i32,
+}
+
+impl Default for MotionBlurSettings {
+    fn default() -> MotionBlurSettings {
+        MotionBlurSettings {
+            intensity: 0.5f32,
+            max_samples: 16,
+        }
+    }
+}
+
+/// Write the sources of the velocity and motion blur shaders to the working directory
+///
+/// Scene shaders that write motion vectors list the velocity library among their library files,
+/// so this must be called before the renderer is set up for them to build as well as the blur.
+pub fn install_motion_blur_shader_sources() -> Result<(), io::Error> {
+    write_entire_file(VELOCITY_LIBRARY_SOURCE, VELOCITY_LIBRARY_FILENAME)?;
+    write_entire_file(VELOCITY_VERTEX_SOURCE, VELOCITY_VERTEX_FILENAME)?;
+    write_entire_file(VELOCITY_FRAGMENT_SOURCE, VELOCITY_FRAGMENT_FILENAME)?;
+    write_entire_file(MOTION_BLUR_VERTEX_SOURCE, MOTION_BLUR_VERTEX_FILENAME)?;
+    write_entire_file(MOTION_BLUR_FRAGMENT_SOURCE, MOTION_BLUR_FRAGMENT_FILENAME)?;
+
+    Ok(())
+}
+
+/// Return a uniform block specification whose members are filled in by the reflection data
+///
+/// names: The names of the members of the block
+fn motion_blur_block_spec(names: &[&'static str]) -> UniformBlockSpec {
+    UniformBlockSpec {
+        set: 0,
+        block_type: UniformType::UniformBuffer,
+        uniforms: names.iter()
+            .map(|name| {
+                BlockUniformSpec {
+                    name: *name,
+                    ..Default::default()
+                }
+            })
+            .collect(),
+        ..Default::default()
+    }
+}
+
+/// Return the specifications of a vertex and fragment shader pair
+fn motion_blur_shader_files(vertex: &'static str,
+                            vertex_spirv: &'static str,
+                            vertex_reflect: &'static str,
+                            fragment: &'static str,
+                            fragment_spirv: &'static str,
+                            fragment_reflect: &'static str)
+                            -> Vec<ShaderFilesSpecification> {
+    vec![ShaderFilesSpecification {
+             filename: vertex,
+             shader_stage: ShaderStage::VertexShader,
+             spirv_out: vertex_spirv,
+             reflect_out: vertex_reflect,
+             entry_point: DEFAULT_ENTRY_POINT,
+         },
+         ShaderFilesSpecification {
+             filename: fragment,
+             shader_stage: ShaderStage::FragmentShader,
+             spirv_out: fragment_spirv,
+             reflect_out: fragment_reflect,
+             entry_point: DEFAULT_ENTRY_POINT,
+         }]
+}
+
+/// Add the uniform blocks and shader specifications of the velocity and motion blur shaders
+///
+/// The velocity shader draws F3 positions into a velocity buffer, a render target of its own
+/// holding the screen-space motion of each pixel in texture coordinate units.  Shaders that
+/// can write a second output may instead include VELOCITY_LIBRARY_FILENAME and write the
+/// motion vector from their own pass.  The velocity buffer is not specific to motion blur, so
+/// a temporal anti-aliasing pass can reproject with the same texture.
+///
+/// The motion blur shader draws F2F2 full-screen triangles, sampling the scene colour and the
+/// velocity buffer.
+///
+/// resource_manager: The resource manager to add the specifications to
+/// velocity_pass_name: The pass the velocity shader is used in
+/// motion_blur_pass_name: The pass the motion blur shader is used in
+pub fn register_motion_blur_shaders(resource_manager: &mut ResourceManager,
+                                    velocity_pass_name: &'static str,
+                                    motion_blur_pass_name: &'static str) {
+    resource_manager.uniform_block_specs
+        .insert(VELOCITY_CAMERA_BLOCK,
+                motion_blur_block_spec(&["velocity_view_projection", "velocity_previous_view_projection"]));
+    resource_manager.uniform_block_specs.insert(MOTION_BLUR_SETTINGS_BLOCK,
+                                                motion_blur_block_spec(&["motion_blur_viewport",
+                                                                         "motion_blur_intensity",
+                                                                         "motion_blur_max_samples"]));
+
+    let velocity_spec = ShaderSpec {
+        name: VELOCITY_SHADER_NAME,
+        library_files: vec![VELOCITY_LIBRARY_FILENAME],
+        shader_files: motion_blur_shader_files(VELOCITY_VERTEX_FILENAME,
+                                               "velocity.vert.spv",
+                                               "velocity.vert.rfl",
+                                               VELOCITY_FRAGMENT_FILENAME,
+                                               "velocity.frag.spv",
+                                               "velocity.frag.rfl"),
+        fallback_shader_files: vec![],
+        uniform_block_names: vec![VELOCITY_CAMERA_BLOCK],
+        uniform_specs: vec![],
+        push_constants: vec![PushConstantSpec {
+                                 name: "velocity_model",
+                                 offset: 0,
+                                 size: 64,
+                             },
+                             PushConstantSpec {
+                                 name: "velocity_previous_model",
+                                 offset: 64,
+                                 size: 64,
+                             }],
+        vertex_array_type: VertexArrayType::F3,
+        primitive: PrimitiveType::PrimitiveTriangles,
+        instance_layout: None,
+        attributes: vec!["position"],
+        fragment_out: "velocity_out",
+        extra_fragment_outs: vec![],
+        render_state: RenderState::opaque(),
+        stencil: None,
+        bindless_textures: false,
+        pass_name: velocity_pass_name,
+    };
+    resource_manager.shader_specs.insert(VELOCITY_SHADER_NAME, velocity_spec);
+
+    let motion_blur_spec = ShaderSpec {
+        name: MOTION_BLUR_SHADER_NAME,
+        library_files: vec![],
+        shader_files: motion_blur_shader_files(MOTION_BLUR_VERTEX_FILENAME,
+                                               "motion_blur.vert.spv",
+                                               "motion_blur.vert.rfl",
+                                               MOTION_BLUR_FRAGMENT_FILENAME,
+                                               "motion_blur.frag.spv",
+                                               "motion_blur.frag.rfl"),
+        fallback_shader_files: vec![],
+        uniform_block_names: vec![MOTION_BLUR_SETTINGS_BLOCK],
+        uniform_specs: vec![UniformSpec {
+                                name: MOTION_BLUR_COLOUR_TEXTURE,
+                                set: 1,
+                                binding: 0,
+                                uniform_type: UniformType::CombinedImageSampler,
+                            },
+                            UniformSpec {
+                                name: MOTION_BLUR_VELOCITY_TEXTURE,
+                                set: 1,
+                                binding: 1,
+                                uniform_type: UniformType::CombinedImageSampler,
+                            }],
+        push_constants: vec![],
+        vertex_array_type: VertexArrayType::F2F2,
+        primitive: PrimitiveType::PrimitiveTriangles,
+        instance_layout: None,
+        attributes: vec!["position", "texcoord"],
+        fragment_out: "motion_blur_colour",
+        extra_fragment_outs: vec![],
+        render_state: RenderState::default(),
+        stencil: None,
+        bindless_textures: false,
+        pass_name: motion_blur_pass_name,
+    };
+    resource_manager.shader_specs.insert(MOTION_BLUR_SHADER_NAME, motion_blur_spec);
+}
+
+/// Draw the motion blur post effect over the whole of the current render target
+///
+/// The scene colour and velocity buffer must be available as the textures named by
+/// MOTION_BLUR_COLOUR_TEXTURE and MOTION_BLUR_VELOCITY_TEXTURE.  Each pixel takes one sample
+/// per pixel of blur, up to the maximum, so still parts of the frame cost a single sample.
+///
+/// renderer: The renderer to draw with
+/// settings: The strength and sample limit of the blur
+/// width: The width of the render target in pixels
+/// height: The height of the render target in pixels
+pub fn render_motion_blur<Rend: Renderer + ?Sized>(renderer: &mut Rend,
+                                                   settings: &MotionBlurSettings,
+                                                   width: u32,
+                                                   height: u32) {
+    renderer.set_uniform_buffer_float_vector(MOTION_BLUR_SETTINGS_BLOCK,
+                                             "motion_blur_viewport",
+                                             &vec![width as f32, height as f32, 0.0f32, 0.0f32]);
+    renderer.set_uniform_buffer_float(MOTION_BLUR_SETTINGS_BLOCK, "motion_blur_intensity", settings.intensity);
+    renderer.set_uniform_buffer_int(MOTION_BLUR_SETTINGS_BLOCK,
+                                    "motion_blur_max_samples",
+                                    settings.max_samples.max(1));
+    renderer.synchronise_uniform_buffer(MOTION_BLUR_SETTINGS_BLOCK);
+
+    renderer.begin_pass(MOTION_BLUR_SHADER_NAME);
+    draw_fullscreen_quad(renderer);
+    renderer.end_pass();
+}
@@ -0,0 +1,407 @@
+// Copyright (c) 2016-2017 Bruce Stenning. All rights reserved.
+//
+// Redistribution and use in source and binary forms, with or without
+// modification, are permitted provided that the following conditions
+// are met:
+//
+// 1. Redistributions of source code must retain the above copyright
+//    notice, this list of conditions and the following disclaimer.
+//
+// 2. Redistributions in binary form must reproduce the above copyright
+//    notice, this list of conditions and the following disclaimer in the
+//    documentation and/or other materials provided with the distribution.
+//
+// 3. Neither the name of the copyright holder nor the names of its
+//    contributors may be used to endorse or promote products derived
+//    from this software without specific prior written permission.
+//
+// THIS SOFTWARE IS PROVIDED BY THE COPYRIGHT HOLDERS AND CONTRIBUTORS
+// AS IS" AND ANY EXPRESS OR IMPLIED WARRANTIES, INCLUDING, BUT NOT
+// LIMITED TO, THE IMPLIED WARRANTIES OF MERCHANTABILITY AND FITNESS
+// FOR A PARTICULAR PURPOSE ARE DISCLAIMED. IN NO EVENT SHALL THE
+// COPYRIGHT HOLDER OR CONTRIBUTORS BE LIABLE FOR ANY DIRECT, INDIRECT,
+// INCIDENTAL, SPECIAL, EXEMPLARY, OR CONSEQUENTIAL DAMAGES (INCLUDING,
+// BUT NOT LIMITED TO, PROCUREMENT OF SUBSTITUTE GOODS OR SERVICES; LOSS
+// OF USE, DATA, OR PROFITS; OR BUSINESS INTERRUPTION) HOWEVER CAUSED
+// AND ON ANY THEORY OF LIABILITY, WHETHER IN CONTRACT, STRICT LIABILITY,
+// OR TORT (INCLUDING NEGLIGENCE OR OTHERWISE) ARISING IN ANY WAY OUT OF
+// THE USE OF THIS SOFTWARE, EVEN IF ADVISED OF THE POSSIBILITY OF SUCH
+// DAMAGE.
+
+use std::io;
+
+use graphics::renderer::*;
+use graphics::resources::*;
+use graphics::shader::*;
+use graphics::texture::*;
+use misc::fileutils::*;
+use algebra::matrix::{ClipSpaceConvention, Mat4};
+use algebra::vector::*;
+
+/// The name the overlay shader is registered under
+pub const OVERLAY_SHADER_NAME: &'static str = "overlay";
+
+/// The name of the texture textured quads are sampled from
+pub const OVERLAY_TEXTURE: &'static str = "overlay_texture";
+
+/// The name of the fragment output of the overlay shader
+pub const OVERLAY_FRAGMENT_OUT: &'static str = "overlay_colour";
+
+const OVERLAY_VERTEX_FILENAME: &'static str = "overlay.vert";
+const OVERLAY_FRAGMENT_FILENAME: &'static str = "overlay.frag";
+
+const OVERLAY_VERTEX_SOURCE: &'static str = include_str!("shaders/overlay.vert");
+const OVERLAY_FRAGMENT_SOURCE: &'static str = include_str!("shaders/overlay.frag");
+
+/// Construct the orthographic projection from pixels to clip space
+///
+/// The origin is the top-left corner of the render target, with y increasing down it, under
+/// every clip space convention.
+///
+/// width: The width of the render target in pixels
+/// height: The height of the render target in pixels
+/// convention: The clip space convention of the renderer
+pub fn overlay_projection(width: u32, height: u32, convention: ClipSpaceConvention) -> Mat4<f32> {
+    Mat4::clip_space_correction(convention) * Mat4::ortho(0.0f32, width as f32, height as f32, 0.0f32, -1.0f32, 1.0f32)
+}
+
+/// A rectangle in pixels, with y increasing down the screen
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub struct OverlayRect {
+    pub x: f32, // The left edge
+    pub y: f32, // The top edge
+    pub width: f32,
+    pub height: f32,
+}
+
+/// How an overlay item is filled
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub enum OverlayFill {
+    Solid,
+    Textured {
+        texture: usize, // The index of the texture in those passed to Overlay::render
+        texcoord_min: Vec2<f32>, // The texture coordinates of the top-left corner
+        texcoord_max: Vec2<f32>, // The texture coordinates of the bottom-right corner
+    },
+}
+
+impl OverlayFill {
+    /// Return the texture the fill samples, if any
+    pub fn texture(&self) -> Option<usize> {
+        match *self {
+            OverlayFill::Solid => None,
+            OverlayFill::Textured { texture, .. } => Some(texture),
+        }
+    }
+}
+
+/// A 2D item drawn over the scene
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub struct OverlayItem {
+    pub layer: i32, // Items of higher layers are drawn over those of lower ones
+    pub rect: OverlayRect,
+    pub colour: [f32; 4], // The colour of a solid fill, or the tint of a textured one
+    pub fill: OverlayFill,
+}
+
+/// 2D items gathered over a frame and drawn over the scene in one pass
+///
+/// The pass projects pixel coordinates orthographically, blends without testing depth, and
+/// draws the items in order of layer and then of submission.  Items are submitted as F2F2
+/// triangles, and consecutive items of the same colour and texture share a draw.
+pub struct Overlay {
+    items: Vec<OverlayItem>,
+}
+
+impl Overlay {
+    /// Create an overlay with no items
+    pub fn new() -> Overlay {
+        Overlay { items: vec![] }
+    }
+
+    /// Add an item
+    ///
+    /// item: The item to add
+    pub fn add(&mut self, item: OverlayItem) {
+        self.items.push(item);
+    }
+
+    /// Add a solid rectangle
+    ///
+    /// layer: The layer to draw the rectangle in
+    /// rect: The rectangle in pixels
+    /// colour: The red, green, blue and alpha components of the rectangle
+    pub fn draw_rect(&mut self, layer: i32, rect: OverlayRect, colour: [f32; 4]) {
+        self.add(OverlayItem {
+            layer: layer,
+            rect: rect,
+            colour: colour,
+            fill: OverlayFill::Solid,
+        });
+    }
+
+    /// Add the outline of a rectangle, drawn inside its edges
+    ///
+    /// layer: The layer to draw the outline in
+    /// rect: The rectangle in pixels
+    /// thickness: The width of the outline in pixels
+    /// colour: The red, green, blue and alpha components of the outline
+    pub fn draw_rect_outline(&mut self, layer: i32, rect: OverlayRect, thickness: f32, colour: [f32; 4]) {
+        let thickness = thickness.min(rect.width / 2.0f32).min(rect.height / 2.0f32);
+        let sides = [OverlayRect { height: thickness, ..rect },
+                     OverlayRect {
+                         y: rect.y + rect.height - thickness,
+                         height: thickness,
+                         ..rect
+                     },
+                     OverlayRect {
+                         y: rect.y + thickness,
+                         width: thickness,
+                         height: rect.height - 2.0f32 * thickness,
+                         ..rect
+                     },
+                     OverlayRect {
+                         x: rect.x + rect.width - thickness,
+                         y: rect.y + thickness,
+                         width: thickness,
+                         height: rect.height - 2.0f32 * thickness,
+                     }];
+        for side in sides.iter() {
+            self.draw_rect(layer, *side, colour);
+        }
+    }
+
+    /// Add a textured quad
+    ///
+    /// layer: The layer to draw the quad in
+    /// rect: The rectangle in pixels
+    /// texture: The index of the texture in those passed to render
+    /// texcoord_min: The texture coordinates of the top-left corner
+    /// texcoord_max: The texture coordinates of the bottom-right corner
+    /// tint: The red, green, blue and alpha components the texture is multiplied by
+    pub fn draw_textured_quad(&mut self,
+                              layer: i32,
+                              rect: OverlayRect,
+                              texture: usize,
+                              texcoord_min: Vec2<f32>,
+                              texcoord_max: Vec2<f32>,
+                              tint: [f32; 4]) {
+        self.add(OverlayItem {
+            layer: layer,
+            rect: rect,
+            colour: tint,
+            fill: OverlayFill::Textured {
+                texture: texture,
+                texcoord_min: texcoord_min,
+                texcoord_max: texcoord_max,
+            },
+        });
+    }
+
+    /// Remove every item, typically at the start of a frame
+    pub fn clear(&mut self) {
+        self.items.clear();
+    }
+
+    /// Return the number of items
+    pub fn len(&self) -> usize {
+        self.items.len()
+    }
+
+    /// Return whether there are no items
+    pub fn is_empty(&self) -> bool {
+        self.items.is_empty()
+    }
+
+    /// Return the items in the order they are drawn, grouped into runs that share a draw
+    pub fn batches(&self) -> Vec<Vec<&OverlayItem>> {
+        // The sort is stable, so items of a layer keep the order they were added in
+        let mut sorted: Vec<&OverlayItem> = self.items.iter().collect();
+        sorted.sort_by_key(|item| item.layer);
+
+        let mut batches: Vec<Vec<&OverlayItem>> = vec![];
+        for item in sorted {
+            let shares_draw = match batches.last() {
+                Some(batch) => batch[0].colour == item.colour && batch[0].fill.texture() == item.fill.texture(),
+                None => false,
+            };
+            if shares_draw {
+                batches.last_mut().unwrap().push(item);
+            } else {
+                batches.push(vec![item]);
+            }
+        }
+
+        batches
+    }
+
+    /// Draw the items over the current render target
+    ///
+    /// On Vulkan textures are bound to the pipeline by name when the renderer is set up, so
+    /// every textured quad samples the texture named by OVERLAY_TEXTURE whatever its index.  On
+    /// OpenGL each run binds its own texture.
+    ///
+    /// renderer: The renderer to draw with
+    /// textures: The textures textured quads refer to by index
+    /// width: The width of the render target in pixels
+    /// height: The height of the render target in pixels
+    pub fn render<Rend: Renderer + ?Sized>(&self,
+                                           renderer: &mut Rend,
+                                           textures: &[&Box<Texture>],
+                                           width: u32,
+                                           height: u32) {
+        if self.is_empty() {
+            return;
+        }
+
+        let projection = overlay_projection(width, height, renderer.clip_space_convention());
+
+        renderer.begin_pass(OVERLAY_SHADER_NAME);
+        renderer.set_push_constant_matrix(0, "overlay_projection", &projection);
+
+        let threaddata_arc = renderer.get_threaddata(0);
+        let mut threaddata = threaddata_arc.lock().unwrap();
+        threaddata.vertex_array_type = VertexArrayType::F2F2;
+
+        for batch in self.batches() {
+            let first = batch[0];
+            if let Some(index) = first.fill.texture() {
+                match textures.get(index) {
+                    Some(texture) => texture.bind(0),
+                    None => {
+                        log_warning!("Skipping overlay items using unknown texture {}", index);
+                        continue;
+                    }
+                }
+            }
+
+            renderer.set_push_constant_vec3(0,
+                                            "overlay_tint",
+                                            &Vec3 {
+                                                x: first.colour[0],
+                                                y: first.colour[1],
+                                                z: first.colour[2],
+                                            });
+            renderer.set_push_constant_float(0, "overlay_opacity", first.colour[3]);
+            renderer.set_push_constant_int(0, "overlay_textured", first.fill.texture().is_some() as i32);
+
+            for item in batch {
+                let (texcoord_min, texcoord_max) = match item.fill {
+                    OverlayFill::Solid => (Vec2 { x: 0.0f32, y: 0.0f32 }, Vec2 { x: 1.0f32, y: 1.0f32 }),
+                    OverlayFill::Textured { texcoord_min, texcoord_max, .. } => (texcoord_min, texcoord_max),
+                };
+                let rect = item.rect;
+                let corners = [Vec2 { x: rect.x, y: rect.y },
+                               Vec2 {
+                                   x: rect.x + rect.width,
+                                   y: rect.y,
+                               },
+                               Vec2 {
+                                   x: rect.x + rect.width,
+                                   y: rect.y + rect.height,
+                               },
+                               Vec2 {
+                                   x: rect.x,
+                                   y: rect.y + rect.height,
+                               }];
+                let texcoords = [texcoord_min,
+                                 Vec2 {
+                                     x: texcoord_max.x,
+                                     y: texcoord_min.y,
+                                 },
+                                 texcoord_max,
+                                 Vec2 {
+                                     x: texcoord_min.x,
+                                     y: texcoord_max.y,
+                                 }];
+                threaddata.add_triangle_st_f2f2(&corners[0], &texcoords[0], &corners[1], &texcoords[1], &corners[2], &texcoords[2]);
+                threaddata.check_flush_st(false, renderer);
+                threaddata.add_triangle_st_f2f2(&corners[0], &texcoords[0], &corners[2], &texcoords[2], &corners[3], &texcoords[3]);
+                threaddata.check_flush_st(false, renderer);
+            }
+
+            // The colour or texture changes with the next run
+            threaddata.check_flush_st(true, renderer);
+        }
+
+        renderer.end_pass();
+    }
+}
+
+/// Write the sources of the overlay shader to the working directory
+///
+/// The overlay shader is built during setup, before any overlay can be drawn, so this must be
+/// called before the renderer is set up.
+pub fn install_overlay_shader_sources() -> Result<(), io::Error> {
+    write_entire_file(OVERLAY_VERTEX_SOURCE, OVERLAY_VERTEX_FILENAME)?;
+    write_entire_file(OVERLAY_FRAGMENT_SOURCE, OVERLAY_FRAGMENT_FILENAME)?;
+
+    Ok(())
+}
+
+/// Add the shader specification of the overlay shader
+///
+/// The shader blends over the colour target without testing depth, so the pass normally comes
+/// last, after the scene and any post-processing.
+///
+/// resource_manager: The resource manager to add the specification to
+/// pass_name: The pass the shader is used in
+pub fn register_overlay_shader(resource_manager: &mut ResourceManager, pass_name: &'static str) {
+    let shader_spec = ShaderSpec {
+        name: OVERLAY_SHADER_NAME,
+        library_files: vec![],
+        shader_files: vec![ShaderFilesSpecification {
+                               filename: OVERLAY_VERTEX_FILENAME,
+                               shader_stage: ShaderStage::VertexShader,
+                               spirv_out: "overlay.vert.spv",
+                               reflect_out: "overlay.vert.rfl",
+                               entry_point: DEFAULT_ENTRY_POINT,
+                           },
+                           ShaderFilesSpecification {
+                               filename: OVERLAY_FRAGMENT_FILENAME,
+                               shader_stage: ShaderStage::FragmentShader,
+                               spirv_out: "overlay.frag.spv",
+                               reflect_out: "overlay.frag.rfl",
+                               entry_point: DEFAULT_ENTRY_POINT,
+                           }],
+        fallback_shader_files: vec![],
+        uniform_block_names: vec![],
+        uniform_specs: vec![UniformSpec {
+                                name: OVERLAY_TEXTURE,
+                                set: 1,
+                                binding: 0,
+                                uniform_type: UniformType::CombinedImageSampler,
+                            }],
+        push_constants: vec![PushConstantSpec {
+                                 name: "overlay_projection",
+                                 offset: 0,
+                                 size: 64,
+                             },
+                             PushConstantSpec {
+                                 name: "overlay_tint",
+                                 offset: 64,
+                                 size: 12,
+                             },
+                             PushConstantSpec {
+                                 name: "overlay_opacity",
+                                 offset: 76,
+                                 size: 4,
+                             },
+                             PushConstantSpec {
+                                 name: "overlay_textured",
+                                 offset: 80,
+                                 size: 4,
+                             }],
+        vertex_array_type: VertexArrayType::F2F2,
+        primitive: PrimitiveType::PrimitiveTriangles,
+        instance_layout: None,
+        attributes: vec!["position", "texcoord"],
+        fragment_out: OVERLAY_FRAGMENT_OUT,
+        extra_fragment_outs: vec![],
+        render_state: RenderState::alpha_blended(),
+        stencil: None,
+        bindless_textures: false,
+        pass_name: pass_name,
+    };
+    resource_manager.shader_specs.insert(OVERLAY_SHADER_NAME, shader_spec);
+}
@@ -0,0 +1,243 @@
+// Copyright (c) 2016-2017 Bruce Stenning. All rights reserved.
+//
+// Redistribution and use in source and binary forms, with or without
+// modification, are permitted provided that the following conditions
+// are met:
+//
+// 1. Redistributions of source code must retain the above copyright
+//    notice, this list of conditions and the following disclaimer.
+//
+// 2. Redistributions in binary form must reproduce the above copyright
+//    notice, this list of conditions and the following disclaimer in the
+//    documentation and/or other materials provided with the distribution.
+//
+// 3. Neither the name of the copyright holder nor the names of its
+//    contributors may be used to endorse or promote products derived
+//    from this software without specific prior written permission.
+//
+// THIS SOFTWARE IS PROVIDED BY THE COPYRIGHT HOLDERS AND CONTRIBUTORS
+// AS IS" AND ANY EXPRESS OR IMPLIED WARRANTIES, INCLUDING, BUT NOT
+// LIMITED TO, THE IMPLIED WARRANTIES OF MERCHANTABILITY AND FITNESS
+// FOR A PARTICULAR PURPOSE ARE DISCLAIMED. IN NO EVENT SHALL THE
+// COPYRIGHT HOLDER OR CONTRIBUTORS BE LIABLE FOR ANY DIRECT, INDIRECT,
+// INCIDENTAL, SPECIAL, EXEMPLARY, OR CONSEQUENTIAL DAMAGES (INCLUDING,
+// BUT NOT LIMITED TO, PROCUREMENT OF SUBSTITUTE GOODS OR SERVICES; LOSS
+// OF USE, DATA, OR PROFITS; OR BUSINESS INTERRUPTION) HOWEVER CAUSED
+// AND ON ANY THEORY OF LIABILITY, WHETHER IN CONTRACT, STRICT LIABILITY,
+// OR TORT (INCLUDING NEGLIGENCE OR OTHERWISE) ARISING IN ANY WAY OUT OF
+// THE USE OF THIS SOFTWARE, EVEN IF ADVISED OF THE POSSIBILITY OF SUCH
+// DAMAGE.
+
+use std::fmt;
+
+use graphics::devicefeatures::*;
+use graphics::perdraw::PerDrawBenchmarkResults;
+
+/// Whether an engine feature works on a backend and device
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub enum FeatureSupport {
+    Supported,
+    Fallback(&'static str), // Works in a reduced form, as described
+    Unsupported, // The passes needing the feature must be skipped
+}
+
+impl fmt::Display for FeatureSupport {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match *self {
+            FeatureSupport::Supported => write!(f, "supported"),
+            FeatureSupport::Fallback(fallback) => write!(f, "fallback: {}", fallback),
+            FeatureSupport::Unsupported => write!(f, "unsupported"),
+        }
+    }
+}
+
+/// What a renderer found its backend and device to offer
+#[derive(Clone, Debug, PartialEq)]
+pub struct BackendCapabilities {
+    pub backend: &'static str,
+    pub device_name: String,
+    pub features: DeviceFeatures, // The device features granted
+    pub max_samples: u32, // The most samples per pixel the render targets support
+    pub sample_count: u32, // The samples per pixel in use
+    pub compute: bool,
+    pub timed_compute: bool, // Whether paced compute slices are timed on the GPU
+    pub bindless_textures: bool,
+    pub gpu_timing: bool, // Whether the passes are timed on the GPU
+    pub per_draw: Option<PerDrawBenchmarkResults>, // The per-draw strategy benchmark, if one has been run
+}
+
+/// One engine feature's support on a backend and device
+#[derive(Clone, Debug, PartialEq)]
+pub struct ParityEntry {
+    pub feature: &'static str,
+    pub support: FeatureSupport,
+}
+
+/// The support of every engine feature on a backend and device, for tooling and bug reports
+#[derive(Clone, Debug, PartialEq)]
+pub struct ParityReport {
+    pub backend: &'static str,
+    pub device_name: String,
+    pub sample_count: u32,
+    pub entries: Vec<ParityEntry>,
+    pub per_draw: Option<PerDrawBenchmarkResults>,
+}
+
+impl ParityReport {
+    /// Build the report of a renderer's capabilities
+    ///
+    /// capabilities: What the renderer found its backend and device to offer
+    pub fn new(capabilities: &BackendCapabilities) -> ParityReport {
+        let features = &capabilities.features;
+        let paced_compute = if capabilities.compute {
+            supported_or(capabilities.timed_compute,
+                         FeatureSupport::Fallback("untimed slices of the initial size"))
+        } else {
+            FeatureSupport::Unsupported
+        };
+        let shader_stores = if features.vertex_pipeline_stores_and_atomics && features.fragment_stores_and_atomics {
+            FeatureSupport::Supported
+        } else if features.fragment_stores_and_atomics {
+            FeatureSupport::Fallback("fragment shaders only")
+        } else {
+            FeatureSupport::Unsupported
+        };
+
+        let supports =
+            [("msaa", supported_or(capabilities.max_samples > 1, FeatureSupport::Fallback("single sampled"))),
+             ("compute", supported_or(capabilities.compute, FeatureSupport::Unsupported)),
+             ("paced_compute", paced_compute),
+             ("tessellation", supported_or(features.tessellation_shader, FeatureSupport::Unsupported)),
+             ("geometry_shader", supported_or(features.geometry_shader, FeatureSupport::Unsupported)),
+             ("bindless_textures",
+              supported_or(capabilities.bindless_textures, FeatureSupport::Fallback("textures bound per material"))),
+             ("wireframe", supported_or(features.fill_mode_non_solid, FeatureSupport::Fallback("always filled"))),
+             ("anisotropic_filtering",
+              supported_or(features.sampler_anisotropy, FeatureSupport::Fallback("isotropic filtering"))),
+             ("wide_lines", supported_or(features.wide_lines, FeatureSupport::Fallback("one pixel wide"))),
+             ("large_points", supported_or(features.large_points, FeatureSupport::Fallback("one pixel"))),
+             ("gpu_timing", supported_or(capabilities.gpu_timing, FeatureSupport::Fallback("CPU timing only"))),
+             ("shader_stores", shader_stores)];
+        let entries = supports.iter()
+            .map(|&(feature, support)| {
+                ParityEntry {
+                    feature: feature,
+                    support: support,
+                }
+            })
+            .collect();
+
+        ParityReport {
+            backend: capabilities.backend,
+            device_name: capabilities.device_name.clone(),
+            sample_count: capabilities.sample_count,
+            entries: entries,
+            per_draw: capabilities.per_draw.clone(),
+        }
+    }
+
+    /// Return the support of a feature, or None if the report does not cover it
+    ///
+    /// feature: The name of the feature, such as "msaa"
+    pub fn support(&self, feature: &str) -> Option<FeatureSupport> {
+        self.entries.iter().find(|entry| entry.feature == feature).map(|entry| entry.support)
+    }
+
+    /// Return the features whose support differs from another report's
+    ///
+    /// other: The report of another backend or device
+    pub fn differences(&self, other: &ParityReport) -> Vec<&'static str> {
+        self.entries
+            .iter()
+            .filter(|entry| other.support(entry.feature) != Some(entry.support))
+            .map(|entry| entry.feature)
+            .collect()
+    }
+}
+
+impl fmt::Display for ParityReport {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        writeln!(f,
+                 "{} on {}, {} sample{} per pixel",
+                 self.backend,
+                 self.device_name,
+                 self.sample_count,
+                 if self.sample_count == 1 { "" } else { "s" })?;
+        for entry in self.entries.iter() {
+            writeln!(f, "  {:<24}{}", entry.feature, entry.support)?;
+        }
+        if let Some(ref per_draw) = self.per_draw {
+            write!(f, "{}", per_draw)?;
+        }
+
+        Ok(())
+    }
+}
+
+/// Return a table of the support of each feature on several backends or devices, with the
+/// features that differ marked by an asterisk
+///
+/// reports: The reports to compare, one per column
+pub fn format_parity_table(reports: &[ParityReport]) -> String {
+    let width = reports.iter()
+        .flat_map(|report| report.entries.iter().map(|entry| entry.support.to_string().len()))
+        .chain(reports.iter().map(|report| report.backend.len()))
+        .max()
+        .unwrap_or(0) + 2;
+
+    let backends: Vec<String> = reports.iter().map(|report| report.backend.to_string()).collect();
+    let mut table = table_row(" ", "", &backends, width);
+
+    let mut features: Vec<&'static str> = vec![];
+    for entry in reports.iter().flat_map(|report| report.entries.iter()) {
+        if !features.contains(&entry.feature) {
+            features.push(entry.feature);
+        }
+    }
+
+    for feature in features.iter() {
+        let supports: Vec<Option<FeatureSupport>> = reports.iter().map(|report| report.support(feature)).collect();
+        let differs = supports.iter().any(|support| *support != supports[0]);
+        let cells: Vec<String> = supports.iter()
+            .map(|support| match *support {
+                Some(support) => support.to_string(),
+                None => "-".to_string(),
+            })
+            .collect();
+        table += &table_row(if differs { "*" } else { " " }, feature, &cells, width);
+    }
+
+    table
+}
+
+/// Return a line of the parity table, with every column but the last padded to the same width
+///
+/// marker: The marker in front of the feature
+/// feature: The name of the feature
+/// cells: The text of each column
+/// width: The width of the columns
+fn table_row(marker: &str, feature: &str, cells: &Vec<String>, width: usize) -> String {
+    let mut row = format!("{} {:<24}", marker, feature);
+    for (i, cell) in cells.iter().enumerate() {
+        if i + 1 < cells.len() {
+            row += &format!("{:<1$}", cell, width);
+        } else {
+            row += cell;
+        }
+    }
+    row.push('\n');
+
+    row
+}
+
+/// Return Supported if a feature is available, or how it is otherwise handled
+///
+/// available: Whether the feature is available
+/// otherwise: The support when it is not
+fn supported_or(available: bool, otherwise: FeatureSupport) -> FeatureSupport {
+    if available {
+        FeatureSupport::Supported
+    } else {
+        otherwise
+    }
+}
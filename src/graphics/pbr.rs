@@ -0,0 +1,328 @@
+// Copyright (c) 2016-2017 Bruce Stenning. All rights reserved.
+//
+// Redistribution and use in source and binary forms, with or without
+// modification, are permitted provided that the following conditions
+// are met:
+//
+// 1. Redistributions of source code must retain the above copyright
+//    notice, this list of conditions and the following disclaimer.
+//
+// 2. Redistributions in binary form must reproduce the above copyright
+//    notice, this list of conditions and the following disclaimer in the
+//    documentation and/or other materials provided with the distribution.
+//
+// 3. Neither the name of the copyright holder nor the names of its
+//    contributors may be used to endorse or promote products derived
+//    from this software without specific prior written permission.
+//
+// THIS SOFTWARE IS PROVIDED BY THE COPYRIGHT HOLDERS AND CONTRIBUTORS
+// AS IS" AND ANY EXPRESS OR IMPLIED WARRANTIES, INCLUDING, BUT NOT
+// LIMITED TO, THE IMPLIED WARRANTIES OF MERCHANTABILITY AND FITNESS
+// FOR A PARTICULAR PURPOSE ARE DISCLAIMED. IN NO EVENT SHALL THE
+// COPYRIGHT HOLDER OR CONTRIBUTORS BE LIABLE FOR ANY DIRECT, INDIRECT,
+// INCIDENTAL, SPECIAL, EXEMPLARY, OR CONSEQUENTIAL DAMAGES (INCLUDING,
+// BUT NOT LIMITED TO, PROCUREMENT OF SUBSTITUTE GOODS OR SERVICES; LOSS
+// OF USE, DATA, OR PROFITS; OR BUSINESS INTERRUPTION) HOWEVER CAUSED
+// AND ON ANY THEORY OF LIABILITY, WHETHER IN CONTRACT, STRICT LIABILITY,
+// OR TORT (INCLUDING NEGLIGENCE OR OTHERWISE) ARISING IN ANY WAY OUT OF
+// THE USE OF THIS SOFTWARE, EVEN IF ADVISED OF THE POSSIBILITY OF SUCH
+// DAMAGE.
+
+use std::io;
+
+use algebra::vector::Vec3;
+use graphics::contactshadows::*;
+use graphics::renderer::*;
+use graphics::resources::*;
+use graphics::shader::*;
+use graphics::uniformlayout::BlockMemberType;
+use misc::fileutils::*;
+
+/// The name the built-in PBR shader is registered under
+pub const PBR_SHADER_NAME: &'static str = "pbr";
+
+/// The name of the uniform block holding the model, view and projection matrices and the eye position
+pub const PBR_CAMERA_BLOCK: &'static str = "pbr_camera";
+
+/// The name of the uniform block holding the light positions and colours
+pub const PBR_LIGHTS_BLOCK: &'static str = "pbr_lights";
+
+/// The name of the uniform block holding the material factors
+pub const PBR_MATERIAL_BLOCK: &'static str = "pbr_material";
+
+/// The number of lights the PBR shader evaluates, which must match PBR_MAX_LIGHTS in the GLSL
+pub const PBR_MAX_LIGHTS: usize = 4;
+
+/// The name of the fragment output of the PBR shader
+pub const PBR_FRAGMENT_OUT: &'static str = "pbr_colour";
+
+/// The names of the material and IBL textures, in binding order within descriptor set 1
+pub const PBR_TEXTURE_NAMES: [&'static str; 8] = ["pbr_base_colour_texture",
+                                                  "pbr_metallic_roughness_texture",
+                                                  "pbr_normal_texture",
+                                                  "pbr_occlusion_texture",
+                                                  "pbr_emissive_texture",
+                                                  "pbr_irradiance_map",
+                                                  "pbr_prefiltered_map",
+                                                  "pbr_brdf_lut"];
+
+const PBR_LIBRARY_FILENAME: &'static str = "pbr_common.glsl";
+const PBR_VERTEX_FILENAME: &'static str = "pbr.vert";
+const PBR_FRAGMENT_FILENAME: &'static str = "pbr.frag";
+
+const PBR_LIBRARY_SOURCE: &'static str = include_str!("shaders/pbr_common.glsl");
+const PBR_VERTEX_SOURCE: &'static str = include_str!("shaders/pbr.vert");
+const PBR_FRAGMENT_SOURCE: &'static str = include_str!("shaders/pbr.frag");
+
+/// Identifies a light added to a light manager
+pub type LightHandle = u32;
+
+/// A light evaluated by the PBR shader
+#[derive(Clone, Copy, Debug)]
+pub struct PbrLight {
+    pub position: Vec3<f32>, // For a directional light, the direction the light travels in
+    pub directional: bool,
+    pub colour: Vec3<f32>,
+    pub intensity: f32,
+    pub contact_shadows: bool, // Whether the light casts contact shadows, where they are enabled
+}
+
+impl PbrLight {
+    /// Create a point light, casting contact shadows
+    ///
+    /// position: The position of the light
+    /// colour: The colour of the light
+    /// intensity: The intensity the colour is scaled by
+    pub fn point(position: Vec3<f32>, colour: Vec3<f32>, intensity: f32) -> PbrLight {
+        PbrLight {
+            position: position,
+            directional: false,
+            colour: colour,
+            intensity: intensity,
+            contact_shadows: true,
+        }
+    }
+
+    /// Create a directional light, casting contact shadows
+    ///
+    /// direction: The direction the light travels in
+    /// colour: The colour of the light
+    /// intensity: The intensity the colour is scaled by
+    pub fn directional(direction: Vec3<f32>, colour: Vec3<f32>, intensity: f32) -> PbrLight {
+        PbrLight {
+            directional: true,
+            ..PbrLight::point(direction, colour, intensity)
+        }
+    }
+}
+
+/// Holds the lights of a scene and publishes them in the PBR_LIGHTS_BLOCK uniform block
+///
+/// The shader evaluates at most PBR_MAX_LIGHTS lights, in the order they were added.
+pub struct LightManager {
+    lights: Vec<(LightHandle, PbrLight)>,
+    next_handle: LightHandle,
+}
+
+impl LightManager {
+    pub fn new() -> LightManager {
+        LightManager {
+            lights: vec![],
+            next_handle: 0,
+        }
+    }
+
+    /// Add a light, returning the handle it may be updated or removed with, or None if there are
+    /// already PBR_MAX_LIGHTS lights
+    ///
+    /// light: The light to add
+    pub fn add_light(&mut self, light: PbrLight) -> Option<LightHandle> {
+        if self.lights.len() >= PBR_MAX_LIGHTS {
+            return None;
+        }
+
+        let handle = self.next_handle;
+        self.next_handle += 1;
+        self.lights.push((handle, light));
+
+        Some(handle)
+    }
+
+    /// Remove a light, returning it if the handle was present
+    ///
+    /// handle: The handle returned when the light was added
+    pub fn remove_light(&mut self, handle: LightHandle) -> Option<PbrLight> {
+        match self.lights.iter().position(|&(h, _)| h == handle) {
+            Some(index) => Some(self.lights.remove(index).1),
+            None => None,
+        }
+    }
+
+    /// Return a light
+    ///
+    /// handle: The handle returned when the light was added
+    pub fn get(&self, handle: LightHandle) -> Option<&PbrLight> {
+        self.lights.iter().find(|&&(h, _)| h == handle).map(|&(_, ref light)| light)
+    }
+
+    /// Return a light to be changed
+    ///
+    /// handle: The handle returned when the light was added
+    pub fn get_mut(&mut self, handle: LightHandle) -> Option<&mut PbrLight> {
+        self.lights.iter_mut().find(|&&mut (h, _)| h == handle).map(|&mut (_, ref mut light)| light)
+    }
+
+    /// Set whether a light casts contact shadows
+    ///
+    /// handle: The handle returned when the light was added
+    /// enabled: Whether the light casts contact shadows
+    pub fn set_contact_shadows(&mut self, handle: LightHandle, enabled: bool) {
+        if let Some(light) = self.get_mut(handle) {
+            light.contact_shadows = enabled;
+        }
+    }
+
+    pub fn len(&self) -> usize {
+        self.lights.len()
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.lights.is_empty()
+    }
+
+    /// Return the mask of the lights casting contact shadows, bit n standing for the nth light
+    pub fn contact_shadow_mask(&self) -> i32 {
+        self.lights
+            .iter()
+            .enumerate()
+            .filter(|&(_, &(_, ref light))| light.contact_shadows)
+            .fold(0, |mask, (i, _)| mask | 1 << i)
+    }
+
+    /// Set the lights in the PBR_LIGHTS_BLOCK uniform block and synchronise it
+    ///
+    /// renderer: The renderer owning the uniform buffer
+    pub fn synchronise<Rend: Renderer + ?Sized>(&self, renderer: &Rend) {
+        let mut positions = vec![0.0f32; 4 * PBR_MAX_LIGHTS];
+        let mut colours = vec![0.0f32; 4 * PBR_MAX_LIGHTS];
+        for (i, &(_, ref light)) in self.lights.iter().enumerate() {
+            positions[4 * i] = light.position.x;
+            positions[4 * i + 1] = light.position.y;
+            positions[4 * i + 2] = light.position.z;
+            positions[4 * i + 3] = if light.directional { 0.0f32 } else { 1.0f32 };
+            colours[4 * i] = light.colour.x;
+            colours[4 * i + 1] = light.colour.y;
+            colours[4 * i + 2] = light.colour.z;
+            colours[4 * i + 3] = light.intensity;
+        }
+
+        renderer.set_uniform_buffer_float_vector(PBR_LIGHTS_BLOCK, "pbr_light_positions", &positions);
+        renderer.set_uniform_buffer_float_vector(PBR_LIGHTS_BLOCK, "pbr_light_colours", &colours);
+        renderer.set_uniform_buffer_int(PBR_LIGHTS_BLOCK, "pbr_light_count", self.lights.len() as i32);
+        renderer.synchronise_uniform_buffer(PBR_LIGHTS_BLOCK);
+    }
+}
+
+/// Write the sources of the built-in PBR shader to the working directory
+///
+/// The shader pipeline builds from files, so this must be called before the renderer is set
+/// up.  The SPIR-V modules and reflection data are then produced alongside the sources by the
+/// usual shader build.
+pub fn install_pbr_shader_sources() -> Result<(), io::Error> {
+    write_entire_file(PBR_LIBRARY_SOURCE, PBR_LIBRARY_FILENAME)?;
+    write_entire_file(PBR_VERTEX_SOURCE, PBR_VERTEX_FILENAME)?;
+    write_entire_file(PBR_FRAGMENT_SOURCE, PBR_FRAGMENT_FILENAME)?;
+
+    Ok(())
+}
+
+/// Return a uniform block specification laid out by the std140 rules from its member types
+///
+/// uniforms: The typed members of the block, in the order pbr_common.glsl declares them
+fn pbr_block_spec(uniforms: Vec<BlockUniformSpec>) -> UniformBlockSpec {
+    UniformBlockSpec {
+        set: 0,
+        block_type: UniformType::UniformBuffer,
+        uniforms: uniforms,
+        ..Default::default()
+    }
+}
+
+/// Add the uniform blocks and the shader specification of the built-in PBR shader
+///
+/// The shader is a metallic-roughness model with normal mapping and image based lighting
+/// hooks, following the material model of glTF 2.0.  It draws F3F3F3 vertices, interpreted
+/// as position, normal and texture coordinate (the third component of which is ignored).
+/// Contact shadows are marched through the texture named by CONTACT_SHADOW_DEPTH_TEXTURE, for
+/// the lights selected by synchronise_contact_shadows.
+///
+/// resource_manager: The resource manager to add the specifications to
+/// pass_name: The pass the shader is used in
+pub fn register_pbr_shaders(resource_manager: &mut ResourceManager, pass_name: &'static str) {
+    resource_manager.uniform_block_specs
+        .insert(PBR_CAMERA_BLOCK,
+                pbr_block_spec(vec![BlockUniformSpec::typed("pbr_model", BlockMemberType::Mat4),
+                                    BlockUniformSpec::typed("pbr_view", BlockMemberType::Mat4),
+                                    BlockUniformSpec::typed("pbr_projection", BlockMemberType::Mat4),
+                                    BlockUniformSpec::typed("pbr_camera_position", BlockMemberType::Vec4)]));
+    resource_manager.uniform_block_specs
+        .insert(PBR_LIGHTS_BLOCK,
+                pbr_block_spec(vec![BlockUniformSpec::typed_array("pbr_light_positions", BlockMemberType::Vec4, PBR_MAX_LIGHTS),
+                                    BlockUniformSpec::typed_array("pbr_light_colours", BlockMemberType::Vec4, PBR_MAX_LIGHTS),
+                                    BlockUniformSpec::typed("pbr_light_count", BlockMemberType::Int)]));
+    resource_manager.uniform_block_specs
+        .insert(PBR_MATERIAL_BLOCK,
+                pbr_block_spec(vec![BlockUniformSpec::typed("pbr_base_colour_factor", BlockMemberType::Vec4),
+                                    BlockUniformSpec::typed("pbr_emissive_factor", BlockMemberType::Vec4),
+                                    BlockUniformSpec::typed("pbr_metallic_factor", BlockMemberType::Float),
+                                    BlockUniformSpec::typed("pbr_roughness_factor", BlockMemberType::Float),
+                                    BlockUniformSpec::typed("pbr_normal_scale", BlockMemberType::Float),
+                                    BlockUniformSpec::typed("pbr_occlusion_strength", BlockMemberType::Float),
+                                    BlockUniformSpec::typed("pbr_ibl_intensity", BlockMemberType::Float)]));
+    register_contact_shadow_block(resource_manager);
+
+    let shader_spec = ShaderSpec {
+        name: PBR_SHADER_NAME,
+        library_files: vec![PBR_LIBRARY_FILENAME],
+        shader_files: vec![ShaderFilesSpecification {
+                               filename: PBR_VERTEX_FILENAME,
+                               shader_stage: ShaderStage::VertexShader,
+                               spirv_out: "pbr.vert.spv",
+                               reflect_out: "pbr.vert.rfl",
+                               entry_point: DEFAULT_ENTRY_POINT,
+                           },
+                           ShaderFilesSpecification {
+                               filename: PBR_FRAGMENT_FILENAME,
+                               shader_stage: ShaderStage::FragmentShader,
+                               spirv_out: "pbr.frag.spv",
+                               reflect_out: "pbr.frag.rfl",
+                               entry_point: DEFAULT_ENTRY_POINT,
+                           }],
+        fallback_shader_files: vec![],
+        uniform_block_names: vec![PBR_CAMERA_BLOCK, PBR_LIGHTS_BLOCK, PBR_MATERIAL_BLOCK, CONTACT_SHADOW_BLOCK],
+        uniform_specs: PBR_TEXTURE_NAMES.iter()
+            .chain(Some(&CONTACT_SHADOW_DEPTH_TEXTURE))
+            .enumerate()
+            .map(|(binding, name)| {
+                UniformSpec {
+                    name: *name,
+                    set: 1,
+                    binding: binding as u32,
+                    uniform_type: UniformType::CombinedImageSampler,
+                }
+            })
+            .collect(),
+        push_constants: vec![],
+        vertex_array_type: VertexArrayType::F3F3F3,
+        primitive: PrimitiveType::PrimitiveTriangles,
+        instance_layout: None,
+        attributes: vec!["position", "normal", "texcoord"],
+        fragment_out: PBR_FRAGMENT_OUT,
+        extra_fragment_outs: vec![],
+        render_state: RenderState::opaque(),
+        stencil: None,
+        bindless_textures: false,
+        pass_name: pass_name,
+    };
+    resource_manager.shader_specs.insert(PBR_SHADER_NAME, shader_spec);
+}
@@ -0,0 +1,487 @@
+// Copyright (c) 2016-2017 Bruce Stenning. All rights reserved.
+//
+// Redistribution and use in source and binary forms, with or without
+// modification, are permitted provided that the following conditions
+// are met:
+//
+// 1. Redistributions of source code must retain the above copyright
+//    notice, this list of conditions and the following disclaimer.
+//
+// 2. Redistributions in binary form must reproduce the above copyright
+//    notice, this list of conditions and the following disclaimer in the
+//    documentation and/or other materials provided with the distribution.
+//
+// 3. Neither the name of the copyright holder nor the names of its
+//    contributors may be used to endorse or promote products derived
+//    from this software without specific prior written permission.
+//
+// THIS SOFTWARE IS PROVIDED BY THE COPYRIGHT HOLDERS AND CONTRIBUTORS
+// AS IS" AND ANY EXPRESS OR IMPLIED WARRANTIES, INCLUDING, BUT NOT
+// LIMITED TO, THE IMPLIED WARRANTIES OF MERCHANTABILITY AND FITNESS
+// FOR A PARTICULAR PURPOSE ARE DISCLAIMED. IN NO EVENT SHALL THE
+// COPYRIGHT HOLDER OR CONTRIBUTORS BE LIABLE FOR ANY DIRECT, INDIRECT,
+// INCIDENTAL, SPECIAL, EXEMPLARY, OR CONSEQUENTIAL DAMAGES (INCLUDING,
+// BUT NOT LIMITED TO, PROCUREMENT OF SUBSTITUTE GOODS OR SERVICES; LOSS
+// OF USE, DATA, OR PROFITS; OR BUSINESS INTERRUPTION) HOWEVER CAUSED
+// AND ON ANY THEORY OF LIABILITY, WHETHER IN CONTRACT, STRICT LIABILITY,
+// OR TORT (INCLUDING NEGLIGENCE OR OTHERWISE) ARISING IN ANY WAY OUT OF
+// THE USE OF THIS SOFTWARE, EVEN IF ADVISED OF THE POSSIBILITY OF SUCH
+// DAMAGE.
+
+use std::fmt;
+use std::io;
+
+use glfw;
+
+use algebra::matrix::Mat4;
+use algebra::vector::Vec3;
+use graphics::renderer::*;
+use graphics::resources::*;
+use graphics::shader::*;
+use graphics::uniformlayout::BlockMemberType;
+use misc::fileutils::*;
+use misc::timer::Timer;
+
+/// The ways of getting small amounts of per-draw data, such as transforms, to the shaders
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub enum PerDrawStrategy {
+    /// Push constants, or plain uniforms under OpenGL, recorded with each draw
+    PushConstants,
+    /// An array in a uniform buffer holding the data of a batch of draws, each selecting its
+    /// element by a push constant index
+    UniformBuffer,
+    /// An array in a storage buffer holding the data of every draw, each selecting its element
+    /// by a push constant index
+    StorageBuffer,
+}
+
+/// Every strategy, in the order they are benchmarked
+pub const PER_DRAW_STRATEGIES: [PerDrawStrategy; 3] = [PerDrawStrategy::PushConstants,
+                                                       PerDrawStrategy::UniformBuffer,
+                                                       PerDrawStrategy::StorageBuffer];
+
+/// The sizes of per-draw data benchmarked, in bytes, each a whole number of matrices
+pub const PER_DRAW_BENCHMARK_SIZES: [usize; 3] = [64, 128, 256];
+
+/// The push constant space every Vulkan implementation provides, in bytes
+pub const PER_DRAW_PUSH_CONSTANT_LIMIT: usize = 128;
+
+/// The number of draws timed for each strategy and size
+pub const DEFAULT_PER_DRAW_BENCHMARK_DRAWS: usize = 2000;
+
+/// The matrices the uniform buffer strategy's array holds, as per_draw_uniform_buffer.vert declares, filling
+/// the 16 KiB every Vulkan implementation allows a uniform buffer binding to cover
+pub const PER_DRAW_UNIFORM_ARRAY_LENGTH: usize = 256;
+
+/// The matrices the storage buffer strategy's array holds, as per_draw_storage_buffer.vert declares, enough
+/// for the default benchmark at the largest size
+pub const PER_DRAW_STORAGE_ARRAY_LENGTH: usize = DEFAULT_PER_DRAW_BENCHMARK_DRAWS * 4;
+
+/// The name of the uniform block the uniform buffer strategy writes
+pub const PER_DRAW_UNIFORM_BLOCK: &'static str = "per_draw_uniforms";
+
+/// The name of the storage block the storage buffer strategy writes
+pub const PER_DRAW_STORAGE_BLOCK: &'static str = "per_draw_storage";
+
+const PER_DRAW_PUSH_MATRICES: [&'static str; 2] = ["per_draw_push_matrix0", "per_draw_push_matrix1"];
+const PER_DRAW_UNIFORM_MATRICES: &'static str = "per_draw_uniform_matrices";
+const PER_DRAW_STORAGE_MATRICES: &'static str = "per_draw_storage_matrices";
+const PER_DRAW_INDEX: &'static str = "per_draw_index";
+const PER_DRAW_MATRIX_COUNT: &'static str = "per_draw_matrix_count";
+
+const PER_DRAW_PUSH_CONSTANTS_VERTEX_FILENAME: &'static str = "per_draw_push_constants.vert";
+const PER_DRAW_UNIFORM_BUFFER_VERTEX_FILENAME: &'static str = "per_draw_uniform_buffer.vert";
+const PER_DRAW_STORAGE_BUFFER_VERTEX_FILENAME: &'static str = "per_draw_storage_buffer.vert";
+const PER_DRAW_FRAGMENT_FILENAME: &'static str = "per_draw.frag";
+
+const PER_DRAW_PUSH_CONSTANTS_VERTEX_SOURCE: &'static str = include_str!("shaders/per_draw_push_constants.vert");
+const PER_DRAW_UNIFORM_BUFFER_VERTEX_SOURCE: &'static str = include_str!("shaders/per_draw_uniform_buffer.vert");
+const PER_DRAW_STORAGE_BUFFER_VERTEX_SOURCE: &'static str = include_str!("shaders/per_draw_storage_buffer.vert");
+const PER_DRAW_FRAGMENT_SOURCE: &'static str = include_str!("shaders/per_draw.frag");
+
+impl PerDrawStrategy {
+    /// Return the name of the benchmark shader using the strategy
+    pub fn shader_name(&self) -> &'static str {
+        match *self {
+            PerDrawStrategy::PushConstants => "per_draw_push_constants",
+            PerDrawStrategy::UniformBuffer => "per_draw_uniform_buffer",
+            PerDrawStrategy::StorageBuffer => "per_draw_storage_buffer",
+        }
+    }
+
+    /// Return whether the strategy can carry the amount of per-draw data specified
+    ///
+    /// size: The size of the per-draw data in bytes
+    pub fn supports_size(&self, size: usize) -> bool {
+        match *self {
+            PerDrawStrategy::PushConstants => size <= PER_DRAW_PUSH_CONSTANT_LIMIT,
+            _ => self.draws_per_buffer(size).map_or(false, |draws| draws > 0),
+        }
+    }
+
+    /// Return the draws whose data the strategy's buffer holds at once, or None for push
+    /// constants, which are recorded with each draw
+    ///
+    /// The buffer is refilled between passes for each batch of this many draws.
+    ///
+    /// size: The size of the per-draw data in bytes
+    pub fn draws_per_buffer(&self, size: usize) -> Option<usize> {
+        let matrices = per_draw_matrices(size);
+        match *self {
+            PerDrawStrategy::PushConstants => None,
+            PerDrawStrategy::UniformBuffer => Some(PER_DRAW_UNIFORM_ARRAY_LENGTH / matrices),
+            PerDrawStrategy::StorageBuffer => Some(PER_DRAW_STORAGE_ARRAY_LENGTH / matrices),
+        }
+    }
+}
+
+/// Return the matrices holding per-draw data of a size
+///
+/// size: The size of the per-draw data in bytes
+fn per_draw_matrices(size: usize) -> usize {
+    ((size + 63) / 64).max(1)
+}
+
+impl fmt::Display for PerDrawStrategy {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        let name = match *self {
+            PerDrawStrategy::PushConstants => "push constants",
+            PerDrawStrategy::UniformBuffer => "uniform buffer",
+            PerDrawStrategy::StorageBuffer => "storage buffer",
+        };
+        write!(f, "{}", name)
+    }
+}
+
+/// The measured cost of each strategy at each size
+#[derive(Clone, Debug, Default, PartialEq)]
+pub struct PerDrawBenchmarkResults {
+    /// The strategy, the size of the per-draw data in bytes and the time per draw in microseconds
+    pub timings: Vec<(PerDrawStrategy, usize, f32)>,
+}
+
+impl PerDrawBenchmarkResults {
+    /// Return the fastest strategy measured for the smallest benchmarked size holding the data
+    ///
+    /// size: The size of the per-draw data in bytes
+    pub fn fastest(&self, size: usize) -> Option<PerDrawStrategy> {
+        let bucket = match PER_DRAW_BENCHMARK_SIZES.iter().find(|s| **s >= size) {
+            Some(s) => *s,
+            None => PER_DRAW_BENCHMARK_SIZES[PER_DRAW_BENCHMARK_SIZES.len() - 1],
+        };
+
+        let mut best: Option<(PerDrawStrategy, f32)> = None;
+        for &(strategy, timing_size, micros) in self.timings.iter() {
+            if timing_size != bucket || !strategy.supports_size(size) {
+                continue;
+            }
+            match best {
+                Some((_, best_micros)) if best_micros <= micros => {}
+                _ => best = Some((strategy, micros)),
+            }
+        }
+        best.map(|b| b.0)
+    }
+}
+
+impl fmt::Display for PerDrawBenchmarkResults {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        writeln!(f, "Per-draw data strategies (microseconds per draw):")?;
+        for &(strategy, size, micros) in self.timings.iter() {
+            writeln!(f, "  {:>4} bytes, {}: {:.3}", size, strategy, micros)?;
+        }
+        Ok(())
+    }
+}
+
+/// Chooses how to pass per-draw data, from the configuration, a benchmark or a default
+///
+/// An override in RendererConfig takes precedence wherever the strategy can carry the data.
+/// Otherwise the fastest strategy from run_benchmark is used, and without a benchmark, push
+/// constants are used where they fit and a uniform buffer where they do not.
+pub struct PerDrawStrategySelector {
+    override_strategy: Option<PerDrawStrategy>,
+    results: Option<PerDrawBenchmarkResults>,
+}
+
+impl PerDrawStrategySelector {
+    /// Create a selector honouring the configured override
+    ///
+    /// config: The renderer configuration
+    pub fn new(config: &RendererConfig) -> PerDrawStrategySelector {
+        PerDrawStrategySelector {
+            override_strategy: config.per_draw_strategy,
+            results: None,
+        }
+    }
+
+    /// Use the results of an earlier benchmark, for example one run offline
+    ///
+    /// Pass them to Renderer::record_per_draw_benchmark as well for the parity report to show them.
+    ///
+    /// results: The benchmark results
+    pub fn set_results(&mut self, results: PerDrawBenchmarkResults) {
+        self.results = Some(results);
+    }
+
+    /// Return the benchmark results, if a benchmark has been run
+    pub fn get_results(&self) -> Option<&PerDrawBenchmarkResults> {
+        self.results.as_ref()
+    }
+
+    /// Choose the strategy for per-draw data of the size specified
+    ///
+    /// size: The size of the per-draw data in bytes
+    pub fn choose(&self, size: usize) -> PerDrawStrategy {
+        if let Some(strategy) = self.override_strategy {
+            if strategy.supports_size(size) {
+                return strategy;
+            }
+        }
+
+        if let Some(strategy) = self.results.as_ref().and_then(|r| r.fastest(size)) {
+            return strategy;
+        }
+
+        if PerDrawStrategy::PushConstants.supports_size(size) {
+            PerDrawStrategy::PushConstants
+        } else {
+            PerDrawStrategy::UniformBuffer
+        }
+    }
+
+    /// Time each strategy at each size on the current device and keep the results
+    ///
+    /// Each measurement is a frame of single-triangle draws, each given its own data through the
+    /// strategy's shader, after a frame of warm-up.  The buffer strategies fill their arrays
+    /// before the pass drawing from them, in as many passes as the draws need, and push only the
+    /// index of each draw.  The time runs from the start of the frame until the flip returns, so
+    /// it covers the uploads, recording and submission; as the flip does not wait for the GPU,
+    /// this favours strategies that are cheap for the CPU.  The results are recorded in the
+    /// renderer's parity report.  The benchmark shaders must have been registered with
+    /// register_per_draw_benchmark_shaders and initialised with the others.
+    ///
+    /// renderer: The renderer to benchmark
+    /// window: The window to flip
+    /// draws: The number of draws timed for each strategy and size
+    pub fn run_benchmark(&mut self, renderer: &mut Box<Renderer>, window: &mut glfw::Context, draws: usize) {
+        let mut results = PerDrawBenchmarkResults::default();
+
+        for size in PER_DRAW_BENCHMARK_SIZES.iter() {
+            for strategy in PER_DRAW_STRATEGIES.iter().filter(|s| s.supports_size(*size)) {
+                let _ = PerDrawStrategySelector::benchmark_frame(renderer, window, *strategy, *size, draws);
+                let seconds = PerDrawStrategySelector::benchmark_frame(renderer, window, *strategy, *size, draws);
+                results.timings.push((*strategy, *size, seconds * 1.0e6f32 / draws.max(1) as f32));
+            }
+        }
+
+        renderer.record_per_draw_benchmark(&results);
+        self.results = Some(results);
+    }
+
+    /// Render and time a frame of draws using one strategy
+    fn benchmark_frame(renderer: &mut Box<Renderer>,
+                       window: &mut glfw::Context,
+                       strategy: PerDrawStrategy,
+                       size: usize,
+                       draws: usize)
+                       -> f32 {
+        let matrices = per_draw_matrices(size);
+        let vertices = [Vec3 { x: 0.0f32, y: 0.0f32, z: 0.0f32 },
+                        Vec3 { x: 0.0f32, y: 0.0f32, z: 0.0f32 },
+                        Vec3 { x: 0.0f32, y: 0.0f32, z: 0.0f32 }];
+
+        // Every draw has data of its own, so that none can reuse the last draw's
+        let data: Vec<Mat4<f32>> = (0..draws * matrices)
+            .map(|i| Mat4::translate(0.0f32, 0.0f32, i as f32 * 1.0e-6f32))
+            .collect();
+
+        let timer = Timer::new();
+        renderer.begin_frame();
+
+        let threaddata_arc = renderer.get_threaddata(0);
+        match strategy.draws_per_buffer(size) {
+            None => {
+                renderer.begin_pass(strategy.shader_name());
+                let mut threaddata = threaddata_arc.lock().unwrap();
+                threaddata.vertex_array_type = VertexArrayType::F3;
+
+                for draw in data.chunks(matrices) {
+                    for (name, matrix) in PER_DRAW_PUSH_MATRICES.iter().zip(draw.iter()) {
+                        renderer.set_push_constant_matrix(0, name, matrix);
+                    }
+
+                    threaddata.add_triangle_st_n3(&vertices[0], &vertices[1], &vertices[2]);
+                    threaddata.check_flush_st(true, &mut **renderer);
+                }
+                drop(threaddata);
+                renderer.end_pass();
+            }
+            Some(batch_draws) => {
+                let (block_name, array_name) = if strategy == PerDrawStrategy::UniformBuffer {
+                    (PER_DRAW_UNIFORM_BLOCK, PER_DRAW_UNIFORM_MATRICES)
+                } else {
+                    (PER_DRAW_STORAGE_BLOCK, PER_DRAW_STORAGE_MATRICES)
+                };
+
+                for batch in data.chunks(batch_draws * matrices) {
+                    renderer.set_uniform_buffer_matrix_array(block_name, array_name, batch);
+                    renderer.synchronise_uniform_buffer(block_name);
+
+                    renderer.begin_pass(strategy.shader_name());
+                    renderer.set_push_constant_int(0, PER_DRAW_MATRIX_COUNT, matrices as i32);
+                    let mut threaddata = threaddata_arc.lock().unwrap();
+                    threaddata.vertex_array_type = VertexArrayType::F3;
+
+                    for draw in 0..batch.len() / matrices {
+                        renderer.set_push_constant_int(0, PER_DRAW_INDEX, (draw * matrices) as i32);
+
+                        threaddata.add_triangle_st_n3(&vertices[0], &vertices[1], &vertices[2]);
+                        threaddata.check_flush_st(true, &mut **renderer);
+                    }
+                    drop(threaddata);
+                    renderer.end_pass();
+                }
+            }
+        }
+
+        renderer.end_frame();
+        renderer.flip(window);
+
+        timer.read_sec()
+    }
+}
+
+/// Write the sources of the per-draw benchmark shaders to the working directory
+///
+/// The vertex shader of every benchmark mode is built during setup, even when only one mode is
+/// measured, so this must be called before the renderer is set up.
+pub fn install_per_draw_benchmark_shader_sources() -> Result<(), io::Error> {
+    write_entire_file(PER_DRAW_PUSH_CONSTANTS_VERTEX_SOURCE,
+                      PER_DRAW_PUSH_CONSTANTS_VERTEX_FILENAME)?;
+    write_entire_file(PER_DRAW_UNIFORM_BUFFER_VERTEX_SOURCE,
+                      PER_DRAW_UNIFORM_BUFFER_VERTEX_FILENAME)?;
+    write_entire_file(PER_DRAW_STORAGE_BUFFER_VERTEX_SOURCE,
+                      PER_DRAW_STORAGE_BUFFER_VERTEX_FILENAME)?;
+    write_entire_file(PER_DRAW_FRAGMENT_SOURCE, PER_DRAW_FRAGMENT_FILENAME)?;
+
+    Ok(())
+}
+
+/// Return the specification of a block holding an array of matrices
+///
+/// block_type: Whether the block is a uniform or storage buffer
+/// name: The name of the array member
+/// array_length: The matrices the array holds
+fn per_draw_block_spec(block_type: UniformType, name: &'static str, array_length: usize) -> UniformBlockSpec {
+    UniformBlockSpec {
+        set: 0,
+        block_type: block_type,
+        uniforms: vec![BlockUniformSpec::typed_array(name, BlockMemberType::Mat4, array_length)],
+        ..Default::default()
+    }
+}
+
+/// Return the push constants selecting a draw's matrices in the buffer strategies' arrays
+fn per_draw_index_push_constants() -> Vec<PushConstantSpec> {
+    vec![PushConstantSpec {
+             name: PER_DRAW_INDEX,
+             offset: 0,
+             size: 4,
+         },
+         PushConstantSpec {
+             name: PER_DRAW_MATRIX_COUNT,
+             offset: 4,
+             size: 4,
+         }]
+}
+
+/// Add the uniform blocks and shader specifications of the per-draw benchmark shaders
+///
+/// There is one F3 shader per strategy, each transforming its positions by matrices taken from
+/// the strategy's source of per-draw data.  The buffer strategies' shaders take the index of the
+/// draw's first matrix and the number of matrices per draw as push constants.
+///
+/// resource_manager: The resource manager to add the specifications to
+/// pass_name: The pass the benchmark shaders are used in
+pub fn register_per_draw_benchmark_shaders(resource_manager: &mut ResourceManager, pass_name: &'static str) {
+    resource_manager.uniform_block_specs
+        .insert(PER_DRAW_UNIFORM_BLOCK,
+                per_draw_block_spec(UniformType::UniformBuffer,
+                                    PER_DRAW_UNIFORM_MATRICES,
+                                    PER_DRAW_UNIFORM_ARRAY_LENGTH));
+    resource_manager.uniform_block_specs
+        .insert(PER_DRAW_STORAGE_BLOCK,
+                per_draw_block_spec(UniformType::StorageBuffer,
+                                    PER_DRAW_STORAGE_MATRICES,
+                                    PER_DRAW_STORAGE_ARRAY_LENGTH));
+
+    for strategy in PER_DRAW_STRATEGIES.iter() {
+        let (vertex, vertex_spirv, vertex_reflect, uniform_block_names, push_constants) = match *strategy {
+            PerDrawStrategy::PushConstants => {
+                (PER_DRAW_PUSH_CONSTANTS_VERTEX_FILENAME,
+                 "per_draw_push_constants.vert.spv",
+                 "per_draw_push_constants.vert.rfl",
+                 vec![],
+                 vec![PushConstantSpec {
+                          name: PER_DRAW_PUSH_MATRICES[0],
+                          offset: 0,
+                          size: 64,
+                      },
+                      PushConstantSpec {
+                          name: PER_DRAW_PUSH_MATRICES[1],
+                          offset: 64,
+                          size: 64,
+                      }])
+            }
+            PerDrawStrategy::UniformBuffer => {
+                (PER_DRAW_UNIFORM_BUFFER_VERTEX_FILENAME,
+                 "per_draw_uniform_buffer.vert.spv",
+                 "per_draw_uniform_buffer.vert.rfl",
+                 vec![PER_DRAW_UNIFORM_BLOCK],
+                 per_draw_index_push_constants())
+            }
+            PerDrawStrategy::StorageBuffer => {
+                (PER_DRAW_STORAGE_BUFFER_VERTEX_FILENAME,
+                 "per_draw_storage_buffer.vert.spv",
+                 "per_draw_storage_buffer.vert.rfl",
+                 vec![PER_DRAW_STORAGE_BLOCK],
+                 per_draw_index_push_constants())
+            }
+        };
+
+        let spec = ShaderSpec {
+            name: strategy.shader_name(),
+            library_files: vec![],
+            shader_files: vec![ShaderFilesSpecification {
+                                   filename: vertex,
+                                   shader_stage: ShaderStage::VertexShader,
+                                   spirv_out: vertex_spirv,
+                                   reflect_out: vertex_reflect,
+                                   entry_point: DEFAULT_ENTRY_POINT,
+                               },
+                               ShaderFilesSpecification {
+                                   filename: PER_DRAW_FRAGMENT_FILENAME,
+                                   shader_stage: ShaderStage::FragmentShader,
+                                   spirv_out: "per_draw.frag.spv",
+                                   reflect_out: "per_draw.frag.rfl",
+                                   entry_point: DEFAULT_ENTRY_POINT,
+                               }],
+            fallback_shader_files: vec![],
+            uniform_block_names: uniform_block_names,
+            uniform_specs: vec![],
+            push_constants: push_constants,
+            vertex_array_type: VertexArrayType::F3,
+            primitive: PrimitiveType::PrimitiveTriangles,
+            instance_layout: None,
+            attributes: vec!["position"],
+            fragment_out: "per_draw_colour",
+            extra_fragment_outs: vec![],
+            render_state: RenderState::default(),
+            stencil: None,
+            bindless_textures: false,
+            pass_name: pass_name,
+        };
+        resource_manager.shader_specs.insert(strategy.shader_name(), spec);
+    }
+}
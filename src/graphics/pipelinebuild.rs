@@ -0,0 +1,226 @@
+// Copyright (c) 2016-2017 Bruce Stenning. All rights reserved.
+//
+// Redistribution and use in source and binary forms, with or without
+// modification, are permitted provided that the following conditions
+// are met:
+//
+// 1. Redistributions of source code must retain the above copyright
+//    notice, this list of conditions and the following disclaimer.
+//
+// 2. Redistributions in binary form must reproduce the above copyright
+//    notice, this list of conditions and the following disclaimer in the
+//    documentation and/or other materials provided with the distribution.
+//
+// 3. Neither the name of the copyright holder nor the names of its
+//    contributors may be used to endorse or promote products derived
+//    from this software without specific prior written permission.
+//
+// THIS SOFTWARE IS PROVIDED BY THE COPYRIGHT HOLDERS AND CONTRIBUTORS
+// AS IS" AND ANY EXPRESS OR IMPLIED WARRANTIES, INCLUDING, BUT NOT
+// LIMITED TO, THE IMPLIED WARRANTIES OF MERCHANTABILITY AND FITNESS
+// FOR A PARTICULAR PURPOSE ARE DISCLAIMED. IN NO EVENT SHALL THE
+// COPYRIGHT HOLDER OR CONTRIBUTORS BE LIABLE FOR ANY DIRECT, INDIRECT,
+// INCIDENTAL, SPECIAL, EXEMPLARY, OR CONSEQUENTIAL DAMAGES (INCLUDING,
+// BUT NOT LIMITED TO, PROCUREMENT OF SUBSTITUTE GOODS OR SERVICES; LOSS
+// OF USE, DATA, OR PROFITS; OR BUSINESS INTERRUPTION) HOWEVER CAUSED
+// AND ON ANY THEORY OF LIABILITY, WHETHER IN CONTRACT, STRICT LIABILITY,
+// OR TORT (INCLUDING NEGLIGENCE OR OTHERWISE) ARISING IN ANY WAY OUT OF
+// THE USE OF THIS SOFTWARE, EVEN IF ADVISED OF THE POSSIBILITY OF SUCH
+// DAMAGE.
+
+use std::collections::HashSet;
+
+/// How far the creation of the pipelines has got, as passed to the progress callback
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub struct PipelineBuildProgress {
+    pub shader_name: &'static str, // The shader whose pipeline has just been created
+    pub built: usize, // The pipelines created so far, including this one
+    pub total: usize, // The pipelines being created
+    pub failed: bool, // The pipeline failed, so the error shader stands in for it
+}
+
+impl PipelineBuildProgress {
+    /// Return the fraction of the pipelines that have been created, from 0 to 1
+    pub fn fraction(&self) -> f32 {
+        if self.total == 0 {
+            1.0
+        } else {
+            self.built as f32 / self.total as f32
+        }
+    }
+
+    /// Return whether every pipeline has been created
+    pub fn is_complete(&self) -> bool {
+        self.built >= self.total
+    }
+}
+
+/// A function told of each pipeline as it is created
+pub type PipelineProgressCallback = Box<Fn(&PipelineBuildProgress) + Send>;
+
+/// Which pipelines startup waits for, and who is told of the progress
+///
+/// The pipelines are created on worker threads.  Startup waits for those of the first frame
+/// shaders, and the rest are picked up as they become ready, or waited for when a pass first
+/// begins with them.  With no first frame shaders named, startup waits for all of them.
+pub struct PipelineBuildOptions {
+    first_frame_shaders: Vec<&'static str>,
+    progress_callback: Option<PipelineProgressCallback>,
+}
+
+impl PipelineBuildOptions {
+    /// Create options that wait for every pipeline and report progress to no one
+    pub fn new() -> PipelineBuildOptions {
+        PipelineBuildOptions {
+            first_frame_shaders: vec![],
+            progress_callback: None,
+        }
+    }
+
+    /// Name the shaders the first frame draws with, whose pipelines startup waits for
+    ///
+    /// shader_names: The names of the shaders, which are created first in the order given
+    pub fn set_first_frame_shaders(&mut self, shader_names: &[&'static str]) {
+        self.first_frame_shaders = shader_names.to_vec();
+    }
+
+    /// Return the shaders whose pipelines startup waits for, empty when it waits for all
+    pub fn first_frame_shaders(&self) -> &[&'static str] {
+        &self.first_frame_shaders
+    }
+
+    /// Set the function told of each pipeline as it is created, on the thread that owns the renderer
+    ///
+    /// callback: The function to call
+    pub fn set_progress_callback(&mut self, callback: PipelineProgressCallback) {
+        self.progress_callback = Some(callback);
+    }
+
+    /// Pass the progress to the callback, if there is one
+    ///
+    /// progress: The progress to report
+    pub fn report(&self, progress: &PipelineBuildProgress) {
+        if let Some(ref callback) = self.progress_callback {
+            callback(progress);
+        }
+    }
+
+    /// Order the shaders for creation, returning them with the number at the front that startup waits for
+    ///
+    /// The first frame shaders come first, in the order they were named, and the rest follow in
+    /// name order.  First frame shaders that are not among those given are left out.
+    ///
+    /// shader_names: The names of the shaders that need pipelines
+    pub fn order(&self, shader_names: &[&'static str]) -> (Vec<&'static str>, usize) {
+        let mut ordered: Vec<&'static str> = vec![];
+        for name in self.first_frame_shaders.iter() {
+            if shader_names.contains(name) && !ordered.contains(name) {
+                ordered.push(*name);
+            }
+        }
+
+        let blocking = if self.first_frame_shaders.is_empty() {
+            shader_names.len()
+        } else {
+            ordered.len()
+        };
+
+        let mut rest: Vec<&'static str> = shader_names.iter().cloned().filter(|name| !ordered.contains(name)).collect();
+        rest.sort();
+        ordered.extend(rest);
+
+        (ordered, blocking)
+    }
+}
+
+/// Which of the pipelines still being created the renderer waits for
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub enum PipelineWait {
+    Ready, // None, taking only those already created
+    Shader(&'static str), // That of a shader about to be drawn with
+    FirstFrame, // Those that startup waits for
+    All, // Every one
+}
+
+/// Keeps count of the pipelines that have been created out of those queued
+pub struct PipelineBuildTracker {
+    total: usize,
+    built: usize,
+    pending: HashSet<&'static str>,
+    blocking: HashSet<&'static str>, // Pending pipelines that startup waits for
+}
+
+impl PipelineBuildTracker {
+    /// Start tracking the pipelines of the shaders as ordered by PipelineBuildOptions::order
+    ///
+    /// ordered: The shaders in the order their pipelines are created
+    /// blocking: The number of shaders at the front whose pipelines startup waits for
+    pub fn new(ordered: &[&'static str], blocking: usize) -> PipelineBuildTracker {
+        PipelineBuildTracker {
+            total: ordered.len(),
+            built: 0,
+            pending: ordered.iter().cloned().collect(),
+            blocking: ordered.iter().take(blocking).cloned().collect(),
+        }
+    }
+
+    /// Record that a pipeline has been created, returning the progress to report
+    ///
+    /// None is returned for a shader that was not pending.
+    ///
+    /// shader_name: The name of the shader
+    /// failed: Whether the pipeline failed to be created
+    pub fn complete(&mut self, shader_name: &'static str, failed: bool) -> Option<PipelineBuildProgress> {
+        if !self.pending.remove(shader_name) {
+            return None;
+        }
+        self.blocking.remove(shader_name);
+        self.built += 1;
+
+        Some(PipelineBuildProgress {
+            shader_name: shader_name,
+            built: self.built,
+            total: self.total,
+            failed: failed,
+        })
+    }
+
+    /// Return whether the shader's pipeline is still being created
+    ///
+    /// shader_name: The name of the shader
+    pub fn is_pending(&self, shader_name: &str) -> bool {
+        self.pending.contains(shader_name)
+    }
+
+    /// Return whether some pipeline that startup waits for is still being created
+    pub fn is_blocking(&self) -> bool {
+        !self.blocking.is_empty()
+    }
+
+    /// Return whether every pipeline has been created
+    pub fn is_finished(&self) -> bool {
+        self.pending.is_empty()
+    }
+
+    /// Return whether a pipeline the wait calls for is still being created
+    ///
+    /// wait: Which pipelines are waited for
+    pub fn must_wait(&self, wait: PipelineWait) -> bool {
+        match wait {
+            PipelineWait::Ready => false,
+            PipelineWait::Shader(shader_name) => self.is_pending(shader_name),
+            PipelineWait::FirstFrame => self.is_blocking(),
+            PipelineWait::All => !self.is_finished(),
+        }
+    }
+}
+
+/// Return the number of worker threads to create the pipelines on
+///
+/// configured: The number from the configuration, 0 for as many as there are rendering threads
+/// render_threads: The number of rendering threads
+/// pipelines: The number of pipelines to create
+pub fn pipeline_build_threads(configured: usize, render_threads: usize, pipelines: usize) -> usize {
+    let threads = if configured == 0 { render_threads } else { configured };
+    threads.max(1).min(pipelines.max(1))
+}
@@ -0,0 +1,397 @@
+// Copyright (c) 2016-2017 Bruce Stenning. All rights reserved.
+//
+// Redistribution and use in source and binary forms, with or without
+// modification, are permitted provided that the following conditions
+// are met:
+//
+// 1. Redistributions of source code must retain the above copyright
+//    notice, this list of conditions and the following disclaimer.
+//
+// 2. Redistributions in binary form must reproduce the above copyright
+//    notice, this list of conditions and the following disclaimer in the
+//    documentation and/or other materials provided with the distribution.
+//
+// 3. Neither the name of the copyright holder nor the names of its
+//    contributors may be used to endorse or promote products derived
+//    from this software without specific prior written permission.
+//
+// THIS SOFTWARE IS PROVIDED BY THE COPYRIGHT HOLDERS AND CONTRIBUTORS
+// AS IS" AND ANY EXPRESS OR IMPLIED WARRANTIES, INCLUDING, BUT NOT
+// LIMITED TO, THE IMPLIED WARRANTIES OF MERCHANTABILITY AND FITNESS
+// FOR A PARTICULAR PURPOSE ARE DISCLAIMED. IN NO EVENT SHALL THE
+// COPYRIGHT HOLDER OR CONTRIBUTORS BE LIABLE FOR ANY DIRECT, INDIRECT,
+// INCIDENTAL, SPECIAL, EXEMPLARY, OR CONSEQUENTIAL DAMAGES (INCLUDING,
+// BUT NOT LIMITED TO, PROCUREMENT OF SUBSTITUTE GOODS OR SERVICES; LOSS
+// OF USE, DATA, OR PROFITS; OR BUSINESS INTERRUPTION) HOWEVER CAUSED
+// AND ON ANY THEORY OF LIABILITY, WHETHER IN CONTRACT, STRICT LIABILITY,
+// OR TORT (INCLUDING NEGLIGENCE OR OTHERWISE) ARISING IN ANY WAY OUT OF
+// THE USE OF THIS SOFTWARE, EVEN IF ADVISED OF THE POSSIBILITY OF SUCH
+// DAMAGE.
+
+use std::fmt;
+use std::io;
+
+use graphics::fullscreen::draw_fullscreen_quad;
+use graphics::renderer::*;
+use graphics::rendertarget::*;
+use graphics::resources::*;
+use graphics::shader::*;
+use misc::fileutils::*;
+
+/// The name the bloom effect and its shader are registered under
+pub const BLOOM_EFFECT_NAME: &'static str = "bloom";
+
+/// The name the FXAA effect and its shader are registered under
+pub const FXAA_EFFECT_NAME: &'static str = "fxaa";
+
+/// The name the vignette effect and its shader are registered under
+pub const VIGNETTE_EFFECT_NAME: &'static str = "vignette";
+
+/// The name of the texture each post effect samples the output of the one before from
+pub const POST_EFFECT_SOURCE_TEXTURE: &'static str = "post_effect_source_texture";
+
+/// The name of the push constant holding the strength of a post effect
+pub const POST_EFFECT_STRENGTH: &'static str = "post_effect_strength";
+
+/// The vertex shader shared by the post effects, which passes on the texture coordinates
+pub const POST_EFFECT_VERTEX_FILENAME: &'static str = "post_effect.vert";
+
+const BLOOM_FRAGMENT_FILENAME: &'static str = "bloom.frag";
+const FXAA_FRAGMENT_FILENAME: &'static str = "fxaa.frag";
+const VIGNETTE_FRAGMENT_FILENAME: &'static str = "vignette.frag";
+
+const POST_EFFECT_VERTEX_SOURCE: &'static str = include_str!("shaders/post_effect.vert");
+const BLOOM_FRAGMENT_SOURCE: &'static str = include_str!("shaders/bloom.frag");
+const FXAA_FRAGMENT_SOURCE: &'static str = include_str!("shaders/fxaa.frag");
+const VIGNETTE_FRAGMENT_SOURCE: &'static str = include_str!("shaders/vignette.frag");
+
+/// An effect of the post-processing chain, drawn with a shader of its own
+#[derive(Clone, Debug, PartialEq)]
+pub struct PostEffect {
+    pub name: &'static str,
+    pub shader_name: &'static str,
+    pub enabled: bool,
+    pub strength: f32, // Passed to the shader as the POST_EFFECT_STRENGTH push constant
+}
+
+impl PostEffect {
+    /// Create an enabled effect at full strength
+    ///
+    /// name: The name the effect is referred to by in the chain
+    /// shader_name: The shader the effect draws with, see register_post_effect_shader
+    pub fn new(name: &'static str, shader_name: &'static str) -> PostEffect {
+        PostEffect {
+            name: name,
+            shader_name: shader_name,
+            enabled: true,
+            strength: 1.0f32,
+        }
+    }
+}
+
+/// Where a step of the chain samples from
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum PostEffectSource {
+    /// The scene as rendered, for the first enabled effect
+    Scene,
+    /// One of the two ping-pong targets, written by the step before
+    Target(usize),
+}
+
+/// An enabled effect with the targets it reads and writes this frame
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub struct PostEffectStep {
+    pub effect: usize, // The index of the effect in the chain
+    pub source: PostEffectSource,
+    pub target: usize, // The ping-pong target the effect draws into, 0 or 1
+}
+
+/// The reasons a chain cannot be changed as asked
+#[derive(Clone, Debug, PartialEq)]
+pub enum PostEffectError {
+    /// There is already an effect of the name in the chain
+    DuplicateEffect(&'static str),
+    /// There is no effect of the name in the chain
+    UnknownEffect(String),
+}
+
+impl fmt::Display for PostEffectError {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match *self {
+            PostEffectError::DuplicateEffect(name) => write!(f, "The post effect chain already has an effect named {}", name),
+            PostEffectError::UnknownEffect(ref name) => write!(f, "The post effect chain has no effect named {}", name),
+        }
+    }
+}
+
+/// An ordered list of post effects, drawn one after another by ping-ponging between two render
+/// targets
+///
+/// Disabled effects are skipped without changing the order, so they can be toggled at runtime
+/// at no cost.  The result of the chain is left in one of the two targets, for a final pass
+/// such as tone-mapping to draw to the swapchain.
+pub struct PostEffectChain {
+    effects: Vec<PostEffect>,
+}
+
+impl PostEffectChain {
+    pub fn new() -> PostEffectChain {
+        PostEffectChain { effects: vec![] }
+    }
+
+    /// Create a chain of the built-in effects, in the order bloom, FXAA and vignette
+    ///
+    /// Their shaders are added by register_builtin_post_effect_shaders.
+    pub fn builtin() -> PostEffectChain {
+        PostEffectChain {
+            effects: vec![PostEffect::new(BLOOM_EFFECT_NAME, BLOOM_EFFECT_NAME),
+                          PostEffect::new(FXAA_EFFECT_NAME, FXAA_EFFECT_NAME),
+                          PostEffect::new(VIGNETTE_EFFECT_NAME, VIGNETTE_EFFECT_NAME)],
+        }
+    }
+
+    /// Return the effects in the order they are drawn
+    pub fn effects(&self) -> &[PostEffect] {
+        &self.effects
+    }
+
+    /// Return the effect of a name
+    ///
+    /// name: The name of the effect
+    pub fn find(&self, name: &str) -> Option<&PostEffect> {
+        self.effects.iter().find(|e| e.name == name)
+    }
+
+    /// Return the position of an effect in the chain
+    ///
+    /// name: The name of the effect
+    pub fn position(&self, name: &str) -> Option<usize> {
+        self.effects.iter().position(|e| e.name == name)
+    }
+
+    /// Add an effect to the end of the chain
+    ///
+    /// effect: The effect to add, whose name must not already be in the chain
+    pub fn push(&mut self, effect: PostEffect) -> Result<(), PostEffectError> {
+        if self.find(effect.name).is_some() {
+            return Err(PostEffectError::DuplicateEffect(effect.name));
+        }
+        self.effects.push(effect);
+        Ok(())
+    }
+
+    /// Remove an effect from the chain, returning it
+    ///
+    /// name: The name of the effect
+    pub fn remove(&mut self, name: &str) -> Result<PostEffect, PostEffectError> {
+        let index = self.index_of(name)?;
+        Ok(self.effects.remove(index))
+    }
+
+    /// Enable or disable an effect
+    ///
+    /// name: The name of the effect
+    /// enabled: Whether the effect should be drawn
+    pub fn set_enabled(&mut self, name: &str, enabled: bool) -> Result<(), PostEffectError> {
+        let index = self.index_of(name)?;
+        self.effects[index].enabled = enabled;
+        Ok(())
+    }
+
+    /// Set the strength of an effect, which the shader interprets
+    ///
+    /// name: The name of the effect
+    /// strength: The strength, where 1 is the effect's default
+    pub fn set_strength(&mut self, name: &str, strength: f32) -> Result<(), PostEffectError> {
+        let index = self.index_of(name)?;
+        self.effects[index].strength = strength;
+        Ok(())
+    }
+
+    /// Move an effect to a new position in the chain, shifting those in between
+    ///
+    /// name: The name of the effect
+    /// position: The new position, which is clamped to the end of the chain
+    pub fn move_to(&mut self, name: &str, position: usize) -> Result<(), PostEffectError> {
+        let index = self.index_of(name)?;
+        let effect = self.effects.remove(index);
+        let position = position.min(self.effects.len());
+        self.effects.insert(position, effect);
+        Ok(())
+    }
+
+    /// Return the steps of the enabled effects, alternating between the two targets
+    pub fn plan(&self) -> Vec<PostEffectStep> {
+        let mut steps: Vec<PostEffectStep> = vec![];
+        let mut source = PostEffectSource::Scene;
+        for (index, effect) in self.effects.iter().enumerate() {
+            if !effect.enabled {
+                continue;
+            }
+
+            let target = match source {
+                PostEffectSource::Target(t) => 1 - t,
+                PostEffectSource::Scene => 0,
+            };
+            steps.push(PostEffectStep {
+                effect: index,
+                source: source,
+                target: target,
+            });
+            source = PostEffectSource::Target(target);
+        }
+
+        steps
+    }
+
+    fn index_of(&self, name: &str) -> Result<usize, PostEffectError> {
+        self.position(name).ok_or_else(|| PostEffectError::UnknownEffect(name.to_string()))
+    }
+}
+
+/// Write the sources of the built-in post effect shaders to the working directory
+///
+/// The effects share the one vertex shader, and each effect registered by register_post_effect_shader
+/// is built from these files during setup, so this must be called before the renderer is set up.
+pub fn install_post_effect_shader_sources() -> Result<(), io::Error> {
+    write_entire_file(POST_EFFECT_VERTEX_SOURCE, POST_EFFECT_VERTEX_FILENAME)?;
+    write_entire_file(BLOOM_FRAGMENT_SOURCE, BLOOM_FRAGMENT_FILENAME)?;
+    write_entire_file(FXAA_FRAGMENT_SOURCE, FXAA_FRAGMENT_FILENAME)?;
+    write_entire_file(VIGNETTE_FRAGMENT_SOURCE, VIGNETTE_FRAGMENT_FILENAME)?;
+
+    Ok(())
+}
+
+/// Add the shader specification of a post effect
+///
+/// The shader draws F2F2 full-screen triangles with POST_EFFECT_VERTEX_FILENAME, which passes
+/// on the texture coordinates as post_effect_uv.  The fragment shader samples the output of the
+/// step before as the sampler2D POST_EFFECT_SOURCE_TEXTURE at set 1, binding 0, may read the
+/// float POST_EFFECT_STRENGTH push constant, and writes post_effect_colour.
+///
+/// resource_manager: The resource manager to add the specification to
+/// shader_name: The name to register the shader under
+/// fragment: The filename of the fragment shader
+/// fragment_spirv: The filename of the compiled fragment shader
+/// fragment_reflect: The filename of the reflection data of the fragment shader
+/// pass_name: The offscreen pass the chain draws in, without depth
+pub fn register_post_effect_shader(resource_manager: &mut ResourceManager,
+                                   shader_name: &'static str,
+                                   fragment: &'static str,
+                                   fragment_spirv: &'static str,
+                                   fragment_reflect: &'static str,
+                                   pass_name: &'static str) {
+    let effect_spec = ShaderSpec {
+        name: shader_name,
+        library_files: vec![],
+        shader_files: vec![ShaderFilesSpecification {
+                               filename: POST_EFFECT_VERTEX_FILENAME,
+                               shader_stage: ShaderStage::VertexShader,
+                               spirv_out: "post_effect.vert.spv",
+                               reflect_out: "post_effect.vert.rfl",
+                               entry_point: DEFAULT_ENTRY_POINT,
+                           },
+                           ShaderFilesSpecification {
+                               filename: fragment,
+                               shader_stage: ShaderStage::FragmentShader,
+                               spirv_out: fragment_spirv,
+                               reflect_out: fragment_reflect,
+                               entry_point: DEFAULT_ENTRY_POINT,
+                           }],
+        fallback_shader_files: vec![],
+        uniform_block_names: vec![],
+        uniform_specs: vec![UniformSpec {
+                                name: POST_EFFECT_SOURCE_TEXTURE,
+                                set: 1,
+                                binding: 0,
+                                uniform_type: UniformType::CombinedImageSampler,
+                            }],
+        push_constants: vec![PushConstantSpec {
+                                 name: POST_EFFECT_STRENGTH,
+                                 offset: 0,
+                                 size: 4,
+                             }],
+        vertex_array_type: VertexArrayType::F2F2,
+        primitive: PrimitiveType::PrimitiveTriangles,
+        instance_layout: None,
+        attributes: vec!["position", "texcoord"],
+        fragment_out: "post_effect_colour",
+        extra_fragment_outs: vec![],
+        render_state: RenderState::default(),
+        stencil: None,
+        bindless_textures: false,
+        pass_name: pass_name,
+    };
+    resource_manager.shader_specs.insert(shader_name, effect_spec);
+}
+
+/// Add the shader specifications of the bloom, FXAA and vignette effects
+///
+/// resource_manager: The resource manager to add the specifications to
+/// pass_name: The offscreen pass the chain draws in, without depth
+pub fn register_builtin_post_effect_shaders(resource_manager: &mut ResourceManager, pass_name: &'static str) {
+    register_post_effect_shader(resource_manager,
+                                BLOOM_EFFECT_NAME,
+                                BLOOM_FRAGMENT_FILENAME,
+                                "bloom.frag.spv",
+                                "bloom.frag.rfl",
+                                pass_name);
+    register_post_effect_shader(resource_manager,
+                                FXAA_EFFECT_NAME,
+                                FXAA_FRAGMENT_FILENAME,
+                                "fxaa.frag.spv",
+                                "fxaa.frag.rfl",
+                                pass_name);
+    register_post_effect_shader(resource_manager,
+                                VIGNETTE_EFFECT_NAME,
+                                VIGNETTE_FRAGMENT_FILENAME,
+                                "vignette.frag.spv",
+                                "vignette.frag.rfl",
+                                pass_name);
+}
+
+/// Draw the enabled effects of a chain, returning the index of the target holding the result
+///
+/// Each effect samples the scene or the target written by the effect before, and draws into
+/// the other of the two targets.  None is returned when no effect is enabled, in which case
+/// the result is the scene itself.  No render target is selected after.
+///
+/// renderer: The renderer to draw with
+/// chain: The effects to draw
+/// scene: The render target holding the scene
+/// targets: The two ping-pong render targets, sized as the scene
+pub fn render_post_effects<Rend: Renderer + ?Sized>(renderer: &mut Rend,
+                                                    chain: &PostEffectChain,
+                                                    scene: &RenderTarget,
+                                                    targets: &mut [Box<RenderTarget>])
+                                                    -> Option<usize> {
+    debug_assert!(targets.len() >= 2);
+
+    let steps = chain.plan();
+    for step in steps.iter() {
+        let effect = &chain.effects()[step.effect];
+
+        let (first, second) = targets.split_at_mut(1);
+        let (target, other) = if step.target == 0 {
+            (&mut first[0], &second[0])
+        } else {
+            (&mut second[0], &first[0])
+        };
+        let source: &RenderTarget = match step.source {
+            PostEffectSource::Scene => scene,
+            PostEffectSource::Target(_) => &**other,
+        };
+
+        renderer.select_render_target(1, &mut **target);
+        renderer.begin_pass(effect.shader_name);
+        renderer.set_draw_texture(0, POST_EFFECT_SOURCE_TEXTURE, &**source.get_texture());
+        renderer.set_push_constant_float(0, POST_EFFECT_STRENGTH, effect.strength);
+
+        draw_fullscreen_quad(renderer);
+
+        renderer.end_pass();
+    }
+    renderer.deselect_render_target();
+
+    steps.last().map(|step| step.target)
+}
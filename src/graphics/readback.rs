@@ -0,0 +1,184 @@
+// Copyright (c) 2016-2017 Bruce Stenning. All rights reserved.
+//
+// Redistribution and use in source and binary forms, with or without
+// modification, are permitted provided that the following conditions
+// are met:
+//
+// 1. Redistributions of source code must retain the above copyright
+//    notice, this list of conditions and the following disclaimer.
+//
+// 2. Redistributions in binary form must reproduce the above copyright
+//    notice, this list of conditions and the following disclaimer in the
+//    documentation and/or other materials provided with the distribution.
+//
+// 3. Neither the name of the copyright holder nor the names of its
+//    contributors may be used to endorse or promote products derived
+//    from this software without specific prior written permission.
+//
+// THIS SOFTWARE IS PROVIDED BY THE COPYRIGHT HOLDERS AND CONTRIBUTORS
+// AS IS" AND ANY EXPRESS OR IMPLIED WARRANTIES, INCLUDING, BUT NOT
+// LIMITED TO, THE IMPLIED WARRANTIES OF MERCHANTABILITY AND FITNESS
+// FOR A PARTICULAR PURPOSE ARE DISCLAIMED. IN NO EVENT SHALL THE
+// COPYRIGHT HOLDER OR CONTRIBUTORS BE LIABLE FOR ANY DIRECT, INDIRECT,
+// INCIDENTAL, SPECIAL, EXEMPLARY, OR CONSEQUENTIAL DAMAGES (INCLUDING,
+// BUT NOT LIMITED TO, PROCUREMENT OF SUBSTITUTE GOODS OR SERVICES; LOSS
+// OF USE, DATA, OR PROFITS; OR BUSINESS INTERRUPTION) HOWEVER CAUSED
+// AND ON ANY THEORY OF LIABILITY, WHETHER IN CONTRACT, STRICT LIABILITY,
+// OR TORT (INCLUDING NEGLIGENCE OR OTHERWISE) ARISING IN ANY WAY OUT OF
+// THE USE OF THIS SOFTWARE, EVEN IF ADVISED OF THE POSSIBILITY OF SUCH
+// DAMAGE.
+
+use std::collections::HashMap;
+
+use graphics::image::*;
+use graphics::renderer::*;
+use graphics::rendertarget::*;
+
+/// Identifies an outstanding asynchronous readback request
+pub type ReadbackHandle = u32;
+
+/// A function called with the contents of a readback once it has completed
+pub type ReadbackCallback = Box<FnMut(Image) + Send>;
+
+/// The number of frames after which the OpenGL renderer maps the pixel buffer object of a readback
+///
+/// OpenGL has no fence per frame to wait on, so this allows for the frames the driver queues.
+/// The Vulkan renderer instead retrieves a readback once the frame fence of its image has
+/// signalled.  Renderer::readback_latency_frames returns the latency of either.
+pub const READBACK_LATENCY_FRAMES: u64 = 2;
+
+/// The contents of a presented frame, as returned by Renderer::capture_frame
+///
+/// The data is RGBA with eight bits per component and the top row first, whichever the backend.
+pub struct FrameCapture {
+    pub width: u32,
+    pub height: u32,
+    pub data: Vec<u8>,
+}
+
+impl FrameCapture {
+    /// Convert the capture into an image, dropping the alpha channel
+    pub fn to_image(&self) -> Image {
+        // Images are created from bottom-up data
+        let mut data: Vec<u8> = Vec::with_capacity((self.width * self.height * 3) as usize);
+        for y in (0..self.height).rev() {
+            for x in 0..self.width {
+                let offset = ((y * self.width + x) * 4) as usize;
+                data.extend_from_slice(&self.data[offset..offset + 3]);
+            }
+        }
+
+        Image::create_from_raw_data(self.width, self.height, &data)
+    }
+
+    /// Write the capture to a file, e.g. for a screenshot
+    ///
+    /// filename: The filename to use for the image on disk
+    pub fn save_to(&self, filename: &str) {
+        self.to_image().save_to(filename);
+    }
+}
+
+/// The completed readbacks of a renderer, held until they are polled for or passed to their callbacks
+pub struct ReadbackResults {
+    completed: HashMap<ReadbackHandle, Image>,
+    callbacks: HashMap<ReadbackHandle, ReadbackCallback>,
+}
+
+impl ReadbackResults {
+    /// Create an empty set of results
+    pub fn new() -> ReadbackResults {
+        ReadbackResults {
+            completed: HashMap::new(),
+            callbacks: HashMap::new(),
+        }
+    }
+
+    /// Pass the contents of a readback to a callback when it completes, rather than holding them
+    ///
+    /// handle: The handle of a readback still in flight
+    /// callback: The function to call with the contents
+    pub fn set_callback(&mut self, handle: ReadbackHandle, callback: ReadbackCallback) {
+        self.callbacks.insert(handle, callback);
+    }
+
+    /// Deliver the contents of a completed readback, to its callback if it has one
+    ///
+    /// handle: The handle of the readback
+    /// image: The contents read back
+    pub fn complete(&mut self, handle: ReadbackHandle, image: Image) {
+        match self.callbacks.remove(&handle) {
+            Some(mut callback) => callback(image),
+            None => {
+                self.completed.insert(handle, image);
+            }
+        }
+    }
+
+    /// Take the contents of a completed readback without a callback
+    ///
+    /// handle: The handle of the readback
+    pub fn take(&mut self, handle: ReadbackHandle) -> Option<Image> {
+        self.completed.remove(&handle)
+    }
+
+    /// Discard the results and callbacks
+    pub fn clear(&mut self) {
+        self.completed.clear();
+        self.callbacks.clear();
+    }
+}
+
+/// A queue of snapshots waiting on asynchronous readbacks before they can be written to disk
+///
+/// Unlike RenderTarget::snapshot, this reports when the snapshots have been written, at the
+/// expense of being updated each frame.
+pub struct SnapshotQueue {
+    pending: Vec<(ReadbackHandle, String)>,
+}
+
+impl SnapshotQueue {
+    /// Create an empty snapshot queue
+    pub fn new() -> SnapshotQueue {
+        SnapshotQueue { pending: vec![] }
+    }
+
+    /// Request that the contents of a render target be written to disk once available
+    ///
+    /// renderer: The renderer object
+    /// render_target: The render target to take the snapshot of
+    /// filename: The filename to save the snapshot to
+    pub fn request(&mut self, renderer: &mut Box<Renderer>, render_target: &RenderTarget, filename: &str) {
+        let handle = renderer.request_readback(render_target);
+        self.pending.push((handle, filename.to_string()));
+    }
+
+    /// Save any snapshots whose readbacks have completed, returning the number saved
+    ///
+    /// This should be called once per frame from the thread that owns the renderer.
+    ///
+    /// renderer: The renderer object
+    pub fn update(&mut self, renderer: &mut Box<Renderer>) -> usize {
+        let mut saved = 0;
+        let mut still_pending = vec![];
+
+        for (handle, filename) in self.pending.drain(..) {
+            match renderer.poll_readback(handle) {
+                Some(image) => {
+                    image.save_to(&filename);
+                    saved += 1;
+                }
+                None => still_pending.push((handle, filename)),
+            }
+        }
+
+        self.pending = still_pending;
+
+        saved
+    }
+
+    /// Are there any snapshots still waiting on their readbacks?
+    pub fn is_empty(&self) -> bool {
+        self.pending.is_empty()
+    }
+}
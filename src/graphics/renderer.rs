@@ -32,6 +32,8 @@ use std::collections::HashMap;
 use std::boxed::Box;
 use std::any::Any;
 use std::cell::RefCell;
+use std::error::Error;
+use std::fmt;
 use std::sync::*;
 use crossbeam;
 
@@ -40,15 +42,41 @@ use glfw;
 use graphics::renderergl::*;
 use graphics::renderervk::*;
 use graphics::rendertarget::*;
+use graphics::rendergraph::*;
+use graphics::renderpasses::RenderPassError;
+use graphics::readback::*;
+use graphics::geometrycapture::*;
+use graphics::computepacing::*;
+use graphics::frametime::*;
+use graphics::interpolation::*;
+use graphics::bandwidth::*;
+use graphics::vertexpool::*;
+use graphics::mesh::*;
+use graphics::memoryallocator::MemoryStats;
+use graphics::memoryusage::MemoryUsage;
+use graphics::perdraw::{PerDrawBenchmarkResults, PerDrawStrategy};
+use graphics::devicefeatures::*;
+use graphics::parity::*;
+use graphics::capturetrigger::CaptureTrigger;
+use graphics::stereo::StereoSettings;
+use graphics::xr::{XrGraphicsBinding, XrSwapchainImage};
+use graphics::gputiming::*;
+use graphics::cputiming::*;
+use graphics::rendererevents::*;
+use graphics::image::*;
 use graphics::resources::*;
 use graphics::shader::*;
+use graphics::shadervariants::ShaderDefines;
 use graphics::texture::*;
-use algebra::matrix::Mat4;
+use algebra::matrix::{ClipSpaceConvention, Mat4};
 use algebra::vector::*;
 
-// Triangle buffer maximum size (in triangles)
+// Triangle buffer default size (in triangles), overridden by RendererConfig::triangle_capacity
 pub const TRIANGLE_ARRAY_SIZE: usize = 512;
 
+// Triangle buffer maximum size (in triangles)
+pub const TRIANGLE_ARRAY_MAX_SIZE: usize = 65536;
+
 // Number of components per vertex: 3 dimensions by 3 attributes
 pub const VERTEX_MAX_COMPONENTS: usize = 3 * 3;
 
@@ -56,7 +84,7 @@ pub const VERTEX_MAX_COMPONENTS: usize = 3 * 3;
 // TODO: Use indexed arrays instead of three explicit vertices per triangle
 pub const TRIANGLE_MAX_COMPONENTS: usize = VERTEX_MAX_COMPONENTS * 3;
 
-// Number of individual components in a full component array
+// Number of individual components in a full component array of the default size
 pub const TRIANGLE_MAX_TOTAL_COMPONENTS: usize = TRIANGLE_ARRAY_SIZE * TRIANGLE_MAX_COMPONENTS;
 
 #[derive(PartialEq)]
@@ -65,6 +93,168 @@ pub enum RendererType {
     RendererVk,
 }
 
+/// Where a renderer presents its frames
+///
+/// A headless renderer has no window, surface or swapchain.  It renders into offscreen images of
+/// the given width and height instead, and its results are read back with capture_frame or from
+/// render targets, e.g. for tests on CI machines or server-side rendering.
+pub enum SurfaceMode<'a> {
+    Windowed(&'a mut glfw::Window),
+    Headless(u32, u32),
+}
+
+/// The reasons a renderer can fail to be created or set up
+#[derive(Debug)]
+pub enum RendererError {
+    /// The rendering API or a capability it depends on is not available
+    Unsupported(String),
+    /// No device, queue or surface satisfies the renderer's requirements
+    NoSuitableDevice(String),
+    /// An API call failed: the name of the call and the result it returned
+    ApiCallFailed(&'static str, String),
+    /// The render pass declarations, or the shaders and render graph passes using them, are invalid
+    InvalidRenderPasses(RenderPassError),
+    /// The data supplied for a resource does not fit its description
+    InvalidData(String),
+}
+
+impl fmt::Display for RendererError {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match *self {
+            RendererError::Unsupported(ref msg) => write!(f, "Unsupported: {}", msg),
+            RendererError::NoSuitableDevice(ref msg) => write!(f, "No suitable device: {}", msg),
+            RendererError::ApiCallFailed(name, ref res) => write!(f, "Unexpected result from {}: {}", name, res),
+            RendererError::InvalidRenderPasses(ref e) => write!(f, "Invalid render passes: {}", e),
+            RendererError::InvalidData(ref msg) => write!(f, "Invalid data: {}", msg),
+        }
+    }
+}
+
+impl Error for RendererError {
+    fn description(&self) -> &str {
+        match *self {
+            RendererError::Unsupported(_) => "renderer unsupported",
+            RendererError::NoSuitableDevice(_) => "no suitable device",
+            RendererError::ApiCallFailed(_, _) => "API call failed",
+            RendererError::InvalidRenderPasses(_) => "invalid render passes",
+            RendererError::InvalidData(_) => "invalid data",
+        }
+    }
+}
+
+/// Optional renderer features chosen by the application at creation time.
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub struct RendererConfig {
+    /// Enable GPU-assisted validation (Vulkan only, implies the validation layer)
+    pub gpu_assisted_validation: bool,
+    /// Enable the synchronization validation preset (Vulkan only, implies the validation layer)
+    pub synchronization_validation: bool,
+    /// The number of samples per pixel in offscreen render targets, 1 to disable multisampling
+    pub sample_count: u32,
+    /// Give the viewport a negative height (Vulkan only), so that clip space y points up as in OpenGL
+    ///
+    /// The built-in decal shader reconstructs positions for the default, unflipped viewport.
+    pub flip_viewport_y: bool,
+    /// The way to pass per-draw data, overriding the benchmark in PerDrawStrategySelector
+    pub per_draw_strategy: Option<PerDrawStrategy>,
+    /// The memory vertex data is stored in (Vulkan only)
+    pub vertex_buffer_storage: VertexBufferStorage,
+    /// The device features the application requires and those it can use where available
+    pub features: FeatureRequest,
+    /// The triangles each thread's data holds before it is flushed, up to TRIANGLE_ARRAY_MAX_SIZE
+    ///
+    /// Larger batches flush less often in dense scenes, while smaller ones save memory, as the
+    /// Vulkan vertex buffers are sized to hold a full batch.
+    pub triangle_capacity: usize,
+    /// Enable the extensions an OpenXR runtime shares its swapchain images through (Vulkan only)
+    pub xr_session: bool,
+    /// Load RenderDoc to capture frames without being launched from it (Vulkan only)
+    pub load_renderdoc: bool,
+    /// Encode the output for display with the sRGB transfer function, so that shaders write linear colour
+    ///
+    /// Vulkan prefers an sRGB swapchain format, and OpenGL enables GL_FRAMEBUFFER_SRGB, which
+    /// needs the window to have been created with an sRGB-capable framebuffer.
+    pub srgb_output: bool,
+    /// Present to an HDR10 swapchain where the surface offers one (Vulkan only)
+    ///
+    /// This needs VK_EXT_swapchain_colorspace, and falls back to srgb_output without it.  The
+    /// tone-mapping pass encodes its output for the display, see is_hdr_output.
+    pub hdr_output: bool,
+    /// The worker threads the pipelines are created on at startup (Vulkan only), 0 for one per rendering thread
+    ///
+    /// See PipelineBuildOptions for the pipelines startup waits for.
+    pub pipeline_threads: usize,
+    /// The mesh memory moved into fuller blocks each frame to defragment it (Vulkan only), 0 to disable
+    ///
+    /// The moves are copies on the transfer queue, so this bounds the bandwidth they take.
+    pub defragment_bytes_per_frame: usize,
+    /// The stereo mode and eye placement to start with, which can be changed by set_stereo_settings
+    pub stereo: StereoSettings,
+}
+
+impl Default for RendererConfig {
+    fn default() -> RendererConfig {
+        RendererConfig {
+            gpu_assisted_validation: false,
+            synchronization_validation: false,
+            sample_count: 1,
+            flip_viewport_y: false,
+            per_draw_strategy: None,
+            vertex_buffer_storage: VertexBufferStorage::Automatic,
+            features: FeatureRequest::default(),
+            triangle_capacity: TRIANGLE_ARRAY_SIZE,
+            xr_session: false,
+            load_renderdoc: false,
+            srgb_output: true,
+            hdr_output: false,
+            pipeline_threads: 0,
+            defragment_bytes_per_frame: 4 * 1024 * 1024,
+            stereo: StereoSettings::default(),
+        }
+    }
+}
+
+/// The memory the Vulkan renderer stores vertex data in
+///
+/// The geometry is streamed, being generated afresh by the threads each frame, so device-local
+/// storage pays for a copy of every vertex each frame in exchange for faster reads by the GPU.
+/// That wins on discrete GPUs, whose host-visible memory is read across the bus.
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub enum VertexBufferStorage {
+    /// Device-local memory the host can write directly where there is any (integrated GPUs or
+    /// resizable BAR), otherwise device-local with staging on discrete GPUs and host-visible
+    /// memory elsewhere
+    Automatic,
+    /// Host-visible, coherent memory written directly by the threads
+    HostVisible,
+    /// Device-local memory, filled from persistent staging buffers by a transfer recorded before
+    /// the draws are submitted
+    DeviceLocal,
+}
+
+/// Frame pacing statistics, with times in milliseconds
+#[derive(Clone, Copy, Debug, Default)]
+pub struct FrameStats {
+    /// The number of frames begun
+    pub frames: u64,
+    /// Whether frames are paced from present completion (VK_KHR_present_wait)
+    pub present_wait_enabled: bool,
+    /// The time the CPU last spent waiting for the previous frame to be presented
+    pub last_present_wait_ms: f32,
+    /// The time the CPU last delayed the start of a frame to reduce latency
+    pub last_pacing_delay_ms: f32,
+    /// A moving average of the time between successive presents completing
+    pub average_present_interval_ms: f32,
+    /// A moving average of the time from a frame starting work to its present being queued
+    pub average_frame_work_ms: f32,
+    /// The uniform buffer uploads, texture switches and binds skipped in the last frame as redundant
+    pub skipped_binds: u64,
+    /// The GPU time of the passes of the latest frame whose timings were harvested
+    pub last_gpu_frame_ms: Option<f32>,
+    /// The submissions to the graphics queue made in the last frame
+    pub queue_submits: u32,
+}
+
 #[derive(Clone, Copy, PartialEq)]
 pub enum VertexArrayType {
     F3,
@@ -75,10 +265,48 @@ pub enum VertexArrayType {
 pub const VERTEX_ARRAY_TYPE_BEGIN_RANGE: u32 = VertexArrayType::F3 as u32;
 pub const VERTEX_ARRAY_TYPE_END_RANGE: u32 = VertexArrayType::F2F2 as u32;
 
-#[derive(Clone, Copy)]
+#[derive(Clone, Copy, PartialEq)]
 pub enum PrimitiveType {
     PrimitiveTriangles,
     PrimitivePatches,
+    PrimitiveLines,
+    PrimitiveLineStrip,
+    PrimitivePoints,
+}
+pub const PRIMITIVE_TYPE_END_RANGE: u32 = PrimitiveType::PrimitivePoints as u32;
+
+impl PrimitiveType {
+    pub fn from_u32(ty: u32) -> PrimitiveType {
+        match ty {
+            0 => PrimitiveType::PrimitiveTriangles,
+            1 => PrimitiveType::PrimitivePatches,
+            2 => PrimitiveType::PrimitiveLines,
+            3 => PrimitiveType::PrimitiveLineStrip,
+            4 => PrimitiveType::PrimitivePoints,
+            _ => panic!("Unexpected primitive type"),
+        }
+    }
+
+    /// Return the number of vertices each entry of the thread data holds
+    ///
+    /// A line strip adds one vertex per entry, each joined to the one before.
+    pub fn vertices_per_primitive(ty: PrimitiveType) -> usize {
+        match ty {
+            PrimitiveType::PrimitiveTriangles => 3,
+            PrimitiveType::PrimitivePatches => 3,
+            PrimitiveType::PrimitiveLines => 2,
+            PrimitiveType::PrimitiveLineStrip => 1,
+            PrimitiveType::PrimitivePoints => 1,
+        }
+    }
+}
+
+/// How the rasteriser fills the primitives of the scene geometry
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub enum PolygonMode {
+    Fill,
+    Line,
+    Point,
 }
 
 impl VertexArrayType {
@@ -91,6 +319,16 @@ impl VertexArrayType {
         }
     }
 
+    /// Return the number of vertex attributes, which take the locations before any per-instance attributes
+    pub fn attribute_count(ty: VertexArrayType) -> usize {
+        match ty {
+            VertexArrayType::F3 => 1,
+            VertexArrayType::F3F3F3 => 3,
+            VertexArrayType::F3F3 => 2,
+            VertexArrayType::F2F2 => 2,
+        }
+    }
+
     pub fn from_u32(ty: u32) -> VertexArrayType {
         match ty {
             0 => VertexArrayType::F3,
@@ -102,6 +340,39 @@ impl VertexArrayType {
     }
 }
 
+/// The per-instance attributes of a shader, read from the instance data of draw_instanced
+///
+/// Each attribute is named, for finding it in GLSL, and has from one to four float components.
+/// The attributes are interleaved in the instance data in the order given, and take the
+/// locations following the vertex attributes.
+#[derive(Clone)]
+pub struct InstanceLayout {
+    pub attributes: Vec<(&'static str, usize)>,
+}
+
+impl InstanceLayout {
+    /// Construct an instance layout
+    ///
+    /// attributes: The name and number of float components of each per-instance attribute
+    pub fn new(attributes: Vec<(&'static str, usize)>) -> InstanceLayout {
+        debug_assert!(attributes.iter().all(|&(_, components)| components >= 1 && components <= 4));
+
+        InstanceLayout { attributes: attributes }
+    }
+
+    /// Return the number of floats of data for each instance
+    pub fn components_per_instance(&self) -> usize {
+        self.attributes.iter().map(|&(_, components)| components).sum()
+    }
+
+    /// Return the offset in floats of an attribute within the data for an instance
+    ///
+    /// index: The index of the attribute
+    pub fn offset_of(&self, index: usize) -> usize {
+        self.attributes[0..index].iter().map(|&(_, components)| components).sum()
+    }
+}
+
 pub struct ThreadData {
     pub thr: usize,
     pub vertex_array_type: VertexArrayType,
@@ -110,6 +381,10 @@ pub struct ThreadData {
     pub finished: bool,
 
     pub data: Vec<f32>,
+    capacity: usize, // The triangles the data holds, after which it is flushed
+
+    // Each batch is written here before it is flushed, while a geometry capture is attached
+    pub capture: Option<Arc<Mutex<GeometryCapture>>>,
 }
 
 // ThreadData needs to be cloneable to permit sending from a worker GL rendering
@@ -124,6 +399,9 @@ impl Clone for ThreadData {
             finished: self.finished,
 
             data: vec![],
+            capacity: self.capacity,
+
+            capture: self.capture.clone(),
         };
 
         td.data = self.data.iter().map(|x| *x).collect();
@@ -134,6 +412,14 @@ impl Clone for ThreadData {
 
 impl ThreadData {
     pub fn new(thr: usize) -> ThreadData {
+        ThreadData::with_capacity(thr, TRIANGLE_ARRAY_SIZE)
+    }
+
+    /// Construct the thread data of a thread, holding a number of triangles before it is flushed
+    ///
+    /// thr: The thread number
+    /// triangles: The capacity in triangles, limited to TRIANGLE_ARRAY_MAX_SIZE
+    pub fn with_capacity(thr: usize, triangles: usize) -> ThreadData {
         let mut td = ThreadData {
             thr: thr,
             vertex_array_type: VertexArrayType::F3F3F3,
@@ -142,17 +428,32 @@ impl ThreadData {
             finished: false,
 
             data: vec![],
-        };
+            capacity: 0,
 
-        td.data.reserve(TRIANGLE_MAX_TOTAL_COMPONENTS);
+            capture: None,
+        };
 
-        for _ in 0..TRIANGLE_MAX_TOTAL_COMPONENTS {
-            td.data.push(0.0f32);
-        }
+        td.set_capacity(triangles);
 
         td
     }
 
+    /// Return the number of triangles the data holds, after which it is flushed
+    pub fn capacity(&self) -> usize {
+        self.capacity
+    }
+
+    /// Change the number of triangles the data holds, after which it is flushed
+    ///
+    /// The current batch should have been flushed, as it is at the end of each pass.
+    ///
+    /// triangles: The capacity in triangles, limited to TRIANGLE_ARRAY_MAX_SIZE
+    pub fn set_capacity(&mut self, triangles: usize) {
+        self.capacity = triangles.max(1).min(TRIANGLE_ARRAY_MAX_SIZE);
+        self.index = self.index.min(self.capacity);
+        self.data.resize(self.capacity * TRIANGLE_MAX_COMPONENTS, 0.0f32);
+    }
+
     /// Add the specified raw triangle data to the thread data array, with no flush-check
     ///
     /// This is for the Normal-only case, with three components
@@ -311,6 +612,54 @@ impl ThreadData {
         self.index += 1;
     }
 
+    /// Add the specified raw line data to the thread data array, with no flush-check
+    ///
+    /// This is for the Vertex + Colour case, with three components each, and the
+    /// PrimitiveLines primitive, e.g. for debug wireframes and gizmos
+    ///
+    /// vi: Vector for ith vertex of the line
+    /// ci: Shader colour inputs at ith vertex of the line
+    pub fn add_line_st_f3f3(&mut self, v1: &Vec3<f32>, c1: &Vec3<f32>, v2: &Vec3<f32>, c2: &Vec3<f32>) {
+        debug_assert!(self.primitive == PrimitiveType::PrimitiveLines);
+        let i = self.index * VertexArrayType::components_per_vertex(self.vertex_array_type) * 2;
+
+        self.data[i + 00] = v1.x;
+        self.data[i + 01] = v1.y;
+        self.data[i + 02] = v1.z;
+        self.data[i + 03] = c1.x;
+        self.data[i + 04] = c1.y;
+        self.data[i + 05] = c1.z;
+        self.data[i + 06] = v2.x;
+        self.data[i + 07] = v2.y;
+        self.data[i + 08] = v2.z;
+        self.data[i + 09] = c2.x;
+        self.data[i + 10] = c2.y;
+        self.data[i + 11] = c2.z;
+
+        self.index += 1;
+    }
+
+    /// Add the specified raw vertex data to the thread data array, with no flush-check
+    ///
+    /// This is for the Vertex + Colour case, with three components each, and the
+    /// PrimitivePoints or PrimitiveLineStrip primitives
+    ///
+    /// v: Vector for the vertex
+    /// c: Shader colour inputs at the vertex
+    pub fn add_vertex_st_f3f3(&mut self, v: &Vec3<f32>, c: &Vec3<f32>) {
+        debug_assert!(PrimitiveType::vertices_per_primitive(self.primitive) == 1);
+        let i = self.index * VertexArrayType::components_per_vertex(self.vertex_array_type);
+
+        self.data[i + 00] = v.x;
+        self.data[i + 01] = v.y;
+        self.data[i + 02] = v.z;
+        self.data[i + 03] = c.x;
+        self.data[i + 04] = c.y;
+        self.data[i + 05] = c.z;
+
+        self.index += 1;
+    }
+
     /// Add the specified raw triangle data to the thread data array
     ///
     /// This is for the Normal-only case, with three components
@@ -374,6 +723,40 @@ impl ThreadData {
         self.add_triangle_st_f3f3(v1, n1, v2, n2, v3, n3);
     }
 
+    /// Add the specified raw line data to the thread data array
+    ///
+    /// This is for the Vertex + Colour case, with three components each
+    ///
+    /// renderer_arc: Atomic reference counted lockable reference to the
+    ///     renderer, only used when single_threaded
+    /// vi: Vector for ith vertex of the line
+    /// ci: Shader colour inputs at ith vertex of the line
+    pub fn add_line_f3f3<Rend: Renderer + ?Sized>(&mut self,
+                                                  renderer_arc: Arc<Mutex<&mut Rend>>,
+                                                  v1: &Vec3<f32>,
+                                                  c1: &Vec3<f32>,
+                                                  v2: &Vec3<f32>,
+                                                  c2: &Vec3<f32>) {
+        self.check_flush(false /* force */, renderer_arc);
+        self.add_line_st_f3f3(v1, c1, v2, c2);
+    }
+
+    /// Add the specified raw vertex data to the thread data array
+    ///
+    /// This is for the Vertex + Colour case, with three components each
+    ///
+    /// renderer_arc: Atomic reference counted lockable reference to the
+    ///     renderer, only used when single_threaded
+    /// v: Vector for the vertex
+    /// c: Shader colour inputs at the vertex
+    pub fn add_vertex_f3f3<Rend: Renderer + ?Sized>(&mut self,
+                                                    renderer_arc: Arc<Mutex<&mut Rend>>,
+                                                    v: &Vec3<f32>,
+                                                    c: &Vec3<f32>) {
+        self.check_flush(false /* force */, renderer_arc);
+        self.add_vertex_st_f3f3(v, c);
+    }
+
     /// This checks whether a flush is required and actions it when necessary
     ///
     /// This is run from the main thread when single-threaded rendering.  Because the renderer's
@@ -383,7 +766,10 @@ impl ThreadData {
     /// force: true if the flush should be forced, and false if the buffer should only be
     ///     flushed when full
     pub fn check_flush_st<Rend: Renderer + ?Sized>(&mut self, force: bool, renderer: &mut Rend) {
-        if force || self.index == TRIANGLE_ARRAY_SIZE {
+        if force || self.index == self.capacity {
+            let _scope = profile_scope("flush");
+            self.capture_batch();
+
             // We can flush directly from the main thread
             //
             match renderer.renderer_type() {
@@ -391,8 +777,7 @@ impl ThreadData {
                 RendererType::RendererVk => RendererVk::flush(Arc::new(Mutex::new(renderer)), self),
             }
 
-            // Reset the triangle index
-            self.index = 0;
+            self.restart_batch(force);
         }
     }
 
@@ -406,7 +791,10 @@ impl ThreadData {
     ///     renderer, only used when single_threaded
     pub fn check_flush<Rend: Renderer + ?Sized>(&mut self, force: bool, renderer_arc: Arc<Mutex<&mut Rend>>) {
         TLS.with(|tl| {
-            if force || self.index == TRIANGLE_ARRAY_SIZE {
+            if force || self.index == self.capacity {
+                let _scope = profile_scope("flush");
+                self.capture_batch();
+
                 let renderer_type;
                 {
                     let renderer = renderer_arc.lock().unwrap();
@@ -436,11 +824,39 @@ impl ThreadData {
                     RendererType::RendererVk => RendererVk::flush(renderer_arc.clone(), self),
                 }
 
-                // Reset the triangle index
-                self.index = 0;
+                self.restart_batch(force);
             }
         });
     }
+
+    /// Reset the triangle index after a flush
+    ///
+    /// A line strip that filled the batch carries its last vertex over, so that the strip
+    /// continues unbroken into the next batch.  A forced flush ends the strip.
+    ///
+    /// force: true if the flush was forced
+    fn restart_batch(&mut self, force: bool) {
+        if !force && self.primitive == PrimitiveType::PrimitiveLineStrip && self.index > 0 {
+            let components = VertexArrayType::components_per_vertex(self.vertex_array_type);
+            let last = (self.index - 1) * components;
+            for c in 0..components {
+                self.data[c] = self.data[last + c];
+            }
+            self.index = 1;
+        } else {
+            self.index = 0;
+        }
+    }
+
+    /// Write the current batch to the attached geometry capture, if any
+    fn capture_batch(&self) {
+        if self.index == 0 {
+            return;
+        }
+        if let Some(ref capture) = self.capture {
+            capture.lock().unwrap().record(self);
+        }
+    }
 }
 
 /// Types must implement this trait in order to be able to use the MT harness
@@ -503,6 +919,10 @@ pub fn mt_render_harness<Object: WorkerThread + Send + Sync, Rend: Renderer + Se
         });
 
         object.render_thread(renderer_arc.clone(), threaddata_arc);
+
+        if cpu_profiling_enabled() {
+            renderer_arc.lock().unwrap().cpu_profiler().submit_current_thread(0);
+        }
     } else {
         // Multi-threaded path
 
@@ -542,7 +962,11 @@ pub fn mt_render_harness<Object: WorkerThread + Send + Sync, Rend: Renderer + Se
                         tl.borrow_mut().backrx.push(backrx);
                     });
 
-                    object.render_thread(renderer_arc, threaddata_arc)
+                    object.render_thread(renderer_arc.clone(), threaddata_arc);
+
+                    if cpu_profiling_enabled() {
+                        renderer_arc.lock().unwrap().cpu_profiler().submit_current_thread(thr);
+                    }
                 });
             }
 
@@ -585,6 +1009,16 @@ pub trait Renderer: Send + Sync {
     /// Return the renderer type
     fn renderer_type(&self) -> RendererType;
 
+    /// Return the clip space convention that projection matrices must follow
+    fn clip_space_convention(&self) -> ClipSpaceConvention;
+
+    /// Destroy the renderer's API objects in a defined order
+    ///
+    /// Textures, render targets and uploads created through the renderer must be dropped
+    /// first, and the renderer must not be used afterwards.  A renderer dropped without being
+    /// shut down shuts itself down and reports it.
+    fn shutdown(&mut self);
+
     /// Obtain an Arc for the ThreadData structure for the specified thread
     fn get_threaddata(&self, thr: usize) -> Arc<Mutex<Box<ThreadData>>>;
 
@@ -593,14 +1027,14 @@ pub trait Renderer: Send + Sync {
 
     /// Finish initialisation of resources
     ///
+    /// Returns an error if the renderer could not be set up to draw them.
+    ///
     /// shaders: A map of the shaders to set up, keyed by name
     /// textures: A map of the textures to set up, keyed by name
     fn finish_resource_initialisation(&mut self,
                                       shaders: &HashMap<&'static str, &Box<Shader>>,
-                                      textures: &HashMap<&'static str, &Box<Texture>>);
-
-    /// Clear the depth buffer before starting rendering
-    fn clear_depth_buffer(&self);
+                                      textures: &HashMap<&'static str, &Box<Texture>>)
+                                      -> Result<(), RendererError>;
 
     /// This converts the primitive type that will be rendered to the renderer's intrinsic type
     fn primitive(&self, primitive_type: PrimitiveType) -> u32;
@@ -611,8 +1045,39 @@ pub trait Renderer: Send + Sync {
     fn set_uniform_buffer_vec3(&self, buffer_name: &str, uniform_name: &str, value: &Vec3<f32>);
     fn set_uniform_buffer_matrix(&self, buffer_name: &str, uniform_name: &str, matrix: &Mat4<f32>);
     fn set_uniform_buffer_float_vector(&self, buffer_name: &str, uniform_name: &str, vector: &Vec<f32>);
+
+    /// Set consecutive elements of an array of matrices in a uniform buffer, from the first
+    ///
+    /// buffer_name: The name of the uniform buffer to contain the new values
+    /// uniform_name: The name of the array member
+    /// matrices: The matrices to set, no more than the length of the array
+    fn set_uniform_buffer_matrix_array(&self, buffer_name: &str, uniform_name: &str, matrices: &[Mat4<f32>]);
     fn synchronise_uniform_buffer(&self, buffer_name: &str);
 
+    /// Push constant configuration
+    ///
+    /// The value applies to the draws flushed by the thread after the call, so any triangles
+    /// accumulated by the thread must be flushed first.  OpenGL sets a uniform of the same
+    /// name on the current program instead, and so must be called from the rendering thread.
+    fn set_push_constant_int(&self, thr: usize, name: &str, value: i32);
+    fn set_push_constant_float(&self, thr: usize, name: &str, value: f32);
+    fn set_push_constant_vec3(&self, thr: usize, name: &str, value: &Vec3<f32>);
+    fn set_push_constant_matrix(&self, thr: usize, name: &str, matrix: &Mat4<f32>);
+
+    /// Switch a texture of the current shader for the draws flushed by the thread after the call
+    ///
+    /// This lets the draws of one pass sample different materials.  As with push constants any
+    /// triangles accumulated by the thread must be flushed first, and the switch lasts until the
+    /// end of the pass.  Vulkan copies the shader's descriptor set for the thread with the
+    /// texture written into it, whereas OpenGL binds the texture to the unit of the sampler of
+    /// the same name on the current program, and so must be called from the rendering thread.
+    /// Shaders reading bindless textures can select a texture with a push constant instead.
+    ///
+    /// thr: The thread whose draws should sample the texture
+    /// name: The name of the texture in the current shader
+    /// texture: The texture to sample
+    fn set_draw_texture(&self, thr: usize, name: &str, texture: &Texture);
+
     /// Flip the back buffer to the front
     fn flip(&self, window: &mut glfw::Context);
 
@@ -625,6 +1090,15 @@ pub trait Renderer: Send + Sync {
     /// Initiate a render pass
     fn begin_pass(&mut self, shader_name: &'static str);
 
+    /// Initiate a render pass with a variant of a shader compiled with a set of defines
+    ///
+    /// The variant must have been registered with register_shader_variant before the renderer
+    /// was set up, and falls back to the base shader otherwise.
+    ///
+    /// shader_name: The name of the base shader
+    /// defines: The defines of the variant
+    fn begin_pass_variant(&mut self, shader_name: &'static str, defines: &ShaderDefines);
+
     /// Terminate a render pass
     fn end_pass(&mut self);
 
@@ -636,15 +1110,345 @@ pub trait Renderer: Send + Sync {
 
     /// Select no render target
     fn deselect_render_target(&mut self);
+
+    /// Return the schedule of the render graph declared in the resource manager
+    ///
+    /// The passes are in the order they should be run.  There are none when no graph was
+    /// declared, or when it could not be scheduled.
+    fn render_graph_schedule(&self) -> &RenderGraphSchedule;
+
+    /// Bind a render target to an attachment of the render graph
+    ///
+    /// The renderer follows the layout of the bound image from then on, so that the transitions
+    /// of each pass start from the layout the image is really in.
+    ///
+    /// attachment: The name of the attachment
+    /// render_target: The render target holding the attachment
+    fn bind_graph_attachment(&mut self, attachment: &'static str, render_target: &RenderTarget);
+
+    /// Prepare the attachments of a pass of the render graph
+    ///
+    /// The layout transitions and barriers the pass needs are made at the next begin_pass, so
+    /// this should be called before the passes of each shader in the graph pass.
+    ///
+    /// pass_name: The name of the pass in the render graph
+    fn begin_graph_pass(&mut self, pass_name: &'static str);
+
+    /// Notify the renderer that the window has been resized
+    ///
+    /// This should be called on receipt of a framebuffer size event, including when toggling
+    /// full screen mode.
+    ///
+    /// width: The new framebuffer width
+    /// height: The new framebuffer height
+    fn window_resized(&mut self, width: u32, height: u32);
+
+    /// Clear the colour contents of a render target to the specified value
+    ///
+    /// Every colour attachment of the target is cleared.
+    ///
+    /// This must be called at a pass boundary, i.e. not between begin_pass and end_pass.
+    ///
+    /// render_target: The render target to clear
+    /// colour: The RGBA value to clear to
+    fn clear_render_target(&mut self, render_target: &RenderTarget, colour: &Vec4<f32>);
+
+    /// Clear the depth contents of a render target to the specified value
+    ///
+    /// This must be called at a pass boundary, i.e. not between begin_pass and end_pass.
+    ///
+    /// render_target: The render target whose depth buffer should be cleared
+    /// depth: The depth value to clear to
+    fn clear_depth_target(&mut self, render_target: &RenderTarget, depth: f32);
+
+    /// Queue an asynchronous readback of the colour contents of a render target
+    ///
+    /// The copy is recorded as part of the current frame, and the result becomes
+    /// available readback_latency_frames frames later without stalling the queue.
+    ///
+    /// Snapshots are taken this way.  capture_frame stays synchronous, as a transition needs the
+    /// outgoing frame before the next one is drawn, and there is no video capture or picking
+    /// to move onto it yet.
+    ///
+    /// render_target: The render target to read back
+    fn request_readback(&mut self, render_target: &RenderTarget) -> ReadbackHandle;
+
+    /// Return the number of frames after which a readback requested this frame is available
+    fn readback_latency_frames(&self) -> u64;
+
+    /// Poll for the result of an earlier readback request
+    ///
+    /// Returns the image once the readback has completed, after which the handle is no
+    /// longer valid.  Returns None while the readback is still in flight.
+    ///
+    /// handle: The handle returned by request_readback
+    fn poll_readback(&mut self, handle: ReadbackHandle) -> Option<Image>;
+
+    /// Queue an asynchronous readback of the colour contents of a render target, calling back
+    /// with the result
+    ///
+    /// This is request_readback with the result passed to the callback as it becomes available,
+    /// during begin_frame, rather than polled for.
+    ///
+    /// render_target: The render target to read back
+    /// callback: The function to call with the contents
+    fn request_readback_callback(&mut self, render_target: &RenderTarget, callback: ReadbackCallback);
+
+    /// Copy the frame about to be presented into CPU memory
+    ///
+    /// This must be called between end_frame and flip, and waits for the frame to finish
+    /// rendering.  Returns None if the window surface cannot be read from.
+    fn capture_frame(&mut self) -> Option<FrameCapture>;
+
+    /// Return the frame pacing statistics
+    fn frame_stats(&self) -> FrameStats;
+
+    /// Return the usage statistics of the device memory allocated to buffers and images
+    fn memory_stats(&self) -> MemoryStats;
+
+    /// Return the memory used by buffers and images, by heap and by what it is used for
+    fn get_memory_usage(&self) -> MemoryUsage;
+
+    /// Return the clock of engine and real time, ticked by begin_frame
+    fn frame_clock(&self) -> &FrameClock;
+
+    /// Return the clock of engine and real time, to pause, step or scale engine time
+    fn frame_clock_mut(&mut self) -> &mut FrameClock;
+
+    /// Return the previous and current transforms of the objects moved by a fixed-timestep
+    /// simulation, published by begin_frame
+    fn transform_interpolator(&self) -> &TransformInterpolator;
+
+    /// Return the transforms of the simulated objects, to record their transforms each step and
+    /// set the interpolation alpha before begin_frame
+    fn transform_interpolator_mut(&mut self) -> &mut TransformInterpolator;
+
+    /// Return the estimated memory traffic of each pass of the last frame
+    fn pass_bandwidth(&self) -> &Vec<PassBandwidth>;
+
+    /// Return the GPU times of the passes of recent frames
+    ///
+    /// Each pass is bracketed by timestamp queries, whose results are read without waiting when
+    /// the frame's queries are next reused, so the timings lag a few frames behind.
+    fn gpu_timings(&self) -> &GpuTimingHistory;
+
+    /// Return the profiler of the CPU time spent in scopes timed with profile_scope
+    ///
+    /// The scopes of the rendering threads are gathered as mt_render_harness returns, and those
+    /// of the thread calling end_frame by end_frame, which numbers that thread after the
+    /// rendering threads.  Profiling is off until enabled with CpuProfiler::set_enabled.
+    fn cpu_profiler(&self) -> &CpuProfiler;
+
+    /// Return the listeners to the renderer's events, to subscribe to them
+    ///
+    /// A listener hears of the device being lost, the swapchain being recreated, shaders being
+    /// rebuilt and validation messages, so that the application can recreate what depends on
+    /// them or log them.
+    fn events(&self) -> &RendererEvents;
+
+    /// Draw the triangles of a thread's data once for each instance, and reset the thread data
+    ///
+    /// The current pass's shader must have an InstanceLayout describing the instance data.
+    ///
+    /// thread_data: The thread data holding the triangles
+    /// instance_data: The interleaved per-instance attributes of all of the instances
+    /// instance_count: The number of instances to draw
+    fn draw_instanced(&mut self, thread_data: &mut ThreadData, instance_data: &Vec<f32>, instance_count: usize);
+
+    /// Draw triangles generated by the current pass's vertex shader without any vertex data
+    ///
+    /// The shader builds each vertex from gl_VertexIndex and gl_InstanceIndex, typically by
+    /// reading a record for the instance from a storage buffer.  The thread data only chooses the
+    /// thread to draw from, and its triangles are left untouched.
+    ///
+    /// thread_data: The thread data of the drawing thread
+    /// vertex_count: The number of vertices of each instance, a multiple of three
+    /// instance_count: The number of instances to draw
+    fn draw_generated(&mut self, thread_data: &mut ThreadData, vertex_count: u32, instance_count: u32);
+
+    /// Upload the vertices and indices of a mesh into buffers the renderer keeps, returning its
+    /// handle, or None if the data is invalid
+    ///
+    /// Static geometry is uploaded once this way, instead of being streamed through the thread
+    /// data every frame.  This must be called outside of a pass.
+    ///
+    /// data: The vertices and optional indices of the mesh
+    fn create_mesh(&mut self, data: &MeshData) -> Option<MeshHandle>;
+
+    /// Draw a mesh created by create_mesh with the current pass's shader
+    ///
+    /// The shader's vertex array type must match the mesh's, and it must take no instance data.
+    /// The thread data only chooses the thread to draw from, and its triangles are left untouched.
+    ///
+    /// thread_data: The thread data of the drawing thread
+    /// mesh: The handle of the mesh
+    fn draw_mesh(&mut self, thread_data: &mut ThreadData, mesh: MeshHandle);
+
+    /// Destroy a mesh, whose buffers are released once the frames drawing it have completed
+    ///
+    /// mesh: The handle of the mesh
+    fn destroy_mesh(&mut self, mesh: MeshHandle);
+
+    /// Create vertex buffers up front, so that a scene suddenly needing many does not hitch
+    ///
+    /// sizes: The number of vertex buffers to have ready for each vertex array type and thread
+    fn prewarm_vertex_buffers(&mut self, sizes: &VertexBufferPoolSizes);
+
+    /// Return the most vertex buffers used in a frame for each vertex array type and thread
+    ///
+    /// These can be saved and used to prewarm the vertex buffers on the next run.
+    fn vertex_buffer_high_water(&self) -> VertexBufferPoolSizes;
+
+    /// Return the number of triangles each thread's data holds before it is flushed
+    fn triangle_capacity(&self) -> usize;
+
+    /// Change the number of triangles each thread's data holds before it is flushed, resizing
+    /// the vertex buffers to match
+    ///
+    /// This must be called outside of a frame.
+    ///
+    /// triangles: The capacity in triangles, limited to TRIANGLE_ARRAY_MAX_SIZE
+    fn set_triangle_capacity(&mut self, triangles: usize);
+
+    /// Return whether compute shaders can be dispatched
+    fn supports_compute(&self) -> bool;
+
+    /// Run a compute shader
+    ///
+    /// This must be called at a pass boundary.  Uniform buffer contents synchronised before
+    /// the call are visible to the shader, and its writes to storage buffers are visible to
+    /// the passes that follow.
+    ///
+    /// shader_name: The name of the compute shader
+    /// x: The number of workgroups in the x dimension
+    /// y: The number of workgroups in the y dimension
+    /// z: The number of workgroups in the z dimension
+    fn dispatch_compute(&mut self, shader_name: &str, x: u32, y: u32, z: u32);
+
+    /// Queue a compute dispatch to be spread over the coming frames within the compute budget
+    ///
+    /// The dispatch is split along x into slices, each told its first workgroup through the
+    /// workgroup_offset push constant, and submitted with each frame's work so that long
+    /// generation passes do not starve rendering.  Shaders without the push constant are
+    /// dispatched whole in a single frame.
+    ///
+    /// shader_name: The name of the compute shader
+    /// x: The number of workgroups in the x dimension
+    /// y: The number of workgroups in the y dimension
+    /// z: The number of workgroups in the z dimension
+    fn queue_paced_compute(&mut self, shader_name: &'static str, x: u32, y: u32, z: u32) -> ComputeJobHandle;
+
+    /// Return the pacer of the queued compute work, for the progress of its jobs
+    fn compute_pacer(&self) -> &ComputePacer;
+
+    /// Set the GPU time given over to paced compute work each frame
+    ///
+    /// budget_ms: The budget in milliseconds
+    fn set_compute_budget(&mut self, budget_ms: f32);
+
+    /// Return whether shaders can read textures from the bindless texture array
+    ///
+    /// Where they cannot, materials are drawn with shaders that bind their textures through
+    /// their own uniforms instead.
+    fn supports_bindless_textures(&self) -> bool;
+
+    /// Add a texture to the bindless texture array
+    ///
+    /// The texture can be sampled by shaders that set bindless_textures, from the next draw
+    /// onwards, by writing the returned index into a material record.  Returns None if bindless
+    /// textures are unsupported or the array is full.
+    ///
+    /// texture: The texture to add
+    fn register_bindless_texture(&mut self, texture: &Texture) -> Option<u32>;
+
+    /// Remove a texture from the bindless texture array
+    ///
+    /// The index is reused once the frames in flight have completed, so no material record
+    /// drawn from now on should refer to it.
+    ///
+    /// index: The index returned when the texture was added
+    fn release_bindless_texture(&mut self, index: u32);
+
+    /// Return the device features granted, out of those requested in the renderer configuration
+    ///
+    /// Passes depending on an optional feature should be skipped when it is not granted.
+    fn granted_features(&self) -> DeviceFeatures;
+
+    /// Return the limits of the device, by which resources such as textures, uniform blocks and
+    /// push constants should be sized
+    fn get_device_limits(&self) -> DeviceLimits;
+
+    /// Return whether the output is an HDR10 swapchain, which expects Rec. 2020 primaries
+    /// encoded with the ST 2084 transfer function rather than sRGB
+    fn is_hdr_output(&self) -> bool;
+
+    /// Set how the primitives of depth tested shaders are rasterised, for inspecting topology
+    ///
+    /// Passes without depth testing, such as the post-processing passes, are always filled.  The
+    /// line and point modes need the fill_mode_non_solid feature, without which they are ignored.
+    ///
+    /// mode: The polygon mode to draw with
+    fn set_polygon_mode(&mut self, mode: PolygonMode);
+
+    /// Return the polygon mode the depth tested shaders are drawn with
+    fn polygon_mode(&self) -> PolygonMode;
+
+    /// Set how the scene is rendered for the two eyes and combined, see render_stereo_eyes
+    ///
+    /// The change takes effect from the next call to render_stereo_eyes, so the mode can be
+    /// switched between frames.
+    ///
+    /// settings: The stereo mode, eye separation and convergence distance
+    fn set_stereo_settings(&mut self, settings: StereoSettings);
+
+    /// Return the stereo settings the scene is rendered with
+    fn stereo_settings(&self) -> StereoSettings;
+
+    /// Return the support of each engine feature on this backend and device, with the fallbacks
+    /// in use
+    ///
+    /// The report can be printed for bug reports, or compared with other backends' reports by
+    /// format_parity_table.
+    fn parity_report(&self) -> ParityReport;
+
+    /// Keep the results of a per-draw strategy benchmark for the parity report
+    ///
+    /// PerDrawStrategySelector::run_benchmark records its results itself, while results loaded
+    /// from an offline run can be recorded here.
+    ///
+    /// results: The benchmark results
+    fn record_per_draw_benchmark(&mut self, results: &PerDrawBenchmarkResults);
+
+    /// Return the handles an OpenXR session is created with to render through this renderer
+    fn xr_graphics_binding(&self) -> XrGraphicsBinding;
+
+    /// Copy the colour contents of a render target into an image acquired from an OpenXR swapchain
+    ///
+    /// The render target is scaled to the size of the image.  This must be called outside of a
+    /// frame, once the render target has been rendered.
+    ///
+    /// render_target: The render target to copy from
+    /// image: The swapchain image to copy into
+    fn copy_to_xr_image(&mut self, render_target: &RenderTarget, image: &XrSwapchainImage);
+
+    /// Capture the next frame in RenderDoc, returning false if RenderDoc is not attached
+    ///
+    /// The capture starts at begin_frame and ends at end_frame, so this is called between frames.
+    /// Calling it again before the capture has started captures the frames after it too.
+    fn trigger_capture(&mut self) -> bool;
+
+    /// Return the capture trigger, for the paths of the captures written
+    fn capture_trigger(&self) -> &CaptureTrigger;
 }
 
 /// Create new threaddata objects for a renderer
 ///
 /// max_threads: The maximum number of rendering threads
-fn create_threaddata_objects(max_threads: usize) -> Vec<Arc<Mutex<Box<ThreadData>>>> {
+/// triangles: The triangles each thread's data holds before it is flushed
+fn create_threaddata_objects(max_threads: usize, triangles: usize) -> Vec<Arc<Mutex<Box<ThreadData>>>> {
     let mut threaddata_arcs = Vec::with_capacity(max_threads);
     for thr in 0..max_threads {
-        threaddata_arcs.push(Arc::new(Mutex::new(Box::new(ThreadData::new(thr)))));
+        threaddata_arcs.push(Arc::new(Mutex::new(Box::new(ThreadData::with_capacity(thr, triangles)))));
     }
 
     threaddata_arcs
@@ -653,17 +1457,22 @@ fn create_threaddata_objects(max_threads: usize) -> Vec<Arc<Mutex<Box<ThreadData
 /// Initial creation of a renderer, but further setup will be carried out later
 ///
 /// glfw: The main GLFW object
-/// window: The GLFW application window
+/// surface_mode: The GLFW application window, or the frame size when rendering headless
 /// renderer_type: The type of renderer to create
 /// resource_manager: The resource manager containing information about shaders, uniforms, etc
 /// application_name: The name of the application (currently only used for Vulkan)
 /// application_version: A string identifying the application version (currently only used for Vulkan)
 /// engine_version: A string identifying the engine version (currently only used for Vulkan)
 /// max_threads: The maximum number of rendering threads
-/// debug_level: The debug level for the renderer
-/// vk_debug_mask: The Vulkan debug mask, for Vulkan API tracing
+/// debug_level: The debug level for the renderer, which also sets the level of messages logged
+/// vk_debug_mask: The Vulkan debug mask of validation messages to report, made from the DEBUG_MASK bits
+/// config: Optional renderer features
+///
+/// Returns an error if the requested renderer cannot be created, e.g. so that an application
+/// can retry with RendererType::RendererGl when Vulkan is unavailable.  Only the Vulkan
+/// renderer can run headless; OpenGL needs a context, so use a hidden window for it instead.
 pub fn create_renderer(glfw: &mut glfw::Glfw,
-                       window: &mut glfw::Window,
+                       surface_mode: SurfaceMode,
                        renderer_type: RendererType,
                        resource_manager: &Arc<Mutex<Box<ResourceManager>>>,
                        application_name: &str,
@@ -671,9 +1480,10 @@ pub fn create_renderer(glfw: &mut glfw::Glfw,
                        engine_version: &str,
                        max_threads: usize,
                        debug_level: u32,
-                       vk_debug_mask: u32)
-                       -> Box<Renderer> {
-    let threaddata_vector = create_threaddata_objects(max_threads);
+                       vk_debug_mask: u32,
+                       config: &RendererConfig)
+                       -> Result<Box<Renderer>, RendererError> {
+    let threaddata_vector = create_threaddata_objects(max_threads, config.triangle_capacity);
     let renderer: Box<Renderer>;
     if renderer_type == RendererType::RendererVk {
         renderer = Box::new(RendererVk::new(application_name,
@@ -682,19 +1492,27 @@ pub fn create_renderer(glfw: &mut glfw::Glfw,
                                             max_threads,
                                             debug_level,
                                             vk_debug_mask,
+                                            config,
                                             glfw,
-                                            window,
+                                            surface_mode,
                                             resource_manager,
-                                            threaddata_vector.clone()));
+                                            threaddata_vector.clone())?);
     } else if renderer_type == RendererType::RendererGl {
+        let window = match surface_mode {
+            SurfaceMode::Windowed(window) => window,
+            SurfaceMode::Headless(..) => {
+                return Err(RendererError::Unsupported("The OpenGL renderer cannot run headless, use a hidden window".to_string()));
+            }
+        };
         renderer = Box::new(RendererGl::new(debug_level,
                                             max_threads,
+                                            config,
                                             window,
                                             resource_manager,
                                             threaddata_vector.clone()));
     } else {
-        panic!("Unknown renderer type requested")
+        return Err(RendererError::Unsupported("Unknown renderer type requested".to_string()));
     }
 
-    renderer
+    Ok(renderer)
 }
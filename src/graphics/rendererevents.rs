@@ -0,0 +1,156 @@
+// Copyright (c) 2016-2017 Bruce Stenning. All rights reserved.
+//
+// Redistribution and use in source and binary forms, with or without
+// modification, are permitted provided that the following conditions
+// are met:
+//
+// 1. Redistributions of source code must retain the above copyright
+//    notice, this list of conditions and the following disclaimer.
+//
+// 2. Redistributions in binary form must reproduce the above copyright
+//    notice, this list of conditions and the following disclaimer in the
+//    documentation and/or other materials provided with the distribution.
+//
+// 3. Neither the name of the copyright holder nor the names of its
+//    contributors may be used to endorse or promote products derived
+//    from this software without specific prior written permission.
+//
+// THIS SOFTWARE IS PROVIDED BY THE COPYRIGHT HOLDERS AND CONTRIBUTORS
+// AS IS" AND ANY EXPRESS OR IMPLIED WARRANTIES, INCLUDING, BUT NOT
+// LIMITED TO, THE IMPLIED WARRANTIES OF MERCHANTABILITY AND FITNESS
+// FOR A PARTICULAR PURPOSE ARE DISCLAIMED. IN NO EVENT SHALL THE
+// COPYRIGHT HOLDER OR CONTRIBUTORS BE LIABLE FOR ANY DIRECT, INDIRECT,
+// INCIDENTAL, SPECIAL, EXEMPLARY, OR CONSEQUENTIAL DAMAGES (INCLUDING,
+// BUT NOT LIMITED TO, PROCUREMENT OF SUBSTITUTE GOODS OR SERVICES; LOSS
+// OF USE, DATA, OR PROFITS; OR BUSINESS INTERRUPTION) HOWEVER CAUSED
+// AND ON ANY THEORY OF LIABILITY, WHETHER IN CONTRACT, STRICT LIABILITY,
+// OR TORT (INCLUDING NEGLIGENCE OR OTHERWISE) ARISING IN ANY WAY OUT OF
+// THE USE OF THIS SOFTWARE, EVEN IF ADVISED OF THE POSSIBILITY OF SUCH
+// DAMAGE.
+
+use std::sync::{Mutex, MutexGuard};
+
+use graphics::debugmessages::DebugMessage;
+
+/// Identifies a subscription to the renderer's events, to cancel it with
+pub type RendererEventSubscription = u32;
+
+/// Receives the renderer's events, so that the application can react to them
+///
+/// Each method does nothing unless overridden.  The listeners are called with the renderer's
+/// list of them locked, so they must not subscribe or unsubscribe from within a call.
+pub trait RendererEventListener: Send {
+    /// The device has been lost, after which the renderer panics, so this is the last chance to
+    /// save or log anything (Vulkan only)
+    fn on_device_lost(&self) {}
+
+    /// The swapchain has been recreated, so the resources sized to it need recreating too
+    ///
+    /// OpenGL reports a resize of the window's framebuffer.
+    ///
+    /// new_extent: The width and height of the new swapchain images
+    fn on_swapchain_recreated(&self, _new_extent: (u32, u32)) {}
+
+    /// A shader has been rebuilt after its source changed, so anything derived from it is stale
+    ///
+    /// shader_name: The name of the shader
+    fn on_shader_rebuilt(&self, _shader_name: &str) {}
+
+    /// The validation layer or loader has passed on a message selected by vk_debug_mask (Vulkan only)
+    ///
+    /// This may be called on any thread, from within the call that produced the message.
+    ///
+    /// message: The message
+    fn on_validation_message(&self, _message: &DebugMessage) {}
+}
+
+struct Subscriptions {
+    last: RendererEventSubscription, // The most recent subscription made
+    listeners: Vec<(RendererEventSubscription, Box<RendererEventListener>)>,
+}
+
+/// The listeners subscribed to the renderer's events
+pub struct RendererEvents {
+    subscriptions: Mutex<Subscriptions>,
+}
+
+impl RendererEvents {
+    pub fn new() -> RendererEvents {
+        RendererEvents {
+            subscriptions: Mutex::new(Subscriptions {
+                last: 0,
+                listeners: vec![],
+            }),
+        }
+    }
+
+    // A listener that panicked has poisoned the lock, but the others still want their events
+    fn lock(&self) -> MutexGuard<Subscriptions> {
+        match self.subscriptions.lock() {
+            Ok(guard) => guard,
+            Err(poisoned) => poisoned.into_inner(),
+        }
+    }
+
+    /// Add a listener, returning the subscription to cancel it with
+    ///
+    /// listener: The listener, which is called in the order of subscription
+    pub fn subscribe(&self, listener: Box<RendererEventListener>) -> RendererEventSubscription {
+        let mut subscriptions = self.lock();
+        subscriptions.last += 1;
+        let subscription = subscriptions.last;
+        subscriptions.listeners.push((subscription, listener));
+
+        subscription
+    }
+
+    /// Remove a listener, returning whether it was subscribed
+    ///
+    /// subscription: The subscription returned by subscribe
+    pub fn unsubscribe(&self, subscription: RendererEventSubscription) -> bool {
+        let mut subscriptions = self.lock();
+        let count = subscriptions.listeners.len();
+        subscriptions.listeners.retain(|&(s, _)| s != subscription);
+
+        subscriptions.listeners.len() != count
+    }
+
+    /// Return the number of listeners subscribed
+    pub fn len(&self) -> usize {
+        self.lock().listeners.len()
+    }
+
+    /// Tell the listeners that the device has been lost
+    pub fn device_lost(&self) {
+        for &(_, ref listener) in self.lock().listeners.iter() {
+            listener.on_device_lost();
+        }
+    }
+
+    /// Tell the listeners that the swapchain has been recreated
+    ///
+    /// new_extent: The width and height of the new swapchain images
+    pub fn swapchain_recreated(&self, new_extent: (u32, u32)) {
+        for &(_, ref listener) in self.lock().listeners.iter() {
+            listener.on_swapchain_recreated(new_extent);
+        }
+    }
+
+    /// Tell the listeners that a shader has been rebuilt
+    ///
+    /// shader_name: The name of the shader
+    pub fn shader_rebuilt(&self, shader_name: &str) {
+        for &(_, ref listener) in self.lock().listeners.iter() {
+            listener.on_shader_rebuilt(shader_name);
+        }
+    }
+
+    /// Pass a message of the validation layer or loader to the listeners
+    ///
+    /// message: The message
+    pub fn validation_message(&self, message: &DebugMessage) {
+        for &(_, ref listener) in self.lock().listeners.iter() {
+            listener.on_validation_message(message);
+        }
+    }
+}
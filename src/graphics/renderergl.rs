@@ -31,9 +31,11 @@
 use std::collections::HashMap;
 use std::vec::Vec;
 use std::ffi::CStr;
+use std::ffi::CString;
 use std::sync::*;
 use std::boxed::Box;
 use std::any::Any;
+use std::cell::Cell;
 use std::mem;
 use std::ptr;
 use std::os::raw::*;
@@ -47,17 +49,42 @@ use graphics::resources::*;
 use graphics::renderer::*;
 use graphics::shader::*;
 use graphics::shaderglsl::*;
+use graphics::shadervariants::ShaderDefines;
 use graphics::texture::*;
 use graphics::rendertarget::*;
 use graphics::rendertargetgl::*;
-use algebra::matrix::Mat4;
+use graphics::rendergraph::*;
+use graphics::streambuffer::*;
+use graphics::readback::*;
+use graphics::computepacing::*;
+use graphics::frametime::*;
+use graphics::interpolation::*;
+use graphics::bandwidth::*;
+use graphics::versioning::*;
+use graphics::vertexpool::*;
+use graphics::mesh::*;
+use graphics::memoryallocator::MemoryStats;
+use graphics::memoryusage::*;
+use graphics::image::*;
+use graphics::devicefeatures::*;
+use graphics::parity::*;
+use graphics::capturetrigger::*;
+use graphics::stereo::StereoSettings;
+use graphics::perdraw::PerDrawBenchmarkResults;
+use graphics::xr::*;
+use graphics::gputiming::*;
+use graphics::cputiming::*;
+use graphics::rendererevents::*;
+use algebra::matrix::{ClipSpaceConvention, Mat4};
 use algebra::vector::Vec3;
+use algebra::vector::Vec4;
+use misc::logging::*;
 
 macro_rules! gl_check {
     () => {{
         let e = unsafe { gl::GetError() };
         if e != 0 {
-            println!("gl::GetError returned {:?}", e);
+            log_error!("gl::GetError returned {:?}", e);
         }
         debug_assert!(e == 0);
     }}
@@ -67,26 +94,261 @@ macro_rules! gl_check_no_assert {
     () => {{
         let e = unsafe { gl::GetError() };
         if e != 0 {
-            println!("gl::GetError returned {:?}", e);
+            log_error!("gl::GetError returned {:?}", e);
         }
     }}
 }
 
+// The frames of pass timestamp queries in flight, after which a frame's results are read if available
+const TIMING_QUERY_FRAMES: usize = 3;
+
+// GL_NVX_gpu_memory_info postdates the bindings, so its queries are declared here, in KiB
+const GPU_MEMORY_INFO_DEDICATED_VIDMEM_NVX: GLenum = 0x9047;
+const GPU_MEMORY_INFO_CURRENT_AVAILABLE_VIDMEM_NVX: GLenum = 0x9049;
+
+// GL_EXT_texture_filter_anisotropic is not in the core bindings, so its limit is declared here
+const MAX_TEXTURE_MAX_ANISOTROPY_EXT: GLenum = 0x84FF;
+
+static GL_MEMORY_USAGE_INIT: Once = Once::new();
+static mut GL_MEMORY_USAGE: *const Mutex<MemoryUsageTracker> = 0 as *const Mutex<MemoryUsageTracker>;
+
+/// Return the tally of the estimated memory of OpenGL buffers, textures and render buffers
+///
+/// The objects belong to the context rather than the renderer, and textures are created without
+/// reaching it, so they are tallied process-wide, keyed by their object identifier and name.
+pub fn gl_memory_usage() -> &'static Mutex<MemoryUsageTracker> {
+    unsafe {
+        GL_MEMORY_USAGE_INIT.call_once(|| {
+            GL_MEMORY_USAGE = Box::into_raw(Box::new(Mutex::new(MemoryUsageTracker::new())));
+        });
+        &*GL_MEMORY_USAGE
+    }
+}
+
+/// The objects and fixed-function state the shaders last selected in the context
+///
+/// Selecting the shader that is already selected then makes no calls.  Anything else that
+/// binds objects or changes the state selected by shaders resets it.
+pub struct GlSelectedState {
+    pub bound: BoundState,
+    pub render_state: Option<(RenderState, Option<StencilState>)>, // As last applied by select
+}
+
+impl GlSelectedState {
+    /// Forget everything selected, so that the next shader selected applies all of its state
+    pub fn reset(&mut self) {
+        self.bound.reset();
+        self.render_state = None;
+    }
+}
+
+static GL_SELECTED_STATE_INIT: Once = Once::new();
+static mut GL_SELECTED_STATE: *const Mutex<GlSelectedState> = 0 as *const Mutex<GlSelectedState>;
+
+/// Return the state the shaders last selected in the context
+///
+/// Shaders are selected without reaching the renderer, so like the memory tally this is
+/// process-wide.
+pub fn gl_selected_state() -> &'static Mutex<GlSelectedState> {
+    unsafe {
+        GL_SELECTED_STATE_INIT.call_once(|| {
+            GL_SELECTED_STATE = Box::into_raw(Box::new(Mutex::new(GlSelectedState {
+                bound: BoundState::new(),
+                render_state: None,
+            })));
+        });
+        &*GL_SELECTED_STATE
+    }
+}
+
+/// Return the estimated size of a texel, as the driver's storage of it is not reported
+///
+/// Unsized formats are taken to be stored with four components of the data type.
+///
+/// internal_format: The internal format of the texture or render buffer
+/// data_type: The data type of the pixel data it was specified with
+pub fn gl_texel_bytes(internal_format: GLenum, data_type: GLenum) -> u64 {
+    match internal_format {
+        gl::RGBA32F => 16,
+        gl::RGBA16F => 8,
+        gl::R32F | gl::DEPTH24_STENCIL8 | gl::DEPTH_COMPONENT24 | gl::DEPTH_COMPONENT32F => 4,
+        _ if data_type == gl::FLOAT => 16,
+        _ => 4,
+    }
+}
+
 pub struct UniformBufferDesc {
     pub size: usize,
     pub bytes: Vec<u8>,
     pub offsets: HashMap<&'static str, usize>,
     pub strides: HashMap<&'static str, usize>,
+    pub version: ContentVersion, // Bumped by each write to the bytes
+    pub written_by_shaders: bool, // Storage buffers are uploaded whenever asked, over what shaders wrote
+}
+
+/// A mesh retained in buffer objects, with a Vertex Array Object pointing at them
+///
+/// Attribute locations belong to programs, so the attribute pointers are set up again whenever
+/// the mesh is drawn with a different program to the last.
+struct RendererGlMesh {
+    vao: GLuint,
+    vbo: GLuint,
+    ebo: Option<GLuint>,
+    vertex_array_type: VertexArrayType,
+    draw_count: usize, // Vertices or indices
+    program: Cell<GLint>, // That the attribute pointers were set up for
+}
+
+impl RendererGlMesh {
+    /// Create the buffer objects of a mesh and upload its data
+    ///
+    /// The Vertex Array Object is left bound on return.
+    ///
+    /// data: The vertices and optional indices of the mesh, which must be valid
+    fn new(data: &MeshData) -> RendererGlMesh {
+        let mut vao: GLuint = 0;
+        let mut vbo: GLuint = 0;
+        let mut ebo = None;
+        unsafe {
+            gl::GenVertexArrays(1, &mut vao);
+            gl::BindVertexArray(vao);
+
+            gl::GenBuffers(1, &mut vbo);
+            gl::BindBuffer(gl::ARRAY_BUFFER, vbo);
+            gl::BufferData(gl::ARRAY_BUFFER,
+                           data.vertex_bytes() as GLsizeiptr,
+                           data.vertices.as_ptr() as *const c_void,
+                           gl::STATIC_DRAW);
+            gl_memory_usage().lock().unwrap().record((gl::BUFFER, vbo as u64),
+                                                     MemoryCategory::VertexBuffers,
+                                                     data.vertex_bytes() as u64);
+
+            // The element array binding is part of the Vertex Array Object
+            if let Some(ref indices) = data.indices {
+                let mut buffer: GLuint = 0;
+                gl::GenBuffers(1, &mut buffer);
+                gl::BindBuffer(gl::ELEMENT_ARRAY_BUFFER, buffer);
+                gl::BufferData(gl::ELEMENT_ARRAY_BUFFER,
+                               data.index_bytes() as GLsizeiptr,
+                               indices.as_ptr() as *const c_void,
+                               gl::STATIC_DRAW);
+                gl_memory_usage().lock().unwrap().record((gl::BUFFER, buffer as u64),
+                                                         MemoryCategory::VertexBuffers,
+                                                         data.index_bytes() as u64);
+                ebo = Some(buffer);
+            }
+        }
+        gl_check!();
+
+        RendererGlMesh {
+            vao: vao,
+            vbo: vbo,
+            ebo: ebo,
+            vertex_array_type: data.vertex_array_type,
+            draw_count: data.draw_count(),
+            program: Cell::new(-1),
+        }
+    }
+
+    /// Point the attributes of a program at the interleaved vertices, with the mesh's Vertex Array
+    /// Object and vertex buffer bound
+    ///
+    /// The vertex attributes split each vertex's components equally between them.
+    ///
+    /// program: The program to be drawn with
+    /// attribute_names: The names of the program's vertex attributes, in order
+    fn point_attributes(&self, program: GLint, attribute_names: &Vec<&'static str>) {
+        let components_per_vertex = VertexArrayType::components_per_vertex(self.vertex_array_type);
+        let attribute_count = VertexArrayType::attribute_count(self.vertex_array_type);
+        let components = components_per_vertex / attribute_count;
+        for (i, name) in attribute_names.iter().take(attribute_count).enumerate() {
+            unsafe {
+                let attribute = gl::GetAttribLocation(program as GLuint, CString::new(*name).unwrap().as_ptr());
+                if attribute > -1 {
+                    gl::VertexAttribPointer(attribute as GLuint,
+                                            components as GLint,
+                                            gl::FLOAT,
+                                            gl::FALSE as GLboolean, // Whether normalised
+                                            (components_per_vertex * mem::size_of::<GLfloat>()) as GLsizei,
+                                            (i * components * mem::size_of::<GLfloat>()) as *const c_void);
+                    gl::EnableVertexAttribArray(attribute as GLuint);
+                }
+            }
+        }
+
+        self.program.set(program);
+    }
+}
+
+impl Drop for RendererGlMesh {
+    fn drop(&mut self) {
+        let mut memory_usage = gl_memory_usage().lock().unwrap();
+        unsafe {
+            gl::DeleteVertexArrays(1, &self.vao);
+            gl::DeleteBuffers(1, &self.vbo);
+            memory_usage.release((gl::BUFFER, self.vbo as u64));
+            if let Some(ebo) = self.ebo {
+                gl::DeleteBuffers(1, &ebo);
+                memory_usage.release((gl::BUFFER, ebo as u64));
+            }
+        }
+    }
+}
+
+/// An asynchronous readback into a pixel buffer object
+struct ReadbackGl {
+    handle: ReadbackHandle,
+    pbo: GLuint,
+    width: u32,
+    height: u32,
+    frame: u64,
 }
 
 // Note: Fields are public for testing
 pub struct RendererGl {
     vertex_array_type: VertexArrayType,
+    attribute_names: Vec<&'static str>, // Of the current pass's shader
+    meshes: MeshRegistry<RendererGlMesh>,
+
+    frame_number: u64,
+    next_readback_handle: ReadbackHandle,
+    readbacks_in_flight: Vec<ReadbackGl>,
+    readbacks_completed: ReadbackResults,
 
     resource_manager: Arc<Mutex<Box<ResourceManager>>>,
 
     uniform_buffer_descs: HashMap<&'static str, UniformBufferDesc>,
     uniform_buffer_natives: HashMap<&'static str, GLuint>,
+    compute_programs: HashMap<&'static str, GLuint>,
+    compute_pacer: ComputePacer,
+    frame_clock: FrameClock,
+    transform_interpolator: TransformInterpolator,
+    render_graph_schedule: RenderGraphSchedule,
+    stream_rings: HashMap<GLuint, StreamRing>, // Per vertex or instance buffer streamed to
+    gpu_memory_info: bool, // Whether GL_NVX_gpu_memory_info reports the video memory available
+    pending_pass_clear: PassClear, // Made by the next begin_pass, once the pass's target is selected
+    bandwidth_stats: BandwidthStats,
+    skipped_binds: SkippedBinds,
+    pass_queries: Vec<GLuint>, // Timestamp queries for TIMING_QUERY_FRAMES frames of passes
+    passes_timed: Vec<(u64, Vec<&'static str>)>, // Per query frame, the frame number and each timed pass's shader
+    timed_pass: Option<usize>, // The index among the frame's timed passes of the pass being drawn
+    gpu_timings: GpuTimingHistory,
+    cpu_profiler: CpuProfiler,
+    events: RendererEvents,
+
+    pub sample_count: u32,
+    pending_resolve: Option<(GLuint, GLuint, u32, u32, u32)>,
+    framebuffer_size: (u32, u32),
+    render_target_size: Option<(u32, u32, u32)>, // The size and colour attachments of the selected render target
+    pub instance_buffer: GLuint, // Holds the instance data of draw_instanced, for all instanced shaders
+    debug_groups: bool, // Whether passes and objects are labelled for debuggers, at debug_level > 0
+    features: DeviceFeatures, // The requested features the context offers
+    limits: DeviceLimits, // Queried when the renderer is created
+    polygon_mode: PolygonMode, // Applied to the depth tested shaders
+    stereo_settings: StereoSettings, // Read by render_stereo_eyes and render_stereo_composite
+    per_draw_benchmark: Option<PerDrawBenchmarkResults>, // Reported by parity_report
+    triangle_capacity: usize, // Of each thread's data, to which the vertex data uploads are sized
+    capture_trigger: CaptureTrigger, // Attached when launched from RenderDoc, as the context already exists
 
     max_threads: usize,
     threaddata_arcs: Vec<Arc<Mutex<Box<ThreadData>>>>,
@@ -97,47 +359,50 @@ impl RendererGl {
     ///
     /// debug_level: The debug level
     /// max_threads: The maximum number of threads
+    /// config: Optional renderer features, of which the sample count and feature request apply to OpenGL
     /// window: The GLFW Window object
     /// resource_manager: The shader resource manager object
     /// threaddata_arcs: A vector of Arcs encapsulating ThreadData structures
     pub fn new(debug_level: u32,
                max_threads: usize,
+               config: &RendererConfig,
                window: &mut glfw::Window,
                resource_manager: &Arc<Mutex<Box<ResourceManager>>>,
                threaddata_arcs: Vec<Arc<Mutex<Box<ThreadData>>>>)
                -> RendererGl {
+        set_log_level(LogLevel::from_debug_level(debug_level));
         gl::load_with(|symbol| window.get_proc_address(symbol) as *const _);
 
-        if debug_level > 0 {
+        if log_enabled(LogLevel::Info) {
             unsafe {
                 let glven = CStr::from_ptr(gl::GetString(gl::VENDOR) as *const i8)
                     .to_string_lossy()
                     .into_owned();
-                println!("GL_VENDOR: {}", glven);
+                log_info!("GL_VENDOR: {}", glven);
                 let glren = CStr::from_ptr(gl::GetString(gl::RENDERER) as *const i8)
                     .to_string_lossy()
                     .into_owned();
-                println!("GL_RENDERER: {}", glren);
+                log_info!("GL_RENDERER: {}", glren);
                 let glver = CStr::from_ptr(gl::GetString(gl::VERSION) as *const i8)
                     .to_string_lossy()
                     .into_owned();
-                println!("GL_VERSION: {}", glver);
+                log_info!("GL_VERSION: {}", glver);
                 let slver = CStr::from_ptr(gl::GetString(gl::SHADING_LANGUAGE_VERSION) as *const i8)
                     .to_string_lossy()
                     .into_owned();
-                println!("GL_SHADING_LANGUAGE_VERSION: {}", slver);
+                log_info!("GL_SHADING_LANGUAGE_VERSION: {}", slver);
 
                 let mut val: GLint = -1;
                 gl::GetIntegerv(gl::MAX_UNIFORM_BUFFER_BINDINGS, &mut val);
-                println!("GL_MAX_UNIFORM_BUFFER_BINDINGS: {}", val);
+                log_info!("GL_MAX_UNIFORM_BUFFER_BINDINGS: {}", val);
                 gl::GetIntegerv(gl::MAX_UNIFORM_BLOCK_SIZE, &mut val);
-                println!("GL_MAX_UNIFORM_BLOCK_SIZE: {}", val);
+                log_info!("GL_MAX_UNIFORM_BLOCK_SIZE: {}", val);
                 gl::GetIntegerv(gl::MAX_VERTEX_UNIFORM_BLOCKS, &mut val);
-                println!("GL_MAX_VERTEX_UNIFORM_BLOCKS: {}", val);
+                log_info!("GL_MAX_VERTEX_UNIFORM_BLOCKS: {}", val);
                 gl::GetIntegerv(gl::MAX_FRAGMENT_UNIFORM_BLOCKS, &mut val);
-                println!("GL_MAX_FRAGMENT_UNIFORM_BLOCKS: {}", val);
+                log_info!("GL_MAX_FRAGMENT_UNIFORM_BLOCKS: {}", val);
                 gl::GetIntegerv(gl::MAX_GEOMETRY_UNIFORM_BLOCKS, &mut val);
-                println!("GL_MAX_GEOMETRY_UNIFORM_BLOCKS: {}", val);
+                log_info!("GL_MAX_GEOMETRY_UNIFORM_BLOCKS: {}", val);
             }
         }
 
@@ -155,16 +420,331 @@ impl RendererGl {
             uniform_buffer_natives.insert(block_name, ubo_handle);
         }
 
+        // Label the passes and objects for debuggers, where KHR_debug is available
+        //
+        let debug_groups = debug_level > 0 && gl::PushDebugGroup::is_loaded() && gl::ObjectLabel::is_loaded();
+        if debug_groups {
+            for (block_name, ubo_handle) in uniform_buffer_natives.iter() {
+                RendererGl::label_object(gl::BUFFER, *ubo_handle, block_name);
+            }
+        }
+
+        // Clamp the sample count to what render buffers support
+        //
+        let mut max_samples: GLint = 1;
+        unsafe {
+            gl::GetIntegerv(gl::MAX_SAMPLES, &mut max_samples);
+        }
+        let sample_count = if config.sample_count > max_samples.max(1) as u32 {
+            log_warning!("{} samples per pixel are not supported, using {}",
+                         config.sample_count,
+                         max_samples);
+            max_samples.max(1) as u32
+        } else {
+            config.sample_count.max(1)
+        };
+
+        // The context is created by GLFW, so the features cannot be enabled selectively and the
+        // required ones are only checked and reported
+        //
+        let available_features = RendererGl::available_features();
+        let features = match config.features.resolve(&available_features) {
+            Ok(f) => f,
+            Err(missing) => {
+                log_warning!("The OpenGL context does not support the required features {:?}", missing);
+                config.features.required.union(&config.features.optional).intersection(&available_features)
+            }
+        };
+        log_info!("Granted features {:?}", features);
+        resource_manager.lock().unwrap().select_shader_stages(&features);
+        let limits = RendererGl::device_limits(&features);
+
+        // Shaders write linear colour, which the default framebuffer encodes for display when it
+        // is sRGB-capable, as asked for when the window was created
+        //
+        if config.srgb_output {
+            let mut encoding: GLint = gl::LINEAR as GLint;
+            unsafe {
+                gl::GetFramebufferAttachmentParameteriv(gl::DRAW_FRAMEBUFFER,
+                                                        gl::BACK_LEFT,
+                                                        gl::FRAMEBUFFER_ATTACHMENT_COLOR_ENCODING,
+                                                        &mut encoding);
+                gl::Enable(gl::FRAMEBUFFER_SRGB);
+            }
+            if encoding != gl::SRGB as GLint {
+                log_warning!("The default framebuffer is not sRGB-capable, so the output will not be encoded");
+            }
+        }
+
+        let gpu_memory_info = RendererGl::has_extension("GL_NVX_gpu_memory_info");
+
+        // The instanced shaders point their per-instance attributes at this buffer when built
+        let mut instance_buffer: GLuint = 0;
+        unsafe {
+            gl::GenBuffers(1, &mut instance_buffer);
+        }
+
+        // Each pass of the last few frames has a pair of timestamp queries
+        let mut pass_queries: Vec<GLuint> = vec![0; TIMING_QUERY_FRAMES * 2 * MAX_TIMED_PASSES];
+        unsafe {
+            gl::GenQueries(pass_queries.len() as GLsizei, pass_queries.as_mut_ptr());
+        }
+
         RendererGl {
             max_threads: max_threads,
             threaddata_arcs: threaddata_arcs,
 
+            sample_count: sample_count,
+            pending_resolve: None,
+            render_target_size: None,
+            instance_buffer: instance_buffer,
+            debug_groups: debug_groups,
+            features: features,
+            limits: limits,
+            polygon_mode: PolygonMode::Fill,
+            stereo_settings: config.stereo,
+            per_draw_benchmark: None,
+            triangle_capacity: config.triangle_capacity.max(1).min(TRIANGLE_ARRAY_MAX_SIZE),
+            capture_trigger: CaptureTrigger::new(false),
+            framebuffer_size: {
+                let (width, height) = window.get_framebuffer_size();
+                (width as u32, height as u32)
+            },
+
             uniform_buffer_descs: HashMap::new(),
             uniform_buffer_natives: uniform_buffer_natives,
+            compute_programs: HashMap::new(),
+            compute_pacer: ComputePacer::new(DEFAULT_COMPUTE_BUDGET_MS),
+            frame_clock: FrameClock::new(),
+            transform_interpolator: TransformInterpolator::new(),
+            render_graph_schedule: RenderGraphSchedule::default(),
+            stream_rings: HashMap::new(),
+            gpu_memory_info: gpu_memory_info,
+            pending_pass_clear: PassClear::default(),
+            bandwidth_stats: BandwidthStats::new(),
+            skipped_binds: SkippedBinds::new(),
+            pass_queries: pass_queries,
+            passes_timed: vec![(0, vec![]); TIMING_QUERY_FRAMES],
+            timed_pass: None,
+            gpu_timings: GpuTimingHistory::new(GPU_TIMING_HISTORY_FRAMES),
+            cpu_profiler: CpuProfiler::new(CPU_TIMING_HISTORY_FRAMES),
+            events: RendererEvents::new(),
 
             resource_manager: resource_manager.clone(),
 
             vertex_array_type: VertexArrayType::F3F3F3,
+            attribute_names: vec![],
+            meshes: MeshRegistry::new(0), // The driver keeps deleted objects until draws using them complete
+
+            frame_number: 0,
+            next_readback_handle: 0,
+            readbacks_in_flight: vec![],
+            readbacks_completed: ReadbackResults::new(),
+        }
+    }
+
+    /// Return a timestamp query of a frame's passes
+    ///
+    /// slot: The frame's slot of TIMING_QUERY_FRAMES
+    /// index: The query's index within the frame, two for each pass
+    fn pass_query(&self, slot: usize, index: usize) -> GLuint {
+        self.pass_queries[slot * 2 * MAX_TIMED_PASSES + index]
+    }
+
+    /// Collect the pass timings of the frame that last used this frame's queries into the history
+    ///
+    /// Only the results the queries report as available are read, so this never waits.  The
+    /// frame number of the new frame is noted.
+    fn harvest_pass_timings(&mut self) {
+        let slot = self.frame_number as usize % TIMING_QUERY_FRAMES;
+        let (frame, shader_names) = mem::replace(&mut self.passes_timed[slot], (self.frame_number, vec![]));
+        if shader_names.is_empty() {
+            return;
+        }
+
+        let timestamps: Vec<Option<u64>> = (0..2 * shader_names.len())
+            .map(|index| {
+                let query = self.pass_query(slot, index);
+                let mut available: GLint = 0;
+                let mut timestamp: GLuint64 = 0;
+                unsafe {
+                    gl::GetQueryObjectiv(query, gl::QUERY_RESULT_AVAILABLE, &mut available);
+                    if available == 0 {
+                        return None;
+                    }
+                    gl::GetQueryObjectui64v(query, gl::QUERY_RESULT, &mut timestamp);
+                }
+                Some(timestamp)
+            })
+            .collect();
+
+        // OpenGL timestamps are in nanoseconds
+        self.gpu_timings.record(FrameTimings::from_timestamps(frame, &shader_names, &timestamps, 1.0));
+    }
+
+    /// Return the names of the extensions of the current context
+    fn extension_names() -> Vec<String> {
+        let mut extension_count: GLint = 0;
+        unsafe {
+            gl::GetIntegerv(gl::NUM_EXTENSIONS, &mut extension_count);
+        }
+
+        (0..extension_count)
+            .map(|i| unsafe {
+                CStr::from_ptr(gl::GetStringi(gl::EXTENSIONS, i as GLuint) as *const i8).to_string_lossy().into_owned()
+            })
+            .collect()
+    }
+
+    /// Return whether the current context has an extension
+    ///
+    /// name: The name of the extension
+    fn has_extension(name: &str) -> bool {
+        RendererGl::extension_names().iter().any(|e| e == name)
+    }
+
+    /// Return the features of the current context, from its version and extensions
+    fn available_features() -> DeviceFeatures {
+        let mut major: GLint = 0;
+        let mut minor: GLint = 0;
+        let mut line_widths: [GLfloat; 2] = [1.0, 1.0];
+        let mut point_sizes: [GLfloat; 2] = [1.0, 1.0];
+        unsafe {
+            gl::GetIntegerv(gl::MAJOR_VERSION, &mut major);
+            gl::GetIntegerv(gl::MINOR_VERSION, &mut minor);
+            gl::GetFloatv(gl::ALIASED_LINE_WIDTH_RANGE, line_widths.as_mut_ptr());
+            gl::GetFloatv(gl::POINT_SIZE_RANGE, point_sizes.as_mut_ptr());
+        }
+
+        let extensions = RendererGl::extension_names();
+        let has_extension = |name: &str| extensions.iter().any(|e| e == name);
+        let version = (major, minor);
+
+        DeviceFeatures {
+            tessellation_shader: version >= (4, 0),
+            geometry_shader: version >= (3, 2),
+            fill_mode_non_solid: true,
+            wide_lines: line_widths[1] > 1.0,
+            large_points: point_sizes[1] > 1.0,
+            sampler_anisotropy: version >= (4, 6) || has_extension("GL_EXT_texture_filter_anisotropic") ||
+                                has_extension("GL_ARB_texture_filter_anisotropic"),
+            depth_clamp: version >= (3, 2),
+            independent_blend: version >= (4, 0),
+            multi_draw_indirect: version >= (4, 3),
+            texture_compression_bc: has_extension("GL_EXT_texture_compression_s3tc"),
+            pipeline_statistics_query: version >= (4, 6) || has_extension("GL_ARB_pipeline_statistics_query"),
+            vertex_pipeline_stores_and_atomics: version >= (4, 3),
+            fragment_stores_and_atomics: version >= (4, 3),
+            shader_float64: version >= (4, 0),
+        }
+    }
+
+    /// Return the limits of the current context, from its implementation-dependent values
+    ///
+    /// Images are limited by the texture sizes, and framebuffers by the render buffer size
+    /// before OpenGL 4.3.  Push constants are plain uniforms, limited by the default block of the
+    /// vertex and fragment stages.
+    ///
+    /// features: The features granted, for whether anisotropic filtering is available
+    fn device_limits(features: &DeviceFeatures) -> DeviceLimits {
+        let get_integer = |name: GLenum| {
+            let mut value: GLint = 0;
+            unsafe {
+                gl::GetIntegerv(name, &mut value);
+            }
+            value.max(0) as u32
+        };
+        let get_indexed_integers = |name: GLenum| {
+            let mut values = [0u32; 3];
+            for (i, v) in values.iter_mut().enumerate() {
+                let mut value: GLint = 0;
+                unsafe {
+                    gl::GetIntegeri_v(name, i as GLuint, &mut value);
+                }
+                *v = value.max(0) as u32;
+            }
+            values
+        };
+
+        let version = (get_integer(gl::MAJOR_VERSION), get_integer(gl::MINOR_VERSION));
+        let (max_framebuffer_width, max_framebuffer_height) = if version >= (4, 3) {
+            (get_integer(gl::MAX_FRAMEBUFFER_WIDTH), get_integer(gl::MAX_FRAMEBUFFER_HEIGHT))
+        } else {
+            (get_integer(gl::MAX_RENDERBUFFER_SIZE), get_integer(gl::MAX_RENDERBUFFER_SIZE))
+        };
+        let compute = gl::DispatchCompute::is_loaded();
+        let uniform_components = get_integer(gl::MAX_VERTEX_UNIFORM_COMPONENTS)
+            .min(get_integer(gl::MAX_FRAGMENT_UNIFORM_COMPONENTS));
+
+        let mut max_sampler_anisotropy: GLfloat = 1.0;
+        if features.sampler_anisotropy {
+            unsafe {
+                gl::GetFloatv(MAX_TEXTURE_MAX_ANISOTROPY_EXT, &mut max_sampler_anisotropy);
+            }
+        }
+
+        let limits = DeviceLimits {
+            max_image_dimension_2d: get_integer(gl::MAX_TEXTURE_SIZE),
+            max_image_dimension_3d: get_integer(gl::MAX_3D_TEXTURE_SIZE),
+            max_image_dimension_cube: get_integer(gl::MAX_CUBE_MAP_TEXTURE_SIZE),
+            max_image_array_layers: get_integer(gl::MAX_ARRAY_TEXTURE_LAYERS),
+            max_framebuffer_width: max_framebuffer_width,
+            max_framebuffer_height: max_framebuffer_height,
+            max_colour_attachments: get_integer(gl::MAX_COLOR_ATTACHMENTS),
+            max_uniform_buffer_range: get_integer(gl::MAX_UNIFORM_BLOCK_SIZE),
+            min_uniform_buffer_offset_alignment: get_integer(gl::UNIFORM_BUFFER_OFFSET_ALIGNMENT) as u64,
+            max_storage_buffer_range: if version >= (4, 3) { get_integer(gl::MAX_SHADER_STORAGE_BLOCK_SIZE) } else { 0 },
+            max_push_constants_size: 4 * uniform_components,
+            max_vertex_input_attributes: get_integer(gl::MAX_VERTEX_ATTRIBS),
+            max_compute_work_group_count: if compute { get_indexed_integers(gl::MAX_COMPUTE_WORK_GROUP_COUNT) } else { [0; 3] },
+            max_compute_work_group_size: if compute { get_indexed_integers(gl::MAX_COMPUTE_WORK_GROUP_SIZE) } else { [0; 3] },
+            max_compute_work_group_invocations: if compute { get_integer(gl::MAX_COMPUTE_WORK_GROUP_INVOCATIONS) } else { 0 },
+            max_sampler_anisotropy: max_sampler_anisotropy.max(1.0),
+        };
+        gl_check_no_assert!();
+
+        limits
+    }
+
+    /// Name an object for debuggers
+    ///
+    /// identifier: The namespace of the object, e.g. GL_BUFFER
+    /// object: The object's name
+    /// label: The label to give the object
+    fn label_object(identifier: GLenum, object: GLuint, label: &str) {
+        unsafe {
+            gl::ObjectLabel(identifier, object, label.len() as GLsizei, label.as_ptr() as *const GLchar);
+        }
+    }
+
+    /// Resolve the multisampled render target selected last, if any, into its textures
+    ///
+    /// A blit only copies the read buffer, so each colour attachment is resolved in turn and the
+    /// draw buffers of the resolve framebuffer are restored afterwards.
+    fn resolve_render_target(&mut self) {
+        if let Some((fbo, resolve_fbo, width, height, colour_attachments)) = self.pending_resolve.take() {
+            let draw_buffers: Vec<GLenum> = (0..colour_attachments).map(|i| gl::COLOR_ATTACHMENT0 + i).collect();
+            unsafe {
+                gl::BindFramebuffer(gl::READ_FRAMEBUFFER, fbo);
+                gl::BindFramebuffer(gl::DRAW_FRAMEBUFFER, resolve_fbo);
+                for draw_buffer in draw_buffers.iter() {
+                    gl::ReadBuffer(*draw_buffer);
+                    gl::DrawBuffers(1, draw_buffer);
+                    gl::BlitFramebuffer(0,
+                                        0,
+                                        width as GLint,
+                                        height as GLint,
+                                        0,
+                                        0,
+                                        width as GLint,
+                                        height as GLint,
+                                        gl::COLOR_BUFFER_BIT,
+                                        gl::NEAREST);
+                }
+                gl::ReadBuffer(gl::COLOR_ATTACHMENT0);
+                gl::DrawBuffers(draw_buffers.len() as GLsizei, draw_buffers.as_ptr());
+            }
+            gl_check!();
         }
     }
 
@@ -175,20 +755,146 @@ impl RendererGl {
         self.uniform_buffer_natives[buffer_name]
     }
 
+    /// Force the next synchronisation of the named uniform buffer to upload its contents
+    ///
+    /// This is needed when the buffer's storage has been reallocated, e.g. by rebuilding a shader.
+    ///
+    /// buffer_name: The name of the buffer
+    pub fn invalidate_uniform_buffer(&self, buffer_name: &str) {
+        if let Some(buffer) = self.uniform_buffer_descs.get(buffer_name) {
+            buffer.version.bump();
+        }
+    }
+
+    /// Stream data into the buffer bound to GL_ARRAY_BUFFER, returning the offset written at
+    ///
+    /// The data is written unsynchronised after what was streamed before, so the draws still
+    /// reading that are not waited on.  Once the buffer is full its storage is orphaned, and
+    /// the driver hands out fresh storage while the draws using the old storage complete.
+    ///
+    /// data: The floats to write
+    /// stride: The number of floats of each vertex or instance, which the offset is a multiple of
+    fn stream_to_bound_buffer(&mut self, data: &[f32], stride: usize) -> usize {
+        let bytes = data.len() * mem::size_of::<GLfloat>();
+        let mut buffer: GLint = 0;
+        let mut buffer_size: GLint = 0;
+        unsafe {
+            gl::GetIntegerv(gl::ARRAY_BUFFER_BINDING, &mut buffer);
+            gl::GetBufferParameteriv(gl::ARRAY_BUFFER, gl::BUFFER_SIZE, &mut buffer_size);
+        }
+
+        // Shaders that are rebuilt get new vertex buffers, whose names may be reused
+        let ring = self.stream_rings.entry(buffer as GLuint).or_insert_with(|| StreamRing::new(DEFAULT_STREAM_BUFFER_SIZE));
+        if (buffer_size as usize) < ring.capacity() {
+            ring.reset();
+        }
+        let write = ring.write(bytes, stride * mem::size_of::<GLfloat>());
+
+        unsafe {
+            if let Some(size) = write.orphan {
+                gl::BufferData(gl::ARRAY_BUFFER, size as GLsizeiptr, ptr::null(), gl::STREAM_DRAW);
+                gl_memory_usage().lock().unwrap().record((gl::BUFFER, buffer as u64), MemoryCategory::VertexBuffers, size as u64);
+            }
+
+            let dst = gl::MapBufferRange(gl::ARRAY_BUFFER,
+                                         write.offset as GLintptr,
+                                         bytes as GLsizeiptr,
+                                         gl::MAP_WRITE_BIT | gl::MAP_INVALIDATE_RANGE_BIT | gl::MAP_UNSYNCHRONIZED_BIT);
+            if dst.is_null() {
+                log_error!("Unable to map {} bytes of vertex buffer {} for streaming", bytes, buffer);
+            } else {
+                ptr::copy_nonoverlapping(data.as_ptr() as *const u8, dst as *mut u8, bytes);
+                gl::UnmapBuffer(gl::ARRAY_BUFFER);
+            }
+        }
+        gl_check!();
+
+        write.offset
+    }
+
+    /// Return the location of the named uniform in the current program, or -1 if it has none
+    ///
+    /// name: The name of the uniform
+    fn current_program_uniform_location(name: &str) -> GLint {
+        let mut program: GLint = 0;
+        unsafe {
+            gl::GetIntegerv(gl::CURRENT_PROGRAM, &mut program);
+            if program == 0 {
+                return -1;
+            }
+            gl::GetUniformLocation(program as GLuint, CString::new(name).unwrap().as_ptr())
+        }
+    }
+
     /// Continue initialising OpenGL structures to the point where stuff can be rendered
     ///
     /// shaders: The shaders to continue setting up
-    pub fn setup(&mut self, shaders: &HashMap<&'static str, &ShaderGlsl>) {
+    pub fn setup(&mut self, shaders: &HashMap<&'static str, &ShaderGlsl>) -> Result<(), RendererError> {
         let res_manager = self.resource_manager.lock().unwrap();
 
+        // OpenGL draws each pass to whatever is bound, but the declarations are checked so that
+        // mistakes show up with either renderer
+        //
+        if let Err(e) = res_manager.validate_render_passes() {
+            log_error!("Invalid render passes: {}", e);
+            return Err(RendererError::InvalidRenderPasses(e));
+        }
+
+        self.render_graph_schedule = match res_manager.render_graph.compile() {
+            Ok(schedule) => schedule,
+            Err(e) => {
+                log_error!("Unable to schedule the render graph: {}", e);
+                RenderGraphSchedule::default()
+            }
+        };
+
         for sh in shaders.iter() {
             let (shader_name, shader) = sh;
+            if shader.is_using_error_program() {
+                continue;
+            }
             let ref shader_spec = res_manager.shader_specs[shader_name];
             for uniform_block_name in shader_spec.uniform_block_names.iter() {
-                let buffer_descriptor = shader.get_uniform_buffer_descriptor_from_uniform_block(uniform_block_name);
+                let mut buffer_descriptor = shader.get_uniform_buffer_descriptor_from_uniform_block(uniform_block_name);
+                buffer_descriptor.written_by_shaders = res_manager.uniform_block_specs[uniform_block_name].block_type ==
+                                                       UniformType::StorageBuffer;
                 self.uniform_buffer_descs.insert(uniform_block_name, buffer_descriptor);
             }
+            if shader_spec.is_compute() {
+                self.compute_programs.insert(shader_name, shader.get_program());
+            }
+            if self.debug_groups {
+                RendererGl::label_object(gl::PROGRAM, shader.get_program(), shader_name);
+            }
+        }
+
+        // Blocks only used by shaders that failed to build still need somewhere to accumulate
+        // their contents, so fall back to the layout calculated from the member types or
+        // reflected from the SPIR-V
+        //
+        for sh in shaders.iter() {
+            let (shader_name, _) = sh;
+            let ref shader_spec = res_manager.shader_specs[shader_name];
+            for uniform_block_name in shader_spec.uniform_block_names.iter() {
+                if self.uniform_buffer_descs.contains_key(uniform_block_name) {
+                    continue;
+                }
+
+                let block = res_manager.uniform_block_specs[uniform_block_name].laid_out();
+                let mut buffer_descriptor = UniformBufferDesc {
+                    size: block.size,
+                    bytes: Vec::with_capacity(block.size),
+                    offsets: block.uniforms.iter().map(|uniform| (uniform.name, uniform.offset)).collect(),
+                    strides: block.uniforms.iter().map(|uniform| (uniform.name, uniform.stride)).collect(),
+                    version: ContentVersion::new(),
+                    written_by_shaders: block.block_type == UniformType::StorageBuffer,
+                };
+                buffer_descriptor.bytes.resize(block.size, 0);
+                self.uniform_buffer_descs.insert(*uniform_block_name, buffer_descriptor);
+            }
         }
+
+        Ok(())
     }
 }
 
@@ -206,6 +912,16 @@ impl Renderer for RendererGl {
         RendererType::RendererGl
     }
 
+    /// Return the clip space convention that projection matrices must follow
+    fn clip_space_convention(&self) -> ClipSpaceConvention {
+        ClipSpaceConvention::OpenGl
+    }
+
+    /// Destroy the renderer's API objects in a defined order
+    ///
+    /// The OpenGL objects belong to the context, which is destroyed with the window.
+    fn shutdown(&mut self) {}
+
     /// Obtain an Arc for the ThreadData structure for the specified thread
     fn get_threaddata(&self, thr: usize) -> Arc<Mutex<Box<ThreadData>>> {
         self.threaddata_arcs[thr].clone()
@@ -222,7 +938,8 @@ impl Renderer for RendererGl {
     /// textures: A map of the textures to set up, keyed by name
     fn finish_resource_initialisation(&mut self,
                                       shaders: &HashMap<&'static str, &Box<Shader>>,
-                                      _: &HashMap<&'static str, &Box<Texture>>) {
+                                      _: &HashMap<&'static str, &Box<Texture>>)
+                                      -> Result<(), RendererError> {
         let mut renderer_gl: &mut RendererGl = match self.as_any_mut().downcast_mut::<RendererGl>() {
             Some(r) => r,
             None => panic!("Unexpected runtime type"),
@@ -237,14 +954,7 @@ impl Renderer for RendererGl {
             };
         }
 
-        renderer_gl.setup(&shaders_gl);
-    }
-
-    /// Clear the depth buffer before starting rendering
-    fn clear_depth_buffer(&self) {
-        unsafe {
-            gl::Clear(gl::DEPTH_BUFFER_BIT);
-        }
+        renderer_gl.setup(&shaders_gl)
     }
 
     /// Convert a renderer primitive type to an OpenGL primitive type
@@ -252,6 +962,9 @@ impl Renderer for RendererGl {
         match primitive_type {
             PrimitiveType::PrimitiveTriangles => gl::TRIANGLES,
             PrimitiveType::PrimitivePatches => gl::PATCHES,
+            PrimitiveType::PrimitiveLines => gl::LINES,
+            PrimitiveType::PrimitiveLineStrip => gl::LINE_STRIP,
+            PrimitiveType::PrimitivePoints => gl::POINTS,
         }
     }
 
@@ -262,6 +975,7 @@ impl Renderer for RendererGl {
     /// value: The value to set for the uniform
     fn set_uniform_buffer_int(&self, buffer_name: &str, uniform_name: &str, value: i32) {
         let ref buffer = self.uniform_buffer_descs[buffer_name];
+        buffer.version.bump();
         let offset = buffer.offsets[uniform_name];
         unsafe {
             let dst: *const u8 = buffer.bytes.as_ptr().offset(offset as isize);
@@ -277,6 +991,7 @@ impl Renderer for RendererGl {
     /// value: The value to set for the uniform
     fn set_uniform_buffer_float(&self, buffer_name: &str, uniform_name: &str, value: f32) {
         let ref buffer = self.uniform_buffer_descs[buffer_name];
+        buffer.version.bump();
         let offset = buffer.offsets[uniform_name];
         unsafe {
             let dst: *const u8 = buffer.bytes.as_ptr().offset(offset as isize);
@@ -292,6 +1007,7 @@ impl Renderer for RendererGl {
     /// value: The value to set for the uniform
     fn set_uniform_buffer_vec3(&self, buffer_name: &str, uniform_name: &str, value: &Vec3<f32>) {
         let ref buffer = self.uniform_buffer_descs[buffer_name];
+        buffer.version.bump();
         let offset = buffer.offsets[uniform_name];
         debug_assert!((offset + 3 * mem::size_of::<f32>()) <= buffer.size);
         unsafe {
@@ -309,6 +1025,7 @@ impl Renderer for RendererGl {
     /// matrix: The value to set for the uniform
     fn set_uniform_buffer_matrix(&self, buffer_name: &str, uniform_name: &str, matrix: &Mat4<f32>) {
         let ref buffer = self.uniform_buffer_descs[buffer_name];
+        buffer.version.bump();
         let offset = buffer.offsets[uniform_name];
         debug_assert!((offset + 16 * mem::size_of::<f32>()) <= buffer.size);
         unsafe {
@@ -319,6 +1036,28 @@ impl Renderer for RendererGl {
         }
     }
 
+    /// Set consecutive elements of an array of matrices in the memory put aside for the named
+    /// uniform buffer
+    ///
+    /// buffer_name: The name of the uniform buffer to contain the new values
+    /// uniform_name: The name of the array member
+    /// matrices: The matrices to set, from the first element
+    fn set_uniform_buffer_matrix_array(&self, buffer_name: &str, uniform_name: &str, matrices: &[Mat4<f32>]) {
+        let ref buffer = self.uniform_buffer_descs[buffer_name];
+        buffer.version.bump();
+        let offset = buffer.offsets[uniform_name];
+        let stride = buffer.strides[uniform_name].max(16 * mem::size_of::<f32>());
+        debug_assert!((offset + matrices.len() * stride) <= buffer.size);
+        unsafe {
+            for (i, matrix) in matrices.iter().enumerate() {
+                let dst: *const u8 = buffer.bytes.as_ptr().offset((i * stride + offset) as isize);
+                let dst_f32 = dst as *mut f32;
+                let src: *const f32 = mem::transmute(matrix);
+                ptr::copy_nonoverlapping(src, dst_f32, 16);
+            }
+        }
+    }
+
     /// Set a floating point vector in part of the memory put aside for the named uniform buffer
     ///
     /// buffer_name: The name of the uniform buffer to contain the new value
@@ -326,6 +1065,7 @@ impl Renderer for RendererGl {
     /// vector: The vector to set for the uniform
     fn set_uniform_buffer_float_vector(&self, buffer_name: &str, uniform_name: &str, vector: &Vec<f32>) {
         let ref buffer = self.uniform_buffer_descs[buffer_name];
+        buffer.version.bump();
         let offset = buffer.offsets[uniform_name];
         let stride = buffer.strides[uniform_name];
         if stride == 0 || stride == 4 {
@@ -355,6 +1095,11 @@ impl Renderer for RendererGl {
     /// buffer_name: The name of the uniform buffer to be configuring
     fn synchronise_uniform_buffer(&self, buffer_name: &str) {
         let ref buffer = self.uniform_buffer_descs[buffer_name];
+        if !buffer.written_by_shaders && !buffer.version.synchronise() {
+            self.skipped_binds.skip();
+            return;
+        }
+
         unsafe {
             gl::BindBuffer(gl::UNIFORM_BUFFER, self.uniform_buffer_natives[buffer_name]);
             let src: *const c_void = mem::transmute(buffer.bytes.as_ptr());
@@ -363,64 +1108,1085 @@ impl Renderer for RendererGl {
         }
     }
 
-    /// Flip the back buffer to the front
+    /// Set an integer uniform on the current program in place of a push constant
     ///
-    /// context: The GLFW context
-    fn flip(&self, context: &mut glfw::Context) {
-        (*context).swap_buffers();
-    }
-
-    /// Begin rendering a new frame
-    fn begin_frame(&mut self) {
-        // Nada
+    /// thr: The thread setting the value, unused on OpenGL
+    /// name: The name of the uniform
+    /// value: The value to set
+    fn set_push_constant_int(&self, _: usize, name: &str, value: i32) {
+        let location = RendererGl::current_program_uniform_location(name);
+        if location >= 0 {
+            unsafe {
+                gl::Uniform1i(location, value);
+            }
+        }
     }
 
-    /// Terminate rendering a new frame
-    fn end_frame(&mut self) {
-        gl_check_no_assert!();
+    /// Set a floating point uniform on the current program in place of a push constant
+    ///
+    /// thr: The thread setting the value, unused on OpenGL
+    /// name: The name of the uniform
+    /// value: The value to set
+    fn set_push_constant_float(&self, _: usize, name: &str, value: f32) {
+        let location = RendererGl::current_program_uniform_location(name);
+        if location >= 0 {
+            unsafe {
+                gl::Uniform1f(location, value);
+            }
+        }
     }
 
-    /// Initiate a render pass
-    fn begin_pass(&mut self, shader_name: &'static str) {
-        {
-            let res_manager = self.resource_manager.lock().unwrap();
-            self.vertex_array_type = res_manager.shader_specs[shader_name].vertex_array_type;
+    /// Set a vector uniform on the current program in place of a push constant
+    ///
+    /// thr: The thread setting the value, unused on OpenGL
+    /// name: The name of the uniform
+    /// value: The value to set
+    fn set_push_constant_vec3(&self, _: usize, name: &str, value: &Vec3<f32>) {
+        let location = RendererGl::current_program_uniform_location(name);
+        if location >= 0 {
+            unsafe {
+                gl::Uniform3f(location, value.x, value.y, value.z);
+            }
         }
     }
 
-    /// Terminate a render pass
-    fn end_pass(&mut self) {
-        // Nada
+    /// Set a matrix uniform on the current program in place of a push constant
+    ///
+    /// thr: The thread setting the value, unused on OpenGL
+    /// name: The name of the uniform
+    /// matrix: The value to set
+    fn set_push_constant_matrix(&self, _: usize, name: &str, matrix: &Mat4<f32>) {
+        let location = RendererGl::current_program_uniform_location(name);
+        if location >= 0 {
+            unsafe {
+                let src: *const f32 = mem::transmute(matrix);
+                gl::UniformMatrix4fv(location, 1, gl::FALSE, src);
+            }
+        }
     }
 
-    /// Select the specified render target to render to
+    /// Bind a texture to the unit of the named sampler on the current program
     ///
-    /// num: The texture number to bind the render target texture to
-    /// render_target: The render target to select
-    fn select_render_target(&mut self, num: i32, render_target: &mut RenderTarget) {
-        {
-            let target_gl = match render_target.as_any_mut().downcast_mut::<RenderTargetGl>() {
-                Some(r) => r,
-                None => panic!("Unexpected runtime type"),
-            };
-
+    /// thr: The thread switching the texture, unused on OpenGL
+    /// name: The name of the sampler uniform
+    /// texture: The texture to sample
+    fn set_draw_texture(&self, _: usize, name: &str, texture: &Texture) {
+        let location = RendererGl::current_program_uniform_location(name);
+        if location >= 0 {
+            let mut program: GLint = 0;
+            let mut unit: GLint = 0;
             unsafe {
-                gl::BindFramebuffer(gl::FRAMEBUFFER, target_gl.get_fbo());
+                gl::GetIntegerv(gl::CURRENT_PROGRAM, &mut program);
+                gl::GetUniformiv(program as GLuint, location, &mut unit);
             }
+            texture.bind(unit);
+            gl_check!();
         }
-
-        render_target.bind_texture(num);
     }
 
-    /// Select no render target
-    fn deselect_render_target(&mut self) {
-        unsafe {
-            gl::BindFramebuffer(gl::FRAMEBUFFER, 0);
+    /// Flip the back buffer to the front
+    ///
+    /// context: The GLFW context
+    fn flip(&self, context: &mut glfw::Context) {
+        (*context).swap_buffers();
+    }
+
+    /// Begin rendering a new frame
+    fn begin_frame(&mut self) {
+        self.capture_trigger.begin_frame(ptr::null_mut());
+        self.frame_number += 1;
+
+        // Retrieve any readbacks that have had sufficient time to complete
+        //
+        let mut still_in_flight = vec![];
+        for readback in self.readbacks_in_flight.drain(..) {
+            if self.frame_number < readback.frame + READBACK_LATENCY_FRAMES {
+                still_in_flight.push(readback);
+                continue;
+            }
+
+            let num_bytes = (readback.width * readback.height * 3) as usize;
+            let mut data: Vec<u8> = vec![];
+            data.resize(num_bytes, 0);
+
+            unsafe {
+                gl::BindBuffer(gl::PIXEL_PACK_BUFFER, readback.pbo);
+                let src = gl::MapBufferRange(gl::PIXEL_PACK_BUFFER, 0, num_bytes as GLsizeiptr, gl::MAP_READ_BIT);
+                if !src.is_null() {
+                    ptr::copy_nonoverlapping(src as *const u8, data.as_mut_ptr(), num_bytes);
+                }
+                gl::UnmapBuffer(gl::PIXEL_PACK_BUFFER);
+                gl::BindBuffer(gl::PIXEL_PACK_BUFFER, 0);
+                gl::DeleteBuffers(1, &readback.pbo);
+            }
+            gl_memory_usage().lock().unwrap().release((gl::BUFFER, readback.pbo as u64));
+
+            self.readbacks_completed.complete(readback.handle,
+                                              Image::create_from_raw_data(readback.width, readback.height, &data));
+        }
+        self.readbacks_in_flight = still_in_flight;
+
+        self.harvest_pass_timings();
+
+        // Publish the frame times for the passes of this frame
+        self.frame_clock.tick();
+        if self.uniform_buffer_descs.contains_key(FRAME_BLOCK) {
+            self.frame_clock.publish(self);
+        }
+
+        // Publish the simulated objects' transforms for blending at the supplied alpha
+        if self.uniform_buffer_descs.contains_key(TRANSFORM_BLOCK) {
+            self.transform_interpolator.publish(self);
+        }
+    }
+
+    /// Terminate rendering a new frame
+    fn end_frame(&mut self) {
+        self.dispatch_compute_slices();
+        self.bandwidth_stats.end_frame();
+        self.skipped_binds.end_frame();
+
+        gl_check_no_assert!();
+        self.capture_trigger.end_frame(ptr::null_mut());
+
+        self.cpu_profiler.end_frame(self.frame_number, self.max_threads);
+    }
+
+    /// Initiate a render pass with a variant of a shader compiled with a set of defines
+    ///
+    /// The variant must have been registered with register_shader_variant before the renderer
+    /// was set up.  Otherwise the base shader is used, and the miss is logged once.
+    ///
+    /// shader_name: The name of the base shader
+    /// defines: The defines of the variant
+    fn begin_pass_variant(&mut self, shader_name: &'static str, defines: &ShaderDefines) {
+        let variant_name = self.resource_manager.lock().unwrap().shader_variants.resolve(shader_name, defines);
+        self.begin_pass(variant_name);
+    }
+
+    /// Initiate a render pass
+    fn begin_pass(&mut self, shader_name: &'static str) {
+        let sampled_textures;
+        let depth_tested;
+        {
+            let res_manager = self.resource_manager.lock().unwrap();
+            self.vertex_array_type = res_manager.shader_specs[shader_name].vertex_array_type;
+            self.attribute_names = res_manager.shader_specs[shader_name].attributes.clone();
+            depth_tested = res_manager.shader_specs[shader_name].render_state.depth_tested();
+            sampled_textures = res_manager.shader_specs[shader_name]
+                .uniform_specs
+                .iter()
+                .filter(|u| u.uniform_type == UniformType::CombinedImageSampler)
+                .count() as u32;
+        }
+
+        // Render targets have RGBA32F colour attachments with a 24-bit depth buffer, and the
+        // default framebuffer is assumed to be RGBA8 with one too
+        //
+        let target = match self.render_target_size {
+            Some((width, height, colour_attachments)) => {
+                PassTargetDesc {
+                    width: width,
+                    height: height,
+                    samples: self.sample_count,
+                    colour_bytes_per_sample: 16 * colour_attachments,
+                    depth_bytes_per_sample: 4,
+                    loads_colour: true,
+                    resolves: true,
+                }
+            }
+            None => {
+                PassTargetDesc {
+                    width: self.framebuffer_size.0,
+                    height: self.framebuffer_size.1,
+                    samples: 1,
+                    colour_bytes_per_sample: 4,
+                    depth_bytes_per_sample: 4,
+                    loads_colour: true,
+                    resolves: false,
+                }
+            }
+        };
+        self.bandwidth_stats.record_pass(PassBandwidth::estimate(shader_name, &target, sampled_textures));
+
+        // Clear the attachments the render graph pass declared clear values for, with the
+        // stencil buffer cleared to zero along with the depth buffer
+        //
+        let pass_clear = mem::replace(&mut self.pending_pass_clear, PassClear::default());
+        if !pass_clear.is_empty() {
+            let mut mask = 0;
+            unsafe {
+                // Clears are masked like writes, so undo the masks the last shader selected
+                gl::ColorMask(gl::TRUE, gl::TRUE, gl::TRUE, gl::TRUE);
+                gl::DepthMask(gl::TRUE);
+                if let Some(colour) = pass_clear.colour {
+                    gl::ClearColor(colour[0], colour[1], colour[2], colour[3]);
+                    mask |= gl::COLOR_BUFFER_BIT;
+                }
+                if let Some(depth) = pass_clear.depth {
+                    gl::ClearDepth(depth as f64);
+                    gl::ClearStencil(0);
+                    mask |= gl::DEPTH_BUFFER_BIT | gl::STENCIL_BUFFER_BIT;
+                }
+                gl::Clear(mask);
+            }
+            gl_check!();
+
+            // The masks the next shader selects must be applied again
+            gl_selected_state().lock().unwrap().render_state = None;
+        }
+
+        let polygon_mode = if depth_tested { self.polygon_mode } else { PolygonMode::Fill };
+        unsafe {
+            gl::PolygonMode(gl::FRONT_AND_BACK,
+                            match polygon_mode {
+                                PolygonMode::Fill => gl::FILL,
+                                PolygonMode::Line => gl::LINE,
+                                PolygonMode::Point => gl::POINT,
+                            });
+        }
+
+        if self.debug_groups {
+            unsafe {
+                gl::PushDebugGroup(gl::DEBUG_SOURCE_APPLICATION,
+                                   0, // Identifier
+                                   shader_name.len() as GLsizei,
+                                   shader_name.as_ptr() as *const GLchar);
+            }
+        }
+
+        let slot = self.frame_number as usize % TIMING_QUERY_FRAMES;
+        let pass_index = self.passes_timed[slot].1.len();
+        if pass_index < MAX_TIMED_PASSES {
+            unsafe {
+                gl::QueryCounter(self.pass_query(slot, 2 * pass_index), gl::TIMESTAMP);
+            }
+            self.passes_timed[slot].1.push(shader_name);
+            self.timed_pass = Some(pass_index);
+        }
+    }
+
+    /// Terminate a render pass
+    fn end_pass(&mut self) {
+        if let Some(pass_index) = self.timed_pass.take() {
+            let slot = self.frame_number as usize % TIMING_QUERY_FRAMES;
+            unsafe {
+                gl::QueryCounter(self.pass_query(slot, 2 * pass_index + 1), gl::TIMESTAMP);
+            }
+        }
+
+        if self.debug_groups {
+            unsafe {
+                gl::PopDebugGroup();
+            }
+        }
+    }
+
+    /// Select the specified render target to render to
+    ///
+    /// num: The texture number to bind the render target texture to
+    /// render_target: The render target to select
+    fn select_render_target(&mut self, num: i32, render_target: &mut RenderTarget) {
+        self.resolve_render_target();
+
+        {
+            let target_gl = match render_target.as_any_mut().downcast_mut::<RenderTargetGl>() {
+                Some(r) => r,
+                None => panic!("Unexpected runtime type"),
+            };
+
+            self.render_target_size = Some((target_gl.get_width(),
+                                            target_gl.get_height(),
+                                            target_gl.get_colour_attachment_count()));
+
+            if target_gl.is_multisampled() {
+                self.pending_resolve = Some((target_gl.get_fbo(),
+                                             target_gl.get_resolve_fbo(),
+                                             target_gl.get_width(),
+                                             target_gl.get_height(),
+                                             target_gl.get_colour_attachment_count()));
+            }
+
+            unsafe {
+                gl::BindFramebuffer(gl::FRAMEBUFFER, target_gl.get_fbo());
+            }
+        }
+
+        render_target.bind_texture(num);
+    }
+
+    /// Select no render target
+    ///
+    /// A multisampled render target is resolved into its texture at this point.
+    fn deselect_render_target(&mut self) {
+        self.resolve_render_target();
+        self.render_target_size = None;
+
+        unsafe {
+            gl::BindFramebuffer(gl::FRAMEBUFFER, 0);
+        }
+    }
+
+    /// Return the schedule of the render graph declared in the resource manager
+    ///
+    /// The passes are in the order they should be run.  There are none when no graph was
+    /// declared, or when it could not be scheduled.
+    fn render_graph_schedule(&self) -> &RenderGraphSchedule {
+        &self.render_graph_schedule
+    }
+
+    /// Bind a render target to an attachment of the render graph
+    ///
+    /// OpenGL has no image layouts to follow, so there is nothing to do.
+    ///
+    /// attachment: The name of the attachment
+    /// render_target: The render target holding the attachment
+    fn bind_graph_attachment(&mut self, _: &'static str, _: &RenderTarget) {}
+
+    /// Prepare the attachments of a pass of the render graph
+    ///
+    /// Rendering to a texture and then sampling it is kept coherent by OpenGL, so only writes
+    /// to the attachments from shader image stores need a barrier before they are sampled.
+    ///
+    /// pass_name: The name of the pass in the render graph
+    fn begin_graph_pass(&mut self, pass_name: &'static str) {
+        let (samples_attachment, clear) = match self.render_graph_schedule.find(pass_name) {
+            Some(pass) => (pass.transitions.iter().any(|t| t.to == AttachmentLayout::ShaderRead), pass.clear),
+            None => {
+                log_error!("No render graph pass named {}", pass_name);
+                return;
+            }
+        };
+        self.pending_pass_clear = clear;
+
+        if samples_attachment {
+            unsafe {
+                gl::MemoryBarrier(gl::TEXTURE_FETCH_BARRIER_BIT | gl::FRAMEBUFFER_BARRIER_BIT);
+            }
+            gl_check!();
+        }
+    }
+
+    /// Notify the renderer that the window has been resized
+    ///
+    /// width: The new framebuffer width
+    /// height: The new framebuffer height
+    fn window_resized(&mut self, width: u32, height: u32) {
+        self.framebuffer_size = (width, height);
+
+        unsafe {
+            gl::Viewport(0, 0, width as GLsizei, height as GLsizei);
+        }
+
+        // The default framebuffer stands in for the swapchain
+        self.events.swapchain_recreated((width, height));
+    }
+
+    /// Clear the colour contents of a render target to the specified value
+    ///
+    /// Every colour attachment of the target is cleared.
+    ///
+    /// render_target: The render target to clear
+    /// colour: The RGBA value to clear to
+    fn clear_render_target(&mut self, render_target: &RenderTarget, colour: &Vec4<f32>) {
+        let target_gl = match render_target.as_any().downcast_ref::<RenderTargetGl>() {
+            Some(r) => r,
+            None => panic!("Unexpected runtime type"),
+        };
+
+        let value = [colour.x, colour.y, colour.z, colour.w];
+        let colour_attachments = target_gl.get_colour_attachment_count() as GLint;
+        unsafe {
+            let mut previous_fbo: GLint = 0;
+            gl::GetIntegerv(gl::DRAW_FRAMEBUFFER_BINDING, &mut previous_fbo);
+
+            gl::BindFramebuffer(gl::DRAW_FRAMEBUFFER, target_gl.get_fbo());
+            for draw_buffer in 0..colour_attachments {
+                gl::ClearBufferfv(gl::COLOR, draw_buffer, value.as_ptr());
+            }
+            if target_gl.is_multisampled() {
+                gl::BindFramebuffer(gl::DRAW_FRAMEBUFFER, target_gl.get_resolve_fbo());
+                for draw_buffer in 0..colour_attachments {
+                    gl::ClearBufferfv(gl::COLOR, draw_buffer, value.as_ptr());
+                }
+            }
+            gl::BindFramebuffer(gl::DRAW_FRAMEBUFFER, previous_fbo as GLuint);
+        }
+        gl_check!();
+    }
+
+    /// Clear the depth contents of a render target to the specified value
+    ///
+    /// render_target: The render target whose depth buffer should be cleared
+    /// depth: The depth value to clear to
+    fn clear_depth_target(&mut self, render_target: &RenderTarget, depth: f32) {
+        let target_gl = match render_target.as_any().downcast_ref::<RenderTargetGl>() {
+            Some(r) => r,
+            None => panic!("Unexpected runtime type"),
+        };
+
+        unsafe {
+            let mut previous_fbo: GLint = 0;
+            gl::GetIntegerv(gl::DRAW_FRAMEBUFFER_BINDING, &mut previous_fbo);
+
+            gl::BindFramebuffer(gl::DRAW_FRAMEBUFFER, target_gl.get_fbo());
+            gl::ClearBufferfi(gl::DEPTH_STENCIL, 0 /* Draw buffer */, depth, 0 /* Stencil */);
+            gl::BindFramebuffer(gl::DRAW_FRAMEBUFFER, previous_fbo as GLuint);
+        }
+        gl_check!();
+    }
+
+    /// Queue an asynchronous readback of the colour contents of a render target
+    ///
+    /// The pixels are read into a pixel buffer object, which is only mapped once
+    /// READBACK_LATENCY_FRAMES frames have passed.
+    ///
+    /// render_target: The render target to read back
+    fn request_readback(&mut self, render_target: &RenderTarget) -> ReadbackHandle {
+        let target_gl = match render_target.as_any().downcast_ref::<RenderTargetGl>() {
+            Some(r) => r,
+            None => panic!("Unexpected runtime type"),
+        };
+
+        let width = target_gl.get_width();
+        let height = target_gl.get_height();
+        let mut pbo: GLuint = 0;
+
+        unsafe {
+            let mut previous_read_fbo: GLint = 0;
+            gl::GetIntegerv(gl::READ_FRAMEBUFFER_BINDING, &mut previous_read_fbo);
+
+            gl::GenBuffers(1, &mut pbo);
+            gl::BindBuffer(gl::PIXEL_PACK_BUFFER, pbo);
+            gl::BufferData(gl::PIXEL_PACK_BUFFER,
+                           (width * height * 3) as GLsizeiptr,
+                           ptr::null(),
+                           gl::STREAM_READ);
+            gl_memory_usage().lock().unwrap().record((gl::BUFFER, pbo as u64),
+                                                     MemoryCategory::Staging,
+                                                     (width * height * 3) as u64);
+
+            gl::BindFramebuffer(gl::READ_FRAMEBUFFER, target_gl.get_resolve_fbo());
+            gl::PixelStorei(gl::PACK_ALIGNMENT, 1);
+            gl::ReadPixels(0,
+                           0,
+                           width as i32,
+                           height as i32,
+                           gl::RGB,
+                           gl::UNSIGNED_BYTE,
+                           ptr::null_mut()); // Offset into the pixel buffer object
+
+            gl::BindBuffer(gl::PIXEL_PACK_BUFFER, 0);
+            gl::BindFramebuffer(gl::READ_FRAMEBUFFER, previous_read_fbo as GLuint);
         }
+        gl_check!();
+
+        let handle = self.next_readback_handle;
+        self.next_readback_handle = self.next_readback_handle.wrapping_add(1);
+
+        self.readbacks_in_flight.push(ReadbackGl {
+            handle: handle,
+            pbo: pbo,
+            width: width,
+            height: height,
+            frame: self.frame_number,
+        });
+
+        handle
+    }
+
+    /// Return the number of frames after which a readback requested this frame is available
+    fn readback_latency_frames(&self) -> u64 {
+        READBACK_LATENCY_FRAMES
+    }
+
+    /// Poll for the result of an earlier readback request
+    ///
+    /// handle: The handle returned by request_readback
+    fn poll_readback(&mut self, handle: ReadbackHandle) -> Option<Image> {
+        self.readbacks_completed.take(handle)
+    }
+
+    /// Queue an asynchronous readback of a render target, calling back with the result
+    ///
+    /// render_target: The render target to read back
+    /// callback: The function to call with the contents
+    fn request_readback_callback(&mut self, render_target: &RenderTarget, callback: ReadbackCallback) {
+        let handle = self.request_readback(render_target);
+        self.readbacks_completed.set_callback(handle, callback);
+    }
+
+    /// Copy the back buffer into CPU memory, before it is swapped to the front
+    fn capture_frame(&mut self) -> Option<FrameCapture> {
+        let (width, height) = self.framebuffer_size;
+        let row_bytes = (width * 4) as usize;
+        let mut bottom_up: Vec<u8> = vec![0; row_bytes * height as usize];
+
+        unsafe {
+            let mut previous_read_fbo: GLint = 0;
+            gl::GetIntegerv(gl::READ_FRAMEBUFFER_BINDING, &mut previous_read_fbo);
+
+            gl::BindFramebuffer(gl::READ_FRAMEBUFFER, 0);
+            gl::ReadBuffer(gl::BACK);
+            gl::PixelStorei(gl::PACK_ALIGNMENT, 1);
+            gl::ReadPixels(0,
+                           0,
+                           width as i32,
+                           height as i32,
+                           gl::RGBA,
+                           gl::UNSIGNED_BYTE,
+                           bottom_up.as_mut_ptr() as *mut c_void);
+
+            gl::BindFramebuffer(gl::READ_FRAMEBUFFER, previous_read_fbo as GLuint);
+        }
+        gl_check!();
+
+        // OpenGL returns the bottom row first
+        let mut data: Vec<u8> = Vec::with_capacity(bottom_up.len());
+        for row in bottom_up.chunks(row_bytes.max(1)).rev() {
+            data.extend_from_slice(row);
+        }
+
+        Some(FrameCapture {
+            width: width,
+            height: height,
+            data: data,
+        })
+    }
+
+    /// Return the frame pacing statistics
+    ///
+    /// OpenGL presentation is left to the driver, so only the frame count is known.
+    fn frame_stats(&self) -> FrameStats {
+        FrameStats {
+            frames: self.frame_number,
+            skipped_binds: self.skipped_binds.last_frame(),
+            last_gpu_frame_ms: self.gpu_timings.latest().map(|timings| timings.total_ms()),
+            ..Default::default()
+        }
+    }
+
+    /// Return the usage statistics of the device memory allocated to buffers and images
+    ///
+    /// OpenGL memory is managed by the driver, so nothing is known of it.
+    fn memory_stats(&self) -> MemoryStats {
+        MemoryStats::default()
+    }
+
+    /// Return the memory used by buffers and images, by heap and by what it is used for
+    ///
+    /// The categories are estimated from the sizes the objects were specified with.  The video
+    /// memory is reported as a single heap where GL_NVX_gpu_memory_info is available, its budget
+    /// being the whole of it.
+    fn get_memory_usage(&self) -> MemoryUsage {
+        let categories = gl_memory_usage().lock().unwrap().categories();
+
+        let mut heaps = vec![];
+        if self.gpu_memory_info {
+            let mut dedicated_kib: GLint = 0;
+            let mut available_kib: GLint = 0;
+            unsafe {
+                gl::GetIntegerv(GPU_MEMORY_INFO_DEDICATED_VIDMEM_NVX, &mut dedicated_kib);
+                gl::GetIntegerv(GPU_MEMORY_INFO_CURRENT_AVAILABLE_VIDMEM_NVX, &mut available_kib);
+            }
+            let size = dedicated_kib.max(0) as u64 * 1024;
+            heaps.push(HeapUsage {
+                size: size,
+                device_local: true,
+                allocated: categories.iter().map(|&(_, usage)| usage.bytes).sum(),
+                budget: Some(size),
+                usage: Some(size.saturating_sub(available_kib.max(0) as u64 * 1024)),
+            });
+        }
+
+        MemoryUsage {
+            heaps: heaps,
+            categories: categories,
+        }
+    }
+
+    /// Return the clock of engine and real time, ticked by begin_frame
+    fn frame_clock(&self) -> &FrameClock {
+        &self.frame_clock
+    }
+
+    /// Return the clock of engine and real time, to pause, step or scale engine time
+    fn frame_clock_mut(&mut self) -> &mut FrameClock {
+        &mut self.frame_clock
+    }
+
+    /// Return the previous and current transforms of the objects moved by a fixed-timestep
+    /// simulation, published by begin_frame
+    fn transform_interpolator(&self) -> &TransformInterpolator {
+        &self.transform_interpolator
+    }
+
+    /// Return the transforms of the simulated objects, to record their transforms each step and
+    /// set the interpolation alpha before begin_frame
+    fn transform_interpolator_mut(&mut self) -> &mut TransformInterpolator {
+        &mut self.transform_interpolator
+    }
+
+    /// Return the estimated memory traffic of each pass of the last frame
+    fn pass_bandwidth(&self) -> &Vec<PassBandwidth> {
+        self.bandwidth_stats.last_frame()
+    }
+
+    /// Return the GPU times of the passes of recent frames
+    ///
+    /// The timings of a frame are harvested TIMING_QUERY_FRAMES frames after it was drawn, and
+    /// passes whose queries have still not completed by then are left out.
+    fn gpu_timings(&self) -> &GpuTimingHistory {
+        &self.gpu_timings
+    }
+
+    /// Return the profiler of the CPU time spent in scopes timed with profile_scope
+    fn cpu_profiler(&self) -> &CpuProfiler {
+        &self.cpu_profiler
+    }
+
+    /// Return the listeners to the renderer's events, to subscribe to them
+    ///
+    /// OpenGL never reports the device being lost or validation messages.
+    fn events(&self) -> &RendererEvents {
+        &self.events
+    }
+
+    /// Draw the triangles of a thread's data once for each instance, and reset the thread data
+    ///
+    /// thread_data: The thread data holding the triangles
+    /// instance_data: The interleaved per-instance attributes of all of the instances
+    /// instance_count: The number of instances to draw
+    fn draw_instanced(&mut self, thread_data: &mut ThreadData, instance_data: &Vec<f32>, instance_count: usize) {
+        if thread_data.index > 0 && instance_count > 0 {
+            debug_assert!(instance_data.len() % instance_count == 0);
+
+            let components_per_vertex = VertexArrayType::components_per_vertex(self.vertex_array_type);
+            let components_per_instance = instance_data.len() / instance_count;
+
+            // Both the instances and the vertices are streamed, and drawn from where they were
+            // written.  The selected shader's vertex buffer is bound again once the instance data
+            // is written.
+            //
+            let mut vertex_buffer: GLint = 0;
+            unsafe {
+                gl::GetIntegerv(gl::ARRAY_BUFFER_BINDING, &mut vertex_buffer);
+                gl::BindBuffer(gl::ARRAY_BUFFER, self.instance_buffer);
+            }
+            let instance_offset = self.stream_to_bound_buffer(instance_data, components_per_instance);
+            unsafe {
+                gl::BindBuffer(gl::ARRAY_BUFFER, vertex_buffer as GLuint);
+            }
+            let vertex_offset = self.stream_to_bound_buffer(&thread_data.data[..thread_data.index * 3 * components_per_vertex],
+                                                            components_per_vertex);
+
+            let first_vertex = vertex_offset / (components_per_vertex * mem::size_of::<GLfloat>());
+            let first_instance = instance_offset / (components_per_instance * mem::size_of::<GLfloat>());
+            unsafe {
+                gl::DrawArraysInstancedBaseInstance(self.primitive(thread_data.primitive),
+                                                    first_vertex as GLint,
+                                                    (thread_data.index * 3) as GLint,
+                                                    instance_count as GLsizei,
+                                                    first_instance as GLuint);
+            }
+        }
+
+        thread_data.index = 0;
+    }
+
+    /// Draw triangles generated by the current pass's vertex shader without any vertex data
+    ///
+    /// thread_data: The thread data of the drawing thread, which is unused
+    /// vertex_count: The number of vertices of each instance, a multiple of three
+    /// instance_count: The number of instances to draw
+    fn draw_generated(&mut self, _: &mut ThreadData, vertex_count: u32, instance_count: u32) {
+        if vertex_count > 0 && instance_count > 0 {
+            unsafe {
+                gl::DrawArraysInstanced(gl::TRIANGLES,
+                                        0, // Starting index
+                                        vertex_count as GLint,
+                                        instance_count as GLsizei);
+            }
+        }
+    }
+
+    /// Upload the vertices and indices of a mesh into static buffer objects, returning its handle,
+    /// or None if the data is invalid
+    ///
+    /// data: The vertices and optional indices of the mesh
+    fn create_mesh(&mut self, data: &MeshData) -> Option<MeshHandle> {
+        if let Err(e) = data.validate() {
+            log_error!("Unable to create a mesh: {}", e);
+            return None;
+        }
+
+        let mesh = RendererGlMesh::new(data);
+
+        // The mesh's objects are left bound, so the next shader selected binds its own again
+        gl_selected_state().lock().unwrap().reset();
+
+        Some(self.meshes.insert(mesh))
+    }
+
+    /// Draw a mesh created by create_mesh with the current pass's shader
+    ///
+    /// The shader's Vertex Array Object and vertex buffer are bound again afterwards, so that
+    /// the streamed draws that follow are unaffected.
+    ///
+    /// thread_data: The thread data of the drawing thread, which is unused
+    /// mesh: The handle of the mesh
+    fn draw_mesh(&mut self, _: &mut ThreadData, mesh: MeshHandle) {
+        let m = match self.meshes.get(mesh) {
+            Some(m) => m,
+            None => {
+                log_error!("No mesh {} to draw", mesh);
+                return;
+            }
+        };
+        if m.vertex_array_type != self.vertex_array_type {
+            log_error!("Mesh {} has a different vertex array type to the current pass's shader", mesh);
+            return;
+        }
+
+        let mut program: GLint = 0;
+        let mut vertex_array: GLint = 0;
+        let mut vertex_buffer: GLint = 0;
+        unsafe {
+            gl::GetIntegerv(gl::CURRENT_PROGRAM, &mut program);
+            gl::GetIntegerv(gl::VERTEX_ARRAY_BINDING, &mut vertex_array);
+            gl::GetIntegerv(gl::ARRAY_BUFFER_BINDING, &mut vertex_buffer);
+
+            gl::BindVertexArray(m.vao);
+            if m.program.get() != program {
+                gl::BindBuffer(gl::ARRAY_BUFFER, m.vbo);
+                m.point_attributes(program, &self.attribute_names);
+            }
+
+            match m.ebo {
+                Some(_) => {
+                    gl::DrawElements(gl::TRIANGLES,
+                                     m.draw_count as GLsizei,
+                                     gl::UNSIGNED_INT,
+                                     ptr::null()); // Offset into the element array buffer
+                }
+                None => {
+                    gl::DrawArrays(gl::TRIANGLES,
+                                   0, // Starting index
+                                   m.draw_count as GLint);
+                }
+            }
+
+            gl::BindVertexArray(vertex_array as GLuint);
+            gl::BindBuffer(gl::ARRAY_BUFFER, vertex_buffer as GLuint);
+        }
+        gl_check!();
+    }
+
+    /// Destroy a mesh, deleting its buffer objects straight away
+    ///
+    /// mesh: The handle of the mesh
+    fn destroy_mesh(&mut self, mesh: MeshHandle) {
+        if !self.meshes.remove(mesh) {
+            log_warning!("No mesh {} to destroy", mesh);
+        }
+    }
+
+    /// Create vertex buffers up front, which the OpenGL renderer does not pool
+    ///
+    /// sizes: The number of vertex buffers to have ready, which is ignored
+    fn prewarm_vertex_buffers(&mut self, _: &VertexBufferPoolSizes) {}
+
+    /// Return the most vertex buffers used in a frame, which is none as they are not pooled
+    fn vertex_buffer_high_water(&self) -> VertexBufferPoolSizes {
+        VertexBufferPoolSizes::new(self.max_threads)
+    }
+
+    /// Return the number of triangles each thread's data holds before it is flushed
+    fn triangle_capacity(&self) -> usize {
+        self.triangle_capacity
+    }
+
+    /// Change the number of triangles each thread's data holds before it is flushed
+    ///
+    /// The vertex data is uploaded at the size of each flush, so only the thread data is resized.
+    ///
+    /// triangles: The capacity in triangles, limited to TRIANGLE_ARRAY_MAX_SIZE
+    fn set_triangle_capacity(&mut self, triangles: usize) {
+        self.triangle_capacity = triangles.max(1).min(TRIANGLE_ARRAY_MAX_SIZE);
+        for threaddata in self.threaddata_arcs.iter() {
+            threaddata.lock().unwrap().set_capacity(self.triangle_capacity);
+        }
+    }
+
+    /// Return whether compute shaders can be dispatched, which needs OpenGL 4.3
+    fn supports_compute(&self) -> bool {
+        gl::DispatchCompute::is_loaded()
+    }
+
+    /// Run a compute shader
+    ///
+    /// shader_name: The name of the compute shader
+    /// x: The number of workgroups in the x dimension
+    /// y: The number of workgroups in the y dimension
+    /// z: The number of workgroups in the z dimension
+    fn dispatch_compute(&mut self, shader_name: &str, x: u32, y: u32, z: u32) {
+        let program = match self.compute_programs.get(shader_name) {
+            Some(p) => *p,
+            None => {
+                log_error!("No compute program for {}", shader_name);
+                return;
+            }
+        };
+
+        unsafe {
+            let mut previous_program: GLint = 0;
+            gl::GetIntegerv(gl::CURRENT_PROGRAM, &mut previous_program);
+
+            gl::UseProgram(program);
+            gl::DispatchCompute(x, y, z);
+
+            // Make the results visible to whatever reads the storage buffers next
+            gl::MemoryBarrier(gl::SHADER_STORAGE_BARRIER_BIT | gl::UNIFORM_BARRIER_BIT | gl::VERTEX_ATTRIB_ARRAY_BARRIER_BIT |
+                              gl::BUFFER_UPDATE_BARRIER_BIT);
+
+            gl::UseProgram(previous_program as GLuint);
+        }
+        gl_check!();
+    }
+
+    /// Queue a compute dispatch to be spread over the coming frames within the compute budget
+    ///
+    /// OpenGL slices are not timed, so their size follows the pacer's initial estimate.
+    ///
+    /// shader_name: The name of the compute shader
+    /// x: The number of workgroups in the x dimension
+    /// y: The number of workgroups in the y dimension
+    /// z: The number of workgroups in the z dimension
+    fn queue_paced_compute(&mut self, shader_name: &'static str, x: u32, y: u32, z: u32) -> ComputeJobHandle {
+        let sliceable = match self.compute_programs.get(shader_name) {
+            Some(program) => {
+                let name = CString::new(WORKGROUP_OFFSET_PUSH_CONSTANT).unwrap();
+                unsafe { gl::GetUniformLocation(*program, name.as_ptr()) != -1 }
+            }
+            None => false,
+        };
+
+        self.compute_pacer.queue(shader_name, x, y, z, sliceable)
+    }
+
+    /// Return the pacer of the queued compute work, for the progress of its jobs
+    fn compute_pacer(&self) -> &ComputePacer {
+        &self.compute_pacer
+    }
+
+    /// Set the GPU time given over to paced compute work each frame
+    ///
+    /// budget_ms: The budget in milliseconds
+    fn set_compute_budget(&mut self, budget_ms: f32) {
+        self.compute_pacer.set_budget_ms(budget_ms);
+    }
+
+    /// Return whether shaders can read textures from the bindless texture array
+    ///
+    /// OpenGL materials always bind their textures through the shaders' own uniforms.
+    fn supports_bindless_textures(&self) -> bool {
+        false
+    }
+
+    /// Add a texture to the bindless texture array, which is unsupported on OpenGL
+    ///
+    /// texture: The texture to add
+    fn register_bindless_texture(&mut self, _: &Texture) -> Option<u32> {
+        None
+    }
+
+    /// Remove a texture from the bindless texture array, which is unsupported on OpenGL
+    ///
+    /// index: The index returned when the texture was added
+    fn release_bindless_texture(&mut self, _: u32) {}
+
+    /// Return the device features granted, out of those requested in the renderer configuration
+    ///
+    /// OpenGL offers every feature the context supports, so these are the requested features the
+    /// context was found to support.
+    fn granted_features(&self) -> DeviceFeatures {
+        self.features
+    }
+
+    /// Return the limits of the context, queried when the renderer was created
+    fn get_device_limits(&self) -> DeviceLimits {
+        self.limits
+    }
+
+    /// Return whether the output is an HDR10 swapchain, which OpenGL never presents to
+    fn is_hdr_output(&self) -> bool {
+        false
+    }
+
+    /// Set how the primitives of depth tested shaders are rasterised, for inspecting topology
+    ///
+    /// The mode is applied with glPolygonMode as each pass begins.
+    ///
+    /// mode: The polygon mode to draw with
+    fn set_polygon_mode(&mut self, mode: PolygonMode) {
+        self.polygon_mode = mode;
+    }
+
+    /// Return the polygon mode the depth tested shaders are drawn with
+    fn polygon_mode(&self) -> PolygonMode {
+        self.polygon_mode
+    }
+
+    /// Set how the scene is rendered for the two eyes and combined, see render_stereo_eyes
+    ///
+    /// settings: The stereo mode, eye separation and convergence distance
+    fn set_stereo_settings(&mut self, settings: StereoSettings) {
+        self.stereo_settings = settings;
+    }
+
+    /// Return the stereo settings the scene is rendered with
+    fn stereo_settings(&self) -> StereoSettings {
+        self.stereo_settings
+    }
+
+    /// Return the support of each engine feature on this backend and device, with the fallbacks
+    /// in use
+    ///
+    /// Paced compute slices are not timed on OpenGL, so they keep their initial size.
+    fn parity_report(&self) -> ParityReport {
+        let mut max_samples: GLint = 1;
+        let device_name = unsafe {
+            gl::GetIntegerv(gl::MAX_SAMPLES, &mut max_samples);
+            CStr::from_ptr(gl::GetString(gl::RENDERER) as *const i8)
+                .to_string_lossy()
+                .into_owned()
+        };
+
+        ParityReport::new(&BackendCapabilities {
+            backend: "OpenGL",
+            device_name: device_name,
+            features: self.features,
+            max_samples: max_samples.max(1) as u32,
+            sample_count: self.sample_count,
+            compute: self.supports_compute(),
+            timed_compute: false,
+            bindless_textures: self.supports_bindless_textures(),
+            gpu_timing: true,
+            per_draw: self.per_draw_benchmark.clone(),
+        })
+    }
+
+    /// Keep the results of a per-draw strategy benchmark for the parity report
+    ///
+    /// results: The benchmark results
+    fn record_per_draw_benchmark(&mut self, results: &PerDrawBenchmarkResults) {
+        self.per_draw_benchmark = Some(results.clone());
+    }
+
+    /// Return the handles an OpenXR session is created with to render through this renderer
+    ///
+    /// The session binds to the current context, whose platform handles come from the window.
+    fn xr_graphics_binding(&self) -> XrGraphicsBinding {
+        XrGraphicsBinding::OpenGl
+    }
+
+    /// Copy the colour contents of a render target into an image acquired from an OpenXR swapchain
+    ///
+    /// The image is a texture of the context, which is attached to a temporary framebuffer and
+    /// blitted into from the render target's resolved framebuffer.
+    ///
+    /// render_target: The render target to copy from
+    /// image: The swapchain image to copy into
+    fn copy_to_xr_image(&mut self, render_target: &RenderTarget, image: &XrSwapchainImage) {
+        let target_gl = match render_target.as_any().downcast_ref::<RenderTargetGl>() {
+            Some(r) => r,
+            None => panic!("Unexpected runtime type"),
+        };
+
+        unsafe {
+            let mut previous_read_fbo: GLint = 0;
+            let mut previous_draw_fbo: GLint = 0;
+            gl::GetIntegerv(gl::READ_FRAMEBUFFER_BINDING, &mut previous_read_fbo);
+            gl::GetIntegerv(gl::DRAW_FRAMEBUFFER_BINDING, &mut previous_draw_fbo);
+
+            let mut fbo: GLuint = 0;
+            gl::GenFramebuffers(1, &mut fbo);
+            gl::BindFramebuffer(gl::DRAW_FRAMEBUFFER, fbo);
+            gl::FramebufferTexture2D(gl::DRAW_FRAMEBUFFER,
+                                     gl::COLOR_ATTACHMENT0,
+                                     gl::TEXTURE_2D,
+                                     image.image as GLuint,
+                                     0); // Mip level
+
+            gl::BindFramebuffer(gl::READ_FRAMEBUFFER, target_gl.get_resolve_fbo());
+            gl::BlitFramebuffer(0,
+                                0,
+                                target_gl.get_width() as GLint,
+                                target_gl.get_height() as GLint,
+                                0,
+                                0,
+                                image.width as GLint,
+                                image.height as GLint,
+                                gl::COLOR_BUFFER_BIT,
+                                gl::LINEAR);
+
+            gl::BindFramebuffer(gl::READ_FRAMEBUFFER, previous_read_fbo as GLuint);
+            gl::BindFramebuffer(gl::DRAW_FRAMEBUFFER, previous_draw_fbo as GLuint);
+            gl::DeleteFramebuffers(1, &fbo);
+        }
+        gl_check!();
+    }
+
+    /// Capture the next frame in RenderDoc, returning false if RenderDoc is not attached
+    ///
+    /// The capture is of the current context.
+    fn trigger_capture(&mut self) -> bool {
+        self.capture_trigger.request(1)
+    }
+
+    /// Return the capture trigger, for the paths of the captures written
+    fn capture_trigger(&self) -> &CaptureTrigger {
+        &self.capture_trigger
     }
 }
 
 impl RendererGl {
+    /// Dispatch the paced compute slices planned for this frame
+    ///
+    /// The workgroup offset push constant is a plain uniform on OpenGL.
+    fn dispatch_compute_slices(&mut self) {
+        if self.compute_pacer.is_idle() {
+            return;
+        }
+
+        let name = CString::new(WORKGROUP_OFFSET_PUSH_CONSTANT).unwrap();
+        for slice in self.compute_pacer.plan_frame() {
+            if let Some(&program) = self.compute_programs.get(slice.shader_name) {
+                unsafe {
+                    let mut previous_program: GLint = 0;
+                    gl::GetIntegerv(gl::CURRENT_PROGRAM, &mut previous_program);
+
+                    gl::UseProgram(program);
+                    let location = gl::GetUniformLocation(program, name.as_ptr());
+                    if location != -1 {
+                        gl::Uniform1ui(location, slice.first_x);
+                    }
+                    gl::DispatchCompute(slice.x, slice.y, slice.z);
+
+                    gl::MemoryBarrier(gl::SHADER_STORAGE_BARRIER_BIT | gl::UNIFORM_BARRIER_BIT |
+                                      gl::VERTEX_ATTRIB_ARRAY_BARRIER_BIT | gl::BUFFER_UPDATE_BARRIER_BIT);
+
+                    gl::UseProgram(previous_program as GLuint);
+                }
+            } else {
+                log_error!("No compute program for {}", slice.shader_name);
+            }
+
+            self.compute_pacer.slice_completed(&slice, None);
+        }
+        gl_check!();
+    }
+
     /// As the main thread, flush the buffers returned by a thread as GL calls
     ///
     /// renderer_arc: Atomic reference counted lockable reference to the renderer
@@ -440,17 +2206,19 @@ impl RendererGl {
             None => panic!("Unexpected runtime type"),
         };
 
-        let components_per_triangle = 3 * VertexArrayType::components_per_vertex(renderer_gl.vertex_array_type);
+        let vertices_per_primitive = PrimitiveType::vertices_per_primitive(thread_data.primitive);
+        let components_per_vertex = VertexArrayType::components_per_vertex(renderer_gl.vertex_array_type);
+        let components_per_primitive = vertices_per_primitive * components_per_vertex;
 
+        // The vertices are drawn from where they were streamed to, the attribute pointers
+        // staying at the start of the buffer
+        //
+        let offset = renderer_gl.stream_to_bound_buffer(&thread_data.data[..thread_data.index * components_per_primitive],
+                                                        components_per_vertex);
         unsafe {
-            gl::BufferData(gl::ARRAY_BUFFER,
-                           (thread_data.index * components_per_triangle * mem::size_of::<GLfloat>()) as GLsizeiptr,
-                           mem::transmute(thread_data.data.as_ptr()),
-                           gl::DYNAMIC_DRAW);
-
             gl::DrawArrays(renderer_gl.primitive(thread_data.primitive),
-                           0, // Starting index
-                           (thread_data.index * 3) as GLint);
+                           (offset / (components_per_vertex * mem::size_of::<GLfloat>())) as GLint, // Starting index
+                           (thread_data.index * vertices_per_primitive) as GLint);
         }
     }
 }
@@ -28,14 +28,18 @@
 // THE USE OF THIS SOFTWARE, EVEN IF ADVISED OF THE POSSIBILITY OF SUCH
 // DAMAGE.
 
-use std::collections::HashMap;
+use std::collections::{HashMap, VecDeque};
 use std::sync::*;
+use std::sync::atomic::{AtomicBool, AtomicUsize, Ordering};
 use std::str;
 use std::ffi::*;
 use std::os::raw::*;
 use std::ptr;
 use std::mem;
+use std::mem::ManuallyDrop;
+use std::cmp;
 use std::any::Any;
+use std::thread;
 
 use semver::Version;
 
@@ -48,12 +52,43 @@ use glfw::*;
 use graphics::renderer::*;
 use graphics::shader::*;
 use graphics::shaderspirv::*;
+use graphics::shadervariants::ShaderDefines;
+use graphics::pipelinebuild::*;
+use graphics::errorshader::*;
 use graphics::texture::*;
 use graphics::texturevk::*;
 use graphics::rendertarget::*;
 use graphics::rendertargetvk::*;
+use graphics::rendergraph::*;
+use graphics::renderpasses::*;
 use graphics::resources::*;
-use algebra::matrix::Mat4;
+use graphics::readback::*;
+use graphics::computepacing::*;
+use graphics::frametime::*;
+use graphics::interpolation::*;
+use graphics::bandwidth::*;
+use graphics::versioning::*;
+use graphics::vertexpool::*;
+use graphics::mesh::*;
+use graphics::memoryallocator::*;
+use graphics::memoryusage::*;
+use graphics::failureinjection::*;
+use graphics::bindless::*;
+use graphics::devicefeatures::*;
+use graphics::parity::*;
+use graphics::capturetrigger::*;
+use graphics::stereo::StereoSettings;
+use graphics::perdraw::PerDrawBenchmarkResults;
+use graphics::debugmessages::*;
+use graphics::rendererevents::*;
+use graphics::xr::*;
+use graphics::gputiming::*;
+use graphics::cputiming::*;
+use misc::fileutils::*;
+use misc::logging::*;
+use misc::timer::Timer;
+use graphics::image::*;
+use algebra::matrix::{ClipSpaceConvention, Mat4};
 use algebra::vector::*;
 
 // TODO: Improve the performance by improving the synchronisation
@@ -72,68 +107,202 @@ macro_rules! matches(
     )
 );
 
+// In debug builds, a call may be failed by the installed failure plan instead of being made,
+// to exercise the error paths in tests
 macro_rules! check_result(
     ($n:expr, $e:expr) => {
-        let res = $e;
+        let res = match injected_failure($n) {
+            Some(failure) => injected_result(failure),
+            None => $e,
+        };
         if !matches!(res, VkResult::VK_SUCCESS) {
-            println!("Unexpected result from {}: {}", $n, res);
+            log_error!("Unexpected result from {}: {}", $n, res);
             panic!("Vulkan API call failed");
         }
     }
 );
 
-// Why is everything back-to-front here?  Because Rust's destruction order
-// is the opposite of C++.  In fact, Rust does not define the destruction
-// order of fields, and this is just the order that the compiler implements.
-//
-// The drop_in_place compiler intrisic can be used to invoke a destructor,
-// but the lifetime of an object is absolutely set in stone, and the destructor
-// will be invoked again at the end of the object's life.  Unlike the very
-// specific lifetime semantics of an object, absolutely no guarantee is
-// placed on the relative lifetimes of objects within the same compound
-// (struct, tuple, array, etc.)
+// As check_result, but first tells the renderer's event listeners if the device has been lost
+macro_rules! check_device_result(
+    ($events:expr, $n:expr, $e:expr) => {
+        let res = match injected_failure($n) {
+            Some(failure) => injected_result(failure),
+            None => $e,
+        };
+        if !matches!(res, VkResult::VK_SUCCESS) {
+            log_error!("Unexpected result from {}: {}", $n, res);
+            if matches!(res, VkResult::VK_ERROR_DEVICE_LOST) {
+                $events.device_lost();
+            }
+            panic!("Vulkan API call failed");
+        }
+    }
+);
+
+macro_rules! try_result(
+    ($n:expr, $e:expr) => {
+        let res = match injected_failure($n) {
+            Some(failure) => injected_result(failure),
+            None => $e,
+        };
+        if !matches!(res, VkResult::VK_SUCCESS) {
+            return Err(RendererError::ApiCallFailed($n, format!("{}", res)));
+        }
+    }
+);
+
+/// Return the API result standing in for an injected failure
+///
+/// failure: The injected failure
+fn injected_result(failure: InjectedFailure) -> VkResult {
+    match failure {
+        InjectedFailure::OutOfDate => VkResult::VK_ERROR_OUT_OF_DATE_KHR,
+        InjectedFailure::DeviceLost => VkResult::VK_ERROR_DEVICE_LOST,
+        InjectedFailure::OutOfMemory => VkResult::VK_ERROR_OUT_OF_DEVICE_MEMORY,
+        InjectedFailure::Generic => VkResult::VK_ERROR_INITIALIZATION_FAILED,
+    }
+}
+
+// Rather than rely on the order in which the compiler drops fields, the objects created by the
+// constructor are held in ManuallyDrop and destroyed explicitly by shutdown(), in the reverse
+// order of their creation.  The other members are emptied by shutdown() in the same way, so
+// the implicit drops that follow it have nothing left to destroy.
 pub struct RendererVk {
-    current_pass_identifier: u32,
+    swapchain_out_of_date: AtomicBool,
+    current_render_target_extent: (u32, u32),
+    current_pass_name: &'static str,
     current_depth_target: Option<VkImage>,
     current_render_target: Option<VkFramebuffer>,
     vertex_array_type: VertexArrayType,
     shader_name: &'static str,
+    pass_index: usize,
     image_index: usize,
+    frame_number: u64,
+
+    pending_graph_barriers: Vec<RendererVkGraphBarrier>,
+    pending_pass_clear: PassClear, // Made by the next begin_pass, from the render graph pass begun
+    queued_command_buffers: Mutex<Vec<(Option<&'static str>, VkCommandBuffer)>>, // Awaiting submission, by graph pass
+    current_graph_pass: Option<&'static str>, // The render graph pass last begun this frame
+    queue_submits: AtomicUsize, // The submissions to the graphics queue made so far this frame
+
+    render_graph_schedule: RenderGraphSchedule,
+    graph_attachments: HashMap<&'static str, RendererVkGraphAttachment>,
+    texture_views: HashMap<&'static str, VkImageView>, // Per texture name, the view its descriptors refer to
+    pending_uniform_updates: Mutex<Vec<(String, Vec<u8>)>>,
+
+    next_readback_handle: ReadbackHandle,
+    readbacks_completed: ReadbackResults,
+    readback_requests: Vec<RendererVkReadback>,
+    readbacks_in_flight: Vec<Vec<RendererVkReadback>>, // Per swapchain image
+    readback_staging: Vec<Vec<RendererVkBuffer>>, // Per swapchain image, the staging buffers free for its readbacks
 
     prepresent_command_buffers: Vec<RendererVkCommandBuffer>,
-    cleardepth_command_buffers: Vec<RendererVkCommandBuffer>,
-    command_buffers: Vec<Vec<RendererVkCommandBuffer>>,
+    compute_command_buffers: Vec<RendererVkCommandBuffer>, // Per swapchain image, for paced compute
+    compute_query_pools: Vec<RendererVkQueryPool>, // Per swapchain image, timing the paced compute slices
+    compute_slices_in_flight: Vec<Vec<ComputeSlice>>, // Per swapchain image
+    compute_pacer: ComputePacer,
+    timestamp_period_ns: f32, // Zero where timestamps are unsupported
+    pass_query_pools: Vec<RendererVkQueryPool>, // Per swapchain image, timing the passes
+    passes_timed: Vec<(u64, Vec<&'static str>)>, // Per swapchain image, the frame number and each timed pass's shader
+    gpu_timings: GpuTimingHistory,
+    cpu_profiler: CpuProfiler,
+    frame_clock: FrameClock,
+    transform_interpolator: TransformInterpolator,
+    bandwidth_stats: BandwidthStats,
+    skipped_binds: SkippedBinds,
+    prepass_command_buffers: Vec<Vec<RendererVkCommandBuffer>>, // Per swapchain image, per pass
+    command_buffers: Vec<Vec<Vec<RendererVkCommandBuffer>>>, // Per swapchain image, per pass, per thread
+    chunk_command_buffers: Vec<Vec<Vec<RendererVkCommandBuffer>>>, // Per swapchain image, per thread
+    chunks_used: Vec<usize>, // Per thread, this frame
+    current_chunk: Vec<Option<usize>>, // Per thread, None for the pass command buffer
+    chunk_draws: Vec<usize>, // Per thread
+    chunk_bytes: Vec<usize>, // Per thread
+    chunk_draw_threshold: usize,
+    chunk_byte_threshold: usize,
+    push_constant_state: Vec<Mutex<Vec<u8>>>, // Per thread
+    draw_textures: Vec<Mutex<RendererVkDrawTextures>>, // Per thread, the textures switched to in the current pass
+    bound_state: Vec<Mutex<BoundState>>, // Per thread, the objects bound in its current command buffer
+    draw_descriptor_pools: Vec<Vec<RendererVkDescriptorPool>>, // Per swapchain image, per thread
+    draw_bindings: HashMap<&'static str, RendererVkDrawBindings>, // Per graphics shader
     command_pools: Vec<RendererVkCommandPool>,
     render_pipelines: HashMap<&'static str, RendererVkPipeline>,
+    pipeline_modules: HashMap<&'static str, Vec<(ShaderStage, VkShaderModule, &'static str)>>, // Per render pipeline
+    polygon_mode: PolygonMode, // Applied to the depth tested shaders
+    polygon_mode_pipelines: HashMap<&'static str, RendererVkPipeline>, // Depth tested pipelines in a non-fill mode
+    stereo_settings: StereoSettings, // Read by render_stereo_eyes and render_stereo_composite
+    per_draw_benchmark: Option<PerDrawBenchmarkResults>, // Reported by parity_report
+    compute_pipelines: HashMap<&'static str, RendererVkComputePipeline>,
+    push_constant_specs: HashMap<&'static str, Vec<PushConstantSpec>>,
+    pipeline_cache: Option<RendererVkPipelineCache>,
+    pipeline_cache_filename: String,
+    pipeline_builder: Option<RendererVkPipelineBuilder>, // Present until the pipelines queued at startup are collected
+    pipeline_threads: usize, // From the configuration, 0 for one per rendering thread
+    error_shader_modules: HashMap<u32, Vec<RendererVkShaderModule>>,
+    pending_pipeline_rebuilds: Mutex<Vec<(&'static str, Vec<(ShaderStage, Vec<u8>, &'static str)>)>>,
+    rebuilt_shader_modules: HashMap<&'static str, Vec<RendererVkShaderModule>>,
+    texture_descriptors: HashMap<&'static str, Vec<(&'static str, u32)>>, // Per shader, texture name and binding
     framebuffers: Vec<RendererVkFramebuffer>,
-    pub render_passes: Vec<RendererVkRenderPass>,
+    pub render_passes: HashMap<&'static str, RendererVkRenderPass>, // Keyed by the names they are declared with
+    // Variants of the render passes that clear attachments, keyed by pass name and whether the
+    // colour and depth attachments are cleared
+    clearing_render_passes: HashMap<(&'static str, bool, bool), RendererVkRenderPass>,
     uniform_buffers: HashMap<&'static str, RendererVkUniformBuffer>,
     descriptor_sets: HashMap<&'static str, RendererVkDescriptorSet>,
     pipeline_layouts: HashMap<&'static str, RendererVkPipelineLayout>,
     descriptor_set_layouts: HashMap<&'static str, RendererVkDescriptorSetLayouts>,
     descriptor_pools: Vec<RendererVkDescriptorPool>,
+    shader_descriptor_pools: HashMap<&'static str, RendererVkDescriptorPool>, // Holding the sets of rebuilt shaders
+    retired_descriptor_pools: Vec<Vec<RendererVkDescriptorPool>>, // Per swapchain image, replaced by rebuilds
+    bindless_textures: Option<RendererVkBindlessTextures>, // Present when the device has descriptor indexing
 
     vertex_buffer_index: Vec<Vec<Vec<i32>>>,
     vertex_buffer: Vec<Vec<Vec<Vec<RendererVkVertexBuffer>>>>,
+    vertex_buffer_storage: VertexBufferStorage, // Never Automatic, which is resolved at creation
+    triangle_capacity: usize, // Of each thread's data, to which the vertex buffers are sized
+    vertex_uploads: Vec<(VkBuffer, VkBuffer, usize)>, // Staging copies for draws not yet submitted
+    vertex_upload_command_buffers: Vec<Vec<RendererVkCommandBuffer>>, // Per swapchain image, per submission
+    meshes: MeshRegistry<RendererVkMesh>, // Retained in device-local memory across frames
+    mesh_moves: Vec<RendererVkMeshMove>, // Defragmentation copies in flight on the transfer queue
+    defragment_bytes_per_frame: usize, // The most mesh memory to start moving each frame
+    vertex_uploads_used: usize, // This frame
+    instance_buffers: Vec<Vec<Vec<RendererVkBuffer>>>, // Per swapchain image, per thread
+    instance_buffers_used: Vec<usize>, // Per thread, this frame
 
     resource_manager: Arc<Mutex<Box<ResourceManager>>>,
 
     // Prior to this point, the members are set up once the requisite data is made available to
     // the renderer.  The following items are created by the constructor.
-    pub aux_command_pool: RendererVkCommandPool,
-
-    render_finished_semaphore: RendererVkSemaphore,
-    image_available_semaphore: RendererVkSemaphore,
-
-    swapchain: RendererVkSwapchain,
-    surface: RendererVkSurface,
-    pub device: RendererVkDevice,
+    pub aux_command_pool: ManuallyDrop<RendererVkCommandPool>,
+    pub transfer_command_pool: ManuallyDrop<RendererVkCommandPool>,
+
+    // Fences are per swapchain image, guarding the resources used to render to that image,
+    // and semaphores are per frame in flight
+    frame_fences: Vec<RendererVkFence>,
+    frame_slot_images: Vec<Option<usize>>, // Per frame in flight, the image whose submission last used its semaphores
+    render_finished_semaphores: Vec<RendererVkSemaphore>,
+    pass_semaphores: Vec<Vec<RendererVkSemaphore>>, // Per swapchain image, between the batches of its submission
+    image_available_semaphores: Vec<RendererVkSemaphore>,
+
+    swapchain: ManuallyDrop<RendererVkSwapchain>,
+    surface: ManuallyDrop<Option<RendererVkSurface>>, // None when headless
+    pub device: ManuallyDrop<RendererVkDevice>,
     queue_families: RendererVkQueueFamilies,
     pub physical_device: RendererVkPhysicalDevice,
     #[allow(dead_code)]
-    debug_callback: Option<RendererVkDebugCallback>,
+    debug_messenger: Option<Box<RendererVkDebugMessenger>>, // Present when vk_debug_mask != 0 and the extension is available
     #[allow(dead_code)]
-    instance: RendererVkInstance,
+    instance: ManuallyDrop<RendererVkInstance>,
+    shut_down: bool,
+
+    pub sample_count: VkSampleCountFlagBits,
+    flip_viewport_y: bool,
+
+    present_wait: Option<RendererVkPresentWait>,
+    debug_utils: Option<RendererVkDebugUtils>, // Present when debug_level > 0 and the extension is available
+    memory_budget: Option<RendererVkMemoryBudget>, // Present when VK_EXT_memory_budget is available
+    capture_trigger: CaptureTrigger, // RenderDoc is loaded before the instance with config.load_renderdoc
+    frame_stats: FrameStats,
+    events: Arc<RendererEvents>, // Shared with the debug messenger, which passes on validation messages
 
     max_threads: usize,
     threaddata_arcs: Vec<Arc<Mutex<Box<ThreadData>>>>,
@@ -144,7 +313,8 @@ unsafe impl Sync for RendererVk {}
 impl RendererVk {
     /// Initialise Vulkan to the point where we have a device and a swapchain
     ///
-    /// TODO: Add proper error handling
+    /// Returns an error if Vulkan is unavailable or any of the objects cannot be created, so that
+    /// the application can fall back to another renderer.
     ///
     /// max_threads: The maximum number of rendering threads
     /// application_name: String containing the application name
@@ -152,8 +322,9 @@ impl RendererVk {
     /// engine_version: String containing the engine version
     /// debug_level: The debug level
     /// vk_debug_mask: A bitmask of Vulkan log messages
+    /// config: Optional renderer features, including the extra validation presets
     /// glfw: The main GLFW object
-    /// window: The GLFW Window object
+    /// surface_mode: The window to present to, or the size of the offscreen images when headless
     /// resource_manager: The shader resource manager
     /// threaddata_arcs: A vector of Arcs encapsulating ThreadData structures
     pub fn new(application_name: &str,
@@ -162,41 +333,95 @@ impl RendererVk {
                max_threads: usize,
                debug_level: u32,
                vk_debug_mask: u32,
+               config: &RendererConfig,
                glfw: &mut Glfw,
-               window: &mut Window,
+               surface_mode: SurfaceMode,
                resource_manager: &Arc<Mutex<Box<ResourceManager>>>,
                threaddata_arcs: Vec<Arc<Mutex<Box<ThreadData>>>>)
-               -> RendererVk {
+               -> Result<RendererVk, RendererError> {
+        set_log_level(LogLevel::from_debug_level(debug_level));
+
+        let headless = match surface_mode {
+            SurfaceMode::Windowed(_) => false,
+            SurfaceMode::Headless(..) => true,
+        };
+
         // Build collections of layer and extension names required by the application
         let mut instance_layers: Vec<String> = vec![];
         let mut instance_extensions: Vec<String> = vec![];
         let mut device_extensions: Vec<String> = vec![];
 
-        // Check GLFW for its requirements
+        // Check GLFW for its requirements.  A headless renderer needs none of the surface extensions.
         if glfw.vulkan_supported() {
-            // List required Vulkan instance extension
-            let reqd_vk_extensions = glfw.get_required_instance_extensions().unwrap_or(vec![]);
-            for ext in reqd_vk_extensions.iter() {
-                instance_extensions.push(ext.clone());
+            if !headless {
+                // List required Vulkan instance extension
+                let reqd_vk_extensions = glfw.get_required_instance_extensions().unwrap_or(vec![]);
+                for ext in reqd_vk_extensions.iter() {
+                    instance_extensions.push(ext.clone());
+                }
             }
         } else {
-            panic!("Vulkan is not supported");
+            return Err(RendererError::Unsupported("Vulkan is not supported".to_string()));
         }
 
         // Now add explicit layers and extensions as desired
         instance_layers.push("VK_LAYER_LUNARG_monitor".to_string());
-        if vk_debug_mask != 0 {
-            instance_layers.push("VK_LAYER_LUNARG_standard_validation".to_string());
+        let mut validation_features = RendererVkValidationFeatures::new(config);
+        if vk_debug_mask != 0 || validation_features.is_enabled() {
+            instance_layers.push("VK_LAYER_KHRONOS_validation".to_string());
             if debug_level > 1 {
                 instance_layers.push("VK_LAYER_LUNARG_api_dump".to_string());
             }
-            instance_extensions.push("VK_EXT_debug_report".to_string());
         }
-        device_extensions.push("VK_KHR_swapchain".to_string());
-        if debug_level > 0 {
-            println!("Requested instance layers {:?}", instance_layers);
-            println!("Requested instance extensions {:?}", instance_extensions);
-            println!("Requested device extensions {:?}", device_extensions);
+        if validation_features.is_enabled() {
+            // The extension is provided by the validation layer itself
+            instance_extensions.push("VK_EXT_validation_features".to_string());
+        }
+        if debug_level > 0 || vk_debug_mask != 0 {
+            // Object names and labelled regions for debuggers such as RenderDoc, and the messenger
+            // the validation layer reports through
+            if RendererVkDebugUtils::instance_supports() {
+                instance_extensions.push("VK_EXT_debug_utils".to_string());
+            } else if vk_debug_mask != 0 {
+                log_warning!("VK_EXT_debug_utils is not available, so validation messages will not be reported");
+            }
+        }
+        if config.hdr_output && !headless {
+            if instance_supports_extension("VK_EXT_swapchain_colorspace") {
+                // The HDR10 colour space of the surface formats is introduced by this extension
+                instance_extensions.push("VK_EXT_swapchain_colorspace".to_string());
+            } else {
+                log_warning!("VK_EXT_swapchain_colorspace is not available, so the output will not be HDR10");
+            }
+        }
+        if instance_supports_extension("VK_KHR_get_physical_device_properties2") {
+            // The memory budget of each heap is queried through the extended device properties
+            instance_extensions.push("VK_KHR_get_physical_device_properties2".to_string());
+        }
+        if !headless {
+            device_extensions.push("VK_KHR_swapchain".to_string());
+        }
+        if config.flip_viewport_y {
+            // Negative viewport heights were introduced by this extension
+            device_extensions.push("VK_KHR_maintenance1".to_string());
+        }
+        if config.xr_session {
+            // The runtime shares its swapchain images with the application through external memory
+            for extension in XR_VULKAN_INSTANCE_EXTENSIONS.iter() {
+                instance_extensions.push(extension.to_string());
+            }
+            for extension in XR_VULKAN_DEVICE_EXTENSIONS.iter() {
+                device_extensions.push(extension.to_string());
+            }
+        }
+        log_info!("Requested instance layers {:?}", instance_layers);
+        log_info!("Requested instance extensions {:?}", instance_extensions);
+        log_info!("Requested device extensions {:?}", device_extensions);
+
+        // RenderDoc hooks the instance as it is created, so it is loaded first
+        let capture_trigger = CaptureTrigger::new(config.load_renderdoc);
+        if capture_trigger.is_available() {
+            log_info!("RenderDoc is attached, so frames can be captured");
         }
 
         // Now start creating all the Vulkan objects required
@@ -204,36 +429,173 @@ impl RendererVk {
                                                application_version,
                                                engine_version,
                                                &instance_layers,
-                                               &instance_extensions);
+                                               &instance_extensions,
+                                               &mut validation_features)?;
 
-        let debug_callback;
-        if vk_debug_mask != 0 {
-            debug_callback = Some(RendererVkDebugCallback::new(&instance, vk_debug_mask));
+        let events = Arc::new(RendererEvents::new());
+        let debug_messenger;
+        if vk_debug_mask != 0 && instance_extensions.iter().any(|e| e == "VK_EXT_debug_utils") {
+            debug_messenger = Some(RendererVkDebugMessenger::new(&instance, vk_debug_mask, events.clone())?);
         } else {
-            debug_callback = None;
+            debug_messenger = None;
         }
 
-        let physical_device = RendererVkPhysicalDevice::new(&instance, debug_level);
+        let physical_device = RendererVkPhysicalDevice::new(&instance, &config.features)?;
+        log_info!("Granted features {:?}", physical_device.granted_features);
+        resource_manager.lock().unwrap().select_shader_stages(&physical_device.granted_features);
+        if config.gpu_assisted_validation &&
+           (!physical_device.granted_features.vertex_pipeline_stores_and_atomics ||
+            !physical_device.granted_features.fragment_stores_and_atomics) {
+            log_warning!("GPU-assisted validation requested but the device does not support stores and atomics in all \
+                         stages, so only some shader stages will be instrumented");
+        }
+
+        let sample_count = physical_device.choose_sample_count(config.sample_count);
 
-        let queue_families = RendererVkQueueFamilies::new(&physical_device);
+        let vertex_buffer_storage = physical_device.choose_vertex_buffer_storage(config.vertex_buffer_storage);
+        log_info!("Vertex buffers use {:?} storage", vertex_buffer_storage);
 
-        let surface = RendererVkSurface::new(window,
+        let mut queue_families = RendererVkQueueFamilies::new(&physical_device)?;
+
+        let hdr10 = instance_extensions.iter().any(|e| e == "VK_EXT_swapchain_colorspace");
+        let (surface, headless_extent) = match surface_mode {
+            SurfaceMode::Windowed(window) => {
+                (Some(RendererVkSurface::new(window,
                                              &instance,
                                              &physical_device,
-                                             queue_families.index,
-                                             debug_level);
+                                             &mut queue_families,
+                                             config.srgb_output,
+                                             hdr10)?),
+                 None)
+            }
+            SurfaceMode::Headless(width, height) => (None, Some((width, height))),
+        };
+
+        let memory_budget_supported = instance_extensions.iter().any(|e| e == "VK_KHR_get_physical_device_properties2") &&
+                                      RendererVkMemoryBudget::device_supports(&physical_device);
+        if memory_budget_supported {
+            device_extensions.push("VK_EXT_memory_budget".to_string());
+        }
+
+        // Pace frames from present completion, and bind textures through one bindless array,
+        // where the driver allows it.  The features may still be refused at device creation, in
+        // which case the device is created with fewer of them, present wait being dropped first.
+        //
+        let present_wait_supported = !headless && RendererVkPresentWait::device_supports(&physical_device);
+        let descriptor_indexing_supported = RendererVkBindlessTextures::device_supports(&physical_device);
+        let mut attempts: Vec<(bool, bool)> = vec![]; // Present wait and descriptor indexing
+        if present_wait_supported && descriptor_indexing_supported {
+            attempts.push((true, true));
+        }
+        if descriptor_indexing_supported {
+            attempts.push((false, true));
+        }
+        if present_wait_supported {
+            attempts.push((true, false));
+        }
+
+        let mut device = None;
+        for (present_wait, descriptor_indexing) in attempts {
+            let mut optional_extensions = device_extensions.clone();
+            if present_wait {
+                optional_extensions.push("VK_KHR_present_id".to_string());
+                optional_extensions.push("VK_KHR_present_wait".to_string());
+            }
+            if descriptor_indexing {
+                optional_extensions.push("VK_KHR_maintenance3".to_string());
+                optional_extensions.push("VK_EXT_descriptor_indexing".to_string());
+            }
+
+            match RendererVkDevice::new(&physical_device,
+                                        queue_families.index,
+                                        queue_families.present_index,
+                                        queue_families.transfer_index,
+                                        &instance_layers,
+                                        &optional_extensions,
+                                        present_wait,
+                                        descriptor_indexing) {
+                Ok(d) => {
+                    device = Some((d, present_wait));
+                    break;
+                }
+                Err(e) => log_warning!("Unable to create the device with {:?} ({}), retrying", optional_extensions, e),
+            }
+        }
+        let (device, present_wait) = match device {
+            Some((d, true)) => {
+                let present_wait = RendererVkPresentWait::new(&d);
+                (d, present_wait)
+            }
+            Some((d, false)) => (d, None),
+            None => {
+                (RendererVkDevice::new(&physical_device,
+                                       queue_families.index,
+                                       queue_families.present_index,
+                                       queue_families.transfer_index,
+                                       &instance_layers,
+                                       &device_extensions,
+                                       false, // present_wait
+                                       false /* descriptor_indexing */)?,
+                 None)
+            }
+        };
+        log_info!("Present wait pacing is {}",
+                  if present_wait.is_some() { "enabled" } else { "disabled" });
+        log_info!("Bindless textures are {}",
+                  if device.descriptor_indexing { "enabled" } else { "disabled" });
+
+        let memory_budget = if memory_budget_supported {
+            RendererVkMemoryBudget::new(&instance)
+        } else {
+            None
+        };
+        log_info!("Memory budgets are {}",
+                  if memory_budget.is_some() { "reported" } else { "unknown" });
 
-        let device = RendererVkDevice::new(&physical_device,
-                                           queue_families.index,
-                                           &instance_layers,
-                                           &device_extensions);
+        let debug_utils = if debug_level > 0 && instance_extensions.iter().any(|e| e == "VK_EXT_debug_utils") {
+            RendererVkDebugUtils::new(&instance)
+        } else {
+            None
+        };
 
-        let swapchain = RendererVkSwapchain::new(&device, &surface, 2 /* swapchain image count */);
+        let aux_command_pool = RendererVkCommandPool::new(&device, queue_families.index)?;
+        let transfer_command_pool = RendererVkCommandPool::new(&device, device.transfer_queue_family_index)?;
+        log_info!("Texture uploads use {} queue",
+                  if device.has_transfer_queue() { "a dedicated transfer" } else { "the graphics" });
+
+        let swapchain = match surface {
+            Some(ref surface) => {
+                RendererVkSwapchain::new(&device,
+                                         surface,
+                                         2, // Swapchain image count
+                                         VK_NULL_HANDLE_MUT() /* Old swapchain */)?
+            }
+            None => {
+                let (width, height) = headless_extent.unwrap();
+                log_info!("Rendering headless to {}x{} offscreen images", width, height);
+                RendererVkSwapchain::new_headless(&device,
+                                                  &physical_device,
+                                                  &aux_command_pool,
+                                                  width,
+                                                  height,
+                                                  2, // Image count
+                                                  config.srgb_output)?
+            }
+        };
 
-        let image_available_semaphore = RendererVkSemaphore::new(&device);
-        let render_finished_semaphore = RendererVkSemaphore::new(&device);
+        let mut image_available_semaphores = vec![];
+        let mut render_finished_semaphores = vec![];
+        let mut frame_fences = vec![];
+        for _ in 0..swapchain.image_count {
+            image_available_semaphores.push(RendererVkSemaphore::new(&device)?);
+            render_finished_semaphores.push(RendererVkSemaphore::new(&device)?);
+            frame_fences.push(RendererVkFence::new(&device, true /* signalled */)?);
+        }
 
-        let aux_command_pool = RendererVkCommandPool::new(&device, queue_families.index);
+        let mut readbacks_in_flight = vec![];
+        for _ in 0..swapchain.image_count {
+            readbacks_in_flight.push(vec![]);
+        }
 
         let mut vertex_buffer = vec![];
         let mut vertex_buffer_index = vec![];
@@ -253,49 +615,150 @@ impl RendererVk {
             }
         }
 
+        // GPU-assisted validation instruments every draw and keeps its output buffers until the
+        // submission completes, so submitting in smaller chunks bounds the extra device memory it needs
+        let (chunk_draw_threshold, chunk_byte_threshold) = if config.gpu_assisted_validation {
+            (GPU_ASSISTED_VALIDATION_CHUNK_DRAW_THRESHOLD, DEFAULT_CHUNK_BYTE_THRESHOLD / 4)
+        } else {
+            (DEFAULT_CHUNK_DRAW_THRESHOLD, DEFAULT_CHUNK_BYTE_THRESHOLD)
+        };
+
         // Now construct the RendererVk object containing all of these good things
-        RendererVk {
+        Ok(RendererVk {
+            sample_count: sample_count,
+            flip_viewport_y: config.flip_viewport_y,
+
             max_threads: max_threads,
             threaddata_arcs: threaddata_arcs,
 
-            instance: instance,
-            debug_callback: debug_callback,
+            instance: ManuallyDrop::new(instance),
+            debug_messenger: debug_messenger,
             physical_device: physical_device,
             queue_families: queue_families,
-            device: device,
-            surface: surface,
-            swapchain: swapchain,
+            device: ManuallyDrop::new(device),
+            surface: ManuallyDrop::new(surface),
+            swapchain: ManuallyDrop::new(swapchain),
+            shut_down: false,
 
-            image_available_semaphore: image_available_semaphore,
-            render_finished_semaphore: render_finished_semaphore,
+            image_available_semaphores: image_available_semaphores,
+            render_finished_semaphores: render_finished_semaphores,
+            pass_semaphores: (0..swapchain.image_count).map(|_| vec![]).collect(),
+            frame_fences: frame_fences,
+            frame_slot_images: vec![None; swapchain.image_count as usize],
 
-            aux_command_pool: aux_command_pool,
+            aux_command_pool: ManuallyDrop::new(aux_command_pool),
+            transfer_command_pool: ManuallyDrop::new(transfer_command_pool),
 
             resource_manager: resource_manager.clone(),
 
             vertex_buffer: vertex_buffer,
             vertex_buffer_index: vertex_buffer_index,
+            vertex_buffer_storage: vertex_buffer_storage,
+            triangle_capacity: config.triangle_capacity.max(1).min(TRIANGLE_ARRAY_MAX_SIZE),
+            vertex_uploads: vec![],
+            vertex_upload_command_buffers: vec![],
+            meshes: MeshRegistry::new(swapchain.image_count),
+            mesh_moves: vec![],
+            defragment_bytes_per_frame: config.defragment_bytes_per_frame,
+            vertex_uploads_used: 0,
+            instance_buffers: (0..swapchain.image_count).map(|_| (0..max_threads).map(|_| vec![]).collect()).collect(),
+            instance_buffers_used: vec![0; max_threads],
 
             descriptor_pools: vec![],
+            shader_descriptor_pools: HashMap::new(),
+            retired_descriptor_pools: (0..swapchain.image_count).map(|_| vec![]).collect(),
+            bindless_textures: None,
             descriptor_set_layouts: HashMap::new(),
             pipeline_layouts: HashMap::new(),
             descriptor_sets: HashMap::new(),
             uniform_buffers: HashMap::new(),
-            render_passes: vec![],
+            render_passes: HashMap::new(),
+            clearing_render_passes: HashMap::new(),
             framebuffers: vec![],
             render_pipelines: HashMap::new(),
+            pipeline_modules: HashMap::new(),
+            polygon_mode: PolygonMode::Fill,
+            polygon_mode_pipelines: HashMap::new(),
+            stereo_settings: config.stereo,
+            per_draw_benchmark: None,
+            compute_pipelines: HashMap::new(),
+            push_constant_specs: HashMap::new(),
+            pipeline_cache: None,
+            pipeline_cache_filename: DEFAULT_PIPELINE_CACHE_FILENAME.to_string(),
+            error_shader_modules: HashMap::new(),
+            pending_pipeline_rebuilds: Mutex::new(vec![]),
+            pipeline_builder: None,
+            pipeline_threads: config.pipeline_threads,
+            rebuilt_shader_modules: HashMap::new(),
+            texture_descriptors: HashMap::new(),
             command_pools: vec![],
             command_buffers: vec![],
-            cleardepth_command_buffers: vec![],
+            prepass_command_buffers: vec![],
+            chunk_command_buffers: vec![],
+            chunks_used: vec![0; max_threads],
+            current_chunk: vec![None; max_threads],
+            chunk_draws: vec![0; max_threads],
+            chunk_bytes: vec![0; max_threads],
+            chunk_draw_threshold: chunk_draw_threshold,
+            chunk_byte_threshold: chunk_byte_threshold,
+            push_constant_state: (0..max_threads).map(|_| Mutex::new(vec![])).collect(),
+            draw_textures: (0..max_threads).map(|_| Mutex::new(RendererVkDrawTextures::new())).collect(),
+            bound_state: (0..max_threads).map(|_| Mutex::new(BoundState::new())).collect(),
+            draw_descriptor_pools: vec![],
+            draw_bindings: HashMap::new(),
             prepresent_command_buffers: vec![],
+            compute_command_buffers: vec![],
+            compute_query_pools: vec![],
+            compute_slices_in_flight: vec![],
+            compute_pacer: ComputePacer::new(DEFAULT_COMPUTE_BUDGET_MS),
+            timestamp_period_ns: 0.0,
+            pass_query_pools: vec![],
+            passes_timed: vec![],
+            gpu_timings: GpuTimingHistory::new(GPU_TIMING_HISTORY_FRAMES),
+            cpu_profiler: CpuProfiler::new(CPU_TIMING_HISTORY_FRAMES),
+            frame_clock: FrameClock::new(),
+            transform_interpolator: TransformInterpolator::new(),
+            bandwidth_stats: BandwidthStats::new(),
+            skipped_binds: SkippedBinds::new(),
+
+            readbacks_in_flight: readbacks_in_flight,
+            readback_staging: (0..swapchain.image_count).map(|_| vec![]).collect(),
+            readback_requests: vec![],
+            readbacks_completed: ReadbackResults::new(),
+            next_readback_handle: 0,
+
+            pending_uniform_updates: Mutex::new(vec![]),
+            pending_graph_barriers: vec![],
+            pending_pass_clear: PassClear::default(),
+            queued_command_buffers: Mutex::new(vec![]),
+            current_graph_pass: None,
+            queue_submits: AtomicUsize::new(0),
+
+            render_graph_schedule: RenderGraphSchedule::default(),
+            graph_attachments: HashMap::new(),
+            texture_views: HashMap::new(),
+
+            frame_stats: FrameStats {
+                present_wait_enabled: present_wait.is_some(),
+                ..Default::default()
+            },
+            present_wait: present_wait,
+            debug_utils: debug_utils,
+            memory_budget: memory_budget,
+            capture_trigger: capture_trigger,
+            events: events,
 
+            frame_number: 0,
             image_index: u32::max_value() as usize,
+            pass_index: 0,
             shader_name: "",
             vertex_array_type: VertexArrayType::F3F3F3,
             current_render_target: None,
             current_depth_target: None,
-            current_pass_identifier: u32::max_value(),
-        }
+            current_pass_name: SWAPCHAIN_PASS,
+            current_render_target_extent: (0, 0),
+            swapchain_out_of_date: AtomicBool::new(false),
+        })
     }
 
     /// Return the raw Vulkan device
@@ -303,6 +766,37 @@ impl RendererVk {
         self.device.raw
     }
 
+    /// Set the file the pipeline cache is loaded from and saved to
+    ///
+    /// This must be called before setup to affect the loading of the cache.
+    ///
+    /// filename: The name of the pipeline cache file
+    pub fn set_pipeline_cache_filename(&mut self, filename: &str) {
+        self.pipeline_cache_filename = filename.to_string();
+    }
+
+    /// Set the function messages from the validation layer and loader are passed to
+    ///
+    /// By default they are logged.  The messages passed are those selected by vk_debug_mask, and
+    /// none are produced if it is 0.  The callback may be called from any thread using the device.
+    ///
+    /// callback: The function to pass messages to
+    pub fn set_debug_message_callback(&self, callback: DebugMessageCallback) {
+        if let Some(ref messenger) = self.debug_messenger {
+            *messenger.callback.lock().unwrap() = callback;
+        }
+    }
+
+    /// Write the contents of the pipeline cache to disk
+    ///
+    /// This happens automatically when the renderer is dropped.
+    pub fn flush_pipeline_cache(&self) {
+        match self.pipeline_cache {
+            Some(ref cache) => cache.save(&self.pipeline_cache_filename),
+            None => (),
+        }
+    }
+
     /// Continue initialising Vulkan structures to the point where stuff can be rendered
     ///
     /// The goal is that the specifics of the renderer setup go in here or in the trait
@@ -311,8 +805,12 @@ impl RendererVk {
     ///
     /// shaders: The shaders to continue setting up
     /// textures: The textures to continue setting up
-    pub fn setup(&mut self, shaders: &HashMap<&'static str, &ShaderSpirv>, textures: &HashMap<&'static str, &TextureVk>) {
-        let res_manager = self.resource_manager.lock().unwrap();
+    pub fn setup(&mut self,
+                 shaders: &HashMap<&'static str, &ShaderSpirv>,
+                 textures: &HashMap<&'static str, &TextureVk>)
+                 -> Result<(), RendererError> {
+        let resource_manager = self.resource_manager.clone();
+        let res_manager = resource_manager.lock().unwrap();
 
         // Create initial vertex buffers
         //
@@ -322,7 +820,9 @@ impl RendererVk {
                     self.vertex_buffer[i as usize][ty as usize][thr]
                         .push(RendererVkVertexBuffer::new(&self.device,
                                                           &self.physical_device,
-                                                          VertexArrayType::from_u32(ty)));
+                                                          VertexArrayType::from_u32(ty),
+                                                          self.triangle_capacity,
+                                                          self.vertex_buffer_storage));
                 }
             }
         }
@@ -343,15 +843,56 @@ impl RendererVk {
         let num_shaders = res_manager.shader_specs.len();
         self.descriptor_pools.push(RendererVkDescriptorPool::new(&self.device,
                                                                  num_shaders * num_uniform_blocks, // Maximum uniform buffers
+                                                                 num_shaders * num_uniform_blocks, // Maximum storage buffers
                                                                  num_shaders, // Maximum combined image samplers
                                                                  num_shaders * num_uniform_blocks /* Maximum desc sets */));
 
-        // Generate a descriptor set layout and a descriptor for each shader
+        // Each thread copies descriptor sets from pools of its own when it switches textures
+        // between draws, which are reset once the frame using them has completed
+        //
+        let graphics_specs = || res_manager.shader_specs.values().filter(|spec| !spec.is_compute());
+        let max_blocks = graphics_specs().map(|spec| spec.uniform_block_names.len()).max().unwrap_or(0);
+        let max_samplers = graphics_specs()
+            .map(|spec| spec.uniform_specs.iter().filter(|u| u.uniform_type == UniformType::CombinedImageSampler).count())
+            .max()
+            .unwrap_or(0);
+        for _ in 0..self.swapchain.image_count {
+            let pools = (0..self.max_threads)
+                .map(|_| {
+                    RendererVkDescriptorPool::new(&self.device,
+                                                  DRAW_DESCRIPTOR_SETS_PER_THREAD * cmp::max(max_blocks, 1),
+                                                  DRAW_DESCRIPTOR_SETS_PER_THREAD * cmp::max(max_blocks, 1),
+                                                  DRAW_DESCRIPTOR_SETS_PER_THREAD * cmp::max(max_samplers, 1),
+                                                  DRAW_DESCRIPTOR_SETS_PER_THREAD)
+                })
+                .collect();
+            self.draw_descriptor_pools.push(pools);
+        }
+
+        // Create the bindless texture array, which textures are added to as they are loaded
+        //
+        if self.device.descriptor_indexing {
+            self.bindless_textures = Some(RendererVkBindlessTextures::new(&self.device, self.swapchain.image_count));
+        }
+
+        // Generate a descriptor set layout and a descriptor for each graphics shader, compute
+        // pipelines having descriptor sets of their own
         //
         for (shader_name, shader_spec) in res_manager.shader_specs.iter() {
+            if shader_spec.is_compute() {
+                continue;
+            }
+
+            if shader_spec.bindless_textures && self.bindless_textures.is_none() {
+                log_warning!("Shader {} reads bindless textures, which the device does not support", shader_name);
+            }
+
             let descriptor_set_layouts = RendererVkDescriptorSetLayouts::new(&self.device, &res_manager, &shader_spec);
 
-            let pipeline_layout = RendererVkPipelineLayout::new(&self.device, &descriptor_set_layouts);
+            let pipeline_layout = RendererVkPipelineLayout::new(&self.device,
+                                                                &descriptor_set_layouts,
+                                                                self.bindless_textures_for(&shader_spec),
+                                                                &shader_spec);
 
             let descriptor_set = RendererVkDescriptorSet::new(&self.device,
                                                               &self.descriptor_pools[0],
@@ -362,3611 +903,10599 @@ impl RendererVk {
 
             self.descriptor_set_layouts.insert(shader_name, descriptor_set_layouts);
             self.pipeline_layouts.insert(shader_name, pipeline_layout);
+            self.push_constant_specs.insert(shader_name, shader_spec.push_constants.clone());
+
+            // Remember the textures bound to the descriptor set, for copying if it is rebuilt
+            let texture_descriptors = shader_spec.uniform_specs
+                .iter()
+                .filter(|u| u.uniform_type == UniformType::CombinedImageSampler && textures.contains_key(u.name))
+                .map(|u| (u.name, u.binding))
+                .collect();
+            self.texture_descriptors.insert(shader_name, texture_descriptors);
             self.descriptor_sets.insert(shader_name, descriptor_set);
+            self.draw_bindings.insert(shader_name, RendererVkDrawBindings::new(&shader_spec, &self.uniform_buffers));
         }
 
-        // The render pass with identifier zero is reserved for the final render to the swapchain.
-        // So the final shader that performs the post-processing will specify zero as the pass identifier.
-        // The other shaders will specify other render pass identifiers used for offscreen rendering.
+        // Every shader draws in a declared render pass, which fixes the target, the colour
+        // attachments and whether there is depth.  A shader that writes fewer colour attachments
+        // than its pass has leaves the remaining attachments undefined.
         //
+        if let Err(e) = res_manager.validate_render_passes() {
+            log_error!("Invalid render passes: {}", e);
+            return Err(RendererError::InvalidRenderPasses(e));
+        }
         let depth_format = self.choose_depth_format();
-        self.render_passes.push(RendererVkRenderPass::new(&self.device,
-                                                          self.surface.format.format,
-                                                          None /* Depth format */));
-        self.render_passes.push(RendererVkRenderPass::new(&self.device,
-                                                          VkFormat::VK_FORMAT_R32G32B32A32_SFLOAT,
-                                                          Some(depth_format)));
+        self.render_graph_schedule = match res_manager.render_graph.compile() {
+            Ok(schedule) => schedule,
+            Err(e) => {
+                log_error!("Unable to schedule the render graph: {}", e);
+                RenderGraphSchedule::default()
+            }
+        };
+        self.create_pass_semaphores();
+
+        // Multisampled offscreen passes resolve into the render target textures at the end of
+        // each render pass instance, so the final pass always samples single-sampled textures.
+        // The swapchain pass overwrites the whole image so does not load its previous contents.
+        //
+        for pass in res_manager.render_passes.passes().iter() {
+            let colour_format = match pass.colour_format {
+                PassColourFormat::Surface => self.swapchain.format,
+                PassColourFormat::Rgba32Float => VkFormat::VK_FORMAT_R32G32B32A32_SFLOAT,
+            };
+            let (samples, offscreen) = match pass.target {
+                PassTarget::Swapchain => (VkSampleCountFlagBits::VK_SAMPLE_COUNT_1_BIT, false),
+                PassTarget::Offscreen => (self.sample_count, true),
+            };
+            let render_pass = RendererVkRenderPass::new(&self.device,
+                                                        colour_format,
+                                                        pass.colour_attachments,
+                                                        if pass.depth { Some(depth_format) } else { None },
+                                                        samples,
+                                                        offscreen, // load_colour
+                                                        self.render_graph_schedule.has_external_dependencies(pass.name),
+                                                        false, // clear_colour
+                                                        false /* clear_depth */);
+            self.render_passes.insert(pass.name, render_pass);
+        }
 
         // Create a framebuffer for each swapchain image
         //
-        for i in 0..self.swapchain.image_count {
-            self.framebuffers.push(RendererVkFramebuffer::new(&self.device,
-                                                              self.swapchain.views[i as usize],
-                                                              None, // Depth image view
-                                                              &self.render_passes[0],
-                                                              self.surface.capabilities.currentExtent.width,
-                                                              self.surface.capabilities.currentExtent.height));
+        self.create_swapchain_framebuffers();
+
+        // Remember which textures the descriptors refer to, so that they can follow render
+        // targets that are resized
+        //
+        for (name, texture) in textures.iter() {
+            self.texture_views.insert(*name, texture.texture.get_view_raw());
         }
 
-        // Create a render pipeline for each shader
+        // Load the pipeline cache saved by a previous run, to save recompiling the pipelines
+        //
+        self.pipeline_cache = Some(RendererVkPipelineCache::new(&self.device, &self.pipeline_cache_filename));
+
+        // Create a compute pipeline for each compute shader, and queue a render pipeline for
+        // each of the others, to be created on worker threads
         //
+        let mut pipeline_jobs: Vec<(&'static str, RendererVkPipelineJob)> = vec![];
         for (shader_name, _) in shaders {
             let ref shader_spec = res_manager.shader_specs[shader_name];
+            let modules = shaders[shader_name].get_shader_modules();
+
+            if shader_spec.is_compute() {
+                if modules.is_empty() {
+                    log_error!("No shader modules for compute shader {}", shader_name);
+                    continue;
+                }
+                match RendererVkComputePipeline::new(&self.device,
+                                                     self.pipeline_cache.as_ref().unwrap(),
+                                                     &res_manager,
+                                                     &shader_spec,
+                                                     modules[0],
+                                                     &self.uniform_buffers,
+                                                     textures) {
+                    Ok(p) => {
+                        let texture_descriptors = shader_spec.uniform_specs
+                            .iter()
+                            .filter(|u| u.uniform_type == UniformType::CombinedImageSampler && textures.contains_key(u.name))
+                            .map(|u| (u.name, u.binding))
+                            .collect();
+                        self.texture_descriptors.insert(shader_name, texture_descriptors);
+                        self.compute_pipelines.insert(shader_name, p);
+                    }
+                    Err(res) => {
+                        // There is nothing meaningful to substitute for a compute shader, so
+                        // dispatches of it are skipped
+                        log_error!("Unexpected result from {}: {}", "vkCreateComputePipelines", res);
+                    }
+                }
+                continue;
+            }
+
+            if modules.is_empty() {
+                log_error!("No shader modules for {}", shader_name);
+                let pipeline = self.create_error_pipeline(*shader_name, shader_spec);
+                self.render_pipelines.insert(*shader_name, pipeline);
+                continue;
+            }
 
-            self.render_pipelines
-                .insert(shader_name,
-                        RendererVkPipeline::new(&self.device,
-                                                &self.render_passes[shader_spec.pass_identifier as usize],
-                                                &shader_spec,
-                                                shaders[shader_name],
-                                                &self.pipeline_layouts[shader_name],
-                                                self.surface.capabilities.currentExtent.width,
-                                                self.surface.capabilities.currentExtent.height));
+            pipeline_jobs.push((*shader_name,
+                                RendererVkPipelineJob::new(&self.device,
+                                                           self.pipeline_cache.as_ref().unwrap(),
+                                                           &self.render_passes[shader_spec.pass_name],
+                                                           &shader_spec,
+                                                           &modules,
+                                                           &self.pipeline_layouts[shader_name],
+                                                           PolygonMode::Fill,
+                                                           self.flip_viewport_y)));
         }
 
+        // Startup waits only for the pipelines of the first frame's shaders, which are created
+        // first, and the rest are collected as they become ready
+        //
+        let shader_names: Vec<&'static str> = pipeline_jobs.iter().map(|&(name, _)| name).collect();
+        let (ordered, blocking) = res_manager.pipeline_builds.order(&shader_names);
+        pipeline_jobs.sort_by_key(|&(name, _)| ordered.iter().position(|n| *n == name));
+        let pipeline_threads = pipeline_build_threads(self.pipeline_threads, self.max_threads, pipeline_jobs.len());
+        log_info!("Creating {} pipelines on {} threads, waiting for {} of them",
+                  ordered.len(),
+                  pipeline_threads,
+                  blocking);
+        self.pipeline_builder = Some(RendererVkPipelineBuilder::new(pipeline_jobs,
+                                                                    pipeline_threads,
+                                                                    PipelineBuildTracker::new(&ordered, blocking)));
+
         // Create a command pool for each thread
         //
         for _ in 0..self.max_threads {
-            self.command_pools.push(RendererVkCommandPool::new(&self.device, self.queue_families.index));
+            self.command_pools.push(RendererVkCommandPool::new(&self.device, self.queue_families.index)?);
         }
 
-        // Create command buffers for each swapchain image for each thread
+        // The per-pass command buffers for each swapchain image are created on demand in
+        // begin_pass, as the number of passes per frame is not known up front
         //
-        for i in 0..self.swapchain.image_count {
+        for _ in 0..self.swapchain.image_count {
             self.command_buffers.push(vec![]);
-            for thr in 0..self.max_threads {
-                self.command_buffers[i as usize].push(RendererVkCommandBuffer::new(&self.device,
-                                                                                   &self.command_pools[thr],
-                                                                                   true /* primary */));
-            }
-
+            self.prepass_command_buffers.push(vec![]);
+            self.chunk_command_buffers.push((0..self.max_threads).map(|_| vec![]).collect());
+            self.vertex_upload_command_buffers.push(vec![]);
         }
 
         // Create additional command buffers per swap chain image
         //
         for _ in 0..self.swapchain.image_count {
-            self.cleardepth_command_buffers.push(RendererVkCommandBuffer::new(&self.device,
-                                                                              &self.aux_command_pool,
-                                                                              true /* primary */));
             self.prepresent_command_buffers.push(RendererVkCommandBuffer::new(&self.device,
                                                                               &self.aux_command_pool,
                                                                               true /* primary */));
+            self.compute_command_buffers.push(RendererVkCommandBuffer::new(&self.device,
+                                                                           &self.aux_command_pool,
+                                                                           true /* primary */));
+            self.compute_query_pools.push(RendererVkQueryPool::new(&self.device, 2 * MAX_COMPUTE_SLICES_PER_FRAME as u32));
+            self.compute_slices_in_flight.push(vec![]);
+            self.pass_query_pools.push(RendererVkQueryPool::new(&self.device, 2 * MAX_TIMED_PASSES as u32));
+            self.passes_timed.push((0, vec![]));
         }
 
+        // Paced compute slices and passes are timed where the device supports timestamps on all queues
+        //
+        let mut properties = VkPhysicalDeviceProperties::default();
+        unsafe {
+            vkGetPhysicalDeviceProperties(self.physical_device.raw, &mut properties);
+        }
+        self.timestamp_period_ns = if properties.limits.timestampComputeAndGraphics != 0 {
+            properties.limits.timestampPeriod
+        } else {
+            0.0
+        };
+
+        drop(res_manager);
+        self.collect_built_pipelines(PipelineWait::FirstFrame);
+        self.create_polygon_mode_pipelines();
+
+        self.name_debug_objects();
+
+        Ok(())
     }
 
-    /// Find an available memory that suits the requirements
+    /// Name a Vulkan object for debuggers, if debug_level > 0 and VK_EXT_debug_utils is present
     ///
+    /// object_type: The VkObjectType of the object, e.g. VK_OBJECT_TYPE_IMAGE
+    /// handle: The object's handle
+    /// name: The name to give the object
+    pub fn name_object(&self, object_type: u32, handle: u64, name: &str) {
+        if let Some(ref debug_utils) = self.debug_utils {
+            debug_utils.name_object(self.device.raw, object_type, handle, name);
+        }
+    }
+
+    /// Name the pipelines, uniform buffers and swapchain images for debuggers
+    fn name_debug_objects(&self) {
+        if self.debug_utils.is_none() {
+            return;
+        }
+
+        for (shader_name, pipeline) in self.render_pipelines.iter() {
+            self.name_object(VK_OBJECT_TYPE_PIPELINE, pipeline.raw as u64, shader_name);
+        }
+        for (shader_name, pipeline) in self.compute_pipelines.iter() {
+            self.name_object(VK_OBJECT_TYPE_PIPELINE, pipeline.raw as u64, shader_name);
+        }
+        for (block_name, uniform_buffer) in self.uniform_buffers.iter() {
+            self.name_object(VK_OBJECT_TYPE_BUFFER, uniform_buffer.buffer.raw as u64, block_name);
+        }
+        for (i, image) in self.swapchain.images.iter().enumerate() {
+            self.name_object(VK_OBJECT_TYPE_IMAGE, *image as u64, &format!("swapchain image {}", i));
+        }
+    }
+
+    /// Create a framebuffer for each swapchain image, sized to the current swapchain extent
+    fn create_swapchain_framebuffers(&mut self) {
+        for i in 0..self.swapchain.image_count {
+            self.framebuffers.push(RendererVkFramebuffer::new(&self.device,
+                                                              &[self.swapchain.views[i as usize]],
+                                                              None, // Depth image view
+                                                              &[], // Resolve image views
+                                                              &self.render_passes[SWAPCHAIN_PASS],
+                                                              self.swapchain.extent.width,
+                                                              self.swapchain.extent.height));
+        }
+    }
+
+    /// Recreate the swapchain and the objects that depend on it after the surface has changed
     ///
-    pub fn find_suitable_memory(physical_device: &RendererVkPhysicalDevice,
-                                type_filter: u32,
-                                properties: VkMemoryPropertyFlags)
-                                -> u32 {
-        let mut memory_properties = VkPhysicalDeviceMemoryProperties::default();
+    /// Returns false if the surface currently has no area (e.g. the window is minimised), or the
+    /// new swapchain could not be created, in which case the swapchain is left as it was, still
+    /// out of date, and recreation should be attempted again.  The offscreen images of a
+    /// headless renderer are never recreated.
+    fn recreate_swapchain(&mut self) -> bool {
+        if self.swapchain.headless {
+            self.swapchain_out_of_date.store(false, Ordering::SeqCst);
+            return true;
+        }
+
+        self.flush_queued_command_buffers();
         unsafe {
-            vkGetPhysicalDeviceMemoryProperties(physical_device.raw, &mut memory_properties);
+            check_result!("vkDeviceWaitIdle", vkDeviceWaitIdle(self.device.raw));
         }
 
-        for i in 0..memory_properties.memoryTypeCount {
-            if ((type_filter as u32) & (1 << i) != 0) &&
-               ((memory_properties.memoryTypes[i as usize].propertyFlags as u32) & (properties as u32)) == (properties as u32) {
-                return i;
-            }
+        let surface = self.surface.as_mut().unwrap();
+        if let Err(e) = surface.refresh_capabilities(&self.physical_device, self.queue_families.present_index) {
+            log_error!("Unable to query the surface capabilities: {}", e);
+            return false;
+        }
+        if surface.capabilities.currentExtent.width == 0 || surface.capabilities.currentExtent.height == 0 {
+            return false;
         }
 
-        // Could not find a suitable memory type
-        u32::max_value()
+        let swapchain = match RendererVkSwapchain::new(&self.device, surface, self.swapchain.image_count, self.swapchain.raw) {
+            Ok(swapchain) => swapchain,
+            Err(e) => {
+                log_error!("Unable to recreate the swapchain: {}", e);
+                return false;
+            }
+        };
+
+        // The framebuffers reference the old swapchain image views, so must go before the old
+        // swapchain, which is retired now the new one has been created from it
+        //
+        self.framebuffers.clear();
+        *self.swapchain = swapchain;
+
+        self.create_swapchain_framebuffers();
+        self.name_debug_objects();
+
+        self.swapchain_out_of_date.store(false, Ordering::SeqCst);
+
+        self.events.swapchain_recreated((self.swapchain.extent.width, self.swapchain.extent.height));
+
+        true
     }
 
-    /// Choose the first format of a list that fits the desired features
+    /// Create the semaphores between the submission batches of a frame, for every swapchain image
     ///
-    ///
-    fn choose_supported_format(&self,
-                               candidates: &Vec<VkFormat>,
-                               tiling: VkImageTiling,
-                               features: VkFormatFeatureFlags)
-                               -> VkFormat {
-        for format in candidates {
-            let mut props = VkFormatProperties::default();
-            unsafe {
-                vkGetPhysicalDeviceFormatProperties(self.physical_device.raw, *format, &mut props);
+    /// The schedule's dependencies bound the number needed.  Should any fail to be created, the
+    /// frames are submitted as a single batch instead.
+    fn create_pass_semaphores(&mut self) {
+        let count = self.render_graph_schedule.dependency_count();
+        for semaphores in self.pass_semaphores.iter_mut() {
+            while semaphores.len() < count {
+                match RendererVkSemaphore::new(&self.device) {
+                    Ok(semaphore) => semaphores.push(semaphore),
+                    Err(e) => {
+                        log_error!("Unable to create pass semaphore: {}, submitting frames as one batch", e);
+                        return;
+                    }
+                }
             }
+        }
+    }
 
-            if tiling as u32 == VkImageTiling::VK_IMAGE_TILING_LINEAR as u32 &&
-               (props.linearTilingFeatures as u32 & features as u32) == features as u32 {
-                return *format;
-            } else if tiling as u32 == VkImageTiling::VK_IMAGE_TILING_OPTIMAL as u32 &&
-                      (props.optimalTilingFeatures as u32 & features as u32) == features as u32 {
-                return *format;
+    /// Build the modules of the error shader for a vertex layout
+    ///
+    /// device: The Vulkan device
+    /// vertex_array_type: The vertex layout the error shader must accept
+    ///
+    /// Returns: The vertex and fragment shader modules, in that order
+    fn create_error_shader_modules(device: &RendererVkDevice, vertex_array_type: VertexArrayType) -> Vec<RendererVkShaderModule> {
+        let name = format!("error{}", vertex_array_type as u32);
+        let vertex = ShaderSpirv::compile_glsl_source(&name,
+                                                      &error_vertex_shader_source(vertex_array_type),
+                                                      ShaderStage::VertexShader)
+            .expect("Failed to compile the error vertex shader");
+        let fragment = ShaderSpirv::compile_glsl_source(&name, &error_fragment_shader_source(), ShaderStage::FragmentShader)
+            .expect("Failed to compile the error fragment shader");
+
+        vec![RendererVkShaderModule::new(device.raw, &vertex), RendererVkShaderModule::new(device.raw, &fragment)]
+    }
+
+    /// Create a pipeline for a shader that draws with the error shader, so that its pass still draws something
+    ///
+    /// shader_name: The name of the shader that failed
+    /// shader_spec: The specification of the shader
+    fn create_error_pipeline(&mut self, shader_name: &'static str, shader_spec: &ShaderSpec) -> RendererVkPipeline {
+        log_warning!("Substituting the error shader for {}", shader_name);
+
+        let ty = shader_spec.vertex_array_type as u32;
+        if !self.error_shader_modules.contains_key(&ty) {
+            self.error_shader_modules
+                .insert(ty, RendererVk::create_error_shader_modules(&self.device, shader_spec.vertex_array_type));
+        }
+        let error_modules: Vec<(ShaderStage, VkShaderModule, &'static str)> = self.error_shader_modules[&ty]
+            .iter()
+            .zip([ShaderStage::VertexShader, ShaderStage::FragmentShader].iter())
+            .map(|(module, stage)| (*stage, module.get_raw(), DEFAULT_ENTRY_POINT))
+            .collect();
+        self.pipeline_modules.insert(shader_name, error_modules.clone());
+
+        match RendererVkPipeline::new(&self.device,
+                                      self.pipeline_cache.as_ref().unwrap(),
+                                      &self.render_passes[shader_spec.pass_name],
+                                      &shader_spec,
+                                      &error_modules,
+                                      &self.pipeline_layouts[shader_name],
+                                      PolygonMode::Fill,
+                                      self.flip_viewport_y) {
+            Ok(p) => p,
+            Err(res) => {
+                log_error!("Unexpected result from {}: {}", "vkCreateGraphicsPipelines", res);
+                panic!("Failed to create the error pipeline for {}", shader_name);
             }
         }
-
-        panic!("Failed to find supported format");
     }
 
-    /// Choose the most desirable depth format that is available
+    /// Take in the pipelines the worker threads have created since the last call
     ///
+    /// A pipeline that failed has the error shader substituted for it.  Each pipeline taken in is
+    /// reported to the progress callback, and the workers are joined once all are in.
     ///
-    pub fn choose_depth_format(&self) -> VkFormat {
-        self.choose_supported_format(
-            &vec![VkFormat::VK_FORMAT_D32_SFLOAT, VkFormat::VK_FORMAT_D32_SFLOAT_S8_UINT, VkFormat::VK_FORMAT_D24_UNORM_S8_UINT],
-            VkImageTiling::VK_IMAGE_TILING_OPTIMAL,
-            VkFormatFeatureFlagBits::VK_FORMAT_FEATURE_DEPTH_STENCIL_ATTACHMENT_BIT as VkFormatFeatureFlags
-        )
-    }
-}
+    /// wait: Which pipelines to wait for, beyond those already created
+    fn collect_built_pipelines(&mut self, wait: PipelineWait) {
+        if self.pipeline_builder.is_none() {
+            return;
+        }
 
-impl Drop for RendererVk {
-    fn drop(&mut self) {
-        unsafe {
-            check_result!("vkQueueWaitIdle",
-                          vkQueueWaitIdle(self.device.graphics_queue));
+        let resource_manager = self.resource_manager.clone();
+        let mut collected = false;
+        loop {
+            let received = match self.pipeline_builder {
+                Some(ref mut builder) => builder.receive(wait),
+                None => None,
+            };
+            let (shader_name, result, modules) = match received {
+                Some(r) => r,
+                None => break,
+            };
 
-            check_result!("vkDeviceWaitIdle", vkDeviceWaitIdle(self.device.raw));
+            let res_manager = resource_manager.lock().unwrap();
+            let failed = match result {
+                Ok(pipeline) => {
+                    self.name_object(VK_OBJECT_TYPE_PIPELINE, pipeline.raw as u64, shader_name);
+                    self.pipeline_modules.insert(shader_name, modules);
+                    self.render_pipelines.insert(shader_name, pipeline);
+                    false
+                }
+                Err(res) => {
+                    log_error!("Unexpected result from {}: {}", "vkCreateGraphicsPipelines", res);
+                    let pipeline = self.create_error_pipeline(shader_name, &res_manager.shader_specs[shader_name]);
+                    self.render_pipelines.insert(shader_name, pipeline);
+                    true
+                }
+            };
+
+            let progress = self.pipeline_builder.as_mut().and_then(|builder| builder.tracker.complete(shader_name, failed));
+            if let Some(progress) = progress {
+                res_manager.pipeline_builds.report(&progress);
+            }
+            collected = true;
         }
 
-        self.descriptor_sets.clear();
-        self.pipeline_layouts.clear();
-        self.descriptor_set_layouts.clear();
-        self.descriptor_pools.clear();
-        self.uniform_buffers.clear();
+        let finished = match self.pipeline_builder {
+            Some(ref builder) => builder.tracker.is_finished(),
+            None => false,
+        };
+        if finished {
+            self.pipeline_builder.take().unwrap().join();
+            log_info!("Created all of the pipelines queued at startup");
+        }
 
-        self.render_pipelines.clear();
-        self.command_pools.clear();
-        self.framebuffers.clear();
-        self.render_passes.clear();
+        // Pipelines that arrive after setup need their variants in the current polygon mode
+        if collected && self.frame_number > 0 && !self.shut_down {
+            self.create_polygon_mode_pipelines();
+        }
     }
-}
 
-pub struct RendererVkInstance {
-    raw: VkInstance,
-}
-
-impl RendererVkInstance {
-    /// Create a Vulkan instance
-    ///
+    /// Record a push constant update into a thread's command buffer for the current pass
     ///
-    fn new(application_name: &str,
-           application_version: &str,
-           engine_version: &str,
-           instance_layers: &Vec<String>,
-           instance_extensions: &Vec<String>)
-           -> RendererVkInstance {
-        let app_name = CString::new(application_name.to_owned()).unwrap().into_raw();
-        let app_version = Version::parse(application_version).unwrap();
-        let eng_version = Version::parse(engine_version).unwrap();
-        let application_info = VkApplicationInfo {
-            sType: VkStructureType::VK_STRUCTURE_TYPE_APPLICATION_INFO,
-            apiVersion: VK_MAKE_VERSION(1, 0, 0),
-            applicationVersion: VK_MAKE_VERSION(app_version.major as u32,
-                                                app_version.minor as u32,
-                                                app_version.patch as u32),
-            engineVersion: VK_MAKE_VERSION(eng_version.major as u32,
-                                           eng_version.minor as u32,
-                                           eng_version.patch as u32),
-            pApplicationName: app_name,
-            pEngineName: app_name,
-            pNext: ptr::null(),
-        };
-
-        let il: Vec<*mut c_char> = instance_layers.iter().map(|x| CString::new(x.to_owned()).unwrap().into_raw()).collect();
-        let ie: Vec<*mut c_char> = instance_extensions.iter().map(|x| CString::new(x.to_owned()).unwrap().into_raw()).collect();
-
-        let instance_create_info = VkInstanceCreateInfo {
-            sType: VkStructureType::VK_STRUCTURE_TYPE_INSTANCE_CREATE_INFO,
-            pApplicationInfo: &application_info,
-            enabledLayerCount: instance_layers.len() as u32,
-            ppEnabledLayerNames: il.as_ptr() as *mut _,
-            enabledExtensionCount: instance_extensions.len() as u32,
-            ppEnabledExtensionNames: ie.as_ptr() as *mut _,
-            flags: 0,
-            pNext: ptr::null(),
+    /// thr: The thread whose command buffer should record the value
+    /// name: The name of the push constant in the current shader
+    /// bytes: The value to set
+    fn push_constant_bytes(&self, thr: usize, name: &str, bytes: &[u8]) {
+        let push_constant = match self.push_constant_specs[self.shader_name].iter().find(|p| p.name == name) {
+            Some(p) => p,
+            None => panic!("Shader {} has no push constant {}", self.shader_name, name),
         };
+        debug_assert!(bytes.len() <= push_constant.size);
 
-        let mut instance: VkInstance = VK_NULL_HANDLE_MUT();
-        unsafe {
-            check_result!("vkCreateInstance",
-                          vkCreateInstance(&instance_create_info, ptr::null(), &mut instance));
+        // Keep a copy of the block, so that it can be replayed into any later chunks of the pass
+        {
+            let mut state = self.push_constant_state[thr].lock().unwrap();
+            let end = push_constant.offset + bytes.len();
+            if state.len() < end {
+                state.resize(end, 0);
+            }
+            state[push_constant.offset..end].copy_from_slice(bytes);
         }
 
-        let _: CString = unsafe { CString::from_raw(app_name) };
-        let _: Vec<CString> = il.iter().map(|x| unsafe { CString::from_raw(*x) }).collect();
-        let _: Vec<CString> = ie.iter().map(|x| unsafe { CString::from_raw(*x) }).collect();
-
-        RendererVkInstance { raw: instance }
-    }
-}
-
-impl Clone for RendererVkInstance {
-    fn clone(&self) -> RendererVkInstance {
-        unimplemented!();
-    }
-}
-
-impl Drop for RendererVkInstance {
-    fn drop(&mut self) {
         unsafe {
-            vkDestroyInstance(self.raw, ptr::null());
+            vkCmdPushConstants(self.current_command_buffer_raw(thr),
+                               self.pipeline_layouts[self.shader_name].raw,
+                               VkShaderStageFlagBits::VK_SHADER_STAGE_ALL_GRAPHICS as VkShaderStageFlags,
+                               push_constant.offset as u32,
+                               bytes.len() as u32,
+                               bytes.as_ptr() as *const c_void);
         }
     }
-}
 
-pub struct RendererVkDebugCallback {
-    instance: VkInstance,
-    raw: VkDebugReportCallbackEXT,
-}
-
-impl RendererVkDebugCallback {
-    /// Install a Vulkan debug callback function
+    /// Copy the current shader's descriptor set with a thread's textures written into it
     ///
+    /// The uniform blocks and the textures the thread has not switched are copied from the
+    /// shader's own descriptor set.  Returns None if the thread's pool for the frame is used up.
     ///
-    fn new(instance: &RendererVkInstance, debug_mask: u32) -> RendererVkDebugCallback {
-        // The following directly follows the bitmask values defined for Vulkan, and
-        // could just be collapsed, but it seems worth the application explicitly
-        // coding these levels
-        let mut flags: u32 = 0;
-        if (debug_mask & 1) != 0 {
-            flags |= VkDebugReportFlagBitsEXT::VK_DEBUG_REPORT_INFORMATION_BIT_EXT as VkDebugReportFlagsEXT
-        };
-        if (debug_mask & 2) != 0 {
-            flags |= VkDebugReportFlagBitsEXT::VK_DEBUG_REPORT_WARNING_BIT_EXT as VkDebugReportFlagsEXT
-        };
-        if (debug_mask & 4) != 0 {
-            flags |= VkDebugReportFlagBitsEXT::VK_DEBUG_REPORT_PERFORMANCE_WARNING_BIT_EXT as VkDebugReportFlagsEXT
-        };
-        if (debug_mask & 8) != 0 {
-            flags |= VkDebugReportFlagBitsEXT::VK_DEBUG_REPORT_ERROR_BIT_EXT as VkDebugReportFlagsEXT
-        };
-        if (debug_mask & 16) != 0 {
-            flags |= VkDebugReportFlagBitsEXT::VK_DEBUG_REPORT_DEBUG_BIT_EXT as VkDebugReportFlagsEXT
-        };
-
-        let debug_callback_create_info = VkDebugReportCallbackCreateInfoEXT {
-            sType: VkStructureType::VK_STRUCTURE_TYPE_DEBUG_REPORT_CALLBACK_CREATE_INFO_EXT,
-            pfnCallback: Some(RendererVkDebugCallback::debug_callback),
-            flags: flags,
+    /// thr: The thread switching textures
+    /// textures: The bindings and images of the textures switched to
+    fn copy_draw_descriptor_set(&self,
+                                thr: usize,
+                                textures: &[(u32, VkDescriptorImageInfo)])
+                                -> Option<VkDescriptorSet> {
+        let layout = self.descriptor_set_layouts[self.shader_name].raw[0];
+        let alloc_info = VkDescriptorSetAllocateInfo {
+            sType: VkStructureType::VK_STRUCTURE_TYPE_DESCRIPTOR_SET_ALLOCATE_INFO,
+            descriptorPool: self.draw_descriptor_pools[self.image_index][thr].raw,
+            descriptorSetCount: 1,
+            pSetLayouts: &layout,
             pNext: ptr::null(),
-            pUserData: ptr::null_mut(),
         };
 
-        let entrypoint_name = CString::new("vkCreateDebugReportCallbackEXT").unwrap();
-        let opt_void_ptr = unsafe { vkGetInstanceProcAddr(instance.raw, entrypoint_name.as_ptr()) };
-        let mut callback_handle: VkDebugReportCallbackEXT = VK_NULL_HANDLE_MUT();
-        if opt_void_ptr.is_some() {
-            let void_fn_ptr = opt_void_ptr.expect("to_be_found");
-            type SrcType = unsafe extern "C" fn();
-            #[allow(non_snake_case)]
-            type DstType = extern "C" fn(instance: VkInstance,
-                                         pCreateInfo: *const VkDebugReportCallbackCreateInfoEXT,
-                                         pAllocator: *const VkAllocationCallbacks,
-                                         pCallback: *mut VkDebugReportCallbackEXT)
-                                         -> VkResult;
-            #[allow(non_snake_case)]
-            let pfn_vkCreateDebugReportCallbackEXT = unsafe { mem::transmute::<SrcType, DstType>(void_fn_ptr) };
-
-            pfn_vkCreateDebugReportCallbackEXT(instance.raw,
-                                               &debug_callback_create_info,
-                                               ptr::null_mut(),
-                                               &mut callback_handle);
-        }
-
-        RendererVkDebugCallback {
-            instance: instance.raw,
-            raw: callback_handle,
+        let mut descriptor_set: VkDescriptorSet = VK_NULL_HANDLE_MUT();
+        let res = unsafe { vkAllocateDescriptorSets(self.device.raw, &alloc_info, &mut descriptor_set) };
+        if res != VkResult::VK_SUCCESS {
+            log_error!("Thread {} has used its {} descriptor sets for switching textures this frame ({})",
+                       thr,
+                       DRAW_DESCRIPTOR_SETS_PER_THREAD,
+                       res);
+            return None;
+        }
+
+        let src = self.descriptor_sets[self.shader_name].raw;
+        let switched = |binding: u32| textures.iter().any(|&(b, _)| b == binding);
+        let copies: Vec<VkCopyDescriptorSet> = self.draw_bindings[self.shader_name]
+            .buffers
+            .iter()
+            .cloned()
+            .chain(self.texture_descriptors[self.shader_name].iter().map(|&(_, binding)| binding).filter(|&b| !switched(b)))
+            .map(|binding| {
+                VkCopyDescriptorSet {
+                    sType: VkStructureType::VK_STRUCTURE_TYPE_COPY_DESCRIPTOR_SET,
+                    srcSet: src,
+                    srcBinding: binding,
+                    srcArrayElement: 0,
+                    dstSet: descriptor_set,
+                    dstBinding: binding,
+                    dstArrayElement: 0,
+                    descriptorCount: 1,
+                    pNext: ptr::null(),
+                }
+            })
+            .collect();
+        let writes: Vec<VkWriteDescriptorSet> = textures.iter()
+            .map(|&(binding, ref image_info)| {
+                VkWriteDescriptorSet {
+                    sType: VkStructureType::VK_STRUCTURE_TYPE_WRITE_DESCRIPTOR_SET,
+                    dstSet: descriptor_set,
+                    dstBinding: binding,
+                    dstArrayElement: 0,
+                    descriptorType: VkDescriptorType::VK_DESCRIPTOR_TYPE_COMBINED_IMAGE_SAMPLER,
+                    descriptorCount: 1,
+                    pBufferInfo: ptr::null(), // Optional
+                    pImageInfo: image_info,
+                    pTexelBufferView: ptr::null(), // Optional
+                    pNext: ptr::null(),
+                }
+            })
+            .collect();
+
+        unsafe {
+            vkUpdateDescriptorSets(self.device.raw,
+                                   writes.len() as u32,
+                                   writes.as_ptr(),
+                                   copies.len() as u32,
+                                   copies.as_ptr());
         }
+
+        Some(descriptor_set)
     }
 
-    /// A Vulkan debug callback function
+    /// Queue the rebuild of the pipeline for a shader whose SPIR-V has changed
     ///
+    /// The rebuild happens at the start of the next frame, when nothing is using the pipeline.
     ///
-    #[allow(unused_variables)]
-    unsafe extern "C" fn debug_callback(flags: VkDebugReportFlagsEXT,
-                                        obj_type: VkDebugReportObjectTypeEXT,
-                                        src_obj: u64,
-                                        location: usize,
-                                        msg_code: i32,
-                                        layer_prefix: *const c_char,
-                                        msg: *const c_char,
-                                        user_data: *mut c_void)
-                                        -> u32 {
-        let f = flags as u32;
+    /// shader_name: The name of the shader
+    /// stages: The stage, SPIR-V bytecode and entry point of each stage in the shader
+    pub fn request_pipeline_rebuild(&self, shader_name: &'static str, stages: Vec<(ShaderStage, Vec<u8>, &'static str)>) {
+        let mut pending = self.pending_pipeline_rebuilds.lock().unwrap();
+        pending.retain(|&(name, _)| name != shader_name);
+        pending.push((shader_name, stages));
+    }
 
-        if (f & VkDebugReportFlagBitsEXT::VK_DEBUG_REPORT_INFORMATION_BIT_EXT as VkDebugReportFlagsEXT) != 0 {
-            print!("INFO: ");
-        }
-        if (f & VkDebugReportFlagBitsEXT::VK_DEBUG_REPORT_WARNING_BIT_EXT as VkDebugReportFlagsEXT) != 0 {
-            print!("WARN: ");
-        }
-        if (f & VkDebugReportFlagBitsEXT::VK_DEBUG_REPORT_PERFORMANCE_WARNING_BIT_EXT as VkDebugReportFlagsEXT) != 0 {
-            print!("PERF: ");
-        }
-        if (f & VkDebugReportFlagBitsEXT::VK_DEBUG_REPORT_ERROR_BIT_EXT as VkDebugReportFlagsEXT) != 0 {
-            print!("ERRR: ");
-        }
-        if (f & VkDebugReportFlagBitsEXT::VK_DEBUG_REPORT_DEBUG_BIT_EXT as VkDebugReportFlagsEXT) != 0 {
-            print!("DEBG: ");
+    /// Rebuild the pipelines requested since the last frame
+    ///
+    /// The descriptor set layouts, pipeline layout and descriptor set are rebuilt too, in case
+    /// the bindings have changed.  The textures are copied across from the previous descriptor
+    /// set, whereas uniform buffers are bound afresh.  The uniform buffers are not resized, so
+    /// changing the size of a uniform block requires a restart.  When a rebuild fails the
+    /// previous pipeline remains in use.
+    fn apply_pipeline_rebuilds(&mut self) {
+        let rebuilds: Vec<(&'static str, Vec<(ShaderStage, Vec<u8>, &'static str)>)> =
+            self.pending_pipeline_rebuilds.lock().unwrap().drain(..).collect();
+        if rebuilds.is_empty() {
+            return;
         }
-        let prefix = CStr::from_ptr(layer_prefix as *const i8)
-            .to_string_lossy()
-            .into_owned();
-        let message = CStr::from_ptr(msg as *const i8)
-            .to_string_lossy()
-            .into_owned();
-        println!("@[{}]: {}", prefix, message);
 
-        false as u32
-    }
-}
+        // A pipeline still being created from the old code would otherwise replace the rebuilt one
+        self.collect_built_pipelines(PipelineWait::All);
 
-impl Drop for RendererVkDebugCallback {
-    fn drop(&mut self) {
+        // Replacing the objects requires all the frames in flight to have finished with them
+        self.flush_queued_command_buffers();
         unsafe {
-            let entrypoint_name = CString::new("vkDestroyDebugReportCallbackEXT").unwrap();
-            let opt_void_ptr = vkGetInstanceProcAddr(self.instance, entrypoint_name.as_ptr());
-            if opt_void_ptr.is_some() {
-                let void_fn_ptr = opt_void_ptr.expect("to_be_found");
-                type SrcType = unsafe extern "C" fn();
-                #[allow(non_snake_case)]
-                type DstType = extern "C" fn(instance: VkInstance,
-                                             pCallback: VkDebugReportCallbackEXT,
-                                             pAllocator: *const VkAllocationCallbacks)
-                                             -> VkResult;
-    			#[allow(non_snake_case)]
-                let pfn_vkDestroyDebugReportCallbackEXT = mem::transmute::<SrcType, DstType>(void_fn_ptr);
-
-                pfn_vkDestroyDebugReportCallbackEXT(self.instance, self.raw, ptr::null());
-            }
+            check_result!("vkDeviceWaitIdle", vkDeviceWaitIdle(self.device.raw));
         }
-    }
-}
 
-pub struct RendererVkPhysicalDevice {
-    raw: VkPhysicalDevice,
-    features: VkPhysicalDeviceFeatures,
-}
+        let resource_manager = self.resource_manager.clone();
+        let res_manager = resource_manager.lock().unwrap();
 
-impl RendererVkPhysicalDevice {
-    /// Enumerate physical devices and choose one
-    ///
-    ///
-    fn new(instance: &RendererVkInstance, debug_level: u32) -> RendererVkPhysicalDevice {
-        let mut physical_device_count: u32 = 0;
-        unsafe {
-            check_result!("vkEnumeratePhysicalDevices",
-                          vkEnumeratePhysicalDevices(instance.raw, &mut physical_device_count, ptr::null_mut()));
-        }
+        let mut rebuilt: Vec<&'static str> = Vec::with_capacity(rebuilds.len());
+        for (shader_name, stages) in rebuilds {
+            let ref shader_spec = res_manager.shader_specs[shader_name];
 
-        // Enumerate the physical devices supported by Vulkan
-        //
-        let mut physical_devices: Vec<VkPhysicalDevice> = vec![];
-        physical_devices.resize(physical_device_count as usize, VK_NULL_HANDLE_MUT());
-        unsafe {
-            // Now enumerate the physical devices
-            check_result!("vkEnumeratePhysicalDevices",
-                          vkEnumeratePhysicalDevices(instance.raw,
-                                                     &mut physical_device_count,
-                                                     physical_devices.as_mut_ptr()));
-        }
+            let shader_modules: Vec<RendererVkShaderModule> =
+                stages.iter().map(|&(_, ref bytecode, _)| RendererVkShaderModule::new(self.device.raw, bytecode)).collect();
+            let modules: Vec<(ShaderStage, VkShaderModule, &'static str)> = stages.iter()
+                .zip(shader_modules.iter())
+                .map(|(&(stage, _, entry_point), module)| (stage, module.get_raw(), entry_point))
+                .collect();
+
+            if shader_spec.is_compute() {
+                let pipeline = match RendererVkComputePipeline::new(&self.device,
+                                                                    self.pipeline_cache.as_ref().unwrap(),
+                                                                    &res_manager,
+                                                                    &shader_spec,
+                                                                    modules[0],
+                                                                    &self.uniform_buffers,
+                                                                    &HashMap::new()) {
+                    Ok(p) => p,
+                    Err(res) => {
+                        log_error!("Unexpected result from {}: {}", "vkCreateComputePipelines", res);
+                        log_warning!("Keeping the previous pipeline for {}", shader_name);
+                        continue;
+                    }
+                };
 
-        let mut best_score = 0;
-        let mut chosen_device = 0;
-        let mut device_properties = VkPhysicalDeviceProperties::default();
-        let mut device_features = VkPhysicalDeviceFeatures::default();
-        for i in 0..physical_device_count {
-            unsafe {
-                vkGetPhysicalDeviceProperties(physical_devices[i as usize], &mut device_properties);
-                vkGetPhysicalDeviceFeatures(physical_devices[i as usize], &mut device_features);
+                let texture_descriptors = match self.compute_pipelines.get(shader_name) {
+                    Some(old) => {
+                        self.copy_texture_descriptors(shader_name, &shader_spec, &old.descriptor_set, &pipeline.descriptor_set)
+                    }
+                    None => vec![],
+                };
+                self.compute_pipelines.insert(shader_name, pipeline);
+                self.texture_descriptors.insert(shader_name, texture_descriptors);
+                self.rebuilt_shader_modules.insert(shader_name, shader_modules);
+                rebuilt.push(shader_name);
+                continue;
             }
 
-            let mut score = 0;
-            if device_properties.deviceType as u32 == VkPhysicalDeviceType::VK_PHYSICAL_DEVICE_TYPE_DISCRETE_GPU as u32 {
-                if debug_level > 0 {
-                    println!("Device {} scores 10 for being a discrete GPU", i);
+            let descriptor_set_layouts = RendererVkDescriptorSetLayouts::new(&self.device, &res_manager, &shader_spec);
+            let pipeline_layout = RendererVkPipelineLayout::new(&self.device,
+                                                                &descriptor_set_layouts,
+                                                                self.bindless_textures_for(&shader_spec),
+                                                                &shader_spec);
+
+            let pipeline = match RendererVkPipeline::new(&self.device,
+                                                         self.pipeline_cache.as_ref().unwrap(),
+                                                         &self.render_passes[shader_spec.pass_name],
+                                                         &shader_spec,
+                                                         &modules,
+                                                         &pipeline_layout,
+                                                         PolygonMode::Fill,
+                                                         self.flip_viewport_y) {
+                Ok(p) => p,
+                Err(res) => {
+                    log_error!("Unexpected result from {}: {}", "vkCreateGraphicsPipelines", res);
+                    log_warning!("Keeping the previous pipeline for {}", shader_name);
+                    continue;
                 }
-                score += 10
             };
-            if device_features.tessellationShader == 0 {
-                if debug_level > 0 {
-                    println!("Device {} scores -100 for not supporting tessellation shaders",
-                             i);
-                }
-                score -= 100;
-            }
-            if device_features.geometryShader == 0 {
-                if debug_level > 0 {
-                    println!("Device {} scores -100 for not supporting geometry shaders",
-                             i);
-                }
-                score -= 100;
-            }
 
-            if debug_level > 0 {
-                let slice = unsafe { CStr::from_ptr(&device_properties.deviceName as *const c_char) };
-                println!("Physical device number {} is {} with score {}",
-                         i,
-                         CStr::to_string_lossy(slice),
-                         score);
-            }
+            // The old descriptor set can't be freed back to its pool, so the new one comes from a
+            // pool of its own, kept with the shader.  A pool from an earlier rebuild is retired with
+            // the set it holds, once the textures have been copied out of it.
+            //
+            let num_uniform_blocks = cmp::max(shader_spec.uniform_block_names.len(), 1);
+            let num_samplers = cmp::max(self.texture_descriptors[shader_name].len(), 1);
+            let descriptor_pool = RendererVkDescriptorPool::new(&self.device,
+                                                                num_uniform_blocks,
+                                                                num_uniform_blocks,
+                                                                num_samplers,
+                                                                1 /* Maximum desc sets */);
+            let descriptor_set = RendererVkDescriptorSet::new(&self.device,
+                                                              &descriptor_pool,
+                                                              &descriptor_set_layouts,
+                                                              &shader_spec,
+                                                              &self.uniform_buffers,
+                                                              &HashMap::new());
 
-            if score > best_score {
-                chosen_device = i;
-                best_score = score;
+            let texture_descriptors = self.copy_texture_descriptors(shader_name,
+                                                                    &shader_spec,
+                                                                    &self.descriptor_sets[shader_name],
+                                                                    &descriptor_set);
+
+            self.render_pipelines.insert(shader_name, pipeline);
+            self.pipeline_modules.insert(shader_name, modules);
+            self.polygon_mode_pipelines.remove(shader_name);
+            self.pipeline_layouts.insert(shader_name, pipeline_layout);
+            self.descriptor_set_layouts.insert(shader_name, descriptor_set_layouts);
+            self.descriptor_sets.insert(shader_name, descriptor_set);
+            if let Some(old_pool) = self.shader_descriptor_pools.insert(shader_name, descriptor_pool) {
+                self.retired_descriptor_pools[self.image_index].push(old_pool);
             }
+            self.texture_descriptors.insert(shader_name, texture_descriptors);
+            self.draw_bindings.insert(shader_name, RendererVkDrawBindings::new(&shader_spec, &self.uniform_buffers));
+            self.push_constant_specs.insert(shader_name, shader_spec.push_constants.clone());
+            self.rebuilt_shader_modules.insert(shader_name, shader_modules);
+            rebuilt.push(shader_name);
         }
-        if debug_level > 0 {
-            println!("Chose physical device: {}", chosen_device);
-        }
-        if best_score < 0 {
-            panic!("Device does not support required features");
-        }
+        drop(res_manager);
 
-        RendererVkPhysicalDevice {
-            raw: physical_devices[chosen_device as usize],
-            features: device_features,
+        self.create_polygon_mode_pipelines();
+
+        self.name_debug_objects();
+
+        for shader_name in rebuilt {
+            self.events.shader_rebuilt(shader_name);
         }
     }
-}
-
-pub struct RendererVkQueueFamilies {
-    #[allow(dead_code)]
-    raw: Vec<VkQueueFamilyProperties>,
-    index: u32,
-}
 
-impl RendererVkQueueFamilies {
-    /// Enumerate queue families and select one that supports graphics
+    /// Copy the texture descriptors of a shader into the descriptor set replacing its old one
     ///
+    /// shader_name: The name of the shader
+    /// shader_spec: The new specification of the shader, whose bindings may have moved
+    /// src: The descriptor set being replaced
+    /// dst: The new descriptor set
     ///
-    fn new(physical_device: &RendererVkPhysicalDevice) -> RendererVkQueueFamilies {
-        let mut queue_family_count: u32 = 0;
+    /// Returns: The texture names and bindings in the new descriptor set
+    fn copy_texture_descriptors(&self,
+                                shader_name: &'static str,
+                                shader_spec: &ShaderSpec,
+                                src: &RendererVkDescriptorSet,
+                                dst: &RendererVkDescriptorSet)
+                                -> Vec<(&'static str, u32)> {
+        let mut texture_descriptors = vec![];
+        let mut copies = vec![];
+        for &(name, old_binding) in self.texture_descriptors[shader_name].iter() {
+            let new_binding = shader_spec.uniform_specs
+                .iter()
+                .find(|u| u.name == name && u.uniform_type == UniformType::CombinedImageSampler)
+                .map(|u| u.binding);
+            if let Some(binding) = new_binding {
+                copies.push(VkCopyDescriptorSet {
+                    sType: VkStructureType::VK_STRUCTURE_TYPE_COPY_DESCRIPTOR_SET,
+                    srcSet: src.raw,
+                    srcBinding: old_binding,
+                    srcArrayElement: 0,
+                    dstSet: dst.raw,
+                    dstBinding: binding,
+                    dstArrayElement: 0,
+                    descriptorCount: 1,
+                    pNext: ptr::null(),
+                });
+                texture_descriptors.push((name, binding));
+            }
+        }
         unsafe {
-            vkGetPhysicalDeviceQueueFamilyProperties(physical_device.raw,
-                                                     &mut queue_family_count,
-                                                     ptr::null_mut());
+            vkUpdateDescriptorSets(self.device.raw,
+                                   0, // Write count
+                                   ptr::null(),
+                                   copies.len() as u32,
+                                   copies.as_ptr());
         }
 
-        let mut queue_properties_array: Vec<VkQueueFamilyProperties> = vec![];
-        queue_properties_array.resize(queue_family_count as usize,
-                                      VkQueueFamilyProperties::default());
+        texture_descriptors
+    }
+
+    /// Point the descriptors and graph attachments referring to a render target at its replacement
+    ///
+    /// Descriptor sets in use by frames in flight cannot be written, so this waits for the device
+    /// to go idle, after which the old render target may be destroyed.
+    ///
+    /// old: The render target being replaced
+    /// new: The replacement, with the same number of colour attachments
+    pub fn replace_render_target(&mut self, old: &RenderTargetVk, new: &RenderTargetVk) {
+        self.flush_queued_command_buffers();
         unsafe {
-            vkGetPhysicalDeviceQueueFamilyProperties(physical_device.raw,
-                                                     &mut queue_family_count,
-                                                     queue_properties_array.as_mut_ptr());
+            check_result!("vkDeviceWaitIdle", vkDeviceWaitIdle(self.device.raw));
         }
 
-        let mut graphics_queue_index: u32 = u32::max_value();
-        for i in 0..queue_family_count {
-            if queue_properties_array[i as usize].queueFlags & (VkQueueFlagBits::VK_QUEUE_GRAPHICS_BIT as VkQueueFlags) != 0 {
-                graphics_queue_index = i;
+        for attachment in 0..old.get_colour_attachment_count() {
+            let old_texture = match old.get_colour_texture(attachment).as_any().downcast_ref::<TextureVk>() {
+                Some(t) => t,
+                None => panic!("Unexpected runtime type"),
+            };
+            let new_texture = match new.get_colour_texture(attachment).as_any().downcast_ref::<TextureVk>() {
+                Some(t) => t,
+                None => panic!("Unexpected runtime type"),
+            };
+
+            self.repoint_texture_descriptors(old_texture, new_texture);
+
+            let old_image = old_texture.texture.get_image_raw();
+            for bound in self.graph_attachments.values_mut().filter(|a| a.image == old_image) {
+                bound.image = new_texture.texture.get_image_raw();
+                bound.layout = VkImageLayout::VK_IMAGE_LAYOUT_COLOR_ATTACHMENT_OPTIMAL;
             }
         }
-        if graphics_queue_index == u32::max_value() {
-            panic!("Suitable queue family not found");
-        }
 
-        RendererVkQueueFamilies {
-            raw: queue_properties_array,
-            index: graphics_queue_index,
+        let old_depth_image = old.get_depth_image_raw();
+        for bound in self.graph_attachments.values_mut().filter(|a| a.image == old_depth_image) {
+            bound.image = new.get_depth_image_raw();
+            bound.layout = VkImageLayout::VK_IMAGE_LAYOUT_DEPTH_STENCIL_ATTACHMENT_OPTIMAL;
         }
     }
-}
-
-pub struct RendererVkSurface {
-    instance: VkInstance,
-    raw: VkSurfaceKHR,
-    format: VkSurfaceFormatKHR,
-    capabilities: VkSurfaceCapabilitiesKHR,
-    presentation: VkPresentModeKHR,
-}
 
-impl RendererVkSurface {
-    /// Create Vulkan window surface
+    /// Point the descriptors referring to a texture at its replacement
     ///
+    /// Descriptor sets in use by frames in flight cannot be written, so this waits for the device
+    /// to go idle, after which the old texture may be destroyed.  Slots of the bindless texture
+    /// array are not tracked, so a texture registered there must be registered again.
     ///
-    fn new(window: &Window,
-           instance: &RendererVkInstance,
-           physical_device: &RendererVkPhysicalDevice,
-           queue_family_index: u32,
-           debug_level: u32)
-           -> RendererVkSurface {
-        // The following test is probably the same as the vkGetPhysicalDeviceSurfaceSupportKHR
-        // call in query_surface_capabilities(), but just make sure GLFW thinks it is happy too
-        if !window.get_physical_device_presentation_support(instance.raw as usize,
-                                                            physical_device.raw as usize,
-                                                            queue_family_index) {
-            panic!("Queue family does not support image presentation");
-        }
-
-        let mut surface: VkSurfaceKHR = VK_NULL_HANDLE_MUT();
+    /// old: The texture being replaced
+    /// new: The replacement
+    pub fn replace_texture(&mut self, old: &TextureVk, new: &TextureVk) {
+        self.flush_queued_command_buffers();
         unsafe {
-            let res = glfw::ffi::glfwCreateWindowSurface(mem::transmute(instance.raw),
-                                                         window.window_ptr(),
-                                                         ptr::null(),
-                                                         mem::transmute(&mut surface));
-            if res != VkResult::VK_SUCCESS as u32 {
-                panic!("Unable to create Vulkan surface");
-            }
+            check_result!("vkDeviceWaitIdle", vkDeviceWaitIdle(self.device.raw));
         }
 
-        RendererVkSurface {
-            instance: instance.raw,
-            raw: surface,
-            format: RendererVkSurface::choose_surface_format(physical_device, surface),
-            capabilities: RendererVkSurface::determine_surface_capabilities(physical_device, queue_family_index, surface),
-            presentation: RendererVkSurface::choose_presentation_mode(physical_device, surface, debug_level),
-        }
+        self.repoint_texture_descriptors(old, new);
     }
 
-    /// Choose Vulkan window surface format
+    /// Write a replacement texture into the descriptors of every name the old texture is known by
     ///
+    /// The device must be idle.
     ///
-    fn choose_surface_format(physical_device: &RendererVkPhysicalDevice, raw_surface: VkSurfaceKHR) -> VkSurfaceFormatKHR {
-        let mut format_count: u32 = 0;
-        unsafe {
-            check_result!("vkGetPhysicalDeviceSurfaceFormatsKHR",
-                          vkGetPhysicalDeviceSurfaceFormatsKHR(physical_device.raw,
-                                                               raw_surface,
-                                                               &mut format_count,
-                                                               ptr::null_mut()));
-        }
-        if format_count == 0 {
-            panic!("Surface formats missing");
+    /// old: The texture being replaced
+    /// new: The replacement
+    fn repoint_texture_descriptors(&mut self, old: &TextureVk, new: &TextureVk) {
+        let old_view = old.texture.get_view_raw();
+        let names: Vec<&'static str> =
+            self.texture_views.iter().filter(|&(_, view)| *view == old_view).map(|(name, _)| *name).collect();
+        for name in names {
+            self.texture_views.insert(name, new.texture.get_view_raw());
+            self.write_texture_descriptors(name, new);
         }
+    }
 
-        // Enumerate the surface formats available and pick one
-        //
-        let mut formats: Vec<VkSurfaceFormatKHR> = vec![];
-        let default_format = VkSurfaceFormatKHR {
-            format: VkFormat::VK_FORMAT_R8G8B8A8_UNORM,
-            colorSpace: VkColorSpaceKHR::VK_COLOR_SPACE_SRGB_NONLINEAR_KHR,
+    /// Write a texture into the descriptor sets of every shader sampling it under a name
+    ///
+    /// name: The name of the texture in the shader specifications
+    /// texture: The texture to write
+    fn write_texture_descriptors(&self, name: &'static str, texture: &TextureVk) {
+        let image_info = VkDescriptorImageInfo {
+            imageLayout: VkImageLayout::VK_IMAGE_LAYOUT_SHADER_READ_ONLY_OPTIMAL,
+            imageView: texture.texture.view.raw,
+            sampler: texture.texture.sampler,
         };
-        formats.resize(format_count as usize, default_format);
-        unsafe {
-            check_result!("vkGetPhysicalDeviceSurfaceFormatsKHR",
-                          vkGetPhysicalDeviceSurfaceFormatsKHR(physical_device.raw,
-                                                               raw_surface,
-                                                               &mut format_count,
-                                                               formats.as_mut_ptr()));
-        }
 
-        // Arbitrarily pick the first available format
-        let mut format = formats[0];
+        let mut descriptor_writes = vec![];
+        for (shader_name, descriptors) in self.texture_descriptors.iter() {
+            let descriptor_set = match self.descriptor_sets.get(shader_name) {
+                Some(set) => set.raw,
+                None => {
+                    match self.compute_pipelines.get(shader_name) {
+                        Some(pipeline) => pipeline.descriptor_set.raw,
+                        None => continue,
+                    }
+                }
+            };
 
-        if format_count == 1 && matches!(formats[0].format, VkFormat::VK_FORMAT_UNDEFINED) {
-            // The driver has indicated that no format is preferred
-            format = default_format;
+            for &(_, binding) in descriptors.iter().filter(|&&(texture_name, _)| texture_name == name) {
+                descriptor_writes.push(VkWriteDescriptorSet {
+                    sType: VkStructureType::VK_STRUCTURE_TYPE_WRITE_DESCRIPTOR_SET,
+                    dstSet: descriptor_set,
+                    dstBinding: binding,
+                    dstArrayElement: 0,
+                    descriptorType: VkDescriptorType::VK_DESCRIPTOR_TYPE_COMBINED_IMAGE_SAMPLER,
+                    descriptorCount: 1,
+                    pBufferInfo: ptr::null(), // Optional
+                    pImageInfo: &image_info,
+                    pTexelBufferView: ptr::null(), // Optional
+                    pNext: ptr::null(),
+                });
+            }
         }
 
-        format
+        unsafe {
+            vkUpdateDescriptorSets(self.device.raw,
+                                   descriptor_writes.len() as u32,
+                                   descriptor_writes.as_ptr(),
+                                   0, // Copy count
+                                   ptr::null() /* Descriptor copies */);
+        }
     }
 
-    /// Determine Vulkan surface capabilities
+    /// Set the sizes at which a thread submits its commands part way through a pass
     ///
+    /// Without a limit a single large pass leaves all of its work unsubmitted until the end
+    /// of the pass.  A threshold of zero disables that limit.
     ///
-    fn determine_surface_capabilities(physical_device: &RendererVkPhysicalDevice,
-                                      graphics_queue_family_index: u32,
-                                      raw_surface: VkSurfaceKHR)
-                                      -> VkSurfaceCapabilitiesKHR {
-        // Determine whether the surface is supported or not
-        let mut supported: VkBool32 = false as VkBool32;
-        unsafe {
-            check_result!("vkGetPhysicalDeviceSurfaceSupportKHR",
-                          vkGetPhysicalDeviceSurfaceSupportKHR(physical_device.raw,
-                                                               graphics_queue_family_index,
-                                                               raw_surface,
-                                                               &mut supported));
-        }
-        if supported != true as VkBool32 {
-            panic!("The surface is not supported");
-        }
+    /// draws: The number of draws after which to submit
+    /// bytes: The number of bytes of vertex data after which to submit
+    pub fn set_chunk_thresholds(&mut self, draws: usize, bytes: usize) {
+        self.chunk_draw_threshold = draws;
+        self.chunk_byte_threshold = bytes;
+    }
 
-        // Query the surface capabilities
-        //
-        let mut surface_capabilities = VkSurfaceCapabilitiesKHR::default();
-        unsafe {
-            check_result!("vkGetPhysicalDeviceSurfaceCapabilitiesKHR",
-                          vkGetPhysicalDeviceSurfaceCapabilitiesKHR(physical_device.raw, raw_surface, &mut surface_capabilities));
+    /// Return the command buffer a thread is currently recording into
+    ///
+    /// thr: The thread
+    fn current_command_buffer_raw(&self, thr: usize) -> VkCommandBuffer {
+        match self.current_chunk[thr] {
+            None => self.command_buffers[self.image_index][self.pass_index][thr].raw,
+            Some(chunk) => self.chunk_command_buffers[self.image_index][thr][chunk].raw,
         }
+    }
 
-        if surface_capabilities.currentExtent.width == u32::max_value() {
-            panic!("Unable to get surface dimensions");
-        }
+    /// Move a thread on to the next vertex buffer of the current type, creating one if necessary
+    ///
+    /// The index of the vertex buffer is returned.
+    ///
+    /// thr: The thread that will write to the vertex buffer
+    fn next_vertex_buffer(&mut self, thr: usize) -> usize {
+        let ty = self.vertex_array_type;
+        let image_index = self.image_index;
 
-        debug_assert!(surface_capabilities.currentExtent.width >= surface_capabilities.minImageExtent.width);
-        debug_assert!(surface_capabilities.currentExtent.width <= surface_capabilities.maxImageExtent.width);
-        debug_assert!(surface_capabilities.currentExtent.height >= surface_capabilities.minImageExtent.height);
-        debug_assert!(surface_capabilities.currentExtent.height <= surface_capabilities.maxImageExtent.height);
+        self.vertex_buffer_index[image_index][ty as usize][thr] += 1;
+        if self.vertex_buffer_index[image_index][ty as usize][thr] ==
+           self.vertex_buffer[image_index][ty as usize][thr].len() as i32 {
+            let storage = self.vertex_buffer_storage;
+            let triangles = self.triangle_capacity;
+            self.vertex_buffer[image_index][ty as usize][thr]
+                .push(RendererVkVertexBuffer::new(&self.device, &self.physical_device, ty, triangles, storage));
+        }
 
-        surface_capabilities
+        self.vertex_buffer_index[image_index][ty as usize][thr] as usize
     }
 
-    /// Choose a Vulkan presentation mode
+    /// Queue the copy of a thread's current vertex buffer from its staging buffer, if it has one
     ///
+    /// Staged data is copied before the draw is submitted, once the host has written it.
     ///
-    fn choose_presentation_mode(physical_device: &RendererVkPhysicalDevice,
-                                raw_surface: VkSurfaceKHR,
-                                debug_level: u32)
-                                -> VkPresentModeKHR {
-        let mut presentation_mode_count: u32 = 0;
-        unsafe {
-            check_result!("vkGetPhysicalDeviceSurfacePresentModesKHR",
-                          vkGetPhysicalDeviceSurfacePresentModesKHR(physical_device.raw,
-                                                                    raw_surface,
-                                                                    &mut presentation_mode_count,
-                                                                    ptr::null_mut()));
+    /// thr: The thread that wrote to the vertex buffer
+    /// bytes: The number of bytes written
+    fn queue_vertex_upload(&mut self, thr: usize, bytes: usize) {
+        let ty = self.vertex_array_type;
+        let image_index = self.image_index;
+        let vb_index = self.vertex_buffer_index[image_index][ty as usize][thr] as usize;
+
+        let ref vertex_buffer = self.vertex_buffer[image_index][ty as usize][thr][vb_index];
+        if let Some(ref staging) = vertex_buffer.staging {
+            self.vertex_uploads.push((staging.raw, vertex_buffer.buffer.raw, bytes));
         }
+    }
 
-        let mut presentation_mode_list: Vec<VkPresentModeKHR> = vec![];
-        presentation_mode_list.resize(presentation_mode_count as usize,
-                                      VkPresentModeKHR::VK_PRESENT_MODE_MAX_ENUM_KHR);
-        unsafe {
-            check_result!("vkGetPhysicalDeviceSurfacePresentModesKHR",
-                          vkGetPhysicalDeviceSurfacePresentModesKHR(physical_device.raw,
-                                                                    raw_surface,
-                                                                    &mut presentation_mode_count,
-                                                                    presentation_mode_list.as_mut_ptr()));
+    /// Return the next host-visible buffer of instance data for a thread, creating one if necessary
+    ///
+    /// thr: The thread that will write to the instance buffer
+    fn next_instance_buffer(&mut self, thr: usize) -> (RendererVkMappedMemory, VkBuffer) {
+        let image_index = self.image_index;
+        let used = self.instance_buffers_used[thr];
+        if self.instance_buffers[image_index][thr].len() == used {
+            let usage = VkBufferUsageFlagBits::VK_BUFFER_USAGE_VERTEX_BUFFER_BIT as VkBufferUsageFlags;
+            let properties = VkMemoryPropertyFlagBits::VK_MEMORY_PROPERTY_HOST_VISIBLE_BIT as VkMemoryPropertyFlags |
+                             VkMemoryPropertyFlagBits::VK_MEMORY_PROPERTY_HOST_COHERENT_BIT as VkMemoryPropertyFlags;
+            self.instance_buffers[image_index][thr].push(RendererVkBuffer::new(&self.device,
+                                                                               &self.physical_device,
+                                                                               usage,
+                                                                               properties,
+                                                                               INSTANCE_BUFFER_SIZE));
+        }
+        self.instance_buffers_used[thr] += 1;
+
+        let ref instance_buffer = self.instance_buffers[image_index][thr][used];
+        (instance_buffer.mapped(), instance_buffer.raw)
+    }
+
+    /// Clear the attachments of the current pass, in the pass's pre-pass command buffer
+    ///
+    /// An otherwise empty instance of a clearing variant of the render pass is recorded, ahead
+    /// of the render pass instances of the threads, which load what it cleared.
+    ///
+    /// clear: The values to clear the attachments to
+    fn record_pass_clear(&mut self, clear: &PassClear) {
+        let key = (self.current_pass_name, clear.colour.is_some(), clear.depth.is_some());
+        if !self.clearing_render_passes.contains_key(&key) {
+            let render_pass = self.render_passes[self.current_pass_name].clearing_variant(&self.device, key.1, key.2);
+            self.clearing_render_passes.insert(key, render_pass);
         }
 
-        let mut presentation_mode: VkPresentModeKHR = VkPresentModeKHR::VK_PRESENT_MODE_MAX_ENUM_KHR;
-        for mode in presentation_mode_list.iter() {
-            if debug_level > 0 {
-                println!("Available presentation mode: {} {}", *mode as i32, *mode);
-            }
-            if matches!(*mode, VkPresentModeKHR::VK_PRESENT_MODE_FIFO_KHR) {
-                presentation_mode = *mode;
+        {
+            let command_buffer = &self.prepass_command_buffers[self.image_index][self.pass_index];
+            let render_pass = &self.clearing_render_passes[&key];
+            render_pass.begin(command_buffer.raw,
+                              self.current_render_target.unwrap(),
+                              self.current_render_target_extent.0,
+                              self.current_render_target_extent.1,
+                              clear);
+            render_pass.end(command_buffer.raw);
+
+            // The threads' render pass instances draw over the cleared attachments
+            let colour_output = VkPipelineStageFlagBits::VK_PIPELINE_STAGE_COLOR_ATTACHMENT_OUTPUT_BIT as VkPipelineStageFlags;
+            let early_tests = VkPipelineStageFlagBits::VK_PIPELINE_STAGE_EARLY_FRAGMENT_TESTS_BIT as VkPipelineStageFlags;
+            let late_tests = VkPipelineStageFlagBits::VK_PIPELINE_STAGE_LATE_FRAGMENT_TESTS_BIT as VkPipelineStageFlags;
+            command_buffer.memory_barrier(VkAccessFlagBits::VK_ACCESS_COLOR_ATTACHMENT_WRITE_BIT as VkAccessFlags |
+                                          VkAccessFlagBits::VK_ACCESS_DEPTH_STENCIL_ATTACHMENT_WRITE_BIT as VkAccessFlags,
+                                          VkAccessFlagBits::VK_ACCESS_COLOR_ATTACHMENT_READ_BIT as VkAccessFlags |
+                                          VkAccessFlagBits::VK_ACCESS_COLOR_ATTACHMENT_WRITE_BIT as VkAccessFlags |
+                                          VkAccessFlagBits::VK_ACCESS_DEPTH_STENCIL_ATTACHMENT_READ_BIT as VkAccessFlags |
+                                          VkAccessFlagBits::VK_ACCESS_DEPTH_STENCIL_ATTACHMENT_WRITE_BIT as VkAccessFlags,
+                                          colour_output | late_tests,
+                                          colour_output | early_tests);
+        }
+
+        // The clear leaves the depth image ready for use as a depth attachment
+        if let (Some(_), Some(depth_image)) = (clear.depth, self.current_depth_target) {
+            for attachment in self.graph_attachments.values_mut().filter(|a| a.image == depth_image) {
+                attachment.layout = VkImageLayout::VK_IMAGE_LAYOUT_DEPTH_STENCIL_ATTACHMENT_OPTIMAL;
             }
         }
-        if matches!(presentation_mode,
-                    VkPresentModeKHR::VK_PRESENT_MODE_MAX_ENUM_KHR) {
-            presentation_mode = presentation_mode_list[0];
-        }
-        if debug_level > 0 {
-            println!("Selected presentation mode is: {} {}",
-                     presentation_mode as i32,
-                     presentation_mode);
+    }
+
+    /// Begin the render pass in a thread's command buffer, which has just been begun, and set
+    /// the viewport and push constants for the pass
+    ///
+    /// The pipeline and descriptor sets are bound by bind_draw_state before the thread's first
+    /// draw, so that threads that draw nothing in the pass bind nothing.
+    ///
+    /// command_buffer: The command buffer to record into
+    /// thr: The thread that owns the command buffer
+    fn begin_thread_render_pass(&self, command_buffer: VkCommandBuffer, thr: usize) {
+        self.bound_state[thr].lock().unwrap().reset();
+
+        if let Some(ref debug_utils) = self.debug_utils {
+            debug_utils.begin_label(command_buffer, self.shader_name);
         }
 
-        presentation_mode
-    }
-}
+        self.render_passes[self.current_pass_name].begin(command_buffer,
+                                                         self.current_render_target.unwrap(),
+                                                         self.current_render_target_extent.0,
+                                                         self.current_render_target_extent.1,
+                                                         &PassClear::default());
 
-impl Drop for RendererVkSurface {
-    fn drop(&mut self) {
+        let (width, height) = self.current_render_target_extent;
+        let viewport = VkViewport {
+            x: 0.0f32,
+            y: if self.flip_viewport_y { height as f32 } else { 0.0f32 },
+            width: width as f32,
+            height: if self.flip_viewport_y { -(height as f32) } else { height as f32 },
+            minDepth: 0.0f32,
+            maxDepth: 1.0f32,
+        };
+        let scissor = VkRect2D {
+            offset: VkOffset2D { x: 0, y: 0 },
+            extent: VkExtent2D {
+                width: width,
+                height: height,
+            },
+        };
+
+        // Later chunks of the pass keep any push constants the thread has set
+        let push_constant_state = self.push_constant_state[thr].lock().unwrap();
         unsafe {
-            vkDestroySurfaceKHR(self.instance, self.raw, ptr::null());
+            vkCmdSetViewport(command_buffer,
+                             0, // First viewport
+                             1, // Viewport count
+                             &viewport);
+            vkCmdSetScissor(command_buffer,
+                            0, // First scissor
+                            1, // Scissor count
+                            &scissor);
+
+            if !push_constant_state.is_empty() {
+                vkCmdPushConstants(command_buffer,
+                                   self.pipeline_layouts[self.shader_name].raw,
+                                   VkShaderStageFlagBits::VK_SHADER_STAGE_ALL_GRAPHICS as VkShaderStageFlags,
+                                   0, // Offset
+                                   push_constant_state.len() as u32,
+                                   push_constant_state.as_ptr() as *const c_void);
+            }
         }
     }
-}
-
-pub struct RendererVkDevice {
-    raw: VkDevice,
-    graphics_queue: VkQueue,
-}
 
-impl RendererVkDevice {
-    /// Set up structures required for creating a Vulkan device
+    /// Bind the pipeline and descriptor sets of the current pass in a thread's command buffer,
+    /// for a draw
     ///
+    /// Only what differs from what the command buffer already has bound is bound, so a pass
+    /// that draws with a single shader binds its pipeline once per command buffer, and its
+    /// descriptor set again only when the thread has switched textures.
     ///
-    fn new(physical_device: &RendererVkPhysicalDevice,
-           queue_family_index: u32,
-           instance_layers: &Vec<String>,
-           device_extensions: &Vec<String>)
-           -> RendererVkDevice {
-        let priorities: Vec<f32> = vec![1.0f32];
-        let queue_create_info = VkDeviceQueueCreateInfo {
-            sType: VkStructureType::VK_STRUCTURE_TYPE_DEVICE_QUEUE_CREATE_INFO,
-            queueCount: 1,
-            queueFamilyIndex: queue_family_index,
-            pQueuePriorities: priorities.as_ptr(),
-            flags: 0,
-            pNext: ptr::null(),
+    /// thr: The thread that owns the command buffer
+    /// command_buffer: The command buffer to record into
+    fn bind_draw_state(&self, thr: usize, command_buffer: VkCommandBuffer) {
+        let mut bound_state = self.bound_state[thr].lock().unwrap();
+        let pipeline = match self.polygon_mode_pipelines.get(self.shader_name) {
+            Some(p) => p.raw,
+            None => self.render_pipelines[self.shader_name].raw,
         };
+        let ref pipeline_layout = self.pipeline_layouts[self.shader_name];
 
-        let il: Vec<*mut c_char> = instance_layers.iter().map(|x| CString::new(x.to_owned()).unwrap().into_raw()).collect();
-        let de: Vec<*mut c_char> = device_extensions.iter().map(|x| CString::new(x.to_owned()).unwrap().into_raw()).collect();
-
-        let device_create_info = VkDeviceCreateInfo {
-            sType: VkStructureType::VK_STRUCTURE_TYPE_DEVICE_CREATE_INFO,
-            queueCreateInfoCount: 1,
-            pQueueCreateInfos: &queue_create_info,
-            // Enabling all features seems like cheating: We have the missed
-            // opportunity to request all the features the application needs and
-            // have the API reject device creation if they are not available.
-            pEnabledFeatures: &physical_device.features,
-            enabledLayerCount: instance_layers.len() as u32,
-            ppEnabledLayerNames: il.as_ptr() as *mut _,
-            enabledExtensionCount: device_extensions.len() as u32,
-            ppEnabledExtensionNames: de.as_ptr() as *mut _,
-            flags: 0,
-            pNext: ptr::null(),
+        // Later chunks of the pass keep any textures the thread has switched to
+        let descriptor_set = match self.draw_textures[thr].lock().unwrap().set {
+            Some(set) => set,
+            None => self.descriptor_sets[self.shader_name].raw,
         };
 
-        // Create a Vulkan device
-        //
-        let mut device: VkDevice = VK_NULL_HANDLE_MUT();
         unsafe {
-            check_result!("vkCreateDevice",
-                          vkCreateDevice(physical_device.raw,
-                                         &device_create_info,
-                                         ptr::null(),
-                                         &mut device));
-        }
-
-        let _: Vec<CString> = il.iter().map(|x| unsafe { CString::from_raw(*x) }).collect();
-        let _: Vec<CString> = de.iter().map(|x| unsafe { CString::from_raw(*x) }).collect();
+            if bound_state.bind_pipeline(pipeline as u64) {
+                vkCmdBindPipeline(command_buffer, VkPipelineBindPoint::VK_PIPELINE_BIND_POINT_GRAPHICS, pipeline);
+            }
 
-        let mut graphics_queue: VkQueue = VK_NULL_HANDLE_MUT();
-        unsafe {
-            vkGetDeviceQueue(device, queue_family_index, 0, &mut graphics_queue);
-        };
+            if bound_state.bind_descriptor_set(pipeline_layout.raw as u64, 0, descriptor_set as u64) {
+                vkCmdBindDescriptorSets(command_buffer,
+                                        VkPipelineBindPoint::VK_PIPELINE_BIND_POINT_GRAPHICS,
+                                        pipeline_layout.raw,
+                                        0, // First set
+                                        1, // Descriptor set count
+                                        &descriptor_set,
+                                        0, // Dynamic offset count
+                                        ptr::null()); // Dynamic offsets
+            }
 
-        RendererVkDevice {
-            raw: device,
-            graphics_queue: graphics_queue,
+            if let Some(bindless_set) = pipeline_layout.bindless_set {
+                if bound_state.bind_descriptor_set(pipeline_layout.raw as u64, BINDLESS_TEXTURE_SET, bindless_set as u64) {
+                    vkCmdBindDescriptorSets(command_buffer,
+                                            VkPipelineBindPoint::VK_PIPELINE_BIND_POINT_GRAPHICS,
+                                            pipeline_layout.raw,
+                                            BINDLESS_TEXTURE_SET,
+                                            1, // Descriptor set count
+                                            &bindless_set,
+                                            0, // Dynamic offset count
+                                            ptr::null()); // Dynamic offsets
+                }
+            }
         }
     }
-}
 
-impl Drop for RendererVkDevice {
-    fn drop(&mut self) {
+    /// Bind vertex buffers in a thread's command buffer, unless they are already bound there
+    ///
+    /// thr: The thread that owns the command buffer
+    /// command_buffer: The command buffer to record into
+    /// vertex_buffers: The buffers, from binding zero, each bound from its start
+    fn bind_vertex_buffers(&self, thr: usize, command_buffer: VkCommandBuffer, vertex_buffers: &[VkBuffer]) {
+        let bound: Vec<(u64, u64)> = vertex_buffers.iter().map(|b| (*b as u64, 0)).collect();
+        if !self.bound_state[thr].lock().unwrap().bind_vertex_buffers(0, &bound) {
+            self.skipped_binds.skip();
+            return;
+        }
+
+        let buffer_offsets: Vec<VkDeviceSize> = vec![0; vertex_buffers.len()];
         unsafe {
-            vkDestroyDevice(self.raw, ptr::null());
+            vkCmdBindVertexBuffers(command_buffer,
+                                   0, // First binding
+                                   vertex_buffers.len() as u32,
+                                   vertex_buffers.as_ptr(),
+                                   buffer_offsets.as_ptr());
         }
     }
-}
-
-pub struct RendererVkSwapchain {
-    device: VkDevice,
-    raw: VkSwapchainKHR,
-    image_count: u32,
-    images: Vec<VkImage>,
-    views: Vec<VkImageView>,
-}
 
-impl RendererVkSwapchain {
-    /// Create a swapchain
-    ///
+    /// End the render pass in a thread's command buffer, along with its labelled region
     ///
-    fn new(device: &RendererVkDevice, surface: &RendererVkSurface, image_count: u32) -> RendererVkSwapchain {
-        debug_assert!(image_count >= surface.capabilities.minImageCount);
-        debug_assert!(image_count <= surface.capabilities.maxImageCount);
-
-        let layers = 1; // Non-stereoscopic
-        debug_assert!(layers <= surface.capabilities.maxImageArrayLayers);
+    /// command_buffer: The command buffer to record into
+    fn end_thread_render_pass(&self, command_buffer: VkCommandBuffer) {
+        self.render_passes[self.current_pass_name].end(command_buffer);
 
-        let usage = VkImageUsageFlagBits::VK_IMAGE_USAGE_COLOR_ATTACHMENT_BIT as VkImageUsageFlags;
-        debug_assert!(((surface.capabilities.supportedUsageFlags as u32) & (usage as u32)) == (usage as u32));
+        if let Some(ref debug_utils) = self.debug_utils {
+            debug_utils.end_label(command_buffer);
+        }
+    }
 
-        let transform = VkSurfaceTransformFlagBitsKHR::VK_SURFACE_TRANSFORM_IDENTITY_BIT_KHR;
-        debug_assert!(((surface.capabilities.supportedTransforms as u32) & (transform as u32)) == (transform as u32));
+    /// Queue command buffers to be submitted to the graphics queue along with the rest of the
+    /// frame's work
+    ///
+    /// The command buffers are noted as belonging to the render graph pass last begun, so that
+    /// end_frame can split its submission at the dependencies between passes.  Work flushed
+    /// before then is ordered by the barriers recorded between the passes.
+    ///
+    /// command_buffers: The raw command buffers to queue
+    fn queue_command_buffers(&self, command_buffers: &Vec<VkCommandBuffer>) {
+        let pass = self.current_graph_pass;
+        self.queued_command_buffers.lock().unwrap().extend(command_buffers.iter().map(|&c| (pass, c)));
+    }
 
-        let alpha = VkCompositeAlphaFlagBitsKHR::VK_COMPOSITE_ALPHA_OPAQUE_BIT_KHR;
-        debug_assert!(((surface.capabilities.supportedCompositeAlpha as u32) & (alpha as u32)) == (alpha as u32));
+    /// Submit the queued command buffers to the graphics queue
+    ///
+    /// This must be called before other work is submitted to the graphics queue, or the queue
+    /// or device is waited on, so that the queued work keeps its place in the queue.
+    pub fn flush_queued_command_buffers(&self) {
+        let command_buffers: Vec<VkCommandBuffer> =
+            self.queued_command_buffers.lock().unwrap().drain(..).map(|(_, c)| c).collect();
+        if !command_buffers.is_empty() {
+            self.submit_command_buffers(&command_buffers);
+        }
+    }
 
-        let swapchain_create_info = VkSwapchainCreateInfoKHR {
-            sType: VkStructureType::VK_STRUCTURE_TYPE_SWAPCHAIN_CREATE_INFO_KHR,
-            surface: surface.raw,
-            minImageCount: image_count,
-            imageFormat: surface.format.format,
-            imageColorSpace: surface.format.colorSpace,
-            imageExtent: VkExtent2D {
-                width: surface.capabilities.currentExtent.width,
-                height: surface.capabilities.currentExtent.height,
-            },
-            imageArrayLayers: layers,
-            imageUsage: usage,
-            imageSharingMode: VkSharingMode::VK_SHARING_MODE_EXCLUSIVE,
-            queueFamilyIndexCount: 0, // None due to VK_SHARING_MODE_EXCLUSIVE
-            pQueueFamilyIndices: ptr::null_mut(), // Null due to VK_SHARING_MODE_EXCLUSIVE
-            preTransform: transform,
-            compositeAlpha: alpha,
-            presentMode: surface.presentation,
-            clipped: true as VkBool32,
-            oldSwapchain: ptr::null_mut(),
-            flags: 0,
+    /// Submit command buffers to the graphics queue without any synchronisation
+    ///
+    /// Submissions to the queue are ordered, so work submitted later waits for the barriers
+    /// recorded here.
+    ///
+    /// command_buffers: The raw command buffers to submit
+    fn submit_command_buffers(&self, command_buffers: &Vec<VkCommandBuffer>) {
+        let submit_info = VkSubmitInfo {
+            sType: VkStructureType::VK_STRUCTURE_TYPE_SUBMIT_INFO,
+            waitSemaphoreCount: 0,
+            pWaitSemaphores: ptr::null(),
+            pWaitDstStageMask: ptr::null(),
+            commandBufferCount: command_buffers.len() as u32,
+            pCommandBuffers: command_buffers.as_ptr(),
+            signalSemaphoreCount: 0,
+            pSignalSemaphores: ptr::null(),
             pNext: ptr::null(),
         };
-
-        let mut swapchain: VkSwapchainKHR = VK_NULL_HANDLE_MUT();
         unsafe {
-            check_result!("vkCreateSwapchainKHR",
-                          vkCreateSwapchainKHR(device.raw,
-                                               &swapchain_create_info,
-                                               ptr::null_mut(),
-                                               &mut swapchain));
+            check_device_result!(self.events,
+                                 "vkQueueSubmit",
+                                 vkQueueSubmit(self.device.graphics_queue,
+                                               1,
+                                               &submit_info,
+                                               VK_NULL_HANDLE_MUT() /* Fence */));
+        }
+        self.queue_submits.fetch_add(1, Ordering::Relaxed);
+    }
+
+    /// Account for a draw by a thread, submitting its commands so far if they are large enough
+    ///
+    /// The thread's render pass is ended and submitted with the rest of the queued work, so that
+    /// the GPU can start on it, then continued in a fresh command buffer with the pass state
+    /// bound again.
+    ///
+    /// thr: The thread that recorded the draw
+    /// bytes: The number of bytes of vertex data used by the draw
+    fn account_for_draw(&mut self, thr: usize, bytes: usize) {
+        self.chunk_draws[thr] += 1;
+        self.chunk_bytes[thr] += bytes;
+
+        let over_draws = self.chunk_draw_threshold > 0 && self.chunk_draws[thr] >= self.chunk_draw_threshold;
+        let over_bytes = self.chunk_byte_threshold > 0 && self.chunk_bytes[thr] >= self.chunk_byte_threshold;
+        if !over_draws && !over_bytes {
+            return;
         }
 
-        let mut local_image_count: u32 = 0;
+        let command_buffer = self.current_command_buffer_raw(thr);
+        self.end_thread_render_pass(command_buffer);
         unsafe {
-            check_result!("vkGetSwapchainImagesKHR",
-                          vkGetSwapchainImagesKHR(device.raw,
-                                                  swapchain,
-                                                  &mut local_image_count,
-                                                  ptr::null_mut()));
+            check_result!("vkEndCommandBuffer", vkEndCommandBuffer(command_buffer));
         }
-        debug_assert!(local_image_count == image_count);
+        self.queue_draw_command_buffers(vec![command_buffer]);
+        self.flush_queued_command_buffers();
+
+        // Chunk command buffers are used once per frame, as earlier ones may still be pending
+        //
+        let image_index = self.image_index;
+        let chunk = self.chunks_used[thr];
+        if self.chunk_command_buffers[image_index][thr].len() == chunk {
+            self.chunk_command_buffers[image_index][thr].push(RendererVkCommandBuffer::new(&self.device,
+                                                                                           &self.command_pools[thr],
+                                                                                           true /* primary */));
+        }
+        self.chunks_used[thr] += 1;
+        self.current_chunk[thr] = Some(chunk);
+        self.chunk_draws[thr] = 0;
+        self.chunk_bytes[thr] = 0;
 
-        let mut swapchain_images: Vec<VkImage> = vec![];
-        swapchain_images.resize(local_image_count as usize, VK_NULL_HANDLE_MUT());
         unsafe {
-            vkGetSwapchainImagesKHR(device.raw,
-                                    swapchain,
-                                    &mut local_image_count,
-                                    swapchain_images.as_mut_ptr());
+            check_result!("vkResetCommandBuffer",
+                          vkResetCommandBuffer(self.chunk_command_buffers[image_index][thr][chunk].raw,
+                                               0 /* flags */));
         }
-        debug_assert!(local_image_count == image_count);
+        self.chunk_command_buffers[image_index][thr][chunk].begin_primary(true, // one_time_submit
+                                                                          false, // render_pass_continue
+                                                                          false /* simultaneous_use */);
+        self.begin_thread_render_pass(self.chunk_command_buffers[image_index][thr][chunk].raw, thr);
+    }
 
-        let mut swapchain_image_views: Vec<VkImageView> = vec![];
-        swapchain_image_views.resize(image_count as usize, VK_NULL_HANDLE_MUT());
-
-        for i in 0..image_count {
-            let create_info = VkImageViewCreateInfo {
-                sType: VkStructureType::VK_STRUCTURE_TYPE_IMAGE_VIEW_CREATE_INFO,
-                image: swapchain_images[i as usize],
-                viewType: VkImageViewType::VK_IMAGE_VIEW_TYPE_2D,
-                format: surface.format.format,
-                components: VkComponentMapping {
-                    r: VkComponentSwizzle::VK_COMPONENT_SWIZZLE_IDENTITY,
-                    g: VkComponentSwizzle::VK_COMPONENT_SWIZZLE_IDENTITY,
-                    b: VkComponentSwizzle::VK_COMPONENT_SWIZZLE_IDENTITY,
-                    a: VkComponentSwizzle::VK_COMPONENT_SWIZZLE_IDENTITY,
-                },
-                subresourceRange: VkImageSubresourceRange {
-                    aspectMask: VkImageAspectFlagBits::VK_IMAGE_ASPECT_COLOR_BIT as VkImageAspectFlags,
-                    baseMipLevel: 0,
-                    levelCount: 1,
-                    baseArrayLayer: 0,
-                    layerCount: 1,
-                },
-                flags: 0,
-                pNext: ptr::null(),
-            };
+    /// Queue command buffers of draws, preceded by the copies of any vertex data they read from
+    /// staging buffers
+    ///
+    /// command_buffers: The command buffers to queue
+    fn queue_draw_command_buffers(&mut self, mut command_buffers: Vec<VkCommandBuffer>) {
+        if !self.vertex_uploads.is_empty() {
+            let image_index = self.image_index;
+            let upload = self.vertex_uploads_used;
+            if self.vertex_upload_command_buffers[image_index].len() == upload {
+                self.vertex_upload_command_buffers[image_index].push(RendererVkCommandBuffer::new(&self.device,
+                                                                                                  &self.aux_command_pool,
+                                                                                                  true /* primary */));
+            }
+            self.vertex_uploads_used += 1;
 
+            let ref command_buffer = self.vertex_upload_command_buffers[image_index][upload];
             unsafe {
-                check_result!("vkCreateImageView",
-                              vkCreateImageView(device.raw,
-                                                &create_info,
-                                                ptr::null_mut(),
-                                                &mut swapchain_image_views[i as usize]));
+                check_result!("vkResetCommandBuffer",
+                              vkResetCommandBuffer(command_buffer.raw, 0 /* flags */));
             }
-        }
+            command_buffer.begin_primary(true, // one_time_submit
+                                         false, // render_pass_continue
+                                         false /* simultaneous_use */);
+
+            for &(staging, buffer, size) in self.vertex_uploads.iter() {
+                let region = VkBufferCopy {
+                    srcOffset: 0,
+                    dstOffset: 0,
+                    size: size as VkDeviceSize,
+                };
+                unsafe {
+                    vkCmdCopyBuffer(command_buffer.raw, staging, buffer, 1 /* Region count */, &region);
+                }
+            }
+            command_buffer.memory_barrier(VkAccessFlagBits::VK_ACCESS_TRANSFER_WRITE_BIT as VkAccessFlags,
+                                          VkAccessFlagBits::VK_ACCESS_VERTEX_ATTRIBUTE_READ_BIT as VkAccessFlags,
+                                          VkPipelineStageFlagBits::VK_PIPELINE_STAGE_TRANSFER_BIT as VkPipelineStageFlags,
+                                          VkPipelineStageFlagBits::VK_PIPELINE_STAGE_VERTEX_INPUT_BIT as VkPipelineStageFlags);
+            command_buffer.end();
+            command_buffers.insert(0, command_buffer.raw);
 
-        RendererVkSwapchain {
-            device: device.raw,
-            raw: swapchain,
-            image_count: image_count,
-            images: swapchain_images,
-            views: swapchain_image_views,
+            self.vertex_uploads.clear();
         }
+
+        self.queue_command_buffers(&command_buffers);
     }
-}
 
-impl Drop for RendererVkSwapchain {
-    fn drop(&mut self) {
-        for view in self.views.iter() {
-            unsafe {
-                vkDestroyImageView(self.device, *view, ptr::null());
-            }
-        }
+    /// Return the index of the per-frame synchronisation objects for the current frame
+    fn frame_slot(&self) -> usize {
+        (self.frame_number % self.image_available_semaphores.len() as u64) as usize
+    }
+
+    /// Record the clear of a depth image, leaving it ready for use as a depth attachment
+    ///
+    /// This must be recorded outside of a render pass.
+    ///
+    /// command_buffer: The command buffer to record into
+    /// depth_image: The raw depth image to clear
+    /// aspect_mask: The aspects of the depth image, which include stencil for a stencil format
+    /// depth: The depth value to clear to
+    fn record_depth_clear(command_buffer: &RendererVkCommandBuffer,
+                          depth_image: VkImage,
+                          aspect_mask: VkImageAspectFlags,
+                          depth: f32) {
+        let clear_stencil = unsafe {
+            mem::transmute_copy(&VkClearDepthStencilValue {
+                depth: depth,
+                stencil: 0,
+            })
+        };
+
+        RendererVkImage::transition_layout(depth_image,
+                                           command_buffer,
+                                           aspect_mask,
+                                           VkImageLayout::VK_IMAGE_LAYOUT_UNDEFINED,
+                                           VkImageLayout::VK_IMAGE_LAYOUT_TRANSFER_DST_OPTIMAL,
+                                           VkPipelineStageFlagBits::VK_PIPELINE_STAGE_COLOR_ATTACHMENT_OUTPUT_BIT
+                                               as VkPipelineStageFlags,
+                                           VkPipelineStageFlagBits::VK_PIPELINE_STAGE_TRANSFER_BIT
+                                               as VkPipelineStageFlags);
+
+        let subresource_range = VkImageSubresourceRange {
+            aspectMask: aspect_mask,
+            baseMipLevel: 0,
+            levelCount: 1,
+            baseArrayLayer: 0,
+            layerCount: 1,
+        };
 
         unsafe {
-            vkDestroySwapchainKHR(self.device, self.raw, ptr::null());
+            vkCmdClearDepthStencilImage(command_buffer.raw,
+                                        depth_image,
+                                        VkImageLayout::VK_IMAGE_LAYOUT_TRANSFER_DST_OPTIMAL,
+                                        &clear_stencil,
+                                        1, // Subrange count
+                                        &subresource_range);
         }
-    }
-}
 
-struct OneTimeCommandBuffer {
-    queue: VkQueue,
-    buffer: RendererVkCommandBuffer,
-}
+        RendererVkImage::transition_layout(depth_image,
+                                           command_buffer,
+                                           aspect_mask,
+                                           VkImageLayout::VK_IMAGE_LAYOUT_TRANSFER_DST_OPTIMAL,
+                                           VkImageLayout::VK_IMAGE_LAYOUT_DEPTH_STENCIL_ATTACHMENT_OPTIMAL,
+                                           VkPipelineStageFlagBits::VK_PIPELINE_STAGE_TRANSFER_BIT
+                                               as VkPipelineStageFlags,
+                                           VkPipelineStageFlagBits::VK_PIPELINE_STAGE_COLOR_ATTACHMENT_OUTPUT_BIT
+                                               as VkPipelineStageFlags);
+    }
 
-impl OneTimeCommandBuffer {
-    /// Create a single-use command buffer
+    /// Record the uniform buffer contents captured by synchronise_uniform_buffer
     ///
+    /// Updating the buffers on the GPU timeline means that the host does not have to wait
+    /// for earlier passes, which may still be reading the previous contents, to complete.
+    /// This must be recorded outside of a render pass.
     ///
-    fn new(device: &RendererVkDevice, command_pool: &RendererVkCommandPool) -> OneTimeCommandBuffer {
-        let command_buffer = RendererVkCommandBuffer::new(device, command_pool, true /* primary */);
+    /// command_buffer: The command buffer to record into
+    fn record_pending_uniform_updates(&self, command_buffer: &RendererVkCommandBuffer) {
+        let mut pending = self.pending_uniform_updates.lock().unwrap();
+        if pending.is_empty() {
+            return;
+        }
 
-        command_buffer.begin_primary(true, // one_time_submit
-                                     false, // render_pass_continue
-                                     false /* simultaneous_use */);
+        command_buffer.memory_barrier(VkAccessFlagBits::VK_ACCESS_UNIFORM_READ_BIT as VkAccessFlags,
+                                      VkAccessFlagBits::VK_ACCESS_TRANSFER_WRITE_BIT as VkAccessFlags,
+                                      VkPipelineStageFlagBits::VK_PIPELINE_STAGE_ALL_GRAPHICS_BIT as VkPipelineStageFlags,
+                                      VkPipelineStageFlagBits::VK_PIPELINE_STAGE_TRANSFER_BIT as VkPipelineStageFlags);
 
-        OneTimeCommandBuffer {
-            queue: device.graphics_queue,
-            buffer: command_buffer,
+        for (buffer_name, bytes) in pending.drain(..) {
+            let ref buffer = self.uniform_buffers[buffer_name.as_str()];
+
+            // vkCmdUpdateBuffer is limited to 64KiB in multiples of four bytes, which is
+            // comfortably more than the uniform blocks in use
+            debug_assert!(bytes.len() <= 65536 && bytes.len() % 4 == 0);
+
+            unsafe {
+                vkCmdUpdateBuffer(command_buffer.raw,
+                                  buffer.buffer.raw,
+                                  0, // Offset
+                                  bytes.len() as VkDeviceSize,
+                                  bytes.as_ptr() as *const c_void);
+            }
         }
+
+        command_buffer.memory_barrier(VkAccessFlagBits::VK_ACCESS_TRANSFER_WRITE_BIT as VkAccessFlags,
+                                      VkAccessFlagBits::VK_ACCESS_UNIFORM_READ_BIT as VkAccessFlags,
+                                      VkPipelineStageFlagBits::VK_PIPELINE_STAGE_TRANSFER_BIT as VkPipelineStageFlags,
+                                      VkPipelineStageFlagBits::VK_PIPELINE_STAGE_ALL_GRAPHICS_BIT as VkPipelineStageFlags);
     }
 
-    /// Execute the single-use command buffer
+    /// Find an available memory that suits the requirements
     ///
     ///
-    fn execute(&mut self) {
-        self.buffer.end();
-
-        let command_buffers = vec![self.buffer.raw];
-        let submit_info = VkSubmitInfo {
-            sType: VkStructureType::VK_STRUCTURE_TYPE_SUBMIT_INFO,
-            commandBufferCount: command_buffers.len() as u32,
-            pCommandBuffers: command_buffers.as_ptr(),
-            waitSemaphoreCount: 0,
-            pWaitSemaphores: ptr::null(),
-            pWaitDstStageMask: ptr::null(),
-            signalSemaphoreCount: 0,
-            pSignalSemaphores: ptr::null(),
-            pNext: ptr::null(),
-        };
-
+    pub fn find_suitable_memory(physical_device: &RendererVkPhysicalDevice,
+                                type_filter: u32,
+                                properties: VkMemoryPropertyFlags)
+                                -> u32 {
+        let mut memory_properties = VkPhysicalDeviceMemoryProperties::default();
         unsafe {
-            check_result!("vkQueueSubmit",
-                          vkQueueSubmit(self.queue, 1, &submit_info, VK_NULL_HANDLE_MUT()));
-            check_result!("vkQueueWaitIdle", vkQueueWaitIdle(self.queue));
+            vkGetPhysicalDeviceMemoryProperties(physical_device.raw, &mut memory_properties);
+        }
+
+        for i in 0..memory_properties.memoryTypeCount {
+            if ((type_filter as u32) & (1 << i) != 0) &&
+               ((memory_properties.memoryTypes[i as usize].propertyFlags as u32) & (properties as u32)) == (properties as u32) {
+                return i;
+            }
         }
+
+        // Could not find a suitable memory type
+        u32::max_value()
     }
-}
 
-pub struct RendererVkImage {
-    device: VkDevice,
-    raw: VkImage,
-    memory: VkDeviceMemory,
-}
+    /// Choose the first format of a list that fits the desired features
+    ///
+    ///
+    fn choose_supported_format(&self,
+                               candidates: &Vec<VkFormat>,
+                               tiling: VkImageTiling,
+                               features: VkFormatFeatureFlags)
+                               -> VkFormat {
+        for format in candidates {
+            let mut props = VkFormatProperties::default();
+            unsafe {
+                vkGetPhysicalDeviceFormatProperties(self.physical_device.raw, *format, &mut props);
+            }
 
-impl RendererVkImage {
-    /// Return the raw image handle
-    pub fn get_image_raw(&self) -> VkImage {
-        self.raw
+            if tiling as u32 == VkImageTiling::VK_IMAGE_TILING_LINEAR as u32 &&
+               (props.linearTilingFeatures as u32 & features as u32) == features as u32 {
+                return *format;
+            } else if tiling as u32 == VkImageTiling::VK_IMAGE_TILING_OPTIMAL as u32 &&
+                      (props.optimalTilingFeatures as u32 & features as u32) == features as u32 {
+                return *format;
+            }
+        }
+
+        panic!("Failed to find supported format");
     }
 
-    /// Create an image
+    /// Choose the most desirable depth format that is available
     ///
+    /// Formats with a stencil component are preferred, so that shaders may use the stencil test.
+    pub fn choose_depth_format(&self) -> VkFormat {
+        self.choose_supported_format(
+            &vec![VkFormat::VK_FORMAT_D32_SFLOAT_S8_UINT, VkFormat::VK_FORMAT_D24_UNORM_S8_UINT, VkFormat::VK_FORMAT_D32_SFLOAT],
+            VkImageTiling::VK_IMAGE_TILING_OPTIMAL,
+            VkFormatFeatureFlagBits::VK_FORMAT_FEATURE_DEPTH_STENCIL_ATTACHMENT_BIT as VkFormatFeatureFlags
+        )
+    }
+
+    /// Return the aspects of the depth images, which include stencil if the depth format has it
+    fn depth_aspect_mask(&self) -> VkImageAspectFlags {
+        RendererVkImage::depth_aspect_mask(self.choose_depth_format())
+    }
+}
+
+impl RendererVk {
+    /// Return the bindless texture array if the shader reads it and the device supports it
     ///
-    pub fn new(device: &RendererVkDevice,
-               physical_device: &RendererVkPhysicalDevice,
-               command_pool: &RendererVkCommandPool,
-               width: u32,
-               height: u32,
-               format: VkFormat,
-               tiling: VkImageTiling,
-               usage: VkImageUsageFlags,
-               memory_properties: VkMemoryPropertyFlags,
-               initial_layout: VkImageLayout,
-               final_layout: VkImageLayout)
-               -> RendererVkImage {
-        let image_info = VkImageCreateInfo {
-            sType: VkStructureType::VK_STRUCTURE_TYPE_IMAGE_CREATE_INFO,
-            imageType: VkImageType::VK_IMAGE_TYPE_2D,
-            extent: VkExtent3D {
-                width: width,
-                height: height,
-                depth: 1,
-            },
-            mipLevels: 1,
-            arrayLayers: 1,
-            format: format,
-            tiling: tiling,
-            initialLayout: VkImageLayout::VK_IMAGE_LAYOUT_PREINITIALIZED,
-            usage: usage,
-            samples: VkSampleCountFlagBits::VK_SAMPLE_COUNT_1_BIT,
-            sharingMode: VkSharingMode::VK_SHARING_MODE_EXCLUSIVE,
-            queueFamilyIndexCount: 0,
-            pQueueFamilyIndices: VK_NULL_HANDLE_MUT(),
-            flags: 0,
-            pNext: ptr::null(),
-        };
+    /// shader_spec: The specification of the shader
+    fn bindless_textures_for(&self, shader_spec: &ShaderSpec) -> Option<&RendererVkBindlessTextures> {
+        if shader_spec.bindless_textures {
+            self.bindless_textures.as_ref()
+        } else {
+            None
+        }
+    }
 
-        let mut image: VkImage = VK_NULL_HANDLE_MUT();
-        unsafe {
-            check_result!("vkCreateImage",
-                          vkCreateImage(device.raw, &image_info, ptr::null_mut(), &mut image));
+    /// Record the paced compute slices planned for this frame, returning whether there are any
+    ///
+    /// Each slice is bracketed by timestamps, which are read back once the frame has completed.
+    fn record_compute_slices(&mut self) -> bool {
+        if self.compute_pacer.is_idle() {
+            return false;
         }
 
-        let mut memory_requirements = VkMemoryRequirements::default();
-        unsafe {
-            vkGetImageMemoryRequirements(device.raw, image, &mut memory_requirements);
+        let slices = self.compute_pacer.plan_frame();
+        if slices.is_empty() {
+            return false;
         }
 
-        let alloc_info = VkMemoryAllocateInfo {
-            sType: VkStructureType::VK_STRUCTURE_TYPE_MEMORY_ALLOCATE_INFO,
-            allocationSize: memory_requirements.size,
-            memoryTypeIndex: RendererVk::find_suitable_memory(physical_device,
-                                                              memory_requirements.memoryTypeBits,
-                                                              memory_properties),
-            pNext: ptr::null(),
-        };
+        let image_index = self.image_index;
+        let timed = self.timestamp_period_ns > 0.0;
+        {
+            let command_buffer = &self.compute_command_buffers[image_index];
+            let query_pool = &self.compute_query_pools[image_index];
 
-        let mut image_memory: VkDeviceMemory = VK_NULL_HANDLE_MUT();
-        unsafe {
-            check_result!("vkAllocateMemory",
-                          vkAllocateMemory(device.raw, &alloc_info, ptr::null(), &mut image_memory));
+            unsafe {
+                check_result!("vkResetCommandBuffer",
+                              vkResetCommandBuffer(command_buffer.raw, 0 /* flags */));
+            }
+            command_buffer.begin_primary(true, // one_time_submit
+                                         false, // render_pass_continue
+                                         true /* simultaneous_use */);
 
-            check_result!("vkBindBufferMemory",
-                          vkBindImageMemory(device.raw, image, image_memory, 0));
-        }
+            if timed {
+                unsafe {
+                    vkCmdResetQueryPool(command_buffer.raw, query_pool.raw, 0, 2 * slices.len() as u32);
+                }
+            }
 
-        let image = RendererVkImage {
-            device: device.raw,
-            raw: image,
-            memory: image_memory,
-        };
+            // Consecutive slices of the same shader share the pipeline and descriptor set bound
+            let mut bound_shader = None;
+            for (i, slice) in slices.iter().enumerate() {
+                let pipeline = match self.compute_pipelines.get(slice.shader_name) {
+                    Some(p) => p,
+                    None => {
+                        log_error!("No compute pipeline for {}", slice.shader_name);
+                        continue;
+                    }
+                };
 
-        let mut aspect_mask = 0;
-        if final_layout as u32 == VkImageLayout::VK_IMAGE_LAYOUT_DEPTH_STENCIL_ATTACHMENT_OPTIMAL as u32 {
-            aspect_mask |= VkImageAspectFlagBits::VK_IMAGE_ASPECT_DEPTH_BIT as VkImageAspectFlags;
+                if timed {
+                    unsafe {
+                        vkCmdWriteTimestamp(command_buffer.raw,
+                                            VkPipelineStageFlagBits::VK_PIPELINE_STAGE_TOP_OF_PIPE_BIT,
+                                            query_pool.raw,
+                                            2 * i as u32);
+                    }
+                }
 
-            if RendererVkImage::has_stencil_component(format) {
-                aspect_mask |= VkImageAspectFlagBits::VK_IMAGE_ASPECT_STENCIL_BIT as VkImageAspectFlags;
+                let bind = bound_shader != Some(slice.shader_name);
+                if !bind {
+                    self.skipped_binds.skip();
+                }
+                pipeline.record_slice(command_buffer, slice, bind);
+                bound_shader = Some(slice.shader_name);
+
+                if timed {
+                    unsafe {
+                        vkCmdWriteTimestamp(command_buffer.raw,
+                                            VkPipelineStageFlagBits::VK_PIPELINE_STAGE_BOTTOM_OF_PIPE_BIT,
+                                            query_pool.raw,
+                                            2 * i as u32 + 1);
+                    }
+                }
             }
-        } else {
-            aspect_mask |= VkImageAspectFlagBits::VK_IMAGE_ASPECT_COLOR_BIT as VkImageAspectFlags;
-        };
 
-        if initial_layout as u32 != final_layout as u32 {
-            RendererVkImage::transition_layout_immediate(image.raw,
-                                               device,
-                                               command_pool,
-                                               aspect_mask,
-                                               initial_layout,
-                                               final_layout,
-                                               VkPipelineStageFlagBits::VK_PIPELINE_STAGE_TOP_OF_PIPE_BIT as VkPipelineStageFlags,
-                                               VkPipelineStageFlagBits::VK_PIPELINE_STAGE_TOP_OF_PIPE_BIT as VkPipelineStageFlags);
+            // Make the results visible to whatever reads the storage buffers next
+            command_buffer.memory_barrier(VkAccessFlagBits::VK_ACCESS_SHADER_WRITE_BIT as VkAccessFlags,
+                                          VkAccessFlagBits::VK_ACCESS_UNIFORM_READ_BIT as VkAccessFlags |
+                                          VkAccessFlagBits::VK_ACCESS_SHADER_READ_BIT as VkAccessFlags |
+                                          VkAccessFlagBits::VK_ACCESS_VERTEX_ATTRIBUTE_READ_BIT as VkAccessFlags |
+                                          VkAccessFlagBits::VK_ACCESS_TRANSFER_READ_BIT as VkAccessFlags,
+                                          VkPipelineStageFlagBits::VK_PIPELINE_STAGE_COMPUTE_SHADER_BIT as VkPipelineStageFlags,
+                                          VkPipelineStageFlagBits::VK_PIPELINE_STAGE_ALL_COMMANDS_BIT as VkPipelineStageFlags);
+
+            command_buffer.end();
         }
 
-        image
-    }
+        self.compute_slices_in_flight[image_index] = slices;
 
-    /// Does the specified format support a stencil component?
-    ///
-    ///
-    fn has_stencil_component(format: VkFormat) -> bool {
-        format as u32 == VkFormat::VK_FORMAT_D32_SFLOAT_S8_UINT as u32 ||
-        format as u32 == VkFormat::VK_FORMAT_D24_UNORM_S8_UINT as u32
+        true
     }
 
-    /// Add a command to the specified command buffer to transition an image layout into a new layout
+    /// Report the paced compute slices of the frame last rendered to this image as completed
     ///
-    ///
-    fn transition_layout(image: VkImage,
-                         command_buffer: &RendererVkCommandBuffer,
-                         aspect_mask: VkImageAspectFlags,
-                         old_layout: VkImageLayout,
-                         new_layout: VkImageLayout,
-                         src_stage_mask: VkPipelineStageFlags,
-                         dst_stage_mask: VkPipelineStageFlags) {
-        let mut barrier = VkImageMemoryBarrier {
-            sType: VkStructureType::VK_STRUCTURE_TYPE_IMAGE_MEMORY_BARRIER,
-            oldLayout: old_layout,
-            newLayout: new_layout,
-            srcQueueFamilyIndex: VK_QUEUE_FAMILY_IGNORED as u32,
-            dstQueueFamilyIndex: VK_QUEUE_FAMILY_IGNORED as u32,
-            image: image,
-            subresourceRange: VkImageSubresourceRange::default(), // Filled in below
-            srcAccessMask: 0, // Filled in below
-            dstAccessMask: 0, // Filled in below
-            pNext: ptr::null(),
+    /// The frame's fence must have been waited on.
+    fn complete_compute_slices(&mut self) {
+        let slices: Vec<ComputeSlice> = self.compute_slices_in_flight[self.image_index].drain(..).collect();
+        if slices.is_empty() {
+            return;
+        }
+
+        let timestamps = if self.timestamp_period_ns > 0.0 {
+            self.compute_query_pools[self.image_index].get_timestamps(2 * slices.len() as u32)
+        } else {
+            vec![]
         };
 
-        barrier.subresourceRange.aspectMask = aspect_mask;
-        barrier.subresourceRange.baseMipLevel = 0;
-        barrier.subresourceRange.levelCount = 1;
-        barrier.subresourceRange.baseArrayLayer = 0;
-        barrier.subresourceRange.layerCount = 1;
+        for (i, slice) in slices.iter().enumerate() {
+            let gpu_ms = if timestamps.is_empty() {
+                None
+            } else {
+                let ticks = timestamps[2 * i + 1].saturating_sub(timestamps[2 * i]);
+                Some(ticks as f32 * self.timestamp_period_ns / 1.0e6)
+            };
+            self.compute_pacer.slice_completed(slice, gpu_ms);
+        }
+    }
 
-        match old_layout {
-            VkImageLayout::VK_IMAGE_LAYOUT_PREINITIALIZED => {
-                barrier.srcAccessMask = VkAccessFlagBits::VK_ACCESS_HOST_WRITE_BIT as VkAccessFlags;
-            }
-            VkImageLayout::VK_IMAGE_LAYOUT_COLOR_ATTACHMENT_OPTIMAL => {
-                barrier.srcAccessMask = VkAccessFlagBits::VK_ACCESS_COLOR_ATTACHMENT_WRITE_BIT as VkAccessFlags;
-            }
-            VkImageLayout::VK_IMAGE_LAYOUT_TRANSFER_SRC_OPTIMAL => {
-                barrier.srcAccessMask = VkAccessFlagBits::VK_ACCESS_TRANSFER_READ_BIT as VkAccessFlags;
-            }
-            VkImageLayout::VK_IMAGE_LAYOUT_TRANSFER_DST_OPTIMAL => {
-                barrier.srcAccessMask = VkAccessFlagBits::VK_ACCESS_TRANSFER_WRITE_BIT as VkAccessFlags;
-            }
-            VkImageLayout::VK_IMAGE_LAYOUT_UNDEFINED => {
-                barrier.srcAccessMask = 0;
-            }
-            _ => {
-                panic!("Unsupported transition source layout");
+    /// Take a step of incremental defragmentation of the mesh memory
+    ///
+    /// The meshes whose moves have completed replace their originals, which are retired until
+    /// the frames in flight have finished with them.  Moves are then started for meshes that can
+    /// go to fuller memory blocks, until defragment_bytes_per_frame have been started, so the
+    /// emptier blocks drain over several frames and are released.
+    ///
+    /// Images are not moved.  Moving one would mean recreating its views and rewriting every
+    /// descriptor and framebuffer referring to it, so images keep the memory they were created
+    /// in, and the blocks they sit in only drain as they are destroyed.
+    fn defragment_meshes(&mut self) {
+        let moves = mem::replace(&mut self.mesh_moves, vec![]);
+        for mesh_move in moves.into_iter() {
+            if mesh_move.is_complete() {
+                let handle = mesh_move.handle;
+                let mesh = mesh_move.into_mesh();
+                mesh.commit_relocation(&self.device);
+                self.meshes.replace(handle, mesh);
+            } else {
+                self.mesh_moves.push(mesh_move);
             }
-        };
+        }
 
-        match new_layout {
-            VkImageLayout::VK_IMAGE_LAYOUT_PREINITIALIZED => {
-                barrier.dstAccessMask = VkAccessFlagBits::VK_ACCESS_HOST_WRITE_BIT as VkAccessFlags;
-            }
-            VkImageLayout::VK_IMAGE_LAYOUT_COLOR_ATTACHMENT_OPTIMAL => {
-                barrier.dstAccessMask = VkAccessFlagBits::VK_ACCESS_COLOR_ATTACHMENT_WRITE_BIT as VkAccessFlags;
-            }
-            VkImageLayout::VK_IMAGE_LAYOUT_TRANSFER_SRC_OPTIMAL => {
-                barrier.dstAccessMask = VkAccessFlagBits::VK_ACCESS_TRANSFER_READ_BIT as VkAccessFlags;
-            }
-            VkImageLayout::VK_IMAGE_LAYOUT_TRANSFER_DST_OPTIMAL => {
-                barrier.dstAccessMask = VkAccessFlagBits::VK_ACCESS_TRANSFER_WRITE_BIT as VkAccessFlags;
-            }
-            VkImageLayout::VK_IMAGE_LAYOUT_SHADER_READ_ONLY_OPTIMAL => {
-                barrier.dstAccessMask = VkAccessFlagBits::VK_ACCESS_SHADER_READ_BIT as VkAccessFlags;
-            }
-            VkImageLayout::VK_IMAGE_LAYOUT_DEPTH_STENCIL_ATTACHMENT_OPTIMAL => {
-                barrier.dstAccessMask = VkAccessFlagBits::VK_ACCESS_DEPTH_STENCIL_ATTACHMENT_WRITE_BIT as VkAccessFlags;
-            }
-            VkImageLayout::VK_IMAGE_LAYOUT_PRESENT_SRC_KHR => {
-                barrier.dstAccessMask = VkAccessFlagBits::VK_ACCESS_MEMORY_READ_BIT as VkAccessFlags;
-            }
-            VkImageLayout::VK_IMAGE_LAYOUT_GENERAL => {
-                barrier.dstAccessMask = VkAccessFlagBits::VK_ACCESS_HOST_READ_BIT as VkAccessFlags;
+        let mut handles = self.meshes.handles();
+        handles.sort();
+        let mut bytes_started = 0;
+        for handle in handles.into_iter() {
+            if bytes_started >= self.defragment_bytes_per_frame {
+                break;
             }
-            VkImageLayout::VK_IMAGE_LAYOUT_UNDEFINED => {
-                barrier.dstAccessMask = 0;
+            if self.mesh_moves.iter().any(|m| m.handle == handle) {
+                continue;
             }
-            _ => {
-                panic!("Unsupported transition destination layout");
+            let mesh_move = match self.meshes.get(handle) {
+                Some(mesh) => mesh.relocate(self, handle),
+                None => None,
+            };
+            if let Some(mesh_move) = mesh_move {
+                bytes_started += mesh_move.mesh.as_ref().map_or(0, |m| m.bytes());
+                self.mesh_moves.push(mesh_move);
             }
-        };
-
-        unsafe {
-            vkCmdPipelineBarrier(command_buffer.raw,
-                                 src_stage_mask,
-                                 dst_stage_mask,
-                                 0, // Dependency flags
-                                 0, // Memory barrier count
-                                 ptr::null(), // Memory barriers
-                                 0, // Buffer memory barrier count
-                                 ptr::null(), // Buffer memory barriers
-                                 1, // Image barrier count
-                                 &barrier); // Image barriers
         }
     }
 
-    /// Transition an image layout into a new layout and execute the transition immediately
-    ///
+    /// Collect the pass timings of the frame last rendered to this image into the history
     ///
-    fn transition_layout_immediate(image: VkImage,
-                                   device: &RendererVkDevice,
-                                   command_pool: &RendererVkCommandPool,
-                                   aspect_mask: VkImageAspectFlags,
-                                   old_layout: VkImageLayout,
-                                   new_layout: VkImageLayout,
-                                   src_stages: VkPipelineStageFlags,
-                                   dst_stages: VkPipelineStageFlags) {
-        let mut one_time = OneTimeCommandBuffer::new(device, command_pool);
-
-        RendererVkImage::transition_layout(image,
-                                           &one_time.buffer,
-                                           aspect_mask,
-                                           old_layout,
-                                           new_layout,
-                                           src_stages,
-                                           dst_stages);
+    /// The frame's fence must have been waited on, but only the results the queries report as
+    /// available are read, so this never waits.  The frame number of the new frame is noted.
+    fn harvest_pass_timings(&mut self) {
+        let (frame, shader_names) = mem::replace(&mut self.passes_timed[self.image_index], (self.frame_number, vec![]));
+        if shader_names.is_empty() {
+            return;
+        }
 
-        one_time.execute();
+        let timestamps = self.pass_query_pools[self.image_index].get_available_timestamps(2 * shader_names.len() as u32);
+        let timings = FrameTimings::from_timestamps(frame, &shader_names, &timestamps, self.timestamp_period_ns);
+        self.frame_stats.last_gpu_frame_ms = Some(timings.total_ms());
+        self.gpu_timings.record(timings);
     }
 
-    /// Copy the contents of an image to another image, e.g. for staging
-    ///
-    /// TODO: Split this up as for transition_layout and transition_layout_immediate
+    /// Create the variants of the depth tested pipelines in the current polygon mode, where missing
     ///
-    pub fn copy(device: &RendererVkDevice,
-                command_pool: &RendererVkCommandPool,
-                source_image: VkImage,
-                destination_image: VkImage,
-                width: u32,
-                height: u32) {
-        let mut one_time = OneTimeCommandBuffer::new(device, command_pool);
+    /// A shader whose variant fails to build keeps drawing filled.
+    fn create_polygon_mode_pipelines(&mut self) {
+        if self.polygon_mode == PolygonMode::Fill || self.pipeline_cache.is_none() {
+            return;
+        }
 
-        // Now copy the staging image to its final destination
-        //
-        let sub_resource = VkImageSubresourceLayers {
-            aspectMask: VkImageAspectFlagBits::VK_IMAGE_ASPECT_COLOR_BIT as VkImageAspectFlags,
-            baseArrayLayer: 0,
-            mipLevel: 0,
-            layerCount: 1,
-        };
+        let resource_manager = self.resource_manager.clone();
+        let res_manager = resource_manager.lock().unwrap();
 
-        let region = VkImageCopy {
-            srcSubresource: sub_resource,
-            dstSubresource: sub_resource,
-            srcOffset: VkOffset3D { x: 0, y: 0, z: 0 },
-            dstOffset: VkOffset3D { x: 0, y: 0, z: 0 },
-            extent: VkExtent3D {
-                width: width,
-                height: height,
-                depth: 1,
-            },
-        };
+        let shader_names: Vec<&'static str> = self.render_pipelines.keys().cloned().collect();
+        for shader_name in shader_names {
+            let ref shader_spec = res_manager.shader_specs[shader_name];
+            if !shader_spec.render_state.depth_tested() || self.polygon_mode_pipelines.contains_key(shader_name) {
+                continue;
+            }
 
-        unsafe {
-            vkCmdCopyImage(one_time.buffer.raw,
-                           source_image,
-                           VkImageLayout::VK_IMAGE_LAYOUT_TRANSFER_SRC_OPTIMAL,
-                           destination_image,
-                           VkImageLayout::VK_IMAGE_LAYOUT_TRANSFER_DST_OPTIMAL,
-                           1,
-                           &region);
+            match RendererVkPipeline::new(&self.device,
+                                          self.pipeline_cache.as_ref().unwrap(),
+                                          &self.render_passes[shader_spec.pass_name],
+                                          &shader_spec,
+                                          &self.pipeline_modules[shader_name],
+                                          &self.pipeline_layouts[shader_name],
+                                          self.polygon_mode,
+                                          self.flip_viewport_y) {
+                Ok(p) => {
+                    self.polygon_mode_pipelines.insert(shader_name, p);
+                }
+                Err(res) => {
+                    log_error!("Unexpected result from {}: {}", "vkCreateGraphicsPipelines", res);
+                    log_warning!("Drawing {} filled", shader_name);
+                }
+            }
         }
+    }
 
-        one_time.execute();
+    /// Report a stage of shutdown at debug level
+    ///
+    /// stage: The description of the stage
+    fn shutdown_stage(&self, stage: &str) {
+        log_info!("RendererVk shutdown: {}", stage);
     }
 }
 
-impl Drop for RendererVkImage {
+impl Drop for RendererVk {
+    /// Shut down if the application did not, which is reported as the order relative to the
+    /// application's own Vulkan objects is then unknown
     fn drop(&mut self) {
-        unsafe {
-            vkDestroyImage(self.device, self.raw, ptr::null());
-            vkFreeMemory(self.device, self.memory, ptr::null());
+        if !self.shut_down {
+            log_warning!("RendererVk dropped without shutdown() being called, shutting down now");
+            self.shutdown();
         }
     }
 }
 
-pub struct RendererVkImageView {
-    device: VkDevice,
-    raw: VkImageView,
+pub struct RendererVkInstance {
+    raw: VkInstance,
 }
 
-impl RendererVkImageView {
-    /// Return the raw image view for this image
-    pub fn get_view_raw(&self) -> VkImageView {
-        self.raw
-    }
-
-    /// Create an image view
+impl RendererVkInstance {
+    /// Create a Vulkan instance
     ///
     ///
-    pub fn new(device: &RendererVkDevice,
-               image: &RendererVkImage,
-               format: VkFormat,
-               aspect_flags: VkImageAspectFlags)
-               -> RendererVkImageView {
-        let image_view_info = VkImageViewCreateInfo {
-            sType: VkStructureType::VK_STRUCTURE_TYPE_IMAGE_VIEW_CREATE_INFO,
-            image: image.raw,
-            viewType: VkImageViewType::VK_IMAGE_VIEW_TYPE_2D,
-            format: format,
-            subresourceRange: VkImageSubresourceRange {
-                aspectMask: aspect_flags,
-                baseMipLevel: 0,
-                levelCount: 1,
-                baseArrayLayer: 0,
-                layerCount: 1,
-            },
-            components: VkComponentMapping {
-                r: VkComponentSwizzle::VK_COMPONENT_SWIZZLE_IDENTITY,
-                g: VkComponentSwizzle::VK_COMPONENT_SWIZZLE_IDENTITY,
-                b: VkComponentSwizzle::VK_COMPONENT_SWIZZLE_IDENTITY,
-                a: VkComponentSwizzle::VK_COMPONENT_SWIZZLE_IDENTITY,
-            },
-            flags: 0,
+    fn new(application_name: &str,
+           application_version: &str,
+           engine_version: &str,
+           instance_layers: &Vec<String>,
+           instance_extensions: &Vec<String>,
+           validation_features: &mut RendererVkValidationFeatures)
+           -> Result<RendererVkInstance, RendererError> {
+        let app_name = CString::new(application_name.to_owned()).unwrap().into_raw();
+        let app_version = Version::parse(application_version).unwrap();
+        let eng_version = Version::parse(engine_version).unwrap();
+        let application_info = VkApplicationInfo {
+            sType: VkStructureType::VK_STRUCTURE_TYPE_APPLICATION_INFO,
+            apiVersion: VK_MAKE_VERSION(1, 0, 0),
+            applicationVersion: VK_MAKE_VERSION(app_version.major as u32,
+                                                app_version.minor as u32,
+                                                app_version.patch as u32),
+            engineVersion: VK_MAKE_VERSION(eng_version.major as u32,
+                                           eng_version.minor as u32,
+                                           eng_version.patch as u32),
+            pApplicationName: app_name,
+            pEngineName: app_name,
             pNext: ptr::null(),
         };
 
-        let mut image_view: VkImageView = VK_NULL_HANDLE_MUT();
-        unsafe {
-            check_result!("vkCreateImageView",
-                          vkCreateImageView(device.raw,
-                                            &image_view_info,
-                                            ptr::null_mut(),
-                                            &mut image_view));
-        }
+        let il: Vec<*mut c_char> = instance_layers.iter().map(|x| CString::new(x.to_owned()).unwrap().into_raw()).collect();
+        let ie: Vec<*mut c_char> = instance_extensions.iter().map(|x| CString::new(x.to_owned()).unwrap().into_raw()).collect();
 
-        RendererVkImageView {
-            device: device.raw,
-            raw: image_view,
-        }
+        let instance_create_info = VkInstanceCreateInfo {
+            sType: VkStructureType::VK_STRUCTURE_TYPE_INSTANCE_CREATE_INFO,
+            pApplicationInfo: &application_info,
+            enabledLayerCount: instance_layers.len() as u32,
+            ppEnabledLayerNames: il.as_ptr() as *mut _,
+            enabledExtensionCount: instance_extensions.len() as u32,
+            ppEnabledExtensionNames: ie.as_ptr() as *mut _,
+            flags: 0,
+            pNext: validation_features.next_ptr(),
+        };
+
+        let mut instance: VkInstance = VK_NULL_HANDLE_MUT();
+        let res = unsafe { vkCreateInstance(&instance_create_info, ptr::null(), &mut instance) };
+
+        let _: CString = unsafe { CString::from_raw(app_name) };
+        let _: Vec<CString> = il.iter().map(|x| unsafe { CString::from_raw(*x) }).collect();
+        let _: Vec<CString> = ie.iter().map(|x| unsafe { CString::from_raw(*x) }).collect();
+
+        try_result!("vkCreateInstance", res);
+
+        Ok(RendererVkInstance { raw: instance })
     }
 }
 
-impl Drop for RendererVkImageView {
-    fn drop(&mut self) {
-        unsafe {
-            vkDestroyImageView(self.device, self.raw, ptr::null());
-        }
-    }
+// VK_EXT_validation_features postdates the bindings, so the structure is declared here
+const VK_STRUCTURE_TYPE_VALIDATION_FEATURES_EXT: u32 = 1000247000;
+const VK_VALIDATION_FEATURE_ENABLE_GPU_ASSISTED_EXT: u32 = 0;
+const VK_VALIDATION_FEATURE_ENABLE_GPU_ASSISTED_RESERVE_BINDING_SLOT_EXT: u32 = 1;
+const VK_VALIDATION_FEATURE_ENABLE_SYNCHRONIZATION_VALIDATION_EXT: u32 = 4;
+
+#[repr(C)]
+#[allow(non_snake_case)]
+struct VkValidationFeaturesEXT {
+    sType: u32,
+    pNext: *const c_void,
+    enabledValidationFeatureCount: u32,
+    pEnabledValidationFeatures: *const u32,
+    disabledValidationFeatureCount: u32,
+    pDisabledValidationFeatures: *const u32,
 }
 
-pub struct RendererVkRenderPass {
-    device: VkDevice,
-    raw: VkRenderPass,
+/// The validation layer features to enable in addition to the default checks
+pub struct RendererVkValidationFeatures {
+    enabled: Vec<u32>,
+    raw: VkValidationFeaturesEXT,
 }
 
-impl RendererVkRenderPass {
-    /// Create a render pass with some default rendering choices
+impl RendererVkValidationFeatures {
+    /// Choose the validation features requested by the renderer configuration
     ///
+    /// GPU-assisted validation also reserves a descriptor set binding slot for the layer's own use,
+    /// so that pipelines using every available set do not silently lose instrumentation.
     ///
-    fn new(device: &RendererVkDevice, colour_format: VkFormat, depth_format: Option<VkFormat>) -> RendererVkRenderPass {
-        // Define the colour and depth attachment references
-        //
-        let color_attachment_refs = vec![VkAttachmentReference {
-                                             attachment: 0,
-                                             layout: VkImageLayout::VK_IMAGE_LAYOUT_COLOR_ATTACHMENT_OPTIMAL,
-                                         }];
-
-        let depth_attachment_ref = VkAttachmentReference {
-            attachment: 1,
-            layout: VkImageLayout::VK_IMAGE_LAYOUT_DEPTH_STENCIL_ATTACHMENT_OPTIMAL,
-        };
-
-        // Create subpasses and subpass dependencies.  Note that the wait stages on the queue
-        // submission has been set to be VK_PIPELINE_STAGE_TOP_OF_PIPE_BIT in order to avoid
-        // an explicit dependency between VK_SUBPASS_EXTERNAL and subpass 0.
-        //
-        let mut sub_passes: Vec<VkSubpassDescription> = vec![];
-        let dependencies: Vec<VkSubpassDependency> = vec![];
-
-        sub_passes.push(VkSubpassDescription {
-            pipelineBindPoint: VkPipelineBindPoint::VK_PIPELINE_BIND_POINT_GRAPHICS,
-            colorAttachmentCount: color_attachment_refs.len() as u32,
-            pColorAttachments: color_attachment_refs.as_ptr(),
-            inputAttachmentCount: 0,
-            pInputAttachments: ptr::null(),
-            pResolveAttachments: ptr::null(),
-            pDepthStencilAttachment: if depth_format.is_some() {
-                &depth_attachment_ref
-            } else {
-                ptr::null()
-            },
-            preserveAttachmentCount: 0,
-            pPreserveAttachments: ptr::null(),
-            flags: 0,
-        });
-
-        // Now define the renderpass
-        //
-        let mut attachments = vec![VkAttachmentDescription {
-                                       format: colour_format,
-                                       samples: VkSampleCountFlagBits::VK_SAMPLE_COUNT_1_BIT,
-                                       loadOp: VkAttachmentLoadOp::VK_ATTACHMENT_LOAD_OP_DONT_CARE,
-                                       storeOp: VkAttachmentStoreOp::VK_ATTACHMENT_STORE_OP_STORE,
-                                       stencilLoadOp: VkAttachmentLoadOp::VK_ATTACHMENT_LOAD_OP_DONT_CARE,
-                                       stencilStoreOp: VkAttachmentStoreOp::VK_ATTACHMENT_STORE_OP_DONT_CARE,
-                                       initialLayout: VkImageLayout::VK_IMAGE_LAYOUT_UNDEFINED,
-                                       finalLayout: VkImageLayout::VK_IMAGE_LAYOUT_COLOR_ATTACHMENT_OPTIMAL,
-                                       flags: 0,
-                                   }];
-        if depth_format.is_some() {
-            attachments.push(VkAttachmentDescription {
-                format: depth_format.unwrap(),
-                samples: VkSampleCountFlagBits::VK_SAMPLE_COUNT_1_BIT,
-                loadOp: VkAttachmentLoadOp::VK_ATTACHMENT_LOAD_OP_LOAD,
-                storeOp: VkAttachmentStoreOp::VK_ATTACHMENT_STORE_OP_STORE,
-                stencilLoadOp: VkAttachmentLoadOp::VK_ATTACHMENT_LOAD_OP_DONT_CARE,
-                stencilStoreOp: VkAttachmentStoreOp::VK_ATTACHMENT_STORE_OP_DONT_CARE,
-                initialLayout: VkImageLayout::VK_IMAGE_LAYOUT_DEPTH_STENCIL_ATTACHMENT_OPTIMAL,
-                finalLayout: VkImageLayout::VK_IMAGE_LAYOUT_DEPTH_STENCIL_ATTACHMENT_OPTIMAL,
-                flags: 0,
-            });
+    /// config: The renderer configuration
+    fn new(config: &RendererConfig) -> RendererVkValidationFeatures {
+        let mut enabled = vec![];
+        if config.gpu_assisted_validation {
+            enabled.push(VK_VALIDATION_FEATURE_ENABLE_GPU_ASSISTED_EXT);
+            enabled.push(VK_VALIDATION_FEATURE_ENABLE_GPU_ASSISTED_RESERVE_BINDING_SLOT_EXT);
         }
-
-        let render_pass_info = VkRenderPassCreateInfo {
-            sType: VkStructureType::VK_STRUCTURE_TYPE_RENDER_PASS_CREATE_INFO,
-            attachmentCount: attachments.len() as u32,
-            pAttachments: attachments.as_ptr(),
-            subpassCount: sub_passes.len() as u32,
-            pSubpasses: sub_passes.as_ptr(),
-            dependencyCount: dependencies.len() as u32,
-            pDependencies: dependencies.as_ptr(),
-            flags: 0,
-            pNext: ptr::null(),
-        };
-
-        let mut render_pass: VkRenderPass = VK_NULL_HANDLE_MUT();
-        unsafe {
-            check_result!("vkCreateRenderPass",
-                          vkCreateRenderPass(device.raw, &render_pass_info, ptr::null(), &mut render_pass));
+        if config.synchronization_validation {
+            enabled.push(VK_VALIDATION_FEATURE_ENABLE_SYNCHRONIZATION_VALIDATION_EXT);
         }
 
-        RendererVkRenderPass {
-            device: device.raw,
-            raw: render_pass,
+        RendererVkValidationFeatures {
+            enabled: enabled,
+            raw: VkValidationFeaturesEXT {
+                sType: VK_STRUCTURE_TYPE_VALIDATION_FEATURES_EXT,
+                pNext: ptr::null(),
+                enabledValidationFeatureCount: 0,
+                pEnabledValidationFeatures: ptr::null(),
+                disabledValidationFeatureCount: 0,
+                pDisabledValidationFeatures: ptr::null(),
+            },
         }
     }
 
-    /// Begin a render pass
-    ///
-    ///
-    pub fn begin(&self, raw_command_buffer: VkCommandBuffer, raw_framebuffer: VkFramebuffer, width: u32, height: u32) {
-        let render_pass_begin_info = VkRenderPassBeginInfo {
-            sType: VkStructureType::VK_STRUCTURE_TYPE_RENDER_PASS_BEGIN_INFO,
-            renderPass: self.raw,
-            framebuffer: raw_framebuffer,
-            renderArea: VkRect2D {
-                offset: VkOffset2D { x: 0, y: 0 },
-                extent: VkExtent2D {
-                    width: width,
-                    height: height,
-                },
-            },
-            clearValueCount: 0,
-            pClearValues: ptr::null(),
-            pNext: ptr::null(),
-        };
+    /// Return whether any additional validation features are enabled
+    pub fn is_enabled(&self) -> bool {
+        !self.enabled.is_empty()
+    }
 
-        unsafe {
-            vkCmdBeginRenderPass(raw_command_buffer,
-                                 &render_pass_begin_info,
-                                 VkSubpassContents::VK_SUBPASS_CONTENTS_INLINE);
+    /// Return the pointer to chain onto the instance create info, or null if nothing is enabled
+    fn next_ptr(&mut self) -> *const c_void {
+        if !self.is_enabled() {
+            return ptr::null();
         }
+
+        // Point the structure at the feature list only once it has reached its final location
+        self.raw.enabledValidationFeatureCount = self.enabled.len() as u32;
+        self.raw.pEnabledValidationFeatures = self.enabled.as_ptr();
+        &self.raw as *const VkValidationFeaturesEXT as *const c_void
     }
+}
 
-    /// End render pass
-    ///
-    ///
-    pub fn end(&self, raw_command_buffer: VkCommandBuffer) {
-        unsafe {
-            vkCmdEndRenderPass(raw_command_buffer);
-        }
+impl Clone for RendererVkInstance {
+    fn clone(&self) -> RendererVkInstance {
+        unimplemented!();
     }
 }
 
-impl Drop for RendererVkRenderPass {
+impl Drop for RendererVkInstance {
     fn drop(&mut self) {
         unsafe {
-            vkDestroyRenderPass(self.device, self.raw, ptr::null());
+            vkDestroyInstance(self.raw, ptr::null());
         }
     }
 }
 
-pub struct RendererVkFramebuffer {
-    device: VkDevice,
-    raw: VkFramebuffer,
+// VK_EXT_debug_utils postdates the bindings, so the structures are declared here
+const VK_STRUCTURE_TYPE_DEBUG_UTILS_OBJECT_NAME_INFO_EXT: u32 = 1000128000;
+const VK_STRUCTURE_TYPE_DEBUG_UTILS_LABEL_EXT: u32 = 1000128002;
+const VK_STRUCTURE_TYPE_DEBUG_UTILS_MESSENGER_CALLBACK_DATA_EXT: u32 = 1000128003;
+const VK_STRUCTURE_TYPE_DEBUG_UTILS_MESSENGER_CREATE_INFO_EXT: u32 = 1000128004;
+
+/// The VkObjectType of a buffer, for RendererVk::name_object
+pub const VK_OBJECT_TYPE_BUFFER: u32 = 9;
+
+/// The VkObjectType of an image, for RendererVk::name_object
+pub const VK_OBJECT_TYPE_IMAGE: u32 = 10;
+
+/// The VkObjectType of a pipeline, for RendererVk::name_object
+pub const VK_OBJECT_TYPE_PIPELINE: u32 = 19;
+
+#[repr(C)]
+#[allow(non_snake_case)]
+struct VkDebugUtilsObjectNameInfoEXT {
+    sType: u32,
+    pNext: *const c_void,
+    objectType: u32,
+    objectHandle: u64,
+    pObjectName: *const c_char,
 }
 
-impl RendererVkFramebuffer {
-    /// Return the raw framebuffer handle
-    pub fn get_framebuffer_raw(&self) -> VkFramebuffer {
-        self.raw
-    }
+#[repr(C)]
+#[allow(non_snake_case)]
+struct VkDebugUtilsLabelEXT {
+    sType: u32,
+    pNext: *const c_void,
+    pLabelName: *const c_char,
+    color: [f32; 4],
+}
+
+#[repr(C)]
+#[allow(non_snake_case)]
+struct VkDebugUtilsMessengerCallbackDataEXT {
+    sType: u32,
+    pNext: *const c_void,
+    flags: u32,
+    pMessageIdName: *const c_char,
+    messageIdNumber: i32,
+    pMessage: *const c_char,
+    queueLabelCount: u32,
+    pQueueLabels: *const VkDebugUtilsLabelEXT,
+    cmdBufLabelCount: u32,
+    pCmdBufLabels: *const VkDebugUtilsLabelEXT,
+    objectCount: u32,
+    pObjects: *const VkDebugUtilsObjectNameInfoEXT,
+}
+
+#[allow(non_snake_case)]
+type PfnDebugUtilsMessengerCallbackEXT = unsafe extern "C" fn(messageSeverity: u32,
+                                                              messageTypes: u32,
+                                                              pCallbackData: *const VkDebugUtilsMessengerCallbackDataEXT,
+                                                              pUserData: *mut c_void)
+                                                              -> u32;
+
+#[repr(C)]
+#[allow(non_snake_case)]
+struct VkDebugUtilsMessengerCreateInfoEXT {
+    sType: u32,
+    pNext: *const c_void,
+    flags: u32,
+    messageSeverity: u32,
+    messageType: u32,
+    pfnUserCallback: PfnDebugUtilsMessengerCallbackEXT,
+    pUserData: *mut c_void,
+}
 
-    /// Create a framebuffer for a colour image view and a depth image view
+// Non-dispatchable handles are 64 bits on all platforms
+#[allow(non_camel_case_types)]
+type VkDebugUtilsMessengerEXT = u64;
+
+#[allow(non_snake_case)]
+type PfnCreateDebugUtilsMessengerEXT = extern "C" fn(instance: VkInstance,
+                                                     pCreateInfo: *const VkDebugUtilsMessengerCreateInfoEXT,
+                                                     pAllocator: *const VkAllocationCallbacks,
+                                                     pMessenger: *mut VkDebugUtilsMessengerEXT)
+                                                     -> VkResult;
+#[allow(non_snake_case)]
+type PfnDestroyDebugUtilsMessengerEXT = extern "C" fn(instance: VkInstance,
+                                                      messenger: VkDebugUtilsMessengerEXT,
+                                                      pAllocator: *const VkAllocationCallbacks);
+
+/// Passes the messages of the validation layer and loader selected by vk_debug_mask to a callback
+pub struct RendererVkDebugMessenger {
+    instance: VkInstance,
+    raw: VkDebugUtilsMessengerEXT,
+    destroy: PfnDestroyDebugUtilsMessengerEXT,
+    debug_mask: u32,
+    callback: Mutex<DebugMessageCallback>,
+    events: Arc<RendererEvents>, // Whose listeners are passed the messages after the callback
+}
+
+impl RendererVkDebugMessenger {
+    /// Create a messenger on an instance created with VK_EXT_debug_utils, which logs messages
+    /// until another callback is set
     ///
+    /// The messenger is boxed as its address is the user data of the messenger callback.
     ///
-    pub fn new(device: &RendererVkDevice,
-               raw_colour_image_view: VkImageView,
-               raw_depth_image_view: Option<VkImageView>,
-               render_pass: &RendererVkRenderPass,
-               width: u32,
-               height: u32)
-               -> RendererVkFramebuffer {
-        let mut framebuffer: VkFramebuffer = VK_NULL_HANDLE_MUT();
+    /// instance: The instance
+    /// debug_mask: The Vulkan debug mask, made from the DEBUG_MASK bits
+    /// events: The renderer's events, whose listeners are also passed the messages
+    fn new(instance: &RendererVkInstance,
+           debug_mask: u32,
+           events: Arc<RendererEvents>)
+           -> Result<Box<RendererVkDebugMessenger>, RendererError> {
+        let load = |name: &str| {
+            let entrypoint_name = CString::new(name).unwrap();
+            unsafe { vkGetInstanceProcAddr(instance.raw, entrypoint_name.as_ptr()) }
+        };
 
-        let mut attachments: Vec<VkImageView> = vec![raw_colour_image_view];
-        if raw_depth_image_view.is_some() {
-            attachments.push(raw_depth_image_view.unwrap());
-        }
+        type SrcType = unsafe extern "C" fn();
+        let (create, destroy) = match (load("vkCreateDebugUtilsMessengerEXT"), load("vkDestroyDebugUtilsMessengerEXT")) {
+            (Some(create), Some(destroy)) => unsafe {
+                (mem::transmute::<SrcType, PfnCreateDebugUtilsMessengerEXT>(create),
+                 mem::transmute::<SrcType, PfnDestroyDebugUtilsMessengerEXT>(destroy))
+            },
+            _ => {
+                return Err(RendererError::Unsupported("VK_EXT_debug_utils messenger entry points missing".to_string()));
+            }
+        };
 
-        let framebuffer_info = VkFramebufferCreateInfo {
-            sType: VkStructureType::VK_STRUCTURE_TYPE_FRAMEBUFFER_CREATE_INFO,
-            renderPass: render_pass.raw,
-            attachmentCount: attachments.len() as u32,
-            pAttachments: attachments.as_ptr(),
-            width: width,
-            height: height,
-            layers: 1,
-            flags: 0,
+        let mut messenger = Box::new(RendererVkDebugMessenger {
+            instance: instance.raw,
+            raw: 0,
+            destroy: destroy,
+            debug_mask: debug_mask,
+            callback: Mutex::new(Box::new(log_debug_message)),
+            events: events,
+        });
+
+        let (severities, types) = messenger_filter(debug_mask);
+        let create_info = VkDebugUtilsMessengerCreateInfoEXT {
+            sType: VK_STRUCTURE_TYPE_DEBUG_UTILS_MESSENGER_CREATE_INFO_EXT,
             pNext: ptr::null(),
+            flags: 0,
+            messageSeverity: severities,
+            messageType: types,
+            pfnUserCallback: RendererVkDebugMessenger::debug_callback,
+            pUserData: &*messenger as *const RendererVkDebugMessenger as *mut c_void,
         };
 
-        unsafe {
-            check_result!("vkCreateFramebuffer",
-                          vkCreateFramebuffer(device.raw,
-                                              &framebuffer_info,
-                                              ptr::null_mut(),
-                                              &mut framebuffer));
-        }
+        try_result!("vkCreateDebugUtilsMessengerEXT",
+                    create(instance.raw, &create_info, ptr::null(), &mut messenger.raw));
 
-        RendererVkFramebuffer {
-            device: device.raw,
-            raw: framebuffer,
+        Ok(messenger)
+    }
+
+    /// The function the messenger calls, which passes wanted messages to the callback
+    ///
+    /// user_data: The RendererVkDebugMessenger
+    unsafe extern "C" fn debug_callback(severity: u32,
+                                        types: u32,
+                                        callback_data: *const VkDebugUtilsMessengerCallbackDataEXT,
+                                        user_data: *mut c_void)
+                                        -> u32 {
+        let messenger = &*(user_data as *const RendererVkDebugMessenger);
+        let data = &*callback_data;
+        let to_string = |s: *const c_char| if s.is_null() {
+            String::new()
+        } else {
+            CStr::from_ptr(s).to_string_lossy().into_owned()
+        };
+
+        let message = DebugMessage {
+            severity: DebugMessageSeverity::from_vk(severity),
+            validation: (types & VK_DEBUG_UTILS_MESSAGE_TYPE_VALIDATION_BIT_EXT) != 0,
+            performance: (types & VK_DEBUG_UTILS_MESSAGE_TYPE_PERFORMANCE_BIT_EXT) != 0,
+            id_name: to_string(data.pMessageIdName),
+            id_number: data.messageIdNumber,
+            message: to_string(data.pMessage),
+        };
+
+        if debug_message_wanted(messenger.debug_mask, &message) {
+            // A callback that panicked has poisoned the lock, but messages are still worth passing on
+            let callback = match messenger.callback.lock() {
+                Ok(guard) => guard,
+                Err(poisoned) => poisoned.into_inner(),
+            };
+            (*callback)(&message);
+            drop(callback);
+
+            messenger.events.validation_message(&message);
         }
+
+        false as u32 // The call that produced the message is not aborted
     }
 }
 
-impl Drop for RendererVkFramebuffer {
+impl Drop for RendererVkDebugMessenger {
     fn drop(&mut self) {
-        unsafe {
-            vkDestroyFramebuffer(self.device, self.raw, ptr::null());
+        if self.raw != 0 {
+            (self.destroy)(self.instance, self.raw, ptr::null());
         }
     }
 }
 
-pub struct RendererVkShaderModule {
-    device: VkDevice,
-    raw: VkShaderModule,
-}
-
-impl RendererVkShaderModule {
-    /// Create a shader module
-    ///
-    /// device: The Vulkan device
-    /// binary: The shader SPIR-V bytecode
-    pub fn new(raw_device: VkDevice, binary: &Vec<u8>) -> RendererVkShaderModule {
-        let create_info = VkShaderModuleCreateInfo {
-            sType: VkStructureType::VK_STRUCTURE_TYPE_SHADER_MODULE_CREATE_INFO,
-            codeSize: binary.len(), // codeSize is the length in bytes
-            pCode: binary.as_ptr() as *const u32,
-            flags: 0,
-            pNext: ptr::null(),
-        };
-        let mut shader_module: VkShaderModule = VK_NULL_HANDLE_MUT();
-        unsafe {
-            check_result!("vkCreateShaderModule",
-                          vkCreateShaderModule(raw_device, &create_info, ptr::null(), &mut shader_module));
-        }
+#[allow(non_snake_case)]
+type PfnSetDebugUtilsObjectNameEXT = extern "C" fn(device: VkDevice, pNameInfo: *const VkDebugUtilsObjectNameInfoEXT)
+                                                   -> VkResult;
+#[allow(non_snake_case)]
+type PfnCmdBeginDebugUtilsLabelEXT = extern "C" fn(commandBuffer: VkCommandBuffer, pLabelInfo: *const VkDebugUtilsLabelEXT);
+#[allow(non_snake_case)]
+type PfnCmdEndDebugUtilsLabelEXT = extern "C" fn(commandBuffer: VkCommandBuffer);
 
-        RendererVkShaderModule {
-            device: raw_device,
-            raw: shader_module,
+/// Return whether the Vulkan implementation offers an instance extension
+///
+/// name: The name of the extension
+fn instance_supports_extension(name: &str) -> bool {
+    let mut count: u32 = 0;
+    unsafe {
+        if vkEnumerateInstanceExtensionProperties(ptr::null(), &mut count, ptr::null_mut()) != VkResult::VK_SUCCESS {
+            return false;
         }
     }
 
-    pub fn get_raw(&self) -> VkShaderModule {
-        self.raw
+    let mut properties: Vec<VkExtensionProperties> = Vec::with_capacity(count as usize);
+    for _ in 0..count {
+        properties.push(VkExtensionProperties::default());
     }
-}
-
-impl Drop for RendererVkShaderModule {
-    fn drop(&mut self) {
-        unsafe {
-            vkDestroyShaderModule(self.device, self.raw, ptr::null());
+    unsafe {
+        if vkEnumerateInstanceExtensionProperties(ptr::null(), &mut count, properties.as_mut_ptr()) != VkResult::VK_SUCCESS {
+            return false;
         }
     }
+
+    properties.iter()
+        .any(|p| unsafe { CStr::from_ptr(p.extensionName.as_ptr()).to_string_lossy() == name })
 }
 
-pub struct RendererVkVertexBuffer {
-    buffer: RendererVkBuffer,
+/// Names objects and labels regions of command buffers, so that captures in debuggers such as
+/// RenderDoc show which pass and shader each command belongs to
+pub struct RendererVkDebugUtils {
+    set_object_name: PfnSetDebugUtilsObjectNameEXT,
+    cmd_begin_label: PfnCmdBeginDebugUtilsLabelEXT,
+    cmd_end_label: PfnCmdEndDebugUtilsLabelEXT,
 }
 
-impl RendererVkVertexBuffer {
-    /// Create a Vulkan vertex buffer
-    ///
-    /// TODO: Using only host-visible-and-coherent won't be the fastest
+impl RendererVkDebugUtils {
+    /// Return whether the Vulkan implementation offers VK_EXT_debug_utils as an instance extension
+    fn instance_supports() -> bool {
+        instance_supports_extension("VK_EXT_debug_utils")
+    }
+
+    /// Load the entry points of an instance created with VK_EXT_debug_utils
     ///
+    /// Returns None if any entry point is missing.
     ///
-    pub fn new(device: &RendererVkDevice,
-               physical_device: &RendererVkPhysicalDevice,
-               array_type: VertexArrayType)
-               -> RendererVkVertexBuffer {
-        let size = match array_type {
-            VertexArrayType::F3 => 3 * mem::size_of::<f32>() * 3 * TRIANGLE_ARRAY_SIZE,
-            VertexArrayType::F3F3F3 => 9 * mem::size_of::<f32>() * 3 * TRIANGLE_ARRAY_SIZE,
-            VertexArrayType::F3F3 => 6 * mem::size_of::<f32>() * 3 * TRIANGLE_ARRAY_SIZE,
-            VertexArrayType::F2F2 => 4 * mem::size_of::<f32>() * 3 * TRIANGLE_ARRAY_SIZE,
-        };
-
-        let properties = unsafe {
-            mem::transmute(VkMemoryPropertyFlagBits::VK_MEMORY_PROPERTY_HOST_VISIBLE_BIT as VkMemoryPropertyFlags |
-                           VkMemoryPropertyFlagBits::VK_MEMORY_PROPERTY_HOST_COHERENT_BIT as VkMemoryPropertyFlags)
+    /// instance: The instance
+    fn new(instance: &RendererVkInstance) -> Option<RendererVkDebugUtils> {
+        let load = |name: &str| {
+            let entrypoint_name = CString::new(name).unwrap();
+            unsafe { vkGetInstanceProcAddr(instance.raw, entrypoint_name.as_ptr()) }
         };
 
-        RendererVkVertexBuffer {
-            buffer: RendererVkBuffer::new(device,
-                                          physical_device,
-                                          VkBufferUsageFlagBits::VK_BUFFER_USAGE_VERTEX_BUFFER_BIT as VkBufferUsageFlags,
-                                          properties,
-                                          size),
+        type SrcType = unsafe extern "C" fn();
+        match (load("vkSetDebugUtilsObjectNameEXT"),
+               load("vkCmdBeginDebugUtilsLabelEXT"),
+               load("vkCmdEndDebugUtilsLabelEXT")) {
+            (Some(set_object_name), Some(cmd_begin_label), Some(cmd_end_label)) => unsafe {
+                Some(RendererVkDebugUtils {
+                    set_object_name: mem::transmute::<SrcType, PfnSetDebugUtilsObjectNameEXT>(set_object_name),
+                    cmd_begin_label: mem::transmute::<SrcType, PfnCmdBeginDebugUtilsLabelEXT>(cmd_begin_label),
+                    cmd_end_label: mem::transmute::<SrcType, PfnCmdEndDebugUtilsLabelEXT>(cmd_end_label),
+                })
+            },
+            _ => None,
         }
     }
-}
-
-pub struct RendererVkUniformBuffer {
-    buffer: RendererVkBuffer,
-    binding: u32,
-    bytes: Vec<u8>,
-    offsets: HashMap<&'static str, usize>,
-    strides: HashMap<&'static str, usize>,
-}
 
-impl RendererVkUniformBuffer {
-    /// Create a Vulkan uniform buffer
-    ///
-    /// TODO: Using only host-visible-and-coherent won't be the fastest
+    /// Name an object
     ///
+    /// device: The device owning the object
+    /// object_type: The VkObjectType of the object
+    /// handle: The object's handle
+    /// name: The name to give the object
+    fn name_object(&self, device: VkDevice, object_type: u32, handle: u64, name: &str) {
+        let object_name = CString::new(name).unwrap();
+        let name_info = VkDebugUtilsObjectNameInfoEXT {
+            sType: VK_STRUCTURE_TYPE_DEBUG_UTILS_OBJECT_NAME_INFO_EXT,
+            pNext: ptr::null(),
+            objectType: object_type,
+            objectHandle: handle,
+            pObjectName: object_name.as_ptr(),
+        };
+
+        // A name is only a debugging aid, so failing to set one is not worth stopping for
+        let _ = (self.set_object_name)(device, &name_info);
+    }
+
+    /// Begin a labelled region of a command buffer
     ///
-    pub fn new(device: &RendererVkDevice,
-               physical_device: &RendererVkPhysicalDevice,
-               spec: &UniformBlockSpec)
-               -> RendererVkUniformBuffer {
-        let properties = unsafe {
-            mem::transmute(VkMemoryPropertyFlagBits::VK_MEMORY_PROPERTY_HOST_VISIBLE_BIT as VkMemoryPropertyFlags |
-                           VkMemoryPropertyFlagBits::VK_MEMORY_PROPERTY_HOST_COHERENT_BIT as VkMemoryPropertyFlags)
+    /// command_buffer: The command buffer to record into
+    /// name: The label of the region
+    fn begin_label(&self, command_buffer: VkCommandBuffer, name: &str) {
+        let label_name = CString::new(name).unwrap();
+        let label = VkDebugUtilsLabelEXT {
+            sType: VK_STRUCTURE_TYPE_DEBUG_UTILS_LABEL_EXT,
+            pNext: ptr::null(),
+            pLabelName: label_name.as_ptr(),
+            color: [0.0f32; 4], // Left to the debugger
         };
 
-        let buffer = RendererVkBuffer::new(device,
-                                           physical_device,
-                                           VkBufferUsageFlagBits::VK_BUFFER_USAGE_UNIFORM_BUFFER_BIT as VkBufferUsageFlags,
-                                           properties,
-                                           spec.size);
+        (self.cmd_begin_label)(command_buffer, &label);
+    }
 
-        let mut bytes = Vec::with_capacity(spec.size);
-        bytes.resize(spec.size, 0);
-        let mut offsets: HashMap<&'static str, usize> = HashMap::new();
-        for uniform in spec.uniforms.iter() {
-            offsets.insert(uniform.name, uniform.offset);
-        }
-        let mut strides: HashMap<&'static str, usize> = HashMap::new();
-        for uniform in spec.uniforms.iter() {
-            strides.insert(uniform.name, uniform.stride);
-        }
-
-        RendererVkUniformBuffer {
-            buffer: buffer,
-            binding: spec.binding,
-            bytes: bytes,
-            offsets: offsets,
-            strides: strides,
-        }
+    /// End the innermost labelled region of a command buffer
+    ///
+    /// command_buffer: The command buffer to record into
+    fn end_label(&self, command_buffer: VkCommandBuffer) {
+        (self.cmd_end_label)(command_buffer);
     }
 }
 
-pub struct RendererVkBuffer {
-    device: VkDevice,
-    raw: VkBuffer,
-    memory: VkDeviceMemory,
-    size: usize, // Size of requested buffer: actual allocation may be bigger
+/// Convert the Vulkan features of a device into the renderer's feature set
+///
+/// features: The features reported by the device
+fn device_features_from_vk(features: &VkPhysicalDeviceFeatures) -> DeviceFeatures {
+    DeviceFeatures {
+        tessellation_shader: features.tessellationShader != 0,
+        geometry_shader: features.geometryShader != 0,
+        fill_mode_non_solid: features.fillModeNonSolid != 0,
+        wide_lines: features.wideLines != 0,
+        large_points: features.largePoints != 0,
+        sampler_anisotropy: features.samplerAnisotropy != 0,
+        depth_clamp: features.depthClamp != 0,
+        independent_blend: features.independentBlend != 0,
+        multi_draw_indirect: features.multiDrawIndirect != 0,
+        texture_compression_bc: features.textureCompressionBC != 0,
+        pipeline_statistics_query: features.pipelineStatisticsQuery != 0,
+        vertex_pipeline_stores_and_atomics: features.vertexPipelineStoresAndAtomics != 0,
+        fragment_stores_and_atomics: features.fragmentStoresAndAtomics != 0,
+        shader_float64: features.shaderFloat64 != 0,
+    }
 }
 
-impl RendererVkBuffer {
-    /// Create a Vulkan buffer of various types
-    ///
-    ///
-    pub fn new(device: &RendererVkDevice,
-               physical_device: &RendererVkPhysicalDevice,
-               usage_bits: VkBufferUsageFlags,
-               memory_type: VkMemoryPropertyFlags,
-               size: usize)
-               -> RendererVkBuffer {
-        debug_assert!(size != 0);
-        let buffer_info = VkBufferCreateInfo {
-            sType: VkStructureType::VK_STRUCTURE_TYPE_BUFFER_CREATE_INFO,
-            size: size as u64,
-            usage: usage_bits as u32,
-            sharingMode: VkSharingMode::VK_SHARING_MODE_EXCLUSIVE,
-            queueFamilyIndexCount: 0, // No sharing
-            pQueueFamilyIndices: ptr::null(), // No sharing
-            flags: 0,
-            pNext: ptr::null_mut(),
-        };
-
-        let mut buffer: VkBuffer = VK_NULL_HANDLE_MUT();
-        unsafe {
-            check_result!("vkCreateBuffer",
-                          vkCreateBuffer(device.raw, &buffer_info, ptr::null(), &mut buffer));
-        }
-
-        let mut memory_requirements = VkMemoryRequirements::default();
-        unsafe {
-            vkGetBufferMemoryRequirements(device.raw, buffer, &mut memory_requirements);
-        }
+/// Convert the Vulkan limits of a device into the renderer's limits
+///
+/// limits: The limits reported by the device
+fn device_limits_from_vk(limits: &VkPhysicalDeviceLimits) -> DeviceLimits {
+    DeviceLimits {
+        max_image_dimension_2d: limits.maxImageDimension2D,
+        max_image_dimension_3d: limits.maxImageDimension3D,
+        max_image_dimension_cube: limits.maxImageDimensionCube,
+        max_image_array_layers: limits.maxImageArrayLayers,
+        max_framebuffer_width: limits.maxFramebufferWidth,
+        max_framebuffer_height: limits.maxFramebufferHeight,
+        max_colour_attachments: limits.maxColorAttachments,
+        max_uniform_buffer_range: limits.maxUniformBufferRange,
+        min_uniform_buffer_offset_alignment: limits.minUniformBufferOffsetAlignment as u64,
+        max_storage_buffer_range: limits.maxStorageBufferRange,
+        max_push_constants_size: limits.maxPushConstantsSize,
+        max_vertex_input_attributes: limits.maxVertexInputAttributes,
+        max_compute_work_group_count: limits.maxComputeWorkGroupCount,
+        max_compute_work_group_size: limits.maxComputeWorkGroupSize,
+        max_compute_work_group_invocations: limits.maxComputeWorkGroupInvocations,
+        max_sampler_anisotropy: limits.maxSamplerAnisotropy,
+    }
+}
 
-        let memory_type = RendererVk::find_suitable_memory(physical_device,
-                                                           memory_requirements.memoryTypeBits,
-                                                           memory_type);
+/// Convert the renderer's feature set into the Vulkan features to enable, leaving all others off
+///
+/// features: The features to enable
+fn vk_features_from_device(features: &DeviceFeatures) -> VkPhysicalDeviceFeatures {
+    VkPhysicalDeviceFeatures {
+        tessellationShader: features.tessellation_shader as VkBool32,
+        geometryShader: features.geometry_shader as VkBool32,
+        fillModeNonSolid: features.fill_mode_non_solid as VkBool32,
+        wideLines: features.wide_lines as VkBool32,
+        largePoints: features.large_points as VkBool32,
+        samplerAnisotropy: features.sampler_anisotropy as VkBool32,
+        depthClamp: features.depth_clamp as VkBool32,
+        independentBlend: features.independent_blend as VkBool32,
+        multiDrawIndirect: features.multi_draw_indirect as VkBool32,
+        textureCompressionBC: features.texture_compression_bc as VkBool32,
+        pipelineStatisticsQuery: features.pipeline_statistics_query as VkBool32,
+        vertexPipelineStoresAndAtomics: features.vertex_pipeline_stores_and_atomics as VkBool32,
+        fragmentStoresAndAtomics: features.fragment_stores_and_atomics as VkBool32,
+        shaderFloat64: features.shader_float64 as VkBool32,
+        ..VkPhysicalDeviceFeatures::default()
+    }
+}
 
-        if memory_type == u32::max_value() {
-            panic!("Unable to identify suitable memory for buffer");
-        }
+/// Convert a renderer primitive type into the Vulkan primitive topology
+///
+/// primitive_type: The primitive type to convert
+fn vk_primitive_topology(primitive_type: PrimitiveType) -> VkPrimitiveTopology {
+    match primitive_type {
+        PrimitiveType::PrimitiveTriangles => VkPrimitiveTopology::VK_PRIMITIVE_TOPOLOGY_TRIANGLE_LIST,
+        PrimitiveType::PrimitivePatches => VkPrimitiveTopology::VK_PRIMITIVE_TOPOLOGY_PATCH_LIST,
+        PrimitiveType::PrimitiveLines => VkPrimitiveTopology::VK_PRIMITIVE_TOPOLOGY_LINE_LIST,
+        PrimitiveType::PrimitiveLineStrip => VkPrimitiveTopology::VK_PRIMITIVE_TOPOLOGY_LINE_STRIP,
+        PrimitiveType::PrimitivePoints => VkPrimitiveTopology::VK_PRIMITIVE_TOPOLOGY_POINT_LIST,
+    }
+}
 
-        let alloc_info = VkMemoryAllocateInfo {
-            sType: VkStructureType::VK_STRUCTURE_TYPE_MEMORY_ALLOCATE_INFO,
-            allocationSize: memory_requirements.size,
-            memoryTypeIndex: memory_type as u32,
-            pNext: ptr::null(),
-        };
+/// Convert a stencil comparison into the Vulkan compare operation
+///
+/// compare: The comparison to convert
+fn vk_compare_op(compare: CompareOp) -> VkCompareOp {
+    match compare {
+        CompareOp::Never => VkCompareOp::VK_COMPARE_OP_NEVER,
+        CompareOp::Less => VkCompareOp::VK_COMPARE_OP_LESS,
+        CompareOp::Equal => VkCompareOp::VK_COMPARE_OP_EQUAL,
+        CompareOp::LessOrEqual => VkCompareOp::VK_COMPARE_OP_LESS_OR_EQUAL,
+        CompareOp::Greater => VkCompareOp::VK_COMPARE_OP_GREATER,
+        CompareOp::NotEqual => VkCompareOp::VK_COMPARE_OP_NOT_EQUAL,
+        CompareOp::GreaterOrEqual => VkCompareOp::VK_COMPARE_OP_GREATER_OR_EQUAL,
+        CompareOp::Always => VkCompareOp::VK_COMPARE_OP_ALWAYS,
+    }
+}
 
-        let mut buffer_memory: VkDeviceMemory = VK_NULL_HANDLE_MUT();
-        unsafe {
-            check_result!("vkAllocateMemory",
-                          vkAllocateMemory(device.raw, &alloc_info, ptr::null(), &mut buffer_memory));
+/// Convert a stencil operation into the Vulkan stencil operation
+///
+/// op: The operation to convert
+fn vk_stencil_op(op: StencilOp) -> VkStencilOp {
+    match op {
+        StencilOp::Keep => VkStencilOp::VK_STENCIL_OP_KEEP,
+        StencilOp::Zero => VkStencilOp::VK_STENCIL_OP_ZERO,
+        StencilOp::Replace => VkStencilOp::VK_STENCIL_OP_REPLACE,
+        StencilOp::IncrementAndClamp => VkStencilOp::VK_STENCIL_OP_INCREMENT_AND_CLAMP,
+        StencilOp::DecrementAndClamp => VkStencilOp::VK_STENCIL_OP_DECREMENT_AND_CLAMP,
+        StencilOp::Invert => VkStencilOp::VK_STENCIL_OP_INVERT,
+        StencilOp::IncrementAndWrap => VkStencilOp::VK_STENCIL_OP_INCREMENT_AND_WRAP,
+        StencilOp::DecrementAndWrap => VkStencilOp::VK_STENCIL_OP_DECREMENT_AND_WRAP,
+    }
+}
 
-            check_result!("vkBindBufferMemory",
-                          vkBindBufferMemory(device.raw, buffer, buffer_memory, 0));
-        }
+/// Convert a polygon mode into its Vulkan equivalent
+///
+/// mode: The polygon mode to convert
+fn vk_polygon_mode(mode: PolygonMode) -> VkPolygonMode {
+    match mode {
+        PolygonMode::Fill => VkPolygonMode::VK_POLYGON_MODE_FILL,
+        PolygonMode::Line => VkPolygonMode::VK_POLYGON_MODE_LINE,
+        PolygonMode::Point => VkPolygonMode::VK_POLYGON_MODE_POINT,
+    }
+}
 
-        RendererVkBuffer {
-            device: device.raw,
-            raw: buffer,
-            memory: buffer_memory,
-            size: size,
-        }
+/// Convert the stencil state of a shader into the Vulkan state of one face
+///
+/// stencil: The stencil state to convert
+fn vk_stencil_op_state(stencil: &StencilState) -> VkStencilOpState {
+    VkStencilOpState {
+        failOp: vk_stencil_op(stencil.fail_op),
+        passOp: vk_stencil_op(stencil.pass_op),
+        depthFailOp: vk_stencil_op(stencil.depth_fail_op),
+        compareOp: vk_compare_op(stencil.compare),
+        compareMask: stencil.compare_mask,
+        writeMask: stencil.write_mask,
+        reference: stencil.reference,
     }
 }
 
-impl Drop for RendererVkBuffer {
-    fn drop(&mut self) {
-        unsafe {
-            vkDestroyBuffer(self.device, self.raw, ptr::null());
-            vkFreeMemory(self.device, self.memory, ptr::null());
-        }
+/// Convert the culled faces of a shader into the Vulkan cull mode
+///
+/// mode: The cull mode to convert
+fn vk_cull_mode(mode: CullMode) -> VkCullModeFlags {
+    match mode {
+        CullMode::None => VkCullModeFlagBits::VK_CULL_MODE_NONE as VkCullModeFlags,
+        CullMode::Front => VkCullModeFlagBits::VK_CULL_MODE_FRONT_BIT as VkCullModeFlags,
+        CullMode::Back => VkCullModeFlagBits::VK_CULL_MODE_BACK_BIT as VkCullModeFlags,
     }
 }
 
-pub struct RendererVkDescriptorSetLayouts {
-    device: VkDevice,
-    raw: Vec<VkDescriptorSetLayout>,
+/// Convert the winding of front faces into the Vulkan front face
+///
+/// Vulkan framebuffer coordinates run down the screen, reversing the winding seen in OpenGL,
+/// unless the viewport is flipped.
+///
+/// front_face: The winding of front faces in OpenGL window coordinates
+/// flip_viewport_y: Whether the viewport is flipped to match OpenGL
+fn vk_front_face(front_face: FrontFace, flip_viewport_y: bool) -> VkFrontFace {
+    if (front_face == FrontFace::CounterClockwise) == flip_viewport_y {
+        VkFrontFace::VK_FRONT_FACE_COUNTER_CLOCKWISE
+    } else {
+        VkFrontFace::VK_FRONT_FACE_CLOCKWISE
+    }
 }
 
-impl RendererVkDescriptorSetLayouts {
-    /// Create a set of descriptor set layouts for a given shader
-    ///
-    ///
-    pub fn new(device: &RendererVkDevice,
-               resource_manager: &ResourceManager,
-               shader_spec: &ShaderSpec)
-               -> RendererVkDescriptorSetLayouts {
-        let max_set = RendererVkDescriptorSetLayouts::number_of_sets(resource_manager, shader_spec);
+/// Convert a blend factor into the Vulkan blend factor
+///
+/// factor: The factor to convert
+fn vk_blend_factor(factor: BlendFactor) -> VkBlendFactor {
+    match factor {
+        BlendFactor::Zero => VkBlendFactor::VK_BLEND_FACTOR_ZERO,
+        BlendFactor::One => VkBlendFactor::VK_BLEND_FACTOR_ONE,
+        BlendFactor::SrcColour => VkBlendFactor::VK_BLEND_FACTOR_SRC_COLOR,
+        BlendFactor::OneMinusSrcColour => VkBlendFactor::VK_BLEND_FACTOR_ONE_MINUS_SRC_COLOR,
+        BlendFactor::DstColour => VkBlendFactor::VK_BLEND_FACTOR_DST_COLOR,
+        BlendFactor::OneMinusDstColour => VkBlendFactor::VK_BLEND_FACTOR_ONE_MINUS_DST_COLOR,
+        BlendFactor::SrcAlpha => VkBlendFactor::VK_BLEND_FACTOR_SRC_ALPHA,
+        BlendFactor::OneMinusSrcAlpha => VkBlendFactor::VK_BLEND_FACTOR_ONE_MINUS_SRC_ALPHA,
+        BlendFactor::DstAlpha => VkBlendFactor::VK_BLEND_FACTOR_DST_ALPHA,
+        BlendFactor::OneMinusDstAlpha => VkBlendFactor::VK_BLEND_FACTOR_ONE_MINUS_DST_ALPHA,
+    }
+}
 
-        // Then iterate through them all and identify the elements belonging to each set
-        //
-        let mut descriptor_set_layouts: Vec<VkDescriptorSetLayout> = vec![];
-        for for_set in 0..(max_set + 1) {
-            let mut set_layout_bindings: Vec<VkDescriptorSetLayoutBinding> = vec![];
+/// Convert a blend operation into the Vulkan blend operation
+///
+/// op: The operation to convert
+fn vk_blend_op(op: BlendOp) -> VkBlendOp {
+    match op {
+        BlendOp::Add => VkBlendOp::VK_BLEND_OP_ADD,
+        BlendOp::Subtract => VkBlendOp::VK_BLEND_OP_SUBTRACT,
+        BlendOp::ReverseSubtract => VkBlendOp::VK_BLEND_OP_REVERSE_SUBTRACT,
+        BlendOp::Min => VkBlendOp::VK_BLEND_OP_MIN,
+        BlendOp::Max => VkBlendOp::VK_BLEND_OP_MAX,
+    }
+}
 
-            for block_name in shader_spec.uniform_block_names.iter() {
-                let ref block = resource_manager.uniform_block_specs[block_name];
+/// Convert the blending of a shader into the Vulkan state of one colour attachment
+///
+/// state: The render state of the shader
+fn vk_color_blend_attachment_state(state: &RenderState) -> VkPipelineColorBlendAttachmentState {
+    // Unblended attachments take the source as it is
+    let blend = state.blend.unwrap_or(BlendState::new(BlendFactor::One, BlendFactor::Zero));
+
+    VkPipelineColorBlendAttachmentState {
+        colorWriteMask: state.colour_write_mask as VkColorComponentFlags,
+        blendEnable: state.blend.is_some() as VkBool32,
+        srcColorBlendFactor: vk_blend_factor(blend.src_colour),
+        dstColorBlendFactor: vk_blend_factor(blend.dst_colour),
+        colorBlendOp: vk_blend_op(blend.colour_op),
+        srcAlphaBlendFactor: vk_blend_factor(blend.src_alpha),
+        dstAlphaBlendFactor: vk_blend_factor(blend.dst_alpha),
+        alphaBlendOp: vk_blend_op(blend.alpha_op),
+    }
+}
 
-                if block.set == for_set {
-                    // println!("Creating descriptor set layout binding for uniform block {} at {}.{}",
-                    //          block_name,
-                    //          block.set,
-                    //          block.binding);
+pub struct RendererVkPhysicalDevice {
+    raw: VkPhysicalDevice,
+    pub granted_features: DeviceFeatures, // The requested features the device offers, which are enabled
+}
 
-                    set_layout_bindings.push(VkDescriptorSetLayoutBinding {
-                        descriptorType: RendererVkDescriptorSetLayouts::internal_descriptor_type(block.block_type),
-                        // stageFlags set to 'all' is overkill, but does it harm performance?
-                        stageFlags: VkShaderStageFlagBits::VK_SHADER_STAGE_ALL as VkShaderStageFlags,
-                        binding: block.binding,
-                        descriptorCount: 1,
-                        pImmutableSamplers: ptr::null(),
-                    });
+impl RendererVkPhysicalDevice {
+    /// Return the largest sample count the device supports for colour and depth attachments
+    /// that does not exceed the requested count
+    ///
+    /// requested: The number of samples per pixel asked for
+    fn choose_sample_count(&self, requested: u32) -> VkSampleCountFlagBits {
+        let mut properties = VkPhysicalDeviceProperties::default();
+        unsafe {
+            vkGetPhysicalDeviceProperties(self.raw, &mut properties);
+        }
+
+        let supported = properties.limits.framebufferColorSampleCounts as u32 &
+                        properties.limits.framebufferDepthSampleCounts as u32;
+
+        let candidates = [(64, VkSampleCountFlagBits::VK_SAMPLE_COUNT_64_BIT),
+                          (32, VkSampleCountFlagBits::VK_SAMPLE_COUNT_32_BIT),
+                          (16, VkSampleCountFlagBits::VK_SAMPLE_COUNT_16_BIT),
+                          (8, VkSampleCountFlagBits::VK_SAMPLE_COUNT_8_BIT),
+                          (4, VkSampleCountFlagBits::VK_SAMPLE_COUNT_4_BIT),
+                          (2, VkSampleCountFlagBits::VK_SAMPLE_COUNT_2_BIT)];
+        for &(count, bit) in candidates.iter() {
+            if count <= requested && (supported & bit as u32) != 0 {
+                if count != requested {
+                    log_warning!("{} samples per pixel are not supported, using {}", requested, count);
                 }
+                return bit;
             }
+        }
 
-            for uniform in shader_spec.uniform_specs.iter() {
-                if uniform.set == for_set && uniform.binding != u32::max_value() {
-                    // println!("Creating descriptor set layout binding for uniform {} at {}.{}",
-                    //          uniform.name,
-                    //          uniform.set,
-                    //          uniform.binding);
+        if requested > 1 {
+            log_warning!("Multisampling is not supported, using 1 sample per pixel");
+        }
+        VkSampleCountFlagBits::VK_SAMPLE_COUNT_1_BIT
+    }
 
-                    set_layout_bindings.push(VkDescriptorSetLayoutBinding {
-                        descriptorType: RendererVkDescriptorSetLayouts::internal_descriptor_type(uniform.uniform_type),
-                        // stageFlags set to 'all' is overkill, but does it harm performance?
-                        stageFlags: VkShaderStageFlagBits::VK_SHADER_STAGE_ALL as VkShaderStageFlags,
-                        binding: uniform.binding,
-                        descriptorCount: 1,
-                        pImmutableSamplers: ptr::null(), // Optional
-                    });
-                }
+    /// Return the names of the device extensions offered, which are empty if they cannot be listed
+    fn extension_names(&self) -> Vec<String> {
+        let mut count: u32 = 0;
+        unsafe {
+            if vkEnumerateDeviceExtensionProperties(self.raw, ptr::null(), &mut count, ptr::null_mut()) !=
+               VkResult::VK_SUCCESS {
+                return vec![];
             }
+        }
 
-            // If there were items in the set, create a descriptor set layout object
-            //
-            if set_layout_bindings.len() > 0 {
-                let set_layout_create_info = VkDescriptorSetLayoutCreateInfo {
-                    sType: VkStructureType::VK_STRUCTURE_TYPE_DESCRIPTOR_SET_LAYOUT_CREATE_INFO,
-                    bindingCount: set_layout_bindings.len() as u32,
-                    pBindings: set_layout_bindings.as_ptr(),
-                    flags: 0,
-                    pNext: ptr::null(),
-                };
-
-                let mut descriptor_set_layout: VkDescriptorSetLayout = VK_NULL_HANDLE_MUT();
-                unsafe {
-                    check_result!("vkCreateDescriptorSetLayout",
-                                  vkCreateDescriptorSetLayout(device.raw,
-                                                              &set_layout_create_info,
-                                                              ptr::null(), // Allocator
-                                                              &mut descriptor_set_layout));
-                }
-
-                descriptor_set_layouts.push(descriptor_set_layout);
+        let mut properties: Vec<VkExtensionProperties> = Vec::with_capacity(count as usize);
+        for _ in 0..count {
+            properties.push(VkExtensionProperties::default());
+        }
+        unsafe {
+            if vkEnumerateDeviceExtensionProperties(self.raw, ptr::null(), &mut count, properties.as_mut_ptr()) !=
+               VkResult::VK_SUCCESS {
+                return vec![];
             }
         }
 
-        RendererVkDescriptorSetLayouts {
-            device: device.raw,
-            raw: descriptor_set_layouts,
-        }
+        properties.iter()
+            .map(|p| unsafe { CStr::from_ptr(p.extensionName.as_ptr()).to_string_lossy().into_owned() })
+            .collect()
     }
 
-    /// Convert a resources general descriptor type to a Vulkan descriptor type
-    ///
-    ///
-    pub fn internal_descriptor_type(descriptor_type: UniformType) -> VkDescriptorType {
-        match descriptor_type {
-            UniformType::Sampler => VkDescriptorType::VK_DESCRIPTOR_TYPE_SAMPLER,
-            UniformType::CombinedImageSampler => VkDescriptorType::VK_DESCRIPTOR_TYPE_COMBINED_IMAGE_SAMPLER,
-            UniformType::SampledImage => VkDescriptorType::VK_DESCRIPTOR_TYPE_SAMPLED_IMAGE,
-            UniformType::StorageImage => VkDescriptorType::VK_DESCRIPTOR_TYPE_STORAGE_IMAGE,
-            UniformType::UniformTexelBuffer => VkDescriptorType::VK_DESCRIPTOR_TYPE_UNIFORM_TEXEL_BUFFER,
-            UniformType::StorageTexelBuffer => VkDescriptorType::VK_DESCRIPTOR_TYPE_STORAGE_TEXEL_BUFFER,
-            UniformType::UniformBuffer => VkDescriptorType::VK_DESCRIPTOR_TYPE_UNIFORM_BUFFER,
-            UniformType::StorageBuffer => VkDescriptorType::VK_DESCRIPTOR_TYPE_STORAGE_BUFFER,
-            UniformType::UniformBufferDynamic => VkDescriptorType::VK_DESCRIPTOR_TYPE_UNIFORM_BUFFER_DYNAMIC,
-            UniformType::StorageBufferDynamic => VkDescriptorType::VK_DESCRIPTOR_TYPE_STORAGE_BUFFER_DYNAMIC,
-            UniformType::InputAttachment => VkDescriptorType::VK_DESCRIPTOR_TYPE_INPUT_ATTACHMENT,
-            UniformType::RangeSize => VkDescriptorType::VK_DESCRIPTOR_TYPE_RANGE_SIZE,
-        }
+    /// Return whether the device has memory that is both device-local and host-visible
+    fn has_host_visible_device_local_memory(&self) -> bool {
+        let properties = VkMemoryPropertyFlagBits::VK_MEMORY_PROPERTY_DEVICE_LOCAL_BIT as VkMemoryPropertyFlags |
+                         VkMemoryPropertyFlagBits::VK_MEMORY_PROPERTY_HOST_VISIBLE_BIT as VkMemoryPropertyFlags |
+                         VkMemoryPropertyFlagBits::VK_MEMORY_PROPERTY_HOST_COHERENT_BIT as VkMemoryPropertyFlags;
+        RendererVk::find_suitable_memory(self, u32::max_value(), properties) != u32::max_value()
     }
 
-    /// Find out the number of sets
+    /// Resolve the storage requested for vertex buffers into the storage to use on this device
     ///
-    ///
-    pub fn number_of_sets(resource_manager: &ResourceManager, shader_spec: &ShaderSpec) -> u32 {
-        let mut max_set: u32 = 0;
-
-        for block_name in shader_spec.uniform_block_names.iter() {
-            let ref block = resource_manager.uniform_block_specs[block_name];
-            if block.set > max_set {
-                max_set = block.set;
-            }
+    /// requested: The storage asked for, resolved if Automatic
+    fn choose_vertex_buffer_storage(&self, requested: VertexBufferStorage) -> VertexBufferStorage {
+        if requested != VertexBufferStorage::Automatic {
+            return requested;
         }
 
-        for uniform in shader_spec.uniform_specs.iter() {
-            if uniform.set > max_set {
-                max_set = uniform.set;
-            }
+        let mut properties = VkPhysicalDeviceProperties::default();
+        unsafe {
+            vkGetPhysicalDeviceProperties(self.raw, &mut properties);
         }
 
-        max_set
-    }
-}
-
-impl Drop for RendererVkDescriptorSetLayouts {
-    fn drop(&mut self) {
-        for dsl in self.raw.iter() {
-            unsafe {
-                vkDestroyDescriptorSetLayout(self.device, *dsl, ptr::null());
-            }
+        let discrete = properties.deviceType as u32 == VkPhysicalDeviceType::VK_PHYSICAL_DEVICE_TYPE_DISCRETE_GPU as u32;
+        if discrete && !self.has_host_visible_device_local_memory() {
+            VertexBufferStorage::DeviceLocal
+        } else {
+            VertexBufferStorage::HostVisible
         }
     }
-}
-
-pub struct RendererVkDescriptorPool {
-    device: VkDevice,
-    raw: VkDescriptorPool,
-}
 
-impl RendererVkDescriptorPool {
-    /// Create a Vulkan descriptor pool
+    /// Enumerate physical devices and choose one
     ///
+    /// Devices without the required features are passed over.  Of the rest, discrete GPUs are
+    /// preferred, and then those offering the most optional features.
     ///
-    pub fn new(device: &RendererVkDevice,
-               max_uniform_buffers: usize,
-               max_combined_image_samplers: usize,
-               max_sets: usize)
-               -> RendererVkDescriptorPool {
-        let uniform_buffer_pool_size = VkDescriptorPoolSize {
-            type_: VkDescriptorType::VK_DESCRIPTOR_TYPE_UNIFORM_BUFFER,
-            descriptorCount: max_uniform_buffers as u32,
-        };
-
-        let combined_image_samplers_pool_size = VkDescriptorPoolSize {
-            type_: VkDescriptorType::VK_DESCRIPTOR_TYPE_COMBINED_IMAGE_SAMPLER,
-            descriptorCount: max_combined_image_samplers as u32,
-        };
-
-        let buffer_pool_sizes = vec![uniform_buffer_pool_size, combined_image_samplers_pool_size];
-
-        let pool_info = VkDescriptorPoolCreateInfo {
-            sType: VkStructureType::VK_STRUCTURE_TYPE_DESCRIPTOR_POOL_CREATE_INFO,
-            poolSizeCount: buffer_pool_sizes.len() as u32,
-            pPoolSizes: buffer_pool_sizes.as_ptr(),
-            maxSets: max_sets as u32,
-            flags: 0,
-            pNext: ptr::null(),
-        };
-
-        let mut descriptor_pool: VkDescriptorPool = VK_NULL_HANDLE_MUT();
+    /// instance: The Vulkan instance
+    /// feature_request: The required and optional features
+    fn new(instance: &RendererVkInstance, feature_request: &FeatureRequest) -> Result<RendererVkPhysicalDevice, RendererError> {
+        let mut physical_device_count: u32 = 0;
         unsafe {
-            check_result!("vkCreateDescriptorPool",
-                          vkCreateDescriptorPool(device.raw, &pool_info, ptr::null(), &mut descriptor_pool));
+            try_result!("vkEnumeratePhysicalDevices",
+                          vkEnumeratePhysicalDevices(instance.raw, &mut physical_device_count, ptr::null_mut()));
         }
 
-        RendererVkDescriptorPool {
-            device: device.raw,
-            raw: descriptor_pool,
+        // Enumerate the physical devices supported by Vulkan
+        //
+        let mut physical_devices: Vec<VkPhysicalDevice> = vec![];
+        physical_devices.resize(physical_device_count as usize, VK_NULL_HANDLE_MUT());
+        unsafe {
+            // Now enumerate the physical devices
+            try_result!("vkEnumeratePhysicalDevices",
+                          vkEnumeratePhysicalDevices(instance.raw,
+                                                     &mut physical_device_count,
+                                                     physical_devices.as_mut_ptr()));
         }
-    }
-}
 
-impl Drop for RendererVkDescriptorPool {
-    fn drop(&mut self) {
-        unsafe {
-            vkDestroyDescriptorPool(self.device, self.raw, ptr::null());
+        let mut best: Option<(i32, u32, DeviceFeatures)> = None; // Score, device number and granted features
+        let mut missing_features = vec![];
+        let mut device_properties = VkPhysicalDeviceProperties::default();
+        let mut device_features = VkPhysicalDeviceFeatures::default();
+        for i in 0..physical_device_count {
+            unsafe {
+                vkGetPhysicalDeviceProperties(physical_devices[i as usize], &mut device_properties);
+                vkGetPhysicalDeviceFeatures(physical_devices[i as usize], &mut device_features);
+            }
+
+            let available = device_features_from_vk(&device_features);
+            let granted = match feature_request.resolve(&available) {
+                Ok(granted) => granted,
+                Err(missing) => {
+                    log_info!("Device {} is passed over for not supporting {:?}", i, missing);
+                    missing_features = missing;
+                    continue;
+                }
+            };
+
+            let mut score = 0;
+            if device_properties.deviceType as u32 == VkPhysicalDeviceType::VK_PHYSICAL_DEVICE_TYPE_DISCRETE_GPU as u32 {
+                log_info!("Device {} scores 10 for being a discrete GPU", i);
+                score += 10
+            };
+            let optional_score = feature_request.optional_score(&available);
+            log_info!("Device {} scores {} for the optional features it supports", i, optional_score);
+            score += optional_score;
+
+            if log_enabled(LogLevel::Info) {
+                let slice = unsafe { CStr::from_ptr(&device_properties.deviceName as *const c_char) };
+                log_info!("Physical device number {} is {} with score {}",
+                          i,
+                          CStr::to_string_lossy(slice),
+                          score);
+            }
+
+            let better = match best {
+                Some((best_score, _, _)) => score > best_score,
+                None => true,
+            };
+            if better {
+                best = Some((score, i, granted));
+            }
         }
+
+        let (chosen_device, granted_features) = match best {
+            Some((_, i, granted)) => (i, granted),
+            None => {
+                return Err(RendererError::NoSuitableDevice(format!("Device does not support required features {:?}",
+                                                                   missing_features)))
+            }
+        };
+        log_info!("Chose physical device: {}", chosen_device);
+
+        Ok(RendererVkPhysicalDevice {
+            raw: physical_devices[chosen_device as usize],
+            granted_features: granted_features,
+        })
     }
 }
 
-// Note: There is no Drop implementation for RendererVkDescriptorSet as
-// VkDescriptorSet objects are destroyed when the VkDescriptorPool they belong
-// to is destroyed
-pub struct RendererVkDescriptorSet {
-    raw: VkDescriptorSet,
+pub struct RendererVkQueueFamilies {
+    #[allow(dead_code)]
+    raw: Vec<VkQueueFamilyProperties>,
+    index: u32,
+    transfer_index: Option<u32>,
+    present_index: u32, // The graphics family when it can present, or when headless
 }
 
-impl RendererVkDescriptorSet {
-    /// Create a Vulkan descriptor set
-    ///
+impl RendererVkQueueFamilies {
+    /// Enumerate queue families and select one that supports graphics
     ///
-    pub fn new(device: &RendererVkDevice,
-               descriptor_pool: &RendererVkDescriptorPool,
-               descriptor_set_layouts: &RendererVkDescriptorSetLayouts,
-               resource: &ShaderSpec,
-               uniform_buffers: &HashMap<&'static str, RendererVkUniformBuffer>,
-               textures: &HashMap<&'static str, &TextureVk>)
-               -> RendererVkDescriptorSet {
-        let alloc_info = VkDescriptorSetAllocateInfo {
-            sType: VkStructureType::VK_STRUCTURE_TYPE_DESCRIPTOR_SET_ALLOCATE_INFO,
-            descriptorPool: descriptor_pool.raw,
-            descriptorSetCount: descriptor_set_layouts.raw.len() as u32,
-            pSetLayouts: descriptor_set_layouts.raw.as_ptr(),
-            pNext: ptr::null(),
-        };
-
-        let mut descriptor_set: VkDescriptorSet = VK_NULL_HANDLE_MUT();
+    /// A separate transfer-capable family is selected too if there is one, preferring a family
+    /// that does nothing but transfers, as that typically maps onto a dedicated copy engine.
+    fn new(physical_device: &RendererVkPhysicalDevice) -> Result<RendererVkQueueFamilies, RendererError> {
+        let mut queue_family_count: u32 = 0;
         unsafe {
-            check_result!("vkAllocateDescriptorSets",
-                          vkAllocateDescriptorSets(device.raw, &alloc_info, &mut descriptor_set));
+            vkGetPhysicalDeviceQueueFamilyProperties(physical_device.raw,
+                                                     &mut queue_family_count,
+                                                     ptr::null_mut());
         }
 
-        // Iterate through all the uniform blocks and generate descriptor set writes for each
-        //
-        let n = resource.uniform_block_names.len();
-        let mut buffer_infos = Vec::with_capacity(n);
-        let mut descriptor_writes = Vec::with_capacity(n);
-        let mut i = 0;
-        for uniform_block_name in resource.uniform_block_names.iter() {
-            let ref uniform_buffer = uniform_buffers[uniform_block_name];
-
-            buffer_infos.push(VkDescriptorBufferInfo {
-                buffer: uniform_buffer.buffer.raw,
-                offset: 0,
-                range: uniform_buffer.buffer.size as u64,
-            });
-
-            descriptor_writes.push(VkWriteDescriptorSet {
-                sType: VkStructureType::VK_STRUCTURE_TYPE_WRITE_DESCRIPTOR_SET,
-                dstSet: descriptor_set,
-                dstBinding: uniform_buffer.binding,
-                dstArrayElement: 0,
-                descriptorType: VkDescriptorType::VK_DESCRIPTOR_TYPE_UNIFORM_BUFFER,
-                descriptorCount: 1,
-                pBufferInfo: &buffer_infos[i],
-                pImageInfo: ptr::null(), // Optional
-                pTexelBufferView: ptr::null(), // Optional
-                pNext: ptr::null(),
-            });
-
-            i += 1;
+        let mut queue_properties_array: Vec<VkQueueFamilyProperties> = vec![];
+        queue_properties_array.resize(queue_family_count as usize,
+                                      VkQueueFamilyProperties::default());
+        unsafe {
+            vkGetPhysicalDeviceQueueFamilyProperties(physical_device.raw,
+                                                     &mut queue_family_count,
+                                                     queue_properties_array.as_mut_ptr());
         }
 
-        // Iterate through all the combined image and sampler uniforms and generate descriptor
-        // set writes for each
-        //
-        i = 0;
-        let mut image_infos = vec![];
-        for uniform_spec in resource.uniform_specs.iter() {
-            if uniform_spec.uniform_type == UniformType::CombinedImageSampler && textures.contains_key(uniform_spec.name) {
-                image_infos.push(VkDescriptorImageInfo {
-                    imageLayout: VkImageLayout::VK_IMAGE_LAYOUT_SHADER_READ_ONLY_OPTIMAL,
-                    imageView: textures[uniform_spec.name].texture.view.raw,
-                    sampler: textures[uniform_spec.name].texture.sampler,
-                });
-
-                descriptor_writes.push(VkWriteDescriptorSet {
-                    sType: VkStructureType::VK_STRUCTURE_TYPE_WRITE_DESCRIPTOR_SET,
-                    dstSet: descriptor_set,
-                    dstBinding: uniform_spec.binding,
-                    dstArrayElement: 0,
-                    descriptorType: VkDescriptorType::VK_DESCRIPTOR_TYPE_COMBINED_IMAGE_SAMPLER,
-                    descriptorCount: 1,
-                    pBufferInfo: ptr::null(), // Optional
-                    pImageInfo: &image_infos[i],
-                    pTexelBufferView: ptr::null(), // Optional
-                    pNext: ptr::null(),
-                });
-
-                i += 1;
+        let mut graphics_queue_index: u32 = u32::max_value();
+        for i in 0..queue_family_count {
+            if queue_properties_array[i as usize].queueFlags & (VkQueueFlagBits::VK_QUEUE_GRAPHICS_BIT as VkQueueFlags) != 0 {
+                graphics_queue_index = i;
             }
         }
+        if graphics_queue_index == u32::max_value() {
+            return Err(RendererError::NoSuitableDevice("Suitable queue family not found".to_string()));
+        }
 
-        unsafe {
-            vkUpdateDescriptorSets(device.raw,
-                                   descriptor_writes.len() as u32,
-                                   descriptor_writes.as_ptr(),
-                                   0, // Copy count
-                                   ptr::null() /* Descriptor copies */);
+        let graphics_bit = VkQueueFlagBits::VK_QUEUE_GRAPHICS_BIT as VkQueueFlags;
+        let compute_bit = VkQueueFlagBits::VK_QUEUE_COMPUTE_BIT as VkQueueFlags;
+        let transfer_bit = VkQueueFlagBits::VK_QUEUE_TRANSFER_BIT as VkQueueFlags;
+        let mut transfer_queue_index: Option<u32> = None;
+        for i in 0..queue_family_count {
+            let flags = queue_properties_array[i as usize].queueFlags;
+            if i == graphics_queue_index || flags & transfer_bit == 0 || flags & graphics_bit != 0 {
+                continue;
+            }
+            if transfer_queue_index.is_none() || flags & compute_bit == 0 {
+                transfer_queue_index = Some(i);
+            }
         }
 
-        RendererVkDescriptorSet { raw: descriptor_set }
+        Ok(RendererVkQueueFamilies {
+            raw: queue_properties_array,
+            index: graphics_queue_index,
+            transfer_index: transfer_queue_index,
+            present_index: graphics_queue_index,
+        })
     }
-}
-
-pub struct RendererVkPipelineLayout {
-    device: VkDevice,
-    raw: VkPipelineLayout,
-}
 
-impl RendererVkPipelineLayout {
-    /// Set up pipeline layout with the specified descriptor set layouts
+    /// Select the family whose queue presents to a surface
     ///
+    /// The graphics family is preferred, so that a single queue does everything.  Otherwise the
+    /// first family able to present is taken, avoiding the transfer family if possible, as its
+    /// queue is submitted to from the upload thread.  Should only the transfer family be able to
+    /// present, transfers are submitted to the graphics queue instead.
     ///
-    pub fn new(device: &RendererVkDevice, descriptor_set_layouts: &RendererVkDescriptorSetLayouts) -> RendererVkPipelineLayout {
-        let pipeline_layout_info = VkPipelineLayoutCreateInfo {
-            sType: VkStructureType::VK_STRUCTURE_TYPE_PIPELINE_LAYOUT_CREATE_INFO,
-            setLayoutCount: descriptor_set_layouts.raw.len() as u32,
-            pSetLayouts: descriptor_set_layouts.raw.as_ptr(),
-            pushConstantRangeCount: 0, // Optional
-            pPushConstantRanges: ptr::null(), // Optional
-            flags: 0,
-            pNext: ptr::null(),
+    /// window: The window the surface was created for
+    /// instance: The Vulkan instance
+    /// physical_device: The physical device
+    /// surface: The surface
+    fn select_present_family(&mut self,
+                             window: &Window,
+                             instance: &RendererVkInstance,
+                             physical_device: &RendererVkPhysicalDevice,
+                             surface: VkSurfaceKHR)
+                             -> Result<(), RendererError> {
+        let supports_present = |index: u32| -> Result<bool, RendererError> {
+            if !window.get_physical_device_presentation_support(instance.raw as usize, physical_device.raw as usize, index) {
+                return Ok(false);
+            }
+
+            let mut supported: VkBool32 = false as VkBool32;
+            unsafe {
+                try_result!("vkGetPhysicalDeviceSurfaceSupportKHR",
+                            vkGetPhysicalDeviceSurfaceSupportKHR(physical_device.raw, index, surface, &mut supported));
+            }
+            Ok(supported == true as VkBool32)
         };
 
-        let mut pipeline_layout: VkPipelineLayout = VK_NULL_HANDLE_MUT();
-        unsafe {
-            check_result!("vkCreatePipelineLayout",
-                          vkCreatePipelineLayout(device.raw,
-                                                 &pipeline_layout_info,
-                                                 ptr::null(),
-                                                 &mut pipeline_layout));
+        if supports_present(self.index)? {
+            self.present_index = self.index;
+            return Ok(());
         }
 
-        RendererVkPipelineLayout {
-            device: device.raw,
-            raw: pipeline_layout,
+        let mut present_index = None;
+        for i in 0..self.raw.len() as u32 {
+            if i != self.index && supports_present(i)? {
+                if present_index.is_none() || present_index == self.transfer_index {
+                    present_index = Some(i);
+                }
+            }
         }
-    }
-}
 
-impl Drop for RendererVkPipelineLayout {
-    fn drop(&mut self) {
-        unsafe {
-            vkDestroyPipelineLayout(self.device, self.raw, ptr::null());
+        match present_index {
+            Some(index) => {
+                if self.transfer_index == Some(index) {
+                    self.transfer_index = None;
+                }
+                log_info!("Presenting from queue family {}, separate from graphics queue family {}", index, self.index);
+                self.present_index = index;
+                Ok(())
+            }
+            None => Err(RendererError::NoSuitableDevice("No queue family supports image presentation".to_string())),
         }
     }
 }
 
-pub struct RendererVkPipeline {
-    device: VkDevice,
-    raw: VkPipeline,
+// VK_EXT_swapchain_colorspace postdates the bindings, so its colour space is declared here
+const VK_COLOR_SPACE_HDR10_ST2084_EXT: u32 = 1000104008;
+
+pub struct RendererVkSurface {
+    instance: VkInstance,
+    raw: VkSurfaceKHR,
+    format: VkSurfaceFormatKHR,
+    capabilities: VkSurfaceCapabilitiesKHR,
+    presentation: VkPresentModeKHR,
 }
 
-impl RendererVkPipeline {
-    /// Create a graphics pipeline with some default rendering choices
+impl RendererVkSurface {
+    /// Create Vulkan window surface
     ///
+    /// The queue family that presents to the surface is selected as part of this.
     ///
-    fn new(device: &RendererVkDevice,
-           render_pass: &RendererVkRenderPass,
-           shader_spec: &ShaderSpec,
-           shader: &ShaderSpirv,
-           pipeline_layout: &RendererVkPipelineLayout,
-           width: u32,
-           height: u32)
-           -> RendererVkPipeline {
-        let viewport = VkViewport {
-            x: 0.0f32,
-            y: 0.0f32,
-            width: width as f32,
-            height: height as f32,
-            minDepth: 0.0f32,
-            maxDepth: 1.0f32,
+    /// window: The window to present to
+    /// instance: The Vulkan instance
+    /// physical_device: The physical device
+    /// queue_families: The queue families, whose present family is selected
+    /// srgb: Whether to prefer a format that encodes with the sRGB transfer function
+    /// hdr10: Whether to prefer an HDR10 format, over the sRGB preference
+    fn new(window: &Window,
+           instance: &RendererVkInstance,
+           physical_device: &RendererVkPhysicalDevice,
+           queue_families: &mut RendererVkQueueFamilies,
+           srgb: bool,
+           hdr10: bool)
+           -> Result<RendererVkSurface, RendererError> {
+        let mut surface: VkSurfaceKHR = VK_NULL_HANDLE_MUT();
+        unsafe {
+            let res = glfw::ffi::glfwCreateWindowSurface(mem::transmute(instance.raw),
+                                                         window.window_ptr(),
+                                                         ptr::null(),
+                                                         mem::transmute(&mut surface));
+            if res != VkResult::VK_SUCCESS as u32 {
+                return Err(RendererError::ApiCallFailed("glfwCreateWindowSurface", format!("{}", res)));
+            }
+        }
+
+        // Wrap the surface straight away, so that it is destroyed if any of the queries fail
+        let mut renderer_surface = RendererVkSurface {
+            instance: instance.raw,
+            raw: surface,
+            format: VkSurfaceFormatKHR {
+                format: VkFormat::VK_FORMAT_UNDEFINED,
+                colorSpace: VkColorSpaceKHR::VK_COLOR_SPACE_SRGB_NONLINEAR_KHR,
+            },
+            capabilities: VkSurfaceCapabilitiesKHR::default(),
+            presentation: VkPresentModeKHR::VK_PRESENT_MODE_FIFO_KHR,
         };
+        queue_families.select_present_family(window, instance, physical_device, surface)?;
+        renderer_surface.format = RendererVkSurface::choose_surface_format(physical_device, surface, srgb, hdr10)?;
+        renderer_surface.capabilities = RendererVkSurface::determine_surface_capabilities(physical_device,
+                                                                                          queue_families.present_index,
+                                                                                          surface)?;
+        renderer_surface.presentation = RendererVkSurface::choose_presentation_mode(physical_device, surface)?;
+
+        Ok(renderer_surface)
+    }
 
-        let scissor = VkRect2D {
-            offset: VkOffset2D { x: 0, y: 0 },
+    /// Query the surface capabilities again, e.g. after the window has been resized
+    ///
+    /// physical_device: The physical device
+    /// queue_family_index: The index of the present queue family
+    fn refresh_capabilities(&mut self,
+                            physical_device: &RendererVkPhysicalDevice,
+                            queue_family_index: u32)
+                            -> Result<(), RendererError> {
+        self.capabilities = RendererVkSurface::determine_surface_capabilities(physical_device, queue_family_index, self.raw)?;
+        Ok(())
+    }
+
+    /// Choose Vulkan window surface format
+    ///
+    /// Eight-bit RGBA and BGRA formats in the sRGB colour space are preferred, with the sRGB
+    /// transfer function or without it as asked, before whatever the driver lists first.
+    ///
+    /// physical_device: The physical device
+    /// raw_surface: The surface
+    /// srgb: Whether to prefer a format that encodes with the sRGB transfer function
+    /// hdr10: Whether to prefer an HDR10 format, which needs VK_EXT_swapchain_colorspace
+    fn choose_surface_format(physical_device: &RendererVkPhysicalDevice,
+                             raw_surface: VkSurfaceKHR,
+                             srgb: bool,
+                             hdr10: bool)
+                             -> Result<VkSurfaceFormatKHR, RendererError> {
+        let mut format_count: u32 = 0;
+        unsafe {
+            try_result!("vkGetPhysicalDeviceSurfaceFormatsKHR",
+                          vkGetPhysicalDeviceSurfaceFormatsKHR(physical_device.raw,
+                                                               raw_surface,
+                                                               &mut format_count,
+                                                               ptr::null_mut()));
+        }
+        if format_count == 0 {
+            return Err(RendererError::NoSuitableDevice("Surface formats missing".to_string()));
+        }
+
+        // Enumerate the surface formats available and pick one
+        //
+        let preferred = if srgb {
+            [VkFormat::VK_FORMAT_B8G8R8A8_SRGB, VkFormat::VK_FORMAT_R8G8B8A8_SRGB]
+        } else {
+            [VkFormat::VK_FORMAT_B8G8R8A8_UNORM, VkFormat::VK_FORMAT_R8G8B8A8_UNORM]
+        };
+
+        let mut formats: Vec<VkSurfaceFormatKHR> = vec![];
+        let default_format = VkSurfaceFormatKHR {
+            format: preferred[1],
+            colorSpace: VkColorSpaceKHR::VK_COLOR_SPACE_SRGB_NONLINEAR_KHR,
+        };
+        formats.resize(format_count as usize, default_format);
+        unsafe {
+            try_result!("vkGetPhysicalDeviceSurfaceFormatsKHR",
+                          vkGetPhysicalDeviceSurfaceFormatsKHR(physical_device.raw,
+                                                               raw_surface,
+                                                               &mut format_count,
+                                                               formats.as_mut_ptr()));
+        }
+
+        if format_count == 1 && matches!(formats[0].format, VkFormat::VK_FORMAT_UNDEFINED) {
+            // The driver has indicated that no format is preferred
+            return Ok(default_format);
+        }
+
+        if hdr10 {
+            let found = formats.iter().find(|f| {
+                (f.format as u32 == VkFormat::VK_FORMAT_A2B10G10R10_UNORM_PACK32 as u32 ||
+                 f.format as u32 == VkFormat::VK_FORMAT_A2R10G10B10_UNORM_PACK32 as u32) &&
+                f.colorSpace as u32 == VK_COLOR_SPACE_HDR10_ST2084_EXT
+            });
+            if let Some(format) = found {
+                log_info!("Presenting HDR10 with {:?}", format.format);
+                return Ok(*format);
+            }
+            log_warning!("The surface offers no HDR10 format, so the output will not be HDR10");
+        }
+
+        for preferred_format in preferred.iter() {
+            let found = formats.iter().find(|f| {
+                f.format as u32 == *preferred_format as u32 &&
+                matches!(f.colorSpace, VkColorSpaceKHR::VK_COLOR_SPACE_SRGB_NONLINEAR_KHR)
+            });
+            if let Some(format) = found {
+                return Ok(*format);
+            }
+        }
+
+        log_warning!("The surface offers no {} format, using {:?}",
+                     if srgb { "sRGB" } else { "UNORM" },
+                     formats[0].format);
+        Ok(formats[0])
+    }
+
+    /// Return whether the surface format is HDR10
+    fn is_hdr10(&self) -> bool {
+        self.format.colorSpace as u32 == VK_COLOR_SPACE_HDR10_ST2084_EXT
+    }
+
+    /// Determine Vulkan surface capabilities
+    ///
+    ///
+    fn determine_surface_capabilities(physical_device: &RendererVkPhysicalDevice,
+                                      present_queue_family_index: u32,
+                                      raw_surface: VkSurfaceKHR)
+                                      -> Result<VkSurfaceCapabilitiesKHR, RendererError> {
+        // Determine whether the surface is supported or not
+        let mut supported: VkBool32 = false as VkBool32;
+        unsafe {
+            try_result!("vkGetPhysicalDeviceSurfaceSupportKHR",
+                          vkGetPhysicalDeviceSurfaceSupportKHR(physical_device.raw,
+                                                               present_queue_family_index,
+                                                               raw_surface,
+                                                               &mut supported));
+        }
+        if supported != true as VkBool32 {
+            return Err(RendererError::NoSuitableDevice("The surface is not supported".to_string()));
+        }
+
+        // Query the surface capabilities
+        //
+        let mut surface_capabilities = VkSurfaceCapabilitiesKHR::default();
+        unsafe {
+            try_result!("vkGetPhysicalDeviceSurfaceCapabilitiesKHR",
+                          vkGetPhysicalDeviceSurfaceCapabilitiesKHR(physical_device.raw, raw_surface, &mut surface_capabilities));
+        }
+
+        if surface_capabilities.currentExtent.width == u32::max_value() {
+            return Err(RendererError::Unsupported("Unable to get surface dimensions".to_string()));
+        }
+
+        debug_assert!(surface_capabilities.currentExtent.width >= surface_capabilities.minImageExtent.width);
+        debug_assert!(surface_capabilities.currentExtent.width <= surface_capabilities.maxImageExtent.width);
+        debug_assert!(surface_capabilities.currentExtent.height >= surface_capabilities.minImageExtent.height);
+        debug_assert!(surface_capabilities.currentExtent.height <= surface_capabilities.maxImageExtent.height);
+
+        Ok(surface_capabilities)
+    }
+
+    /// Choose a Vulkan presentation mode
+    ///
+    ///
+    fn choose_presentation_mode(physical_device: &RendererVkPhysicalDevice,
+                                raw_surface: VkSurfaceKHR)
+                                -> Result<VkPresentModeKHR, RendererError> {
+        let mut presentation_mode_count: u32 = 0;
+        unsafe {
+            try_result!("vkGetPhysicalDeviceSurfacePresentModesKHR",
+                          vkGetPhysicalDeviceSurfacePresentModesKHR(physical_device.raw,
+                                                                    raw_surface,
+                                                                    &mut presentation_mode_count,
+                                                                    ptr::null_mut()));
+        }
+
+        let mut presentation_mode_list: Vec<VkPresentModeKHR> = vec![];
+        presentation_mode_list.resize(presentation_mode_count as usize,
+                                      VkPresentModeKHR::VK_PRESENT_MODE_MAX_ENUM_KHR);
+        unsafe {
+            try_result!("vkGetPhysicalDeviceSurfacePresentModesKHR",
+                          vkGetPhysicalDeviceSurfacePresentModesKHR(physical_device.raw,
+                                                                    raw_surface,
+                                                                    &mut presentation_mode_count,
+                                                                    presentation_mode_list.as_mut_ptr()));
+        }
+
+        let mut presentation_mode: VkPresentModeKHR = VkPresentModeKHR::VK_PRESENT_MODE_MAX_ENUM_KHR;
+        for mode in presentation_mode_list.iter() {
+            log_info!("Available presentation mode: {} {}", *mode as i32, *mode);
+            if matches!(*mode, VkPresentModeKHR::VK_PRESENT_MODE_FIFO_KHR) {
+                presentation_mode = *mode;
+            }
+        }
+        if matches!(presentation_mode,
+                    VkPresentModeKHR::VK_PRESENT_MODE_MAX_ENUM_KHR) {
+            if presentation_mode_list.is_empty() {
+                return Err(RendererError::NoSuitableDevice("No presentation modes available".to_string()));
+            }
+            presentation_mode = presentation_mode_list[0];
+        }
+        log_info!("Selected presentation mode is: {} {}",
+                  presentation_mode as i32,
+                  presentation_mode);
+
+        Ok(presentation_mode)
+    }
+}
+
+impl Drop for RendererVkSurface {
+    fn drop(&mut self) {
+        unsafe {
+            vkDestroySurfaceKHR(self.instance, self.raw, ptr::null());
+        }
+    }
+}
+
+pub struct RendererVkDevice {
+    raw: VkDevice,
+    graphics_queue: VkQueue,
+    graphics_queue_family_index: u32,
+    // The graphics queue when it can present
+    present_queue: VkQueue,
+    present_queue_family_index: u32,
+    // The graphics queue when there is no separate transfer queue family
+    transfer_queue: VkQueue,
+    transfer_queue_family_index: u32,
+    pub descriptor_indexing: bool, // Whether the descriptor indexing features for bindless textures are enabled
+    pub features: DeviceFeatures, // The features enabled
+    pub allocator: Arc<Mutex<MemoryAllocator<RendererVkMemoryBlock>>>, // Sub-allocates buffer and image memory
+    pub memory_usage: Arc<Mutex<MemoryUsageTracker>>, // The memory of each buffer and image, by category
+}
+
+impl RendererVkDevice {
+    /// Return whether transfers are submitted to a queue separate from the graphics queue
+    pub fn has_transfer_queue(&self) -> bool {
+        self.transfer_queue_family_index != self.graphics_queue_family_index
+    }
+
+    /// Return whether presents are submitted to a queue separate from the graphics queue
+    pub fn has_present_queue(&self) -> bool {
+        self.present_queue_family_index != self.graphics_queue_family_index
+    }
+}
+
+impl RendererVkDevice {
+    /// Set up structures required for creating a Vulkan device
+    ///
+    /// A queue is created for each distinct family given.
+    ///
+    /// physical_device: The physical device
+    /// queue_family_index: The index of the graphics queue family
+    /// present_queue_family_index: The index of the family presenting to the surface
+    /// transfer_queue_family_index: The index of a separate transfer queue family, if there is one
+    fn new(physical_device: &RendererVkPhysicalDevice,
+           queue_family_index: u32,
+           present_queue_family_index: u32,
+           transfer_queue_family_index: Option<u32>,
+           instance_layers: &Vec<String>,
+           device_extensions: &Vec<String>,
+           present_wait: bool,
+           descriptor_indexing: bool)
+           -> Result<RendererVkDevice, RendererError> {
+        // The present id, present wait and descriptor indexing features are chained when their
+        // extensions are enabled
+        //
+        let mut present_wait_features = VkPhysicalDevicePresentWaitFeaturesKHR {
+            sType: VK_STRUCTURE_TYPE_PHYSICAL_DEVICE_PRESENT_WAIT_FEATURES_KHR,
+            pNext: ptr::null_mut(),
+            presentWait: true as VkBool32,
+        };
+        let present_id_features = VkPhysicalDevicePresentIdFeaturesKHR {
+            sType: VK_STRUCTURE_TYPE_PHYSICAL_DEVICE_PRESENT_ID_FEATURES_KHR,
+            pNext: &mut present_wait_features as *mut VkPhysicalDevicePresentWaitFeaturesKHR as *mut c_void,
+            presentId: true as VkBool32,
+        };
+        let descriptor_indexing_features = VkPhysicalDeviceDescriptorIndexingFeaturesEXT {
+            sType: VK_STRUCTURE_TYPE_PHYSICAL_DEVICE_DESCRIPTOR_INDEXING_FEATURES_EXT,
+            pNext: if present_wait {
+                &present_id_features as *const VkPhysicalDevicePresentIdFeaturesKHR as *mut c_void
+            } else {
+                ptr::null_mut()
+            },
+            shaderSampledImageArrayNonUniformIndexing: true as VkBool32,
+            descriptorBindingSampledImageUpdateAfterBind: true as VkBool32,
+            descriptorBindingUpdateUnusedWhilePending: true as VkBool32,
+            descriptorBindingPartiallyBound: true as VkBool32,
+            descriptorBindingVariableDescriptorCount: true as VkBool32,
+            runtimeDescriptorArray: true as VkBool32,
+            ..VkPhysicalDeviceDescriptorIndexingFeaturesEXT::default()
+        };
+
+        // Only the features granted from the application's request are enabled
+        let enabled_features = vk_features_from_device(&physical_device.granted_features);
+
+        let priorities: Vec<f32> = vec![1.0f32];
+        let mut queue_create_infos = vec![VkDeviceQueueCreateInfo {
+                                              sType: VkStructureType::VK_STRUCTURE_TYPE_DEVICE_QUEUE_CREATE_INFO,
+                                              queueCount: 1,
+                                              queueFamilyIndex: queue_family_index,
+                                              pQueuePriorities: priorities.as_ptr(),
+                                              flags: 0,
+                                              pNext: ptr::null(),
+                                          }];
+        if present_queue_family_index != queue_family_index {
+            queue_create_infos.push(VkDeviceQueueCreateInfo {
+                sType: VkStructureType::VK_STRUCTURE_TYPE_DEVICE_QUEUE_CREATE_INFO,
+                queueCount: 1,
+                queueFamilyIndex: present_queue_family_index,
+                pQueuePriorities: priorities.as_ptr(),
+                flags: 0,
+                pNext: ptr::null(),
+            });
+        }
+        if let Some(transfer_index) = transfer_queue_family_index {
+            debug_assert!(transfer_index != present_queue_family_index);
+            queue_create_infos.push(VkDeviceQueueCreateInfo {
+                sType: VkStructureType::VK_STRUCTURE_TYPE_DEVICE_QUEUE_CREATE_INFO,
+                queueCount: 1,
+                queueFamilyIndex: transfer_index,
+                pQueuePriorities: priorities.as_ptr(),
+                flags: 0,
+                pNext: ptr::null(),
+            });
+        }
+
+        let il: Vec<*mut c_char> = instance_layers.iter().map(|x| CString::new(x.to_owned()).unwrap().into_raw()).collect();
+        let de: Vec<*mut c_char> = device_extensions.iter().map(|x| CString::new(x.to_owned()).unwrap().into_raw()).collect();
+
+        let device_create_info = VkDeviceCreateInfo {
+            sType: VkStructureType::VK_STRUCTURE_TYPE_DEVICE_CREATE_INFO,
+            queueCreateInfoCount: queue_create_infos.len() as u32,
+            pQueueCreateInfos: queue_create_infos.as_ptr(),
+            pEnabledFeatures: &enabled_features,
+            enabledLayerCount: instance_layers.len() as u32,
+            ppEnabledLayerNames: il.as_ptr() as *mut _,
+            enabledExtensionCount: device_extensions.len() as u32,
+            ppEnabledExtensionNames: de.as_ptr() as *mut _,
+            flags: 0,
+            pNext: if descriptor_indexing {
+                &descriptor_indexing_features as *const VkPhysicalDeviceDescriptorIndexingFeaturesEXT as *const c_void
+            } else if present_wait {
+                &present_id_features as *const VkPhysicalDevicePresentIdFeaturesKHR as *const c_void
+            } else {
+                ptr::null()
+            },
+        };
+
+        // Create a Vulkan device
+        //
+        let mut device: VkDevice = VK_NULL_HANDLE_MUT();
+        let res = unsafe { vkCreateDevice(physical_device.raw, &device_create_info, ptr::null(), &mut device) };
+
+        let _: Vec<CString> = il.iter().map(|x| unsafe { CString::from_raw(*x) }).collect();
+        let _: Vec<CString> = de.iter().map(|x| unsafe { CString::from_raw(*x) }).collect();
+
+        try_result!("vkCreateDevice", res);
+
+        let mut graphics_queue: VkQueue = VK_NULL_HANDLE_MUT();
+        unsafe {
+            vkGetDeviceQueue(device, queue_family_index, 0, &mut graphics_queue);
+        };
+
+        let mut present_queue = graphics_queue;
+        if present_queue_family_index != queue_family_index {
+            unsafe {
+                vkGetDeviceQueue(device, present_queue_family_index, 0, &mut present_queue);
+            };
+        }
+
+        let mut transfer_queue = graphics_queue;
+        if let Some(transfer_index) = transfer_queue_family_index {
+            unsafe {
+                vkGetDeviceQueue(device, transfer_index, 0, &mut transfer_queue);
+            };
+        }
+
+        Ok(RendererVkDevice {
+            raw: device,
+            graphics_queue: graphics_queue,
+            graphics_queue_family_index: queue_family_index,
+            present_queue: present_queue,
+            present_queue_family_index: present_queue_family_index,
+            transfer_queue: transfer_queue,
+            transfer_queue_family_index: transfer_queue_family_index.unwrap_or(queue_family_index),
+            descriptor_indexing: descriptor_indexing,
+            features: physical_device.granted_features,
+            allocator: Arc::new(Mutex::new(MemoryAllocator::new(DEFAULT_MEMORY_BLOCK_SIZE))),
+            memory_usage: Arc::new(Mutex::new(MemoryUsageTracker::new())),
+        })
+    }
+
+    /// Sub-allocate memory for a buffer or image from the pools of the device
+    ///
+    /// physical_device: The physical device
+    /// requirements: The memory requirements of the resource
+    /// memory_type: The index of the memory type to allocate from
+    /// kind: The layout of the resource
+    fn allocate_memory(&self,
+                       physical_device: &RendererVkPhysicalDevice,
+                       requirements: &VkMemoryRequirements,
+                       memory_type: u32,
+                       kind: MemoryResourceKind)
+                       -> MemoryAllocation<RendererVkMemoryBlock> {
+        let mut memory_properties = VkPhysicalDeviceMemoryProperties::default();
+        unsafe {
+            vkGetPhysicalDeviceMemoryProperties(physical_device.raw, &mut memory_properties);
+        }
+
+        let device = self.raw;
+        let mut allocator = self.allocator.lock().unwrap();
+        match allocator.allocate(memory_type,
+                                 kind,
+                                 requirements.size,
+                                 requirements.alignment,
+                                 &mut |size| RendererVkMemoryBlock::new(device, &memory_properties, memory_type, size)) {
+            Some(allocation) => allocation,
+            None => {
+                log_error!("Device memory in use: {:?}", allocator.stats());
+                panic!("Unable to allocate {} bytes of memory type {}", requirements.size, memory_type);
+            }
+        }
+    }
+}
+
+// VK_EXT_memory_budget postdates the bindings, so the structures are declared here
+const VK_STRUCTURE_TYPE_PHYSICAL_DEVICE_MEMORY_PROPERTIES_2: u32 = 1000059006;
+const VK_STRUCTURE_TYPE_PHYSICAL_DEVICE_MEMORY_BUDGET_PROPERTIES_EXT: u32 = 1000237000;
+const VK_MAX_MEMORY_HEAPS: usize = 16;
+
+#[repr(C)]
+#[allow(non_snake_case)]
+struct VkPhysicalDeviceMemoryProperties2 {
+    sType: u32,
+    pNext: *mut c_void,
+    memoryProperties: VkPhysicalDeviceMemoryProperties,
+}
+
+#[repr(C)]
+#[allow(non_snake_case)]
+struct VkPhysicalDeviceMemoryBudgetPropertiesEXT {
+    sType: u32,
+    pNext: *mut c_void,
+    heapBudget: [u64; VK_MAX_MEMORY_HEAPS],
+    heapUsage: [u64; VK_MAX_MEMORY_HEAPS],
+}
+
+#[allow(non_snake_case)]
+type PfnGetPhysicalDeviceMemoryProperties2KHR = extern "C" fn(physicalDevice: VkPhysicalDevice,
+                                                              pMemoryProperties: *mut VkPhysicalDeviceMemoryProperties2);
+
+/// Reports how much of each memory heap the process may use, and how much it is using
+///
+/// The usage includes memory allocated by the driver and other APIs, so it may exceed what the
+/// renderer has allocated itself.
+pub struct RendererVkMemoryBudget {
+    get_memory_properties2: PfnGetPhysicalDeviceMemoryProperties2KHR,
+}
+
+impl RendererVkMemoryBudget {
+    /// Return whether the physical device offers VK_EXT_memory_budget
+    ///
+    /// physical_device: The physical device to query
+    fn device_supports(physical_device: &RendererVkPhysicalDevice) -> bool {
+        physical_device.extension_names().iter().any(|n| n == "VK_EXT_memory_budget")
+    }
+
+    /// Load vkGetPhysicalDeviceMemoryProperties2KHR from an instance created with
+    /// VK_KHR_get_physical_device_properties2
+    ///
+    /// Returns None if the entry point is missing, so that no budgets are reported.
+    ///
+    /// instance: The instance
+    fn new(instance: &RendererVkInstance) -> Option<RendererVkMemoryBudget> {
+        let entrypoint_name = CString::new("vkGetPhysicalDeviceMemoryProperties2KHR").unwrap();
+        let opt_void_ptr = unsafe { vkGetInstanceProcAddr(instance.raw, entrypoint_name.as_ptr()) };
+
+        opt_void_ptr.map(|void_fn_ptr| {
+            type SrcType = unsafe extern "C" fn();
+            RendererVkMemoryBudget {
+                get_memory_properties2: unsafe {
+                    mem::transmute::<SrcType, PfnGetPhysicalDeviceMemoryProperties2KHR>(void_fn_ptr)
+                },
+            }
+        })
+    }
+
+    /// Return the budget and the usage of each heap, in bytes
+    ///
+    /// physical_device: The physical device, whose device was created with VK_EXT_memory_budget
+    fn heap_budgets(&self, physical_device: &RendererVkPhysicalDevice) -> Vec<(u64, u64)> {
+        let mut budget = VkPhysicalDeviceMemoryBudgetPropertiesEXT {
+            sType: VK_STRUCTURE_TYPE_PHYSICAL_DEVICE_MEMORY_BUDGET_PROPERTIES_EXT,
+            pNext: ptr::null_mut(),
+            heapBudget: [0; VK_MAX_MEMORY_HEAPS],
+            heapUsage: [0; VK_MAX_MEMORY_HEAPS],
+        };
+        let mut properties = VkPhysicalDeviceMemoryProperties2 {
+            sType: VK_STRUCTURE_TYPE_PHYSICAL_DEVICE_MEMORY_PROPERTIES_2,
+            pNext: &mut budget as *mut VkPhysicalDeviceMemoryBudgetPropertiesEXT as *mut c_void,
+            memoryProperties: VkPhysicalDeviceMemoryProperties::default(),
+        };
+        (self.get_memory_properties2)(physical_device.raw, &mut properties);
+
+        (0..properties.memoryProperties.memoryHeapCount as usize)
+            .map(|h| (budget.heapBudget[h], budget.heapUsage[h]))
+            .collect()
+    }
+}
+
+impl Drop for RendererVkDevice {
+    fn drop(&mut self) {
+        let mut allocator = self.allocator.lock().unwrap();
+        let stats = allocator.stats();
+        if stats.allocations > 0 {
+            log_warning!("{} buffers and images outlive the device", stats.allocations);
+        }
+        for block in allocator.drain_blocks() {
+            block.release(self.raw);
+        }
+
+        unsafe {
+            vkDestroyDevice(self.raw, ptr::null());
+        }
+    }
+}
+
+/// A block of device memory that buffers and images are sub-allocated from
+///
+/// Memory cannot be mapped more than once, so host-visible blocks are mapped for their lifetime
+/// rather than by each resource in them.
+#[derive(Clone, Copy)]
+pub struct RendererVkMemoryBlock {
+    memory: VkDeviceMemory,
+    pointer: *mut c_void, // Null unless host-visible
+    coherent: bool,
+}
+
+impl RendererVkMemoryBlock {
+    /// Allocate a block of memory, mapping it if it is host-visible
+    ///
+    /// Returns None if the memory could not be allocated.
+    ///
+    /// device: The logical device
+    /// memory_properties: The memory properties of the physical device
+    /// memory_type: The index of the memory type
+    /// size: The size of the block
+    fn new(device: VkDevice,
+           memory_properties: &VkPhysicalDeviceMemoryProperties,
+           memory_type: u32,
+           size: u64)
+           -> Option<RendererVkMemoryBlock> {
+        let alloc_info = VkMemoryAllocateInfo {
+            sType: VkStructureType::VK_STRUCTURE_TYPE_MEMORY_ALLOCATE_INFO,
+            allocationSize: size,
+            memoryTypeIndex: memory_type,
+            pNext: ptr::null(),
+        };
+
+        let mut memory: VkDeviceMemory = VK_NULL_HANDLE_MUT();
+        let res = match injected_failure("vkAllocateMemory") {
+            Some(failure) => injected_result(failure),
+            None => unsafe { vkAllocateMemory(device, &alloc_info, ptr::null(), &mut memory) },
+        };
+        if !matches!(res, VkResult::VK_SUCCESS) {
+            log_error!("Unexpected result from vkAllocateMemory: {}", res);
+            return None;
+        }
+
+        let host_visible = VkMemoryPropertyFlagBits::VK_MEMORY_PROPERTY_HOST_VISIBLE_BIT as VkMemoryPropertyFlags;
+        let host_coherent = VkMemoryPropertyFlagBits::VK_MEMORY_PROPERTY_HOST_COHERENT_BIT as VkMemoryPropertyFlags;
+        let property_flags = memory_properties.memoryTypes[memory_type as usize].propertyFlags as VkMemoryPropertyFlags;
+
+        let mut pointer: *mut c_void = ptr::null_mut();
+        if (property_flags & host_visible) != 0 {
+            unsafe {
+                check_result!("vkMapMemory",
+                              vkMapMemory(device,
+                                          memory,
+                                          0, // Offset
+                                          VK_WHOLE_SIZE as u64,
+                                          0, // Flags, reserved
+                                          &mut pointer));
+            }
+        }
+
+        Some(RendererVkMemoryBlock {
+            memory: memory,
+            pointer: pointer,
+            coherent: (property_flags & host_coherent) != 0,
+        })
+    }
+
+    /// Return the host address of an allocation in the block, which must be host-visible
+    ///
+    /// allocation: The allocation
+    fn mapped_pointer(allocation: &MemoryAllocation<RendererVkMemoryBlock>) -> *mut c_void {
+        if allocation.memory.pointer.is_null() {
+            panic!("The memory is not host-visible");
+        }
+        unsafe { (allocation.memory.pointer as *mut u8).offset(allocation.offset as isize) as *mut c_void }
+    }
+
+    /// Return the memory of a destroyed buffer or image to the pools of the device
+    ///
+    /// device: The logical device
+    /// allocator: The device's allocator
+    /// allocation: The allocation
+    fn free(device: VkDevice,
+            allocator: &Mutex<MemoryAllocator<RendererVkMemoryBlock>>,
+            allocation: &MemoryAllocation<RendererVkMemoryBlock>) {
+        if let Some(block) = allocator.lock().unwrap().free(allocation) {
+            block.release(device);
+        }
+    }
+
+    /// Unmap and free the block
+    ///
+    /// device: The logical device
+    fn release(&self, device: VkDevice) {
+        unsafe {
+            if !self.pointer.is_null() {
+                vkUnmapMemory(device, self.memory);
+            }
+            vkFreeMemory(device, self.memory, ptr::null());
+        }
+    }
+}
+
+// VK_KHR_present_id and VK_KHR_present_wait postdate the bindings, so the structures are declared here
+const VK_STRUCTURE_TYPE_PRESENT_ID_KHR: u32 = 1000294000;
+const VK_STRUCTURE_TYPE_PHYSICAL_DEVICE_PRESENT_ID_FEATURES_KHR: u32 = 1000294001;
+const VK_STRUCTURE_TYPE_PHYSICAL_DEVICE_PRESENT_WAIT_FEATURES_KHR: u32 = 1000248000;
+
+#[repr(C)]
+#[allow(non_snake_case)]
+struct VkPresentIdKHR {
+    sType: u32,
+    pNext: *const c_void,
+    swapchainCount: u32,
+    pPresentIds: *const u64,
+}
+
+#[repr(C)]
+#[allow(non_snake_case)]
+struct VkPhysicalDevicePresentIdFeaturesKHR {
+    sType: u32,
+    pNext: *mut c_void,
+    presentId: VkBool32,
+}
+
+#[repr(C)]
+#[allow(non_snake_case)]
+struct VkPhysicalDevicePresentWaitFeaturesKHR {
+    sType: u32,
+    pNext: *mut c_void,
+    presentWait: VkBool32,
+}
+
+// VK_EXT_descriptor_indexing also postdates the bindings
+const VK_STRUCTURE_TYPE_DESCRIPTOR_SET_LAYOUT_BINDING_FLAGS_CREATE_INFO_EXT: u32 = 1000161000;
+const VK_STRUCTURE_TYPE_PHYSICAL_DEVICE_DESCRIPTOR_INDEXING_FEATURES_EXT: u32 = 1000161001;
+const VK_STRUCTURE_TYPE_DESCRIPTOR_SET_VARIABLE_DESCRIPTOR_COUNT_ALLOCATE_INFO_EXT: u32 = 1000161003;
+const VK_DESCRIPTOR_BINDING_UPDATE_AFTER_BIND_BIT_EXT: u32 = 0x1;
+const VK_DESCRIPTOR_BINDING_UPDATE_UNUSED_WHILE_PENDING_BIT_EXT: u32 = 0x2;
+const VK_DESCRIPTOR_BINDING_PARTIALLY_BOUND_BIT_EXT: u32 = 0x4;
+const VK_DESCRIPTOR_BINDING_VARIABLE_DESCRIPTOR_COUNT_BIT_EXT: u32 = 0x8;
+const VK_DESCRIPTOR_SET_LAYOUT_CREATE_UPDATE_AFTER_BIND_POOL_BIT_EXT: u32 = 0x2;
+const VK_DESCRIPTOR_POOL_CREATE_UPDATE_AFTER_BIND_BIT_EXT: u32 = 0x2;
+
+#[repr(C)]
+#[allow(non_snake_case)]
+struct VkPhysicalDeviceDescriptorIndexingFeaturesEXT {
+    sType: u32,
+    pNext: *mut c_void,
+    shaderInputAttachmentArrayDynamicIndexing: VkBool32,
+    shaderUniformTexelBufferArrayDynamicIndexing: VkBool32,
+    shaderStorageTexelBufferArrayDynamicIndexing: VkBool32,
+    shaderUniformBufferArrayNonUniformIndexing: VkBool32,
+    shaderSampledImageArrayNonUniformIndexing: VkBool32,
+    shaderStorageBufferArrayNonUniformIndexing: VkBool32,
+    shaderStorageImageArrayNonUniformIndexing: VkBool32,
+    shaderInputAttachmentArrayNonUniformIndexing: VkBool32,
+    shaderUniformTexelBufferArrayNonUniformIndexing: VkBool32,
+    shaderStorageTexelBufferArrayNonUniformIndexing: VkBool32,
+    descriptorBindingUniformBufferUpdateAfterBind: VkBool32,
+    descriptorBindingSampledImageUpdateAfterBind: VkBool32,
+    descriptorBindingStorageImageUpdateAfterBind: VkBool32,
+    descriptorBindingStorageBufferUpdateAfterBind: VkBool32,
+    descriptorBindingUniformTexelBufferUpdateAfterBind: VkBool32,
+    descriptorBindingStorageTexelBufferUpdateAfterBind: VkBool32,
+    descriptorBindingUpdateUnusedWhilePending: VkBool32,
+    descriptorBindingPartiallyBound: VkBool32,
+    descriptorBindingVariableDescriptorCount: VkBool32,
+    runtimeDescriptorArray: VkBool32,
+}
+
+impl Default for VkPhysicalDeviceDescriptorIndexingFeaturesEXT {
+    fn default() -> VkPhysicalDeviceDescriptorIndexingFeaturesEXT {
+        VkPhysicalDeviceDescriptorIndexingFeaturesEXT {
+            sType: VK_STRUCTURE_TYPE_PHYSICAL_DEVICE_DESCRIPTOR_INDEXING_FEATURES_EXT,
+            pNext: ptr::null_mut(),
+            shaderInputAttachmentArrayDynamicIndexing: false as VkBool32,
+            shaderUniformTexelBufferArrayDynamicIndexing: false as VkBool32,
+            shaderStorageTexelBufferArrayDynamicIndexing: false as VkBool32,
+            shaderUniformBufferArrayNonUniformIndexing: false as VkBool32,
+            shaderSampledImageArrayNonUniformIndexing: false as VkBool32,
+            shaderStorageBufferArrayNonUniformIndexing: false as VkBool32,
+            shaderStorageImageArrayNonUniformIndexing: false as VkBool32,
+            shaderInputAttachmentArrayNonUniformIndexing: false as VkBool32,
+            shaderUniformTexelBufferArrayNonUniformIndexing: false as VkBool32,
+            shaderStorageTexelBufferArrayNonUniformIndexing: false as VkBool32,
+            descriptorBindingUniformBufferUpdateAfterBind: false as VkBool32,
+            descriptorBindingSampledImageUpdateAfterBind: false as VkBool32,
+            descriptorBindingStorageImageUpdateAfterBind: false as VkBool32,
+            descriptorBindingStorageBufferUpdateAfterBind: false as VkBool32,
+            descriptorBindingUniformTexelBufferUpdateAfterBind: false as VkBool32,
+            descriptorBindingStorageTexelBufferUpdateAfterBind: false as VkBool32,
+            descriptorBindingUpdateUnusedWhilePending: false as VkBool32,
+            descriptorBindingPartiallyBound: false as VkBool32,
+            descriptorBindingVariableDescriptorCount: false as VkBool32,
+            runtimeDescriptorArray: false as VkBool32,
+        }
+    }
+}
+
+#[repr(C)]
+#[allow(non_snake_case)]
+struct VkDescriptorSetLayoutBindingFlagsCreateInfoEXT {
+    sType: u32,
+    pNext: *const c_void,
+    bindingCount: u32,
+    pBindingFlags: *const u32,
+}
+
+#[repr(C)]
+#[allow(non_snake_case)]
+struct VkDescriptorSetVariableDescriptorCountAllocateInfoEXT {
+    sType: u32,
+    pNext: *const c_void,
+    descriptorSetCount: u32,
+    pDescriptorCounts: *const u32,
+}
+
+#[allow(non_snake_case)]
+type PfnWaitForPresentKHR = extern "C" fn(device: VkDevice,
+                                          swapchain: VkSwapchainKHR,
+                                          presentId: u64,
+                                          timeout: u64)
+                                          -> VkResult;
+
+/// How long to wait for a present to complete before starting the frame regardless
+pub const PRESENT_WAIT_TIMEOUT_NS: u64 = 100_000_000;
+
+/// The fraction of the present interval left spare when delaying the start of a frame, to
+/// absorb variation in the frame's CPU work and the GPU work that follows it
+pub const PRESENT_WAIT_SAFETY_FRACTION: f32 = 0.25;
+
+// The weight of the newest sample in the moving averages of the frame statistics
+const FRAME_STATS_SMOOTHING: f32 = 0.1;
+
+/// Paces the CPU from present completion, so that frames start as late as they can
+///
+/// Each present is tagged with the frame number as its present id.  Before the next frame begins
+/// the CPU waits for the previous present to reach the display, then delays further by the part
+/// of the present interval that the frame's work is not expected to need.
+pub struct RendererVkPresentWait {
+    wait_for_present: PfnWaitForPresentKHR,
+    timer: Timer,
+    last_present_complete_ms: Option<f32>,
+    frame_start_ms: Option<f32>,
+}
+
+impl RendererVkPresentWait {
+    /// Return whether the physical device offers both present id and present wait
+    ///
+    /// physical_device: The physical device to query
+    fn device_supports(physical_device: &RendererVkPhysicalDevice) -> bool {
+        let names = physical_device.extension_names();
+        names.iter().any(|n| n == "VK_KHR_present_id") && names.iter().any(|n| n == "VK_KHR_present_wait")
+    }
+
+    /// Load vkWaitForPresentKHR from a device created with the present wait extensions
+    ///
+    /// Returns None if the entry point is missing, so that presentation is not paced.
+    ///
+    /// device: The device
+    fn new(device: &RendererVkDevice) -> Option<RendererVkPresentWait> {
+        let entrypoint_name = CString::new("vkWaitForPresentKHR").unwrap();
+        let opt_void_ptr = unsafe { vkGetDeviceProcAddr(device.raw, entrypoint_name.as_ptr()) };
+
+        opt_void_ptr.map(|void_fn_ptr| {
+            type SrcType = unsafe extern "C" fn();
+            RendererVkPresentWait {
+                wait_for_present: unsafe { mem::transmute::<SrcType, PfnWaitForPresentKHR>(void_fn_ptr) },
+                timer: Timer::new(),
+                last_present_complete_ms: None,
+                frame_start_ms: None,
+            }
+        })
+    }
+
+    /// Wait for the previous present and delay the start of the next frame, updating the stats
+    ///
+    /// Returns the result of the wait, which is VK_SUCCESS or VK_TIMEOUT unless the swapchain
+    /// is out of date or the device has been lost.
+    ///
+    /// device: The device
+    /// swapchain: The swapchain the previous frame was presented to
+    /// present_id: The present id of the previous frame
+    /// stats: The statistics to update
+    fn pace(&mut self,
+            device: VkDevice,
+            swapchain: VkSwapchainKHR,
+            present_id: u64,
+            stats: &mut FrameStats)
+            -> VkResult {
+        let wait_start = self.now_ms();
+        if let Some(frame_start) = self.frame_start_ms {
+            stats.average_frame_work_ms = RendererVkPresentWait::smooth(stats.average_frame_work_ms,
+                                                                        wait_start - frame_start);
+        }
+
+        let res = (self.wait_for_present)(device, swapchain, present_id, PRESENT_WAIT_TIMEOUT_NS);
+        let present_complete = self.now_ms();
+        stats.last_present_wait_ms = present_complete - wait_start;
+
+        let mut delay_ms = 0.0f32;
+        if res == VkResult::VK_SUCCESS {
+            if let Some(last) = self.last_present_complete_ms {
+                stats.average_present_interval_ms = RendererVkPresentWait::smooth(stats.average_present_interval_ms,
+                                                                                  present_complete - last);
+            }
+            self.last_present_complete_ms = Some(present_complete);
+
+            let budget = stats.average_present_interval_ms * (1.0f32 - PRESENT_WAIT_SAFETY_FRACTION);
+            delay_ms = (budget - stats.average_frame_work_ms).max(0.0f32);
+            if delay_ms > 0.0f32 {
+                ::std::thread::sleep(::std::time::Duration::from_micros((delay_ms * 1000.0f32) as u64));
+            }
+        }
+        stats.last_pacing_delay_ms = delay_ms;
+
+        self.frame_start_ms = Some(self.now_ms());
+
+        res
+    }
+
+    /// Return the time since pacing began in milliseconds
+    fn now_ms(&self) -> f32 {
+        self.timer.read_ns() as f32 / 1.0e6f32
+    }
+
+    /// Blend a new sample into a moving average, starting from the first sample
+    fn smooth(average: f32, sample: f32) -> f32 {
+        if average == 0.0f32 {
+            sample
+        } else {
+            average + (sample - average) * FRAME_STATS_SMOOTHING
+        }
+    }
+}
+
+pub struct RendererVkSwapchain {
+    device: VkDevice,
+    raw: VkSwapchainKHR,
+    image_count: u32,
+    images: Vec<VkImage>,
+    views: Vec<VkImageView>,
+    extent: VkExtent2D,
+    format: VkFormat,
+    capturable: bool, // Whether the images can be the source of a copy, for capture_frame
+    headless: bool, // Whether the images are offscreen ones, with no swapchain to present them
+    present_layout: VkImageLayout, // The layout the images are left in at the end of each frame
+    offscreen_images: Vec<RendererVkImage>,
+}
+
+impl RendererVkSwapchain {
+    /// Create a swapchain
+    ///
+    /// device: The logical device
+    /// surface: The surface to present to
+    /// image_count: The number of swapchain images
+    /// old_swapchain: The swapchain being replaced, or a null handle
+    fn new(device: &RendererVkDevice,
+           surface: &RendererVkSurface,
+           image_count: u32,
+           old_swapchain: VkSwapchainKHR)
+           -> Result<RendererVkSwapchain, RendererError> {
+        debug_assert!(image_count >= surface.capabilities.minImageCount);
+        debug_assert!(image_count <= surface.capabilities.maxImageCount);
+
+        let layers = 1; // Non-stereoscopic
+        debug_assert!(layers <= surface.capabilities.maxImageArrayLayers);
+
+        let mut usage = VkImageUsageFlagBits::VK_IMAGE_USAGE_COLOR_ATTACHMENT_BIT as VkImageUsageFlags;
+        debug_assert!(((surface.capabilities.supportedUsageFlags as u32) & (usage as u32)) == (usage as u32));
+
+        // Frame capture copies from the swapchain images, where the surface allows it
+        let transfer_src = VkImageUsageFlagBits::VK_IMAGE_USAGE_TRANSFER_SRC_BIT as u32;
+        let capturable = ((surface.capabilities.supportedUsageFlags as u32) & transfer_src) == transfer_src;
+        if capturable {
+            usage |= transfer_src as VkImageUsageFlags;
+        }
+
+        let transform = VkSurfaceTransformFlagBitsKHR::VK_SURFACE_TRANSFORM_IDENTITY_BIT_KHR;
+        debug_assert!(((surface.capabilities.supportedTransforms as u32) & (transform as u32)) == (transform as u32));
+
+        let alpha = VkCompositeAlphaFlagBitsKHR::VK_COMPOSITE_ALPHA_OPAQUE_BIT_KHR;
+        debug_assert!(((surface.capabilities.supportedCompositeAlpha as u32) & (alpha as u32)) == (alpha as u32));
+
+        // Images rendered on the graphics queue and presented from another are shared between the
+        // two families, rather than having their ownership transferred each frame
+        let queue_family_indices = [device.graphics_queue_family_index, device.present_queue_family_index];
+        let sharing_mode = if device.has_present_queue() {
+            VkSharingMode::VK_SHARING_MODE_CONCURRENT
+        } else {
+            VkSharingMode::VK_SHARING_MODE_EXCLUSIVE
+        };
+
+        let swapchain_create_info = VkSwapchainCreateInfoKHR {
+            sType: VkStructureType::VK_STRUCTURE_TYPE_SWAPCHAIN_CREATE_INFO_KHR,
+            surface: surface.raw,
+            minImageCount: image_count,
+            imageFormat: surface.format.format,
+            imageColorSpace: surface.format.colorSpace,
+            imageExtent: VkExtent2D {
+                width: surface.capabilities.currentExtent.width,
+                height: surface.capabilities.currentExtent.height,
+            },
+            imageArrayLayers: layers,
+            imageUsage: usage,
+            imageSharingMode: sharing_mode,
+            queueFamilyIndexCount: if device.has_present_queue() { queue_family_indices.len() as u32 } else { 0 },
+            pQueueFamilyIndices: if device.has_present_queue() {
+                queue_family_indices.as_ptr() as *mut u32
+            } else {
+                ptr::null_mut()
+            },
+            preTransform: transform,
+            compositeAlpha: alpha,
+            presentMode: surface.presentation,
+            clipped: true as VkBool32,
+            oldSwapchain: old_swapchain,
+            flags: 0,
+            pNext: ptr::null(),
+        };
+
+        let mut swapchain: VkSwapchainKHR = VK_NULL_HANDLE_MUT();
+        unsafe {
+            try_result!("vkCreateSwapchainKHR",
+                        vkCreateSwapchainKHR(device.raw,
+                                             &swapchain_create_info,
+                                             ptr::null_mut(),
+                                             &mut swapchain));
+        }
+
+        // Wrap the swapchain straight away, so that it and any views created so far are destroyed
+        // if a later call fails
+        let mut renderer_swapchain = RendererVkSwapchain {
+            device: device.raw,
+            raw: swapchain,
+            image_count: image_count,
+            images: vec![],
+            views: vec![],
+            extent: VkExtent2D {
+                width: surface.capabilities.currentExtent.width,
+                height: surface.capabilities.currentExtent.height,
+            },
+            format: surface.format.format,
+            capturable: capturable,
+            headless: false,
+            present_layout: VkImageLayout::VK_IMAGE_LAYOUT_PRESENT_SRC_KHR,
+            offscreen_images: vec![],
+        };
+
+        let mut local_image_count: u32 = 0;
+        unsafe {
+            try_result!("vkGetSwapchainImagesKHR",
+                        vkGetSwapchainImagesKHR(device.raw,
+                                                swapchain,
+                                                &mut local_image_count,
+                                                ptr::null_mut()));
+        }
+        debug_assert!(local_image_count == image_count);
+
+        let mut swapchain_images: Vec<VkImage> = vec![];
+        swapchain_images.resize(local_image_count as usize, VK_NULL_HANDLE_MUT());
+        unsafe {
+            vkGetSwapchainImagesKHR(device.raw,
+                                    swapchain,
+                                    &mut local_image_count,
+                                    swapchain_images.as_mut_ptr());
+        }
+        debug_assert!(local_image_count == image_count);
+
+        renderer_swapchain.images = swapchain_images;
+        renderer_swapchain.create_views(device)?;
+
+        Ok(renderer_swapchain)
+    }
+
+    /// Create offscreen images standing in for a swapchain, for a renderer without a window
+    ///
+    /// The images are left ready to be copied from at the end of each frame, so that the frame
+    /// can be captured, and are never presented.
+    ///
+    /// device: The logical device
+    /// physical_device: The physical device
+    /// command_pool: The command pool
+    /// width: The width of the images
+    /// height: The height of the images
+    /// image_count: The number of images
+    /// srgb: Whether the images encode their contents with the sRGB transfer function
+    fn new_headless(device: &RendererVkDevice,
+                    physical_device: &RendererVkPhysicalDevice,
+                    command_pool: &RendererVkCommandPool,
+                    width: u32,
+                    height: u32,
+                    image_count: u32,
+                    srgb: bool)
+                    -> Result<RendererVkSwapchain, RendererError> {
+        if width == 0 || height == 0 {
+            return Err(RendererError::Unsupported("Headless rendering needs a non-empty image size".to_string()));
+        }
+
+        let format = if srgb {
+            VkFormat::VK_FORMAT_R8G8B8A8_SRGB
+        } else {
+            VkFormat::VK_FORMAT_R8G8B8A8_UNORM
+        };
+        let mut renderer_swapchain = RendererVkSwapchain {
+            device: device.raw,
+            raw: VK_NULL_HANDLE_MUT(),
+            image_count: image_count,
+            images: vec![],
+            views: vec![],
             extent: VkExtent2D {
                 width: width,
                 height: height,
             },
+            format: format,
+            capturable: true,
+            headless: true,
+            present_layout: VkImageLayout::VK_IMAGE_LAYOUT_TRANSFER_SRC_OPTIMAL,
+            offscreen_images: vec![],
+        };
+
+        for _ in 0..image_count {
+            // The first render pass of a frame discards the contents, so no transition is needed
+            let image = RendererVkImage::new(device,
+                                             physical_device,
+                                             command_pool,
+                                             width,
+                                             height,
+                                             1, // Mip levels
+                                             format,
+                                             VkImageTiling::VK_IMAGE_TILING_OPTIMAL,
+                                             VkImageUsageFlagBits::VK_IMAGE_USAGE_COLOR_ATTACHMENT_BIT as VkImageUsageFlags |
+                                             VkImageUsageFlagBits::VK_IMAGE_USAGE_TRANSFER_SRC_BIT as VkImageUsageFlags,
+                                             VkMemoryPropertyFlagBits::VK_MEMORY_PROPERTY_DEVICE_LOCAL_BIT as VkMemoryPropertyFlags,
+                                             VkImageLayout::VK_IMAGE_LAYOUT_UNDEFINED,
+                                             VkImageLayout::VK_IMAGE_LAYOUT_UNDEFINED);
+            renderer_swapchain.images.push(image.get_image_raw());
+            renderer_swapchain.offscreen_images.push(image);
+        }
+        renderer_swapchain.create_views(device)?;
+
+        Ok(renderer_swapchain)
+    }
+
+    /// Create a view of each of the images
+    ///
+    ///
+    fn create_views(&mut self, device: &RendererVkDevice) -> Result<(), RendererError> {
+        for i in 0..self.image_count {
+            let create_info = VkImageViewCreateInfo {
+                sType: VkStructureType::VK_STRUCTURE_TYPE_IMAGE_VIEW_CREATE_INFO,
+                image: self.images[i as usize],
+                viewType: VkImageViewType::VK_IMAGE_VIEW_TYPE_2D,
+                format: self.format,
+                components: VkComponentMapping {
+                    r: VkComponentSwizzle::VK_COMPONENT_SWIZZLE_IDENTITY,
+                    g: VkComponentSwizzle::VK_COMPONENT_SWIZZLE_IDENTITY,
+                    b: VkComponentSwizzle::VK_COMPONENT_SWIZZLE_IDENTITY,
+                    a: VkComponentSwizzle::VK_COMPONENT_SWIZZLE_IDENTITY,
+                },
+                subresourceRange: VkImageSubresourceRange {
+                    aspectMask: VkImageAspectFlagBits::VK_IMAGE_ASPECT_COLOR_BIT as VkImageAspectFlags,
+                    baseMipLevel: 0,
+                    levelCount: 1,
+                    baseArrayLayer: 0,
+                    layerCount: 1,
+                },
+                flags: 0,
+                pNext: ptr::null(),
+            };
+
+            let mut view: VkImageView = VK_NULL_HANDLE_MUT();
+            unsafe {
+                try_result!("vkCreateImageView",
+                            vkCreateImageView(device.raw, &create_info, ptr::null_mut(), &mut view));
+            }
+            self.views.push(view);
+        }
+
+        Ok(())
+    }
+}
+
+impl Drop for RendererVkSwapchain {
+    fn drop(&mut self) {
+        for view in self.views.iter() {
+            unsafe {
+                vkDestroyImageView(self.device, *view, ptr::null());
+            }
+        }
+
+        // The offscreen images of a headless renderer are destroyed with the swapchain object
+        if !self.headless {
+            unsafe {
+                vkDestroySwapchainKHR(self.device, self.raw, ptr::null());
+            }
+        }
+    }
+}
+
+struct OneTimeCommandBuffer {
+    queue: VkQueue,
+    buffer: RendererVkCommandBuffer,
+}
+
+impl OneTimeCommandBuffer {
+    /// Create a single-use command buffer
+    ///
+    ///
+    fn new(device: &RendererVkDevice, command_pool: &RendererVkCommandPool) -> OneTimeCommandBuffer {
+        let command_buffer = RendererVkCommandBuffer::new(device, command_pool, true /* primary */);
+
+        command_buffer.begin_primary(true, // one_time_submit
+                                     false, // render_pass_continue
+                                     false /* simultaneous_use */);
+
+        OneTimeCommandBuffer {
+            queue: device.graphics_queue,
+            buffer: command_buffer,
+        }
+    }
+
+    /// Execute the single-use command buffer
+    ///
+    ///
+    fn execute(&mut self) {
+        self.buffer.end();
+
+        let command_buffers = vec![self.buffer.raw];
+        let submit_info = VkSubmitInfo {
+            sType: VkStructureType::VK_STRUCTURE_TYPE_SUBMIT_INFO,
+            commandBufferCount: command_buffers.len() as u32,
+            pCommandBuffers: command_buffers.as_ptr(),
+            waitSemaphoreCount: 0,
+            pWaitSemaphores: ptr::null(),
+            pWaitDstStageMask: ptr::null(),
+            signalSemaphoreCount: 0,
+            pSignalSemaphores: ptr::null(),
+            pNext: ptr::null(),
+        };
+
+        unsafe {
+            check_result!("vkQueueSubmit",
+                          vkQueueSubmit(self.queue, 1, &submit_info, VK_NULL_HANDLE_MUT()));
+            check_result!("vkQueueWaitIdle", vkQueueWaitIdle(self.queue));
+        }
+    }
+}
+
+pub struct RendererVkImage {
+    device: VkDevice,
+    raw: VkImage,
+    allocator: Arc<Mutex<MemoryAllocator<RendererVkMemoryBlock>>>,
+    allocation: MemoryAllocation<RendererVkMemoryBlock>,
+    memory_usage: Arc<Mutex<MemoryUsageTracker>>,
+    mip_levels: u32,
+    array_layers: u32,
+    cube_compatible: bool,
+}
+
+impl RendererVkImage {
+    /// Return the host address of the image's memory, which must be host-visible
+    pub fn mapped_pointer(&self) -> *mut c_void {
+        RendererVkMemoryBlock::mapped_pointer(&self.allocation)
+    }
+
+    /// Return the raw image handle
+    pub fn get_image_raw(&self) -> VkImage {
+        self.raw
+    }
+
+    /// Return the number of mip levels in the image
+    pub fn get_mip_levels(&self) -> u32 {
+        self.mip_levels
+    }
+
+    /// Return the number of array layers in the image, six for a cubemap
+    pub fn get_array_layers(&self) -> u32 {
+        self.array_layers
+    }
+
+    /// Return whether the image can be viewed as a cubemap
+    pub fn is_cube_compatible(&self) -> bool {
+        self.cube_compatible
+    }
+
+    /// Return the number of mip levels in a full chain down to 1x1 for the specified size
+    ///
+    /// width: The width of the largest level
+    /// height: The height of the largest level
+    pub fn full_mip_chain_levels(width: u32, height: u32) -> u32 {
+        32 - width.max(height).max(1).leading_zeros()
+    }
+
+    /// Create an image
+    ///
+    ///
+    pub fn new(device: &RendererVkDevice,
+               physical_device: &RendererVkPhysicalDevice,
+               command_pool: &RendererVkCommandPool,
+               width: u32,
+               height: u32,
+               mip_levels: u32,
+               format: VkFormat,
+               tiling: VkImageTiling,
+               usage: VkImageUsageFlags,
+               memory_properties: VkMemoryPropertyFlags,
+               initial_layout: VkImageLayout,
+               final_layout: VkImageLayout)
+               -> RendererVkImage {
+        RendererVkImage::new_multisampled(device,
+                                          physical_device,
+                                          command_pool,
+                                          width,
+                                          height,
+                                          mip_levels,
+                                          VkSampleCountFlagBits::VK_SAMPLE_COUNT_1_BIT,
+                                          format,
+                                          tiling,
+                                          usage,
+                                          memory_properties,
+                                          initial_layout,
+                                          final_layout)
+    }
+
+    /// Create an image with the specified number of samples per pixel
+    ///
+    /// A multisampled image must have a single mip level and optimal tiling.
+    pub fn new_multisampled(device: &RendererVkDevice,
+                            physical_device: &RendererVkPhysicalDevice,
+                            command_pool: &RendererVkCommandPool,
+                            width: u32,
+                            height: u32,
+                            mip_levels: u32,
+                            samples: VkSampleCountFlagBits,
+                            format: VkFormat,
+                            tiling: VkImageTiling,
+                            usage: VkImageUsageFlags,
+                            memory_properties: VkMemoryPropertyFlags,
+                            initial_layout: VkImageLayout,
+                            final_layout: VkImageLayout)
+                            -> RendererVkImage {
+        RendererVkImage::new_shared(device,
+                                    physical_device,
+                                    command_pool,
+                                    width,
+                                    height,
+                                    mip_levels,
+                                    samples,
+                                    format,
+                                    tiling,
+                                    usage,
+                                    memory_properties,
+                                    initial_layout,
+                                    final_layout,
+                                    &vec![])
+    }
+
+    /// Create an image that may be used concurrently by the queue families specified
+    ///
+    /// An empty list of queue families creates an image owned by one queue family at a time.
+    pub fn new_shared(device: &RendererVkDevice,
+                      physical_device: &RendererVkPhysicalDevice,
+                      command_pool: &RendererVkCommandPool,
+                      width: u32,
+                      height: u32,
+                      mip_levels: u32,
+                      samples: VkSampleCountFlagBits,
+                      format: VkFormat,
+                      tiling: VkImageTiling,
+                      usage: VkImageUsageFlags,
+                      memory_properties: VkMemoryPropertyFlags,
+                      initial_layout: VkImageLayout,
+                      final_layout: VkImageLayout,
+                      queue_family_indices: &Vec<u32>)
+                      -> RendererVkImage {
+        RendererVkImage::new_layered(device,
+                                     physical_device,
+                                     command_pool,
+                                     width,
+                                     height,
+                                     mip_levels,
+                                     1,
+                                     false,
+                                     samples,
+                                     format,
+                                     tiling,
+                                     usage,
+                                     memory_properties,
+                                     initial_layout,
+                                     final_layout,
+                                     queue_family_indices)
+    }
+
+    /// Create an image with the specified number of array layers
+    ///
+    /// A cube-compatible image must be square with six layers, one per face in the order +X, -X,
+    /// +Y, -Y, +Z, -Z, and may also be viewed as a 2D array of its faces.
+    pub fn new_layered(device: &RendererVkDevice,
+                       physical_device: &RendererVkPhysicalDevice,
+                       command_pool: &RendererVkCommandPool,
+                       width: u32,
+                       height: u32,
+                       mip_levels: u32,
+                       array_layers: u32,
+                       cube_compatible: bool,
+                       samples: VkSampleCountFlagBits,
+                       format: VkFormat,
+                       tiling: VkImageTiling,
+                       usage: VkImageUsageFlags,
+                       memory_properties: VkMemoryPropertyFlags,
+                       initial_layout: VkImageLayout,
+                       final_layout: VkImageLayout,
+                       queue_family_indices: &Vec<u32>)
+                       -> RendererVkImage {
+        if cube_compatible && (width != height || array_layers != 6) {
+            panic!("A cubemap must have six square faces");
+        }
+
+        let image_info = VkImageCreateInfo {
+            sType: VkStructureType::VK_STRUCTURE_TYPE_IMAGE_CREATE_INFO,
+            imageType: VkImageType::VK_IMAGE_TYPE_2D,
+            extent: VkExtent3D {
+                width: width,
+                height: height,
+                depth: 1,
+            },
+            mipLevels: mip_levels,
+            arrayLayers: array_layers,
+            format: format,
+            tiling: tiling,
+            initialLayout: VkImageLayout::VK_IMAGE_LAYOUT_PREINITIALIZED,
+            usage: usage,
+            samples: samples,
+            sharingMode: if queue_family_indices.len() > 1 {
+                VkSharingMode::VK_SHARING_MODE_CONCURRENT
+            } else {
+                VkSharingMode::VK_SHARING_MODE_EXCLUSIVE
+            },
+            queueFamilyIndexCount: if queue_family_indices.len() > 1 { queue_family_indices.len() as u32 } else { 0 },
+            pQueueFamilyIndices: if queue_family_indices.len() > 1 {
+                queue_family_indices.as_ptr() as *mut u32
+            } else {
+                VK_NULL_HANDLE_MUT()
+            },
+            flags: if cube_compatible {
+                VkImageCreateFlagBits::VK_IMAGE_CREATE_CUBE_COMPATIBLE_BIT as VkImageCreateFlags
+            } else {
+                0
+            },
+            pNext: ptr::null(),
+        };
+
+        let mut image: VkImage = VK_NULL_HANDLE_MUT();
+        unsafe {
+            check_result!("vkCreateImage",
+                          vkCreateImage(device.raw, &image_info, ptr::null_mut(), &mut image));
+        }
+
+        let mut memory_requirements = VkMemoryRequirements::default();
+        unsafe {
+            vkGetImageMemoryRequirements(device.raw, image, &mut memory_requirements);
+        }
+
+        let memory_type = RendererVk::find_suitable_memory(physical_device,
+                                                           memory_requirements.memoryTypeBits,
+                                                           memory_properties);
+        if memory_type == u32::max_value() {
+            panic!("Unable to identify suitable memory for image");
+        }
+
+        let kind = if tiling as u32 == VkImageTiling::VK_IMAGE_TILING_LINEAR as u32 {
+            MemoryResourceKind::Linear
+        } else {
+            MemoryResourceKind::Optimal
+        };
+        let allocation = device.allocate_memory(physical_device, &memory_requirements, memory_type, kind);
+        unsafe {
+            check_result!("vkBindImageMemory",
+                          vkBindImageMemory(device.raw, image, allocation.memory.memory, allocation.offset));
+        }
+
+        // Attachments are counted as render targets, even where they are also sampled
+        let attachment_usage = VkImageUsageFlagBits::VK_IMAGE_USAGE_COLOR_ATTACHMENT_BIT as u32 |
+                               VkImageUsageFlagBits::VK_IMAGE_USAGE_DEPTH_STENCIL_ATTACHMENT_BIT as u32;
+        let category = if (usage as u32) & attachment_usage != 0 {
+            MemoryCategory::RenderTargets
+        } else {
+            MemoryCategory::Textures
+        };
+        device.memory_usage.lock().unwrap().record((VK_OBJECT_TYPE_IMAGE, image as u64), category, allocation.size);
+
+        let image = RendererVkImage {
+            device: device.raw,
+            raw: image,
+            allocator: device.allocator.clone(),
+            allocation: allocation,
+            memory_usage: device.memory_usage.clone(),
+            mip_levels: mip_levels,
+            array_layers: array_layers,
+            cube_compatible: cube_compatible,
+        };
+
+        let mut aspect_mask = 0;
+        if final_layout as u32 == VkImageLayout::VK_IMAGE_LAYOUT_DEPTH_STENCIL_ATTACHMENT_OPTIMAL as u32 {
+            aspect_mask |= RendererVkImage::depth_aspect_mask(format);
+        } else {
+            aspect_mask |= VkImageAspectFlagBits::VK_IMAGE_ASPECT_COLOR_BIT as VkImageAspectFlags;
+        };
+
+        if initial_layout as u32 != final_layout as u32 {
+            RendererVkImage::transition_layout_immediate(image.raw,
+                                               device,
+                                               command_pool,
+                                               aspect_mask,
+                                               initial_layout,
+                                               final_layout,
+                                               VkPipelineStageFlagBits::VK_PIPELINE_STAGE_TOP_OF_PIPE_BIT as VkPipelineStageFlags,
+                                               VkPipelineStageFlagBits::VK_PIPELINE_STAGE_TOP_OF_PIPE_BIT as VkPipelineStageFlags);
+        }
+
+        image
+    }
+
+    /// Does the specified format support a stencil component?
+    ///
+    ///
+    fn has_stencil_component(format: VkFormat) -> bool {
+        format as u32 == VkFormat::VK_FORMAT_D32_SFLOAT_S8_UINT as u32 ||
+        format as u32 == VkFormat::VK_FORMAT_D24_UNORM_S8_UINT as u32
+    }
+
+    /// Return the aspects of a depth image of the specified format
+    ///
+    /// format: The depth format
+    pub fn depth_aspect_mask(format: VkFormat) -> VkImageAspectFlags {
+        let mut aspect_mask = VkImageAspectFlagBits::VK_IMAGE_ASPECT_DEPTH_BIT as VkImageAspectFlags;
+        if RendererVkImage::has_stencil_component(format) {
+            aspect_mask |= VkImageAspectFlagBits::VK_IMAGE_ASPECT_STENCIL_BIT as VkImageAspectFlags;
+        }
+
+        aspect_mask
+    }
+
+    /// Add a command to the specified command buffer to transition an image layout into a new layout
+    ///
+    /// All mip levels and array layers of the image are transitioned.
+    fn transition_layout(image: VkImage,
+                         command_buffer: &RendererVkCommandBuffer,
+                         aspect_mask: VkImageAspectFlags,
+                         old_layout: VkImageLayout,
+                         new_layout: VkImageLayout,
+                         src_stage_mask: VkPipelineStageFlags,
+                         dst_stage_mask: VkPipelineStageFlags) {
+        RendererVkImage::transition_mip_levels(image,
+                                               command_buffer,
+                                               aspect_mask,
+                                               0,
+                                               VK_REMAINING_MIP_LEVELS as u32,
+                                               old_layout,
+                                               new_layout,
+                                               src_stage_mask,
+                                               dst_stage_mask);
+    }
+
+    /// Add a command to the specified command buffer to transition a range of mip levels of an image
+    ///
+    /// Every array layer of the levels is transitioned.
+    ///
+    /// base_mip_level: The first level to transition
+    /// level_count: The number of levels to transition
+    fn transition_mip_levels(image: VkImage,
+                             command_buffer: &RendererVkCommandBuffer,
+                             aspect_mask: VkImageAspectFlags,
+                             base_mip_level: u32,
+                             level_count: u32,
+                             old_layout: VkImageLayout,
+                             new_layout: VkImageLayout,
+                             src_stage_mask: VkPipelineStageFlags,
+                             dst_stage_mask: VkPipelineStageFlags) {
+        let mut barrier = VkImageMemoryBarrier {
+            sType: VkStructureType::VK_STRUCTURE_TYPE_IMAGE_MEMORY_BARRIER,
+            oldLayout: old_layout,
+            newLayout: new_layout,
+            srcQueueFamilyIndex: VK_QUEUE_FAMILY_IGNORED as u32,
+            dstQueueFamilyIndex: VK_QUEUE_FAMILY_IGNORED as u32,
+            image: image,
+            subresourceRange: VkImageSubresourceRange::default(), // Filled in below
+            srcAccessMask: 0, // Filled in below
+            dstAccessMask: 0, // Filled in below
+            pNext: ptr::null(),
+        };
+
+        barrier.subresourceRange.aspectMask = aspect_mask;
+        barrier.subresourceRange.baseMipLevel = base_mip_level;
+        barrier.subresourceRange.levelCount = level_count;
+        barrier.subresourceRange.baseArrayLayer = 0;
+        barrier.subresourceRange.layerCount = VK_REMAINING_ARRAY_LAYERS as u32;
+
+        match old_layout {
+            VkImageLayout::VK_IMAGE_LAYOUT_PREINITIALIZED => {
+                barrier.srcAccessMask = VkAccessFlagBits::VK_ACCESS_HOST_WRITE_BIT as VkAccessFlags;
+            }
+            VkImageLayout::VK_IMAGE_LAYOUT_COLOR_ATTACHMENT_OPTIMAL => {
+                barrier.srcAccessMask = VkAccessFlagBits::VK_ACCESS_COLOR_ATTACHMENT_WRITE_BIT as VkAccessFlags;
+            }
+            VkImageLayout::VK_IMAGE_LAYOUT_TRANSFER_SRC_OPTIMAL => {
+                barrier.srcAccessMask = VkAccessFlagBits::VK_ACCESS_TRANSFER_READ_BIT as VkAccessFlags;
+            }
+            VkImageLayout::VK_IMAGE_LAYOUT_TRANSFER_DST_OPTIMAL => {
+                barrier.srcAccessMask = VkAccessFlagBits::VK_ACCESS_TRANSFER_WRITE_BIT as VkAccessFlags;
+            }
+            VkImageLayout::VK_IMAGE_LAYOUT_SHADER_READ_ONLY_OPTIMAL => {
+                barrier.srcAccessMask = VkAccessFlagBits::VK_ACCESS_SHADER_READ_BIT as VkAccessFlags;
+            }
+            VkImageLayout::VK_IMAGE_LAYOUT_DEPTH_STENCIL_ATTACHMENT_OPTIMAL => {
+                barrier.srcAccessMask = VkAccessFlagBits::VK_ACCESS_DEPTH_STENCIL_ATTACHMENT_WRITE_BIT as VkAccessFlags;
+            }
+            VkImageLayout::VK_IMAGE_LAYOUT_UNDEFINED => {
+                barrier.srcAccessMask = 0;
+            }
+            _ => {
+                panic!("Unsupported transition source layout");
+            }
+        };
+
+        match new_layout {
+            VkImageLayout::VK_IMAGE_LAYOUT_PREINITIALIZED => {
+                barrier.dstAccessMask = VkAccessFlagBits::VK_ACCESS_HOST_WRITE_BIT as VkAccessFlags;
+            }
+            VkImageLayout::VK_IMAGE_LAYOUT_COLOR_ATTACHMENT_OPTIMAL => {
+                barrier.dstAccessMask = VkAccessFlagBits::VK_ACCESS_COLOR_ATTACHMENT_WRITE_BIT as VkAccessFlags;
+            }
+            VkImageLayout::VK_IMAGE_LAYOUT_TRANSFER_SRC_OPTIMAL => {
+                barrier.dstAccessMask = VkAccessFlagBits::VK_ACCESS_TRANSFER_READ_BIT as VkAccessFlags;
+            }
+            VkImageLayout::VK_IMAGE_LAYOUT_TRANSFER_DST_OPTIMAL => {
+                barrier.dstAccessMask = VkAccessFlagBits::VK_ACCESS_TRANSFER_WRITE_BIT as VkAccessFlags;
+            }
+            VkImageLayout::VK_IMAGE_LAYOUT_SHADER_READ_ONLY_OPTIMAL => {
+                barrier.dstAccessMask = VkAccessFlagBits::VK_ACCESS_SHADER_READ_BIT as VkAccessFlags;
+            }
+            VkImageLayout::VK_IMAGE_LAYOUT_DEPTH_STENCIL_ATTACHMENT_OPTIMAL => {
+                barrier.dstAccessMask = VkAccessFlagBits::VK_ACCESS_DEPTH_STENCIL_ATTACHMENT_WRITE_BIT as VkAccessFlags;
+            }
+            VkImageLayout::VK_IMAGE_LAYOUT_PRESENT_SRC_KHR => {
+                barrier.dstAccessMask = VkAccessFlagBits::VK_ACCESS_MEMORY_READ_BIT as VkAccessFlags;
+            }
+            VkImageLayout::VK_IMAGE_LAYOUT_GENERAL => {
+                barrier.dstAccessMask = VkAccessFlagBits::VK_ACCESS_HOST_READ_BIT as VkAccessFlags;
+            }
+            VkImageLayout::VK_IMAGE_LAYOUT_UNDEFINED => {
+                barrier.dstAccessMask = 0;
+            }
+            _ => {
+                panic!("Unsupported transition destination layout");
+            }
+        };
+
+        unsafe {
+            vkCmdPipelineBarrier(command_buffer.raw,
+                                 src_stage_mask,
+                                 dst_stage_mask,
+                                 0, // Dependency flags
+                                 0, // Memory barrier count
+                                 ptr::null(), // Memory barriers
+                                 0, // Buffer memory barrier count
+                                 ptr::null(), // Buffer memory barriers
+                                 1, // Image barrier count
+                                 &barrier); // Image barriers
+        }
+    }
+
+    /// Transition an image layout into a new layout and execute the transition immediately
+    ///
+    ///
+    pub fn transition_layout_immediate(image: VkImage,
+                                       device: &RendererVkDevice,
+                                       command_pool: &RendererVkCommandPool,
+                                       aspect_mask: VkImageAspectFlags,
+                                       old_layout: VkImageLayout,
+                                       new_layout: VkImageLayout,
+                                       src_stages: VkPipelineStageFlags,
+                                       dst_stages: VkPipelineStageFlags) {
+        let mut one_time = OneTimeCommandBuffer::new(device, command_pool);
+
+        RendererVkImage::transition_layout(image,
+                                           &one_time.buffer,
+                                           aspect_mask,
+                                           old_layout,
+                                           new_layout,
+                                           src_stages,
+                                           dst_stages);
+
+        one_time.execute();
+    }
+
+    /// Copy the contents of an image to another image, e.g. for staging
+    ///
+    /// TODO: Split this up as for transition_layout and transition_layout_immediate
+    ///
+    pub fn copy(device: &RendererVkDevice,
+                command_pool: &RendererVkCommandPool,
+                source_image: VkImage,
+                destination_image: VkImage,
+                width: u32,
+                height: u32) {
+        RendererVkImage::copy_to_layer(device, command_pool, source_image, destination_image, width, height, 0);
+    }
+
+    /// Copy the contents of a single-layer image into one array layer of another image
+    ///
+    /// layer: The destination array layer, or cubemap face
+    pub fn copy_to_layer(device: &RendererVkDevice,
+                         command_pool: &RendererVkCommandPool,
+                         source_image: VkImage,
+                         destination_image: VkImage,
+                         width: u32,
+                         height: u32,
+                         layer: u32) {
+        let mut one_time = OneTimeCommandBuffer::new(device, command_pool);
+
+        // Now copy the staging image to its final destination
+        //
+        let sub_resource = VkImageSubresourceLayers {
+            aspectMask: VkImageAspectFlagBits::VK_IMAGE_ASPECT_COLOR_BIT as VkImageAspectFlags,
+            baseArrayLayer: 0,
+            mipLevel: 0,
+            layerCount: 1,
+        };
+
+        let region = VkImageCopy {
+            srcSubresource: sub_resource,
+            dstSubresource: VkImageSubresourceLayers { baseArrayLayer: layer, ..sub_resource },
+            srcOffset: VkOffset3D { x: 0, y: 0, z: 0 },
+            dstOffset: VkOffset3D { x: 0, y: 0, z: 0 },
+            extent: VkExtent3D {
+                width: width,
+                height: height,
+                depth: 1,
+            },
+        };
+
+        unsafe {
+            vkCmdCopyImage(one_time.buffer.raw,
+                           source_image,
+                           VkImageLayout::VK_IMAGE_LAYOUT_TRANSFER_SRC_OPTIMAL,
+                           destination_image,
+                           VkImageLayout::VK_IMAGE_LAYOUT_TRANSFER_DST_OPTIMAL,
+                           1,
+                           &region);
+        }
+
+        one_time.execute();
+    }
+
+    /// Fill the mip chain of an image from its first level and make every level shader-readable
+    ///
+    /// Each level is blitted from the one above with linear filtering.  All levels must be in
+    /// VK_IMAGE_LAYOUT_TRANSFER_DST_OPTIMAL, with the first level holding the image contents.
+    ///
+    /// width: The width of the first level
+    /// height: The height of the first level
+    pub fn generate_mipmaps(&self, device: &RendererVkDevice, command_pool: &RendererVkCommandPool, width: u32, height: u32) {
+        let mut one_time = OneTimeCommandBuffer::new(device, command_pool);
+
+        let aspect_mask = VkImageAspectFlagBits::VK_IMAGE_ASPECT_COLOR_BIT as VkImageAspectFlags;
+        let transfer_stage = VkPipelineStageFlagBits::VK_PIPELINE_STAGE_TRANSFER_BIT as VkPipelineStageFlags;
+
+        let mut level_width = width as i32;
+        let mut level_height = height as i32;
+        for level in 1..self.mip_levels {
+            // The level above becomes the source of this blit, and is finished with afterwards
+            //
+            RendererVkImage::transition_mip_levels(self.raw,
+                                                   &one_time.buffer,
+                                                   aspect_mask,
+                                                   level - 1,
+                                                   1,
+                                                   VkImageLayout::VK_IMAGE_LAYOUT_TRANSFER_DST_OPTIMAL,
+                                                   VkImageLayout::VK_IMAGE_LAYOUT_TRANSFER_SRC_OPTIMAL,
+                                                   transfer_stage,
+                                                   transfer_stage);
+
+            let next_width = (level_width / 2).max(1);
+            let next_height = (level_height / 2).max(1);
+
+            let blit = VkImageBlit {
+                srcSubresource: VkImageSubresourceLayers {
+                    aspectMask: aspect_mask,
+                    mipLevel: level - 1,
+                    baseArrayLayer: 0,
+                    layerCount: 1,
+                },
+                srcOffsets: [VkOffset3D { x: 0, y: 0, z: 0 },
+                             VkOffset3D {
+                                 x: level_width,
+                                 y: level_height,
+                                 z: 1,
+                             }],
+                dstSubresource: VkImageSubresourceLayers {
+                    aspectMask: aspect_mask,
+                    mipLevel: level,
+                    baseArrayLayer: 0,
+                    layerCount: 1,
+                },
+                dstOffsets: [VkOffset3D { x: 0, y: 0, z: 0 },
+                             VkOffset3D {
+                                 x: next_width,
+                                 y: next_height,
+                                 z: 1,
+                             }],
+            };
+
+            unsafe {
+                vkCmdBlitImage(one_time.buffer.raw,
+                               self.raw,
+                               VkImageLayout::VK_IMAGE_LAYOUT_TRANSFER_SRC_OPTIMAL,
+                               self.raw,
+                               VkImageLayout::VK_IMAGE_LAYOUT_TRANSFER_DST_OPTIMAL,
+                               1,
+                               &blit,
+                               VkFilter::VK_FILTER_LINEAR);
+            }
+
+            level_width = next_width;
+            level_height = next_height;
+        }
+
+        // Every level but the last is now a transfer source, and the last is still a destination
+        //
+        let fragment_stage = VkPipelineStageFlagBits::VK_PIPELINE_STAGE_FRAGMENT_SHADER_BIT as VkPipelineStageFlags;
+        if self.mip_levels > 1 {
+            RendererVkImage::transition_mip_levels(self.raw,
+                                                   &one_time.buffer,
+                                                   aspect_mask,
+                                                   0,
+                                                   self.mip_levels - 1,
+                                                   VkImageLayout::VK_IMAGE_LAYOUT_TRANSFER_SRC_OPTIMAL,
+                                                   VkImageLayout::VK_IMAGE_LAYOUT_SHADER_READ_ONLY_OPTIMAL,
+                                                   transfer_stage,
+                                                   fragment_stage);
+        }
+        RendererVkImage::transition_mip_levels(self.raw,
+                                               &one_time.buffer,
+                                               aspect_mask,
+                                               self.mip_levels - 1,
+                                               1,
+                                               VkImageLayout::VK_IMAGE_LAYOUT_TRANSFER_DST_OPTIMAL,
+                                               VkImageLayout::VK_IMAGE_LAYOUT_SHADER_READ_ONLY_OPTIMAL,
+                                               transfer_stage,
+                                               fragment_stage);
+
+        one_time.execute();
+    }
+}
+
+impl Drop for RendererVkImage {
+    fn drop(&mut self) {
+        unsafe {
+            vkDestroyImage(self.device, self.raw, ptr::null());
+        }
+        RendererVkMemoryBlock::free(self.device, &self.allocator, &self.allocation);
+        self.memory_usage.lock().unwrap().release((VK_OBJECT_TYPE_IMAGE, self.raw as u64));
+    }
+}
+
+pub struct RendererVkImageView {
+    device: VkDevice,
+    raw: VkImageView,
+}
+
+impl RendererVkImageView {
+    /// Return the raw image view for this image
+    pub fn get_view_raw(&self) -> VkImageView {
+        self.raw
+    }
+
+    /// Create an image view
+    ///
+    /// The view type follows the image: a cube for a cube-compatible image, a 2D array for an
+    /// image with several layers and 2D otherwise.
+    pub fn new(device: &RendererVkDevice,
+               image: &RendererVkImage,
+               format: VkFormat,
+               aspect_flags: VkImageAspectFlags)
+               -> RendererVkImageView {
+        let view_type = if image.cube_compatible {
+            VkImageViewType::VK_IMAGE_VIEW_TYPE_CUBE
+        } else if image.array_layers > 1 {
+            VkImageViewType::VK_IMAGE_VIEW_TYPE_2D_ARRAY
+        } else {
+            VkImageViewType::VK_IMAGE_VIEW_TYPE_2D
+        };
+
+        RendererVkImageView::new_typed(device, image, format, aspect_flags, view_type)
+    }
+
+    /// Create an image view of the specified type covering every mip level and array layer
+    ///
+    /// view_type: The view type, which must be compatible with the image's layers
+    pub fn new_typed(device: &RendererVkDevice,
+                     image: &RendererVkImage,
+                     format: VkFormat,
+                     aspect_flags: VkImageAspectFlags,
+                     view_type: VkImageViewType)
+                     -> RendererVkImageView {
+        let image_view_info = VkImageViewCreateInfo {
+            sType: VkStructureType::VK_STRUCTURE_TYPE_IMAGE_VIEW_CREATE_INFO,
+            image: image.raw,
+            viewType: view_type,
+            format: format,
+            subresourceRange: VkImageSubresourceRange {
+                aspectMask: aspect_flags,
+                baseMipLevel: 0,
+                levelCount: image.mip_levels,
+                baseArrayLayer: 0,
+                layerCount: image.array_layers,
+            },
+            components: VkComponentMapping {
+                r: VkComponentSwizzle::VK_COMPONENT_SWIZZLE_IDENTITY,
+                g: VkComponentSwizzle::VK_COMPONENT_SWIZZLE_IDENTITY,
+                b: VkComponentSwizzle::VK_COMPONENT_SWIZZLE_IDENTITY,
+                a: VkComponentSwizzle::VK_COMPONENT_SWIZZLE_IDENTITY,
+            },
+            flags: 0,
+            pNext: ptr::null(),
+        };
+
+        let mut image_view: VkImageView = VK_NULL_HANDLE_MUT();
+        unsafe {
+            check_result!("vkCreateImageView",
+                          vkCreateImageView(device.raw,
+                                            &image_view_info,
+                                            ptr::null_mut(),
+                                            &mut image_view));
+        }
+
+        RendererVkImageView {
+            device: device.raw,
+            raw: image_view,
+        }
+    }
+}
+
+impl Drop for RendererVkImageView {
+    fn drop(&mut self) {
+        unsafe {
+            vkDestroyImageView(self.device, self.raw, ptr::null());
+        }
+    }
+}
+
+/// The image bound to an attachment of the render graph, and the layout it is in
+struct RendererVkGraphAttachment {
+    image: VkImage,
+    aspect_mask: VkImageAspectFlags,
+    layout: VkImageLayout,
+}
+
+/// A layout transition of a render graph attachment waiting to be recorded before a pass
+struct RendererVkGraphBarrier {
+    image: VkImage,
+    aspect_mask: VkImageAspectFlags,
+    old_layout: VkImageLayout,
+    new_layout: VkImageLayout,
+}
+
+impl RendererVkGraphBarrier {
+    /// Return the image layout of an attachment layout of the render graph
+    fn layout(layout: AttachmentLayout) -> VkImageLayout {
+        match layout {
+            AttachmentLayout::Undefined => VkImageLayout::VK_IMAGE_LAYOUT_UNDEFINED,
+            AttachmentLayout::ColourAttachment => VkImageLayout::VK_IMAGE_LAYOUT_COLOR_ATTACHMENT_OPTIMAL,
+            AttachmentLayout::DepthAttachment => VkImageLayout::VK_IMAGE_LAYOUT_DEPTH_STENCIL_ATTACHMENT_OPTIMAL,
+            AttachmentLayout::ShaderRead => VkImageLayout::VK_IMAGE_LAYOUT_SHADER_READ_ONLY_OPTIMAL,
+            AttachmentLayout::Present => VkImageLayout::VK_IMAGE_LAYOUT_PRESENT_SRC_KHR,
+        }
+    }
+
+    /// Return the pipeline stages of a later pass that wait on an earlier one for a hazard
+    ///
+    /// kind: The hazard between the passes
+    fn wait_stages(kind: DependencyKind) -> VkPipelineStageFlags {
+        match kind {
+            DependencyKind::ReadAfterWrite => {
+                VkPipelineStageFlagBits::VK_PIPELINE_STAGE_FRAGMENT_SHADER_BIT as VkPipelineStageFlags
+            }
+            DependencyKind::WriteAfterRead |
+            DependencyKind::WriteAfterWrite => {
+                VkPipelineStageFlagBits::VK_PIPELINE_STAGE_EARLY_FRAGMENT_TESTS_BIT as VkPipelineStageFlags |
+                VkPipelineStageFlagBits::VK_PIPELINE_STAGE_LATE_FRAGMENT_TESTS_BIT as VkPipelineStageFlags |
+                VkPipelineStageFlagBits::VK_PIPELINE_STAGE_COLOR_ATTACHMENT_OUTPUT_BIT as VkPipelineStageFlags
+            }
+        }
+    }
+
+    /// Return the pipeline stages that use an image in a layout
+    fn stage(layout: VkImageLayout) -> VkPipelineStageFlags {
+        match layout {
+            VkImageLayout::VK_IMAGE_LAYOUT_COLOR_ATTACHMENT_OPTIMAL => {
+                VkPipelineStageFlagBits::VK_PIPELINE_STAGE_COLOR_ATTACHMENT_OUTPUT_BIT as VkPipelineStageFlags
+            }
+            VkImageLayout::VK_IMAGE_LAYOUT_DEPTH_STENCIL_ATTACHMENT_OPTIMAL => {
+                VkPipelineStageFlagBits::VK_PIPELINE_STAGE_EARLY_FRAGMENT_TESTS_BIT as VkPipelineStageFlags |
+                VkPipelineStageFlagBits::VK_PIPELINE_STAGE_LATE_FRAGMENT_TESTS_BIT as VkPipelineStageFlags
+            }
+            VkImageLayout::VK_IMAGE_LAYOUT_SHADER_READ_ONLY_OPTIMAL => {
+                VkPipelineStageFlagBits::VK_PIPELINE_STAGE_FRAGMENT_SHADER_BIT as VkPipelineStageFlags
+            }
+            VkImageLayout::VK_IMAGE_LAYOUT_PRESENT_SRC_KHR => {
+                VkPipelineStageFlagBits::VK_PIPELINE_STAGE_BOTTOM_OF_PIPE_BIT as VkPipelineStageFlags
+            }
+            _ => VkPipelineStageFlagBits::VK_PIPELINE_STAGE_TOP_OF_PIPE_BIT as VkPipelineStageFlags,
+        }
+    }
+
+    /// Record the transition
+    ///
+    /// command_buffer: The command buffer to record into, outside of a render pass
+    fn record(&self, command_buffer: &RendererVkCommandBuffer) {
+        RendererVkImage::transition_layout(self.image,
+                                           command_buffer,
+                                           self.aspect_mask,
+                                           self.old_layout,
+                                           self.new_layout,
+                                           RendererVkGraphBarrier::stage(self.old_layout),
+                                           RendererVkGraphBarrier::stage(self.new_layout));
+    }
+}
+
+pub struct RendererVkRenderPass {
+    device: VkDevice,
+    raw: VkRenderPass,
+    samples: VkSampleCountFlagBits,
+    colour_attachments: u32,
+    colour_format: VkFormat,
+    depth_format: Option<VkFormat>,
+    load_colour: bool,
+    external_dependencies: bool,
+}
+
+// VkClearValue is a union of a colour and a depth and stencil value, of four 32-bit words, which
+// is filled in as such here
+#[repr(C)]
+#[derive(Clone, Copy)]
+struct RendererVkClearValue {
+    words: [u32; 4],
+}
+
+impl RendererVkClearValue {
+    /// Return a floating point colour clear value
+    ///
+    /// colour: The RGBA value
+    fn colour(colour: [f32; 4]) -> RendererVkClearValue {
+        RendererVkClearValue { words: [colour[0].to_bits(), colour[1].to_bits(), colour[2].to_bits(), colour[3].to_bits()] }
+    }
+
+    /// Return a depth and stencil clear value, with the stencil zeroed
+    ///
+    /// depth: The depth value
+    fn depth(depth: f32) -> RendererVkClearValue {
+        RendererVkClearValue { words: [depth.to_bits(), 0, 0, 0] }
+    }
+}
+
+impl RendererVkRenderPass {
+    /// Return the number of colour attachments, which are written by fragment outputs 0 onwards
+    pub fn get_colour_attachment_count(&self) -> u32 {
+        self.colour_attachments
+    }
+
+    /// Return the number of samples per pixel of the colour and depth attachments
+    pub fn get_samples(&self) -> VkSampleCountFlagBits {
+        self.samples
+    }
+
+    /// Return whether the pass resolves its multisampled colour attachments into further attachments
+    pub fn is_multisampled(&self) -> bool {
+        self.samples as u32 != VkSampleCountFlagBits::VK_SAMPLE_COUNT_1_BIT as u32
+    }
+
+    /// Return whether the pass has a depth and stencil attachment
+    pub fn has_depth(&self) -> bool {
+        self.depth_format.is_some()
+    }
+
+    /// Create a render pass with some default rendering choices
+    ///
+    /// The colour attachments come first, followed by the depth attachment if there is one, and
+    /// are loaded when load_colour is set rather than overwritten.  When samples is
+    /// more than one the colour and depth attachments are multisampled, and each colour
+    /// attachment is resolved into a single-sampled attachment after them.  External
+    /// dependencies order the pass's attachment writes and shader reads after earlier render pass
+    /// instances, and before later ones, for render graph passes that share attachments.
+    ///
+    /// A render pass that clears attachments is only begun once at the start of a pass, as each
+    /// thread's render pass instance would otherwise clear what the others drew.  It is
+    /// compatible with the one that does not, so shares its framebuffers and pipelines.
+    fn new(device: &RendererVkDevice,
+           colour_format: VkFormat,
+           colour_attachments: u32,
+           depth_format: Option<VkFormat>,
+           samples: VkSampleCountFlagBits,
+           load_colour: bool,
+           external_dependencies: bool,
+           clear_colour: bool,
+           clear_depth: bool)
+           -> RendererVkRenderPass {
+        debug_assert!(colour_attachments >= 1);
+        let multisampled = samples as u32 != VkSampleCountFlagBits::VK_SAMPLE_COUNT_1_BIT as u32;
+
+        // Define the colour and depth attachment references
+        //
+        let color_attachment_refs: Vec<VkAttachmentReference> = (0..colour_attachments)
+            .map(|i| {
+                VkAttachmentReference {
+                    attachment: i,
+                    layout: VkImageLayout::VK_IMAGE_LAYOUT_COLOR_ATTACHMENT_OPTIMAL,
+                }
+            })
+            .collect();
+
+        let depth_attachment_ref = VkAttachmentReference {
+            attachment: colour_attachments,
+            layout: VkImageLayout::VK_IMAGE_LAYOUT_DEPTH_STENCIL_ATTACHMENT_OPTIMAL,
+        };
+
+        let first_resolve_attachment = if depth_format.is_some() {
+            colour_attachments + 1
+        } else {
+            colour_attachments
+        };
+        let resolve_attachment_refs: Vec<VkAttachmentReference> = (0..colour_attachments)
+            .map(|i| {
+                VkAttachmentReference {
+                    attachment: first_resolve_attachment + i,
+                    layout: VkImageLayout::VK_IMAGE_LAYOUT_COLOR_ATTACHMENT_OPTIMAL,
+                }
+            })
+            .collect();
+
+        // Create subpasses and subpass dependencies.  Note that the wait stages on the queue
+        // submission has been set to be VK_PIPELINE_STAGE_TOP_OF_PIPE_BIT in order to avoid
+        // an explicit dependency between VK_SUBPASS_EXTERNAL and subpass 0, unless the render
+        // graph shares the attachments with other passes.
+        //
+        let mut sub_passes: Vec<VkSubpassDescription> = vec![];
+        let mut dependencies: Vec<VkSubpassDependency> = vec![];
+        if external_dependencies {
+            let attachment_stages = VkPipelineStageFlagBits::VK_PIPELINE_STAGE_COLOR_ATTACHMENT_OUTPUT_BIT as VkPipelineStageFlags |
+                                    VkPipelineStageFlagBits::VK_PIPELINE_STAGE_EARLY_FRAGMENT_TESTS_BIT as VkPipelineStageFlags |
+                                    VkPipelineStageFlagBits::VK_PIPELINE_STAGE_LATE_FRAGMENT_TESTS_BIT as VkPipelineStageFlags;
+            let attachment_writes = VkAccessFlagBits::VK_ACCESS_COLOR_ATTACHMENT_WRITE_BIT as VkAccessFlags |
+                                    VkAccessFlagBits::VK_ACCESS_DEPTH_STENCIL_ATTACHMENT_WRITE_BIT as VkAccessFlags;
+            let shader_stages = VkPipelineStageFlagBits::VK_PIPELINE_STAGE_FRAGMENT_SHADER_BIT as VkPipelineStageFlags;
+            let shader_reads = VkAccessFlagBits::VK_ACCESS_SHADER_READ_BIT as VkAccessFlags;
+
+            dependencies.push(VkSubpassDependency {
+                srcSubpass: VK_SUBPASS_EXTERNAL as u32,
+                dstSubpass: 0,
+                srcStageMask: attachment_stages | shader_stages,
+                dstStageMask: attachment_stages | shader_stages,
+                srcAccessMask: attachment_writes | shader_reads,
+                dstAccessMask: attachment_writes | shader_reads,
+                dependencyFlags: 0,
+            });
+            dependencies.push(VkSubpassDependency {
+                srcSubpass: 0,
+                dstSubpass: VK_SUBPASS_EXTERNAL as u32,
+                srcStageMask: attachment_stages,
+                dstStageMask: attachment_stages | shader_stages,
+                srcAccessMask: attachment_writes,
+                dstAccessMask: attachment_writes | shader_reads,
+                dependencyFlags: 0,
+            });
+        }
+
+        sub_passes.push(VkSubpassDescription {
+            pipelineBindPoint: VkPipelineBindPoint::VK_PIPELINE_BIND_POINT_GRAPHICS,
+            colorAttachmentCount: color_attachment_refs.len() as u32,
+            pColorAttachments: color_attachment_refs.as_ptr(),
+            inputAttachmentCount: 0,
+            pInputAttachments: ptr::null(),
+            pResolveAttachments: if multisampled {
+                resolve_attachment_refs.as_ptr()
+            } else {
+                ptr::null()
+            },
+            pDepthStencilAttachment: if depth_format.is_some() {
+                &depth_attachment_ref
+            } else {
+                ptr::null()
+            },
+            preserveAttachmentCount: 0,
+            pPreserveAttachments: ptr::null(),
+            flags: 0,
+        });
+
+        // Now define the renderpass
+        //
+        // Offscreen passes load the existing colour contents, so that each thread's render pass
+        // instance and any explicit clear_render_target calls are preserved.  The swapchain pass
+        // overwrites the whole image so its previous contents are not required.  Cleared
+        // attachments discard their previous contents whatever layout they are in.
+        //
+        let (colour_load_op, colour_initial_layout) = if clear_colour {
+            (VkAttachmentLoadOp::VK_ATTACHMENT_LOAD_OP_CLEAR, VkImageLayout::VK_IMAGE_LAYOUT_UNDEFINED)
+        } else if load_colour {
+            (VkAttachmentLoadOp::VK_ATTACHMENT_LOAD_OP_LOAD, VkImageLayout::VK_IMAGE_LAYOUT_COLOR_ATTACHMENT_OPTIMAL)
+        } else {
+            (VkAttachmentLoadOp::VK_ATTACHMENT_LOAD_OP_DONT_CARE, VkImageLayout::VK_IMAGE_LAYOUT_UNDEFINED)
+        };
+        let (depth_load_op, depth_initial_layout) = if clear_depth {
+            (VkAttachmentLoadOp::VK_ATTACHMENT_LOAD_OP_CLEAR, VkImageLayout::VK_IMAGE_LAYOUT_UNDEFINED)
+        } else {
+            (VkAttachmentLoadOp::VK_ATTACHMENT_LOAD_OP_LOAD, VkImageLayout::VK_IMAGE_LAYOUT_DEPTH_STENCIL_ATTACHMENT_OPTIMAL)
+        };
+
+        let mut attachments: Vec<VkAttachmentDescription> = vec![];
+        for _ in 0..colour_attachments {
+            attachments.push(VkAttachmentDescription {
+                format: colour_format,
+                samples: samples,
+                loadOp: colour_load_op,
+                storeOp: VkAttachmentStoreOp::VK_ATTACHMENT_STORE_OP_STORE,
+                stencilLoadOp: VkAttachmentLoadOp::VK_ATTACHMENT_LOAD_OP_DONT_CARE,
+                stencilStoreOp: VkAttachmentStoreOp::VK_ATTACHMENT_STORE_OP_DONT_CARE,
+                initialLayout: colour_initial_layout,
+                finalLayout: VkImageLayout::VK_IMAGE_LAYOUT_COLOR_ATTACHMENT_OPTIMAL,
+                flags: 0,
+            });
+        }
+        if depth_format.is_some() {
+            attachments.push(VkAttachmentDescription {
+                format: depth_format.unwrap(),
+                samples: samples,
+                loadOp: depth_load_op,
+                storeOp: VkAttachmentStoreOp::VK_ATTACHMENT_STORE_OP_STORE,
+                stencilLoadOp: depth_load_op, // Kept between passes for portals, unless cleared
+                stencilStoreOp: VkAttachmentStoreOp::VK_ATTACHMENT_STORE_OP_STORE,
+                initialLayout: depth_initial_layout,
+                finalLayout: VkImageLayout::VK_IMAGE_LAYOUT_DEPTH_STENCIL_ATTACHMENT_OPTIMAL,
+                flags: 0,
+            });
+        }
+        for _ in 0..(if multisampled { colour_attachments } else { 0 }) {
+            // Every sample is written on resolve, so the previous contents are not needed
+            attachments.push(VkAttachmentDescription {
+                format: colour_format,
+                samples: VkSampleCountFlagBits::VK_SAMPLE_COUNT_1_BIT,
+                loadOp: VkAttachmentLoadOp::VK_ATTACHMENT_LOAD_OP_DONT_CARE,
+                storeOp: VkAttachmentStoreOp::VK_ATTACHMENT_STORE_OP_STORE,
+                stencilLoadOp: VkAttachmentLoadOp::VK_ATTACHMENT_LOAD_OP_DONT_CARE,
+                stencilStoreOp: VkAttachmentStoreOp::VK_ATTACHMENT_STORE_OP_DONT_CARE,
+                initialLayout: VkImageLayout::VK_IMAGE_LAYOUT_UNDEFINED,
+                finalLayout: VkImageLayout::VK_IMAGE_LAYOUT_COLOR_ATTACHMENT_OPTIMAL,
+                flags: 0,
+            });
+        }
+
+        let render_pass_info = VkRenderPassCreateInfo {
+            sType: VkStructureType::VK_STRUCTURE_TYPE_RENDER_PASS_CREATE_INFO,
+            attachmentCount: attachments.len() as u32,
+            pAttachments: attachments.as_ptr(),
+            subpassCount: sub_passes.len() as u32,
+            pSubpasses: sub_passes.as_ptr(),
+            dependencyCount: dependencies.len() as u32,
+            pDependencies: dependencies.as_ptr(),
+            flags: 0,
+            pNext: ptr::null(),
+        };
+
+        let mut render_pass: VkRenderPass = VK_NULL_HANDLE_MUT();
+        unsafe {
+            check_result!("vkCreateRenderPass",
+                          vkCreateRenderPass(device.raw, &render_pass_info, ptr::null(), &mut render_pass));
+        }
+
+        RendererVkRenderPass {
+            device: device.raw,
+            raw: render_pass,
+            samples: samples,
+            colour_attachments: colour_attachments,
+            colour_format: colour_format,
+            depth_format: depth_format,
+            load_colour: load_colour,
+            external_dependencies: external_dependencies,
+        }
+    }
+
+    /// Create a variant of the render pass that clears some of its attachments as it begins
+    ///
+    /// device: The logical device
+    /// clear_colour: Whether the colour attachments are cleared
+    /// clear_depth: Whether the depth attachment, if there is one, is cleared
+    fn clearing_variant(&self, device: &RendererVkDevice, clear_colour: bool, clear_depth: bool) -> RendererVkRenderPass {
+        RendererVkRenderPass::new(device,
+                                  self.colour_format,
+                                  self.colour_attachments,
+                                  self.depth_format,
+                                  self.samples,
+                                  self.load_colour,
+                                  self.external_dependencies,
+                                  clear_colour,
+                                  clear_depth && self.depth_format.is_some())
+    }
+
+    /// Begin a render pass
+    ///
+    /// The clear values are only used by attachments the render pass clears.
+    ///
+    /// raw_command_buffer: The command buffer to record into
+    /// raw_framebuffer: The framebuffer to render to
+    /// width: The width of the area rendered
+    /// height: The height of the area rendered
+    /// clear: The values to clear to
+    pub fn begin(&self,
+                 raw_command_buffer: VkCommandBuffer,
+                 raw_framebuffer: VkFramebuffer,
+                 width: u32,
+                 height: u32,
+                 clear: &PassClear) {
+        // The colour attachments come first, then the depth attachment
+        let mut clear_values = vec![RendererVkClearValue::colour(clear.colour.unwrap_or([0.0f32; 4]));
+                                    self.colour_attachments as usize];
+        if self.depth_format.is_some() {
+            clear_values.push(RendererVkClearValue::depth(clear.depth.unwrap_or(1.0f32)));
+        }
+
+        let render_pass_begin_info = VkRenderPassBeginInfo {
+            sType: VkStructureType::VK_STRUCTURE_TYPE_RENDER_PASS_BEGIN_INFO,
+            renderPass: self.raw,
+            framebuffer: raw_framebuffer,
+            renderArea: VkRect2D {
+                offset: VkOffset2D { x: 0, y: 0 },
+                extent: VkExtent2D {
+                    width: width,
+                    height: height,
+                },
+            },
+            clearValueCount: clear_values.len() as u32,
+            pClearValues: clear_values.as_ptr() as *const VkClearValue,
+            pNext: ptr::null(),
+        };
+
+        unsafe {
+            vkCmdBeginRenderPass(raw_command_buffer,
+                                 &render_pass_begin_info,
+                                 VkSubpassContents::VK_SUBPASS_CONTENTS_INLINE);
+        }
+    }
+
+    /// End render pass
+    ///
+    ///
+    pub fn end(&self, raw_command_buffer: VkCommandBuffer) {
+        unsafe {
+            vkCmdEndRenderPass(raw_command_buffer);
+        }
+    }
+}
+
+impl Drop for RendererVkRenderPass {
+    fn drop(&mut self) {
+        unsafe {
+            vkDestroyRenderPass(self.device, self.raw, ptr::null());
+        }
+    }
+}
+
+pub struct RendererVkFramebuffer {
+    device: VkDevice,
+    raw: VkFramebuffer,
+}
+
+impl RendererVkFramebuffer {
+    /// Return the raw framebuffer handle
+    pub fn get_framebuffer_raw(&self) -> VkFramebuffer {
+        self.raw
+    }
+
+    /// Create a framebuffer for colour image views and a depth image view
+    ///
+    /// There is a colour view for each colour attachment of the render pass.  For a
+    /// multisampled render pass the colour and depth views are the multisampled images, and the
+    /// resolve views are the single-sampled images they are resolved into, in the same order.
+    pub fn new(device: &RendererVkDevice,
+               raw_colour_image_views: &[VkImageView],
+               raw_depth_image_view: Option<VkImageView>,
+               raw_resolve_image_views: &[VkImageView],
+               render_pass: &RendererVkRenderPass,
+               width: u32,
+               height: u32)
+               -> RendererVkFramebuffer {
+        debug_assert!(raw_colour_image_views.len() as u32 == render_pass.get_colour_attachment_count());
+
+        let mut framebuffer: VkFramebuffer = VK_NULL_HANDLE_MUT();
+
+        let mut attachments: Vec<VkImageView> = raw_colour_image_views.to_vec();
+        if raw_depth_image_view.is_some() {
+            attachments.push(raw_depth_image_view.unwrap());
+        }
+        attachments.extend_from_slice(raw_resolve_image_views);
+
+        let framebuffer_info = VkFramebufferCreateInfo {
+            sType: VkStructureType::VK_STRUCTURE_TYPE_FRAMEBUFFER_CREATE_INFO,
+            renderPass: render_pass.raw,
+            attachmentCount: attachments.len() as u32,
+            pAttachments: attachments.as_ptr(),
+            width: width,
+            height: height,
+            layers: 1,
+            flags: 0,
+            pNext: ptr::null(),
+        };
+
+        unsafe {
+            check_result!("vkCreateFramebuffer",
+                          vkCreateFramebuffer(device.raw,
+                                              &framebuffer_info,
+                                              ptr::null_mut(),
+                                              &mut framebuffer));
+        }
+
+        RendererVkFramebuffer {
+            device: device.raw,
+            raw: framebuffer,
+        }
+    }
+}
+
+impl Drop for RendererVkFramebuffer {
+    fn drop(&mut self) {
+        unsafe {
+            vkDestroyFramebuffer(self.device, self.raw, ptr::null());
+        }
+    }
+}
+
+pub struct RendererVkShaderModule {
+    device: VkDevice,
+    raw: VkShaderModule,
+}
+
+impl RendererVkShaderModule {
+    /// Create a shader module
+    ///
+    /// device: The Vulkan device
+    /// binary: The shader SPIR-V bytecode
+    pub fn new(raw_device: VkDevice, binary: &Vec<u8>) -> RendererVkShaderModule {
+        let create_info = VkShaderModuleCreateInfo {
+            sType: VkStructureType::VK_STRUCTURE_TYPE_SHADER_MODULE_CREATE_INFO,
+            codeSize: binary.len(), // codeSize is the length in bytes
+            pCode: binary.as_ptr() as *const u32,
+            flags: 0,
+            pNext: ptr::null(),
+        };
+        let mut shader_module: VkShaderModule = VK_NULL_HANDLE_MUT();
+        unsafe {
+            check_result!("vkCreateShaderModule",
+                          vkCreateShaderModule(raw_device, &create_info, ptr::null(), &mut shader_module));
+        }
+
+        RendererVkShaderModule {
+            device: raw_device,
+            raw: shader_module,
+        }
+    }
+
+    pub fn get_raw(&self) -> VkShaderModule {
+        self.raw
+    }
+}
+
+impl Drop for RendererVkShaderModule {
+    fn drop(&mut self) {
+        unsafe {
+            vkDestroyShaderModule(self.device, self.raw, ptr::null());
+        }
+    }
+}
+
+pub struct RendererVkVertexBuffer {
+    buffer: RendererVkBuffer,
+    staging: Option<RendererVkBuffer>, // Written by the host in place of the buffer, for device-local storage
+}
+
+impl RendererVkVertexBuffer {
+    /// Create a Vulkan vertex buffer
+    ///
+    /// Device-local storage has a persistent host-visible staging buffer of the same size,
+    /// written by the host and copied into the vertex buffer before the draws are submitted.
+    ///
+    /// device: The logical device
+    /// physical_device: The physical device
+    /// array_type: The vertex layout, which with the triangle count determines the size of the buffer
+    /// triangles: The number of triangles the buffer holds
+    /// storage: The memory to store the vertex data in, which must not be Automatic
+    pub fn new(device: &RendererVkDevice,
+               physical_device: &RendererVkPhysicalDevice,
+               array_type: VertexArrayType,
+               triangles: usize,
+               storage: VertexBufferStorage)
+               -> RendererVkVertexBuffer {
+        let size = VertexArrayType::components_per_vertex(array_type) * mem::size_of::<f32>() * 3 * triangles;
+
+        let host_properties = VkMemoryPropertyFlagBits::VK_MEMORY_PROPERTY_HOST_VISIBLE_BIT as VkMemoryPropertyFlags |
+                              VkMemoryPropertyFlagBits::VK_MEMORY_PROPERTY_HOST_COHERENT_BIT as VkMemoryPropertyFlags;
+        let device_local = VkMemoryPropertyFlagBits::VK_MEMORY_PROPERTY_DEVICE_LOCAL_BIT as VkMemoryPropertyFlags;
+        let vertex_usage = VkBufferUsageFlagBits::VK_BUFFER_USAGE_VERTEX_BUFFER_BIT as VkBufferUsageFlags;
+        let staging_usage = VkBufferUsageFlagBits::VK_BUFFER_USAGE_TRANSFER_SRC_BIT as VkBufferUsageFlags;
+
+        match storage {
+            VertexBufferStorage::DeviceLocal => {
+                RendererVkVertexBuffer {
+                    buffer: RendererVkBuffer::new(device,
+                                                  physical_device,
+                                                  vertex_usage |
+                                                  VkBufferUsageFlagBits::VK_BUFFER_USAGE_TRANSFER_DST_BIT as VkBufferUsageFlags,
+                                                  device_local,
+                                                  size),
+                    staging: Some(RendererVkBuffer::new(device,
+                                                        physical_device,
+                                                        staging_usage,
+                                                        host_properties,
+                                                        size)),
+                }
+            }
+            _ => {
+                // Host-visible memory that is also device-local is the best of both, where it exists
+                let properties = if physical_device.has_host_visible_device_local_memory() {
+                    host_properties | device_local
+                } else {
+                    host_properties
+                };
+
+                RendererVkVertexBuffer {
+                    buffer: RendererVkBuffer::new(device, physical_device, vertex_usage, properties, size),
+                    staging: None,
+                }
+            }
+        }
+    }
+
+    /// Return the mapped memory the host writes the vertex data to
+    fn host_memory(&self) -> RendererVkMappedMemory {
+        match self.staging {
+            Some(ref staging) => staging.mapped(),
+            None => self.buffer.mapped(),
+        }
+    }
+}
+
+pub struct RendererVkMesh {
+    vertex_buffer: RendererVkBuffer,
+    index_buffer: Option<RendererVkBuffer>,
+    vertex_array_type: VertexArrayType,
+    draw_count: u32, // Vertices or indices
+}
+
+impl RendererVkMesh {
+    /// Create a mesh in device-local memory, uploading its data through staging buffers
+    ///
+    /// The upload is waited on, after which the staging buffers are destroyed.  Where there is a
+    /// dedicated transfer queue the buffers are shared between it and the graphics queue, so that
+    /// defragmentation can move them there.
+    ///
+    /// device: The logical device
+    /// physical_device: The physical device
+    /// command_pool: The command pool to record the upload from
+    /// data: The vertices and optional indices of the mesh, which must be valid
+    pub fn new(device: &RendererVkDevice,
+               physical_device: &RendererVkPhysicalDevice,
+               command_pool: &RendererVkCommandPool,
+               data: &MeshData)
+               -> RendererVkMesh {
+        let host_properties = VkMemoryPropertyFlagBits::VK_MEMORY_PROPERTY_HOST_VISIBLE_BIT as VkMemoryPropertyFlags |
+                              VkMemoryPropertyFlagBits::VK_MEMORY_PROPERTY_HOST_COHERENT_BIT as VkMemoryPropertyFlags;
+        let device_local = VkMemoryPropertyFlagBits::VK_MEMORY_PROPERTY_DEVICE_LOCAL_BIT as VkMemoryPropertyFlags;
+        let staging_usage = VkBufferUsageFlagBits::VK_BUFFER_USAGE_TRANSFER_SRC_BIT as VkBufferUsageFlags;
+        let transfer = VkBufferUsageFlagBits::VK_BUFFER_USAGE_TRANSFER_SRC_BIT as VkBufferUsageFlags |
+                       VkBufferUsageFlagBits::VK_BUFFER_USAGE_TRANSFER_DST_BIT as VkBufferUsageFlags;
+        let vertex_usage = VkBufferUsageFlagBits::VK_BUFFER_USAGE_VERTEX_BUFFER_BIT as VkBufferUsageFlags | transfer;
+        let index_usage = VkBufferUsageFlagBits::VK_BUFFER_USAGE_INDEX_BUFFER_BIT as VkBufferUsageFlags | transfer;
+        let queue_family_indices = if device.has_transfer_queue() {
+            vec![device.graphics_queue_family_index, device.transfer_queue_family_index]
+        } else {
+            vec![]
+        };
+
+        let vertex_bytes = data.vertex_bytes();
+        let vertex_buffer = RendererVkBuffer::new_shared(device,
+                                                         physical_device,
+                                                         vertex_usage,
+                                                         device_local,
+                                                         vertex_bytes,
+                                                         &queue_family_indices);
+        let vertex_staging = RendererVkBuffer::new(device, physical_device, staging_usage, host_properties, vertex_bytes);
+        vertex_staging.mapped().write_floats(&data.vertices);
+
+        let mut copies = vec![(vertex_staging, vertex_buffer.raw, vertex_bytes)];
+
+        let index_buffer = match data.indices {
+            Some(ref indices) => {
+                let index_bytes = data.index_bytes();
+                let index_buffer = RendererVkBuffer::new_shared(device,
+                                                                physical_device,
+                                                                index_usage,
+                                                                device_local,
+                                                                index_bytes,
+                                                                &queue_family_indices);
+                let index_staging = RendererVkBuffer::new(device, physical_device, staging_usage, host_properties, index_bytes);
+                let mapped = index_staging.mapped();
+                unsafe {
+                    ptr::copy_nonoverlapping(indices.as_ptr(), mapped.as_ptr() as *mut u32, indices.len()); // Words
+                }
+                mapped.flush();
+
+                copies.push((index_staging, index_buffer.raw, index_bytes));
+                Some(index_buffer)
+            }
+            None => None,
+        };
+
+        let mut one_time = OneTimeCommandBuffer::new(device, command_pool);
+        for &(ref staging, buffer, size) in copies.iter() {
+            let region = VkBufferCopy {
+                srcOffset: 0,
+                dstOffset: 0,
+                size: size as VkDeviceSize,
+            };
+            unsafe {
+                vkCmdCopyBuffer(one_time.buffer.raw, staging.raw, buffer, 1 /* Region count */, &region);
+            }
+        }
+        one_time.buffer.memory_barrier(VkAccessFlagBits::VK_ACCESS_TRANSFER_WRITE_BIT as VkAccessFlags,
+                                       VkAccessFlagBits::VK_ACCESS_VERTEX_ATTRIBUTE_READ_BIT as VkAccessFlags |
+                                       VkAccessFlagBits::VK_ACCESS_INDEX_READ_BIT as VkAccessFlags,
+                                       VkPipelineStageFlagBits::VK_PIPELINE_STAGE_TRANSFER_BIT as VkPipelineStageFlags,
+                                       VkPipelineStageFlagBits::VK_PIPELINE_STAGE_VERTEX_INPUT_BIT as VkPipelineStageFlags);
+        one_time.execute();
+
+        RendererVkMesh {
+            vertex_buffer: vertex_buffer,
+            index_buffer: index_buffer,
+            vertex_array_type: data.vertex_array_type,
+            draw_count: data.draw_count() as u32,
+        }
+    }
+
+    /// Return the size of the buffers of the mesh in bytes
+    fn bytes(&self) -> usize {
+        self.vertex_buffer.size + self.index_buffer.as_ref().map_or(0, |b| b.size)
+    }
+
+    /// Count the moves of the buffers of a relocated mesh, once it has replaced the original
+    ///
+    /// device: The logical device the mesh was created on
+    fn commit_relocation(&self, device: &RendererVkDevice) {
+        self.vertex_buffer.commit_relocation(device);
+        if let Some(ref index_buffer) = self.index_buffer {
+            index_buffer.commit_relocation(device);
+        }
+    }
+
+    /// Start moving the mesh into fuller memory blocks on the transfer queue, returning None if
+    /// its buffers have nowhere better to go or the move cannot be started
+    ///
+    /// Both buffers move or neither does.  The mesh itself is untouched, and keeps being drawn
+    /// from until the move completes and the moved copy replaces it.
+    ///
+    /// renderer: The renderer object
+    /// handle: The handle of the mesh
+    fn relocate(&self, renderer: &RendererVk, handle: MeshHandle) -> Option<RendererVkMeshMove> {
+        let vertex_buffer = match self.vertex_buffer.relocated(&renderer.device) {
+            Some(buffer) => buffer,
+            None => return None,
+        };
+        let index_buffer = match self.index_buffer {
+            Some(ref index_buffer) => {
+                match index_buffer.relocated(&renderer.device) {
+                    Some(buffer) => Some(buffer),
+                    None => return None,
+                }
+            }
+            None => None,
+        };
+
+        // Without a fence the move is abandoned, dropping the buffers reserved for it
+        //
+        let fence = match RendererVkFence::new(&renderer.device, false /* signalled */) {
+            Ok(f) => f,
+            Err(e) => {
+                log_warning!("Unable to create mesh move fence: {}, leaving the mesh in place", e);
+                return None;
+            }
+        };
+
+        let command_buffer = RendererVkCommandBuffer::new(&renderer.device, &renderer.transfer_command_pool, true /* primary */);
+        command_buffer.begin_primary(true, // one_time_submit
+                                     false, // render_pass_continue
+                                     false /* simultaneous_use */);
+
+        let mut copies = vec![(self.vertex_buffer.raw, vertex_buffer.raw, vertex_buffer.size)];
+        if let (&Some(ref from), &Some(ref to)) = (&self.index_buffer, &index_buffer) {
+            copies.push((from.raw, to.raw, to.size));
+        }
+        for &(from, to, size) in copies.iter() {
+            let region = VkBufferCopy {
+                srcOffset: 0,
+                dstOffset: 0,
+                size: size as VkDeviceSize,
+            };
+            unsafe {
+                vkCmdCopyBuffer(command_buffer.raw, from, to, 1 /* Region count */, &region);
+            }
+        }
+
+        command_buffer.end();
+
+        // As with texture uploads, the fence makes the copy visible to the submissions made
+        // after it is seen to have signalled
+        //
+        let command_buffers = vec![command_buffer.raw];
+        let submit_info = VkSubmitInfo {
+            sType: VkStructureType::VK_STRUCTURE_TYPE_SUBMIT_INFO,
+            commandBufferCount: command_buffers.len() as u32,
+            pCommandBuffers: command_buffers.as_ptr(),
+            waitSemaphoreCount: 0,
+            pWaitSemaphores: ptr::null(),
+            pWaitDstStageMask: ptr::null(),
+            signalSemaphoreCount: 0,
+            pSignalSemaphores: ptr::null(),
+            pNext: ptr::null(),
+        };
+
+        unsafe {
+            check_result!("vkQueueSubmit",
+                          vkQueueSubmit(renderer.device.transfer_queue, 1, &submit_info, fence.raw));
+        }
+
+        Some(RendererVkMeshMove {
+            device: renderer.device.raw,
+            command_pool: renderer.transfer_command_pool.raw,
+            command_buffer: command_buffer,
+            fence: fence,
+            handle: handle,
+            mesh: Some(RendererVkMesh {
+                vertex_buffer: vertex_buffer,
+                index_buffer: index_buffer,
+                vertex_array_type: self.vertex_array_type,
+                draw_count: self.draw_count,
+            }),
+        })
+    }
+}
+
+/// A move of a mesh into fuller memory blocks in flight on the transfer queue
+///
+/// Once the copy is complete the moved mesh replaces the original under the same handle, and
+/// the original is retired until the frames drawing from it have completed.
+pub struct RendererVkMeshMove {
+    device: VkDevice,
+    command_pool: VkCommandPool,
+    command_buffer: RendererVkCommandBuffer,
+    fence: RendererVkFence,
+    handle: MeshHandle, // Of the mesh being moved
+    mesh: Option<RendererVkMesh>, // The moved copy, taken once the copy is complete
+}
+
+impl RendererVkMeshMove {
+    /// Return whether the copy has completed, without blocking
+    pub fn is_complete(&self) -> bool {
+        self.fence.is_signalled()
+    }
+
+    /// Take the moved mesh, waiting for the copy to complete first if need be
+    pub fn into_mesh(mut self) -> RendererVkMesh {
+        self.fence.wait();
+        self.mesh.take().unwrap()
+    }
+}
+
+impl Drop for RendererVkMeshMove {
+    fn drop(&mut self) {
+        // Both meshes and the command buffer must outlive the copy
+        self.fence.wait();
+        unsafe {
+            vkFreeCommandBuffers(self.device, self.command_pool, 1, &self.command_buffer.raw);
+        }
+    }
+}
+
+pub struct RendererVkUniformBuffer {
+    buffer: RendererVkBuffer,
+    binding: u32,
+    descriptor_type: VkDescriptorType,
+    bytes: Vec<u8>,
+    offsets: HashMap<&'static str, usize>,
+    strides: HashMap<&'static str, usize>,
+    version: ContentVersion, // Bumped by each write to the bytes
+}
+
+impl RendererVkUniformBuffer {
+    /// Create a Vulkan uniform buffer
+    ///
+    /// The buffer is device-local, with its contents written by vkCmdUpdateBuffer.  Blocks of
+    /// type StorageBuffer are created as storage buffers, so that compute shaders can write them.
+    /// Blocks whose members all have types are laid out by the std140 or std430 rules, and
+    /// others by the offsets they were given.
+    ///
+    ///
+    pub fn new(device: &RendererVkDevice,
+               physical_device: &RendererVkPhysicalDevice,
+               spec: &UniformBlockSpec)
+               -> RendererVkUniformBuffer {
+        let spec = spec.laid_out();
+        let (usage, descriptor_type) = if spec.block_type == UniformType::StorageBuffer {
+            (VkBufferUsageFlagBits::VK_BUFFER_USAGE_STORAGE_BUFFER_BIT as VkBufferUsageFlags,
+             VkDescriptorType::VK_DESCRIPTOR_TYPE_STORAGE_BUFFER)
+        } else {
+            (VkBufferUsageFlagBits::VK_BUFFER_USAGE_UNIFORM_BUFFER_BIT as VkBufferUsageFlags,
+             VkDescriptorType::VK_DESCRIPTOR_TYPE_UNIFORM_BUFFER)
+        };
+        let buffer = RendererVkBuffer::new(device,
+                                           physical_device,
+                                           usage | VkBufferUsageFlagBits::VK_BUFFER_USAGE_TRANSFER_DST_BIT as VkBufferUsageFlags,
+                                           VkMemoryPropertyFlagBits::VK_MEMORY_PROPERTY_DEVICE_LOCAL_BIT as VkMemoryPropertyFlags,
+                                           spec.size);
+
+        let mut bytes = Vec::with_capacity(spec.size);
+        bytes.resize(spec.size, 0);
+        let mut offsets: HashMap<&'static str, usize> = HashMap::new();
+        for uniform in spec.uniforms.iter() {
+            offsets.insert(uniform.name, uniform.offset);
+        }
+        let mut strides: HashMap<&'static str, usize> = HashMap::new();
+        for uniform in spec.uniforms.iter() {
+            strides.insert(uniform.name, uniform.stride);
+        }
+
+        RendererVkUniformBuffer {
+            buffer: buffer,
+            binding: spec.binding,
+            descriptor_type: descriptor_type,
+            bytes: bytes,
+            offsets: offsets,
+            strides: strides,
+            version: ContentVersion::new(),
+        }
+    }
+
+    /// Return whether the contents can be written by shaders, so must be uploaded whenever asked
+    fn is_written_by_shaders(&self) -> bool {
+        self.descriptor_type == VkDescriptorType::VK_DESCRIPTOR_TYPE_STORAGE_BUFFER
+    }
+}
+
+pub struct RendererVkBuffer {
+    device: VkDevice,
+    raw: VkBuffer,
+    allocator: Arc<Mutex<MemoryAllocator<RendererVkMemoryBlock>>>,
+    allocation: MemoryAllocation<RendererVkMemoryBlock>,
+    memory_usage: Arc<Mutex<MemoryUsageTracker>>,
+    size: usize, // Size of requested buffer: actual allocation may be bigger
+    mapped: Option<RendererVkMappedMemory>, // Mapped for the lifetime of host-visible buffers
+    usage_bits: VkBufferUsageFlags, // Kept to create the buffer again when it is relocated
+    queue_family_indices: Vec<u32>, // The queue families sharing the buffer, empty if exclusive
+}
+
+/// The persistently mapped memory of a host-visible buffer
+///
+/// This is a copy of the mapping, so that it can be written without holding the buffer.  It must
+/// not outlive the buffer.
+#[derive(Clone, Copy)]
+pub struct RendererVkMappedMemory {
+    device: VkDevice,
+    memory: VkDeviceMemory,
+    pointer: *mut c_void,
+    coherent: bool,
+}
+
+impl RendererVkMappedMemory {
+    /// Return the host address of the start of the memory
+    pub fn as_ptr(&self) -> *mut c_void {
+        self.pointer
+    }
+
+    /// Write floats to the start of the memory
+    ///
+    /// Memory that is not host-coherent has the writes flushed to make them visible to the device.
+    ///
+    /// data: The floats to write
+    pub fn write_floats(&self, data: &[f32]) {
+        unsafe {
+            ptr::copy_nonoverlapping(data.as_ptr(), self.pointer as *mut f32, data.len()); // Words
+        }
+        self.flush();
+    }
+
+    /// Make host writes to the memory visible to the device, where it is not host-coherent
+    pub fn flush(&self) {
+        if !self.coherent {
+            let range = self.whole_range();
+            unsafe {
+                check_result!("vkFlushMappedMemoryRanges",
+                              vkFlushMappedMemoryRanges(self.device, 1, &range));
+            }
+        }
+    }
+
+    /// Make device writes to the memory visible to the host, where it is not host-coherent
+    ///
+    /// The writes must have been made available to the host by a barrier and completed.
+    pub fn invalidate(&self) {
+        if !self.coherent {
+            let range = self.whole_range();
+            unsafe {
+                check_result!("vkInvalidateMappedMemoryRanges",
+                              vkInvalidateMappedMemoryRanges(self.device, 1, &range));
+            }
+        }
+    }
+
+    /// Return the range of the whole block the memory is in, which is aligned to any non-coherent
+    /// atom size
+    fn whole_range(&self) -> VkMappedMemoryRange {
+        VkMappedMemoryRange {
+            sType: VkStructureType::VK_STRUCTURE_TYPE_MAPPED_MEMORY_RANGE,
+            memory: self.memory,
+            offset: 0,
+            size: VK_WHOLE_SIZE as u64,
+            pNext: ptr::null(),
+        }
+    }
+}
+
+impl RendererVkBuffer {
+    /// Create a Vulkan buffer of various types
+    ///
+    ///
+    pub fn new(device: &RendererVkDevice,
+               physical_device: &RendererVkPhysicalDevice,
+               usage_bits: VkBufferUsageFlags,
+               memory_type: VkMemoryPropertyFlags,
+               size: usize)
+               -> RendererVkBuffer {
+        RendererVkBuffer::new_shared(device, physical_device, usage_bits, memory_type, size, &vec![])
+    }
+
+    /// Create a Vulkan buffer, shared between queue families
+    ///
+    /// With more than one queue family the buffer uses concurrent sharing, so that it can be
+    /// used by each without ownership transfers.
+    ///
+    /// queue_family_indices: The queue families that will use the buffer, empty for exclusive use
+    pub fn new_shared(device: &RendererVkDevice,
+                      physical_device: &RendererVkPhysicalDevice,
+                      usage_bits: VkBufferUsageFlags,
+                      memory_type: VkMemoryPropertyFlags,
+                      size: usize,
+                      queue_family_indices: &Vec<u32>)
+                      -> RendererVkBuffer {
+        debug_assert!(size != 0);
+        let buffer = RendererVkBuffer::create_raw(device, usage_bits, size, queue_family_indices);
+
+        let mut memory_requirements = VkMemoryRequirements::default();
+        unsafe {
+            vkGetBufferMemoryRequirements(device.raw, buffer, &mut memory_requirements);
+        }
+
+        let host_coherent = VkMemoryPropertyFlagBits::VK_MEMORY_PROPERTY_HOST_COHERENT_BIT as VkMemoryPropertyFlags;
+
+        let mut memory_type_index = RendererVk::find_suitable_memory(physical_device,
+                                                                     memory_requirements.memoryTypeBits,
+                                                                     memory_type);
+
+        // Host-visible memory that is not coherent will do, with its writes flushed explicitly
+        if memory_type_index == u32::max_value() && (memory_type & host_coherent) != 0 {
+            memory_type_index = RendererVk::find_suitable_memory(physical_device,
+                                                                 memory_requirements.memoryTypeBits,
+                                                                 memory_type & !host_coherent);
+        }
+
+        if memory_type_index == u32::max_value() {
+            panic!("Unable to identify suitable memory for buffer");
+        }
+
+        let allocation = device.allocate_memory(physical_device,
+                                                &memory_requirements,
+                                                memory_type_index,
+                                                MemoryResourceKind::Linear);
+        RendererVkBuffer::bind(device, buffer, allocation, usage_bits, size, queue_family_indices)
+    }
+
+    /// Create a buffer again in a fuller memory block of its pool, returning None if none has room
+    ///
+    /// The contents are not copied, and the original keeps its memory until it is dropped, which
+    /// must wait until the copy and any frames using the original have completed.  See
+    /// MemoryAllocator::relocate.
+    ///
+    /// device: The logical device the buffer was created on
+    pub fn relocated(&self, device: &RendererVkDevice) -> Option<RendererVkBuffer> {
+        let allocation = match device.allocator.lock().unwrap().relocate(&self.allocation) {
+            Some(allocation) => allocation,
+            None => return None,
+        };
+        let buffer = RendererVkBuffer::create_raw(device, self.usage_bits, self.size, &self.queue_family_indices);
+        Some(RendererVkBuffer::bind(device, buffer, allocation, self.usage_bits, self.size, &self.queue_family_indices))
+    }
+
+    /// Count the move of a buffer created by relocated in the allocator's statistics
+    ///
+    /// This is called once the buffer has replaced the original.
+    ///
+    /// device: The logical device the buffer was created on
+    pub fn commit_relocation(&self, device: &RendererVkDevice) {
+        device.allocator.lock().unwrap().commit_relocation(&self.allocation);
+    }
+
+    /// Create a buffer object without memory
+    ///
+    /// device: The logical device
+    /// usage_bits: The usage of the buffer
+    /// size: The size of the buffer in bytes
+    /// queue_family_indices: The queue families that will use the buffer, empty for exclusive use
+    fn create_raw(device: &RendererVkDevice,
+                  usage_bits: VkBufferUsageFlags,
+                  size: usize,
+                  queue_family_indices: &Vec<u32>)
+                  -> VkBuffer {
+        let shared = queue_family_indices.len() > 1;
+        let buffer_info = VkBufferCreateInfo {
+            sType: VkStructureType::VK_STRUCTURE_TYPE_BUFFER_CREATE_INFO,
+            size: size as u64,
+            usage: usage_bits as u32,
+            sharingMode: if shared {
+                VkSharingMode::VK_SHARING_MODE_CONCURRENT
+            } else {
+                VkSharingMode::VK_SHARING_MODE_EXCLUSIVE
+            },
+            queueFamilyIndexCount: if shared { queue_family_indices.len() as u32 } else { 0 },
+            pQueueFamilyIndices: if shared { queue_family_indices.as_ptr() } else { ptr::null() },
+            flags: 0,
+            pNext: ptr::null_mut(),
+        };
+
+        let mut buffer: VkBuffer = VK_NULL_HANDLE_MUT();
+        unsafe {
+            check_result!("vkCreateBuffer",
+                          vkCreateBuffer(device.raw, &buffer_info, ptr::null(), &mut buffer));
+        }
+        buffer
+    }
+
+    /// Bind a buffer object to its memory, mapping it if it is host-visible
+    ///
+    /// device: The logical device
+    /// buffer: The buffer object, from create_raw
+    /// allocation: The memory to bind
+    /// usage_bits: The usage the buffer was created with
+    /// size: The size the buffer was created with
+    /// queue_family_indices: The queue families the buffer was created for
+    fn bind(device: &RendererVkDevice,
+            buffer: VkBuffer,
+            allocation: MemoryAllocation<RendererVkMemoryBlock>,
+            usage_bits: VkBufferUsageFlags,
+            size: usize,
+            queue_family_indices: &Vec<u32>)
+            -> RendererVkBuffer {
+        unsafe {
+            check_result!("vkBindBufferMemory",
+                          vkBindBufferMemory(device.raw, buffer, allocation.memory.memory, allocation.offset));
+        }
+
+        // Host-visible memory is mapped once with its block, rather than around each access
+        //
+        let mapped = if allocation.memory.pointer.is_null() {
+            None
+        } else {
+            Some(RendererVkMappedMemory {
+                device: device.raw,
+                memory: allocation.memory.memory,
+                pointer: RendererVkMemoryBlock::mapped_pointer(&allocation),
+                coherent: allocation.memory.coherent,
+            })
+        };
+
+        device.memory_usage.lock().unwrap().record((VK_OBJECT_TYPE_BUFFER, buffer as u64),
+                                                   RendererVkBuffer::memory_category(usage_bits),
+                                                   allocation.size);
+
+        RendererVkBuffer {
+            device: device.raw,
+            raw: buffer,
+            allocator: device.allocator.clone(),
+            allocation: allocation,
+            memory_usage: device.memory_usage.clone(),
+            size: size,
+            mapped: mapped,
+            usage_bits: usage_bits,
+            queue_family_indices: queue_family_indices.clone(),
+        }
+    }
+
+    /// Return what the memory of a buffer is used for, from its usage
+    ///
+    /// usage_bits: The usage the buffer was created with
+    fn memory_category(usage_bits: VkBufferUsageFlags) -> MemoryCategory {
+        let has_usage = |bit: VkBufferUsageFlagBits| (usage_bits as u32) & (bit as u32) != 0;
+        if has_usage(VkBufferUsageFlagBits::VK_BUFFER_USAGE_VERTEX_BUFFER_BIT) ||
+           has_usage(VkBufferUsageFlagBits::VK_BUFFER_USAGE_INDEX_BUFFER_BIT) {
+            MemoryCategory::VertexBuffers
+        } else if has_usage(VkBufferUsageFlagBits::VK_BUFFER_USAGE_UNIFORM_BUFFER_BIT) {
+            MemoryCategory::Uniforms
+        } else if has_usage(VkBufferUsageFlagBits::VK_BUFFER_USAGE_STORAGE_BUFFER_BIT) {
+            MemoryCategory::StorageBuffers
+        } else {
+            MemoryCategory::Staging
+        }
+    }
+
+    /// Return the mapping of the buffer, which must be host-visible
+    pub fn mapped(&self) -> RendererVkMappedMemory {
+        match self.mapped {
+            Some(mapped) => mapped,
+            None => panic!("The buffer is not host-visible"),
+        }
+    }
+}
+
+impl Drop for RendererVkBuffer {
+    fn drop(&mut self) {
+        unsafe {
+            vkDestroyBuffer(self.device, self.raw, ptr::null());
+        }
+        RendererVkMemoryBlock::free(self.device, &self.allocator, &self.allocation);
+        self.memory_usage.lock().unwrap().release((VK_OBJECT_TYPE_BUFFER, self.raw as u64));
+    }
+}
+
+pub struct RendererVkDescriptorSetLayouts {
+    device: VkDevice,
+    raw: Vec<VkDescriptorSetLayout>,
+}
+
+impl RendererVkDescriptorSetLayouts {
+    /// Create a set of descriptor set layouts for a given shader
+    ///
+    ///
+    pub fn new(device: &RendererVkDevice,
+               resource_manager: &ResourceManager,
+               shader_spec: &ShaderSpec)
+               -> RendererVkDescriptorSetLayouts {
+        let max_set = RendererVkDescriptorSetLayouts::number_of_sets(resource_manager, shader_spec);
+
+        // Then iterate through them all and identify the elements belonging to each set
+        //
+        let mut descriptor_set_layouts: Vec<VkDescriptorSetLayout> = vec![];
+        for for_set in 0..(max_set + 1) {
+            let mut set_layout_bindings: Vec<VkDescriptorSetLayoutBinding> = vec![];
+
+            for block_name in shader_spec.uniform_block_names.iter() {
+                let ref block = resource_manager.uniform_block_specs[block_name];
+
+                if block.set == for_set {
+                    // println!("Creating descriptor set layout binding for uniform block {} at {}.{}",
+                    //          block_name,
+                    //          block.set,
+                    //          block.binding);
+
+                    set_layout_bindings.push(VkDescriptorSetLayoutBinding {
+                        descriptorType: RendererVkDescriptorSetLayouts::internal_descriptor_type(block.block_type),
+                        // stageFlags set to 'all' is overkill, but does it harm performance?
+                        stageFlags: VkShaderStageFlagBits::VK_SHADER_STAGE_ALL as VkShaderStageFlags,
+                        binding: block.binding,
+                        descriptorCount: 1,
+                        pImmutableSamplers: ptr::null(),
+                    });
+                }
+            }
+
+            for uniform in shader_spec.uniform_specs.iter() {
+                if uniform.set == for_set && uniform.binding != u32::max_value() {
+                    // println!("Creating descriptor set layout binding for uniform {} at {}.{}",
+                    //          uniform.name,
+                    //          uniform.set,
+                    //          uniform.binding);
+
+                    set_layout_bindings.push(VkDescriptorSetLayoutBinding {
+                        descriptorType: RendererVkDescriptorSetLayouts::internal_descriptor_type(uniform.uniform_type),
+                        // stageFlags set to 'all' is overkill, but does it harm performance?
+                        stageFlags: VkShaderStageFlagBits::VK_SHADER_STAGE_ALL as VkShaderStageFlags,
+                        binding: uniform.binding,
+                        descriptorCount: 1,
+                        pImmutableSamplers: ptr::null(), // Optional
+                    });
+                }
+            }
+
+            // If there were items in the set, create a descriptor set layout object
+            //
+            if set_layout_bindings.len() > 0 {
+                let set_layout_create_info = VkDescriptorSetLayoutCreateInfo {
+                    sType: VkStructureType::VK_STRUCTURE_TYPE_DESCRIPTOR_SET_LAYOUT_CREATE_INFO,
+                    bindingCount: set_layout_bindings.len() as u32,
+                    pBindings: set_layout_bindings.as_ptr(),
+                    flags: 0,
+                    pNext: ptr::null(),
+                };
+
+                let mut descriptor_set_layout: VkDescriptorSetLayout = VK_NULL_HANDLE_MUT();
+                unsafe {
+                    check_result!("vkCreateDescriptorSetLayout",
+                                  vkCreateDescriptorSetLayout(device.raw,
+                                                              &set_layout_create_info,
+                                                              ptr::null(), // Allocator
+                                                              &mut descriptor_set_layout));
+                }
+
+                descriptor_set_layouts.push(descriptor_set_layout);
+            }
+        }
+
+        RendererVkDescriptorSetLayouts {
+            device: device.raw,
+            raw: descriptor_set_layouts,
+        }
+    }
+
+    /// Convert a resources general descriptor type to a Vulkan descriptor type
+    ///
+    ///
+    pub fn internal_descriptor_type(descriptor_type: UniformType) -> VkDescriptorType {
+        match descriptor_type {
+            UniformType::Sampler => VkDescriptorType::VK_DESCRIPTOR_TYPE_SAMPLER,
+            UniformType::CombinedImageSampler => VkDescriptorType::VK_DESCRIPTOR_TYPE_COMBINED_IMAGE_SAMPLER,
+            UniformType::SampledImage => VkDescriptorType::VK_DESCRIPTOR_TYPE_SAMPLED_IMAGE,
+            UniformType::StorageImage => VkDescriptorType::VK_DESCRIPTOR_TYPE_STORAGE_IMAGE,
+            UniformType::UniformTexelBuffer => VkDescriptorType::VK_DESCRIPTOR_TYPE_UNIFORM_TEXEL_BUFFER,
+            UniformType::StorageTexelBuffer => VkDescriptorType::VK_DESCRIPTOR_TYPE_STORAGE_TEXEL_BUFFER,
+            UniformType::UniformBuffer => VkDescriptorType::VK_DESCRIPTOR_TYPE_UNIFORM_BUFFER,
+            UniformType::StorageBuffer => VkDescriptorType::VK_DESCRIPTOR_TYPE_STORAGE_BUFFER,
+            UniformType::UniformBufferDynamic => VkDescriptorType::VK_DESCRIPTOR_TYPE_UNIFORM_BUFFER_DYNAMIC,
+            UniformType::StorageBufferDynamic => VkDescriptorType::VK_DESCRIPTOR_TYPE_STORAGE_BUFFER_DYNAMIC,
+            UniformType::InputAttachment => VkDescriptorType::VK_DESCRIPTOR_TYPE_INPUT_ATTACHMENT,
+            UniformType::RangeSize => VkDescriptorType::VK_DESCRIPTOR_TYPE_RANGE_SIZE,
+        }
+    }
+
+    /// Find out the number of sets
+    ///
+    ///
+    pub fn number_of_sets(resource_manager: &ResourceManager, shader_spec: &ShaderSpec) -> u32 {
+        let mut max_set: u32 = 0;
+
+        for block_name in shader_spec.uniform_block_names.iter() {
+            let ref block = resource_manager.uniform_block_specs[block_name];
+            if block.set > max_set {
+                max_set = block.set;
+            }
+        }
+
+        for uniform in shader_spec.uniform_specs.iter() {
+            if uniform.set > max_set {
+                max_set = uniform.set;
+            }
+        }
+
+        max_set
+    }
+}
+
+impl Drop for RendererVkDescriptorSetLayouts {
+    fn drop(&mut self) {
+        for dsl in self.raw.iter() {
+            unsafe {
+                vkDestroyDescriptorSetLayout(self.device, *dsl, ptr::null());
+            }
+        }
+    }
+}
+
+pub struct RendererVkDescriptorPool {
+    device: VkDevice,
+    raw: VkDescriptorPool,
+}
+
+impl RendererVkDescriptorPool {
+    /// Create a Vulkan descriptor pool
+    ///
+    ///
+    pub fn new(device: &RendererVkDevice,
+               max_uniform_buffers: usize,
+               max_storage_buffers: usize,
+               max_combined_image_samplers: usize,
+               max_sets: usize)
+               -> RendererVkDescriptorPool {
+        let uniform_buffer_pool_size = VkDescriptorPoolSize {
+            type_: VkDescriptorType::VK_DESCRIPTOR_TYPE_UNIFORM_BUFFER,
+            descriptorCount: max_uniform_buffers as u32,
+        };
+
+        let storage_buffer_pool_size = VkDescriptorPoolSize {
+            type_: VkDescriptorType::VK_DESCRIPTOR_TYPE_STORAGE_BUFFER,
+            descriptorCount: max_storage_buffers as u32,
+        };
+
+        let combined_image_samplers_pool_size = VkDescriptorPoolSize {
+            type_: VkDescriptorType::VK_DESCRIPTOR_TYPE_COMBINED_IMAGE_SAMPLER,
+            descriptorCount: max_combined_image_samplers as u32,
+        };
+
+        let buffer_pool_sizes = vec![uniform_buffer_pool_size, storage_buffer_pool_size, combined_image_samplers_pool_size];
+
+        let pool_info = VkDescriptorPoolCreateInfo {
+            sType: VkStructureType::VK_STRUCTURE_TYPE_DESCRIPTOR_POOL_CREATE_INFO,
+            poolSizeCount: buffer_pool_sizes.len() as u32,
+            pPoolSizes: buffer_pool_sizes.as_ptr(),
+            maxSets: max_sets as u32,
+            flags: 0,
+            pNext: ptr::null(),
+        };
+
+        let mut descriptor_pool: VkDescriptorPool = VK_NULL_HANDLE_MUT();
+        unsafe {
+            check_result!("vkCreateDescriptorPool",
+                          vkCreateDescriptorPool(device.raw, &pool_info, ptr::null(), &mut descriptor_pool));
+        }
+
+        RendererVkDescriptorPool {
+            device: device.raw,
+            raw: descriptor_pool,
+        }
+    }
+
+    /// Return every descriptor set allocated from the pool to it
+    pub fn reset(&self) {
+        unsafe {
+            check_result!("vkResetDescriptorPool",
+                          vkResetDescriptorPool(self.device, self.raw, 0 /* flags */));
+        }
+    }
+}
+
+impl Drop for RendererVkDescriptorPool {
+    fn drop(&mut self) {
+        unsafe {
+            vkDestroyDescriptorPool(self.device, self.raw, ptr::null());
+        }
+    }
+}
+
+// Note: There is no Drop implementation for RendererVkDescriptorSet as
+// VkDescriptorSet objects are destroyed when the VkDescriptorPool they belong
+// to is destroyed
+pub struct RendererVkDescriptorSet {
+    raw: VkDescriptorSet,
+}
+
+impl RendererVkDescriptorSet {
+    /// Create a Vulkan descriptor set
+    ///
+    ///
+    pub fn new(device: &RendererVkDevice,
+               descriptor_pool: &RendererVkDescriptorPool,
+               descriptor_set_layouts: &RendererVkDescriptorSetLayouts,
+               resource: &ShaderSpec,
+               uniform_buffers: &HashMap<&'static str, RendererVkUniformBuffer>,
+               textures: &HashMap<&'static str, &TextureVk>)
+               -> RendererVkDescriptorSet {
+        let alloc_info = VkDescriptorSetAllocateInfo {
+            sType: VkStructureType::VK_STRUCTURE_TYPE_DESCRIPTOR_SET_ALLOCATE_INFO,
+            descriptorPool: descriptor_pool.raw,
+            descriptorSetCount: descriptor_set_layouts.raw.len() as u32,
+            pSetLayouts: descriptor_set_layouts.raw.as_ptr(),
+            pNext: ptr::null(),
+        };
+
+        let mut descriptor_set: VkDescriptorSet = VK_NULL_HANDLE_MUT();
+        unsafe {
+            check_result!("vkAllocateDescriptorSets",
+                          vkAllocateDescriptorSets(device.raw, &alloc_info, &mut descriptor_set));
+        }
+
+        // Iterate through all the uniform blocks and generate descriptor set writes for each
+        //
+        let n = resource.uniform_block_names.len();
+        let mut buffer_infos = Vec::with_capacity(n);
+        let mut descriptor_writes = Vec::with_capacity(n);
+        let mut i = 0;
+        for uniform_block_name in resource.uniform_block_names.iter() {
+            let ref uniform_buffer = uniform_buffers[uniform_block_name];
+
+            buffer_infos.push(VkDescriptorBufferInfo {
+                buffer: uniform_buffer.buffer.raw,
+                offset: 0,
+                range: uniform_buffer.buffer.size as u64,
+            });
+
+            descriptor_writes.push(VkWriteDescriptorSet {
+                sType: VkStructureType::VK_STRUCTURE_TYPE_WRITE_DESCRIPTOR_SET,
+                dstSet: descriptor_set,
+                dstBinding: uniform_buffer.binding,
+                dstArrayElement: 0,
+                descriptorType: uniform_buffer.descriptor_type,
+                descriptorCount: 1,
+                pBufferInfo: &buffer_infos[i],
+                pImageInfo: ptr::null(), // Optional
+                pTexelBufferView: ptr::null(), // Optional
+                pNext: ptr::null(),
+            });
+
+            i += 1;
+        }
+
+        // Iterate through all the combined image and sampler uniforms and generate descriptor
+        // set writes for each
+        //
+        i = 0;
+        let mut image_infos = vec![];
+        for uniform_spec in resource.uniform_specs.iter() {
+            if uniform_spec.uniform_type == UniformType::CombinedImageSampler && textures.contains_key(uniform_spec.name) {
+                image_infos.push(VkDescriptorImageInfo {
+                    imageLayout: VkImageLayout::VK_IMAGE_LAYOUT_SHADER_READ_ONLY_OPTIMAL,
+                    imageView: textures[uniform_spec.name].texture.view.raw,
+                    sampler: textures[uniform_spec.name].texture.sampler,
+                });
+
+                descriptor_writes.push(VkWriteDescriptorSet {
+                    sType: VkStructureType::VK_STRUCTURE_TYPE_WRITE_DESCRIPTOR_SET,
+                    dstSet: descriptor_set,
+                    dstBinding: uniform_spec.binding,
+                    dstArrayElement: 0,
+                    descriptorType: VkDescriptorType::VK_DESCRIPTOR_TYPE_COMBINED_IMAGE_SAMPLER,
+                    descriptorCount: 1,
+                    pBufferInfo: ptr::null(), // Optional
+                    pImageInfo: &image_infos[i],
+                    pTexelBufferView: ptr::null(), // Optional
+                    pNext: ptr::null(),
+                });
+
+                i += 1;
+            }
+        }
+
+        unsafe {
+            vkUpdateDescriptorSets(device.raw,
+                                   descriptor_writes.len() as u32,
+                                   descriptor_writes.as_ptr(),
+                                   0, // Copy count
+                                   ptr::null() /* Descriptor copies */);
+        }
+
+        RendererVkDescriptorSet { raw: descriptor_set }
+    }
+}
+
+/// The bindings of a graphics shader's descriptor set, for the copies of it made when a thread
+/// switches textures between draws
+pub struct RendererVkDrawBindings {
+    buffers: Vec<u32>, // The bindings of the uniform and storage blocks
+    samplers: Vec<(&'static str, u32)>, // The names and bindings of the combined image samplers
+}
+
+impl RendererVkDrawBindings {
+    /// Collect the bindings of a shader's descriptor set
+    ///
+    /// shader_spec: The specification of the shader
+    /// uniform_buffers: The uniform buffers, with the bindings of the blocks
+    pub fn new(shader_spec: &ShaderSpec,
+               uniform_buffers: &HashMap<&'static str, RendererVkUniformBuffer>)
+               -> RendererVkDrawBindings {
+        RendererVkDrawBindings {
+            buffers: shader_spec.uniform_block_names.iter().map(|name| uniform_buffers[name].binding).collect(),
+            samplers: shader_spec.uniform_specs
+                .iter()
+                .filter(|u| u.uniform_type == UniformType::CombinedImageSampler)
+                .map(|u| (u.name, u.binding))
+                .collect(),
+        }
+    }
+}
+
+/// The textures a thread has switched to for its draws in the current pass
+pub struct RendererVkDrawTextures {
+    set: Option<VkDescriptorSet>, // The copy of the shader's descriptor set with them written, once there is one
+    textures: Vec<(u32, VkDescriptorImageInfo)>, // By binding
+}
+
+impl RendererVkDrawTextures {
+    /// Create the state of a thread that draws with the shader's own textures
+    pub fn new() -> RendererVkDrawTextures {
+        RendererVkDrawTextures {
+            set: None,
+            textures: vec![],
+        }
+    }
+}
+
+/// The bindless texture array shared by the shaders that set bindless_textures
+///
+/// One variable-count array of combined image sampler descriptors is written as textures are
+/// registered.  Descriptors may be written after the set is bound and while frames using other
+/// slots are in flight, and slots that were never written are left unbound.
+pub struct RendererVkBindlessTextures {
+    device: VkDevice,
+    pool: VkDescriptorPool,
+    layout: VkDescriptorSetLayout,
+    empty_layout: VkDescriptorSetLayout, // Fills the sets before BINDLESS_TEXTURE_SET that a shader does not use
+    set: VkDescriptorSet,
+    slots: BindlessSlots,
+}
+
+impl RendererVkBindlessTextures {
+    /// Return whether the physical device offers descriptor indexing
+    ///
+    /// physical_device: The physical device to query
+    fn device_supports(physical_device: &RendererVkPhysicalDevice) -> bool {
+        let names = physical_device.extension_names();
+        names.iter().any(|n| n == "VK_KHR_maintenance3") && names.iter().any(|n| n == "VK_EXT_descriptor_indexing")
+    }
+
+    /// Create the descriptor set of the array on a device created with descriptor indexing
+    ///
+    /// device: The device
+    /// retire_frames: The number of frames a released slot is held back for
+    fn new(device: &RendererVkDevice, retire_frames: u32) -> RendererVkBindlessTextures {
+        let binding_flags: u32 = VK_DESCRIPTOR_BINDING_UPDATE_AFTER_BIND_BIT_EXT |
+                                 VK_DESCRIPTOR_BINDING_UPDATE_UNUSED_WHILE_PENDING_BIT_EXT |
+                                 VK_DESCRIPTOR_BINDING_PARTIALLY_BOUND_BIT_EXT |
+                                 VK_DESCRIPTOR_BINDING_VARIABLE_DESCRIPTOR_COUNT_BIT_EXT;
+        let binding_flags_info = VkDescriptorSetLayoutBindingFlagsCreateInfoEXT {
+            sType: VK_STRUCTURE_TYPE_DESCRIPTOR_SET_LAYOUT_BINDING_FLAGS_CREATE_INFO_EXT,
+            pNext: ptr::null(),
+            bindingCount: 1,
+            pBindingFlags: &binding_flags,
+        };
+        let binding = VkDescriptorSetLayoutBinding {
+            descriptorType: VkDescriptorType::VK_DESCRIPTOR_TYPE_COMBINED_IMAGE_SAMPLER,
+            stageFlags: VkShaderStageFlagBits::VK_SHADER_STAGE_ALL as VkShaderStageFlags,
+            binding: BINDLESS_TEXTURE_BINDING,
+            descriptorCount: BINDLESS_MAX_TEXTURES,
+            pImmutableSamplers: ptr::null(),
+        };
+        let layout_info = VkDescriptorSetLayoutCreateInfo {
+            sType: VkStructureType::VK_STRUCTURE_TYPE_DESCRIPTOR_SET_LAYOUT_CREATE_INFO,
+            bindingCount: 1,
+            pBindings: &binding,
+            flags: VK_DESCRIPTOR_SET_LAYOUT_CREATE_UPDATE_AFTER_BIND_POOL_BIT_EXT as VkDescriptorSetLayoutCreateFlags,
+            pNext: &binding_flags_info as *const VkDescriptorSetLayoutBindingFlagsCreateInfoEXT as *const c_void,
+        };
+        let empty_layout_info = VkDescriptorSetLayoutCreateInfo {
+            sType: VkStructureType::VK_STRUCTURE_TYPE_DESCRIPTOR_SET_LAYOUT_CREATE_INFO,
+            bindingCount: 0,
+            pBindings: ptr::null(),
+            flags: 0,
+            pNext: ptr::null(),
+        };
+
+        let mut layout: VkDescriptorSetLayout = VK_NULL_HANDLE_MUT();
+        let mut empty_layout: VkDescriptorSetLayout = VK_NULL_HANDLE_MUT();
+        unsafe {
+            check_result!("vkCreateDescriptorSetLayout",
+                          vkCreateDescriptorSetLayout(device.raw, &layout_info, ptr::null(), &mut layout));
+            check_result!("vkCreateDescriptorSetLayout",
+                          vkCreateDescriptorSetLayout(device.raw, &empty_layout_info, ptr::null(), &mut empty_layout));
+        }
+
+        let pool_size = VkDescriptorPoolSize {
+            type_: VkDescriptorType::VK_DESCRIPTOR_TYPE_COMBINED_IMAGE_SAMPLER,
+            descriptorCount: BINDLESS_MAX_TEXTURES,
+        };
+        let pool_info = VkDescriptorPoolCreateInfo {
+            sType: VkStructureType::VK_STRUCTURE_TYPE_DESCRIPTOR_POOL_CREATE_INFO,
+            poolSizeCount: 1,
+            pPoolSizes: &pool_size,
+            maxSets: 1,
+            flags: VK_DESCRIPTOR_POOL_CREATE_UPDATE_AFTER_BIND_BIT_EXT as VkDescriptorPoolCreateFlags,
+            pNext: ptr::null(),
+        };
+
+        let mut pool: VkDescriptorPool = VK_NULL_HANDLE_MUT();
+        unsafe {
+            check_result!("vkCreateDescriptorPool",
+                          vkCreateDescriptorPool(device.raw, &pool_info, ptr::null(), &mut pool));
+        }
+
+        let descriptor_count = BINDLESS_MAX_TEXTURES;
+        let variable_count_info = VkDescriptorSetVariableDescriptorCountAllocateInfoEXT {
+            sType: VK_STRUCTURE_TYPE_DESCRIPTOR_SET_VARIABLE_DESCRIPTOR_COUNT_ALLOCATE_INFO_EXT,
+            pNext: ptr::null(),
+            descriptorSetCount: 1,
+            pDescriptorCounts: &descriptor_count,
+        };
+        let alloc_info = VkDescriptorSetAllocateInfo {
+            sType: VkStructureType::VK_STRUCTURE_TYPE_DESCRIPTOR_SET_ALLOCATE_INFO,
+            descriptorPool: pool,
+            descriptorSetCount: 1,
+            pSetLayouts: &layout,
+            pNext: &variable_count_info as *const VkDescriptorSetVariableDescriptorCountAllocateInfoEXT as *const c_void,
+        };
+
+        let mut set: VkDescriptorSet = VK_NULL_HANDLE_MUT();
+        unsafe {
+            check_result!("vkAllocateDescriptorSets",
+                          vkAllocateDescriptorSets(device.raw, &alloc_info, &mut set));
+        }
+
+        RendererVkBindlessTextures {
+            device: device.raw,
+            pool: pool,
+            layout: layout,
+            empty_layout: empty_layout,
+            set: set,
+            slots: BindlessSlots::new(BINDLESS_MAX_TEXTURES, retire_frames),
+        }
+    }
+
+    /// Write a texture into a slot of the array
+    ///
+    /// index: The slot to write
+    /// texture_vk: The texture, which must be in the shader read layout when it is sampled
+    fn write(&self, index: u32, texture_vk: &TextureVk) {
+        let image_info = VkDescriptorImageInfo {
+            imageLayout: VkImageLayout::VK_IMAGE_LAYOUT_SHADER_READ_ONLY_OPTIMAL,
+            imageView: texture_vk.texture.view.raw,
+            sampler: texture_vk.texture.sampler,
+        };
+        let descriptor_write = VkWriteDescriptorSet {
+            sType: VkStructureType::VK_STRUCTURE_TYPE_WRITE_DESCRIPTOR_SET,
+            dstSet: self.set,
+            dstBinding: BINDLESS_TEXTURE_BINDING,
+            dstArrayElement: index,
+            descriptorType: VkDescriptorType::VK_DESCRIPTOR_TYPE_COMBINED_IMAGE_SAMPLER,
+            descriptorCount: 1,
+            pBufferInfo: ptr::null(),
+            pImageInfo: &image_info,
+            pTexelBufferView: ptr::null(),
+            pNext: ptr::null(),
+        };
+
+        unsafe {
+            vkUpdateDescriptorSets(self.device,
+                                   1, // Write count
+                                   &descriptor_write,
+                                   0, // Copy count
+                                   ptr::null() /* Descriptor copies */);
+        }
+    }
+}
+
+impl Drop for RendererVkBindlessTextures {
+    fn drop(&mut self) {
+        unsafe {
+            vkDestroyDescriptorPool(self.device, self.pool, ptr::null());
+            vkDestroyDescriptorSetLayout(self.device, self.layout, ptr::null());
+            vkDestroyDescriptorSetLayout(self.device, self.empty_layout, ptr::null());
+        }
+    }
+}
+
+pub struct RendererVkPipelineLayout {
+    device: VkDevice,
+    raw: VkPipelineLayout,
+    bindless_set: Option<VkDescriptorSet>, // Bound at BINDLESS_TEXTURE_SET for shaders reading the bindless textures
+}
+
+impl RendererVkPipelineLayout {
+    /// Set up pipeline layout with the specified descriptor set layouts
+    ///
+    ///
+    /// The push constant block, if any, is a single range visible to all graphics stages, or
+    /// to the compute stage for a compute shader.  The bindless texture array, when given, is
+    /// appended at BINDLESS_TEXTURE_SET after empty layouts for any sets the shader lacks.
+    ///
+    ///
+    pub fn new(device: &RendererVkDevice,
+               descriptor_set_layouts: &RendererVkDescriptorSetLayouts,
+               bindless_textures: Option<&RendererVkBindlessTextures>,
+               shader_spec: &ShaderSpec)
+               -> RendererVkPipelineLayout {
+        let mut set_layouts = descriptor_set_layouts.raw.clone();
+        if let Some(bindless_textures) = bindless_textures {
+            debug_assert!(set_layouts.len() <= BINDLESS_TEXTURE_SET as usize);
+            while set_layouts.len() < BINDLESS_TEXTURE_SET as usize {
+                set_layouts.push(bindless_textures.empty_layout);
+            }
+            set_layouts.push(bindless_textures.layout);
+        }
+
+        let push_constants_size = shader_spec.push_constants
+            .iter()
+            .map(|push_constant| push_constant.offset + push_constant.size)
+            .max()
+            .unwrap_or(0);
+        let stage_flags = if shader_spec.is_compute() {
+            VkShaderStageFlagBits::VK_SHADER_STAGE_COMPUTE_BIT as VkShaderStageFlags
+        } else {
+            VkShaderStageFlagBits::VK_SHADER_STAGE_ALL_GRAPHICS as VkShaderStageFlags
+        };
+        let push_constant_ranges = if push_constants_size > 0 {
+            vec![VkPushConstantRange {
+                     stageFlags: stage_flags,
+                     offset: 0,
+                     size: push_constants_size as u32,
+                 }]
+        } else {
+            vec![]
+        };
+
+        let pipeline_layout_info = VkPipelineLayoutCreateInfo {
+            sType: VkStructureType::VK_STRUCTURE_TYPE_PIPELINE_LAYOUT_CREATE_INFO,
+            setLayoutCount: set_layouts.len() as u32,
+            pSetLayouts: set_layouts.as_ptr(),
+            pushConstantRangeCount: push_constant_ranges.len() as u32,
+            pPushConstantRanges: push_constant_ranges.as_ptr(),
+            flags: 0,
+            pNext: ptr::null(),
+        };
+
+        let mut pipeline_layout: VkPipelineLayout = VK_NULL_HANDLE_MUT();
+        unsafe {
+            check_result!("vkCreatePipelineLayout",
+                          vkCreatePipelineLayout(device.raw,
+                                                 &pipeline_layout_info,
+                                                 ptr::null(),
+                                                 &mut pipeline_layout));
+        }
+
+        RendererVkPipelineLayout {
+            device: device.raw,
+            raw: pipeline_layout,
+            bindless_set: bindless_textures.map(|bindless_textures| bindless_textures.set),
+        }
+    }
+}
+
+impl Drop for RendererVkPipelineLayout {
+    fn drop(&mut self) {
+        unsafe {
+            vkDestroyPipelineLayout(self.device, self.raw, ptr::null());
+        }
+    }
+}
+
+/// The default number of draws after which a thread submits its commands part way through a pass
+pub const DEFAULT_CHUNK_DRAW_THRESHOLD: usize = 256;
+
+/// The number of draws after which a thread submits its commands when GPU-assisted validation is enabled
+pub const GPU_ASSISTED_VALIDATION_CHUNK_DRAW_THRESHOLD: usize = 64;
+
+/// The default number of bytes of vertex data after which a thread submits its commands part way through a pass
+pub const DEFAULT_CHUNK_BYTE_THRESHOLD: usize = 16 * 1024 * 1024;
+
+/// The number of descriptor sets each thread may copy per frame when it switches textures between draws
+pub const DRAW_DESCRIPTOR_SETS_PER_THREAD: usize = 1024;
+
+/// The size of each buffer of instance data, with larger instanced draws split across several
+pub const INSTANCE_BUFFER_SIZE: usize = 64 * 1024;
+
+/// The default name of the file the pipeline cache is saved to
+pub const DEFAULT_PIPELINE_CACHE_FILENAME: &'static str = "pipeline_cache.bin";
+
+pub struct RendererVkPipelineCache {
+    device: VkDevice,
+    raw: VkPipelineCache,
+}
+
+impl RendererVkPipelineCache {
+    /// Create a pipeline cache, seeded with the data saved by a previous run if available
+    ///
+    /// The driver checks that the data was saved on the same driver and device, and ignores
+    /// it otherwise, so a stale file is harmless.
+    ///
+    /// device: The Vulkan device
+    /// filename: The file holding the saved pipeline cache data
+    pub fn new(device: &RendererVkDevice, filename: &str) -> RendererVkPipelineCache {
+        let initial_data = match read_binary_file(filename, false /* dump */) {
+            Ok(bytes) => bytes,
+            Err(_) => vec![],
+        };
+
+        let create_info = VkPipelineCacheCreateInfo {
+            sType: VkStructureType::VK_STRUCTURE_TYPE_PIPELINE_CACHE_CREATE_INFO,
+            initialDataSize: initial_data.len(),
+            pInitialData: initial_data.as_ptr() as *const c_void,
+            flags: 0,
+            pNext: ptr::null(),
+        };
+
+        let mut pipeline_cache: VkPipelineCache = VK_NULL_HANDLE_MUT();
+        unsafe {
+            check_result!("vkCreatePipelineCache",
+                          vkCreatePipelineCache(device.raw, &create_info, ptr::null(), &mut pipeline_cache));
+        }
+
+        RendererVkPipelineCache {
+            device: device.raw,
+            raw: pipeline_cache,
+        }
+    }
+
+    /// Save the contents of the pipeline cache to disk
+    ///
+    /// A failure to write the file is reported but is not fatal, as the cache is only an
+    /// optimisation.
+    ///
+    /// filename: The file to save the pipeline cache data to
+    pub fn save(&self, filename: &str) {
+        let mut size: usize = 0;
+        unsafe {
+            check_result!("vkGetPipelineCacheData",
+                          vkGetPipelineCacheData(self.device, self.raw, &mut size, ptr::null_mut()));
+        }
+
+        let mut data: Vec<u8> = Vec::with_capacity(size);
+        data.resize(size, 0);
+        unsafe {
+            check_result!("vkGetPipelineCacheData",
+                          vkGetPipelineCacheData(self.device, self.raw, &mut size, data.as_mut_ptr() as *mut c_void));
+        }
+        data.truncate(size);
+
+        match write_binary_file(&data, filename) {
+            Ok(_) => (),
+            Err(e) => log_error!("Failed to save the pipeline cache to {}: {}", filename, e),
+        }
+    }
+}
+
+impl Drop for RendererVkPipelineCache {
+    fn drop(&mut self) {
+        unsafe {
+            vkDestroyPipelineCache(self.device, self.raw, ptr::null());
+        }
+    }
+}
+
+/// Everything needed to create a graphics pipeline, which can be done on any thread
+///
+/// The handles are only borrowed, so the objects they refer to must outlive the job.
+pub struct RendererVkPipelineJob {
+    device: VkDevice,
+    pipeline_cache: VkPipelineCache, // Internally synchronized, so shared by the workers
+    render_pass: VkRenderPass,
+    samples: VkSampleCountFlagBits, // Of the render pass
+    colour_attachments: u32, // Of the render pass
+    pipeline_layout: VkPipelineLayout,
+    shader_spec: ShaderSpec,
+    modules: Vec<(ShaderStage, VkShaderModule, &'static str)>,
+    polygon_mode: PolygonMode,
+    flip_viewport_y: bool,
+}
+unsafe impl Send for RendererVkPipelineJob {}
+
+impl RendererVkPipelineJob {
+    /// Gather what is needed to create a graphics pipeline
+    ///
+    /// device: The logical device
+    /// pipeline_cache: The cache to create the pipeline through
+    /// render_pass: The render pass the pipeline draws in
+    /// shader_spec: The specification of the shader
+    /// modules: The shader modules of each stage, with their entry points
+    /// pipeline_layout: The layout of the pipeline's descriptor sets and push constants
+    /// polygon_mode: How the triangles are rasterised
+    /// flip_viewport_y: Whether the viewport has a negative height
+    fn new(device: &RendererVkDevice,
+           pipeline_cache: &RendererVkPipelineCache,
+           render_pass: &RendererVkRenderPass,
+           shader_spec: &ShaderSpec,
+           modules: &Vec<(ShaderStage, VkShaderModule, &'static str)>,
+           pipeline_layout: &RendererVkPipelineLayout,
+           polygon_mode: PolygonMode,
+           flip_viewport_y: bool)
+           -> RendererVkPipelineJob {
+        RendererVkPipelineJob {
+            device: device.raw,
+            pipeline_cache: pipeline_cache.raw,
+            render_pass: render_pass.raw,
+            samples: render_pass.samples,
+            colour_attachments: render_pass.get_colour_attachment_count(),
+            pipeline_layout: pipeline_layout.raw,
+            shader_spec: shader_spec.clone(),
+            modules: modules.clone(),
+            polygon_mode: polygon_mode,
+            flip_viewport_y: flip_viewport_y,
+        }
+    }
+
+    /// Create the graphics pipeline
+    fn build(&self) -> Result<RendererVkPipeline, VkResult> {
+        let shader_spec = &self.shader_spec;
+        let modules = &self.modules;
+        let polygon_mode = self.polygon_mode;
+        let flip_viewport_y = self.flip_viewport_y;
+
+        // The viewport and scissor are dynamic so that the pipeline survives swapchain
+        // recreation and can render to targets of differing sizes
+        //
+        let viewport_state = VkPipelineViewportStateCreateInfo {
+            sType: VkStructureType::VK_STRUCTURE_TYPE_PIPELINE_VIEWPORT_STATE_CREATE_INFO,
+            viewportCount: 1,
+            pViewports: ptr::null(), // Dynamic
+            scissorCount: 1,
+            pScissors: ptr::null(), // Dynamic
+            flags: 0,
+            pNext: ptr::null(),
+        };
+
+        let dynamic_states = vec![VkDynamicState::VK_DYNAMIC_STATE_VIEWPORT, VkDynamicState::VK_DYNAMIC_STATE_SCISSOR];
+        let dynamic_state_info = VkPipelineDynamicStateCreateInfo {
+            sType: VkStructureType::VK_STRUCTURE_TYPE_PIPELINE_DYNAMIC_STATE_CREATE_INFO,
+            dynamicStateCount: dynamic_states.len() as u32,
+            pDynamicStates: dynamic_states.as_ptr(),
+            flags: 0,
+            pNext: ptr::null(),
+        };
+
+        let rasterizer = VkPipelineRasterizationStateCreateInfo {
+            sType: VkStructureType::VK_STRUCTURE_TYPE_PIPELINE_RASTERIZATION_STATE_CREATE_INFO,
+            depthClampEnable: false as VkBool32,
+            rasterizerDiscardEnable: false as VkBool32,
+            polygonMode: vk_polygon_mode(polygon_mode),
+            lineWidth: 1.0f32,
+            cullMode: vk_cull_mode(shader_spec.render_state.cull_mode),
+            frontFace: vk_front_face(shader_spec.render_state.front_face, flip_viewport_y),
+            depthBiasEnable: false as VkBool32,
+            depthBiasConstantFactor: 0.0f32, // Optional
+            depthBiasClamp: 0.0f32, // Optional
+            depthBiasSlopeFactor: 0.0f32, // Optional
+            flags: 0,
+            pNext: ptr::null(),
+        };
+
+        let multisampling = VkPipelineMultisampleStateCreateInfo {
+            sType: VkStructureType::VK_STRUCTURE_TYPE_PIPELINE_MULTISAMPLE_STATE_CREATE_INFO,
+            sampleShadingEnable: false as VkBool32,
+            rasterizationSamples: self.samples,
+            minSampleShading: 1.0f32, // Optional
+            pSampleMask: ptr::null(), // Optional
+            alphaToCoverageEnable: false as VkBool32, // Optional
+            alphaToOneEnable: false as VkBool32, // Optional
+            flags: 0,
+            pNext: ptr::null(),
+        };
+
+        // Every colour attachment of the render pass is blended the same way, since differing
+        // states would need the independent blend feature
+        //
+        let color_blend_attachments: Vec<VkPipelineColorBlendAttachmentState> = (0..self.colour_attachments)
+            .map(|_| vk_color_blend_attachment_state(&shader_spec.render_state))
+            .collect();
+
+        let color_blending = VkPipelineColorBlendStateCreateInfo {
+            sType: VkStructureType::VK_STRUCTURE_TYPE_PIPELINE_COLOR_BLEND_STATE_CREATE_INFO,
+            logicOpEnable: false as VkBool32,
+            logicOp: VkLogicOp::VK_LOGIC_OP_COPY,
+            attachmentCount: color_blend_attachments.len() as u32,
+            pAttachments: color_blend_attachments.as_ptr(),
+            blendConstants: [0.0f32, 0.0f32, 0.0f32, 0.0f32],
+            flags: 0,
+            pNext: ptr::null(),
+        };
+
+        let tessellation_state_create_info = VkPipelineTessellationStateCreateInfo {
+            sType: VkStructureType::VK_STRUCTURE_TYPE_PIPELINE_TESSELLATION_STATE_CREATE_INFO,
+            patchControlPoints: 3, // To agree with the tessellation control and evaluation shaders
+            flags: 0,
+            pNext: ptr::null(),
+        };
+
+        let entrypoint_names: Vec<CString> = modules.iter().map(|module| CString::new(module.2).unwrap()).collect();
+
+        let maximum_shader_stages = 5;
+
+        let mut shader_stage_infos: Vec<VkPipelineShaderStageCreateInfo> = Vec::with_capacity(maximum_shader_stages);
+        let mut has_tessellation = false;
+        for (module, entrypoint_name) in modules.iter().zip(entrypoint_names.iter()) {
+            let (shader_stage, shader_module, _) = *module;
+
+            if shader_stage == ShaderStage::TessControlShader || shader_stage == ShaderStage::TessEvalShader {
+                has_tessellation = true;
+            }
+
+            shader_stage_infos.push(VkPipelineShaderStageCreateInfo {
+                sType: VkStructureType::VK_STRUCTURE_TYPE_PIPELINE_SHADER_STAGE_CREATE_INFO,
+                stage: ShaderSpirv::internal_shader_stage(shader_stage),
+                module: shader_module,
+                pName: entrypoint_name.as_ptr(),
+                pSpecializationInfo: ptr::null(),
+                flags: 0,
+                pNext: ptr::null(),
+            });
+        }
+
+        let input_assembly_info = VkPipelineInputAssemblyStateCreateInfo {
+            sType: VkStructureType::VK_STRUCTURE_TYPE_PIPELINE_INPUT_ASSEMBLY_STATE_CREATE_INFO,
+            topology: if has_tessellation {
+                VkPrimitiveTopology::VK_PRIMITIVE_TOPOLOGY_PATCH_LIST
+            } else {
+                vk_primitive_topology(shader_spec.primitive)
+            },
+            primitiveRestartEnable: false as VkBool32,
+            flags: 0,
+            pNext: ptr::null(),
+        };
+
+        let (bindings, attributes) = RendererVkPipeline::vertex_input_bindings_and_attributes_info(shader_spec.vertex_array_type,
+                                                                                                  &shader_spec.instance_layout);
+        let vertex_input_info = VkPipelineVertexInputStateCreateInfo {
+            sType: VkStructureType::VK_STRUCTURE_TYPE_PIPELINE_VERTEX_INPUT_STATE_CREATE_INFO,
+            vertexBindingDescriptionCount: bindings.len() as u32,
+            pVertexBindingDescriptions: bindings.as_ptr(),
+            vertexAttributeDescriptionCount: attributes.len() as u32,
+            pVertexAttributeDescriptions: attributes.as_ptr(),
+            flags: 0,
+            pNext: ptr::null(),
+        };
+
+        let stencil_op_state = match shader_spec.stencil {
+            Some(ref stencil) => vk_stencil_op_state(stencil),
+            None => VkStencilOpState::default(),
+        };
+        let depth_stencil_info = VkPipelineDepthStencilStateCreateInfo {
+            sType: VkStructureType::VK_STRUCTURE_TYPE_PIPELINE_DEPTH_STENCIL_STATE_CREATE_INFO,
+            depthTestEnable: shader_spec.render_state.depth_tested() as VkBool32,
+            depthWriteEnable: shader_spec.render_state.writes_depth() as VkBool32,
+            depthCompareOp: vk_compare_op(shader_spec.render_state.depth_compare.unwrap_or(CompareOp::Always)),
+            depthBoundsTestEnable: false as VkBool32,
+            minDepthBounds: 0.0f32, // Optional
+            maxDepthBounds: 1.0f32, // Optional
+            stencilTestEnable: shader_spec.stencil.is_some() as VkBool32,
+            front: stencil_op_state,
+            back: stencil_op_state,
+            flags: 0,
+            pNext: ptr::null(),
+        };
+
+        let pipeline_info = VkGraphicsPipelineCreateInfo {
+            sType: VkStructureType::VK_STRUCTURE_TYPE_GRAPHICS_PIPELINE_CREATE_INFO,
+            stageCount: modules.len() as u32,
+            pStages: shader_stage_infos.as_ptr(),
+            pVertexInputState: &vertex_input_info,
+            pInputAssemblyState: &input_assembly_info,
+            pViewportState: &viewport_state,
+            pRasterizationState: &rasterizer,
+            pMultisampleState: &multisampling,
+            pDepthStencilState: &depth_stencil_info,
+            pColorBlendState: &color_blending,
+            pDynamicState: &dynamic_state_info,
+            layout: self.pipeline_layout,
+            renderPass: self.render_pass,
+            subpass: 0,
+            basePipelineIndex: -1, // Optional: This indicates that the new pipeline is not derived
+            basePipelineHandle: VK_NULL_HANDLE_MUT(), // Optional: This indicates that the new pipeline is not derived
+            pTessellationState: if has_tessellation {
+                &tessellation_state_create_info
+            } else {
+                ptr::null()
+            },
+            flags: 0,
+            pNext: ptr::null(),
+        };
+
+        let mut render_pipeline: VkPipeline = VK_NULL_HANDLE_MUT();
+        let res = unsafe {
+            vkCreateGraphicsPipelines(self.device,
+                                      self.pipeline_cache,
+                                      1, // Number of pipelines to create
+                                      &pipeline_info,
+                                      ptr::null(),
+                                      &mut render_pipeline)
+        };
+
+        match res {
+            VkResult::VK_SUCCESS => {
+                Ok(RendererVkPipeline {
+                    device: self.device,
+                    raw: render_pipeline,
+                })
+            }
+            _ => Err(res),
+        }
+    }
+}
+
+/// Worker threads creating graphics pipelines, which the renderer collects as they are created
+pub struct RendererVkPipelineBuilder {
+    results: mpsc::Receiver<(&'static str, Result<RendererVkPipeline, VkResult>)>,
+    workers: Vec<thread::JoinHandle<()>>,
+    modules: HashMap<&'static str, Vec<(ShaderStage, VkShaderModule, &'static str)>>, // Of the pipelines not yet collected
+    tracker: PipelineBuildTracker,
+}
+
+impl RendererVkPipelineBuilder {
+    /// Start creating the pipelines on worker threads, which take the jobs in the order given
+    ///
+    /// jobs: The pipelines to create, with the names of their shaders
+    /// threads: The number of worker threads
+    /// tracker: Tracks the progress through the jobs
+    fn new(jobs: Vec<(&'static str, RendererVkPipelineJob)>,
+           threads: usize,
+           tracker: PipelineBuildTracker)
+           -> RendererVkPipelineBuilder {
+        let modules = jobs.iter().map(|&(name, ref job)| (name, job.modules.clone())).collect();
+        let queue = Arc::new(Mutex::new(jobs.into_iter().collect::<VecDeque<(&'static str, RendererVkPipelineJob)>>()));
+        let (tx, rx) = mpsc::channel();
+
+        let workers = (0..threads)
+            .map(|_| {
+                let queue = queue.clone();
+                let tx = tx.clone();
+                thread::spawn(move || loop {
+                    let job = queue.lock().unwrap().pop_front();
+                    match job {
+                        Some((name, job)) => {
+                            if tx.send((name, job.build())).is_err() {
+                                break;
+                            }
+                        }
+                        None => break,
+                    }
+                })
+            })
+            .collect();
+
+        RendererVkPipelineBuilder {
+            results: rx,
+            workers: workers,
+            modules: modules,
+            tracker: tracker,
+        }
+    }
+
+    /// Receive the next pipeline created, with its shader's name and modules
+    ///
+    /// None is returned when no pipeline is ready and the wait does not call for one, or when
+    /// the workers have finished.
+    ///
+    /// wait: Which pipelines to wait for
+    fn receive(&mut self,
+               wait: PipelineWait)
+               -> Option<(&'static str, Result<RendererVkPipeline, VkResult>, Vec<(ShaderStage, VkShaderModule, &'static str)>)> {
+        let received = if self.tracker.must_wait(wait) {
+            self.results.recv().ok()
+        } else {
+            self.results.try_recv().ok()
+        };
+
+        received.map(|(name, result)| (name, result, self.modules.remove(name).unwrap_or(vec![])))
+    }
+
+    /// Wait for the worker threads to exit
+    fn join(self) {
+        for worker in self.workers {
+            if worker.join().is_err() {
+                log_error!("A pipeline worker thread panicked");
+            }
+        }
+    }
+}
+
+pub struct RendererVkPipeline {
+    device: VkDevice,
+    raw: VkPipeline,
+}
+
+// The pipelines are sent back from the worker threads that create them
+unsafe impl Send for RendererVkPipeline {}
+
+impl RendererVkPipeline {
+    /// Create a graphics pipeline with some default rendering choices
+    ///
+    ///
+    fn new(device: &RendererVkDevice,
+           pipeline_cache: &RendererVkPipelineCache,
+           render_pass: &RendererVkRenderPass,
+           shader_spec: &ShaderSpec,
+           modules: &Vec<(ShaderStage, VkShaderModule, &'static str)>,
+           pipeline_layout: &RendererVkPipelineLayout,
+           polygon_mode: PolygonMode,
+           flip_viewport_y: bool)
+           -> Result<RendererVkPipeline, VkResult> {
+        RendererVkPipelineJob::new(device,
+                                   pipeline_cache,
+                                   render_pass,
+                                   shader_spec,
+                                   modules,
+                                   pipeline_layout,
+                                   polygon_mode,
+                                   flip_viewport_y)
+            .build()
+    }
+
+    /// Return pipeline vertex input state bindings and attributes structures for a given vertex array type
+    ///
+    /// Per-instance attributes are read from a second binding, at the locations following the
+    /// vertex attributes.
+    ///
+    /// array_type: The vertex array type
+    /// instance_layout: The per-instance attributes, if the shader is instanced
+    fn vertex_input_bindings_and_attributes_info
+        (array_type: VertexArrayType,
+         instance_layout: &Option<InstanceLayout>)
+         -> (Vec<VkVertexInputBindingDescription>, Vec<VkVertexInputAttributeDescription>) {
+        let mut bindings;
+        let mut attributes;
+        match array_type {
+            VertexArrayType::F3 => {
+                bindings = vec![VkVertexInputBindingDescription {
+                                    binding: 0,
+                                    stride: 3 * mem::size_of::<f32>() as u32,
+                                    inputRate: VkVertexInputRate::VK_VERTEX_INPUT_RATE_VERTEX,
+                                }];
+                attributes = vec![VkVertexInputAttributeDescription {
+                                      binding: 0,
+                                      location: 0,
+                                      format: VkFormat::VK_FORMAT_R32G32B32_SFLOAT,
+                                      offset: 0,
+                                  }];
+            }
+            VertexArrayType::F3F3F3 => {
+                bindings = vec![VkVertexInputBindingDescription {
+                                    binding: 0,
+                                    stride: 9 * mem::size_of::<f32>() as u32,
+                                    inputRate: VkVertexInputRate::VK_VERTEX_INPUT_RATE_VERTEX,
+                                }];
+                attributes = vec![VkVertexInputAttributeDescription {
+                                      binding: 0,
+                                      location: 0,
+                                      format: VkFormat::VK_FORMAT_R32G32B32_SFLOAT,
+                                      offset: 0,
+                                  },
+                                  VkVertexInputAttributeDescription {
+                                      binding: 0,
+                                      location: 1,
+                                      format: VkFormat::VK_FORMAT_R32G32B32_SFLOAT,
+                                      offset: 3 * mem::size_of::<f32>() as u32,
+                                  },
+                                  VkVertexInputAttributeDescription {
+                                      binding: 0,
+                                      location: 2,
+                                      format: VkFormat::VK_FORMAT_R32G32B32_SFLOAT,
+                                      offset: 6 * mem::size_of::<f32>() as u32,
+                                  }];
+            }
+            VertexArrayType::F3F3 => {
+                bindings = vec![VkVertexInputBindingDescription {
+                                    binding: 0,
+                                    stride: 6 * mem::size_of::<f32>() as u32,
+                                    inputRate: VkVertexInputRate::VK_VERTEX_INPUT_RATE_VERTEX,
+                                }];
+                attributes = vec![VkVertexInputAttributeDescription {
+                                      binding: 0,
+                                      location: 0,
+                                      format: VkFormat::VK_FORMAT_R32G32B32_SFLOAT,
+                                      offset: 0,
+                                  },
+                                  VkVertexInputAttributeDescription {
+                                      binding: 0,
+                                      location: 1,
+                                      format: VkFormat::VK_FORMAT_R32G32B32_SFLOAT,
+                                      offset: 3 * mem::size_of::<f32>() as u32,
+                                  }];
+            }
+            VertexArrayType::F2F2 => {
+                bindings = vec![VkVertexInputBindingDescription {
+                                    binding: 0,
+                                    stride: 4 * mem::size_of::<f32>() as u32,
+                                    inputRate: VkVertexInputRate::VK_VERTEX_INPUT_RATE_VERTEX,
+                                }];
+                attributes = vec![VkVertexInputAttributeDescription {
+                                      binding: 0,
+                                      location: 0,
+                                      format: VkFormat::VK_FORMAT_R32G32_SFLOAT,
+                                      offset: 0,
+                                  },
+                                  VkVertexInputAttributeDescription {
+                                      binding: 0,
+                                      location: 1,
+                                      format: VkFormat::VK_FORMAT_R32G32_SFLOAT,
+                                      offset: 2 * mem::size_of::<f32>() as u32,
+                                  }];
+            }
+        }
+
+        if let Some(ref layout) = *instance_layout {
+            bindings.push(VkVertexInputBindingDescription {
+                binding: 1,
+                stride: (layout.components_per_instance() * mem::size_of::<f32>()) as u32,
+                inputRate: VkVertexInputRate::VK_VERTEX_INPUT_RATE_INSTANCE,
+            });
+
+            for (i, &(_, components)) in layout.attributes.iter().enumerate() {
+                attributes.push(VkVertexInputAttributeDescription {
+                    binding: 1,
+                    location: (VertexArrayType::attribute_count(array_type) + i) as u32,
+                    format: match components {
+                        1 => VkFormat::VK_FORMAT_R32_SFLOAT,
+                        2 => VkFormat::VK_FORMAT_R32G32_SFLOAT,
+                        3 => VkFormat::VK_FORMAT_R32G32B32_SFLOAT,
+                        _ => VkFormat::VK_FORMAT_R32G32B32A32_SFLOAT,
+                    },
+                    offset: (layout.offset_of(i) * mem::size_of::<f32>()) as u32,
+                });
+            }
+        }
+
+        (bindings, attributes)
+    }
+}
+
+impl Drop for RendererVkPipeline {
+    fn drop(&mut self) {
+        unsafe {
+            vkDestroyPipeline(self.device, self.raw, ptr::null());
+        }
+    }
+}
+
+pub struct RendererVkComputePipeline {
+    device: VkDevice,
+    raw: VkPipeline,
+    descriptor_set: RendererVkDescriptorSet,
+    descriptor_pool: RendererVkDescriptorPool,
+    pipeline_layout: RendererVkPipelineLayout,
+    descriptor_set_layouts: RendererVkDescriptorSetLayouts,
+    workgroup_offset: Option<usize>, // The push constant offset of the paced slice's first workgroup
+}
+
+impl RendererVkComputePipeline {
+    /// Create a compute pipeline, along with the descriptor sets it binds
+    ///
+    /// Compute pipelines are bound outside the render passes, so they keep descriptor sets of
+    /// their own rather than sharing the graphics ones.
+    ///
+    /// device: The Vulkan device
+    /// pipeline_cache: The pipeline cache to build through
+    /// resource_manager: The resource manager holding the uniform block specifications
+    /// shader_spec: The specification of the compute shader
+    /// module: The compute shader module, with its stage and entry point
+    /// uniform_buffers: The uniform and storage buffers, keyed by block name
+    /// textures: The textures to bind, keyed by uniform name
+    fn new(device: &RendererVkDevice,
+           pipeline_cache: &RendererVkPipelineCache,
+           resource_manager: &ResourceManager,
+           shader_spec: &ShaderSpec,
+           module: (ShaderStage, VkShaderModule, &'static str),
+           uniform_buffers: &HashMap<&'static str, RendererVkUniformBuffer>,
+           textures: &HashMap<&'static str, &TextureVk>)
+           -> Result<RendererVkComputePipeline, VkResult> {
+        let (shader_stage, shader_module, entry_point) = module;
+        debug_assert!(shader_stage == ShaderStage::ComputeShader);
+
+        let descriptor_set_layouts = RendererVkDescriptorSetLayouts::new(device, resource_manager, shader_spec);
+        let pipeline_layout = RendererVkPipelineLayout::new(device, &descriptor_set_layouts, None, shader_spec);
+
+        let num_blocks = cmp::max(shader_spec.uniform_block_names.len(), 1);
+        let num_samplers = cmp::max(shader_spec.uniform_specs.len(), 1);
+        let descriptor_pool = RendererVkDescriptorPool::new(device,
+                                                            num_blocks,
+                                                            num_blocks,
+                                                            num_samplers,
+                                                            cmp::max(descriptor_set_layouts.raw.len(), 1));
+        let descriptor_set = RendererVkDescriptorSet::new(device,
+                                                          &descriptor_pool,
+                                                          &descriptor_set_layouts,
+                                                          shader_spec,
+                                                          uniform_buffers,
+                                                          textures);
+
+        let entrypoint_name = CString::new(entry_point).unwrap();
+        let pipeline_info = VkComputePipelineCreateInfo {
+            sType: VkStructureType::VK_STRUCTURE_TYPE_COMPUTE_PIPELINE_CREATE_INFO,
+            stage: VkPipelineShaderStageCreateInfo {
+                sType: VkStructureType::VK_STRUCTURE_TYPE_PIPELINE_SHADER_STAGE_CREATE_INFO,
+                stage: ShaderSpirv::internal_shader_stage(shader_stage),
+                module: shader_module,
+                pName: entrypoint_name.as_ptr(),
+                pSpecializationInfo: ptr::null(),
+                flags: 0,
+                pNext: ptr::null(),
+            },
+            layout: pipeline_layout.raw,
+            basePipelineHandle: VK_NULL_HANDLE_MUT(),
+            basePipelineIndex: -1,
+            flags: 0,
+            pNext: ptr::null(),
+        };
+
+        let mut compute_pipeline: VkPipeline = VK_NULL_HANDLE_MUT();
+        let res = unsafe {
+            vkCreateComputePipelines(device.raw,
+                                     pipeline_cache.raw,
+                                     1, // Number of pipelines to create
+                                     &pipeline_info,
+                                     ptr::null(),
+                                     &mut compute_pipeline)
+        };
+
+        match res {
+            VkResult::VK_SUCCESS => {
+                Ok(RendererVkComputePipeline {
+                    device: device.raw,
+                    raw: compute_pipeline,
+                    descriptor_set: descriptor_set,
+                    descriptor_pool: descriptor_pool,
+                    pipeline_layout: pipeline_layout,
+                    descriptor_set_layouts: descriptor_set_layouts,
+                    workgroup_offset: shader_spec.push_constants
+                        .iter()
+                        .find(|p| p.name == WORKGROUP_OFFSET_PUSH_CONSTANT)
+                        .map(|p| p.offset),
+                })
+            }
+            _ => Err(res),
+        }
+    }
+
+    /// Return whether the shader takes the workgroup offset, so that its dispatches can be sliced
+    fn is_sliceable(&self) -> bool {
+        self.workgroup_offset.is_some()
+    }
+
+    /// Record a slice of a paced dispatch, passing the index of its first workgroup
+    ///
+    /// command_buffer: The command buffer to record into
+    /// slice: The slice to dispatch
+    /// bind: Whether to bind the pipeline and its descriptor set, which are otherwise already bound
+    fn record_slice(&self, command_buffer: &RendererVkCommandBuffer, slice: &ComputeSlice, bind: bool) {
+        if bind {
+            self.bind(command_buffer);
+        }
+
+        if let Some(offset) = self.workgroup_offset {
+            unsafe {
+                vkCmdPushConstants(command_buffer.raw,
+                                   self.pipeline_layout.raw,
+                                   VkShaderStageFlagBits::VK_SHADER_STAGE_COMPUTE_BIT as VkShaderStageFlags,
+                                   offset as u32,
+                                   mem::size_of::<u32>() as u32,
+                                   &slice.first_x as *const u32 as *const c_void);
+            }
+        }
+
+        unsafe {
+            vkCmdDispatch(command_buffer.raw, slice.x, slice.y, slice.z);
+        }
+    }
+
+    /// Bind the pipeline and its descriptor set
+    ///
+    /// command_buffer: The command buffer to record into, which must be outside a render pass
+    fn bind(&self, command_buffer: &RendererVkCommandBuffer) {
+        let descriptor_sets = vec![self.descriptor_set.raw];
+        unsafe {
+            vkCmdBindPipeline(command_buffer.raw,
+                              VkPipelineBindPoint::VK_PIPELINE_BIND_POINT_COMPUTE,
+                              self.raw);
+            vkCmdBindDescriptorSets(command_buffer.raw,
+                                    VkPipelineBindPoint::VK_PIPELINE_BIND_POINT_COMPUTE,
+                                    self.pipeline_layout.raw,
+                                    0, // First set
+                                    descriptor_sets.len() as u32,
+                                    descriptor_sets.as_ptr(),
+                                    0, // Dynamic offset count
+                                    ptr::null()); // Dynamic offsets
+        }
+    }
+
+    /// Record a dispatch of the pipeline
+    ///
+    /// command_buffer: The command buffer to record into, which must be outside a render pass
+    /// x: The number of workgroups in the x dimension
+    /// y: The number of workgroups in the y dimension
+    /// z: The number of workgroups in the z dimension
+    fn record_dispatch(&self, command_buffer: &RendererVkCommandBuffer, x: u32, y: u32, z: u32) {
+        self.bind(command_buffer);
+        unsafe {
+            vkCmdDispatch(command_buffer.raw, x, y, z);
+        }
+    }
+}
+
+impl Drop for RendererVkComputePipeline {
+    fn drop(&mut self) {
+        unsafe {
+            vkDestroyPipeline(self.device, self.raw, ptr::null());
+        }
+    }
+}
+
+pub struct RendererVkCommandPool {
+    device: VkDevice,
+    raw: VkCommandPool,
+}
+
+impl RendererVkCommandPool {
+    /// Create a command pool
+    ///
+    /// device: The Vulkan device
+    pub fn new(device: &RendererVkDevice, queue_family_index: u32) -> Result<RendererVkCommandPool, RendererError> {
+        let pool_info = VkCommandPoolCreateInfo {
+            sType: VkStructureType::VK_STRUCTURE_TYPE_COMMAND_POOL_CREATE_INFO,
+            queueFamilyIndex: queue_family_index,
+            flags: VkCommandPoolCreateFlagBits::VK_COMMAND_POOL_CREATE_RESET_COMMAND_BUFFER_BIT as VkCommandPoolCreateFlags,
+            pNext: ptr::null(),
+        };
+
+        let mut command_pool: VkCommandPool = VK_NULL_HANDLE_MUT();
+        unsafe {
+            try_result!("vkCreateCommandPool",
+                        vkCreateCommandPool(device.raw, &pool_info, ptr::null(), &mut command_pool));
+        }
+
+        Ok(RendererVkCommandPool {
+            device: device.raw,
+            raw: command_pool,
+        })
+    }
+}
+
+impl Drop for RendererVkCommandPool {
+    fn drop(&mut self) {
+        unsafe {
+            vkDestroyCommandPool(self.device, self.raw, ptr::null());
+        }
+    }
+}
+
+// Note: There is no Drop implementation for RendererVkCommandBuffer as
+// VkCommandBuffer objects are destroyed when the VkCommandPool they belong
+// to is destroyed
+pub struct RendererVkCommandBuffer {
+    raw: VkCommandBuffer,
+    primary: bool,
+}
+
+impl RendererVkCommandBuffer {
+    /// Create a command buffer
+    ///
+    /// device: The Vulkan device
+    /// primary: true if this is to be a primary command buffers, false if it is to be
+    ///     a secondary command buffer
+    pub fn new(device: &RendererVkDevice, command_pool: &RendererVkCommandPool, primary: bool) -> RendererVkCommandBuffer {
+        let allocate_info = VkCommandBufferAllocateInfo {
+            sType: VkStructureType::VK_STRUCTURE_TYPE_COMMAND_BUFFER_ALLOCATE_INFO,
+            commandPool: command_pool.raw,
+            level: if primary {
+                VkCommandBufferLevel::VK_COMMAND_BUFFER_LEVEL_PRIMARY
+            } else {
+                VkCommandBufferLevel::VK_COMMAND_BUFFER_LEVEL_SECONDARY
+            },
+            commandBufferCount: 1,
+            pNext: ptr::null(),
+        };
+
+        let mut command_buffer: VkCommandBuffer = VK_NULL_HANDLE_MUT();
+        unsafe {
+            check_result!("vkAllocateCommandBuffers",
+                          vkAllocateCommandBuffers(device.raw, &allocate_info, &mut command_buffer));
+        }
+
+        RendererVkCommandBuffer {
+            raw: command_buffer,
+            primary: primary,
+        }
+    }
+
+    /// Begin recording to the primary command buffer
+    ///
+    ///
+    pub fn begin_primary(&self, one_time_submit: bool, render_pass_continue: bool, simultaneous_use: bool) {
+        debug_assert!(self.primary);
+
+        let mut flags: u32 = 0;
+        if one_time_submit {
+            flags |= VkCommandBufferUsageFlagBits::VK_COMMAND_BUFFER_USAGE_ONE_TIME_SUBMIT_BIT as VkCommandBufferUsageFlags;
+        };
+        if render_pass_continue {
+            flags |= VkCommandBufferUsageFlagBits::VK_COMMAND_BUFFER_USAGE_RENDER_PASS_CONTINUE_BIT as VkCommandBufferUsageFlags;
+        };
+        if simultaneous_use {
+            flags |= VkCommandBufferUsageFlagBits::VK_COMMAND_BUFFER_USAGE_SIMULTANEOUS_USE_BIT as VkCommandBufferUsageFlags;
+        };
+
+        let begin_info = VkCommandBufferBeginInfo {
+            sType: VkStructureType::VK_STRUCTURE_TYPE_COMMAND_BUFFER_BEGIN_INFO,
+            flags: flags,
+            pInheritanceInfo: ptr::null(),
+            pNext: ptr::null(),
+        };
+
+        unsafe {
+            vkBeginCommandBuffer(self.raw, &begin_info);
+        }
+    }
+
+    /// Begin recording to the secondary command buffer using the supplied inheritance info
+    ///
+    ///
+    pub fn begin_secondary(&self, renderpass: &RendererVkRenderPass, subpass: u32, framebuffer: &RendererVkFramebuffer) {
+        debug_assert!(!self.primary);
+
+        let inheritance_info = VkCommandBufferInheritanceInfo {
+            sType: VkStructureType::VK_STRUCTURE_TYPE_COMMAND_BUFFER_INHERITANCE_INFO,
+            pNext: ptr::null(),
+            renderPass: renderpass.raw,
+            subpass: subpass,
+            framebuffer: framebuffer.raw,
+            occlusionQueryEnable: true as VkBool32,
+            queryFlags: VkQueryControlFlagBits::VK_QUERY_CONTROL_PRECISE_BIT as VkQueryControlFlags,
+            pipelineStatistics: 0,
+        };
+
+        let begin_info = VkCommandBufferBeginInfo {
+            sType: VkStructureType::VK_STRUCTURE_TYPE_COMMAND_BUFFER_BEGIN_INFO,
+            flags: VkCommandBufferUsageFlagBits::VK_COMMAND_BUFFER_USAGE_SIMULTANEOUS_USE_BIT as VkCommandBufferUsageFlags |
+                   VkCommandBufferUsageFlagBits::VK_COMMAND_BUFFER_USAGE_RENDER_PASS_CONTINUE_BIT as VkCommandBufferUsageFlags,
+            pInheritanceInfo: &inheritance_info,
+            pNext: ptr::null(),
+        };
+
+        unsafe {
+            vkBeginCommandBuffer(self.raw, &begin_info);
+        }
+    }
+
+    /// End recording to the command buffer
+    ///
+    ///
+    pub fn end(&self) {
+        unsafe {
+            check_result!("vkEndCommandBuffer", vkEndCommandBuffer(self.raw));
+        }
+    }
+
+    /// Emit an explicit pipeline image memory barrier, raw
+    ///
+    ///
+    pub fn image_memory_barrier_raw(command_buffer: VkCommandBuffer,
+                                    image: VkImage,
+                                    src_access_mask: VkAccessFlags,
+                                    dst_access_mask: VkAccessFlags,
+                                    old_layout: VkImageLayout,
+                                    new_layout: VkImageLayout,
+                                    aspect_mask: VkImageAspectFlags,
+                                    src_stage_mask: VkPipelineStageFlags,
+                                    dst_stage_mask: VkPipelineStageFlags) {
+        let barrier = VkImageMemoryBarrier {
+            sType: VkStructureType::VK_STRUCTURE_TYPE_IMAGE_MEMORY_BARRIER,
+            srcAccessMask: src_access_mask,
+            dstAccessMask: dst_access_mask,
+            oldLayout: old_layout,
+            newLayout: new_layout,
+            srcQueueFamilyIndex: VK_QUEUE_FAMILY_IGNORED as u32,
+            dstQueueFamilyIndex: VK_QUEUE_FAMILY_IGNORED as u32,
+            image: image,
+            subresourceRange: VkImageSubresourceRange {
+                aspectMask: aspect_mask,
+                baseMipLevel: 0,
+                levelCount: 1,
+                baseArrayLayer: 0,
+                layerCount: 1,
+            },
+            pNext: ptr::null(),
+        };
+
+        unsafe {
+            vkCmdPipelineBarrier(command_buffer,
+                                 src_stage_mask,
+                                 dst_stage_mask,
+                                 0, // Dependency flags
+                                 0, // Memory barrier count
+                                 ptr::null(), // Memory barriers
+                                 0, // Buffer memory barrier count
+                                 ptr::null(), // Buffer memory barriers
+                                 1, // Image barrier count
+                                 &barrier); // Image barriers
+        }
+    }
+
+    /// Emit an explicit pipeline image memory barrier
+    ///
+    ///
+    pub fn image_memory_barrier(&self,
+                                image: VkImage,
+                                src_access_mask: VkAccessFlags,
+                                dst_access_mask: VkAccessFlags,
+                                old_layout: VkImageLayout,
+                                new_layout: VkImageLayout,
+                                aspect_mask: VkImageAspectFlags,
+                                src_stage_mask: VkPipelineStageFlags,
+                                dst_stage_mask: VkPipelineStageFlags) {
+        RendererVkCommandBuffer::image_memory_barrier_raw(self.raw,
+                                                          image,
+                                                          src_access_mask,
+                                                          dst_access_mask,
+                                                          old_layout,
+                                                          new_layout,
+                                                          aspect_mask,
+                                                          src_stage_mask,
+                                                          dst_stage_mask);
+    }
+
+    /// Emit an explicit pipeline buffer memory barrier, raw
+    ///
+    ///
+    pub fn buffer_memory_barrier_raw(command_buffer: VkCommandBuffer,
+                                     buffer: VkBuffer,
+                                     src_access_mask: VkAccessFlags,
+                                     dst_access_mask: VkAccessFlags,
+                                     src_stage_mask: VkPipelineStageFlags,
+                                     dst_stage_mask: VkPipelineStageFlags) {
+        let barrier = VkBufferMemoryBarrier {
+            sType: VkStructureType::VK_STRUCTURE_TYPE_BUFFER_MEMORY_BARRIER,
+            srcAccessMask: src_access_mask,
+            dstAccessMask: dst_access_mask,
+            srcQueueFamilyIndex: VK_QUEUE_FAMILY_IGNORED as u32,
+            dstQueueFamilyIndex: VK_QUEUE_FAMILY_IGNORED as u32,
+            buffer: buffer,
+            offset: 0,
+            size: VK_WHOLE_SIZE as u64,
+            pNext: ptr::null(),
+        };
+
+        unsafe {
+            vkCmdPipelineBarrier(command_buffer,
+                                 src_stage_mask,
+                                 dst_stage_mask,
+                                 0, // Dependency flags
+                                 0, // Memory barrier count
+                                 ptr::null(), // Memory barriers
+                                 1, // Buffer memory barrier count
+                                 &barrier, // Buffer memory barriers
+                                 0, // Image barrier count
+                                 ptr::null()); // Image barriers
+        }
+    }
+
+    /// Emit an explicit pipeline buffer memory barrier
+    ///
+    ///
+    pub fn buffer_memory_barrier(&self,
+                                 buffer: VkBuffer,
+                                 src_access_mask: VkAccessFlags,
+                                 dst_access_mask: VkAccessFlags,
+                                 src_stage_mask: VkPipelineStageFlags,
+                                 dst_stage_mask: VkPipelineStageFlags) {
+        RendererVkCommandBuffer::buffer_memory_barrier_raw(self.raw,
+                                                           buffer,
+                                                           src_access_mask,
+                                                           dst_access_mask,
+                                                           src_stage_mask,
+                                                           dst_stage_mask);
+    }
+
+    /// Emit an explicit pipeline memory barrier, raw
+    ///
+    ///
+    pub fn memory_barrier_raw(command_buffer: VkCommandBuffer,
+                              src_access_mask: VkAccessFlags,
+                              dst_access_mask: VkAccessFlags,
+                              src_stage_mask: VkPipelineStageFlags,
+                              dst_stage_mask: VkPipelineStageFlags) {
+        let barrier = VkMemoryBarrier {
+            sType: VkStructureType::VK_STRUCTURE_TYPE_MEMORY_BARRIER,
+            srcAccessMask: src_access_mask,
+            dstAccessMask: dst_access_mask,
+            pNext: ptr::null(),
+        };
+
+        unsafe {
+            vkCmdPipelineBarrier(command_buffer,
+                                 src_stage_mask,
+                                 dst_stage_mask,
+                                 0, // Dependency flags
+                                 1, // Memory barrier count
+                                 &barrier, // Memory barriers
+                                 0, // Buffer memory barrier count
+                                 ptr::null(), // Buffer memory barriers
+                                 0, // Image barrier count
+                                 ptr::null()); // Image barriers
+        }
+    }
+
+    /// Emit an explicit pipeline memory barrier
+    ///
+    ///
+    pub fn memory_barrier(&self,
+                          src_access_mask: VkAccessFlags,
+                          dst_access_mask: VkAccessFlags,
+                          src_stage_mask: VkPipelineStageFlags,
+                          dst_stage_mask: VkPipelineStageFlags) {
+        RendererVkCommandBuffer::memory_barrier_raw(self.raw,
+                                                    src_access_mask,
+                                                    dst_access_mask,
+                                                    src_stage_mask,
+                                                    dst_stage_mask);
+    }
+}
+
+pub struct RendererVkSemaphore {
+    device: VkDevice,
+    raw: VkSemaphore,
+}
+
+impl RendererVkSemaphore {
+    /// Create a semaphore
+    ///
+    ///
+    pub fn new(device: &RendererVkDevice) -> Result<RendererVkSemaphore, RendererError> {
+        let semaphore_create_info = VkSemaphoreCreateInfo {
+            sType: VkStructureType::VK_STRUCTURE_TYPE_SEMAPHORE_CREATE_INFO,
+            flags: 0,
+            pNext: ptr::null_mut(),
+        };
+
+        let mut semaphore: VkSemaphore = VK_NULL_HANDLE_MUT();
+        unsafe {
+            try_result!("vkCreateSemaphore",
+                        vkCreateSemaphore(device.raw,
+                                          &semaphore_create_info,
+                                          ptr::null_mut(),
+                                          &mut semaphore));
+        }
+
+        Ok(RendererVkSemaphore {
+            device: device.raw,
+            raw: semaphore,
+        })
+    }
+}
+
+impl Drop for RendererVkSemaphore {
+    fn drop(&mut self) {
+        unsafe {
+            vkDestroySemaphore(self.device, self.raw, ptr::null());
+        }
+    }
+}
+
+pub struct RendererVkFence {
+    device: VkDevice,
+    raw: VkFence,
+}
+
+impl RendererVkFence {
+    /// Create a fence
+    ///
+    /// device: The Vulkan device
+    /// signalled: Whether the fence starts out signalled
+    pub fn new(device: &RendererVkDevice, signalled: bool) -> Result<RendererVkFence, RendererError> {
+        let fence_create_info = VkFenceCreateInfo {
+            sType: VkStructureType::VK_STRUCTURE_TYPE_FENCE_CREATE_INFO,
+            flags: if signalled {
+                VkFenceCreateFlagBits::VK_FENCE_CREATE_SIGNALED_BIT as VkFenceCreateFlags
+            } else {
+                0
+            },
+            pNext: ptr::null(),
+        };
+
+        let mut fence: VkFence = VK_NULL_HANDLE_MUT();
+        unsafe {
+            try_result!("vkCreateFence",
+                        vkCreateFence(device.raw, &fence_create_info, ptr::null_mut(), &mut fence));
+        }
+
+        Ok(RendererVkFence {
+            device: device.raw,
+            raw: fence,
+        })
+    }
+
+    /// Return whether the fence has been signalled, without blocking
+    pub fn is_signalled(&self) -> bool {
+        let res = unsafe { vkGetFenceStatus(self.device, self.raw) };
+        match res {
+            VkResult::VK_SUCCESS => true,
+            VkResult::VK_NOT_READY => false,
+            _ => {
+                log_error!("Unexpected result from {}: {}", "vkGetFenceStatus", res);
+                panic!("Vulkan API call failed");
+            }
+        }
+    }
+
+    /// Block until the fence has been signalled
+    pub fn wait(&self) {
+        unsafe {
+            check_result!("vkWaitForFences",
+                          vkWaitForFences(self.device,
+                                          1, // Fence count
+                                          &self.raw,
+                                          true as VkBool32, // Wait for all
+                                          u64::max_value() /* No timeout */));
+        }
+    }
+}
+
+impl Drop for RendererVkFence {
+    fn drop(&mut self) {
+        unsafe {
+            vkDestroyFence(self.device, self.raw, ptr::null());
+        }
+    }
+}
+
+pub struct RendererVkQueryPool {
+    device: VkDevice,
+    raw: VkQueryPool,
+    count: u32,
+}
+
+impl RendererVkQueryPool {
+    /// Create a pool of timestamp queries
+    ///
+    /// device: The logical device
+    /// count: The number of queries in the pool
+    pub fn new(device: &RendererVkDevice, count: u32) -> RendererVkQueryPool {
+        let create_info = VkQueryPoolCreateInfo {
+            sType: VkStructureType::VK_STRUCTURE_TYPE_QUERY_POOL_CREATE_INFO,
+            queryType: VkQueryType::VK_QUERY_TYPE_TIMESTAMP,
+            queryCount: count,
+            pipelineStatistics: 0,
+            flags: 0,
+            pNext: ptr::null(),
+        };
+
+        let mut query_pool: VkQueryPool = VK_NULL_HANDLE_MUT();
+        unsafe {
+            check_result!("vkCreateQueryPool",
+                          vkCreateQueryPool(device.raw, &create_info, ptr::null(), &mut query_pool));
+        }
+
+        RendererVkQueryPool {
+            device: device.raw,
+            raw: query_pool,
+            count: count,
+        }
+    }
+
+    /// Read back the first queries of the pool, which must have been written by completed work
+    ///
+    /// count: The number of queries to read
+    pub fn get_timestamps(&self, count: u32) -> Vec<u64> {
+        debug_assert!(count <= self.count);
+
+        let mut timestamps: Vec<u64> = vec![0; count as usize];
+        if count > 0 {
+            unsafe {
+                check_result!("vkGetQueryPoolResults",
+                              vkGetQueryPoolResults(self.device,
+                                                    self.raw,
+                                                    0, // First query
+                                                    count,
+                                                    timestamps.len() * mem::size_of::<u64>(),
+                                                    timestamps.as_mut_ptr() as *mut c_void,
+                                                    mem::size_of::<u64>() as u64,
+                                                    VkQueryResultFlagBits::VK_QUERY_RESULT_64_BIT as VkQueryResultFlags));
+            }
+        }
+
+        timestamps
+    }
+
+    /// Read back the first queries of the pool without waiting, giving None for those not yet available
+    ///
+    /// count: The number of queries to read
+    pub fn get_available_timestamps(&self, count: u32) -> Vec<Option<u64>> {
+        debug_assert!(count <= self.count);
+
+        // Each result is followed by its availability
+        let mut results: Vec<u64> = vec![0; 2 * count as usize];
+        if count > 0 {
+            let res = unsafe {
+                vkGetQueryPoolResults(self.device,
+                                      self.raw,
+                                      0, // First query
+                                      count,
+                                      results.len() * mem::size_of::<u64>(),
+                                      results.as_mut_ptr() as *mut c_void,
+                                      2 * mem::size_of::<u64>() as u64,
+                                      VkQueryResultFlagBits::VK_QUERY_RESULT_64_BIT as VkQueryResultFlags |
+                                      VkQueryResultFlagBits::VK_QUERY_RESULT_WITH_AVAILABILITY_BIT as VkQueryResultFlags)
+            };
+            match res {
+                VkResult::VK_SUCCESS |
+                VkResult::VK_NOT_READY => {}
+                _ => {
+                    log_error!("Unexpected result from {}: {}", "vkGetQueryPoolResults", res);
+                    return vec![None; count as usize];
+                }
+            }
+        }
+
+        results.chunks(2).map(|result| if result[1] != 0 { Some(result[0]) } else { None }).collect()
+    }
+}
+
+impl Drop for RendererVkQueryPool {
+    fn drop(&mut self) {
+        unsafe {
+            vkDestroyQueryPool(self.device, self.raw, ptr::null());
+        }
+    }
+}
+
+pub struct RendererVkReadback {
+    handle: ReadbackHandle,
+    buffer: RendererVkBuffer,
+    image: VkImage,
+    width: u32,
+    height: u32,
+}
+
+impl RendererVkReadback {
+    /// Create a readback of an RGBA floating point colour image into a host-visible staging buffer
+    ///
+    /// handle: The handle identifying this readback
+    /// buffer: The staging buffer, of at least staging_size bytes
+    /// image: The raw image to read back
+    /// width: The width of the image
+    /// height: The height of the image
+    pub fn new(handle: ReadbackHandle, buffer: RendererVkBuffer, image: VkImage, width: u32, height: u32) -> RendererVkReadback {
+        assert!(buffer.size >= RendererVkReadback::staging_size(width, height));
+
+        RendererVkReadback {
+            handle: handle,
+            buffer: buffer,
+            image: image,
+            width: width,
+            height: height,
+        }
+    }
+
+    /// Return the size of the staging buffer needed to read back an image
+    ///
+    /// width: The width of the image
+    /// height: The height of the image
+    pub fn staging_size(width: u32, height: u32) -> usize {
+        (width * height * 4) as usize * mem::size_of::<f32>()
+    }
+
+    /// Create a staging buffer for readbacks
+    ///
+    /// device: The logical device
+    /// physical_device: The physical device
+    /// size: The size of the buffer in bytes
+    pub fn new_staging_buffer(device: &RendererVkDevice,
+                              physical_device: &RendererVkPhysicalDevice,
+                              size: usize)
+                              -> RendererVkBuffer {
+        RendererVkBuffer::new(device,
+                              physical_device,
+                              VkBufferUsageFlagBits::VK_BUFFER_USAGE_TRANSFER_DST_BIT as VkBufferUsageFlags,
+                              VkMemoryPropertyFlagBits::VK_MEMORY_PROPERTY_HOST_VISIBLE_BIT as VkMemoryPropertyFlags |
+                              VkMemoryPropertyFlagBits::VK_MEMORY_PROPERTY_HOST_COHERENT_BIT as VkMemoryPropertyFlags,
+                              size)
+    }
+
+    /// Return the staging buffer, for reuse by a later readback once this one has been retrieved
+    pub fn into_buffer(self) -> RendererVkBuffer {
+        self.buffer
+    }
+
+    /// Record the copy of the image into the staging buffer
+    ///
+    /// The image is expected to be in the colour attachment layout, as left by the render pass,
+    /// and is returned to that layout afterwards.
+    ///
+    /// command_buffer: The command buffer to record the copy into
+    pub fn record(&self, command_buffer: &RendererVkCommandBuffer) {
+        command_buffer.image_memory_barrier(self.image,
+                                            VkAccessFlagBits::VK_ACCESS_COLOR_ATTACHMENT_WRITE_BIT as VkAccessFlags,
+                                            VkAccessFlagBits::VK_ACCESS_TRANSFER_READ_BIT as VkAccessFlags,
+                                            VkImageLayout::VK_IMAGE_LAYOUT_COLOR_ATTACHMENT_OPTIMAL,
+                                            VkImageLayout::VK_IMAGE_LAYOUT_TRANSFER_SRC_OPTIMAL,
+                                            VkImageAspectFlagBits::VK_IMAGE_ASPECT_COLOR_BIT as VkImageAspectFlags,
+                                            VkPipelineStageFlagBits::VK_PIPELINE_STAGE_COLOR_ATTACHMENT_OUTPUT_BIT
+                                                as VkPipelineStageFlags,
+                                            VkPipelineStageFlagBits::VK_PIPELINE_STAGE_TRANSFER_BIT as VkPipelineStageFlags);
+
+        let region = VkBufferImageCopy {
+            bufferOffset: 0,
+            bufferRowLength: 0, // Tightly packed
+            bufferImageHeight: 0, // Tightly packed
+            imageSubresource: VkImageSubresourceLayers {
+                aspectMask: VkImageAspectFlagBits::VK_IMAGE_ASPECT_COLOR_BIT as VkImageAspectFlags,
+                mipLevel: 0,
+                baseArrayLayer: 0,
+                layerCount: 1,
+            },
+            imageOffset: VkOffset3D { x: 0, y: 0, z: 0 },
+            imageExtent: VkExtent3D {
+                width: self.width,
+                height: self.height,
+                depth: 1,
+            },
+        };
+
+        unsafe {
+            vkCmdCopyImageToBuffer(command_buffer.raw,
+                                   self.image,
+                                   VkImageLayout::VK_IMAGE_LAYOUT_TRANSFER_SRC_OPTIMAL,
+                                   self.buffer.raw,
+                                   1, // Region count
+                                   &region);
+        }
+
+        command_buffer.image_memory_barrier(self.image,
+                                            VkAccessFlagBits::VK_ACCESS_TRANSFER_READ_BIT as VkAccessFlags,
+                                            VkAccessFlagBits::VK_ACCESS_COLOR_ATTACHMENT_WRITE_BIT as VkAccessFlags,
+                                            VkImageLayout::VK_IMAGE_LAYOUT_TRANSFER_SRC_OPTIMAL,
+                                            VkImageLayout::VK_IMAGE_LAYOUT_COLOR_ATTACHMENT_OPTIMAL,
+                                            VkImageAspectFlagBits::VK_IMAGE_ASPECT_COLOR_BIT as VkImageAspectFlags,
+                                            VkPipelineStageFlagBits::VK_PIPELINE_STAGE_TRANSFER_BIT as VkPipelineStageFlags,
+                                            VkPipelineStageFlagBits::VK_PIPELINE_STAGE_COLOR_ATTACHMENT_OUTPUT_BIT
+                                                as VkPipelineStageFlags);
+
+        // Make the transfer visible to the host before it maps the staging buffer
+        //
+        command_buffer.buffer_memory_barrier(self.buffer.raw,
+                                             VkAccessFlagBits::VK_ACCESS_TRANSFER_WRITE_BIT as VkAccessFlags,
+                                             VkAccessFlagBits::VK_ACCESS_HOST_READ_BIT as VkAccessFlags,
+                                             VkPipelineStageFlagBits::VK_PIPELINE_STAGE_TRANSFER_BIT as VkPipelineStageFlags,
+                                             VkPipelineStageFlagBits::VK_PIPELINE_STAGE_HOST_BIT as VkPipelineStageFlags);
+    }
+
+    /// Map the staging buffer and convert its contents into an image
+    ///
+    /// The command buffer that recorded the copy must have completed execution.
+    pub fn retrieve(&self) -> Image {
+        let mut data: Vec<u8> = vec![];
+        data.resize((self.width * self.height * 3) as usize, 0);
+
+        let mapped = self.buffer.mapped();
+        mapped.invalidate();
+
+        RendererVkTexture::convert_rgba_f32_to_rgb_u8(mapped.as_ptr() as *const f32,
+                                                      self.width,
+                                                      self.height,
+                                                      (self.width * 4) as u64 * mem::size_of::<f32>() as u64,
+                                                      &mut data);
+
+        Image::create_from_raw_data(self.width, self.height, &data)
+    }
+}
+
+pub struct RendererVkTexture {
+    device: VkDevice,
+    queue: VkQueue,
+    #[allow(dead_code)]
+    image: RendererVkImage,
+    view: RendererVkImageView,
+    sampler: VkSampler,
+    width: u32,
+    height: u32,
+    format: VkFormat,
+    row_pitch: u64,
+}
+
+impl RendererVkTexture {
+    /// Return the raw image view for this texture
+    pub fn get_view_raw(&self) -> VkImageView {
+        self.view.raw
+    }
+
+    /// Return the raw image for this texture
+    pub fn get_image_raw(&self) -> VkImage {
+        self.image.raw
+    }
+
+    /// Return the width of this texture
+    pub fn get_width(&self) -> u32 {
+        self.width
+    }
+
+    /// Return the height of this texture
+    pub fn get_height(&self) -> u32 {
+        self.height
+    }
+
+    /// Return the number of layers in this texture
+    pub fn get_layers(&self) -> u32 {
+        self.image.array_layers
+    }
+
+    /// Return the format of this texture
+    pub fn get_format(&self) -> VkFormat {
+        self.format
+    }
+
+    /// Constructor for a Vulkan texture object
+    ///
+    /// When generate_mipmaps is set and the format can be filtered linearly, the full mip chain
+    /// is generated from the data by successive blits, and the sampler uses every level.
+    /// Otherwise the texture has a single level.
+    pub fn new(renderer: &RendererVk,
+               width: u32,
+               height: u32,
+               format: VkFormat,
+               bytes_per_pixel: u32,
+               data: &Vec<u8>,
+               generate_mipmaps: bool)
+               -> RendererVkTexture {
+        let (staging_image, staging_image_layout) = RendererVkTexture::stage(renderer,
+                                                                             width,
+                                                                             height,
+                                                                             format,
+                                                                             bytes_per_pixel,
+                                                                             data);
+
+        // Mip generation blits with linear filtering, which not every format supports
+        //
+        let mip_levels = if generate_mipmaps && data.len() > 0 {
+            let mut props = VkFormatProperties::default();
+            unsafe {
+                vkGetPhysicalDeviceFormatProperties(renderer.physical_device.raw, format, &mut props);
+            }
+
+            let filter_linear = VkFormatFeatureFlagBits::VK_FORMAT_FEATURE_SAMPLED_IMAGE_FILTER_LINEAR_BIT as u32;
+            if (props.optimalTilingFeatures as u32 & filter_linear) == filter_linear {
+                RendererVkImage::full_mip_chain_levels(width, height)
+            } else {
+                log_warning!("Format {:?} cannot be filtered linearly, not generating mipmaps", format);
+                1
+            }
+        } else {
+            1
+        };
+
+        // Create the device-local image to copy into
+        //
+        let image = RendererVkImage::new(&renderer.device,
+                                         &renderer.physical_device,
+                                         &renderer.aux_command_pool,
+                                         width,
+                                         height,
+                                         mip_levels,
+                                         format,
+                                         VkImageTiling::VK_IMAGE_TILING_OPTIMAL,
+                                         VkImageUsageFlagBits::VK_IMAGE_USAGE_TRANSFER_SRC_BIT as VkImageUsageFlags |
+                                         VkImageUsageFlagBits::VK_IMAGE_USAGE_TRANSFER_DST_BIT as VkImageUsageFlags |
+                                         VkImageUsageFlagBits::VK_IMAGE_USAGE_SAMPLED_BIT as VkImageUsageFlags |
+                                         VkImageUsageFlagBits::VK_IMAGE_USAGE_COLOR_ATTACHMENT_BIT as VkImageUsageFlags,
+                                         VkMemoryPropertyFlagBits::VK_MEMORY_PROPERTY_DEVICE_LOCAL_BIT as VkMemoryPropertyFlags,
+                                         VkImageLayout::VK_IMAGE_LAYOUT_PREINITIALIZED,
+                                         VkImageLayout::VK_IMAGE_LAYOUT_TRANSFER_DST_OPTIMAL);
+
+        // Now copy from the staging image to its final location
+        //
+        if data.len() > 0 {
+            RendererVkImage::copy(&renderer.device,
+                                  &renderer.aux_command_pool,
+                                  staging_image.raw,
+                                  image.raw,
+                                  width,
+                                  height);
+        }
+
+        // The final image needs to be in a layout suitable for being used in the shader, which
+        // mip generation leaves it in
+        //
+        if mip_levels > 1 {
+            image.generate_mipmaps(&renderer.device, &renderer.aux_command_pool, width, height);
+        } else {
+            RendererVkImage::transition_layout_immediate(image.raw,
+                                               &renderer.device,
+                                               &renderer.aux_command_pool,
+                                               VkImageAspectFlagBits::VK_IMAGE_ASPECT_COLOR_BIT as VkImageAspectFlags,
+                                               VkImageLayout::VK_IMAGE_LAYOUT_TRANSFER_DST_OPTIMAL,
+                                               VkImageLayout::VK_IMAGE_LAYOUT_SHADER_READ_ONLY_OPTIMAL,
+                                               VkPipelineStageFlagBits::VK_PIPELINE_STAGE_TOP_OF_PIPE_BIT as VkPipelineStageFlags,
+                                               VkPipelineStageFlagBits::VK_PIPELINE_STAGE_TOP_OF_PIPE_BIT as VkPipelineStageFlags);
+        }
+
+        // Create an image view for the texture in its final location
+        //
+        let view = RendererVkImageView::new(&renderer.device,
+                                            &image,
+                                            format,
+                                            VkImageAspectFlagBits::VK_IMAGE_ASPECT_COLOR_BIT as VkImageAspectFlags);
+
+        // Create a sampler for the texture
+        //
+        let sampler = RendererVkTexture::create_sampler(&renderer.device, mip_levels);
+
+        RendererVkTexture {
+            device: renderer.device.raw,
+            queue: renderer.device.graphics_queue,
+            image: image,
+            view: view,
+            sampler: sampler,
+            width: width,
+            height: height,
+            format: format,
+            row_pitch: staging_image_layout.rowPitch,
+        }
+    }
+
+    /// Create a host-accessible staging image holding the data, ready to be copied from
+    ///
+    /// Returns the staging image and its layout, whose row pitch may include padding.
+    fn stage(renderer: &RendererVk,
+             width: u32,
+             height: u32,
+             format: VkFormat,
+             bytes_per_pixel: u32,
+             data: &Vec<u8>)
+             -> (RendererVkImage, VkSubresourceLayout) {
+        // Create a new host-accessible staging image to format the image data into
+        //
+        let props = VkMemoryPropertyFlagBits::VK_MEMORY_PROPERTY_HOST_VISIBLE_BIT as VkMemoryPropertyFlags |
+                    VkMemoryPropertyFlagBits::VK_MEMORY_PROPERTY_HOST_COHERENT_BIT as VkMemoryPropertyFlags;
+
+        let staging_image = RendererVkImage::new(&renderer.device,
+                                                 &renderer.physical_device,
+                                                 &renderer.aux_command_pool,
+                                                 width,
+                                                 height,
+                                                 1,
+                                                 format,
+                                                 VkImageTiling::VK_IMAGE_TILING_LINEAR,
+                                                 VkImageUsageFlagBits::VK_IMAGE_USAGE_TRANSFER_SRC_BIT as VkImageUsageFlags,
+                                                 props,
+                                                 VkImageLayout::VK_IMAGE_LAYOUT_PREINITIALIZED,
+                                                 VkImageLayout::VK_IMAGE_LAYOUT_PREINITIALIZED);
+
+        // Query the subresource layout information
+        //
+        let subresource = VkImageSubresource {
+            aspectMask: VkImageAspectFlagBits::VK_IMAGE_ASPECT_COLOR_BIT as VkImageAspectFlags,
+            mipLevel: 0,
+            arrayLayer: 0,
+        };
+
+        let mut staging_image_layout = VkSubresourceLayout::default();
+        unsafe {
+            vkGetImageSubresourceLayout(renderer.device.raw,
+                                        staging_image.raw,
+                                        &subresource,
+                                        &mut staging_image_layout);
+        }
+
+        if data.len() > 0 {
+            // Reformat the raw image data into the image's host-addressable memory
+            //
+            let raw = staging_image.mapped_pointer();
+
+            // TODO: Optimise this when the image layout contains no padding
+            let raw_u8 = raw as *mut u8;
+
+            unsafe {
+                for y in 0..height {
+                    ptr::copy_nonoverlapping(&data[(y * width * bytes_per_pixel) as usize],
+                                             raw_u8.offset(y as isize * staging_image_layout.rowPitch as isize),
+                                             (width * bytes_per_pixel) as usize);
+                }
+            }
+        }
+
+        // The staging image needs to be in a layout suitable for being the source of a copy
+        //
+        RendererVkImage::transition_layout_immediate(staging_image.raw,
+                                           &renderer.device,
+                                           &renderer.aux_command_pool,
+                                           VkImageAspectFlagBits::VK_IMAGE_ASPECT_COLOR_BIT as VkImageAspectFlags,
+                                           VkImageLayout::VK_IMAGE_LAYOUT_PREINITIALIZED,
+                                           VkImageLayout::VK_IMAGE_LAYOUT_TRANSFER_SRC_OPTIMAL,
+                                           VkPipelineStageFlagBits::VK_PIPELINE_STAGE_TOP_OF_PIPE_BIT as VkPipelineStageFlags,
+                                           VkPipelineStageFlagBits::VK_PIPELINE_STAGE_TOP_OF_PIPE_BIT as VkPipelineStageFlags);
+
+        (staging_image, staging_image_layout)
+    }
+
+    /// Create a texture with several layers, to be filled a layer at a time with upload_layer
+    ///
+    /// A cubemap has six square layers, one per face in the order +X, -X, +Y, -Y, +Z, -Z, and
+    /// the view is a cube rather than a 2D array.  Layered textures have a single mip level.
+    ///
+    /// renderer: The renderer object
+    /// width: The width of each layer
+    /// height: The height of each layer
+    /// layers: The number of layers
+    /// cube: Whether the texture is a cubemap
+    /// format: The format of the texture
+    /// bytes_per_pixel: The size of a pixel of the data in bytes
+    pub fn new_layered(renderer: &RendererVk,
+                       width: u32,
+                       height: u32,
+                       layers: u32,
+                       cube: bool,
+                       format: VkFormat,
+                       bytes_per_pixel: u32)
+                       -> RendererVkTexture {
+        let image = RendererVkImage::new_layered(&renderer.device,
+                                                 &renderer.physical_device,
+                                                 &renderer.aux_command_pool,
+                                                 width,
+                                                 height,
+                                                 1,
+                                                 layers,
+                                                 cube,
+                                                 VkSampleCountFlagBits::VK_SAMPLE_COUNT_1_BIT,
+                                                 format,
+                                                 VkImageTiling::VK_IMAGE_TILING_OPTIMAL,
+                                                 VkImageUsageFlagBits::VK_IMAGE_USAGE_TRANSFER_DST_BIT as VkImageUsageFlags |
+                                                 VkImageUsageFlagBits::VK_IMAGE_USAGE_SAMPLED_BIT as VkImageUsageFlags |
+                                                 VkImageUsageFlagBits::VK_IMAGE_USAGE_COLOR_ATTACHMENT_BIT as VkImageUsageFlags,
+                                                 VkMemoryPropertyFlagBits::VK_MEMORY_PROPERTY_DEVICE_LOCAL_BIT as VkMemoryPropertyFlags,
+                                                 VkImageLayout::VK_IMAGE_LAYOUT_UNDEFINED,
+                                                 VkImageLayout::VK_IMAGE_LAYOUT_SHADER_READ_ONLY_OPTIMAL,
+                                                 &vec![]);
+
+        let view = RendererVkImageView::new(&renderer.device,
+                                            &image,
+                                            format,
+                                            VkImageAspectFlagBits::VK_IMAGE_ASPECT_COLOR_BIT as VkImageAspectFlags);
+        let sampler = RendererVkTexture::create_sampler(&renderer.device, 1);
+
+        RendererVkTexture {
+            device: renderer.device.raw,
+            queue: renderer.device.graphics_queue,
+            image: image,
+            view: view,
+            sampler: sampler,
+            width: width,
+            height: height,
+            format: format,
+            row_pitch: (width * bytes_per_pixel) as u64,
+        }
+    }
+
+    /// Replace the contents of one layer, or cubemap face, of the texture
+    ///
+    /// The texture must not be in use by a frame in flight.
+    ///
+    /// renderer: The renderer object
+    /// layer: The layer to replace
+    /// bytes_per_pixel: The size of a pixel of the data in bytes
+    /// data: The image data for the layer
+    pub fn upload_layer(&self, renderer: &RendererVk, layer: u32, bytes_per_pixel: u32, data: &Vec<u8>) {
+        if layer >= self.image.array_layers {
+            panic!("Layer {} is out of range for a texture of {} layers", layer, self.image.array_layers);
+        }
+
+        let (staging_image, _) = RendererVkTexture::stage(renderer,
+                                                          self.width,
+                                                          self.height,
+                                                          self.format,
+                                                          bytes_per_pixel,
+                                                          data);
+
+        // Earlier passes sampling the layer must read it before it is replaced
+        renderer.flush_queued_command_buffers();
+
+        let colour_aspect = VkImageAspectFlagBits::VK_IMAGE_ASPECT_COLOR_BIT as VkImageAspectFlags;
+        let top_of_pipe = VkPipelineStageFlagBits::VK_PIPELINE_STAGE_TOP_OF_PIPE_BIT as VkPipelineStageFlags;
+
+        RendererVkImage::transition_layout_immediate(self.image.raw,
+                                                     &renderer.device,
+                                                     &renderer.aux_command_pool,
+                                                     colour_aspect,
+                                                     VkImageLayout::VK_IMAGE_LAYOUT_SHADER_READ_ONLY_OPTIMAL,
+                                                     VkImageLayout::VK_IMAGE_LAYOUT_TRANSFER_DST_OPTIMAL,
+                                                     top_of_pipe,
+                                                     top_of_pipe);
+
+        RendererVkImage::copy_to_layer(&renderer.device,
+                                       &renderer.aux_command_pool,
+                                       staging_image.raw,
+                                       self.image.raw,
+                                       self.width,
+                                       self.height,
+                                       layer);
+
+        RendererVkImage::transition_layout_immediate(self.image.raw,
+                                                     &renderer.device,
+                                                     &renderer.aux_command_pool,
+                                                     colour_aspect,
+                                                     VkImageLayout::VK_IMAGE_LAYOUT_TRANSFER_DST_OPTIMAL,
+                                                     VkImageLayout::VK_IMAGE_LAYOUT_SHADER_READ_ONLY_OPTIMAL,
+                                                     top_of_pipe,
+                                                     top_of_pipe);
+    }
+
+    /// Replace the contents of a rectangle of the top mip level of a plain texture
+    ///
+    /// The data goes through a staging buffer and is copied straight into the rectangle of the
+    /// image, waiting for the copy to complete.
+    ///
+    /// renderer: The renderer object
+    /// x: The column of the left edge of the rectangle
+    /// y: The row of the first row of the rectangle
+    /// width: The width of the rectangle
+    /// height: The height of the rectangle
+    /// bytes_per_pixel: The size of a pixel of the data in bytes
+    /// data: The image data for the rectangle
+    pub fn update_region(&self,
+                         renderer: &RendererVk,
+                         x: u32,
+                         y: u32,
+                         width: u32,
+                         height: u32,
+                         bytes_per_pixel: u32,
+                         data: &Vec<u8>) {
+        if let Err(e) = check_texture_region(self.width, self.height, x, y, width, height, bytes_per_pixel, data.len()) {
+            panic!("Unable to update a texture region: {}", e);
+        }
+
+        let host_properties = VkMemoryPropertyFlagBits::VK_MEMORY_PROPERTY_HOST_VISIBLE_BIT as VkMemoryPropertyFlags |
+                              VkMemoryPropertyFlagBits::VK_MEMORY_PROPERTY_HOST_COHERENT_BIT as VkMemoryPropertyFlags;
+        let staging_buffer = RendererVkBuffer::new(&renderer.device,
+                                                   &renderer.physical_device,
+                                                   VkBufferUsageFlagBits::VK_BUFFER_USAGE_TRANSFER_SRC_BIT as VkBufferUsageFlags,
+                                                   host_properties,
+                                                   data.len());
+
+        let mapped = staging_buffer.mapped();
+        unsafe {
+            ptr::copy_nonoverlapping(data.as_ptr(), mapped.as_ptr() as *mut u8, data.len());
+        }
+        mapped.flush();
+
+        // Earlier passes sampling the texture must read it before the rectangle is replaced
+        renderer.flush_queued_command_buffers();
+
+        let colour_aspect = VkImageAspectFlagBits::VK_IMAGE_ASPECT_COLOR_BIT as VkImageAspectFlags;
+        let all_commands = VkPipelineStageFlagBits::VK_PIPELINE_STAGE_ALL_COMMANDS_BIT as VkPipelineStageFlags;
+        let transfer = VkPipelineStageFlagBits::VK_PIPELINE_STAGE_TRANSFER_BIT as VkPipelineStageFlags;
+
+        let mut one_time = OneTimeCommandBuffer::new(&renderer.device, &renderer.aux_command_pool);
+
+        one_time.buffer.image_memory_barrier(self.image.raw,
+                                             VkAccessFlagBits::VK_ACCESS_SHADER_READ_BIT as VkAccessFlags,
+                                             VkAccessFlagBits::VK_ACCESS_TRANSFER_WRITE_BIT as VkAccessFlags,
+                                             VkImageLayout::VK_IMAGE_LAYOUT_SHADER_READ_ONLY_OPTIMAL,
+                                             VkImageLayout::VK_IMAGE_LAYOUT_TRANSFER_DST_OPTIMAL,
+                                             colour_aspect,
+                                             all_commands,
+                                             transfer);
+
+        let region = VkBufferImageCopy {
+            bufferOffset: 0,
+            bufferRowLength: 0, // Tightly packed
+            bufferImageHeight: 0, // Tightly packed
+            imageSubresource: VkImageSubresourceLayers {
+                aspectMask: colour_aspect,
+                mipLevel: 0,
+                baseArrayLayer: 0,
+                layerCount: 1,
+            },
+            imageOffset: VkOffset3D {
+                x: x as i32,
+                y: y as i32,
+                z: 0,
+            },
+            imageExtent: VkExtent3D {
+                width: width,
+                height: height,
+                depth: 1,
+            },
         };
 
-        let viewport_state = VkPipelineViewportStateCreateInfo {
-            sType: VkStructureType::VK_STRUCTURE_TYPE_PIPELINE_VIEWPORT_STATE_CREATE_INFO,
-            viewportCount: 1,
-            pViewports: &viewport,
-            scissorCount: 1,
-            pScissors: &scissor,
-            flags: 0,
-            pNext: ptr::null(),
+        unsafe {
+            vkCmdCopyBufferToImage(one_time.buffer.raw,
+                                   staging_buffer.raw,
+                                   self.image.raw,
+                                   VkImageLayout::VK_IMAGE_LAYOUT_TRANSFER_DST_OPTIMAL,
+                                   1, // Region count
+                                   &region);
+        }
+
+        one_time.buffer.image_memory_barrier(self.image.raw,
+                                             VkAccessFlagBits::VK_ACCESS_TRANSFER_WRITE_BIT as VkAccessFlags,
+                                             VkAccessFlagBits::VK_ACCESS_SHADER_READ_BIT as VkAccessFlags,
+                                             VkImageLayout::VK_IMAGE_LAYOUT_TRANSFER_DST_OPTIMAL,
+                                             VkImageLayout::VK_IMAGE_LAYOUT_SHADER_READ_ONLY_OPTIMAL,
+                                             colour_aspect,
+                                             transfer,
+                                             all_commands);
+
+        one_time.execute();
+    }
+
+    /// Start uploading a texture on the transfer queue, returning without waiting for the copy
+    ///
+    /// The copy goes through a tightly-packed staging buffer.  Where there is a dedicated
+    /// transfer queue the image is shared between it and the graphics queue, so no ownership
+    /// transfer is needed.  Transfer queues cannot blit, so the texture has a single mip level.
+    ///
+    /// Returns an error if the data is too small for the texture or the upload cannot be started.
+    ///
+    /// renderer: The renderer object
+    /// width: The width of the texture
+    /// height: The height of the texture
+    /// format: The format of the texture
+    /// bytes_per_pixel: The size of a pixel of the data in bytes
+    /// data: The image data
+    pub fn upload_async(renderer: &RendererVk,
+                        width: u32,
+                        height: u32,
+                        format: VkFormat,
+                        bytes_per_pixel: u32,
+                        data: &Vec<u8>)
+                        -> Result<RendererVkTextureUpload, RendererError> {
+        let size = (width * height * bytes_per_pixel) as usize;
+        if data.len() < size {
+            return Err(RendererError::InvalidData(format!("{} bytes of texture data for a {}x{} texture of {} bytes",
+                                                          data.len(),
+                                                          width,
+                                                          height,
+                                                          size)));
+        }
+
+        let fence = RendererVkFence::new(&renderer.device, false /* signalled */)?;
+
+        let staging_buffer = RendererVkBuffer::new(&renderer.device,
+                                                   &renderer.physical_device,
+                                                   VkBufferUsageFlagBits::VK_BUFFER_USAGE_TRANSFER_SRC_BIT as VkBufferUsageFlags,
+                                                   VkMemoryPropertyFlagBits::VK_MEMORY_PROPERTY_HOST_VISIBLE_BIT as VkMemoryPropertyFlags |
+                                                   VkMemoryPropertyFlagBits::VK_MEMORY_PROPERTY_HOST_COHERENT_BIT as VkMemoryPropertyFlags,
+                                                   size);
+
+        let mapped = staging_buffer.mapped();
+        unsafe {
+            ptr::copy_nonoverlapping(data.as_ptr(), mapped.as_ptr() as *mut u8, size);
+        }
+        mapped.flush();
+
+        let queue_family_indices = if renderer.device.has_transfer_queue() {
+            vec![renderer.device.graphics_queue_family_index,
+                 renderer.device.transfer_queue_family_index]
+        } else {
+            vec![]
         };
 
-        let rasterizer = VkPipelineRasterizationStateCreateInfo {
-            sType: VkStructureType::VK_STRUCTURE_TYPE_PIPELINE_RASTERIZATION_STATE_CREATE_INFO,
-            depthClampEnable: false as VkBool32,
-            rasterizerDiscardEnable: false as VkBool32,
-            polygonMode: VkPolygonMode::VK_POLYGON_MODE_FILL,
-            lineWidth: 1.0f32,
-            cullMode: VkCullModeFlagBits::VK_CULL_MODE_NONE as VkCullModeFlags,
-            frontFace: VkFrontFace::VK_FRONT_FACE_COUNTER_CLOCKWISE,
-            depthBiasEnable: false as VkBool32,
-            depthBiasConstantFactor: 0.0f32, // Optional
-            depthBiasClamp: 0.0f32, // Optional
-            depthBiasSlopeFactor: 0.0f32, // Optional
-            flags: 0,
-            pNext: ptr::null(),
+        // The image is left in its initial layout here, as the layout transitions are recorded
+        // into the transfer command buffer alongside the copy
+        //
+        let image = RendererVkImage::new_shared(&renderer.device,
+                                                &renderer.physical_device,
+                                                &renderer.transfer_command_pool,
+                                                width,
+                                                height,
+                                                1, // Mip levels
+                                                VkSampleCountFlagBits::VK_SAMPLE_COUNT_1_BIT,
+                                                format,
+                                                VkImageTiling::VK_IMAGE_TILING_OPTIMAL,
+                                                VkImageUsageFlagBits::VK_IMAGE_USAGE_TRANSFER_SRC_BIT as VkImageUsageFlags |
+                                                VkImageUsageFlagBits::VK_IMAGE_USAGE_TRANSFER_DST_BIT as VkImageUsageFlags |
+                                                VkImageUsageFlagBits::VK_IMAGE_USAGE_SAMPLED_BIT as VkImageUsageFlags,
+                                                VkMemoryPropertyFlagBits::VK_MEMORY_PROPERTY_DEVICE_LOCAL_BIT as VkMemoryPropertyFlags,
+                                                VkImageLayout::VK_IMAGE_LAYOUT_PREINITIALIZED,
+                                                VkImageLayout::VK_IMAGE_LAYOUT_PREINITIALIZED,
+                                                &queue_family_indices);
+
+        let command_buffer = RendererVkCommandBuffer::new(&renderer.device, &renderer.transfer_command_pool, true /* primary */);
+        command_buffer.begin_primary(true, // one_time_submit
+                                     false, // render_pass_continue
+                                     false /* simultaneous_use */);
+
+        command_buffer.image_memory_barrier(image.raw,
+                                            0,
+                                            VkAccessFlagBits::VK_ACCESS_TRANSFER_WRITE_BIT as VkAccessFlags,
+                                            VkImageLayout::VK_IMAGE_LAYOUT_PREINITIALIZED,
+                                            VkImageLayout::VK_IMAGE_LAYOUT_TRANSFER_DST_OPTIMAL,
+                                            VkImageAspectFlagBits::VK_IMAGE_ASPECT_COLOR_BIT as VkImageAspectFlags,
+                                            VkPipelineStageFlagBits::VK_PIPELINE_STAGE_TOP_OF_PIPE_BIT as VkPipelineStageFlags,
+                                            VkPipelineStageFlagBits::VK_PIPELINE_STAGE_TRANSFER_BIT as VkPipelineStageFlags);
+
+        let region = VkBufferImageCopy {
+            bufferOffset: 0,
+            bufferRowLength: 0, // Tightly packed
+            bufferImageHeight: 0, // Tightly packed
+            imageSubresource: VkImageSubresourceLayers {
+                aspectMask: VkImageAspectFlagBits::VK_IMAGE_ASPECT_COLOR_BIT as VkImageAspectFlags,
+                mipLevel: 0,
+                baseArrayLayer: 0,
+                layerCount: 1,
+            },
+            imageOffset: VkOffset3D { x: 0, y: 0, z: 0 },
+            imageExtent: VkExtent3D {
+                width: width,
+                height: height,
+                depth: 1,
+            },
         };
 
-        let multisampling = VkPipelineMultisampleStateCreateInfo {
-            sType: VkStructureType::VK_STRUCTURE_TYPE_PIPELINE_MULTISAMPLE_STATE_CREATE_INFO,
-            sampleShadingEnable: false as VkBool32,
-            rasterizationSamples: VkSampleCountFlagBits::VK_SAMPLE_COUNT_1_BIT,
-            minSampleShading: 1.0f32, // Optional
-            pSampleMask: ptr::null(), // Optional
-            alphaToCoverageEnable: false as VkBool32, // Optional
-            alphaToOneEnable: false as VkBool32, // Optional
-            flags: 0,
+        unsafe {
+            vkCmdCopyBufferToImage(command_buffer.raw,
+                                   staging_buffer.raw,
+                                   image.raw,
+                                   VkImageLayout::VK_IMAGE_LAYOUT_TRANSFER_DST_OPTIMAL,
+                                   1, // Region count
+                                   &region);
+        }
+
+        // The transfer queue cannot wait on shader stages, so the barrier only orders the
+        // transition after the copy; the fence then makes the upload visible to later submissions
+        //
+        command_buffer.image_memory_barrier(image.raw,
+                                            VkAccessFlagBits::VK_ACCESS_TRANSFER_WRITE_BIT as VkAccessFlags,
+                                            0,
+                                            VkImageLayout::VK_IMAGE_LAYOUT_TRANSFER_DST_OPTIMAL,
+                                            VkImageLayout::VK_IMAGE_LAYOUT_SHADER_READ_ONLY_OPTIMAL,
+                                            VkImageAspectFlagBits::VK_IMAGE_ASPECT_COLOR_BIT as VkImageAspectFlags,
+                                            VkPipelineStageFlagBits::VK_PIPELINE_STAGE_TRANSFER_BIT as VkPipelineStageFlags,
+                                            VkPipelineStageFlagBits::VK_PIPELINE_STAGE_BOTTOM_OF_PIPE_BIT as VkPipelineStageFlags);
+
+        command_buffer.end();
+
+        let command_buffers = vec![command_buffer.raw];
+        let submit_info = VkSubmitInfo {
+            sType: VkStructureType::VK_STRUCTURE_TYPE_SUBMIT_INFO,
+            commandBufferCount: command_buffers.len() as u32,
+            pCommandBuffers: command_buffers.as_ptr(),
+            waitSemaphoreCount: 0,
+            pWaitSemaphores: ptr::null(),
+            pWaitDstStageMask: ptr::null(),
+            signalSemaphoreCount: 0,
+            pSignalSemaphores: ptr::null(),
             pNext: ptr::null(),
         };
 
-        let color_blend_attachment = if shader_spec.alpha_blending_enabled {
-            VkPipelineColorBlendAttachmentState {
-                colorWriteMask: VkColorComponentFlagBits::VK_COLOR_COMPONENT_R_BIT as VkColorComponentFlags |
-                                VkColorComponentFlagBits::VK_COLOR_COMPONENT_G_BIT as VkColorComponentFlags |
-                                VkColorComponentFlagBits::VK_COLOR_COMPONENT_B_BIT as VkColorComponentFlags |
-                                VkColorComponentFlagBits::VK_COLOR_COMPONENT_A_BIT as VkColorComponentFlags,
-                blendEnable: true as VkBool32,
-                srcColorBlendFactor: VkBlendFactor::VK_BLEND_FACTOR_SRC_ALPHA,
-                dstColorBlendFactor: VkBlendFactor::VK_BLEND_FACTOR_ONE_MINUS_SRC_ALPHA,
-                colorBlendOp: VkBlendOp::VK_BLEND_OP_ADD,
-                srcAlphaBlendFactor: VkBlendFactor::VK_BLEND_FACTOR_ONE,
-                dstAlphaBlendFactor: VkBlendFactor::VK_BLEND_FACTOR_ZERO,
-                alphaBlendOp: VkBlendOp::VK_BLEND_OP_ADD, // Optional
-            }
-        } else {
-            VkPipelineColorBlendAttachmentState {
-                colorWriteMask: VkColorComponentFlagBits::VK_COLOR_COMPONENT_R_BIT as VkColorComponentFlags |
-                                VkColorComponentFlagBits::VK_COLOR_COMPONENT_G_BIT as VkColorComponentFlags |
-                                VkColorComponentFlagBits::VK_COLOR_COMPONENT_B_BIT as VkColorComponentFlags |
-                                VkColorComponentFlagBits::VK_COLOR_COMPONENT_A_BIT as VkColorComponentFlags,
-                blendEnable: true as VkBool32,
-                srcColorBlendFactor: VkBlendFactor::VK_BLEND_FACTOR_ONE,
-                dstColorBlendFactor: VkBlendFactor::VK_BLEND_FACTOR_ZERO,
-                colorBlendOp: VkBlendOp::VK_BLEND_OP_ADD,
-                srcAlphaBlendFactor: VkBlendFactor::VK_BLEND_FACTOR_ONE,
-                dstAlphaBlendFactor: VkBlendFactor::VK_BLEND_FACTOR_ZERO,
-                alphaBlendOp: VkBlendOp::VK_BLEND_OP_ADD,
-            }
-        };
-
-        let color_blending = if shader_spec.alpha_blending_enabled {
-            VkPipelineColorBlendStateCreateInfo {
-                sType: VkStructureType::VK_STRUCTURE_TYPE_PIPELINE_COLOR_BLEND_STATE_CREATE_INFO,
-                logicOpEnable: false as VkBool32,
-                logicOp: VkLogicOp::VK_LOGIC_OP_COPY,
-                attachmentCount: 1,
-                pAttachments: &color_blend_attachment,
-                blendConstants: [0.0f32, 0.0f32, 0.0f32, 0.0f32],
-                flags: 0,
-                pNext: ptr::null(),
-            }
-        } else {
-            VkPipelineColorBlendStateCreateInfo {
-                sType: VkStructureType::VK_STRUCTURE_TYPE_PIPELINE_COLOR_BLEND_STATE_CREATE_INFO,
-                logicOpEnable: false as VkBool32,
-                logicOp: VkLogicOp::VK_LOGIC_OP_COPY,
-                attachmentCount: 1,
-                pAttachments: &color_blend_attachment,
-                blendConstants: [0.0f32, 0.0f32, 0.0f32, 0.0f32],
-                flags: 0,
-                pNext: ptr::null(),
-            }
+        let res = match injected_failure("vkQueueSubmit") {
+            Some(failure) => injected_result(failure),
+            None => unsafe { vkQueueSubmit(renderer.device.transfer_queue, 1, &submit_info, fence.raw) },
         };
+        if !matches!(res, VkResult::VK_SUCCESS) {
+            unsafe {
+                vkFreeCommandBuffers(renderer.device.raw, renderer.transfer_command_pool.raw, 1, &command_buffer.raw);
+            }
+            return Err(RendererError::ApiCallFailed("vkQueueSubmit", format!("{}", res)));
+        }
 
-        let tessellation_state_create_info = VkPipelineTessellationStateCreateInfo {
-            sType: VkStructureType::VK_STRUCTURE_TYPE_PIPELINE_TESSELLATION_STATE_CREATE_INFO,
-            patchControlPoints: 3, // To agree with the tessellation control and evaluation shaders
+        let view = RendererVkImageView::new(&renderer.device,
+                                            &image,
+                                            format,
+                                            VkImageAspectFlagBits::VK_IMAGE_ASPECT_COLOR_BIT as VkImageAspectFlags);
+        let sampler = RendererVkTexture::create_sampler(&renderer.device, 1);
+
+        Ok(RendererVkTextureUpload {
+            device: renderer.device.raw,
+            command_pool: renderer.transfer_command_pool.raw,
+            command_buffer: command_buffer,
+            fence: fence,
+            staging_buffer: staging_buffer,
+            texture: Some(RendererVkTexture {
+                device: renderer.device.raw,
+                queue: renderer.device.graphics_queue,
+                image: image,
+                view: view,
+                sampler: sampler,
+                width: width,
+                height: height,
+                format: format,
+                row_pitch: (width * bytes_per_pixel) as u64,
+            }),
+        })
+    }
+
+    /// Create a linearly-filtered, repeating sampler covering the mip levels specified
+    ///
+    /// device: The Vulkan device
+    /// mip_levels: The number of mip levels in the texture
+    fn create_sampler(device: &RendererVkDevice, mip_levels: u32) -> VkSampler {
+        let sampler_info = VkSamplerCreateInfo {
+            sType: VkStructureType::VK_STRUCTURE_TYPE_SAMPLER_CREATE_INFO,
+            magFilter: VkFilter::VK_FILTER_LINEAR,
+            minFilter: VkFilter::VK_FILTER_LINEAR,
+            addressModeU: VkSamplerAddressMode::VK_SAMPLER_ADDRESS_MODE_REPEAT,
+            addressModeV: VkSamplerAddressMode::VK_SAMPLER_ADDRESS_MODE_REPEAT,
+            addressModeW: VkSamplerAddressMode::VK_SAMPLER_ADDRESS_MODE_REPEAT,
+            anisotropyEnable: device.features.sampler_anisotropy as VkBool32,
+            maxAnisotropy: 16.0,
+            borderColor: VkBorderColor::VK_BORDER_COLOR_INT_OPAQUE_BLACK,
+            unnormalizedCoordinates: false as VkBool32,
+            compareEnable: false as VkBool32,
+            compareOp: VkCompareOp::VK_COMPARE_OP_ALWAYS,
+            mipmapMode: VkSamplerMipmapMode::VK_SAMPLER_MIPMAP_MODE_LINEAR,
+            mipLodBias: 0.0f32,
+            minLod: 0.0f32,
+            maxLod: mip_levels as f32,
             flags: 0,
-            pNext: ptr::null(),
+            pNext: ptr::null_mut(),
         };
 
-        let entrypoint_name = CString::new("main").unwrap().into_raw();
+        let mut sampler: VkSampler = VK_NULL_HANDLE_MUT();
+        unsafe {
+            check_result!("vkCreateSampler",
+                          vkCreateSampler(device.raw,
+                                          &sampler_info,
+                                          ptr::null(),
+                                          &mut sampler));
+        }
+
+        sampler
+    }
+
+    /// Obtain the pixel contents of a Vulkan texture object
+    ///
+    /// TODO: Optimise this, maybe
+    ///
+    ///
+    pub fn read_pixels(&self, renderer: &RendererVk) -> Vec<u8> {
+        renderer.flush_queued_command_buffers();
+
+        let mut data: Vec<u8> = vec![];
+        let num_bytes: usize = (self.height * self.width * 3) as usize;
+        data.resize(num_bytes, 0);
+
+        // Create a new host-accessible staging image to format the image data into
+        //
+        let props = VkMemoryPropertyFlagBits::VK_MEMORY_PROPERTY_HOST_VISIBLE_BIT as VkMemoryPropertyFlags |
+                    VkMemoryPropertyFlagBits::VK_MEMORY_PROPERTY_HOST_COHERENT_BIT as VkMemoryPropertyFlags;
+
+        let staging_image = RendererVkImage::new(&renderer.device,
+                                                 &renderer.physical_device,
+                                                 &renderer.aux_command_pool,
+                                                 self.width,
+                                                 self.height,
+                                                 1,
+                                                 self.format, // VkFormat::VK_FORMAT_R8G8B8A8_UNORM,
+                                                 VkImageTiling::VK_IMAGE_TILING_LINEAR,
+                                                 VkImageUsageFlagBits::VK_IMAGE_USAGE_TRANSFER_DST_BIT as VkImageUsageFlags,
+                                                 props,
+                                                 VkImageLayout::VK_IMAGE_LAYOUT_PREINITIALIZED,
+                                                 VkImageLayout::VK_IMAGE_LAYOUT_TRANSFER_DST_OPTIMAL);
+
+        // Transition the render target to something that we can transfer from
+        //
+        RendererVkImage::transition_layout_immediate(self.image.raw,
+                                           &renderer.device,
+                                           &renderer.aux_command_pool,
+                                           VkImageAspectFlagBits::VK_IMAGE_ASPECT_COLOR_BIT as VkImageAspectFlags,
+                                           VkImageLayout::VK_IMAGE_LAYOUT_COLOR_ATTACHMENT_OPTIMAL,
+                                           VkImageLayout::VK_IMAGE_LAYOUT_TRANSFER_SRC_OPTIMAL,
+                                           VkPipelineStageFlagBits::VK_PIPELINE_STAGE_TOP_OF_PIPE_BIT as VkPipelineStageFlags,
+                                           VkPipelineStageFlagBits::VK_PIPELINE_STAGE_TOP_OF_PIPE_BIT as VkPipelineStageFlags);
+
+        // Now copy from the device local memory to the staging image
+        //
+        RendererVkImage::copy(&renderer.device,
+                              &renderer.aux_command_pool,
+                              self.image.raw,
+                              staging_image.raw,
+                              self.width,
+                              self.height);
+
+        // Transition the render target back to something that we can render to
+        //
+        RendererVkImage::transition_layout_immediate(self.image.raw,
+                                           &renderer.device,
+                                           &renderer.aux_command_pool,
+                                           VkImageAspectFlagBits::VK_IMAGE_ASPECT_COLOR_BIT as VkImageAspectFlags,
+                                           VkImageLayout::VK_IMAGE_LAYOUT_TRANSFER_SRC_OPTIMAL,
+                                           VkImageLayout::VK_IMAGE_LAYOUT_COLOR_ATTACHMENT_OPTIMAL,
+                                           VkPipelineStageFlagBits::VK_PIPELINE_STAGE_TOP_OF_PIPE_BIT as VkPipelineStageFlags,
+                                           VkPipelineStageFlagBits::VK_PIPELINE_STAGE_TOP_OF_PIPE_BIT as VkPipelineStageFlags);
+
+        // Transition the staging image to something that we can map
+        //
+        RendererVkImage::transition_layout_immediate(staging_image.raw,
+                                           &renderer.device,
+                                           &renderer.aux_command_pool,
+                                           VkImageAspectFlagBits::VK_IMAGE_ASPECT_COLOR_BIT as VkImageAspectFlags,
+                                           VkImageLayout::VK_IMAGE_LAYOUT_TRANSFER_DST_OPTIMAL,
+                                           VkImageLayout::VK_IMAGE_LAYOUT_GENERAL,
+                                           VkPipelineStageFlagBits::VK_PIPELINE_STAGE_TOP_OF_PIPE_BIT as VkPipelineStageFlags,
+                                           VkPipelineStageFlagBits::VK_PIPELINE_STAGE_TOP_OF_PIPE_BIT as VkPipelineStageFlags);
+
+        // The staging image's memory is mapped for its lifetime
+        //
+        let raw = staging_image.mapped_pointer();
+
+        RendererVkTexture::convert_rgba_f32_to_rgb_u8(raw as *const f32, self.width, self.height, self.row_pitch, &mut data);
+
+        data
+    }
+
+    /// Convert mapped RGBA floating point pixels to RGB bytes, flipping vertically
+    ///
+    /// raw_f32: The mapped source pixels
+    /// width: The width of the source in pixels
+    /// height: The height of the source in pixels
+    /// row_pitch: The distance between the starts of successive source rows, in bytes
+    /// data: The destination, which must hold width * height * 3 bytes
+    pub fn convert_rgba_f32_to_rgb_u8(raw_f32: *const f32, width: u32, height: u32, row_pitch: u64, data: &mut Vec<u8>) {
+        unsafe {
+            for y in 0..height {
+                for x in 0..width {
+                    for i in 0..3 {
+                        let f = *raw_f32.offset(((y as u64 * (row_pitch >> 2) + 4 * x as u64) + i as u64) as isize);
+                        data[(((height - 1 - y) * width + x) * 3 + i) as usize] = (f * 255.0) as u8;
+                    }
+                }
+            }
+        }
+    }
+}
+
+/// An upload of texture data in flight on the transfer queue
+///
+/// The texture may only be used once the upload is complete, so either poll is_complete or
+/// call wait before taking it with into_texture, which waits if need be.
+pub struct RendererVkTextureUpload {
+    device: VkDevice,
+    command_pool: VkCommandPool,
+    command_buffer: RendererVkCommandBuffer,
+    fence: RendererVkFence,
+    #[allow(dead_code)]
+    staging_buffer: RendererVkBuffer,
+    texture: Option<RendererVkTexture>,
+}
+
+impl RendererVkTextureUpload {
+    /// Return whether the upload has completed, without blocking
+    pub fn is_complete(&self) -> bool {
+        self.fence.is_signalled()
+    }
+
+    /// Block until the upload has completed
+    pub fn wait(&self) {
+        self.fence.wait();
+    }
+
+    /// Take the uploaded texture, waiting for the upload to complete first if need be
+    pub fn into_texture(mut self) -> RendererVkTexture {
+        self.wait();
+        self.texture.take().unwrap()
+    }
+}
+
+impl Drop for RendererVkTextureUpload {
+    fn drop(&mut self) {
+        // The staging buffer and command buffer must outlive the copy
+        self.wait();
+        unsafe {
+            vkFreeCommandBuffers(self.device, self.command_pool, 1, &self.command_buffer.raw);
+        }
+    }
+}
+
+impl Drop for RendererVkTexture {
+    fn drop(&mut self) {
+        unsafe {
+            // This is possibly using a sledgehammer to crack a nut, but the sampler
+            // must not be in use when we try to destroy it
+            check_result!("vkQueueWaitIdle", vkQueueWaitIdle(self.queue));
+            vkDestroySampler(self.device, self.sampler, ptr::null());
+        }
+    }
+}
 
-        let maximum_shader_stages = 5;
-        let modules = shader.get_shader_modules();
+impl Renderer for RendererVk {
+    /// To facilitate downcasting back to a concrete type
+    fn as_any(&self) -> &Any {
+        self
+    }
+    fn as_any_mut(&mut self) -> &mut Any {
+        self
+    }
 
-        let mut shader_stage_infos: Vec<VkPipelineShaderStageCreateInfo> = Vec::with_capacity(maximum_shader_stages);
-        let mut has_tessellation = false;
-        for module in modules.iter() {
-            let (shader_stage, shader_module) = *module;
+    /// Return the renderer type
+    fn renderer_type(&self) -> RendererType {
+        RendererType::RendererVk
+    }
 
-            if shader_stage == ShaderStage::TessControlShader || shader_stage == ShaderStage::TessEvalShader {
-                has_tessellation = true;
+    /// Destroy the Vulkan objects in the reverse order of their creation
+    fn shutdown(&mut self) {
+        if self.shut_down {
+            return;
+        }
+        self.shut_down = true;
+
+        self.shutdown_stage("waiting for the device to become idle");
+        self.flush_queued_command_buffers();
+        unsafe {
+            check_result!("vkQueueWaitIdle",
+                          vkQueueWaitIdle(self.device.graphics_queue));
+            if self.device.has_present_queue() {
+                check_result!("vkQueueWaitIdle",
+                              vkQueueWaitIdle(self.device.present_queue));
             }
 
-            shader_stage_infos.push(VkPipelineShaderStageCreateInfo {
-                sType: VkStructureType::VK_STRUCTURE_TYPE_PIPELINE_SHADER_STAGE_CREATE_INFO,
-                stage: ShaderSpirv::internal_shader_stage(shader_stage),
-                module: shader_module,
-                pName: entrypoint_name,
-                pSpecializationInfo: ptr::null(),
-                flags: 0,
-                pNext: ptr::null(),
-            });
+            check_result!("vkDeviceWaitIdle", vkDeviceWaitIdle(self.device.raw));
         }
 
-        let input_assembly_info = VkPipelineInputAssemblyStateCreateInfo {
-            sType: VkStructureType::VK_STRUCTURE_TYPE_PIPELINE_INPUT_ASSEMBLY_STATE_CREATE_INFO,
-            topology: if has_tessellation {
-                VkPrimitiveTopology::VK_PRIMITIVE_TOPOLOGY_PATCH_LIST
-            } else {
-                VkPrimitiveTopology::VK_PRIMITIVE_TOPOLOGY_TRIANGLE_LIST
-            },
-            primitiveRestartEnable: false as VkBool32,
-            flags: 0,
-            pNext: ptr::null(),
-        };
-
-        let (bindings, attributes) = RendererVkPipeline::vertex_input_bindings_and_attributes_info(shader_spec.vertex_array_type);
-        let vertex_input_info = VkPipelineVertexInputStateCreateInfo {
-            sType: VkStructureType::VK_STRUCTURE_TYPE_PIPELINE_VERTEX_INPUT_STATE_CREATE_INFO,
-            vertexBindingDescriptionCount: bindings.len() as u32,
-            pVertexBindingDescriptions: bindings.as_ptr(),
-            vertexAttributeDescriptionCount: attributes.len() as u32,
-            pVertexAttributeDescriptions: attributes.as_ptr(),
-            flags: 0,
-            pNext: ptr::null(),
-        };
+        // The workers creating pipelines use the device and the pipeline cache
+        self.collect_built_pipelines(PipelineWait::All);
+        self.flush_pipeline_cache();
+
+        self.shutdown_stage("destroying per-frame resources");
+        self.graph_attachments.clear();
+        self.texture_views.clear();
+        self.pending_graph_barriers.clear();
+        self.readback_requests.clear();
+        self.readbacks_in_flight.clear();
+        self.readback_staging.clear();
+        self.readbacks_completed.clear();
+        self.vertex_buffer.clear();
+        self.vertex_buffer_index.clear();
+        self.instance_buffers.clear();
+        self.prepresent_command_buffers.clear();
+        self.compute_command_buffers.clear();
+        self.compute_query_pools.clear();
+        self.pass_query_pools.clear();
+        self.vertex_upload_command_buffers.clear();
+        self.prepass_command_buffers.clear();
+        self.command_buffers.clear();
+        self.chunk_command_buffers.clear();
+        self.frame_fences.clear();
+        self.render_finished_semaphores.clear();
+        self.pass_semaphores.clear();
+        self.image_available_semaphores.clear();
+        self.present_wait = None;
+        self.debug_utils = None;
+
+        self.shutdown_stage("destroying pipelines");
+        self.polygon_mode_pipelines.clear();
+        self.render_pipelines.clear();
+        self.pipeline_modules.clear();
+        self.compute_pipelines.clear();
+        self.pipeline_cache = None;
+        self.error_shader_modules.clear();
+        self.rebuilt_shader_modules.clear();
+        self.pipeline_layouts.clear();
 
-        let depth_stencil_info = VkPipelineDepthStencilStateCreateInfo {
-            sType: VkStructureType::VK_STRUCTURE_TYPE_PIPELINE_DEPTH_STENCIL_STATE_CREATE_INFO,
-            depthTestEnable: shader_spec.depth_test_enabled as VkBool32,
-            depthWriteEnable: shader_spec.depth_test_enabled as VkBool32,
-            depthCompareOp: VkCompareOp::VK_COMPARE_OP_LESS,
-            depthBoundsTestEnable: false as VkBool32,
-            minDepthBounds: 0.0f32, // Optional
-            maxDepthBounds: 1.0f32, // Optional
-            stencilTestEnable: false as VkBool32,
-            front: VkStencilOpState::default(), // Optional
-            back: VkStencilOpState::default(), // Optional
-            flags: 0,
-            pNext: ptr::null(),
-        };
+        self.shutdown_stage("destroying descriptors and uniform buffers");
+        self.descriptor_sets.clear();
+        self.descriptor_set_layouts.clear();
+        self.draw_bindings.clear();
+        self.draw_descriptor_pools.clear();
+        self.retired_descriptor_pools.clear();
+        self.shader_descriptor_pools.clear();
+        self.descriptor_pools.clear();
+        self.bindless_textures = None;
+        self.uniform_buffers.clear();
+        self.mesh_moves.clear();
+        self.meshes.clear();
 
-        let pipeline_info = VkGraphicsPipelineCreateInfo {
-            sType: VkStructureType::VK_STRUCTURE_TYPE_GRAPHICS_PIPELINE_CREATE_INFO,
-            stageCount: modules.len() as u32,
-            pStages: shader_stage_infos.as_ptr(),
-            pVertexInputState: &vertex_input_info,
-            pInputAssemblyState: &input_assembly_info,
-            pViewportState: &viewport_state,
-            pRasterizationState: &rasterizer,
-            pMultisampleState: &multisampling,
-            pDepthStencilState: &depth_stencil_info,
-            pColorBlendState: &color_blending,
-            pDynamicState: ptr::null(), // Optional
-            layout: pipeline_layout.raw,
-            renderPass: render_pass.raw,
-            subpass: 0,
-            basePipelineIndex: -1, // Optional: This indicates that the new pipeline is not derived
-            basePipelineHandle: VK_NULL_HANDLE_MUT(), // Optional: This indicates that the new pipeline is not derived
-            pTessellationState: if has_tessellation {
-                &tessellation_state_create_info
-            } else {
-                ptr::null()
-            },
-            flags: 0,
-            pNext: ptr::null(),
-        };
+        self.shutdown_stage("destroying framebuffers, render passes and command pools");
+        self.framebuffers.clear();
+        self.clearing_render_passes.clear();
+        self.render_passes.clear();
+        self.command_pools.clear();
+        unsafe {
+            ManuallyDrop::drop(&mut self.transfer_command_pool);
+            ManuallyDrop::drop(&mut self.aux_command_pool);
+        }
 
-        let mut render_pipeline: VkPipeline = VK_NULL_HANDLE_MUT();
+        self.shutdown_stage("destroying the swapchain and surface");
         unsafe {
-            check_result!("vkCreateGraphicsPipelines",
-                          vkCreateGraphicsPipelines(device.raw,
-                                                    VK_NULL_HANDLE_MUT(), // Optional pipeline cache
-                                                    1, // Number of pipelines to create
-                                                    &pipeline_info,
-                                                    ptr::null(),
-                                                    &mut render_pipeline));
+            ManuallyDrop::drop(&mut self.swapchain);
+            ManuallyDrop::drop(&mut self.surface);
         }
 
-        let _: CString = unsafe { CString::from_raw(entrypoint_name) };
+        self.shutdown_stage("destroying the device");
+        unsafe {
+            ManuallyDrop::drop(&mut self.device);
+        }
 
-        RendererVkPipeline {
-            device: device.raw,
-            raw: render_pipeline,
+        self.shutdown_stage("destroying the instance");
+        self.debug_messenger = None;
+        unsafe {
+            ManuallyDrop::drop(&mut self.instance);
         }
     }
 
-    /// Return pipeline vertex input state bindings and attributes structures for a given vertex array type
-    ///
-    ///
-    fn vertex_input_bindings_and_attributes_info
-        (array_type: VertexArrayType)
-         -> (Vec<VkVertexInputBindingDescription>, Vec<VkVertexInputAttributeDescription>) {
-        let bindings;
-        let attributes;
-        match array_type {
-            VertexArrayType::F3 => {
-                bindings = vec![VkVertexInputBindingDescription {
-                                    binding: 0,
-                                    stride: 3 * mem::size_of::<f32>() as u32,
-                                    inputRate: VkVertexInputRate::VK_VERTEX_INPUT_RATE_VERTEX,
-                                }];
-                attributes = vec![VkVertexInputAttributeDescription {
-                                      binding: 0,
-                                      location: 0,
-                                      format: VkFormat::VK_FORMAT_R32G32B32_SFLOAT,
-                                      offset: 0,
-                                  }];
-            }
-            VertexArrayType::F3F3F3 => {
-                bindings = vec![VkVertexInputBindingDescription {
-                                    binding: 0,
-                                    stride: 9 * mem::size_of::<f32>() as u32,
-                                    inputRate: VkVertexInputRate::VK_VERTEX_INPUT_RATE_VERTEX,
-                                }];
-                attributes = vec![VkVertexInputAttributeDescription {
-                                      binding: 0,
-                                      location: 0,
-                                      format: VkFormat::VK_FORMAT_R32G32B32_SFLOAT,
-                                      offset: 0,
-                                  },
-                                  VkVertexInputAttributeDescription {
-                                      binding: 0,
-                                      location: 1,
-                                      format: VkFormat::VK_FORMAT_R32G32B32_SFLOAT,
-                                      offset: 3 * mem::size_of::<f32>() as u32,
-                                  },
-                                  VkVertexInputAttributeDescription {
-                                      binding: 0,
-                                      location: 2,
-                                      format: VkFormat::VK_FORMAT_R32G32B32_SFLOAT,
-                                      offset: 6 * mem::size_of::<f32>() as u32,
-                                  }];
-            }
-            VertexArrayType::F3F3 => {
-                bindings = vec![VkVertexInputBindingDescription {
-                                    binding: 0,
-                                    stride: 6 * mem::size_of::<f32>() as u32,
-                                    inputRate: VkVertexInputRate::VK_VERTEX_INPUT_RATE_VERTEX,
-                                }];
-                attributes = vec![VkVertexInputAttributeDescription {
-                                      binding: 0,
-                                      location: 0,
-                                      format: VkFormat::VK_FORMAT_R32G32B32_SFLOAT,
-                                      offset: 0,
-                                  },
-                                  VkVertexInputAttributeDescription {
-                                      binding: 0,
-                                      location: 1,
-                                      format: VkFormat::VK_FORMAT_R32G32B32_SFLOAT,
-                                      offset: 3 * mem::size_of::<f32>() as u32,
-                                  }];
-            }
-            VertexArrayType::F2F2 => {
-                bindings = vec![VkVertexInputBindingDescription {
-                                    binding: 0,
-                                    stride: 4 * mem::size_of::<f32>() as u32,
-                                    inputRate: VkVertexInputRate::VK_VERTEX_INPUT_RATE_VERTEX,
-                                }];
-                attributes = vec![VkVertexInputAttributeDescription {
-                                      binding: 0,
-                                      location: 0,
-                                      format: VkFormat::VK_FORMAT_R32G32_SFLOAT,
-                                      offset: 0,
-                                  },
-                                  VkVertexInputAttributeDescription {
-                                      binding: 0,
-                                      location: 1,
-                                      format: VkFormat::VK_FORMAT_R32G32_SFLOAT,
-                                      offset: 2 * mem::size_of::<f32>() as u32,
-                                  }];
-            }
+    /// Return the clip space convention that projection matrices must follow
+    fn clip_space_convention(&self) -> ClipSpaceConvention {
+        if self.flip_viewport_y {
+            ClipSpaceConvention::VulkanFlippedViewport
+        } else {
+            ClipSpaceConvention::Vulkan
         }
-
-        (bindings, attributes)
     }
-}
 
-impl Drop for RendererVkPipeline {
-    fn drop(&mut self) {
-        unsafe {
-            vkDestroyPipeline(self.device, self.raw, ptr::null());
-        }
+    /// Obtain an Arc for the ThreadData structure for the specified thread
+    fn get_threaddata(&self, thr: usize) -> Arc<Mutex<Box<ThreadData>>> {
+        self.threaddata_arcs[thr].clone()
     }
-}
 
-pub struct RendererVkCommandPool {
-    device: VkDevice,
-    raw: VkCommandPool,
-}
+    /// Return the maximum number of threads allowed
+    fn get_maxthreads(&self) -> usize {
+        return self.max_threads;
+    }
 
-impl RendererVkCommandPool {
-    /// Create a command pool
+    /// Finish initialisation of resources
     ///
-    /// device: The Vulkan device
-    pub fn new(device: &RendererVkDevice, queue_family_index: u32) -> RendererVkCommandPool {
-        let pool_info = VkCommandPoolCreateInfo {
-            sType: VkStructureType::VK_STRUCTURE_TYPE_COMMAND_POOL_CREATE_INFO,
-            queueFamilyIndex: queue_family_index,
-            flags: VkCommandPoolCreateFlagBits::VK_COMMAND_POOL_CREATE_RESET_COMMAND_BUFFER_BIT as VkCommandPoolCreateFlags,
-            pNext: ptr::null(),
+    /// shaders: A map of the shaders to set up, keyed by name
+    /// textures: A map of the textures to set up, keyed by name
+    fn finish_resource_initialisation(&mut self,
+                                      shaders: &HashMap<&'static str, &Box<Shader>>,
+                                      textures: &HashMap<&'static str, &Box<Texture>>)
+                                      -> Result<(), RendererError> {
+        let mut renderer_vk: &mut RendererVk = match self.as_any_mut().downcast_mut::<RendererVk>() {
+            Some(r) => r,
+            None => panic!("Unexpected runtime type"),
         };
 
-        let mut command_pool: VkCommandPool = VK_NULL_HANDLE_MUT();
-        unsafe {
-            check_result!("vkCreateCommandPool",
-                          vkCreateCommandPool(device.raw, &pool_info, ptr::null(), &mut command_pool));
-        }
-
-        RendererVkCommandPool {
-            device: device.raw,
-            raw: command_pool,
+        let mut shaders_vk = HashMap::new();
+        for shader in shaders.iter() {
+            let (nm, sh) = shader;
+            match sh.as_any().downcast_ref::<ShaderSpirv>() {
+                Some(s) => shaders_vk.insert(*nm, s),
+                None => panic!("Unexpected runtime type"),
+            };
         }
-    }
-}
 
-impl Drop for RendererVkCommandPool {
-    fn drop(&mut self) {
-        unsafe {
-            vkDestroyCommandPool(self.device, self.raw, ptr::null());
+        let mut textures_vk = HashMap::new();
+        for texture in textures.iter() {
+            let (nm, tx) = texture;
+            match tx.as_any().downcast_ref::<TextureVk>() {
+                Some(t) => textures_vk.insert(*nm, t),
+                None => panic!("Unexpected runtime type"),
+            };
         }
+
+        renderer_vk.setup(&shaders_vk, &textures_vk)
     }
-}
 
-// Note: There is no Drop implementation for RendererVkCommandBuffer as
-// VkCommandBuffer objects are destroyed when the VkCommandPool they belong
-// to is destroyed
-pub struct RendererVkCommandBuffer {
-    raw: VkCommandBuffer,
-    primary: bool,
-}
+    /// Convert a renderer primitive type to a Vulkan primitive topology
+    fn primitive(&self, primitive_type: PrimitiveType) -> u32 {
+        vk_primitive_topology(primitive_type) as u32
+    }
 
-impl RendererVkCommandBuffer {
-    /// Create a command buffer
+    /// Set a integer in part of the memory put aside for the named uniform buffer
     ///
-    /// device: The Vulkan device
-    /// primary: true if this is to be a primary command buffers, false if it is to be
-    ///     a secondary command buffer
-    pub fn new(device: &RendererVkDevice, command_pool: &RendererVkCommandPool, primary: bool) -> RendererVkCommandBuffer {
-        let allocate_info = VkCommandBufferAllocateInfo {
-            sType: VkStructureType::VK_STRUCTURE_TYPE_COMMAND_BUFFER_ALLOCATE_INFO,
-            commandPool: command_pool.raw,
-            level: if primary {
-                VkCommandBufferLevel::VK_COMMAND_BUFFER_LEVEL_PRIMARY
-            } else {
-                VkCommandBufferLevel::VK_COMMAND_BUFFER_LEVEL_SECONDARY
-            },
-            commandBufferCount: 1,
-            pNext: ptr::null(),
-        };
-
-        let mut command_buffer: VkCommandBuffer = VK_NULL_HANDLE_MUT();
+    /// buffer_name: The name of the uniform buffer to contain the new value
+    /// uniform_name: The name of the uniform whose value should be set
+    /// value: The value to set for the uniform
+    fn set_uniform_buffer_int(&self, buffer_name: &str, uniform_name: &str, value: i32) {
+        let ref buffer = self.uniform_buffers[buffer_name];
+        buffer.version.bump();
+        let offset = buffer.offsets[uniform_name];
         unsafe {
-            check_result!("vkAllocateCommandBuffers",
-                          vkAllocateCommandBuffers(device.raw, &allocate_info, &mut command_buffer));
-        }
-
-        RendererVkCommandBuffer {
-            raw: command_buffer,
-            primary: primary,
+            let dst: *const u8 = buffer.bytes.as_ptr().offset(offset as isize);
+            let dst_i32 = dst as *mut i32;
+            *dst_i32 = value;
         }
     }
 
-    /// Begin recording to the primary command buffer
-    ///
+    /// Set a floating point in part of the memory put aside for the named uniform buffer
     ///
-    pub fn begin_primary(&self, one_time_submit: bool, render_pass_continue: bool, simultaneous_use: bool) {
-        debug_assert!(self.primary);
-
-        let mut flags: u32 = 0;
-        if one_time_submit {
-            flags |= VkCommandBufferUsageFlagBits::VK_COMMAND_BUFFER_USAGE_ONE_TIME_SUBMIT_BIT as VkCommandBufferUsageFlags;
-        };
-        if render_pass_continue {
-            flags |= VkCommandBufferUsageFlagBits::VK_COMMAND_BUFFER_USAGE_RENDER_PASS_CONTINUE_BIT as VkCommandBufferUsageFlags;
-        };
-        if simultaneous_use {
-            flags |= VkCommandBufferUsageFlagBits::VK_COMMAND_BUFFER_USAGE_SIMULTANEOUS_USE_BIT as VkCommandBufferUsageFlags;
-        };
-
-        let begin_info = VkCommandBufferBeginInfo {
-            sType: VkStructureType::VK_STRUCTURE_TYPE_COMMAND_BUFFER_BEGIN_INFO,
-            flags: flags,
-            pInheritanceInfo: ptr::null(),
-            pNext: ptr::null(),
-        };
-
+    /// buffer_name: The name of the uniform buffer to contain the new value
+    /// uniform_name: The name of the uniform whose value should be set
+    /// value: The value to set for the uniform
+    fn set_uniform_buffer_float(&self, buffer_name: &str, uniform_name: &str, value: f32) {
+        let ref buffer = self.uniform_buffers[buffer_name];
+        buffer.version.bump();
+        let offset = buffer.offsets[uniform_name];
         unsafe {
-            vkBeginCommandBuffer(self.raw, &begin_info);
+            let dst: *const u8 = buffer.bytes.as_ptr().offset(offset as isize);
+            let dst_f32 = dst as *mut f32;
+            *dst_f32 = value;
         }
     }
 
-    /// Begin recording to the secondary command buffer using the supplied inheritance info
-    ///
+    /// Set a 3-component vector in part of the memory put aside for the named uniform buffer
     ///
-    pub fn begin_secondary(&self, renderpass: &RendererVkRenderPass, subpass: u32, framebuffer: &RendererVkFramebuffer) {
-        debug_assert!(!self.primary);
-
-        let inheritance_info = VkCommandBufferInheritanceInfo {
-            sType: VkStructureType::VK_STRUCTURE_TYPE_COMMAND_BUFFER_INHERITANCE_INFO,
-            pNext: ptr::null(),
-            renderPass: renderpass.raw,
-            subpass: subpass,
-            framebuffer: framebuffer.raw,
-            occlusionQueryEnable: true as VkBool32,
-            queryFlags: VkQueryControlFlagBits::VK_QUERY_CONTROL_PRECISE_BIT as VkQueryControlFlags,
-            pipelineStatistics: 0,
-        };
-
-        let begin_info = VkCommandBufferBeginInfo {
-            sType: VkStructureType::VK_STRUCTURE_TYPE_COMMAND_BUFFER_BEGIN_INFO,
-            flags: VkCommandBufferUsageFlagBits::VK_COMMAND_BUFFER_USAGE_SIMULTANEOUS_USE_BIT as VkCommandBufferUsageFlags |
-                   VkCommandBufferUsageFlagBits::VK_COMMAND_BUFFER_USAGE_RENDER_PASS_CONTINUE_BIT as VkCommandBufferUsageFlags,
-            pInheritanceInfo: &inheritance_info,
-            pNext: ptr::null(),
-        };
-
+    /// buffer_name: The name of the uniform buffer to contain the new value
+    /// uniform_name: The name of the uniform whose value should be set
+    /// value: The value to set for the uniform
+    fn set_uniform_buffer_vec3(&self, buffer_name: &str, uniform_name: &str, value: &Vec3<f32>) {
+        let ref buffer = self.uniform_buffers[buffer_name];
+        buffer.version.bump();
+        let offset = buffer.offsets[uniform_name];
         unsafe {
-            vkBeginCommandBuffer(self.raw, &begin_info);
+            let dst: *const u8 = buffer.bytes.as_ptr().offset(offset as isize);
+            let dst_f32 = dst as *mut f32;
+            let src: *const f32 = mem::transmute(value);
+            ptr::copy_nonoverlapping(src, dst_f32, 3);
         }
     }
 
-    /// End recording to the command buffer
-    ///
+    /// Set a 4x4-component matrix in part of the memory put aside for the named uniform buffer
     ///
-    pub fn end(&self) {
+    /// buffer_name: The name of the uniform buffer to contain the new value
+    /// uniform_name: The name of the uniform whose value should be set
+    /// matrix: The value to set for the uniform
+    fn set_uniform_buffer_matrix(&self, buffer_name: &str, uniform_name: &str, matrix: &Mat4<f32>) {
+        let ref buffer = self.uniform_buffers[buffer_name];
+        buffer.version.bump();
+        let offset = buffer.offsets[uniform_name];
         unsafe {
-            check_result!("vkEndCommandBuffer", vkEndCommandBuffer(self.raw));
+            let dst: *const u8 = buffer.bytes.as_ptr().offset(offset as isize);
+            let dst_f32 = dst as *mut f32;
+            let src: *const f32 = mem::transmute(matrix);
+            ptr::copy_nonoverlapping(src, dst_f32, 16);
         }
     }
 
-    /// Emit an explicit pipeline image memory barrier, raw
+    /// Set consecutive elements of an array of matrices in the memory put aside for the named
+    /// uniform buffer
     ///
-    ///
-    pub fn image_memory_barrier_raw(command_buffer: VkCommandBuffer,
-                                    image: VkImage,
-                                    src_access_mask: VkAccessFlags,
-                                    dst_access_mask: VkAccessFlags,
-                                    old_layout: VkImageLayout,
-                                    new_layout: VkImageLayout,
-                                    aspect_mask: VkImageAspectFlags,
-                                    src_stage_mask: VkPipelineStageFlags,
-                                    dst_stage_mask: VkPipelineStageFlags) {
-        let barrier = VkImageMemoryBarrier {
-            sType: VkStructureType::VK_STRUCTURE_TYPE_IMAGE_MEMORY_BARRIER,
-            srcAccessMask: src_access_mask,
-            dstAccessMask: dst_access_mask,
-            oldLayout: old_layout,
-            newLayout: new_layout,
-            srcQueueFamilyIndex: VK_QUEUE_FAMILY_IGNORED as u32,
-            dstQueueFamilyIndex: VK_QUEUE_FAMILY_IGNORED as u32,
-            image: image,
-            subresourceRange: VkImageSubresourceRange {
-                aspectMask: aspect_mask,
-                baseMipLevel: 0,
-                levelCount: 1,
-                baseArrayLayer: 0,
-                layerCount: 1,
-            },
-            pNext: ptr::null(),
-        };
-
+    /// buffer_name: The name of the uniform buffer to contain the new values
+    /// uniform_name: The name of the array member
+    /// matrices: The matrices to set, from the first element
+    fn set_uniform_buffer_matrix_array(&self, buffer_name: &str, uniform_name: &str, matrices: &[Mat4<f32>]) {
+        let ref buffer = self.uniform_buffers[buffer_name];
+        buffer.version.bump();
+        let offset = buffer.offsets[uniform_name];
+        let stride = buffer.strides[uniform_name].max(16 * mem::size_of::<f32>());
         unsafe {
-            vkCmdPipelineBarrier(command_buffer,
-                                 src_stage_mask,
-                                 dst_stage_mask,
-                                 0, // Dependency flags
-                                 0, // Memory barrier count
-                                 ptr::null(), // Memory barriers
-                                 0, // Buffer memory barrier count
-                                 ptr::null(), // Buffer memory barriers
-                                 1, // Image barrier count
-                                 &barrier); // Image barriers
+            for (i, matrix) in matrices.iter().enumerate() {
+                let dst: *const u8 = buffer.bytes.as_ptr().offset((i * stride + offset) as isize);
+                let dst_f32 = dst as *mut f32;
+                let src: *const f32 = mem::transmute(matrix);
+                ptr::copy_nonoverlapping(src, dst_f32, 16);
+            }
         }
     }
 
-    /// Emit an explicit pipeline image memory barrier
-    ///
+    /// Set a floating point vector in part of the memory put aside for the named uniform buffer
     ///
-    pub fn image_memory_barrier(&self,
-                                image: VkImage,
-                                src_access_mask: VkAccessFlags,
-                                dst_access_mask: VkAccessFlags,
-                                old_layout: VkImageLayout,
-                                new_layout: VkImageLayout,
-                                aspect_mask: VkImageAspectFlags,
-                                src_stage_mask: VkPipelineStageFlags,
-                                dst_stage_mask: VkPipelineStageFlags) {
-        RendererVkCommandBuffer::image_memory_barrier_raw(self.raw,
-                                                          image,
-                                                          src_access_mask,
-                                                          dst_access_mask,
-                                                          old_layout,
-                                                          new_layout,
-                                                          aspect_mask,
-                                                          src_stage_mask,
-                                                          dst_stage_mask);
+    /// buffer_name: The name of the uniform buffer to contain the new value
+    /// uniform_name: The name of the uniform whose value should be set
+    /// vector: The vector to set for the uniform
+    fn set_uniform_buffer_float_vector(&self, buffer_name: &str, uniform_name: &str, vector: &Vec<f32>) {
+        let ref buffer = self.uniform_buffers[buffer_name];
+        buffer.version.bump();
+        let offset = buffer.offsets[uniform_name];
+        let stride = buffer.strides[uniform_name];
+        if stride == 0 || stride == 4 {
+            unsafe {
+                let dst: *const u8 = buffer.bytes.as_ptr().offset(offset as isize);
+                let dst_f32 = dst as *mut f32;
+                let src: *const f32 = mem::transmute(vector.as_ptr());
+                ptr::copy_nonoverlapping(src, dst_f32, vector.len());
+            }
+        } else {
+            // This path requires observing the stride
+            unsafe {
+                for i in 0..vector.len() {
+                    let dst: *const u8 = buffer.bytes.as_ptr().offset((i * stride + offset) as isize);
+                    let dst_f32 = dst as *mut f32;
+                    let src: *const f32 = mem::transmute(vector.as_ptr().offset(i as isize));
+                    *dst_f32 = *src;
+                }
+            }
+        }
     }
 
-    /// Emit an explicit pipeline buffer memory barrier, raw
+    /// Update the accumulated contents to the named uniform buffer
     ///
+    /// The contents are captured now and written to the buffer on the GPU timeline at the
+    /// start of the next pass, so this must be called at a pass boundary.
     ///
-    pub fn buffer_memory_barrier_raw(command_buffer: VkCommandBuffer,
-                                     buffer: VkBuffer,
-                                     src_access_mask: VkAccessFlags,
-                                     dst_access_mask: VkAccessFlags,
-                                     src_stage_mask: VkPipelineStageFlags,
-                                     dst_stage_mask: VkPipelineStageFlags) {
-        let barrier = VkBufferMemoryBarrier {
-            sType: VkStructureType::VK_STRUCTURE_TYPE_BUFFER_MEMORY_BARRIER,
-            srcAccessMask: src_access_mask,
-            dstAccessMask: dst_access_mask,
-            srcQueueFamilyIndex: VK_QUEUE_FAMILY_IGNORED as u32,
-            dstQueueFamilyIndex: VK_QUEUE_FAMILY_IGNORED as u32,
-            buffer: buffer,
-            offset: 0,
-            size: VK_WHOLE_SIZE as u64,
-            pNext: ptr::null(),
-        };
-
-        unsafe {
-            vkCmdPipelineBarrier(command_buffer,
-                                 src_stage_mask,
-                                 dst_stage_mask,
-                                 0, // Dependency flags
-                                 0, // Memory barrier count
-                                 ptr::null(), // Memory barriers
-                                 1, // Buffer memory barrier count
-                                 &barrier, // Buffer memory barriers
-                                 0, // Image barrier count
-                                 ptr::null()); // Image barriers
+    /// buffer_name: The name of the uniform buffer to be configuring
+    fn synchronise_uniform_buffer(&self, buffer_name: &str) {
+        let ref buffer = self.uniform_buffers[buffer_name];
+        if !buffer.is_written_by_shaders() && !buffer.version.synchronise() {
+            self.skipped_binds.skip();
+            return;
         }
+
+        // println!("Synchronising {} ({} bytes, binding = {})",
+        //          buffer_name,
+        //          buffer.size,
+        //          buffer.binding);
+        // dump_byte_vector(&buffer.bytes);
+        let mut pending = self.pending_uniform_updates.lock().unwrap();
+        pending.retain(|&(ref name, _)| name != buffer_name);
+        pending.push((buffer_name.to_string(), buffer.bytes.clone()));
     }
 
-    /// Emit an explicit pipeline buffer memory barrier
-    ///
+    /// Set an integer push constant
     ///
-    pub fn buffer_memory_barrier(&self,
-                                 buffer: VkBuffer,
-                                 src_access_mask: VkAccessFlags,
-                                 dst_access_mask: VkAccessFlags,
-                                 src_stage_mask: VkPipelineStageFlags,
-                                 dst_stage_mask: VkPipelineStageFlags) {
-        RendererVkCommandBuffer::buffer_memory_barrier_raw(self.raw,
-                                                           buffer,
-                                                           src_access_mask,
-                                                           dst_access_mask,
-                                                           src_stage_mask,
-                                                           dst_stage_mask);
+    /// thr: The thread whose command buffer should record the value
+    /// name: The name of the push constant
+    /// value: The value to set
+    fn set_push_constant_int(&self, thr: usize, name: &str, value: i32) {
+        let bytes: [u8; 4] = unsafe { mem::transmute(value) };
+        self.push_constant_bytes(thr, name, &bytes);
     }
 
-    /// Emit an explicit pipeline memory barrier, raw
-    ///
+    /// Set a floating point push constant
     ///
-    pub fn memory_barrier_raw(command_buffer: VkCommandBuffer,
-                              src_access_mask: VkAccessFlags,
-                              dst_access_mask: VkAccessFlags,
-                              src_stage_mask: VkPipelineStageFlags,
-                              dst_stage_mask: VkPipelineStageFlags) {
-        let barrier = VkMemoryBarrier {
-            sType: VkStructureType::VK_STRUCTURE_TYPE_MEMORY_BARRIER,
-            srcAccessMask: src_access_mask,
-            dstAccessMask: dst_access_mask,
-            pNext: ptr::null(),
-        };
-
-        unsafe {
-            vkCmdPipelineBarrier(command_buffer,
-                                 src_stage_mask,
-                                 dst_stage_mask,
-                                 0, // Dependency flags
-                                 1, // Memory barrier count
-                                 &barrier, // Memory barriers
-                                 0, // Buffer memory barrier count
-                                 ptr::null(), // Buffer memory barriers
-                                 0, // Image barrier count
-                                 ptr::null()); // Image barriers
-        }
+    /// thr: The thread whose command buffer should record the value
+    /// name: The name of the push constant
+    /// value: The value to set
+    fn set_push_constant_float(&self, thr: usize, name: &str, value: f32) {
+        let bytes: [u8; 4] = unsafe { mem::transmute(value) };
+        self.push_constant_bytes(thr, name, &bytes);
     }
 
-    /// Emit an explicit pipeline memory barrier
-    ///
+    /// Set a vector push constant
     ///
-    pub fn memory_barrier(&self,
-                          src_access_mask: VkAccessFlags,
-                          dst_access_mask: VkAccessFlags,
-                          src_stage_mask: VkPipelineStageFlags,
-                          dst_stage_mask: VkPipelineStageFlags) {
-        RendererVkCommandBuffer::memory_barrier_raw(self.raw,
-                                                    src_access_mask,
-                                                    dst_access_mask,
-                                                    src_stage_mask,
-                                                    dst_stage_mask);
+    /// thr: The thread whose command buffer should record the value
+    /// name: The name of the push constant
+    /// value: The value to set
+    fn set_push_constant_vec3(&self, thr: usize, name: &str, value: &Vec3<f32>) {
+        let bytes: [u8; 12] = unsafe { mem::transmute([value.x, value.y, value.z]) };
+        self.push_constant_bytes(thr, name, &bytes);
     }
-}
 
-pub struct RendererVkSemaphore {
-    device: VkDevice,
-    raw: VkSemaphore,
-}
+    /// Set a matrix push constant
+    ///
+    /// thr: The thread whose command buffer should record the value
+    /// name: The name of the push constant
+    /// matrix: The value to set
+    fn set_push_constant_matrix(&self, thr: usize, name: &str, matrix: &Mat4<f32>) {
+        let bytes: [u8; 64] = unsafe { mem::transmute_copy(matrix) };
+        self.push_constant_bytes(thr, name, &bytes);
+    }
 
-impl RendererVkSemaphore {
-    /// Create a semaphore
+    /// Switch a texture of the current shader for the draws a thread records after the call
     ///
+    /// A copy of the shader's descriptor set is made with the texture written into it, which is
+    /// bound in the thread's command buffer before its next draw.  Switching to the texture
+    /// already in use does nothing.
     ///
-    pub fn new(device: &RendererVkDevice) -> RendererVkSemaphore {
-        let semaphore_create_info = VkSemaphoreCreateInfo {
-            sType: VkStructureType::VK_STRUCTURE_TYPE_SEMAPHORE_CREATE_INFO,
-            flags: 0,
-            pNext: ptr::null_mut(),
+    /// thr: The thread whose command buffer should record the switch
+    /// name: The name of the texture in the current shader
+    /// texture: The texture to sample
+    fn set_draw_texture(&self, thr: usize, name: &str, texture: &Texture) {
+        let texture_vk = match texture.as_any().downcast_ref::<TextureVk>() {
+            Some(t) => t,
+            None => panic!("Unexpected runtime type"),
+        };
+        let binding = match self.draw_bindings[self.shader_name].samplers.iter().find(|&&(n, _)| n == name) {
+            Some(&(_, binding)) => binding,
+            None => panic!("Shader {} has no texture {}", self.shader_name, name),
         };
 
-        let mut semaphore: VkSemaphore = VK_NULL_HANDLE_MUT();
-        unsafe {
-            check_result!("vkCreateSemaphore",
-                          vkCreateSemaphore(device.raw,
-                                            &semaphore_create_info,
-                                            ptr::null_mut(),
-                                            &mut semaphore));
+        let image_info = VkDescriptorImageInfo {
+            imageLayout: VkImageLayout::VK_IMAGE_LAYOUT_SHADER_READ_ONLY_OPTIMAL,
+            imageView: texture_vk.texture.view.raw,
+            sampler: texture_vk.texture.sampler,
+        };
+
+        let mut draw_textures = self.draw_textures[thr].lock().unwrap();
+        match draw_textures.textures.iter().position(|&(b, _)| b == binding) {
+            Some(i) => {
+                let ref current = draw_textures.textures[i].1;
+                if current.imageView == image_info.imageView && current.sampler == image_info.sampler {
+                    self.skipped_binds.skip();
+                    return;
+                }
+                draw_textures.textures[i].1 = image_info;
+            }
+            None => draw_textures.textures.push((binding, image_info)),
         }
 
-        RendererVkSemaphore {
-            device: device.raw,
-            raw: semaphore,
+        if let Some(descriptor_set) = self.copy_draw_descriptor_set(thr, &draw_textures.textures) {
+            draw_textures.set = Some(descriptor_set);
         }
     }
-}
 
-impl Drop for RendererVkSemaphore {
-    fn drop(&mut self) {
-        unsafe {
-            vkDestroySemaphore(self.device, self.raw, ptr::null());
+    /// Flip the back buffer to the front
+    ///
+    /// context: The GLFW context, not used on Vulkan
+    fn flip(&self, _: &mut Context) {
+        if self.swapchain.headless {
+            // Nothing to present to
+            return;
         }
-    }
-}
 
-pub struct RendererVkFence {
-    device: VkDevice,
-    raw: VkFence,
-}
+        let signal_semaphores = vec![self.render_finished_semaphores[self.frame_slot()].raw];
+        let swapchains = vec![self.swapchain.raw];
+        let image_indices = vec![self.image_index as u32];
 
-impl RendererVkFence {
-    /// Create a fence
-    ///
-    ///
-    pub fn new(device: &RendererVkDevice) -> RendererVkFence {
-        let fence_create_info = VkFenceCreateInfo {
-            sType: VkStructureType::VK_STRUCTURE_TYPE_FENCE_CREATE_INFO,
-            flags: VkFenceCreateFlagBits::VK_FENCE_CREATE_SIGNALED_BIT as VkFenceCreateFlags,
+        // Tag the present with the frame number, for the next frame to wait on
+        //
+        let present_ids = vec![self.frame_number];
+        let present_id_info = VkPresentIdKHR {
+            sType: VK_STRUCTURE_TYPE_PRESENT_ID_KHR,
             pNext: ptr::null(),
+            swapchainCount: present_ids.len() as u32,
+            pPresentIds: present_ids.as_ptr(),
         };
 
-        let mut fence: VkFence = VK_NULL_HANDLE_MUT();
-        unsafe {
-            check_result!("vkCreateFence",
-                          vkCreateFence(device.raw, &fence_create_info, ptr::null_mut(), &mut fence));
-        }
-
-        RendererVkFence {
-            device: device.raw,
-            raw: fence,
-        }
-    }
-}
+        let present_info = VkPresentInfoKHR {
+            sType: VkStructureType::VK_STRUCTURE_TYPE_PRESENT_INFO_KHR,
+            waitSemaphoreCount: signal_semaphores.len() as u32,
+            pWaitSemaphores: signal_semaphores.as_ptr(),
+            swapchainCount: swapchains.len() as u32,
+            pSwapchains: swapchains.as_ptr(),
+            pImageIndices: image_indices.as_ptr(),
+            pResults: ptr::null_mut(), // Optional
+            pNext: if self.present_wait.is_some() {
+                &present_id_info as *const VkPresentIdKHR as *const c_void
+            } else {
+                ptr::null()
+            },
+        };
 
-impl Drop for RendererVkFence {
-    fn drop(&mut self) {
         unsafe {
-            vkDestroyFence(self.device, self.raw, ptr::null());
+            // The present is always made, so that the render finished semaphore is waited on,
+            // and an injected failure replaces its result
+            let mut res = vkQueuePresentKHR(self.device.present_queue, &present_info);
+            if let Some(failure) = injected_failure("vkQueuePresentKHR") {
+                res = injected_result(failure);
+            }
+            match res {
+                VkResult::VK_SUCCESS => {}
+                VkResult::VK_SUBOPTIMAL_KHR |
+                VkResult::VK_ERROR_OUT_OF_DATE_KHR => {
+                    // Recreate the swapchain before the next image is acquired
+                    self.swapchain_out_of_date.store(true, Ordering::SeqCst);
+                }
+                _ => {
+                    log_error!("Unexpected result from {}: {}", "vkQueuePresentKHR", res);
+                    if res == VkResult::VK_ERROR_DEVICE_LOST {
+                        self.events.device_lost();
+                    }
+                    panic!("Vulkan API call failed");
+                }
+            }
         }
     }
-}
 
-pub struct RendererVkTexture {
-    device: VkDevice,
-    queue: VkQueue,
-    #[allow(dead_code)]
-    image: RendererVkImage,
-    view: RendererVkImageView,
-    sampler: VkSampler,
-    width: u32,
-    height: u32,
-    format: VkFormat,
-    row_pitch: u64,
-}
+    /// Begin rendering a new frame
+    ///
+    /// With present wait pacing, this first waits for the previous frame to be presented.
+    fn begin_frame(&mut self) {
+        let renderdoc_device = self.renderdoc_device();
+        self.capture_trigger.begin_frame(renderdoc_device);
 
-impl RendererVkTexture {
-    /// Return the raw image view for this texture
-    pub fn get_view_raw(&self) -> VkImageView {
-        self.view.raw
-    }
+        self.collect_built_pipelines(PipelineWait::Ready);
+        self.apply_pipeline_rebuilds();
 
-    /// Constructor for a Vulkan texture object
-    ///
-    ///
-    pub fn new(renderer: &RendererVk,
-               width: u32,
-               height: u32,
-               format: VkFormat,
-               bytes_per_pixel: u32,
-               data: &Vec<u8>)
-               -> RendererVkTexture {
-        // Create a new host-accessible staging image to format the image data into
+        // A swapchain about to be recreated may never complete the present, so the wait is
+        // skipped for it
         //
-        let props = VkMemoryPropertyFlagBits::VK_MEMORY_PROPERTY_HOST_VISIBLE_BIT as VkMemoryPropertyFlags |
-                    VkMemoryPropertyFlagBits::VK_MEMORY_PROPERTY_HOST_COHERENT_BIT as VkMemoryPropertyFlags;
+        if self.frame_number > 0 && !self.swapchain_out_of_date.load(Ordering::SeqCst) {
+            let device = self.device.raw;
+            let swapchain = self.swapchain.raw;
+            let frame_number = self.frame_number;
+            let mut disable = false;
+            if let Some(ref mut present_wait) = self.present_wait {
+                match present_wait.pace(device, swapchain, frame_number, &mut self.frame_stats) {
+                    VkResult::VK_SUCCESS |
+                    VkResult::VK_TIMEOUT => {}
+                    VkResult::VK_ERROR_OUT_OF_DATE_KHR => self.swapchain_out_of_date.store(true, Ordering::SeqCst),
+                    res => {
+                        log_error!("Unexpected result from {}: {}, no longer pacing frames",
+                                   "vkWaitForPresentKHR",
+                                   res);
+                        disable = true;
+                    }
+                }
+            }
+            if disable {
+                self.present_wait = None;
+                self.frame_stats.present_wait_enabled = false;
+            }
+        }
 
-        let staging_image = RendererVkImage::new(&renderer.device,
-                                                 &renderer.physical_device,
-                                                 &renderer.aux_command_pool,
-                                                 width,
-                                                 height,
-                                                 format,
-                                                 VkImageTiling::VK_IMAGE_TILING_LINEAR,
-                                                 VkImageUsageFlagBits::VK_IMAGE_USAGE_TRANSFER_SRC_BIT as VkImageUsageFlags,
-                                                 props,
-                                                 VkImageLayout::VK_IMAGE_LAYOUT_PREINITIALIZED,
-                                                 VkImageLayout::VK_IMAGE_LAYOUT_PREINITIALIZED);
+        self.frame_number += 1;
+        let frame_slot = self.frame_slot();
 
-        // Query the subresource layout information
+        // The acquire semaphore of this frame slot may still be waited on by the submission that
+        // last used it, which need not be for the image about to be acquired, so wait for that
+        // submission's fence before handing the semaphore to the acquire
         //
-        let subresource = VkImageSubresource {
-            aspectMask: VkImageAspectFlagBits::VK_IMAGE_ASPECT_COLOR_BIT as VkImageAspectFlags,
-            mipLevel: 0,
-            arrayLayer: 0,
-        };
-
-        let mut staging_image_layout = VkSubresourceLayout::default();
-        unsafe {
-            vkGetImageSubresourceLayout(renderer.device.raw,
-                                        staging_image.raw,
-                                        &subresource,
-                                        &mut staging_image_layout);
+        if let Some(previous_image) = self.frame_slot_images[frame_slot] {
+            unsafe {
+                check_device_result!(self.events,
+                                     "vkWaitForFences",
+                                     vkWaitForFences(self.device.raw,
+                                                     1, // Fence count
+                                                     &self.frame_fences[previous_image].raw,
+                                                     true as VkBool32, // Wait for all
+                                                     u64::max_value() /* No timeout */));
+            }
         }
 
-        if data.len() > 0 {
-            // Map the image into host-addressable memory and then reformat the raw image data into it
-            //
-            let mut raw: *mut c_void = VK_NULL_HANDLE_MUT();
-            unsafe {
-                check_result!("vkMapMemory",
-                              vkMapMemory(renderer.device.raw,
-                                          staging_image.memory,
-                                          0, // Offset
-                                          VK_WHOLE_SIZE as u64,
-                                          0, // Flags
-                                          &mut raw));
+        // Acquire the next image in the swapchain, or take the offscreen images in turn when headless
+        //
+        let mut image_index: u32 = (self.frame_number % self.swapchain.image_count as u64) as u32;
+        while !self.swapchain.headless {
+            if self.swapchain_out_of_date.load(Ordering::SeqCst) && !self.recreate_swapchain() {
+                // The window has no area, so wait for something to happen to it
+                unsafe {
+                    glfw::ffi::glfwWaitEvents();
+                }
+                continue;
             }
 
-            // TODO: Optimise this when the image layout contains no padding
-            let raw_u8 = raw as *mut u8;
+            let res = match injected_failure("vkAcquireNextImageKHR") {
+                Some(failure) => injected_result(failure),
+                None => unsafe {
+                    vkAcquireNextImageKHR(self.device.raw,
+                                          self.swapchain.raw,
+                                          u64::max_value(), // No timeout
+                                          self.image_available_semaphores[frame_slot].raw, // Semaphore
+                                          VK_NULL_HANDLE_MUT(), // Fence
+                                          &mut image_index)
+                },
+            };
 
-            unsafe {
-                for y in 0..height {
-                    ptr::copy_nonoverlapping(&data[(y * width * bytes_per_pixel) as usize],
-                                             raw_u8.offset(y as isize * staging_image_layout.rowPitch as isize),
-                                             (width * bytes_per_pixel) as usize);
+            match res {
+                VkResult::VK_SUCCESS => break,
+                VkResult::VK_SUBOPTIMAL_KHR => {
+                    // The image is still usable, so render this frame and recreate after presenting
+                    self.swapchain_out_of_date.store(true, Ordering::SeqCst);
+                    break;
+                }
+                VkResult::VK_ERROR_OUT_OF_DATE_KHR => {
+                    self.swapchain_out_of_date.store(true, Ordering::SeqCst);
+                }
+                _ => {
+                    log_error!("Unexpected result from {}: {}", "vkAcquireNextImageKHR", res);
+                    if res == VkResult::VK_ERROR_DEVICE_LOST {
+                        self.events.device_lost();
+                    }
+                    panic!("Vulkan API call failed");
                 }
             }
+        }
+        self.image_index = image_index as usize;
+        self.pass_index = 0;
 
-            unsafe {
-                vkUnmapMemory(renderer.device.raw, staging_image.memory);
+        // Wait for the GPU to finish the previous frame rendered to this swapchain image, after
+        // which the command buffers, vertex buffers and readbacks for the image may be reused
+        //
+        unsafe {
+            check_device_result!(self.events,
+                                 "vkWaitForFences",
+                                 vkWaitForFences(self.device.raw,
+                                                 1, // Fence count
+                                                 &self.frame_fences[self.image_index].raw,
+                                                 true as VkBool32, // Wait for all
+                                                 u64::max_value() /* No timeout */));
+        }
+
+        for readback in self.readbacks_in_flight[self.image_index].drain(..) {
+            let handle = readback.handle;
+            self.readbacks_completed.complete(handle, readback.retrieve());
+            self.readback_staging[self.image_index].push(readback.into_buffer());
+        }
+
+        for pool in self.draw_descriptor_pools[self.image_index].iter() {
+            pool.reset();
+        }
+        self.retired_descriptor_pools[self.image_index].clear();
+
+        self.complete_compute_slices();
+        self.harvest_pass_timings();
+
+        if let Some(ref mut bindless_textures) = self.bindless_textures {
+            bindless_textures.slots.advance_frame();
+        }
+        self.meshes.advance_frame();
+        self.defragment_meshes();
+
+        for thr in 0..self.max_threads {
+            self.chunks_used[thr] = 0;
+            self.instance_buffers_used[thr] = 0;
+        }
+        self.vertex_uploads_used = 0;
+        self.current_graph_pass = None;
+
+        for ty in VERTEX_ARRAY_TYPE_BEGIN_RANGE..VERTEX_ARRAY_TYPE_END_RANGE + 1 {
+            for thr in 0..self.max_threads {
+                self.vertex_buffer_index[self.image_index][ty as usize][thr] = -1;
             }
         }
 
-        // The staging image needs to be in a layout suitable for being the source of a copy
-        //
-        RendererVkImage::transition_layout_immediate(staging_image.raw,
-                                           &renderer.device,
-                                           &renderer.aux_command_pool,
-                                           VkImageAspectFlagBits::VK_IMAGE_ASPECT_COLOR_BIT as VkImageAspectFlags,
-                                           VkImageLayout::VK_IMAGE_LAYOUT_PREINITIALIZED,
-                                           VkImageLayout::VK_IMAGE_LAYOUT_TRANSFER_SRC_OPTIMAL,
-                                           VkPipelineStageFlagBits::VK_PIPELINE_STAGE_TOP_OF_PIPE_BIT as VkPipelineStageFlags,
-                                           VkPipelineStageFlagBits::VK_PIPELINE_STAGE_TOP_OF_PIPE_BIT as VkPipelineStageFlags);
+        // Set the default render target
+        self.deselect_render_target();
+
+        // Publish the frame times for the passes of this frame
+        self.frame_clock.tick();
+        if self.uniform_buffers.contains_key(FRAME_BLOCK) {
+            self.frame_clock.publish(self);
+        }
+
+        // Publish the simulated objects' transforms for blending at the supplied alpha
+        if self.uniform_buffers.contains_key(TRANSFORM_BLOCK) {
+            self.transform_interpolator.publish(self);
+        }
+    }
 
-        // Create the device-local image to copy into
-        //
-        let image = RendererVkImage::new(&renderer.device,
-                                         &renderer.physical_device,
-                                         &renderer.aux_command_pool,
-                                         width,
-                                         height,
-                                         format,
-                                         VkImageTiling::VK_IMAGE_TILING_OPTIMAL,
-                                         VkImageUsageFlagBits::VK_IMAGE_USAGE_TRANSFER_SRC_BIT as VkImageUsageFlags |
-                                         VkImageUsageFlagBits::VK_IMAGE_USAGE_TRANSFER_DST_BIT as VkImageUsageFlags |
-                                         VkImageUsageFlagBits::VK_IMAGE_USAGE_SAMPLED_BIT as VkImageUsageFlags |
-                                         VkImageUsageFlagBits::VK_IMAGE_USAGE_COLOR_ATTACHMENT_BIT as VkImageUsageFlags,
-                                         VkMemoryPropertyFlagBits::VK_MEMORY_PROPERTY_DEVICE_LOCAL_BIT as VkMemoryPropertyFlags,
-                                         VkImageLayout::VK_IMAGE_LAYOUT_PREINITIALIZED,
-                                         VkImageLayout::VK_IMAGE_LAYOUT_TRANSFER_DST_OPTIMAL);
+    /// Terminate rendering a frame
+    fn end_frame(&mut self) {
+        let frame_slot = self.frame_slot();
 
-        // Now copy from the staging image to its final location
+        // Add a pipeline barrier to ensure all the thread command buffers have finished before presenting
         //
-        if data.len() > 0 {
-            RendererVkImage::copy(&renderer.device,
-                                  &renderer.aux_command_pool,
-                                  staging_image.raw,
-                                  image.raw,
-                                  width,
-                                  height);
+        unsafe {
+            check_result!("vkResetCommandBuffer",
+                          vkResetCommandBuffer(self.prepresent_command_buffers[self.image_index].raw,
+                                               0 /* flags */));
         }
 
-        // The final image needs to be in a layout suitable for being used in the shader
+        self.prepresent_command_buffers[self.image_index].begin_primary(true, // one_time_submit
+                                                                        false, // render_pass_continue
+                                                                        true /* simultaneous_use */);
+
+        // Record the copies for any readbacks requested during this frame
         //
-        RendererVkImage::transition_layout_immediate(image.raw,
-                                           &renderer.device,
-                                           &renderer.aux_command_pool,
-                                           VkImageAspectFlagBits::VK_IMAGE_ASPECT_COLOR_BIT as VkImageAspectFlags,
-                                           VkImageLayout::VK_IMAGE_LAYOUT_TRANSFER_DST_OPTIMAL,
-                                           VkImageLayout::VK_IMAGE_LAYOUT_SHADER_READ_ONLY_OPTIMAL,
-                                           VkPipelineStageFlagBits::VK_PIPELINE_STAGE_TOP_OF_PIPE_BIT as VkPipelineStageFlags,
-                                           VkPipelineStageFlagBits::VK_PIPELINE_STAGE_TOP_OF_PIPE_BIT as VkPipelineStageFlags);
+        for readback in self.readback_requests.drain(..) {
+            readback.record(&self.prepresent_command_buffers[self.image_index]);
+            self.readbacks_in_flight[self.image_index].push(readback);
+        }
 
-        // Create an image view for the texture in its final location
+        self.prepresent_command_buffers[self.image_index]
+            .image_memory_barrier(self.swapchain.images[self.image_index],
+                                  VkAccessFlagBits::VK_ACCESS_COLOR_ATTACHMENT_WRITE_BIT as VkAccessFlags,
+                                  VkAccessFlagBits::VK_ACCESS_MEMORY_READ_BIT as VkAccessFlags,
+                                  VkImageLayout::VK_IMAGE_LAYOUT_COLOR_ATTACHMENT_OPTIMAL,
+                                  self.swapchain.present_layout,
+                                  VkImageAspectFlagBits::VK_IMAGE_ASPECT_COLOR_BIT as VkImageAspectFlags,
+                                  VkPipelineStageFlagBits::VK_PIPELINE_STAGE_ALL_GRAPHICS_BIT as VkPipelineStageFlags,
+                                  VkPipelineStageFlagBits::VK_PIPELINE_STAGE_ALL_GRAPHICS_BIT as VkPipelineStageFlags);
+
+        self.prepresent_command_buffers[self.image_index].end();
+
+        // Submit the frame's queued passes and the pre-present command buffer to the queue in one
+        // call, split into batches at the dependencies between the render graph passes, and
+        // signal the fence for this swapchain image once everything submitted has completed
         //
-        let view = RendererVkImageView::new(&renderer.device,
-                                            &image,
-                                            format,
-                                            VkImageAspectFlagBits::VK_IMAGE_ASPECT_COLOR_BIT as VkImageAspectFlags);
+        let queued: Vec<(Option<&'static str>, VkCommandBuffer)> = self.queued_command_buffers.lock().unwrap().drain(..).collect();
+        let mut passes: Vec<Option<&'static str>> = queued.iter().map(|&(pass, _)| pass).collect();
+        let mut command_buffers: Vec<VkCommandBuffer> = queued.iter().map(|&(_, c)| c).collect();
+        if self.record_compute_slices() {
+            passes.push(None);
+            command_buffers.push(self.compute_command_buffers[self.image_index].raw);
+        }
+        passes.push(None);
+        command_buffers.push(self.prepresent_command_buffers[self.image_index].raw);
 
-        // Create a sampler for the texture
+        // A binary semaphore is waited on once, so each edge between batches has its own.
+        // Without enough of them the command buffers are submitted in order as one batch.
         //
-        let sampler_info = VkSamplerCreateInfo {
-            sType: VkStructureType::VK_STRUCTURE_TYPE_SAMPLER_CREATE_INFO,
-            magFilter: VkFilter::VK_FILTER_LINEAR,
-            minFilter: VkFilter::VK_FILTER_LINEAR,
-            addressModeU: VkSamplerAddressMode::VK_SAMPLER_ADDRESS_MODE_REPEAT,
-            addressModeV: VkSamplerAddressMode::VK_SAMPLER_ADDRESS_MODE_REPEAT,
-            addressModeW: VkSamplerAddressMode::VK_SAMPLER_ADDRESS_MODE_REPEAT,
-            anisotropyEnable: true as VkBool32,
-            maxAnisotropy: 16.0,
-            borderColor: VkBorderColor::VK_BORDER_COLOR_INT_OPAQUE_BLACK,
-            unnormalizedCoordinates: false as VkBool32,
-            compareEnable: false as VkBool32,
-            compareOp: VkCompareOp::VK_COMPARE_OP_ALWAYS,
-            mipmapMode: VkSamplerMipmapMode::VK_SAMPLER_MIPMAP_MODE_LINEAR,
-            mipLodBias: 0.0f32,
-            minLod: 0.0f32,
-            maxLod: 0.0f32,
-            flags: 0,
-            pNext: ptr::null_mut(),
-        };
+        let mut batches = self.render_graph_schedule.submission_batches(&passes);
+        let edges: usize = batches.iter().map(|b| b.waits.len()).sum();
+        if self.pass_semaphores[self.image_index].len() < edges {
+            batches = vec![SubmissionBatch {
+                               start: 0,
+                               waits: vec![],
+                           }];
+        }
+
+        let mut wait_semaphores: Vec<Vec<VkSemaphore>> = vec![vec![]; batches.len()];
+        let mut wait_stages: Vec<Vec<VkPipelineStageFlags>> = vec![vec![]; batches.len()];
+        let mut signal_semaphores: Vec<Vec<VkSemaphore>> = vec![vec![]; batches.len()];
+
+        // Without a swapchain there is no acquire to wait for, nor a present to signal.  Only
+        // the batch first writing the swapchain image waits for it to be acquired, so the
+        // offscreen passes of earlier batches can start straight away.
+        //
+        if !self.swapchain.headless {
+            let acquire_batch = self.render_graph_schedule.swapchain_batch(&passes, &batches).unwrap_or(batches.len() - 1);
+            wait_semaphores[acquire_batch].push(self.image_available_semaphores[frame_slot].raw);
+            wait_stages[acquire_batch]
+                .push(VkPipelineStageFlagBits::VK_PIPELINE_STAGE_COLOR_ATTACHMENT_OUTPUT_BIT as VkPipelineStageFlags);
+            signal_semaphores[batches.len() - 1].push(self.render_finished_semaphores[frame_slot].raw);
+        }
+
+        let mut edge = 0;
+        for (i, batch) in batches.iter().enumerate() {
+            for &(from, kind) in batch.waits.iter() {
+                let semaphore = self.pass_semaphores[self.image_index][edge].raw;
+                edge += 1;
+                signal_semaphores[from].push(semaphore);
+                wait_semaphores[i].push(semaphore);
+                wait_stages[i].push(RendererVkGraphBarrier::wait_stages(kind));
+            }
+        }
 
-        let mut sampler: VkSampler = VK_NULL_HANDLE_MUT();
+        let submit_infos: Vec<VkSubmitInfo> = batches.iter()
+            .enumerate()
+            .map(|(i, batch)| {
+                let end = batches.get(i + 1).map_or(command_buffers.len(), |b| b.start);
+                VkSubmitInfo {
+                    sType: VkStructureType::VK_STRUCTURE_TYPE_SUBMIT_INFO,
+                    waitSemaphoreCount: wait_semaphores[i].len() as u32,
+                    pWaitSemaphores: wait_semaphores[i].as_ptr(),
+                    pWaitDstStageMask: wait_stages[i].as_ptr(),
+                    commandBufferCount: (end - batch.start) as u32,
+                    pCommandBuffers: command_buffers[batch.start..end].as_ptr(),
+                    signalSemaphoreCount: signal_semaphores[i].len() as u32,
+                    pSignalSemaphores: signal_semaphores[i].as_ptr(),
+                    pNext: ptr::null(),
+                }
+            })
+            .collect();
         unsafe {
-            check_result!("vkCreateSampler",
-                          vkCreateSampler(renderer.device.raw,
-                                          &sampler_info,
-                                          ptr::null(),
-                                          &mut sampler));
-        }
+            check_result!("vkResetFences",
+                          vkResetFences(self.device.raw, 1, &self.frame_fences[self.image_index].raw));
 
-        RendererVkTexture {
-            device: renderer.device.raw,
-            queue: renderer.device.graphics_queue,
-            image: image,
-            view: view,
-            sampler: sampler,
-            width: width,
-            height: height,
-            format: format,
-            row_pitch: staging_image_layout.rowPitch,
+            check_device_result!(self.events,
+                                 "vkQueueSubmit",
+                                 vkQueueSubmit(self.device.graphics_queue,
+                                               submit_infos.len() as u32,
+                                               submit_infos.as_ptr(),
+                                               self.frame_fences[self.image_index].raw));
         }
-    }
+        self.frame_slot_images[frame_slot] = Some(self.image_index);
+        self.frame_stats.queue_submits = self.queue_submits.swap(0, Ordering::Relaxed) as u32 + 1;
 
+        self.bandwidth_stats.end_frame();
+        self.skipped_binds.end_frame();
 
-    /// Obtain the pixel contents of a Vulkan texture object
-    ///
-    /// TODO: Optimise this, maybe
-    ///
-    ///
-    pub fn read_pixels(&self, renderer: &RendererVk) -> Vec<u8> {
-        let mut data: Vec<u8> = vec![];
-        let num_bytes: usize = (self.height * self.width * 3) as usize;
-        data.resize(num_bytes, 0);
+        let renderdoc_device = self.renderdoc_device();
+        self.capture_trigger.end_frame(renderdoc_device);
 
-        // Create a new host-accessible staging image to format the image data into
-        //
-        let props = VkMemoryPropertyFlagBits::VK_MEMORY_PROPERTY_HOST_VISIBLE_BIT as VkMemoryPropertyFlags |
-                    VkMemoryPropertyFlagBits::VK_MEMORY_PROPERTY_HOST_COHERENT_BIT as VkMemoryPropertyFlags;
+        self.cpu_profiler.end_frame(self.frame_number, self.max_threads);
+    }
 
-        let staging_image = RendererVkImage::new(&renderer.device,
-                                                 &renderer.physical_device,
-                                                 &renderer.aux_command_pool,
-                                                 self.width,
-                                                 self.height,
-                                                 self.format, // VkFormat::VK_FORMAT_R8G8B8A8_UNORM,
-                                                 VkImageTiling::VK_IMAGE_TILING_LINEAR,
-                                                 VkImageUsageFlagBits::VK_IMAGE_USAGE_TRANSFER_DST_BIT as VkImageUsageFlags,
-                                                 props,
-                                                 VkImageLayout::VK_IMAGE_LAYOUT_PREINITIALIZED,
-                                                 VkImageLayout::VK_IMAGE_LAYOUT_TRANSFER_DST_OPTIMAL);
+    /// Initiate a render pass with a variant of a shader compiled with a set of defines
+    ///
+    /// The variant must have been registered with register_shader_variant before the renderer
+    /// was set up.  Otherwise the base shader is used, and the miss is logged once.
+    ///
+    /// shader_name: The name of the base shader
+    /// defines: The defines of the variant
+    fn begin_pass_variant(&mut self, shader_name: &'static str, defines: &ShaderDefines) {
+        let variant_name = self.resource_manager.lock().unwrap().shader_variants.resolve(shader_name, defines);
+        self.begin_pass(variant_name);
+    }
 
-        // Transition the render target to something that we can transfer from
-        //
-        RendererVkImage::transition_layout_immediate(self.image.raw,
-                                           &renderer.device,
-                                           &renderer.aux_command_pool,
-                                           VkImageAspectFlagBits::VK_IMAGE_ASPECT_COLOR_BIT as VkImageAspectFlags,
-                                           VkImageLayout::VK_IMAGE_LAYOUT_COLOR_ATTACHMENT_OPTIMAL,
-                                           VkImageLayout::VK_IMAGE_LAYOUT_TRANSFER_SRC_OPTIMAL,
-                                           VkPipelineStageFlagBits::VK_PIPELINE_STAGE_TOP_OF_PIPE_BIT as VkPipelineStageFlags,
-                                           VkPipelineStageFlagBits::VK_PIPELINE_STAGE_TOP_OF_PIPE_BIT as VkPipelineStageFlags);
+    /// Begin a pass with the specified shader
+    ///
+    ///
+    fn begin_pass(&mut self, shader_name: &'static str) {
+        self.collect_built_pipelines(PipelineWait::Shader(shader_name));
+        self.shader_name = shader_name;
 
-        // Now copy from the device local memory to the staging image
-        //
-        RendererVkImage::copy(&renderer.device,
-                              &renderer.aux_command_pool,
-                              self.image.raw,
-                              staging_image.raw,
-                              self.width,
-                              self.height);
+        let sampled_textures;
+        {
+            let res_manager = self.resource_manager.lock().unwrap();
+            let ref shader_spec = res_manager.shader_specs[shader_name];
+            self.vertex_array_type = shader_spec.vertex_array_type;
+            self.current_pass_name = shader_spec.pass_name;
+            sampled_textures = shader_spec.uniform_specs
+                .iter()
+                .filter(|u| u.uniform_type == UniformType::CombinedImageSampler)
+                .count() as u32;
+        }
 
-        // Transition the render target back to something that we can render to
+        // Offscreen targets have RGBA32F colour attachments with depth, loaded and resolved,
+        // while the swapchain pass overwrites a single-sampled image without depth
         //
-        RendererVkImage::transition_layout_immediate(self.image.raw,
-                                           &renderer.device,
-                                           &renderer.aux_command_pool,
-                                           VkImageAspectFlagBits::VK_IMAGE_ASPECT_COLOR_BIT as VkImageAspectFlags,
-                                           VkImageLayout::VK_IMAGE_LAYOUT_TRANSFER_SRC_OPTIMAL,
-                                           VkImageLayout::VK_IMAGE_LAYOUT_COLOR_ATTACHMENT_OPTIMAL,
-                                           VkPipelineStageFlagBits::VK_PIPELINE_STAGE_TOP_OF_PIPE_BIT as VkPipelineStageFlags,
-                                           VkPipelineStageFlagBits::VK_PIPELINE_STAGE_TOP_OF_PIPE_BIT as VkPipelineStageFlags);
+        let (width, height) = self.current_render_target_extent;
+        let target = if self.current_depth_target.is_some() {
+            let colour_attachments = self.render_passes[self.current_pass_name].get_colour_attachment_count();
+            PassTargetDesc {
+                width: width,
+                height: height,
+                samples: self.sample_count as u32,
+                colour_bytes_per_sample: 16 * colour_attachments,
+                depth_bytes_per_sample: 4,
+                loads_colour: true,
+                resolves: true,
+            }
+        } else {
+            PassTargetDesc {
+                width: width,
+                height: height,
+                samples: 1,
+                colour_bytes_per_sample: 4,
+                depth_bytes_per_sample: 0,
+                loads_colour: false,
+                resolves: false,
+            }
+        };
+        self.bandwidth_stats.record_pass(PassBandwidth::estimate(shader_name, &target, sampled_textures));
 
-        // Transition the staging image to something that we can map
+        // Each pass in a frame has its own set of command buffers, as earlier passes may still
+        // be executing.  Create them the first time a frame has this many passes.
         //
-        RendererVkImage::transition_layout_immediate(staging_image.raw,
-                                           &renderer.device,
-                                           &renderer.aux_command_pool,
-                                           VkImageAspectFlagBits::VK_IMAGE_ASPECT_COLOR_BIT as VkImageAspectFlags,
-                                           VkImageLayout::VK_IMAGE_LAYOUT_TRANSFER_DST_OPTIMAL,
-                                           VkImageLayout::VK_IMAGE_LAYOUT_GENERAL,
-                                           VkPipelineStageFlagBits::VK_PIPELINE_STAGE_TOP_OF_PIPE_BIT as VkPipelineStageFlags,
-                                           VkPipelineStageFlagBits::VK_PIPELINE_STAGE_TOP_OF_PIPE_BIT as VkPipelineStageFlags);
+        let image_index = self.image_index;
+        let pass_index = self.pass_index;
+        if self.command_buffers[image_index].len() == pass_index {
+            let mut pass_command_buffers = Vec::with_capacity(self.max_threads);
+            for thr in 0..self.max_threads {
+                pass_command_buffers.push(RendererVkCommandBuffer::new(&self.device,
+                                                                       &self.command_pools[thr],
+                                                                       true /* primary */));
+            }
+            self.command_buffers[image_index].push(pass_command_buffers);
+            self.prepass_command_buffers[image_index].push(RendererVkCommandBuffer::new(&self.device,
+                                                                                        &self.aux_command_pool,
+                                                                                        true /* primary */));
+        }
 
-        // Map the staging image data into memory
+        // Work that must happen outside the render pass is queued straight away, so that it
+        // precedes any chunks the threads submit during the pass
         //
-        let mut raw: *mut c_void = VK_NULL_HANDLE_MUT();
         unsafe {
-            check_result!("vkMapMemory",
-                          vkMapMemory(renderer.device.raw,
-                                      staging_image.memory,
-                                      0, // Offset
-                                      VK_WHOLE_SIZE as u64,
-                                      0, // Flags
-                                      &mut raw));
+            check_result!("vkResetCommandBuffer",
+                          vkResetCommandBuffer(self.prepass_command_buffers[image_index][pass_index].raw,
+                                               0 /* flags */));
         }
+        self.prepass_command_buffers[image_index][pass_index].begin_primary(true, // one_time_submit
+                                                                            false, // render_pass_continue
+                                                                            true /* simultaneous_use */);
 
-        let raw_f32 = raw as *const f32;
-
-        unsafe {
-            for y in 0..self.height {
-                for x in 0..self.width {
-                    for i in 0..3 {
-                        let f = *raw_f32.offset(((y as u64 * (self.row_pitch >> 2) + 4 * x as u64) + i as u64) as isize);
-                        data[(((self.height - 1 - y) * self.width + x) * 3 + i) as usize] = (f * 255.0) as u8;
-                    }
+        // The first pass resets the frame's queries, which must happen outside a render pass
+        //
+        if self.timestamp_period_ns > 0.0 && pass_index < MAX_TIMED_PASSES {
+            let command_buffer_raw = self.prepass_command_buffers[image_index][pass_index].raw;
+            let query_pool_raw = self.pass_query_pools[image_index].raw;
+            unsafe {
+                if pass_index == 0 {
+                    vkCmdResetQueryPool(command_buffer_raw, query_pool_raw, 0, 2 * MAX_TIMED_PASSES as u32);
                 }
+                vkCmdWriteTimestamp(command_buffer_raw,
+                                    VkPipelineStageFlagBits::VK_PIPELINE_STAGE_TOP_OF_PIPE_BIT,
+                                    query_pool_raw,
+                                    2 * pass_index as u32);
             }
+            self.passes_timed[image_index].1.push(shader_name);
         }
 
-        unsafe {
-            vkUnmapMemory(renderer.device.raw, staging_image.memory);
+        self.record_pending_uniform_updates(&self.prepass_command_buffers[image_index][pass_index]);
+
+        for barrier in self.pending_graph_barriers.drain(..) {
+            barrier.record(&self.prepass_command_buffers[image_index][pass_index]);
         }
 
-        data
-    }
-}
+        let pass_clear = mem::replace(&mut self.pending_pass_clear, PassClear::default());
+        if !pass_clear.is_empty() {
+            self.record_pass_clear(&pass_clear);
+        }
 
-impl Drop for RendererVkTexture {
-    fn drop(&mut self) {
-        unsafe {
-            // This is possibly using a sledgehammer to crack a nut, but the sampler
-            // must not be in use when we try to destroy it
-            check_result!("vkQueueWaitIdle", vkQueueWaitIdle(self.queue));
-            vkDestroySampler(self.device, self.sampler, ptr::null());
+        self.prepass_command_buffers[image_index][pass_index].end();
+        self.queue_command_buffers(&vec![self.prepass_command_buffers[image_index][pass_index].raw]);
+
+        for thr in 0..self.max_threads {
+            self.current_chunk[thr] = None;
+            self.chunk_draws[thr] = 0;
+            self.chunk_bytes[thr] = 0;
+            self.push_constant_state[thr].lock().unwrap().clear();
+            *self.draw_textures[thr].lock().unwrap() = RendererVkDrawTextures::new();
+
+            unsafe {
+                check_result!("vkResetCommandBuffer",
+                              vkResetCommandBuffer(self.command_buffers[image_index][pass_index][thr].raw,
+                                                   0 /* flags */));
+            }
+
+            self.command_buffers[image_index][pass_index][thr].begin_primary(true, // one_time_submit
+                                                                             false, // render_pass_continue
+                                                                             true /* simultaneous_use */);
+
+            self.begin_thread_render_pass(self.command_buffers[image_index][pass_index][thr].raw, thr);
         }
     }
-}
 
-impl Renderer for RendererVk {
-    /// To facilitate downcasting back to a concrete type
-    fn as_any(&self) -> &Any {
-        self
-    }
-    fn as_any_mut(&mut self) -> &mut Any {
-        self
+    /// Finish a pass with the specified shader
+    fn end_pass(&mut self) {
+        // Finish the render passes and command buffers of each thread's current chunk and
+        // store the command buffers in a collection to be queued
+        //
+        let mut command_buffers = Vec::with_capacity(self.max_threads);
+        for thr in 0..self.max_threads {
+            let command_buffer = self.current_command_buffer_raw(thr);
+            self.end_thread_render_pass(command_buffer);
+
+            // The last thread's command buffer is submitted last, so its timestamp follows all
+            // of the pass's work
+            if thr == self.max_threads - 1 && self.timestamp_period_ns > 0.0 && self.pass_index < MAX_TIMED_PASSES {
+                unsafe {
+                    vkCmdWriteTimestamp(command_buffer,
+                                        VkPipelineStageFlagBits::VK_PIPELINE_STAGE_BOTTOM_OF_PIPE_BIT,
+                                        self.pass_query_pools[self.image_index].raw,
+                                        2 * self.pass_index as u32 + 1);
+                }
+            }
+
+            unsafe {
+                check_result!("vkEndCommandBuffer", vkEndCommandBuffer(command_buffer));
+            }
+            command_buffers.push(command_buffer);
+        }
+
+        // Queue the command buffers, to be submitted with the rest of the frame in end_frame.
+        // There is no need to wait for them, as the later passes are ordered after them by the
+        // barriers recorded between the passes.
+        //
+        self.queue_draw_command_buffers(command_buffers);
+
+        self.pass_index += 1;
     }
 
-    /// Return the renderer type
-    fn renderer_type(&self) -> RendererType {
-        RendererType::RendererVk
+    /// Select the render target so that renderpasses output there instead of the swapchain
+    ///
+    /// num: The texture number to bind the render target texture to
+    /// render_target: The render target to select
+    fn select_render_target(&mut self, _: i32, render_target: &mut RenderTarget) {
+        let target_vk = match render_target.as_any_mut().downcast_mut::<RenderTargetVk>() {
+            Some(r) => r,
+            None => panic!("Unexpected runtime type"),
+        };
+
+        self.current_render_target = Some(target_vk.get_framebuffer_raw());
+        self.current_depth_target = Some(target_vk.get_depth_image_raw());
+        self.current_render_target_extent = (target_vk.get_width(), target_vk.get_height());
     }
 
-    /// Obtain an Arc for the ThreadData structure for the specified thread
-    fn get_threaddata(&self, thr: usize) -> Arc<Mutex<Box<ThreadData>>> {
-        self.threaddata_arcs[thr].clone()
+    /// Deselect the render target so that renderpasses output to the swapchain
+    fn deselect_render_target(&mut self) {
+        self.current_render_target = Some(self.framebuffers[self.image_index].raw);
+        self.current_depth_target = None;
+        self.current_render_target_extent = (self.swapchain.extent.width, self.swapchain.extent.height);
     }
 
-    /// Return the maximum number of threads allowed
-    fn get_maxthreads(&self) -> usize {
-        return self.max_threads;
+    /// Return the schedule of the render graph declared in the resource manager
+    ///
+    /// The passes are in the order they should be run.  There are none when no graph was
+    /// declared, or when it could not be scheduled.
+    fn render_graph_schedule(&self) -> &RenderGraphSchedule {
+        &self.render_graph_schedule
     }
 
-    /// Finish initialisation of resources
+    /// Bind a render target to an attachment of the render graph
     ///
-    /// shaders: A map of the shaders to set up, keyed by name
-    /// textures: A map of the textures to set up, keyed by name
-    fn finish_resource_initialisation(&mut self,
-                                      shaders: &HashMap<&'static str, &Box<Shader>>,
-                                      textures: &HashMap<&'static str, &Box<Texture>>) {
-        let mut renderer_vk: &mut RendererVk = match self.as_any_mut().downcast_mut::<RendererVk>() {
+    /// Depth attachments bind the target's depth image, and colour attachments its texture.
+    /// Both are in their attachment layouts once the render target is set up.  The swapchain
+    /// attachment is transitioned by the swapchain render pass and needs no binding.
+    ///
+    /// attachment: The name of the attachment
+    /// render_target: The render target holding the attachment
+    fn bind_graph_attachment(&mut self, attachment: &'static str, render_target: &RenderTarget) {
+        let target_vk = match render_target.as_any().downcast_ref::<RenderTargetVk>() {
             Some(r) => r,
             None => panic!("Unexpected runtime type"),
         };
 
-        let mut shaders_vk = HashMap::new();
-        for shader in shaders.iter() {
-            let (nm, sh) = shader;
-            match sh.as_any().downcast_ref::<ShaderSpirv>() {
-                Some(s) => shaders_vk.insert(*nm, s),
+        let bound = if self.render_graph_schedule.is_depth_attachment(attachment) {
+            RendererVkGraphAttachment {
+                image: target_vk.get_depth_image_raw(),
+                aspect_mask: self.depth_aspect_mask(),
+                layout: VkImageLayout::VK_IMAGE_LAYOUT_DEPTH_STENCIL_ATTACHMENT_OPTIMAL,
+            }
+        } else {
+            let texture_vk = match target_vk.texture.as_any().downcast_ref::<TextureVk>() {
+                Some(t) => t,
                 None => panic!("Unexpected runtime type"),
             };
-        }
+            RendererVkGraphAttachment {
+                image: texture_vk.texture.get_image_raw(),
+                aspect_mask: VkImageAspectFlagBits::VK_IMAGE_ASPECT_COLOR_BIT as VkImageAspectFlags,
+                layout: VkImageLayout::VK_IMAGE_LAYOUT_COLOR_ATTACHMENT_OPTIMAL,
+            }
+        };
+        self.graph_attachments.insert(attachment, bound);
+    }
 
-        let mut textures_vk = HashMap::new();
-        for texture in textures.iter() {
-            let (nm, tx) = texture;
-            match tx.as_any().downcast_ref::<TextureVk>() {
-                Some(t) => textures_vk.insert(*nm, t),
-                None => panic!("Unexpected runtime type"),
-            };
+    /// Prepare the attachments of a pass of the render graph
+    ///
+    /// The transitions of the bound attachments are recorded before the next pass begins.
+    /// Attachments that are not bound are left alone.
+    ///
+    /// pass_name: The name of the pass in the render graph
+    fn begin_graph_pass(&mut self, pass_name: &'static str) {
+        let (transitions, clear) = match self.render_graph_schedule.find(pass_name) {
+            Some(pass) => (pass.transitions.clone(), pass.clear),
+            None => {
+                log_error!("No render graph pass named {}", pass_name);
+                return;
+            }
+        };
+        self.pending_pass_clear = clear;
+        self.current_graph_pass = Some(pass_name);
+
+        for transition in transitions {
+            if let Some(attachment) = self.graph_attachments.get_mut(transition.attachment) {
+                let new_layout = RendererVkGraphBarrier::layout(transition.to);
+                if attachment.layout as u32 != new_layout as u32 {
+                    self.pending_graph_barriers.push(RendererVkGraphBarrier {
+                        image: attachment.image,
+                        aspect_mask: attachment.aspect_mask,
+                        old_layout: attachment.layout,
+                        new_layout: new_layout,
+                    });
+                    attachment.layout = new_layout;
+                }
+            }
         }
-
-        renderer_vk.setup(&shaders_vk, &textures_vk);
     }
 
-    /// Clear the depth buffer before starting rendering
-    fn clear_depth_buffer(&self) {
-        // First check that there is a depth target bound
-        assert!(self.current_depth_target.is_some());
-
-        unsafe {
-            check_result!("vkResetCommandBuffer",
-                          vkResetCommandBuffer(self.cleardepth_command_buffers[self.image_index].raw,
-                                               0 /* flags */));
-        }
+    /// Notify the renderer that the window has been resized
+    ///
+    /// The swapchain is recreated when the next frame begins.
+    ///
+    /// width: The new framebuffer width
+    /// height: The new framebuffer height
+    fn window_resized(&mut self, _: u32, _: u32) {
+        self.swapchain_out_of_date.store(true, Ordering::SeqCst);
+    }
 
-        // Clear the frame.  We need to do the clear here instead of as a
-        // load op since all of the threads share the same pipelines and
-        // render pass.
-        //
-        let clear_stencil = unsafe {
-            mem::transmute_copy(&VkClearDepthStencilValue {
-                depth: 1.0f32,
-                stencil: 0,
-            })
+    /// Clear the colour contents of a render target to the specified value
+    ///
+    /// Every colour attachment of the target is cleared.
+    ///
+    /// render_target: The render target to clear
+    /// colour: The RGBA value to clear to
+    fn clear_render_target(&mut self, render_target: &RenderTarget, colour: &Vec4<f32>) {
+        let target_vk = match render_target.as_any().downcast_ref::<RenderTargetVk>() {
+            Some(r) => r,
+            None => panic!("Unexpected runtime type"),
         };
 
-        self.cleardepth_command_buffers[self.image_index].begin_primary(true, // one_time_submit
-                                                                        false, // render_pass_continue
-                                                                        true /* simultaneous_use */);
-
-        RendererVkImage::transition_layout(self.current_depth_target.unwrap(),
-                                           &self.cleardepth_command_buffers[self.image_index],
-                                           VkImageAspectFlagBits::VK_IMAGE_ASPECT_DEPTH_BIT as VkImageAspectFlags,
-                                           VkImageLayout::VK_IMAGE_LAYOUT_UNDEFINED,
-                                           VkImageLayout::VK_IMAGE_LAYOUT_TRANSFER_DST_OPTIMAL,
-                                           VkPipelineStageFlagBits::VK_PIPELINE_STAGE_COLOR_ATTACHMENT_OUTPUT_BIT
-                                               as VkPipelineStageFlags,
-                                           VkPipelineStageFlagBits::VK_PIPELINE_STAGE_TRANSFER_BIT
-                                               as VkPipelineStageFlags);
-
+        // Every colour attachment is cleared.  A multisampled target is cleared in both its
+        // multisampled and resolved images, since the textures are only overwritten by a resolve
+        // when the target is next rendered to.
+        //
+        let mut images = target_vk.get_colour_images_raw();
+        images.extend(target_vk.get_multisample_colour_images_raw());
+        let clear_colour = unsafe { mem::transmute_copy(&[colour.x, colour.y, colour.z, colour.w]) };
         let subresource_range = VkImageSubresourceRange {
-            aspectMask: VkImageAspectFlagBits::VK_IMAGE_ASPECT_DEPTH_BIT as VkImageAspectFlags,
+            aspectMask: VkImageAspectFlagBits::VK_IMAGE_ASPECT_COLOR_BIT as VkImageAspectFlags,
             baseMipLevel: 0,
             levelCount: 1,
             baseArrayLayer: 0,
             layerCount: 1,
         };
 
-        unsafe {
-            vkCmdClearDepthStencilImage(self.cleardepth_command_buffers[self.image_index].raw,
-                                        self.current_depth_target.unwrap(),
-                                        VkImageLayout::VK_IMAGE_LAYOUT_TRANSFER_DST_OPTIMAL,
-                                        &clear_stencil,
-                                        1, // Subrange count
-                                        &subresource_range);
+        self.flush_queued_command_buffers();
+        let mut one_time = OneTimeCommandBuffer::new(&self.device, &self.aux_command_pool);
+
+        for image in images {
+            // The previous contents are being discarded, so the transition can be from undefined
+            //
+            RendererVkImage::transition_layout(image,
+                                               &one_time.buffer,
+                                               VkImageAspectFlagBits::VK_IMAGE_ASPECT_COLOR_BIT as VkImageAspectFlags,
+                                               VkImageLayout::VK_IMAGE_LAYOUT_UNDEFINED,
+                                               VkImageLayout::VK_IMAGE_LAYOUT_TRANSFER_DST_OPTIMAL,
+                                               VkPipelineStageFlagBits::VK_PIPELINE_STAGE_COLOR_ATTACHMENT_OUTPUT_BIT
+                                                   as VkPipelineStageFlags,
+                                               VkPipelineStageFlagBits::VK_PIPELINE_STAGE_TRANSFER_BIT
+                                                   as VkPipelineStageFlags);
+
+            unsafe {
+                vkCmdClearColorImage(one_time.buffer.raw,
+                                     image,
+                                     VkImageLayout::VK_IMAGE_LAYOUT_TRANSFER_DST_OPTIMAL,
+                                     &clear_colour,
+                                     1, // Subrange count
+                                     &subresource_range);
+            }
+
+            RendererVkImage::transition_layout(image,
+                                               &one_time.buffer,
+                                               VkImageAspectFlagBits::VK_IMAGE_ASPECT_COLOR_BIT as VkImageAspectFlags,
+                                               VkImageLayout::VK_IMAGE_LAYOUT_TRANSFER_DST_OPTIMAL,
+                                               VkImageLayout::VK_IMAGE_LAYOUT_COLOR_ATTACHMENT_OPTIMAL,
+                                               VkPipelineStageFlagBits::VK_PIPELINE_STAGE_TRANSFER_BIT
+                                                   as VkPipelineStageFlags,
+                                               VkPipelineStageFlagBits::VK_PIPELINE_STAGE_COLOR_ATTACHMENT_OUTPUT_BIT
+                                                   as VkPipelineStageFlags);
         }
 
-        RendererVkImage::transition_layout(self.current_depth_target.unwrap(),
-                                           &self.cleardepth_command_buffers[self.image_index],
-                                           VkImageAspectFlagBits::VK_IMAGE_ASPECT_DEPTH_BIT as VkImageAspectFlags,
-                                           VkImageLayout::VK_IMAGE_LAYOUT_TRANSFER_DST_OPTIMAL,
-                                           VkImageLayout::VK_IMAGE_LAYOUT_DEPTH_STENCIL_ATTACHMENT_OPTIMAL,
-                                           VkPipelineStageFlagBits::VK_PIPELINE_STAGE_TRANSFER_BIT
-                                               as VkPipelineStageFlags,
-                                           VkPipelineStageFlagBits::VK_PIPELINE_STAGE_COLOR_ATTACHMENT_OUTPUT_BIT
-                                               as VkPipelineStageFlags);
+        one_time.execute();
+    }
+
+    /// Clear the depth contents of a render target to the specified value
+    ///
+    /// render_target: The render target whose depth buffer should be cleared
+    /// depth: The depth value to clear to
+    fn clear_depth_target(&mut self, render_target: &RenderTarget, depth: f32) {
+        let target_vk = match render_target.as_any().downcast_ref::<RenderTargetVk>() {
+            Some(r) => r,
+            None => panic!("Unexpected runtime type"),
+        };
 
-        self.cleardepth_command_buffers[self.image_index].end();
+        self.flush_queued_command_buffers();
+        let mut one_time = OneTimeCommandBuffer::new(&self.device, &self.aux_command_pool);
+        RendererVk::record_depth_clear(&one_time.buffer, target_vk.get_depth_image_raw(), self.depth_aspect_mask(), depth);
+        one_time.execute();
+    }
 
-        // Submit the command buffer to the queue
-        //
-        let mut command_buffers = Vec::with_capacity(1);
-        command_buffers.push(self.cleardepth_command_buffers[self.image_index].raw);
+    /// Queue an asynchronous readback of the colour contents of a render target
+    ///
+    /// The copy is recorded into the pre-present command buffer at the end of the frame.
+    ///
+    /// render_target: The render target to read back
+    fn request_readback(&mut self, render_target: &RenderTarget) -> ReadbackHandle {
+        let target_vk = match render_target.as_any().downcast_ref::<RenderTargetVk>() {
+            Some(r) => r,
+            None => panic!("Unexpected runtime type"),
+        };
 
-        let wait_semaphores: Vec<VkSemaphore> = vec![];
-        let wait_stages: Vec<VkPipelineStageFlags> =
-            vec![VkPipelineStageFlagBits::VK_PIPELINE_STAGE_TOP_OF_PIPE_BIT as VkPipelineStageFlags];
-        let signal_semaphores = vec![];
+        let texture_vk = match target_vk.texture.as_any().downcast_ref::<TextureVk>() {
+            Some(t) => t,
+            None => panic!("Unexpected runtime type"),
+        };
 
-        let submit_info = VkSubmitInfo {
-            sType: VkStructureType::VK_STRUCTURE_TYPE_SUBMIT_INFO,
-            waitSemaphoreCount: wait_semaphores.len() as u32,
-            pWaitSemaphores: wait_semaphores.as_ptr(),
-            pWaitDstStageMask: wait_stages.as_ptr(),
-            commandBufferCount: command_buffers.len() as u32,
-            pCommandBuffers: command_buffers.as_ptr(),
-            signalSemaphoreCount: signal_semaphores.len() as u32,
-            pSignalSemaphores: signal_semaphores.as_ptr(),
-            pNext: ptr::null(),
+        let handle = self.next_readback_handle;
+        self.next_readback_handle = self.next_readback_handle.wrapping_add(1);
+
+        // Reuse a staging buffer freed when this swapchain image's earlier readbacks were retrieved
+        //
+        let width = texture_vk.texture.get_width();
+        let height = texture_vk.texture.get_height();
+        let size = RendererVkReadback::staging_size(width, height);
+        let staging = &mut self.readback_staging[self.image_index];
+        let buffer = match staging.iter().position(|b| b.size >= size) {
+            Some(i) => staging.swap_remove(i),
+            None => RendererVkReadback::new_staging_buffer(&self.device, &self.physical_device, size),
         };
-        unsafe {
-            // TODO: Deal with VK_ERROR_DEVICE_LOST result
-            check_result!("vkQueueSubmit",
-                          vkQueueSubmit(self.device.graphics_queue,
-                                        1,
-                                        &submit_info,
-                                        VK_NULL_HANDLE_MUT() /* Fence */));
 
-            check_result!("vkQueueWaitIdle",
-                          vkQueueWaitIdle(self.device.graphics_queue));
-        }
+        let readback = RendererVkReadback::new(handle, buffer, texture_vk.texture.get_image_raw(), width, height);
+        self.readback_requests.push(readback);
+
+        handle
     }
 
-    /// Convert a renderer primitive type to an OpenGL primitive type
-    fn primitive(&self, _: PrimitiveType) -> u32 {
-        0
+    /// Return the number of frames after which a readback requested this frame is available
+    ///
+    /// A readback is retrieved once the fence of its swapchain image has signalled, which is
+    /// waited for when the image is next acquired, as many frames later as there are frame fences.
+    fn readback_latency_frames(&self) -> u64 {
+        self.frame_fences.len() as u64
     }
 
-    /// Set a integer in part of the memory put aside for the named uniform buffer
+    /// Poll for the result of an earlier readback request
     ///
-    /// buffer_name: The name of the uniform buffer to contain the new value
-    /// uniform_name: The name of the uniform whose value should be set
-    /// value: The value to set for the uniform
-    fn set_uniform_buffer_int(&self, buffer_name: &str, uniform_name: &str, value: i32) {
-        let ref buffer = self.uniform_buffers[buffer_name];
-        let offset = buffer.offsets[uniform_name];
-        unsafe {
-            let dst: *const u8 = buffer.bytes.as_ptr().offset(offset as isize);
-            let dst_i32 = dst as *mut i32;
-            *dst_i32 = value;
-        }
+    /// handle: The handle returned by request_readback
+    fn poll_readback(&mut self, handle: ReadbackHandle) -> Option<Image> {
+        self.readbacks_completed.take(handle)
     }
 
-    /// Set a floating point in part of the memory put aside for the named uniform buffer
+    /// Queue an asynchronous readback of a render target, calling back with the result
     ///
-    /// buffer_name: The name of the uniform buffer to contain the new value
-    /// uniform_name: The name of the uniform whose value should be set
-    /// value: The value to set for the uniform
-    fn set_uniform_buffer_float(&self, buffer_name: &str, uniform_name: &str, value: f32) {
-        let ref buffer = self.uniform_buffers[buffer_name];
-        let offset = buffer.offsets[uniform_name];
-        unsafe {
-            let dst: *const u8 = buffer.bytes.as_ptr().offset(offset as isize);
-            let dst_f32 = dst as *mut f32;
-            *dst_f32 = value;
-        }
+    /// render_target: The render target to read back
+    /// callback: The function to call with the contents
+    fn request_readback_callback(&mut self, render_target: &RenderTarget, callback: ReadbackCallback) {
+        let handle = self.request_readback(render_target);
+        self.readbacks_completed.set_callback(handle, callback);
     }
 
-    /// Set a 3-component vector in part of the memory put aside for the named uniform buffer
+    /// Copy the swapchain image about to be presented into CPU memory
     ///
-    /// buffer_name: The name of the uniform buffer to contain the new value
-    /// uniform_name: The name of the uniform whose value should be set
-    /// value: The value to set for the uniform
-    fn set_uniform_buffer_vec3(&self, buffer_name: &str, uniform_name: &str, value: &Vec3<f32>) {
-        let ref buffer = self.uniform_buffers[buffer_name];
-        let offset = buffer.offsets[uniform_name];
+    /// The copy is submitted after the frame and waited for, and the image is returned to the
+    /// presentation layout afterwards.  BGRA swapchain formats are swizzled to RGBA.
+    fn capture_frame(&mut self) -> Option<FrameCapture> {
+        if !self.swapchain.capturable {
+            log_warning!("The swapchain images cannot be copied from, so the frame cannot be captured");
+            return None;
+        }
+
+        let swizzle_bgra = match self.swapchain.format {
+            VkFormat::VK_FORMAT_B8G8R8A8_UNORM |
+            VkFormat::VK_FORMAT_B8G8R8A8_SRGB => true,
+            VkFormat::VK_FORMAT_R8G8B8A8_UNORM |
+            VkFormat::VK_FORMAT_R8G8B8A8_SRGB => false,
+            format => {
+                log_warning!("Capturing frames from swapchain format {:?} is not supported", format);
+                return None;
+            }
+        };
+
+        let width = self.swapchain.extent.width;
+        let height = self.swapchain.extent.height;
+        let image = self.swapchain.images[self.image_index];
+        let colour_aspect = VkImageAspectFlagBits::VK_IMAGE_ASPECT_COLOR_BIT as VkImageAspectFlags;
+        let transfer_stage = VkPipelineStageFlagBits::VK_PIPELINE_STAGE_TRANSFER_BIT as VkPipelineStageFlags;
+
+        let buffer = RendererVkBuffer::new(&self.device,
+                                           &self.physical_device,
+                                           VkBufferUsageFlagBits::VK_BUFFER_USAGE_TRANSFER_DST_BIT as VkBufferUsageFlags,
+                                           VkMemoryPropertyFlagBits::VK_MEMORY_PROPERTY_HOST_VISIBLE_BIT as VkMemoryPropertyFlags |
+                                           VkMemoryPropertyFlagBits::VK_MEMORY_PROPERTY_HOST_COHERENT_BIT as VkMemoryPropertyFlags,
+                                           (width * height * 4) as usize);
+
+        // The queue executes the copy after the frame, which left the image ready to present
+        // (or, when headless, already ready to copy from)
+        //
+        let present_layout = self.swapchain.present_layout;
+        self.flush_queued_command_buffers();
+        let mut one_time = OneTimeCommandBuffer::new(&self.device, &self.aux_command_pool);
+
+        one_time.buffer.image_memory_barrier(image,
+                                             VkAccessFlagBits::VK_ACCESS_MEMORY_READ_BIT as VkAccessFlags,
+                                             VkAccessFlagBits::VK_ACCESS_TRANSFER_READ_BIT as VkAccessFlags,
+                                             present_layout,
+                                             VkImageLayout::VK_IMAGE_LAYOUT_TRANSFER_SRC_OPTIMAL,
+                                             colour_aspect,
+                                             VkPipelineStageFlagBits::VK_PIPELINE_STAGE_ALL_COMMANDS_BIT as VkPipelineStageFlags,
+                                             transfer_stage);
+
+        let region = VkBufferImageCopy {
+            bufferOffset: 0,
+            bufferRowLength: 0, // Tightly packed
+            bufferImageHeight: 0, // Tightly packed
+            imageSubresource: VkImageSubresourceLayers {
+                aspectMask: colour_aspect,
+                mipLevel: 0,
+                baseArrayLayer: 0,
+                layerCount: 1,
+            },
+            imageOffset: VkOffset3D { x: 0, y: 0, z: 0 },
+            imageExtent: VkExtent3D {
+                width: width,
+                height: height,
+                depth: 1,
+            },
+        };
+
         unsafe {
-            let dst: *const u8 = buffer.bytes.as_ptr().offset(offset as isize);
-            let dst_f32 = dst as *mut f32;
-            let src: *const f32 = mem::transmute(value);
-            ptr::copy_nonoverlapping(src, dst_f32, 3);
+            vkCmdCopyImageToBuffer(one_time.buffer.raw,
+                                   image,
+                                   VkImageLayout::VK_IMAGE_LAYOUT_TRANSFER_SRC_OPTIMAL,
+                                   buffer.raw,
+                                   1, // Region count
+                                   &region);
+        }
+
+        one_time.buffer.image_memory_barrier(image,
+                                             VkAccessFlagBits::VK_ACCESS_TRANSFER_READ_BIT as VkAccessFlags,
+                                             VkAccessFlagBits::VK_ACCESS_MEMORY_READ_BIT as VkAccessFlags,
+                                             VkImageLayout::VK_IMAGE_LAYOUT_TRANSFER_SRC_OPTIMAL,
+                                             present_layout,
+                                             colour_aspect,
+                                             transfer_stage,
+                                             VkPipelineStageFlagBits::VK_PIPELINE_STAGE_BOTTOM_OF_PIPE_BIT as VkPipelineStageFlags);
+
+        one_time.buffer.buffer_memory_barrier(buffer.raw,
+                                              VkAccessFlagBits::VK_ACCESS_TRANSFER_WRITE_BIT as VkAccessFlags,
+                                              VkAccessFlagBits::VK_ACCESS_HOST_READ_BIT as VkAccessFlags,
+                                              transfer_stage,
+                                              VkPipelineStageFlagBits::VK_PIPELINE_STAGE_HOST_BIT as VkPipelineStageFlags);
+
+        one_time.execute();
+
+        // Map the staging buffer and copy out its contents
+        //
+        let mut data: Vec<u8> = vec![0; (width * height * 4) as usize];
+        let mapped = buffer.mapped();
+        mapped.invalidate();
+        unsafe {
+            ptr::copy_nonoverlapping(mapped.as_ptr() as *const u8, data.as_mut_ptr(), data.len());
+        }
+
+        if swizzle_bgra {
+            for pixel in data.chunks_mut(4) {
+                pixel.swap(0, 2);
+            }
         }
+
+        Some(FrameCapture {
+            width: width,
+            height: height,
+            data: data,
+        })
     }
 
-    /// Set a 4x4-component matrix in part of the memory put aside for the named uniform buffer
+    /// Return the frame pacing statistics
+    fn frame_stats(&self) -> FrameStats {
+        FrameStats {
+            frames: self.frame_number,
+            skipped_binds: self.skipped_binds.last_frame(),
+            ..self.frame_stats
+        }
+    }
+
+    /// Return the usage statistics of the device memory allocated to buffers and images
+    fn memory_stats(&self) -> MemoryStats {
+        self.device.allocator.lock().unwrap().stats()
+    }
+
+    /// Return the memory used by buffers and images, by heap and by what it is used for
     ///
-    /// buffer_name: The name of the uniform buffer to contain the new value
-    /// uniform_name: The name of the uniform whose value should be set
-    /// matrix: The value to set for the uniform
-    fn set_uniform_buffer_matrix(&self, buffer_name: &str, uniform_name: &str, matrix: &Mat4<f32>) {
-        let ref buffer = self.uniform_buffers[buffer_name];
-        let offset = buffer.offsets[uniform_name];
+    /// Each heap's budget and the process's usage of it are reported where VK_EXT_memory_budget
+    /// is available.
+    fn get_memory_usage(&self) -> MemoryUsage {
+        let mut memory_properties = VkPhysicalDeviceMemoryProperties::default();
         unsafe {
-            let dst: *const u8 = buffer.bytes.as_ptr().offset(offset as isize);
-            let dst_f32 = dst as *mut f32;
-            let src: *const f32 = mem::transmute(matrix);
-            ptr::copy_nonoverlapping(src, dst_f32, 16);
+            vkGetPhysicalDeviceMemoryProperties(self.physical_device.raw, &mut memory_properties);
+        }
+        let budgets = self.memory_budget.as_ref().map(|b| b.heap_budgets(&self.physical_device));
+
+        let allocator = self.device.allocator.lock().unwrap();
+        let heaps = (0..memory_properties.memoryHeapCount as usize)
+            .map(|h| {
+                let heap = &memory_properties.memoryHeaps[h];
+                let allocated = (0..memory_properties.memoryTypeCount)
+                    .filter(|t| memory_properties.memoryTypes[*t as usize].heapIndex as usize == h)
+                    .map(|t| allocator.memory_type_stats(t).bytes_reserved)
+                    .sum();
+                HeapUsage {
+                    size: heap.size,
+                    device_local: (heap.flags as u32) & (VkMemoryHeapFlagBits::VK_MEMORY_HEAP_DEVICE_LOCAL_BIT as u32) != 0,
+                    allocated: allocated,
+                    budget: budgets.as_ref().map(|b| b[h].0),
+                    usage: budgets.as_ref().map(|b| b[h].1),
+                }
+            })
+            .collect();
+
+        MemoryUsage {
+            heaps: heaps,
+            categories: self.device.memory_usage.lock().unwrap().categories(),
         }
     }
 
-    /// Set a floating point vector in part of the memory put aside for the named uniform buffer
+    /// Return the clock of engine and real time, ticked by begin_frame
+    fn frame_clock(&self) -> &FrameClock {
+        &self.frame_clock
+    }
+
+    /// Return the clock of engine and real time, to pause, step or scale engine time
+    fn frame_clock_mut(&mut self) -> &mut FrameClock {
+        &mut self.frame_clock
+    }
+
+    /// Return the previous and current transforms of the objects moved by a fixed-timestep
+    /// simulation, published by begin_frame
+    fn transform_interpolator(&self) -> &TransformInterpolator {
+        &self.transform_interpolator
+    }
+
+    /// Return the transforms of the simulated objects, to record their transforms each step and
+    /// set the interpolation alpha before begin_frame
+    fn transform_interpolator_mut(&mut self) -> &mut TransformInterpolator {
+        &mut self.transform_interpolator
+    }
+
+    /// Return the estimated memory traffic of each pass of the last frame
+    fn pass_bandwidth(&self) -> &Vec<PassBandwidth> {
+        self.bandwidth_stats.last_frame()
+    }
+
+    /// Return the GPU times of the passes of recent frames
     ///
-    /// buffer_name: The name of the uniform buffer to contain the new value
-    /// uniform_name: The name of the uniform whose value should be set
-    /// vector: The vector to set for the uniform
-    fn set_uniform_buffer_float_vector(&self, buffer_name: &str, uniform_name: &str, vector: &Vec<f32>) {
-        let ref buffer = self.uniform_buffers[buffer_name];
-        let offset = buffer.offsets[uniform_name];
-        let stride = buffer.strides[uniform_name];
-        if stride == 0 || stride == 4 {
-            unsafe {
-                let dst: *const u8 = buffer.bytes.as_ptr().offset(offset as isize);
-                let dst_f32 = dst as *mut f32;
-                let src: *const f32 = mem::transmute(vector.as_ptr());
-                ptr::copy_nonoverlapping(src, dst_f32, vector.len());
+    /// The timings of a frame are harvested when its swapchain image is next acquired, after
+    /// its fence has been waited on, so they lag by the number of swapchain images.
+    fn gpu_timings(&self) -> &GpuTimingHistory {
+        &self.gpu_timings
+    }
+
+    /// Return the profiler of the CPU time spent in scopes timed with profile_scope
+    fn cpu_profiler(&self) -> &CpuProfiler {
+        &self.cpu_profiler
+    }
+
+    /// Return the listeners to the renderer's events, to subscribe to them
+    fn events(&self) -> &RendererEvents {
+        &self.events
+    }
+
+    /// Draw the triangles of a thread's data once for each instance, and reset the thread data
+    ///
+    /// Instance data larger than an instance buffer is drawn by several draws of the triangles.
+    ///
+    /// thread_data: The thread data holding the triangles
+    /// instance_data: The interleaved per-instance attributes of all of the instances
+    /// instance_count: The number of instances to draw
+    fn draw_instanced(&mut self, thread_data: &mut ThreadData, instance_data: &Vec<f32>, instance_count: usize) {
+        if thread_data.index > 0 && instance_count > 0 {
+            debug_assert!(instance_data.len() % instance_count == 0);
+
+            let thr = thread_data.thr;
+            let ty = self.vertex_array_type;
+            let image_index = self.image_index;
+
+            let vertex_components = 3 * VertexArrayType::components_per_vertex(ty) * thread_data.index;
+            let vb_index = self.next_vertex_buffer(thr);
+            let vertex_buffer_raw;
+            {
+                let ref vertex_buffer = self.vertex_buffer[image_index][ty as usize][thr][vb_index];
+                vertex_buffer.host_memory().write_floats(&thread_data.data[0..vertex_components]);
+                vertex_buffer_raw = vertex_buffer.buffer.raw;
             }
-        } else {
-            // This path requires observing the stride
-            unsafe {
-                for i in 0..vector.len() {
-                    let dst: *const u8 = buffer.bytes.as_ptr().offset((i * stride + offset) as isize);
-                    let dst_f32 = dst as *mut f32;
-                    let src: *const f32 = mem::transmute(vector.as_ptr().offset(i as isize));
-                    *dst_f32 = *src;
+            let vertex_bytes = vertex_components * mem::size_of::<f32>();
+            self.queue_vertex_upload(thr, vertex_bytes);
+
+            let components_per_instance = instance_data.len() / instance_count;
+            let instances_per_buffer = INSTANCE_BUFFER_SIZE / (components_per_instance * mem::size_of::<f32>());
+            debug_assert!(instances_per_buffer > 0);
+
+            let command_buffer_raw = self.current_command_buffer_raw(thr);
+            self.bind_draw_state(thr, command_buffer_raw);
+            let mut first_instance = 0;
+            while first_instance < instance_count {
+                let count = cmp::min(instances_per_buffer, instance_count - first_instance);
+                let (instance_memory, instance_buffer_raw) = self.next_instance_buffer(thr);
+                instance_memory.write_floats(&instance_data[first_instance * components_per_instance..
+                                                            (first_instance + count) * components_per_instance]);
+
+                self.bind_vertex_buffers(thr, command_buffer_raw, &[vertex_buffer_raw, instance_buffer_raw]);
+                unsafe {
+                    vkCmdDraw(command_buffer_raw,
+                              3 * thread_data.index as u32, // Vertex count
+                              count as u32, // Instance count
+                              0, // First vertex
+                              0); // First instance
                 }
+
+                first_instance += count;
+            }
+
+            self.account_for_draw(thr, vertex_bytes + instance_data.len() * mem::size_of::<f32>());
+        }
+
+        thread_data.index = 0;
+    }
+
+    /// Draw triangles generated by the current pass's vertex shader without any vertex data
+    ///
+    /// No vertex buffers are bound, as the shader reads none of the pipeline's vertex inputs.
+    ///
+    /// thread_data: The thread data of the drawing thread
+    /// vertex_count: The number of vertices of each instance, a multiple of three
+    /// instance_count: The number of instances to draw
+    fn draw_generated(&mut self, thread_data: &mut ThreadData, vertex_count: u32, instance_count: u32) {
+        if vertex_count > 0 && instance_count > 0 {
+            let thr = thread_data.thr;
+            let command_buffer_raw = self.current_command_buffer_raw(thr);
+            self.bind_draw_state(thr, command_buffer_raw);
+            unsafe {
+                vkCmdDraw(command_buffer_raw,
+                          vertex_count,
+                          instance_count,
+                          0, // First vertex
+                          0); // First instance
             }
+
+            self.account_for_draw(thr, 0);
         }
     }
 
-    /// Update the accumulated contents to the named uniform buffer
+    /// Upload the vertices and indices of a mesh into device-local buffers, returning its handle,
+    /// or None if the data is invalid
     ///
-    /// buffer_name: The name of the uniform buffer to be configuring
-    fn synchronise_uniform_buffer(&self, buffer_name: &str) {
-        let ref buffer = self.uniform_buffers[buffer_name];
-        // println!("Synchronising {} ({} bytes, binding = {})",
-        //          buffer_name,
-        //          buffer.size,
-        //          buffer.binding);
-        // dump_byte_vector(&buffer.bytes);
-        unsafe {
-            let mut data: *mut c_void = VK_NULL_HANDLE_MUT();
-            check_result!("vkMapMemory",
-                          vkMapMemory(self.device.raw,
-                                      buffer.buffer.memory,
-                                      0, // Offset
-                                      VK_WHOLE_SIZE as u64,
-                                      0, // Flags
-                                      &mut data));
-            ptr::copy_nonoverlapping(buffer.bytes.as_ptr(), data as *mut u8, buffer.buffer.size);
-            vkUnmapMemory(self.device.raw, buffer.buffer.memory);
+    /// The upload goes through staging buffers and is waited on, so this suits loading rather
+    /// than the middle of a frame.
+    ///
+    /// data: The vertices and optional indices of the mesh
+    fn create_mesh(&mut self, data: &MeshData) -> Option<MeshHandle> {
+        if let Err(e) = data.validate() {
+            log_error!("Unable to create a mesh: {}", e);
+            return None;
         }
+
+        self.flush_queued_command_buffers();
+        let mesh = RendererVkMesh::new(&self.device, &self.physical_device, &self.aux_command_pool, data);
+
+        Some(self.meshes.insert(mesh))
     }
 
-    /// Flip the back buffer to the front
+    /// Draw a mesh created by create_mesh with the current pass's pipeline
     ///
-    /// context: The GLFW context, not used on Vulkan
-    fn flip(&self, _: &mut Context) {
-        let signal_semaphores = vec![self.render_finished_semaphore.raw];
-        let swapchains = vec![self.swapchain.raw];
-        let image_indices = vec![self.image_index as u32];
-
-        let present_info = VkPresentInfoKHR {
-            sType: VkStructureType::VK_STRUCTURE_TYPE_PRESENT_INFO_KHR,
-            waitSemaphoreCount: signal_semaphores.len() as u32,
-            pWaitSemaphores: signal_semaphores.as_ptr(),
-            swapchainCount: swapchains.len() as u32,
-            pSwapchains: swapchains.as_ptr(),
-            pImageIndices: image_indices.as_ptr(),
-            pResults: ptr::null_mut(), // Optional
-            pNext: ptr::null(),
+    /// thread_data: The thread data of the drawing thread
+    /// mesh: The handle of the mesh
+    fn draw_mesh(&mut self, thread_data: &mut ThreadData, mesh: MeshHandle) {
+        let (vertex_buffer_raw, index_buffer_raw, draw_count) = match self.meshes.get(mesh) {
+            Some(m) => {
+                if m.vertex_array_type != self.vertex_array_type {
+                    log_error!("Mesh {} has a different vertex array type to the current pass's shader", mesh);
+                    return;
+                }
+                (m.vertex_buffer.raw, m.index_buffer.as_ref().map(|b| b.raw), m.draw_count)
+            }
+            None => {
+                log_error!("No mesh {} to draw", mesh);
+                return;
+            }
         };
 
+        let thr = thread_data.thr;
+        let command_buffer_raw = self.current_command_buffer_raw(thr);
+        self.bind_draw_state(thr, command_buffer_raw);
+        self.bind_vertex_buffers(thr, command_buffer_raw, &[vertex_buffer_raw]);
         unsafe {
-            check_result!("vkQueuePresentKHR",
-                          vkQueuePresentKHR(self.device.graphics_queue, &present_info));
+            match index_buffer_raw {
+                Some(index_buffer_raw) => {
+                    vkCmdBindIndexBuffer(command_buffer_raw,
+                                         index_buffer_raw,
+                                         0, // Offset
+                                         VkIndexType::VK_INDEX_TYPE_UINT32);
+                    vkCmdDrawIndexed(command_buffer_raw,
+                                     draw_count, // Index count
+                                     1, // Instance count
+                                     0, // First index
+                                     0, // Vertex offset
+                                     0); // First instance
+                }
+                None => {
+                    vkCmdDraw(command_buffer_raw,
+                              draw_count, // Vertex count
+                              1, // Instance count
+                              0, // First vertex
+                              0); // First instance
+                }
+            }
+        }
 
-            check_result!("vkQueueWaitIdle",
-                          vkQueueWaitIdle(self.device.graphics_queue));
+        // Nothing is streamed, so the draw adds none of the bytes that end a chunk early
+        self.account_for_draw(thr, 0);
+    }
+
+    /// Destroy a mesh, whose buffers are kept until the frames in flight have completed
+    ///
+    /// mesh: The handle of the mesh
+    fn destroy_mesh(&mut self, mesh: MeshHandle) {
+        if !self.meshes.remove(mesh) {
+            log_warning!("No mesh {} to destroy", mesh);
         }
     }
 
-    /// Begin rendering a new frame
-    fn begin_frame(&mut self) {
-        // Acquire the next image in the swapchain
-        //
-        let mut image_index: u32 = 0;
-        unsafe {
-            // TODO: Deal with VK_SUBOPTIMAL_KHR and VK_ERROR_OUT_OF_DATE_KHR results
-            check_result!("vkAcquireNextImageKHR",
-                          vkAcquireNextImageKHR(self.device.raw,
-                                                self.swapchain.raw,
-                                                u64::max_value(), // No timeout
-                                                self.image_available_semaphore.raw, // Semaphore
-                                                VK_NULL_HANDLE_MUT(), // Fence
-                                                &mut image_index));
+    /// Create vertex buffers up front, so that a scene suddenly needing many does not hitch
+    ///
+    /// The buffers are created for every swapchain image, as each has its own pools.
+    ///
+    /// sizes: The number of vertex buffers to have ready for each vertex array type and thread
+    fn prewarm_vertex_buffers(&mut self, sizes: &VertexBufferPoolSizes) {
+        let storage = self.vertex_buffer_storage;
+        let triangles = self.triangle_capacity;
+        let mut created = 0;
+        for image_pools in self.vertex_buffer.iter_mut() {
+            for ty in VERTEX_ARRAY_TYPE_BEGIN_RANGE..VERTEX_ARRAY_TYPE_END_RANGE + 1 {
+                let array_type = VertexArrayType::from_u32(ty);
+                for (thr, pool) in image_pools[ty as usize].iter_mut().enumerate() {
+                    while pool.len() < sizes.get(array_type, thr) {
+                        pool.push(RendererVkVertexBuffer::new(&self.device,
+                                                              &self.physical_device,
+                                                              array_type,
+                                                              triangles,
+                                                              storage));
+                        created += 1;
+                    }
+                }
+            }
         }
-        self.image_index = image_index as usize;
 
-        // Set the default render target
-        self.deselect_render_target();
+        log_info!("Prewarmed {} vertex buffers", created);
     }
 
-    /// Terminate rendering a frame
-    fn end_frame(&mut self) {
-        // Add a pipeline barrier to ensure all the thread command buffers have finished before presenting
-        //
+    /// Return the most vertex buffers used in a frame for each vertex array type and thread
+    ///
+    /// The pools only grow, so their sizes are the most used by any frame so far.
+    fn vertex_buffer_high_water(&self) -> VertexBufferPoolSizes {
+        let mut sizes = VertexBufferPoolSizes::new(self.max_threads);
+        for image_pools in self.vertex_buffer.iter() {
+            for ty in VERTEX_ARRAY_TYPE_BEGIN_RANGE..VERTEX_ARRAY_TYPE_END_RANGE + 1 {
+                let array_type = VertexArrayType::from_u32(ty);
+                for (thr, pool) in image_pools[ty as usize].iter().enumerate() {
+                    if pool.len() > sizes.get(array_type, thr) {
+                        sizes.set(array_type, thr, pool.len());
+                    }
+                }
+            }
+        }
+
+        sizes
+    }
+
+    /// Return the number of triangles each thread's data holds before it is flushed
+    fn triangle_capacity(&self) -> usize {
+        self.triangle_capacity
+    }
+
+    /// Change the number of triangles each thread's data holds before it is flushed, resizing
+    /// the vertex buffers to match
+    ///
+    /// The device is idled and every pooled vertex buffer is replaced by one of the new size,
+    /// keeping the number of buffers in each pool.
+    ///
+    /// triangles: The capacity in triangles, limited to TRIANGLE_ARRAY_MAX_SIZE
+    fn set_triangle_capacity(&mut self, triangles: usize) {
+        let triangles = triangles.max(1).min(TRIANGLE_ARRAY_MAX_SIZE);
+        if triangles == self.triangle_capacity {
+            return;
+        }
+
+        self.flush_queued_command_buffers();
         unsafe {
-            check_result!("vkResetCommandBuffer",
-                          vkResetCommandBuffer(self.prepresent_command_buffers[self.image_index].raw,
-                                               0 /* flags */));
+            check_result!("vkDeviceWaitIdle", vkDeviceWaitIdle(self.device.raw));
         }
 
-        self.prepresent_command_buffers[self.image_index].begin_primary(true, // one_time_submit
-                                                                        false, // render_pass_continue
-                                                                        true /* simultaneous_use */);
+        self.triangle_capacity = triangles;
+        for threaddata in self.threaddata_arcs.iter() {
+            threaddata.lock().unwrap().set_capacity(triangles);
+        }
 
-        self.prepresent_command_buffers[self.image_index]
-            .image_memory_barrier(self.swapchain.images[self.image_index],
-                                  VkAccessFlagBits::VK_ACCESS_COLOR_ATTACHMENT_WRITE_BIT as VkAccessFlags,
-                                  VkAccessFlagBits::VK_ACCESS_MEMORY_READ_BIT as VkAccessFlags,
-                                  VkImageLayout::VK_IMAGE_LAYOUT_COLOR_ATTACHMENT_OPTIMAL,
-                                  VkImageLayout::VK_IMAGE_LAYOUT_PRESENT_SRC_KHR,
-                                  VkImageAspectFlagBits::VK_IMAGE_ASPECT_COLOR_BIT as VkImageAspectFlags,
-                                  VkPipelineStageFlagBits::VK_PIPELINE_STAGE_ALL_GRAPHICS_BIT as VkPipelineStageFlags,
-                                  VkPipelineStageFlagBits::VK_PIPELINE_STAGE_ALL_GRAPHICS_BIT as VkPipelineStageFlags);
+        let storage = self.vertex_buffer_storage;
+        for image_pools in self.vertex_buffer.iter_mut() {
+            for ty in VERTEX_ARRAY_TYPE_BEGIN_RANGE..VERTEX_ARRAY_TYPE_END_RANGE + 1 {
+                let array_type = VertexArrayType::from_u32(ty);
+                for pool in image_pools[ty as usize].iter_mut() {
+                    let count = pool.len();
+                    pool.clear();
+                    for _ in 0..count {
+                        pool.push(RendererVkVertexBuffer::new(&self.device,
+                                                              &self.physical_device,
+                                                              array_type,
+                                                              triangles,
+                                                              storage));
+                    }
+                }
+            }
+        }
 
-        self.prepresent_command_buffers[self.image_index].end();
+        log_info!("Resized the vertex buffers to {} triangles", triangles);
+    }
 
-        // Submit the command buffer to the queue
-        //
-        let mut command_buffers = Vec::with_capacity(1);
-        command_buffers.push(self.prepresent_command_buffers[self.image_index].raw);
+    /// Return whether compute shaders can be dispatched, which Vulkan always supports
+    fn supports_compute(&self) -> bool {
+        true
+    }
 
-        let wait_semaphores: Vec<VkSemaphore> = vec![self.image_available_semaphore.raw];
-        let wait_stages: Vec<VkPipelineStageFlags> =
-            vec![VkPipelineStageFlagBits::VK_PIPELINE_STAGE_TOP_OF_PIPE_BIT as VkPipelineStageFlags];
-        let signal_semaphores = vec![self.render_finished_semaphore.raw];
+    /// Run a compute shader
+    ///
+    /// The dispatch is submitted straight away and waited for, after any pending uniform
+    /// buffer updates, since the queue executes it in order with the frames already submitted.
+    ///
+    /// shader_name: The name of the compute shader
+    /// x: The number of workgroups in the x dimension
+    /// y: The number of workgroups in the y dimension
+    /// z: The number of workgroups in the z dimension
+    fn dispatch_compute(&mut self, shader_name: &str, x: u32, y: u32, z: u32) {
+        let pipeline = match self.compute_pipelines.get(shader_name) {
+            Some(p) => p,
+            None => {
+                log_error!("No compute pipeline for {}", shader_name);
+                return;
+            }
+        };
 
-        let submit_info = VkSubmitInfo {
-            sType: VkStructureType::VK_STRUCTURE_TYPE_SUBMIT_INFO,
-            waitSemaphoreCount: wait_semaphores.len() as u32,
-            pWaitSemaphores: wait_semaphores.as_ptr(),
-            pWaitDstStageMask: wait_stages.as_ptr(),
-            commandBufferCount: command_buffers.len() as u32,
-            pCommandBuffers: command_buffers.as_ptr(),
-            signalSemaphoreCount: signal_semaphores.len() as u32,
-            pSignalSemaphores: signal_semaphores.as_ptr(),
-            pNext: ptr::null(),
+        self.flush_queued_command_buffers();
+        let mut command_buffer = OneTimeCommandBuffer::new(&self.device, &self.aux_command_pool);
+        self.record_pending_uniform_updates(&command_buffer.buffer);
+        command_buffer.buffer
+            .memory_barrier(VkAccessFlagBits::VK_ACCESS_TRANSFER_WRITE_BIT as VkAccessFlags |
+                            VkAccessFlagBits::VK_ACCESS_SHADER_WRITE_BIT as VkAccessFlags,
+                            VkAccessFlagBits::VK_ACCESS_UNIFORM_READ_BIT as VkAccessFlags |
+                            VkAccessFlagBits::VK_ACCESS_SHADER_READ_BIT as VkAccessFlags |
+                            VkAccessFlagBits::VK_ACCESS_SHADER_WRITE_BIT as VkAccessFlags,
+                            VkPipelineStageFlagBits::VK_PIPELINE_STAGE_TRANSFER_BIT as VkPipelineStageFlags |
+                            VkPipelineStageFlagBits::VK_PIPELINE_STAGE_ALL_GRAPHICS_BIT as VkPipelineStageFlags |
+                            VkPipelineStageFlagBits::VK_PIPELINE_STAGE_COMPUTE_SHADER_BIT as VkPipelineStageFlags,
+                            VkPipelineStageFlagBits::VK_PIPELINE_STAGE_COMPUTE_SHADER_BIT as VkPipelineStageFlags);
+
+        pipeline.record_dispatch(&command_buffer.buffer, x, y, z);
+
+        // Make the results visible to whatever reads the storage buffers next
+        command_buffer.buffer
+            .memory_barrier(VkAccessFlagBits::VK_ACCESS_SHADER_WRITE_BIT as VkAccessFlags,
+                            VkAccessFlagBits::VK_ACCESS_UNIFORM_READ_BIT as VkAccessFlags |
+                            VkAccessFlagBits::VK_ACCESS_SHADER_READ_BIT as VkAccessFlags |
+                            VkAccessFlagBits::VK_ACCESS_VERTEX_ATTRIBUTE_READ_BIT as VkAccessFlags |
+                            VkAccessFlagBits::VK_ACCESS_TRANSFER_READ_BIT as VkAccessFlags,
+                            VkPipelineStageFlagBits::VK_PIPELINE_STAGE_COMPUTE_SHADER_BIT as VkPipelineStageFlags,
+                            VkPipelineStageFlagBits::VK_PIPELINE_STAGE_ALL_COMMANDS_BIT as VkPipelineStageFlags);
+
+        command_buffer.execute();
+    }
+
+    /// Queue a compute dispatch to be spread over the coming frames within the compute budget
+    ///
+    /// The slices are submitted with each frame's pre-present work and timed with timestamp
+    /// queries, which refine the estimate of how many workgroups fit in the budget.
+    ///
+    /// shader_name: The name of the compute shader
+    /// x: The number of workgroups in the x dimension
+    /// y: The number of workgroups in the y dimension
+    /// z: The number of workgroups in the z dimension
+    fn queue_paced_compute(&mut self, shader_name: &'static str, x: u32, y: u32, z: u32) -> ComputeJobHandle {
+        let sliceable = match self.compute_pipelines.get(shader_name) {
+            Some(p) => p.is_sliceable(),
+            None => false,
         };
-        unsafe {
-            // TODO: Deal with VK_ERROR_DEVICE_LOST result
-            check_result!("vkQueueSubmit",
-                          vkQueueSubmit(self.device.graphics_queue,
-                                        1,
-                                        &submit_info,
-                                        VK_NULL_HANDLE_MUT() /* Fence */));
-        }
+
+        self.compute_pacer.queue(shader_name, x, y, z, sliceable)
     }
 
-    /// Begin a pass with the specified shader
+    /// Return the pacer of the queued compute work, for the progress of its jobs
+    fn compute_pacer(&self) -> &ComputePacer {
+        &self.compute_pacer
+    }
+
+    /// Set the GPU time given over to paced compute work each frame
     ///
+    /// budget_ms: The budget in milliseconds
+    fn set_compute_budget(&mut self, budget_ms: f32) {
+        self.compute_pacer.set_budget_ms(budget_ms);
+    }
+
+    /// Return whether shaders can read textures from the bindless texture array
     ///
-    fn begin_pass(&mut self, shader_name: &'static str) {
-        self.shader_name = shader_name;
+    /// This needs a device created with the descriptor indexing features.
+    fn supports_bindless_textures(&self) -> bool {
+        self.bindless_textures.is_some()
+    }
 
-        {
-            let res_manager = self.resource_manager.lock().unwrap();
-            let ref shader_spec = res_manager.shader_specs[shader_name];
-            self.vertex_array_type = shader_spec.vertex_array_type;
-            self.current_pass_identifier = shader_spec.pass_identifier;
-        }
+    /// Add a texture to the bindless texture array
+    ///
+    /// The descriptor is written straight away, since slots that frames in flight may use are
+    /// never rewritten.  Returns None if bindless textures are unsupported or the array is full.
+    ///
+    /// texture: The texture to add
+    fn register_bindless_texture(&mut self, texture: &Texture) -> Option<u32> {
+        let texture_vk = match texture.as_any().downcast_ref::<TextureVk>() {
+            Some(t) => t,
+            None => panic!("Unexpected runtime type"),
+        };
 
-        for thr in 0..self.max_threads {
-            unsafe {
-                check_result!("vkResetCommandBuffer",
-                              vkResetCommandBuffer(self.command_buffers[self.image_index][thr].raw,
-                                                   0 /* flags */));
+        let bindless_textures = self.bindless_textures.as_mut()?;
+        let index = match bindless_textures.slots.allocate() {
+            Some(index) => index,
+            None => {
+                log_error!("The bindless texture array is full ({} textures)", bindless_textures.slots.capacity());
+                return None;
             }
+        };
+        bindless_textures.write(index, texture_vk);
 
-            self.command_buffers[self.image_index][thr].begin_primary(true, // one_time_submit
-                                                                      false, // render_pass_continue
-                                                                      true /* simultaneous_use */);
+        Some(index)
+    }
 
-            self.render_passes[self.current_pass_identifier as usize].begin(self.command_buffers[self.image_index][thr].raw,
-                                                                            self.current_render_target.unwrap(),
-                                                                            self.surface.capabilities.currentExtent.width,
-                                                                            self.surface.capabilities.currentExtent.height);
+    /// Remove a texture from the bindless texture array
+    ///
+    /// The slot is left as it is until the frames in flight have completed, and is then reused.
+    ///
+    /// index: The index returned when the texture was added
+    fn release_bindless_texture(&mut self, index: u32) {
+        if let Some(ref mut bindless_textures) = self.bindless_textures {
+            bindless_textures.slots.release(index);
         }
+    }
 
-        for ty in VERTEX_ARRAY_TYPE_BEGIN_RANGE..VERTEX_ARRAY_TYPE_END_RANGE + 1 {
-            for thr in 0..self.max_threads {
-                self.vertex_buffer_index[self.image_index][ty as usize][thr] = -1;
-            }
+    /// Return the device features granted, out of those requested in the renderer configuration
+    ///
+    /// The device was created with exactly these features enabled.
+    fn granted_features(&self) -> DeviceFeatures {
+        self.device.features
+    }
+
+    /// Return the limits of the device, from VkPhysicalDeviceLimits
+    fn get_device_limits(&self) -> DeviceLimits {
+        let mut properties = VkPhysicalDeviceProperties::default();
+        unsafe {
+            vkGetPhysicalDeviceProperties(self.physical_device.raw, &mut properties);
         }
 
-        for thr in 0..self.max_threads {
-            unsafe {
-                vkCmdBindPipeline(self.command_buffers[self.image_index][thr].raw,
-                                  VkPipelineBindPoint::VK_PIPELINE_BIND_POINT_GRAPHICS,
-                                  self.render_pipelines[shader_name].raw);
-            }
+        device_limits_from_vk(&properties.limits)
+    }
 
-            let descriptor_sets = vec![self.descriptor_sets[self.shader_name].raw];
-            unsafe {
-                vkCmdBindDescriptorSets(self.command_buffers[self.image_index][thr].raw,
-                                        VkPipelineBindPoint::VK_PIPELINE_BIND_POINT_GRAPHICS,
-                                        self.pipeline_layouts[self.shader_name].raw,
-                                        0, // First set
-                                        descriptor_sets.len() as u32,
-                                        descriptor_sets.as_ptr(),
-                                        0, // Dynamic offset count
-                                        ptr::null()); // Dynamic offsets
-            }
+    /// Return whether the output is an HDR10 swapchain, which expects Rec. 2020 primaries
+    /// encoded with the ST 2084 transfer function rather than sRGB
+    ///
+    /// This is only the case when hdr_output was requested and the surface offered the format.
+    fn is_hdr_output(&self) -> bool {
+        match *self.surface {
+            Some(ref surface) => surface.is_hdr10(),
+            None => false,
         }
     }
 
-    /// Finish a pass with the specified shader
-    fn end_pass(&mut self) {
-        // Finish the command buffers and render passes and store the command buffers in a
-        // collection to be submitted
-        //
-        let mut command_buffers = Vec::with_capacity(self.max_threads);
-        for thr in 0..self.max_threads {
-            self.render_passes[self.current_pass_identifier as usize].end(self.command_buffers[self.image_index][thr].raw);
+    /// Set how the primitives of depth tested shaders are rasterised, for inspecting topology
+    ///
+    /// Each depth tested pipeline gets a variant built with the polygon mode, which is bound in
+    /// its place.  Changing the mode waits for the device to go idle.
+    ///
+    /// mode: The polygon mode to draw with
+    fn set_polygon_mode(&mut self, mode: PolygonMode) {
+        if mode == self.polygon_mode {
+            return;
+        }
+        if mode != PolygonMode::Fill && !self.device.features.fill_mode_non_solid {
+            log_warning!("Polygon mode {:?} needs the fill_mode_non_solid feature", mode);
+            return;
+        }
 
-            self.command_buffers[self.image_index][thr].end();
-            command_buffers.push(self.command_buffers[self.image_index][thr].raw);
+        // The variants of the previous mode may still be in use by the frames in flight
+        self.flush_queued_command_buffers();
+        unsafe {
+            check_result!("vkDeviceWaitIdle", vkDeviceWaitIdle(self.device.raw));
         }
+        self.polygon_mode_pipelines.clear();
+        self.polygon_mode = mode;
+        self.create_polygon_mode_pipelines();
+    }
 
-        // Submit the command buffers to the queue
-        //
-        let wait_semaphores: Vec<VkSemaphore> = vec![];
-        let wait_stages: Vec<VkPipelineStageFlags> =
-            vec![VkPipelineStageFlagBits::VK_PIPELINE_STAGE_TOP_OF_PIPE_BIT as VkPipelineStageFlags];
-        let signal_semaphores = vec![];
+    /// Return the polygon mode the depth tested shaders are drawn with
+    fn polygon_mode(&self) -> PolygonMode {
+        self.polygon_mode
+    }
 
-        let submit_info = VkSubmitInfo {
-            sType: VkStructureType::VK_STRUCTURE_TYPE_SUBMIT_INFO,
-            waitSemaphoreCount: wait_semaphores.len() as u32,
-            pWaitSemaphores: wait_semaphores.as_ptr(),
-            pWaitDstStageMask: wait_stages.as_ptr(),
-            commandBufferCount: command_buffers.len() as u32,
-            pCommandBuffers: command_buffers.as_ptr(),
-            signalSemaphoreCount: signal_semaphores.len() as u32,
-            pSignalSemaphores: signal_semaphores.as_ptr(),
-            pNext: ptr::null(),
-        };
+    /// Set how the scene is rendered for the two eyes and combined, see render_stereo_eyes
+    ///
+    /// settings: The stereo mode, eye separation and convergence distance
+    fn set_stereo_settings(&mut self, settings: StereoSettings) {
+        self.stereo_settings = settings;
+    }
+
+    /// Return the stereo settings the scene is rendered with
+    fn stereo_settings(&self) -> StereoSettings {
+        self.stereo_settings
+    }
+
+    /// Return the support of each engine feature on this backend and device, with the fallbacks
+    /// in use
+    ///
+    /// Paced compute slices and passes are timed wherever the device supports timestamps.
+    fn parity_report(&self) -> ParityReport {
+        let mut properties = VkPhysicalDeviceProperties::default();
         unsafe {
-            // TODO: Deal with VK_ERROR_DEVICE_LOST result
-            check_result!("vkQueueSubmit",
-                          vkQueueSubmit(self.device.graphics_queue,
-                                        1,
-                                        &submit_info,
-                                        VK_NULL_HANDLE_MUT() /* Fence */));
+            vkGetPhysicalDeviceProperties(self.physical_device.raw, &mut properties);
+        }
+        let device_name = unsafe { CStr::from_ptr(&properties.deviceName as *const c_char) };
+
+        // The sample counts are bits valued at the counts they stand for
+        let sample_counts = properties.limits.framebufferColorSampleCounts as u32 &
+                            properties.limits.framebufferDepthSampleCounts as u32;
+        let max_samples = (0..32).rev().map(|bit| 1u32 << bit).find(|count| sample_counts & count != 0).unwrap_or(1);
+
+        ParityReport::new(&BackendCapabilities {
+            backend: "Vulkan",
+            device_name: CStr::to_string_lossy(device_name).into_owned(),
+            features: self.device.features,
+            max_samples: max_samples,
+            sample_count: self.sample_count as u32,
+            compute: self.supports_compute(),
+            timed_compute: self.timestamp_period_ns > 0.0,
+            bindless_textures: self.supports_bindless_textures(),
+            gpu_timing: self.timestamp_period_ns > 0.0,
+            per_draw: self.per_draw_benchmark.clone(),
+        })
+    }
 
-            check_result!("vkQueueWaitIdle",
-                          vkQueueWaitIdle(self.device.graphics_queue));
+    /// Keep the results of a per-draw strategy benchmark for the parity report
+    ///
+    /// results: The benchmark results
+    fn record_per_draw_benchmark(&mut self, results: &PerDrawBenchmarkResults) {
+        self.per_draw_benchmark = Some(results.clone());
+    }
+
+    /// Return the handles an OpenXR session is created with to render through this renderer
+    ///
+    /// The session shares the graphics queue, which is the first of its family.
+    fn xr_graphics_binding(&self) -> XrGraphicsBinding {
+        XrGraphicsBinding::Vulkan {
+            instance: self.instance.raw as usize,
+            physical_device: self.physical_device.raw as usize,
+            device: self.device.raw as usize,
+            queue_family_index: self.queue_families.index,
+            queue_index: 0,
         }
     }
 
-    /// Select the render target so that renderpasses output there instead of the swapchain
+    /// Copy the colour contents of a render target into an image acquired from an OpenXR swapchain
     ///
-    /// num: The texture number to bind the render target texture to
-    /// render_target: The render target to select
-    fn select_render_target(&mut self, _: i32, render_target: &mut RenderTarget) {
-        let target_vk = match render_target.as_any_mut().downcast_mut::<RenderTargetVk>() {
+    /// Both images are in the colour attachment layout, as left by the render pass and as OpenXR
+    /// requires of an acquired image, and are returned to it after the blit.
+    ///
+    /// render_target: The render target to copy from
+    /// image: The swapchain image to copy into
+    fn copy_to_xr_image(&mut self, render_target: &RenderTarget, image: &XrSwapchainImage) {
+        let target_vk = match render_target.as_any().downcast_ref::<RenderTargetVk>() {
             Some(r) => r,
             None => panic!("Unexpected runtime type"),
         };
 
-        self.current_render_target = Some(target_vk.get_framebuffer_raw());
-        self.current_depth_target = Some(target_vk.get_depth_image_raw());
+        let texture_vk = match target_vk.texture.as_any().downcast_ref::<TextureVk>() {
+            Some(t) => t,
+            None => panic!("Unexpected runtime type"),
+        };
+
+        let source = texture_vk.texture.get_image_raw();
+        let destination = image.image as usize as VkImage;
+        let colour_aspect = VkImageAspectFlagBits::VK_IMAGE_ASPECT_COLOR_BIT as VkImageAspectFlags;
+        let attachment_access = VkAccessFlagBits::VK_ACCESS_COLOR_ATTACHMENT_WRITE_BIT as VkAccessFlags;
+        let attachment_stage = VkPipelineStageFlagBits::VK_PIPELINE_STAGE_COLOR_ATTACHMENT_OUTPUT_BIT as VkPipelineStageFlags;
+        let transfer_stage = VkPipelineStageFlagBits::VK_PIPELINE_STAGE_TRANSFER_BIT as VkPipelineStageFlags;
+
+        self.flush_queued_command_buffers();
+        let mut one_time = OneTimeCommandBuffer::new(&self.device, &self.aux_command_pool);
+
+        one_time.buffer.image_memory_barrier(source,
+                                             attachment_access,
+                                             VkAccessFlagBits::VK_ACCESS_TRANSFER_READ_BIT as VkAccessFlags,
+                                             VkImageLayout::VK_IMAGE_LAYOUT_COLOR_ATTACHMENT_OPTIMAL,
+                                             VkImageLayout::VK_IMAGE_LAYOUT_TRANSFER_SRC_OPTIMAL,
+                                             colour_aspect,
+                                             attachment_stage,
+                                             transfer_stage);
+        one_time.buffer.image_memory_barrier(destination,
+                                             attachment_access,
+                                             VkAccessFlagBits::VK_ACCESS_TRANSFER_WRITE_BIT as VkAccessFlags,
+                                             VkImageLayout::VK_IMAGE_LAYOUT_COLOR_ATTACHMENT_OPTIMAL,
+                                             VkImageLayout::VK_IMAGE_LAYOUT_TRANSFER_DST_OPTIMAL,
+                                             colour_aspect,
+                                             attachment_stage,
+                                             transfer_stage);
+
+        let sub_resource = VkImageSubresourceLayers {
+            aspectMask: colour_aspect,
+            mipLevel: 0,
+            baseArrayLayer: 0,
+            layerCount: 1,
+        };
+        let blit = VkImageBlit {
+            srcSubresource: sub_resource,
+            srcOffsets: [VkOffset3D { x: 0, y: 0, z: 0 },
+                         VkOffset3D {
+                             x: target_vk.get_width() as i32,
+                             y: target_vk.get_height() as i32,
+                             z: 1,
+                         }],
+            dstSubresource: sub_resource,
+            dstOffsets: [VkOffset3D { x: 0, y: 0, z: 0 },
+                         VkOffset3D {
+                             x: image.width as i32,
+                             y: image.height as i32,
+                             z: 1,
+                         }],
+        };
+
+        unsafe {
+            vkCmdBlitImage(one_time.buffer.raw,
+                           source,
+                           VkImageLayout::VK_IMAGE_LAYOUT_TRANSFER_SRC_OPTIMAL,
+                           destination,
+                           VkImageLayout::VK_IMAGE_LAYOUT_TRANSFER_DST_OPTIMAL,
+                           1,
+                           &blit,
+                           VkFilter::VK_FILTER_LINEAR);
+        }
+
+        one_time.buffer.image_memory_barrier(source,
+                                             VkAccessFlagBits::VK_ACCESS_TRANSFER_READ_BIT as VkAccessFlags,
+                                             attachment_access,
+                                             VkImageLayout::VK_IMAGE_LAYOUT_TRANSFER_SRC_OPTIMAL,
+                                             VkImageLayout::VK_IMAGE_LAYOUT_COLOR_ATTACHMENT_OPTIMAL,
+                                             colour_aspect,
+                                             transfer_stage,
+                                             attachment_stage);
+        one_time.buffer.image_memory_barrier(destination,
+                                             VkAccessFlagBits::VK_ACCESS_TRANSFER_WRITE_BIT as VkAccessFlags,
+                                             attachment_access,
+                                             VkImageLayout::VK_IMAGE_LAYOUT_TRANSFER_DST_OPTIMAL,
+                                             VkImageLayout::VK_IMAGE_LAYOUT_COLOR_ATTACHMENT_OPTIMAL,
+                                             colour_aspect,
+                                             transfer_stage,
+                                             attachment_stage);
+
+        one_time.execute();
     }
 
-    /// Deselect the render target so that renderpasses output to the swapchain
-    fn deselect_render_target(&mut self) {
-        self.current_render_target = Some(self.framebuffers[self.image_index].raw);
-        self.current_depth_target = None;
+    /// Capture the next frame in RenderDoc, returning false if RenderDoc is not attached
+    ///
+    /// The capture is of the renderer's instance, and ends once the frame has been submitted.
+    fn trigger_capture(&mut self) -> bool {
+        self.capture_trigger.request(1)
+    }
+
+    /// Return the capture trigger, for the paths of the captures written
+    fn capture_trigger(&self) -> &CaptureTrigger {
+        &self.capture_trigger
     }
 }
 
 impl RendererVk {
+    /// Return the device pointer RenderDoc identifies the instance by, its dispatch table
+    fn renderdoc_device(&self) -> *mut c_void {
+        unsafe { *(self.instance.raw as *const *mut c_void) }
+    }
+
     /// Flush the calculated vertex data
     ///
     /// renderer_arc: Atomic reference counted lockable reference to the
@@ -3981,7 +11510,6 @@ impl RendererVk {
 
         let ty;
         let image_index;
-        let device;
         let command_buffer_raw;
         let vertex_buffer_memory;
         let vertex_buffer_raw;
@@ -3996,58 +11524,29 @@ impl RendererVk {
             ty = renderer_vk.vertex_array_type;
             image_index = renderer_vk.image_index;
 
-            // Move on to the next vertex buffer of the current type, creating one if necessary
-            //
-            renderer_vk.vertex_buffer_index[image_index][ty as usize][thr] += 1;
-            if renderer_vk.vertex_buffer_index[image_index][ty as usize][thr] ==
-               renderer_vk.vertex_buffer[image_index][ty as usize][thr].len() as i32 {
-                renderer_vk.vertex_buffer[image_index as usize][ty as usize][thr]
-                    .push(RendererVkVertexBuffer::new(&renderer_vk.device, &renderer_vk.physical_device, ty));
-            }
+            let vb_index = renderer_vk.next_vertex_buffer(thr);
 
-            device = renderer_vk.device.raw;
-            command_buffer_raw = renderer_vk.command_buffers[image_index][thr].raw;
+            command_buffer_raw = renderer_vk.current_command_buffer_raw(thr);
 
-            let vb_index = renderer_vk.vertex_buffer_index[image_index][ty as usize][thr] as usize;
-            vertex_buffer_memory = renderer_vk.vertex_buffer[image_index][ty as usize][thr][vb_index].buffer.memory;
+            vertex_buffer_memory = renderer_vk.vertex_buffer[image_index][ty as usize][thr][vb_index].host_memory();
             vertex_buffer_raw = renderer_vk.vertex_buffer[image_index][ty as usize][thr][vb_index].buffer.raw;
+
+            renderer_vk.bind_draw_state(thr, command_buffer_raw);
+            renderer_vk.bind_vertex_buffers(thr, command_buffer_raw, &[vertex_buffer_raw]);
         }
 
         {
-            let components_per_triangle = 3 * VertexArrayType::components_per_vertex(ty);
+            let vertices_per_primitive = PrimitiveType::vertices_per_primitive(thread_data.primitive);
+            let components_per_primitive = vertices_per_primitive * VertexArrayType::components_per_vertex(ty);
 
             // println!("---");
             // println!("{} triangles", thread_data.index);
             // use misc::fileutils;
             // fileutils::dump_float_vector(&thread_data.data, components_per_triangle * thread_data.index, 6 /* columns */);
 
-            let mut raw_buffer: *mut c_void = ptr::null_mut();
-            unsafe {
-                // TODO: Could leave this mapped and then explicitly synchronise after the copy
-                check_result!("vkMapMemory",
-                              vkMapMemory(device,
-                                          vertex_buffer_memory,
-                                          0, // Offset
-                                          (components_per_triangle * thread_data.index * mem::size_of::<f32>()) as u64,
-                                          0, // Flags, reserved
-                                          &mut raw_buffer));
-
-                ptr::copy_nonoverlapping(thread_data.data.as_ptr(),
-                                         raw_buffer as *mut f32,
-                                         components_per_triangle * thread_data.index); // Words
-
-                vkUnmapMemory(device, vertex_buffer_memory);
-            }
+            vertex_buffer_memory.write_floats(&thread_data.data[0..components_per_primitive * thread_data.index]);
 
-            let vertex_buffers = vec![vertex_buffer_raw];
-            let buffer_offsets: Vec<VkDeviceSize> = vec![0];
             unsafe {
-                vkCmdBindVertexBuffers(command_buffer_raw,
-                                       0, // First binding
-                                       vertex_buffers.len() as u32,
-                                       vertex_buffers.as_ptr(),
-                                       buffer_offsets.as_ptr());
-
                 // In fact, at the moment apart from the font, the geometry is entirely static if you
                 // ignore CPU-based culling.  The tessellation and geometry shaders do almost all the
                 // work, so constructing the command buffers could be done once up front and then just
@@ -4055,11 +11554,26 @@ impl RendererVk {
                 // the application, and where it may head in the future, it warrants keeping it
                 // generating the command buffers from scratch every frame.
                 vkCmdDraw(command_buffer_raw,
-                          3 * thread_data.index as u32, // Vertex count
+                          (vertices_per_primitive * thread_data.index) as u32, // Vertex count
                           1, // Instance count
                           0, // First vertex
                           0); // First instance
             }
         }
+
+        {
+            let mut renderer = renderer_arc.lock().unwrap();
+
+            let renderer_vk: &mut RendererVk = match renderer.as_any_mut().downcast_mut::<RendererVk>() {
+                Some(r) => r,
+                None => panic!("Unexpected runtime type"),
+            };
+
+            let bytes = PrimitiveType::vertices_per_primitive(thread_data.primitive) * VertexArrayType::components_per_vertex(ty) *
+                        thread_data.index * mem::size_of::<f32>();
+
+            renderer_vk.queue_vertex_upload(thr, bytes);
+            renderer_vk.account_for_draw(thr, bytes);
+        }
     }
 }
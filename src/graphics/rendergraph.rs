@@ -0,0 +1,553 @@
+// Copyright (c) 2016-2017 Bruce Stenning. All rights reserved.
+//
+// Redistribution and use in source and binary forms, with or without
+// modification, are permitted provided that the following conditions
+// are met:
+//
+// 1. Redistributions of source code must retain the above copyright
+//    notice, this list of conditions and the following disclaimer.
+//
+// 2. Redistributions in binary form must reproduce the above copyright
+//    notice, this list of conditions and the following disclaimer in the
+//    documentation and/or other materials provided with the distribution.
+//
+// 3. Neither the name of the copyright holder nor the names of its
+//    contributors may be used to endorse or promote products derived
+//    from this software without specific prior written permission.
+//
+// THIS SOFTWARE IS PROVIDED BY THE COPYRIGHT HOLDERS AND CONTRIBUTORS
+// AS IS" AND ANY EXPRESS OR IMPLIED WARRANTIES, INCLUDING, BUT NOT
+// LIMITED TO, THE IMPLIED WARRANTIES OF MERCHANTABILITY AND FITNESS
+// FOR A PARTICULAR PURPOSE ARE DISCLAIMED. IN NO EVENT SHALL THE
+// COPYRIGHT HOLDER OR CONTRIBUTORS BE LIABLE FOR ANY DIRECT, INDIRECT,
+// INCIDENTAL, SPECIAL, EXEMPLARY, OR CONSEQUENTIAL DAMAGES (INCLUDING,
+// BUT NOT LIMITED TO, PROCUREMENT OF SUBSTITUTE GOODS OR SERVICES; LOSS
+// OF USE, DATA, OR PROFITS; OR BUSINESS INTERRUPTION) HOWEVER CAUSED
+// AND ON ANY THEORY OF LIABILITY, WHETHER IN CONTRACT, STRICT LIABILITY,
+// OR TORT (INCLUDING NEGLIGENCE OR OTHERWISE) ARISING IN ANY WAY OUT OF
+// THE USE OF THIS SOFTWARE, EVEN IF ADVISED OF THE POSSIBILITY OF SUCH
+// DAMAGE.
+use std::collections::HashMap;
+use std::fmt;
+
+/// The name of the attachment standing for the swapchain image, written by the final pass
+pub const SWAPCHAIN_ATTACHMENT: &'static str = "swapchain";
+
+/// The way an attachment is used, and so the layout its image must be in, at a point in the frame
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum AttachmentLayout {
+    /// The contents are not needed
+    Undefined,
+    /// Written as a colour attachment
+    ColourAttachment,
+    /// Written as a depth attachment
+    DepthAttachment,
+    /// Sampled by shaders
+    ShaderRead,
+    /// Presented to the window
+    Present,
+}
+
+/// The hazard that orders one pass after another
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum DependencyKind {
+    /// The later pass samples what the earlier pass wrote
+    ReadAfterWrite,
+    /// The later pass overwrites what the earlier pass sampled
+    WriteAfterRead,
+    /// The later pass draws over what the earlier pass wrote
+    WriteAfterWrite,
+}
+
+/// An ordering constraint between two passes, through an attachment they share
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct PassDependency {
+    pub from: &'static str,
+    pub to: &'static str,
+    pub attachment: &'static str,
+    pub kind: DependencyKind,
+}
+
+/// A change of the layout of an attachment before a pass
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct LayoutTransition {
+    pub attachment: &'static str,
+    pub from: AttachmentLayout,
+    pub to: AttachmentLayout,
+}
+
+/// The values a pass clears its attachments to as it begins, where it clears them
+#[derive(Clone, Copy, Debug, Default, PartialEq)]
+pub struct PassClear {
+    /// The RGBA value every colour attachment is cleared to
+    pub colour: Option<[f32; 4]>,
+    /// The value the depth attachment is cleared to, along with zeroing the stencil
+    pub depth: Option<f32>,
+}
+
+impl PassClear {
+    /// Return whether any attachment is cleared
+    pub fn is_empty(&self) -> bool {
+        self.colour.is_none() && self.depth.is_none()
+    }
+}
+
+/// A pass of the render graph with the attachments it reads and writes
+#[derive(Clone, Debug)]
+pub struct RenderGraphPass {
+    /// The name the pass is referred to by
+    pub name: &'static str,
+    /// The name of the render pass the shaders of the pass are registered with
+    pub render_pass: &'static str,
+    /// The attachments sampled by the pass
+    pub inputs: Vec<&'static str>,
+    /// The attachments the pass draws colour into
+    pub colour_outputs: Vec<&'static str>,
+    /// The attachment the pass tests and writes depth in, if any
+    pub depth_output: Option<&'static str>,
+    /// The values the attachments are cleared to as the pass begins
+    pub clear: PassClear,
+}
+
+impl RenderGraphPass {
+    /// Create a pass that reads and writes no attachments yet
+    ///
+    /// name: The name the pass is referred to by
+    /// render_pass: The name of the render pass the shaders of the pass are registered with
+    pub fn new(name: &'static str, render_pass: &'static str) -> RenderGraphPass {
+        RenderGraphPass {
+            name: name,
+            render_pass: render_pass,
+            inputs: vec![],
+            colour_outputs: vec![],
+            depth_output: None,
+            clear: PassClear::default(),
+        }
+    }
+
+    /// Add an attachment sampled by the pass
+    pub fn reads(mut self, attachment: &'static str) -> RenderGraphPass {
+        self.inputs.push(attachment);
+        self
+    }
+
+    /// Add an attachment the pass draws colour into
+    pub fn writes(mut self, attachment: &'static str) -> RenderGraphPass {
+        self.colour_outputs.push(attachment);
+        self
+    }
+
+    /// Set the attachment the pass tests and writes depth in
+    pub fn writes_depth(mut self, attachment: &'static str) -> RenderGraphPass {
+        self.depth_output = Some(attachment);
+        self
+    }
+
+    /// Clear the colour attachments as the pass begins
+    ///
+    /// colour: The RGBA value to clear to
+    pub fn clears_colour(mut self, colour: [f32; 4]) -> RenderGraphPass {
+        self.clear.colour = Some(colour);
+        self
+    }
+
+    /// Clear the depth attachment, and zero the stencil, as the pass begins
+    ///
+    /// depth: The depth value to clear to
+    pub fn clears_depth(mut self, depth: f32) -> RenderGraphPass {
+        self.clear.depth = Some(depth);
+        self
+    }
+
+    fn outputs(&self) -> Vec<&'static str> {
+        let mut outputs = self.colour_outputs.clone();
+        outputs.extend(self.depth_output);
+        outputs
+    }
+}
+
+/// The reasons a render graph cannot be scheduled
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub enum RenderGraphError {
+    /// More than one pass has the name
+    DuplicatePass(&'static str),
+    /// The pass samples an attachment it also writes
+    FeedbackLoop {
+        pass: &'static str,
+        attachment: &'static str,
+    },
+    /// The passes depend on each other in a cycle
+    Cycle(Vec<&'static str>),
+}
+
+impl fmt::Display for RenderGraphError {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match *self {
+            RenderGraphError::DuplicatePass(name) => write!(f, "More than one render graph pass is named {}", name),
+            RenderGraphError::FeedbackLoop { pass, attachment } => {
+                write!(f, "Render graph pass {} samples {}, which it also writes", pass, attachment)
+            }
+            RenderGraphError::Cycle(ref passes) => write!(f, "Render graph passes {} depend on each other", passes.join(", ")),
+        }
+    }
+}
+
+/// A pass of a scheduled render graph, with what must happen before it runs
+#[derive(Clone, Debug)]
+pub struct ScheduledPass {
+    pub name: &'static str,
+    pub render_pass: &'static str,
+    /// The layout changes of its attachments, to be made before the pass begins
+    pub transitions: Vec<LayoutTransition>,
+    /// The earlier passes it waits on
+    pub dependencies: Vec<PassDependency>,
+    /// The outputs whose contents were written earlier in the frame, and so must be loaded
+    pub loads: Vec<&'static str>,
+    /// The values the attachments are cleared to as the pass begins
+    pub clear: PassClear,
+}
+
+/// A run of a frame's command buffers submitted together, with the earlier runs it waits on
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct SubmissionBatch {
+    /// The index of the first command buffer of the batch, which runs up to the next batch
+    pub start: usize,
+    /// The earlier batches holding passes this batch depends on, with each hazard between them
+    pub waits: Vec<(usize, DependencyKind)>,
+}
+
+/// The execution order of a render graph, with the transitions and dependencies between passes
+#[derive(Clone, Debug, Default)]
+pub struct RenderGraphSchedule {
+    passes: Vec<ScheduledPass>,
+    final_transitions: Vec<LayoutTransition>,
+    depth_attachments: Vec<&'static str>,
+    swapchain_writers: Vec<&'static str>,
+}
+
+impl RenderGraphSchedule {
+    /// Return the passes in the order they are to be run
+    pub fn passes(&self) -> &[ScheduledPass] {
+        &self.passes
+    }
+
+    /// Return the names of the passes in the order they are to be run
+    pub fn order(&self) -> Vec<&'static str> {
+        self.passes.iter().map(|p| p.name).collect()
+    }
+
+    /// Return the scheduled pass with a name
+    ///
+    /// name: The name of the pass
+    pub fn find(&self, name: &str) -> Option<&ScheduledPass> {
+        self.passes.iter().find(|p| p.name == name)
+    }
+
+    /// Return the layout changes made after the last pass, such as presenting the swapchain
+    pub fn final_transitions(&self) -> &[LayoutTransition] {
+        &self.final_transitions
+    }
+
+    /// Return whether an attachment holds depth rather than colour
+    ///
+    /// attachment: The name of the attachment
+    pub fn is_depth_attachment(&self, attachment: &str) -> bool {
+        self.depth_attachments.contains(&attachment)
+    }
+
+    /// Return the number of dependencies between the passes
+    ///
+    /// No frame has more waits between its submission batches than this.
+    pub fn dependency_count(&self) -> usize {
+        self.passes.iter().map(|p| p.dependencies.len()).sum()
+    }
+
+    /// Return whether the passes of a render pass wait on, or are waited on by, other passes
+    ///
+    /// A render pass that does needs external subpass dependencies.
+    ///
+    /// render_pass: The name of the render pass
+    pub fn has_external_dependencies(&self, render_pass: &str) -> bool {
+        let names: Vec<&'static str> = self.passes.iter().filter(|p| p.render_pass == render_pass).map(|p| p.name).collect();
+        self.passes
+            .iter()
+            .flat_map(|p| p.dependencies.iter())
+            .any(|d| names.contains(&d.from) || names.contains(&d.to))
+    }
+
+    /// Split command buffers into batches at the dependency edges between their passes
+    ///
+    /// A batch ends before the first command buffer of a pass depending on a pass in it, so that
+    /// passes with no dependency between them share a batch and may overlap.  Waits on earlier
+    /// batches are added to the batch being built.  Command buffers belonging to no pass, and
+    /// passes the schedule does not have, join the batch being built.  Dependencies on passes
+    /// with no command buffers among those given, such as ones already submitted, are ignored.
+    ///
+    /// passes: The graph pass each command buffer belongs to, in submission order
+    pub fn submission_batches(&self, passes: &[Option<&'static str>]) -> Vec<SubmissionBatch> {
+        let mut batches: Vec<SubmissionBatch> = vec![];
+        let mut batch_of_pass: HashMap<&'static str, usize> = HashMap::new();
+        for (i, pass) in passes.iter().enumerate() {
+            if batches.is_empty() {
+                batches.push(SubmissionBatch {
+                    start: i,
+                    waits: vec![],
+                });
+            }
+
+            let name = match *pass {
+                Some(name) if !batch_of_pass.contains_key(name) => name,
+                _ => continue,
+            };
+
+            let mut waits: Vec<(usize, DependencyKind)> = vec![];
+            for dependency in self.find(name).iter().flat_map(|p| p.dependencies.iter()) {
+                if let Some(&batch) = batch_of_pass.get(dependency.from) {
+                    if !waits.contains(&(batch, dependency.kind)) {
+                        waits.push((batch, dependency.kind));
+                    }
+                }
+            }
+
+            let current = batches.len() - 1;
+            if waits.iter().any(|&(batch, _)| batch == current) {
+                batches.push(SubmissionBatch {
+                    start: i,
+                    waits: waits,
+                });
+            } else {
+                for wait in waits {
+                    if !batches[current].waits.contains(&wait) {
+                        batches[current].waits.push(wait);
+                    }
+                }
+            }
+            batch_of_pass.insert(name, batches.len() - 1);
+        }
+
+        batches
+    }
+
+    /// Return the batch that must wait for the swapchain image to be acquired
+    ///
+    /// That is the batch holding the first command buffer of a pass writing the swapchain
+    /// attachment.  Command buffers belonging to no pass, or to passes the schedule does not
+    /// have, may write it too, so are treated the same.
+    ///
+    /// passes: The graph pass each command buffer belongs to, in submission order
+    /// batches: The batches submission_batches split the command buffers into
+    pub fn swapchain_batch(&self, passes: &[Option<&'static str>], batches: &[SubmissionBatch]) -> Option<usize> {
+        let first = passes.iter().position(|pass| match *pass {
+            Some(name) => self.swapchain_writers.contains(&name) || self.find(name).is_none(),
+            None => true,
+        });
+        first.map(|i| batches.iter().rposition(|b| b.start <= i).unwrap_or(0))
+    }
+}
+
+/// Passes declared with their attachments, from which the execution order is computed
+///
+/// Writers of an attachment run in the order they are declared.  A pass that samples an
+/// attachment reads what the writers declared before it left, or if it is declared before all
+/// of them, the attachment as the last writer leaves it.  Otherwise passes may be declared in
+/// any order, and unrelated passes keep the order they are declared in.  Attachments no pass
+/// writes, such as loaded textures, are taken to be ready and are left alone.
+#[derive(Clone, Debug, Default)]
+pub struct RenderGraph {
+    passes: Vec<RenderGraphPass>,
+}
+
+impl RenderGraph {
+    /// Create an empty render graph
+    pub fn new() -> RenderGraph {
+        RenderGraph { passes: vec![] }
+    }
+
+    /// Declare a pass
+    ///
+    /// pass: The pass with its attachments
+    pub fn add_pass(&mut self, pass: RenderGraphPass) {
+        self.passes.push(pass);
+    }
+
+    /// Return the declared passes
+    pub fn passes(&self) -> &[RenderGraphPass] {
+        &self.passes
+    }
+
+    /// Return whether no passes have been declared
+    pub fn is_empty(&self) -> bool {
+        self.passes.is_empty()
+    }
+
+    /// Compute the execution order of the passes and the transitions between them
+    pub fn compile(&self) -> Result<RenderGraphSchedule, RenderGraphError> {
+        for (i, pass) in self.passes.iter().enumerate() {
+            if self.passes[..i].iter().any(|p| p.name == pass.name) {
+                return Err(RenderGraphError::DuplicatePass(pass.name));
+            }
+            if let Some(attachment) = pass.inputs.iter().find(|a| pass.outputs().contains(a)) {
+                return Err(RenderGraphError::FeedbackLoop {
+                    pass: pass.name,
+                    attachment: attachment,
+                });
+            }
+        }
+
+        let dependencies = self.dependencies();
+
+        // Order the passes so that each follows those it depends on, preferring the order they
+        // were declared in where there is a choice
+        //
+        let mut scheduled = vec![false; self.passes.len()];
+        let mut order: Vec<usize> = Vec::with_capacity(self.passes.len());
+        while order.len() < self.passes.len() {
+            let next = (0..self.passes.len()).find(|&i| {
+                !scheduled[i] &&
+                dependencies.iter().filter(|&&(_, to, _, _)| to == i).all(|&(from, _, _, _)| scheduled[from])
+            });
+            match next {
+                Some(i) => {
+                    scheduled[i] = true;
+                    order.push(i);
+                }
+                None => {
+                    let remaining = (0..self.passes.len()).filter(|&i| !scheduled[i]).map(|i| self.passes[i].name).collect();
+                    return Err(RenderGraphError::Cycle(remaining));
+                }
+            }
+        }
+
+        // Follow the layout of each attachment through the frame
+        //
+        let mut layouts: HashMap<&'static str, AttachmentLayout> = HashMap::new();
+        let mut passes = Vec::with_capacity(order.len());
+        for &i in order.iter() {
+            let pass = &self.passes[i];
+            let mut transitions = vec![];
+            let mut loads = vec![];
+
+            let mut required: Vec<(&'static str, AttachmentLayout)> = vec![];
+            for input in pass.inputs.iter().filter(|a| self.is_written(a)) {
+                required.push((*input, AttachmentLayout::ShaderRead));
+            }
+            for output in pass.colour_outputs.iter() {
+                required.push((*output, AttachmentLayout::ColourAttachment));
+            }
+            for output in pass.depth_output.iter() {
+                required.push((*output, AttachmentLayout::DepthAttachment));
+            }
+
+            // Outputs the pass clears are overwritten, so their earlier contents are not loaded
+            let cleared = |layout: AttachmentLayout| match layout {
+                AttachmentLayout::ColourAttachment => pass.clear.colour.is_some(),
+                AttachmentLayout::DepthAttachment => pass.clear.depth.is_some(),
+                _ => false,
+            };
+
+            for (attachment, layout) in required {
+                let current = *layouts.get(attachment).unwrap_or(&AttachmentLayout::Undefined);
+                if current != AttachmentLayout::Undefined && layout != AttachmentLayout::ShaderRead && !cleared(layout) {
+                    loads.push(attachment);
+                }
+                if current != layout {
+                    transitions.push(LayoutTransition {
+                        attachment: attachment,
+                        from: current,
+                        to: layout,
+                    });
+                    layouts.insert(attachment, layout);
+                }
+            }
+
+            passes.push(ScheduledPass {
+                name: pass.name,
+                render_pass: pass.render_pass,
+                transitions: transitions,
+                dependencies: dependencies.iter()
+                    .filter(|&&(_, to, _, _)| to == i)
+                    .map(|&(from, _, kind, attachment)| {
+                        PassDependency {
+                            from: self.passes[from].name,
+                            to: pass.name,
+                            attachment: attachment,
+                            kind: kind,
+                        }
+                    })
+                    .collect(),
+                loads: loads,
+                clear: pass.clear,
+            });
+        }
+
+        let mut final_transitions = vec![];
+        if let Some(&current) = layouts.get(SWAPCHAIN_ATTACHMENT) {
+            final_transitions.push(LayoutTransition {
+                attachment: SWAPCHAIN_ATTACHMENT,
+                from: current,
+                to: AttachmentLayout::Present,
+            });
+        }
+
+        let mut depth_attachments: Vec<&'static str> = self.passes.iter().filter_map(|p| p.depth_output).collect();
+        depth_attachments.sort();
+        depth_attachments.dedup();
+
+        let swapchain_writers = self.passes
+            .iter()
+            .filter(|p| p.colour_outputs.contains(&SWAPCHAIN_ATTACHMENT))
+            .map(|p| p.name)
+            .collect();
+
+        Ok(RenderGraphSchedule {
+            passes: passes,
+            final_transitions: final_transitions,
+            depth_attachments: depth_attachments,
+            swapchain_writers: swapchain_writers,
+        })
+    }
+
+    /// Return whether any pass writes an attachment
+    fn is_written(&self, attachment: &str) -> bool {
+        self.passes.iter().any(|p| p.outputs().contains(&attachment))
+    }
+
+    /// Return the dependencies between passes, as the indices of the earlier and later pass
+    fn dependencies(&self) -> Vec<(usize, usize, DependencyKind, &'static str)> {
+        let mut dependencies: Vec<(usize, usize, DependencyKind, &'static str)> = vec![];
+
+        let mut attachments: Vec<&'static str> = self.passes.iter().flat_map(|p| p.outputs()).collect();
+        attachments.sort();
+        attachments.dedup();
+
+        for attachment in attachments {
+            let writers: Vec<usize> =
+                (0..self.passes.len()).filter(|&i| self.passes[i].outputs().contains(&attachment)).collect();
+
+            let mut found = vec![];
+            for pair in writers.windows(2) {
+                found.push((pair[0], pair[1], DependencyKind::WriteAfterWrite));
+            }
+
+            for reader in (0..self.passes.len()).filter(|&i| self.passes[i].inputs.contains(&attachment)) {
+                match writers.iter().rposition(|&w| w < reader) {
+                    Some(position) => {
+                        found.push((writers[position], reader, DependencyKind::ReadAfterWrite));
+                        if let Some(&next) = writers.get(position + 1) {
+                            found.push((reader, next, DependencyKind::WriteAfterRead));
+                        }
+                    }
+                    None => found.push((*writers.last().unwrap(), reader, DependencyKind::ReadAfterWrite)),
+                }
+            }
+
+            for (from, to, kind) in found {
+                if !dependencies.iter().any(|&(f, t, _, _)| f == from && t == to) {
+                    dependencies.push((from, to, kind, attachment));
+                }
+            }
+        }
+
+        dependencies
+    }
+}
@@ -0,0 +1,228 @@
+// Copyright (c) 2016-2017 Bruce Stenning. All rights reserved.
+//
+// Redistribution and use in source and binary forms, with or without
+// modification, are permitted provided that the following conditions
+// are met:
+//
+// 1. Redistributions of source code must retain the above copyright
+//    notice, this list of conditions and the following disclaimer.
+//
+// 2. Redistributions in binary form must reproduce the above copyright
+//    notice, this list of conditions and the following disclaimer in the
+//    documentation and/or other materials provided with the distribution.
+//
+// 3. Neither the name of the copyright holder nor the names of its
+//    contributors may be used to endorse or promote products derived
+//    from this software without specific prior written permission.
+//
+// THIS SOFTWARE IS PROVIDED BY THE COPYRIGHT HOLDERS AND CONTRIBUTORS
+// AS IS" AND ANY EXPRESS OR IMPLIED WARRANTIES, INCLUDING, BUT NOT
+// LIMITED TO, THE IMPLIED WARRANTIES OF MERCHANTABILITY AND FITNESS
+// FOR A PARTICULAR PURPOSE ARE DISCLAIMED. IN NO EVENT SHALL THE
+// COPYRIGHT HOLDER OR CONTRIBUTORS BE LIABLE FOR ANY DIRECT, INDIRECT,
+// INCIDENTAL, SPECIAL, EXEMPLARY, OR CONSEQUENTIAL DAMAGES (INCLUDING,
+// BUT NOT LIMITED TO, PROCUREMENT OF SUBSTITUTE GOODS OR SERVICES; LOSS
+// OF USE, DATA, OR PROFITS; OR BUSINESS INTERRUPTION) HOWEVER CAUSED
+// AND ON ANY THEORY OF LIABILITY, WHETHER IN CONTRACT, STRICT LIABILITY,
+// OR TORT (INCLUDING NEGLIGENCE OR OTHERWISE) ARISING IN ANY WAY OUT OF
+// THE USE OF THIS SOFTWARE, EVEN IF ADVISED OF THE POSSIBILITY OF SUCH
+// DAMAGE.
+
+use std::fmt;
+
+/// The name of the render pass that draws to the swapchain, which is always declared
+pub const SWAPCHAIN_PASS: &'static str = "swapchain";
+
+/// What a render pass draws to
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum PassTarget {
+    /// The swapchain image being presented
+    Swapchain,
+    /// Render target textures, which later passes may sample
+    Offscreen,
+}
+
+/// The format of the colour attachments of a render pass
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum PassColourFormat {
+    /// That of the window surface, for the swapchain pass
+    Surface,
+    /// Four 32-bit floating point channels, as render targets are created with
+    Rgba32Float,
+}
+
+/// A render pass with its target and attachments
+#[derive(Clone, Debug, PartialEq)]
+pub struct RenderPassDecl {
+    pub name: &'static str,
+    pub target: PassTarget,
+    pub colour_format: PassColourFormat,
+    /// The number of colour attachments, written by fragment outputs 0 onwards
+    pub colour_attachments: u32,
+    /// Whether the pass has a depth and stencil attachment
+    pub depth: bool,
+}
+
+impl RenderPassDecl {
+    /// Declare the pass drawing a single colour attachment to the swapchain, without depth
+    fn swapchain() -> RenderPassDecl {
+        RenderPassDecl {
+            name: SWAPCHAIN_PASS,
+            target: PassTarget::Swapchain,
+            colour_format: PassColourFormat::Surface,
+            colour_attachments: 1,
+            depth: false,
+        }
+    }
+
+    /// Declare a pass drawing to render targets, with a depth attachment
+    ///
+    /// name: The name of the pass, referred to by shader specifications
+    /// colour_attachments: The number of colour attachments
+    pub fn offscreen(name: &'static str, colour_attachments: u32) -> RenderPassDecl {
+        RenderPassDecl {
+            name: name,
+            target: PassTarget::Offscreen,
+            colour_format: PassColourFormat::Rgba32Float,
+            colour_attachments: colour_attachments,
+            depth: true,
+        }
+    }
+
+    /// Leave out the depth attachment of an offscreen pass
+    pub fn without_depth(mut self) -> RenderPassDecl {
+        self.depth = false;
+        self
+    }
+}
+
+/// Something that draws in a render pass, checked against its declaration
+#[derive(Clone, Debug, PartialEq)]
+pub struct PassUse {
+    /// The name of the shader or render graph pass
+    pub user: &'static str,
+    /// The name of the render pass it draws in
+    pub pass_name: &'static str,
+    /// The number of colour attachments it writes
+    pub colour_outputs: u32,
+}
+
+/// The reasons render pass declarations, or their uses, are invalid
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub enum RenderPassError {
+    /// More than one pass has the name
+    DuplicatePass(&'static str),
+    /// A pass other than the swapchain pass draws to the swapchain
+    SwapchainTarget(&'static str),
+    /// A shader or render graph pass draws in a pass that is not declared
+    UnknownPass {
+        user: &'static str,
+        pass: &'static str,
+    },
+    /// A shader or render graph pass writes more colour attachments than its pass has
+    TooManyOutputs {
+        user: &'static str,
+        pass: &'static str,
+        outputs: u32,
+        attachments: u32,
+    },
+}
+
+impl fmt::Display for RenderPassError {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match *self {
+            RenderPassError::DuplicatePass(name) => write!(f, "More than one render pass is named {}", name),
+            RenderPassError::SwapchainTarget(name) => {
+                write!(f, "Render pass {} draws to the swapchain, which only {} may", name, SWAPCHAIN_PASS)
+            }
+            RenderPassError::UnknownPass { user, pass } => {
+                write!(f, "{} draws in render pass {}, which is not declared", user, pass)
+            }
+            RenderPassError::TooManyOutputs { user, pass, outputs, attachments } => {
+                write!(f,
+                       "{} writes {} colour attachments, but render pass {} has {}",
+                       user,
+                       outputs,
+                       pass,
+                       attachments)
+            }
+        }
+    }
+}
+
+/// The render passes shaders draw in, keyed by name
+///
+/// A render pass fixes the target drawn to, the format and number of its colour attachments and
+/// whether it has a depth attachment.  The pass drawing to the swapchain is always declared.
+#[derive(Clone, Debug)]
+pub struct RenderPassRegistry {
+    passes: Vec<RenderPassDecl>,
+}
+
+impl Default for RenderPassRegistry {
+    fn default() -> RenderPassRegistry {
+        RenderPassRegistry::new()
+    }
+}
+
+impl RenderPassRegistry {
+    /// Create a registry holding just the swapchain pass
+    pub fn new() -> RenderPassRegistry {
+        RenderPassRegistry { passes: vec![RenderPassDecl::swapchain()] }
+    }
+
+    /// Declare a render pass
+    ///
+    /// pass: The pass with its target and attachments
+    pub fn declare(&mut self, pass: RenderPassDecl) -> Result<(), RenderPassError> {
+        if self.find(pass.name).is_some() {
+            return Err(RenderPassError::DuplicatePass(pass.name));
+        }
+        if pass.target == PassTarget::Swapchain {
+            return Err(RenderPassError::SwapchainTarget(pass.name));
+        }
+
+        self.passes.push(pass);
+        Ok(())
+    }
+
+    /// Return the declared passes
+    pub fn passes(&self) -> &[RenderPassDecl] {
+        &self.passes
+    }
+
+    /// Return the declared pass with a name
+    ///
+    /// name: The name of the pass
+    pub fn find(&self, name: &str) -> Option<&RenderPassDecl> {
+        self.passes.iter().find(|p| p.name == name)
+    }
+
+    /// Check that everything drawing in a render pass draws in a declared one, and has the
+    /// colour attachments it writes
+    ///
+    /// uses: The shaders and render graph passes with the render passes they draw in
+    pub fn validate(&self, uses: &[PassUse]) -> Result<(), RenderPassError> {
+        for u in uses.iter() {
+            let pass = match self.find(u.pass_name) {
+                Some(pass) => pass,
+                None => {
+                    return Err(RenderPassError::UnknownPass {
+                        user: u.user,
+                        pass: u.pass_name,
+                    })
+                }
+            };
+            if u.colour_outputs > pass.colour_attachments {
+                return Err(RenderPassError::TooManyOutputs {
+                    user: u.user,
+                    pass: pass.name,
+                    outputs: u.colour_outputs,
+                    attachments: pass.colour_attachments,
+                });
+            }
+        }
+
+        Ok(())
+    }
+}
@@ -46,9 +46,33 @@ pub trait RenderTarget {
     /// Return the texture object for this render target
     fn get_texture(&self) -> &Box<Texture>;
 
+    /// Return the number of colour attachments of this render target
+    ///
+    /// Fragment output n of the shaders rendering to the target is written to attachment n.
+    fn colour_attachment_count(&self) -> u32;
+
+    /// Return the texture object of a colour attachment of this render target
+    ///
+    /// attachment: The index of the colour attachment, where 0 is the texture of get_texture
+    fn get_colour_texture(&self, attachment: u32) -> &Box<Texture>;
+
     /// Take a snapshot to disk
     ///
+    /// The contents are read back asynchronously, and written once they are available
+    /// Renderer::readback_latency_frames frames later, so frames must keep being rendered until then.
+    ///
     /// renderer: The renderer object
     /// filename: The filename to save the snapshot to
-    fn snapshot(&self, renderer: &Box<Renderer>, filename: &str);
+    fn snapshot(&self, renderer: &mut Box<Renderer>, filename: &str);
+
+    /// Recreate the storage of this render target at a new size, discarding its contents
+    ///
+    /// Shaders sampling the target's textures and render graph attachments bound to it go on
+    /// referring to it, so post-processing targets can follow the window as it is resized.  The
+    /// target must not be selected at the time.
+    ///
+    /// renderer: The renderer object
+    /// width: The new width
+    /// height: The new height
+    fn resize(&mut self, renderer: &mut Box<Renderer>, width: u32, height: u32);
 }
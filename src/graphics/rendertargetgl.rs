@@ -28,7 +28,6 @@
 // THE USE OF THIS SOFTWARE, EVEN IF ADVISED OF THE POSSIBILITY OF SUCH
 // DAMAGE.
 
-use std::mem;
 use std::any::Any;
 
 use gl;
@@ -36,34 +35,94 @@ use gl::types::*;
 
 use graphics::rendertarget::*;
 use graphics::renderer::*;
+use graphics::renderergl::*;
 use graphics::texture::*;
 use graphics::texturegl::*;
 use graphics::image::*;
+use graphics::memoryusage::MemoryCategory;
 
 pub struct RenderTargetGl {
     texture: Box<Texture>,
+    extra_textures: Vec<Box<Texture>>,
     width: u32,
     height: u32,
     fbo: GLuint,
+    resolve_fbo: GLuint,
+    depth_renderbuffer: GLuint,
+    multisample_renderbuffers: Vec<GLuint>, // The colour render buffers then the depth one, when multisampled
 }
 
 impl RenderTargetGl {
     /// Return the OpenGL framebuffer object identifier for this render target
+    ///
+    /// This is the framebuffer that is drawn to, which is multisampled if the target is.
     pub fn get_fbo(&self) -> GLuint {
         self.fbo
     }
 
+    /// Return the framebuffer object holding the texture, which multisampled targets resolve to
+    pub fn get_resolve_fbo(&self) -> GLuint {
+        self.resolve_fbo
+    }
+
+    /// Return whether the target draws into multisampled render buffers
+    pub fn is_multisampled(&self) -> bool {
+        self.fbo != self.resolve_fbo
+    }
+
     /// Return the texture object for this render target
     pub fn get_texture(&self) -> &Box<Texture> {
         &self.texture
     }
 
+    /// Return the number of colour attachments, which are drawn to as draw buffers 0 onwards
+    pub fn get_colour_attachment_count(&self) -> u32 {
+        1 + self.extra_textures.len() as u32
+    }
+
+    /// Return the width of this render target
+    pub fn get_width(&self) -> u32 {
+        self.width
+    }
+
+    /// Return the height of this render target
+    pub fn get_height(&self) -> u32 {
+        self.height
+    }
+
     /// Configure texture as a render-to-texture target
     ///
+    /// When the renderer was created with more than one sample per pixel, drawing goes to
+    /// multisampled colour and depth render buffers, which are resolved into the texture when
+    /// the target is deselected.
+    ///
     /// width: Texture width
     /// height: Texture height
     pub fn new(renderer: &mut Box<Renderer>, width: u32, height: u32) -> RenderTargetGl {
-        let texture_gl = TextureGl::new_float_rgba(renderer, width, height, &vec![]);
+        RenderTargetGl::new_multiple(renderer, width, height, 1)
+    }
+
+    /// Configure textures as the colour attachments of a render-to-texture target
+    ///
+    /// Each texture is attached to its own draw buffer, which the fragment outputs of the
+    /// shaders rendering to the target write to in order.
+    ///
+    /// width: Texture width
+    /// height: Texture height
+    /// colour_attachments: The number of colour attachments, at least one
+    pub fn new_multiple(renderer: &mut Box<Renderer>, width: u32, height: u32, colour_attachments: u32) -> RenderTargetGl {
+        debug_assert!(colour_attachments >= 1);
+
+        let texture_gl = TextureGl::new_float_rgba(renderer, width, height, &vec![], false);
+        let extra_textures: Vec<TextureGl> = (1..colour_attachments)
+            .map(|_| TextureGl::new_float_rgba(renderer, width, height, &vec![], false))
+            .collect();
+        let draw_buffers: Vec<GLenum> = (0..colour_attachments).map(|i| gl::COLOR_ATTACHMENT0 + i).collect();
+
+        let sample_count = match renderer.as_any().downcast_ref::<RendererGl>() {
+            Some(r) => r.sample_count,
+            None => panic!("Unexpected runtime type"),
+        };
 
         let mut fbo: GLuint = 0;
         let mut drb: GLuint = 0;
@@ -76,14 +135,22 @@ impl RenderTargetGl {
                                      gl::TEXTURE_2D,
                                      texture_gl.texture_name,
                                      0); // Level
+            for (i, extra_texture) in extra_textures.iter().enumerate() {
+                gl::FramebufferTexture2D(gl::FRAMEBUFFER,
+                                         gl::COLOR_ATTACHMENT1 + i as GLenum,
+                                         gl::TEXTURE_2D,
+                                         extra_texture.texture_name,
+                                         0); // Level
+            }
+            gl::DrawBuffers(draw_buffers.len() as GLsizei, draw_buffers.as_ptr());
 
             gl::GenRenderbuffers(1, &mut drb);
             gl::BindRenderbuffer(gl::RENDERBUFFER, drb);
             gl::RenderbufferStorage(gl::RENDERBUFFER,
-                                    gl::DEPTH_COMPONENT24,
+                                    gl::DEPTH24_STENCIL8,
                                     width as GLint,
                                     height as GLint);
-            gl::FramebufferRenderbuffer(gl::FRAMEBUFFER, gl::DEPTH_ATTACHMENT, gl::RENDERBUFFER, drb);
+            gl::FramebufferRenderbuffer(gl::FRAMEBUFFER, gl::DEPTH_STENCIL_ATTACHMENT, gl::RENDERBUFFER, drb);
 
             let status = gl::CheckFramebufferStatus(gl::FRAMEBUFFER);
             debug_assert!(status == gl::FRAMEBUFFER_COMPLETE);
@@ -91,11 +158,76 @@ impl RenderTargetGl {
             gl::BindFramebuffer(gl::FRAMEBUFFER, 0);
         }
 
+        let mut multisample_fbo = fbo;
+        let mut multisample_renderbuffers = vec![];
+        if sample_count > 1 {
+            let mut msdrb: GLuint = 0;
+
+            unsafe {
+                gl::GenFramebuffers(1, &mut multisample_fbo);
+                gl::BindFramebuffer(gl::FRAMEBUFFER, multisample_fbo);
+
+                for draw_buffer in draw_buffers.iter() {
+                    let mut crb: GLuint = 0;
+                    gl::GenRenderbuffers(1, &mut crb);
+                    gl::BindRenderbuffer(gl::RENDERBUFFER, crb);
+                    gl::RenderbufferStorageMultisample(gl::RENDERBUFFER,
+                                                       sample_count as GLsizei,
+                                                       gl::RGBA32F,
+                                                       width as GLint,
+                                                       height as GLint);
+                    gl::FramebufferRenderbuffer(gl::FRAMEBUFFER, *draw_buffer, gl::RENDERBUFFER, crb);
+                    multisample_renderbuffers.push(crb);
+                }
+                gl::DrawBuffers(draw_buffers.len() as GLsizei, draw_buffers.as_ptr());
+
+                gl::GenRenderbuffers(1, &mut msdrb);
+                gl::BindRenderbuffer(gl::RENDERBUFFER, msdrb);
+                gl::RenderbufferStorageMultisample(gl::RENDERBUFFER,
+                                                   sample_count as GLsizei,
+                                                   gl::DEPTH24_STENCIL8,
+                                                   width as GLint,
+                                                   height as GLint);
+                gl::FramebufferRenderbuffer(gl::FRAMEBUFFER, gl::DEPTH_STENCIL_ATTACHMENT, gl::RENDERBUFFER, msdrb);
+                multisample_renderbuffers.push(msdrb);
+
+                let status = gl::CheckFramebufferStatus(gl::FRAMEBUFFER);
+                debug_assert!(status == gl::FRAMEBUFFER_COMPLETE);
+
+                gl::BindFramebuffer(gl::FRAMEBUFFER, 0);
+            }
+        }
+
+        // The textures were recorded as sampled ones, and are recorded again as attachments
+        //
+        let texel_bytes = gl_texel_bytes(gl::RGBA, gl::FLOAT);
+        let depth_texel_bytes = gl_texel_bytes(gl::DEPTH24_STENCIL8, gl::UNSIGNED_INT);
+        let pixels = width as u64 * height as u64;
+        {
+            let mut memory_usage = gl_memory_usage().lock().unwrap();
+            for texture in Some(&texture_gl).into_iter().chain(extra_textures.iter()) {
+                memory_usage.record((gl::TEXTURE, texture.texture_name as u64),
+                                    MemoryCategory::RenderTargets,
+                                    pixels * texel_bytes);
+            }
+            memory_usage.record((gl::RENDERBUFFER, drb as u64), MemoryCategory::RenderTargets, pixels * depth_texel_bytes);
+            for (i, renderbuffer) in multisample_renderbuffers.iter().enumerate() {
+                let bytes = if i == multisample_renderbuffers.len() - 1 { depth_texel_bytes } else { texel_bytes };
+                memory_usage.record((gl::RENDERBUFFER, *renderbuffer as u64),
+                                    MemoryCategory::RenderTargets,
+                                    pixels * sample_count as u64 * bytes);
+            }
+        }
+
         RenderTargetGl {
             texture: Box::new(texture_gl),
+            extra_textures: extra_textures.into_iter().map(|t| Box::new(t) as Box<Texture>).collect(),
             width: width,
             height: height,
-            fbo: fbo,
+            fbo: multisample_fbo,
+            resolve_fbo: fbo,
+            depth_renderbuffer: drb,
+            multisample_renderbuffers: multisample_renderbuffers,
         }
     }
 }
@@ -121,25 +253,89 @@ impl RenderTarget for RenderTargetGl {
         &self.texture
     }
 
-    /// Take a snapshot to disk
+    /// Return the number of colour attachments of this render target
+    fn colour_attachment_count(&self) -> u32 {
+        self.get_colour_attachment_count()
+    }
+
+    /// Return the texture object of a colour attachment of this render target
+    ///
+    /// attachment: The index of the colour attachment, where 0 is the texture of get_texture
+    fn get_colour_texture(&self, attachment: u32) -> &Box<Texture> {
+        if attachment == 0 {
+            &self.texture
+        } else {
+            &self.extra_textures[attachment as usize - 1]
+        }
+    }
+
+    /// Take a snapshot to disk, through the readback queue
     ///
     /// renderer: The renderer object
     /// filename: The filename to save the snapshot to
-    fn snapshot(&self, _: &Box<Renderer>, filename: &str) {
-        let mut data: Vec<u8> = vec![];
-        data.resize((self.width * self.height * 3) as usize, 0);
+    fn snapshot(&self, renderer: &mut Box<Renderer>, filename: &str) {
+        let filename = filename.to_string();
+        renderer.request_readback_callback(self, Box::new(move |image: Image| image.save_to(&filename)));
+    }
 
+    /// Recreate the storage of this render target at a new size, discarding its contents
+    ///
+    /// The textures and render buffers are reallocated in place, so they keep their names and
+    /// the framebuffer objects stay complete without anything being rebound.
+    ///
+    /// renderer: The renderer object
+    /// width: The new width
+    /// height: The new height
+    fn resize(&mut self, renderer: &mut Box<Renderer>, width: u32, height: u32) {
+        if width == self.width && height == self.height {
+            return;
+        }
+
+        let sample_count = match renderer.as_any().downcast_ref::<RendererGl>() {
+            Some(r) => r.sample_count,
+            None => panic!("Unexpected runtime type"),
+        };
+
+        for texture in Some(&mut self.texture).into_iter().chain(self.extra_textures.iter_mut()) {
+            match texture.as_any_mut().downcast_mut::<TextureGl>() {
+                Some(t) => t.reallocate(gl::RGBA, width, height),
+                None => panic!("Unexpected runtime type"),
+            }
+        }
+
+        let depth_index = self.multisample_renderbuffers.len().saturating_sub(1);
         unsafe {
-            gl::ReadPixels(0,
-                           0,
-                           self.width as i32,
-                           self.height as i32,
-                           gl::RGB,
-                           gl::UNSIGNED_BYTE,
-                           mem::transmute(data.as_ptr()));
+            gl::BindRenderbuffer(gl::RENDERBUFFER, self.depth_renderbuffer);
+            gl::RenderbufferStorage(gl::RENDERBUFFER,
+                                    gl::DEPTH24_STENCIL8,
+                                    width as GLint,
+                                    height as GLint);
+
+            for (i, renderbuffer) in self.multisample_renderbuffers.iter().enumerate() {
+                gl::BindRenderbuffer(gl::RENDERBUFFER, *renderbuffer);
+                gl::RenderbufferStorageMultisample(gl::RENDERBUFFER,
+                                                   sample_count as GLsizei,
+                                                   if i == depth_index { gl::DEPTH24_STENCIL8 } else { gl::RGBA32F },
+                                                   width as GLint,
+                                                   height as GLint);
+            }
+
+            gl::BindRenderbuffer(gl::RENDERBUFFER, 0);
+        }
+
+        let texel_bytes = gl_texel_bytes(gl::RGBA, gl::FLOAT);
+        let depth_texel_bytes = gl_texel_bytes(gl::DEPTH24_STENCIL8, gl::UNSIGNED_INT);
+        let pixels = width as u64 * height as u64;
+        {
+            let mut memory_usage = gl_memory_usage().lock().unwrap();
+            memory_usage.resize((gl::RENDERBUFFER, self.depth_renderbuffer as u64), pixels * depth_texel_bytes);
+            for (i, renderbuffer) in self.multisample_renderbuffers.iter().enumerate() {
+                let bytes = if i == depth_index { depth_texel_bytes } else { texel_bytes };
+                memory_usage.resize((gl::RENDERBUFFER, *renderbuffer as u64), pixels * sample_count as u64 * bytes);
+            }
         }
 
-        let image = Image::create_from_raw_data(self.width, self.height, &data);
-        image.save_to(filename);
+        self.width = width;
+        self.height = height;
     }
 }
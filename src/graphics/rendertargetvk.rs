@@ -45,9 +45,13 @@ pub struct RenderTargetVk {
     width: u32,
     height: u32,
     pub texture: Box<Texture>,
+    pub extra_textures: Vec<Box<Texture>>, // The textures of colour attachments 1 onwards
     depth_image_view: RendererVkImageView,
     depth_image: RendererVkImage,
+    multisample_colour_image_views: Vec<RendererVkImageView>,
+    multisample_colour_images: Vec<RendererVkImage>,
     framebuffer: Option<RendererVkFramebuffer>,
+    pass_name: Option<&'static str>, // The render pass the framebuffer was set up for
 }
 
 impl RenderTargetVk {
@@ -65,13 +69,68 @@ impl RenderTargetVk {
         self.depth_image.get_image_raw()
     }
 
+    /// Return the raw handles of the multisampled colour images, which are empty unless the
+    /// target is multisampled
+    ///
+    /// The textures hold the resolved contents, as of the end of the last render pass instance.
+    pub fn get_multisample_colour_images_raw(&self) -> Vec<VkImage> {
+        self.multisample_colour_images.iter().map(|image| image.get_image_raw()).collect()
+    }
+
+    /// Return the raw handles of the images of the colour attachment textures, in attachment order
+    pub fn get_colour_images_raw(&self) -> Vec<VkImage> {
+        let mut images = vec![];
+        for texture in Some(&self.texture).into_iter().chain(self.extra_textures.iter()) {
+            match texture.as_any().downcast_ref::<TextureVk>() {
+                Some(t) => images.push(t.texture.get_image_raw()),
+                None => panic!("Unexpected runtime type"),
+            }
+        }
+
+        images
+    }
+
+    /// Return the number of colour attachments
+    pub fn get_colour_attachment_count(&self) -> u32 {
+        1 + self.extra_textures.len() as u32
+    }
+
+    /// Return the width of this render target
+    pub fn get_width(&self) -> u32 {
+        self.width
+    }
+
+    /// Return the height of this render target
+    pub fn get_height(&self) -> u32 {
+        self.height
+    }
+
     /// Configure texture as a render-to-texture target
     ///
+    /// When the renderer was created with more than one sample per pixel, the target renders
+    /// into multisampled colour and depth images and resolves the colour into the texture.
+    ///
     /// texture: The texture the render target will use as storage
     /// width: Texture width
     /// height: Texture height
     pub fn new(renderer: &mut Box<Renderer>, width: u32, height: u32) -> RenderTargetVk {
-        let texture_vk = TextureVk::new_float_rgba(renderer, width, height, &vec![]);
+        RenderTargetVk::new_multiple(renderer, width, height, 1)
+    }
+
+    /// Configure textures as the colour attachments of a render-to-texture target
+    ///
+    /// The target must be set up with a pass whose render pass has the same number of colour
+    /// attachments, i.e. whose shaders have that many fragment outputs.
+    ///
+    /// width: Texture width
+    /// height: Texture height
+    /// colour_attachments: The number of colour attachments, at least one
+    pub fn new_multiple(renderer: &mut Box<Renderer>, width: u32, height: u32, colour_attachments: u32) -> RenderTargetVk {
+        debug_assert!(colour_attachments >= 1);
+
+        let textures_vk: Vec<TextureVk> = (0..colour_attachments)
+            .map(|_| TextureVk::new_float_rgba(renderer, width, height, &vec![], false))
+            .collect();
 
         let renderer_vk = match renderer.as_any_mut().downcast_mut::<RendererVk>() {
             Some(r) => r,
@@ -79,55 +138,157 @@ impl RenderTargetVk {
         };
 
         let depth_format = renderer_vk.choose_depth_format();
+        let samples = renderer_vk.sample_count;
 
         let depth_image =
-            RendererVkImage::new(&renderer_vk.device,
-                                 &renderer_vk.physical_device,
-                                 &renderer_vk.aux_command_pool,
-                                 width,
-                                 height,
-                                 depth_format,
-                                 VkImageTiling::VK_IMAGE_TILING_OPTIMAL,
-                                 VkImageUsageFlagBits::VK_IMAGE_USAGE_DEPTH_STENCIL_ATTACHMENT_BIT as VkImageUsageFlags,
-                                 VkMemoryPropertyFlagBits::VK_MEMORY_PROPERTY_DEVICE_LOCAL_BIT as VkMemoryPropertyFlags,
-                                 VkImageLayout::VK_IMAGE_LAYOUT_UNDEFINED,
-                                 VkImageLayout::VK_IMAGE_LAYOUT_DEPTH_STENCIL_ATTACHMENT_OPTIMAL);
+            RendererVkImage::new_multisampled(&renderer_vk.device,
+                                              &renderer_vk.physical_device,
+                                              &renderer_vk.aux_command_pool,
+                                              width,
+                                              height,
+                                              1,
+                                              samples,
+                                              depth_format,
+                                              VkImageTiling::VK_IMAGE_TILING_OPTIMAL,
+                                              VkImageUsageFlagBits::VK_IMAGE_USAGE_DEPTH_STENCIL_ATTACHMENT_BIT
+                                                  as VkImageUsageFlags |
+                                              VkImageUsageFlagBits::VK_IMAGE_USAGE_TRANSFER_DST_BIT as VkImageUsageFlags,
+                                              VkMemoryPropertyFlagBits::VK_MEMORY_PROPERTY_DEVICE_LOCAL_BIT
+                                                  as VkMemoryPropertyFlags,
+                                              VkImageLayout::VK_IMAGE_LAYOUT_UNDEFINED,
+                                              VkImageLayout::VK_IMAGE_LAYOUT_DEPTH_STENCIL_ATTACHMENT_OPTIMAL);
+
+        // The multisampled colour images persist between render pass instances like the textures
+        // do, so they are stored rather than transient
+        //
+        let mut multisample_colour_images = vec![];
+        if samples as u32 != VkSampleCountFlagBits::VK_SAMPLE_COUNT_1_BIT as u32 {
+            for _ in 0..colour_attachments {
+                let image =
+                    RendererVkImage::new_multisampled(&renderer_vk.device,
+                                                      &renderer_vk.physical_device,
+                                                      &renderer_vk.aux_command_pool,
+                                                      width,
+                                                      height,
+                                                      1,
+                                                      samples,
+                                                      VkFormat::VK_FORMAT_R32G32B32A32_SFLOAT,
+                                                      VkImageTiling::VK_IMAGE_TILING_OPTIMAL,
+                                                      VkImageUsageFlagBits::VK_IMAGE_USAGE_COLOR_ATTACHMENT_BIT
+                                                          as VkImageUsageFlags |
+                                                      VkImageUsageFlagBits::VK_IMAGE_USAGE_TRANSFER_DST_BIT as VkImageUsageFlags,
+                                                      VkMemoryPropertyFlagBits::VK_MEMORY_PROPERTY_DEVICE_LOCAL_BIT
+                                                          as VkMemoryPropertyFlags,
+                                                      VkImageLayout::VK_IMAGE_LAYOUT_UNDEFINED,
+                                                      VkImageLayout::VK_IMAGE_LAYOUT_COLOR_ATTACHMENT_OPTIMAL);
+                multisample_colour_images.push(image);
+            }
+        }
+        let multisample_colour_image_views: Vec<RendererVkImageView> = multisample_colour_images.iter()
+            .map(|image| {
+                RendererVkImageView::new(&renderer_vk.device,
+                                         image,
+                                         VkFormat::VK_FORMAT_R32G32B32A32_SFLOAT,
+                                         VkImageAspectFlagBits::VK_IMAGE_ASPECT_COLOR_BIT as VkImageAspectFlags)
+            })
+            .collect();
+
+        // Offscreen render passes load the existing colour contents, so the colour images must
+        // start out in the layout that the render pass expects
+        //
+        for texture_vk in textures_vk.iter() {
+            RendererVkImage::transition_layout_immediate(texture_vk.texture.get_image_raw(),
+                                                         &renderer_vk.device,
+                                                         &renderer_vk.aux_command_pool,
+                                                         VkImageAspectFlagBits::VK_IMAGE_ASPECT_COLOR_BIT as VkImageAspectFlags,
+                                                         VkImageLayout::VK_IMAGE_LAYOUT_UNDEFINED,
+                                                         VkImageLayout::VK_IMAGE_LAYOUT_COLOR_ATTACHMENT_OPTIMAL,
+                                                         VkPipelineStageFlagBits::VK_PIPELINE_STAGE_TOP_OF_PIPE_BIT
+                                                             as VkPipelineStageFlags,
+                                                         VkPipelineStageFlagBits::VK_PIPELINE_STAGE_TOP_OF_PIPE_BIT
+                                                             as VkPipelineStageFlags);
+        }
 
         let depth_image_view = RendererVkImageView::new(&renderer_vk.device,
                                                         &depth_image,
                                                         depth_format,
-                                                        VkImageAspectFlagBits::VK_IMAGE_ASPECT_DEPTH_BIT as VkImageAspectFlags);
+                                                        RendererVkImage::depth_aspect_mask(depth_format));
+
+        let name = format!("render target {}x{}", width, height);
+        for (i, texture_vk) in textures_vk.iter().enumerate() {
+            let texture_name = if i == 0 {
+                name.clone()
+            } else {
+                format!("{} colour {}", name, i)
+            };
+            renderer_vk.name_object(VK_OBJECT_TYPE_IMAGE, texture_vk.texture.get_image_raw() as u64, &texture_name);
+        }
+        renderer_vk.name_object(VK_OBJECT_TYPE_IMAGE,
+                                depth_image.get_image_raw() as u64,
+                                &format!("{} depth", name));
+        for (i, image) in multisample_colour_images.iter().enumerate() {
+            renderer_vk.name_object(VK_OBJECT_TYPE_IMAGE,
+                                    image.get_image_raw() as u64,
+                                    &format!("{} multisampled colour {}", name, i));
+        }
+
+        let mut textures = textures_vk.into_iter().map(|t| Box::new(t) as Box<Texture>);
+        let texture = textures.next().unwrap();
 
         RenderTargetVk {
             width: width,
             height: height,
-            texture: Box::new(texture_vk),
+            texture: texture,
+            extra_textures: textures.collect(),
             depth_image: depth_image,
             depth_image_view: depth_image_view,
+            multisample_colour_image_views: multisample_colour_image_views,
+            multisample_colour_images: multisample_colour_images,
             framebuffer: None,
+            pass_name: None,
         }
     }
 
     /// Continue configuration of the framebuffer object
     ///
-    /// renderer_vk: The Vulkan renderer object
-    pub fn setup(&mut self, renderer: &Box<Renderer>, pass_identifier: u32) {
+    /// The depth image is only attached where the render pass has a depth attachment.
+    ///
+    /// renderer: The renderer object
+    /// pass_name: The name of the render pass the target is drawn in
+    pub fn setup(&mut self, renderer: &Box<Renderer>, pass_name: &'static str) {
         let renderer_vk = match renderer.as_any().downcast_ref::<RendererVk>() {
             Some(r) => r,
             None => panic!("Unexpected runtime type"),
         };
 
-        let texture_vk = match self.texture.as_any().downcast_ref::<TextureVk>() {
-            Some(t) => t,
-            None => panic!("Unexpected runtime type"),
+        let mut texture_views = vec![];
+        for texture in Some(&self.texture).into_iter().chain(self.extra_textures.iter()) {
+            match texture.as_any().downcast_ref::<TextureVk>() {
+                Some(t) => texture_views.push(t.texture.get_view_raw()),
+                None => panic!("Unexpected runtime type"),
+            }
+        }
+
+        let (colour_views, resolve_views) = if self.multisample_colour_image_views.is_empty() {
+            (texture_views, vec![])
+        } else {
+            (self.multisample_colour_image_views.iter().map(|view| view.get_view_raw()).collect(), texture_views)
         };
 
+        let render_pass = &renderer_vk.render_passes[pass_name];
+        let depth_view = if render_pass.has_depth() {
+            Some(self.depth_image_view.get_view_raw())
+        } else {
+            None
+        };
         self.framebuffer = Some(RendererVkFramebuffer::new(&renderer_vk.device,
-                                                           texture_vk.texture.get_view_raw(),
-                                                           Some(self.depth_image_view.get_view_raw()),
-                                                           &renderer_vk.render_passes[pass_identifier as usize],
+                                                           &colour_views,
+                                                           depth_view,
+                                                           &resolve_views,
+                                                           render_pass,
                                                            self.width,
                                                            self.height));
+        self.pass_name = Some(pass_name);
     }
 }
 
@@ -152,23 +313,58 @@ impl RenderTarget for RenderTargetVk {
         &self.texture
     }
 
-    /// Take a snapshot to disk
+    /// Return the number of colour attachments of this render target
+    fn colour_attachment_count(&self) -> u32 {
+        self.get_colour_attachment_count()
+    }
+
+    /// Return the texture object of a colour attachment of this render target
+    ///
+    /// attachment: The index of the colour attachment, where 0 is the texture of get_texture
+    fn get_colour_texture(&self, attachment: u32) -> &Box<Texture> {
+        if attachment == 0 {
+            &self.texture
+        } else {
+            &self.extra_textures[attachment as usize - 1]
+        }
+    }
+
+    /// Take a snapshot to disk, through the readback queue
     ///
     /// renderer: The renderer object
     /// filename: The filename to save the snapshot to
-    fn snapshot(&self, renderer: &Box<Renderer>, filename: &str) {
-        let renderer_vk = match renderer.as_any().downcast_ref::<RendererVk>() {
-            Some(r) => r,
-            None => panic!("Unexpected runtime type"),
-        };
+    fn snapshot(&self, renderer: &mut Box<Renderer>, filename: &str) {
+        let filename = filename.to_string();
+        renderer.request_readback_callback(self, Box::new(move |image: Image| image.save_to(&filename)));
+    }
 
-        let texture_vk = match self.texture.as_any().downcast_ref::<TextureVk>() {
-            Some(t) => t,
-            None => panic!("Unexpected runtime type"),
-        };
+    /// Recreate the storage of this render target at a new size, discarding its contents
+    ///
+    /// A replacement target is created, and set up for the same pass, and the renderer's
+    /// descriptors and graph attachments referring to this one are pointed at it.  That waits for
+    /// the device to go idle, after which the old images, views and framebuffer are destroyed.
+    ///
+    /// renderer: The renderer object
+    /// width: The new width
+    /// height: The new height
+    fn resize(&mut self, renderer: &mut Box<Renderer>, width: u32, height: u32) {
+        if width == self.width && height == self.height {
+            return;
+        }
+
+        let mut resized = RenderTargetVk::new_multiple(renderer, width, height, self.get_colour_attachment_count());
+        if let Some(pass_name) = self.pass_name {
+            resized.setup(renderer, pass_name);
+        }
+
+        {
+            let renderer_vk = match renderer.as_any_mut().downcast_mut::<RendererVk>() {
+                Some(r) => r,
+                None => panic!("Unexpected runtime type"),
+            };
+            renderer_vk.replace_render_target(self, &resized);
+        }
 
-        let data: Vec<u8> = texture_vk.texture.read_pixels(renderer_vk);
-        let image = Image::create_from_raw_data(self.width, self.height, &data);
-        image.save_to(filename);
+        *self = resized;
     }
 }
@@ -0,0 +1,218 @@
+// Copyright (c) 2016-2017 Bruce Stenning. All rights reserved.
+//
+// Redistribution and use in source and binary forms, with or without
+// modification, are permitted provided that the following conditions
+// are met:
+//
+// 1. Redistributions of source code must retain the above copyright
+//    notice, this list of conditions and the following disclaimer.
+//
+// 2. Redistributions in binary form must reproduce the above copyright
+//    notice, this list of conditions and the following disclaimer in the
+//    documentation and/or other materials provided with the distribution.
+//
+// 3. Neither the name of the copyright holder nor the names of its
+//    contributors may be used to endorse or promote products derived
+//    from this software without specific prior written permission.
+//
+// THIS SOFTWARE IS PROVIDED BY THE COPYRIGHT HOLDERS AND CONTRIBUTORS
+// AS IS" AND ANY EXPRESS OR IMPLIED WARRANTIES, INCLUDING, BUT NOT
+// LIMITED TO, THE IMPLIED WARRANTIES OF MERCHANTABILITY AND FITNESS
+// FOR A PARTICULAR PURPOSE ARE DISCLAIMED. IN NO EVENT SHALL THE
+// COPYRIGHT HOLDER OR CONTRIBUTORS BE LIABLE FOR ANY DIRECT, INDIRECT,
+// INCIDENTAL, SPECIAL, EXEMPLARY, OR CONSEQUENTIAL DAMAGES (INCLUDING,
+// BUT NOT LIMITED TO, PROCUREMENT OF SUBSTITUTE GOODS OR SERVICES; LOSS
+// OF USE, DATA, OR PROFITS; OR BUSINESS INTERRUPTION) HOWEVER CAUSED
+// AND ON ANY THEORY OF LIABILITY, WHETHER IN CONTRACT, STRICT LIABILITY,
+// OR TORT (INCLUDING NEGLIGENCE OR OTHERWISE) ARISING IN ANY WAY OUT OF
+// THE USE OF THIS SOFTWARE, EVEN IF ADVISED OF THE POSSIBILITY OF SUCH
+// DAMAGE.
+use std::boxed::Box;
+use std::sync::mpsc::{channel, sync_channel, Receiver, Sender, SyncSender};
+use std::thread;
+use std::thread::JoinHandle;
+
+use graphics::renderer::Renderer;
+
+/// A command recorded by the application for the render thread to execute
+pub type FrameCommand<T> = Box<FnMut(&mut T) + Send>;
+
+/// The commands of a frame, recorded by the application and executed in order by the render thread
+///
+/// Executed queues are handed back to the application for reuse, so that recording a frame does
+/// not allocate once the queues have grown to the size of a typical frame.
+pub struct FrameCommands<T> {
+    commands: Vec<FrameCommand<T>>,
+}
+
+impl<T> FrameCommands<T> {
+    /// Construct an empty queue of commands
+    pub fn new() -> FrameCommands<T> {
+        FrameCommands { commands: vec![] }
+    }
+
+    /// Record a command
+    ///
+    /// command: The command, which is given the render thread's target when executed
+    pub fn push<F>(&mut self, command: F)
+        where F: FnMut(&mut T) + Send + 'static
+    {
+        self.commands.push(Box::new(command));
+    }
+
+    /// Return the number of commands recorded
+    pub fn len(&self) -> usize {
+        self.commands.len()
+    }
+
+    /// Return whether no commands have been recorded
+    pub fn is_empty(&self) -> bool {
+        self.commands.is_empty()
+    }
+
+    /// Execute the commands in the order they were recorded, leaving the queue empty
+    ///
+    /// target: The target to execute the commands on
+    pub fn execute(&mut self, target: &mut T) {
+        for mut command in self.commands.drain(..) {
+            command(target);
+        }
+    }
+}
+
+impl FrameCommands<Box<Renderer>> {
+    /// Record the start of a frame
+    pub fn begin_frame(&mut self) {
+        self.push(|renderer: &mut Box<Renderer>| renderer.begin_frame());
+    }
+
+    /// Record the end of a frame
+    pub fn end_frame(&mut self) {
+        self.push(|renderer: &mut Box<Renderer>| renderer.end_frame());
+    }
+
+    /// Record the start of a pass
+    ///
+    /// shader_name: The name of the shader to draw the pass with
+    pub fn begin_pass(&mut self, shader_name: &'static str) {
+        self.push(move |renderer: &mut Box<Renderer>| renderer.begin_pass(shader_name));
+    }
+
+    /// Record the end of a pass
+    pub fn end_pass(&mut self) {
+        self.push(|renderer: &mut Box<Renderer>| renderer.end_pass());
+    }
+
+    /// Record setting a float in a uniform buffer
+    ///
+    /// buffer_name: The name of the uniform buffer
+    /// uniform_name: The name of the uniform
+    /// value: The value to set
+    pub fn set_uniform_buffer_float(&mut self, buffer_name: &'static str, uniform_name: &'static str, value: f32) {
+        self.push(move |renderer: &mut Box<Renderer>| renderer.set_uniform_buffer_float(buffer_name, uniform_name, value));
+    }
+
+    /// Record the synchronisation of a uniform buffer
+    ///
+    /// buffer_name: The name of the uniform buffer
+    pub fn synchronise_uniform_buffer(&mut self, buffer_name: &'static str) {
+        self.push(move |renderer: &mut Box<Renderer>| renderer.synchronise_uniform_buffer(buffer_name));
+    }
+}
+
+/// A persistent thread that owns a target, such as a renderer, and executes frames of commands on it
+///
+/// The application records a frame while the render thread executes the previous one, giving a
+/// frame latency of one: submitting blocks while the thread is still busy with the last frame.
+/// The target is created on the render thread, as graphics contexts are tied to the thread they
+/// are made current on.
+pub struct RenderThread<T: Send + 'static> {
+    frame_tx: Option<SyncSender<FrameCommands<T>>>,
+    recycled_rx: Receiver<FrameCommands<T>>,
+    handle: Option<JoinHandle<T>>,
+    frames_submitted: u64,
+}
+
+impl<T: Send + 'static> RenderThread<T> {
+    /// Start the render thread
+    ///
+    /// create: Creates the target on the render thread
+    pub fn spawn<F>(create: F) -> RenderThread<T>
+        where F: FnOnce() -> T + Send + 'static
+    {
+        // A rendezvous channel, so that a frame is only handed over once the last has executed
+        let (frame_tx, frame_rx) = sync_channel::<FrameCommands<T>>(0);
+        let (recycled_tx, recycled_rx): (Sender<FrameCommands<T>>, Receiver<FrameCommands<T>>) = channel();
+
+        let handle = thread::Builder::new()
+            .name("render".to_string())
+            .spawn(move || {
+                let mut target = create();
+                for mut frame in frame_rx.iter() {
+                    frame.execute(&mut target);
+
+                    // The application may have stopped taking queues back, which is fine
+                    let _ = recycled_tx.send(frame);
+                }
+                target
+            })
+            .unwrap();
+
+        RenderThread {
+            frame_tx: Some(frame_tx),
+            recycled_rx: recycled_rx,
+            handle: Some(handle),
+            frames_submitted: 0,
+        }
+    }
+
+    /// Return an empty queue to record the next frame into, reusing an executed one if possible
+    pub fn begin_frame(&mut self) -> FrameCommands<T> {
+        match self.recycled_rx.try_recv() {
+            Ok(frame) => frame,
+            Err(_) => FrameCommands::new(),
+        }
+    }
+
+    /// Hand a recorded frame to the render thread
+    ///
+    /// This blocks until the render thread has finished executing the previous frame.
+    ///
+    /// frame: The recorded frame
+    pub fn submit(&mut self, frame: FrameCommands<T>) {
+        match self.frame_tx {
+            Some(ref frame_tx) => {
+                if frame_tx.send(frame).is_err() {
+                    panic!("The render thread has stopped");
+                }
+            }
+            None => panic!("The render thread has been finished"),
+        }
+        self.frames_submitted += 1;
+    }
+
+    /// Return the number of frames submitted
+    pub fn frames_submitted(&self) -> u64 {
+        self.frames_submitted
+    }
+
+    /// Wait for the submitted frames to execute, stop the render thread and return its target
+    ///
+    /// A renderer should be shut down by a last submitted command, on the thread that owns it.
+    pub fn finish(mut self) -> T {
+        self.frame_tx = None;
+        match self.handle.take() {
+            Some(handle) => handle.join().unwrap(),
+            None => panic!("The render thread has been finished"),
+        }
+    }
+}
+
+impl<T: Send + 'static> Drop for RenderThread<T> {
+    fn drop(&mut self) {
+        self.frame_tx = None;
+        if let Some(handle) = self.handle.take() {
+            let _ = handle.join();
+        }
+    }
+}
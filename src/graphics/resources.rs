@@ -32,8 +32,15 @@ use std::collections::HashMap;
 use std::str::FromStr;
 use num::Zero;
 
+use graphics::devicefeatures::DeviceFeatures;
 use graphics::shader::*;
 use graphics::renderer::*;
+use graphics::rendergraph::*;
+use graphics::renderpasses::*;
+use graphics::pipelinebuild::PipelineBuildOptions;
+use graphics::shadervariants::*;
+use graphics::spirvreflect::*;
+use graphics::uniformlayout::*;
 use misc::fileutils::*;
 
 pub enum RenderTargetId {
@@ -41,11 +48,21 @@ pub enum RenderTargetId {
     Offscreen = 1,
 }
 
+/// The entry point used by GLSL shaders, which cannot name their own
+pub const DEFAULT_ENTRY_POINT: &'static str = "main";
+
+/// A specifier for the source of a single shader stage
+///
+/// Several stages may name the same spirv_out, in which case each is compiled on its own and
+/// the results are linked with spirv-link into one SPIR-V module containing all of their entry
+/// points.  Stages sharing a module must differ in their stage or entry point.  OpenGL ignores
+/// entry_point, as GLSL always uses main.
 pub struct ShaderFilesSpecification {
     pub filename: &'static str,
     pub shader_stage: ShaderStage,
     pub spirv_out: &'static str,
     pub reflect_out: &'static str,
+    pub entry_point: &'static str,
 }
 
 impl Clone for ShaderFilesSpecification {
@@ -55,6 +72,7 @@ impl Clone for ShaderFilesSpecification {
             shader_stage: self.shader_stage,
             spirv_out: self.spirv_out,
             reflect_out: self.reflect_out,
+            entry_point: self.entry_point,
         }
     }
 }
@@ -64,7 +82,7 @@ impl Clone for ShaderFilesSpecification {
 /// This matches all of the types in VkDescriptorType on the
 /// assumption that this covers all the OpenGL types too.  It
 /// should not need to exactly concur with the Vulkan types.
-#[derive(Clone, Copy, PartialEq)]
+#[derive(Clone, Copy, Debug, PartialEq)]
 pub enum UniformType {
     Sampler = 0,
     CombinedImageSampler = 1,
@@ -113,6 +131,46 @@ impl Default for UniformBlockSpec {
     }
 }
 
+impl UniformBlockSpec {
+    /// Return the layout rules of the block: std430 for storage blocks and std140 otherwise
+    pub fn block_layout(&self) -> BlockLayout {
+        if self.block_type == UniformType::StorageBuffer {
+            BlockLayout::Std430
+        } else {
+            BlockLayout::Std140
+        }
+    }
+
+    /// Calculate the layout of the block from the types of its members
+    ///
+    /// Returns None unless every member has a type.
+    pub fn calculated_layout(&self) -> Option<CalculatedBlockLayout> {
+        let mut members = Vec::with_capacity(self.uniforms.len());
+        for uniform in self.uniforms.iter() {
+            members.push((uniform.member_type?, uniform.array_length));
+        }
+
+        Some(calculate_block_layout(self.block_layout(), &members))
+    }
+
+    /// Return a copy of the specification whose size, member offsets and strides are calculated
+    /// from the types of its members
+    ///
+    /// Blocks with untyped members keep the layout given to them, such as by SPIR-V reflection.
+    pub fn laid_out(&self) -> UniformBlockSpec {
+        let mut spec = self.clone();
+        if let Some(layout) = self.calculated_layout() {
+            spec.size = layout.size;
+            for (uniform, member) in spec.uniforms.iter_mut().zip(layout.members.iter()) {
+                uniform.offset = member.offset;
+                uniform.stride = member.stride;
+            }
+        }
+
+        spec
+    }
+}
+
 // A specifier for a uniform outside a block
 pub struct UniformSpec {
     pub name: &'static str,
@@ -148,6 +206,8 @@ pub struct BlockUniformSpec {
     pub name: &'static str,
     pub offset: usize, // Used by Vulkan
     pub stride: usize, // Used by Vulkan
+    pub member_type: Option<BlockMemberType>, // When every member has a type, the offsets and strides are calculated
+    pub array_length: usize, // Zero if the member is not an array
 }
 
 impl Clone for BlockUniformSpec {
@@ -156,6 +216,8 @@ impl Clone for BlockUniformSpec {
             name: self.name,
             offset: self.offset,
             stride: self.stride,
+            member_type: self.member_type,
+            array_length: self.array_length,
         }
     }
 }
@@ -166,6 +228,354 @@ impl Default for BlockUniformSpec {
             name: "none",
             offset: 0,
             stride: 0,
+            member_type: None,
+            array_length: 0,
+        }
+    }
+}
+
+impl BlockUniformSpec {
+    /// Construct the specification of a member whose offset is calculated from its type
+    ///
+    /// name: The name of the member
+    /// member_type: The GLSL type of the member
+    pub fn typed(name: &'static str, member_type: BlockMemberType) -> BlockUniformSpec {
+        BlockUniformSpec {
+            name: name,
+            member_type: Some(member_type),
+            ..Default::default()
+        }
+    }
+
+    /// Construct the specification of an array member whose offset and stride are calculated
+    /// from its type
+    ///
+    /// name: The name of the member
+    /// member_type: The GLSL type of each element
+    /// array_length: The number of elements
+    pub fn typed_array(name: &'static str, member_type: BlockMemberType, array_length: usize) -> BlockUniformSpec {
+        BlockUniformSpec {
+            name: name,
+            member_type: Some(member_type),
+            array_length: array_length,
+            ..Default::default()
+        }
+    }
+}
+
+// A specifier for a member of the push constant block of a shader
+//
+// On OpenGL the member is instead set as a plain uniform of the same name.
+pub struct PushConstantSpec {
+    pub name: &'static str,
+    pub offset: usize,
+    pub size: usize,
+}
+
+impl Clone for PushConstantSpec {
+    fn clone(&self) -> PushConstantSpec {
+        PushConstantSpec {
+            name: self.name,
+            offset: self.offset,
+            size: self.size,
+        }
+    }
+}
+
+/// The comparison made by the depth test, or between the reference value and the stencil buffer
+///
+/// The values match VkCompareOp.
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub enum CompareOp {
+    Never = 0,
+    Less = 1,
+    Equal = 2,
+    LessOrEqual = 3,
+    Greater = 4,
+    NotEqual = 5,
+    GreaterOrEqual = 6,
+    Always = 7,
+}
+
+/// The operation applied to the stencil buffer after the stencil and depth tests
+///
+/// The values match VkStencilOp.
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub enum StencilOp {
+    Keep = 0,
+    Zero = 1,
+    Replace = 2,
+    IncrementAndClamp = 3,
+    DecrementAndClamp = 4,
+    Invert = 5,
+    IncrementAndWrap = 6,
+    DecrementAndWrap = 7,
+}
+
+/// The stencil test of a shader, applied alike to front and back faces
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub struct StencilState {
+    pub compare: CompareOp,
+    pub fail_op: StencilOp, // Applied where the stencil test fails
+    pub pass_op: StencilOp, // Applied where both the stencil and depth tests pass
+    pub depth_fail_op: StencilOp, // Applied where the stencil test passes but the depth test fails
+    pub reference: u32,
+    pub compare_mask: u32, // Masks both the reference and the stored value before comparing
+    pub write_mask: u32, // The bits of the stored value that may be written
+}
+
+impl StencilState {
+    /// Return a state that writes the reference value wherever the shader draws, e.g. to mark a portal
+    ///
+    /// reference: The value to write
+    pub fn write(reference: u32) -> StencilState {
+        StencilState {
+            compare: CompareOp::Always,
+            pass_op: StencilOp::Replace,
+            reference: reference,
+            ..StencilState::default()
+        }
+    }
+
+    /// Return a state that only draws where the stencil buffer holds the reference value
+    ///
+    /// reference: The value to compare against
+    pub fn equal(reference: u32) -> StencilState {
+        StencilState {
+            compare: CompareOp::Equal,
+            write_mask: 0,
+            reference: reference,
+            ..StencilState::default()
+        }
+    }
+
+    /// Return the state with a different reference value
+    ///
+    /// reference: The new reference value
+    pub fn with_reference(self, reference: u32) -> StencilState {
+        StencilState { reference: reference, ..self }
+    }
+}
+
+impl Default for StencilState {
+    fn default() -> StencilState {
+        StencilState {
+            compare: CompareOp::Always,
+            fail_op: StencilOp::Keep,
+            pass_op: StencilOp::Keep,
+            depth_fail_op: StencilOp::Keep,
+            reference: 0,
+            compare_mask: 0xff,
+            write_mask: 0xff,
+        }
+    }
+}
+
+/// The faces of triangles discarded before rasterisation
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub enum CullMode {
+    None,
+    Front,
+    Back,
+}
+
+/// The winding of the triangles that face the viewer, as seen in OpenGL window coordinates
+///
+/// The Vulkan pipelines reverse the winding unless the viewport is flipped to match OpenGL.
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub enum FrontFace {
+    CounterClockwise,
+    Clockwise,
+}
+
+/// A factor the source or destination is multiplied by when blending
+///
+/// The values match VkBlendFactor.
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub enum BlendFactor {
+    Zero = 0,
+    One = 1,
+    SrcColour = 2,
+    OneMinusSrcColour = 3,
+    DstColour = 4,
+    OneMinusDstColour = 5,
+    SrcAlpha = 6,
+    OneMinusSrcAlpha = 7,
+    DstAlpha = 8,
+    OneMinusDstAlpha = 9,
+}
+
+/// How the weighted source and destination are combined when blending
+///
+/// The values match VkBlendOp.
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub enum BlendOp {
+    Add = 0,
+    Subtract = 1,
+    ReverseSubtract = 2,
+    Min = 3,
+    Max = 4,
+}
+
+/// The blend equations of the colour and alpha channels, applied to every colour attachment
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub struct BlendState {
+    pub src_colour: BlendFactor,
+    pub dst_colour: BlendFactor,
+    pub colour_op: BlendOp,
+    pub src_alpha: BlendFactor,
+    pub dst_alpha: BlendFactor,
+    pub alpha_op: BlendOp,
+}
+
+impl BlendState {
+    /// Return the equations weighting the source and destination alike in every channel
+    ///
+    /// src: The factor of the source
+    /// dst: The factor of the destination
+    pub fn new(src: BlendFactor, dst: BlendFactor) -> BlendState {
+        BlendState {
+            src_colour: src,
+            dst_colour: dst,
+            colour_op: BlendOp::Add,
+            src_alpha: src,
+            dst_alpha: dst,
+            alpha_op: BlendOp::Add,
+        }
+    }
+
+    /// Return the equations with the alpha channel weighted differently from the colour
+    ///
+    /// src: The factor of the source alpha
+    /// dst: The factor of the destination alpha
+    pub fn with_alpha(self, src: BlendFactor, dst: BlendFactor) -> BlendState {
+        BlendState {
+            src_alpha: src,
+            dst_alpha: dst,
+            ..self
+        }
+    }
+}
+
+/// A common way of blending a shader's colour with the target, declared with its render state
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub enum BlendMode {
+    Disabled, // The colour replaces the target's
+    Alpha, // The colour is blended over the target by its alpha, which is written as it is
+    PremultipliedAlpha, // The colour, already multiplied by its alpha, is blended over the target
+    Additive, // The colour weighted by its alpha is added to the target, as for glows and particles
+    Multiply, // The target is multiplied by the colour, as for tinting, leaving its alpha alone
+}
+
+impl BlendMode {
+    /// Return the blend equations of the mode, or None if it does not blend
+    pub fn blend_state(self) -> Option<BlendState> {
+        match self {
+            BlendMode::Disabled => None,
+            BlendMode::Alpha => {
+                Some(BlendState::new(BlendFactor::SrcAlpha, BlendFactor::OneMinusSrcAlpha)
+                    .with_alpha(BlendFactor::One, BlendFactor::Zero))
+            }
+            BlendMode::PremultipliedAlpha => Some(BlendState::new(BlendFactor::One, BlendFactor::OneMinusSrcAlpha)),
+            BlendMode::Additive => {
+                Some(BlendState::new(BlendFactor::SrcAlpha, BlendFactor::One).with_alpha(BlendFactor::Zero, BlendFactor::One))
+            }
+            BlendMode::Multiply => {
+                Some(BlendState::new(BlendFactor::DstColour, BlendFactor::Zero).with_alpha(BlendFactor::Zero, BlendFactor::One))
+            }
+        }
+    }
+}
+
+/// The colour channels written by a shader, combined into a mask
+///
+/// The values match VkColorComponentFlagBits.
+pub const COLOUR_WRITE_RED: u32 = 0x1;
+pub const COLOUR_WRITE_GREEN: u32 = 0x2;
+pub const COLOUR_WRITE_BLUE: u32 = 0x4;
+pub const COLOUR_WRITE_ALPHA: u32 = 0x8;
+pub const COLOUR_WRITE_ALL: u32 = COLOUR_WRITE_RED | COLOUR_WRITE_GREEN | COLOUR_WRITE_BLUE | COLOUR_WRITE_ALPHA;
+
+/// The fixed-function state a shader draws with, honoured by the Vulkan pipelines and when
+/// an OpenGL shader is selected
+///
+/// The default draws every triangle unblended without testing depth, as full-screen passes do.
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub struct RenderState {
+    pub cull_mode: CullMode,
+    pub front_face: FrontFace,
+    pub blend: Option<BlendState>, // The blend equations, or None to replace the target's colour
+    pub depth_compare: Option<CompareOp>, // The depth test, or None to draw without testing or writing depth
+    pub depth_write: bool, // Whether fragments passing the depth test write their depth
+    pub colour_write_mask: u32, // The COLOUR_WRITE_* channels written to every colour attachment
+}
+
+impl RenderState {
+    /// Return the state of opaque geometry, which tests and writes depth
+    pub fn opaque() -> RenderState {
+        RenderState {
+            depth_compare: Some(CompareOp::Less),
+            depth_write: true,
+            ..RenderState::default()
+        }
+    }
+
+    /// Return the state of translucent overlays, which blend by alpha without testing depth
+    pub fn alpha_blended() -> RenderState {
+        RenderState::default().with_blend_mode(BlendMode::Alpha)
+    }
+
+    /// Return the state blending in one of the common ways
+    ///
+    /// mode: How the colour is blended with the target
+    pub fn with_blend_mode(self, mode: BlendMode) -> RenderState {
+        RenderState { blend: mode.blend_state(), ..self }
+    }
+
+    /// Return the state with back or front faces culled
+    ///
+    /// cull_mode: The faces to cull
+    /// front_face: The winding of the faces that face the viewer
+    pub fn with_culling(self, cull_mode: CullMode, front_face: FrontFace) -> RenderState {
+        RenderState {
+            cull_mode: cull_mode,
+            front_face: front_face,
+            ..self
+        }
+    }
+
+    /// Return the state with a different depth test
+    ///
+    /// compare: The comparison with the depth buffer, or None to not test depth
+    /// write: Whether fragments passing the test write their depth
+    pub fn with_depth(self, compare: Option<CompareOp>, write: bool) -> RenderState {
+        RenderState {
+            depth_compare: compare,
+            depth_write: write,
+            ..self
+        }
+    }
+
+    /// Return whether fragments are tested against the depth buffer
+    pub fn depth_tested(&self) -> bool {
+        self.depth_compare.is_some()
+    }
+
+    /// Return whether fragments write the depth buffer, which they only do when tested
+    pub fn writes_depth(&self) -> bool {
+        self.depth_tested() && self.depth_write
+    }
+}
+
+impl Default for RenderState {
+    fn default() -> RenderState {
+        RenderState {
+            cull_mode: CullMode::None,
+            front_face: FrontFace::CounterClockwise,
+            blend: None,
+            depth_compare: None,
+            depth_write: false,
+            colour_write_mask: COLOUR_WRITE_ALL,
         }
     }
 }
@@ -175,14 +585,58 @@ pub struct ShaderSpec {
     pub name: &'static str,
     pub library_files: Vec<&'static str>,
     pub shader_files: Vec<ShaderFilesSpecification>,
+    pub fallback_shader_files: Vec<Vec<ShaderFilesSpecification>>, // Alternative stage sets, for devices lacking a stage
     pub uniform_block_names: Vec<&'static str>,
     pub uniform_specs: Vec<UniformSpec>,
+    pub push_constants: Vec<PushConstantSpec>,
     pub vertex_array_type: VertexArrayType,
+    pub primitive: PrimitiveType, // The topology Vulkan assembles, unless tessellating; OpenGL draws that of the thread data
+    pub instance_layout: Option<InstanceLayout>,
     pub attributes: Vec<&'static str>,
     pub fragment_out: &'static str,
-    pub depth_test_enabled: bool,
-    pub alpha_blending_enabled: bool,
-    pub pass_identifier: u32,
+    pub extra_fragment_outs: Vec<&'static str>, // Written to locations 1 onwards, for multiple render targets
+    pub render_state: RenderState, // Culling, blending and the depth test
+    pub stencil: Option<StencilState>, // The stencil test, or None to leave the stencil buffer untouched
+    pub bindless_textures: bool, // Whether the shader reads the bindless texture array, where it is supported
+    pub pass_name: &'static str, // The render pass the shader draws in, declared with the resource manager
+}
+
+impl ShaderSpec {
+    /// Return whether the shader is a compute shader rather than a graphics pipeline
+    pub fn is_compute(&self) -> bool {
+        self.shader_files.iter().any(|file| file.shader_stage == ShaderStage::ComputeShader)
+    }
+
+    /// Return the number of colour attachments the shader writes, one per fragment output
+    pub fn colour_attachment_count(&self) -> u32 {
+        1 + self.extra_fragment_outs.len() as u32
+    }
+
+    /// Switch to the first of the fallback stage sets the device supports, where the shader
+    /// files need a stage it lacks
+    ///
+    /// The fallbacks are tried in order, so they should be listed from the most capable.  This
+    /// returns whether the shader files were replaced, or the stages missing when no stage set
+    /// is supported, in which case the shader is left as it is.
+    ///
+    /// features: The features granted to the device
+    pub fn select_stages(&mut self, features: &DeviceFeatures) -> Result<bool, Vec<ShaderStage>> {
+        let missing = features.missing_stages(self.shader_files.iter().map(|f| f.shader_stage));
+        if missing.is_empty() {
+            return Ok(false);
+        }
+
+        let fallback = self.fallback_shader_files
+            .iter()
+            .position(|files| features.missing_stages(files.iter().map(|f| f.shader_stage)).is_empty());
+        match fallback {
+            Some(index) => {
+                self.shader_files = self.fallback_shader_files[index].clone();
+                Ok(true)
+            }
+            None => Err(missing),
+        }
+    }
 }
 
 impl Clone for ShaderSpec {
@@ -191,14 +645,20 @@ impl Clone for ShaderSpec {
             name: self.name,
             library_files: self.library_files.clone(),
             shader_files: self.shader_files.clone(),
+            fallback_shader_files: self.fallback_shader_files.clone(),
             uniform_block_names: self.uniform_block_names.clone(),
             uniform_specs: self.uniform_specs.clone(),
+            push_constants: self.push_constants.clone(),
             vertex_array_type: self.vertex_array_type.clone(),
+            primitive: self.primitive,
+            instance_layout: self.instance_layout.clone(),
             attributes: self.attributes.clone(),
             fragment_out: self.fragment_out.clone(),
-            depth_test_enabled: self.depth_test_enabled,
-            alpha_blending_enabled: self.alpha_blending_enabled,
-            pass_identifier: self.pass_identifier,
+            extra_fragment_outs: self.extra_fragment_outs.clone(),
+            render_state: self.render_state,
+            stencil: self.stencil,
+            bindless_textures: self.bindless_textures,
+            pass_name: self.pass_name,
         }
     }
 }
@@ -206,6 +666,14 @@ impl Clone for ShaderSpec {
 pub struct ResourceManager {
     pub uniform_block_specs: HashMap<&'static str, UniformBlockSpec>,
     pub shader_specs: HashMap<&'static str, ShaderSpec>,
+    /// The passes of the frame with their attachments, scheduled when the renderer is set up
+    pub render_graph: RenderGraph,
+    /// The render passes the shaders draw in, created when the renderer is set up
+    pub render_passes: RenderPassRegistry,
+    /// The variants of the shaders compiled with different defines, see register_shader_variant
+    pub shader_variants: ShaderVariants,
+    /// Which pipelines startup waits for and who is told of the progress (Vulkan only)
+    pub pipeline_builds: PipelineBuildOptions,
 }
 
 impl ResourceManager {
@@ -219,6 +687,10 @@ impl ResourceManager {
         ResourceManager {
             uniform_block_specs: uniform_block_specs,
             shader_specs: shader_specs,
+            render_graph: RenderGraph::new(),
+            render_passes: RenderPassRegistry::new(),
+            shader_variants: ShaderVariants::new(),
+            pipeline_builds: PipelineBuildOptions::new(),
         }
     }
 
@@ -384,6 +856,152 @@ impl ResourceManager {
         }
     }
 
+    /// Compare the uniform block, opaque uniform and push constant specifications with the
+    /// layouts reflected from the shaders' SPIR-V modules
+    ///
+    /// The modules must have been compiled.  Shaders whose modules cannot be read are reported
+    /// as a mismatch of their own.
+    ///
+    /// debug: true if the mismatches should be printed, false otherwise
+    pub fn verify_spirv_reflection(&self, debug: bool) -> Vec<ReflectionMismatch> {
+        let mut mismatches = vec![];
+        for (_, shader_spec) in self.shader_specs.iter() {
+            let reflection = match reflect_shader(shader_spec) {
+                Ok(r) => r,
+                Err(e) => {
+                    mismatches.push(ReflectionMismatch {
+                        shader: shader_spec.name,
+                        name: shader_spec.name.to_string(),
+                        description: format!("unable to reflect the SPIR-V: {}", e),
+                    });
+                    continue;
+                }
+            };
+
+            for uniform_block_name in shader_spec.uniform_block_names.iter() {
+                match (self.uniform_block_specs.get(uniform_block_name), reflection.block(uniform_block_name)) {
+                    (Some(spec), Some(block)) => {
+                        mismatches.extend(verify_block_spec(shader_spec.name, uniform_block_name, &spec.laid_out(), block))
+                    }
+                    (None, _) => {
+                        mismatches.push(ReflectionMismatch {
+                            shader: shader_spec.name,
+                            name: uniform_block_name.to_string(),
+                            description: "is used by the shader but has no specification".to_string(),
+                        })
+                    }
+                    // The block may be optimised out of a shader that does not read it
+                    (Some(_), None) => {}
+                }
+            }
+
+            for uniform_spec in shader_spec.uniform_specs.iter() {
+                if let Some(uniform) = reflection.uniform(uniform_spec.name) {
+                    let set_differs = uniform_spec.set != u32::max_value() && Some(uniform_spec.set) != uniform.set;
+                    let binding_differs = uniform_spec.binding != u32::max_value() &&
+                                          Some(uniform_spec.binding) != uniform.binding;
+                    if set_differs || binding_differs {
+                        mismatches.push(ReflectionMismatch {
+                            shader: shader_spec.name,
+                            name: uniform_spec.name.to_string(),
+                            description: format!("set {} and binding {} are specified but the shader uses {:?} and {:?}",
+                                                 uniform_spec.set,
+                                                 uniform_spec.binding,
+                                                 uniform.set,
+                                                 uniform.binding),
+                        });
+                    }
+                }
+            }
+
+            if let Some(block) = reflection.blocks.iter().find(|b| b.push_constant) {
+                mismatches.extend(verify_push_constants(shader_spec.name, &shader_spec.push_constants, block));
+            }
+        }
+
+        if debug {
+            for mismatch in mismatches.iter() {
+                log_warning!("Reflection mismatch in {}", mismatch);
+            }
+        }
+
+        mismatches
+    }
+
+    /// Fill in the bindings, block sizes and member offsets from the shaders' SPIR-V modules
+    ///
+    /// This is the counterpart of read_reflection_data that decodes the modules themselves, so
+    /// that the specifications need only name the members of each block.
+    ///
+    /// debug: true if debug statements should be dumped, false otherwise
+    pub fn apply_spirv_reflection(&mut self, debug: bool) {
+        for (_, shader_spec) in self.shader_specs.iter_mut() {
+            let reflection = match reflect_shader(shader_spec) {
+                Ok(r) => r,
+                Err(e) => {
+                    log_warning!("Unable to reflect the SPIR-V of {}: {}", shader_spec.name, e);
+                    continue;
+                }
+            };
+
+            for uniform_block_name in shader_spec.uniform_block_names.iter() {
+                if let (Some(spec), Some(block)) = (self.uniform_block_specs.get_mut(uniform_block_name),
+                                                    reflection.block(uniform_block_name)) {
+                    if debug {
+                        log_info!("Reflected block {} of {} bytes at set {:?}, binding {:?}",
+                                  uniform_block_name,
+                                  block.size,
+                                  block.set,
+                                  block.binding);
+                    }
+                    apply_block_reflection(spec, block);
+                }
+            }
+
+            for uniform_spec in shader_spec.uniform_specs.iter_mut() {
+                if let Some(uniform) = reflection.uniform(uniform_spec.name) {
+                    if let Some(set) = uniform.set {
+                        uniform_spec.set = set;
+                    }
+                    if let Some(binding) = uniform.binding {
+                        uniform_spec.binding = binding;
+                    }
+                }
+            }
+
+            if let Some(block) = reflection.blocks.iter().find(|b| b.push_constant) {
+                for push_constant in shader_spec.push_constants.iter_mut() {
+                    if let Some(member) = block.member(push_constant.name) {
+                        push_constant.offset = member.offset;
+                        push_constant.size = member.size;
+                    }
+                }
+            }
+        }
+    }
+
+    /// Switch each shader needing a stage the device lacks to a stage set it supports
+    ///
+    /// The renderers call this once the device features are known, before the shaders are built.
+    /// Shaders with no supported stage set are left as they are, and are reported, so the passes
+    /// drawing with them should be skipped according to the granted features.
+    ///
+    /// features: The features granted to the device
+    pub fn select_shader_stages(&mut self, features: &DeviceFeatures) {
+        for (name, spec) in self.shader_specs.iter_mut() {
+            match spec.select_stages(features) {
+                Ok(true) => log_info!("Shader {} uses fallback stages, as the device lacks some of its own", name),
+                Ok(false) => (),
+                Err(missing) => {
+                    let stages: Vec<&'static str> = missing.iter().map(|s| shader_stage_name(*s)).collect();
+                    log_warning!("Shader {} needs {:?} shaders, which the device lacks, and has no fallback",
+                                 name,
+                                 stages);
+                }
+            }
+        }
+    }
+
     /// Return the shader resource specification object for the given shader name
     ///
     /// name: The name of the desired shader
@@ -392,4 +1010,31 @@ impl ResourceManager {
     pub fn get_shader_spec(&self, name: &str) -> ShaderSpec {
         self.shader_specs[name].clone()
     }
+
+    /// Check that the graphics shaders and render graph passes draw in declared render passes
+    ///
+    /// Compute shaders draw in no render pass, so are not checked.
+    pub fn validate_render_passes(&self) -> Result<(), RenderPassError> {
+        let mut uses: Vec<PassUse> = self.shader_specs
+            .values()
+            .filter(|spec| !spec.is_compute())
+            .map(|spec| {
+                PassUse {
+                    user: spec.name,
+                    pass_name: spec.pass_name,
+                    colour_outputs: spec.colour_attachment_count(),
+                }
+            })
+            .collect();
+        uses.sort_by_key(|u| u.user);
+        uses.extend(self.render_graph.passes().iter().map(|pass| {
+            PassUse {
+                user: pass.name,
+                pass_name: pass.render_pass,
+                colour_outputs: pass.colour_outputs.len() as u32,
+            }
+        }));
+
+        self.render_passes.validate(&uses)
+    }
 }
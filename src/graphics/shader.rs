@@ -36,13 +36,14 @@ use graphics::renderer::Renderer;
 use graphics::resources::*;
 use misc::embeddedresources::*;
 
-#[derive(Clone, Copy, PartialEq)]
+#[derive(Clone, Copy, Debug, PartialEq)]
 pub enum ShaderStage {
     VertexShader,
     TessControlShader,
     TessEvalShader,
     GeometryShader,
     FragmentShader,
+    ComputeShader,
 }
 
 /// Convert a ShaderStage to string for debug
@@ -53,6 +54,72 @@ pub fn shader_stage_name(shader_stage: ShaderStage) -> &'static str {
         ShaderStage::TessEvalShader => "tesselation evaluation",
         ShaderStage::GeometryShader => "geometry",
         ShaderStage::FragmentShader => "fragment",
+        ShaderStage::ComputeShader => "compute",
+    }
+}
+
+/// The stages of a shader compiled into one SPIR-V module
+pub struct SpirvModulePlan {
+    pub spirv_out: &'static str, // The file the module is written to
+    pub stages: Vec<usize>, // The indices of the shader files whose entry points the module holds
+}
+
+/// Group the stages of a shader by the SPIR-V module they are compiled into
+///
+/// The pipeline selects each stage from its module by the stage and entry point, so no two
+/// stages sharing a module may have both in common.
+///
+/// shader_files: The specifications of the stages
+///
+/// Returns: The modules in the order of their first stage, or a description of the clash
+pub fn plan_spirv_modules(shader_files: &[ShaderFilesSpecification]) -> Result<Vec<SpirvModulePlan>, String> {
+    let mut modules: Vec<SpirvModulePlan> = vec![];
+    for (index, shader_file) in shader_files.iter().enumerate() {
+        match modules.iter().position(|module| module.spirv_out == shader_file.spirv_out) {
+            Some(position) => {
+                let clashes = modules[position].stages.iter().any(|stage| {
+                    shader_files[*stage].shader_stage == shader_file.shader_stage &&
+                    shader_files[*stage].entry_point == shader_file.entry_point
+                });
+                if clashes {
+                    return Err(format!("{} has two {} stages with the entry point {}",
+                                       shader_file.spirv_out,
+                                       shader_stage_name(shader_file.shader_stage),
+                                       shader_file.entry_point));
+                }
+                modules[position].stages.push(index);
+            }
+            None => {
+                modules.push(SpirvModulePlan {
+                    spirv_out: shader_file.spirv_out,
+                    stages: vec![index],
+                })
+            }
+        }
+    }
+
+    Ok(modules)
+}
+
+/// The languages shader sources may be written in
+#[derive(Clone, Copy, PartialEq)]
+pub enum ShaderLanguage {
+    Glsl,
+    Hlsl,
+}
+
+impl ShaderLanguage {
+    /// Determine the language of a shader source file from its extension
+    ///
+    /// Files ending in .hlsl are HLSL, and anything else is taken to be GLSL.
+    ///
+    /// filename: The name of the source file
+    pub fn from_filename(filename: &str) -> ShaderLanguage {
+        if filename.ends_with(".hlsl") {
+            ShaderLanguage::Hlsl
+        } else {
+            ShaderLanguage::Glsl
+        }
     }
 }
 
@@ -44,10 +44,15 @@ use regex::Regex;
 use gl;
 use gl::types::*;
 
-use graphics::renderer::Renderer;
+use graphics::renderer::*;
 use graphics::renderergl::*;
 use graphics::shader::*;
+use graphics::shaderhlsl::*;
+use graphics::errorshader::*;
 use graphics::resources::*;
+use graphics::versioning::*;
+use graphics::glslinclude::*;
+use graphics::memoryusage::MemoryCategory;
 use misc::fileutils::*;
 use misc::embeddedresources::*;
 
@@ -72,10 +77,15 @@ pub struct ShaderGlsl {
     uniform_specs: Vec<UniformSpec>,
     attribute_names: Vec<&'static str>,
     fragment_out: &'static str,
-    depth_test_enabled: bool,
-    alpha_blending_enabled: bool,
+    extra_fragment_outs: Vec<&'static str>,
+    render_state: RenderState,
+    stencil: Option<StencilState>,
+    vertex_array_type: VertexArrayType,
+    instance_layout: Option<InstanceLayout>,
+    using_error_program: bool,
 
-    file_mod_times: HashMap<&'static str, SystemTime>,
+    source_files: Vec<String>, // The GLSL library, source and included files the sources were built from
+    file_mod_times: HashMap<String, SystemTime>,
 
     program: GLint,
     vao: GLuint,
@@ -104,9 +114,14 @@ impl ShaderGlsl {
             uniform_specs: vec![],
             attribute_names: vec![],
             fragment_out: "",
-            depth_test_enabled: false,
-            alpha_blending_enabled: false,
-
+            extra_fragment_outs: vec![],
+            render_state: RenderState::default(),
+            stencil: None,
+            vertex_array_type: VertexArrayType::F3F3F3,
+            instance_layout: None,
+            using_error_program: false,
+
+            source_files: vec![],
             file_mod_times: HashMap::new(),
 
             program: -1,
@@ -129,6 +144,7 @@ impl ShaderGlsl {
             ShaderStage::TessEvalShader => gl::TESS_EVALUATION_SHADER,
             ShaderStage::GeometryShader => gl::GEOMETRY_SHADER,
             ShaderStage::FragmentShader => gl::FRAGMENT_SHADER,
+            ShaderStage::ComputeShader => gl::COMPUTE_SHADER,
         }
     }
 
@@ -160,39 +176,64 @@ impl ShaderGlsl {
             // Create a Vertex Buffer Object
             gl::GenBuffers(1, &mut vbo);
 
-            // Bind them
+            // Bind them, so that the next shader selected binds its own again
             gl::BindVertexArray(vao);
             gl::BindBuffer(gl::ARRAY_BUFFER, vbo);
+            gl_selected_state().lock().unwrap().reset();
 
-            // Read the library source file
-            let mut source_names = vec![];
-            let mut lib_source = String::new();
-            for filename in self.lib_files.iter() {
-                let now = SystemTime::now();
-                self.file_mod_times.insert(filename, now);
-                let source = read_text_file(autos, filename);
-                lib_source = lib_source.to_string() + &source + &"\n#line 1\n";
+            // Load and compile GLSL shader sources, with the library files and includes expanded
+            //
+            // Every file read is recorded, even when the build fails, so fixing any of them triggers a rebuild
+            let now = SystemTime::now();
+            for shader_file in self.shader_files.iter() {
+                self.file_mod_times.insert(shader_file.filename.to_string(), now);
             }
-
-            // Load and compile GLSL shader sources
+            let mut preprocessor = GlslPreprocessor::new();
             let mut shaders = vec![];
             for shader_file in self.shader_files.iter() {
-                let now = SystemTime::now();
-                self.file_mod_times.insert(shader_file.filename, now);
-                let source = read_text_file(autos, &shader_file.filename);
-                let full_source = lib_source.to_string() + &source;
+                let full_source = if ShaderLanguage::from_filename(shader_file.filename) == ShaderLanguage::Hlsl {
+                    // HLSL is translated through SPIR-V, so must be on disk and can't use the library files
+                    match translate_hlsl_to_glsl(shader_file.filename, shader_file.shader_stage, shader_file.entry_point) {
+                        Some(glsl) => glsl,
+                        None => {
+                            gl::DeleteVertexArrays(1, &vao);
+                            gl::DeleteBuffers(1, &vbo);
+                            self.fall_back_to_error_program();
+                            return;
+                        }
+                    }
+                } else {
+                    let expanded = preprocess_glsl(&mut preprocessor, autos, &self.lib_files, shader_file.filename);
+                    for filename in preprocessor.files().iter() {
+                        self.file_mod_times.insert(filename.clone(), now);
+                    }
+                    self.source_files = preprocessor.files().clone();
+                    match expanded {
+                        Ok(source) => source,
+                        Err(e) => {
+                            log_error!("Failed to preprocess {} shader for {}: {}",
+                                       shader_stage_name(shader_file.shader_stage),
+                                       self.shader_name,
+                                       e);
+                            gl::DeleteVertexArrays(1, &vao);
+                            gl::DeleteBuffers(1, &vbo);
+                            self.fall_back_to_error_program();
+                            return;
+                        }
+                    }
+                };
                 let compiled = compile_glsl(&self.shader_name,
-                                            &source_names,
+                                            preprocessor.files(),
                                             &full_source,
                                             shader_file.shader_stage,
                                             self.old_driver);
                 if compiled < 0 {
                     gl::DeleteVertexArrays(1, &vao);
                     gl::DeleteBuffers(1, &vbo);
+                    self.fall_back_to_error_program();
                     return;
                 }
                 shaders.push(compiled as GLuint);
-                source_names.pop();
             }
 
             // Link the shader program
@@ -200,6 +241,7 @@ impl ShaderGlsl {
             if program < 0 {
                 gl::DeleteVertexArrays(1, &vao);
                 gl::DeleteBuffers(1, &vbo);
+                self.fall_back_to_error_program();
                 return;
             }
 
@@ -209,14 +251,52 @@ impl ShaderGlsl {
             // Find all the required uniform block information and store it
             let mut uniform_block_descs = HashMap::new();
             for block_name in self.uniform_block_names.iter() {
-                let ref block = resource_manager.lock().unwrap().uniform_block_specs[block_name];
+                let block = resource_manager.lock().unwrap().uniform_block_specs[block_name].laid_out();
+
+                // Storage blocks are not described by the uniform queries, so take their layout
+                // from the member types or the reflection data instead
+                if block.block_type == UniformType::StorageBuffer {
+                    let block_index = gl::GetProgramResourceIndex(program as GLuint,
+                                                                  gl::SHADER_STORAGE_BLOCK,
+                                                                  CString::new(block_name.clone()).unwrap().as_ptr());
+                    if block_index == gl::INVALID_INDEX {
+                        log_error!("build_shader could not find storage block {} for {}",
+                                   block_name,
+                                   self.shader_name);
+                        continue;
+                    }
+
+                    let ssbo_handle = renderer_gl.get_uniform_buffer_handle(block_name);
+                    gl::BindBuffer(gl::SHADER_STORAGE_BUFFER, ssbo_handle);
+                    gl::BufferData(gl::SHADER_STORAGE_BUFFER,
+                                   block.size as isize,
+                                   ptr::null(),
+                                   gl::DYNAMIC_DRAW);
+                    gl_memory_usage().lock().unwrap().record((gl::BUFFER, ssbo_handle as u64),
+                                                             MemoryCategory::StorageBuffers,
+                                                             block.size as u64);
+                    gl::BindBufferBase(gl::SHADER_STORAGE_BUFFER, block.binding, ssbo_handle);
+                    gl::ShaderStorageBlockBinding(program as GLuint, block_index, block.binding);
+                    gl::BindBuffer(gl::SHADER_STORAGE_BUFFER, 0);
+
+                    uniform_block_descs.insert(*block_name,
+                                               UniformBlockDesc {
+                                                   size: block.size,
+                                                   index: block_index,
+                                                   handle: ssbo_handle,
+                                                   binding: block.binding,
+                                                   offsets: block.uniforms.iter().map(|u| (u.name, u.offset)).collect(),
+                                                   strides: block.uniforms.iter().map(|u| (u.name, u.stride)).collect(),
+                                               });
+                    continue;
+                }
 
                 let block_index = gl::GetUniformBlockIndex(program as GLuint,
                                                            CString::new(block_name.clone()).unwrap().as_ptr());
                 if block_index == gl::INVALID_INDEX {
-                    println!("build_shader could not find uniform block {} for {}",
-                             block_name,
-                             self.shader_name);
+                    log_error!("build_shader could not find uniform block {} for {}",
+                               block_name,
+                               self.shader_name);
                 } else {
                     let mut block_size: GLint = 0;
                     gl::GetActiveUniformBlockiv(program as GLuint,
@@ -245,6 +325,10 @@ impl ShaderGlsl {
                                    block_size as isize,
                                    ptr::null(),
                                    gl::DYNAMIC_DRAW);
+                    gl_memory_usage().lock().unwrap().record((gl::BUFFER, ubo_handle as u64),
+                                                             MemoryCategory::Uniforms,
+                                                             block_size as u64);
+                    renderer_gl.invalidate_uniform_buffer(block_name);
                     gl::BindBufferBase(gl::UNIFORM_BUFFER, block.binding, ubo_handle);
                     gl::UniformBlockBinding(program as GLuint, block_index, block.binding);
 
@@ -278,13 +362,23 @@ impl ShaderGlsl {
                                             gl::UNIFORM_ARRAY_STRIDE,
                                             strides.as_mut_ptr());
 
-                    // Now check each and store the valid offsets for each uniform
+                    // Now check each and store the valid offsets for each uniform, reporting any that
+                    // disagree with the std140 layout calculated from the member types
+                    let calculated = block.calculated_layout().is_some();
                     for (offset, uniform) in offsets.iter().zip(block.uniforms.iter()) {
                         if *offset == -1 {
-                            println!("Failed to find uniform block {} name {}",
-                                     block_name,
-                                     uniform.name);
+                            log_error!("Failed to find uniform block {} name {}",
+                                       block_name,
+                                       uniform.name);
                         } else {
+                            if calculated && *offset as usize != uniform.offset {
+                                log_warning!("Uniform {} of block {} is at offset {} in {}, but at {} by its type",
+                                             uniform.name,
+                                             block_name,
+                                             *offset,
+                                             self.shader_name,
+                                             uniform.offset);
+                            }
                             // println!("build_shader {} uniform block {} size {} uniform {}, offset {}",
                             //          self.shader_name,
                             //          block_name,
@@ -322,9 +416,9 @@ impl ShaderGlsl {
                 let uniform = gl::GetUniformLocation(program as GLuint,
                                                      CString::new(spec.name.clone()).unwrap().as_ptr());
                 if uniform == -1 {
-                    println!("build_shader could not find uniform {} for {}",
-                             spec.name,
-                             self.shader_name);
+                    log_error!("build_shader could not find uniform {} for {}",
+                               spec.name,
+                               self.shader_name);
                 } else {
                     uniforms.insert(spec.name.clone(), uniform);
                 }
@@ -336,14 +430,40 @@ impl ShaderGlsl {
                 let attribute = gl::GetAttribLocation(program as GLuint,
                                                       CString::new((*name).clone()).unwrap().as_ptr());
                 if attribute == -1 {
-                    println!("build_shader could not find attribute {} for {}",
-                             name,
-                             self.shader_name);
+                    log_error!("build_shader could not find attribute {} for {}",
+                               name,
+                               self.shader_name);
                 } else {
                     attributes.insert((*name).clone(), attribute);
                 }
             }
 
+            // Point the per-instance attributes at the renderer's instance buffer, advancing once
+            // per instance, which the Vertex Array Object records
+            if let Some(ref layout) = self.instance_layout {
+                let stride = layout.components_per_instance();
+                gl::BindBuffer(gl::ARRAY_BUFFER, renderer_gl.instance_buffer);
+                for (i, &(name, components)) in layout.attributes.iter().enumerate() {
+                    let attribute = gl::GetAttribLocation(program as GLuint, CString::new(name).unwrap().as_ptr());
+                    if attribute == -1 {
+                        log_error!("build_shader could not find instance attribute {} for {}",
+                                   name,
+                                   self.shader_name);
+                        continue;
+                    }
+
+                    gl::VertexAttribPointer(attribute as GLuint,
+                                            components as GLint,
+                                            gl::FLOAT,
+                                            gl::FALSE as GLboolean, // Whether normalised
+                                            (stride * mem::size_of::<GLfloat>()) as GLsizei,
+                                            (layout.offset_of(i) * mem::size_of::<GLfloat>()) as *const raw::c_void);
+                    gl::EnableVertexAttribArray(attribute as GLuint);
+                    gl::VertexAttribDivisor(attribute as GLuint, 1);
+                }
+                gl::BindBuffer(gl::ARRAY_BUFFER, vbo);
+            }
+
             // At this point we can update things
             self.program = program;
             self.vao = vao;
@@ -352,16 +472,103 @@ impl ShaderGlsl {
             self.uniform_block_descs = uniform_block_descs;
             self.uniforms = uniforms;
             self.attributes = attributes;
+            self.using_error_program = false;
 
-            // Define the fragment output variable
+            // Define the fragment output variables, one per draw buffer of the render target
             gl::BindFragDataLocation(self.program as GLuint,
                                      0,
                                      CString::new(self.fragment_out.clone()).unwrap().as_ptr());
+            for (i, fragment_out) in self.extra_fragment_outs.iter().enumerate() {
+                gl::BindFragDataLocation(self.program as GLuint,
+                                         1 + i as GLuint,
+                                         CString::new(*fragment_out).unwrap().as_ptr());
+            }
         }
 
         self.generate_warnings = true;
     }
 
+    /// Substitute the built-in error program after a failure to build the shader
+    ///
+    /// A shader that has previously been built successfully keeps its program, so that a
+    /// mistake made while editing a shader does not disturb the rest of the frame.
+    fn fall_back_to_error_program(&mut self) {
+        if self.using_error_program {
+            return;
+        }
+        if self.program != -1 {
+            log_warning!("Keeping the previous program for {} after the rebuild failed",
+                         self.shader_name);
+            return;
+        }
+
+        log_warning!("Substituting the error shader for {}", self.shader_name);
+
+        unsafe {
+            let mut vao: GLuint = 0;
+            let mut vbo: GLuint = 0;
+            gl::GenVertexArrays(1, &mut vao);
+            gl::GenBuffers(1, &mut vbo);
+            gl::BindVertexArray(vao);
+            gl::BindBuffer(gl::ARRAY_BUFFER, vbo);
+            gl_selected_state().lock().unwrap().reset();
+
+            let vertex_shader = compile_glsl(&self.shader_name,
+                                             &vec![],
+                                             &error_vertex_shader_source(self.vertex_array_type),
+                                             ShaderStage::VertexShader,
+                                             self.old_driver);
+            let fragment_shader = compile_glsl(&self.shader_name,
+                                               &vec![],
+                                               &error_fragment_shader_source(),
+                                               ShaderStage::FragmentShader,
+                                               self.old_driver);
+            if vertex_shader < 0 || fragment_shader < 0 {
+                panic!("Failed to compile the error shader for {}", self.shader_name);
+            }
+
+            let shaders = vec![vertex_shader as GLuint, fragment_shader as GLuint];
+            let program = link_program(&self.shader_name, &shaders);
+            if program < 0 {
+                panic!("Failed to link the error shader for {}", self.shader_name);
+            }
+
+            // The position is always the first attribute, so map the name the caller will use
+            // for it onto the position input of the error program
+            let mut attributes = HashMap::new();
+            if let Some(name) = self.attribute_names.first() {
+                let attribute = gl::GetAttribLocation(program as GLuint, CString::new("position").unwrap().as_ptr());
+                attributes.insert(*name, attribute);
+            }
+
+            gl::BindFragDataLocation(program as GLuint,
+                                     0,
+                                     CString::new(ERROR_SHADER_FRAGMENT_OUT).unwrap().as_ptr());
+
+            self.program = program;
+            self.vao = vao;
+            self.vbo = vbo;
+            self.shaders = shaders;
+            self.uniform_block_descs = HashMap::new();
+            self.uniforms = HashMap::new();
+            self.attributes = attributes;
+        }
+
+        // The uniforms and remaining attributes of the shader do not exist in the error program
+        self.generate_warnings = false;
+        self.using_error_program = true;
+    }
+
+    /// Return whether the built-in error program has been substituted for the shader
+    pub fn is_using_error_program(&self) -> bool {
+        self.using_error_program
+    }
+
+    /// Return the handle of the linked program
+    pub fn get_program(&self) -> GLuint {
+        self.program as GLuint
+    }
+
     /// Get the uniform buffer layout from a uniform block
     ///
     /// block_name: The name of the block to return the buffer information for
@@ -378,6 +585,8 @@ impl ShaderGlsl {
             bytes: Vec::with_capacity(block.size),
             offsets: block.offsets.clone(),
             strides: block.strides.clone(),
+            version: ContentVersion::new(),
+            written_by_shaders: false,
         };
         descriptor.bytes.resize(block.size, 0);
 
@@ -397,9 +606,9 @@ impl ShaderGlsl {
     fn get_uniform(&self, name: &str) -> GLint {
         if !self.uniforms.contains_key(name) {
             if self.generate_warnings {
-                println!("get_uniform could not find {} for {}",
-                         name,
-                         self.shader_name);
+                log_error!("get_uniform could not find {} for {}",
+                           name,
+                           self.shader_name);
             }
             return -1;
         }
@@ -419,9 +628,9 @@ impl ShaderGlsl {
     fn get_attribute(&self, name: &str) -> GLint {
         if !self.attributes.contains_key(name) {
             if self.generate_warnings {
-                println!("get_attribute could not find {} for {}",
-                         name,
-                         self.shader_name);
+                log_error!("get_attribute could not find {} for {}",
+                           name,
+                           self.shader_name);
             }
             return -1;
         }
@@ -468,8 +677,11 @@ impl Shader for ShaderGlsl {
         self.uniform_specs = shader_spec.uniform_specs.clone();
         self.attribute_names = shader_spec.attributes.clone();
         self.fragment_out = shader_spec.fragment_out.clone();
-        self.depth_test_enabled = shader_spec.depth_test_enabled;
-        self.alpha_blending_enabled = shader_spec.alpha_blending_enabled;
+        self.extra_fragment_outs = shader_spec.extra_fragment_outs.clone();
+        self.render_state = shader_spec.render_state;
+        self.stencil = shader_spec.stencil;
+        self.vertex_array_type = shader_spec.vertex_array_type;
+        self.instance_layout = shader_spec.instance_layout.clone();
 
         self.build_shader_helper(autos, renderer, resource_manager);
     }
@@ -491,53 +703,118 @@ impl Shader for ShaderGlsl {
         }
 
         let mut recompile = false;
-        for filename in self.lib_files.iter() {
-            if get_last_modification_timestamp(filename).unwrap() > self.file_mod_times[filename] {
-                recompile = true;
-            }
-        }
-        for shader_file in self.shader_files.iter() {
-            if get_last_modification_timestamp(shader_file.filename).unwrap() > self.file_mod_times[shader_file.filename] {
-                recompile = true;
+        let shader_filenames = self.shader_files.iter().map(|f| f.filename.to_string());
+        for filename in shader_filenames.chain(self.source_files.iter().cloned()) {
+            match (get_last_modification_timestamp(&filename), self.file_mod_times.get(&filename)) {
+                (Ok(modified), Some(read)) if modified > *read => recompile = true,
+                _ => (),
             }
         }
 
         if recompile {
-            println!("Recompiling {}", self.shader_name);
+            log_info!("Recompiling {}", self.shader_name);
+            let previous_program = self.program;
             self.build_shader_helper(autos, renderer, resource_manager);
+
+            // A failed build keeps the previous program or substitutes the error program
+            if self.program != previous_program && !self.using_error_program {
+                renderer.events().shader_rebuilt(self.shader_name);
+            }
         }
 
         recompile
     }
 
     /// Tell the renderer to use the shader
+    ///
+    /// Only the objects and state that differ from those last selected are changed, so selecting
+    /// the shader again for each pass costs nothing.
     fn select(&self) {
+        let mut selected = gl_selected_state().lock().unwrap();
         unsafe {
-            gl::BindVertexArray(self.vao);
-            gl::BindBuffer(gl::ARRAY_BUFFER, self.vbo);
-            gl::UseProgram(self.program as GLuint);
+            if selected.bound.bind_vertex_array(self.vao as u64) {
+                gl::BindVertexArray(self.vao);
+            }
+            if selected.bound.bind_vertex_buffers(0, &[(self.vbo as u64, 0)]) {
+                gl::BindBuffer(gl::ARRAY_BUFFER, self.vbo);
+            }
+            if selected.bound.bind_pipeline(self.program as u64) {
+                gl::UseProgram(self.program as GLuint);
+            }
         }
 
-        if self.depth_test_enabled {
-            unsafe {
-                gl::DepthFunc(gl::LESS);
+        if selected.render_state == Some((self.render_state, self.stencil)) {
+            return;
+        }
+        selected.render_state = Some((self.render_state, self.stencil));
+
+        let ref state = self.render_state;
+        match state.cull_mode {
+            CullMode::None => unsafe {
+                gl::Disable(gl::CULL_FACE);
+            },
+            CullMode::Front => unsafe {
+                gl::CullFace(gl::FRONT);
+                gl::Enable(gl::CULL_FACE);
+            },
+            CullMode::Back => unsafe {
+                gl::CullFace(gl::BACK);
+                gl::Enable(gl::CULL_FACE);
+            },
+        }
+        unsafe {
+            gl::FrontFace(match state.front_face {
+                FrontFace::CounterClockwise => gl::CCW,
+                FrontFace::Clockwise => gl::CW,
+            });
+        }
+
+        match state.depth_compare {
+            Some(compare) => unsafe {
+                gl::DepthFunc(gl_compare_func(compare));
+                gl::DepthMask(state.depth_write as GLboolean);
                 gl::Enable(gl::DEPTH_TEST);
-            }
-        } else {
-            unsafe {
+            },
+            None => unsafe {
                 gl::Disable(gl::DEPTH_TEST);
-            }
+            },
         }
 
-        if self.alpha_blending_enabled {
-            unsafe {
-                gl::BlendFunc(gl::SRC_ALPHA, gl::ONE_MINUS_SRC_ALPHA);
+        match state.blend {
+            Some(ref blend) => unsafe {
+                gl::BlendFuncSeparate(gl_blend_factor(blend.src_colour),
+                                      gl_blend_factor(blend.dst_colour),
+                                      gl_blend_factor(blend.src_alpha),
+                                      gl_blend_factor(blend.dst_alpha));
+                gl::BlendEquationSeparate(gl_blend_equation(blend.colour_op), gl_blend_equation(blend.alpha_op));
                 gl::Enable(gl::BLEND);
-            }
-        } else {
-            unsafe {
+            },
+            None => unsafe {
                 gl::Disable(gl::BLEND);
-            }
+            },
+        }
+
+        unsafe {
+            gl::ColorMask((state.colour_write_mask & COLOUR_WRITE_RED != 0) as GLboolean,
+                          (state.colour_write_mask & COLOUR_WRITE_GREEN != 0) as GLboolean,
+                          (state.colour_write_mask & COLOUR_WRITE_BLUE != 0) as GLboolean,
+                          (state.colour_write_mask & COLOUR_WRITE_ALPHA != 0) as GLboolean);
+        }
+
+        match self.stencil {
+            Some(ref stencil) => unsafe {
+                gl::StencilFunc(gl_compare_func(stencil.compare), stencil.reference as GLint, stencil.compare_mask);
+                gl::StencilOp(gl_stencil_op(stencil.fail_op),
+                              gl_stencil_op(stencil.depth_fail_op),
+                              gl_stencil_op(stencil.pass_op));
+                gl::StencilMask(stencil.write_mask);
+                gl::Enable(gl::STENCIL_TEST);
+            },
+            None => unsafe {
+                // Restore the full write mask, which also masks stencil clears
+                gl::StencilMask(!0);
+                gl::Disable(gl::STENCIL_TEST);
+            },
         }
     }
 
@@ -597,17 +874,81 @@ impl Drop for ShaderGlsl {
                 gl::DeleteBuffers(1, &self.vbo);
                 gl::DeleteVertexArrays(1, &self.vao);
             }
+            gl_memory_usage().lock().unwrap().release((gl::BUFFER, self.vbo as u64));
         }
     }
 }
 
+/// Convert a depth or stencil comparison into the OpenGL comparison function
+///
+/// compare: The comparison to convert
+fn gl_compare_func(compare: CompareOp) -> GLenum {
+    match compare {
+        CompareOp::Never => gl::NEVER,
+        CompareOp::Less => gl::LESS,
+        CompareOp::Equal => gl::EQUAL,
+        CompareOp::LessOrEqual => gl::LEQUAL,
+        CompareOp::Greater => gl::GREATER,
+        CompareOp::NotEqual => gl::NOTEQUAL,
+        CompareOp::GreaterOrEqual => gl::GEQUAL,
+        CompareOp::Always => gl::ALWAYS,
+    }
+}
+
+/// Convert a stencil operation into the OpenGL stencil operation
+///
+/// op: The operation to convert
+fn gl_stencil_op(op: StencilOp) -> GLenum {
+    match op {
+        StencilOp::Keep => gl::KEEP,
+        StencilOp::Zero => gl::ZERO,
+        StencilOp::Replace => gl::REPLACE,
+        StencilOp::IncrementAndClamp => gl::INCR,
+        StencilOp::DecrementAndClamp => gl::DECR,
+        StencilOp::Invert => gl::INVERT,
+        StencilOp::IncrementAndWrap => gl::INCR_WRAP,
+        StencilOp::DecrementAndWrap => gl::DECR_WRAP,
+    }
+}
+
+/// Convert a blend factor into the OpenGL blend factor
+///
+/// factor: The factor to convert
+fn gl_blend_factor(factor: BlendFactor) -> GLenum {
+    match factor {
+        BlendFactor::Zero => gl::ZERO,
+        BlendFactor::One => gl::ONE,
+        BlendFactor::SrcColour => gl::SRC_COLOR,
+        BlendFactor::OneMinusSrcColour => gl::ONE_MINUS_SRC_COLOR,
+        BlendFactor::DstColour => gl::DST_COLOR,
+        BlendFactor::OneMinusDstColour => gl::ONE_MINUS_DST_COLOR,
+        BlendFactor::SrcAlpha => gl::SRC_ALPHA,
+        BlendFactor::OneMinusSrcAlpha => gl::ONE_MINUS_SRC_ALPHA,
+        BlendFactor::DstAlpha => gl::DST_ALPHA,
+        BlendFactor::OneMinusDstAlpha => gl::ONE_MINUS_DST_ALPHA,
+    }
+}
+
+/// Convert a blend operation into the OpenGL blend equation
+///
+/// op: The operation to convert
+fn gl_blend_equation(op: BlendOp) -> GLenum {
+    match op {
+        BlendOp::Add => gl::FUNC_ADD,
+        BlendOp::Subtract => gl::FUNC_SUBTRACT,
+        BlendOp::ReverseSubtract => gl::FUNC_REVERSE_SUBTRACT,
+        BlendOp::Min => gl::MIN,
+        BlendOp::Max => gl::MAX,
+    }
+}
+
 /// Compile the GLSL passed in as a string
 ///
 /// Based on the C code at:
 /// https://www.opengl.org/wiki/Shader_Compilation
 ///
 /// name: The name of the shader
-/// source_names: The names of the source files
+/// source_names: The names of the source files, indexed by the source string numbers of the #line directives
 /// glsl: The source to compile
 /// shadertype: The type of shader being compiled
 /// old_driver: true to avoid GL_KHR_vulkan_glsl
@@ -640,11 +981,11 @@ fn compile_glsl(name: &str, source_names: &Vec<String>, glsl: &str, shader_stage
         gl::GetShaderiv(shader, gl::INFO_LOG_LENGTH, &mut max_length);
 
         if successful == (gl::FALSE as GLint) {
-            println!("Failed to compile {} shader for {}",
-                     shader_stage_name(shader_stage),
-                     name);
-            for source in source_names {
-                println!("The source includes: {}", source);
+            log_error!("Failed to compile {} shader for {}",
+                       shader_stage_name(shader_stage),
+                       name);
+            for (number, source) in source_names.iter().enumerate() {
+                log_error!("Source string {} is {}", number, source);
             }
         }
 
@@ -658,7 +999,7 @@ fn compile_glsl(name: &str, source_names: &Vec<String>, glsl: &str, shader_stage
                                  ptr::null_mut(),
                                  info_log.as_mut_ptr() as *mut GLchar);
 
-            println!("Compilation log:\n{}", str::from_utf8(&info_log).unwrap());
+            log_error!("Compilation log:\n{}", str::from_utf8(&info_log).unwrap());
         }
 
         if successful == (gl::FALSE as GLint) {
@@ -694,7 +1035,7 @@ fn link_program(name: &str, shaders: &Vec<GLuint>) -> GLint {
         gl::GetProgramiv(program, gl::INFO_LOG_LENGTH, &mut max_length);
 
         if successful == (gl::FALSE as GLint) {
-            println!("Failed to link shader {}", name);
+            log_error!("Failed to link shader {}", name);
         }
 
         if max_length > 1 {
@@ -707,7 +1048,7 @@ fn link_program(name: &str, shaders: &Vec<GLuint>) -> GLint {
                                   ptr::null_mut(),
                                   info_log.as_mut_ptr() as *mut GLchar);
 
-            println!("Link log:\n{}", str::from_utf8(&info_log).unwrap());
+            log_error!("Link log:\n{}", str::from_utf8(&info_log).unwrap());
 
             if successful == (gl::FALSE as GLint) {
                 return -1 as GLint;
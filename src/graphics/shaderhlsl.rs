@@ -0,0 +1,180 @@
+// Copyright (c) 2016-2017 Bruce Stenning. All rights reserved.
+//
+// Redistribution and use in source and binary forms, with or without
+// modification, are permitted provided that the following conditions
+// are met:
+//
+// 1. Redistributions of source code must retain the above copyright
+//    notice, this list of conditions and the following disclaimer.
+//
+// 2. Redistributions in binary form must reproduce the above copyright
+//    notice, this list of conditions and the following disclaimer in the
+//    documentation and/or other materials provided with the distribution.
+//
+// 3. Neither the name of the copyright holder nor the names of its
+//    contributors may be used to endorse or promote products derived
+//    from this software without specific prior written permission.
+//
+// THIS SOFTWARE IS PROVIDED BY THE COPYRIGHT HOLDERS AND CONTRIBUTORS
+// AS IS" AND ANY EXPRESS OR IMPLIED WARRANTIES, INCLUDING, BUT NOT
+// LIMITED TO, THE IMPLIED WARRANTIES OF MERCHANTABILITY AND FITNESS
+// FOR A PARTICULAR PURPOSE ARE DISCLAIMED. IN NO EVENT SHALL THE
+// COPYRIGHT HOLDER OR CONTRIBUTORS BE LIABLE FOR ANY DIRECT, INDIRECT,
+// INCIDENTAL, SPECIAL, EXEMPLARY, OR CONSEQUENTIAL DAMAGES (INCLUDING,
+// BUT NOT LIMITED TO, PROCUREMENT OF SUBSTITUTE GOODS OR SERVICES; LOSS
+// OF USE, DATA, OR PROFITS; OR BUSINESS INTERRUPTION) HOWEVER CAUSED
+// AND ON ANY THEORY OF LIABILITY, WHETHER IN CONTRACT, STRICT LIABILITY,
+// OR TORT (INCLUDING NEGLIGENCE OR OTHERWISE) ARISING IN ANY WAY OUT OF
+// THE USE OF THIS SOFTWARE, EVEN IF ADVISED OF THE POSSIBILITY OF SUCH
+// DAMAGE.
+
+use std::process::Command;
+use std::fs::remove_file;
+
+use graphics::shader::*;
+use graphics::shaderspirv::*;
+
+/// Return the DXC target profile for a shader stage
+///
+/// shader_stage: The shader stage to return the profile for
+///
+/// Returns: The shader model 6.0 profile name for the stage
+pub fn hlsl_profile(shader_stage: ShaderStage) -> &'static str {
+    match shader_stage {
+        ShaderStage::VertexShader => "vs_6_0",
+        ShaderStage::TessControlShader => "hs_6_0",
+        ShaderStage::TessEvalShader => "ds_6_0",
+        ShaderStage::GeometryShader => "gs_6_0",
+        ShaderStage::FragmentShader => "ps_6_0",
+        ShaderStage::ComputeShader => "cs_6_0",
+    }
+}
+
+/// Compile an HLSL source file to SPIR-V with DXC
+///
+/// filename: The HLSL source file
+/// shader_stage: The stage to compile the entry point for
+/// entry_point: The name of the entry point function
+/// spirv_out: The file to write the SPIR-V to
+/// debug_output_level: Debug output level (0 = silent, 1 = minimal, 2 = full)
+///
+/// Returns: true if the compilation succeeded
+pub fn compile_hlsl_to_spirv(filename: &str,
+                             shader_stage: ShaderStage,
+                             entry_point: &str,
+                             spirv_out: &str,
+                             debug_output_level: u32)
+                             -> bool {
+    let mut command = Command::new("dxc");
+    command.arg("-spirv") // SPIR-V output
+            .arg("-fspv-target-env=vulkan1.0")
+            .arg("-T") // Target profile
+            .arg(hlsl_profile(shader_stage))
+            .arg("-E") // Entry point
+            .arg(entry_point)
+            .arg("-Fo") // Specify output file
+            .arg(spirv_out)
+            .arg(filename);
+
+    if debug_output_level > 1 {
+        log_debug!("Running dxc for the {} entry point of {}", entry_point, filename);
+    }
+    let output = command.output().expect("Failed to invoke HLSL to SPIR-V compiler");
+    if !output.status.success() {
+        log_error!("Failed to compile the {} entry point of {} ({}): {}{}",
+                   entry_point,
+                   filename,
+                   output.status,
+                   String::from_utf8_lossy(&output.stdout),
+                   String::from_utf8_lossy(&output.stderr));
+    } else if debug_output_level > 1 {
+        log_debug!("dxc stdout: {}", String::from_utf8_lossy(&output.stdout));
+        log_debug!("dxc stderr: {}", String::from_utf8_lossy(&output.stderr));
+    }
+
+    output.status.success()
+}
+
+/// Generate the reflection data for an HLSL source file
+///
+/// DXC has no equivalent of the reflection output of glslangValidator that the resource
+/// manager reads, so the HLSL front end of glslangValidator is used for this.
+///
+/// filename: The HLSL source file
+/// shader_stage: The stage of the entry point
+/// entry_point: The name of the entry point function
+///
+/// Returns: The reflection data, or None if the source could not be parsed
+pub fn reflect_hlsl(filename: &str, shader_stage: ShaderStage, entry_point: &str) -> Option<String> {
+    let temporary_spirv = format!("{}.reflect.spv", filename);
+    let output = Command::new("glslangValidator")
+        .arg("-D") // HLSL input
+        .arg("-V") // SPIR-V output with Vulkan semantics
+        .arg("-q") // Build reflection data
+        .arg("-S") // Stage
+        .arg(ShaderSpirv::shader_extension_name(shader_stage))
+        .arg("-e") // Entry point
+        .arg(entry_point)
+        .arg("-o")
+        .arg(&temporary_spirv)
+        .arg(filename)
+        .output()
+        .expect("Failed to invoke glslangValidator");
+
+    let _ = remove_file(&temporary_spirv);
+
+    if !output.status.success() {
+        log_error!("Failed to reflect the {} entry point of {}: {}{}",
+                   entry_point,
+                   filename,
+                   String::from_utf8_lossy(&output.stdout),
+                   String::from_utf8_lossy(&output.stderr));
+        return None;
+    }
+
+    Some(String::from_utf8_lossy(&output.stdout).to_string())
+}
+
+/// Translate an HLSL source file to GLSL for the OpenGL renderer
+///
+/// The source is compiled to SPIR-V with DXC and then decompiled with SPIRV-Cross.  The
+/// #version directive is removed, as the GLSL shader adds its own.  The entry point of the
+/// translated source is always main.
+///
+/// filename: The HLSL source file
+/// shader_stage: The stage to translate the entry point for
+/// entry_point: The name of the entry point function
+///
+/// Returns: The GLSL source, or None if the translation failed
+pub fn translate_hlsl_to_glsl(filename: &str, shader_stage: ShaderStage, entry_point: &str) -> Option<String> {
+    let temporary_spirv = format!("{}.{}.spv",
+                                  filename,
+                                  ShaderSpirv::shader_extension_name(shader_stage));
+    if !compile_hlsl_to_spirv(filename, shader_stage, entry_point, &temporary_spirv, 0) {
+        let _ = remove_file(&temporary_spirv);
+        return None;
+    }
+
+    let output = Command::new("spirv-cross")
+        .arg("--version")
+        .arg("450")
+        .arg("--no-es")
+        .arg(&temporary_spirv)
+        .output()
+        .expect("Failed to invoke SPIR-V to GLSL translator");
+
+    remove_file(&temporary_spirv).expect("Failed to remove temporary file");
+
+    if !output.status.success() {
+        log_error!("Failed to translate {} to GLSL: {}", filename, String::from_utf8_lossy(&output.stderr));
+        return None;
+    }
+
+    let glsl = String::from_utf8_lossy(&output.stdout)
+        .lines()
+        .filter(|line| !line.starts_with("#version"))
+        .collect::<Vec<&str>>()
+        .join("\n");
+
+    Some(glsl)
+}
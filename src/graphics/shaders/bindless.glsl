@@ -0,0 +1,37 @@
+#extension GL_EXT_nonuniform_qualifier : require
+
+// The bindless texture array and the material records that index it
+//
+// The set, sizes and layout must match BINDLESS_TEXTURE_SET, MATERIAL_MAX_RECORDS and
+// MaterialRecord in bindless.rs.
+
+#define BINDLESS_NO_TEXTURE 0xffffffffu
+#define MATERIAL_MAX_RECORDS 1024
+
+layout(set = 2, binding = 0) uniform sampler2D bindless_textures[];
+
+struct MaterialRecord {
+    uint albedo_texture;
+    uint normal_texture;
+    uint metallic_roughness_texture;
+    uint emissive_texture;
+    vec4 base_colour;
+    float metallic;
+    float roughness;
+    float normal_scale;
+    float emissive_strength;
+};
+
+layout(std430, set = 0, binding = 8) readonly buffer material_buffer {
+    MaterialRecord material_records[MATERIAL_MAX_RECORDS];
+};
+
+// Sample a texture of a material record, returning the fallback when the record has none
+//
+// The index may differ between invocations, so must be marked as non-uniform.
+vec4 bindless_texture(uint index, vec2 uv, vec4 fallback) {
+    if (index == BINDLESS_NO_TEXTURE) {
+        return fallback;
+    }
+    return texture(bindless_textures[nonuniformEXT(index)], uv);
+}
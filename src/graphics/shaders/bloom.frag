@@ -0,0 +1,36 @@
+// Add a blurred copy of the parts of the scene brighter than white, in a single pass
+
+layout(location = 0) in vec2 post_effect_uv;
+
+layout(location = 0) out vec4 post_effect_colour;
+
+layout(set = 1, binding = 0) uniform sampler2D post_effect_source_texture;
+
+layout(push_constant) uniform post_effect_push_constants {
+    float post_effect_strength;
+};
+
+const float threshold = 1.0;
+const float radius = 8.0;
+
+void main() {
+    vec4 colour = texture(post_effect_source_texture, post_effect_uv);
+    vec2 texel = 1.0 / vec2(textureSize(post_effect_source_texture, 0));
+
+    // Rings of taps at growing distances, weighted by a Gaussian of the distance
+    vec3 bloom = vec3(0.0);
+    float total = 0.0;
+    for (int ring = 1; ring <= 4; ring++) {
+        float distance = radius * float(ring) / 4.0;
+        float weight = exp(-2.0 * float(ring * ring) / 16.0);
+        for (int i = 0; i < 8; i++) {
+            float angle = (float(i) + 0.5 * float(ring)) * 0.785398;
+            vec2 uv = post_effect_uv + vec2(cos(angle), sin(angle)) * distance * texel;
+            vec3 tap = texture(post_effect_source_texture, uv).rgb;
+            bloom += max(tap - threshold, vec3(0.0)) * weight;
+            total += weight;
+        }
+    }
+
+    post_effect_colour = vec4(colour.rgb + bloom / total * post_effect_strength, colour.a);
+}
@@ -0,0 +1,54 @@
+// Checkerboard resolve: the columns rendered this frame come straight from the half-width image,
+// and the others are reprojected from the previous resolve along the motion vectors
+
+layout(location = 0) out vec4 checkerboard_colour;
+
+layout(set = 0, binding = 0) uniform checkerboard_settings {
+    vec4 checkerboard_viewport;
+    int checkerboard_parity;
+    int checkerboard_history_valid;
+};
+
+layout(set = 1, binding = 0) uniform sampler2D checkerboard_current_texture;
+layout(set = 1, binding = 1) uniform sampler2D checkerboard_history_texture;
+layout(set = 1, binding = 2) uniform sampler2D checkerboard_velocity_texture;
+
+vec4 fetch_current(ivec2 texel) {
+    ivec2 size = textureSize(checkerboard_current_texture, 0);
+    return texelFetch(checkerboard_current_texture, clamp(texel, ivec2(0), size - 1), 0);
+}
+
+void main() {
+    ivec2 pixel = ivec2(gl_FragCoord.xy);
+    vec2 uv = gl_FragCoord.xy / checkerboard_viewport.xy;
+
+    // Texel x of the half-width image covers output column 2x + parity this frame
+    int column = pixel.x - checkerboard_parity;
+    if ((column & 1) == 0) {
+        checkerboard_colour = fetch_current(ivec2(column >> 1, pixel.y));
+        return;
+    }
+
+    // The columns either side were rendered this frame, and bound the reprojected history so that
+    // disocclusions and lighting changes do not leave stale colour behind
+    int left = (column - 1) >> 1;
+    vec4 lowest = vec4(1.0e30);
+    vec4 highest = vec4(-1.0e30);
+    for (int dy = -1; dy <= 1; dy++) {
+        for (int dx = 0; dx <= 1; dx++) {
+            vec4 neighbour = fetch_current(ivec2(left + dx, pixel.y + dy));
+            lowest = min(lowest, neighbour);
+            highest = max(highest, neighbour);
+        }
+    }
+    vec4 spatial = 0.5 * (fetch_current(ivec2(left, pixel.y)) + fetch_current(ivec2(left + 1, pixel.y)));
+
+    vec2 previous_uv = uv - texture(checkerboard_velocity_texture, uv).xy;
+    bool on_screen = all(greaterThanEqual(previous_uv, vec2(0.0))) && all(lessThanEqual(previous_uv, vec2(1.0)));
+    if (checkerboard_history_valid == 0 || !on_screen) {
+        checkerboard_colour = spatial;
+        return;
+    }
+
+    checkerboard_colour = clamp(texture(checkerboard_history_texture, previous_uv), lowest, highest);
+}
@@ -0,0 +1,72 @@
+// Box decals projected onto the scene through the depth buffer
+//
+// Each fragment of the box reconstructs the scene position behind it, and is kept only if that
+// position lies inside the box.  The decal is projected down the local y axis.  Only the faces
+// facing away from the viewer are shaded, so that each pixel is blended once whether or not the
+// camera is inside the box.
+
+layout(location = 0) out vec4 decal_colour;
+
+layout(set = 0, binding = 0) uniform decal_camera {
+    mat4 decal_view_projection;
+    mat4 decal_inverse_view_projection;
+    vec4 decal_viewport;
+    vec4 decal_light_direction;
+};
+
+layout(push_constant) uniform decal_instance {
+    mat4 decal_inverse_model;
+    float decal_atlas_u;
+    float decal_atlas_v;
+    float decal_atlas_width;
+    float decal_atlas_height;
+    float decal_albedo_opacity;
+    float decal_normal_strength;
+};
+
+layout(set = 1, binding = 0) uniform sampler2D decal_depth_texture;
+layout(set = 1, binding = 1) uniform sampler2D decal_albedo_atlas;
+layout(set = 1, binding = 2) uniform sampler2D decal_normal_atlas;
+
+void main() {
+#ifdef VULKAN
+    bool front_facing = !gl_FrontFacing;
+#else
+    bool front_facing = gl_FrontFacing;
+#endif
+    if (front_facing) {
+        discard;
+    }
+
+    vec2 screen_uv = gl_FragCoord.xy / decal_viewport.xy;
+    float depth = texture(decal_depth_texture, screen_uv).r;
+#ifdef VULKAN
+    vec4 ndc = vec4(screen_uv * 2.0 - 1.0, depth, 1.0);
+#else
+    vec4 ndc = vec4(screen_uv * 2.0 - 1.0, depth * 2.0 - 1.0, 1.0);
+#endif
+    vec4 world = decal_inverse_view_projection * ndc;
+    world /= world.w;
+
+    vec3 local = (decal_inverse_model * world).xyz;
+    if (any(greaterThan(abs(local), vec3(0.5)))) {
+        discard;
+    }
+
+    vec2 atlas_uv = vec2(decal_atlas_u, decal_atlas_v) + (local.xz + 0.5) * vec2(decal_atlas_width, decal_atlas_height);
+    vec4 albedo = texture(decal_albedo_atlas, atlas_uv);
+
+    // The scene normals are not available to a forward pass, so the normal map is applied by
+    // relighting the decal relative to the normal reconstructed from the depth buffer
+    vec3 geometric_normal = normalize(cross(dFdx(world.xyz), dFdy(world.xyz)));
+    vec3 tangent_normal = texture(decal_normal_atlas, atlas_uv).xyz * 2.0 - 1.0;
+    mat3 decal_to_world = transpose(mat3(decal_inverse_model));
+    vec3 tangent = normalize(decal_to_world[0]);
+    vec3 bitangent = normalize(decal_to_world[2]);
+    vec3 normal = normalize(tangent * tangent_normal.x + bitangent * tangent_normal.y + geometric_normal * tangent_normal.z);
+
+    vec3 l = -normalize(decal_light_direction.xyz);
+    float relight = max(dot(normal, l), 0.0) / max(dot(geometric_normal, l), 0.05);
+
+    decal_colour = vec4(albedo.rgb * mix(1.0, relight, decal_normal_strength), albedo.a * decal_albedo_opacity);
+}
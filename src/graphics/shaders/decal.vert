@@ -0,0 +1,14 @@
+// Decal boxes are submitted already transformed to world space
+
+layout(location = 0) in vec3 position;
+
+layout(set = 0, binding = 0) uniform decal_camera {
+    mat4 decal_view_projection;
+    mat4 decal_inverse_view_projection;
+    vec4 decal_viewport;
+    vec4 decal_light_direction;
+};
+
+void main() {
+    gl_Position = decal_view_projection * vec4(position, 1.0);
+}
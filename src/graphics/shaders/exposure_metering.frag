@@ -0,0 +1,30 @@
+// Downsample the HDR scene to log2 luminance for metering the exposure
+
+layout(location = 0) in vec2 metering_uv;
+
+layout(location = 0) out vec4 exposure_log_luminance;
+
+layout(set = 1, binding = 0) uniform sampler2D exposure_source_texture;
+
+// These match EXPOSURE_MIN_LOG_LUMINANCE, EXPOSURE_MAX_LOG_LUMINANCE and EXPOSURE_METERING_SIZE
+const float min_log_luminance = -12.0;
+const float max_log_luminance = 12.0;
+const float metering_size = 64.0;
+
+void main() {
+    // Average the log luminance over a grid of taps covering this pixel's part of the scene,
+    // which is the log of the geometric mean luminance
+    float log_luminance = 0.0;
+    for (int y = 0; y < 4; y++) {
+        for (int x = 0; x < 4; x++) {
+            vec2 offset = (vec2(x, y) - 1.5) / (4.0 * metering_size);
+            vec3 colour = texture(exposure_source_texture, metering_uv + offset).rgb;
+            float luminance = dot(colour, vec3(0.2126, 0.7152, 0.0722));
+            log_luminance += log2(max(luminance, 1.0e-6));
+        }
+    }
+    log_luminance /= 16.0;
+
+    float scaled = clamp((log_luminance - min_log_luminance) / (max_log_luminance - min_log_luminance), 0.0, 1.0);
+    exposure_log_luminance = vec4(scaled, scaled, scaled, 1.0);
+}
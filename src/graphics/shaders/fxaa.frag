@@ -0,0 +1,54 @@
+// Smooth aliased edges along the direction of the luminance gradient, after FXAA 3.11
+
+layout(location = 0) in vec2 post_effect_uv;
+
+layout(location = 0) out vec4 post_effect_colour;
+
+layout(set = 1, binding = 0) uniform sampler2D post_effect_source_texture;
+
+layout(push_constant) uniform post_effect_push_constants {
+    float post_effect_strength;
+};
+
+const float edge_threshold = 0.125;
+const float edge_threshold_min = 0.0312;
+const float span_max = 8.0;
+
+float luma(vec3 colour) {
+    return dot(colour, vec3(0.299, 0.587, 0.114));
+}
+
+void main() {
+    vec2 texel = 1.0 / vec2(textureSize(post_effect_source_texture, 0));
+    vec4 centre = texture(post_effect_source_texture, post_effect_uv);
+
+    float luma_m = luma(centre.rgb);
+    float luma_nw = luma(texture(post_effect_source_texture, post_effect_uv + vec2(-1.0, -1.0) * texel).rgb);
+    float luma_ne = luma(texture(post_effect_source_texture, post_effect_uv + vec2(1.0, -1.0) * texel).rgb);
+    float luma_sw = luma(texture(post_effect_source_texture, post_effect_uv + vec2(-1.0, 1.0) * texel).rgb);
+    float luma_se = luma(texture(post_effect_source_texture, post_effect_uv + vec2(1.0, 1.0) * texel).rgb);
+
+    float luma_min = min(luma_m, min(min(luma_nw, luma_ne), min(luma_sw, luma_se)));
+    float luma_max = max(luma_m, max(max(luma_nw, luma_ne), max(luma_sw, luma_se)));
+
+    // Leave pixels away from edges, which are most of the frame, as they are
+    if (luma_max - luma_min < max(edge_threshold_min, luma_max * edge_threshold)) {
+        post_effect_colour = centre;
+        return;
+    }
+
+    vec2 direction = vec2(-((luma_nw + luma_ne) - (luma_sw + luma_se)), (luma_nw + luma_sw) - (luma_ne + luma_se));
+    float reduce = max((luma_nw + luma_ne + luma_sw + luma_se) * 0.03125, 1.0 / 128.0);
+    float scale = 1.0 / (min(abs(direction.x), abs(direction.y)) + reduce);
+    direction = clamp(direction * scale, vec2(-span_max), vec2(span_max)) * texel;
+
+    vec3 near = 0.5 * (texture(post_effect_source_texture, post_effect_uv - direction / 6.0).rgb +
+                       texture(post_effect_source_texture, post_effect_uv + direction / 6.0).rgb);
+    vec3 far = 0.5 * near + 0.25 * (texture(post_effect_source_texture, post_effect_uv - direction / 2.0).rgb +
+                                    texture(post_effect_source_texture, post_effect_uv + direction / 2.0).rgb);
+
+    // Fall back to the narrower blend where the wider one reaches past the local range
+    float luma_far = luma(far);
+    vec3 smoothed = (luma_far < luma_min || luma_far > luma_max) ? near : far;
+    post_effect_colour = vec4(mix(centre.rgb, smoothed, clamp(post_effect_strength, 0.0, 1.0)), centre.a);
+}
@@ -0,0 +1,13 @@
+// Glyphs tinted by their record's colour, with the coverage taken from the atlas's red channel
+
+layout(location = 0) in vec2 glyph_texcoord;
+layout(location = 1) in vec4 glyph_colour;
+
+layout(location = 0) out vec4 gpu_text_colour;
+
+layout(set = 1, binding = 0) uniform sampler2D gpu_text_atlas;
+
+void main() {
+    float coverage = texture(gpu_text_atlas, glyph_texcoord).r;
+    gpu_text_colour = vec4(glyph_colour.rgb, glyph_colour.a * coverage);
+}
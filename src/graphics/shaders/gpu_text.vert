@@ -0,0 +1,44 @@
+// Glyph quads expanded from the glyph records, six vertices for each instance
+//
+// The size and layout must match GPU_TEXT_MAX_GLYPHS and GlyphInstance in gputext.rs.
+
+#define GPU_TEXT_MAX_GLYPHS 262144
+
+struct GlyphInstance {
+    vec2 position; // The top-left corner in pixels
+    uint glyph; // The cell of the glyph in the atlas grid
+    uint colour; // RGBA8, with red in the lowest byte
+};
+
+layout(std430, set = 0, binding = 0) readonly buffer glyph_buffer {
+    GlyphInstance glyph_instances[GPU_TEXT_MAX_GLYPHS];
+};
+
+layout(set = 0, binding = 1) uniform gpu_text_settings {
+    vec4 text_viewport; // Width and height in pixels, and the sign of clip space y down the screen
+    vec4 text_glyph_grid; // Width and height of a glyph in pixels, and the columns and rows of the atlas
+};
+
+layout(location = 0) out vec2 glyph_texcoord;
+layout(location = 1) out vec4 glyph_colour;
+
+const vec2 glyph_corners[6] = vec2[](vec2(0.0, 0.0),
+                                     vec2(1.0, 0.0),
+                                     vec2(1.0, 1.0),
+                                     vec2(0.0, 0.0),
+                                     vec2(1.0, 1.0),
+                                     vec2(0.0, 1.0));
+
+void main() {
+    GlyphInstance instance = glyph_instances[gl_InstanceIndex];
+    vec2 corner = glyph_corners[gl_VertexIndex];
+
+    vec2 pixel = instance.position + corner * text_glyph_grid.xy;
+    vec2 clip = pixel / text_viewport.xy * 2.0 - 1.0;
+    gl_Position = vec4(clip.x, clip.y * text_viewport.z, 0.0, 1.0);
+
+    uint columns = uint(text_glyph_grid.z);
+    vec2 cell = vec2(float(instance.glyph % columns), float(instance.glyph / columns));
+    glyph_texcoord = (cell + corner) / text_glyph_grid.zw;
+    glyph_colour = unpackUnorm4x8(instance.colour);
+}
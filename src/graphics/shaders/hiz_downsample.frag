@@ -0,0 +1,38 @@
+// Reduce the texels of the previous Hi-Z level that this texel covers, or copy the depth buffer
+
+layout(location = 0) out vec4 hiz_depth;
+
+layout(set = 1, binding = 0) uniform sampler2D hiz_source_texture;
+
+layout(push_constant) uniform hiz_push_constants {
+    int hiz_source_width;
+    int hiz_source_height;
+    int hiz_target_width;
+    int hiz_target_height;
+    int hiz_reduction;
+};
+
+// The source texels covered along an axis, as hiz_footprint
+ivec2 hiz_footprint(int texel, int target_size, int source_size) {
+    int first = texel * source_size / target_size;
+    int end = ((texel + 1) * source_size + target_size - 1) / target_size;
+    return ivec2(first, min(max(end, first + 1), source_size));
+}
+
+void main() {
+    ivec2 texel = ivec2(gl_FragCoord.xy);
+    ivec2 footprint_x = hiz_footprint(texel.x, hiz_target_width, hiz_source_width);
+    ivec2 footprint_y = hiz_footprint(texel.y, hiz_target_height, hiz_source_height);
+
+    // A level at most halves the one before, so a texel covers at most three source texels
+    // along each axis
+    float depth = texelFetch(hiz_source_texture, ivec2(footprint_x.x, footprint_y.x), 0).r;
+    for (int y = footprint_y.x; y < footprint_y.y; y++) {
+        for (int x = footprint_x.x; x < footprint_x.y; x++) {
+            float source = texelFetch(hiz_source_texture, ivec2(x, y), 0).r;
+            depth = hiz_reduction == 0 ? min(depth, source) : max(depth, source);
+        }
+    }
+
+    hiz_depth = vec4(depth, depth, depth, 1.0);
+}
@@ -0,0 +1,26 @@
+// Blending of the object transforms published by the renderer's transform interpolator
+
+// Return the rotation matrix of a unit quaternion
+mat3 interpolation_rotation(vec4 q) {
+    return mat3(1.0 - 2.0 * (q.y * q.y + q.z * q.z), 2.0 * (q.x * q.y + q.z * q.w), 2.0 * (q.x * q.z - q.y * q.w),
+                2.0 * (q.x * q.y - q.z * q.w), 1.0 - 2.0 * (q.x * q.x + q.z * q.z), 2.0 * (q.y * q.z + q.x * q.w),
+                2.0 * (q.x * q.z + q.y * q.w), 2.0 * (q.y * q.z - q.x * q.w), 1.0 - 2.0 * (q.x * q.x + q.y * q.y));
+}
+
+// Return the model matrix blended between an object's previous and current transforms
+//
+// The transforms are the six vec4s published for the object: the previous position, rotation
+// and scale followed by the current ones.  Rotations take the shorter way round.
+mat4 interpolated_model_matrix(vec4 previous_position, vec4 previous_rotation, vec4 previous_scale,
+                               vec4 current_position, vec4 current_rotation, vec4 current_scale, float alpha) {
+    vec4 target_rotation = dot(previous_rotation, current_rotation) < 0.0 ? -current_rotation : current_rotation;
+    vec4 rotation = normalize(mix(previous_rotation, target_rotation, alpha));
+    vec3 position = mix(previous_position.xyz, current_position.xyz, alpha);
+    vec3 scale = mix(previous_scale.xyz, current_scale.xyz, alpha);
+
+    mat3 basis = interpolation_rotation(rotation);
+    return mat4(vec4(basis[0] * scale.x, 0.0),
+                vec4(basis[1] * scale.y, 0.0),
+                vec4(basis[2] * scale.z, 0.0),
+                vec4(position, 1.0));
+}
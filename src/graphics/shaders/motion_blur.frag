@@ -0,0 +1,32 @@
+// Motion blur along the per-pixel velocity, with as many samples as the blur spans pixels
+
+layout(location = 0) out vec4 motion_blur_colour;
+
+layout(set = 0, binding = 0) uniform motion_blur_settings {
+    vec4 motion_blur_viewport;
+    float motion_blur_intensity;
+    int motion_blur_max_samples;
+};
+
+layout(set = 1, binding = 0) uniform sampler2D motion_blur_colour_texture;
+layout(set = 1, binding = 1) uniform sampler2D motion_blur_velocity_texture;
+
+void main() {
+    vec2 uv = gl_FragCoord.xy / motion_blur_viewport.xy;
+    vec2 velocity = texture(motion_blur_velocity_texture, uv).xy * motion_blur_intensity;
+
+    // Still pixels, which are the majority in most frames, take a single sample
+    int samples = clamp(int(ceil(length(velocity * motion_blur_viewport.xy))), 1, motion_blur_max_samples);
+    if (samples == 1) {
+        motion_blur_colour = texture(motion_blur_colour_texture, uv);
+        return;
+    }
+
+    vec4 sum = vec4(0.0);
+    for (int i = 0; i < samples; i++) {
+        float t = float(i) / float(samples - 1) - 0.5;
+        sum += texture(motion_blur_colour_texture, uv + velocity * t);
+    }
+
+    motion_blur_colour = sum / float(samples);
+}
@@ -0,0 +1,21 @@
+// Solid rectangles in the colour of their run, or textured quads tinted by it
+
+layout(location = 0) in vec2 overlay_texcoord;
+
+layout(location = 0) out vec4 overlay_colour;
+
+layout(push_constant) uniform overlay_run {
+    mat4 overlay_projection;
+    vec3 overlay_tint;
+    float overlay_opacity;
+    int overlay_textured;
+};
+
+layout(set = 1, binding = 0) uniform sampler2D overlay_texture;
+
+void main() {
+    overlay_colour = vec4(overlay_tint, overlay_opacity);
+    if (overlay_textured != 0) {
+        overlay_colour *= texture(overlay_texture, overlay_texcoord);
+    }
+}
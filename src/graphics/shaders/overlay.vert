@@ -0,0 +1,18 @@
+// Overlay quads in pixels, projected orthographically with the origin at the top left
+
+layout(location = 0) in vec2 position;
+layout(location = 1) in vec2 texcoord;
+
+layout(push_constant) uniform overlay_run {
+    mat4 overlay_projection;
+    vec3 overlay_tint;
+    float overlay_opacity;
+    int overlay_textured;
+};
+
+layout(location = 0) out vec2 overlay_texcoord;
+
+void main() {
+    gl_Position = overlay_projection * vec4(position, 0.0, 1.0);
+    overlay_texcoord = texcoord;
+}
@@ -0,0 +1,183 @@
+// Metallic-roughness shading following the glTF 2.0 specification, with a GGX distribution,
+// Smith height-correlated visibility and Schlick Fresnel
+
+layout(location = 0) in vec3 world_position;
+layout(location = 1) in vec3 world_normal;
+layout(location = 2) in vec2 uv;
+
+layout(location = 0) out vec4 pbr_colour;
+
+layout(set = 1, binding = 0) uniform sampler2D pbr_base_colour_texture;
+layout(set = 1, binding = 1) uniform sampler2D pbr_metallic_roughness_texture;
+layout(set = 1, binding = 2) uniform sampler2D pbr_normal_texture;
+layout(set = 1, binding = 3) uniform sampler2D pbr_occlusion_texture;
+layout(set = 1, binding = 4) uniform sampler2D pbr_emissive_texture;
+
+// Image based lighting hooks: equirectangular irradiance and prefiltered radiance maps, and
+// the split-sum BRDF lookup table.  Setting pbr_ibl_intensity to zero disables them.
+layout(set = 1, binding = 5) uniform sampler2D pbr_irradiance_map;
+layout(set = 1, binding = 6) uniform sampler2D pbr_prefiltered_map;
+layout(set = 1, binding = 7) uniform sampler2D pbr_brdf_lut;
+
+// Short-range shadows marched through a depth of the scene drawn before the lighting, such as
+// level 0 of the Hi-Z pyramid, towards the lights whose bits are set in the light mask
+layout(set = 0, binding = 3) uniform pbr_contact_shadows {
+    float pbr_contact_shadow_length;
+    float pbr_contact_shadow_thickness;
+    float pbr_contact_shadow_intensity;
+    int pbr_contact_shadow_steps;
+    int pbr_contact_shadow_light_mask;
+};
+
+layout(set = 1, binding = 8) uniform sampler2D pbr_contact_shadow_depth;
+
+const float PI = 3.14159265359;
+const float PREFILTERED_MIP_LEVELS = 5.0;
+
+// Build the tangent frame from screen space derivatives, as the vertex layout has no tangents
+mat3 cotangent_frame(vec3 n, vec3 p, vec2 tc) {
+    vec3 dp1 = dFdx(p);
+    vec3 dp2 = dFdy(p);
+    vec2 duv1 = dFdx(tc);
+    vec2 duv2 = dFdy(tc);
+
+    vec3 dp2perp = cross(dp2, n);
+    vec3 dp1perp = cross(n, dp1);
+    vec3 t = dp2perp * duv1.x + dp1perp * duv2.x;
+    vec3 b = dp2perp * duv1.y + dp1perp * duv2.y;
+
+    float invmax = inversesqrt(max(dot(t, t), dot(b, b)));
+    return mat3(t * invmax, b * invmax, n);
+}
+
+vec2 equirectangular_uv(vec3 direction) {
+    return vec2(atan(direction.z, direction.x) / (2.0 * PI) + 0.5, acos(clamp(direction.y, -1.0, 1.0)) / PI);
+}
+
+float distribution_ggx(float n_dot_h, float alpha) {
+    float alpha2 = alpha * alpha;
+    float d = n_dot_h * n_dot_h * (alpha2 - 1.0) + 1.0;
+    return alpha2 / (PI * d * d);
+}
+
+float visibility_smith_ggx(float n_dot_l, float n_dot_v, float alpha) {
+    float alpha2 = alpha * alpha;
+    float ggxv = n_dot_l * sqrt(n_dot_v * n_dot_v * (1.0 - alpha2) + alpha2);
+    float ggxl = n_dot_v * sqrt(n_dot_l * n_dot_l * (1.0 - alpha2) + alpha2);
+    float ggx = ggxv + ggxl;
+    return ggx > 0.0 ? 0.5 / ggx : 0.0;
+}
+
+vec3 fresnel_schlick(vec3 f0, float v_dot_h) {
+    return f0 + (vec3(1.0) - f0) * pow(clamp(1.0 - v_dot_h, 0.0, 1.0), 5.0);
+}
+
+vec3 ibl_contribution(vec3 n, vec3 v, vec3 diffuse_colour, vec3 f0, float roughness) {
+    float n_dot_v = clamp(dot(n, v), 0.0, 1.0);
+    vec3 r = reflect(-v, n);
+
+    vec3 irradiance = texture(pbr_irradiance_map, equirectangular_uv(n)).rgb;
+    vec3 radiance = textureLod(pbr_prefiltered_map, equirectangular_uv(r), roughness * PREFILTERED_MIP_LEVELS).rgb;
+    vec2 brdf = texture(pbr_brdf_lut, vec2(n_dot_v, roughness)).rg;
+
+    return (irradiance * diffuse_colour + radiance * (f0 * brdf.x + brdf.y)) * pbr_ibl_intensity;
+}
+
+// Return the view-space z of a depth from the depth buffer, with the perspective projection
+float view_depth(float depth) {
+#ifdef VULKAN
+    float ndc_z = depth;
+#else
+    float ndc_z = depth * 2.0 - 1.0;
+#endif
+    return -pbr_projection[3][2] / (ndc_z + pbr_projection[2][2]);
+}
+
+// Return the fraction of a light that reaches a point past the nearby geometry, by marching a
+// short ray towards the light through the depth of the scene
+float contact_shadow(vec3 position, vec3 l) {
+    vec3 step_vector = l * (pbr_contact_shadow_length / float(pbr_contact_shadow_steps));
+
+    // Interleaved gradient noise staggers the steps between neighbouring pixels, trading banding
+    // for noise
+    float jitter = fract(52.9829189 * fract(dot(gl_FragCoord.xy, vec2(0.06711056, 0.00583715))));
+    vec3 ray = position + step_vector * jitter;
+    for (int i = 0; i < pbr_contact_shadow_steps; i++) {
+        ray += step_vector;
+        vec4 view = pbr_view * vec4(ray, 1.0);
+        vec4 clip = pbr_projection * view;
+        vec2 ray_uv = clip.xy / clip.w * 0.5 + 0.5;
+        if (any(lessThan(ray_uv, vec2(0.0))) || any(greaterThan(ray_uv, vec2(1.0)))) {
+            break;
+        }
+
+        // View space looks down -z, so the ray is behind the scene where its z is lower
+        float behind = view_depth(texture(pbr_contact_shadow_depth, ray_uv).r) - view.z;
+        if (behind > 0.0 && behind < pbr_contact_shadow_thickness) {
+            return 1.0 - pbr_contact_shadow_intensity;
+        }
+    }
+
+    return 1.0;
+}
+
+void main() {
+    vec4 base_colour = texture(pbr_base_colour_texture, uv) * pbr_base_colour_factor;
+    vec4 metallic_roughness = texture(pbr_metallic_roughness_texture, uv);
+
+    // glTF packs roughness into the green channel and metalness into the blue channel
+    float metallic = clamp(metallic_roughness.b * pbr_metallic_factor, 0.0, 1.0);
+    float roughness = clamp(metallic_roughness.g * pbr_roughness_factor, 0.04, 1.0);
+    float alpha = roughness * roughness;
+
+    vec3 tangent_normal = texture(pbr_normal_texture, uv).xyz * 2.0 - 1.0;
+    tangent_normal.xy *= pbr_normal_scale;
+    vec3 geometric_normal = normalize(world_normal);
+    vec3 n = normalize(cotangent_frame(geometric_normal, world_position, uv) * tangent_normal);
+    vec3 v = normalize(pbr_camera_position.xyz - world_position);
+    float n_dot_v = clamp(abs(dot(n, v)), 0.001, 1.0);
+
+    vec3 f0 = mix(vec3(0.04), base_colour.rgb, metallic);
+    vec3 diffuse_colour = base_colour.rgb * (1.0 - metallic);
+
+    vec3 colour = vec3(0.0);
+    for (int i = 0; i < pbr_light_count && i < PBR_MAX_LIGHTS; i++) {
+        vec3 l;
+        float attenuation;
+        if (pbr_light_positions[i].w == 0.0) {
+            l = normalize(-pbr_light_positions[i].xyz);
+            attenuation = 1.0;
+        } else {
+            vec3 to_light = pbr_light_positions[i].xyz - world_position;
+            l = normalize(to_light);
+            attenuation = 1.0 / max(dot(to_light, to_light), 0.0001);
+        }
+
+        vec3 h = normalize(l + v);
+        float n_dot_l = clamp(dot(n, l), 0.0, 1.0);
+        float n_dot_h = clamp(dot(n, h), 0.0, 1.0);
+        float v_dot_h = clamp(dot(v, h), 0.0, 1.0);
+
+        vec3 f = fresnel_schlick(f0, v_dot_h);
+        vec3 diffuse = (vec3(1.0) - f) * diffuse_colour / PI;
+        vec3 specular = f * distribution_ggx(n_dot_h, alpha) * visibility_smith_ggx(n_dot_l, n_dot_v, alpha);
+
+        float shadow = 1.0;
+        if (pbr_contact_shadow_steps > 0 && (pbr_contact_shadow_light_mask & (1 << i)) != 0 && n_dot_l > 0.0) {
+            shadow = contact_shadow(world_position, l);
+        }
+
+        vec3 radiance = pbr_light_colours[i].rgb * pbr_light_colours[i].a * attenuation;
+        colour += (diffuse + specular) * radiance * n_dot_l * shadow;
+    }
+
+    if (pbr_ibl_intensity > 0.0) {
+        colour += ibl_contribution(n, v, diffuse_colour, f0, roughness);
+    }
+
+    float occlusion = texture(pbr_occlusion_texture, uv).r;
+    colour = mix(colour, colour * occlusion, pbr_occlusion_strength);
+    colour += texture(pbr_emissive_texture, uv).rgb * pbr_emissive_factor.rgb;
+
+    pbr_colour = vec4(colour, base_colour.a);
+}
@@ -0,0 +1,15 @@
+layout(location = 0) in vec3 position;
+layout(location = 1) in vec3 normal;
+layout(location = 2) in vec3 texcoord;
+
+layout(location = 0) out vec3 world_position;
+layout(location = 1) out vec3 world_normal;
+layout(location = 2) out vec2 uv;
+
+void main() {
+    vec4 world = pbr_model * vec4(position, 1.0);
+    world_position = world.xyz;
+    world_normal = mat3(transpose(inverse(pbr_model))) * normal;
+    uv = texcoord.xy;
+    gl_Position = pbr_projection * pbr_view * world;
+}
@@ -0,0 +1,33 @@
+// Uniform blocks shared by the stages of the built-in PBR shader
+//
+// Block members are prefixed rather than accessed through an instance name, so that the
+// reflection data names them the same way as the ResourceManager specifications.
+
+#define PBR_MAX_LIGHTS 4
+
+layout(set = 0, binding = 0) uniform pbr_camera {
+    mat4 pbr_model;
+    mat4 pbr_view;
+    mat4 pbr_projection;
+    vec4 pbr_camera_position;
+};
+
+// Light positions have w = 0 for directional lights, in which case xyz is the direction
+// the light travels in.  The alpha channel of the colour is the intensity.
+layout(set = 0, binding = 1) uniform pbr_lights {
+    vec4 pbr_light_positions[PBR_MAX_LIGHTS];
+    vec4 pbr_light_colours[PBR_MAX_LIGHTS];
+    int pbr_light_count;
+};
+
+// Each factor multiplies the corresponding texture, so untextured materials bind 1x1 white
+// textures (and a flat normal map) and control the surface with these alone
+layout(set = 0, binding = 2) uniform pbr_material {
+    vec4 pbr_base_colour_factor;
+    vec4 pbr_emissive_factor;
+    float pbr_metallic_factor;
+    float pbr_roughness_factor;
+    float pbr_normal_scale;
+    float pbr_occlusion_strength;
+    float pbr_ibl_intensity;
+};
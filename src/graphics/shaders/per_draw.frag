@@ -0,0 +1,5 @@
+layout(location = 0) out vec4 per_draw_colour;
+
+void main() {
+    per_draw_colour = vec4(1.0);
+}
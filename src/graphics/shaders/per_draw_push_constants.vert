@@ -0,0 +1,10 @@
+layout(location = 0) in vec3 position;
+
+layout(push_constant) uniform per_draw_push_constants {
+    mat4 per_draw_push_matrix0;
+    mat4 per_draw_push_matrix1;
+};
+
+void main() {
+    gl_Position = per_draw_push_matrix0 * per_draw_push_matrix1 * vec4(position, 1.0);
+}
@@ -0,0 +1,18 @@
+layout(location = 0) in vec3 position;
+
+layout(set = 0, binding = 0) uniform per_draw_uniforms {
+    mat4 per_draw_uniform_matrices[256];
+};
+
+layout(push_constant) uniform per_draw_index_constants {
+    int per_draw_index;
+    int per_draw_matrix_count;
+};
+
+void main() {
+    vec4 transformed = vec4(position, 1.0);
+    for (int i = per_draw_matrix_count - 1; i >= 0; i--) {
+        transformed = per_draw_uniform_matrices[per_draw_index + i] * transformed;
+    }
+    gl_Position = transformed;
+}
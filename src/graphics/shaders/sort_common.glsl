@@ -0,0 +1,57 @@
+// The key/value pairs sorted by the bitonic and radix sorts, and the parameters of each dispatch
+//
+// The sizes must match SORT_MAX_ELEMENTS, SORT_WORKGROUP_SIZE and RADIX_BITS in gpusort.rs.
+
+#define SORT_MAX_ELEMENTS 65536
+#define SORT_WORKGROUP_SIZE 256
+#define RADIX_BITS 4
+#define RADIX_BUCKETS 16
+#define SORT_PADDING_KEY 0xffffffffu
+
+layout(std430, set = 0, binding = 0) buffer sort_buffer {
+    uint sort_keys[SORT_MAX_ELEMENTS];
+    uint sort_values[SORT_MAX_ELEMENTS];
+    uint sort_scratch_keys[SORT_MAX_ELEMENTS];
+    uint sort_scratch_values[SORT_MAX_ELEMENTS];
+    uint sort_histogram[RADIX_BUCKETS * (SORT_MAX_ELEMENTS / SORT_WORKGROUP_SIZE)];
+};
+
+layout(set = 0, binding = 1) uniform sort_params {
+    int sort_count;
+    int sort_padded_count;
+    int sort_stage;
+    int sort_step;
+    int sort_shift;
+    int sort_flip;
+};
+
+// Return the number of workgroups the radix sort passes cover the pairs with
+uint sort_workgroup_count() {
+    return (uint(sort_count) + SORT_WORKGROUP_SIZE - 1) / SORT_WORKGROUP_SIZE;
+}
+
+// Return the digit of a key sorted by the current radix sort pass
+uint sort_digit(uint key) {
+    return (key >> uint(sort_shift)) & uint(RADIX_BUCKETS - 1);
+}
+
+// Return a key read by the current radix sort pass, which alternates between the arrays
+uint sort_source_key(uint i) {
+    return sort_flip == 0 ? sort_keys[i] : sort_scratch_keys[i];
+}
+
+// Return a value read by the current radix sort pass
+uint sort_source_value(uint i) {
+    return sort_flip == 0 ? sort_values[i] : sort_scratch_values[i];
+}
+
+// Write a pair to the arrays the current radix sort pass is not reading
+void sort_write(uint i, uint key, uint value) {
+    if (sort_flip == 0) {
+        sort_scratch_keys[i] = key;
+        sort_scratch_values[i] = value;
+    } else {
+        sort_keys[i] = key;
+        sort_values[i] = value;
+    }
+}
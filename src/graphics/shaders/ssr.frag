@@ -0,0 +1,125 @@
+// Screen-space reflections, marched through the depth buffer and composited over the lighting
+//
+// Each pixel reflects the view ray about its G-buffer normal in view space and steps along the
+// reflection until it passes behind the depth buffer, then bisects to refine the hit.  Rough
+// surfaces blur the reflected colour around the hit.  Reflections fade into the environment
+// probe towards the screen edges, at the end of the ray, and for rays towards the viewer.
+
+layout(location = 0) out vec4 ssr_colour;
+
+layout(set = 0, binding = 0) uniform ssr_settings {
+    mat4 ssr_projection;
+    mat4 ssr_inverse_projection;
+    mat4 ssr_inverse_view;
+    vec4 ssr_viewport;
+    float ssr_max_distance;
+    float ssr_thickness;
+    float ssr_edge_fade;
+    float ssr_roughness_cutoff;
+    float ssr_intensity;
+    int ssr_max_steps;
+    int ssr_refinement_steps;
+    int ssr_blur_samples;
+};
+
+layout(set = 1, binding = 0) uniform sampler2D ssr_colour_texture;
+layout(set = 1, binding = 1) uniform sampler2D ssr_depth_texture;
+layout(set = 1, binding = 2) uniform sampler2D ssr_normal_texture;
+layout(set = 1, binding = 3) uniform samplerCube ssr_environment_texture;
+
+// Return the view-space position of the scene at a screen position
+vec3 ssr_view_position(vec2 uv) {
+    float depth = texture(ssr_depth_texture, uv).r;
+#ifdef VULKAN
+    vec4 ndc = vec4(uv * 2.0 - 1.0, depth, 1.0);
+#else
+    vec4 ndc = vec4(uv * 2.0 - 1.0, depth * 2.0 - 1.0, 1.0);
+#endif
+    vec4 view = ssr_inverse_projection * ndc;
+    return view.xyz / view.w;
+}
+
+// Return the screen position of a view-space position
+vec2 ssr_project(vec3 view_position) {
+    vec4 clip = ssr_projection * vec4(view_position, 1.0);
+    return clip.xy / clip.w * 0.5 + 0.5;
+}
+
+void main() {
+    vec2 uv = gl_FragCoord.xy / ssr_viewport.xy;
+    vec4 lighting = texture(ssr_colour_texture, uv);
+    vec4 gbuffer = texture(ssr_normal_texture, uv);
+    float roughness = gbuffer.a;
+
+    vec3 position = ssr_view_position(uv);
+    vec3 normal = normalize(gbuffer.rgb * 2.0 - 1.0);
+    vec3 view_direction = normalize(position);
+    vec3 reflection = reflect(view_direction, normal);
+
+    // Schlick's approximation for a dielectric, less the light scattered by roughness
+    float fresnel = 0.04 + 0.96 * pow(1.0 - max(dot(-view_direction, normal), 0.0), 5.0);
+    float strength = ssr_intensity * fresnel * (1.0 - roughness);
+
+    vec3 world_reflection = mat3(ssr_inverse_view) * reflection;
+    vec3 probe = texture(ssr_environment_texture, world_reflection).rgb;
+
+    // Rays towards the viewer soon leave what the depth buffer shows, as do rough reflections
+    float confidence = clamp(-reflection.z * 4.0, 0.0, 1.0) * step(roughness, ssr_roughness_cutoff);
+    vec2 hit_uv = uv;
+    if (confidence > 0.0) {
+        float step_length = ssr_max_distance / float(ssr_max_steps);
+        vec3 ray = position;
+        bool hit = false;
+        int steps = 0;
+        for (int i = 0; i < ssr_max_steps; i++) {
+            ray += reflection * step_length;
+            hit_uv = ssr_project(ray);
+            steps = i;
+            if (any(lessThan(hit_uv, vec2(0.0))) || any(greaterThan(hit_uv, vec2(1.0)))) {
+                break;
+            }
+            // View space looks down -z, so a ray behind the scene has the smaller z
+            float behind = ssr_view_position(hit_uv).z - ray.z;
+            if (behind > 0.0 && behind < ssr_thickness) {
+                hit = true;
+                break;
+            }
+        }
+
+        if (hit) {
+            // Bisect between the last step in front of the scene and the first behind it
+            vec3 front = ray - reflection * step_length;
+            vec3 back = ray;
+            for (int i = 0; i < ssr_refinement_steps; i++) {
+                vec3 middle = (front + back) * 0.5;
+                if (ssr_view_position(ssr_project(middle)).z > middle.z) {
+                    back = middle;
+                } else {
+                    front = middle;
+                }
+            }
+            hit_uv = ssr_project(back);
+
+            vec2 edge = min(hit_uv, 1.0 - hit_uv);
+            confidence *= clamp(min(edge.x, edge.y) / ssr_edge_fade, 0.0, 1.0);
+            confidence *= 1.0 - float(steps) / float(ssr_max_steps);
+        } else {
+            confidence = 0.0;
+        }
+    }
+
+    vec3 reflected = probe;
+    if (confidence > 0.0) {
+        // Blur rough reflections over a disc growing with the roughness and the ray length
+        float radius = roughness * length(hit_uv - uv) * 0.25;
+        vec3 sum = vec3(0.0);
+        for (int i = 0; i < ssr_blur_samples; i++) {
+            float angle = float(i) * 2.39996;
+            float r = radius * sqrt((float(i) + 0.5) / float(ssr_blur_samples));
+            sum += texture(ssr_colour_texture, hit_uv + r * vec2(cos(angle), sin(angle))).rgb;
+        }
+        reflected = mix(probe, sum / float(ssr_blur_samples), confidence);
+    }
+
+    ssr_colour = vec4(lighting.rgb + reflected * strength, lighting.a);
+}
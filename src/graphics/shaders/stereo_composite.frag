@@ -0,0 +1,16 @@
+// Present the two eyes side-by-side, top-bottom or as an anaglyph, with stereo_composite
+
+layout(location = 0) in vec2 stereo_uv;
+
+layout(location = 0) out vec4 stereo_colour;
+
+layout(set = 1, binding = 0) uniform sampler2D stereo_left_eye_texture;
+layout(set = 1, binding = 1) uniform sampler2D stereo_right_eye_texture;
+
+layout(push_constant) uniform stereo_push_constants {
+    int stereo_mode;
+};
+
+void main() {
+    stereo_colour = stereo_composite(stereo_left_eye_texture, stereo_right_eye_texture, stereo_uv, stereo_mode);
+}
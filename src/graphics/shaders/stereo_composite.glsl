@@ -0,0 +1,24 @@
+// Combine the images rendered for the two eyes for display on a single screen
+
+vec4 stereo_composite(sampler2D left_eye, sampler2D right_eye, vec2 uv, int mode) {
+    if (mode == 1) {
+        // Side-by-side, with the left eye on the left
+        if (uv.x < 0.5) {
+            return texture(left_eye, vec2(uv.x * 2.0, uv.y));
+        }
+        return texture(right_eye, vec2(uv.x * 2.0 - 1.0, uv.y));
+    } else if (mode == 2) {
+        // Top-bottom, with the left eye at the top
+        if (uv.y < 0.5) {
+            return texture(left_eye, vec2(uv.x, uv.y * 2.0));
+        }
+        return texture(right_eye, vec2(uv.x, uv.y * 2.0 - 1.0));
+    } else if (mode == 3) {
+        // Red-cyan anaglyph from the luminance of each eye
+        vec3 weights = vec3(0.299, 0.587, 0.114);
+        float left_luminance = dot(texture(left_eye, uv).rgb, weights);
+        float right_luminance = dot(texture(right_eye, uv).rgb, weights);
+        return vec4(left_luminance, right_luminance, right_luminance, 1.0);
+    }
+    return texture(left_eye, uv);
+}
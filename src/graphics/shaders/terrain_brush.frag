@@ -0,0 +1,64 @@
+// Project a ring or disc straight down onto the terrain, reconstructed from the depth buffer
+
+layout(location = 0) out vec4 brush_colour;
+
+layout(set = 0, binding = 0) uniform brush_camera {
+    mat4 brush_view_projection;
+    mat4 brush_inverse_view_projection;
+    vec4 brush_viewport;
+};
+
+layout(push_constant) uniform brush_instance {
+    vec3 brush_centre;
+    float brush_radius;
+    vec3 brush_tint;
+    float brush_opacity;
+    float brush_falloff;
+    float brush_ring_width; // Zero for a disc
+    float brush_height;
+};
+
+layout(set = 1, binding = 0) uniform sampler2D brush_depth_texture;
+
+void main() {
+    // Only the back faces are drawn, so that the brush still shows with the camera inside its box
+#ifdef VULKAN
+    bool front_facing = !gl_FrontFacing;
+#else
+    bool front_facing = gl_FrontFacing;
+#endif
+    if (front_facing) {
+        discard;
+    }
+
+    vec2 screen_uv = gl_FragCoord.xy / brush_viewport.xy;
+    float depth = texture(brush_depth_texture, screen_uv).r;
+#ifdef VULKAN
+    vec4 ndc = vec4(screen_uv * 2.0 - 1.0, depth, 1.0);
+#else
+    vec4 ndc = vec4(screen_uv * 2.0 - 1.0, depth * 2.0 - 1.0, 1.0);
+#endif
+    vec4 world = brush_inverse_view_projection * ndc;
+    world /= world.w;
+
+    vec3 offset = world.xyz - brush_centre;
+    float distance = length(offset.xz);
+    if (distance > brush_radius || abs(offset.y) > brush_height) {
+        discard;
+    }
+
+    // This matches Brush::coverage
+    float from_edge = brush_radius - distance;
+    float coverage;
+    if (brush_ring_width > 0.0) {
+        coverage = from_edge <= brush_ring_width ? 1.0 : 0.0;
+    } else {
+        float edge = brush_radius * clamp(brush_falloff, 0.0, 1.0);
+        coverage = edge > 0.0 ? min(from_edge / edge, 1.0) : 1.0;
+    }
+    if (coverage <= 0.0) {
+        discard;
+    }
+
+    brush_colour = vec4(brush_tint, brush_opacity * coverage);
+}
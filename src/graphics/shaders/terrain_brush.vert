@@ -0,0 +1,13 @@
+// Draw the box around a terrain brush, within which the brush is projected onto the terrain
+
+layout(location = 0) in vec3 position;
+
+layout(set = 0, binding = 0) uniform brush_camera {
+    mat4 brush_view_projection;
+    mat4 brush_inverse_view_projection;
+    vec4 brush_viewport;
+};
+
+void main() {
+    gl_Position = brush_view_projection * vec4(position, 1.0);
+}
@@ -0,0 +1,26 @@
+// Glyphs tinted by the colour of their run, with the coverage taken from the atlas's red channel
+//
+// A signed distance field atlas holds 0.5 at the edge of each glyph, which is antialiased over
+// about a pixel whatever the scale the text is drawn at.
+
+layout(location = 0) in vec2 glyph_texcoord;
+
+layout(location = 0) out vec4 text_colour;
+
+layout(push_constant) uniform text_run {
+    vec3 text_tint;
+    float text_opacity;
+    int text_distance_field;
+};
+
+layout(set = 1, binding = 0) uniform sampler2D text_atlas;
+
+void main() {
+    float texel = texture(text_atlas, glyph_texcoord).r;
+    float coverage = texel;
+    if (text_distance_field != 0) {
+        float edge = max(0.5 * fwidth(texel), 1.0e-4);
+        coverage = smoothstep(0.5 - edge, 0.5 + edge, texel);
+    }
+    text_colour = vec4(text_tint, text_opacity * coverage);
+}
@@ -0,0 +1,11 @@
+// Glyph quads built on the CPU, already in clip space
+
+layout(location = 0) in vec2 position;
+layout(location = 1) in vec2 texcoord;
+
+layout(location = 0) out vec2 glyph_texcoord;
+
+void main() {
+    gl_Position = vec4(position, 0.0, 1.0);
+    glyph_texcoord = texcoord;
+}
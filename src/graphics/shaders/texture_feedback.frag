@@ -0,0 +1,31 @@
+// Write the streaming slot and mip level of the texture each pixel samples
+
+layout(location = 0) in vec2 uv;
+
+layout(location = 0) out vec4 texture_feedback;
+
+layout(push_constant) uniform texture_feedback_push_constants {
+    int feedback_texture_slot;
+    float feedback_texture_width;
+    float feedback_texture_height;
+    float feedback_lod_bias;
+};
+
+// This matches TEXTURE_FEEDBACK_MAX_MIPS
+const float max_mip = 15.0;
+
+void main() {
+    // Estimate the mip level from the texel derivatives, as the hardware would at the screen's
+    // resolution rather than the feedback target's
+    vec2 texel = uv * vec2(feedback_texture_width, feedback_texture_height);
+    vec2 dx = dFdx(texel);
+    vec2 dy = dFdy(texel);
+    float lod = 0.5 * log2(max(max(dot(dx, dx), dot(dy, dy)), 1.0e-8)) + feedback_lod_bias;
+    float mip = floor(clamp(lod, 0.0, max_mip));
+
+    // The slot is offset by one so that the cleared target reads as nothing sampled.  A quarter
+    // is added to each byte so that it survives both rounding and truncation to eight bits.
+    int encoded = feedback_texture_slot + 1;
+    vec3 bytes = vec3(float(encoded & 0xff), float((encoded >> 8) & 0xff), mip);
+    texture_feedback = vec4((bytes + 0.25) / 255.0, 1.0);
+}
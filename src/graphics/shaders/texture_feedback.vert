@@ -0,0 +1,19 @@
+// Transform the scene as the PBR shader does, for feedback of the textures it samples
+
+layout(location = 0) in vec3 position;
+layout(location = 1) in vec3 normal;
+layout(location = 2) in vec3 texcoord;
+
+layout(location = 0) out vec2 uv;
+
+layout(set = 0, binding = 0) uniform pbr_camera {
+    mat4 pbr_model;
+    mat4 pbr_view;
+    mat4 pbr_projection;
+    vec4 pbr_camera_position;
+};
+
+void main() {
+    uv = texcoord.xy;
+    gl_Position = pbr_projection * pbr_view * pbr_model * vec4(position, 1.0);
+}
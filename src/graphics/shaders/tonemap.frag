@@ -0,0 +1,71 @@
+// Tone-map the exposed HDR scene for an sRGB or HDR10 display
+
+layout(location = 0) in vec2 tonemap_uv;
+
+layout(location = 0) out vec4 tonemap_colour;
+
+layout(set = 0, binding = 0) uniform frame_block {
+    float frame_time;
+    float frame_delta_time;
+    float frame_real_time;
+    float frame_real_delta_time;
+    float frame_time_scale;
+    float frame_exposure;
+};
+
+layout(set = 0, binding = 1) uniform tonemap_settings {
+    int tonemap_operator;
+    int tonemap_hdr10;
+    float tonemap_white;
+    float tonemap_paper_white_nits;
+    float tonemap_peak_nits;
+};
+
+layout(set = 1, binding = 0) uniform sampler2D tonemap_source_texture;
+
+// These match TonemapOperator::apply
+vec3 reinhard(vec3 c, float white) {
+    return c * (1.0 + c / (white * white)) / (1.0 + c);
+}
+
+vec3 aces(vec3 c) {
+    return (c * (2.51 * c + 0.03)) / (c * (2.43 * c + 0.59) + 0.14);
+}
+
+vec3 tonemap(vec3 c, float white) {
+    c = max(c, vec3(0.0));
+    vec3 mapped = tonemap_operator == 0 ? reinhard(c, white) : aces(c);
+    return clamp(mapped, 0.0, 1.0);
+}
+
+// This matches nits_to_pq
+vec3 nits_to_pq(vec3 nits) {
+    const float m1 = 2610.0 / 16384.0;
+    const float m2 = 2523.0 / 4096.0 * 128.0;
+    const float c1 = 3424.0 / 4096.0;
+    const float c2 = 2413.0 / 4096.0 * 32.0;
+    const float c3 = 2392.0 / 4096.0 * 32.0;
+
+    vec3 y = pow(clamp(nits / 10000.0, 0.0, 1.0), vec3(m1));
+    return pow((c1 + c2 * y) / (1.0 + c3 * y), vec3(m2));
+}
+
+// The Rec. 709 primaries of the scene expressed in Rec. 2020, in column-major order
+const mat3 rec709_to_rec2020 = mat3(0.6274, 0.0691, 0.0164,
+                                    0.3293, 0.9195, 0.0880,
+                                    0.0433, 0.0114, 0.8956);
+
+void main() {
+    vec4 scene = texture(tonemap_source_texture, tonemap_uv);
+    vec3 exposed = scene.rgb * frame_exposure;
+
+    if (tonemap_hdr10 != 0) {
+        // Compress into the headroom above paper white, as TonemapSettings::hdr10_nits
+        float headroom = tonemap_peak_nits / tonemap_paper_white_nits;
+        vec3 nits = tonemap(exposed / headroom, tonemap_white / headroom) * tonemap_peak_nits;
+        tonemap_colour = vec4(nits_to_pq(rec709_to_rec2020 * nits), 1.0);
+    } else {
+        // The sRGB target encodes the colour as it is written
+        tonemap_colour = vec4(tonemap(exposed, tonemap_white), 1.0);
+    }
+}
@@ -0,0 +1,37 @@
+// Cross-fade or wipe from a captured frame of the outgoing scene to the incoming scene
+
+layout(location = 0) out vec4 transition_colour;
+
+layout(set = 0, binding = 0) uniform transition_settings {
+    vec4 transition_viewport;
+    vec4 transition_direction;
+    float transition_progress;
+    float transition_softness;
+    int transition_style;
+};
+
+layout(set = 1, binding = 0) uniform sampler2D transition_outgoing_texture;
+layout(set = 1, binding = 1) uniform sampler2D transition_incoming_texture;
+
+void main() {
+    vec2 uv = gl_FragCoord.xy / transition_viewport.xy;
+    vec4 outgoing = texture(transition_outgoing_texture, uv);
+    vec4 incoming = texture(transition_incoming_texture, uv);
+
+    float blend = transition_progress;
+    if (transition_style == 1) {
+        // The position along the wipe, from zero at the starting edge to one at the far edge
+        vec2 direction = transition_direction.xy;
+        float position = dot(uv - 0.5, direction) + 0.5;
+
+        // Widen the sweep by the softness so that the edge fully enters and leaves the screen
+        float edge = transition_progress * (1.0 + transition_softness);
+        if (transition_softness > 0.0) {
+            blend = 1.0 - smoothstep(edge - transition_softness, edge, position);
+        } else {
+            blend = step(position, edge);
+        }
+    }
+
+    transition_colour = mix(outgoing, incoming, blend);
+}
@@ -0,0 +1,8 @@
+layout(location = 0) in vec4 current_clip;
+layout(location = 1) in vec4 previous_clip;
+
+layout(location = 0) out vec4 velocity_out;
+
+void main() {
+    velocity_out = vec4(velocity_motion_vector(current_clip, previous_clip), 0.0, 1.0);
+}
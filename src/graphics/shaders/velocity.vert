@@ -0,0 +1,20 @@
+layout(location = 0) in vec3 position;
+
+layout(location = 0) out vec4 current_clip;
+layout(location = 1) out vec4 previous_clip;
+
+layout(set = 0, binding = 0) uniform velocity_camera {
+    mat4 velocity_view_projection;
+    mat4 velocity_previous_view_projection;
+};
+
+layout(push_constant) uniform velocity_object {
+    mat4 velocity_model;
+    mat4 velocity_previous_model;
+};
+
+void main() {
+    current_clip = velocity_view_projection * velocity_model * vec4(position, 1.0);
+    previous_clip = velocity_previous_view_projection * velocity_previous_model * vec4(position, 1.0);
+    gl_Position = current_clip;
+}
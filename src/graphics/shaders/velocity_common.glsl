@@ -0,0 +1,9 @@
+// Screen-space motion vectors, shared by the velocity pass and any pass writing velocity itself
+
+// Return the motion of a point since the previous frame, in texture coordinate units
+//
+// The current and previous positions are in clip space.  The result is zero for a point that
+// has not moved on screen, and points from the previous position to the current one.
+vec2 velocity_motion_vector(vec4 current_clip, vec4 previous_clip) {
+    return (current_clip.xy / current_clip.w - previous_clip.xy / previous_clip.w) * 0.5;
+}
@@ -0,0 +1,20 @@
+// Darken the corners of the frame, by up to half at full strength
+
+layout(location = 0) in vec2 post_effect_uv;
+
+layout(location = 0) out vec4 post_effect_colour;
+
+layout(set = 1, binding = 0) uniform sampler2D post_effect_source_texture;
+
+layout(push_constant) uniform post_effect_push_constants {
+    float post_effect_strength;
+};
+
+void main() {
+    vec4 colour = texture(post_effect_source_texture, post_effect_uv);
+
+    // The squared distance from the centre, which is 1 in the corners
+    vec2 offset = post_effect_uv - 0.5;
+    float falloff = smoothstep(0.25, 1.0, 2.0 * dot(offset, offset));
+    post_effect_colour = vec4(colour.rgb * (1.0 - 0.5 * post_effect_strength * falloff), colour.a);
+}
@@ -31,13 +31,16 @@
 use std::sync::*;
 use std::boxed::Box;
 use std::any::Any;
+use std::collections::HashMap;
 use std::process::Command;
 use std::fs::remove_file;
-use std::time::UNIX_EPOCH;
+use std::time::{SystemTime, UNIX_EPOCH};
 
 use vk::vulkan::*;
 
 use graphics::shader::*;
+use graphics::shaderhlsl::*;
+use graphics::glslinclude::*;
 use graphics::renderer::*;
 use graphics::renderervk::*;
 use graphics::resources::*;
@@ -53,11 +56,19 @@ pub struct ShaderSpirv {
     fragment_out: &'static str,
 
     shader_modules: Vec<RendererVkShaderModule>,
-    shader_modules_raw: Vec<(ShaderStage, VkShaderModule)>,
+    shader_modules_raw: Vec<(ShaderStage, VkShaderModule, &'static str)>,
+
+    spirv_mod_times: HashMap<&'static str, SystemTime>,
 }
 unsafe impl Send for ShaderSpirv {}
 unsafe impl Sync for ShaderSpirv {}
 
+// The source of one stage of a shader, ready to compile
+struct StageSource {
+    expanded: Option<String>, // The expanded GLSL, or None for HLSL, which DXC reads from the file
+    input_filenames: Vec<String>, // The files the source was read from
+}
+
 impl ShaderSpirv {
     pub fn new(renderer: &Box<Renderer>) -> ShaderSpirv {
         let renderer_vk: &RendererVk = match renderer.as_any().downcast_ref::<RendererVk>() {
@@ -75,6 +86,8 @@ impl ShaderSpirv {
 
             shader_modules: vec![],
             shader_modules_raw: vec![],
+
+            spirv_mod_times: HashMap::new(),
         }
     }
 
@@ -90,6 +103,7 @@ impl ShaderSpirv {
             ShaderStage::TessEvalShader => VkShaderStageFlagBits::VK_SHADER_STAGE_TESSELLATION_EVALUATION_BIT,
             ShaderStage::GeometryShader => VkShaderStageFlagBits::VK_SHADER_STAGE_GEOMETRY_BIT,
             ShaderStage::FragmentShader => VkShaderStageFlagBits::VK_SHADER_STAGE_FRAGMENT_BIT,
+            ShaderStage::ComputeShader => VkShaderStageFlagBits::VK_SHADER_STAGE_COMPUTE_BIT,
         }
     }
 
@@ -105,11 +119,16 @@ impl ShaderSpirv {
             ShaderStage::TessEvalShader => "tese",
             ShaderStage::GeometryShader => "geom",
             ShaderStage::FragmentShader => "frag",
+            ShaderStage::ComputeShader => "comp",
         }
     }
 
     /// Compile all of the files used by a specific shader
     ///
+    /// Each stage is compiled on its own.  Stages naming the same spirv_out are then linked
+    /// into one module holding all of their entry points, and the whole module is rebuilt when
+    /// an input of any of its stages changes.
+    ///
     /// spec: The specification of the shader resource to build
     /// conditionally: When true, compare the timestamps of the input and
     ///     output to decide whether to compile or not
@@ -117,107 +136,296 @@ impl ShaderSpirv {
     /// all_succeeded: Set this flag to false whenever any of the files
     ///     failed to compile
     pub fn compile_shader_resource(spec: &ShaderSpec, conditionally: bool, debug_output_level: u32, all_succeeded: &mut bool) {
-        for shader_file in spec.shader_files.iter() {
-            let extension = ShaderSpirv::shader_extension_name(shader_file.shader_stage);
-            let stage_name = shader_stage_name(shader_file.shader_stage);
+        let modules = match plan_spirv_modules(&spec.shader_files) {
+            Ok(modules) => modules,
+            Err(e) => {
+                log_error!("Failed to build the SPIR-V for {}: {}", spec.name, e);
+                *all_succeeded = false;
+                return;
+            }
+        };
 
-            let mut output_timestamp = match get_last_modification_timestamp(shader_file.spirv_out) {
-                Err(_) => UNIX_EPOCH,
-                Ok(t) => t,
-            };
-            let output_timestamp_rfl = match get_last_modification_timestamp(shader_file.reflect_out) {
+        for module in modules.iter() {
+            let shader_files: Vec<&ShaderFilesSpecification> =
+                module.stages.iter().map(|stage| &spec.shader_files[*stage]).collect();
+
+            let mut output_timestamp = match get_last_modification_timestamp(module.spirv_out) {
                 Err(_) => UNIX_EPOCH,
                 Ok(t) => t,
             };
-            if output_timestamp_rfl < output_timestamp {
-                output_timestamp = output_timestamp_rfl;
-            }
-
-            let mut rebuild = false;
-            for lib_filename in spec.library_files.iter() {
-                let input_timestamp = match get_last_modification_timestamp(lib_filename) {
-                    Err(e) => panic!("{}", e),
+            for shader_file in shader_files.iter() {
+                let output_timestamp_rfl = match get_last_modification_timestamp(shader_file.reflect_out) {
+                    Err(_) => UNIX_EPOCH,
                     Ok(t) => t,
                 };
-                if input_timestamp > output_timestamp {
-                    rebuild = true;
+                if output_timestamp_rfl < output_timestamp {
+                    output_timestamp = output_timestamp_rfl;
                 }
             }
-            let input_timestamp = match get_last_modification_timestamp(shader_file.filename) {
-                Err(e) => panic!("{}", e),
-                Ok(t) => t,
-            };
-            if input_timestamp > output_timestamp {
-                rebuild = true;
+
+            // GLSL is expanded up front, as the files it includes are inputs too
+            //
+            let mut sources: Vec<StageSource> = vec![];
+            for shader_file in shader_files.iter() {
+                if ShaderLanguage::from_filename(shader_file.filename) == ShaderLanguage::Hlsl {
+                    sources.push(StageSource {
+                        expanded: None,
+                        input_filenames: vec![shader_file.filename.to_string()],
+                    });
+                    continue;
+                }
+
+                let mut preprocessor = GlslPreprocessor::new();
+                match preprocess_glsl(&mut preprocessor, None, &spec.library_files, shader_file.filename) {
+                    Ok(source) => {
+                        sources.push(StageSource {
+                            expanded: Some(source),
+                            input_filenames: preprocessor.files().clone(),
+                        })
+                    }
+                    Err(e) => {
+                        println!("Failed to preprocess the {} stage of {}: {}",
+                                 shader_stage_name(shader_file.shader_stage),
+                                 spec.name,
+                                 e);
+                        break;
+                    }
+                }
+            }
+            if sources.len() < shader_files.len() {
+                *all_succeeded = false;
+                continue;
+            }
+
+            let mut rebuild = false;
+            for source in sources.iter() {
+                for input_filename in source.input_filenames.iter() {
+                    let input_timestamp = match get_last_modification_timestamp(input_filename) {
+                        Err(e) => panic!("{}", e),
+                        Ok(t) => t,
+                    };
+                    if input_timestamp > output_timestamp {
+                        rebuild = true;
+                    }
+                }
             }
             if conditionally && !rebuild {
                 if debug_output_level > 0 {
-                    println!("Skipping compilation of SPIR-V for {}, for {} stage",
-                             spec.name,
-                             extension);
+                    for shader_file in shader_files.iter() {
+                        println!("Skipping compilation of SPIR-V for {}, for {} stage",
+                                 spec.name,
+                                 ShaderSpirv::shader_extension_name(shader_file.shader_stage));
+                    }
                 }
                 continue;
             }
 
-            if debug_output_level > 0 {
-                println!("Compiling SPIR-V for {}, stage {}", spec.name, stage_name);
+            // A module of one stage is compiled straight to its file, and the stages of a
+            // shared module to intermediate files that are then linked
+            //
+            let linked = shader_files.len() > 1;
+            let mut stage_outputs: Vec<String> = vec![];
+            let mut module_succeeded = true;
+            for (shader_file, source) in shader_files.iter().zip(sources.iter()) {
+                let stage_output = if linked {
+                    format!("{}.{}.part", module.spirv_out, stage_outputs.len())
+                } else {
+                    module.spirv_out.to_string()
+                };
+                if !ShaderSpirv::compile_stage(spec, shader_file, source, &stage_output, debug_output_level) {
+                    module_succeeded = false;
+                }
+                stage_outputs.push(stage_output);
             }
 
-            let mut lib_source = "#version 450 core\n\n".to_string();
-            for lib_filename in spec.library_files.iter() {
-                if debug_output_level > 1 {
-                    println!("Incorporating library file {}", lib_filename);
+            if linked {
+                if module_succeeded {
+                    module_succeeded = ShaderSpirv::link_spirv_modules(&stage_outputs, module.spirv_out, debug_output_level);
+                }
+                for stage_output in stage_outputs.iter() {
+                    let _ = remove_file(stage_output);
                 }
-                let source = read_text_file(None, lib_filename);
-                lib_source = lib_source + &source + &"\n#line 1\n";
             }
 
-            if debug_output_level > 1 {
-                println!("Incorporating source file {}", shader_file.filename);
+            if !module_succeeded {
+                *all_succeeded = false;
             }
-            let source = read_text_file(None, &shader_file.filename);
-            let full_source = lib_source.clone() + &source;
-            write_entire_file(&full_source, &("temp.".to_string() + &extension)).expect("Failed to write shader temporary file");
+        }
+    }
 
-            // Build the SPIR-V
-            //
-            let mut command;
-            command = Command::new("glslangValidator");
-            command.arg("-V") // SPIR-V output with Vulkan semantics
-                    .arg("-q") // Build reflection data
-                    .arg("-o") // Specify output file
-                    .arg(shader_file.spirv_out)
-                    .arg("temp.".to_string() + &extension);
-
-            if debug_output_level > 1 {
-                println!("Running glslangValidator:");
+    /// Compile one stage of a shader to SPIR-V, writing its reflection data
+    ///
+    /// spec: The specification of the shader the stage belongs to
+    /// shader_file: The specification of the stage
+    /// source: The expanded source of the stage and the files it was read from
+    /// spirv_out: The file to write the SPIR-V to
+    /// debug_output_level: Debug output level (0 = silent, 1 = minimal, 2 = full)
+    ///
+    /// Returns: true if the compilation succeeded
+    fn compile_stage(spec: &ShaderSpec,
+                     shader_file: &ShaderFilesSpecification,
+                     source: &StageSource,
+                     spirv_out: &str,
+                     debug_output_level: u32)
+                     -> bool {
+        let extension = ShaderSpirv::shader_extension_name(shader_file.shader_stage);
+        let stage_name = shader_stage_name(shader_file.shader_stage);
+
+        if debug_output_level > 0 {
+            println!("Compiling SPIR-V for {}, stage {}", spec.name, stage_name);
+        }
+
+        // HLSL is compiled by DXC, without the GLSL library files
+        //
+        let expanded = match source.expanded {
+            Some(ref expanded) => expanded,
+            None => {
+                let compiled = compile_hlsl_to_spirv(shader_file.filename,
+                                                     shader_file.shader_stage,
+                                                     shader_file.entry_point,
+                                                     spirv_out,
+                                                     debug_output_level);
+                let reflection = if compiled {
+                    reflect_hlsl(shader_file.filename, shader_file.shader_stage, shader_file.entry_point)
+                } else {
+                    None
+                };
+                return match reflection {
+                    Some(r) => {
+                        write_entire_file(&r, shader_file.reflect_out).expect("Failed to write shader reflection file");
+                        true
+                    }
+                    None => false,
+                };
             }
-            let output = command.output().expect("Failed to invoke GLSL to SPIR-V compiler");
-            if debug_output_level > 1 || !output.status.success() {
-                println!("Status: {}", output.status);
-                println!("stdout: {}", String::from_utf8_lossy(&output.stdout));
-                println!("stderr: {}", String::from_utf8_lossy(&output.stderr));
+        };
+
+        if debug_output_level > 1 {
+            for (number, filename) in source.input_filenames.iter().enumerate() {
+                println!("Incorporating source string {}, {}", number, filename);
             }
+        }
+        let full_source = "#version 450 core\n\n".to_string() + expanded;
+        write_entire_file(&full_source, &("temp.".to_string() + &extension)).expect("Failed to write shader temporary file");
 
+        // Build the SPIR-V
+        //
+        let mut command;
+        command = Command::new("glslangValidator");
+        command.arg("-V") // SPIR-V output with Vulkan semantics
+                .arg("-q") // Build reflection data
+                .arg("-o") // Specify output file
+                .arg(spirv_out);
+        if shader_file.entry_point != DEFAULT_ENTRY_POINT {
+            // Rename the GLSL main function to the requested entry point
+            command.arg("-e")
+                    .arg(shader_file.entry_point)
+                    .arg("--source-entrypoint")
+                    .arg(DEFAULT_ENTRY_POINT);
+        }
+        command.arg("temp.".to_string() + &extension);
+
+        if debug_output_level > 1 {
+            println!("Running glslangValidator:");
+        }
+        let output = command.output().expect("Failed to invoke GLSL to SPIR-V compiler");
+        if debug_output_level > 1 || !output.status.success() {
             if !output.status.success() {
-                *all_succeeded = false;
-            } else {
-                write_entire_file(&String::from_utf8_lossy(&output.stdout),
-                                  shader_file.reflect_out)
-                    .expect("Failed to write shader reflection file");
+                for (number, filename) in source.input_filenames.iter().enumerate() {
+                    println!("Source string {} is {}", number, filename);
+                }
             }
+            println!("Status: {}", output.status);
+            println!("stdout: {}", String::from_utf8_lossy(&output.stdout));
+            println!("stderr: {}", String::from_utf8_lossy(&output.stderr));
+        }
 
-            // Remove temporary file
-            remove_file("temp.".to_string() + &extension).expect("Failed to remove temporary file");
+        if output.status.success() {
+            write_entire_file(&String::from_utf8_lossy(&output.stdout),
+                              shader_file.reflect_out)
+                .expect("Failed to write shader reflection file");
+        }
 
-            if debug_output_level > 1 {
-                println!("");
-            }
+        // Remove temporary file
+        remove_file("temp.".to_string() + &extension).expect("Failed to remove temporary file");
+
+        if debug_output_level > 1 {
+            println!("");
+        }
+
+        output.status.success()
+    }
+
+    /// Link the separately compiled stages of a shared module into one SPIR-V module
+    ///
+    /// stage_outputs: The SPIR-V files of the stages
+    /// spirv_out: The file to write the linked module to
+    /// debug_output_level: Debug output level (0 = silent, 1 = minimal, 2 = full)
+    ///
+    /// Returns: true if the link succeeded
+    fn link_spirv_modules(stage_outputs: &Vec<String>, spirv_out: &str, debug_output_level: u32) -> bool {
+        if debug_output_level > 1 {
+            println!("Running spirv-link:");
+        }
+        let output = Command::new("spirv-link")
+            .args(stage_outputs)
+            .arg("-o") // Specify output file
+            .arg(spirv_out)
+            .output()
+            .expect("Failed to invoke SPIR-V linker");
+
+        if !output.status.success() {
+            log_error!("Failed to link {}: {}", spirv_out, String::from_utf8_lossy(&output.stderr));
         }
+
+        output.status.success()
+    }
+
+    /// Compile a single GLSL source held in memory to SPIR-V
+    ///
+    /// This is used for the built-in shaders, which do not live on disk.
+    ///
+    /// name: The name to use for the temporary files and in diagnostics
+    /// source: The GLSL source, without a #version directive
+    /// shader_stage: The stage the source is for
+    ///
+    /// Returns: The SPIR-V bytecode, or None if the compilation failed
+    pub fn compile_glsl_source(name: &str, source: &str, shader_stage: ShaderStage) -> Option<Vec<u8>> {
+        let extension = ShaderSpirv::shader_extension_name(shader_stage);
+        let source_filename = format!("temp_{}.{}", name, extension);
+        let spirv_filename = format!("temp_{}.{}.spv", name, extension);
+
+        let full_source = "#version 450 core\n\n".to_string() + source;
+        write_entire_file(&full_source, &source_filename).expect("Failed to write shader temporary file");
+
+        let output = Command::new("glslangValidator")
+            .arg("-V") // SPIR-V output with Vulkan semantics
+            .arg("-o") // Specify output file
+            .arg(&spirv_filename)
+            .arg(&source_filename)
+            .output()
+            .expect("Failed to invoke GLSL to SPIR-V compiler");
+
+        remove_file(&source_filename).expect("Failed to remove temporary file");
+
+        if !output.status.success() {
+            println!("Failed to compile {} stage of {}", shader_stage_name(shader_stage), name);
+            println!("stdout: {}", String::from_utf8_lossy(&output.stdout));
+            println!("stderr: {}", String::from_utf8_lossy(&output.stderr));
+            return None;
+        }
+
+        let bytecode = read_binary_file(&spirv_filename, false /* debug */).ok();
+        remove_file(&spirv_filename).expect("Failed to remove temporary file");
+
+        bytecode
     }
 
     /// Return the raw Vulkan shader modules
-    pub fn get_shader_modules(&self) -> Vec<(ShaderStage, VkShaderModule)> {
+    ///
+    /// This is empty if any of the stages failed to load, in which case the renderer
+    /// substitutes the built-in error shader.
+    pub fn get_shader_modules(&self) -> Vec<(ShaderStage, VkShaderModule, &'static str)> {
         // Clone the vector of raw shader module handles
         self.shader_modules_raw.clone()
     }
@@ -255,26 +463,125 @@ impl Shader for ShaderSpirv {
         self.fragment_out = resources.fragment_out.clone();
 
         for shader_file in self.shader_files.iter() {
-            let bytecode = read_binary_file(shader_file.spirv_out, false /* debug */).expect("Unable to read SPIR-V");
+            let timestamp = get_last_modification_timestamp(shader_file.spirv_out).unwrap_or(UNIX_EPOCH);
+            self.spirv_mod_times.insert(shader_file.spirv_out, timestamp);
+        }
+
+        let mut module_indices: HashMap<&'static str, usize> = HashMap::new();
+        for shader_file in self.shader_files.iter() {
+            // Stages sharing a SPIR-V file share the shader module, selecting their own entry point
+            if let Some(index) = module_indices.get(shader_file.spirv_out) {
+                let raw = self.shader_modules[*index].get_raw();
+                self.shader_modules_raw.push((shader_file.shader_stage, raw, shader_file.entry_point));
+                continue;
+            }
+
+            let bytecode = match read_binary_file(shader_file.spirv_out, false /* debug */) {
+                Ok(b) => b,
+                Err(e) => {
+                    println!("Unable to read SPIR-V {} for {}: {}",
+                             shader_file.spirv_out,
+                             self.shader_name,
+                             e);
+                    self.shader_modules_raw.clear();
+                    self.shader_modules.clear();
+                    return;
+                }
+            };
             let shader_module = RendererVkShaderModule::new(self.device, &bytecode);
-            self.shader_modules_raw.push((shader_file.shader_stage, shader_module.get_raw()));
+            self.shader_modules_raw.push((shader_file.shader_stage, shader_module.get_raw(), shader_file.entry_point));
+            module_indices.insert(shader_file.spirv_out, self.shader_modules.len());
             self.shader_modules.push(shader_module);
         }
     }
 
     /// Check whether the shader needs to be recompiled
     ///
+    /// Sources that are newer than their SPIR-V are recompiled, and when any SPIR-V has changed
+    /// the reflection data is read again and the renderer is asked to rebuild the pipeline at
+    /// the start of the next frame.  A failure to compile leaves the previous pipeline in use.
+    ///
     /// autos: The automatically generated resources object, only used for
     ///     checking whther the resources have been "baked in"
     /// renderer: The renderer object
     /// resource_manager: The shader resource manager
     fn check_for_rebuild(&mut self,
-                         _: Option<&EmbeddedResources>,
-                         _: &Box<Renderer>,
-                         _: &Arc<Mutex<Box<ResourceManager>>>)
+                         autos: Option<&EmbeddedResources>,
+                         renderer: &Box<Renderer>,
+                         resource_manager: &Arc<Mutex<Box<ResourceManager>>>)
                          -> bool {
-        // There is no shader recompilation at runtime for Vulkan as the moment
-        false
+        match autos {
+            Some(ref autos) if autos.use_me() => return false, // The resources are not on disk
+            _ => (),
+        };
+
+        let spec = resource_manager.lock().unwrap().shader_specs[self.shader_name].clone();
+
+        let mut all_succeeded = true;
+        ShaderSpirv::compile_shader_resource(&spec, true /* conditionally */, 0, &mut all_succeeded);
+        if !all_succeeded {
+            println!("Keeping the previous pipeline for {} after the rebuild failed",
+                     self.shader_name);
+            return false;
+        }
+
+        let changed = self.shader_files.iter().any(|shader_file| {
+            match get_last_modification_timestamp(shader_file.spirv_out) {
+                Ok(t) => t > self.spirv_mod_times[shader_file.spirv_out],
+                Err(_) => false,
+            }
+        });
+        if !changed {
+            return false;
+        }
+
+        println!("Reloading {}", self.shader_name);
+
+        let mut stages = vec![];
+        for shader_file in self.shader_files.iter() {
+            match read_binary_file(shader_file.spirv_out, false /* debug */) {
+                Ok(bytecode) => {
+                    stages.push((shader_file.shader_stage, bytecode, shader_file.entry_point, shader_file.spirv_out))
+                }
+                Err(e) => {
+                    println!("Unable to read SPIR-V {} for {}: {}",
+                             shader_file.spirv_out,
+                             self.shader_name,
+                             e);
+                    return false;
+                }
+            }
+        }
+
+        resource_manager.lock().unwrap().read_reflection_data(false /* debug */);
+
+        // Replace the local modules, sharing them between stages as when first built
+        //
+        self.shader_modules_raw.clear();
+        self.shader_modules.clear();
+        let mut module_indices: HashMap<&'static str, usize> = HashMap::new();
+        for &(shader_stage, ref bytecode, entry_point, spirv_out) in stages.iter() {
+            if !module_indices.contains_key(spirv_out) {
+                module_indices.insert(spirv_out, self.shader_modules.len());
+                self.shader_modules.push(RendererVkShaderModule::new(self.device, bytecode));
+            }
+            let raw = self.shader_modules[module_indices[spirv_out]].get_raw();
+            self.shader_modules_raw.push((shader_stage, raw, entry_point));
+        }
+
+        for shader_file in self.shader_files.iter() {
+            let timestamp = get_last_modification_timestamp(shader_file.spirv_out).unwrap_or(UNIX_EPOCH);
+            self.spirv_mod_times.insert(shader_file.spirv_out, timestamp);
+        }
+
+        let renderer_vk: &RendererVk = match renderer.as_any().downcast_ref::<RendererVk>() {
+            Some(r) => r,
+            None => panic!("Unexpected runtime type"),
+        };
+        let stages = stages.into_iter().map(|(stage, bytecode, entry_point, _)| (stage, bytecode, entry_point)).collect();
+        renderer_vk.request_pipeline_rebuild(self.shader_name, stages);
+
+        true
     }
 
     /// Tell the renderer to use the shader
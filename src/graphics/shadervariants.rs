@@ -0,0 +1,395 @@
+// Copyright (c) 2016-2017 Bruce Stenning. All rights reserved.
+//
+// Redistribution and use in source and binary forms, with or without
+// modification, are permitted provided that the following conditions
+// are met:
+//
+// 1. Redistributions of source code must retain the above copyright
+//    notice, this list of conditions and the following disclaimer.
+//
+// 2. Redistributions in binary form must reproduce the above copyright
+//    notice, this list of conditions and the following disclaimer in the
+//    documentation and/or other materials provided with the distribution.
+//
+// 3. Neither the name of the copyright holder nor the names of its
+//    contributors may be used to endorse or promote products derived
+//    from this software without specific prior written permission.
+//
+// THIS SOFTWARE IS PROVIDED BY THE COPYRIGHT HOLDERS AND CONTRIBUTORS
+// AS IS" AND ANY EXPRESS OR IMPLIED WARRANTIES, INCLUDING, BUT NOT
+// LIMITED TO, THE IMPLIED WARRANTIES OF MERCHANTABILITY AND FITNESS
+// FOR A PARTICULAR PURPOSE ARE DISCLAIMED. IN NO EVENT SHALL THE
+// COPYRIGHT HOLDER OR CONTRIBUTORS BE LIABLE FOR ANY DIRECT, INDIRECT,
+// INCIDENTAL, SPECIAL, EXEMPLARY, OR CONSEQUENTIAL DAMAGES (INCLUDING,
+// BUT NOT LIMITED TO, PROCUREMENT OF SUBSTITUTE GOODS OR SERVICES; LOSS
+// OF USE, DATA, OR PROFITS; OR BUSINESS INTERRUPTION) HOWEVER CAUSED
+// AND ON ANY THEORY OF LIABILITY, WHETHER IN CONTRACT, STRICT LIABILITY,
+// OR TORT (INCLUDING NEGLIGENCE OR OTHERWISE) ARISING IN ANY WAY OUT OF
+// THE USE OF THIS SOFTWARE, EVEN IF ADVISED OF THE POSSIBILITY OF SUCH
+// DAMAGE.
+
+use std::collections::{HashMap, HashSet};
+use std::fmt;
+use std::fs;
+
+use graphics::resources::*;
+use graphics::shader::ShaderLanguage;
+use misc::fileutils::*;
+
+/// The most options a shader can be declared with, as every permutation of them may be built
+pub const SHADER_VARIANT_MAX_OPTIONS: usize = 8;
+
+/// The defines a variant of a shader is compiled with
+///
+/// The defines are kept sorted by name, so that a set of them names the same variant whatever
+/// order it was built in.
+#[derive(Clone, Debug, Default, PartialEq, Eq, Hash)]
+pub struct ShaderDefines {
+    defines: Vec<(&'static str, String)>,
+}
+
+impl ShaderDefines {
+    pub fn new() -> ShaderDefines {
+        ShaderDefines { defines: vec![] }
+    }
+
+    /// Create a set of defines that are each set to 1
+    ///
+    /// flags: The names to define
+    pub fn from_flags(flags: &[&'static str]) -> ShaderDefines {
+        flags.iter().fold(ShaderDefines::new(), |defines, flag| defines.flag(flag))
+    }
+
+    /// Define a name as 1, for features tested with #ifdef or #if
+    ///
+    /// name: The name to define
+    pub fn flag(self, name: &'static str) -> ShaderDefines {
+        self.value(name, "1")
+    }
+
+    /// Define a name as a value, replacing any value it already has
+    ///
+    /// name: The name to define
+    /// value: The value to define it as
+    pub fn value(mut self, name: &'static str, value: &str) -> ShaderDefines {
+        match self.defines.binary_search_by(|d| d.0.cmp(name)) {
+            Ok(index) => self.defines[index].1 = value.to_string(),
+            Err(index) => self.defines.insert(index, (name, value.to_string())),
+        }
+        self
+    }
+
+    /// Return whether nothing is defined, which is the base shader
+    pub fn is_empty(&self) -> bool {
+        self.defines.is_empty()
+    }
+
+    /// Return the value of a define
+    ///
+    /// name: The name of the define
+    pub fn get(&self, name: &str) -> Option<&str> {
+        self.defines.iter().find(|d| d.0 == name).map(|d| d.1.as_str())
+    }
+
+    /// Return the names that are defined, in order
+    pub fn names(&self) -> Vec<&'static str> {
+        self.defines.iter().map(|d| d.0).collect()
+    }
+
+    /// Check that every name is a GLSL identifier and every value a single token
+    pub fn validate(&self) -> Result<(), ShaderVariantError> {
+        for &(name, ref value) in self.defines.iter() {
+            let identifier = name.chars().next().map_or(false, |c| c.is_ascii_alphabetic() || c == '_') &&
+                             name.chars().all(|c| c.is_ascii_alphanumeric() || c == '_');
+            let token = !value.is_empty() && value.chars().all(|c| c.is_ascii_alphanumeric() || c == '_' || c == '.');
+            if !identifier || !token {
+                return Err(ShaderVariantError::InvalidDefine(format!("{}={}", name, value)));
+            }
+        }
+        Ok(())
+    }
+
+    /// Return the GLSL that defines each name, to precede the sources of the shader
+    pub fn preamble(&self) -> String {
+        self.defines.iter().map(|&(name, ref value)| format!("#define {} {}\n", name, value)).collect()
+    }
+
+    /// Return a tag identifying the defines in the names of the variant and its files
+    ///
+    /// Flags appear by name alone, and other values after a hyphen.
+    pub fn tag(&self) -> String {
+        self.defines
+            .iter()
+            .map(|&(name, ref value)| if value == "1" {
+                name.to_string()
+            } else {
+                format!("{}-{}", name, value)
+            })
+            .collect::<Vec<String>>()
+            .join("_")
+    }
+
+    /// Return every combination of a set of flags, starting with none of them
+    ///
+    /// options: The flags that may be defined
+    pub fn permutations(options: &[&'static str]) -> Vec<ShaderDefines> {
+        (0..1usize << options.len())
+            .map(|mask| {
+                options.iter()
+                    .enumerate()
+                    .filter(|&(bit, _)| mask & (1 << bit) != 0)
+                    .fold(ShaderDefines::new(), |defines, (_, option)| defines.flag(option))
+            })
+            .collect()
+    }
+}
+
+/// Return the name a variant of a shader is registered under
+///
+/// The base shader keeps its own name.
+///
+/// base: The name of the base shader
+/// defines: The defines of the variant
+pub fn shader_variant_name(base: &str, defines: &ShaderDefines) -> String {
+    if defines.is_empty() {
+        base.to_string()
+    } else {
+        format!("{}[{}]", base, defines.tag())
+    }
+}
+
+/// Return the name of a file generated for a variant, e.g. its SPIR-V or the defines
+///
+/// base: The name of the base shader
+/// defines: The defines of the variant
+/// filename: The name of the file of the base shader
+pub fn shader_variant_filename(base: &str, defines: &ShaderDefines, filename: &str) -> String {
+    format!("{}.{}.{}", base, defines.tag(), filename)
+}
+
+/// The reasons a variant of a shader cannot be registered
+#[derive(Clone, Debug, PartialEq)]
+pub enum ShaderVariantError {
+    /// There is no shader of the name to derive variants from
+    UnknownShader(String),
+    /// The define is not one of the options declared for the shader
+    UnknownOption {
+        shader: &'static str,
+        option: &'static str,
+    },
+    /// The define is not a GLSL identifier, or its value is not a single token
+    InvalidDefine(String),
+    /// The shader is declared with more than SHADER_VARIANT_MAX_OPTIONS options
+    TooManyOptions(&'static str),
+    /// The shader has HLSL sources, which are compiled without the library files the defines are in
+    HlslShader(&'static str),
+}
+
+impl fmt::Display for ShaderVariantError {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match *self {
+            ShaderVariantError::UnknownShader(ref name) => write!(f, "There is no shader {} to make a variant of", name),
+            ShaderVariantError::UnknownOption { shader, option } => {
+                write!(f, "{} is not an option of shader {}", option, shader)
+            }
+            ShaderVariantError::InvalidDefine(ref define) => write!(f, "{} is not a valid define", define),
+            ShaderVariantError::TooManyOptions(shader) => {
+                write!(f, "Shader {} has more than {} options", shader, SHADER_VARIANT_MAX_OPTIONS)
+            }
+            ShaderVariantError::HlslShader(shader) => write!(f, "Shader {} has HLSL sources, which can't vary", shader),
+        }
+    }
+}
+
+/// The variants registered for each shader, keyed by their defines
+///
+/// Each variant is an ordinary shader specification in the resource manager, so it is built
+/// along with the other shaders and its SPIR-V is only recompiled when a source has changed.
+/// Only the variants that are registered are built, rather than every permutation.
+pub struct ShaderVariants {
+    options: HashMap<&'static str, Vec<&'static str>>, // The defines each shader may vary by, when declared
+    variants: HashMap<(&'static str, ShaderDefines), &'static str>,
+    reported: HashSet<(&'static str, ShaderDefines)>, // Missing variants already logged
+}
+
+impl ShaderVariants {
+    pub fn new() -> ShaderVariants {
+        ShaderVariants {
+            options: HashMap::new(),
+            variants: HashMap::new(),
+            reported: HashSet::new(),
+        }
+    }
+
+    /// Declare the defines a shader may vary by, after which others are rejected
+    ///
+    /// base: The name of the base shader
+    /// options: The names of the defines
+    pub fn declare_options(&mut self, base: &'static str, options: &[&'static str]) -> Result<(), ShaderVariantError> {
+        if options.len() > SHADER_VARIANT_MAX_OPTIONS {
+            return Err(ShaderVariantError::TooManyOptions(base));
+        }
+        self.options.insert(base, options.to_vec());
+        Ok(())
+    }
+
+    /// Return the declared options of a shader
+    ///
+    /// base: The name of the base shader
+    pub fn options(&self, base: &str) -> Option<&Vec<&'static str>> {
+        self.options.get(base)
+    }
+
+    /// Check that a set of defines is valid for a shader
+    ///
+    /// base: The name of the base shader
+    /// defines: The defines of the variant
+    pub fn check(&self, base: &'static str, defines: &ShaderDefines) -> Result<(), ShaderVariantError> {
+        defines.validate()?;
+        if let Some(options) = self.options.get(base) {
+            if let Some(option) = defines.names().into_iter().find(|name| !options.contains(name)) {
+                return Err(ShaderVariantError::UnknownOption {
+                    shader: base,
+                    option: option,
+                });
+            }
+        }
+        Ok(())
+    }
+
+    /// Return the name of a registered variant, or of the base shader when there are no defines
+    ///
+    /// base: The name of the base shader
+    /// defines: The defines of the variant
+    pub fn find(&self, base: &'static str, defines: &ShaderDefines) -> Option<&'static str> {
+        if defines.is_empty() {
+            return Some(base);
+        }
+        self.variants.get(&(base, defines.clone())).cloned()
+    }
+
+    /// Record the name a variant is registered under
+    ///
+    /// base: The name of the base shader
+    /// defines: The defines of the variant
+    /// name: The name of the variant's shader specification
+    pub fn insert(&mut self, base: &'static str, defines: &ShaderDefines, name: &'static str) {
+        self.variants.insert((base, defines.clone()), name);
+    }
+
+    /// Return the number of variants registered, across all shaders
+    pub fn len(&self) -> usize {
+        self.variants.len()
+    }
+
+    /// Note that a variant was asked for without being registered, returning whether this is
+    /// the first time, so that it is reported once rather than every frame
+    ///
+    /// base: The name of the base shader
+    /// defines: The defines of the variant
+    pub fn report_missing(&mut self, base: &'static str, defines: &ShaderDefines) -> bool {
+        self.reported.insert((base, defines.clone()))
+    }
+
+    /// Return the name the renderer should begin a pass of a variant with
+    ///
+    /// A variant that is not registered falls back to the base shader, and is logged once.
+    ///
+    /// base: The name of the base shader
+    /// defines: The defines of the variant
+    pub fn resolve(&mut self, base: &'static str, defines: &ShaderDefines) -> &'static str {
+        match self.find(base, defines) {
+            Some(name) => name,
+            None => {
+                if self.report_missing(base, defines) {
+                    log_warning!("Variant {} is not registered, so {} is used instead",
+                                 shader_variant_name(base, defines),
+                                 base);
+                }
+                base
+            }
+        }
+    }
+}
+
+/// Return the specification of a variant of a shader
+///
+/// The variant reads the same sources, preceded by a library file holding the defines, and
+/// writes SPIR-V and reflection data of its own.  The names are leaked, as shader names live for
+/// the rest of the program.
+///
+/// base_spec: The specification of the base shader
+/// defines: The defines of the variant
+/// defines_filename: The file holding the preamble of the defines
+pub fn shader_variant_spec(base_spec: &ShaderSpec, defines: &ShaderDefines, defines_filename: &'static str) -> ShaderSpec {
+    fn leak(s: String) -> &'static str {
+        Box::leak(s.into_boxed_str())
+    }
+
+    let mut spec = base_spec.clone();
+    spec.name = leak(shader_variant_name(base_spec.name, defines));
+    spec.library_files.insert(0, defines_filename);
+    for shader_file in spec.shader_files.iter_mut().chain(spec.fallback_shader_files.iter_mut().flat_map(|f| f.iter_mut())) {
+        shader_file.spirv_out = leak(shader_variant_filename(base_spec.name, defines, shader_file.spirv_out));
+        shader_file.reflect_out = leak(shader_variant_filename(base_spec.name, defines, shader_file.reflect_out));
+    }
+    spec
+}
+
+/// Add the specification of a variant of a shader, returning the name it is registered under
+///
+/// This must be called before the renderer is set up, like the other shader registrations.  A
+/// variant that is already registered is not added again.  The defines are written to a file
+/// in the working directory, which is left alone when it is up to date so the SPIR-V of the
+/// variant is not recompiled needlessly.
+///
+/// resource_manager: The resource manager holding the base shader
+/// base: The name of the base shader
+/// defines: The defines of the variant
+pub fn register_shader_variant(resource_manager: &mut ResourceManager,
+                               base: &'static str,
+                               defines: &ShaderDefines)
+                               -> Result<&'static str, ShaderVariantError> {
+    if let Some(name) = resource_manager.shader_variants.find(base, defines) {
+        return Ok(name);
+    }
+    resource_manager.shader_variants.check(base, defines)?;
+
+    let spec = match resource_manager.shader_specs.get(base) {
+        Some(base_spec) => {
+            if base_spec.shader_files.iter().any(|f| ShaderLanguage::from_filename(f.filename) == ShaderLanguage::Hlsl) {
+                return Err(ShaderVariantError::HlslShader(base));
+            }
+
+            let defines_filename = shader_variant_filename(base, defines, "defines.glsl");
+            let preamble = defines.preamble();
+            if fs::read_to_string(&defines_filename).ok().as_ref() != Some(&preamble) {
+                write_entire_file(&preamble, &defines_filename).expect("Failed to write shader variant defines");
+            }
+            shader_variant_spec(base_spec, defines, Box::leak(defines_filename.into_boxed_str()))
+        }
+        None => return Err(ShaderVariantError::UnknownShader(base.to_string())),
+    };
+
+    let name = spec.name;
+    resource_manager.shader_specs.insert(name, spec);
+    resource_manager.shader_variants.insert(base, defines, name);
+    Ok(name)
+}
+
+/// Declare the options of a shader and add a specification for every permutation of them
+///
+/// resource_manager: The resource manager holding the base shader
+/// base: The name of the base shader
+/// options: The names of the defines, each of which is on or off
+pub fn register_shader_permutations(resource_manager: &mut ResourceManager,
+                                    base: &'static str,
+                                    options: &[&'static str])
+                                    -> Result<Vec<&'static str>, ShaderVariantError> {
+    resource_manager.shader_variants.declare_options(base, options)?;
+
+    ShaderDefines::permutations(options)
+        .iter()
+        .map(|defines| register_shader_variant(resource_manager, base, defines))
+        .collect()
+}
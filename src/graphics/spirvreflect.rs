@@ -0,0 +1,609 @@
+// Copyright (c) 2016-2017 Bruce Stenning. All rights reserved.
+//
+// Redistribution and use in source and binary forms, with or without
+// modification, are permitted provided that the following conditions
+// are met:
+//
+// 1. Redistributions of source code must retain the above copyright
+//    notice, this list of conditions and the following disclaimer.
+//
+// 2. Redistributions in binary form must reproduce the above copyright
+//    notice, this list of conditions and the following disclaimer in the
+//    documentation and/or other materials provided with the distribution.
+//
+// 3. Neither the name of the copyright holder nor the names of its
+//    contributors may be used to endorse or promote products derived
+//    from this software without specific prior written permission.
+//
+// THIS SOFTWARE IS PROVIDED BY THE COPYRIGHT HOLDERS AND CONTRIBUTORS
+// AS IS" AND ANY EXPRESS OR IMPLIED WARRANTIES, INCLUDING, BUT NOT
+// LIMITED TO, THE IMPLIED WARRANTIES OF MERCHANTABILITY AND FITNESS
+// FOR A PARTICULAR PURPOSE ARE DISCLAIMED. IN NO EVENT SHALL THE
+// COPYRIGHT HOLDER OR CONTRIBUTORS BE LIABLE FOR ANY DIRECT, INDIRECT,
+// INCIDENTAL, SPECIAL, EXEMPLARY, OR CONSEQUENTIAL DAMAGES (INCLUDING,
+// BUT NOT LIMITED TO, PROCUREMENT OF SUBSTITUTE GOODS OR SERVICES; LOSS
+// OF USE, DATA, OR PROFITS; OR BUSINESS INTERRUPTION) HOWEVER CAUSED
+// AND ON ANY THEORY OF LIABILITY, WHETHER IN CONTRACT, STRICT LIABILITY,
+// OR TORT (INCLUDING NEGLIGENCE OR OTHERWISE) ARISING IN ANY WAY OUT OF
+// THE USE OF THIS SOFTWARE, EVEN IF ADVISED OF THE POSSIBILITY OF SUCH
+// DAMAGE.
+
+use std::collections::{HashMap, HashSet};
+use std::fmt;
+use std::io;
+
+use graphics::resources::*;
+use misc::fileutils::*;
+
+/// The first word of every SPIR-V module
+pub const SPIRV_MAGIC: u32 = 0x07230203;
+
+// The opcodes of the instructions the reflection reads
+const OP_NAME: u32 = 5;
+const OP_MEMBER_NAME: u32 = 6;
+const OP_TYPE_BOOL: u32 = 20;
+const OP_TYPE_INT: u32 = 21;
+const OP_TYPE_FLOAT: u32 = 22;
+const OP_TYPE_VECTOR: u32 = 23;
+const OP_TYPE_MATRIX: u32 = 24;
+const OP_TYPE_IMAGE: u32 = 25;
+const OP_TYPE_SAMPLER: u32 = 26;
+const OP_TYPE_SAMPLED_IMAGE: u32 = 27;
+const OP_TYPE_ARRAY: u32 = 28;
+const OP_TYPE_RUNTIME_ARRAY: u32 = 29;
+const OP_TYPE_STRUCT: u32 = 30;
+const OP_TYPE_POINTER: u32 = 32;
+const OP_CONSTANT: u32 = 43;
+const OP_VARIABLE: u32 = 59;
+const OP_DECORATE: u32 = 71;
+const OP_MEMBER_DECORATE: u32 = 72;
+
+// The decorations the reflection reads
+const DECORATION_BLOCK: u32 = 2;
+const DECORATION_BUFFER_BLOCK: u32 = 3;
+const DECORATION_ROW_MAJOR: u32 = 4;
+const DECORATION_ARRAY_STRIDE: u32 = 6;
+const DECORATION_MATRIX_STRIDE: u32 = 7;
+const DECORATION_BINDING: u32 = 33;
+const DECORATION_DESCRIPTOR_SET: u32 = 34;
+const DECORATION_OFFSET: u32 = 35;
+
+// The storage classes of the variables the reflection reads
+const STORAGE_CLASS_UNIFORM_CONSTANT: u32 = 0;
+const STORAGE_CLASS_UNIFORM: u32 = 2;
+const STORAGE_CLASS_PUSH_CONSTANT: u32 = 9;
+const STORAGE_CLASS_STORAGE_BUFFER: u32 = 12;
+
+/// A type declared by a module, as far as the layout of blocks needs it
+#[derive(Clone, Debug)]
+enum SpirvType {
+    Scalar(usize), // Size in bytes
+    Vector(u32, u32), // Component type and count
+    Matrix(u32, u32), // Column type and count
+    Array(u32, u32), // Element type and the constant holding the length
+    RuntimeArray(u32), // Element type
+    Struct(Vec<u32>), // Member types
+    Pointer(u32, u32), // Storage class and pointee type
+    Image,
+    Sampler,
+    SampledImage,
+}
+
+/// A member of a block, with its layout
+#[derive(Clone, Debug, PartialEq)]
+pub struct ReflectedMember {
+    pub name: String,
+    pub offset: usize,
+    pub size: usize, // Zero for a runtime array
+    pub stride: usize, // The array stride, or zero if the member is not an array
+}
+
+/// A uniform, storage or push constant block declared by a module
+#[derive(Clone, Debug, PartialEq)]
+pub struct ReflectedBlock {
+    pub name: String, // The name of the block's type, which the uniform block specifications are keyed by
+    pub block_type: UniformType,
+    pub push_constant: bool,
+    pub set: Option<u32>, // None for a push constant block
+    pub binding: Option<u32>,
+    pub size: usize,
+    pub members: Vec<ReflectedMember>,
+}
+
+impl ReflectedBlock {
+    /// Return a member of the block by name
+    ///
+    /// name: The name of the member
+    pub fn member(&self, name: &str) -> Option<&ReflectedMember> {
+        self.members.iter().find(|m| m.name == name)
+    }
+}
+
+/// An opaque uniform declared by a module, such as a sampler
+#[derive(Clone, Debug, PartialEq)]
+pub struct ReflectedUniform {
+    pub name: String,
+    pub uniform_type: UniformType,
+    pub set: Option<u32>,
+    pub binding: Option<u32>,
+}
+
+/// The descriptor sets, bindings and block layouts decoded from SPIR-V modules
+///
+/// This reads the decorations of the modules themselves, so it describes whatever compiler
+/// produced them, GLSL and HLSL alike.
+#[derive(Clone, Debug, Default, PartialEq)]
+pub struct SpirvReflection {
+    pub blocks: Vec<ReflectedBlock>,
+    pub uniforms: Vec<ReflectedUniform>,
+}
+
+impl SpirvReflection {
+    /// Decode the blocks and opaque uniforms of a SPIR-V module
+    ///
+    /// bytecode: The module, in either byte order
+    pub fn parse(bytecode: &Vec<u8>) -> Result<SpirvReflection, io::Error> {
+        if bytecode.len() % 4 != 0 || bytecode.len() < 20 {
+            return Err(io::Error::new(io::ErrorKind::InvalidData, "Not a SPIR-V module"));
+        }
+
+        let mut offset = 0;
+        let mut words: Vec<u32> = Vec::with_capacity(bytecode.len() / 4);
+        while offset < bytecode.len() {
+            words.push(read_u32(bytecode, &mut offset)?);
+        }
+        if words[0] == SPIRV_MAGIC.swap_bytes() {
+            for word in words.iter_mut() {
+                *word = word.swap_bytes();
+            }
+        } else if words[0] != SPIRV_MAGIC {
+            return Err(io::Error::new(io::ErrorKind::InvalidData, "Not a SPIR-V module"));
+        }
+
+        let mut names: HashMap<u32, String> = HashMap::new();
+        let mut member_names: HashMap<(u32, u32), String> = HashMap::new();
+        let mut decorations: HashMap<(u32, u32), u32> = HashMap::new(); // The first operand, or zero
+        let mut member_decorations: HashMap<(u32, u32, u32), u32> = HashMap::new();
+        let mut types: HashMap<u32, SpirvType> = HashMap::new();
+        let mut constants: HashMap<u32, u32> = HashMap::new();
+        let mut variables: Vec<(u32, u32, u32)> = vec![]; // Pointer type, result and storage class
+
+        // The instructions follow the five words of the header
+        //
+        let mut index = 5;
+        while index < words.len() {
+            let word_count = (words[index] >> 16) as usize;
+            let opcode = words[index] & 0xffff;
+            if word_count == 0 || index + word_count > words.len() {
+                return Err(io::Error::new(io::ErrorKind::InvalidData, "Truncated SPIR-V instruction"));
+            }
+            let operands = &words[index + 1..index + word_count];
+            index += word_count;
+
+            let operand = |i: usize| operands.get(i).cloned().unwrap_or(0);
+            match opcode {
+                OP_NAME if !operands.is_empty() => {
+                    names.insert(operands[0], decode_string(&operands[1..]));
+                }
+                OP_MEMBER_NAME if operands.len() > 1 => {
+                    member_names.insert((operands[0], operands[1]), decode_string(&operands[2..]));
+                }
+                OP_DECORATE if operands.len() > 1 => {
+                    decorations.insert((operands[0], operands[1]), operand(2));
+                }
+                OP_MEMBER_DECORATE if operands.len() > 2 => {
+                    member_decorations.insert((operands[0], operands[1], operands[2]), operand(3));
+                }
+                OP_TYPE_BOOL => {
+                    types.insert(operand(0), SpirvType::Scalar(4));
+                }
+                OP_TYPE_INT | OP_TYPE_FLOAT => {
+                    types.insert(operand(0), SpirvType::Scalar(operand(1) as usize / 8));
+                }
+                OP_TYPE_VECTOR => {
+                    types.insert(operand(0), SpirvType::Vector(operand(1), operand(2)));
+                }
+                OP_TYPE_MATRIX => {
+                    types.insert(operand(0), SpirvType::Matrix(operand(1), operand(2)));
+                }
+                OP_TYPE_IMAGE => {
+                    types.insert(operand(0), SpirvType::Image);
+                }
+                OP_TYPE_SAMPLER => {
+                    types.insert(operand(0), SpirvType::Sampler);
+                }
+                OP_TYPE_SAMPLED_IMAGE => {
+                    types.insert(operand(0), SpirvType::SampledImage);
+                }
+                OP_TYPE_ARRAY => {
+                    types.insert(operand(0), SpirvType::Array(operand(1), operand(2)));
+                }
+                OP_TYPE_RUNTIME_ARRAY => {
+                    types.insert(operand(0), SpirvType::RuntimeArray(operand(1)));
+                }
+                OP_TYPE_STRUCT if !operands.is_empty() => {
+                    types.insert(operands[0], SpirvType::Struct(operands[1..].to_vec()));
+                }
+                OP_TYPE_POINTER => {
+                    types.insert(operand(0), SpirvType::Pointer(operand(1), operand(2)));
+                }
+                OP_CONSTANT => {
+                    constants.insert(operand(1), operand(2));
+                }
+                OP_VARIABLE => {
+                    variables.push((operand(0), operand(1), operand(2)));
+                }
+                _ => {}
+            }
+        }
+
+        let layout = SpirvLayout {
+            types: &types,
+            constants: &constants,
+            decorations: &decorations,
+            member_decorations: &member_decorations,
+        };
+
+        let mut reflection = SpirvReflection::default();
+        for &(pointer_type, variable, storage_class) in variables.iter() {
+            let pointee = match types.get(&pointer_type) {
+                Some(&SpirvType::Pointer(_, pointee)) => pointee,
+                _ => continue,
+            };
+            let set = decorations.get(&(variable, DECORATION_DESCRIPTOR_SET)).cloned();
+            let binding = decorations.get(&(variable, DECORATION_BINDING)).cloned();
+
+            let block_type = match storage_class {
+                STORAGE_CLASS_UNIFORM if decorations.contains_key(&(pointee, DECORATION_BUFFER_BLOCK)) => {
+                    Some(UniformType::StorageBuffer)
+                }
+                STORAGE_CLASS_UNIFORM => Some(UniformType::UniformBuffer),
+                STORAGE_CLASS_STORAGE_BUFFER => Some(UniformType::StorageBuffer),
+                STORAGE_CLASS_PUSH_CONSTANT => Some(UniformType::UniformBuffer),
+                STORAGE_CLASS_UNIFORM_CONSTANT => None,
+                _ => continue,
+            };
+
+            match block_type {
+                Some(block_type) => {
+                    let members = match types.get(&pointee) {
+                        Some(&SpirvType::Struct(ref members)) => members,
+                        _ => continue,
+                    };
+                    if !decorations.contains_key(&(pointee, DECORATION_BLOCK)) &&
+                       !decorations.contains_key(&(pointee, DECORATION_BUFFER_BLOCK)) {
+                        continue;
+                    }
+
+                    let reflected_members = members.iter()
+                        .enumerate()
+                        .map(|(i, &member_type)| {
+                            let member = i as u32;
+                            ReflectedMember {
+                                name: member_names.get(&(pointee, member)).cloned().unwrap_or(format!("_m{}", member)),
+                                offset: layout.member_decoration(pointee, member, DECORATION_OFFSET) as usize,
+                                size: layout.member_size(pointee, member, member_type),
+                                stride: layout.decoration(member_type, DECORATION_ARRAY_STRIDE) as usize,
+                            }
+                        })
+                        .collect();
+
+                    let push_constant = storage_class == STORAGE_CLASS_PUSH_CONSTANT;
+                    reflection.blocks.push(ReflectedBlock {
+                        name: names.get(&pointee).or(names.get(&variable)).cloned().unwrap_or(format!("_{}", variable)),
+                        block_type: block_type,
+                        push_constant: push_constant,
+                        set: if push_constant { None } else { set },
+                        binding: if push_constant { None } else { binding },
+                        size: layout.type_size(pointee, None),
+                        members: reflected_members,
+                    });
+                }
+                None => {
+                    // Arrays of opaque types are bound as a whole
+                    let mut element = pointee;
+                    while let Some(&SpirvType::Array(e, _)) = types.get(&element) {
+                        element = e;
+                    }
+                    while let Some(&SpirvType::RuntimeArray(e)) = types.get(&element) {
+                        element = e;
+                    }
+                    let uniform_type = match types.get(&element) {
+                        Some(&SpirvType::SampledImage) => UniformType::CombinedImageSampler,
+                        Some(&SpirvType::Image) => UniformType::SampledImage,
+                        Some(&SpirvType::Sampler) => UniformType::Sampler,
+                        _ => continue,
+                    };
+
+                    reflection.uniforms.push(ReflectedUniform {
+                        name: names.get(&variable).cloned().unwrap_or(format!("_{}", variable)),
+                        uniform_type: uniform_type,
+                        set: set,
+                        binding: binding,
+                    });
+                }
+            }
+        }
+
+        Ok(reflection)
+    }
+
+    /// Add the blocks and uniforms of another module that this does not already have
+    ///
+    /// Stages of one shader declare the blocks they share identically, so the first stage
+    /// declaring a block is kept.
+    ///
+    /// other: The reflection of the other module
+    pub fn merge(&mut self, other: SpirvReflection) {
+        for block in other.blocks {
+            if self.block(&block.name).is_none() {
+                self.blocks.push(block);
+            }
+        }
+        for uniform in other.uniforms {
+            if self.uniform(&uniform.name).is_none() {
+                self.uniforms.push(uniform);
+            }
+        }
+    }
+
+    /// Return a block by name
+    ///
+    /// name: The name of the block's type
+    pub fn block(&self, name: &str) -> Option<&ReflectedBlock> {
+        self.blocks.iter().find(|b| b.name == name)
+    }
+
+    /// Return an opaque uniform by name
+    ///
+    /// name: The name of the uniform
+    pub fn uniform(&self, name: &str) -> Option<&ReflectedUniform> {
+        self.uniforms.iter().find(|u| u.name == name)
+    }
+}
+
+/// The types and decorations of a module, from which the sizes of types in blocks are found
+struct SpirvLayout<'a> {
+    types: &'a HashMap<u32, SpirvType>,
+    constants: &'a HashMap<u32, u32>,
+    decorations: &'a HashMap<(u32, u32), u32>,
+    member_decorations: &'a HashMap<(u32, u32, u32), u32>,
+}
+
+impl<'a> SpirvLayout<'a> {
+    fn decoration(&self, id: u32, decoration: u32) -> u32 {
+        self.decorations.get(&(id, decoration)).cloned().unwrap_or(0)
+    }
+
+    fn member_decoration(&self, structure: u32, member: u32, decoration: u32) -> u32 {
+        self.member_decorations.get(&(structure, member, decoration)).cloned().unwrap_or(0)
+    }
+
+    /// Return the size of a member of a structure, whose matrices are laid out by its decorations
+    fn member_size(&self, structure: u32, member: u32, member_type: u32) -> usize {
+        let matrix_stride = match self.member_decorations.get(&(structure, member, DECORATION_MATRIX_STRIDE)) {
+            Some(&stride) => {
+                let row_major = self.member_decorations.contains_key(&(structure, member, DECORATION_ROW_MAJOR));
+                Some((stride as usize, row_major))
+            }
+            None => None,
+        };
+        self.type_size(member_type, matrix_stride)
+    }
+
+    /// Return the size of a type in a block
+    ///
+    /// Arrays are as long as their stride times their length, so a block's size includes the
+    /// padding after its last array element.
+    ///
+    /// id: The type
+    /// matrix_stride: The stride of a matrix, and whether it is row major, from the member holding it
+    fn type_size(&self, id: u32, matrix_stride: Option<(usize, bool)>) -> usize {
+        match self.types.get(&id) {
+            Some(&SpirvType::Scalar(size)) => size,
+            Some(&SpirvType::Vector(component, count)) => count as usize * self.type_size(component, None),
+            Some(&SpirvType::Matrix(column, columns)) => {
+                match matrix_stride {
+                    Some((stride, true)) => {
+                        let rows = match self.types.get(&column) {
+                            Some(&SpirvType::Vector(_, rows)) => rows,
+                            _ => 1,
+                        };
+                        rows as usize * stride
+                    }
+                    Some((stride, false)) => columns as usize * stride,
+                    None => columns as usize * self.type_size(column, None),
+                }
+            }
+            Some(&SpirvType::Array(element, length)) => {
+                let length = self.constants.get(&length).cloned().unwrap_or(0) as usize;
+                let stride = self.decoration(id, DECORATION_ARRAY_STRIDE) as usize;
+                if stride > 0 {
+                    length * stride
+                } else {
+                    length * self.type_size(element, matrix_stride)
+                }
+            }
+            Some(&SpirvType::Struct(ref members)) => {
+                members.iter()
+                    .enumerate()
+                    .map(|(i, &member_type)| {
+                        let member = i as u32;
+                        self.member_decoration(id, member, DECORATION_OFFSET) as usize +
+                        self.member_size(id, member, member_type)
+                    })
+                    .max()
+                    .unwrap_or(0)
+            }
+            _ => 0,
+        }
+    }
+}
+
+/// Decode a nul-terminated literal string from the words holding it
+fn decode_string(words: &[u32]) -> String {
+    let mut bytes: Vec<u8> = vec![];
+    'words: for word in words.iter() {
+        for i in 0..4 {
+            let byte = (word >> (i * 8)) as u8;
+            if byte == 0 {
+                break 'words;
+            }
+            bytes.push(byte);
+        }
+    }
+    String::from_utf8_lossy(&bytes).into_owned()
+}
+
+/// A difference between a handwritten specification and the layout reflected from the SPIR-V
+#[derive(Clone, Debug, PartialEq)]
+pub struct ReflectionMismatch {
+    pub shader: &'static str,
+    pub name: String, // The block or uniform
+    pub description: String,
+}
+
+impl fmt::Display for ReflectionMismatch {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        write!(f, "{}: {}: {}", self.shader, self.name, self.description)
+    }
+}
+
+/// Compare a uniform block specification with the block reflected from a shader
+///
+/// Bindings left unassigned in the specification, and members the shader does not declare,
+/// are not compared.  Block sizes are only compared once the specification has one.
+///
+/// shader: The name of the shader the block was reflected from
+/// block_name: The name of the block
+/// spec: The uniform block specification
+/// block: The reflected block
+pub fn verify_block_spec(shader: &'static str,
+                         block_name: &str,
+                         spec: &UniformBlockSpec,
+                         block: &ReflectedBlock)
+                         -> Vec<ReflectionMismatch> {
+    let mut mismatches = vec![];
+    let mut mismatch = |description: String| {
+        mismatches.push(ReflectionMismatch {
+            shader: shader,
+            name: block_name.to_string(),
+            description: description,
+        });
+    };
+
+    if spec.block_type != block.block_type {
+        mismatch("the block type differs".to_string());
+    }
+    if let Some(set) = block.set {
+        if spec.set != u32::max_value() && spec.set != set {
+            mismatch(format!("set {} is specified but the shader uses set {}", spec.set, set));
+        }
+    }
+    if let Some(binding) = block.binding {
+        if spec.binding != u32::max_value() && spec.binding != binding {
+            mismatch(format!("binding {} is specified but the shader uses binding {}", spec.binding, binding));
+        }
+    }
+    if spec.size != 0 && spec.size != block.size {
+        mismatch(format!("size {} is specified but the shader's block is {} bytes", spec.size, block.size));
+    }
+
+    let specified: HashSet<&str> = spec.uniforms.iter().map(|u| u.name).collect();
+    for uniform in spec.uniforms.iter() {
+        if let Some(member) = block.member(uniform.name) {
+            if uniform.offset != member.offset {
+                mismatch(format!("{} is specified at offset {} but is at offset {}",
+                                 uniform.name,
+                                 uniform.offset,
+                                 member.offset));
+            }
+            if uniform.stride != 0 && uniform.stride != member.stride {
+                mismatch(format!("{} is specified with stride {} but has stride {}",
+                                 uniform.name,
+                                 uniform.stride,
+                                 member.stride));
+            }
+        }
+    }
+    for member in block.members.iter() {
+        if !specified.contains(member.name.as_str()) {
+            mismatch(format!("{} is declared by the shader but not specified", member.name));
+        }
+    }
+
+    mismatches
+}
+
+/// Compare the push constant specifications of a shader with its reflected push constant block
+///
+/// shader: The name of the shader the block was reflected from
+/// push_constants: The push constant specifications
+/// block: The reflected push constant block
+pub fn verify_push_constants(shader: &'static str,
+                             push_constants: &Vec<PushConstantSpec>,
+                             block: &ReflectedBlock)
+                             -> Vec<ReflectionMismatch> {
+    let mut mismatches = vec![];
+    for push_constant in push_constants.iter() {
+        let description = match block.member(push_constant.name) {
+            Some(member) if member.offset != push_constant.offset || member.size != push_constant.size => {
+                format!("offset {} and size {} are specified but the shader has offset {} and size {}",
+                        push_constant.offset,
+                        push_constant.size,
+                        member.offset,
+                        member.size)
+            }
+            Some(_) => continue,
+            None => "is specified but not declared by the shader".to_string(),
+        };
+        mismatches.push(ReflectionMismatch {
+            shader: shader,
+            name: push_constant.name.to_string(),
+            description: description,
+        });
+    }
+
+    mismatches
+}
+
+/// Replace the set, binding, size and member offsets of a uniform block specification with
+/// those reflected from a shader
+///
+/// Members the shader does not declare keep their offsets.
+///
+/// spec: The uniform block specification to update
+/// block: The reflected block
+pub fn apply_block_reflection(spec: &mut UniformBlockSpec, block: &ReflectedBlock) {
+    if let Some(set) = block.set {
+        spec.set = set;
+    }
+    if let Some(binding) = block.binding {
+        spec.binding = binding;
+    }
+    spec.size = block.size;
+    for uniform in spec.uniforms.iter_mut() {
+        if let Some(member) = block.member(uniform.name) {
+            uniform.offset = member.offset;
+            uniform.stride = member.stride;
+        }
+    }
+}
+
+/// Reflect the SPIR-V modules of every stage of a shader
+///
+/// Stages sharing a module are only read once.
+///
+/// spec: The shader specification, whose modules must have been compiled
+pub fn reflect_shader(spec: &ShaderSpec) -> Result<SpirvReflection, io::Error> {
+    let mut reflection = SpirvReflection::default();
+    let mut read: HashSet<&'static str> = HashSet::new();
+    for shader_file in spec.shader_files.iter() {
+        if read.insert(shader_file.spirv_out) {
+            let bytecode = read_binary_file(shader_file.spirv_out, false)?;
+            reflection.merge(SpirvReflection::parse(&bytecode)?);
+        }
+    }
+
+    Ok(reflection)
+}
@@ -0,0 +1,267 @@
+// Copyright (c) 2016-2017 Bruce Stenning. All rights reserved.
+//
+// Redistribution and use in source and binary forms, with or without
+// modification, are permitted provided that the following conditions
+// are met:
+//
+// 1. Redistributions of source code must retain the above copyright
+//    notice, this list of conditions and the following disclaimer.
+//
+// 2. Redistributions in binary form must reproduce the above copyright
+//    notice, this list of conditions and the following disclaimer in the
+//    documentation and/or other materials provided with the distribution.
+//
+// 3. Neither the name of the copyright holder nor the names of its
+//    contributors may be used to endorse or promote products derived
+//    from this software without specific prior written permission.
+//
+// THIS SOFTWARE IS PROVIDED BY THE COPYRIGHT HOLDERS AND CONTRIBUTORS
+// AS IS" AND ANY EXPRESS OR IMPLIED WARRANTIES, INCLUDING, BUT NOT
+// LIMITED TO, THE IMPLIED WARRANTIES OF MERCHANTABILITY AND FITNESS
+// FOR A PARTICULAR PURPOSE ARE DISCLAIMED. IN NO EVENT SHALL THE
+// COPYRIGHT HOLDER OR CONTRIBUTORS BE LIABLE FOR ANY DIRECT, INDIRECT,
+// INCIDENTAL, SPECIAL, EXEMPLARY, OR CONSEQUENTIAL DAMAGES (INCLUDING,
+// BUT NOT LIMITED TO, PROCUREMENT OF SUBSTITUTE GOODS OR SERVICES; LOSS
+// OF USE, DATA, OR PROFITS; OR BUSINESS INTERRUPTION) HOWEVER CAUSED
+// AND ON ANY THEORY OF LIABILITY, WHETHER IN CONTRACT, STRICT LIABILITY,
+// OR TORT (INCLUDING NEGLIGENCE OR OTHERWISE) ARISING IN ANY WAY OUT OF
+// THE USE OF THIS SOFTWARE, EVEN IF ADVISED OF THE POSSIBILITY OF SUCH
+// DAMAGE.
+use std::io;
+
+use algebra::matrix::Mat4;
+use graphics::fullscreen::draw_fullscreen_quad;
+use graphics::renderer::*;
+use graphics::resources::*;
+use graphics::shader::*;
+use graphics::uniformlayout::BlockMemberType;
+use misc::fileutils::*;
+
+/// The name the screen-space reflections shader is registered under
+pub const SSR_SHADER_NAME: &'static str = "ssr";
+
+/// The name of the uniform block holding the camera matrices and reflection settings
+pub const SSR_SETTINGS_BLOCK: &'static str = "ssr_settings";
+
+/// The name of the texture the lighting result is sampled from, and reflected
+pub const SSR_COLOUR_TEXTURE: &'static str = "ssr_colour_texture";
+
+/// The name of the texture the scene depth buffer is sampled from
+pub const SSR_DEPTH_TEXTURE: &'static str = "ssr_depth_texture";
+
+/// The name of the G-buffer texture holding view-space normals, scaled to [0, 1] in RGB, with
+/// the roughness in alpha
+pub const SSR_NORMAL_TEXTURE: &'static str = "ssr_normal_texture";
+
+/// The name of the environment probe cubemap sampled where rays miss
+pub const SSR_ENVIRONMENT_TEXTURE: &'static str = "ssr_environment_texture";
+
+const SSR_VERTEX_FILENAME: &'static str = "ssr.vert";
+const SSR_FRAGMENT_FILENAME: &'static str = "ssr.frag";
+
+const SSR_VERTEX_SOURCE: &'static str = include_str!("shaders/ssr.vert");
+const SSR_FRAGMENT_SOURCE: &'static str = include_str!("shaders/ssr.frag");
+
+/// Presets trading the accuracy of the reflections for their cost
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub enum SsrQuality {
+    Low,
+    Medium,
+    High,
+    Ultra,
+}
+
+/// Settings for the screen-space reflections pass
+#[derive(Clone, Copy, Debug)]
+pub struct SsrSettings {
+    /// The number of steps each ray is marched before it is taken to have missed
+    pub max_steps: i32,
+    /// The number of bisection steps refining a hit
+    pub refinement_steps: i32,
+    /// The number of samples blurring rough reflections
+    pub blur_samples: i32,
+    /// The furthest a ray travels, in view space units
+    pub max_distance: f32,
+    /// How far behind the depth buffer a ray may pass and still hit, in view space units
+    pub thickness: f32,
+    /// The fraction of the screen at each edge over which reflections fade into the probe
+    pub edge_fade: f32,
+    /// The roughness above which surfaces take only the probe reflection
+    pub roughness_cutoff: f32,
+    /// The strength of the reflections
+    pub intensity: f32,
+}
+
+impl SsrSettings {
+    /// Return the settings of a quality preset
+    ///
+    /// quality: The preset
+    pub fn from_quality(quality: SsrQuality) -> SsrSettings {
+        let (max_steps, refinement_steps, blur_samples) = match quality {
+            SsrQuality::Low => (16, 2, 1),
+            SsrQuality::Medium => (32, 4, 4),
+            SsrQuality::High => (64, 6, 8),
+            SsrQuality::Ultra => (128, 8, 16),
+        };
+
+        SsrSettings {
+            max_steps: max_steps,
+            refinement_steps: refinement_steps,
+            blur_samples: blur_samples,
+            max_distance: 50.0f32,
+            thickness: 0.5f32,
+            edge_fade: 0.1f32,
+            roughness_cutoff: 0.8f32,
+            intensity: 1.0f32,
+        }
+    }
+}
+
+impl Default for SsrSettings {
+    fn default() -> SsrSettings {
+        SsrSettings::from_quality(SsrQuality::Medium)
+    }
+}
+
+/// Write the sources of the screen-space reflections shader to the working directory
+///
+/// The reflections shader is built from these files when the renderer is set up, so this must be
+/// called before then even if the pass is only enabled later.
+pub fn install_ssr_shader_sources() -> Result<(), io::Error> {
+    write_entire_file(SSR_VERTEX_SOURCE, SSR_VERTEX_FILENAME)?;
+    write_entire_file(SSR_FRAGMENT_SOURCE, SSR_FRAGMENT_FILENAME)?;
+
+    Ok(())
+}
+
+/// Add the uniform block and shader specification of the screen-space reflections shader
+///
+/// The shader draws F2F2 full-screen triangles, writing the lighting result with the
+/// reflections composited over it.  Rays are marched through the depth buffer from the
+/// G-buffer normals; where they miss, leave the screen or the surface is too rough, the
+/// environment probe is reflected instead.
+///
+/// resource_manager: The resource manager to add the specifications to
+/// pass_name: The pass the reflections shader is used in
+pub fn register_ssr_shader(resource_manager: &mut ResourceManager, pass_name: &'static str) {
+    let block_spec = UniformBlockSpec {
+        set: 0,
+        block_type: UniformType::UniformBuffer,
+        uniforms: vec![BlockUniformSpec::typed("ssr_projection", BlockMemberType::Mat4),
+                       BlockUniformSpec::typed("ssr_inverse_projection", BlockMemberType::Mat4),
+                       BlockUniformSpec::typed("ssr_inverse_view", BlockMemberType::Mat4),
+                       BlockUniformSpec::typed("ssr_viewport", BlockMemberType::Vec4),
+                       BlockUniformSpec::typed("ssr_max_distance", BlockMemberType::Float),
+                       BlockUniformSpec::typed("ssr_thickness", BlockMemberType::Float),
+                       BlockUniformSpec::typed("ssr_edge_fade", BlockMemberType::Float),
+                       BlockUniformSpec::typed("ssr_roughness_cutoff", BlockMemberType::Float),
+                       BlockUniformSpec::typed("ssr_intensity", BlockMemberType::Float),
+                       BlockUniformSpec::typed("ssr_max_steps", BlockMemberType::Int),
+                       BlockUniformSpec::typed("ssr_refinement_steps", BlockMemberType::Int),
+                       BlockUniformSpec::typed("ssr_blur_samples", BlockMemberType::Int)],
+        ..Default::default()
+    };
+    resource_manager.uniform_block_specs.insert(SSR_SETTINGS_BLOCK, block_spec);
+
+    let ssr_spec = ShaderSpec {
+        name: SSR_SHADER_NAME,
+        library_files: vec![],
+        shader_files: vec![ShaderFilesSpecification {
+                               filename: SSR_VERTEX_FILENAME,
+                               shader_stage: ShaderStage::VertexShader,
+                               spirv_out: "ssr.vert.spv",
+                               reflect_out: "ssr.vert.rfl",
+                               entry_point: DEFAULT_ENTRY_POINT,
+                           },
+                           ShaderFilesSpecification {
+                               filename: SSR_FRAGMENT_FILENAME,
+                               shader_stage: ShaderStage::FragmentShader,
+                               spirv_out: "ssr.frag.spv",
+                               reflect_out: "ssr.frag.rfl",
+                               entry_point: DEFAULT_ENTRY_POINT,
+                           }],
+        fallback_shader_files: vec![],
+        uniform_block_names: vec![SSR_SETTINGS_BLOCK],
+        uniform_specs: vec![UniformSpec {
+                                name: SSR_COLOUR_TEXTURE,
+                                set: 1,
+                                binding: 0,
+                                uniform_type: UniformType::CombinedImageSampler,
+                            },
+                            UniformSpec {
+                                name: SSR_DEPTH_TEXTURE,
+                                set: 1,
+                                binding: 1,
+                                uniform_type: UniformType::CombinedImageSampler,
+                            },
+                            UniformSpec {
+                                name: SSR_NORMAL_TEXTURE,
+                                set: 1,
+                                binding: 2,
+                                uniform_type: UniformType::CombinedImageSampler,
+                            },
+                            UniformSpec {
+                                name: SSR_ENVIRONMENT_TEXTURE,
+                                set: 1,
+                                binding: 3,
+                                uniform_type: UniformType::CombinedImageSampler,
+                            }],
+        push_constants: vec![],
+        vertex_array_type: VertexArrayType::F2F2,
+        primitive: PrimitiveType::PrimitiveTriangles,
+        instance_layout: None,
+        attributes: vec!["position", "texcoord"],
+        fragment_out: "ssr_colour",
+        extra_fragment_outs: vec![],
+        render_state: RenderState::default(),
+        stencil: None,
+        bindless_textures: false,
+        pass_name: pass_name,
+    };
+    resource_manager.shader_specs.insert(SSR_SHADER_NAME, ssr_spec);
+}
+
+/// Draw the lighting result with screen-space reflections over the whole of the current render
+/// target
+///
+/// The lighting result, depth buffer, G-buffer normals and environment probe must be available
+/// as the textures named by SSR_COLOUR_TEXTURE, SSR_DEPTH_TEXTURE, SSR_NORMAL_TEXTURE and
+/// SSR_ENVIRONMENT_TEXTURE.  The render target must not be the one the lighting result is in.
+///
+/// renderer: The renderer to draw with
+/// settings: The quality and strength of the reflections
+/// projection: The projection matrix the scene was drawn with
+/// view: The view matrix the scene was drawn with
+/// width: The width of the render target in pixels
+/// height: The height of the render target in pixels
+pub fn render_ssr<Rend: Renderer + ?Sized>(renderer: &mut Rend,
+                                           settings: &SsrSettings,
+                                           projection: &Mat4<f32>,
+                                           view: &Mat4<f32>,
+                                           width: u32,
+                                           height: u32) {
+    let inverse_projection = projection.inverse().unwrap_or(Mat4::newidentity());
+    let inverse_view = view.inverse().unwrap_or(Mat4::newidentity());
+
+    renderer.set_uniform_buffer_matrix(SSR_SETTINGS_BLOCK, "ssr_projection", projection);
+    renderer.set_uniform_buffer_matrix(SSR_SETTINGS_BLOCK, "ssr_inverse_projection", &inverse_projection);
+    renderer.set_uniform_buffer_matrix(SSR_SETTINGS_BLOCK, "ssr_inverse_view", &inverse_view);
+    renderer.set_uniform_buffer_float_vector(SSR_SETTINGS_BLOCK,
+                                             "ssr_viewport",
+                                             &vec![width as f32, height as f32, 0.0f32, 0.0f32]);
+    renderer.set_uniform_buffer_float(SSR_SETTINGS_BLOCK, "ssr_max_distance", settings.max_distance);
+    renderer.set_uniform_buffer_float(SSR_SETTINGS_BLOCK, "ssr_thickness", settings.thickness);
+    renderer.set_uniform_buffer_float(SSR_SETTINGS_BLOCK, "ssr_edge_fade", settings.edge_fade.max(1.0e-3f32));
+    renderer.set_uniform_buffer_float(SSR_SETTINGS_BLOCK, "ssr_roughness_cutoff", settings.roughness_cutoff);
+    renderer.set_uniform_buffer_float(SSR_SETTINGS_BLOCK, "ssr_intensity", settings.intensity);
+    renderer.set_uniform_buffer_int(SSR_SETTINGS_BLOCK, "ssr_max_steps", settings.max_steps.max(1));
+    renderer.set_uniform_buffer_int(SSR_SETTINGS_BLOCK, "ssr_refinement_steps", settings.refinement_steps.max(0));
+    renderer.set_uniform_buffer_int(SSR_SETTINGS_BLOCK, "ssr_blur_samples", settings.blur_samples.max(1));
+    renderer.synchronise_uniform_buffer(SSR_SETTINGS_BLOCK);
+
+    renderer.begin_pass(SSR_SHADER_NAME);
+
+    draw_fullscreen_quad(renderer);
+
+    renderer.end_pass();
+}
@@ -0,0 +1,279 @@
+// Copyright (c) 2016-2017 Bruce Stenning. All rights reserved.
+//
+// Redistribution and use in source and binary forms, with or without
+// modification, are permitted provided that the following conditions
+// are met:
+//
+// 1. Redistributions of source code must retain the above copyright
+//    notice, this list of conditions and the following disclaimer.
+//
+// 2. Redistributions in binary form must reproduce the above copyright
+//    notice, this list of conditions and the following disclaimer in the
+//    documentation and/or other materials provided with the distribution.
+//
+// 3. Neither the name of the copyright holder nor the names of its
+//    contributors may be used to endorse or promote products derived
+//    from this software without specific prior written permission.
+//
+// THIS SOFTWARE IS PROVIDED BY THE COPYRIGHT HOLDERS AND CONTRIBUTORS
+// AS IS" AND ANY EXPRESS OR IMPLIED WARRANTIES, INCLUDING, BUT NOT
+// LIMITED TO, THE IMPLIED WARRANTIES OF MERCHANTABILITY AND FITNESS
+// FOR A PARTICULAR PURPOSE ARE DISCLAIMED. IN NO EVENT SHALL THE
+// COPYRIGHT HOLDER OR CONTRIBUTORS BE LIABLE FOR ANY DIRECT, INDIRECT,
+// INCIDENTAL, SPECIAL, EXEMPLARY, OR CONSEQUENTIAL DAMAGES (INCLUDING,
+// BUT NOT LIMITED TO, PROCUREMENT OF SUBSTITUTE GOODS OR SERVICES; LOSS
+// OF USE, DATA, OR PROFITS; OR BUSINESS INTERRUPTION) HOWEVER CAUSED
+// AND ON ANY THEORY OF LIABILITY, WHETHER IN CONTRACT, STRICT LIABILITY,
+// OR TORT (INCLUDING NEGLIGENCE OR OTHERWISE) ARISING IN ANY WAY OUT OF
+// THE USE OF THIS SOFTWARE, EVEN IF ADVISED OF THE POSSIBILITY OF SUCH
+// DAMAGE.
+
+use std::io;
+
+use algebra::matrix::Mat4;
+use graphics::fullscreen::draw_fullscreen_quad;
+use graphics::renderer::*;
+use graphics::rendertarget::*;
+use graphics::resources::*;
+use graphics::shader::*;
+use graphics::xr::eye_index;
+use misc::fileutils::*;
+
+/// The name the stereo composite shader is registered under
+pub const STEREO_COMPOSITE_SHADER_NAME: &'static str = "stereo_composite";
+
+/// The name of the texture the left eye is sampled from by the composite
+pub const STEREO_LEFT_EYE_TEXTURE: &'static str = "stereo_left_eye_texture";
+
+/// The name of the texture the right eye is sampled from by the composite
+pub const STEREO_RIGHT_EYE_TEXTURE: &'static str = "stereo_right_eye_texture";
+
+/// The name of the push constant holding the StereoMode as an integer
+pub const STEREO_MODE: &'static str = "stereo_mode";
+
+/// The GLSL library defining stereo_composite, for final passes that combine the eyes themselves
+pub const STEREO_COMPOSITE_LIBRARY_FILENAME: &'static str = "stereo_composite.glsl";
+
+/// GLSL library source for combining the two eyes in the final pass
+///
+/// stereo_composite takes the textures rendered for each eye, the texture coordinate of the
+/// output fragment and the StereoMode as an integer.
+pub const STEREO_COMPOSITE_GLSL: &'static str = include_str!("shaders/stereo_composite.glsl");
+
+const STEREO_COMPOSITE_VERTEX_FILENAME: &'static str = "stereo_composite.vert";
+const STEREO_COMPOSITE_FRAGMENT_FILENAME: &'static str = "stereo_composite.frag";
+
+const STEREO_COMPOSITE_VERTEX_SOURCE: &'static str = include_str!("shaders/stereo_composite.vert");
+const STEREO_COMPOSITE_FRAGMENT_SOURCE: &'static str = include_str!("shaders/stereo_composite.frag");
+
+/// The ways in which the two eyes can be presented on a single display
+#[derive(Clone, Copy, PartialEq, Debug)]
+pub enum StereoMode {
+    Mono = 0,
+    SideBySide = 1,
+    TopBottom = 2,
+    Anaglyph = 3,
+}
+
+#[derive(Clone, Copy, PartialEq, Debug)]
+pub enum Eye {
+    Left,
+    Right,
+}
+
+/// Settings for rendering in stereo without a headset
+///
+/// The scene is rendered once per eye into separate render targets by render_stereo_eyes, and
+/// the final pass combines them by render_stereo_composite according to the mode.  The settings
+/// start as RendererConfig::stereo, and all the fields may be changed at runtime through
+/// Renderer::set_stereo_settings.
+#[derive(Clone, Copy, Debug)]
+pub struct StereoSettings {
+    pub mode: StereoMode,
+    pub eye_separation: f32,
+    pub convergence: f32,
+}
+
+impl Default for StereoSettings {
+    fn default() -> StereoSettings {
+        StereoSettings {
+            mode: StereoMode::Mono,
+            eye_separation: 0.065f32,
+            convergence: 2.0f32,
+        }
+    }
+}
+
+impl StereoSettings {
+    /// Return the eyes to render the scene for, in order
+    ///
+    /// In mono mode only the left eye is rendered, and it is not offset.
+    pub fn eyes(&self) -> Vec<Eye> {
+        if self.mode == StereoMode::Mono {
+            vec![Eye::Left]
+        } else {
+            vec![Eye::Left, Eye::Right]
+        }
+    }
+
+    /// Return the distance to move the camera along its right axis for an eye
+    ///
+    /// eye: The eye to return the offset for
+    pub fn eye_offset(&self, eye: Eye) -> f32 {
+        if self.mode == StereoMode::Mono {
+            return 0.0f32;
+        }
+        match eye {
+            Eye::Left => -0.5f32 * self.eye_separation,
+            Eye::Right => 0.5f32 * self.eye_separation,
+        }
+    }
+
+    /// Produce the view matrix for an eye from the view matrix for the centre of the head
+    ///
+    /// view: The view matrix for the centre of the head
+    /// eye: The eye to produce the view matrix for
+    pub fn eye_view(&self, view: &Mat4<f32>, eye: Eye) -> Mat4<f32> {
+        Mat4::translate(-self.eye_offset(eye), 0.0f32, 0.0f32) * *view
+    }
+
+    /// Produce the projection matrix for an eye from the projection matrix for the head
+    ///
+    /// The frustum is sheared horizontally so that objects at the convergence distance have
+    /// no parallax, appearing at the depth of the screen.
+    ///
+    /// projection: The symmetric projection matrix for the centre of the head
+    /// eye: The eye to produce the projection matrix for
+    pub fn eye_projection(&self, projection: &Mat4<f32>, eye: Eye) -> Mat4<f32> {
+        let mut matrix = *projection;
+        matrix.m[2][0] -= projection.m[0][0] * self.eye_offset(eye) / self.convergence;
+
+        matrix
+    }
+}
+
+/// Write the sources of the stereo composite shader to the working directory
+///
+/// The shader is built from these files when the renderer is set up, so this must be called before
+/// then even if the renderer starts in mono.
+pub fn install_stereo_shader_sources() -> Result<(), io::Error> {
+    write_entire_file(STEREO_COMPOSITE_GLSL, STEREO_COMPOSITE_LIBRARY_FILENAME)?;
+    write_entire_file(STEREO_COMPOSITE_VERTEX_SOURCE, STEREO_COMPOSITE_VERTEX_FILENAME)?;
+    write_entire_file(STEREO_COMPOSITE_FRAGMENT_SOURCE, STEREO_COMPOSITE_FRAGMENT_FILENAME)?;
+
+    Ok(())
+}
+
+/// Add the shader specification of the stereo composite shader
+///
+/// The shader draws F2F2 full-screen triangles, sampling the two eyes as the textures named by
+/// STEREO_LEFT_EYE_TEXTURE and STEREO_RIGHT_EYE_TEXTURE and reading the mode from the STEREO_MODE
+/// push constant.
+///
+/// resource_manager: The resource manager to add the specification to
+/// pass_name: The final pass the composite is drawn in, which draws to the swapchain
+pub fn register_stereo_composite_shader(resource_manager: &mut ResourceManager, pass_name: &'static str) {
+    let composite_spec = ShaderSpec {
+        name: STEREO_COMPOSITE_SHADER_NAME,
+        library_files: vec![STEREO_COMPOSITE_LIBRARY_FILENAME],
+        shader_files: vec![ShaderFilesSpecification {
+                               filename: STEREO_COMPOSITE_VERTEX_FILENAME,
+                               shader_stage: ShaderStage::VertexShader,
+                               spirv_out: "stereo_composite.vert.spv",
+                               reflect_out: "stereo_composite.vert.rfl",
+                               entry_point: DEFAULT_ENTRY_POINT,
+                           },
+                           ShaderFilesSpecification {
+                               filename: STEREO_COMPOSITE_FRAGMENT_FILENAME,
+                               shader_stage: ShaderStage::FragmentShader,
+                               spirv_out: "stereo_composite.frag.spv",
+                               reflect_out: "stereo_composite.frag.rfl",
+                               entry_point: DEFAULT_ENTRY_POINT,
+                           }],
+        fallback_shader_files: vec![],
+        uniform_block_names: vec![],
+        uniform_specs: vec![UniformSpec {
+                                name: STEREO_LEFT_EYE_TEXTURE,
+                                set: 1,
+                                binding: 0,
+                                uniform_type: UniformType::CombinedImageSampler,
+                            },
+                            UniformSpec {
+                                name: STEREO_RIGHT_EYE_TEXTURE,
+                                set: 1,
+                                binding: 1,
+                                uniform_type: UniformType::CombinedImageSampler,
+                            }],
+        push_constants: vec![PushConstantSpec {
+                                 name: STEREO_MODE,
+                                 offset: 0,
+                                 size: 4,
+                             }],
+        vertex_array_type: VertexArrayType::F2F2,
+        primitive: PrimitiveType::PrimitiveTriangles,
+        instance_layout: None,
+        attributes: vec!["position", "texcoord"],
+        fragment_out: "stereo_colour",
+        extra_fragment_outs: vec![],
+        render_state: RenderState::default(),
+        stencil: None,
+        bindless_textures: false,
+        pass_name: pass_name,
+    };
+    resource_manager.shader_specs.insert(STEREO_COMPOSITE_SHADER_NAME, composite_spec);
+}
+
+/// Render the scene once for each eye of the renderer's stereo settings, into the eye's target
+///
+/// The draw function is called with each eye's target selected, and the view and projection
+/// matrices for the eye, and must clear and draw the whole scene.  In mono mode only the left
+/// eye's target is drawn, with the matrices unchanged.  No render target is selected after.
+///
+/// renderer: The renderer to draw with
+/// view: The view matrix for the centre of the head
+/// projection: The symmetric projection matrix for the centre of the head
+/// eyes: The render targets of the left and right eyes, sized as the output
+/// draw: The function drawing the scene, given the renderer, the eye and its view and projection
+pub fn render_stereo_eyes<Rend, F>(renderer: &mut Rend,
+                                   view: &Mat4<f32>,
+                                   projection: &Mat4<f32>,
+                                   eyes: &mut [Box<RenderTarget>],
+                                   mut draw: F)
+    where Rend: Renderer + ?Sized,
+          F: FnMut(&mut Rend, Eye, &Mat4<f32>, &Mat4<f32>)
+{
+    debug_assert!(eyes.len() >= 2);
+
+    let settings = renderer.stereo_settings();
+    for &eye in settings.eyes().iter() {
+        let eye_view = settings.eye_view(view, eye);
+        let eye_projection = settings.eye_projection(projection, eye);
+
+        renderer.select_render_target(1, &mut *eyes[eye_index(eye)]);
+        draw(renderer, eye, &eye_view, &eye_projection);
+    }
+    renderer.deselect_render_target();
+}
+
+/// Combine the eyes over the whole of the current render target, as the renderer's stereo
+/// settings select
+///
+/// In mono mode the left eye is drawn as it is.
+///
+/// renderer: The renderer to draw with
+/// eyes: The render targets of the left and right eyes, drawn by render_stereo_eyes
+pub fn render_stereo_composite<Rend: Renderer + ?Sized>(renderer: &mut Rend, eyes: &[Box<RenderTarget>]) {
+    debug_assert!(eyes.len() >= 2);
+
+    let mode = renderer.stereo_settings().mode;
+    let right = if mode == StereoMode::Mono { Eye::Left } else { Eye::Right };
+
+    renderer.begin_pass(STEREO_COMPOSITE_SHADER_NAME);
+    renderer.set_draw_texture(0, STEREO_LEFT_EYE_TEXTURE, &**eyes[eye_index(Eye::Left)].get_texture());
+    renderer.set_draw_texture(0, STEREO_RIGHT_EYE_TEXTURE, &**eyes[eye_index(right)].get_texture());
+    renderer.set_push_constant_int(0, STEREO_MODE, mode as i32);
+
+    draw_fullscreen_quad(renderer);
+
+    renderer.end_pass();
+}
@@ -0,0 +1,105 @@
+// Copyright (c) 2016-2017 Bruce Stenning. All rights reserved.
+//
+// Redistribution and use in source and binary forms, with or without
+// modification, are permitted provided that the following conditions
+// are met:
+//
+// 1. Redistributions of source code must retain the above copyright
+//    notice, this list of conditions and the following disclaimer.
+//
+// 2. Redistributions in binary form must reproduce the above copyright
+//    notice, this list of conditions and the following disclaimer in the
+//    documentation and/or other materials provided with the distribution.
+//
+// 3. Neither the name of the copyright holder nor the names of its
+//    contributors may be used to endorse or promote products derived
+//    from this software without specific prior written permission.
+//
+// THIS SOFTWARE IS PROVIDED BY THE COPYRIGHT HOLDERS AND CONTRIBUTORS
+// AS IS" AND ANY EXPRESS OR IMPLIED WARRANTIES, INCLUDING, BUT NOT
+// LIMITED TO, THE IMPLIED WARRANTIES OF MERCHANTABILITY AND FITNESS
+// FOR A PARTICULAR PURPOSE ARE DISCLAIMED. IN NO EVENT SHALL THE
+// COPYRIGHT HOLDER OR CONTRIBUTORS BE LIABLE FOR ANY DIRECT, INDIRECT,
+// INCIDENTAL, SPECIAL, EXEMPLARY, OR CONSEQUENTIAL DAMAGES (INCLUDING,
+// BUT NOT LIMITED TO, PROCUREMENT OF SUBSTITUTE GOODS OR SERVICES; LOSS
+// OF USE, DATA, OR PROFITS; OR BUSINESS INTERRUPTION) HOWEVER CAUSED
+// AND ON ANY THEORY OF LIABILITY, WHETHER IN CONTRACT, STRICT LIABILITY,
+// OR TORT (INCLUDING NEGLIGENCE OR OTHERWISE) ARISING IN ANY WAY OUT OF
+// THE USE OF THIS SOFTWARE, EVEN IF ADVISED OF THE POSSIBILITY OF SUCH
+// DAMAGE.
+
+/// The default size of each OpenGL streaming vertex buffer
+pub const DEFAULT_STREAM_BUFFER_SIZE: usize = 4 * 1024 * 1024;
+
+/// Where an upload to a streaming buffer is written
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub struct StreamWrite {
+    /// The byte offset in the buffer
+    pub offset: usize,
+    /// The size to give the buffer fresh storage of before writing, if it must have some
+    pub orphan: Option<usize>,
+}
+
+/// Places uploads in a buffer front to back, orphaning its storage when it fills up
+///
+/// Each upload is written after those made since the storage was last orphaned, so it may be
+/// written without waiting for the draws still reading the earlier ones.  When an upload does
+/// not fit, the storage is orphaned, the driver keeping the old storage until the draws using
+/// it have completed, and the upload is written at the start of the fresh storage.  An upload
+/// larger than the buffer grows it to fit.
+#[derive(Clone, Debug)]
+pub struct StreamRing {
+    capacity: usize,
+    offset: usize, // Where the next upload may start
+    allocated: bool, // Whether the buffer has storage of the capacity
+}
+
+impl StreamRing {
+    /// Create a ring for a buffer that has no storage yet
+    ///
+    /// capacity: The size in bytes to give the buffer's storage
+    pub fn new(capacity: usize) -> StreamRing {
+        StreamRing {
+            capacity: capacity,
+            offset: 0,
+            allocated: false,
+        }
+    }
+
+    /// Place an upload, returning where to write it
+    ///
+    /// bytes: The size of the upload
+    /// alignment: The multiple of bytes the offset must be, such as the size of a vertex
+    pub fn write(&mut self, bytes: usize, alignment: usize) -> StreamWrite {
+        let alignment = alignment.max(1);
+        let aligned = (self.offset + alignment - 1) / alignment * alignment;
+
+        if self.allocated && aligned + bytes <= self.capacity {
+            self.offset = aligned + bytes;
+            return StreamWrite {
+                offset: aligned,
+                orphan: None,
+            };
+        }
+
+        self.capacity = self.capacity.max(bytes);
+        self.allocated = true;
+        self.offset = bytes;
+        StreamWrite {
+            offset: 0,
+            orphan: Some(self.capacity),
+        }
+    }
+
+    /// Forget the buffer's storage, for when it has been replaced or deleted, so that the next
+    /// upload gives it storage afresh
+    pub fn reset(&mut self) {
+        self.offset = 0;
+        self.allocated = false;
+    }
+
+    /// Return the size in bytes of the buffer's storage
+    pub fn capacity(&self) -> usize {
+        self.capacity
+    }
+}
@@ -0,0 +1,596 @@
+// Copyright (c) 2016-2017 Bruce Stenning. All rights reserved.
+//
+// Redistribution and use in source and binary forms, with or without
+// modification, are permitted provided that the following conditions
+// are met:
+//
+// 1. Redistributions of source code must retain the above copyright
+//    notice, this list of conditions and the following disclaimer.
+//
+// 2. Redistributions in binary form must reproduce the above copyright
+//    notice, this list of conditions and the following disclaimer in the
+//    documentation and/or other materials provided with the distribution.
+//
+// 3. Neither the name of the copyright holder nor the names of its
+//    contributors may be used to endorse or promote products derived
+//    from this software without specific prior written permission.
+//
+// THIS SOFTWARE IS PROVIDED BY THE COPYRIGHT HOLDERS AND CONTRIBUTORS
+// AS IS" AND ANY EXPRESS OR IMPLIED WARRANTIES, INCLUDING, BUT NOT
+// LIMITED TO, THE IMPLIED WARRANTIES OF MERCHANTABILITY AND FITNESS
+// FOR A PARTICULAR PURPOSE ARE DISCLAIMED. IN NO EVENT SHALL THE
+// COPYRIGHT HOLDER OR CONTRIBUTORS BE LIABLE FOR ANY DIRECT, INDIRECT,
+// INCIDENTAL, SPECIAL, EXEMPLARY, OR CONSEQUENTIAL DAMAGES (INCLUDING,
+// BUT NOT LIMITED TO, PROCUREMENT OF SUBSTITUTE GOODS OR SERVICES; LOSS
+// OF USE, DATA, OR PROFITS; OR BUSINESS INTERRUPTION) HOWEVER CAUSED
+// AND ON ANY THEORY OF LIABILITY, WHETHER IN CONTRACT, STRICT LIABILITY,
+// OR TORT (INCLUDING NEGLIGENCE OR OTHERWISE) ARISING IN ANY WAY OUT OF
+// THE USE OF THIS SOFTWARE, EVEN IF ADVISED OF THE POSSIBILITY OF SUCH
+// DAMAGE.
+
+use std::collections::HashMap;
+use std::io;
+
+use graphics::gputext::GlyphGrid;
+use graphics::renderer::*;
+use graphics::resources::*;
+use graphics::shader::*;
+use graphics::texture::*;
+use misc::embeddedresources::*;
+use misc::fileutils::*;
+use algebra::vector::*;
+
+/// The name the text shader is registered under
+pub const TEXT_SHADER_NAME: &'static str = "text";
+
+/// The name of the texture the glyphs are sampled from
+pub const TEXT_ATLAS_TEXTURE: &'static str = "text_atlas";
+
+/// The name of the fragment output of the text shader
+pub const TEXT_FRAGMENT_OUT: &'static str = "text_colour";
+
+const TEXT_VERTEX_FILENAME: &'static str = "text.vert";
+const TEXT_FRAGMENT_FILENAME: &'static str = "text.frag";
+
+const TEXT_VERTEX_SOURCE: &'static str = include_str!("shaders/text.vert");
+const TEXT_FRAGMENT_SOURCE: &'static str = include_str!("shaders/text.frag");
+
+/// A glyph of a font, with its metrics in pixels at a scale of one
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub struct FontGlyph {
+    pub texcoord_min: Vec2<f32>, // The top-left corner of the glyph in the atlas
+    pub texcoord_max: Vec2<f32>, // The bottom-right corner of the glyph in the atlas
+    pub width: f32,
+    pub height: f32,
+    pub x_offset: f32, // From the pen position to the left edge of the glyph
+    pub y_offset: f32, // From the top of the line to the top edge of the glyph
+    pub advance: f32, // From the pen position to that of the next glyph
+}
+
+/// A font drawn from an atlas texture, which holds either the coverage of each glyph or its
+/// signed distance field in the red channel
+#[derive(Clone, Debug, PartialEq)]
+pub struct Font {
+    pub line_height: f32, // The distance between successive lines in pixels
+    pub distance_field: bool, // Whether the atlas holds signed distance fields rather than coverage
+    pub page: String, // The filename of the atlas texture, if the font description names one
+    glyphs: HashMap<char, FontGlyph>,
+    kerning: HashMap<(char, char), f32>, // Adjustments to the advance between pairs of characters
+}
+
+/// Split a line of a BMFont description into its tag and key-value pairs
+///
+/// Values may be quoted, in which case they can contain spaces.
+///
+/// line: The line to split
+fn parse_bmfont_line(line: &str) -> (&str, HashMap<&str, &str>) {
+    let line = line.trim();
+    let (tag, mut rest) = match line.find(' ') {
+        Some(i) => (&line[..i], line[i..].trim_start()),
+        None => (line, ""),
+    };
+
+    let mut pairs = HashMap::new();
+    while let Some(equals) = rest.find('=') {
+        let key = rest[..equals].trim();
+        let after = &rest[equals + 1..];
+        let (value, remainder) = if after.starts_with('"') {
+            match after[1..].find('"') {
+                Some(close) => (&after[1..close + 1], &after[close + 2..]),
+                None => (&after[1..], ""),
+            }
+        } else {
+            match after.find(' ') {
+                Some(space) => (&after[..space], &after[space..]),
+                None => (after, ""),
+            }
+        };
+        pairs.insert(key, value);
+        rest = remainder.trim_start();
+    }
+
+    (tag, pairs)
+}
+
+/// Return a numeric value of a BMFont line
+///
+/// pairs: The key-value pairs of the line
+/// key: The key of the value
+fn bmfont_value(pairs: &HashMap<&str, &str>, key: &str) -> Result<f32, io::Error> {
+    match pairs.get(key) {
+        Some(value) => {
+            value.parse::<f32>()
+                .map_err(|_| io::Error::new(io::ErrorKind::InvalidData, format!("Invalid {} in font: {}", key, value)))
+        }
+        None => Err(io::Error::new(io::ErrorKind::InvalidData, format!("Missing {} in font", key))),
+    }
+}
+
+/// Return the character of a BMFont character id
+///
+/// pairs: The key-value pairs of the line
+/// key: The key of the id
+fn bmfont_char(pairs: &HashMap<&str, &str>, key: &str) -> Result<char, io::Error> {
+    let id = bmfont_value(pairs, key)? as u32;
+    match ::std::char::from_u32(id) {
+        Some(c) => Ok(c),
+        None => Err(io::Error::new(io::ErrorKind::InvalidData, format!("Invalid character {} in font", id))),
+    }
+}
+
+impl Font {
+    /// Parse a font description in the text format of AngelCode's BMFont
+    ///
+    /// This is also written by the common signed distance field generators, which add a
+    /// distanceField line.  Only single-page fonts are supported.
+    ///
+    /// description: The contents of the .fnt file
+    pub fn parse_bmfont(description: &str) -> Result<Font, io::Error> {
+        let mut font = Font {
+            line_height: 0.0f32,
+            distance_field: false,
+            page: String::new(),
+            glyphs: HashMap::new(),
+            kerning: HashMap::new(),
+        };
+        let mut atlas_width = 0.0f32;
+        let mut atlas_height = 0.0f32;
+
+        for line in description.lines() {
+            let (tag, pairs) = parse_bmfont_line(line);
+            match tag {
+                "common" => {
+                    font.line_height = bmfont_value(&pairs, "lineHeight")?;
+                    atlas_width = bmfont_value(&pairs, "scaleW")?;
+                    atlas_height = bmfont_value(&pairs, "scaleH")?;
+                    if pairs.get("pages").map_or(false, |pages| *pages != "1") {
+                        return Err(io::Error::new(io::ErrorKind::InvalidData, "Fonts of several pages are not supported"));
+                    }
+                }
+                "page" => {
+                    font.page = pairs.get("file").unwrap_or(&"").to_string();
+                }
+                "distanceField" => {
+                    font.distance_field = true;
+                }
+                "char" => {
+                    if atlas_width <= 0.0f32 || atlas_height <= 0.0f32 {
+                        return Err(io::Error::new(io::ErrorKind::InvalidData, "Font characters precede the common line"));
+                    }
+
+                    let (x, y) = (bmfont_value(&pairs, "x")?, bmfont_value(&pairs, "y")?);
+                    let (width, height) = (bmfont_value(&pairs, "width")?, bmfont_value(&pairs, "height")?);
+                    font.glyphs.insert(bmfont_char(&pairs, "id")?,
+                                       FontGlyph {
+                                           texcoord_min: Vec2 {
+                                               x: x / atlas_width,
+                                               y: y / atlas_height,
+                                           },
+                                           texcoord_max: Vec2 {
+                                               x: (x + width) / atlas_width,
+                                               y: (y + height) / atlas_height,
+                                           },
+                                           width: width,
+                                           height: height,
+                                           x_offset: bmfont_value(&pairs, "xoffset")?,
+                                           y_offset: bmfont_value(&pairs, "yoffset")?,
+                                           advance: bmfont_value(&pairs, "xadvance")?,
+                                       });
+                }
+                "kerning" => {
+                    font.kerning.insert((bmfont_char(&pairs, "first")?, bmfont_char(&pairs, "second")?),
+                                        bmfont_value(&pairs, "amount")?);
+                }
+                _ => {}
+            }
+        }
+
+        if font.glyphs.is_empty() {
+            return Err(io::Error::new(io::ErrorKind::InvalidData, "Font has no characters"));
+        }
+
+        Ok(font)
+    }
+
+    /// Load a font description in BMFont's text format from a file or embedded resource
+    ///
+    /// embedded: The embedded resources to prefer, if any
+    /// filename: The filename of the .fnt file
+    pub fn load(embedded: Option<&EmbeddedResources>, filename: &str) -> Result<Font, io::Error> {
+        Font::parse_bmfont(&read_text_file(embedded, filename))
+    }
+
+    /// Construct a monospaced coverage font from the grid atlas of the GPU text path
+    ///
+    /// grid: The layout of the atlas
+    pub fn from_glyph_grid(grid: &GlyphGrid) -> Font {
+        let mut glyphs = HashMap::new();
+        for cell in 0..grid.columns * grid.rows {
+            let c = match ::std::char::from_u32(grid.first_character + cell) {
+                Some(c) => c,
+                None => continue,
+            };
+            let (column, row) = ((cell % grid.columns) as f32, (cell / grid.columns) as f32);
+            glyphs.insert(c,
+                          FontGlyph {
+                              texcoord_min: Vec2 {
+                                  x: column / grid.columns as f32,
+                                  y: row / grid.rows as f32,
+                              },
+                              texcoord_max: Vec2 {
+                                  x: (column + 1.0f32) / grid.columns as f32,
+                                  y: (row + 1.0f32) / grid.rows as f32,
+                              },
+                              width: grid.glyph_width,
+                              height: grid.glyph_height,
+                              x_offset: 0.0f32,
+                              y_offset: 0.0f32,
+                              advance: grid.glyph_width,
+                          });
+        }
+
+        Font {
+            line_height: grid.glyph_height,
+            distance_field: false,
+            page: String::new(),
+            glyphs: glyphs,
+            kerning: HashMap::new(),
+        }
+    }
+
+    /// Return the glyph of a character, or None if the font does not hold it
+    ///
+    /// c: The character
+    pub fn glyph(&self, c: char) -> Option<&FontGlyph> {
+        self.glyphs.get(&c)
+    }
+
+    /// Return the adjustment to the advance from one character to the next
+    ///
+    /// first: The character drawn first
+    /// second: The character following it
+    pub fn kerning(&self, first: char, second: char) -> f32 {
+        *self.kerning.get(&(first, second)).unwrap_or(&0.0f32)
+    }
+
+    /// Lay out a string from a top-left corner as one quad per glyph, in pixels
+    ///
+    /// Newlines return to the starting column on the next line.  Characters missing from the
+    /// font are skipped.
+    ///
+    /// x: The left edge in pixels
+    /// y: The top edge in pixels, with y increasing down the screen
+    /// scale: The size of the text relative to the size the font was made at
+    /// text: The string to lay out
+    pub fn layout(&self, x: f32, y: f32, scale: f32, text: &str) -> Vec<TextQuad> {
+        let mut quads = vec![];
+        let mut pen_x = x;
+        let mut pen_y = y;
+        let mut previous: Option<char> = None;
+        for c in text.chars() {
+            if c == '\n' {
+                pen_x = x;
+                pen_y += self.line_height * scale;
+                previous = None;
+                continue;
+            }
+
+            let glyph = match self.glyph(c) {
+                Some(glyph) => glyph,
+                None => continue,
+            };
+            if let Some(p) = previous {
+                pen_x += self.kerning(p, c) * scale;
+            }
+
+            if glyph.width > 0.0f32 && glyph.height > 0.0f32 {
+                let left = pen_x + glyph.x_offset * scale;
+                let top = pen_y + glyph.y_offset * scale;
+                quads.push(TextQuad {
+                    top_left: Vec2 { x: left, y: top },
+                    bottom_right: Vec2 {
+                        x: left + glyph.width * scale,
+                        y: top + glyph.height * scale,
+                    },
+                    texcoord_min: glyph.texcoord_min,
+                    texcoord_max: glyph.texcoord_max,
+                });
+            }
+
+            pen_x += glyph.advance * scale;
+            previous = Some(c);
+        }
+
+        quads
+    }
+
+    /// Return the width and height in pixels of a string drawn at a scale
+    ///
+    /// The width is that of the longest line's advances, and the height is a line height per line.
+    ///
+    /// scale: The size of the text relative to the size the font was made at
+    /// text: The string to measure
+    pub fn measure(&self, scale: f32, text: &str) -> (f32, f32) {
+        let mut width = 0.0f32;
+        let mut lines = 0;
+        for line in text.split('\n') {
+            let mut line_width = 0.0f32;
+            let mut previous: Option<char> = None;
+            for c in line.chars() {
+                if let Some(glyph) = self.glyph(c) {
+                    if let Some(p) = previous {
+                        line_width += self.kerning(p, c);
+                    }
+                    line_width += glyph.advance;
+                    previous = Some(c);
+                }
+            }
+            width = width.max(line_width * scale);
+            lines += 1;
+        }
+
+        (width, lines as f32 * self.line_height * scale)
+    }
+}
+
+/// A glyph laid out on the screen, in pixels with y increasing down the screen
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub struct TextQuad {
+    pub top_left: Vec2<f32>,
+    pub bottom_right: Vec2<f32>,
+    pub texcoord_min: Vec2<f32>,
+    pub texcoord_max: Vec2<f32>,
+}
+
+/// The glyphs of consecutive strings drawn in the same colour
+struct TextRun {
+    colour: [f32; 4],
+    quads: Vec<TextQuad>,
+}
+
+/// Strings gathered over a frame and drawn over the scene in one pass
+///
+/// The glyph quads are built on the CPU into the F2F2 vertex stream, so unlike the GPU text path
+/// the font can be proportional, kerned, or a signed distance field that stays sharp at any
+/// scale.  It suits debug overlays and UI labels of up to a few thousand glyphs a frame.
+pub struct TextOverlay {
+    font: Font,
+    runs: Vec<TextRun>,
+}
+
+impl TextOverlay {
+    /// Create an overlay with no text
+    ///
+    /// font: The font the text is drawn in
+    pub fn new(font: Font) -> TextOverlay {
+        TextOverlay {
+            font: font,
+            runs: vec![],
+        }
+    }
+
+    /// Return the font the text is drawn in
+    pub fn font(&self) -> &Font {
+        &self.font
+    }
+
+    /// Add a string, returning the number of glyphs added
+    ///
+    /// x: The left edge in pixels
+    /// y: The top edge in pixels, with y increasing down the screen
+    /// scale: The size of the text relative to the size the font was made at
+    /// colour: The red, green, blue and alpha components of the text
+    /// text: The string to draw
+    pub fn draw_text(&mut self, x: f32, y: f32, scale: f32, colour: [f32; 4], text: &str) -> usize {
+        let quads = self.font.layout(x, y, scale, text);
+        let added = quads.len();
+        if added == 0 {
+            return 0;
+        }
+
+        // Strings of the same colour share a draw
+        let same_colour = self.runs.last().map_or(false, |run| run.colour == colour);
+        if same_colour {
+            self.runs.last_mut().unwrap().quads.extend(quads);
+        } else {
+            self.runs.push(TextRun {
+                colour: colour,
+                quads: quads,
+            });
+        }
+
+        added
+    }
+
+    /// Remove every string, typically at the start of a frame
+    pub fn clear(&mut self) {
+        self.runs.clear();
+    }
+
+    /// Return the number of glyphs
+    pub fn len(&self) -> usize {
+        self.runs.iter().map(|run| run.quads.len()).sum()
+    }
+
+    /// Return the number of draws the text takes, one for each change of colour
+    pub fn draws(&self) -> usize {
+        self.runs.len()
+    }
+
+    /// Return whether there is no text
+    pub fn is_empty(&self) -> bool {
+        self.runs.is_empty()
+    }
+
+    /// Draw the text over the current render target
+    ///
+    /// On Vulkan the atlas is bound to the pipeline by name when the renderer is set up, so it
+    /// must be the texture named by TEXT_ATLAS_TEXTURE.
+    ///
+    /// renderer: The renderer to draw with
+    /// atlas: The texture holding the font's glyphs in its red channel
+    /// width: The width of the render target in pixels
+    /// height: The height of the render target in pixels
+    pub fn render<Rend: Renderer + ?Sized>(&self, renderer: &mut Rend, atlas: &Box<Texture>, width: u32, height: u32) {
+        if self.is_empty() {
+            return;
+        }
+
+        // Clip space y points down the screen on Vulkan but up it on OpenGL
+        let y_sign = if renderer.renderer_type() == RendererType::RendererVk {
+            1.0f32
+        } else {
+            -1.0f32
+        };
+        let to_clip = |pixel: Vec2<f32>| {
+            Vec2 {
+                x: pixel.x / width as f32 * 2.0f32 - 1.0f32,
+                y: (pixel.y / height as f32 * 2.0f32 - 1.0f32) * y_sign,
+            }
+        };
+
+        renderer.begin_pass(TEXT_SHADER_NAME);
+        atlas.bind(0);
+
+        let threaddata_arc = renderer.get_threaddata(0);
+        let mut threaddata = threaddata_arc.lock().unwrap();
+        threaddata.vertex_array_type = VertexArrayType::F2F2;
+
+        renderer.set_push_constant_int(0, "text_distance_field", self.font.distance_field as i32);
+        for run in self.runs.iter() {
+            renderer.set_push_constant_vec3(0,
+                                            "text_tint",
+                                            &Vec3 {
+                                                x: run.colour[0],
+                                                y: run.colour[1],
+                                                z: run.colour[2],
+                                            });
+            renderer.set_push_constant_float(0, "text_opacity", run.colour[3]);
+
+            for quad in run.quads.iter() {
+                let corners = [to_clip(quad.top_left),
+                               to_clip(Vec2 {
+                                   x: quad.bottom_right.x,
+                                   y: quad.top_left.y,
+                               }),
+                               to_clip(quad.bottom_right),
+                               to_clip(Vec2 {
+                                   x: quad.top_left.x,
+                                   y: quad.bottom_right.y,
+                               })];
+                let texcoords = [quad.texcoord_min,
+                                 Vec2 {
+                                     x: quad.texcoord_max.x,
+                                     y: quad.texcoord_min.y,
+                                 },
+                                 quad.texcoord_max,
+                                 Vec2 {
+                                     x: quad.texcoord_min.x,
+                                     y: quad.texcoord_max.y,
+                                 }];
+                threaddata.add_triangle_st_f2f2(&corners[0], &texcoords[0], &corners[1], &texcoords[1], &corners[2], &texcoords[2]);
+                threaddata.check_flush_st(false, renderer);
+                threaddata.add_triangle_st_f2f2(&corners[0], &texcoords[0], &corners[2], &texcoords[2], &corners[3], &texcoords[3]);
+                threaddata.check_flush_st(false, renderer);
+            }
+
+            // The colour changes with the next run
+            threaddata.check_flush_st(true, renderer);
+        }
+
+        renderer.end_pass();
+    }
+}
+
+/// Write the sources of the text shader to the working directory
+///
+/// Fonts are loaded at run time, but the text shader is built from these files during setup, so
+/// this must be called before the renderer is set up.
+pub fn install_text_shader_sources() -> Result<(), io::Error> {
+    write_entire_file(TEXT_VERTEX_SOURCE, TEXT_VERTEX_FILENAME)?;
+    write_entire_file(TEXT_FRAGMENT_SOURCE, TEXT_FRAGMENT_FILENAME)?;
+
+    Ok(())
+}
+
+/// Add the shader specification of the text shader
+///
+/// The shader blends the glyphs over the colour target without testing depth, so the pass
+/// normally comes last.
+///
+/// resource_manager: The resource manager to add the specification to
+/// pass_name: The pass the shader is used in
+pub fn register_text_shader(resource_manager: &mut ResourceManager, pass_name: &'static str) {
+    let shader_spec = ShaderSpec {
+        name: TEXT_SHADER_NAME,
+        library_files: vec![],
+        shader_files: vec![ShaderFilesSpecification {
+                               filename: TEXT_VERTEX_FILENAME,
+                               shader_stage: ShaderStage::VertexShader,
+                               spirv_out: "text.vert.spv",
+                               reflect_out: "text.vert.rfl",
+                               entry_point: DEFAULT_ENTRY_POINT,
+                           },
+                           ShaderFilesSpecification {
+                               filename: TEXT_FRAGMENT_FILENAME,
+                               shader_stage: ShaderStage::FragmentShader,
+                               spirv_out: "text.frag.spv",
+                               reflect_out: "text.frag.rfl",
+                               entry_point: DEFAULT_ENTRY_POINT,
+                           }],
+        fallback_shader_files: vec![],
+        uniform_block_names: vec![],
+        uniform_specs: vec![UniformSpec {
+                                name: TEXT_ATLAS_TEXTURE,
+                                set: 1,
+                                binding: 0,
+                                uniform_type: UniformType::CombinedImageSampler,
+                            }],
+        push_constants: vec![PushConstantSpec {
+                                 name: "text_tint",
+                                 offset: 0,
+                                 size: 12,
+                             },
+                             PushConstantSpec {
+                                 name: "text_opacity",
+                                 offset: 12,
+                                 size: 4,
+                             },
+                             PushConstantSpec {
+                                 name: "text_distance_field",
+                                 offset: 16,
+                                 size: 4,
+                             }],
+        vertex_array_type: VertexArrayType::F2F2,
+        primitive: PrimitiveType::PrimitiveTriangles,
+        instance_layout: None,
+        attributes: vec!["position", "texcoord"],
+        fragment_out: TEXT_FRAGMENT_OUT,
+        extra_fragment_outs: vec![],
+        render_state: RenderState::alpha_blended(),
+        stencil: None,
+        bindless_textures: false,
+        pass_name: pass_name,
+    };
+    resource_manager.shader_specs.insert(TEXT_SHADER_NAME, shader_spec);
+}
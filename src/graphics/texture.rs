@@ -29,6 +29,13 @@
 // DAMAGE.
 
 use std::any::Any;
+use std::time::{SystemTime, UNIX_EPOCH};
+
+use graphics::renderer::Renderer;
+use misc::fileutils::*;
+
+/// The number of faces of a cubemap texture
+pub const CUBE_FACES: u32 = 6;
 
 pub trait Texture {
     /// To facilitate downcasting back to a concrete type
@@ -37,4 +44,118 @@ pub trait Texture {
 
     /// Bind the texture as the first active texture
     fn bind(&self, num: i32);
+
+    /// Return the number of layers, six for a cubemap and one for a plain texture
+    fn get_layers(&self) -> u32;
+
+    /// Replace the contents of one layer of an array texture, or one face of a cubemap
+    ///
+    /// Cubemap faces are in the order +X, -X, +Y, -Y, +Z, -Z.  A layered texture has no mip
+    /// chain, and the contents of a layer are undefined until it is first uploaded.
+    ///
+    /// renderer: The renderer object
+    /// layer: The layer or face to replace
+    /// data: The image data for the whole layer, in the format of the texture
+    fn upload_layer(&mut self, renderer: &mut Box<Renderer>, layer: u32, data: &Vec<u8>);
+
+    /// Replace the contents of a rectangle of a plain texture, leaving the rest of it untouched
+    ///
+    /// The rows of the data are counted from the first row of the texture's data, as when it
+    /// was created.  Only the top mip level is written, so the smaller levels of a texture with
+    /// a generated mip chain keep their previous contents.
+    ///
+    /// renderer: The renderer object
+    /// x: The column of the left edge of the rectangle
+    /// y: The row of the first row of the rectangle
+    /// width: The width of the rectangle
+    /// height: The height of the rectangle
+    /// data: The image data for the rectangle, tightly packed in the format of the texture
+    fn update_region(&mut self, renderer: &mut Box<Renderer>, x: u32, y: u32, width: u32, height: u32, data: &Vec<u8>);
+
+    /// Reload the texture if it was loaded from a file that has since been modified
+    ///
+    /// The image is uploaded again, at its new size if that has changed, and the descriptors of
+    /// the shaders sampling it are updated.  Textures not loaded from a file on disk are never
+    /// reloaded.
+    ///
+    /// renderer: The renderer object
+    ///
+    /// Returns: true if the texture was reloaded
+    fn check_for_reload(&mut self, renderer: &mut Box<Renderer>) -> bool;
+}
+
+/// Check that a rectangle lies within a texture and that the data to write to it fills it
+///
+/// texture_width: The width of the texture
+/// texture_height: The height of the texture
+/// x: The column of the left edge of the rectangle
+/// y: The row of the first row of the rectangle
+/// width: The width of the rectangle
+/// height: The height of the rectangle
+/// bytes_per_pixel: The size of a pixel of the texture in bytes
+/// data_len: The size of the data in bytes
+pub fn check_texture_region(texture_width: u32,
+                            texture_height: u32,
+                            x: u32,
+                            y: u32,
+                            width: u32,
+                            height: u32,
+                            bytes_per_pixel: u32,
+                            data_len: usize)
+                            -> Result<(), String> {
+    if width == 0 || height == 0 {
+        return Err(format!("the region {}x{} is empty", width, height));
+    }
+    if x as u64 + width as u64 > texture_width as u64 || y as u64 + height as u64 > texture_height as u64 {
+        return Err(format!("the region {}x{} at ({}, {}) extends beyond the {}x{} texture",
+                           width,
+                           height,
+                           x,
+                           y,
+                           texture_width,
+                           texture_height));
+    }
+
+    let expected = width as usize * height as usize * bytes_per_pixel as usize;
+    if data_len != expected {
+        return Err(format!("{} bytes of data do not fill the {} bytes of the region", data_len, expected));
+    }
+
+    Ok(())
+}
+
+/// The file a texture was loaded from, which is watched for modification
+#[derive(Clone, Debug)]
+pub struct TextureSource {
+    pub filename: String,
+    pub generate_mipmaps: bool,
+    pub loaded: SystemTime, // The modification time of the file when it was last loaded
+}
+
+impl TextureSource {
+    /// Record the file a texture is being loaded from
+    ///
+    /// filename: The name of the file
+    /// generate_mipmaps: Whether the texture has a generated mip chain
+    pub fn new(filename: &str, generate_mipmaps: bool) -> TextureSource {
+        TextureSource {
+            filename: filename.to_string(),
+            generate_mipmaps: generate_mipmaps,
+            loaded: get_last_modification_timestamp(filename).unwrap_or(UNIX_EPOCH),
+        }
+    }
+
+    /// Return whether the file has been modified since it was last loaded, noting the new time if so
+    ///
+    /// A file that cannot be found, for instance while an editor is replacing it, is not reported
+    /// as modified until it reappears.
+    pub fn check_modified(&mut self) -> bool {
+        match get_last_modification_timestamp(&self.filename) {
+            Ok(modified) if modified > self.loaded => {
+                self.loaded = modified;
+                true
+            }
+            _ => false,
+        }
+    }
 }
@@ -0,0 +1,432 @@
+// Copyright (c) 2016-2017 Bruce Stenning. All rights reserved.
+//
+// Redistribution and use in source and binary forms, with or without
+// modification, are permitted provided that the following conditions
+// are met:
+//
+// 1. Redistributions of source code must retain the above copyright
+//    notice, this list of conditions and the following disclaimer.
+//
+// 2. Redistributions in binary form must reproduce the above copyright
+//    notice, this list of conditions and the following disclaimer in the
+//    documentation and/or other materials provided with the distribution.
+//
+// 3. Neither the name of the copyright holder nor the names of its
+//    contributors may be used to endorse or promote products derived
+//    from this software without specific prior written permission.
+//
+// THIS SOFTWARE IS PROVIDED BY THE COPYRIGHT HOLDERS AND CONTRIBUTORS
+// AS IS" AND ANY EXPRESS OR IMPLIED WARRANTIES, INCLUDING, BUT NOT
+// LIMITED TO, THE IMPLIED WARRANTIES OF MERCHANTABILITY AND FITNESS
+// FOR A PARTICULAR PURPOSE ARE DISCLAIMED. IN NO EVENT SHALL THE
+// COPYRIGHT HOLDER OR CONTRIBUTORS BE LIABLE FOR ANY DIRECT, INDIRECT,
+// INCIDENTAL, SPECIAL, EXEMPLARY, OR CONSEQUENTIAL DAMAGES (INCLUDING,
+// BUT NOT LIMITED TO, PROCUREMENT OF SUBSTITUTE GOODS OR SERVICES; LOSS
+// OF USE, DATA, OR PROFITS; OR BUSINESS INTERRUPTION) HOWEVER CAUSED
+// AND ON ANY THEORY OF LIABILITY, WHETHER IN CONTRACT, STRICT LIABILITY,
+// OR TORT (INCLUDING NEGLIGENCE OR OTHERWISE) ARISING IN ANY WAY OUT OF
+// THE USE OF THIS SOFTWARE, EVEN IF ADVISED OF THE POSSIBILITY OF SUCH
+// DAMAGE.
+use std::collections::HashMap;
+use std::io;
+
+use graphics::image::Image;
+use graphics::pbr::PBR_CAMERA_BLOCK;
+use graphics::readback::ReadbackHandle;
+use graphics::renderer::*;
+use graphics::rendertarget::*;
+use graphics::resources::*;
+use graphics::shader::*;
+use misc::fileutils::*;
+
+/// The name the texture feedback shader is registered under
+pub const TEXTURE_FEEDBACK_SHADER_NAME: &'static str = "texture_feedback";
+
+/// The push constant holding the streaming slot of the texture being drawn
+pub const FEEDBACK_TEXTURE_SLOT: &'static str = "feedback_texture_slot";
+
+/// The push constant holding the width in texels of the finest mip level of the texture being drawn
+pub const FEEDBACK_TEXTURE_WIDTH: &'static str = "feedback_texture_width";
+
+/// The push constant holding the height in texels of the finest mip level of the texture being drawn
+pub const FEEDBACK_TEXTURE_HEIGHT: &'static str = "feedback_texture_height";
+
+/// The push constant holding the bias from the feedback target's resolution to the screen's
+pub const FEEDBACK_LOD_BIAS: &'static str = "feedback_lod_bias";
+
+/// The width and height of the render target the feedback pass draws into
+pub const TEXTURE_FEEDBACK_SIZE: u32 = 128;
+
+/// The number of streaming slots the feedback can distinguish, as the slot is written in two bytes
+pub const TEXTURE_FEEDBACK_MAX_SLOTS: u32 = 65535;
+
+/// The number of mip levels the feedback can distinguish
+pub const TEXTURE_FEEDBACK_MAX_MIPS: usize = 16;
+
+/// The number of feedback readbacks without a texture being sampled before it is evicted to its coarsest level
+pub const TEXTURE_FEEDBACK_STALE_READBACKS: u64 = 8;
+
+const TEXTURE_FEEDBACK_VERTEX_FILENAME: &'static str = "texture_feedback.vert";
+const TEXTURE_FEEDBACK_FRAGMENT_FILENAME: &'static str = "texture_feedback.frag";
+
+const TEXTURE_FEEDBACK_VERTEX_SOURCE: &'static str = include_str!("shaders/texture_feedback.vert");
+const TEXTURE_FEEDBACK_FRAGMENT_SOURCE: &'static str = include_str!("shaders/texture_feedback.frag");
+
+/// Return the LOD bias that corrects the derivatives of the low resolution feedback pass to the screen's
+///
+/// screen_width: The width of the screen the scene is drawn to
+/// feedback_width: The width of the feedback render target
+pub fn feedback_lod_bias(screen_width: u32, feedback_width: u32) -> f32 {
+    -(screen_width.max(1) as f32 / feedback_width.max(1) as f32).log2()
+}
+
+/// Decode a pixel of the feedback readback into the streaming slot and mip level it sampled
+///
+/// The red and green channels hold the slot plus one, so that the cleared target reads as nothing
+/// sampled, and the blue channel holds the mip level.
+///
+/// pixel: The RGB bytes of the pixel
+pub fn decode_feedback_pixel(pixel: &[u8]) -> Option<(u32, usize)> {
+    let encoded = pixel[0] as u32 | (pixel[1] as u32) << 8;
+    if encoded == 0 {
+        None
+    } else {
+        Some((encoded - 1, (pixel[2] as usize).min(TEXTURE_FEEDBACK_MAX_MIPS - 1)))
+    }
+}
+
+/// The pixels of the feedback in which a texture was sampled, by mip level
+#[derive(Clone, Debug, PartialEq)]
+pub struct TextureCoverage {
+    pub pixels: [u32; TEXTURE_FEEDBACK_MAX_MIPS],
+    pub total: u32,
+}
+
+impl TextureCoverage {
+    pub fn new() -> TextureCoverage {
+        TextureCoverage {
+            pixels: [0; TEXTURE_FEEDBACK_MAX_MIPS],
+            total: 0,
+        }
+    }
+
+    /// Return the finest mip level sampled, if any was
+    pub fn finest_mip(&self) -> Option<usize> {
+        self.pixels.iter().position(|&count| count > 0)
+    }
+}
+
+/// Which textures and mip levels one readback of the feedback pass found sampled
+pub struct TextureFeedback {
+    pub pixels: u32, // The number of pixels in the feedback, sampled or not
+    pub textures: HashMap<u32, TextureCoverage>, // By streaming slot
+}
+
+impl TextureFeedback {
+    pub fn new() -> TextureFeedback {
+        TextureFeedback {
+            pixels: 0,
+            textures: HashMap::new(),
+        }
+    }
+
+    /// Build the feedback from a readback of the feedback render target
+    ///
+    /// image: The RGB readback of the feedback render target
+    pub fn from_feedback_image(image: &Image) -> TextureFeedback {
+        let mut feedback = TextureFeedback::new();
+        for pixel in image.get_data().chunks(3) {
+            feedback.add(decode_feedback_pixel(pixel));
+        }
+        feedback
+    }
+
+    /// Add a pixel of feedback
+    ///
+    /// sample: The streaming slot and mip level sampled, or None for a pixel where no texture was
+    pub fn add(&mut self, sample: Option<(u32, usize)>) {
+        self.pixels += 1;
+        if let Some((slot, mip)) = sample {
+            let coverage = self.textures.entry(slot).or_insert_with(TextureCoverage::new);
+            coverage.pixels[mip] += 1;
+            coverage.total += 1;
+        }
+    }
+
+    /// Return the fraction of the screen in which the texture was sampled
+    ///
+    /// slot: The streaming slot of the texture
+    pub fn coverage(&self, slot: u32) -> f32 {
+        match self.textures.get(&slot) {
+            Some(coverage) => coverage.total as f32 / self.pixels.max(1) as f32,
+            None => 0.0f32,
+        }
+    }
+}
+
+/// The residency of a texture managed by the streamer
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub struct StreamedTexture {
+    pub mip_count: usize,
+    pub resident_mip: usize, // The finest mip level resident, those coarser are resident too
+    pub wanted_mip: usize, // The finest mip level the feedback has asked for
+    pub coverage: f32, // The fraction of the screen the texture covered when last sampled
+    pub last_sampled: Option<u64>, // The feedback readback the texture was last sampled in
+}
+
+/// A mip level to upload or evict, with the priority to do so
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub struct StreamingRequest {
+    pub slot: u32,
+    pub mip: usize,
+    pub priority: f32,
+}
+
+/// Prioritises the uploads and evictions of streamed mip levels from feedback of what was sampled
+///
+/// A feedback pass draws the scene into a low resolution target, writing the streaming slot and
+/// mip level of each pixel's texture, which update reads back asynchronously as with exposure
+/// metering.  Uploads go to the textures covering most of the screen that are furthest from the
+/// level they want, and evictions to those no longer sampled or sampled coarser than resident,
+/// which reflects what was actually drawn rather than an estimate from distance.
+pub struct TextureStreamer {
+    textures: HashMap<u32, StreamedTexture>,
+    readbacks: u64,
+    pending_readback: Option<ReadbackHandle>,
+}
+
+impl TextureStreamer {
+    pub fn new() -> TextureStreamer {
+        TextureStreamer {
+            textures: HashMap::new(),
+            readbacks: 0,
+            pending_readback: None,
+        }
+    }
+
+    /// Start managing a texture, with only its coarsest mip level resident
+    ///
+    /// slot: The streaming slot the texture is drawn with in the feedback pass
+    /// mip_count: The number of mip levels of the texture
+    pub fn register(&mut self, slot: u32, mip_count: usize) {
+        debug_assert!(slot < TEXTURE_FEEDBACK_MAX_SLOTS);
+        let coarsest = mip_count.max(1) - 1;
+        self.textures.insert(slot,
+                             StreamedTexture {
+                                 mip_count: mip_count.max(1),
+                                 resident_mip: coarsest,
+                                 wanted_mip: coarsest,
+                                 coverage: 0.0f32,
+                                 last_sampled: None,
+                             });
+    }
+
+    /// Stop managing a texture
+    ///
+    /// slot: The streaming slot of the texture
+    pub fn unregister(&mut self, slot: u32) {
+        self.textures.remove(&slot);
+    }
+
+    /// Return the residency of a texture
+    ///
+    /// slot: The streaming slot of the texture
+    pub fn get(&self, slot: u32) -> Option<&StreamedTexture> {
+        self.textures.get(&slot)
+    }
+
+    /// Record that a mip level was uploaded or evicted, so that it and those coarser are resident
+    ///
+    /// slot: The streaming slot of the texture
+    /// mip: The finest mip level now resident
+    pub fn mark_resident(&mut self, slot: u32, mip: usize) {
+        if let Some(texture) = self.textures.get_mut(&slot) {
+            texture.resident_mip = mip.min(texture.mip_count - 1);
+        }
+    }
+
+    /// Update the wanted mip levels from a readback of the feedback pass
+    ///
+    /// Textures that go unsampled for TEXTURE_FEEDBACK_STALE_READBACKS readbacks fall back to
+    /// wanting only their coarsest level.
+    ///
+    /// feedback: The textures and mip levels sampled
+    pub fn apply_feedback(&mut self, feedback: &TextureFeedback) {
+        self.readbacks += 1;
+        for (slot, texture) in self.textures.iter_mut() {
+            let sampled = feedback.textures.get(slot).and_then(|coverage| coverage.finest_mip());
+            match sampled {
+                Some(mip) => {
+                    texture.wanted_mip = mip.min(texture.mip_count - 1);
+                    texture.coverage = feedback.coverage(*slot);
+                    texture.last_sampled = Some(self.readbacks);
+                }
+                None => {
+                    let stale = match texture.last_sampled {
+                        Some(last) => self.readbacks - last >= TEXTURE_FEEDBACK_STALE_READBACKS,
+                        None => true,
+                    };
+                    if stale {
+                        texture.wanted_mip = texture.mip_count - 1;
+                        texture.coverage = 0.0f32;
+                    }
+                }
+            }
+        }
+    }
+
+    /// Return the next finer mip level of each texture that wants more detail, highest priority first
+    ///
+    /// The priority is the screen coverage of the texture times the number of levels it is short.
+    pub fn uploads(&self) -> Vec<StreamingRequest> {
+        let mut requests: Vec<StreamingRequest> = self.textures
+            .iter()
+            .filter(|&(_, texture)| texture.resident_mip > texture.wanted_mip)
+            .map(|(slot, texture)| {
+                StreamingRequest {
+                    slot: *slot,
+                    mip: texture.resident_mip - 1,
+                    priority: texture.coverage * (texture.resident_mip - texture.wanted_mip) as f32,
+                }
+            })
+            .collect();
+        requests.sort_by(|a, b| b.priority.partial_cmp(&a.priority).unwrap().then(a.slot.cmp(&b.slot)));
+        requests
+    }
+
+    /// Return the finest resident mip level of each texture that has more detail than it wants,
+    /// highest priority first
+    ///
+    /// The priority is the number of levels in excess, weighted towards textures covering little
+    /// of the screen.
+    pub fn evictions(&self) -> Vec<StreamingRequest> {
+        let mut requests: Vec<StreamingRequest> = self.textures
+            .iter()
+            .filter(|&(_, texture)| texture.resident_mip < texture.wanted_mip)
+            .map(|(slot, texture)| {
+                StreamingRequest {
+                    slot: *slot,
+                    mip: texture.resident_mip,
+                    priority: (texture.wanted_mip - texture.resident_mip) as f32 * (1.0f32 - texture.coverage),
+                }
+            })
+            .collect();
+        requests.sort_by(|a, b| b.priority.partial_cmp(&a.priority).unwrap().then(a.slot.cmp(&b.slot)));
+        requests
+    }
+
+    /// Read back the last feedback pass and apply it
+    ///
+    /// This should be called once per frame, after the feedback pass has been drawn.  A new
+    /// readback is only requested once the previous one has completed.
+    ///
+    /// renderer: The renderer that drew the feedback pass
+    /// feedback_target: The render target the feedback pass drew into
+    pub fn update<Rend: Renderer + ?Sized>(&mut self, renderer: &mut Rend, feedback_target: &RenderTarget) {
+        if let Some(handle) = self.pending_readback {
+            if let Some(image) = renderer.poll_readback(handle) {
+                self.apply_feedback(&TextureFeedback::from_feedback_image(&image));
+                self.pending_readback = None;
+            }
+        }
+        if self.pending_readback.is_none() {
+            self.pending_readback = Some(renderer.request_readback(feedback_target));
+        }
+    }
+}
+
+/// Write the sources of the texture feedback shader to the working directory
+///
+/// The feedback shader is built alongside the PBR shader whose vertices it draws, so as with
+/// install_pbr_shader_sources this must be called before the renderer is set up.
+pub fn install_texture_feedback_shader_sources() -> Result<(), io::Error> {
+    write_entire_file(TEXTURE_FEEDBACK_VERTEX_SOURCE, TEXTURE_FEEDBACK_VERTEX_FILENAME)?;
+    write_entire_file(TEXTURE_FEEDBACK_FRAGMENT_SOURCE, TEXTURE_FEEDBACK_FRAGMENT_FILENAME)?;
+
+    Ok(())
+}
+
+/// Add the shader specification of the texture feedback shader
+///
+/// The feedback shader draws the same F3F3F3 vertices as the PBR shader, transformed by its
+/// camera block, so register_pbr_shaders must have been called.  It should draw into a render
+/// target of TEXTURE_FEEDBACK_SIZE square, cleared to black.
+///
+/// resource_manager: The resource manager to add the specification to
+/// pass_name: The pass the feedback shader is used in, which draws to an offscreen target
+pub fn register_texture_feedback_shader(resource_manager: &mut ResourceManager, pass_name: &'static str) {
+    let feedback_spec = ShaderSpec {
+        name: TEXTURE_FEEDBACK_SHADER_NAME,
+        library_files: vec![],
+        shader_files: vec![ShaderFilesSpecification {
+                               filename: TEXTURE_FEEDBACK_VERTEX_FILENAME,
+                               shader_stage: ShaderStage::VertexShader,
+                               spirv_out: "texture_feedback.vert.spv",
+                               reflect_out: "texture_feedback.vert.rfl",
+                               entry_point: DEFAULT_ENTRY_POINT,
+                           },
+                           ShaderFilesSpecification {
+                               filename: TEXTURE_FEEDBACK_FRAGMENT_FILENAME,
+                               shader_stage: ShaderStage::FragmentShader,
+                               spirv_out: "texture_feedback.frag.spv",
+                               reflect_out: "texture_feedback.frag.rfl",
+                               entry_point: DEFAULT_ENTRY_POINT,
+                           }],
+        fallback_shader_files: vec![],
+        uniform_block_names: vec![PBR_CAMERA_BLOCK],
+        uniform_specs: vec![],
+        push_constants: vec![PushConstantSpec {
+                                 name: FEEDBACK_TEXTURE_SLOT,
+                                 offset: 0,
+                                 size: 4,
+                             },
+                             PushConstantSpec {
+                                 name: FEEDBACK_TEXTURE_WIDTH,
+                                 offset: 4,
+                                 size: 4,
+                             },
+                             PushConstantSpec {
+                                 name: FEEDBACK_TEXTURE_HEIGHT,
+                                 offset: 8,
+                                 size: 4,
+                             },
+                             PushConstantSpec {
+                                 name: FEEDBACK_LOD_BIAS,
+                                 offset: 12,
+                                 size: 4,
+                             }],
+        vertex_array_type: VertexArrayType::F3F3F3,
+        primitive: PrimitiveType::PrimitiveTriangles,
+        instance_layout: None,
+        attributes: vec!["position", "normal", "texcoord"],
+        fragment_out: "texture_feedback",
+        extra_fragment_outs: vec![],
+        render_state: RenderState::opaque(),
+        stencil: None,
+        bindless_textures: false,
+        pass_name: pass_name,
+    };
+    resource_manager.shader_specs.insert(TEXTURE_FEEDBACK_SHADER_NAME, feedback_spec);
+}
+
+/// Set the texture sampled by the following feedback draws of a thread
+///
+/// This is called within the feedback pass before drawing the geometry using each texture.
+///
+/// renderer: The renderer drawing the feedback pass
+/// thr: The thread that draws the geometry
+/// slot: The streaming slot of the texture
+/// width: The width in texels of the texture's finest mip level
+/// height: The height in texels of the texture's finest mip level
+/// lod_bias: The bias from feedback_lod_bias for the screen the scene is drawn to
+pub fn set_feedback_texture<Rend: Renderer + ?Sized>(renderer: &Rend,
+                                                     thr: usize,
+                                                     slot: u32,
+                                                     width: u32,
+                                                     height: u32,
+                                                     lod_bias: f32) {
+    renderer.set_push_constant_int(thr, FEEDBACK_TEXTURE_SLOT, slot as i32);
+    renderer.set_push_constant_float(thr, FEEDBACK_TEXTURE_WIDTH, width as f32);
+    renderer.set_push_constant_float(thr, FEEDBACK_TEXTURE_HEIGHT, height as f32);
+    renderer.set_push_constant_float(thr, FEEDBACK_LOD_BIAS, lod_bias);
+}
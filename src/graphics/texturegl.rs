@@ -36,12 +36,25 @@ use std::any::Any;
 use gl;
 use gl::types::*;
 
-use graphics::texture::Texture;
+use graphics::texture::{Texture, TextureSource, CUBE_FACES, check_texture_region};
 use graphics::renderer::Renderer;
+use graphics::renderergl::{gl_memory_usage, gl_texel_bytes};
+use graphics::memoryusage::MemoryCategory;
+use graphics::colourspace::ColourSpace;
+use graphics::image::Image;
+use misc::embeddedresources::*;
 
 #[derive(Clone)]
 pub struct TextureGl {
     pub texture_name: GLuint,
+    target: GLenum,
+    internal_format: GLuint,
+    width: GLuint,
+    height: GLuint,
+    layers: GLuint,
+    data_format: GLuint,
+    data_type: GLuint,
+    source: Option<TextureSource>, // The file the texture was loaded from, if it is to be reloaded
 }
 
 impl TextureGl {
@@ -51,13 +64,20 @@ impl TextureGl {
     /// width: The width of the texture
     /// height: The height of the texture
     /// data: The image data, empty if just defining the texture not populating it
-    pub fn new_float_rgba(_: &mut Box<Renderer>, width: u32, height: u32, data: &Vec<u8>) -> TextureGl {
+    /// generate_mipmaps: Whether to generate the full mip chain from the data
+    pub fn new_float_rgba(_: &mut Box<Renderer>,
+                          width: u32,
+                          height: u32,
+                          data: &Vec<u8>,
+                          generate_mipmaps: bool)
+                          -> TextureGl {
         TextureGl::new_specific(gl::RGBA,
                                 gl::RGBA,
                                 gl::FLOAT,
                                 width as GLuint,
                                 height as GLuint,
-                                data)
+                                data,
+                                generate_mipmaps)
     }
 
     /// Set up a new 3-component byte texture of the specified dimensions and the specified contents
@@ -66,13 +86,142 @@ impl TextureGl {
     /// width: The width of the texture
     /// height: The height of the texture
     /// data: The image data, empty if just defining the texture not populating it
-    pub fn new_ubyte_rgba(_: &mut Box<Renderer>, width: u32, height: u32, data: &Vec<u8>) -> TextureGl {
-        TextureGl::new_specific(gl::RGBA,
+    /// generate_mipmaps: Whether to generate the full mip chain from the data
+    pub fn new_ubyte_rgba(renderer: &mut Box<Renderer>,
+                          width: u32,
+                          height: u32,
+                          data: &Vec<u8>,
+                          generate_mipmaps: bool)
+                          -> TextureGl {
+        TextureGl::new_ubyte_rgba_with_colour_space(renderer, width, height, data, generate_mipmaps, ColourSpace::Linear)
+    }
+
+    /// Set up a new 4-component byte texture whose data is encoded in the given colour space
+    ///
+    /// sRGB textures have the SRGB8_ALPHA8 internal format, so that sampling them decodes the
+    /// data to linear.
+    ///
+    /// renderer: The renderer object
+    /// width: The width of the texture
+    /// height: The height of the texture
+    /// data: The image data, empty if just defining the texture not populating it
+    /// generate_mipmaps: Whether to generate the full mip chain from the data
+    /// colour_space: The colour space of the data
+    pub fn new_ubyte_rgba_with_colour_space(_: &mut Box<Renderer>,
+                                            width: u32,
+                                            height: u32,
+                                            data: &Vec<u8>,
+                                            generate_mipmaps: bool,
+                                            colour_space: ColourSpace)
+                                            -> TextureGl {
+        let internal_format = match colour_space {
+            ColourSpace::Linear => gl::RGBA,
+            ColourSpace::Srgb => gl::SRGB8_ALPHA8,
+        };
+        TextureGl::new_specific(internal_format,
                                 gl::RGBA,
                                 gl::UNSIGNED_BYTE,
                                 width as GLuint,
                                 height as GLuint,
-                                data)
+                                data,
+                                generate_mipmaps)
+    }
+
+    /// Set up a new 4-component byte texture from a PNG file, to be reloaded when the file is modified
+    ///
+    /// Textures loaded from the embedded resources are not reloaded.
+    ///
+    /// renderer: The renderer object
+    /// embedded: The embedded resources object
+    /// filename: The name of the PNG file
+    /// generate_mipmaps: Whether to generate the full mip chain from the image
+    pub fn new_from_png(renderer: &mut Box<Renderer>,
+                        embedded: Option<&EmbeddedResources>,
+                        filename: &str,
+                        generate_mipmaps: bool)
+                        -> TextureGl {
+        TextureGl::new_from_png_with_colour_space(renderer, embedded, filename, generate_mipmaps, ColourSpace::Linear)
+    }
+
+    /// Set up a new 4-component byte texture from a PNG file in the given colour space, e.g. sRGB
+    /// for an albedo map
+    ///
+    /// renderer: The renderer object
+    /// embedded: The embedded resources object
+    /// filename: The name of the PNG file
+    /// generate_mipmaps: Whether to generate the full mip chain from the image
+    /// colour_space: The colour space of the image
+    pub fn new_from_png_with_colour_space(renderer: &mut Box<Renderer>,
+                                          embedded: Option<&EmbeddedResources>,
+                                          filename: &str,
+                                          generate_mipmaps: bool,
+                                          colour_space: ColourSpace)
+                                          -> TextureGl {
+        let image = Image::load_png(embedded, filename);
+        let mut texture = TextureGl::new_ubyte_rgba_with_colour_space(renderer,
+                                                                      image.get_width(),
+                                                                      image.get_height(),
+                                                                      image.get_data(),
+                                                                      generate_mipmaps,
+                                                                      colour_space);
+        match embedded {
+            Some(ref embedded) if embedded.use_me() => (),
+            _ => texture.source = Some(TextureSource::new(filename, generate_mipmaps)),
+        }
+
+        texture
+    }
+
+    /// Set up a new 4-component byte cubemap with square faces of the specified size
+    ///
+    /// The faces are filled with upload_layer.
+    ///
+    /// renderer: The renderer object
+    /// size: The width and height of each face
+    pub fn new_ubyte_rgba_cube(_: &mut Box<Renderer>, size: u32) -> TextureGl {
+        TextureGl::new_layered_specific(gl::TEXTURE_CUBE_MAP,
+                                        gl::RGBA,
+                                        gl::RGBA,
+                                        gl::UNSIGNED_BYTE,
+                                        size as GLuint,
+                                        size as GLuint,
+                                        CUBE_FACES as GLuint)
+    }
+
+    /// Set up a new 4-component byte 2D array texture of the specified dimensions
+    ///
+    /// The layers are filled with upload_layer.
+    ///
+    /// renderer: The renderer object
+    /// width: The width of each layer
+    /// height: The height of each layer
+    /// layers: The number of layers
+    pub fn new_ubyte_rgba_array(_: &mut Box<Renderer>, width: u32, height: u32, layers: u32) -> TextureGl {
+        TextureGl::new_layered_specific(gl::TEXTURE_2D_ARRAY,
+                                        gl::RGBA,
+                                        gl::RGBA,
+                                        gl::UNSIGNED_BYTE,
+                                        width as GLuint,
+                                        height as GLuint,
+                                        layers as GLuint)
+    }
+
+    /// Set up a new 4-component float 2D array texture of the specified dimensions
+    ///
+    /// The layers are filled with upload_layer.
+    ///
+    /// renderer: The renderer object
+    /// width: The width of each layer
+    /// height: The height of each layer
+    /// layers: The number of layers
+    pub fn new_float_rgba_array(_: &mut Box<Renderer>, width: u32, height: u32, layers: u32) -> TextureGl {
+        TextureGl::new_layered_specific(gl::TEXTURE_2D_ARRAY,
+                                        gl::RGBA32F,
+                                        gl::RGBA,
+                                        gl::FLOAT,
+                                        width as GLuint,
+                                        height as GLuint,
+                                        layers as GLuint)
     }
 
     /// Bind the texture as the specified active texture number
@@ -82,10 +231,101 @@ impl TextureGl {
         unsafe {
             gl::ActiveTexture(match num {
                 1 => gl::TEXTURE1,
+                2 => gl::TEXTURE2,
                 _ => gl::TEXTURE0,
             });
 
+            gl::BindTexture(self.target, self.texture_name);
+        }
+    }
+
+    /// Replace the contents of one layer of an array texture, or one face of a cubemap
+    ///
+    /// layer: The layer or face to replace
+    /// data: The image data for the whole layer, in the format of the texture
+    pub fn upload_layer(&self, layer: u32, data: &Vec<u8>) {
+        if layer >= self.layers {
+            panic!("Layer {} is out of range for a texture of {} layers", layer, self.layers);
+        }
+
+        unsafe {
+            gl::PixelStorei(gl::UNPACK_ALIGNMENT, 1);
+            gl::BindTexture(self.target, self.texture_name);
+
+            let ptr: *const raw::c_void = mem::transmute(data.as_ptr());
+            match self.target {
+                gl::TEXTURE_CUBE_MAP => {
+                    gl::TexSubImage2D(gl::TEXTURE_CUBE_MAP_POSITIVE_X + layer,
+                                      0, // Level
+                                      0, // X offset
+                                      0, // Y offset
+                                      self.width as GLint,
+                                      self.height as GLint,
+                                      self.data_format,
+                                      self.data_type,
+                                      ptr);
+                }
+                gl::TEXTURE_2D_ARRAY => {
+                    gl::TexSubImage3D(gl::TEXTURE_2D_ARRAY,
+                                      0, // Level
+                                      0, // X offset
+                                      0, // Y offset
+                                      layer as GLint,
+                                      self.width as GLint,
+                                      self.height as GLint,
+                                      1, // Depth
+                                      self.data_format,
+                                      self.data_type,
+                                      ptr);
+                }
+                _ => {
+                    gl::TexSubImage2D(gl::TEXTURE_2D,
+                                      0, // Level
+                                      0, // X offset
+                                      0, // Y offset
+                                      self.width as GLint,
+                                      self.height as GLint,
+                                      self.data_format,
+                                      self.data_type,
+                                      ptr);
+                }
+            }
+        }
+    }
+}
+
+impl TextureGl {
+    /// Replace the contents of a rectangle of the top mip level of a plain texture
+    ///
+    /// x: The column of the left edge of the rectangle
+    /// y: The row of the first row of the rectangle
+    /// width: The width of the rectangle
+    /// height: The height of the rectangle
+    /// data: The image data for the rectangle, tightly packed in the format of the texture
+    pub fn update_region(&self, x: u32, y: u32, width: u32, height: u32, data: &Vec<u8>) {
+        if self.target != gl::TEXTURE_2D {
+            panic!("Regions can only be updated in plain textures");
+        }
+
+        let bytes_per_pixel = gl_texel_bytes(gl::RGBA, self.data_type) as u32;
+        if let Err(e) = check_texture_region(self.width, self.height, x, y, width, height, bytes_per_pixel, data.len()) {
+            panic!("Unable to update a texture region: {}", e);
+        }
+
+        unsafe {
+            gl::PixelStorei(gl::UNPACK_ALIGNMENT, 1);
             gl::BindTexture(gl::TEXTURE_2D, self.texture_name);
+
+            let ptr: *const raw::c_void = mem::transmute(data.as_ptr());
+            gl::TexSubImage2D(gl::TEXTURE_2D,
+                              0, // Level
+                              x as GLint,
+                              y as GLint,
+                              width as GLint,
+                              height as GLint,
+                              self.data_format,
+                              self.data_type,
+                              ptr);
         }
     }
 }
@@ -110,6 +350,78 @@ impl Texture for TextureGl {
 
         texture_gl.bind(num);
     }
+
+    /// Return the number of layers, six for a cubemap and one for a plain texture
+    fn get_layers(&self) -> u32 {
+        self.layers
+    }
+
+    /// Replace the contents of one layer of an array texture, or one face of a cubemap
+    ///
+    /// renderer: The renderer object
+    /// layer: The layer or face to replace
+    /// data: The image data for the whole layer, in the format of the texture
+    fn upload_layer(&mut self, _: &mut Box<Renderer>, layer: u32, data: &Vec<u8>) {
+        TextureGl::upload_layer(self, layer, data);
+    }
+
+    /// Replace the contents of a rectangle of a plain texture, leaving the rest of it untouched
+    ///
+    /// renderer: The renderer object
+    /// x: The column of the left edge of the rectangle
+    /// y: The row of the first row of the rectangle
+    /// width: The width of the rectangle
+    /// height: The height of the rectangle
+    /// data: The image data for the rectangle, tightly packed in the format of the texture
+    fn update_region(&mut self, _: &mut Box<Renderer>, x: u32, y: u32, width: u32, height: u32, data: &Vec<u8>) {
+        TextureGl::update_region(self, x, y, width, height, data);
+    }
+
+    /// Reload the texture if it was loaded from a file that has since been modified
+    ///
+    /// The storage is specified again under the same name, so bindings and framebuffer
+    /// attachments remain valid and no descriptors need updating.
+    ///
+    /// renderer: The renderer object
+    fn check_for_reload(&mut self, _: &mut Box<Renderer>) -> bool {
+        let generate_mipmaps = match self.source {
+            Some(ref mut source) if source.check_modified() => source.generate_mipmaps,
+            _ => return false,
+        };
+        let filename = self.source.as_ref().unwrap().filename.clone();
+        log_info!("Reloading texture {}", filename);
+
+        let image = Image::load_from_png(&filename);
+        self.width = image.get_width() as GLuint;
+        self.height = image.get_height() as GLuint;
+
+        unsafe {
+            gl::PixelStorei(gl::UNPACK_ALIGNMENT, 1);
+            gl::BindTexture(gl::TEXTURE_2D, self.texture_name);
+
+            let ptr: *const raw::c_void = mem::transmute(image.get_data().as_ptr());
+            gl::TexImage2D(gl::TEXTURE_2D,
+                           0, // Level
+                           self.internal_format as GLint,
+                           self.width as GLint,
+                           self.height as GLint,
+                           0, // Border
+                           self.data_format,
+                           self.data_type,
+                           ptr);
+            if generate_mipmaps {
+                gl::GenerateMipmap(gl::TEXTURE_2D);
+            }
+        }
+
+        let mut bytes = self.width as u64 * self.height as u64 * gl_texel_bytes(self.internal_format, self.data_type);
+        if generate_mipmaps {
+            bytes += bytes / 3; // The smaller levels add a third
+        }
+        gl_memory_usage().lock().unwrap().resize((gl::TEXTURE, self.texture_name as u64), bytes);
+
+        true
+    }
 }
 
 impl TextureGl {
@@ -121,12 +433,15 @@ impl TextureGl {
     /// width: The width of the texture
     /// height: The height of the texture
     /// data: The image data, empty if just defining the texture not populating it
+    /// generate_mipmaps: Whether to generate the full mip chain from the data and sample it
+    ///     trilinearly
     pub fn new_specific(internal_format: GLuint,
                         data_format: GLuint,
                         data_type: GLuint,
                         width: GLuint,
                         height: GLuint,
-                        data: &Vec<u8>)
+                        data: &Vec<u8>,
+                        generate_mipmaps: bool)
                         -> TextureGl {
         let mut texture_name: GLuint = 0;
 
@@ -154,8 +469,150 @@ impl TextureGl {
                            data_format,
                            data_type,
                            ptr);
+
+            if generate_mipmaps && data.len() != 0 {
+                gl::GenerateMipmap(gl::TEXTURE_2D);
+                gl::TexParameteri(gl::TEXTURE_2D, gl::TEXTURE_MAG_FILTER, gl::LINEAR as GLint);
+                gl::TexParameteri(gl::TEXTURE_2D,
+                                  gl::TEXTURE_MIN_FILTER,
+                                  gl::LINEAR_MIPMAP_LINEAR as GLint);
+            }
+        }
+
+        let mut bytes = width as u64 * height as u64 * gl_texel_bytes(internal_format, data_type);
+        if generate_mipmaps && data.len() != 0 {
+            bytes += bytes / 3; // The smaller levels add a third
+        }
+        gl_memory_usage().lock().unwrap().record((gl::TEXTURE, texture_name as u64), MemoryCategory::Textures, bytes);
+
+        TextureGl {
+            texture_name: texture_name,
+            target: gl::TEXTURE_2D,
+            internal_format: internal_format,
+            width: width,
+            height: height,
+            layers: 1,
+            data_format: data_format,
+            data_type: data_type,
+            source: None,
+        }
+    }
+
+    /// Reallocate the storage of a plain texture at a new size, discarding its contents
+    ///
+    /// The texture keeps its name, so its bindings and framebuffer attachments remain valid.
+    ///
+    /// internal_format: The internal format the texture was created with
+    /// width: The new width
+    /// height: The new height
+    pub fn reallocate(&mut self, internal_format: GLuint, width: u32, height: u32) {
+        debug_assert!(self.target == gl::TEXTURE_2D);
+
+        unsafe {
+            gl::BindTexture(gl::TEXTURE_2D, self.texture_name);
+            gl::TexImage2D(gl::TEXTURE_2D,
+                           0, // Level
+                           internal_format as GLint,
+                           width as GLint,
+                           height as GLint,
+                           0, // Border
+                           self.data_format,
+                           self.data_type,
+                           ptr::null());
+        }
+
+        self.internal_format = internal_format;
+        self.width = width as GLuint;
+        self.height = height as GLuint;
+
+        // Any category the texture was recorded under, such as a render target's, is kept
+        gl_memory_usage().lock().unwrap().resize((gl::TEXTURE, self.texture_name as u64),
+                                                 width as u64 * height as u64 *
+                                                 gl_texel_bytes(internal_format, self.data_type));
+    }
+
+    /// Set up a new layered texture of the specified target and format, leaving it unpopulated
+    ///
+    /// target: GL_TEXTURE_CUBE_MAP, with six square layers, or GL_TEXTURE_2D_ARRAY
+    /// internal_format: The interal format of the texture
+    /// data_format: The data format of the pixel data
+    /// data_type: The data type of the pixel data
+    /// width: The width of each layer
+    /// height: The height of each layer
+    /// layers: The number of layers
+    pub fn new_layered_specific(target: GLenum,
+                                internal_format: GLuint,
+                                data_format: GLuint,
+                                data_type: GLuint,
+                                width: GLuint,
+                                height: GLuint,
+                                layers: GLuint)
+                                -> TextureGl {
+        let mut texture_name: GLuint = 0;
+
+        unsafe {
+            gl::GenTextures(1, &mut texture_name);
+
+            gl::BindTexture(target, texture_name);
+            gl::TexParameteri(target, gl::TEXTURE_MAG_FILTER, gl::LINEAR as GLint);
+            gl::TexParameteri(target, gl::TEXTURE_MIN_FILTER, gl::LINEAR as GLint);
+            gl::TexParameteri(target, gl::TEXTURE_MAX_LEVEL, 0);
+
+            if target == gl::TEXTURE_CUBE_MAP {
+                if width != height || layers != CUBE_FACES {
+                    panic!("A cubemap must have six square faces");
+                }
+
+                // Filter across the edges of faces, as Vulkan always does
+                gl::Enable(gl::TEXTURE_CUBE_MAP_SEAMLESS);
+
+                gl::TexParameteri(target, gl::TEXTURE_WRAP_S, gl::CLAMP_TO_EDGE as GLint);
+                gl::TexParameteri(target, gl::TEXTURE_WRAP_T, gl::CLAMP_TO_EDGE as GLint);
+                gl::TexParameteri(target, gl::TEXTURE_WRAP_R, gl::CLAMP_TO_EDGE as GLint);
+
+                for face in 0..layers {
+                    gl::TexImage2D(gl::TEXTURE_CUBE_MAP_POSITIVE_X + face,
+                                   0, // Level
+                                   internal_format as GLint,
+                                   width as GLint,
+                                   height as GLint,
+                                   0, // Border
+                                   data_format,
+                                   data_type,
+                                   ptr::null());
+                }
+            } else {
+                gl::TexParameteri(target, gl::TEXTURE_WRAP_S, gl::REPEAT as GLint);
+                gl::TexParameteri(target, gl::TEXTURE_WRAP_T, gl::REPEAT as GLint);
+
+                gl::TexImage3D(target,
+                               0, // Level
+                               internal_format as GLint,
+                               width as GLint,
+                               height as GLint,
+                               layers as GLint,
+                               0, // Border
+                               data_format,
+                               data_type,
+                               ptr::null());
+            }
         }
 
-        TextureGl { texture_name: texture_name }
+        gl_memory_usage().lock().unwrap().record((gl::TEXTURE, texture_name as u64),
+                                                 MemoryCategory::Textures,
+                                                 width as u64 * height as u64 * layers as u64 *
+                                                 gl_texel_bytes(internal_format, data_type));
+
+        TextureGl {
+            texture_name: texture_name,
+            target: target,
+            internal_format: internal_format,
+            width: width,
+            height: height,
+            layers: layers,
+            data_format: data_format,
+            data_type: data_type,
+            source: None,
+        }
     }
 }
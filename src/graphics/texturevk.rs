@@ -30,14 +30,18 @@
 
 use std::any::Any;
 
-use graphics::texture::Texture;
-use graphics::renderer::Renderer;
+use graphics::texture::{Texture, TextureSource, CUBE_FACES};
+use graphics::colourspace::ColourSpace;
+use graphics::renderer::{Renderer, RendererError};
 use graphics::renderervk::*;
+use graphics::image::Image;
+use misc::embeddedresources::*;
 
 use vk::vulkan::*;
 
 pub struct TextureVk {
     pub texture: RendererVkTexture,
+    source: Option<TextureSource>, // The file the texture was loaded from, if it is to be reloaded
 }
 
 impl TextureVk {
@@ -47,7 +51,13 @@ impl TextureVk {
     /// width: The width of the texture
     /// height: The height of the texture
     /// data: The image data, empty if just defining the texture not populating it
-    pub fn new_float_rgba(renderer: &mut Box<Renderer>, width: u32, height: u32, data: &Vec<u8>) -> TextureVk {
+    /// generate_mipmaps: Whether to generate the full mip chain from the data
+    pub fn new_float_rgba(renderer: &mut Box<Renderer>,
+                          width: u32,
+                          height: u32,
+                          data: &Vec<u8>,
+                          generate_mipmaps: bool)
+                          -> TextureVk {
         let renderer_vk = match renderer.as_any_mut().downcast_mut::<RendererVk>() {
             Some(r) => r,
             None => panic!("Unexpected runtime type"),
@@ -58,9 +68,13 @@ impl TextureVk {
                                              height,
                                              VkFormat::VK_FORMAT_R32G32B32A32_SFLOAT,
                                              16, // Four single-precision floats
-                                             data);
+                                             data,
+                                             generate_mipmaps);
 
-        TextureVk { texture: texture }
+        TextureVk {
+            texture: texture,
+            source: None,
+        }
     }
 
     /// Set up a new 3-component byte texture of the specified dimensions and the specified contents
@@ -69,20 +83,197 @@ impl TextureVk {
     /// width: The width of the texture
     /// height: The height of the texture
     /// data: The image data, empty if just defining the texture not populating it
-    pub fn new_ubyte_rgba(renderer: &mut Box<Renderer>, width: u32, height: u32, data: &Vec<u8>) -> TextureVk {
+    /// generate_mipmaps: Whether to generate the full mip chain from the data
+    pub fn new_ubyte_rgba(renderer: &mut Box<Renderer>,
+                          width: u32,
+                          height: u32,
+                          data: &Vec<u8>,
+                          generate_mipmaps: bool)
+                          -> TextureVk {
+        TextureVk::new_ubyte_rgba_with_colour_space(renderer, width, height, data, generate_mipmaps, ColourSpace::Linear)
+    }
+
+    /// Set up a new 4-component byte texture whose data is encoded in the given colour space
+    ///
+    /// sRGB textures have an _SRGB format, so that sampling them decodes the data to linear.
+    ///
+    /// renderer: The renderer object
+    /// width: The width of the texture
+    /// height: The height of the texture
+    /// data: The image data, empty if just defining the texture not populating it
+    /// generate_mipmaps: Whether to generate the full mip chain from the data
+    /// colour_space: The colour space of the data
+    pub fn new_ubyte_rgba_with_colour_space(renderer: &mut Box<Renderer>,
+                                            width: u32,
+                                            height: u32,
+                                            data: &Vec<u8>,
+                                            generate_mipmaps: bool,
+                                            colour_space: ColourSpace)
+                                            -> TextureVk {
         let renderer_vk = match renderer.as_any_mut().downcast_mut::<RendererVk>() {
             Some(r) => r,
             None => panic!("Unexpected runtime type"),
         };
 
-        let texture = RendererVkTexture::new(renderer_vk,
-                                             width,
-                                             height,
-                                             VkFormat::VK_FORMAT_R8G8B8A8_UNORM,
-                                             4,
-                                             data);
+        let format = match colour_space {
+            ColourSpace::Linear => VkFormat::VK_FORMAT_R8G8B8A8_UNORM,
+            ColourSpace::Srgb => VkFormat::VK_FORMAT_R8G8B8A8_SRGB,
+        };
+        let texture = RendererVkTexture::new(renderer_vk, width, height, format, 4, data, generate_mipmaps);
+
+        TextureVk {
+            texture: texture,
+            source: None,
+        }
+    }
+
+    /// Set up a new 4-component byte texture from a PNG file, to be reloaded when the file is modified
+    ///
+    /// Textures loaded from the embedded resources are not reloaded.
+    ///
+    /// renderer: The renderer object
+    /// embedded: The embedded resources object
+    /// filename: The name of the PNG file
+    /// generate_mipmaps: Whether to generate the full mip chain from the image
+    pub fn new_from_png(renderer: &mut Box<Renderer>,
+                        embedded: Option<&EmbeddedResources>,
+                        filename: &str,
+                        generate_mipmaps: bool)
+                        -> TextureVk {
+        TextureVk::new_from_png_with_colour_space(renderer, embedded, filename, generate_mipmaps, ColourSpace::Linear)
+    }
+
+    /// Set up a new 4-component byte texture from a PNG file in the given colour space, e.g. sRGB
+    /// for an albedo map
+    ///
+    /// renderer: The renderer object
+    /// embedded: The embedded resources object
+    /// filename: The name of the PNG file
+    /// generate_mipmaps: Whether to generate the full mip chain from the image
+    /// colour_space: The colour space of the image
+    pub fn new_from_png_with_colour_space(renderer: &mut Box<Renderer>,
+                                          embedded: Option<&EmbeddedResources>,
+                                          filename: &str,
+                                          generate_mipmaps: bool,
+                                          colour_space: ColourSpace)
+                                          -> TextureVk {
+        let image = Image::load_png(embedded, filename);
+        let mut texture = TextureVk::new_ubyte_rgba_with_colour_space(renderer,
+                                                                      image.get_width(),
+                                                                      image.get_height(),
+                                                                      image.get_data(),
+                                                                      generate_mipmaps,
+                                                                      colour_space);
+        match embedded {
+            Some(ref embedded) if embedded.use_me() => (),
+            _ => texture.source = Some(TextureSource::new(filename, generate_mipmaps)),
+        }
+
+        texture
+    }
+
+    /// Set up a new 4-component byte cubemap with square faces of the specified size
+    ///
+    /// The faces are filled with upload_layer.
+    ///
+    /// renderer: The renderer object
+    /// size: The width and height of each face
+    pub fn new_ubyte_rgba_cube(renderer: &mut Box<Renderer>, size: u32) -> TextureVk {
+        TextureVk::new_layered(renderer, size, size, CUBE_FACES, true, VkFormat::VK_FORMAT_R8G8B8A8_UNORM, 4)
+    }
+
+    /// Set up a new 4-component byte 2D array texture of the specified dimensions
+    ///
+    /// The layers are filled with upload_layer.
+    ///
+    /// renderer: The renderer object
+    /// width: The width of each layer
+    /// height: The height of each layer
+    /// layers: The number of layers
+    pub fn new_ubyte_rgba_array(renderer: &mut Box<Renderer>, width: u32, height: u32, layers: u32) -> TextureVk {
+        TextureVk::new_layered(renderer, width, height, layers, false, VkFormat::VK_FORMAT_R8G8B8A8_UNORM, 4)
+    }
+
+    /// Set up a new 4-component float 2D array texture of the specified dimensions
+    ///
+    /// The layers are filled with upload_layer.
+    ///
+    /// renderer: The renderer object
+    /// width: The width of each layer
+    /// height: The height of each layer
+    /// layers: The number of layers
+    pub fn new_float_rgba_array(renderer: &mut Box<Renderer>, width: u32, height: u32, layers: u32) -> TextureVk {
+        TextureVk::new_layered(renderer,
+                               width,
+                               height,
+                               layers,
+                               false,
+                               VkFormat::VK_FORMAT_R32G32B32A32_SFLOAT,
+                               16) // Four single-precision floats
+    }
+
+    /// Set up a new layered texture of the specified format
+    ///
+    ///
+    fn new_layered(renderer: &mut Box<Renderer>,
+                   width: u32,
+                   height: u32,
+                   layers: u32,
+                   cube: bool,
+                   format: VkFormat,
+                   bytes_per_pixel: u32)
+                   -> TextureVk {
+        let renderer_vk = match renderer.as_any_mut().downcast_mut::<RendererVk>() {
+            Some(r) => r,
+            None => panic!("Unexpected runtime type"),
+        };
+
+        let texture = RendererVkTexture::new_layered(renderer_vk, width, height, layers, cube, format, bytes_per_pixel);
+
+        TextureVk {
+            texture: texture,
+            source: None,
+        }
+    }
+
+    /// Return the size of a pixel of the texture in bytes
+    fn bytes_per_pixel(&self) -> u32 {
+        match self.texture.get_format() {
+            VkFormat::VK_FORMAT_R32G32B32A32_SFLOAT => 16,
+            _ => 4,
+        }
+    }
+
+    /// Start uploading a 4-component byte texture on the transfer queue
+    ///
+    /// Rendering carries on while the copy is in flight; the texture is made with from_upload.
+    /// Returns an error if the data is too small or the upload cannot be started.
+    ///
+    /// renderer: The renderer object
+    /// width: The width of the texture
+    /// height: The height of the texture
+    /// data: The image data
+    pub fn new_ubyte_rgba_async(renderer: &mut Box<Renderer>,
+                                width: u32,
+                                height: u32,
+                                data: &Vec<u8>)
+                                -> Result<RendererVkTextureUpload, RendererError> {
+        let renderer_vk = match renderer.as_any_mut().downcast_mut::<RendererVk>() {
+            Some(r) => r,
+            None => panic!("Unexpected runtime type"),
+        };
+
+        RendererVkTexture::upload_async(renderer_vk, width, height, VkFormat::VK_FORMAT_R8G8B8A8_UNORM, 4, data)
+    }
 
-        TextureVk { texture: texture }
+    /// Create a texture from an upload, waiting for the upload to complete if need be
+    ///
+    /// upload: The upload, as returned by new_ubyte_rgba_async
+    pub fn from_upload(upload: RendererVkTextureUpload) -> TextureVk {
+        TextureVk {
+            texture: upload.into_texture(),
+            source: None,
+        }
     }
 
     /// Bind the texture as the specified active texture number
@@ -104,4 +295,77 @@ impl Texture for TextureVk {
     ///
     /// num: The texture number to bind the texture to
     fn bind(&self, _: i32) {}
+
+    /// Return the number of layers, six for a cubemap and one for a plain texture
+    fn get_layers(&self) -> u32 {
+        self.texture.get_layers()
+    }
+
+    /// Replace the contents of one layer of an array texture, or one face of a cubemap
+    ///
+    /// renderer: The renderer object
+    /// layer: The layer or face to replace
+    /// data: The image data for the whole layer, in the format of the texture
+    fn upload_layer(&mut self, renderer: &mut Box<Renderer>, layer: u32, data: &Vec<u8>) {
+        let renderer_vk = match renderer.as_any().downcast_ref::<RendererVk>() {
+            Some(r) => r,
+            None => panic!("Unexpected runtime type"),
+        };
+
+        self.texture.upload_layer(renderer_vk, layer, self.bytes_per_pixel(), data);
+    }
+
+    /// Replace the contents of a rectangle of a plain texture, leaving the rest of it untouched
+    ///
+    /// renderer: The renderer object
+    /// x: The column of the left edge of the rectangle
+    /// y: The row of the first row of the rectangle
+    /// width: The width of the rectangle
+    /// height: The height of the rectangle
+    /// data: The image data for the rectangle, tightly packed in the format of the texture
+    fn update_region(&mut self, renderer: &mut Box<Renderer>, x: u32, y: u32, width: u32, height: u32, data: &Vec<u8>) {
+        let renderer_vk = match renderer.as_any().downcast_ref::<RendererVk>() {
+            Some(r) => r,
+            None => panic!("Unexpected runtime type"),
+        };
+
+        self.texture.update_region(renderer_vk, x, y, width, height, self.bytes_per_pixel(), data);
+    }
+
+    /// Reload the texture if it was loaded from a file that has since been modified
+    ///
+    /// A new image of the same format is created from the file through a fresh staging copy, the
+    /// descriptor sets sampling the texture are pointed at it once the device is idle, and the old
+    /// image is then destroyed.
+    ///
+    /// renderer: The renderer object
+    fn check_for_reload(&mut self, renderer: &mut Box<Renderer>) -> bool {
+        let generate_mipmaps = match self.source {
+            Some(ref mut source) if source.check_modified() => source.generate_mipmaps,
+            _ => return false,
+        };
+        let filename = self.source.as_ref().unwrap().filename.clone();
+        log_info!("Reloading texture {}", filename);
+
+        let image = Image::load_from_png(&filename);
+        let renderer_vk = match renderer.as_any_mut().downcast_mut::<RendererVk>() {
+            Some(r) => r,
+            None => panic!("Unexpected runtime type"),
+        };
+
+        let reloaded = TextureVk {
+            texture: RendererVkTexture::new(renderer_vk,
+                                            image.get_width(),
+                                            image.get_height(),
+                                            self.texture.get_format(),
+                                            4,
+                                            image.get_data(),
+                                            generate_mipmaps),
+            source: None,
+        };
+        renderer_vk.replace_texture(self, &reloaded);
+        self.texture = reloaded.texture;
+
+        true
+    }
 }
@@ -0,0 +1,231 @@
+// Copyright (c) 2016-2017 Bruce Stenning. All rights reserved.
+//
+// Redistribution and use in source and binary forms, with or without
+// modification, are permitted provided that the following conditions
+// are met:
+//
+// 1. Redistributions of source code must retain the above copyright
+//    notice, this list of conditions and the following disclaimer.
+//
+// 2. Redistributions in binary form must reproduce the above copyright
+//    notice, this list of conditions and the following disclaimer in the
+//    documentation and/or other materials provided with the distribution.
+//
+// 3. Neither the name of the copyright holder nor the names of its
+//    contributors may be used to endorse or promote products derived
+//    from this software without specific prior written permission.
+//
+// THIS SOFTWARE IS PROVIDED BY THE COPYRIGHT HOLDERS AND CONTRIBUTORS
+// AS IS" AND ANY EXPRESS OR IMPLIED WARRANTIES, INCLUDING, BUT NOT
+// LIMITED TO, THE IMPLIED WARRANTIES OF MERCHANTABILITY AND FITNESS
+// FOR A PARTICULAR PURPOSE ARE DISCLAIMED. IN NO EVENT SHALL THE
+// COPYRIGHT HOLDER OR CONTRIBUTORS BE LIABLE FOR ANY DIRECT, INDIRECT,
+// INCIDENTAL, SPECIAL, EXEMPLARY, OR CONSEQUENTIAL DAMAGES (INCLUDING,
+// BUT NOT LIMITED TO, PROCUREMENT OF SUBSTITUTE GOODS OR SERVICES; LOSS
+// OF USE, DATA, OR PROFITS; OR BUSINESS INTERRUPTION) HOWEVER CAUSED
+// AND ON ANY THEORY OF LIABILITY, WHETHER IN CONTRACT, STRICT LIABILITY,
+// OR TORT (INCLUDING NEGLIGENCE OR OTHERWISE) ARISING IN ANY WAY OUT OF
+// THE USE OF THIS SOFTWARE, EVEN IF ADVISED OF THE POSSIBILITY OF SUCH
+// DAMAGE.
+
+use std::io;
+
+use graphics::exposure::register_frame_exposure;
+use graphics::frametime::FRAME_BLOCK;
+use graphics::fullscreen::draw_fullscreen_quad;
+use graphics::renderer::*;
+use graphics::resources::*;
+use graphics::shader::*;
+use misc::fileutils::*;
+
+/// The name the tone-mapping shader is registered under
+pub const TONEMAP_SHADER_NAME: &'static str = "tonemap";
+
+/// The name of the uniform block holding the tone-mapping settings
+pub const TONEMAP_SETTINGS_BLOCK: &'static str = "tonemap_settings";
+
+/// The name of the texture the HDR scene is sampled from by the tone-mapping pass
+pub const TONEMAP_SOURCE_TEXTURE: &'static str = "tonemap_source_texture";
+
+const TONEMAP_VERTEX_FILENAME: &'static str = "tonemap.vert";
+const TONEMAP_FRAGMENT_FILENAME: &'static str = "tonemap.frag";
+
+const TONEMAP_VERTEX_SOURCE: &'static str = include_str!("shaders/tonemap.vert");
+const TONEMAP_FRAGMENT_SOURCE: &'static str = include_str!("shaders/tonemap.frag");
+
+/// The curve mapping exposed scene luminance into the range of the display
+///
+/// The functions here are the reference for tonemap.frag, which must be kept in step.
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub enum TonemapOperator {
+    /// Reinhard's operator, with the luminance that maps to white
+    Reinhard,
+    /// Narkowicz's fit of the ACES filmic curve, with more contrast and a softer shoulder
+    Aces,
+}
+
+impl TonemapOperator {
+    /// Return the value the shader selects the operator by
+    pub fn shader_index(&self) -> i32 {
+        match *self {
+            TonemapOperator::Reinhard => 0,
+            TonemapOperator::Aces => 1,
+        }
+    }
+
+    /// Map an exposed colour component into the range 0 to 1
+    ///
+    /// c: The exposed linear component
+    /// white: The component that maps to 1 under Reinhard's operator
+    pub fn apply(&self, c: f32, white: f32) -> f32 {
+        let c = c.max(0.0);
+        let mapped = match *self {
+            TonemapOperator::Reinhard => c * (1.0 + c / (white * white)) / (1.0 + c),
+            TonemapOperator::Aces => (c * (2.51 * c + 0.03)) / (c * (2.43 * c + 0.59) + 0.14),
+        };
+        mapped.max(0.0).min(1.0)
+    }
+}
+
+/// Settings for the tone-mapping pass
+#[derive(Clone, Copy, Debug)]
+pub struct TonemapSettings {
+    pub operator: TonemapOperator,
+    pub white: f32, // The exposed luminance mapped to white by Reinhard's operator
+    pub paper_white_nits: f32, // The luminance of diffuse white on HDR10 output
+    pub peak_nits: f32, // The brightest luminance of the display on HDR10 output
+}
+
+impl Default for TonemapSettings {
+    fn default() -> TonemapSettings {
+        TonemapSettings {
+            operator: TonemapOperator::Aces,
+            white: 4.0f32,
+            paper_white_nits: 200.0f32,
+            peak_nits: 1000.0f32,
+        }
+    }
+}
+
+impl TonemapSettings {
+    /// Return the luminance in nits an exposed colour component is displayed at on HDR10 output
+    ///
+    /// The operator compresses into the headroom up to the peak, so that the scene is scaled to
+    /// keep mid-tones near paper white while the highlights roll off before the peak.
+    ///
+    /// c: The exposed linear component
+    pub fn hdr10_nits(&self, c: f32) -> f32 {
+        let headroom = self.peak_nits / self.paper_white_nits;
+        self.operator.apply(c / headroom, self.white / headroom) * self.peak_nits
+    }
+}
+
+/// Write the sources of the tone-mapping shader to the working directory
+///
+/// The shader is built from these files when the renderer is set up, so this must be called before
+/// then.  register_tonemap_shader adds the exposure member itself, so no metering sources are needed.
+pub fn install_tonemap_shader_sources() -> Result<(), io::Error> {
+    write_entire_file(TONEMAP_VERTEX_SOURCE, TONEMAP_VERTEX_FILENAME)?;
+    write_entire_file(TONEMAP_FRAGMENT_SOURCE, TONEMAP_FRAGMENT_FILENAME)?;
+
+    Ok(())
+}
+
+/// Add the uniform blocks and shader specification of the tone-mapping shader
+///
+/// The shader draws F2F2 full-screen triangles, sampling the HDR scene and scaling it by the
+/// frame_exposure of the FRAME_BLOCK uniform block, which AutoExposure keeps up to date from
+/// its luminance metering.  The block is registered here if it has not been already.
+///
+/// resource_manager: The resource manager to add the specifications to
+/// pass_name: The pass the tone-mapping shader is used in, which draws to the swapchain
+pub fn register_tonemap_shader(resource_manager: &mut ResourceManager, pass_name: &'static str) {
+    register_frame_exposure(resource_manager);
+
+    resource_manager.uniform_block_specs.insert(TONEMAP_SETTINGS_BLOCK,
+                                                UniformBlockSpec {
+                                                    set: 0,
+                                                    block_type: UniformType::UniformBuffer,
+                                                    uniforms: ["tonemap_operator",
+                                                               "tonemap_hdr10",
+                                                               "tonemap_white",
+                                                               "tonemap_paper_white_nits",
+                                                               "tonemap_peak_nits"]
+                                                        .iter()
+                                                        .map(|name| {
+                                                            BlockUniformSpec {
+                                                                name: *name,
+                                                                ..Default::default()
+                                                            }
+                                                        })
+                                                        .collect(),
+                                                    ..Default::default()
+                                                });
+
+    let tonemap_spec = ShaderSpec {
+        name: TONEMAP_SHADER_NAME,
+        library_files: vec![],
+        shader_files: vec![ShaderFilesSpecification {
+                               filename: TONEMAP_VERTEX_FILENAME,
+                               shader_stage: ShaderStage::VertexShader,
+                               spirv_out: "tonemap.vert.spv",
+                               reflect_out: "tonemap.vert.rfl",
+                               entry_point: DEFAULT_ENTRY_POINT,
+                           },
+                           ShaderFilesSpecification {
+                               filename: TONEMAP_FRAGMENT_FILENAME,
+                               shader_stage: ShaderStage::FragmentShader,
+                               spirv_out: "tonemap.frag.spv",
+                               reflect_out: "tonemap.frag.rfl",
+                               entry_point: DEFAULT_ENTRY_POINT,
+                           }],
+        fallback_shader_files: vec![],
+        uniform_block_names: vec![FRAME_BLOCK, TONEMAP_SETTINGS_BLOCK],
+        uniform_specs: vec![UniformSpec {
+                                name: TONEMAP_SOURCE_TEXTURE,
+                                set: 1,
+                                binding: 0,
+                                uniform_type: UniformType::CombinedImageSampler,
+                            }],
+        push_constants: vec![],
+        vertex_array_type: VertexArrayType::F2F2,
+        primitive: PrimitiveType::PrimitiveTriangles,
+        instance_layout: None,
+        attributes: vec!["position", "texcoord"],
+        fragment_out: "tonemap_colour",
+        extra_fragment_outs: vec![],
+        render_state: RenderState::default(),
+        stencil: None,
+        bindless_textures: false,
+        pass_name: pass_name,
+    };
+    resource_manager.shader_specs.insert(TONEMAP_SHADER_NAME, tonemap_spec);
+}
+
+/// Tone-map the HDR scene over the whole of the current render target
+///
+/// The HDR scene must be available as the texture named by TONEMAP_SOURCE_TEXTURE.  On an sRGB
+/// target the output is linear and encoded when written, while on HDR10 output the shader
+/// converts to Rec. 2020 and encodes with the ST 2084 transfer function itself.
+///
+/// renderer: The renderer to draw with
+/// settings: The operator and display luminances to map with
+pub fn render_tonemap<Rend: Renderer + ?Sized>(renderer: &mut Rend, settings: &TonemapSettings) {
+    let hdr10 = if renderer.is_hdr_output() { 1 } else { 0 };
+    renderer.set_uniform_buffer_int(TONEMAP_SETTINGS_BLOCK, "tonemap_operator", settings.operator.shader_index());
+    renderer.set_uniform_buffer_int(TONEMAP_SETTINGS_BLOCK, "tonemap_hdr10", hdr10);
+    renderer.set_uniform_buffer_float(TONEMAP_SETTINGS_BLOCK, "tonemap_white", settings.white.max(1.0e-3));
+    renderer.set_uniform_buffer_float(TONEMAP_SETTINGS_BLOCK,
+                                      "tonemap_paper_white_nits",
+                                      settings.paper_white_nits.max(1.0));
+    renderer.set_uniform_buffer_float(TONEMAP_SETTINGS_BLOCK,
+                                      "tonemap_peak_nits",
+                                      settings.peak_nits.max(settings.paper_white_nits));
+    renderer.synchronise_uniform_buffer(TONEMAP_SETTINGS_BLOCK);
+
+    renderer.begin_pass(TONEMAP_SHADER_NAME);
+
+    draw_fullscreen_quad(renderer);
+
+    renderer.end_pass();
+}
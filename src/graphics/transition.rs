@@ -0,0 +1,328 @@
+// Copyright (c) 2016-2017 Bruce Stenning. All rights reserved.
+//
+// Redistribution and use in source and binary forms, with or without
+// modification, are permitted provided that the following conditions
+// are met:
+//
+// 1. Redistributions of source code must retain the above copyright
+//    notice, this list of conditions and the following disclaimer.
+//
+// 2. Redistributions in binary form must reproduce the above copyright
+//    notice, this list of conditions and the following disclaimer in the
+//    documentation and/or other materials provided with the distribution.
+//
+// 3. Neither the name of the copyright holder nor the names of its
+//    contributors may be used to endorse or promote products derived
+//    from this software without specific prior written permission.
+//
+// THIS SOFTWARE IS PROVIDED BY THE COPYRIGHT HOLDERS AND CONTRIBUTORS
+// AS IS" AND ANY EXPRESS OR IMPLIED WARRANTIES, INCLUDING, BUT NOT
+// LIMITED TO, THE IMPLIED WARRANTIES OF MERCHANTABILITY AND FITNESS
+// FOR A PARTICULAR PURPOSE ARE DISCLAIMED. IN NO EVENT SHALL THE
+// COPYRIGHT HOLDER OR CONTRIBUTORS BE LIABLE FOR ANY DIRECT, INDIRECT,
+// INCIDENTAL, SPECIAL, EXEMPLARY, OR CONSEQUENTIAL DAMAGES (INCLUDING,
+// BUT NOT LIMITED TO, PROCUREMENT OF SUBSTITUTE GOODS OR SERVICES; LOSS
+// OF USE, DATA, OR PROFITS; OR BUSINESS INTERRUPTION) HOWEVER CAUSED
+// AND ON ANY THEORY OF LIABILITY, WHETHER IN CONTRACT, STRICT LIABILITY,
+// OR TORT (INCLUDING NEGLIGENCE OR OTHERWISE) ARISING IN ANY WAY OUT OF
+// THE USE OF THIS SOFTWARE, EVEN IF ADVISED OF THE POSSIBILITY OF SUCH
+// DAMAGE.
+
+use std::io;
+
+use algebra::vector::Vec2;
+use graphics::fullscreen::draw_fullscreen_quad;
+use graphics::renderer::*;
+use graphics::resources::*;
+use graphics::shader::*;
+use graphics::texture::*;
+use misc::fileutils::*;
+
+/// The name the transition shader is registered under
+pub const TRANSITION_SHADER_NAME: &'static str = "transition";
+
+/// The name of the uniform block holding the transition settings
+pub const TRANSITION_SETTINGS_BLOCK: &'static str = "transition_settings";
+
+/// The name of the texture the captured frame of the outgoing scene is sampled from
+pub const TRANSITION_OUTGOING_TEXTURE: &'static str = "transition_outgoing_texture";
+
+/// The name of the texture the incoming scene is sampled from
+pub const TRANSITION_INCOMING_TEXTURE: &'static str = "transition_incoming_texture";
+
+const TRANSITION_VERTEX_FILENAME: &'static str = "transition.vert";
+const TRANSITION_FRAGMENT_FILENAME: &'static str = "transition.frag";
+
+const TRANSITION_VERTEX_SOURCE: &'static str = include_str!("shaders/transition.vert");
+const TRANSITION_FRAGMENT_SOURCE: &'static str = include_str!("shaders/transition.frag");
+
+/// The curve mapping the elapsed fraction of a transition to how far it has progressed
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub enum Easing {
+    Linear,
+    EaseIn,
+    EaseOut,
+    EaseInOut,
+}
+
+impl Easing {
+    /// Return the eased progress for the elapsed fraction of a transition
+    ///
+    /// t: The elapsed fraction, clamped to [0, 1]
+    pub fn apply(&self, t: f32) -> f32 {
+        let t = t.max(0.0f32).min(1.0f32);
+        match *self {
+            Easing::Linear => t,
+            Easing::EaseIn => t * t,
+            Easing::EaseOut => t * (2.0f32 - t),
+            Easing::EaseInOut => t * t * (3.0f32 - 2.0f32 * t),
+        }
+    }
+}
+
+/// The edge a wipe starts from, sweeping the incoming scene across to the opposite edge
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub enum WipeDirection {
+    LeftToRight,
+    RightToLeft,
+    BottomToTop,
+    TopToBottom,
+}
+
+/// How the incoming scene replaces the outgoing one
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub enum TransitionStyle {
+    CrossFade,
+    Wipe(WipeDirection),
+}
+
+impl TransitionStyle {
+    /// Return the style number and wipe direction given to the transition shader
+    fn shader_parameters(&self) -> (i32, Vec2<f32>) {
+        match *self {
+            TransitionStyle::CrossFade => (0, Vec2 { x: 0.0f32, y: 0.0f32 }),
+            TransitionStyle::Wipe(WipeDirection::LeftToRight) => (1, Vec2 { x: 1.0f32, y: 0.0f32 }),
+            TransitionStyle::Wipe(WipeDirection::RightToLeft) => (1, Vec2 { x: -1.0f32, y: 0.0f32 }),
+            TransitionStyle::Wipe(WipeDirection::BottomToTop) => (1, Vec2 { x: 0.0f32, y: 1.0f32 }),
+            TransitionStyle::Wipe(WipeDirection::TopToBottom) => (1, Vec2 { x: 0.0f32, y: -1.0f32 }),
+        }
+    }
+}
+
+/// A transition in progress from a captured frame of the outgoing scene to the incoming scene
+///
+/// While the transition is active the application draws the transition pass, with
+/// render_transition, as its final pass instead of its usual one.  The incoming scene is
+/// rendered as normal into the render target whose texture is bound as
+/// TRANSITION_INCOMING_TEXTURE.
+pub struct Transition {
+    style: TransitionStyle,
+    easing: Easing,
+    duration: f32,
+    elapsed: f32,
+    softness: f32,
+}
+
+impl Transition {
+    /// Create a transition
+    ///
+    /// style: Whether to cross-fade or wipe
+    /// easing: The curve the transition progresses along
+    /// duration: The length of the transition in seconds
+    pub fn new(style: TransitionStyle, easing: Easing, duration: f32) -> Transition {
+        Transition {
+            style: style,
+            easing: easing,
+            duration: duration.max(0.0f32),
+            elapsed: 0.0f32,
+            softness: 0.05f32,
+        }
+    }
+
+    /// Set the width of the blended edge of a wipe, as a fraction of the screen
+    ///
+    /// softness: The width of the edge, zero for a hard edge
+    pub fn set_softness(&mut self, softness: f32) {
+        self.softness = softness.max(0.0f32);
+    }
+
+    /// Advance the transition
+    ///
+    /// delta: The time since the last frame in seconds, normally the engine time delta of the frame clock
+    pub fn advance(&mut self, delta: f32) {
+        self.elapsed = (self.elapsed + delta.max(0.0f32)).min(self.duration);
+    }
+
+    /// Return the eased progress of the transition, from zero showing only the outgoing scene
+    /// to one showing only the incoming scene
+    pub fn progress(&self) -> f32 {
+        if self.duration > 0.0f32 {
+            self.easing.apply(self.elapsed / self.duration)
+        } else {
+            1.0f32
+        }
+    }
+
+    /// Return whether the transition has finished, so the usual final pass can resume
+    pub fn is_finished(&self) -> bool {
+        self.elapsed >= self.duration
+    }
+
+    /// Return the style of the transition
+    pub fn get_style(&self) -> TransitionStyle {
+        self.style
+    }
+}
+
+/// Write the sources of the transition shader to the working directory
+///
+/// The transition shader is built with the others when the renderer is set up, so this must be
+/// called before then; the outgoing frame itself is only captured once a transition starts.
+pub fn install_transition_shader_sources() -> Result<(), io::Error> {
+    write_entire_file(TRANSITION_VERTEX_SOURCE, TRANSITION_VERTEX_FILENAME)?;
+    write_entire_file(TRANSITION_FRAGMENT_SOURCE, TRANSITION_FRAGMENT_FILENAME)?;
+
+    Ok(())
+}
+
+/// Add the uniform block and shader specification of the transition shader
+///
+/// The transition shader draws F2F2 full-screen triangles, sampling the captured outgoing frame
+/// and the incoming scene.  It belongs to the final pass, which it temporarily stands in for.
+///
+/// resource_manager: The resource manager to add the specifications to
+/// pass_name: The pass the transition shader is used in, normally SWAPCHAIN_PASS for the final pass
+pub fn register_transition_shader(resource_manager: &mut ResourceManager, pass_name: &'static str) {
+    resource_manager.uniform_block_specs.insert(TRANSITION_SETTINGS_BLOCK,
+                                                UniformBlockSpec {
+                                                    set: 0,
+                                                    block_type: UniformType::UniformBuffer,
+                                                    uniforms: ["transition_viewport",
+                                                               "transition_direction",
+                                                               "transition_progress",
+                                                               "transition_softness",
+                                                               "transition_style"]
+                                                        .iter()
+                                                        .map(|name| {
+                                                            BlockUniformSpec {
+                                                                name: *name,
+                                                                ..Default::default()
+                                                            }
+                                                        })
+                                                        .collect(),
+                                                    ..Default::default()
+                                                });
+
+    let transition_spec = ShaderSpec {
+        name: TRANSITION_SHADER_NAME,
+        library_files: vec![],
+        shader_files: vec![ShaderFilesSpecification {
+                               filename: TRANSITION_VERTEX_FILENAME,
+                               shader_stage: ShaderStage::VertexShader,
+                               spirv_out: "transition.vert.spv",
+                               reflect_out: "transition.vert.rfl",
+                               entry_point: DEFAULT_ENTRY_POINT,
+                           },
+                           ShaderFilesSpecification {
+                               filename: TRANSITION_FRAGMENT_FILENAME,
+                               shader_stage: ShaderStage::FragmentShader,
+                               spirv_out: "transition.frag.spv",
+                               reflect_out: "transition.frag.rfl",
+                               entry_point: DEFAULT_ENTRY_POINT,
+                           }],
+        fallback_shader_files: vec![],
+        uniform_block_names: vec![TRANSITION_SETTINGS_BLOCK],
+        uniform_specs: vec![UniformSpec {
+                                name: TRANSITION_OUTGOING_TEXTURE,
+                                set: 1,
+                                binding: 0,
+                                uniform_type: UniformType::CombinedImageSampler,
+                            },
+                            UniformSpec {
+                                name: TRANSITION_INCOMING_TEXTURE,
+                                set: 1,
+                                binding: 1,
+                                uniform_type: UniformType::CombinedImageSampler,
+                            }],
+        push_constants: vec![],
+        vertex_array_type: VertexArrayType::F2F2,
+        primitive: PrimitiveType::PrimitiveTriangles,
+        instance_layout: None,
+        attributes: vec!["position", "texcoord"],
+        fragment_out: "transition_colour",
+        extra_fragment_outs: vec![],
+        render_state: RenderState::default(),
+        stencil: None,
+        bindless_textures: false,
+        pass_name: pass_name,
+    };
+    resource_manager.shader_specs.insert(TRANSITION_SHADER_NAME, transition_spec);
+}
+
+/// Capture the frame just rendered into the texture bound as TRANSITION_OUTGOING_TEXTURE
+///
+/// Call this between end_frame and flip on the last frame of the outgoing scene.  The texture
+/// must be a single layer RGBA texture of the frame size, e.g. from new_ubyte_rgba_array, and
+/// the capture is reordered to the row order the backend samples textures in.
+///
+/// Returns false if the frame could not be captured, in which case the transition should cut.
+///
+/// renderer: The renderer that drew the frame
+/// texture: The texture to capture into
+pub fn capture_outgoing_frame(renderer: &mut Box<Renderer>, texture: &mut Box<Texture>) -> bool {
+    let capture = match renderer.capture_frame() {
+        Some(capture) => capture,
+        None => return false,
+    };
+
+    // Captures are top row first, but OpenGL textures start from the bottom row
+    let data = if renderer.renderer_type() == RendererType::RendererGl {
+        let row_bytes = (capture.width * 4) as usize;
+        let mut flipped = Vec::with_capacity(capture.data.len());
+        for row in capture.data.chunks(row_bytes).rev() {
+            flipped.extend_from_slice(row);
+        }
+        flipped
+    } else {
+        capture.data
+    };
+
+    texture.upload_layer(renderer, 0, &data);
+
+    true
+}
+
+/// Draw the transition over the whole of the current render target
+///
+/// The captured outgoing frame and the incoming scene must be available as the textures named by
+/// TRANSITION_OUTGOING_TEXTURE and TRANSITION_INCOMING_TEXTURE.
+///
+/// renderer: The renderer to draw with
+/// transition: The transition in progress
+/// width: The width of the render target in pixels
+/// height: The height of the render target in pixels
+pub fn render_transition<Rend: Renderer + ?Sized>(renderer: &mut Rend, transition: &Transition, width: u32, height: u32) {
+    let (style, mut direction) = transition.get_style().shader_parameters();
+
+    // Fragment coordinates start from the top row on Vulkan but the bottom row on OpenGL
+    if renderer.renderer_type() == RendererType::RendererVk {
+        direction.y = -direction.y;
+    }
+
+    renderer.set_uniform_buffer_float_vector(TRANSITION_SETTINGS_BLOCK,
+                                             "transition_viewport",
+                                             &vec![width as f32, height as f32, 0.0f32, 0.0f32]);
+    renderer.set_uniform_buffer_float_vector(TRANSITION_SETTINGS_BLOCK,
+                                             "transition_direction",
+                                             &vec![direction.x, direction.y, 0.0f32, 0.0f32]);
+    renderer.set_uniform_buffer_float(TRANSITION_SETTINGS_BLOCK, "transition_progress", transition.progress());
+    renderer.set_uniform_buffer_float(TRANSITION_SETTINGS_BLOCK, "transition_softness", transition.softness);
+    renderer.set_uniform_buffer_int(TRANSITION_SETTINGS_BLOCK, "transition_style", style);
+    renderer.synchronise_uniform_buffer(TRANSITION_SETTINGS_BLOCK);
+
+    renderer.begin_pass(TRANSITION_SHADER_NAME);
+
+    draw_fullscreen_quad(renderer);
+
+    renderer.end_pass();
+}
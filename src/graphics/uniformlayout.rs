@@ -0,0 +1,149 @@
+// Copyright (c) 2016-2017 Bruce Stenning. All rights reserved.
+//
+// Redistribution and use in source and binary forms, with or without
+// modification, are permitted provided that the following conditions
+// are met:
+//
+// 1. Redistributions of source code must retain the above copyright
+//    notice, this list of conditions and the following disclaimer.
+//
+// 2. Redistributions in binary form must reproduce the above copyright
+//    notice, this list of conditions and the following disclaimer in the
+//    documentation and/or other materials provided with the distribution.
+//
+// 3. Neither the name of the copyright holder nor the names of its
+//    contributors may be used to endorse or promote products derived
+//    from this software without specific prior written permission.
+//
+// THIS SOFTWARE IS PROVIDED BY THE COPYRIGHT HOLDERS AND CONTRIBUTORS
+// AS IS" AND ANY EXPRESS OR IMPLIED WARRANTIES, INCLUDING, BUT NOT
+// LIMITED TO, THE IMPLIED WARRANTIES OF MERCHANTABILITY AND FITNESS
+// FOR A PARTICULAR PURPOSE ARE DISCLAIMED. IN NO EVENT SHALL THE
+// COPYRIGHT HOLDER OR CONTRIBUTORS BE LIABLE FOR ANY DIRECT, INDIRECT,
+// INCIDENTAL, SPECIAL, EXEMPLARY, OR CONSEQUENTIAL DAMAGES (INCLUDING,
+// BUT NOT LIMITED TO, PROCUREMENT OF SUBSTITUTE GOODS OR SERVICES; LOSS
+// OF USE, DATA, OR PROFITS; OR BUSINESS INTERRUPTION) HOWEVER CAUSED
+// AND ON ANY THEORY OF LIABILITY, WHETHER IN CONTRACT, STRICT LIABILITY,
+// OR TORT (INCLUDING NEGLIGENCE OR OTHERWISE) ARISING IN ANY WAY OUT OF
+// THE USE OF THIS SOFTWARE, EVEN IF ADVISED OF THE POSSIBILITY OF SUCH
+// DAMAGE.
+
+/// The rules by which the members of a block are laid out in memory
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum BlockLayout {
+    Std140, // Uniform blocks: arrays and matrix columns are padded to 16 bytes
+    Std430, // Storage blocks and push constants: arrays and matrix columns are only aligned to their elements
+}
+
+/// The GLSL type of a member of a uniform or storage block
+///
+/// Matrices are column major, with as many rows as columns.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum BlockMemberType {
+    Float,
+    Int,
+    UInt,
+    Bool,
+    Vec2,
+    Vec3,
+    Vec4,
+    IVec2,
+    IVec3,
+    IVec4,
+    UVec2,
+    UVec3,
+    UVec4,
+    Mat2,
+    Mat3,
+    Mat4,
+}
+
+impl BlockMemberType {
+    /// Return the number of columns, and the number of components in each column
+    fn shape(self) -> (usize, usize) {
+        match self {
+            BlockMemberType::Float | BlockMemberType::Int | BlockMemberType::UInt | BlockMemberType::Bool => (1, 1),
+            BlockMemberType::Vec2 | BlockMemberType::IVec2 | BlockMemberType::UVec2 => (1, 2),
+            BlockMemberType::Vec3 | BlockMemberType::IVec3 | BlockMemberType::UVec3 => (1, 3),
+            BlockMemberType::Vec4 | BlockMemberType::IVec4 | BlockMemberType::UVec4 => (1, 4),
+            BlockMemberType::Mat2 => (2, 2),
+            BlockMemberType::Mat3 => (3, 3),
+            BlockMemberType::Mat4 => (4, 4),
+        }
+    }
+
+    /// Return the base alignment and the size of the type, outside any array
+    ///
+    /// layout: The layout rules of the block
+    fn alignment_and_size(self, layout: BlockLayout) -> (usize, usize) {
+        // Every component is 4 bytes, and three component vectors are aligned as four
+        let (columns, rows) = self.shape();
+        let vector_size = 4 * rows;
+        let vector_alignment = if rows == 3 { 16 } else { vector_size };
+        if columns == 1 {
+            return (vector_alignment, vector_size);
+        }
+
+        // A matrix is laid out as an array of its column vectors
+        let column_stride = match layout {
+            BlockLayout::Std140 => round_up(vector_alignment, 16),
+            BlockLayout::Std430 => vector_alignment,
+        };
+        (column_stride, columns * column_stride)
+    }
+}
+
+/// Where a member of a block is, and how far apart the elements of an array member are
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub struct MemberLayout {
+    pub offset: usize,
+    pub stride: usize, // The array stride, or zero if the member is not an array
+    pub size: usize,
+}
+
+/// The layout of every member of a block, and the size of the block
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct CalculatedBlockLayout {
+    pub members: Vec<MemberLayout>, // In the order the members are declared
+    pub size: usize, // The end of the last member, as reflected from SPIR-V
+}
+
+/// Round a size up to a multiple of an alignment
+fn round_up(size: usize, alignment: usize) -> usize {
+    (size + alignment - 1) / alignment * alignment
+}
+
+/// Calculate the offsets, array strides and size of a block from the types of its members
+///
+/// layout: The layout rules of the block
+/// members: The type of each member in declaration order, with its array length, or zero if
+///          it is not an array
+pub fn calculate_block_layout(layout: BlockLayout, members: &[(BlockMemberType, usize)]) -> CalculatedBlockLayout {
+    let mut end = 0;
+    let member_layouts = members.iter()
+        .map(|&(member_type, array_length)| {
+            let (mut alignment, mut size) = member_type.alignment_and_size(layout);
+            let mut stride = 0;
+            if array_length > 0 {
+                if layout == BlockLayout::Std140 {
+                    alignment = round_up(alignment, 16);
+                }
+                stride = round_up(size, alignment);
+                size = array_length * stride;
+            }
+
+            let offset = round_up(end, alignment);
+            end = offset + size;
+            MemberLayout {
+                offset: offset,
+                stride: stride,
+                size: size,
+            }
+        })
+        .collect();
+
+    CalculatedBlockLayout {
+        members: member_layouts,
+        size: end,
+    }
+}
@@ -0,0 +1,190 @@
+// Copyright (c) 2016-2017 Bruce Stenning. All rights reserved.
+//
+// Redistribution and use in source and binary forms, with or without
+// modification, are permitted provided that the following conditions
+// are met:
+//
+// 1. Redistributions of source code must retain the above copyright
+//    notice, this list of conditions and the following disclaimer.
+//
+// 2. Redistributions in binary form must reproduce the above copyright
+//    notice, this list of conditions and the following disclaimer in the
+//    documentation and/or other materials provided with the distribution.
+//
+// 3. Neither the name of the copyright holder nor the names of its
+//    contributors may be used to endorse or promote products derived
+//    from this software without specific prior written permission.
+//
+// THIS SOFTWARE IS PROVIDED BY THE COPYRIGHT HOLDERS AND CONTRIBUTORS
+// AS IS" AND ANY EXPRESS OR IMPLIED WARRANTIES, INCLUDING, BUT NOT
+// LIMITED TO, THE IMPLIED WARRANTIES OF MERCHANTABILITY AND FITNESS
+// FOR A PARTICULAR PURPOSE ARE DISCLAIMED. IN NO EVENT SHALL THE
+// COPYRIGHT HOLDER OR CONTRIBUTORS BE LIABLE FOR ANY DIRECT, INDIRECT,
+// INCIDENTAL, SPECIAL, EXEMPLARY, OR CONSEQUENTIAL DAMAGES (INCLUDING,
+// BUT NOT LIMITED TO, PROCUREMENT OF SUBSTITUTE GOODS OR SERVICES; LOSS
+// OF USE, DATA, OR PROFITS; OR BUSINESS INTERRUPTION) HOWEVER CAUSED
+// AND ON ANY THEORY OF LIABILITY, WHETHER IN CONTRACT, STRICT LIABILITY,
+// OR TORT (INCLUDING NEGLIGENCE OR OTHERWISE) ARISING IN ANY WAY OUT OF
+// THE USE OF THIS SOFTWARE, EVEN IF ADVISED OF THE POSSIBILITY OF SUCH
+// DAMAGE.
+use std::sync::atomic::{AtomicUsize, Ordering};
+
+/// A count of the writes to the contents of a buffer, and of the writes already synchronised
+///
+/// The contents of uniform buffers are written through shared references, so the counts are
+/// atomic.  A buffer whose contents have not been written since they were last synchronised need
+/// not be uploaded again.
+pub struct ContentVersion {
+    version: AtomicUsize,
+    synchronised: AtomicUsize,
+}
+
+impl ContentVersion {
+    /// Construct a version for contents that have not yet been synchronised
+    pub fn new() -> ContentVersion {
+        ContentVersion {
+            version: AtomicUsize::new(1),
+            synchronised: AtomicUsize::new(0),
+        }
+    }
+
+    /// Record a write to the contents
+    pub fn bump(&self) {
+        self.version.fetch_add(1, Ordering::Relaxed);
+    }
+
+    /// Return the number of writes to the contents, which changes whenever they are written
+    pub fn version(&self) -> usize {
+        self.version.load(Ordering::Relaxed)
+    }
+
+    /// Mark the contents as synchronised, returning whether they had been written since last time
+    pub fn synchronise(&self) -> bool {
+        let version = self.version();
+        self.synchronised.swap(version, Ordering::Relaxed) != version
+    }
+}
+
+/// A count of the binds and uploads skipped because the bound state already matched
+pub struct SkippedBinds {
+    current: AtomicUsize,
+    last_frame: AtomicUsize,
+}
+
+impl SkippedBinds {
+    /// Construct a count with nothing skipped
+    pub fn new() -> SkippedBinds {
+        SkippedBinds {
+            current: AtomicUsize::new(0),
+            last_frame: AtomicUsize::new(0),
+        }
+    }
+
+    /// Count a skipped bind or upload in the current frame
+    pub fn skip(&self) {
+        self.current.fetch_add(1, Ordering::Relaxed);
+    }
+
+    /// Finish counting for the current frame
+    pub fn end_frame(&self) {
+        self.last_frame.store(self.current.swap(0, Ordering::Relaxed), Ordering::Relaxed);
+    }
+
+    /// Return the number skipped in the last complete frame
+    pub fn last_frame(&self) -> u64 {
+        self.last_frame.load(Ordering::Relaxed) as u64
+    }
+}
+
+/// The pipeline or program, descriptor sets and vertex buffers bound in a command buffer or
+/// context, so that binding the same object again can be skipped
+///
+/// Handles are compared as integers, so that Vulkan handles and OpenGL names are tracked alike.
+/// Each bind returns whether the object must be bound, and records it as bound if so.
+#[derive(Clone, Debug, Default)]
+pub struct BoundState {
+    pipeline: Option<u64>,
+    vertex_array: Option<u64>, // OpenGL only
+    layout: Option<u64>, // The pipeline layout the descriptor sets were bound with
+    descriptor_sets: Vec<Option<u64>>, // By set number
+    vertex_buffers: Vec<Option<(u64, u64)>>, // By binding, with the offset into the buffer
+}
+
+impl BoundState {
+    /// Construct the state of a command buffer with nothing bound
+    pub fn new() -> BoundState {
+        BoundState::default()
+    }
+
+    /// Forget everything bound, as when a command buffer is begun
+    pub fn reset(&mut self) {
+        *self = BoundState::default();
+    }
+
+    /// Record the binding of a pipeline or program, returning whether it must be bound
+    ///
+    /// pipeline: The pipeline or program
+    pub fn bind_pipeline(&mut self, pipeline: u64) -> bool {
+        if self.pipeline == Some(pipeline) {
+            return false;
+        }
+        self.pipeline = Some(pipeline);
+        true
+    }
+
+    /// Record the binding of a vertex array object, returning whether it must be bound
+    ///
+    /// vertex_array: The vertex array object
+    pub fn bind_vertex_array(&mut self, vertex_array: u64) -> bool {
+        if self.vertex_array == Some(vertex_array) {
+            return false;
+        }
+        self.vertex_array = Some(vertex_array);
+        true
+    }
+
+    /// Record the binding of a descriptor set, returning whether it must be bound
+    ///
+    /// A different pipeline layout may disturb the sets bound with the last one, so they are
+    /// all forgotten.
+    ///
+    /// layout: The pipeline layout the set is bound with
+    /// set: The set number
+    /// descriptor_set: The descriptor set
+    pub fn bind_descriptor_set(&mut self, layout: u64, set: u32, descriptor_set: u64) -> bool {
+        if self.layout != Some(layout) {
+            self.layout = Some(layout);
+            self.descriptor_sets.clear();
+        }
+
+        let set = set as usize;
+        if self.descriptor_sets.len() <= set {
+            self.descriptor_sets.resize(set + 1, None);
+        }
+        if self.descriptor_sets[set] == Some(descriptor_set) {
+            return false;
+        }
+        self.descriptor_sets[set] = Some(descriptor_set);
+        true
+    }
+
+    /// Record the binding of consecutive vertex buffers, returning whether any must be bound
+    ///
+    /// first_binding: The binding of the first buffer
+    /// buffers: Each buffer with the offset into it
+    pub fn bind_vertex_buffers(&mut self, first_binding: u32, buffers: &[(u64, u64)]) -> bool {
+        let first = first_binding as usize;
+        if self.vertex_buffers.len() < first + buffers.len() {
+            self.vertex_buffers.resize(first + buffers.len(), None);
+        }
+
+        let mut changed = false;
+        for (i, buffer) in buffers.iter().enumerate() {
+            if self.vertex_buffers[first + i] != Some(*buffer) {
+                self.vertex_buffers[first + i] = Some(*buffer);
+                changed = true;
+            }
+        }
+        changed
+    }
+}
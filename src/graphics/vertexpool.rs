@@ -0,0 +1,150 @@
+// Copyright (c) 2016-2017 Bruce Stenning. All rights reserved.
+//
+// Redistribution and use in source and binary forms, with or without
+// modification, are permitted provided that the following conditions
+// are met:
+//
+// 1. Redistributions of source code must retain the above copyright
+//    notice, this list of conditions and the following disclaimer.
+//
+// 2. Redistributions in binary form must reproduce the above copyright
+//    notice, this list of conditions and the following disclaimer in the
+//    documentation and/or other materials provided with the distribution.
+//
+// 3. Neither the name of the copyright holder nor the names of its
+//    contributors may be used to endorse or promote products derived
+//    from this software without specific prior written permission.
+//
+// THIS SOFTWARE IS PROVIDED BY THE COPYRIGHT HOLDERS AND CONTRIBUTORS
+// AS IS" AND ANY EXPRESS OR IMPLIED WARRANTIES, INCLUDING, BUT NOT
+// LIMITED TO, THE IMPLIED WARRANTIES OF MERCHANTABILITY AND FITNESS
+// FOR A PARTICULAR PURPOSE ARE DISCLAIMED. IN NO EVENT SHALL THE
+// COPYRIGHT HOLDER OR CONTRIBUTORS BE LIABLE FOR ANY DIRECT, INDIRECT,
+// INCIDENTAL, SPECIAL, EXEMPLARY, OR CONSEQUENTIAL DAMAGES (INCLUDING,
+// BUT NOT LIMITED TO, PROCUREMENT OF SUBSTITUTE GOODS OR SERVICES; LOSS
+// OF USE, DATA, OR PROFITS; OR BUSINESS INTERRUPTION) HOWEVER CAUSED
+// AND ON ANY THEORY OF LIABILITY, WHETHER IN CONTRACT, STRICT LIABILITY,
+// OR TORT (INCLUDING NEGLIGENCE OR OTHERWISE) ARISING IN ANY WAY OUT OF
+// THE USE OF THIS SOFTWARE, EVEN IF ADVISED OF THE POSSIBILITY OF SUCH
+// DAMAGE.
+use std::io;
+
+use graphics::renderer::*;
+use misc::fileutils::*;
+
+/// The default name of the file the vertex buffer high-water marks are saved to
+pub const DEFAULT_VERTEX_POOL_FILENAME: &'static str = "vertex_pool.txt";
+
+/// The number of vertex buffers to have ready for each vertex array type and thread
+///
+/// These are used both to prewarm the pools of vertex buffers and to report the most used in a
+/// frame, so that one run's high-water marks can be saved and used to prewarm the next.
+#[derive(Clone, Debug, PartialEq)]
+pub struct VertexBufferPoolSizes {
+    counts: Vec<Vec<usize>>, // Per vertex array type, per thread
+}
+
+impl VertexBufferPoolSizes {
+    /// Construct sizes of zero for every vertex array type
+    ///
+    /// max_threads: The number of threads
+    pub fn new(max_threads: usize) -> VertexBufferPoolSizes {
+        VertexBufferPoolSizes { counts: (0..VERTEX_ARRAY_TYPE_END_RANGE + 1).map(|_| vec![0; max_threads]).collect() }
+    }
+
+    /// Return the number of threads
+    pub fn max_threads(&self) -> usize {
+        self.counts[0].len()
+    }
+
+    /// Return the number of vertex buffers for a vertex array type and thread
+    ///
+    /// ty: The vertex array type
+    /// thr: The thread, for which threads beyond those known have no buffers
+    pub fn get(&self, ty: VertexArrayType, thr: usize) -> usize {
+        match self.counts[ty as usize].get(thr) {
+            Some(count) => *count,
+            None => 0,
+        }
+    }
+
+    /// Set the number of vertex buffers for a vertex array type and thread
+    ///
+    /// ty: The vertex array type
+    /// thr: The thread
+    /// count: The number of vertex buffers
+    pub fn set(&mut self, ty: VertexArrayType, thr: usize, count: usize) {
+        self.counts[ty as usize][thr] = count;
+    }
+
+    /// Raise each count to at least the corresponding count of other sizes
+    ///
+    /// other: The sizes to merge in, whose threads beyond these are ignored
+    pub fn merge_max(&mut self, other: &VertexBufferPoolSizes) {
+        for (ty, counts) in self.counts.iter_mut().enumerate() {
+            for (thr, count) in counts.iter_mut().enumerate() {
+                *count = (*count).max(other.get(VertexArrayType::from_u32(ty as u32), thr));
+            }
+        }
+    }
+
+    /// Return the sizes as text, with a line of vertex array type, thread and count for each non-zero count
+    pub fn serialise(&self) -> String {
+        let mut contents = String::new();
+        for (ty, counts) in self.counts.iter().enumerate() {
+            for (thr, count) in counts.iter().enumerate() {
+                if *count > 0 {
+                    contents.push_str(&format!("{} {} {}\n", ty, thr, count));
+                }
+            }
+        }
+
+        contents
+    }
+
+    /// Construct sizes from text written by serialise
+    ///
+    /// Lines that cannot be parsed, or are for unknown vertex array types or threads, are ignored.
+    ///
+    /// contents: The text
+    /// max_threads: The number of threads
+    pub fn parse(contents: &str, max_threads: usize) -> VertexBufferPoolSizes {
+        let mut sizes = VertexBufferPoolSizes::new(max_threads);
+        for line in contents.lines() {
+            let fields: Vec<usize> = line.split_whitespace().filter_map(|field| field.parse().ok()).collect();
+            if fields.len() != 3 {
+                continue;
+            }
+
+            let (ty, thr, count) = (fields[0], fields[1], fields[2]);
+            if ty <= VERTEX_ARRAY_TYPE_END_RANGE as usize && thr < max_threads {
+                sizes.counts[ty][thr] = count;
+            }
+        }
+
+        sizes
+    }
+
+    /// Save the sizes to a file
+    ///
+    /// filename: The name of the file
+    pub fn save(&self, filename: &str) -> Result<(), io::Error> {
+        write_entire_file(&self.serialise(), filename)
+    }
+
+    /// Load sizes saved to a file, returning None if it cannot be read
+    ///
+    /// filename: The name of the file
+    /// max_threads: The number of threads
+    pub fn load(filename: &str, max_threads: usize) -> Option<VertexBufferPoolSizes> {
+        match read_binary_file(filename, false /* dump */) {
+            Ok(bytes) => {
+                match String::from_utf8(bytes) {
+                    Ok(contents) => Some(VertexBufferPoolSizes::parse(&contents, max_threads)),
+                    Err(_) => None,
+                }
+            }
+            Err(_) => None,
+        }
+    }
+}
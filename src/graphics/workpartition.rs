@@ -0,0 +1,455 @@
+// Copyright (c) 2016-2017 Bruce Stenning. All rights reserved.
+//
+// Redistribution and use in source and binary forms, with or without
+// modification, are permitted provided that the following conditions
+// are met:
+//
+// 1. Redistributions of source code must retain the above copyright
+//    notice, this list of conditions and the following disclaimer.
+//
+// 2. Redistributions in binary form must reproduce the above copyright
+//    notice, this list of conditions and the following disclaimer in the
+//    documentation and/or other materials provided with the distribution.
+//
+// 3. Neither the name of the copyright holder nor the names of its
+//    contributors may be used to endorse or promote products derived
+//    from this software without specific prior written permission.
+//
+// THIS SOFTWARE IS PROVIDED BY THE COPYRIGHT HOLDERS AND CONTRIBUTORS
+// AS IS" AND ANY EXPRESS OR IMPLIED WARRANTIES, INCLUDING, BUT NOT
+// LIMITED TO, THE IMPLIED WARRANTIES OF MERCHANTABILITY AND FITNESS
+// FOR A PARTICULAR PURPOSE ARE DISCLAIMED. IN NO EVENT SHALL THE
+// COPYRIGHT HOLDER OR CONTRIBUTORS BE LIABLE FOR ANY DIRECT, INDIRECT,
+// INCIDENTAL, SPECIAL, EXEMPLARY, OR CONSEQUENTIAL DAMAGES (INCLUDING,
+// BUT NOT LIMITED TO, PROCUREMENT OF SUBSTITUTE GOODS OR SERVICES; LOSS
+// OF USE, DATA, OR PROFITS; OR BUSINESS INTERRUPTION) HOWEVER CAUSED
+// AND ON ANY THEORY OF LIABILITY, WHETHER IN CONTRACT, STRICT LIABILITY,
+// OR TORT (INCLUDING NEGLIGENCE OR OTHERWISE) ARISING IN ANY WAY OUT OF
+// THE USE OF THIS SOFTWARE, EVEN IF ADVISED OF THE POSSIBILITY OF SUCH
+// DAMAGE.
+
+use std::io;
+use std::sync::Mutex;
+use std::sync::atomic::{AtomicUsize, Ordering};
+
+use misc::fileutils::*;
+
+// Identifies a work partition log file, followed by the format version
+const PARTITION_MAGIC: &'static [u8; 4] = b"WYWP";
+const PARTITION_VERSION: u32 = 1;
+
+/// How the chunks of a frame's work are assigned to the rendering threads
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub enum PartitionMode {
+    Interleaved, // Chunk n goes to thread n modulo the number of threads
+    Fixed, // Each thread takes an equal contiguous range of chunks
+    Seeded(u32), // The chunks are shuffled by a seeded generator, then interleaved
+    Dynamic, // Each thread claims the next unclaimed chunk whenever it is free
+}
+
+/// The chunks each thread handled in a frame, in the order it handled them
+#[derive(Clone, Debug, PartialEq)]
+pub struct FramePartition {
+    pub frame: u64,
+    pub chunk_count: u32,
+    pub threads: Vec<Vec<u32>>,
+}
+
+impl FramePartition {
+    /// Append the little-endian binary encoding of the partition
+    ///
+    /// bytes: The buffer to append to
+    pub fn encode(&self, bytes: &mut Vec<u8>) {
+        push_u32(bytes, self.frame as u32);
+        push_u32(bytes, (self.frame >> 32) as u32);
+        push_u32(bytes, self.chunk_count);
+        push_u32(bytes, self.threads.len() as u32);
+        for chunks in self.threads.iter() {
+            push_u32(bytes, chunks.len() as u32);
+            for chunk in chunks.iter() {
+                push_u32(bytes, *chunk);
+            }
+        }
+    }
+
+    /// Decode a partition, returning it and the offset just past it
+    ///
+    /// bytes: The encoded log
+    /// offset: The offset of the partition within the log
+    pub fn decode(bytes: &Vec<u8>, offset: usize) -> Result<(FramePartition, usize), io::Error> {
+        let mut offset = offset;
+        let frame_low = read_u32(bytes, &mut offset)? as u64;
+        let frame_high = read_u32(bytes, &mut offset)? as u64;
+        let chunk_count = read_u32(bytes, &mut offset)?;
+        let thread_count = read_u32(bytes, &mut offset)?;
+
+        let mut threads = vec![];
+        for _ in 0..thread_count {
+            let count = read_u32(bytes, &mut offset)?;
+            if count > chunk_count {
+                return Err(io::Error::new(io::ErrorKind::InvalidData, "Corrupt work partition"));
+            }
+
+            let mut chunks = Vec::with_capacity(count as usize);
+            for _ in 0..count {
+                let chunk = read_u32(bytes, &mut offset)?;
+                if chunk >= chunk_count {
+                    return Err(io::Error::new(io::ErrorKind::InvalidData, "Corrupt work partition"));
+                }
+                chunks.push(chunk);
+            }
+            threads.push(chunks);
+        }
+
+        Ok((FramePartition {
+                frame: frame_low | (frame_high << 32),
+                chunk_count: chunk_count,
+                threads: threads,
+            },
+            offset))
+    }
+}
+
+/// The partitions of consecutive frames, recorded to reproduce a run's assignment of work
+#[derive(Clone, Debug, PartialEq)]
+pub struct PartitionLog {
+    pub frames: Vec<FramePartition>,
+}
+
+impl PartitionLog {
+    pub fn new() -> PartitionLog {
+        PartitionLog { frames: vec![] }
+    }
+
+    /// Return the binary encoding of the log
+    pub fn encode(&self) -> Vec<u8> {
+        let mut bytes = PARTITION_MAGIC.to_vec();
+        push_u32(&mut bytes, PARTITION_VERSION);
+        for frame in self.frames.iter() {
+            frame.encode(&mut bytes);
+        }
+
+        bytes
+    }
+
+    /// Decode a log held in memory
+    ///
+    /// bytes: The contents of the log file
+    pub fn decode(bytes: &Vec<u8>) -> Result<PartitionLog, io::Error> {
+        if bytes.len() < PARTITION_MAGIC.len() || &bytes[0..PARTITION_MAGIC.len()] != &PARTITION_MAGIC[..] {
+            return Err(io::Error::new(io::ErrorKind::InvalidData, "Not a work partition log"));
+        }
+
+        let mut offset = PARTITION_MAGIC.len();
+        let version = read_u32(bytes, &mut offset)?;
+        if version != PARTITION_VERSION {
+            return Err(io::Error::new(io::ErrorKind::InvalidData,
+                                      format!("Unsupported work partition log version {}", version)));
+        }
+
+        let mut frames = vec![];
+        while offset < bytes.len() {
+            let (frame, next) = FramePartition::decode(bytes, offset)?;
+            frames.push(frame);
+            offset = next;
+        }
+
+        Ok(PartitionLog { frames: frames })
+    }
+
+    /// Write the log to a file
+    ///
+    /// filename: The file to write
+    pub fn save(&self, filename: &str) -> Result<(), io::Error> {
+        write_binary_file(&self.encode(), filename)
+    }
+
+    /// Load a log file
+    ///
+    /// filename: The log file written by save
+    pub fn load(filename: &str) -> Result<PartitionLog, io::Error> {
+        let bytes = read_binary_file(filename, false)?;
+        PartitionLog::decode(&bytes)
+    }
+}
+
+/// The assignment of one frame's chunks of work to the rendering threads
+///
+/// This is shared by the threads, each of which calls next_chunk with its thread number until
+/// it returns None.  The chunks each thread handled are kept when recording.
+pub struct WorkPartition {
+    frame: u64,
+    chunk_count: usize,
+    assigned: Option<Vec<Vec<usize>>>, // The chunks of each thread, or None to claim them dynamically
+    cursors: Vec<AtomicUsize>, // The position of each thread within its assigned chunks
+    next_claim: AtomicUsize, // The next chunk to be claimed dynamically
+    handled: Option<Vec<Mutex<Vec<u32>>>>, // The chunks each thread handled, when recording
+}
+
+impl WorkPartition {
+    fn new(frame: u64,
+           chunk_count: usize,
+           thread_count: usize,
+           assigned: Option<Vec<Vec<usize>>>,
+           recording: bool)
+           -> WorkPartition {
+        WorkPartition {
+            frame: frame,
+            chunk_count: chunk_count,
+            assigned: assigned,
+            cursors: (0..thread_count).map(|_| AtomicUsize::new(0)).collect(),
+            next_claim: AtomicUsize::new(0),
+            handled: if recording {
+                Some((0..thread_count).map(|_| Mutex::new(vec![])).collect())
+            } else {
+                None
+            },
+        }
+    }
+
+    /// Return the next chunk for a thread to handle, or None when it has no more work
+    ///
+    /// thr: The thread number, as held in the thread's data
+    pub fn next_chunk(&self, thr: usize) -> Option<usize> {
+        if thr >= self.cursors.len() {
+            return None;
+        }
+
+        let chunk = match self.assigned {
+            Some(ref assigned) => {
+                let position = self.cursors[thr].fetch_add(1, Ordering::Relaxed);
+                assigned[thr].get(position).cloned()
+            }
+            None => {
+                let claim = self.next_claim.fetch_add(1, Ordering::Relaxed);
+                if claim < self.chunk_count { Some(claim) } else { None }
+            }
+        };
+
+        if let (Some(chunk), Some(handled)) = (chunk, self.handled.as_ref()) {
+            handled[thr].lock().unwrap().push(chunk as u32);
+        }
+
+        chunk
+    }
+
+    /// Return the frame the partition belongs to
+    pub fn frame(&self) -> u64 {
+        self.frame
+    }
+
+    /// Return the number of chunks the frame's work is divided into
+    pub fn chunk_count(&self) -> usize {
+        self.chunk_count
+    }
+
+    /// Return the number of threads the chunks are shared between
+    pub fn thread_count(&self) -> usize {
+        self.cursors.len()
+    }
+
+    /// Return the chunks each thread has handled so far, if the partition is being recorded
+    pub fn handled(&self) -> Option<FramePartition> {
+        self.handled.as_ref().map(|handled| {
+            FramePartition {
+                frame: self.frame,
+                chunk_count: self.chunk_count as u32,
+                threads: handled.iter().map(|chunks| chunks.lock().unwrap().clone()).collect(),
+            }
+        })
+    }
+}
+
+/// Partitions each frame's work between the rendering threads, recording or replaying the
+/// partitions so that multi-threaded rendering bugs can be reproduced
+///
+/// The application divides its scene into chunks, calls begin_frame before running the MT
+/// harness and has each thread's render_thread take chunks from the returned partition.  Only
+/// the dynamic mode varies between runs; recording it and replaying the log repeats a run's
+/// assignment exactly.
+pub struct WorkPartitioner {
+    mode: PartitionMode,
+    frame: u64,
+    recording: Option<PartitionLog>,
+    replay: Option<PartitionLog>,
+    replay_index: usize,
+}
+
+impl WorkPartitioner {
+    /// Construct a partitioner
+    ///
+    /// mode: How chunks are assigned to threads
+    pub fn new(mode: PartitionMode) -> WorkPartitioner {
+        WorkPartitioner {
+            mode: mode,
+            frame: 0,
+            recording: None,
+            replay: None,
+            replay_index: 0,
+        }
+    }
+
+    /// Return how chunks are assigned to threads, when not replaying
+    pub fn mode(&self) -> PartitionMode {
+        self.mode
+    }
+
+    /// Set how chunks are assigned to threads, when not replaying
+    ///
+    /// mode: The partition mode
+    pub fn set_mode(&mut self, mode: PartitionMode) {
+        self.mode = mode;
+    }
+
+    /// Start recording the chunks each thread handles, discarding any previous recording
+    pub fn start_recording(&mut self) {
+        self.recording = Some(PartitionLog::new());
+    }
+
+    /// Stop recording, returning the partitions recorded
+    pub fn stop_recording(&mut self) -> Option<PartitionLog> {
+        self.recording.take()
+    }
+
+    /// Replay the partitions of a log, one per frame from the next frame on
+    ///
+    /// Once the log is exhausted, the partition mode applies again.
+    ///
+    /// log: The recorded partitions
+    pub fn start_replay(&mut self, log: PartitionLog) {
+        self.replay = Some(log);
+        self.replay_index = 0;
+    }
+
+    /// Return whether a log is being replayed
+    pub fn is_replaying(&self) -> bool {
+        self.replay.is_some()
+    }
+
+    /// Partition the next frame's chunks
+    ///
+    /// chunk_count: The number of chunks the frame's work is divided into
+    /// thread_count: The number of rendering threads
+    pub fn begin_frame(&mut self, chunk_count: usize, thread_count: usize) -> WorkPartition {
+        self.frame += 1;
+
+        let assigned = match self.next_replayed(chunk_count, thread_count) {
+            Some(assigned) => Some(assigned),
+            None => {
+                match self.mode {
+                    PartitionMode::Interleaved => Some(interleave(&(0..chunk_count).collect(), thread_count)),
+                    PartitionMode::Fixed => Some(split_contiguous(chunk_count, thread_count)),
+                    PartitionMode::Seeded(seed) => {
+                        Some(interleave(&shuffled_chunks(chunk_count, seed, self.frame), thread_count))
+                    }
+                    PartitionMode::Dynamic => None,
+                }
+            }
+        };
+
+        WorkPartition::new(self.frame, chunk_count, thread_count, assigned, self.recording.is_some())
+    }
+
+    /// Record the chunks each thread handled in a frame, once the threads have finished
+    ///
+    /// partition: The partition returned by begin_frame
+    pub fn end_frame(&mut self, partition: &WorkPartition) {
+        if let (Some(log), Some(frame)) = (self.recording.as_mut(), partition.handled()) {
+            log.frames.push(frame);
+        }
+    }
+
+    /// Return the assignment of the next replayed frame, if replaying
+    ///
+    /// A frame whose chunk or thread count does not match is not replayed, and ends the replay.
+    fn next_replayed(&mut self, chunk_count: usize, thread_count: usize) -> Option<Vec<Vec<usize>>> {
+        let assigned = match self.replay {
+            Some(ref log) => {
+                match log.frames.get(self.replay_index) {
+                    Some(recorded) => {
+                        if recorded.chunk_count as usize == chunk_count && recorded.threads.len() <= thread_count {
+                            let mut assigned: Vec<Vec<usize>> = recorded.threads
+                                .iter()
+                                .map(|chunks| chunks.iter().map(|chunk| *chunk as usize).collect())
+                                .collect();
+                            assigned.resize(thread_count, vec![]);
+                            Some(assigned)
+                        } else {
+                            log_warning!("Replayed work partition of frame {} has {} chunks on {} threads, not {} on {}",
+                                         recorded.frame,
+                                         recorded.chunk_count,
+                                         recorded.threads.len(),
+                                         chunk_count,
+                                         thread_count);
+                            None
+                        }
+                    }
+                    None => None,
+                }
+            }
+            None => return None,
+        };
+
+        if assigned.is_some() {
+            self.replay_index += 1;
+        } else {
+            self.replay = None;
+        }
+
+        assigned
+    }
+}
+
+/// Deal chunks to threads in turn
+///
+/// chunks: The chunks, in the order they are dealt
+/// thread_count: The number of threads
+fn interleave(chunks: &Vec<usize>, thread_count: usize) -> Vec<Vec<usize>> {
+    let mut assigned = vec![vec![]; thread_count];
+    if thread_count > 0 {
+        for (i, chunk) in chunks.iter().enumerate() {
+            assigned[i % thread_count].push(*chunk);
+        }
+    }
+
+    assigned
+}
+
+/// Split the chunks into equal contiguous ranges, the earlier threads taking any remainder
+///
+/// chunk_count: The number of chunks
+/// thread_count: The number of threads
+fn split_contiguous(chunk_count: usize, thread_count: usize) -> Vec<Vec<usize>> {
+    let mut assigned = Vec::with_capacity(thread_count);
+    let mut start = 0;
+    for thr in 0..thread_count {
+        let count = chunk_count / thread_count + if thr < chunk_count % thread_count { 1 } else { 0 };
+        assigned.push((start..start + count).collect());
+        start += count;
+    }
+
+    assigned
+}
+
+/// Return the chunks in an order shuffled by a xorshift generator, which differs between frames
+/// but is the same for a seed on every run
+///
+/// chunk_count: The number of chunks
+/// seed: The seed of the generator
+/// frame: The frame number, mixed into the seed
+fn shuffled_chunks(chunk_count: usize, seed: u32, frame: u64) -> Vec<usize> {
+    let mut state = seed ^ (frame as u32).wrapping_mul(0x9e37_79b9);
+    if state == 0 {
+        state = 1;
+    }
+
+    let mut chunks: Vec<usize> = (0..chunk_count).collect();
+    for i in (1..chunk_count).rev() {
+        state ^= state << 13;
+        state ^= state >> 17;
+        state ^= state << 5;
+        chunks.swap(i, state as usize % (i + 1));
+    }
+
+    chunks
+}
@@ -0,0 +1,351 @@
+// Copyright (c) 2016-2017 Bruce Stenning. All rights reserved.
+//
+// Redistribution and use in source and binary forms, with or without
+// modification, are permitted provided that the following conditions
+// are met:
+//
+// 1. Redistributions of source code must retain the above copyright
+//    notice, this list of conditions and the following disclaimer.
+//
+// 2. Redistributions in binary form must reproduce the above copyright
+//    notice, this list of conditions and the following disclaimer in the
+//    documentation and/or other materials provided with the distribution.
+//
+// 3. Neither the name of the copyright holder nor the names of its
+//    contributors may be used to endorse or promote products derived
+//    from this software without specific prior written permission.
+//
+// THIS SOFTWARE IS PROVIDED BY THE COPYRIGHT HOLDERS AND CONTRIBUTORS
+// AS IS" AND ANY EXPRESS OR IMPLIED WARRANTIES, INCLUDING, BUT NOT
+// LIMITED TO, THE IMPLIED WARRANTIES OF MERCHANTABILITY AND FITNESS
+// FOR A PARTICULAR PURPOSE ARE DISCLAIMED. IN NO EVENT SHALL THE
+// COPYRIGHT HOLDER OR CONTRIBUTORS BE LIABLE FOR ANY DIRECT, INDIRECT,
+// INCIDENTAL, SPECIAL, EXEMPLARY, OR CONSEQUENTIAL DAMAGES (INCLUDING,
+// BUT NOT LIMITED TO, PROCUREMENT OF SUBSTITUTE GOODS OR SERVICES; LOSS
+// OF USE, DATA, OR PROFITS; OR BUSINESS INTERRUPTION) HOWEVER CAUSED
+// AND ON ANY THEORY OF LIABILITY, WHETHER IN CONTRACT, STRICT LIABILITY,
+// OR TORT (INCLUDING NEGLIGENCE OR OTHERWISE) ARISING IN ANY WAY OUT OF
+// THE USE OF THIS SOFTWARE, EVEN IF ADVISED OF THE POSSIBILITY OF SUCH
+// DAMAGE.
+
+use glfw;
+
+use algebra::matrix::{ClipSpaceConvention, Mat4};
+use algebra::quaternion::Quaternion;
+use algebra::vector::Vec3;
+use graphics::renderer::*;
+use graphics::rendertarget::*;
+use graphics::resources::*;
+use graphics::stereo::Eye;
+
+/// The name of the uniform block holding the view and projection matrices of both eyes
+pub const XR_EYES_BLOCK: &'static str = "xr_eyes";
+
+/// The name of the push constant selecting the eye being rendered, 0 for left and 1 for right
+pub const XR_EYE_PUSH_CONSTANT: &'static str = "xr_eye";
+
+/// The Vulkan instance extensions runtimes ask for through XR_KHR_vulkan_enable
+pub const XR_VULKAN_INSTANCE_EXTENSIONS: [&'static str; 4] = ["VK_KHR_get_physical_device_properties2",
+                                                              "VK_KHR_external_memory_capabilities",
+                                                              "VK_KHR_external_semaphore_capabilities",
+                                                              "VK_KHR_external_fence_capabilities"];
+
+/// The Vulkan device extensions runtimes ask for through XR_KHR_vulkan_enable
+#[cfg(not(windows))]
+pub const XR_VULKAN_DEVICE_EXTENSIONS: [&'static str; 5] = ["VK_KHR_dedicated_allocation",
+                                                            "VK_KHR_get_memory_requirements2",
+                                                            "VK_KHR_external_memory",
+                                                            "VK_KHR_external_memory_fd",
+                                                            "VK_KHR_external_semaphore"];
+
+/// The Vulkan device extensions runtimes ask for through XR_KHR_vulkan_enable
+#[cfg(windows)]
+pub const XR_VULKAN_DEVICE_EXTENSIONS: [&'static str; 5] = ["VK_KHR_dedicated_allocation",
+                                                            "VK_KHR_get_memory_requirements2",
+                                                            "VK_KHR_external_memory",
+                                                            "VK_KHR_external_memory_win32",
+                                                            "VK_KHR_external_semaphore"];
+
+/// The handles an OpenXR session is created with, for its graphics binding
+///
+/// The Vulkan handles are the raw dispatchable handles, for XrGraphicsBindingVulkanKHR.  The
+/// OpenGL binding needs the platform's display and context, which the application takes from
+/// its window as the renderer's context is the current one.
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub enum XrGraphicsBinding {
+    Vulkan {
+        instance: usize,
+        physical_device: usize,
+        device: usize,
+        queue_family_index: u32,
+        queue_index: u32,
+    },
+    OpenGl,
+}
+
+/// The angles of the sides of an eye's field of view from its forward direction, in radians
+///
+/// As in XrFovf, the left and down angles are normally negative.
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub struct XrFov {
+    pub angle_left: f32,
+    pub angle_right: f32,
+    pub angle_up: f32,
+    pub angle_down: f32,
+}
+
+impl XrFov {
+    /// Construct the projection matrix of the field of view, which is asymmetric in general
+    ///
+    /// near: The distance from the eye to the near clip plane
+    /// far: The distance from the eye to the far clip plane
+    /// convention: The clip space convention of the backend, from Renderer::clip_space_convention
+    pub fn projection(&self, near: f32, far: f32, convention: ClipSpaceConvention) -> Mat4<f32> {
+        let tan_left = self.angle_left.tan();
+        let tan_right = self.angle_right.tan();
+        let tan_up = self.angle_up.tan();
+        let tan_down = self.angle_down.tan();
+        let width = tan_right - tan_left;
+        let height = tan_up - tan_down;
+
+        let mut matrix = Mat4::new();
+        matrix.m[0][0] = 2.0f32 / width;
+        matrix.m[2][0] = (tan_right + tan_left) / width;
+        matrix.m[1][1] = 2.0f32 / height;
+        matrix.m[2][1] = (tan_up + tan_down) / height;
+        matrix.m[2][2] = (far + near) / (near - far);
+        matrix.m[3][2] = (2.0f32 * far * near) / (near - far);
+        matrix.m[2][3] = -1.0f32;
+
+        Mat4::clip_space_correction(convention) * matrix
+    }
+}
+
+/// The position and orientation of an eye in the session's reference space
+#[derive(Clone, Copy)]
+pub struct XrPose {
+    pub orientation: Quaternion<f32>,
+    pub position: Vec3<f32>,
+}
+
+impl XrPose {
+    /// Construct the view matrix of the pose, which maps the reference space into eye space
+    ///
+    /// Eye space looks down the negative Z axis, as OpenXR's view space does.
+    pub fn view(&self) -> Mat4<f32> {
+        let right = Quaternion::rotate(&Vec3 {
+                                           x: 1.0f32,
+                                           y: 0.0f32,
+                                           z: 0.0f32,
+                                       },
+                                       &self.orientation);
+        let up = Quaternion::rotate(&Vec3 {
+                                        x: 0.0f32,
+                                        y: 1.0f32,
+                                        z: 0.0f32,
+                                    },
+                                    &self.orientation);
+        let back = Quaternion::rotate(&Vec3 {
+                                          x: 0.0f32,
+                                          y: 0.0f32,
+                                          z: 1.0f32,
+                                      },
+                                      &self.orientation);
+
+        // The third row is the eye's Z axis, which points away from where it looks
+        Mat4::modelview(&self.position, &back, &right, &up)
+    }
+}
+
+/// An eye's view of the scene as located by the session for a frame
+#[derive(Clone, Copy)]
+pub struct XrView {
+    pub pose: XrPose,
+    pub fov: XrFov,
+}
+
+/// The timing of a frame of the session, from xrWaitFrame
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub struct XrFrameState {
+    pub predicted_display_time: i64,
+    pub should_render: bool,
+}
+
+/// An image of one of the session's swapchains, acquired for an eye
+///
+/// The image is the VkImage, or the OpenGL texture name, as enumerated from the swapchain.  A
+/// Vulkan image is in the colour attachment layout while acquired, as OpenXR requires.
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub struct XrSwapchainImage {
+    pub image: u64,
+    pub width: u32,
+    pub height: u32,
+}
+
+/// The application's OpenXR session, over whichever OpenXR binding it uses
+///
+/// The session is created with the renderer's xr_graphics_binding, with a swapchain per eye, and
+/// drives the frame loop of begin_xr_frame and flip_xr.
+pub trait XrSession {
+    /// Wait for and begin the next frame, returning None when the session is not running
+    fn wait_frame(&mut self) -> Option<XrFrameState>;
+
+    /// Locate both eyes at the time the frame is displayed, in the order left then right
+    ///
+    /// display_time: The predicted display time of the frame
+    fn locate_views(&mut self, display_time: i64) -> [XrView; 2];
+
+    /// Acquire and wait for the next image of an eye's swapchain
+    ///
+    /// eye: The eye whose swapchain to acquire from
+    fn acquire_image(&mut self, eye: Eye) -> XrSwapchainImage;
+
+    /// Release the image last acquired from an eye's swapchain
+    ///
+    /// eye: The eye whose swapchain to release to
+    fn release_image(&mut self, eye: Eye);
+
+    /// End the frame, submitting a projection layer of both eyes if they were rendered
+    ///
+    /// display_time: The predicted display time of the frame
+    /// views: The views the eyes were rendered from
+    /// rendered: Whether the swapchain images were rendered to
+    fn end_frame(&mut self, display_time: i64, views: &[XrView; 2], rendered: bool);
+}
+
+/// A frame of the session, with the eye matrices it is rendered with
+pub struct XrStereoFrame {
+    pub state: XrFrameState,
+    pub views: [XrView; 2],
+}
+
+impl XrStereoFrame {
+    /// Return the view matrix of an eye
+    ///
+    /// world_to_space: The matrix from world space into the session's reference space
+    /// eye: The eye to return the view matrix of
+    pub fn eye_view(&self, world_to_space: &Mat4<f32>, eye: Eye) -> Mat4<f32> {
+        self.views[eye_index(eye)].pose.view() * *world_to_space
+    }
+
+    /// Return the projection matrix of an eye
+    ///
+    /// near: The distance from the eye to the near clip plane
+    /// far: The distance from the eye to the far clip plane
+    /// convention: The clip space convention of the backend, from Renderer::clip_space_convention
+    /// eye: The eye to return the projection matrix of
+    pub fn eye_projection(&self, near: f32, far: f32, convention: ClipSpaceConvention, eye: Eye) -> Mat4<f32> {
+        self.views[eye_index(eye)].fov.projection(near, far, convention)
+    }
+}
+
+/// Return the index of an eye in the views and uniforms, 0 for left and 1 for right
+///
+/// eye: The eye
+pub fn eye_index(eye: Eye) -> usize {
+    match eye {
+        Eye::Left => 0,
+        Eye::Right => 1,
+    }
+}
+
+/// Add the uniform block of both eyes' matrices, whose members are filled in by the reflection
+/// data
+///
+/// Shaders rendering for the headset declare the block and pick an eye's matrices by the
+/// XR_EYE_PUSH_CONSTANT, which is set before each eye's passes as the scene is rendered once
+/// per eye.
+///
+/// resource_manager: The resource manager to add the specification to
+pub fn register_xr_eyes_block(resource_manager: &mut ResourceManager) {
+    let block_spec = UniformBlockSpec {
+        set: 0,
+        block_type: UniformType::UniformBuffer,
+        uniforms: ["xr_left_view", "xr_left_projection", "xr_right_view", "xr_right_projection"]
+            .iter()
+            .map(|name| {
+                BlockUniformSpec {
+                    name: *name,
+                    ..Default::default()
+                }
+            })
+            .collect(),
+        ..Default::default()
+    };
+    resource_manager.uniform_block_specs.insert(XR_EYES_BLOCK, block_spec);
+}
+
+/// Wait for the next frame of the session and locate the eyes for it
+///
+/// Returns None when the session is not running, in which case nothing is rendered for the
+/// headset this frame.
+///
+/// session: The application's OpenXR session
+pub fn begin_xr_frame(session: &mut XrSession) -> Option<XrStereoFrame> {
+    let state = match session.wait_frame() {
+        Some(s) => s,
+        None => return None,
+    };
+    let views = session.locate_views(state.predicted_display_time);
+
+    Some(XrStereoFrame {
+        state: state,
+        views: views,
+    })
+}
+
+/// Set both eyes' matrices of a frame in their uniform block
+///
+/// renderer: The renderer owning the uniform buffer
+/// frame: The frame of the session
+/// world_to_space: The matrix from world space into the session's reference space
+/// near: The distance from the eyes to the near clip plane
+/// far: The distance from the eyes to the far clip plane
+pub fn synchronise_xr_eyes<Rend: Renderer + ?Sized>(renderer: &Rend,
+                                                    frame: &XrStereoFrame,
+                                                    world_to_space: &Mat4<f32>,
+                                                    near: f32,
+                                                    far: f32) {
+    let convention = renderer.clip_space_convention();
+    for &(eye, view_name, projection_name) in [(Eye::Left, "xr_left_view", "xr_left_projection"),
+                                               (Eye::Right, "xr_right_view", "xr_right_projection")]
+        .iter() {
+        renderer.set_uniform_buffer_matrix(XR_EYES_BLOCK, view_name, &frame.eye_view(world_to_space, eye));
+        renderer.set_uniform_buffer_matrix(XR_EYES_BLOCK,
+                                           projection_name,
+                                           &frame.eye_projection(near, far, convention, eye));
+    }
+    renderer.synchronise_uniform_buffer(XR_EYES_BLOCK);
+}
+
+/// Hand the rendered eyes to the session and end its frame, then present the desktop mirror
+///
+/// This takes the place of Renderer::flip for a frame rendered for the headset, after
+/// end_frame.  Each eye's render target is copied into an image acquired from its swapchain.
+/// The mirror window shows whatever the frame's final pass drew to it, such as the eyes
+/// combined by stereo_composite.
+///
+/// renderer: The renderer the eyes were rendered with
+/// session: The application's OpenXR session
+/// frame: The frame begun by begin_xr_frame
+/// eyes: The render targets of the left and right eyes
+/// mirror: The desktop mirror window, if there is one
+pub fn flip_xr(renderer: &mut Renderer,
+               session: &mut XrSession,
+               frame: &XrStereoFrame,
+               eyes: [&RenderTarget; 2],
+               mirror: Option<&mut glfw::Context>) {
+    let rendered = frame.state.should_render;
+    if rendered {
+        for &eye in [Eye::Left, Eye::Right].iter() {
+            let image = session.acquire_image(eye);
+            renderer.copy_to_xr_image(eyes[eye_index(eye)], &image);
+            session.release_image(eye);
+        }
+    }
+    session.end_frame(frame.state.predicted_display_time, &frame.views, rendered);
+
+    if let Some(window) = mirror {
+        renderer.flip(window);
+    }
+}
@@ -40,12 +40,69 @@ extern crate image;
 extern crate semver;
 extern crate regex;
 
+#[macro_use]
+pub mod misc;
 pub mod graphics;
 pub mod algebra;
-pub mod misc;
 
 pub mod unittests {
     pub mod vector_test;
     pub mod quaternion_test;
     pub mod matrix_test;
+    pub mod geospatial_test;
+    pub mod atlas_test;
+    pub mod geometrycapture_test;
+    pub mod computepacing_test;
+    pub mod transition_test;
+    pub mod frametime_test;
+    pub mod bandwidth_test;
+    pub mod versioning_test;
+    pub mod renderthread_test;
+    pub mod vertexpool_test;
+    pub mod failureinjection_test;
+    pub mod interpolation_test;
+    pub mod exposure_test;
+    pub mod rendergraph_test;
+    pub mod gpusort_test;
+    pub mod bindless_test;
+    pub mod devicefeatures_test;
+    pub mod gputext_test;
+    pub mod gputiming_test;
+    pub mod texturefeedback_test;
+    pub mod brushes_test;
+    pub mod hiz_test;
+    pub mod contactshadows_test;
+    pub mod workpartition_test;
+    pub mod parity_test;
+    pub mod xr_test;
+    pub mod capturetrigger_test;
+    pub mod spirvreflect_test;
+    pub mod glslinclude_test;
+    pub mod deflate_test;
+    pub mod texture_test;
+    pub mod memoryallocator_test;
+    pub mod text_test;
+    pub mod overlay_test;
+    pub mod debugmessages_test;
+    pub mod logging_test;
+    pub mod renderpasses_test;
+    pub mod streambuffer_test;
+    pub mod memoryusage_test;
+    pub mod uniformlayout_test;
+    pub mod mesh_test;
+    pub mod colourspace_test;
+    pub mod tonemap_test;
+    pub mod posteffects_test;
+    pub mod shadervariants_test;
+    pub mod pipelinebuild_test;
+    pub mod cputiming_test;
+    pub mod rendererevents_test;
+    pub mod ssr_test;
+    pub mod checkerboard_test;
+    pub mod motionblur_test;
+    pub mod stereo_test;
+    pub mod perdraw_test;
+    pub mod decals_test;
+    pub mod shader_test;
+    pub mod readback_test;
 }
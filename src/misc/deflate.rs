@@ -0,0 +1,442 @@
+// Copyright (c) 2016-2017 Bruce Stenning. All rights reserved.
+//
+// Redistribution and use in source and binary forms, with or without
+// modification, are permitted provided that the following conditions
+// are met:
+//
+// 1. Redistributions of source code must retain the above copyright
+//    notice, this list of conditions and the following disclaimer.
+//
+// 2. Redistributions in binary form must reproduce the above copyright
+//    notice, this list of conditions and the following disclaimer in the
+//    documentation and/or other materials provided with the distribution.
+//
+// 3. Neither the name of the copyright holder nor the names of its
+//    contributors may be used to endorse or promote products derived
+//    from this software without specific prior written permission.
+//
+// THIS SOFTWARE IS PROVIDED BY THE COPYRIGHT HOLDERS AND CONTRIBUTORS
+// AS IS" AND ANY EXPRESS OR IMPLIED WARRANTIES, INCLUDING, BUT NOT
+// LIMITED TO, THE IMPLIED WARRANTIES OF MERCHANTABILITY AND FITNESS
+// FOR A PARTICULAR PURPOSE ARE DISCLAIMED. IN NO EVENT SHALL THE
+// COPYRIGHT HOLDER OR CONTRIBUTORS BE LIABLE FOR ANY DIRECT, INDIRECT,
+// INCIDENTAL, SPECIAL, EXEMPLARY, OR CONSEQUENTIAL DAMAGES (INCLUDING,
+// BUT NOT LIMITED TO, PROCUREMENT OF SUBSTITUTE GOODS OR SERVICES; LOSS
+// OF USE, DATA, OR PROFITS; OR BUSINESS INTERRUPTION) HOWEVER CAUSED
+// AND ON ANY THEORY OF LIABILITY, WHETHER IN CONTRACT, STRICT LIABILITY,
+// OR TORT (INCLUDING NEGLIGENCE OR OTHERWISE) ARISING IN ANY WAY OUT OF
+// THE USE OF THIS SOFTWARE, EVEN IF ADVISED OF THE POSSIBILITY OF SUCH
+// DAMAGE.
+
+use std::cmp;
+use std::io;
+
+// The base lengths and extra bits of the length symbols 257 to 285
+const LENGTH_BASE: [u16; 29] = [3, 4, 5, 6, 7, 8, 9, 10, 11, 13, 15, 17, 19, 23, 27, 31, 35, 43, 51, 59, 67, 83, 99, 115, 131,
+                                163, 195, 227, 258];
+const LENGTH_EXTRA: [u8; 29] = [0, 0, 0, 0, 0, 0, 0, 0, 1, 1, 1, 1, 2, 2, 2, 2, 3, 3, 3, 3, 4, 4, 4, 4, 5, 5, 5, 5, 0];
+
+// The base distances and extra bits of the distance symbols
+const DISTANCE_BASE: [u16; 30] = [1, 2, 3, 4, 5, 7, 9, 13, 17, 25, 33, 49, 65, 97, 129, 193, 257, 385, 513, 769, 1025, 1537,
+                                  2049, 3073, 4097, 6145, 8193, 12289, 16385, 24577];
+const DISTANCE_EXTRA: [u8; 30] = [0, 0, 0, 0, 1, 1, 2, 2, 3, 3, 4, 4, 5, 5, 6, 6, 7, 7, 8, 8, 9, 9, 10, 10, 11, 11, 12, 12, 13,
+                                  13];
+
+// The order the code length code lengths of a dynamic block are stored in
+const CODE_LENGTH_ORDER: [usize; 19] = [16, 17, 18, 0, 8, 7, 9, 6, 10, 5, 11, 4, 12, 3, 13, 2, 14, 1, 15];
+
+const MAX_CODE_BITS: usize = 15;
+const WINDOW_SIZE: usize = 32768;
+const MIN_MATCH: usize = 3;
+const MAX_MATCH: usize = 258;
+const MAX_CHAIN: usize = 64; // How many earlier positions are tried for each match
+const HASH_BITS: usize = 15;
+
+fn invalid(message: &str) -> io::Error {
+    io::Error::new(io::ErrorKind::InvalidData, message.to_string())
+}
+
+/// A canonical Huffman code, decoded a bit at a time
+struct Huffman {
+    counts: [u16; MAX_CODE_BITS + 1], // The number of codes of each length
+    symbols: Vec<u16>, // The symbols in order of their codes
+}
+
+impl Huffman {
+    /// Build the code from the code length of each symbol, where zero leaves the symbol unused
+    ///
+    /// lengths: The code length of each symbol
+    fn new(lengths: &[u8]) -> Result<Huffman, io::Error> {
+        let mut counts = [0u16; MAX_CODE_BITS + 1];
+        for length in lengths.iter() {
+            counts[*length as usize] += 1;
+        }
+        counts[0] = 0;
+
+        // Incomplete codes are allowed, as a block may use only a single distance code
+        let mut left: i32 = 1;
+        for length in 1..MAX_CODE_BITS + 1 {
+            left = (left << 1) - counts[length] as i32;
+            if left < 0 {
+                return Err(invalid("Over-subscribed Huffman code"));
+            }
+        }
+
+        let mut offsets = [0u16; MAX_CODE_BITS + 1];
+        for length in 1..MAX_CODE_BITS {
+            offsets[length + 1] = offsets[length] + counts[length];
+        }
+        let mut symbols = vec![0u16; lengths.len()];
+        for (symbol, length) in lengths.iter().enumerate() {
+            if *length != 0 {
+                symbols[offsets[*length as usize] as usize] = symbol as u16;
+                offsets[*length as usize] += 1;
+            }
+        }
+
+        Ok(Huffman {
+            counts: counts,
+            symbols: symbols,
+        })
+    }
+
+    /// The fixed literal and length code
+    fn fixed_literals() -> Huffman {
+        let mut lengths = [0u8; 288];
+        for (symbol, length) in lengths.iter_mut().enumerate() {
+            *length = match symbol {
+                0..=143 => 8,
+                144..=255 => 9,
+                256..=279 => 7,
+                _ => 8,
+            };
+        }
+        Huffman::new(&lengths).unwrap()
+    }
+
+    /// The fixed distance code
+    fn fixed_distances() -> Huffman {
+        Huffman::new(&[5u8; 30]).unwrap()
+    }
+}
+
+/// Reads a DEFLATE stream, least significant bit first
+struct BitReader<'a> {
+    data: &'a [u8],
+    position: usize,
+    bits: u32,
+    bit_count: u32,
+}
+
+impl<'a> BitReader<'a> {
+    fn read_bits(&mut self, count: u32) -> Result<u32, io::Error> {
+        while self.bit_count < count {
+            if self.position >= self.data.len() {
+                return Err(io::Error::new(io::ErrorKind::UnexpectedEof, "The compressed data is truncated"));
+            }
+            self.bits |= (self.data[self.position] as u32) << self.bit_count;
+            self.position += 1;
+            self.bit_count += 8;
+        }
+
+        let value = self.bits & ((1u32 << count) - 1);
+        self.bits = if count < 32 { self.bits >> count } else { 0 };
+        self.bit_count -= count;
+
+        Ok(value)
+    }
+
+    fn decode(&mut self, huffman: &Huffman) -> Result<u16, io::Error> {
+        // Codes are stored most significant bit first, so are built up a bit at a time
+        let mut code: i32 = 0;
+        let mut first: i32 = 0;
+        let mut index: i32 = 0;
+        for length in 1..MAX_CODE_BITS + 1 {
+            code |= self.read_bits(1)? as i32;
+            let count = huffman.counts[length] as i32;
+            if code - first < count {
+                return Ok(huffman.symbols[(index + code - first) as usize]);
+            }
+            index += count;
+            first = (first + count) << 1;
+            code <<= 1;
+        }
+
+        Err(invalid("Invalid Huffman code"))
+    }
+}
+
+/// Decompress a raw DEFLATE stream, as described by RFC 1951
+///
+/// data: The compressed data, without a zlib or gzip header
+/// size_hint: The expected size of the decompressed data, used to reserve the output
+pub fn inflate(data: &[u8], size_hint: usize) -> Result<Vec<u8>, io::Error> {
+    let mut output: Vec<u8> = Vec::with_capacity(size_hint);
+    let mut reader = BitReader {
+        data: data,
+        position: 0,
+        bits: 0,
+        bit_count: 0,
+    };
+
+    loop {
+        let last = reader.read_bits(1)? == 1;
+        match reader.read_bits(2)? {
+            0 => {
+                // A stored block starts on a byte boundary
+                reader.bits = 0;
+                reader.bit_count = 0;
+                let position = reader.position;
+                if position + 4 > data.len() {
+                    return Err(io::Error::new(io::ErrorKind::UnexpectedEof, "The compressed data is truncated"));
+                }
+                let length = data[position] as usize | (data[position + 1] as usize) << 8;
+                let complement = data[position + 2] as usize | (data[position + 3] as usize) << 8;
+                if length != !complement & 0xffff {
+                    return Err(invalid("Stored block length does not match its complement"));
+                }
+                if position + 4 + length > data.len() {
+                    return Err(io::Error::new(io::ErrorKind::UnexpectedEof, "The compressed data is truncated"));
+                }
+                output.extend_from_slice(&data[position + 4..position + 4 + length]);
+                reader.position = position + 4 + length;
+            }
+            1 => inflate_block(&mut reader, &mut output, &Huffman::fixed_literals(), &Huffman::fixed_distances())?,
+            2 => {
+                let (literals, distances) = read_dynamic_codes(&mut reader)?;
+                inflate_block(&mut reader, &mut output, &literals, &distances)?;
+            }
+            _ => return Err(invalid("Invalid block type")),
+        }
+
+        if last {
+            return Ok(output);
+        }
+    }
+}
+
+/// Read the literal and length code and the distance code of a dynamic block
+fn read_dynamic_codes(reader: &mut BitReader) -> Result<(Huffman, Huffman), io::Error> {
+    let literal_count = reader.read_bits(5)? as usize + 257;
+    let distance_count = reader.read_bits(5)? as usize + 1;
+    let code_length_count = reader.read_bits(4)? as usize + 4;
+    if literal_count > 286 || distance_count > 30 {
+        return Err(invalid("Too many codes in dynamic block"));
+    }
+
+    let mut code_length_lengths = [0u8; 19];
+    for index in CODE_LENGTH_ORDER.iter().take(code_length_count) {
+        code_length_lengths[*index] = reader.read_bits(3)? as u8;
+    }
+    let code_lengths_code = Huffman::new(&code_length_lengths)?;
+
+    let total = literal_count + distance_count;
+    let mut lengths = vec![0u8; total];
+    let mut index = 0;
+    while index < total {
+        let symbol = reader.decode(&code_lengths_code)?;
+        if symbol < 16 {
+            lengths[index] = symbol as u8;
+            index += 1;
+            continue;
+        }
+
+        let (length, repeat) = match symbol {
+            16 => {
+                if index == 0 {
+                    return Err(invalid("Repeated code length with no previous length"));
+                }
+                (lengths[index - 1], 3 + reader.read_bits(2)? as usize)
+            }
+            17 => (0, 3 + reader.read_bits(3)? as usize),
+            _ => (0, 11 + reader.read_bits(7)? as usize),
+        };
+        if index + repeat > total {
+            return Err(invalid("Code lengths overrun the dynamic block's codes"));
+        }
+        for _ in 0..repeat {
+            lengths[index] = length;
+            index += 1;
+        }
+    }
+
+    if lengths[256] == 0 {
+        return Err(invalid("Dynamic block has no end of block code"));
+    }
+
+    Ok((Huffman::new(&lengths[..literal_count])?, Huffman::new(&lengths[literal_count..])?))
+}
+
+/// Decode the symbols of a Huffman coded block up to its end of block code
+fn inflate_block(reader: &mut BitReader,
+                 output: &mut Vec<u8>,
+                 literals: &Huffman,
+                 distances: &Huffman)
+                 -> Result<(), io::Error> {
+    loop {
+        let symbol = reader.decode(literals)? as usize;
+        if symbol < 256 {
+            output.push(symbol as u8);
+            continue;
+        }
+        if symbol == 256 {
+            return Ok(());
+        }
+
+        let length_symbol = symbol - 257;
+        if length_symbol >= LENGTH_BASE.len() {
+            return Err(invalid("Invalid length symbol"));
+        }
+        let length = LENGTH_BASE[length_symbol] as usize + reader.read_bits(LENGTH_EXTRA[length_symbol] as u32)? as usize;
+
+        let distance_symbol = reader.decode(distances)? as usize;
+        if distance_symbol >= DISTANCE_BASE.len() {
+            return Err(invalid("Invalid distance symbol"));
+        }
+        let distance = DISTANCE_BASE[distance_symbol] as usize +
+                       reader.read_bits(DISTANCE_EXTRA[distance_symbol] as u32)? as usize;
+        if distance > output.len() {
+            return Err(invalid("Distance reaches back before the start of the data"));
+        }
+
+        // The copy may overlap the bytes it produces
+        let start = output.len() - distance;
+        for offset in 0..length {
+            let byte = output[start + offset];
+            output.push(byte);
+        }
+    }
+}
+
+/// Writes a DEFLATE stream, least significant bit first
+struct BitWriter {
+    output: Vec<u8>,
+    bits: u32,
+    bit_count: u32,
+}
+
+impl BitWriter {
+    fn write_bits(&mut self, value: u32, count: u32) {
+        self.bits |= value << self.bit_count;
+        self.bit_count += count;
+        while self.bit_count >= 8 {
+            self.output.push(self.bits as u8);
+            self.bits >>= 8;
+            self.bit_count -= 8;
+        }
+    }
+
+    /// Write a Huffman code, which is stored most significant bit first
+    fn write_code(&mut self, code: u32, length: u32) {
+        let mut reversed = 0;
+        for bit in 0..length {
+            reversed |= ((code >> bit) & 1) << (length - 1 - bit);
+        }
+        self.write_bits(reversed, length);
+    }
+
+    /// Write a literal or length symbol with the fixed code
+    fn write_fixed_literal(&mut self, symbol: u32) {
+        match symbol {
+            0..=143 => self.write_code(0x30 + symbol, 8),
+            144..=255 => self.write_code(0x190 + symbol - 144, 9),
+            256..=279 => self.write_code(symbol - 256, 7),
+            _ => self.write_code(0xc0 + symbol - 280, 8),
+        }
+    }
+
+    fn write_match(&mut self, length: usize, distance: usize) {
+        let length_symbol = LENGTH_BASE.iter().rposition(|base| *base as usize <= length).unwrap();
+        self.write_fixed_literal(257 + length_symbol as u32);
+        self.write_bits((length - LENGTH_BASE[length_symbol] as usize) as u32,
+                        LENGTH_EXTRA[length_symbol] as u32);
+
+        let distance_symbol = DISTANCE_BASE.iter().rposition(|base| *base as usize <= distance).unwrap();
+        self.write_code(distance_symbol as u32, 5);
+        self.write_bits((distance - DISTANCE_BASE[distance_symbol] as usize) as u32,
+                        DISTANCE_EXTRA[distance_symbol] as u32);
+    }
+
+    fn finish(mut self) -> Vec<u8> {
+        if self.bit_count > 0 {
+            self.output.push(self.bits as u8);
+        }
+        self.output
+    }
+}
+
+/// Hash the three bytes starting at a position, for finding earlier matches
+fn hash(data: &[u8], position: usize) -> usize {
+    ((data[position] as usize) << 10 ^ (data[position + 1] as usize) << 5 ^ data[position + 2] as usize) & ((1 << HASH_BITS) - 1)
+}
+
+/// Add a position to the head of its hash chain
+fn insert_hash(data: &[u8], position: usize, head: &mut Vec<usize>, previous: &mut Vec<usize>) {
+    if position + MIN_MATCH <= data.len() {
+        let h = hash(data, position);
+        previous[position] = head[h];
+        head[h] = position;
+    }
+}
+
+/// Compress data to a raw DEFLATE stream
+///
+/// This finds matches through hash chains and writes a single block with the fixed codes, which
+/// favours a simple, fast compressor over the best ratio.  The output can be read by any
+/// DEFLATE decoder.
+///
+/// data: The data to compress
+pub fn deflate(data: &[u8]) -> Vec<u8> {
+    let mut writer = BitWriter {
+        output: Vec::with_capacity(data.len() / 2),
+        bits: 0,
+        bit_count: 0,
+    };
+    writer.write_bits(1, 1); // The last block
+    writer.write_bits(1, 2); // Fixed codes
+
+    let none = usize::max_value();
+    let mut head = vec![none; 1 << HASH_BITS];
+    let mut previous = vec![none; data.len()];
+
+    let mut position = 0;
+    while position < data.len() {
+        let mut best_length = 0;
+        let mut best_distance = 0;
+        if position + MIN_MATCH <= data.len() {
+            let longest = cmp::min(MAX_MATCH, data.len() - position);
+            let mut candidate = head[hash(data, position)];
+            let mut chain = 0;
+            while candidate != none && position - candidate <= WINDOW_SIZE && chain < MAX_CHAIN {
+                let mut length = 0;
+                while length < longest && data[candidate + length] == data[position + length] {
+                    length += 1;
+                }
+                if length > best_length {
+                    best_length = length;
+                    best_distance = position - candidate;
+                    if length == longest {
+                        break;
+                    }
+                }
+                candidate = previous[candidate];
+                chain += 1;
+            }
+        }
+
+        if best_length >= MIN_MATCH {
+            writer.write_match(best_length, best_distance);
+            for offset in 0..best_length {
+                insert_hash(data, position + offset, &mut head, &mut previous);
+            }
+            position += best_length;
+        } else {
+            writer.write_fixed_literal(data[position] as u32);
+            insert_hash(data, position, &mut head, &mut previous);
+            position += 1;
+        }
+    }
+
+    writer.write_fixed_literal(256);
+    writer.finish()
+}
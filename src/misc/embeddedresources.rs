@@ -28,14 +28,37 @@
 // THE USE OF THIS SOFTWARE, EVEN IF ADVISED OF THE POSSIBILITY OF SUCH
 // DAMAGE.
 
+use std::borrow::Cow;
 use std::collections::HashMap;
+use std::io;
+use std::str;
+use std::sync::Mutex;
+
+use misc::deflate::*;
+use misc::fileutils::*;
+
+/// The first word of a resource archive, "WYRA"
+pub const RESOURCE_ARCHIVE_MAGIC: u32 = 0x41525957;
+
+// The flags of a resource in an archive
+const RESOURCE_DEFLATED: u32 = 1;
 
 pub trait EmbeddedResources {
     /// Indicates whether there are embedded resources or not
     fn use_me(&self) -> bool;
 
-    /// Accessor for the embedded resources
+    /// Accessor for the embedded resources held uncompressed
     fn resources(&self) -> &HashMap<&'static str, &'static str>;
+
+    /// Return the contents of an embedded resource
+    ///
+    /// By default this looks the resource up in the uncompressed resources.  Implementations
+    /// holding compressed resources decompress them here, on first access.
+    ///
+    /// name: The name of the resource
+    fn resource(&self, name: &str) -> Option<Cow<'static, [u8]>> {
+        self.resources().get(name).map(|r| Cow::Borrowed(r.as_bytes()))
+    }
 }
 
 // The following is an "empty" implementation of the trait for applications
@@ -66,3 +89,176 @@ impl EmbeddedResources for NoEmbedded {
         &self.resources
     }
 }
+
+// A resource in an archive, which is decompressed when first accessed
+struct ArchiveEntry {
+    data: &'static [u8],
+    size: usize, // The size once decompressed
+    deflated: bool,
+}
+
+/// Embedded resources held in a single archive, each compressed with DEFLATE
+///
+/// The archive starts with an index of the resources, so only its index is read up front and
+/// each resource is decompressed the first time it is accessed.  Decompressed resources are kept
+/// for the life of the program, as the embedded data itself is.  Archives are written by
+/// build_resource_archive, for the application to embed with include_bytes!.
+pub struct CompressedResources {
+    resources: HashMap<&'static str, &'static str>, // Empty, as every resource is in the archive
+    index: HashMap<&'static str, ArchiveEntry>,
+    decompressed: Mutex<HashMap<&'static str, &'static [u8]>>,
+}
+
+impl CompressedResources {
+    /// Read the index of an archive, leaving its resources compressed
+    ///
+    /// archive: The archive, as embedded in the binary
+    pub fn new(archive: &'static [u8]) -> Result<CompressedResources, io::Error> {
+        let invalid = |message: &str| io::Error::new(io::ErrorKind::InvalidData, message.to_string());
+
+        let mut offset = 0;
+        if read_u32(archive, &mut offset)? != RESOURCE_ARCHIVE_MAGIC {
+            return Err(invalid("Not a resource archive"));
+        }
+
+        let count = read_u32(archive, &mut offset)?;
+        let mut index = HashMap::new();
+        for _ in 0..count {
+            let name_length = read_u32(archive, &mut offset)? as usize;
+            if offset + name_length > archive.len() {
+                return Err(io::Error::new(io::ErrorKind::UnexpectedEof, "Truncated resource archive"));
+            }
+            let name = match str::from_utf8(&archive[offset..offset + name_length]) {
+                Ok(n) => n,
+                Err(_) => return Err(invalid("Resource name is not UTF-8")),
+            };
+            offset += name_length;
+
+            let flags = read_u32(archive, &mut offset)?;
+            let data_offset = read_u32(archive, &mut offset)? as usize;
+            let data_length = read_u32(archive, &mut offset)? as usize;
+            let size = read_u32(archive, &mut offset)? as usize;
+            if data_offset + data_length > archive.len() {
+                return Err(io::Error::new(io::ErrorKind::UnexpectedEof, "Truncated resource archive"));
+            }
+
+            index.insert(name,
+                         ArchiveEntry {
+                             data: &archive[data_offset..data_offset + data_length],
+                             size: size,
+                             deflated: flags & RESOURCE_DEFLATED != 0,
+                         });
+        }
+
+        Ok(CompressedResources {
+            resources: HashMap::new(),
+            index: index,
+            decompressed: Mutex::new(HashMap::new()),
+        })
+    }
+
+    /// Return the names of the resources in the archive
+    pub fn names(&self) -> Vec<&'static str> {
+        let mut names: Vec<&'static str> = self.index.keys().cloned().collect();
+        names.sort();
+        names
+    }
+
+    /// Return whether a resource has been decompressed yet
+    ///
+    /// name: The name of the resource
+    pub fn is_decompressed(&self, name: &str) -> bool {
+        self.decompressed.lock().unwrap().contains_key(name)
+    }
+}
+
+impl EmbeddedResources for CompressedResources {
+    fn use_me(&self) -> bool {
+        true
+    }
+
+    fn resources(&self) -> &HashMap<&'static str, &'static str> {
+        &self.resources
+    }
+
+    fn resource(&self, name: &str) -> Option<Cow<'static, [u8]>> {
+        let (&name, entry) = match self.index.get_key_value(name) {
+            Some(e) => e,
+            None => return None,
+        };
+        if !entry.deflated {
+            return Some(Cow::Borrowed(entry.data));
+        }
+
+        let mut decompressed = self.decompressed.lock().unwrap();
+        if let Some(data) = decompressed.get(name) {
+            return Some(Cow::Borrowed(*data));
+        }
+
+        let data = match inflate(entry.data, entry.size) {
+            Ok(d) => d,
+            Err(e) => panic!("Failed to decompress embedded resource {}: {}", name, e),
+        };
+        if data.len() != entry.size {
+            panic!("Embedded resource {} decompressed to {} bytes instead of {}",
+                   name,
+                   data.len(),
+                   entry.size);
+        }
+        let data: &'static [u8] = Box::leak(data.into_boxed_slice());
+        decompressed.insert(name, data);
+
+        Some(Cow::Borrowed(data))
+    }
+}
+
+/// Build a resource archive, compressing each resource that gets smaller for it
+///
+/// resources: The name and contents of each resource
+pub fn build_resource_archive(resources: &[(&str, &[u8])]) -> Vec<u8> {
+    let stored: Vec<(u32, Vec<u8>)> = resources.iter()
+        .map(|&(_, contents)| {
+            let compressed = deflate(contents);
+            if compressed.len() < contents.len() {
+                (RESOURCE_DEFLATED, compressed)
+            } else {
+                (0, contents.to_vec())
+            }
+        })
+        .collect();
+
+    let index_size = 8 + resources.iter().map(|&(name, _)| 20 + name.len()).sum::<usize>();
+
+    let mut archive = vec![];
+    push_u32(&mut archive, RESOURCE_ARCHIVE_MAGIC);
+    push_u32(&mut archive, resources.len() as u32);
+    let mut data_offset = index_size;
+    for (&(name, contents), &(flags, ref data)) in resources.iter().zip(stored.iter()) {
+        push_u32(&mut archive, name.len() as u32);
+        archive.extend_from_slice(name.as_bytes());
+        push_u32(&mut archive, flags);
+        push_u32(&mut archive, data_offset as u32);
+        push_u32(&mut archive, data.len() as u32);
+        push_u32(&mut archive, contents.len() as u32);
+        data_offset += data.len();
+    }
+    for &(_, ref data) in stored.iter() {
+        archive.extend_from_slice(data);
+    }
+
+    archive
+}
+
+/// Write a resource archive of files on disk, named by their paths
+///
+/// filenames: The files to archive
+/// archive_filename: The name of the archive to write
+pub fn write_resource_archive(filenames: &Vec<&str>, archive_filename: &str) -> Result<(), io::Error> {
+    let mut contents = vec![];
+    for filename in filenames.iter() {
+        contents.push(read_binary_file(filename, false /* dump */)?);
+    }
+    let resources: Vec<(&str, &[u8])> = filenames.iter().zip(contents.iter()).map(|(f, c)| (*f, c.as_slice())).collect();
+
+    write_binary_file(&build_resource_archive(&resources), archive_filename)
+}
@@ -56,7 +56,12 @@ pub fn read_text_file(embedded: Option<&EmbeddedResources>, filename: &str) -> S
     if use_embedded {
         // Use embedded resources
         match embedded {
-            Some(ref embedded) => contents = embedded.resources()[filename].to_string(),
+            Some(ref embedded) => {
+                match embedded.resource(filename) {
+                    Some(bytes) => contents = String::from_utf8_lossy(&bytes).into_owned(),
+                    None => panic!("No embedded resource {}", filename),
+                }
+            }
             None => (),
         };
     } else {
@@ -235,6 +240,44 @@ pub fn read_binary_file(filename: &str, dump: bool) -> Result<Vec<u8>, io::Error
     Ok(bytecode)
 }
 
+/// Write the specified bytes to a new file
+///
+/// bytes: What to write
+/// filename: Where
+pub fn write_binary_file(bytes: &Vec<u8>, filename: &str) -> Result<(), io::Error> {
+    let mut output_file = File::create(filename)?;
+    output_file.write_all(bytes)?;
+
+    Ok(())
+}
+
+/// Append the little-endian encoding of a value to a byte vector
+///
+/// bytes: The bytes to append to
+/// value: The value to encode
+pub fn push_u32(bytes: &mut Vec<u8>, value: u32) {
+    for i in 0..4 {
+        bytes.push((value >> (i * 8)) as u8);
+    }
+}
+
+/// Read a little-endian value from a byte vector, advancing the offset past it
+///
+/// bytes: The bytes to read from
+/// offset: The offset of the value, updated to the offset just past it
+pub fn read_u32(bytes: &[u8], offset: &mut usize) -> Result<u32, io::Error> {
+    if *offset + 4 > bytes.len() {
+        return Err(io::Error::new(io::ErrorKind::UnexpectedEof, "Truncated binary data"));
+    }
+
+    let mut value = 0u32;
+    for i in 0..4 {
+        value |= (bytes[*offset + i] as u32) << (i * 8);
+    }
+    *offset += 4;
+    Ok(value)
+}
+
 /// Write the specified contents to a new file
 ///
 /// contents: What to write
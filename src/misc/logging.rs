@@ -0,0 +1,165 @@
+// Copyright (c) 2016-2017 Bruce Stenning. All rights reserved.
+//
+// Redistribution and use in source and binary forms, with or without
+// modification, are permitted provided that the following conditions
+// are met:
+//
+// 1. Redistributions of source code must retain the above copyright
+//    notice, this list of conditions and the following disclaimer.
+//
+// 2. Redistributions in binary form must reproduce the above copyright
+//    notice, this list of conditions and the following disclaimer in the
+//    documentation and/or other materials provided with the distribution.
+//
+// 3. Neither the name of the copyright holder nor the names of its
+//    contributors may be used to endorse or promote products derived
+//    from this software without specific prior written permission.
+//
+// THIS SOFTWARE IS PROVIDED BY THE COPYRIGHT HOLDERS AND CONTRIBUTORS
+// AS IS" AND ANY EXPRESS OR IMPLIED WARRANTIES, INCLUDING, BUT NOT
+// LIMITED TO, THE IMPLIED WARRANTIES OF MERCHANTABILITY AND FITNESS
+// FOR A PARTICULAR PURPOSE ARE DISCLAIMED. IN NO EVENT SHALL THE
+// COPYRIGHT HOLDER OR CONTRIBUTORS BE LIABLE FOR ANY DIRECT, INDIRECT,
+// INCIDENTAL, SPECIAL, EXEMPLARY, OR CONSEQUENTIAL DAMAGES (INCLUDING,
+// BUT NOT LIMITED TO, PROCUREMENT OF SUBSTITUTE GOODS OR SERVICES; LOSS
+// OF USE, DATA, OR PROFITS; OR BUSINESS INTERRUPTION) HOWEVER CAUSED
+// AND ON ANY THEORY OF LIABILITY, WHETHER IN CONTRACT, STRICT LIABILITY,
+// OR TORT (INCLUDING NEGLIGENCE OR OTHERWISE) ARISING IN ANY WAY OUT OF
+// THE USE OF THIS SOFTWARE, EVEN IF ADVISED OF THE POSSIBILITY OF SUCH
+// DAMAGE.
+
+use std::fmt;
+use std::sync::{Mutex, Once};
+use std::sync::atomic::{AtomicUsize, Ordering};
+
+/// Log a message at error level, with the arguments of format!
+#[macro_export]
+macro_rules! log_error(
+    ($($arg:tt)*) => {
+        $crate::misc::logging::log($crate::misc::logging::LogLevel::Error, format_args!($($arg)*))
+    }
+);
+
+/// Log a message at warning level, with the arguments of format!
+#[macro_export]
+macro_rules! log_warning(
+    ($($arg:tt)*) => {
+        $crate::misc::logging::log($crate::misc::logging::LogLevel::Warning, format_args!($($arg)*))
+    }
+);
+
+/// Log a message at info level, with the arguments of format!
+#[macro_export]
+macro_rules! log_info(
+    ($($arg:tt)*) => {
+        $crate::misc::logging::log($crate::misc::logging::LogLevel::Info, format_args!($($arg)*))
+    }
+);
+
+/// Log a message at debug level, with the arguments of format!
+#[macro_export]
+macro_rules! log_debug(
+    ($($arg:tt)*) => {
+        $crate::misc::logging::log($crate::misc::logging::LogLevel::Debug, format_args!($($arg)*))
+    }
+);
+
+/// The importance of a logged message, from the most to the least important
+#[derive(Clone, Copy, Debug, PartialEq, Eq, PartialOrd, Ord)]
+pub enum LogLevel {
+    Error = 0,
+    Warning = 1,
+    Info = 2,
+    Debug = 3,
+}
+
+impl LogLevel {
+    /// Return the least important level logged at a renderer debug level
+    ///
+    /// Warnings and errors are always logged, info from debug level 1 and debug from level 2.
+    ///
+    /// debug_level: The debug level of the renderer
+    pub fn from_debug_level(debug_level: u32) -> LogLevel {
+        match debug_level {
+            0 => LogLevel::Warning,
+            1 => LogLevel::Info,
+            _ => LogLevel::Debug,
+        }
+    }
+
+    fn from_usize(level: usize) -> LogLevel {
+        match level {
+            0 => LogLevel::Error,
+            1 => LogLevel::Warning,
+            2 => LogLevel::Info,
+            _ => LogLevel::Debug,
+        }
+    }
+}
+
+/// A function logged messages are passed to, such as one writing to a file or in-game console
+pub type LogSink = Box<Fn(LogLevel, &str) + Send>;
+
+static LOG_LEVEL: AtomicUsize = AtomicUsize::new(LogLevel::Warning as usize);
+static SINK_INIT: Once = Once::new();
+static mut SINK: *const Mutex<Option<LogSink>> = 0 as *const Mutex<Option<LogSink>>;
+
+/// Return the process-wide sink, which messages from any thread are passed to
+fn sink() -> &'static Mutex<Option<LogSink>> {
+    unsafe {
+        SINK_INIT.call_once(|| {
+            SINK = Box::into_raw(Box::new(Mutex::new(None)));
+        });
+        &*SINK
+    }
+}
+
+/// Set the least important level of message logged
+///
+/// Renderers set this from their debug level as they are created, so to override it, set it
+/// after creating the renderer.
+///
+/// level: The level
+pub fn set_log_level(level: LogLevel) {
+    LOG_LEVEL.store(level as usize, Ordering::SeqCst);
+}
+
+/// Return the least important level of message logged
+pub fn log_level() -> LogLevel {
+    LogLevel::from_usize(LOG_LEVEL.load(Ordering::Relaxed))
+}
+
+/// Return whether messages of a level are logged, for guarding work done only to log a message
+///
+/// level: The level
+pub fn log_enabled(level: LogLevel) -> bool {
+    level <= log_level()
+}
+
+/// Set the function logged messages are passed to, replacing any previous one
+///
+/// A sink that ignores its messages suppresses the output altogether.
+///
+/// log_sink: The sink, or None to print messages to stdout
+pub fn set_log_sink(log_sink: Option<LogSink>) {
+    *sink().lock().unwrap() = log_sink;
+}
+
+/// Log a message, if its level is logged
+///
+/// This costs a single atomic load for a level that is not logged.  The macros log_error!,
+/// log_warning!, log_info! and log_debug! call this.
+///
+/// level: The level of the message
+/// args: The message
+pub fn log(level: LogLevel, args: fmt::Arguments) {
+    if !log_enabled(level) {
+        return;
+    }
+
+    let message = fmt::format(args);
+    match *sink().lock().unwrap() {
+        Some(ref log_sink) => log_sink(level, &message),
+        None => println!("{}", message),
+    }
+}
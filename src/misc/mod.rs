@@ -29,6 +29,9 @@
 // DAMAGE.
 
 pub mod conversions;
+pub mod deflate;
 pub mod embeddedresources;
 pub mod fileutils;
+#[macro_use]
+pub mod logging;
 pub mod timer;
@@ -0,0 +1,86 @@
+// Copyright (c) 2016-2017 Bruce Stenning. All rights reserved.
+//
+// Redistribution and use in source and binary forms, with or without
+// modification, are permitted provided that the following conditions
+// are met:
+//
+// 1. Redistributions of source code must retain the above copyright
+//    notice, this list of conditions and the following disclaimer.
+//
+// 2. Redistributions in binary form must reproduce the above copyright
+//    notice, this list of conditions and the following disclaimer in the
+//    documentation and/or other materials provided with the distribution.
+//
+// 3. Neither the name of the copyright holder nor the names of its
+//    contributors may be used to endorse or promote products derived
+//    from this software without specific prior written permission.
+//
+// THIS SOFTWARE IS PROVIDED BY THE COPYRIGHT HOLDERS AND CONTRIBUTORS
+// AS IS" AND ANY EXPRESS OR IMPLIED WARRANTIES, INCLUDING, BUT NOT
+// LIMITED TO, THE IMPLIED WARRANTIES OF MERCHANTABILITY AND FITNESS
+// FOR A PARTICULAR PURPOSE ARE DISCLAIMED. IN NO EVENT SHALL THE
+// COPYRIGHT HOLDER OR CONTRIBUTORS BE LIABLE FOR ANY DIRECT, INDIRECT,
+// INCIDENTAL, SPECIAL, EXEMPLARY, OR CONSEQUENTIAL DAMAGES (INCLUDING,
+// BUT NOT LIMITED TO, PROCUREMENT OF SUBSTITUTE GOODS OR SERVICES; LOSS
+// OF USE, DATA, OR PROFITS; OR BUSINESS INTERRUPTION) HOWEVER CAUSED
+// AND ON ANY THEORY OF LIABILITY, WHETHER IN CONTRACT, STRICT LIABILITY,
+// OR TORT (INCLUDING NEGLIGENCE OR OTHERWISE) ARISING IN ANY WAY OUT OF
+// THE USE OF THIS SOFTWARE, EVEN IF ADVISED OF THE POSSIBILITY OF SUCH
+// DAMAGE.
+
+#![allow(unused_imports)]
+
+use graphics::atlas::*;
+
+#[cfg(test)]
+fn solid_image(width: u32, height: u32, value: u8) -> Vec<u8> {
+    vec![value; (width * height * 4) as usize]
+}
+
+#[test]
+fn atlas_packed_images_do_not_overlap_and_stay_on_the_page() {
+    let mut atlas = TextureAtlas::new(64, 1);
+    let mut rects = vec![];
+    for i in 0..20 {
+        let (w, h) = (3 + (i * 7) % 11, 2 + (i * 5) % 9);
+        let rect = atlas.add_image(&format!("image{}", i), w, h, &solid_image(w, h, i as u8)).unwrap();
+        assert!(rect.x >= 1 && rect.x + rect.width + 1 <= 64);
+        assert!(rect.y >= 1 && rect.y + rect.height + 1 <= 64);
+        rects.push(rect);
+    }
+
+    for a in 0..rects.len() {
+        for b in (a + 1)..rects.len() {
+            assert!(!rects[a].overlaps(&rects[b]), "{:?} overlaps {:?}", rects[a], rects[b]);
+        }
+    }
+}
+
+#[test]
+fn atlas_adds_pages_incrementally_without_moving_earlier_images() {
+    let mut atlas = TextureAtlas::new(32, 0);
+    let first = atlas.add_image("first", 32, 20, &solid_image(32, 20, 1)).unwrap();
+    assert_eq!(atlas.page_count(), 1);
+    assert_eq!(atlas.dirty_pages(), vec![0]);
+
+    let second = atlas.add_image("second", 16, 16, &solid_image(16, 16, 2)).unwrap();
+    assert_eq!(second.page, 1);
+    assert_eq!(atlas.get("first"), Some(&first));
+    assert!(atlas.add_image("too_big", 33, 1, &solid_image(33, 1, 3)).is_none());
+
+    let uv = atlas.get_uv("second").unwrap();
+    assert_eq!(uv, [0.0f32, 0.0f32, 0.5f32, 0.5f32]);
+}
+
+#[test]
+fn atlas_gutter_repeats_edge_texels() {
+    let mut atlas = TextureAtlas::new(8, 1);
+    let rect = atlas.add_image("a", 2, 1, &vec![10, 10, 10, 10, 20, 20, 20, 20]).unwrap();
+    let data = atlas.get_page_data(rect.page);
+    let texel = |x: u32, y: u32| data[((y * 8 + x) * 4) as usize];
+
+    assert_eq!(texel(rect.x - 1, rect.y), 10);
+    assert_eq!(texel(rect.x + 2, rect.y), 20);
+    assert_eq!(texel(rect.x - 1, rect.y - 1), 10);
+    assert_eq!(texel(rect.x + 2, rect.y + 1), 20);
+}
@@ -0,0 +1,90 @@
+// Copyright (c) 2016-2017 Bruce Stenning. All rights reserved.
+//
+// Redistribution and use in source and binary forms, with or without
+// modification, are permitted provided that the following conditions
+// are met:
+//
+// 1. Redistributions of source code must retain the above copyright
+//    notice, this list of conditions and the following disclaimer.
+//
+// 2. Redistributions in binary form must reproduce the above copyright
+//    notice, this list of conditions and the following disclaimer in the
+//    documentation and/or other materials provided with the distribution.
+//
+// 3. Neither the name of the copyright holder nor the names of its
+//    contributors may be used to endorse or promote products derived
+//    from this software without specific prior written permission.
+//
+// THIS SOFTWARE IS PROVIDED BY THE COPYRIGHT HOLDERS AND CONTRIBUTORS
+// AS IS" AND ANY EXPRESS OR IMPLIED WARRANTIES, INCLUDING, BUT NOT
+// LIMITED TO, THE IMPLIED WARRANTIES OF MERCHANTABILITY AND FITNESS
+// FOR A PARTICULAR PURPOSE ARE DISCLAIMED. IN NO EVENT SHALL THE
+// COPYRIGHT HOLDER OR CONTRIBUTORS BE LIABLE FOR ANY DIRECT, INDIRECT,
+// INCIDENTAL, SPECIAL, EXEMPLARY, OR CONSEQUENTIAL DAMAGES (INCLUDING,
+// BUT NOT LIMITED TO, PROCUREMENT OF SUBSTITUTE GOODS OR SERVICES; LOSS
+// OF USE, DATA, OR PROFITS; OR BUSINESS INTERRUPTION) HOWEVER CAUSED
+// AND ON ANY THEORY OF LIABILITY, WHETHER IN CONTRACT, STRICT LIABILITY,
+// OR TORT (INCLUDING NEGLIGENCE OR OTHERWISE) ARISING IN ANY WAY OUT OF
+// THE USE OF THIS SOFTWARE, EVEN IF ADVISED OF THE POSSIBILITY OF SUCH
+// DAMAGE.
+
+#![allow(unused_imports)]
+
+use graphics::bandwidth::*;
+
+#[test]
+fn pass_bandwidth_counts_attachments_and_textures() {
+    let target = PassTargetDesc {
+        width: 100,
+        height: 10,
+        samples: 1,
+        colour_bytes_per_sample: 4,
+        depth_bytes_per_sample: 0,
+        loads_colour: false,
+        resolves: false,
+    };
+    let pass = PassBandwidth::estimate("final", &target, 2);
+    assert_eq!(pass.attachment_read_bytes, 0);
+    assert_eq!(pass.attachment_write_bytes, 4000);
+    assert_eq!(pass.texture_read_bytes, 2 * 1000 * ASSUMED_TEXEL_BYTES);
+    assert_eq!(pass.gigabytes_per_second(), None);
+
+    // Four samples with depth, loading and resolving the colour
+    let target = PassTargetDesc {
+        samples: 4,
+        colour_bytes_per_sample: 16,
+        depth_bytes_per_sample: 4,
+        loads_colour: true,
+        resolves: true,
+        ..target
+    };
+    let pass = PassBandwidth::estimate("scene", &target, 0);
+    assert_eq!(pass.attachment_read_bytes, 16000 + 64000 + 64000);
+    assert_eq!(pass.attachment_write_bytes, 64000 + 16000 + 16000);
+}
+
+#[test]
+fn bandwidth_stats_report_the_last_completed_frame() {
+    let target = PassTargetDesc {
+        width: 10,
+        height: 10,
+        samples: 1,
+        colour_bytes_per_sample: 4,
+        depth_bytes_per_sample: 0,
+        loads_colour: false,
+        resolves: false,
+    };
+    let mut stats = BandwidthStats::new();
+    stats.record_pass(PassBandwidth::estimate("first", &target, 0));
+    assert!(stats.last_frame().is_empty());
+
+    stats.end_frame();
+    stats.record_pass(PassBandwidth::estimate("second", &target, 0));
+    assert_eq!(stats.last_frame().len(), 1);
+    assert_eq!(stats.last_frame()[0].shader_name, "first");
+    assert_eq!(stats.last_frame_total_bytes(), 400);
+
+    stats.end_frame();
+    assert_eq!(stats.last_frame().len(), 1);
+    assert_eq!(stats.last_frame()[0].shader_name, "second");
+}
@@ -0,0 +1,88 @@
+// Copyright (c) 2016-2017 Bruce Stenning. All rights reserved.
+//
+// Redistribution and use in source and binary forms, with or without
+// modification, are permitted provided that the following conditions
+// are met:
+//
+// 1. Redistributions of source code must retain the above copyright
+//    notice, this list of conditions and the following disclaimer.
+//
+// 2. Redistributions in binary form must reproduce the above copyright
+//    notice, this list of conditions and the following disclaimer in the
+//    documentation and/or other materials provided with the distribution.
+//
+// 3. Neither the name of the copyright holder nor the names of its
+//    contributors may be used to endorse or promote products derived
+//    from this software without specific prior written permission.
+//
+// THIS SOFTWARE IS PROVIDED BY THE COPYRIGHT HOLDERS AND CONTRIBUTORS
+// AS IS" AND ANY EXPRESS OR IMPLIED WARRANTIES, INCLUDING, BUT NOT
+// LIMITED TO, THE IMPLIED WARRANTIES OF MERCHANTABILITY AND FITNESS
+// FOR A PARTICULAR PURPOSE ARE DISCLAIMED. IN NO EVENT SHALL THE
+// COPYRIGHT HOLDER OR CONTRIBUTORS BE LIABLE FOR ANY DIRECT, INDIRECT,
+// INCIDENTAL, SPECIAL, EXEMPLARY, OR CONSEQUENTIAL DAMAGES (INCLUDING,
+// BUT NOT LIMITED TO, PROCUREMENT OF SUBSTITUTE GOODS OR SERVICES; LOSS
+// OF USE, DATA, OR PROFITS; OR BUSINESS INTERRUPTION) HOWEVER CAUSED
+// AND ON ANY THEORY OF LIABILITY, WHETHER IN CONTRACT, STRICT LIABILITY,
+// OR TORT (INCLUDING NEGLIGENCE OR OTHERWISE) ARISING IN ANY WAY OUT OF
+// THE USE OF THIS SOFTWARE, EVEN IF ADVISED OF THE POSSIBILITY OF SUCH
+// DAMAGE.
+#![allow(unused_imports)]
+
+use graphics::bindless::*;
+
+#[test]
+fn released_slots_are_reused_lowest_first() {
+    let mut slots = BindlessSlots::new(4, 0);
+    assert_eq!(slots.allocate(), Some(0));
+    assert_eq!(slots.allocate(), Some(1));
+    assert_eq!(slots.allocate(), Some(2));
+    assert_eq!(slots.allocate(), Some(3));
+    assert_eq!(slots.allocate(), None);
+
+    slots.release(2);
+    slots.release(1);
+    assert_eq!(slots.in_use(), 2);
+    assert_eq!(slots.allocate(), Some(1));
+    assert_eq!(slots.allocate(), Some(2));
+    assert_eq!(slots.capacity(), 4);
+}
+
+#[test]
+fn released_slots_wait_for_frames_in_flight() {
+    let mut slots = BindlessSlots::new(2, 2);
+    assert_eq!(slots.allocate(), Some(0));
+    assert_eq!(slots.allocate(), Some(1));
+
+    // A frame still in flight may be sampling the texture through its old index
+    slots.release(0);
+    assert_eq!(slots.allocate(), None);
+    slots.advance_frame();
+    assert_eq!(slots.allocate(), None);
+    slots.advance_frame();
+    assert_eq!(slots.allocate(), Some(0));
+}
+
+#[test]
+fn material_table_packs_records_in_order() {
+    let mut table = MaterialTable::new();
+    assert!(table.is_empty());
+
+    let mut record = MaterialRecord::default();
+    record.albedo_texture = 7;
+    record.roughness = 0.25;
+    assert_eq!(table.add(MaterialRecord::default()), Some(0));
+    assert_eq!(table.add(record), Some(1));
+    assert_eq!(table.get(1).albedo_texture, 7);
+
+    let packed = table.packed();
+    assert_eq!(packed.len(), 2 * MATERIAL_RECORD_FLOATS);
+    assert_eq!(packed[0].to_bits(), BINDLESS_NO_TEXTURE);
+    assert_eq!(packed[MATERIAL_RECORD_FLOATS].to_bits(), 7);
+    assert_eq!(packed[MATERIAL_RECORD_FLOATS + 9], 0.25);
+
+    while table.len() < MATERIAL_MAX_RECORDS {
+        table.add(MaterialRecord::default());
+    }
+    assert_eq!(table.add(record), None);
+}
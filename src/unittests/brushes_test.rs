@@ -0,0 +1,76 @@
+// Copyright (c) 2016-2017 Bruce Stenning. All rights reserved.
+//
+// Redistribution and use in source and binary forms, with or without
+// modification, are permitted provided that the following conditions
+// are met:
+//
+// 1. Redistributions of source code must retain the above copyright
+//    notice, this list of conditions and the following disclaimer.
+//
+// 2. Redistributions in binary form must reproduce the above copyright
+//    notice, this list of conditions and the following disclaimer in the
+//    documentation and/or other materials provided with the distribution.
+//
+// 3. Neither the name of the copyright holder nor the names of its
+//    contributors may be used to endorse or promote products derived
+//    from this software without specific prior written permission.
+//
+// THIS SOFTWARE IS PROVIDED BY THE COPYRIGHT HOLDERS AND CONTRIBUTORS
+// AS IS" AND ANY EXPRESS OR IMPLIED WARRANTIES, INCLUDING, BUT NOT
+// LIMITED TO, THE IMPLIED WARRANTIES OF MERCHANTABILITY AND FITNESS
+// FOR A PARTICULAR PURPOSE ARE DISCLAIMED. IN NO EVENT SHALL THE
+// COPYRIGHT HOLDER OR CONTRIBUTORS BE LIABLE FOR ANY DIRECT, INDIRECT,
+// INCIDENTAL, SPECIAL, EXEMPLARY, OR CONSEQUENTIAL DAMAGES (INCLUDING,
+// BUT NOT LIMITED TO, PROCUREMENT OF SUBSTITUTE GOODS OR SERVICES; LOSS
+// OF USE, DATA, OR PROFITS; OR BUSINESS INTERRUPTION) HOWEVER CAUSED
+// AND ON ANY THEORY OF LIABILITY, WHETHER IN CONTRACT, STRICT LIABILITY,
+// OR TORT (INCLUDING NEGLIGENCE OR OTHERWISE) ARISING IN ANY WAY OUT OF
+// THE USE OF THIS SOFTWARE, EVEN IF ADVISED OF THE POSSIBILITY OF SUCH
+// DAMAGE.
+#![allow(unused_imports)]
+
+use algebra::vector::Vec3;
+use graphics::brushes::*;
+
+#[test]
+fn brush_coverage_fades_a_disc_and_outlines_a_ring() {
+    let centre = Vec3 { x: 0.0f32, y: 0.0f32, z: 0.0f32 };
+    let white = Vec3 { x: 1.0f32, y: 1.0f32, z: 1.0f32 };
+
+    let mut disc = Brush::new(BrushShape::Disc, centre, 4.0, white);
+    disc.opacity = 1.0;
+    disc.falloff = 0.5;
+    assert_eq!(disc.coverage(0.0), 1.0);
+    assert_eq!(disc.coverage(2.0), 1.0);
+    assert_eq!(disc.coverage(3.0), 0.5);
+    assert_eq!(disc.coverage(4.5), 0.0);
+
+    let mut ring = Brush::new(BrushShape::Ring, centre, 4.0, white);
+    ring.ring_width = 0.5;
+    assert_eq!(ring.coverage(1.0), 0.0);
+    assert_eq!(ring.coverage(3.75), ring.opacity);
+    assert_eq!(ring.coverage(4.25), 0.0);
+}
+
+#[test]
+fn hidden_brushes_are_not_drawn_until_shown_again() {
+    let origin = Vec3 { x: 0.0f32, y: 0.0f32, z: 0.0f32 };
+    let red = Vec3 { x: 1.0f32, y: 0.0f32, z: 0.0f32 };
+
+    let mut overlay = BrushOverlay::new();
+    let a = overlay.add_brush(Brush::new(BrushShape::Ring, origin, 2.0, red));
+    let b = overlay.add_brush(Brush::new(BrushShape::Disc, origin, 1.0, red));
+    assert_eq!(overlay.visible_brushes(), vec![a, b]);
+
+    overlay.set_brush_visible(a, false);
+    assert_eq!(overlay.visible_brushes(), vec![b]);
+
+    let cursor = Vec3 { x: 5.0f32, y: 1.0f32, z: -3.0f32 };
+    overlay.set_brush_position(a, &cursor);
+    overlay.set_brush_visible(a, true);
+    assert_eq!(overlay.visible_brushes(), vec![a, b]);
+    assert_eq!(overlay.get(a).unwrap().centre.x, 5.0);
+
+    assert!(overlay.remove_brush(b).is_some());
+    assert_eq!(overlay.visible_brushes(), vec![a]);
+}
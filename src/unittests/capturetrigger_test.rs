@@ -0,0 +1,69 @@
+// Copyright (c) 2016-2017 Bruce Stenning. All rights reserved.
+//
+// Redistribution and use in source and binary forms, with or without
+// modification, are permitted provided that the following conditions
+// are met:
+//
+// 1. Redistributions of source code must retain the above copyright
+//    notice, this list of conditions and the following disclaimer.
+//
+// 2. Redistributions in binary form must reproduce the above copyright
+//    notice, this list of conditions and the following disclaimer in the
+//    documentation and/or other materials provided with the distribution.
+//
+// 3. Neither the name of the copyright holder nor the names of its
+//    contributors may be used to endorse or promote products derived
+//    from this software without specific prior written permission.
+//
+// THIS SOFTWARE IS PROVIDED BY THE COPYRIGHT HOLDERS AND CONTRIBUTORS
+// AS IS" AND ANY EXPRESS OR IMPLIED WARRANTIES, INCLUDING, BUT NOT
+// LIMITED TO, THE IMPLIED WARRANTIES OF MERCHANTABILITY AND FITNESS
+// FOR A PARTICULAR PURPOSE ARE DISCLAIMED. IN NO EVENT SHALL THE
+// COPYRIGHT HOLDER OR CONTRIBUTORS BE LIABLE FOR ANY DIRECT, INDIRECT,
+// INCIDENTAL, SPECIAL, EXEMPLARY, OR CONSEQUENTIAL DAMAGES (INCLUDING,
+// BUT NOT LIMITED TO, PROCUREMENT OF SUBSTITUTE GOODS OR SERVICES; LOSS
+// OF USE, DATA, OR PROFITS; OR BUSINESS INTERRUPTION) HOWEVER CAUSED
+// AND ON ANY THEORY OF LIABILITY, WHETHER IN CONTRACT, STRICT LIABILITY,
+// OR TORT (INCLUDING NEGLIGENCE OR OTHERWISE) ARISING IN ANY WAY OUT OF
+// THE USE OF THIS SOFTWARE, EVEN IF ADVISED OF THE POSSIBILITY OF SUCH
+// DAMAGE.
+#![allow(unused_imports)]
+
+use graphics::capturetrigger::*;
+
+#[test]
+fn requested_frames_are_captured_one_at_a_time() {
+    let mut schedule = CaptureSchedule::new();
+    assert!(!schedule.begin_frame());
+    assert!(!schedule.end_frame());
+
+    schedule.request(2);
+    assert_eq!(schedule.pending(), 2);
+    assert!(schedule.begin_frame());
+    assert!(schedule.is_capturing());
+
+    // A frame already being captured is not started again
+    assert!(!schedule.begin_frame());
+    assert!(schedule.end_frame());
+    assert_eq!(schedule.pending(), 1);
+
+    assert!(schedule.begin_frame());
+    assert!(schedule.end_frame());
+    assert!(!schedule.begin_frame());
+    assert_eq!(schedule.completed(), 2);
+}
+
+#[test]
+fn a_request_during_a_capture_takes_the_next_frame() {
+    let mut schedule = CaptureSchedule::new();
+    schedule.request(1);
+    assert!(schedule.begin_frame());
+
+    schedule.request(1);
+    assert!(schedule.end_frame());
+    assert!(!schedule.is_capturing());
+    assert!(schedule.begin_frame());
+    assert!(schedule.end_frame());
+    assert_eq!(schedule.pending(), 0);
+    assert_eq!(schedule.completed(), 2);
+}
@@ -0,0 +1,114 @@
+// Copyright (c) 2016-2017 Bruce Stenning. All rights reserved.
+//
+// Redistribution and use in source and binary forms, with or without
+// modification, are permitted provided that the following conditions
+// are met:
+//
+// 1. Redistributions of source code must retain the above copyright
+//    notice, this list of conditions and the following disclaimer.
+//
+// 2. Redistributions in binary form must reproduce the above copyright
+//    notice, this list of conditions and the following disclaimer in the
+//    documentation and/or other materials provided with the distribution.
+//
+// 3. Neither the name of the copyright holder nor the names of its
+//    contributors may be used to endorse or promote products derived
+//    from this software without specific prior written permission.
+//
+// THIS SOFTWARE IS PROVIDED BY THE COPYRIGHT HOLDERS AND CONTRIBUTORS
+// AS IS" AND ANY EXPRESS OR IMPLIED WARRANTIES, INCLUDING, BUT NOT
+// LIMITED TO, THE IMPLIED WARRANTIES OF MERCHANTABILITY AND FITNESS
+// FOR A PARTICULAR PURPOSE ARE DISCLAIMED. IN NO EVENT SHALL THE
+// COPYRIGHT HOLDER OR CONTRIBUTORS BE LIABLE FOR ANY DIRECT, INDIRECT,
+// INCIDENTAL, SPECIAL, EXEMPLARY, OR CONSEQUENTIAL DAMAGES (INCLUDING,
+// BUT NOT LIMITED TO, PROCUREMENT OF SUBSTITUTE GOODS OR SERVICES; LOSS
+// OF USE, DATA, OR PROFITS; OR BUSINESS INTERRUPTION) HOWEVER CAUSED
+// AND ON ANY THEORY OF LIABILITY, WHETHER IN CONTRACT, STRICT LIABILITY,
+// OR TORT (INCLUDING NEGLIGENCE OR OTHERWISE) ARISING IN ANY WAY OUT OF
+// THE USE OF THIS SOFTWARE, EVEN IF ADVISED OF THE POSSIBILITY OF SUCH
+// DAMAGE.
+#![allow(unused_imports)]
+
+use std::collections::HashMap;
+
+use algebra::matrix::Mat4;
+use algebra::vector::Vec4;
+use graphics::checkerboard::*;
+use graphics::renderer::*;
+use graphics::resources::*;
+use graphics::shader::*;
+
+#[test]
+fn alternate_frames_render_the_other_columns_into_the_other_target() {
+    let mut checkerboard = Checkerboard::new(true);
+    let mut parities = vec![];
+    for _ in 0..4 {
+        checkerboard.begin_frame();
+        assert_eq!(checkerboard.history_target_index(), 1 - checkerboard.resolve_target_index());
+        assert_eq!(checkerboard.resolve_target_index(), checkerboard.parity() as usize);
+        parities.push(checkerboard.parity());
+    }
+    assert_eq!(parities, vec![1, 0, 1, 0]);
+    assert_eq!(checkerboard.render_size(1920, 1080), (960, 1080));
+}
+
+#[test]
+fn jitter_moves_the_half_width_texel_centres_onto_the_output_columns() {
+    let width = 8;
+    let mut checkerboard = Checkerboard::new(true);
+    for _ in 0..2 {
+        checkerboard.begin_frame();
+        let jittered = checkerboard.jitter_projection(&Mat4::newidentity(), width);
+        for x in 0..width / 2 {
+            let column = 2 * x + checkerboard.parity() as u32;
+            let column_centre = -1.0f32 + (2 * column + 1) as f32 / width as f32;
+            let texel_centre = -1.0f32 + (2 * x + 1) as f32 / (width / 2) as f32;
+
+            let moved = jittered.mul_by_vec4(Vec4 {
+                x: column_centre,
+                y: 0.5f32,
+                z: 0.25f32,
+                w: 1.0f32,
+            });
+            assert!((moved.x - texel_centre).abs() < 1.0e-6f32);
+            assert_eq!(moved.y, 0.5f32);
+            assert_eq!(moved.z, 0.25f32);
+        }
+    }
+}
+
+#[test]
+fn disabled_checkerboarding_renders_the_whole_output() {
+    let mut checkerboard = Checkerboard::new(true);
+    checkerboard.set_enabled(false);
+    assert!(!checkerboard.is_enabled());
+
+    let parity = checkerboard.parity();
+    checkerboard.begin_frame();
+    assert_eq!(checkerboard.parity(), parity);
+    assert_eq!(checkerboard.render_size(1920, 1080), (1920, 1080));
+
+    let projection = Mat4::perspective(60.0f32, 1.5f32, 0.1f32, 100.0f32);
+    let jittered = checkerboard.jitter_projection(&projection, 1920);
+    assert_eq!(jittered.m, projection.m);
+}
+
+#[test]
+fn resolve_samples_three_inputs_from_a_std140_block() {
+    let mut resource_manager = ResourceManager::new(HashMap::new(), HashMap::new());
+    register_checkerboard_shader(&mut resource_manager, "resolve");
+
+    let spec = &resource_manager.shader_specs[CHECKERBOARD_RESOLVE_SHADER_NAME];
+    assert_eq!(spec.pass_name, "resolve");
+    assert!(spec.vertex_array_type == VertexArrayType::F2F2);
+    let textures: Vec<(&str, u32, u32)> = spec.uniform_specs.iter().map(|u| (u.name, u.set, u.binding)).collect();
+    assert_eq!(textures,
+               vec![(CHECKERBOARD_CURRENT_TEXTURE, 1, 0),
+                    (CHECKERBOARD_HISTORY_TEXTURE, 1, 1),
+                    (CHECKERBOARD_VELOCITY_TEXTURE, 1, 2)]);
+
+    let block = resource_manager.uniform_block_specs[CHECKERBOARD_SETTINGS_BLOCK].laid_out();
+    let offsets: Vec<(&str, usize)> = block.uniforms.iter().map(|u| (u.name, u.offset)).collect();
+    assert_eq!(offsets,
+               vec![("checkerboard_viewport", 0), ("checkerboard_parity", 16), ("checkerboard_history_valid", 20)]);
+}
@@ -0,0 +1,66 @@
+// Copyright (c) 2016-2017 Bruce Stenning. All rights reserved.
+//
+// Redistribution and use in source and binary forms, with or without
+// modification, are permitted provided that the following conditions
+// are met:
+//
+// 1. Redistributions of source code must retain the above copyright
+//    notice, this list of conditions and the following disclaimer.
+//
+// 2. Redistributions in binary form must reproduce the above copyright
+//    notice, this list of conditions and the following disclaimer in the
+//    documentation and/or other materials provided with the distribution.
+//
+// 3. Neither the name of the copyright holder nor the names of its
+//    contributors may be used to endorse or promote products derived
+//    from this software without specific prior written permission.
+//
+// THIS SOFTWARE IS PROVIDED BY THE COPYRIGHT HOLDERS AND CONTRIBUTORS
+// AS IS" AND ANY EXPRESS OR IMPLIED WARRANTIES, INCLUDING, BUT NOT
+// LIMITED TO, THE IMPLIED WARRANTIES OF MERCHANTABILITY AND FITNESS
+// FOR A PARTICULAR PURPOSE ARE DISCLAIMED. IN NO EVENT SHALL THE
+// COPYRIGHT HOLDER OR CONTRIBUTORS BE LIABLE FOR ANY DIRECT, INDIRECT,
+// INCIDENTAL, SPECIAL, EXEMPLARY, OR CONSEQUENTIAL DAMAGES (INCLUDING,
+// BUT NOT LIMITED TO, PROCUREMENT OF SUBSTITUTE GOODS OR SERVICES; LOSS
+// OF USE, DATA, OR PROFITS; OR BUSINESS INTERRUPTION) HOWEVER CAUSED
+// AND ON ANY THEORY OF LIABILITY, WHETHER IN CONTRACT, STRICT LIABILITY,
+// OR TORT (INCLUDING NEGLIGENCE OR OTHERWISE) ARISING IN ANY WAY OUT OF
+// THE USE OF THIS SOFTWARE, EVEN IF ADVISED OF THE POSSIBILITY OF SUCH
+// DAMAGE.
+#![allow(unused_imports)]
+
+use graphics::colourspace::*;
+
+#[test]
+fn srgb_encoding_round_trips_and_keeps_the_ends_of_the_range() {
+    for i in 0..256 {
+        let c = i as f32 / 255.0;
+        assert!((linear_to_srgb(srgb_to_linear(c)) - c).abs() < 1.0e-5);
+    }
+
+    assert_eq!(srgb_to_linear(0.0), 0.0);
+    assert!((srgb_to_linear(1.0) - 1.0).abs() < 1.0e-6);
+    assert!((linear_to_srgb(1.0) - 1.0).abs() < 1.0e-6);
+}
+
+#[test]
+fn srgb_mid_grey_is_darker_when_linear() {
+    // The familiar 50% grey of sRGB is about 21% of the linear intensity
+    assert!((srgb_to_linear(0.5) - 0.214).abs() < 1.0e-3);
+    assert!((linear_to_srgb(0.214) - 0.5).abs() < 1.0e-3);
+
+    // The linear segment near black
+    assert!((srgb_to_linear(0.02) - 0.02 / 12.92).abs() < 1.0e-7);
+}
+
+#[test]
+fn pq_encoding_spans_the_hdr10_range() {
+    assert!(nits_to_pq(0.0).abs() < 1.0e-6);
+    assert!((nits_to_pq(PQ_MAX_NITS) - 1.0).abs() < 1.0e-5);
+
+    // 100 nits, the usual SDR reference white, encodes to about half the signal range
+    assert!((nits_to_pq(100.0) - 0.508).abs() < 1.0e-3);
+
+    assert!(nits_to_pq(1000.0) > nits_to_pq(200.0));
+    assert_eq!(nits_to_pq(20000.0), nits_to_pq(PQ_MAX_NITS));
+}
@@ -0,0 +1,87 @@
+// Copyright (c) 2016-2017 Bruce Stenning. All rights reserved.
+//
+// Redistribution and use in source and binary forms, with or without
+// modification, are permitted provided that the following conditions
+// are met:
+//
+// 1. Redistributions of source code must retain the above copyright
+//    notice, this list of conditions and the following disclaimer.
+//
+// 2. Redistributions in binary form must reproduce the above copyright
+//    notice, this list of conditions and the following disclaimer in the
+//    documentation and/or other materials provided with the distribution.
+//
+// 3. Neither the name of the copyright holder nor the names of its
+//    contributors may be used to endorse or promote products derived
+//    from this software without specific prior written permission.
+//
+// THIS SOFTWARE IS PROVIDED BY THE COPYRIGHT HOLDERS AND CONTRIBUTORS
+// AS IS" AND ANY EXPRESS OR IMPLIED WARRANTIES, INCLUDING, BUT NOT
+// LIMITED TO, THE IMPLIED WARRANTIES OF MERCHANTABILITY AND FITNESS
+// FOR A PARTICULAR PURPOSE ARE DISCLAIMED. IN NO EVENT SHALL THE
+// COPYRIGHT HOLDER OR CONTRIBUTORS BE LIABLE FOR ANY DIRECT, INDIRECT,
+// INCIDENTAL, SPECIAL, EXEMPLARY, OR CONSEQUENTIAL DAMAGES (INCLUDING,
+// BUT NOT LIMITED TO, PROCUREMENT OF SUBSTITUTE GOODS OR SERVICES; LOSS
+// OF USE, DATA, OR PROFITS; OR BUSINESS INTERRUPTION) HOWEVER CAUSED
+// AND ON ANY THEORY OF LIABILITY, WHETHER IN CONTRACT, STRICT LIABILITY,
+// OR TORT (INCLUDING NEGLIGENCE OR OTHERWISE) ARISING IN ANY WAY OUT OF
+// THE USE OF THIS SOFTWARE, EVEN IF ADVISED OF THE POSSIBILITY OF SUCH
+// DAMAGE.
+
+#![allow(unused_imports)]
+
+use graphics::computepacing::*;
+
+#[test]
+fn compute_pacer_slices_a_large_dispatch_to_fit_the_budget_once_timed() {
+    let mut pacer = ComputePacer::new(2.0);
+    let job = pacer.queue("generate", 1000, 4, 1, true);
+
+    // The first frame uses the initial estimate
+    let slices = pacer.plan_frame();
+    assert_eq!(slices.len(), 1);
+    assert_eq!(slices[0].first_x, 0);
+
+    // Report that each workgroup took 0.01ms, so 2ms affords 50 columns of 4 workgroups
+    let first = slices[0];
+    pacer.slice_completed(&first, Some(0.01 * first.workgroups() as f32));
+    let slices = pacer.plan_frame();
+    assert_eq!(slices.len(), 1);
+    assert_eq!(slices[0].first_x, first.x);
+    assert_eq!(slices[0].x, 50);
+
+    let progress = pacer.progress(job);
+    println!("progress is {}", progress);
+    assert!(progress > 0.0 && progress < 1.0);
+}
+
+#[test]
+fn compute_pacer_always_makes_progress_and_completes_jobs() {
+    let mut pacer = ComputePacer::new(0.0);
+    let job = pacer.queue("generate", 3, 1, 1, true);
+
+    let mut frames = 0;
+    while !pacer.is_idle() {
+        let slices = pacer.plan_frame();
+        assert!(!slices.is_empty());
+        for slice in slices.iter() {
+            pacer.slice_completed(slice, Some(10.0));
+        }
+        frames += 1;
+        assert!(frames <= 3);
+    }
+
+    assert_eq!(pacer.progress(job), 1.0);
+    assert_eq!(pacer.overall_progress(), 1.0);
+}
+
+#[test]
+fn compute_pacer_dispatches_unsliceable_jobs_whole() {
+    let mut pacer = ComputePacer::new(0.5);
+    pacer.queue("fixed", 64, 64, 1, false);
+
+    let slices = pacer.plan_frame();
+    assert_eq!(slices.len(), 1);
+    assert_eq!(slices[0].x, 64);
+    assert!(pacer.plan_frame().is_empty());
+}
@@ -0,0 +1,85 @@
+// Copyright (c) 2016-2017 Bruce Stenning. All rights reserved.
+//
+// Redistribution and use in source and binary forms, with or without
+// modification, are permitted provided that the following conditions
+// are met:
+//
+// 1. Redistributions of source code must retain the above copyright
+//    notice, this list of conditions and the following disclaimer.
+//
+// 2. Redistributions in binary form must reproduce the above copyright
+//    notice, this list of conditions and the following disclaimer in the
+//    documentation and/or other materials provided with the distribution.
+//
+// 3. Neither the name of the copyright holder nor the names of its
+//    contributors may be used to endorse or promote products derived
+//    from this software without specific prior written permission.
+//
+// THIS SOFTWARE IS PROVIDED BY THE COPYRIGHT HOLDERS AND CONTRIBUTORS
+// AS IS" AND ANY EXPRESS OR IMPLIED WARRANTIES, INCLUDING, BUT NOT
+// LIMITED TO, THE IMPLIED WARRANTIES OF MERCHANTABILITY AND FITNESS
+// FOR A PARTICULAR PURPOSE ARE DISCLAIMED. IN NO EVENT SHALL THE
+// COPYRIGHT HOLDER OR CONTRIBUTORS BE LIABLE FOR ANY DIRECT, INDIRECT,
+// INCIDENTAL, SPECIAL, EXEMPLARY, OR CONSEQUENTIAL DAMAGES (INCLUDING,
+// BUT NOT LIMITED TO, PROCUREMENT OF SUBSTITUTE GOODS OR SERVICES; LOSS
+// OF USE, DATA, OR PROFITS; OR BUSINESS INTERRUPTION) HOWEVER CAUSED
+// AND ON ANY THEORY OF LIABILITY, WHETHER IN CONTRACT, STRICT LIABILITY,
+// OR TORT (INCLUDING NEGLIGENCE OR OTHERWISE) ARISING IN ANY WAY OUT OF
+// THE USE OF THIS SOFTWARE, EVEN IF ADVISED OF THE POSSIBILITY OF SUCH
+// DAMAGE.
+#![allow(unused_imports)]
+
+use algebra::vector::Vec3;
+use graphics::contactshadows::*;
+use graphics::pbr::*;
+
+#[test]
+fn quality_presets_scale_the_step_count() {
+    let low = ContactShadowSettings::from_quality(ContactShadowQuality::Low);
+    let high = ContactShadowSettings::from_quality(ContactShadowQuality::High);
+    assert!(low.step_count() < ContactShadowSettings::default().step_count());
+    assert!(ContactShadowSettings::default().step_count() < high.step_count());
+
+    let mut settings = ContactShadowSettings {
+        steps: 1000,
+        ..high
+    };
+    assert_eq!(settings.step_count(), CONTACT_SHADOW_MAX_STEPS);
+    settings.steps = -3;
+    assert_eq!(settings.step_count(), 0);
+    settings = ContactShadowSettings {
+        enabled: false,
+        ..high
+    };
+    assert_eq!(settings.step_count(), 0);
+}
+
+#[test]
+fn contact_shadow_mask_follows_the_light_order() {
+    let mut lights = LightManager::new();
+    let colour = Vec3 { x: 1.0, y: 1.0, z: 1.0 };
+    let sun = lights.add_light(PbrLight::directional(Vec3 { x: 0.0, y: -1.0, z: 0.0 }, colour, 3.0f32)).unwrap();
+    let lamp = lights.add_light(PbrLight::point(Vec3 { x: 0.0, y: 2.0, z: 0.0 }, colour, 10.0f32)).unwrap();
+    let fill = lights.add_light(PbrLight::point(Vec3 { x: 4.0, y: 2.0, z: 0.0 }, colour, 1.0f32)).unwrap();
+    assert_eq!(lights.contact_shadow_mask(), 0b111);
+
+    lights.set_contact_shadows(lamp, false);
+    assert_eq!(lights.contact_shadow_mask(), 0b101);
+
+    // Removing a light moves the later lights down a slot
+    assert!(lights.remove_light(sun).unwrap().directional);
+    assert_eq!(lights.contact_shadow_mask(), 0b10);
+    assert!(lights.get(fill).unwrap().contact_shadows);
+    assert!(lights.get(sun).is_none());
+}
+
+#[test]
+fn lights_beyond_the_shader_limit_are_refused() {
+    let mut lights = LightManager::new();
+    let light = PbrLight::point(Vec3 { x: 0.0, y: 0.0, z: 0.0 }, Vec3 { x: 1.0, y: 1.0, z: 1.0 }, 1.0f32);
+    for _ in 0..PBR_MAX_LIGHTS {
+        assert!(lights.add_light(light).is_some());
+    }
+    assert!(lights.add_light(light).is_none());
+    assert_eq!(lights.len(), PBR_MAX_LIGHTS);
+}
@@ -0,0 +1,119 @@
+// Copyright (c) 2016-2017 Bruce Stenning. All rights reserved.
+//
+// Redistribution and use in source and binary forms, with or without
+// modification, are permitted provided that the following conditions
+// are met:
+//
+// 1. Redistributions of source code must retain the above copyright
+//    notice, this list of conditions and the following disclaimer.
+//
+// 2. Redistributions in binary form must reproduce the above copyright
+//    notice, this list of conditions and the following disclaimer in the
+//    documentation and/or other materials provided with the distribution.
+//
+// 3. Neither the name of the copyright holder nor the names of its
+//    contributors may be used to endorse or promote products derived
+//    from this software without specific prior written permission.
+//
+// THIS SOFTWARE IS PROVIDED BY THE COPYRIGHT HOLDERS AND CONTRIBUTORS
+// AS IS" AND ANY EXPRESS OR IMPLIED WARRANTIES, INCLUDING, BUT NOT
+// LIMITED TO, THE IMPLIED WARRANTIES OF MERCHANTABILITY AND FITNESS
+// FOR A PARTICULAR PURPOSE ARE DISCLAIMED. IN NO EVENT SHALL THE
+// COPYRIGHT HOLDER OR CONTRIBUTORS BE LIABLE FOR ANY DIRECT, INDIRECT,
+// INCIDENTAL, SPECIAL, EXEMPLARY, OR CONSEQUENTIAL DAMAGES (INCLUDING,
+// BUT NOT LIMITED TO, PROCUREMENT OF SUBSTITUTE GOODS OR SERVICES; LOSS
+// OF USE, DATA, OR PROFITS; OR BUSINESS INTERRUPTION) HOWEVER CAUSED
+// AND ON ANY THEORY OF LIABILITY, WHETHER IN CONTRACT, STRICT LIABILITY,
+// OR TORT (INCLUDING NEGLIGENCE OR OTHERWISE) ARISING IN ANY WAY OUT OF
+// THE USE OF THIS SOFTWARE, EVEN IF ADVISED OF THE POSSIBILITY OF SUCH
+// DAMAGE.
+#![allow(unused_imports)]
+
+use graphics::cputiming::*;
+
+#[test]
+fn recorder_nests_scopes() {
+    let mut recorder = CpuScopeRecorder::new();
+    recorder.begin("generate", 1000);
+    recorder.begin("trees", 1500);
+    recorder.end(2500);
+    recorder.begin("rocks", 3000);
+    recorder.end(3250);
+    recorder.end(4000);
+
+    let (scopes, dropped) = recorder.take();
+    assert_eq!(dropped, 0);
+    assert_eq!(scopes.len(), 3);
+    assert_eq!((scopes[0].name, scopes[0].depth, scopes[0].duration_ns), ("generate", 0, 3000));
+    assert_eq!((scopes[1].name, scopes[1].depth, scopes[1].duration_ns), ("trees", 1, 1000));
+    assert_eq!((scopes[2].name, scopes[2].depth, scopes[2].duration_ns), ("rocks", 1, 250));
+    assert!(recorder.take().0.is_empty());
+}
+
+#[test]
+fn profiler_aggregates_threads_per_frame() {
+    let scope = |name, start_ns, duration_ns, depth| {
+        CpuScope {
+            name: name,
+            thread: 0,
+            depth: depth,
+            start_ns: start_ns,
+            duration_ns: duration_ns,
+        }
+    };
+
+    let mut profiler = CpuProfiler::new(2);
+    profiler.submit(1, vec![scope("trees", 2000, 3_000_000, 0)], 0);
+    profiler.submit(0, vec![scope("trees", 1000, 1_000_000, 0), scope("flush", 1500, 500_000, 1)], 2);
+    profiler.end_frame(1, 2);
+
+    {
+        let frame = profiler.latest().unwrap();
+        assert_eq!(frame.frame, 1);
+        assert_eq!(frame.dropped, 2);
+        assert_eq!(frame.threads(), vec![0, 1]);
+        assert_eq!(frame.scopes[0].thread, 0);
+        assert_eq!(frame.scope_ms("trees"), 4.0);
+        assert_eq!(frame.thread_ms(0), 1.0);
+    }
+
+    // Frames without scopes are not kept, and the oldest are discarded
+    profiler.end_frame(2, 2);
+    assert_eq!(profiler.frames().len(), 1);
+    profiler.submit(0, vec![scope("trees", 9000, 2_000_000, 0)], 0);
+    profiler.end_frame(3, 2);
+    profiler.submit(0, vec![scope("rocks", 9000, 2_000_000, 0)], 0);
+    profiler.end_frame(4, 2);
+    assert_eq!(profiler.frames().len(), 2);
+    assert_eq!(profiler.average_scope_ms("trees"), Some(2.0));
+    assert_eq!(profiler.average_scope_ms("grass"), None);
+}
+
+#[test]
+fn chrome_trace_has_an_event_per_scope() {
+    let frame = CpuFrameTimings {
+        frame: 7,
+        scopes: vec![CpuScope {
+                         name: "say \"hi\"",
+                         thread: 3,
+                         depth: 0,
+                         start_ns: 5000,
+                         duration_ns: 1500,
+                     },
+                     CpuScope {
+                         name: "flush",
+                         thread: 1,
+                         depth: 0,
+                         start_ns: 7000,
+                         duration_ns: 250,
+                     }],
+        dropped: 0,
+    };
+
+    let json = chrome_trace_json(vec![frame].iter());
+    assert!(json.starts_with("{\"traceEvents\":["));
+    assert!(json.contains("{\"name\":\"say \\\"hi\\\"\",\"cat\":\"cpu\",\"ph\":\"X\","));
+    assert!(json.contains("\"ts\":0.000,\"dur\":1.500,\"pid\":0,\"tid\":3}"));
+    assert!(json.contains("\"ts\":2.000,\"dur\":0.250,\"pid\":0,\"tid\":1}"));
+    assert_eq!(json.matches("\"ph\":\"X\"").count(), 2);
+}
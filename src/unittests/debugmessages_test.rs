@@ -0,0 +1,86 @@
+// Copyright (c) 2016-2017 Bruce Stenning. All rights reserved.
+//
+// Redistribution and use in source and binary forms, with or without
+// modification, are permitted provided that the following conditions
+// are met:
+//
+// 1. Redistributions of source code must retain the above copyright
+//    notice, this list of conditions and the following disclaimer.
+//
+// 2. Redistributions in binary form must reproduce the above copyright
+//    notice, this list of conditions and the following disclaimer in the
+//    documentation and/or other materials provided with the distribution.
+//
+// 3. Neither the name of the copyright holder nor the names of its
+//    contributors may be used to endorse or promote products derived
+//    from this software without specific prior written permission.
+//
+// THIS SOFTWARE IS PROVIDED BY THE COPYRIGHT HOLDERS AND CONTRIBUTORS
+// AS IS" AND ANY EXPRESS OR IMPLIED WARRANTIES, INCLUDING, BUT NOT
+// LIMITED TO, THE IMPLIED WARRANTIES OF MERCHANTABILITY AND FITNESS
+// FOR A PARTICULAR PURPOSE ARE DISCLAIMED. IN NO EVENT SHALL THE
+// COPYRIGHT HOLDER OR CONTRIBUTORS BE LIABLE FOR ANY DIRECT, INDIRECT,
+// INCIDENTAL, SPECIAL, EXEMPLARY, OR CONSEQUENTIAL DAMAGES (INCLUDING,
+// BUT NOT LIMITED TO, PROCUREMENT OF SUBSTITUTE GOODS OR SERVICES; LOSS
+// OF USE, DATA, OR PROFITS; OR BUSINESS INTERRUPTION) HOWEVER CAUSED
+// AND ON ANY THEORY OF LIABILITY, WHETHER IN CONTRACT, STRICT LIABILITY,
+// OR TORT (INCLUDING NEGLIGENCE OR OTHERWISE) ARISING IN ANY WAY OUT OF
+// THE USE OF THIS SOFTWARE, EVEN IF ADVISED OF THE POSSIBILITY OF SUCH
+// DAMAGE.
+#![allow(unused_imports)]
+
+use graphics::debugmessages::*;
+
+#[cfg(test)]
+fn message(severity: DebugMessageSeverity, validation: bool, performance: bool) -> DebugMessage {
+    DebugMessage {
+        severity: severity,
+        validation: validation,
+        performance: performance,
+        id_name: "VUID-test".to_string(),
+        id_number: 1,
+        message: "test".to_string(),
+    }
+}
+
+#[test]
+fn messenger_filter_follows_mask_bits() {
+    assert_eq!(messenger_filter(0),
+               (0, VK_DEBUG_UTILS_MESSAGE_TYPE_GENERAL_BIT_EXT | VK_DEBUG_UTILS_MESSAGE_TYPE_VALIDATION_BIT_EXT));
+
+    let (severities, types) = messenger_filter(DEBUG_MASK_ERROR | DEBUG_MASK_DEBUG);
+    assert_eq!(severities,
+               VK_DEBUG_UTILS_MESSAGE_SEVERITY_ERROR_BIT_EXT | VK_DEBUG_UTILS_MESSAGE_SEVERITY_VERBOSE_BIT_EXT);
+    assert_eq!(types & VK_DEBUG_UTILS_MESSAGE_TYPE_PERFORMANCE_BIT_EXT, 0);
+
+    // Performance warnings are reported at warning severity
+    let (severities, types) = messenger_filter(DEBUG_MASK_PERFORMANCE);
+    assert_eq!(severities, VK_DEBUG_UTILS_MESSAGE_SEVERITY_WARNING_BIT_EXT);
+    assert!((types & VK_DEBUG_UTILS_MESSAGE_TYPE_PERFORMANCE_BIT_EXT) != 0);
+}
+
+#[test]
+fn wanted_messages_separate_performance_from_warnings() {
+    let warning = message(DebugMessageSeverity::Warning, true, false);
+    let performance = message(DebugMessageSeverity::Warning, false, true);
+
+    assert!(debug_message_wanted(DEBUG_MASK_WARNING, &warning));
+    assert!(!debug_message_wanted(DEBUG_MASK_WARNING, &performance));
+    assert!(debug_message_wanted(DEBUG_MASK_PERFORMANCE, &performance));
+    assert!(!debug_message_wanted(DEBUG_MASK_PERFORMANCE, &warning));
+    assert!(!debug_message_wanted(DEBUG_MASK_ERROR, &warning));
+    assert!(debug_message_wanted(DEBUG_MASK_ERROR, &message(DebugMessageSeverity::Error, true, false)));
+}
+
+#[test]
+fn severity_and_prefix_from_vk_bits() {
+    assert_eq!(DebugMessageSeverity::from_vk(VK_DEBUG_UTILS_MESSAGE_SEVERITY_VERBOSE_BIT_EXT),
+               DebugMessageSeverity::Verbose);
+    assert_eq!(DebugMessageSeverity::from_vk(VK_DEBUG_UTILS_MESSAGE_SEVERITY_INFO_BIT_EXT |
+                                             VK_DEBUG_UTILS_MESSAGE_SEVERITY_ERROR_BIT_EXT),
+               DebugMessageSeverity::Error);
+
+    assert_eq!(message(DebugMessageSeverity::Info, false, false).prefix(), "INFO");
+    assert_eq!(message(DebugMessageSeverity::Error, true, false).prefix(), "ERRR");
+    assert_eq!(message(DebugMessageSeverity::Warning, false, true).prefix(), "PERF");
+}
@@ -0,0 +1,116 @@
+// Copyright (c) 2016-2017 Bruce Stenning. All rights reserved.
+//
+// Redistribution and use in source and binary forms, with or without
+// modification, are permitted provided that the following conditions
+// are met:
+//
+// 1. Redistributions of source code must retain the above copyright
+//    notice, this list of conditions and the following disclaimer.
+//
+// 2. Redistributions in binary form must reproduce the above copyright
+//    notice, this list of conditions and the following disclaimer in the
+//    documentation and/or other materials provided with the distribution.
+//
+// 3. Neither the name of the copyright holder nor the names of its
+//    contributors may be used to endorse or promote products derived
+//    from this software without specific prior written permission.
+//
+// THIS SOFTWARE IS PROVIDED BY THE COPYRIGHT HOLDERS AND CONTRIBUTORS
+// AS IS" AND ANY EXPRESS OR IMPLIED WARRANTIES, INCLUDING, BUT NOT
+// LIMITED TO, THE IMPLIED WARRANTIES OF MERCHANTABILITY AND FITNESS
+// FOR A PARTICULAR PURPOSE ARE DISCLAIMED. IN NO EVENT SHALL THE
+// COPYRIGHT HOLDER OR CONTRIBUTORS BE LIABLE FOR ANY DIRECT, INDIRECT,
+// INCIDENTAL, SPECIAL, EXEMPLARY, OR CONSEQUENTIAL DAMAGES (INCLUDING,
+// BUT NOT LIMITED TO, PROCUREMENT OF SUBSTITUTE GOODS OR SERVICES; LOSS
+// OF USE, DATA, OR PROFITS; OR BUSINESS INTERRUPTION) HOWEVER CAUSED
+// AND ON ANY THEORY OF LIABILITY, WHETHER IN CONTRACT, STRICT LIABILITY,
+// OR TORT (INCLUDING NEGLIGENCE OR OTHERWISE) ARISING IN ANY WAY OUT OF
+// THE USE OF THIS SOFTWARE, EVEN IF ADVISED OF THE POSSIBILITY OF SUCH
+// DAMAGE.
+
+#![allow(unused_imports)]
+
+use algebra::matrix::Mat4;
+use graphics::decals::*;
+
+fn decal(atlas: &'static str, x: f32) -> Decal {
+    Decal {
+        transform: Mat4::translate(x, 0.0f32, 0.0f32),
+        atlas: atlas,
+        atlas_rect: [0.0f32, 0.0f32, 0.5f32, 0.5f32],
+        albedo_opacity: 1.0f32,
+        normal_strength: 1.0f32,
+    }
+}
+
+#[test]
+fn decals_are_added_and_removed_by_handle() {
+    let mut decals = DecalManager::new();
+    assert!(decals.is_empty());
+
+    let first = decals.add_decal(decal("stone", 1.0f32)).unwrap();
+    let second = decals.add_decal(decal("stone", 2.0f32)).unwrap();
+    assert!(first != second);
+    assert_eq!(decals.len(), 2);
+    assert_eq!(decals.get(second).unwrap().transform.m[3][0], 2.0f32);
+
+    assert_eq!(decals.remove_decal(first).unwrap().transform.m[3][0], 1.0f32);
+    assert!(decals.remove_decal(first).is_none());
+    assert!(decals.get(first).is_none());
+
+    // Handles are not reused after removal
+    let third = decals.add_decal(decal("stone", 3.0f32)).unwrap();
+    assert!(third != first && third != second);
+    assert_eq!(decals.len(), 2);
+}
+
+#[test]
+fn decals_with_non_invertible_transforms_are_skipped() {
+    let mut decals = DecalManager::new();
+    let mut flat = decal("stone", 0.0f32);
+    flat.transform = Mat4::new();
+    assert!(decals.add_decal(flat).is_none());
+    assert!(decals.is_empty());
+
+    // Moving a decal somewhere it cannot be inverted leaves it where it was
+    let handle = decals.add_decal(decal("stone", 1.0f32)).unwrap();
+    decals.update_decal_transform(handle, &Mat4::new());
+    assert_eq!(decals.get(handle).unwrap().transform.m[3][0], 1.0f32);
+}
+
+#[test]
+fn decals_are_updated_by_handle() {
+    let mut decals = DecalManager::new();
+    let handle = decals.add_decal(decal("stone", 1.0f32)).unwrap();
+
+    decals.update_decal_transform(handle, &Mat4::translate(0.0f32, 4.0f32, 0.0f32));
+    decals.update_decal_texture(handle, "moss", [0.5f32, 0.5f32, 0.25f32, 0.25f32]);
+    let updated = decals.get(handle).unwrap();
+    assert_eq!(updated.transform.m[3][1], 4.0f32);
+    assert_eq!(updated.atlas, "moss");
+    assert_eq!(updated.atlas_rect, [0.5f32, 0.5f32, 0.25f32, 0.25f32]);
+
+    // Unknown handles are ignored
+    decals.update_decal_transform(handle + 1, &Mat4::newidentity());
+    decals.update_decal_texture(handle + 1, "stone", [0.0f32; 4]);
+    assert_eq!(decals.len(), 1);
+}
+
+#[test]
+fn batches_group_the_decals_by_atlas_in_sorted_order() {
+    let mut decals = DecalManager::new();
+    let moss = decals.add_decal(decal("moss", 0.0f32)).unwrap();
+    let stone = decals.add_decal(decal("stone", 1.0f32)).unwrap();
+    let blood = decals.add_decal(decal("blood", 2.0f32)).unwrap();
+    let more_moss = decals.add_decal(decal("moss", 3.0f32)).unwrap();
+    let more_stone = decals.add_decal(decal("stone", 4.0f32)).unwrap();
+
+    assert_eq!(decals.batches(),
+               vec![("blood", vec![blood]), ("moss", vec![moss, more_moss]), ("stone", vec![stone, more_stone])]);
+
+    // A decal moved to another atlas changes batch, and an emptied batch disappears
+    decals.update_decal_texture(blood, "stone", [0.0f32; 4]);
+    decals.remove_decal(moss);
+    decals.remove_decal(more_moss);
+    assert_eq!(decals.batches(), vec![("stone", vec![stone, blood, more_stone])]);
+}
@@ -0,0 +1,82 @@
+// Copyright (c) 2016-2017 Bruce Stenning. All rights reserved.
+//
+// Redistribution and use in source and binary forms, with or without
+// modification, are permitted provided that the following conditions
+// are met:
+//
+// 1. Redistributions of source code must retain the above copyright
+//    notice, this list of conditions and the following disclaimer.
+//
+// 2. Redistributions in binary form must reproduce the above copyright
+//    notice, this list of conditions and the following disclaimer in the
+//    documentation and/or other materials provided with the distribution.
+//
+// 3. Neither the name of the copyright holder nor the names of its
+//    contributors may be used to endorse or promote products derived
+//    from this software without specific prior written permission.
+//
+// THIS SOFTWARE IS PROVIDED BY THE COPYRIGHT HOLDERS AND CONTRIBUTORS
+// AS IS" AND ANY EXPRESS OR IMPLIED WARRANTIES, INCLUDING, BUT NOT
+// LIMITED TO, THE IMPLIED WARRANTIES OF MERCHANTABILITY AND FITNESS
+// FOR A PARTICULAR PURPOSE ARE DISCLAIMED. IN NO EVENT SHALL THE
+// COPYRIGHT HOLDER OR CONTRIBUTORS BE LIABLE FOR ANY DIRECT, INDIRECT,
+// INCIDENTAL, SPECIAL, EXEMPLARY, OR CONSEQUENTIAL DAMAGES (INCLUDING,
+// BUT NOT LIMITED TO, PROCUREMENT OF SUBSTITUTE GOODS OR SERVICES; LOSS
+// OF USE, DATA, OR PROFITS; OR BUSINESS INTERRUPTION) HOWEVER CAUSED
+// AND ON ANY THEORY OF LIABILITY, WHETHER IN CONTRACT, STRICT LIABILITY,
+// OR TORT (INCLUDING NEGLIGENCE OR OTHERWISE) ARISING IN ANY WAY OUT OF
+// THE USE OF THIS SOFTWARE, EVEN IF ADVISED OF THE POSSIBILITY OF SUCH
+// DAMAGE.
+#![allow(unused_imports)]
+
+use misc::deflate::*;
+use misc::embeddedresources::*;
+
+#[test]
+fn streams_from_other_encoders_are_inflated() {
+    // Written by zlib, as a dynamic block and as a stored block
+    let dynamic = [0x1d, 0x88, 0xc1, 0x01, 0x00, 0x00, 0x10, 0x81, 0x66, 0x0d, 0xfb, 0xcf, 0x70, 0xdc, 0x47, 0x0a, 0x09, 0x44,
+                   0x57, 0x64, 0x6f, 0x92, 0xe1, 0x2d, 0xeb, 0x32, 0xb8, 0xc9, 0xa4, 0xf1, 0x00];
+    assert_eq!(inflate(&dynamic, 0).unwrap(),
+               b"abbaababbabadaabaababdbaabdaabadbababcaacbaacaddaa".to_vec());
+
+    let stored = [0x01, 0x06, 0x00, 0xf9, 0xff, 0x73, 0x74, 0x6f, 0x72, 0x65, 0x64];
+    assert_eq!(inflate(&stored, 0).unwrap(), b"stored".to_vec());
+
+    assert!(inflate(&dynamic[..20], 0).is_err());
+    assert!(inflate(&[0x07], 0).is_err()); // The reserved block type
+}
+
+#[test]
+fn compressed_data_round_trips() {
+    let mut data: Vec<u8> = b"uniform sampler2D albedo;\n".iter().cycle().take(4000).cloned().collect();
+    data.extend((0..3000u32).map(|i| (i * 7919 % 251) as u8));
+
+    let compressed = deflate(&data);
+    assert!(compressed.len() < data.len() / 2);
+    assert_eq!(inflate(&compressed, data.len()).unwrap(), data);
+
+    assert_eq!(inflate(&deflate(&[]), 0).unwrap(), vec![]);
+    assert_eq!(inflate(&deflate(b"ab"), 0).unwrap(), b"ab".to_vec());
+}
+
+#[test]
+fn archived_resources_are_decompressed_on_first_access() {
+    let shader = b"void main() {}\nvoid main() {}\nvoid main() {}\nvoid main() {}\n".to_vec();
+    let noise = vec![3u8, 141, 59, 26];
+    let archive = build_resource_archive(&[("shader.glsl", &shader), ("noise.bin", &noise)]);
+    let archive: &'static [u8] = Box::leak(archive.into_boxed_slice());
+
+    let resources = CompressedResources::new(archive).unwrap();
+    assert_eq!(resources.names(), vec!["noise.bin", "shader.glsl"]);
+    assert!(!resources.is_decompressed("shader.glsl"));
+
+    assert_eq!(resources.resource("shader.glsl").unwrap().to_vec(), shader);
+    assert!(resources.is_decompressed("shader.glsl"));
+    assert_eq!(resources.resource("noise.bin").unwrap().to_vec(), noise);
+    assert!(!resources.is_decompressed("noise.bin")); // Stored, as it would not shrink
+    assert!(resources.resource("missing").is_none());
+
+    assert!(CompressedResources::new(&archive[..archive.len() - 1]).is_err());
+    assert!(CompressedResources::new(b"not an archive").is_err());
+}
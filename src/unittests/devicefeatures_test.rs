@@ -0,0 +1,141 @@
+// Copyright (c) 2016-2017 Bruce Stenning. All rights reserved.
+//
+// Redistribution and use in source and binary forms, with or without
+// modification, are permitted provided that the following conditions
+// are met:
+//
+// 1. Redistributions of source code must retain the above copyright
+//    notice, this list of conditions and the following disclaimer.
+//
+// 2. Redistributions in binary form must reproduce the above copyright
+//    notice, this list of conditions and the following disclaimer in the
+//    documentation and/or other materials provided with the distribution.
+//
+// 3. Neither the name of the copyright holder nor the names of its
+//    contributors may be used to endorse or promote products derived
+//    from this software without specific prior written permission.
+//
+// THIS SOFTWARE IS PROVIDED BY THE COPYRIGHT HOLDERS AND CONTRIBUTORS
+// AS IS" AND ANY EXPRESS OR IMPLIED WARRANTIES, INCLUDING, BUT NOT
+// LIMITED TO, THE IMPLIED WARRANTIES OF MERCHANTABILITY AND FITNESS
+// FOR A PARTICULAR PURPOSE ARE DISCLAIMED. IN NO EVENT SHALL THE
+// COPYRIGHT HOLDER OR CONTRIBUTORS BE LIABLE FOR ANY DIRECT, INDIRECT,
+// INCIDENTAL, SPECIAL, EXEMPLARY, OR CONSEQUENTIAL DAMAGES (INCLUDING,
+// BUT NOT LIMITED TO, PROCUREMENT OF SUBSTITUTE GOODS OR SERVICES; LOSS
+// OF USE, DATA, OR PROFITS; OR BUSINESS INTERRUPTION) HOWEVER CAUSED
+// AND ON ANY THEORY OF LIABILITY, WHETHER IN CONTRACT, STRICT LIABILITY,
+// OR TORT (INCLUDING NEGLIGENCE OR OTHERWISE) ARISING IN ANY WAY OUT OF
+// THE USE OF THIS SOFTWARE, EVEN IF ADVISED OF THE POSSIBILITY OF SUCH
+// DAMAGE.
+#![allow(unused_imports)]
+
+use graphics::devicefeatures::*;
+use graphics::shader::ShaderStage;
+
+#[test]
+fn flags_round_trip_in_declaration_order() {
+    let features = DeviceFeatures {
+        geometry_shader: true,
+        shader_float64: true,
+        ..DeviceFeatures::none()
+    };
+    let flags = features.flags();
+    assert_eq!(flags[1], ("geometry_shader", true));
+    assert_eq!(flags[FEATURE_COUNT - 1], ("shader_float64", true));
+
+    let mut states = [false; FEATURE_COUNT];
+    for (i, &(_, set)) in flags.iter().enumerate() {
+        states[i] = set;
+    }
+    assert_eq!(DeviceFeatures::from_flags(&states), features);
+    assert_eq!(DeviceFeatures::all().count(), FEATURE_COUNT as u32);
+    assert_eq!(features.count(), 2);
+}
+
+#[test]
+fn missing_required_features_reject_the_device() {
+    let request = FeatureRequest {
+        required: DeviceFeatures {
+            tessellation_shader: true,
+            geometry_shader: true,
+            ..DeviceFeatures::none()
+        },
+        optional: DeviceFeatures::none(),
+    };
+    let available = DeviceFeatures {
+        geometry_shader: true,
+        sampler_anisotropy: true,
+        ..DeviceFeatures::none()
+    };
+    assert_eq!(request.resolve(&available), Err(vec!["tessellation_shader"]));
+}
+
+#[test]
+fn optional_features_are_granted_where_available() {
+    let request = FeatureRequest {
+        required: DeviceFeatures {
+            tessellation_shader: true,
+            ..DeviceFeatures::none()
+        },
+        optional: DeviceFeatures {
+            wide_lines: true,
+            depth_clamp: true,
+            ..DeviceFeatures::none()
+        },
+    };
+    let available = DeviceFeatures {
+        tessellation_shader: true,
+        depth_clamp: true,
+        shader_float64: true,
+        ..DeviceFeatures::none()
+    };
+
+    let granted = request.resolve(&available).unwrap();
+    assert!(granted.tessellation_shader && granted.depth_clamp);
+    assert!(!granted.wide_lines && !granted.shader_float64);
+    assert_eq!(request.optional_score(&available), 1);
+    assert_eq!(request.optional_score(&DeviceFeatures::all()), 2);
+}
+
+#[test]
+fn devices_without_geometry_or_tessellation_are_not_refused_or_marked_down() {
+    let request = FeatureRequest::default();
+    let available = DeviceFeatures {
+        sampler_anisotropy: true,
+        ..DeviceFeatures::none()
+    };
+    let granted = request.resolve(&available).unwrap();
+    assert!(!granted.tessellation_shader && !granted.geometry_shader);
+
+    let with_stages = DeviceFeatures {
+        tessellation_shader: true,
+        geometry_shader: true,
+        ..available
+    };
+    assert_eq!(request.optional_score(&with_stages), request.optional_score(&available));
+
+    assert!(granted.supports_stage(ShaderStage::VertexShader));
+    assert!(!granted.supports_stage(ShaderStage::TessEvalShader));
+    let stages = [ShaderStage::VertexShader,
+                  ShaderStage::TessControlShader,
+                  ShaderStage::TessEvalShader,
+                  ShaderStage::GeometryShader,
+                  ShaderStage::FragmentShader];
+    assert_eq!(granted.missing_stages(stages.iter().cloned()),
+               vec![ShaderStage::TessControlShader, ShaderStage::TessEvalShader, ShaderStage::GeometryShader]);
+    assert!(with_stages.missing_stages(stages.iter().cloned()).is_empty());
+}
+
+#[test]
+fn device_limits_bound_image_sizes_and_dispatches() {
+    let limits = DeviceLimits {
+        max_image_dimension_2d: 4096,
+        max_compute_work_group_count: [65535, 65535, 64],
+        ..DeviceLimits::default()
+    };
+    assert!(limits.fits_image_2d(4096, 1));
+    assert!(!limits.fits_image_2d(1, 4097));
+    assert!(limits.fits_dispatch(65535, 1, 64));
+    assert!(!limits.fits_dispatch(1, 1, 65));
+    assert!(!DeviceLimits::default().fits_dispatch(1, 1, 1));
+}
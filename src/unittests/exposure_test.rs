@@ -0,0 +1,91 @@
+// Copyright (c) 2016-2017 Bruce Stenning. All rights reserved.
+//
+// Redistribution and use in source and binary forms, with or without
+// modification, are permitted provided that the following conditions
+// are met:
+//
+// 1. Redistributions of source code must retain the above copyright
+//    notice, this list of conditions and the following disclaimer.
+//
+// 2. Redistributions in binary form must reproduce the above copyright
+//    notice, this list of conditions and the following disclaimer in the
+//    documentation and/or other materials provided with the distribution.
+//
+// 3. Neither the name of the copyright holder nor the names of its
+//    contributors may be used to endorse or promote products derived
+//    from this software without specific prior written permission.
+//
+// THIS SOFTWARE IS PROVIDED BY THE COPYRIGHT HOLDERS AND CONTRIBUTORS
+// AS IS" AND ANY EXPRESS OR IMPLIED WARRANTIES, INCLUDING, BUT NOT
+// LIMITED TO, THE IMPLIED WARRANTIES OF MERCHANTABILITY AND FITNESS
+// FOR A PARTICULAR PURPOSE ARE DISCLAIMED. IN NO EVENT SHALL THE
+// COPYRIGHT HOLDER OR CONTRIBUTORS BE LIABLE FOR ANY DIRECT, INDIRECT,
+// INCIDENTAL, SPECIAL, EXEMPLARY, OR CONSEQUENTIAL DAMAGES (INCLUDING,
+// BUT NOT LIMITED TO, PROCUREMENT OF SUBSTITUTE GOODS OR SERVICES; LOSS
+// OF USE, DATA, OR PROFITS; OR BUSINESS INTERRUPTION) HOWEVER CAUSED
+// AND ON ANY THEORY OF LIABILITY, WHETHER IN CONTRACT, STRICT LIABILITY,
+// OR TORT (INCLUDING NEGLIGENCE OR OTHERWISE) ARISING IN ANY WAY OUT OF
+// THE USE OF THIS SOFTWARE, EVEN IF ADVISED OF THE POSSIBILITY OF SUCH
+// DAMAGE.
+#![allow(unused_imports)]
+
+use algebra::vector::Vec2;
+use graphics::exposure::*;
+use graphics::image::Image;
+
+#[test]
+fn histogram_average_ignores_the_extremes() {
+    let mut histogram = LuminanceHistogram::new();
+    assert_eq!(histogram.average_log_luminance(0.0, 1.0), None);
+
+    // Mostly mid-grey, with a little black and a little very bright
+    histogram.add(-2.0, 90.0);
+    histogram.add(EXPOSURE_MIN_LOG_LUMINANCE, 5.0);
+    histogram.add(EXPOSURE_MAX_LOG_LUMINANCE, 5.0);
+
+    let all = histogram.average_log_luminance(0.0, 1.0).unwrap();
+    let trimmed = histogram.average_log_luminance(0.05, 0.95).unwrap();
+    let bin_width = (EXPOSURE_MAX_LOG_LUMINANCE - EXPOSURE_MIN_LOG_LUMINANCE) / EXPOSURE_HISTOGRAM_BINS as f32;
+    assert!((trimmed + 2.0).abs() <= bin_width);
+    assert!((all - trimmed).abs() < 1.0);
+
+    // Spot metering only counts the centre
+    let centre = Vec2 { x: 0.5f32, y: 0.5f32 };
+    let corner = Vec2 { x: 0.0f32, y: 0.0f32 };
+    assert_eq!(MeteringMode::Spot.weight(corner), 0.0);
+    assert_eq!(MeteringMode::Spot.weight(centre), 1.0);
+    assert!(MeteringMode::CentreWeighted.weight(corner) < MeteringMode::CentreWeighted.weight(centre));
+}
+
+#[test]
+fn exposure_adapts_towards_the_metered_scene() {
+    let mut auto_exposure = AutoExposure::new(ExposureSettings::default());
+    assert_eq!(auto_exposure.exposure(), 1.0);
+
+    // A uniformly mid-grey metering image is exposed to the key value
+    let key_log_luminance = 0.18f32.log2();
+    let scaled = (key_log_luminance - EXPOSURE_MIN_LOG_LUMINANCE) /
+                 (EXPOSURE_MAX_LOG_LUMINANCE - EXPOSURE_MIN_LOG_LUMINANCE);
+    let byte = (scaled * 255.0).round() as u8;
+    let image = Image::create_from_raw_data(4, 4, &vec![byte; 4 * 4 * 3]);
+    let histogram = LuminanceHistogram::from_metering_image(&image, MeteringMode::Average);
+    auto_exposure.meter(&histogram);
+    auto_exposure.adapt(0.016);
+    let settled = auto_exposure.exposure();
+    assert!((settled - 1.0).abs() < 0.25);
+
+    // A scene four stops brighter is adapted to gradually
+    let mut brighter = LuminanceHistogram::new();
+    brighter.add(key_log_luminance + 4.0, 1.0);
+    auto_exposure.meter(&brighter);
+    auto_exposure.adapt(0.1);
+    let adapting = auto_exposure.exposure();
+    assert!(adapting < settled && adapting > settled / 16.0);
+    for _ in 0..100 {
+        auto_exposure.adapt(0.1);
+    }
+    assert!((auto_exposure.exposure() * 16.0 / settled - 1.0).abs() < 0.25);
+
+    auto_exposure.set_manual_exposure(Some(2.0));
+    assert_eq!(auto_exposure.exposure(), 2.0);
+}
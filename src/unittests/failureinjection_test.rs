@@ -0,0 +1,66 @@
+// Copyright (c) 2016-2017 Bruce Stenning. All rights reserved.
+//
+// Redistribution and use in source and binary forms, with or without
+// modification, are permitted provided that the following conditions
+// are met:
+//
+// 1. Redistributions of source code must retain the above copyright
+//    notice, this list of conditions and the following disclaimer.
+//
+// 2. Redistributions in binary form must reproduce the above copyright
+//    notice, this list of conditions and the following disclaimer in the
+//    documentation and/or other materials provided with the distribution.
+//
+// 3. Neither the name of the copyright holder nor the names of its
+//    contributors may be used to endorse or promote products derived
+//    from this software without specific prior written permission.
+//
+// THIS SOFTWARE IS PROVIDED BY THE COPYRIGHT HOLDERS AND CONTRIBUTORS
+// AS IS" AND ANY EXPRESS OR IMPLIED WARRANTIES, INCLUDING, BUT NOT
+// LIMITED TO, THE IMPLIED WARRANTIES OF MERCHANTABILITY AND FITNESS
+// FOR A PARTICULAR PURPOSE ARE DISCLAIMED. IN NO EVENT SHALL THE
+// COPYRIGHT HOLDER OR CONTRIBUTORS BE LIABLE FOR ANY DIRECT, INDIRECT,
+// INCIDENTAL, SPECIAL, EXEMPLARY, OR CONSEQUENTIAL DAMAGES (INCLUDING,
+// BUT NOT LIMITED TO, PROCUREMENT OF SUBSTITUTE GOODS OR SERVICES; LOSS
+// OF USE, DATA, OR PROFITS; OR BUSINESS INTERRUPTION) HOWEVER CAUSED
+// AND ON ANY THEORY OF LIABILITY, WHETHER IN CONTRACT, STRICT LIABILITY,
+// OR TORT (INCLUDING NEGLIGENCE OR OTHERWISE) ARISING IN ANY WAY OUT OF
+// THE USE OF THIS SOFTWARE, EVEN IF ADVISED OF THE POSSIBILITY OF SUCH
+// DAMAGE.
+#![allow(unused_imports)]
+
+use graphics::failureinjection::*;
+
+#[test]
+fn failure_plan_fails_targeted_calls_after_skipping() {
+    let mut plan = FailurePlan::new();
+    plan.fail_call("vkQueueSubmit", 2, 1, InjectedFailure::DeviceLost);
+
+    assert_eq!(plan.next_failure("vkQueueSubmit"), None);
+    assert_eq!(plan.next_failure("vkAllocateMemory"), None);
+    assert_eq!(plan.next_failure("vkQueueSubmit"), None);
+    assert_eq!(plan.next_failure("vkQueueSubmit"), Some(InjectedFailure::DeviceLost));
+    assert_eq!(plan.next_failure("vkQueueSubmit"), None);
+    assert_eq!(plan.injected(), 1);
+}
+
+#[test]
+fn failure_plan_random_failures_follow_the_probability() {
+    let mut plan = FailurePlan::new();
+    plan.fail_randomly(1.0, 7);
+    assert_eq!(plan.next_failure("vkAcquireNextImageKHR"), Some(InjectedFailure::OutOfDate));
+    assert_eq!(plan.next_failure("vkAllocateMemory"), Some(InjectedFailure::OutOfMemory));
+
+    plan.fail_randomly(0.25, 7);
+    let failures = (0..1000).filter(|_| plan.next_failure("vkCreateBuffer").is_some()).count();
+    assert!(failures > 150 && failures < 350);
+
+    // The same seed repeats the same failures
+    let mut first = FailurePlan::new();
+    let mut second = FailurePlan::new();
+    first.fail_randomly(0.5, 42);
+    second.fail_randomly(0.5, 42);
+    for _ in 0..100 {
+        assert_eq!(first.next_failure("vkCreateImage"), second.next_failure("vkCreateImage"));
+    }
+}
@@ -0,0 +1,69 @@
+// Copyright (c) 2016-2017 Bruce Stenning. All rights reserved.
+//
+// Redistribution and use in source and binary forms, with or without
+// modification, are permitted provided that the following conditions
+// are met:
+//
+// 1. Redistributions of source code must retain the above copyright
+//    notice, this list of conditions and the following disclaimer.
+//
+// 2. Redistributions in binary form must reproduce the above copyright
+//    notice, this list of conditions and the following disclaimer in the
+//    documentation and/or other materials provided with the distribution.
+//
+// 3. Neither the name of the copyright holder nor the names of its
+//    contributors may be used to endorse or promote products derived
+//    from this software without specific prior written permission.
+//
+// THIS SOFTWARE IS PROVIDED BY THE COPYRIGHT HOLDERS AND CONTRIBUTORS
+// AS IS" AND ANY EXPRESS OR IMPLIED WARRANTIES, INCLUDING, BUT NOT
+// LIMITED TO, THE IMPLIED WARRANTIES OF MERCHANTABILITY AND FITNESS
+// FOR A PARTICULAR PURPOSE ARE DISCLAIMED. IN NO EVENT SHALL THE
+// COPYRIGHT HOLDER OR CONTRIBUTORS BE LIABLE FOR ANY DIRECT, INDIRECT,
+// INCIDENTAL, SPECIAL, EXEMPLARY, OR CONSEQUENTIAL DAMAGES (INCLUDING,
+// BUT NOT LIMITED TO, PROCUREMENT OF SUBSTITUTE GOODS OR SERVICES; LOSS
+// OF USE, DATA, OR PROFITS; OR BUSINESS INTERRUPTION) HOWEVER CAUSED
+// AND ON ANY THEORY OF LIABILITY, WHETHER IN CONTRACT, STRICT LIABILITY,
+// OR TORT (INCLUDING NEGLIGENCE OR OTHERWISE) ARISING IN ANY WAY OUT OF
+// THE USE OF THIS SOFTWARE, EVEN IF ADVISED OF THE POSSIBILITY OF SUCH
+// DAMAGE.
+
+#![allow(unused_imports)]
+
+use graphics::frametime::*;
+
+#[test]
+fn frame_clock_scales_and_pauses_engine_time_only() {
+    let mut clock = FrameClock::new();
+    clock.advance(0.1);
+    assert_eq!(clock.get_delta(), 0.1);
+
+    clock.set_time_scale(0.5);
+    clock.advance(0.1);
+    assert_eq!(clock.get_delta(), 0.05);
+    assert_eq!(clock.get_real_delta(), 0.1);
+
+    clock.pause();
+    clock.advance(0.1);
+    assert_eq!(clock.get_delta(), 0.0);
+    assert!((clock.get_time() - 0.15).abs() < 1.0e-6);
+    assert!((clock.get_real_time() - 0.3).abs() < 1.0e-6);
+}
+
+#[test]
+fn frame_clock_steps_while_paused_and_clamps_hitches() {
+    let mut clock = FrameClock::new();
+    clock.pause();
+    clock.step(1.0 / 60.0);
+    clock.advance(0.5);
+    assert_eq!(clock.get_delta(), 1.0 / 60.0);
+
+    // The step only applies to one tick
+    clock.advance(0.5);
+    assert_eq!(clock.get_delta(), 0.0);
+
+    clock.resume();
+    clock.advance(10.0);
+    assert_eq!(clock.get_delta(), DEFAULT_MAX_DELTA_TIME);
+    assert_eq!(clock.get_real_delta(), 10.0);
+}
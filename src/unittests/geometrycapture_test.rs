@@ -0,0 +1,101 @@
+// Copyright (c) 2016-2017 Bruce Stenning. All rights reserved.
+//
+// Redistribution and use in source and binary forms, with or without
+// modification, are permitted provided that the following conditions
+// are met:
+//
+// 1. Redistributions of source code must retain the above copyright
+//    notice, this list of conditions and the following disclaimer.
+//
+// 2. Redistributions in binary form must reproduce the above copyright
+//    notice, this list of conditions and the following disclaimer in the
+//    documentation and/or other materials provided with the distribution.
+//
+// 3. Neither the name of the copyright holder nor the names of its
+//    contributors may be used to endorse or promote products derived
+//    from this software without specific prior written permission.
+//
+// THIS SOFTWARE IS PROVIDED BY THE COPYRIGHT HOLDERS AND CONTRIBUTORS
+// AS IS" AND ANY EXPRESS OR IMPLIED WARRANTIES, INCLUDING, BUT NOT
+// LIMITED TO, THE IMPLIED WARRANTIES OF MERCHANTABILITY AND FITNESS
+// FOR A PARTICULAR PURPOSE ARE DISCLAIMED. IN NO EVENT SHALL THE
+// COPYRIGHT HOLDER OR CONTRIBUTORS BE LIABLE FOR ANY DIRECT, INDIRECT,
+// INCIDENTAL, SPECIAL, EXEMPLARY, OR CONSEQUENTIAL DAMAGES (INCLUDING,
+// BUT NOT LIMITED TO, PROCUREMENT OF SUBSTITUTE GOODS OR SERVICES; LOSS
+// OF USE, DATA, OR PROFITS; OR BUSINESS INTERRUPTION) HOWEVER CAUSED
+// AND ON ANY THEORY OF LIABILITY, WHETHER IN CONTRACT, STRICT LIABILITY,
+// OR TORT (INCLUDING NEGLIGENCE OR OTHERWISE) ARISING IN ANY WAY OUT OF
+// THE USE OF THIS SOFTWARE, EVEN IF ADVISED OF THE POSSIBILITY OF SUCH
+// DAMAGE.
+
+#![allow(unused_imports)]
+
+use graphics::geometrycapture::*;
+use graphics::renderer::*;
+
+#[cfg(test)]
+fn encode_capture(streams: &Vec<GeometryStream>) -> Vec<u8> {
+    let mut bytes = b"WYGC".to_vec();
+    bytes.extend_from_slice(&[1, 0, 0, 0]);
+    for stream in streams.iter() {
+        stream.encode(&mut bytes);
+    }
+    bytes
+}
+
+#[test]
+fn geometry_capture_streams_round_trip() {
+    let streams = vec![GeometryStream {
+                           frame: 1,
+                           thread: 0,
+                           vertex_array_type: VertexArrayType::F3,
+                           primitive: PrimitiveType::PrimitiveTriangles,
+                           triangles: 1,
+                           data: vec![1.0f32, -2.0f32, 3.5f32, 0.0f32, 1.0e-7f32, 4.0f32, 8.0f32, 9.0f32, -0.25f32],
+                       },
+                       GeometryStream {
+                           frame: (1u64 << 40) + 2,
+                           thread: 3,
+                           vertex_array_type: VertexArrayType::F2F2,
+                           primitive: PrimitiveType::PrimitivePatches,
+                           triangles: 2,
+                           data: (0..24).map(|i| i as f32 * 0.5f32).collect(),
+                       },
+                       GeometryStream {
+                           frame: (1u64 << 40) + 2,
+                           thread: 1,
+                           vertex_array_type: VertexArrayType::F3F3,
+                           primitive: PrimitiveType::PrimitiveLines,
+                           triangles: 2,
+                           data: (0..24).map(|i| i as f32).collect(),
+                       }];
+
+    let replay = GeometryReplay::decode(&encode_capture(&streams)).unwrap();
+    let decoded = replay.get_streams();
+    assert_eq!(decoded.len(), 3);
+    for (a, b) in streams.iter().zip(decoded.iter()) {
+        assert_eq!(a.frame, b.frame);
+        assert_eq!(a.thread, b.thread);
+        assert!(a.vertex_array_type == b.vertex_array_type);
+        assert_eq!(a.primitive as u32, b.primitive as u32);
+        assert_eq!(a.triangles, b.triangles);
+        assert_eq!(a.data, b.data);
+    }
+    assert_eq!(replay.frames(), vec![1, (1u64 << 40) + 2]);
+}
+
+#[test]
+fn geometry_capture_rejects_truncated_and_foreign_files() {
+    let stream = GeometryStream {
+        frame: 1,
+        thread: 0,
+        vertex_array_type: VertexArrayType::F3F3,
+        primitive: PrimitiveType::PrimitiveTriangles,
+        triangles: 1,
+        data: vec![0.0f32; 18],
+    };
+    let mut bytes = encode_capture(&vec![stream]);
+    bytes.pop();
+    assert!(GeometryReplay::decode(&bytes).is_err());
+    assert!(GeometryReplay::decode(&b"PNG\0\x01\0\0\0".to_vec()).is_err());
+}
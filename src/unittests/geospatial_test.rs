@@ -0,0 +1,96 @@
+// Copyright (c) 2016-2017 Bruce Stenning. All rights reserved.
+//
+// Redistribution and use in source and binary forms, with or without
+// modification, are permitted provided that the following conditions
+// are met:
+//
+// 1. Redistributions of source code must retain the above copyright
+//    notice, this list of conditions and the following disclaimer.
+//
+// 2. Redistributions in binary form must reproduce the above copyright
+//    notice, this list of conditions and the following disclaimer in the
+//    documentation and/or other materials provided with the distribution.
+//
+// 3. Neither the name of the copyright holder nor the names of its
+//    contributors may be used to endorse or promote products derived
+//    from this software without specific prior written permission.
+//
+// THIS SOFTWARE IS PROVIDED BY THE COPYRIGHT HOLDERS AND CONTRIBUTORS
+// AS IS" AND ANY EXPRESS OR IMPLIED WARRANTIES, INCLUDING, BUT NOT
+// LIMITED TO, THE IMPLIED WARRANTIES OF MERCHANTABILITY AND FITNESS
+// FOR A PARTICULAR PURPOSE ARE DISCLAIMED. IN NO EVENT SHALL THE
+// COPYRIGHT HOLDER OR CONTRIBUTORS BE LIABLE FOR ANY DIRECT, INDIRECT,
+// INCIDENTAL, SPECIAL, EXEMPLARY, OR CONSEQUENTIAL DAMAGES (INCLUDING,
+// BUT NOT LIMITED TO, PROCUREMENT OF SUBSTITUTE GOODS OR SERVICES; LOSS
+// OF USE, DATA, OR PROFITS; OR BUSINESS INTERRUPTION) HOWEVER CAUSED
+// AND ON ANY THEORY OF LIABILITY, WHETHER IN CONTRACT, STRICT LIABILITY,
+// OR TORT (INCLUDING NEGLIGENCE OR OTHERWISE) ARISING IN ANY WAY OUT OF
+// THE USE OF THIS SOFTWARE, EVEN IF ADVISED OF THE POSSIBILITY OF SUCH
+// DAMAGE.
+
+#![allow(unused_imports)]
+
+use algebra::vector::Vec3;
+use algebra::geospatial::*;
+
+#[test]
+fn geodetic_to_ecef_on_the_equator_at_the_prime_meridian_is_the_semi_major_axis() {
+    let p = geodetic_to_ecef(0.0, 0.0, 0.0);
+    assert!((p.x - WGS84_SEMI_MAJOR_AXIS).abs() < 1.0e-6);
+    assert!(p.y.abs() < 1.0e-6);
+    assert!(p.z.abs() < 1.0e-6);
+}
+
+#[test]
+fn ecef_to_geodetic_round_trips_to_within_a_millimetre() {
+    for &(latitude, longitude, height) in &[(51.4778, -0.0014, 45.0), (-33.8568, 151.2153, 5.0), (89.9, 10.0, 2500.0)] {
+        let (lat2, lon2, height2) = ecef_to_geodetic(&geodetic_to_ecef(latitude, longitude, height));
+        println!("expected {} {} {}, result {} {} {}", latitude, longitude, height, lat2, lon2, height2);
+
+        // A millimetre is about 1e-8 degrees of latitude
+        assert!((lat2 - latitude).abs() < 1.0e-8);
+        assert!((lon2 - longitude).abs() < 1.0e-8);
+        assert!((height2 - height).abs() < 1.0e-3);
+    }
+}
+
+#[test]
+fn enu_frame_maps_a_point_above_the_origin_to_up() {
+    let frame = EnuFrame::new(45.0, 90.0, 0.0);
+    let above = geodetic_to_ecef(45.0, 90.0, 100.0);
+    let enu = frame.ecef_to_enu(&above);
+    println!("result is {} {} {}", enu.x, enu.y, enu.z);
+    assert!(enu.x.abs() < 1.0e-6);
+    assert!(enu.y.abs() < 1.0e-6);
+    assert!((enu.z - 100.0).abs() < 1.0e-6);
+
+    let back = frame.enu_to_ecef(&enu);
+    assert!((back.x - above.x).abs() < 1.0e-6);
+    assert!((back.y - above.y).abs() < 1.0e-6);
+    assert!((back.z - above.z).abs() < 1.0e-6);
+}
+
+#[test]
+fn camera_relative_transform_keeps_centimetre_precision_at_planetary_distances() {
+    let camera = geodetic_to_ecef(40.0, -105.0, 1600.0);
+    let chunk_origin = Vec3 {
+        x: camera.x + 1000.0,
+        y: camera.y,
+        z: camera.z,
+    };
+    let vertex = Vec3 {
+        x: chunk_origin.x + 12.345,
+        y: chunk_origin.y - 6.789,
+        z: chunk_origin.z + 0.01,
+    };
+
+    let transform = CameraRelativeTransform::new(camera);
+    let residuals = CameraRelativeTransform::residuals(&chunk_origin, &[vertex]);
+    let relative = transform.chunk_matrix(&chunk_origin).mul_by_vec3(residuals[0]).project();
+
+    let expected = &vertex - &camera;
+    println!("result is {}, expected is {} {} {}", relative, expected.x, expected.y, expected.z);
+    assert!((relative.x as f64 - expected.x).abs() < 0.01);
+    assert!((relative.y as f64 - expected.y).abs() < 0.01);
+    assert!((relative.z as f64 - expected.z).abs() < 0.01);
+}
@@ -0,0 +1,88 @@
+// Copyright (c) 2016-2017 Bruce Stenning. All rights reserved.
+//
+// Redistribution and use in source and binary forms, with or without
+// modification, are permitted provided that the following conditions
+// are met:
+//
+// 1. Redistributions of source code must retain the above copyright
+//    notice, this list of conditions and the following disclaimer.
+//
+// 2. Redistributions in binary form must reproduce the above copyright
+//    notice, this list of conditions and the following disclaimer in the
+//    documentation and/or other materials provided with the distribution.
+//
+// 3. Neither the name of the copyright holder nor the names of its
+//    contributors may be used to endorse or promote products derived
+//    from this software without specific prior written permission.
+//
+// THIS SOFTWARE IS PROVIDED BY THE COPYRIGHT HOLDERS AND CONTRIBUTORS
+// AS IS" AND ANY EXPRESS OR IMPLIED WARRANTIES, INCLUDING, BUT NOT
+// LIMITED TO, THE IMPLIED WARRANTIES OF MERCHANTABILITY AND FITNESS
+// FOR A PARTICULAR PURPOSE ARE DISCLAIMED. IN NO EVENT SHALL THE
+// COPYRIGHT HOLDER OR CONTRIBUTORS BE LIABLE FOR ANY DIRECT, INDIRECT,
+// INCIDENTAL, SPECIAL, EXEMPLARY, OR CONSEQUENTIAL DAMAGES (INCLUDING,
+// BUT NOT LIMITED TO, PROCUREMENT OF SUBSTITUTE GOODS OR SERVICES; LOSS
+// OF USE, DATA, OR PROFITS; OR BUSINESS INTERRUPTION) HOWEVER CAUSED
+// AND ON ANY THEORY OF LIABILITY, WHETHER IN CONTRACT, STRICT LIABILITY,
+// OR TORT (INCLUDING NEGLIGENCE OR OTHERWISE) ARISING IN ANY WAY OUT OF
+// THE USE OF THIS SOFTWARE, EVEN IF ADVISED OF THE POSSIBILITY OF SUCH
+// DAMAGE.
+#![allow(unused_imports)]
+
+use std::collections::HashMap;
+
+use graphics::glslinclude::*;
+
+#[cfg(test)]
+fn expand_all(files: &HashMap<&'static str, &'static str>, filename: &str) -> (Result<String, ::std::io::Error>, Vec<String>) {
+    let mut preprocessor = GlslPreprocessor::new();
+    let result = preprocessor.expand(filename, &mut |name: &str| files.get(name).map(|s| s.to_string()));
+    (result, preprocessor.files().clone())
+}
+
+#[test]
+fn nested_includes_are_expanded_with_line_directives() {
+    let mut files = HashMap::new();
+    files.insert("shaders/main.frag", "void f();\n#include \"common/light.glsl\"\nvoid main() {}\n");
+    files.insert("shaders/common/light.glsl", "  #  include <../maths.glsl>\nfloat light;\n");
+    files.insert("shaders/maths.glsl", "float pi;\n");
+
+    let (result, read) = expand_all(&files, "shaders/main.frag");
+    assert_eq!(read, vec!["shaders/main.frag", "shaders/common/light.glsl", "shaders/maths.glsl"]);
+    assert_eq!(result.unwrap(),
+               "#line 1 0\nvoid f();\n\
+                #line 1 1\n\
+                #line 1 2\nfloat pi;\n#line 2 1\nfloat light;\n\
+                #line 3 0\nvoid main() {}\n");
+}
+
+#[test]
+fn cycles_and_missing_files_are_reported() {
+    let mut files = HashMap::new();
+    files.insert("a.glsl", "#include \"b.glsl\"\n");
+    files.insert("b.glsl", "#include \"a.glsl\"\n");
+    files.insert("c.glsl", "#include \"missing.glsl\"\n");
+
+    let (result, _) = expand_all(&files, "a.glsl");
+    let error = result.unwrap_err();
+    assert!(error.to_string().contains("a.glsl -> b.glsl -> a.glsl"));
+
+    // The files read before the failure are still recorded, for watching
+    let (result, read) = expand_all(&files, "c.glsl");
+    assert!(result.unwrap_err().to_string().contains("included from c.glsl"));
+    assert_eq!(read, vec!["c.glsl", "missing.glsl"]);
+}
+
+#[test]
+fn directives_and_paths_are_parsed() {
+    assert_eq!(parse_include("#include \"a.glsl\""), Some("a.glsl"));
+    assert_eq!(parse_include("\t# include <b.glsl> // comment"), Some("b.glsl"));
+    assert_eq!(parse_include("#include a.glsl"), None);
+    assert_eq!(parse_include("#include \"\""), None);
+    assert_eq!(parse_include("// #include \"a.glsl\""), None);
+
+    assert_eq!(resolve_include_path("shaders/a/b.glsl", "../c.glsl"), "shaders/c.glsl");
+    assert_eq!(resolve_include_path("b.glsl", "./x/../c.glsl"), "c.glsl");
+    assert_eq!(resolve_include_path("shaders/b.glsl", "../../c.glsl"), "../c.glsl");
+    assert_eq!(resolve_include_path("shaders/b.glsl", "/abs/c.glsl"), "/abs/c.glsl");
+}
@@ -0,0 +1,98 @@
+// Copyright (c) 2016-2017 Bruce Stenning. All rights reserved.
+//
+// Redistribution and use in source and binary forms, with or without
+// modification, are permitted provided that the following conditions
+// are met:
+//
+// 1. Redistributions of source code must retain the above copyright
+//    notice, this list of conditions and the following disclaimer.
+//
+// 2. Redistributions in binary form must reproduce the above copyright
+//    notice, this list of conditions and the following disclaimer in the
+//    documentation and/or other materials provided with the distribution.
+//
+// 3. Neither the name of the copyright holder nor the names of its
+//    contributors may be used to endorse or promote products derived
+//    from this software without specific prior written permission.
+//
+// THIS SOFTWARE IS PROVIDED BY THE COPYRIGHT HOLDERS AND CONTRIBUTORS
+// AS IS" AND ANY EXPRESS OR IMPLIED WARRANTIES, INCLUDING, BUT NOT
+// LIMITED TO, THE IMPLIED WARRANTIES OF MERCHANTABILITY AND FITNESS
+// FOR A PARTICULAR PURPOSE ARE DISCLAIMED. IN NO EVENT SHALL THE
+// COPYRIGHT HOLDER OR CONTRIBUTORS BE LIABLE FOR ANY DIRECT, INDIRECT,
+// INCIDENTAL, SPECIAL, EXEMPLARY, OR CONSEQUENTIAL DAMAGES (INCLUDING,
+// BUT NOT LIMITED TO, PROCUREMENT OF SUBSTITUTE GOODS OR SERVICES; LOSS
+// OF USE, DATA, OR PROFITS; OR BUSINESS INTERRUPTION) HOWEVER CAUSED
+// AND ON ANY THEORY OF LIABILITY, WHETHER IN CONTRACT, STRICT LIABILITY,
+// OR TORT (INCLUDING NEGLIGENCE OR OTHERWISE) ARISING IN ANY WAY OUT OF
+// THE USE OF THIS SOFTWARE, EVEN IF ADVISED OF THE POSSIBILITY OF SUCH
+// DAMAGE.
+#![allow(unused_imports)]
+
+use graphics::gpusort::*;
+
+/// Return pseudo-random keys below a bound, with each value recording the key's original index
+#[cfg(test)]
+fn random_pairs(count: usize, bound: u32) -> (Vec<u32>, Vec<u32>) {
+    let mut state = 0x2545f491u32;
+    let keys = (0..count)
+        .map(|_| {
+            state ^= state << 13;
+            state ^= state >> 17;
+            state ^= state << 5;
+            state % bound
+        })
+        .collect();
+    (keys, (0..count as u32).collect())
+}
+
+#[test]
+fn sort_method_depends_on_count_and_compute() {
+    assert_eq!(choose_sort_method(100000, false), SortMethod::Cpu);
+    assert_eq!(choose_sort_method(CPU_SORT_MAX_ELEMENTS, true), SortMethod::Cpu);
+    assert_eq!(choose_sort_method(CPU_SORT_MAX_ELEMENTS + 1, true), SortMethod::Bitonic);
+    assert_eq!(choose_sort_method(BITONIC_MAX_ELEMENTS + 1, true), SortMethod::Radix);
+    assert_eq!(choose_gpu_sort_method(10), SortMethod::Bitonic);
+
+    // A padding dispatch, then 1 + 2 + ... + 11 merge steps for 2048 pairs
+    let bitonic = SortPlan::new(2000, SortMethod::Bitonic);
+    assert_eq!(bitonic.dispatches.len(), 1 + 66);
+    assert_eq!(bitonic.dispatches[0].stage, 0);
+    assert_eq!(bitonic.dispatches[1].workgroups, 8);
+
+    // Count, scan and scatter for each of the eight digits, ending back in the pair arrays
+    let radix = SortPlan::new(10000, SortMethod::Radix);
+    assert_eq!(radix.dispatches.len(), 24);
+    assert!(radix.dispatches.last().unwrap().flip);
+    assert_eq!(radix.dispatches[1].shader_name, RADIX_SCAN_SHADER_NAME);
+    assert!(SortPlan::new(10, SortMethod::Cpu).dispatches.is_empty());
+}
+
+#[test]
+fn bitonic_sort_orders_keys_with_values() {
+    for &count in [1usize, 2, 255, 1000, 4096].iter() {
+        let (keys, values) = random_pairs(count, 500);
+        let (sorted_keys, sorted_values) = SortPlan::new(count, SortMethod::Bitonic).emulate(&keys, &values);
+
+        assert_eq!(sorted_keys.len(), count);
+        assert!(sorted_keys.windows(2).all(|w| w[0] <= w[1]));
+        for (key, value) in sorted_keys.iter().zip(sorted_values.iter()) {
+            assert_eq!(keys[*value as usize], *key);
+        }
+    }
+}
+
+#[test]
+fn radix_sort_matches_stable_cpu_sort() {
+    let (mut keys, mut values) = random_pairs(20000, 0xffffffff);
+    keys[7] = 0xffffffff;
+    keys[8] = 0;
+    for key in keys[100..200].iter_mut() {
+        *key = 12345;
+    }
+
+    let (sorted_keys, sorted_values) = SortPlan::new(keys.len(), SortMethod::Radix).emulate(&keys, &values);
+    sort_pairs_cpu(&mut keys, &mut values);
+    assert_eq!(sorted_keys, keys);
+    assert_eq!(sorted_values, values);
+}
@@ -0,0 +1,101 @@
+// Copyright (c) 2016-2017 Bruce Stenning. All rights reserved.
+//
+// Redistribution and use in source and binary forms, with or without
+// modification, are permitted provided that the following conditions
+// are met:
+//
+// 1. Redistributions of source code must retain the above copyright
+//    notice, this list of conditions and the following disclaimer.
+//
+// 2. Redistributions in binary form must reproduce the above copyright
+//    notice, this list of conditions and the following disclaimer in the
+//    documentation and/or other materials provided with the distribution.
+//
+// 3. Neither the name of the copyright holder nor the names of its
+//    contributors may be used to endorse or promote products derived
+//    from this software without specific prior written permission.
+//
+// THIS SOFTWARE IS PROVIDED BY THE COPYRIGHT HOLDERS AND CONTRIBUTORS
+// AS IS" AND ANY EXPRESS OR IMPLIED WARRANTIES, INCLUDING, BUT NOT
+// LIMITED TO, THE IMPLIED WARRANTIES OF MERCHANTABILITY AND FITNESS
+// FOR A PARTICULAR PURPOSE ARE DISCLAIMED. IN NO EVENT SHALL THE
+// COPYRIGHT HOLDER OR CONTRIBUTORS BE LIABLE FOR ANY DIRECT, INDIRECT,
+// INCIDENTAL, SPECIAL, EXEMPLARY, OR CONSEQUENTIAL DAMAGES (INCLUDING,
+// BUT NOT LIMITED TO, PROCUREMENT OF SUBSTITUTE GOODS OR SERVICES; LOSS
+// OF USE, DATA, OR PROFITS; OR BUSINESS INTERRUPTION) HOWEVER CAUSED
+// AND ON ANY THEORY OF LIABILITY, WHETHER IN CONTRACT, STRICT LIABILITY,
+// OR TORT (INCLUDING NEGLIGENCE OR OTHERWISE) ARISING IN ANY WAY OUT OF
+// THE USE OF THIS SOFTWARE, EVEN IF ADVISED OF THE POSSIBILITY OF SUCH
+// DAMAGE.
+#![allow(unused_imports)]
+#![allow(dead_code)]
+
+use graphics::gputext::*;
+
+// Printable ASCII in a grid of 16 by 6 cells
+#[cfg(test)]
+const ASCII_GRID: GlyphGrid = GlyphGrid {
+    columns: 16,
+    rows: 6,
+    first_character: 32,
+    glyph_width: 8.0,
+    glyph_height: 16.0,
+};
+
+#[test]
+fn colours_pack_with_red_in_the_lowest_byte() {
+    assert_eq!(pack_colour([1.0, 0.0, 0.0, 0.0]), 0x000000ff);
+    assert_eq!(pack_colour([0.0, 0.0, 0.0, 1.0]), 0xff000000);
+    assert_eq!(pack_colour([2.0, -1.0, 0.5, 1.0]), 0xff8000ff);
+
+    let mut out = vec![];
+    GlyphInstance {
+            x: 3.0,
+            y: 4.0,
+            glyph: 33,
+            colour: 0xffffffff,
+        }
+        .pack(&mut out);
+    assert_eq!(out.len(), GLYPH_INSTANCE_FLOATS);
+    assert_eq!(out[2].to_bits(), 33);
+    assert_eq!(out[3].to_bits(), 0xffffffff);
+}
+
+#[test]
+fn text_is_laid_out_along_lines_of_fixed_advance() {
+    let mut batch = GpuTextBatch::new(ASCII_GRID);
+    assert_eq!(ASCII_GRID.glyph_of('A'), Some(33));
+    assert_eq!(ASCII_GRID.glyph_of('\u{e9}'), None);
+
+    // The space is the first cell of the atlas, while the character missing from it only advances
+    assert_eq!(batch.add_text(10.0, 20.0, "A B\n\u{e9}C", [1.0, 1.0, 1.0, 1.0]), 4);
+    assert_eq!(batch.len(), 4);
+
+    let packed = batch.packed();
+    let position = |i: usize| (packed[i * GLYPH_INSTANCE_FLOATS], packed[i * GLYPH_INSTANCE_FLOATS + 1]);
+    assert_eq!(position(0), (10.0, 20.0));
+    assert_eq!(position(1), (18.0, 20.0)); // The space
+    assert_eq!(position(2), (26.0, 20.0));
+    assert_eq!(position(3), (18.0, 36.0));
+    assert_eq!(packed[GLYPH_INSTANCE_FLOATS + 2].to_bits(), 0);
+}
+
+#[test]
+fn full_batches_cut_text_short() {
+    let mut batch = GpuTextBatch::new(ASCII_GRID);
+    let glyph = GlyphInstance {
+        x: 0.0,
+        y: 0.0,
+        glyph: 1,
+        colour: 0,
+    };
+    for _ in 0..GPU_TEXT_MAX_GLYPHS - 2 {
+        assert!(batch.add_glyph(glyph));
+    }
+
+    assert_eq!(batch.add_text(0.0, 0.0, "abc", [1.0, 1.0, 1.0, 1.0]), 2);
+    assert!(!batch.add_glyph(glyph));
+
+    batch.clear();
+    assert!(batch.is_empty());
+}
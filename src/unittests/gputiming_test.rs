@@ -0,0 +1,68 @@
+// Copyright (c) 2016-2017 Bruce Stenning. All rights reserved.
+//
+// Redistribution and use in source and binary forms, with or without
+// modification, are permitted provided that the following conditions
+// are met:
+//
+// 1. Redistributions of source code must retain the above copyright
+//    notice, this list of conditions and the following disclaimer.
+//
+// 2. Redistributions in binary form must reproduce the above copyright
+//    notice, this list of conditions and the following disclaimer in the
+//    documentation and/or other materials provided with the distribution.
+//
+// 3. Neither the name of the copyright holder nor the names of its
+//    contributors may be used to endorse or promote products derived
+//    from this software without specific prior written permission.
+//
+// THIS SOFTWARE IS PROVIDED BY THE COPYRIGHT HOLDERS AND CONTRIBUTORS
+// AS IS" AND ANY EXPRESS OR IMPLIED WARRANTIES, INCLUDING, BUT NOT
+// LIMITED TO, THE IMPLIED WARRANTIES OF MERCHANTABILITY AND FITNESS
+// FOR A PARTICULAR PURPOSE ARE DISCLAIMED. IN NO EVENT SHALL THE
+// COPYRIGHT HOLDER OR CONTRIBUTORS BE LIABLE FOR ANY DIRECT, INDIRECT,
+// INCIDENTAL, SPECIAL, EXEMPLARY, OR CONSEQUENTIAL DAMAGES (INCLUDING,
+// BUT NOT LIMITED TO, PROCUREMENT OF SUBSTITUTE GOODS OR SERVICES; LOSS
+// OF USE, DATA, OR PROFITS; OR BUSINESS INTERRUPTION) HOWEVER CAUSED
+// AND ON ANY THEORY OF LIABILITY, WHETHER IN CONTRACT, STRICT LIABILITY,
+// OR TORT (INCLUDING NEGLIGENCE OR OTHERWISE) ARISING IN ANY WAY OUT OF
+// THE USE OF THIS SOFTWARE, EVEN IF ADVISED OF THE POSSIBILITY OF SUCH
+// DAMAGE.
+#![allow(unused_imports)]
+
+use graphics::gputiming::*;
+
+#[test]
+fn timestamps_pair_into_pass_timings() {
+    let names = ["shadow", "scene", "post"];
+    let timestamps = [Some(1000), Some(3000), Some(3000), None, Some(5000), Some(9000)];
+
+    // 500ns ticks, with the second pass's end not yet available
+    let timings = FrameTimings::from_timestamps(7, &names, &timestamps, 500.0);
+    assert_eq!(timings.frame, 7);
+    assert_eq!(timings.unavailable, 1);
+    assert_eq!(timings.passes.len(), 2);
+    assert_eq!(timings.passes[0].shader_name, "shadow");
+    assert_eq!(timings.passes[0].gpu_ms, 1.0);
+    assert_eq!(timings.passes[1].shader_name, "post");
+    assert_eq!(timings.passes[1].gpu_ms, 2.0);
+    assert_eq!(timings.total_ms(), 3.0);
+}
+
+#[test]
+fn history_keeps_the_latest_frames() {
+    let mut history = GpuTimingHistory::new(2);
+    assert!(history.latest().is_none());
+
+    for frame in 0..3 {
+        let end = 1000000 * (frame + 1);
+        history.record(FrameTimings::from_timestamps(frame, &["scene"], &[Some(0), Some(end)], 1.0));
+    }
+    history.record(FrameTimings::from_timestamps(3, &["scene"], &[None, None], 1.0));
+
+    assert_eq!(history.frames().len(), 2);
+    assert_eq!(history.frames()[0].frame, 2);
+    assert_eq!(history.latest().unwrap().frame, 3);
+    assert_eq!(history.average_pass_ms("scene"), Some(3.0));
+    assert_eq!(history.average_pass_ms("post"), None);
+    assert_eq!(history.unavailable(), 1);
+}
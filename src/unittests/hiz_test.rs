@@ -0,0 +1,77 @@
+// Copyright (c) 2016-2017 Bruce Stenning. All rights reserved.
+//
+// Redistribution and use in source and binary forms, with or without
+// modification, are permitted provided that the following conditions
+// are met:
+//
+// 1. Redistributions of source code must retain the above copyright
+//    notice, this list of conditions and the following disclaimer.
+//
+// 2. Redistributions in binary form must reproduce the above copyright
+//    notice, this list of conditions and the following disclaimer in the
+//    documentation and/or other materials provided with the distribution.
+//
+// 3. Neither the name of the copyright holder nor the names of its
+//    contributors may be used to endorse or promote products derived
+//    from this software without specific prior written permission.
+//
+// THIS SOFTWARE IS PROVIDED BY THE COPYRIGHT HOLDERS AND CONTRIBUTORS
+// AS IS" AND ANY EXPRESS OR IMPLIED WARRANTIES, INCLUDING, BUT NOT
+// LIMITED TO, THE IMPLIED WARRANTIES OF MERCHANTABILITY AND FITNESS
+// FOR A PARTICULAR PURPOSE ARE DISCLAIMED. IN NO EVENT SHALL THE
+// COPYRIGHT HOLDER OR CONTRIBUTORS BE LIABLE FOR ANY DIRECT, INDIRECT,
+// INCIDENTAL, SPECIAL, EXEMPLARY, OR CONSEQUENTIAL DAMAGES (INCLUDING,
+// BUT NOT LIMITED TO, PROCUREMENT OF SUBSTITUTE GOODS OR SERVICES; LOSS
+// OF USE, DATA, OR PROFITS; OR BUSINESS INTERRUPTION) HOWEVER CAUSED
+// AND ON ANY THEORY OF LIABILITY, WHETHER IN CONTRACT, STRICT LIABILITY,
+// OR TORT (INCLUDING NEGLIGENCE OR OTHERWISE) ARISING IN ANY WAY OUT OF
+// THE USE OF THIS SOFTWARE, EVEN IF ADVISED OF THE POSSIBILITY OF SUCH
+// DAMAGE.
+#![allow(unused_imports)]
+
+use graphics::hiz::*;
+use graphics::rendergraph::*;
+use graphics::renderpasses::*;
+
+#[test]
+fn pyramid_halves_each_level_down_to_a_single_texel() {
+    let pyramid = HiZPyramid::new(640, 360, HiZReduction::Max);
+    assert_eq!(pyramid.level_count(), 10);
+    assert_eq!(hiz_level_count(1, 1), 1);
+
+    let sizes: Vec<(u32, u32)> = pyramid.levels().iter().map(|l| (l.width, l.height)).collect();
+    assert_eq!(sizes[0], (640, 360));
+    assert_eq!(sizes[4], (40, 22));
+    assert_eq!(sizes[8], (2, 1));
+    assert_eq!(sizes[9], (1, 1));
+    assert_eq!(pyramid.levels()[1].source_width, 640);
+}
+
+#[test]
+fn odd_sized_levels_keep_the_extreme_depths() {
+    // The nearest and furthest depths sit in the middle row and column of a 3x3 buffer, which
+    // a plain 2x2 reduction would drop
+    let depth = vec![0.5, 0.5, 0.5, 0.5, 0.5, 0.9, 0.5, 0.1, 0.5];
+
+    let max = HiZPyramid::new(3, 3, HiZReduction::Max).build(&depth);
+    assert_eq!(max.len(), 2);
+    assert_eq!(max[0], depth);
+    assert_eq!(max[1], vec![0.9]);
+
+    let min = HiZPyramid::new(3, 3, HiZReduction::Min).build(&depth);
+    assert_eq!(min[1], vec![0.1]);
+
+    assert_eq!(hiz_footprint(0, 1, 3), (0, 3));
+    assert_eq!(hiz_footprint(1, 2, 4), (2, 4));
+}
+
+#[test]
+fn pyramid_passes_follow_the_opaque_pass() {
+    let mut graph = RenderGraph::new();
+    add_hiz_graph_passes(&mut graph, "scene_depth", "hiz", 3);
+    graph.add_pass(RenderGraphPass::new("opaque", "scene").writes("scene_colour").writes_depth("scene_depth"));
+    graph.add_pass(RenderGraphPass::new("ssr", SWAPCHAIN_PASS).reads(HIZ_LEVEL_TEXTURES[2]).writes("swapchain"));
+
+    let order = graph.compile().unwrap().order();
+    assert_eq!(order, vec!["opaque", "hiz_downsample_0", "hiz_downsample_1", "hiz_downsample_2", "ssr"]);
+}
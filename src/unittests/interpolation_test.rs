@@ -0,0 +1,109 @@
+// Copyright (c) 2016-2017 Bruce Stenning. All rights reserved.
+//
+// Redistribution and use in source and binary forms, with or without
+// modification, are permitted provided that the following conditions
+// are met:
+//
+// 1. Redistributions of source code must retain the above copyright
+//    notice, this list of conditions and the following disclaimer.
+//
+// 2. Redistributions in binary form must reproduce the above copyright
+//    notice, this list of conditions and the following disclaimer in the
+//    documentation and/or other materials provided with the distribution.
+//
+// 3. Neither the name of the copyright holder nor the names of its
+//    contributors may be used to endorse or promote products derived
+//    from this software without specific prior written permission.
+//
+// THIS SOFTWARE IS PROVIDED BY THE COPYRIGHT HOLDERS AND CONTRIBUTORS
+// AS IS" AND ANY EXPRESS OR IMPLIED WARRANTIES, INCLUDING, BUT NOT
+// LIMITED TO, THE IMPLIED WARRANTIES OF MERCHANTABILITY AND FITNESS
+// FOR A PARTICULAR PURPOSE ARE DISCLAIMED. IN NO EVENT SHALL THE
+// COPYRIGHT HOLDER OR CONTRIBUTORS BE LIABLE FOR ANY DIRECT, INDIRECT,
+// INCIDENTAL, SPECIAL, EXEMPLARY, OR CONSEQUENTIAL DAMAGES (INCLUDING,
+// BUT NOT LIMITED TO, PROCUREMENT OF SUBSTITUTE GOODS OR SERVICES; LOSS
+// OF USE, DATA, OR PROFITS; OR BUSINESS INTERRUPTION) HOWEVER CAUSED
+// AND ON ANY THEORY OF LIABILITY, WHETHER IN CONTRACT, STRICT LIABILITY,
+// OR TORT (INCLUDING NEGLIGENCE OR OTHERWISE) ARISING IN ANY WAY OUT OF
+// THE USE OF THIS SOFTWARE, EVEN IF ADVISED OF THE POSSIBILITY OF SUCH
+// DAMAGE.
+#![allow(unused_imports)]
+
+use algebra::matrix::Mat4;
+use algebra::quaternion::Quaternion;
+use algebra::vector::*;
+use graphics::interpolation::*;
+
+#[cfg(test)]
+fn moved_to(x: f32, degrees: f32) -> ObjectTransform {
+    ObjectTransform {
+        position: Vec3 {
+            x: x,
+            y: 0.0f32,
+            z: 0.0f32,
+        },
+        rotation: Quaternion::axis_and_angle_to_quaternion(&Vec3 {
+                                                               x: 0.0f32,
+                                                               y: 0.0f32,
+                                                               z: 1.0f32,
+                                                           },
+                                                           degrees),
+        ..ObjectTransform::identity()
+    }
+}
+
+#[test]
+fn interpolator_blends_between_steps() {
+    let mut interpolator = TransformInterpolator::new();
+
+    // A new object does not move until the next step
+    interpolator.begin_step();
+    interpolator.set_transform(7, &moved_to(2.0, 0.0));
+    interpolator.set_alpha(0.5);
+    assert_eq!(interpolator.transform(7).unwrap().position.x, 2.0);
+
+    interpolator.begin_step();
+    interpolator.set_transform(7, &moved_to(4.0, 90.0));
+    let halfway = interpolator.transform(7).unwrap();
+    assert_eq!(halfway.position.x, 3.0);
+    assert!(halfway.rotation.approx_eq_ulps(&moved_to(0.0, 45.0).rotation, 4));
+
+    // The model matrix translates, and rotates x by 45 degrees
+    let model = interpolator.model_matrix(7).unwrap();
+    let origin = model.mul_by_vec3(Vec3 {
+        x: 0.0f32,
+        y: 0.0f32,
+        z: 0.0f32,
+    });
+    let x_axis = model.mul_by_vec3(Vec3 {
+        x: 1.0f32,
+        y: 0.0f32,
+        z: 0.0f32,
+    });
+    assert_eq!(origin.x, 3.0);
+    assert!((x_axis.x - 3.0 - 0.5f32.sqrt()).abs() < 1.0e-5);
+    assert!((x_axis.y - 0.5f32.sqrt()).abs() < 1.0e-5);
+
+    // A teleport skips the blend
+    interpolator.teleport(7, &moved_to(10.0, 0.0));
+    assert_eq!(interpolator.transform(7).unwrap().position.x, 10.0);
+    assert_eq!(interpolator.transform(8).is_none(), true);
+}
+
+#[test]
+fn interpolator_reuses_free_slots_in_transform_data() {
+    let mut interpolator = TransformInterpolator::new();
+    interpolator.set_transform(1, &moved_to(1.0, 0.0));
+    interpolator.set_transform(2, &moved_to(2.0, 0.0));
+    assert_eq!(interpolator.object_index(2), Some(1));
+
+    interpolator.remove_object(1);
+    let data = interpolator.transform_data();
+    assert_eq!(data.len(), 2 * TRANSFORM_VEC4S_PER_OBJECT * 4);
+    assert_eq!(data[0], 0.0);
+    assert_eq!(data[TRANSFORM_VEC4S_PER_OBJECT * 4], 2.0);
+
+    interpolator.set_transform(3, &moved_to(3.0, 0.0));
+    assert_eq!(interpolator.object_index(3), Some(0));
+    assert_eq!(interpolator.object_index(1), None);
+}
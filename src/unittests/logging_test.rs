@@ -0,0 +1,73 @@
+// Copyright (c) 2016-2017 Bruce Stenning. All rights reserved.
+//
+// Redistribution and use in source and binary forms, with or without
+// modification, are permitted provided that the following conditions
+// are met:
+//
+// 1. Redistributions of source code must retain the above copyright
+//    notice, this list of conditions and the following disclaimer.
+//
+// 2. Redistributions in binary form must reproduce the above copyright
+//    notice, this list of conditions and the following disclaimer in the
+//    documentation and/or other materials provided with the distribution.
+//
+// 3. Neither the name of the copyright holder nor the names of its
+//    contributors may be used to endorse or promote products derived
+//    from this software without specific prior written permission.
+//
+// THIS SOFTWARE IS PROVIDED BY THE COPYRIGHT HOLDERS AND CONTRIBUTORS
+// AS IS" AND ANY EXPRESS OR IMPLIED WARRANTIES, INCLUDING, BUT NOT
+// LIMITED TO, THE IMPLIED WARRANTIES OF MERCHANTABILITY AND FITNESS
+// FOR A PARTICULAR PURPOSE ARE DISCLAIMED. IN NO EVENT SHALL THE
+// COPYRIGHT HOLDER OR CONTRIBUTORS BE LIABLE FOR ANY DIRECT, INDIRECT,
+// INCIDENTAL, SPECIAL, EXEMPLARY, OR CONSEQUENTIAL DAMAGES (INCLUDING,
+// BUT NOT LIMITED TO, PROCUREMENT OF SUBSTITUTE GOODS OR SERVICES; LOSS
+// OF USE, DATA, OR PROFITS; OR BUSINESS INTERRUPTION) HOWEVER CAUSED
+// AND ON ANY THEORY OF LIABILITY, WHETHER IN CONTRACT, STRICT LIABILITY,
+// OR TORT (INCLUDING NEGLIGENCE OR OTHERWISE) ARISING IN ANY WAY OUT OF
+// THE USE OF THIS SOFTWARE, EVEN IF ADVISED OF THE POSSIBILITY OF SUCH
+// DAMAGE.
+#![allow(unused_imports)]
+
+use std::sync::{Arc, Mutex};
+
+use misc::logging::*;
+
+#[test]
+fn log_level_follows_debug_level() {
+    assert_eq!(LogLevel::from_debug_level(0), LogLevel::Warning);
+    assert_eq!(LogLevel::from_debug_level(1), LogLevel::Info);
+    assert_eq!(LogLevel::from_debug_level(2), LogLevel::Debug);
+    assert_eq!(LogLevel::from_debug_level(7), LogLevel::Debug);
+
+    assert!(LogLevel::Error < LogLevel::Warning);
+    assert!(LogLevel::Info < LogLevel::Debug);
+}
+
+// The level and sink are process-wide, so they are only changed by this one test
+#[test]
+fn messages_above_the_level_reach_the_sink() {
+    let received: Arc<Mutex<Vec<(LogLevel, String)>>> = Arc::new(Mutex::new(vec![]));
+    let sink_received = received.clone();
+    set_log_sink(Some(Box::new(move |level, message| {
+        sink_received.lock().unwrap().push((level, message.to_string()));
+    })));
+
+    set_log_level(LogLevel::Warning);
+    assert!(log_enabled(LogLevel::Error));
+    assert!(!log_enabled(LogLevel::Info));
+    log_error!("error {}", 1);
+    log_warning!("warning {}", 2);
+    log_info!("info {}", 3);
+
+    set_log_level(LogLevel::Debug);
+    log_debug!("debug {}", 4);
+
+    set_log_sink(None);
+    set_log_level(LogLevel::Warning);
+
+    assert_eq!(*received.lock().unwrap(),
+               vec![(LogLevel::Error, "error 1".to_string()),
+                    (LogLevel::Warning, "warning 2".to_string()),
+                    (LogLevel::Debug, "debug 4".to_string())]);
+}
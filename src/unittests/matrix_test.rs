@@ -31,7 +31,8 @@
 #![allow(unused_imports)]
 
 use algebra::vector::Vec3;
-use algebra::matrix::Mat4;
+use algebra::matrix::{ClipSpaceConvention, Mat4};
+use algebra::quaternion::Quaternion;
 use misc::conversions::degrees_to_radians;
 
 #[test]
@@ -310,3 +311,165 @@ fn mat4_rotate_vec() {
     println!("expected 2 is {}", e);
     assert!(v3.approx_eq_ulps(&e, 2));
 }
+
+#[test]
+fn mat4_inverse_undoes_transform() {
+    let m1 = Mat4::translate(3.0f32, 4.0f32, 5.0f32) * Mat4::rotatez(30.0f32) * Mat4::rotatex(45.0f32);
+    let m2 = m1.inverse().unwrap();
+    let v1 = Vec3 {
+        x: 6.0f32,
+        y: 7.0f32,
+        z: 8.0f32,
+    };
+    let v2 = m2.mul_by_vec3(m1.mul_by_vec3(v1).project()).project();
+    println!("result is {}", v2);
+    println!("expected is {}", v1);
+    assert!(v2.approx_eq_ulps(&v1, 16));
+    assert!(Mat4::<f32>::new().inverse().is_none());
+}
+
+#[test]
+fn mat4_projection_for_each_convention_gives_the_same_window_position() {
+    let conventions = [ClipSpaceConvention::OpenGl,
+                       ClipSpaceConvention::Vulkan,
+                       ClipSpaceConvention::VulkanFlippedViewport];
+
+    // Above and to the right of the centre of the view, so near the top right of the window
+    let v1 = Vec3 {
+        x: 1.0f32,
+        y: 2.0f32,
+        z: -10.0f32,
+    };
+    let windows: Vec<Vec3<f32>> = conventions.iter()
+        .map(|c| {
+            let ndc = Mat4::projection_for(60.0f32, 1.5f32, 1.0f32, 100.0f32, *c).mul_by_vec3(v1).project();
+            c.ndc_to_window(&ndc, 640.0f32, 480.0f32)
+        })
+        .collect();
+
+    println!("results are {}, {}, {}", windows[0], windows[1], windows[2]);
+    assert!(windows[0].x > 320.0f32 && windows[0].y < 240.0f32);
+    assert!(windows[0].z > 0.0f32 && windows[0].z < 1.0f32);
+    for w in windows.iter().skip(1) {
+        assert!((w.x - windows[0].x).abs() < 1.0e-3f32);
+        assert!((w.y - windows[0].y).abs() < 1.0e-3f32);
+        assert!((w.z - windows[0].z).abs() < 1.0e-5f32);
+    }
+}
+
+#[test]
+fn mat4_clip_space_correction_adapts_opengl_projection() {
+    let gl = Mat4::projection_for(60.0f32, 1.5f32, 1.0f32, 100.0f32, ClipSpaceConvention::OpenGl);
+    let vk = Mat4::projection_for(60.0f32, 1.5f32, 1.0f32, 100.0f32, ClipSpaceConvention::Vulkan);
+    let corrected = Mat4::clip_space_correction(ClipSpaceConvention::Vulkan) * gl;
+    for col in 0..4 {
+        for row in 0..4 {
+            assert!((corrected.m[col][row] - vk.m[col][row]).abs() < 1.0e-6f32);
+        }
+    }
+}
+
+#[test]
+fn mat4_transpose_swaps_rows_and_columns() {
+    let mut m = Mat4::new();
+    for col in 0..4 {
+        for row in 0..4 {
+            m.m[col][row] = (col * 4 + row) as f32;
+        }
+    }
+
+    let t = m.transpose();
+    for col in 0..4 {
+        for row in 0..4 {
+            assert_eq!(t.m[col][row], m.m[row][col]);
+        }
+    }
+}
+
+#[test]
+fn mat4_perspective_and_ortho_map_the_view_volume_to_ndc() {
+    // A point on the top edge of the far plane
+    let far_top = Vec3 {
+        x: 0.0f32,
+        y: 100.0f32 * degrees_to_radians(30.0f32).tan(),
+        z: -100.0f32,
+    };
+    let ndc = Mat4::perspective(60.0f32, 1.5f32, 1.0f32, 100.0f32).mul_by_vec3(far_top).project();
+    println!("result is {}", ndc);
+    assert!((ndc.y - 1.0f32).abs() < 1.0e-5f32);
+    assert!((ndc.z - 1.0f32).abs() < 1.0e-5f32);
+
+    let near_corner = Vec3 {
+        x: -4.0f32,
+        y: 3.0f32,
+        z: -1.0f32,
+    };
+    let ndc = Mat4::ortho(-4.0f32, 4.0f32, -3.0f32, 3.0f32, 1.0f32, 10.0f32).mul_by_vec3(near_corner).project();
+    println!("result is {}", ndc);
+    assert!(ndc.approx_eq_ulps(&Vec3 {
+                                   x: -1.0f32,
+                                   y: 1.0f32,
+                                   z: -1.0f32,
+                               },
+                               4));
+}
+
+#[test]
+fn mat4_look_at_puts_the_target_down_the_negative_z_axis() {
+    let eye = Vec3 {
+        x: 1.0f32,
+        y: 2.0f32,
+        z: 3.0f32,
+    };
+    let target = Vec3 {
+        x: 4.0f32,
+        y: 2.0f32,
+        z: -1.0f32,
+    };
+    let up = Vec3 {
+        x: 0.0f32,
+        y: 1.0f32,
+        z: 0.0f32,
+    };
+
+    let v1 = Mat4::look_at(&eye, &target, &up).mul_by_vec3(target).project();
+    println!("result is {}", v1);
+    assert!(v1.x.abs() < 1.0e-5f32 && v1.y.abs() < 1.0e-5f32);
+    assert!((v1.z + 5.0f32).abs() < 1.0e-5f32);
+}
+
+#[test]
+fn mat4_decompose_inverts_compose() {
+    let translation = Vec3 {
+        x: 5.0f32,
+        y: -2.0f32,
+        z: 0.5f32,
+    };
+    let rotation = Quaternion::axis_and_angle_to_quaternion(&Vec3 {
+                                                                x: 0.0f32,
+                                                                y: 0.6f32,
+                                                                z: 0.8f32,
+                                                            },
+                                                            40.0f32);
+    let scale = Vec3 {
+        x: -2.0f32,
+        y: 1.0f32,
+        z: 3.0f32,
+    };
+
+    let (t, r, s) = Mat4::compose(&translation, &rotation, &scale).decompose().unwrap();
+    println!("result is {}, {}, {}", t, r, s);
+    assert!((t - translation).magnitude() < 1.0e-5f32);
+    assert!((s - scale).magnitude() < 1.0e-5f32);
+    assert!((r.dot(&rotation).abs() - 1.0f32).abs() < 1.0e-5f32);
+
+    assert!(Mat4::compose(&translation,
+                          &rotation,
+                          &Vec3 {
+                              x: 1.0f32,
+                              y: 0.0f32,
+                              z: 1.0f32,
+                          })
+        .decompose()
+        .is_none());
+}
@@ -0,0 +1,150 @@
+// Copyright (c) 2016-2017 Bruce Stenning. All rights reserved.
+//
+// Redistribution and use in source and binary forms, with or without
+// modification, are permitted provided that the following conditions
+// are met:
+//
+// 1. Redistributions of source code must retain the above copyright
+//    notice, this list of conditions and the following disclaimer.
+//
+// 2. Redistributions in binary form must reproduce the above copyright
+//    notice, this list of conditions and the following disclaimer in the
+//    documentation and/or other materials provided with the distribution.
+//
+// 3. Neither the name of the copyright holder nor the names of its
+//    contributors may be used to endorse or promote products derived
+//    from this software without specific prior written permission.
+//
+// THIS SOFTWARE IS PROVIDED BY THE COPYRIGHT HOLDERS AND CONTRIBUTORS
+// AS IS" AND ANY EXPRESS OR IMPLIED WARRANTIES, INCLUDING, BUT NOT
+// LIMITED TO, THE IMPLIED WARRANTIES OF MERCHANTABILITY AND FITNESS
+// FOR A PARTICULAR PURPOSE ARE DISCLAIMED. IN NO EVENT SHALL THE
+// COPYRIGHT HOLDER OR CONTRIBUTORS BE LIABLE FOR ANY DIRECT, INDIRECT,
+// INCIDENTAL, SPECIAL, EXEMPLARY, OR CONSEQUENTIAL DAMAGES (INCLUDING,
+// BUT NOT LIMITED TO, PROCUREMENT OF SUBSTITUTE GOODS OR SERVICES; LOSS
+// OF USE, DATA, OR PROFITS; OR BUSINESS INTERRUPTION) HOWEVER CAUSED
+// AND ON ANY THEORY OF LIABILITY, WHETHER IN CONTRACT, STRICT LIABILITY,
+// OR TORT (INCLUDING NEGLIGENCE OR OTHERWISE) ARISING IN ANY WAY OUT OF
+// THE USE OF THIS SOFTWARE, EVEN IF ADVISED OF THE POSSIBILITY OF SUCH
+// DAMAGE.
+
+#![allow(unused_imports)]
+
+use graphics::memoryallocator::*;
+
+#[test]
+fn block_ranges_align_allocations_and_merge_freed_ranges() {
+    let mut ranges = MemoryBlockRanges::new(1024);
+
+    assert_eq!(ranges.allocate(100, 1), Some(0));
+    assert_eq!(ranges.allocate(100, 256), Some(256));
+    assert_eq!(ranges.allocate(1024, 1), None);
+    assert_eq!(*ranges.free_ranges(), vec![(100, 156), (356, 668)]);
+
+    // The padding left by alignment is used by later allocations that fit
+    assert_eq!(ranges.allocate(150, 2), Some(100));
+
+    ranges.free(256, 100);
+    ranges.free(0, 100);
+    ranges.free(100, 150);
+    assert!(ranges.is_empty());
+    assert_eq!(*ranges.free_ranges(), vec![(0, 1024)]);
+}
+
+#[test]
+fn allocator_pools_blocks_by_memory_type_and_kind() {
+    let mut allocator: MemoryAllocator<u32> = MemoryAllocator::new(1024);
+    let mut next_block = 0u32;
+    let mut allocate_block = |_size: u64| {
+        next_block += 1;
+        Some(next_block)
+    };
+
+    let a = allocator.allocate(0, MemoryResourceKind::Linear, 256, 16, &mut allocate_block).unwrap();
+    let b = allocator.allocate(0, MemoryResourceKind::Linear, 256, 16, &mut allocate_block).unwrap();
+    let c = allocator.allocate(0, MemoryResourceKind::Optimal, 256, 16, &mut allocate_block).unwrap();
+    let d = allocator.allocate(1, MemoryResourceKind::Linear, 256, 16, &mut allocate_block).unwrap();
+    let e = allocator.allocate(0, MemoryResourceKind::Linear, 600, 16, &mut allocate_block).unwrap();
+
+    assert_eq!((a.memory, a.offset), (1, 0));
+    assert_eq!((b.memory, b.offset), (1, 256));
+    assert_eq!(c.memory, 2);
+    assert_eq!(d.memory, 3);
+    assert_eq!((e.memory, e.offset), (4, 0)); // Too large to share a block
+
+    let stats = allocator.stats();
+    assert_eq!(stats.blocks, 4);
+    assert_eq!(stats.dedicated_blocks, 1);
+    assert_eq!(stats.allocations, 5);
+    assert_eq!(stats.bytes_reserved, 3 * 1024 + 600);
+    assert_eq!(stats.bytes_used, 4 * 256 + 600);
+    assert_eq!(allocator.memory_type_stats(1).bytes_used, 256);
+}
+
+#[test]
+fn allocator_releases_dedicated_and_spare_empty_blocks() {
+    let mut allocator: MemoryAllocator<u32> = MemoryAllocator::new(1024);
+    let mut next_block = 0u32;
+    let mut allocate_block = |_size: u64| {
+        next_block += 1;
+        Some(next_block)
+    };
+
+    let a = allocator.allocate(0, MemoryResourceKind::Linear, 1000, 1, &mut allocate_block).unwrap();
+    let b = allocator.allocate(0, MemoryResourceKind::Linear, 512, 1, &mut allocate_block).unwrap();
+    let c = allocator.allocate(0, MemoryResourceKind::Linear, 512, 1, &mut allocate_block).unwrap();
+    let e = allocator.allocate(0, MemoryResourceKind::Linear, 512, 1, &mut allocate_block).unwrap();
+    assert!(a.memory != b.memory && b.memory == c.memory && c.memory != e.memory);
+
+    assert_eq!(allocator.free(&a), Some(a.memory));
+    assert_eq!(allocator.free(&b), None);
+    assert_eq!(allocator.free(&c), None); // The pool keeps one empty block
+    assert_eq!(allocator.free(&e), Some(e.memory));
+    assert_eq!(allocator.stats().blocks, 1);
+
+    // The empty block is reused, and released with the rest on shutdown
+    let d = allocator.allocate(0, MemoryResourceKind::Linear, 500, 1, &mut allocate_block).unwrap();
+    assert_eq!(d.memory, b.memory);
+    assert_eq!(allocator.drain_blocks(), vec![b.memory]);
+    assert_eq!(allocator.free(&d), None);
+}
+
+#[test]
+fn relocation_drains_the_emptier_blocks_into_the_fuller_ones() {
+    let mut allocator: MemoryAllocator<u32> = MemoryAllocator::new(1024);
+    let mut next_block = 0u32;
+    let mut allocate_block = |_size: u64| {
+        next_block += 1;
+        Some(next_block)
+    };
+
+    let a = allocator.allocate(0, MemoryResourceKind::Linear, 500, 16, &mut allocate_block).unwrap();
+    let b = allocator.allocate(0, MemoryResourceKind::Linear, 200, 16, &mut allocate_block).unwrap();
+    let c = allocator.allocate(0, MemoryResourceKind::Linear, 500, 16, &mut allocate_block).unwrap();
+    let d = allocator.allocate(0, MemoryResourceKind::Linear, 300, 256, &mut allocate_block).unwrap();
+    let dedicated = allocator.allocate(0, MemoryResourceKind::Linear, 600, 16, &mut allocate_block).unwrap();
+    assert!(a.memory == b.memory && c.memory == d.memory && a.memory != c.memory);
+
+    // Freeing from the start of the first block leaves the free memory in four ranges
+    allocator.free(&a);
+    let stats = allocator.stats();
+    assert_eq!((stats.free_ranges, stats.largest_free_range), (4, 512));
+    assert!(stats.fragmentation() > 0.4);
+
+    // The fuller block takes nothing from the emptier one, and dedicated blocks never move
+    assert!(allocator.relocate(&c).is_none());
+    assert!(allocator.relocate(&dedicated).is_none());
+
+    let moved = allocator.relocate(&b).unwrap();
+    assert_eq!((moved.memory, moved.offset), (c.memory, 816));
+    assert_eq!(allocator.free(&b), None); // The pool keeps its one empty block
+
+    // The move is only counted once committed, so an abandoned one would not be
+    assert_eq!(allocator.stats().relocations, 0);
+    allocator.commit_relocation(&moved);
+
+    let stats = allocator.stats();
+    assert_eq!((stats.relocations, stats.bytes_relocated), (1, 200));
+    assert_eq!(stats.allocations, 4);
+    assert_eq!(allocator.memory_type_stats(0).bytes_used, 1000 + 600);
+}
@@ -0,0 +1,75 @@
+// Copyright (c) 2016-2017 Bruce Stenning. All rights reserved.
+//
+// Redistribution and use in source and binary forms, with or without
+// modification, are permitted provided that the following conditions
+// are met:
+//
+// 1. Redistributions of source code must retain the above copyright
+//    notice, this list of conditions and the following disclaimer.
+//
+// 2. Redistributions in binary form must reproduce the above copyright
+//    notice, this list of conditions and the following disclaimer in the
+//    documentation and/or other materials provided with the distribution.
+//
+// 3. Neither the name of the copyright holder nor the names of its
+//    contributors may be used to endorse or promote products derived
+//    from this software without specific prior written permission.
+//
+// THIS SOFTWARE IS PROVIDED BY THE COPYRIGHT HOLDERS AND CONTRIBUTORS
+// AS IS" AND ANY EXPRESS OR IMPLIED WARRANTIES, INCLUDING, BUT NOT
+// LIMITED TO, THE IMPLIED WARRANTIES OF MERCHANTABILITY AND FITNESS
+// FOR A PARTICULAR PURPOSE ARE DISCLAIMED. IN NO EVENT SHALL THE
+// COPYRIGHT HOLDER OR CONTRIBUTORS BE LIABLE FOR ANY DIRECT, INDIRECT,
+// INCIDENTAL, SPECIAL, EXEMPLARY, OR CONSEQUENTIAL DAMAGES (INCLUDING,
+// BUT NOT LIMITED TO, PROCUREMENT OF SUBSTITUTE GOODS OR SERVICES; LOSS
+// OF USE, DATA, OR PROFITS; OR BUSINESS INTERRUPTION) HOWEVER CAUSED
+// AND ON ANY THEORY OF LIABILITY, WHETHER IN CONTRACT, STRICT LIABILITY,
+// OR TORT (INCLUDING NEGLIGENCE OR OTHERWISE) ARISING IN ANY WAY OUT OF
+// THE USE OF THIS SOFTWARE, EVEN IF ADVISED OF THE POSSIBILITY OF SUCH
+// DAMAGE.
+#![allow(unused_imports)]
+
+use graphics::memoryusage::*;
+
+#[test]
+fn memory_usage_tracker_replaces_records_of_the_same_resource() {
+    let mut tracker = MemoryUsageTracker::new();
+    tracker.record((1, 7), MemoryCategory::Textures, 4096);
+    tracker.record((1, 7), MemoryCategory::Textures, 1024);
+    tracker.record((2, 7), MemoryCategory::Uniforms, 256);
+
+    let usage = MemoryUsage { heaps: vec![], categories: tracker.categories() };
+    assert_eq!(usage.category(MemoryCategory::Textures), CategoryUsage { resources: 1, bytes: 1024 });
+    assert_eq!(usage.category(MemoryCategory::Uniforms), CategoryUsage { resources: 1, bytes: 256 });
+    assert_eq!(usage.total_bytes(), 1280);
+}
+
+#[test]
+fn memory_usage_tracker_resizes_keep_the_category_and_releases_forget() {
+    let mut tracker = MemoryUsageTracker::new();
+    tracker.record((1, 3), MemoryCategory::RenderTargets, 100);
+    tracker.resize((1, 3), 400);
+    tracker.resize((1, 4), 50);
+
+    let categories = tracker.categories();
+    assert_eq!(categories.iter().map(|&(c, _)| c).collect::<Vec<_>>(), MEMORY_CATEGORIES.to_vec());
+    let usage = MemoryUsage { heaps: vec![], categories: categories };
+    assert_eq!(usage.category(MemoryCategory::RenderTargets), CategoryUsage { resources: 1, bytes: 400 });
+    assert_eq!(usage.total_bytes(), 400);
+
+    tracker.release((1, 3));
+    assert_eq!(MemoryUsage { heaps: vec![], categories: tracker.categories() }.total_bytes(), 0);
+}
+
+#[test]
+fn heap_usage_is_available_only_where_the_budget_is_known() {
+    let mut heap = HeapUsage { size: 8192, device_local: true, allocated: 1024, budget: None, usage: None };
+    assert_eq!(heap.available(), None);
+
+    heap.budget = Some(6000);
+    heap.usage = Some(2000);
+    assert_eq!(heap.available(), Some(4000));
+
+    heap.usage = Some(7000);
+    assert_eq!(heap.available(), Some(0));
+}
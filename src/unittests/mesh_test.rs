@@ -0,0 +1,95 @@
+// Copyright (c) 2016-2017 Bruce Stenning. All rights reserved.
+//
+// Redistribution and use in source and binary forms, with or without
+// modification, are permitted provided that the following conditions
+// are met:
+//
+// 1. Redistributions of source code must retain the above copyright
+//    notice, this list of conditions and the following disclaimer.
+//
+// 2. Redistributions in binary form must reproduce the above copyright
+//    notice, this list of conditions and the following disclaimer in the
+//    documentation and/or other materials provided with the distribution.
+//
+// 3. Neither the name of the copyright holder nor the names of its
+//    contributors may be used to endorse or promote products derived
+//    from this software without specific prior written permission.
+//
+// THIS SOFTWARE IS PROVIDED BY THE COPYRIGHT HOLDERS AND CONTRIBUTORS
+// AS IS" AND ANY EXPRESS OR IMPLIED WARRANTIES, INCLUDING, BUT NOT
+// LIMITED TO, THE IMPLIED WARRANTIES OF MERCHANTABILITY AND FITNESS
+// FOR A PARTICULAR PURPOSE ARE DISCLAIMED. IN NO EVENT SHALL THE
+// COPYRIGHT HOLDER OR CONTRIBUTORS BE LIABLE FOR ANY DIRECT, INDIRECT,
+// INCIDENTAL, SPECIAL, EXEMPLARY, OR CONSEQUENTIAL DAMAGES (INCLUDING,
+// BUT NOT LIMITED TO, PROCUREMENT OF SUBSTITUTE GOODS OR SERVICES; LOSS
+// OF USE, DATA, OR PROFITS; OR BUSINESS INTERRUPTION) HOWEVER CAUSED
+// AND ON ANY THEORY OF LIABILITY, WHETHER IN CONTRACT, STRICT LIABILITY,
+// OR TORT (INCLUDING NEGLIGENCE OR OTHERWISE) ARISING IN ANY WAY OUT OF
+// THE USE OF THIS SOFTWARE, EVEN IF ADVISED OF THE POSSIBILITY OF SUCH
+// DAMAGE.
+#![allow(unused_imports)]
+
+use graphics::mesh::*;
+use graphics::renderer::*;
+
+#[test]
+fn mesh_data_counts_vertices_and_indices() {
+    let quad = MeshData::indexed(VertexArrayType::F2F2, vec![0.0; 4 * 4], vec![0, 1, 2, 2, 1, 3]);
+    assert_eq!(quad.vertex_count(), 4);
+    assert_eq!(quad.draw_count(), 6);
+    assert_eq!(quad.vertex_bytes(), 64);
+    assert_eq!(quad.index_bytes(), 24);
+    assert!(quad.validate().is_ok());
+
+    let triangle = MeshData::new(VertexArrayType::F3, vec![0.0; 9]);
+    assert_eq!(triangle.draw_count(), 3);
+    assert_eq!(triangle.index_bytes(), 0);
+    assert!(triangle.validate().is_ok());
+}
+
+#[test]
+fn mesh_data_rejects_partial_vertices_triangles_and_stray_indices() {
+    assert!(MeshData::new(VertexArrayType::F3, vec![]).validate().is_err());
+    assert!(MeshData::new(VertexArrayType::F3F3, vec![0.0; 10]).validate().is_err());
+    assert!(MeshData::new(VertexArrayType::F3, vec![0.0; 12]).validate().is_err());
+    assert!(MeshData::indexed(VertexArrayType::F3, vec![0.0; 9], vec![0, 1]).validate().is_err());
+    assert!(MeshData::indexed(VertexArrayType::F3, vec![0.0; 9], vec![0, 1, 3]).validate().is_err());
+}
+
+#[test]
+fn mesh_registry_keeps_destroyed_meshes_for_the_frames_in_flight() {
+    let mut registry: MeshRegistry<&str> = MeshRegistry::new(2);
+    let first = registry.insert("first");
+    let second = registry.insert("second");
+    assert!(first != second);
+
+    assert!(registry.remove(first));
+    assert!(!registry.remove(first));
+    assert!(registry.get(first).is_none());
+    assert_eq!(registry.get(second), Some(&"second"));
+    assert_eq!((registry.len(), registry.retiring()), (1, 1));
+
+    registry.advance_frame();
+    assert_eq!(registry.retiring(), 1);
+    registry.advance_frame();
+    assert_eq!(registry.retiring(), 0);
+
+    // Handles are not reused
+    assert!(registry.insert("third") > second);
+}
+
+#[test]
+fn replacing_a_mesh_keeps_its_handle_and_retires_the_old_one() {
+    let mut registry: MeshRegistry<&str> = MeshRegistry::new(1);
+    let mesh = registry.insert("before");
+
+    assert!(registry.replace(mesh, "after"));
+    assert_eq!(registry.get(mesh), Some(&"after"));
+    assert_eq!((registry.len(), registry.retiring()), (1, 1));
+    assert_eq!(registry.handles(), vec![mesh]);
+
+    // A mesh destroyed while it was being moved stays destroyed
+    assert!(registry.remove(mesh));
+    assert!(!registry.replace(mesh, "moved"));
+    assert!(registry.get(mesh).is_none());
+}
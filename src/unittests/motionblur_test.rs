@@ -0,0 +1,85 @@
+// Copyright (c) 2016-2017 Bruce Stenning. All rights reserved.
+//
+// Redistribution and use in source and binary forms, with or without
+// modification, are permitted provided that the following conditions
+// are met:
+//
+// 1. Redistributions of source code must retain the above copyright
+//    notice, this list of conditions and the following disclaimer.
+//
+// 2. Redistributions in binary form must reproduce the above copyright
+//    notice, this list of conditions and the following disclaimer in the
+//    documentation and/or other materials provided with the distribution.
+//
+// 3. Neither the name of the copyright holder nor the names of its
+//    contributors may be used to endorse or promote products derived
+//    from this software without specific prior written permission.
+//
+// THIS SOFTWARE IS PROVIDED BY THE COPYRIGHT HOLDERS AND CONTRIBUTORS
+// AS IS" AND ANY EXPRESS OR IMPLIED WARRANTIES, INCLUDING, BUT NOT
+// LIMITED TO, THE IMPLIED WARRANTIES OF MERCHANTABILITY AND FITNESS
+// FOR A PARTICULAR PURPOSE ARE DISCLAIMED. IN NO EVENT SHALL THE
+// COPYRIGHT HOLDER OR CONTRIBUTORS BE LIABLE FOR ANY DIRECT, INDIRECT,
+// INCIDENTAL, SPECIAL, EXEMPLARY, OR CONSEQUENTIAL DAMAGES (INCLUDING,
+// BUT NOT LIMITED TO, PROCUREMENT OF SUBSTITUTE GOODS OR SERVICES; LOSS
+// OF USE, DATA, OR PROFITS; OR BUSINESS INTERRUPTION) HOWEVER CAUSED
+// AND ON ANY THEORY OF LIABILITY, WHETHER IN CONTRACT, STRICT LIABILITY,
+// OR TORT (INCLUDING NEGLIGENCE OR OTHERWISE) ARISING IN ANY WAY OUT OF
+// THE USE OF THIS SOFTWARE, EVEN IF ADVISED OF THE POSSIBILITY OF SUCH
+// DAMAGE.
+
+#![allow(unused_imports)]
+
+use algebra::matrix::Mat4;
+use graphics::motionblur::*;
+
+#[test]
+fn the_first_frame_has_no_motion() {
+    let mut tracker = MotionVectorTracker::new();
+    let view_projection = Mat4::perspective(60.0f32, 1.5f32, 0.1f32, 100.0f32);
+    tracker.begin_frame(&view_projection);
+    assert_eq!(tracker.previous_view_projection().m, view_projection.m);
+
+    let model = Mat4::translate(1.0f32, 2.0f32, 3.0f32);
+    tracker.set_model(7, &model);
+    assert_eq!(tracker.previous_model(7).unwrap().m, model.m);
+    assert!(tracker.previous_model(8).is_none());
+}
+
+#[test]
+fn begin_frame_makes_the_current_transforms_the_previous_ones() {
+    let mut tracker = MotionVectorTracker::new();
+    let first_view_projection = Mat4::translate(0.0f32, 0.0f32, -1.0f32);
+    let second_view_projection = Mat4::translate(0.0f32, 0.0f32, -2.0f32);
+    let first_model = Mat4::translate(1.0f32, 0.0f32, 0.0f32);
+    let second_model = Mat4::translate(2.0f32, 0.0f32, 0.0f32);
+
+    tracker.begin_frame(&first_view_projection);
+    tracker.set_model(1, &first_model);
+
+    tracker.begin_frame(&second_view_projection);
+    assert_eq!(tracker.view_projection().m, second_view_projection.m);
+    assert_eq!(tracker.previous_view_projection().m, first_view_projection.m);
+    assert_eq!(tracker.previous_model(1).unwrap().m, first_model.m);
+
+    tracker.set_model(1, &second_model);
+    assert_eq!(tracker.previous_model(1).unwrap().m, first_model.m);
+
+    tracker.begin_frame(&second_view_projection);
+    assert_eq!(tracker.previous_model(1).unwrap().m, second_model.m);
+}
+
+#[test]
+fn a_removed_object_has_no_motion_when_it_returns() {
+    let mut tracker = MotionVectorTracker::new();
+    tracker.begin_frame(&Mat4::newidentity());
+    tracker.set_model(3, &Mat4::translate(1.0f32, 0.0f32, 0.0f32));
+
+    tracker.begin_frame(&Mat4::newidentity());
+    tracker.remove_object(3);
+    assert!(tracker.previous_model(3).is_none());
+
+    let model = Mat4::translate(5.0f32, 0.0f32, 0.0f32);
+    tracker.set_model(3, &model);
+    assert_eq!(tracker.previous_model(3).unwrap().m, model.m);
+}
@@ -0,0 +1,113 @@
+// Copyright (c) 2016-2017 Bruce Stenning. All rights reserved.
+//
+// Redistribution and use in source and binary forms, with or without
+// modification, are permitted provided that the following conditions
+// are met:
+//
+// 1. Redistributions of source code must retain the above copyright
+//    notice, this list of conditions and the following disclaimer.
+//
+// 2. Redistributions in binary form must reproduce the above copyright
+//    notice, this list of conditions and the following disclaimer in the
+//    documentation and/or other materials provided with the distribution.
+//
+// 3. Neither the name of the copyright holder nor the names of its
+//    contributors may be used to endorse or promote products derived
+//    from this software without specific prior written permission.
+//
+// THIS SOFTWARE IS PROVIDED BY THE COPYRIGHT HOLDERS AND CONTRIBUTORS
+// AS IS" AND ANY EXPRESS OR IMPLIED WARRANTIES, INCLUDING, BUT NOT
+// LIMITED TO, THE IMPLIED WARRANTIES OF MERCHANTABILITY AND FITNESS
+// FOR A PARTICULAR PURPOSE ARE DISCLAIMED. IN NO EVENT SHALL THE
+// COPYRIGHT HOLDER OR CONTRIBUTORS BE LIABLE FOR ANY DIRECT, INDIRECT,
+// INCIDENTAL, SPECIAL, EXEMPLARY, OR CONSEQUENTIAL DAMAGES (INCLUDING,
+// BUT NOT LIMITED TO, PROCUREMENT OF SUBSTITUTE GOODS OR SERVICES; LOSS
+// OF USE, DATA, OR PROFITS; OR BUSINESS INTERRUPTION) HOWEVER CAUSED
+// AND ON ANY THEORY OF LIABILITY, WHETHER IN CONTRACT, STRICT LIABILITY,
+// OR TORT (INCLUDING NEGLIGENCE OR OTHERWISE) ARISING IN ANY WAY OUT OF
+// THE USE OF THIS SOFTWARE, EVEN IF ADVISED OF THE POSSIBILITY OF SUCH
+// DAMAGE.
+
+#![allow(unused_imports)]
+
+use graphics::overlay::*;
+use algebra::matrix::{ClipSpaceConvention, Mat4};
+use algebra::vector::*;
+
+#[test]
+fn projection_puts_the_origin_at_the_top_left_under_every_convention() {
+    let conventions = [ClipSpaceConvention::OpenGl,
+                       ClipSpaceConvention::Vulkan,
+                       ClipSpaceConvention::VulkanFlippedViewport];
+    for convention in conventions.iter() {
+        let projection = overlay_projection(640, 480, *convention);
+        for &(x, y, window_x, window_y) in [(0.0f32, 0.0f32, 0.0f32, 0.0f32),
+                                            (640.0f32, 480.0f32, 640.0f32, 480.0f32),
+                                            (160.0f32, 360.0f32, 160.0f32, 360.0f32)]
+            .iter() {
+            let ndc = projection.mul_by_vec3(Vec3 { x: x, y: y, z: 0.0f32 }).project();
+            let window = convention.ndc_to_window(&ndc, 640.0f32, 480.0f32);
+            println!("result is {}", window);
+            assert!((window.x - window_x).abs() < 1.0e-3f32 && (window.y - window_y).abs() < 1.0e-3f32);
+            assert!(window.z >= 0.0f32 && window.z <= 1.0f32);
+        }
+    }
+}
+
+#[test]
+fn batches_are_sorted_by_layer_and_share_draws_of_the_same_state() {
+    let rect = OverlayRect {
+        x: 10.0f32,
+        y: 20.0f32,
+        width: 30.0f32,
+        height: 40.0f32,
+    };
+    let grey = [0.5f32, 0.5f32, 0.5f32, 0.8f32];
+    let white = [1.0f32, 1.0f32, 1.0f32, 1.0f32];
+    let uv_min = Vec2 { x: 0.0f32, y: 0.0f32 };
+    let uv_max = Vec2 { x: 1.0f32, y: 1.0f32 };
+
+    let mut overlay = Overlay::new();
+    overlay.draw_textured_quad(2, rect, 0, uv_min, uv_max, white);
+    overlay.draw_rect(0, rect, grey);
+    overlay.draw_textured_quad(2, OverlayRect { x: 50.0f32, ..rect }, 0, uv_min, uv_max, white);
+    overlay.draw_rect(1, rect, white);
+    overlay.draw_rect(0, OverlayRect { x: 90.0f32, ..rect }, grey);
+    overlay.draw_textured_quad(2, rect, 1, uv_min, uv_max, white);
+    assert_eq!(overlay.len(), 6);
+
+    let batches = overlay.batches();
+    let layers: Vec<Vec<i32>> = batches.iter().map(|b| b.iter().map(|item| item.layer).collect()).collect();
+    assert_eq!(layers, vec![vec![0, 0], vec![1], vec![2, 2], vec![2]]);
+    assert_eq!(batches[0][1].rect.x, 90.0f32);
+    assert_eq!(batches[2][1].rect.x, 50.0f32);
+    assert_eq!(batches[3][0].fill.texture(), Some(1));
+
+    overlay.clear();
+    assert!(overlay.batches().is_empty());
+}
+
+#[test]
+fn outlines_are_drawn_inside_the_edges_of_the_rectangle() {
+    let mut overlay = Overlay::new();
+    overlay.draw_rect_outline(0,
+                              OverlayRect {
+                                  x: 0.0f32,
+                                  y: 0.0f32,
+                                  width: 100.0f32,
+                                  height: 50.0f32,
+                              },
+                              2.0f32,
+                              [1.0f32, 0.0f32, 0.0f32, 1.0f32]);
+
+    let batches = overlay.batches();
+    assert_eq!(batches.len(), 1);
+
+    // The sides cover the border exactly once
+    let area: f32 = batches[0].iter().map(|item| item.rect.width * item.rect.height).sum();
+    assert_eq!(area, 100.0f32 * 50.0f32 - 96.0f32 * 46.0f32);
+    for item in batches[0].iter() {
+        assert!(item.rect.x >= 0.0f32 && item.rect.x + item.rect.width <= 100.0f32);
+        assert!(item.rect.y >= 0.0f32 && item.rect.y + item.rect.height <= 50.0f32);
+    }
+}
@@ -0,0 +1,109 @@
+// Copyright (c) 2016-2017 Bruce Stenning. All rights reserved.
+//
+// Redistribution and use in source and binary forms, with or without
+// modification, are permitted provided that the following conditions
+// are met:
+//
+// 1. Redistributions of source code must retain the above copyright
+//    notice, this list of conditions and the following disclaimer.
+//
+// 2. Redistributions in binary form must reproduce the above copyright
+//    notice, this list of conditions and the following disclaimer in the
+//    documentation and/or other materials provided with the distribution.
+//
+// 3. Neither the name of the copyright holder nor the names of its
+//    contributors may be used to endorse or promote products derived
+//    from this software without specific prior written permission.
+//
+// THIS SOFTWARE IS PROVIDED BY THE COPYRIGHT HOLDERS AND CONTRIBUTORS
+// AS IS" AND ANY EXPRESS OR IMPLIED WARRANTIES, INCLUDING, BUT NOT
+// LIMITED TO, THE IMPLIED WARRANTIES OF MERCHANTABILITY AND FITNESS
+// FOR A PARTICULAR PURPOSE ARE DISCLAIMED. IN NO EVENT SHALL THE
+// COPYRIGHT HOLDER OR CONTRIBUTORS BE LIABLE FOR ANY DIRECT, INDIRECT,
+// INCIDENTAL, SPECIAL, EXEMPLARY, OR CONSEQUENTIAL DAMAGES (INCLUDING,
+// BUT NOT LIMITED TO, PROCUREMENT OF SUBSTITUTE GOODS OR SERVICES; LOSS
+// OF USE, DATA, OR PROFITS; OR BUSINESS INTERRUPTION) HOWEVER CAUSED
+// AND ON ANY THEORY OF LIABILITY, WHETHER IN CONTRACT, STRICT LIABILITY,
+// OR TORT (INCLUDING NEGLIGENCE OR OTHERWISE) ARISING IN ANY WAY OUT OF
+// THE USE OF THIS SOFTWARE, EVEN IF ADVISED OF THE POSSIBILITY OF SUCH
+// DAMAGE.
+#![allow(unused_imports)]
+
+use graphics::devicefeatures::*;
+use graphics::parity::*;
+use graphics::perdraw::*;
+
+#[cfg(test)]
+fn capabilities(backend: &'static str) -> BackendCapabilities {
+    BackendCapabilities {
+        backend: backend,
+        device_name: "Test GPU".to_string(),
+        features: DeviceFeatures::all(),
+        max_samples: 8,
+        sample_count: 4,
+        compute: true,
+        timed_compute: true,
+        bindless_textures: true,
+        gpu_timing: true,
+        per_draw: None,
+    }
+}
+
+#[test]
+fn missing_capabilities_report_their_fallbacks() {
+    let full = ParityReport::new(&capabilities("Vulkan"));
+    assert!(full.entries.iter().all(|entry| entry.support == FeatureSupport::Supported));
+
+    let report = ParityReport::new(&BackendCapabilities {
+        features: DeviceFeatures {
+            vertex_pipeline_stores_and_atomics: false,
+            fill_mode_non_solid: false,
+            ..DeviceFeatures::all()
+        },
+        compute: false,
+        max_samples: 1,
+        ..capabilities("OpenGL")
+    });
+    assert_eq!(report.support("msaa"), Some(FeatureSupport::Fallback("single sampled")));
+    assert_eq!(report.support("compute"), Some(FeatureSupport::Unsupported));
+    assert_eq!(report.support("paced_compute"), Some(FeatureSupport::Unsupported));
+    assert_eq!(report.support("wireframe"), Some(FeatureSupport::Fallback("always filled")));
+    assert_eq!(report.support("shader_stores"), Some(FeatureSupport::Fallback("fragment shaders only")));
+    assert_eq!(report.support("ray_tracing"), None);
+
+    assert_eq!(report.differences(&full), vec!["msaa", "compute", "paced_compute", "wireframe", "shader_stores"]);
+}
+
+#[test]
+fn table_marks_the_features_that_differ() {
+    let vulkan = ParityReport::new(&capabilities("Vulkan"));
+    let opengl = ParityReport::new(&BackendCapabilities {
+        timed_compute: false,
+        bindless_textures: false,
+        ..capabilities("OpenGL")
+    });
+
+    let table = format_parity_table(&[vulkan.clone(), opengl]);
+    let lines: Vec<&str> = table.lines().collect();
+    assert_eq!(lines.len(), vulkan.entries.len() + 1);
+    assert!(lines[0].contains("Vulkan") && lines[0].contains("OpenGL"));
+    assert!(lines.iter().any(|line| line.starts_with("* bindless_textures") && line.contains("textures bound per material")));
+    assert!(lines.iter().any(|line| line.starts_with("  msaa")));
+
+    let printed = vulkan.to_string();
+    assert!(printed.starts_with("Vulkan on Test GPU, 4 samples per pixel"));
+    assert!(printed.contains("tessellation"));
+}
+
+#[test]
+fn the_report_prints_the_per_draw_benchmark() {
+    assert!(!ParityReport::new(&capabilities("Vulkan")).to_string().contains("Per-draw"));
+
+    let results = PerDrawBenchmarkResults { timings: vec![(PerDrawStrategy::StorageBuffer, 64, 0.25f32)] };
+    let report = ParityReport::new(&BackendCapabilities {
+        per_draw: Some(results.clone()),
+        ..capabilities("Vulkan")
+    });
+    assert_eq!(report.per_draw, Some(results));
+    assert!(report.to_string().contains("    64 bytes, storage buffer: 0.250"));
+}
@@ -0,0 +1,108 @@
+// Copyright (c) 2016-2017 Bruce Stenning. All rights reserved.
+//
+// Redistribution and use in source and binary forms, with or without
+// modification, are permitted provided that the following conditions
+// are met:
+//
+// 1. Redistributions of source code must retain the above copyright
+//    notice, this list of conditions and the following disclaimer.
+//
+// 2. Redistributions in binary form must reproduce the above copyright
+//    notice, this list of conditions and the following disclaimer in the
+//    documentation and/or other materials provided with the distribution.
+//
+// 3. Neither the name of the copyright holder nor the names of its
+//    contributors may be used to endorse or promote products derived
+//    from this software without specific prior written permission.
+//
+// THIS SOFTWARE IS PROVIDED BY THE COPYRIGHT HOLDERS AND CONTRIBUTORS
+// AS IS" AND ANY EXPRESS OR IMPLIED WARRANTIES, INCLUDING, BUT NOT
+// LIMITED TO, THE IMPLIED WARRANTIES OF MERCHANTABILITY AND FITNESS
+// FOR A PARTICULAR PURPOSE ARE DISCLAIMED. IN NO EVENT SHALL THE
+// COPYRIGHT HOLDER OR CONTRIBUTORS BE LIABLE FOR ANY DIRECT, INDIRECT,
+// INCIDENTAL, SPECIAL, EXEMPLARY, OR CONSEQUENTIAL DAMAGES (INCLUDING,
+// BUT NOT LIMITED TO, PROCUREMENT OF SUBSTITUTE GOODS OR SERVICES; LOSS
+// OF USE, DATA, OR PROFITS; OR BUSINESS INTERRUPTION) HOWEVER CAUSED
+// AND ON ANY THEORY OF LIABILITY, WHETHER IN CONTRACT, STRICT LIABILITY,
+// OR TORT (INCLUDING NEGLIGENCE OR OTHERWISE) ARISING IN ANY WAY OUT OF
+// THE USE OF THIS SOFTWARE, EVEN IF ADVISED OF THE POSSIBILITY OF SUCH
+// DAMAGE.
+
+#![allow(unused_imports)]
+
+use graphics::perdraw::*;
+use graphics::renderer::*;
+
+fn results() -> PerDrawBenchmarkResults {
+    PerDrawBenchmarkResults {
+        timings: vec![(PerDrawStrategy::PushConstants, 64, 0.5f32),
+                      (PerDrawStrategy::UniformBuffer, 64, 0.75f32),
+                      (PerDrawStrategy::StorageBuffer, 64, 0.25f32),
+                      (PerDrawStrategy::PushConstants, 128, 0.5f32),
+                      (PerDrawStrategy::UniformBuffer, 128, 0.75f32),
+                      (PerDrawStrategy::StorageBuffer, 128, 1.0f32),
+                      (PerDrawStrategy::UniformBuffer, 256, 0.5f32),
+                      (PerDrawStrategy::StorageBuffer, 256, 1.5f32)],
+    }
+}
+
+fn selector(override_strategy: Option<PerDrawStrategy>) -> PerDrawStrategySelector {
+    PerDrawStrategySelector::new(&RendererConfig {
+        per_draw_strategy: override_strategy,
+        ..Default::default()
+    })
+}
+
+#[test]
+fn the_fastest_strategy_is_taken_from_the_smallest_size_holding_the_data() {
+    let results = results();
+    assert_eq!(results.fastest(16), Some(PerDrawStrategy::StorageBuffer));
+    assert_eq!(results.fastest(64), Some(PerDrawStrategy::StorageBuffer));
+    assert_eq!(results.fastest(65), Some(PerDrawStrategy::PushConstants));
+    assert_eq!(results.fastest(192), Some(PerDrawStrategy::UniformBuffer));
+
+    // Beyond the largest size the largest is used, without the push constants that cannot hold it
+    assert_eq!(results.fastest(1024), Some(PerDrawStrategy::UniformBuffer));
+
+    assert_eq!(PerDrawBenchmarkResults::default().fastest(64), None);
+}
+
+#[test]
+fn the_override_is_chosen_wherever_it_holds_the_data() {
+    let mut push_constants = selector(Some(PerDrawStrategy::PushConstants));
+    push_constants.set_results(results());
+    assert_eq!(push_constants.choose(64), PerDrawStrategy::PushConstants);
+    assert_eq!(push_constants.choose(128), PerDrawStrategy::PushConstants);
+
+    // Push constants cannot hold 256 bytes, so the benchmark decides
+    assert_eq!(push_constants.choose(256), PerDrawStrategy::UniformBuffer);
+
+    let storage = selector(Some(PerDrawStrategy::StorageBuffer));
+    assert_eq!(storage.choose(64), PerDrawStrategy::StorageBuffer);
+    assert_eq!(storage.choose(256), PerDrawStrategy::StorageBuffer);
+}
+
+#[test]
+fn without_a_benchmark_push_constants_are_chosen_where_they_fit() {
+    let unbenchmarked = selector(None);
+    assert!(unbenchmarked.get_results().is_none());
+    assert_eq!(unbenchmarked.choose(64), PerDrawStrategy::PushConstants);
+    assert_eq!(unbenchmarked.choose(PER_DRAW_PUSH_CONSTANT_LIMIT), PerDrawStrategy::PushConstants);
+    assert_eq!(unbenchmarked.choose(PER_DRAW_PUSH_CONSTANT_LIMIT + 1), PerDrawStrategy::UniformBuffer);
+
+    let mut benchmarked = selector(None);
+    benchmarked.set_results(results());
+    assert_eq!(benchmarked.choose(64), PerDrawStrategy::StorageBuffer);
+    assert_eq!(benchmarked.get_results(), Some(&results()));
+}
+
+#[test]
+fn the_buffer_strategies_batch_the_draws_their_arrays_hold() {
+    assert_eq!(PerDrawStrategy::PushConstants.draws_per_buffer(64), None);
+    assert_eq!(PerDrawStrategy::UniformBuffer.draws_per_buffer(64), Some(PER_DRAW_UNIFORM_ARRAY_LENGTH));
+    assert_eq!(PerDrawStrategy::UniformBuffer.draws_per_buffer(100), Some(PER_DRAW_UNIFORM_ARRAY_LENGTH / 2));
+    assert_eq!(PerDrawStrategy::StorageBuffer.draws_per_buffer(256), Some(DEFAULT_PER_DRAW_BENCHMARK_DRAWS));
+
+    assert!(PerDrawStrategy::UniformBuffer.supports_size(PER_DRAW_UNIFORM_ARRAY_LENGTH * 64));
+    assert!(!PerDrawStrategy::UniformBuffer.supports_size(PER_DRAW_UNIFORM_ARRAY_LENGTH * 64 + 1));
+}
@@ -0,0 +1,82 @@
+// Copyright (c) 2016-2017 Bruce Stenning. All rights reserved.
+//
+// Redistribution and use in source and binary forms, with or without
+// modification, are permitted provided that the following conditions
+// are met:
+//
+// 1. Redistributions of source code must retain the above copyright
+//    notice, this list of conditions and the following disclaimer.
+//
+// 2. Redistributions in binary form must reproduce the above copyright
+//    notice, this list of conditions and the following disclaimer in the
+//    documentation and/or other materials provided with the distribution.
+//
+// 3. Neither the name of the copyright holder nor the names of its
+//    contributors may be used to endorse or promote products derived
+//    from this software without specific prior written permission.
+//
+// THIS SOFTWARE IS PROVIDED BY THE COPYRIGHT HOLDERS AND CONTRIBUTORS
+// AS IS" AND ANY EXPRESS OR IMPLIED WARRANTIES, INCLUDING, BUT NOT
+// LIMITED TO, THE IMPLIED WARRANTIES OF MERCHANTABILITY AND FITNESS
+// FOR A PARTICULAR PURPOSE ARE DISCLAIMED. IN NO EVENT SHALL THE
+// COPYRIGHT HOLDER OR CONTRIBUTORS BE LIABLE FOR ANY DIRECT, INDIRECT,
+// INCIDENTAL, SPECIAL, EXEMPLARY, OR CONSEQUENTIAL DAMAGES (INCLUDING,
+// BUT NOT LIMITED TO, PROCUREMENT OF SUBSTITUTE GOODS OR SERVICES; LOSS
+// OF USE, DATA, OR PROFITS; OR BUSINESS INTERRUPTION) HOWEVER CAUSED
+// AND ON ANY THEORY OF LIABILITY, WHETHER IN CONTRACT, STRICT LIABILITY,
+// OR TORT (INCLUDING NEGLIGENCE OR OTHERWISE) ARISING IN ANY WAY OUT OF
+// THE USE OF THIS SOFTWARE, EVEN IF ADVISED OF THE POSSIBILITY OF SUCH
+// DAMAGE.
+#![allow(unused_imports)]
+
+use graphics::pipelinebuild::*;
+
+#[test]
+fn order_puts_first_frame_shaders_first() {
+    let mut options = PipelineBuildOptions::new();
+
+    // Without first frame shaders, startup waits for all of them
+    let (ordered, blocking) = options.order(&["sky", "mesh", "overlay"]);
+    assert_eq!(ordered, vec!["mesh", "overlay", "sky"]);
+    assert_eq!(blocking, 3);
+
+    // Unknown and repeated names are left out
+    options.set_first_frame_shaders(&["sky", "missing", "overlay", "sky"]);
+    let (ordered, blocking) = options.order(&["sky", "mesh", "text", "overlay"]);
+    assert_eq!(ordered, vec!["sky", "overlay", "mesh", "text"]);
+    assert_eq!(blocking, 2);
+}
+
+#[test]
+fn tracker_reports_progress_and_waits() {
+    let mut tracker = PipelineBuildTracker::new(&["sky", "overlay", "mesh"], 2);
+    assert!(tracker.must_wait(PipelineWait::FirstFrame));
+    assert!(tracker.must_wait(PipelineWait::Shader("mesh")));
+    assert!(!tracker.must_wait(PipelineWait::Ready));
+
+    let progress = tracker.complete("overlay", false).unwrap();
+    assert_eq!(progress.built, 1);
+    assert_eq!(progress.total, 3);
+    assert!(!progress.is_complete());
+    assert!(tracker.complete("overlay", false).is_none());
+
+    let progress = tracker.complete("sky", true).unwrap();
+    assert!(progress.failed);
+    assert!(!tracker.must_wait(PipelineWait::FirstFrame));
+    assert!(tracker.must_wait(PipelineWait::All));
+    assert!(!tracker.must_wait(PipelineWait::Shader("sky")));
+
+    let progress = tracker.complete("mesh", false).unwrap();
+    assert!(progress.is_complete());
+    assert_eq!(progress.fraction(), 1.0);
+    assert!(tracker.is_finished());
+    assert!(!tracker.must_wait(PipelineWait::All));
+}
+
+#[test]
+fn build_threads_are_bounded_by_the_pipelines() {
+    assert_eq!(pipeline_build_threads(0, 4, 20), 4);
+    assert_eq!(pipeline_build_threads(0, 4, 2), 2);
+    assert_eq!(pipeline_build_threads(8, 4, 20), 8);
+    assert_eq!(pipeline_build_threads(0, 0, 0), 1);
+}
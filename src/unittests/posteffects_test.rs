@@ -0,0 +1,85 @@
+// Copyright (c) 2016-2017 Bruce Stenning. All rights reserved.
+//
+// Redistribution and use in source and binary forms, with or without
+// modification, are permitted provided that the following conditions
+// are met:
+//
+// 1. Redistributions of source code must retain the above copyright
+//    notice, this list of conditions and the following disclaimer.
+//
+// 2. Redistributions in binary form must reproduce the above copyright
+//    notice, this list of conditions and the following disclaimer in the
+//    documentation and/or other materials provided with the distribution.
+//
+// 3. Neither the name of the copyright holder nor the names of its
+//    contributors may be used to endorse or promote products derived
+//    from this software without specific prior written permission.
+//
+// THIS SOFTWARE IS PROVIDED BY THE COPYRIGHT HOLDERS AND CONTRIBUTORS
+// AS IS" AND ANY EXPRESS OR IMPLIED WARRANTIES, INCLUDING, BUT NOT
+// LIMITED TO, THE IMPLIED WARRANTIES OF MERCHANTABILITY AND FITNESS
+// FOR A PARTICULAR PURPOSE ARE DISCLAIMED. IN NO EVENT SHALL THE
+// COPYRIGHT HOLDER OR CONTRIBUTORS BE LIABLE FOR ANY DIRECT, INDIRECT,
+// INCIDENTAL, SPECIAL, EXEMPLARY, OR CONSEQUENTIAL DAMAGES (INCLUDING,
+// BUT NOT LIMITED TO, PROCUREMENT OF SUBSTITUTE GOODS OR SERVICES; LOSS
+// OF USE, DATA, OR PROFITS; OR BUSINESS INTERRUPTION) HOWEVER CAUSED
+// AND ON ANY THEORY OF LIABILITY, WHETHER IN CONTRACT, STRICT LIABILITY,
+// OR TORT (INCLUDING NEGLIGENCE OR OTHERWISE) ARISING IN ANY WAY OUT OF
+// THE USE OF THIS SOFTWARE, EVEN IF ADVISED OF THE POSSIBILITY OF SUCH
+// DAMAGE.
+#![allow(unused_imports)]
+
+use graphics::posteffects::*;
+
+#[test]
+fn plan_ping_pongs_between_the_targets_and_skips_disabled_effects() {
+    let mut chain = PostEffectChain::builtin();
+    let steps = chain.plan();
+    assert_eq!(steps.len(), 3);
+    assert_eq!(steps[0].source, PostEffectSource::Scene);
+    assert_eq!(steps[0].target, 0);
+    assert_eq!(steps[1].source, PostEffectSource::Target(0));
+    assert_eq!(steps[1].target, 1);
+    assert_eq!(steps[2].source, PostEffectSource::Target(1));
+    assert_eq!(steps[2].target, 0);
+
+    chain.set_enabled(FXAA_EFFECT_NAME, false).unwrap();
+    let steps = chain.plan();
+    assert_eq!(steps.len(), 2);
+    assert_eq!(steps[1].effect, 2);
+    assert_eq!(steps[1].source, PostEffectSource::Target(0));
+    assert_eq!(steps[1].target, 1);
+
+    for name in [BLOOM_EFFECT_NAME, VIGNETTE_EFFECT_NAME].iter() {
+        chain.set_enabled(name, false).unwrap();
+    }
+    assert!(chain.plan().is_empty());
+}
+
+#[test]
+fn effects_can_be_reordered_and_removed() {
+    let mut chain = PostEffectChain::builtin();
+    chain.move_to(VIGNETTE_EFFECT_NAME, 0).unwrap();
+    assert_eq!(chain.position(VIGNETTE_EFFECT_NAME), Some(0));
+    assert_eq!(chain.position(BLOOM_EFFECT_NAME), Some(1));
+
+    chain.move_to(VIGNETTE_EFFECT_NAME, 10).unwrap();
+    assert_eq!(chain.position(VIGNETTE_EFFECT_NAME), Some(2));
+
+    let removed = chain.remove(FXAA_EFFECT_NAME).unwrap();
+    assert_eq!(removed.shader_name, FXAA_EFFECT_NAME);
+    assert_eq!(chain.effects().len(), 2);
+    assert!(chain.find(FXAA_EFFECT_NAME).is_none());
+}
+
+#[test]
+fn unknown_and_duplicate_effects_are_rejected() {
+    let mut chain = PostEffectChain::new();
+    chain.push(PostEffect::new("sharpen", "sharpen")).unwrap();
+    assert_eq!(chain.push(PostEffect::new("sharpen", "sharpen_other")),
+               Err(PostEffectError::DuplicateEffect("sharpen")));
+    assert_eq!(chain.set_strength("blur", 0.5), Err(PostEffectError::UnknownEffect("blur".to_string())));
+
+    chain.set_strength("sharpen", 0.5).unwrap();
+    assert_eq!(chain.find("sharpen").unwrap().strength, 0.5);
+}
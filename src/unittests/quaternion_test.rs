@@ -170,3 +170,68 @@ fn rotation() {
 
     assert!(v2.approx_eq_ulps(&e1, 2));
 }
+
+#[test]
+fn slerp_interpolates_the_angle_between_rotations() {
+    let axis = Vec3 {
+        x: 0.0f32,
+        y: 0.0f32,
+        z: 1.0f32,
+    };
+    let q1 = Quaternion::identity();
+    let q2 = Quaternion::axis_and_angle_to_quaternion(&axis, 90.0f32);
+
+    assert!(Quaternion::slerp(&q1, &q2, 0.0f32).approx_eq_ulps(&q1, 4));
+    assert!(Quaternion::slerp(&q1, &q2, 1.0f32).approx_eq_ulps(&q2, 4));
+
+    let halfway = Quaternion::slerp(&q1, &q2, 0.5f32);
+    let e1 = Quaternion::axis_and_angle_to_quaternion(&axis, 45.0f32);
+
+    println!("result is {}", halfway);
+    println!("expected is {}", e1);
+
+    assert!((halfway.dot(&e1) - 1.0f32).abs() < 1.0e-6f32);
+}
+
+#[test]
+fn to_mat4_rotates_as_the_quaternion_does() {
+    let q1 = Quaternion::axis_and_angle_to_quaternion(&Vec3 {
+                                                          x: 1.0f32,
+                                                          y: 2.0f32,
+                                                          z: -0.5f32,
+                                                      }
+                                                      .normalise(),
+                                                      70.0f32);
+    let v1 = Vec3 {
+        x: 0.3f32,
+        y: -1.0f32,
+        z: 2.0f32,
+    };
+
+    let v2 = q1.to_mat4().mul_by_vec3(v1).project();
+    let e1 = Quaternion::rotate(&v1, &q1);
+
+    println!("result is {}", v2);
+    println!("expected is {}", e1);
+
+    assert!((v2 - e1).magnitude() < 1.0e-5f32);
+}
+
+#[test]
+fn from_mat4_recovers_the_rotation() {
+    let q1 = Quaternion::axis_and_angle_to_quaternion(&Vec3 {
+                                                          x: -1.0f32,
+                                                          y: 0.0f32,
+                                                          z: 1.0f32,
+                                                      }
+                                                      .normalise(),
+                                                      200.0f32);
+
+    let q2 = Quaternion::from_mat4(&q1.to_mat4());
+
+    println!("result is {}", q2);
+    println!("expected is {}", q1);
+
+    // q and -q describe the same rotation
+    assert!((q2.dot(&q1).abs() - 1.0f32).abs() < 1.0e-6f32);
+}
@@ -0,0 +1,65 @@
+// Copyright (c) 2016-2017 Bruce Stenning. All rights reserved.
+//
+// Redistribution and use in source and binary forms, with or without
+// modification, are permitted provided that the following conditions
+// are met:
+//
+// 1. Redistributions of source code must retain the above copyright
+//    notice, this list of conditions and the following disclaimer.
+//
+// 2. Redistributions in binary form must reproduce the above copyright
+//    notice, this list of conditions and the following disclaimer in the
+//    documentation and/or other materials provided with the distribution.
+//
+// 3. Neither the name of the copyright holder nor the names of its
+//    contributors may be used to endorse or promote products derived
+//    from this software without specific prior written permission.
+//
+// THIS SOFTWARE IS PROVIDED BY THE COPYRIGHT HOLDERS AND CONTRIBUTORS
+// AS IS" AND ANY EXPRESS OR IMPLIED WARRANTIES, INCLUDING, BUT NOT
+// LIMITED TO, THE IMPLIED WARRANTIES OF MERCHANTABILITY AND FITNESS
+// FOR A PARTICULAR PURPOSE ARE DISCLAIMED. IN NO EVENT SHALL THE
+// COPYRIGHT HOLDER OR CONTRIBUTORS BE LIABLE FOR ANY DIRECT, INDIRECT,
+// INCIDENTAL, SPECIAL, EXEMPLARY, OR CONSEQUENTIAL DAMAGES (INCLUDING,
+// BUT NOT LIMITED TO, PROCUREMENT OF SUBSTITUTE GOODS OR SERVICES; LOSS
+// OF USE, DATA, OR PROFITS; OR BUSINESS INTERRUPTION) HOWEVER CAUSED
+// AND ON ANY THEORY OF LIABILITY, WHETHER IN CONTRACT, STRICT LIABILITY,
+// OR TORT (INCLUDING NEGLIGENCE OR OTHERWISE) ARISING IN ANY WAY OUT OF
+// THE USE OF THIS SOFTWARE, EVEN IF ADVISED OF THE POSSIBILITY OF SUCH
+// DAMAGE.
+#![allow(unused_imports)]
+
+use std::sync::{Arc, Mutex};
+
+use graphics::image::*;
+use graphics::readback::*;
+
+#[test]
+fn completed_readbacks_are_held_until_taken() {
+    let mut results = ReadbackResults::new();
+    assert!(results.take(3).is_none());
+
+    results.complete(3, Image::create_from_raw_data(2, 1, &vec![0u8; 6]));
+    assert_eq!(results.take(3).map(|image| image.get_width()), Some(2));
+    assert!(results.take(3).is_none());
+}
+
+#[test]
+fn readbacks_with_callbacks_are_passed_to_them_instead() {
+    let delivered: Arc<Mutex<Vec<(u32, u32)>>> = Arc::new(Mutex::new(vec![]));
+    let mut results = ReadbackResults::new();
+    let sink = delivered.clone();
+    results.set_callback(7, Box::new(move |image: Image| sink.lock().unwrap().push((image.get_width(), image.get_height()))));
+
+    // Other readbacks are still held for polling
+    results.complete(8, Image::create_from_raw_data(1, 1, &vec![0u8; 3]));
+    results.complete(7, Image::create_from_raw_data(4, 2, &vec![0u8; 24]));
+    assert_eq!(*delivered.lock().unwrap(), vec![(4, 2)]);
+    assert!(results.take(7).is_none());
+    assert!(results.take(8).is_some());
+
+    // The callback is called once, and a later readback with the handle is held instead
+    results.complete(7, Image::create_from_raw_data(4, 2, &vec![0u8; 24]));
+    assert_eq!(delivered.lock().unwrap().len(), 1);
+    assert!(results.take(7).is_some());
+}
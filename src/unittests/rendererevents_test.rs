@@ -0,0 +1,99 @@
+// Copyright (c) 2016-2017 Bruce Stenning. All rights reserved.
+//
+// Redistribution and use in source and binary forms, with or without
+// modification, are permitted provided that the following conditions
+// are met:
+//
+// 1. Redistributions of source code must retain the above copyright
+//    notice, this list of conditions and the following disclaimer.
+//
+// 2. Redistributions in binary form must reproduce the above copyright
+//    notice, this list of conditions and the following disclaimer in the
+//    documentation and/or other materials provided with the distribution.
+//
+// 3. Neither the name of the copyright holder nor the names of its
+//    contributors may be used to endorse or promote products derived
+//    from this software without specific prior written permission.
+//
+// THIS SOFTWARE IS PROVIDED BY THE COPYRIGHT HOLDERS AND CONTRIBUTORS
+// AS IS" AND ANY EXPRESS OR IMPLIED WARRANTIES, INCLUDING, BUT NOT
+// LIMITED TO, THE IMPLIED WARRANTIES OF MERCHANTABILITY AND FITNESS
+// FOR A PARTICULAR PURPOSE ARE DISCLAIMED. IN NO EVENT SHALL THE
+// COPYRIGHT HOLDER OR CONTRIBUTORS BE LIABLE FOR ANY DIRECT, INDIRECT,
+// INCIDENTAL, SPECIAL, EXEMPLARY, OR CONSEQUENTIAL DAMAGES (INCLUDING,
+// BUT NOT LIMITED TO, PROCUREMENT OF SUBSTITUTE GOODS OR SERVICES; LOSS
+// OF USE, DATA, OR PROFITS; OR BUSINESS INTERRUPTION) HOWEVER CAUSED
+// AND ON ANY THEORY OF LIABILITY, WHETHER IN CONTRACT, STRICT LIABILITY,
+// OR TORT (INCLUDING NEGLIGENCE OR OTHERWISE) ARISING IN ANY WAY OUT OF
+// THE USE OF THIS SOFTWARE, EVEN IF ADVISED OF THE POSSIBILITY OF SUCH
+// DAMAGE.
+#![allow(unused_imports)]
+
+use std::sync::{Arc, Mutex};
+
+use graphics::debugmessages::*;
+use graphics::rendererevents::*;
+
+#[cfg(test)]
+struct RecordingListener {
+    events: Arc<Mutex<Vec<String>>>,
+}
+
+#[cfg(test)]
+impl RendererEventListener for RecordingListener {
+    fn on_swapchain_recreated(&self, new_extent: (u32, u32)) {
+        self.events.lock().unwrap().push(format!("swapchain {}x{}", new_extent.0, new_extent.1));
+    }
+
+    fn on_shader_rebuilt(&self, shader_name: &str) {
+        self.events.lock().unwrap().push(format!("shader {}", shader_name));
+    }
+}
+
+// Overrides nothing, so only counts as subscribed
+#[cfg(test)]
+struct SilentListener;
+
+#[cfg(test)]
+impl RendererEventListener for SilentListener {}
+
+#[test]
+fn listeners_receive_their_events() {
+    let events = RendererEvents::new();
+    let received = Arc::new(Mutex::new(vec![]));
+    events.subscribe(Box::new(RecordingListener { events: received.clone() }));
+    events.subscribe(Box::new(SilentListener));
+
+    events.swapchain_recreated((1280, 720));
+    events.shader_rebuilt("terrain");
+    events.device_lost();
+    events.validation_message(&DebugMessage {
+        severity: DebugMessageSeverity::Error,
+        validation: true,
+        performance: false,
+        id_name: "VUID-test".to_string(),
+        id_number: 1,
+        message: "test".to_string(),
+    });
+
+    assert_eq!(*received.lock().unwrap(), vec!["swapchain 1280x720".to_string(), "shader terrain".to_string()]);
+}
+
+#[test]
+fn unsubscribed_listeners_are_not_called() {
+    let events = RendererEvents::new();
+    let first = Arc::new(Mutex::new(vec![]));
+    let second = Arc::new(Mutex::new(vec![]));
+    let a = events.subscribe(Box::new(RecordingListener { events: first.clone() }));
+    let b = events.subscribe(Box::new(RecordingListener { events: second.clone() }));
+    assert!(a != b);
+    assert_eq!(events.len(), 2);
+
+    assert!(events.unsubscribe(a));
+    assert!(!events.unsubscribe(a));
+    events.shader_rebuilt("sky");
+
+    assert!(first.lock().unwrap().is_empty());
+    assert_eq!(*second.lock().unwrap(), vec!["shader sky".to_string()]);
+    assert_eq!(events.len(), 1);
+}
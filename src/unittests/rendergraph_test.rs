@@ -0,0 +1,219 @@
+// Copyright (c) 2016-2017 Bruce Stenning. All rights reserved.
+//
+// Redistribution and use in source and binary forms, with or without
+// modification, are permitted provided that the following conditions
+// are met:
+//
+// 1. Redistributions of source code must retain the above copyright
+//    notice, this list of conditions and the following disclaimer.
+//
+// 2. Redistributions in binary form must reproduce the above copyright
+//    notice, this list of conditions and the following disclaimer in the
+//    documentation and/or other materials provided with the distribution.
+//
+// 3. Neither the name of the copyright holder nor the names of its
+//    contributors may be used to endorse or promote products derived
+//    from this software without specific prior written permission.
+//
+// THIS SOFTWARE IS PROVIDED BY THE COPYRIGHT HOLDERS AND CONTRIBUTORS
+// AS IS" AND ANY EXPRESS OR IMPLIED WARRANTIES, INCLUDING, BUT NOT
+// LIMITED TO, THE IMPLIED WARRANTIES OF MERCHANTABILITY AND FITNESS
+// FOR A PARTICULAR PURPOSE ARE DISCLAIMED. IN NO EVENT SHALL THE
+// COPYRIGHT HOLDER OR CONTRIBUTORS BE LIABLE FOR ANY DIRECT, INDIRECT,
+// INCIDENTAL, SPECIAL, EXEMPLARY, OR CONSEQUENTIAL DAMAGES (INCLUDING,
+// BUT NOT LIMITED TO, PROCUREMENT OF SUBSTITUTE GOODS OR SERVICES; LOSS
+// OF USE, DATA, OR PROFITS; OR BUSINESS INTERRUPTION) HOWEVER CAUSED
+// AND ON ANY THEORY OF LIABILITY, WHETHER IN CONTRACT, STRICT LIABILITY,
+// OR TORT (INCLUDING NEGLIGENCE OR OTHERWISE) ARISING IN ANY WAY OUT OF
+// THE USE OF THIS SOFTWARE, EVEN IF ADVISED OF THE POSSIBILITY OF SUCH
+// DAMAGE.
+#![allow(unused_imports)]
+
+use graphics::rendergraph::*;
+use graphics::renderpasses::*;
+
+#[cfg(test)]
+fn deferred_graph() -> RenderGraph {
+    // The composite is declared first, and reads the lighting as the last pass leaves it
+    let mut graph = RenderGraph::new();
+    graph.add_pass(RenderGraphPass::new("composite", SWAPCHAIN_PASS).reads("lighting").writes(SWAPCHAIN_ATTACHMENT));
+    graph.add_pass(RenderGraphPass::new("geometry", "scene").writes("gbuffer").writes_depth("depth"));
+    graph.add_pass(RenderGraphPass::new("lighting", "scene").reads("gbuffer").reads("depth").writes("lighting"));
+    graph.add_pass(RenderGraphPass::new("transparent", "scene").reads("environment").writes("lighting").writes_depth("depth"));
+    graph
+}
+
+#[test]
+fn render_graph_orders_passes_by_dependency() {
+    let schedule = deferred_graph().compile().unwrap();
+    assert_eq!(schedule.order(), vec!["geometry", "lighting", "transparent", "composite"]);
+
+    let lighting = schedule.find("lighting").unwrap();
+    assert!(lighting.dependencies.contains(&PassDependency {
+        from: "geometry",
+        to: "lighting",
+        attachment: "depth",
+        kind: DependencyKind::ReadAfterWrite,
+    }));
+
+    // The transparent pass draws depth after the lighting pass has sampled it
+    let transparent = schedule.find("transparent").unwrap();
+    assert!(transparent.dependencies.iter().any(|d| d.from == "lighting" && d.kind == DependencyKind::WriteAfterRead));
+    assert_eq!(transparent.loads, vec!["lighting", "depth"]);
+
+    assert!(schedule.is_depth_attachment("depth"));
+    assert!(!schedule.is_depth_attachment("gbuffer"));
+    assert!(schedule.has_external_dependencies(SWAPCHAIN_PASS));
+}
+
+#[test]
+fn render_graph_transitions_attachments() {
+    let schedule = deferred_graph().compile().unwrap();
+
+    let geometry = schedule.find("geometry").unwrap();
+    assert!(geometry.loads.is_empty());
+    assert_eq!(geometry.transitions[1],
+               LayoutTransition {
+                   attachment: "depth",
+                   from: AttachmentLayout::Undefined,
+                   to: AttachmentLayout::DepthAttachment,
+               });
+
+    // Attachments no pass writes are left alone
+    let transparent = schedule.find("transparent").unwrap();
+    assert_eq!(transparent.transitions,
+               vec![LayoutTransition {
+                        attachment: "depth",
+                        from: AttachmentLayout::ShaderRead,
+                        to: AttachmentLayout::DepthAttachment,
+                    }]);
+
+    assert_eq!(schedule.final_transitions()[0].to, AttachmentLayout::Present);
+}
+
+#[test]
+fn render_graph_rejects_cycles_and_feedback() {
+    let mut graph = RenderGraph::new();
+    graph.add_pass(RenderGraphPass::new("blur", "scene").reads("a").writes("a"));
+    assert_eq!(graph.compile().unwrap_err(),
+               RenderGraphError::FeedbackLoop {
+                   pass: "blur",
+                   attachment: "a",
+               });
+
+    // The first reads what the second leaves, which reads what the first writes
+    let mut graph = RenderGraph::new();
+    graph.add_pass(RenderGraphPass::new("first", "scene").reads("b").writes("a"));
+    graph.add_pass(RenderGraphPass::new("second", "scene").reads("a").writes("b"));
+    match graph.compile() {
+        Err(RenderGraphError::Cycle(passes)) => assert_eq!(passes, vec!["first", "second"]),
+        other => panic!("Unexpected result {:?}", other),
+    }
+}
+
+#[test]
+fn render_graph_passes_keep_clear_values() {
+    let mut graph = RenderGraph::new();
+    graph.add_pass(RenderGraphPass::new("geometry", "scene")
+                       .writes("gbuffer")
+                       .writes_depth("depth")
+                       .clears_colour([0.0f32, 0.0f32, 0.0f32, 1.0f32])
+                       .clears_depth(1.0f32));
+    graph.add_pass(RenderGraphPass::new("composite", SWAPCHAIN_PASS).reads("gbuffer").writes(SWAPCHAIN_ATTACHMENT));
+    let schedule = graph.compile().unwrap();
+
+    let geometry = schedule.find("geometry").unwrap();
+    assert_eq!(geometry.clear,
+               PassClear {
+                   colour: Some([0.0f32, 0.0f32, 0.0f32, 1.0f32]),
+                   depth: Some(1.0f32),
+               });
+    assert!(schedule.find("composite").unwrap().clear.is_empty());
+}
+
+#[test]
+fn render_graph_does_not_load_cleared_outputs() {
+    // The transparent pass starts its depth afresh, but still draws over the lighting
+    let mut graph = RenderGraph::new();
+    graph.add_pass(RenderGraphPass::new("composite", SWAPCHAIN_PASS).reads("lighting").writes(SWAPCHAIN_ATTACHMENT));
+    graph.add_pass(RenderGraphPass::new("geometry", "scene").writes("gbuffer").writes_depth("depth"));
+    graph.add_pass(RenderGraphPass::new("lighting", "scene").reads("gbuffer").reads("depth").writes("lighting"));
+    graph.add_pass(RenderGraphPass::new("transparent", "scene")
+                       .reads("environment")
+                       .writes("lighting")
+                       .writes_depth("depth")
+                       .clears_depth(1.0f32));
+    let schedule = graph.compile().unwrap();
+    assert_eq!(schedule.find("transparent").unwrap().loads, vec!["lighting"]);
+}
+
+#[test]
+fn submission_batches_split_at_dependency_edges() {
+    let schedule = deferred_graph().compile().unwrap();
+    let passes = [Some("geometry"), Some("geometry"), None, Some("lighting"), Some("transparent"), None, Some("composite"), None];
+    let batches = schedule.submission_batches(&passes);
+
+    assert_eq!(batches.iter().map(|b| b.start).collect::<Vec<_>>(), vec![0, 3, 4, 6]);
+    assert!(batches[0].waits.is_empty());
+    assert_eq!(batches[1].waits, vec![(0, DependencyKind::ReadAfterWrite)]);
+
+    // The transparent pass draws over the geometry's depth after the lighting has sampled it
+    assert_eq!(batches[2].waits,
+               vec![(0, DependencyKind::WriteAfterWrite), (1, DependencyKind::WriteAfterRead)]);
+    assert_eq!(batches[3].waits, vec![(2, DependencyKind::ReadAfterWrite)]);
+
+    // Each wait comes from a dependency, so the schedule's dependencies bound the semaphores needed
+    let waits: usize = batches.iter().map(|b| b.waits.len()).sum();
+    assert_eq!(waits, 4);
+    assert!(waits <= schedule.dependency_count());
+}
+
+#[test]
+fn the_swapchain_batch_holds_the_first_command_buffer_that_may_write_it() {
+    let schedule = deferred_graph().compile().unwrap();
+    let passes = [Some("geometry"), Some("lighting"), Some("transparent"), Some("composite"), None];
+    let batches = schedule.submission_batches(&passes);
+    assert_eq!(batches.len(), 4);
+    assert_eq!(schedule.swapchain_batch(&passes, &batches), Some(3));
+
+    // Command buffers of no pass, or of passes outside the graph, may draw to the swapchain
+    let passes = [Some("geometry"), None, Some("lighting"), Some("composite")];
+    let batches = schedule.submission_batches(&passes);
+    assert_eq!(schedule.swapchain_batch(&passes, &batches), Some(0));
+    let passes = [Some("geometry"), Some("lighting"), Some("hud")];
+    let batches = schedule.submission_batches(&passes);
+    assert_eq!(schedule.swapchain_batch(&passes, &batches), Some(1));
+
+    let passes = [Some("geometry"), Some("lighting")];
+    let batches = schedule.submission_batches(&passes);
+    assert_eq!(schedule.swapchain_batch(&passes, &batches), None);
+}
+
+#[test]
+fn submission_batches_keep_independent_passes_together() {
+    let mut graph = RenderGraph::new();
+    graph.add_pass(RenderGraphPass::new("shadows", "shadow").writes_depth("shadow_map"));
+    graph.add_pass(RenderGraphPass::new("geometry", "scene").writes("gbuffer"));
+    graph.add_pass(RenderGraphPass::new("lighting", "scene").reads("shadow_map").reads("gbuffer").writes("lighting"));
+    let schedule = graph.compile().unwrap();
+
+    // Both inputs of the lighting are in the first batch, which is waited on once
+    let batches = schedule.submission_batches(&[Some("shadows"), Some("geometry"), Some("lighting")]);
+    assert_eq!(batches,
+               vec![SubmissionBatch {
+                        start: 0,
+                        waits: vec![],
+                    },
+                    SubmissionBatch {
+                        start: 2,
+                        waits: vec![(0, DependencyKind::ReadAfterWrite)],
+                    }]);
+
+    // Command buffers sent before the first pass, and passes already submitted, wait on nothing
+    let batches = schedule.submission_batches(&[None, Some("lighting")]);
+    assert_eq!(batches,
+               vec![SubmissionBatch {
+                        start: 0,
+                        waits: vec![],
+                    }]);
+}
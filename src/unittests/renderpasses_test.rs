@@ -0,0 +1,87 @@
+// Copyright (c) 2016-2017 Bruce Stenning. All rights reserved.
+//
+// Redistribution and use in source and binary forms, with or without
+// modification, are permitted provided that the following conditions
+// are met:
+//
+// 1. Redistributions of source code must retain the above copyright
+//    notice, this list of conditions and the following disclaimer.
+//
+// 2. Redistributions in binary form must reproduce the above copyright
+//    notice, this list of conditions and the following disclaimer in the
+//    documentation and/or other materials provided with the distribution.
+//
+// 3. Neither the name of the copyright holder nor the names of its
+//    contributors may be used to endorse or promote products derived
+//    from this software without specific prior written permission.
+//
+// THIS SOFTWARE IS PROVIDED BY THE COPYRIGHT HOLDERS AND CONTRIBUTORS
+// AS IS" AND ANY EXPRESS OR IMPLIED WARRANTIES, INCLUDING, BUT NOT
+// LIMITED TO, THE IMPLIED WARRANTIES OF MERCHANTABILITY AND FITNESS
+// FOR A PARTICULAR PURPOSE ARE DISCLAIMED. IN NO EVENT SHALL THE
+// COPYRIGHT HOLDER OR CONTRIBUTORS BE LIABLE FOR ANY DIRECT, INDIRECT,
+// INCIDENTAL, SPECIAL, EXEMPLARY, OR CONSEQUENTIAL DAMAGES (INCLUDING,
+// BUT NOT LIMITED TO, PROCUREMENT OF SUBSTITUTE GOODS OR SERVICES; LOSS
+// OF USE, DATA, OR PROFITS; OR BUSINESS INTERRUPTION) HOWEVER CAUSED
+// AND ON ANY THEORY OF LIABILITY, WHETHER IN CONTRACT, STRICT LIABILITY,
+// OR TORT (INCLUDING NEGLIGENCE OR OTHERWISE) ARISING IN ANY WAY OUT OF
+// THE USE OF THIS SOFTWARE, EVEN IF ADVISED OF THE POSSIBILITY OF SUCH
+// DAMAGE.
+#![allow(unused_imports)]
+
+use graphics::renderpasses::*;
+
+#[test]
+fn render_pass_registry_declares_swapchain_pass() {
+    let registry = RenderPassRegistry::new();
+    let swapchain = registry.find(SWAPCHAIN_PASS).unwrap();
+    assert_eq!(swapchain.target, PassTarget::Swapchain);
+    assert_eq!(swapchain.colour_format, PassColourFormat::Surface);
+    assert_eq!(swapchain.colour_attachments, 1);
+    assert!(!swapchain.depth);
+    assert!(registry.find("scene").is_none());
+}
+
+#[test]
+fn render_pass_registry_rejects_colliding_passes() {
+    let mut registry = RenderPassRegistry::new();
+    assert_eq!(registry.declare(RenderPassDecl::offscreen("gbuffer", 3)), Ok(()));
+    assert_eq!(registry.declare(RenderPassDecl::offscreen("gbuffer", 1).without_depth()),
+               Err(RenderPassError::DuplicatePass("gbuffer")));
+    assert_eq!(registry.declare(RenderPassDecl::offscreen(SWAPCHAIN_PASS, 1)),
+               Err(RenderPassError::DuplicatePass(SWAPCHAIN_PASS)));
+
+    // Only the pass declared with the registry draws to the swapchain
+    let mut second = RenderPassDecl::offscreen("mirror", 1);
+    second.target = PassTarget::Swapchain;
+    assert_eq!(registry.declare(second), Err(RenderPassError::SwapchainTarget("mirror")));
+    assert_eq!(registry.passes().iter().map(|p| p.name).collect::<Vec<_>>(), vec![SWAPCHAIN_PASS, "gbuffer"]);
+}
+
+#[test]
+fn render_pass_registry_validates_uses() {
+    let mut registry = RenderPassRegistry::new();
+    registry.declare(RenderPassDecl::offscreen("gbuffer", 3)).unwrap();
+
+    let use_of = |user, pass_name, colour_outputs| {
+        PassUse {
+            user: user,
+            pass_name: pass_name,
+            colour_outputs: colour_outputs,
+        }
+    };
+    assert_eq!(registry.validate(&[use_of("geometry", "gbuffer", 3), use_of("composite", SWAPCHAIN_PASS, 1)]),
+               Ok(()));
+    assert_eq!(registry.validate(&[use_of("bloom", "postprocess", 1)]),
+               Err(RenderPassError::UnknownPass {
+                   user: "bloom",
+                   pass: "postprocess",
+               }));
+    assert_eq!(registry.validate(&[use_of("composite", SWAPCHAIN_PASS, 2)]),
+               Err(RenderPassError::TooManyOutputs {
+                   user: "composite",
+                   pass: SWAPCHAIN_PASS,
+                   outputs: 2,
+                   attachments: 1,
+               }));
+}
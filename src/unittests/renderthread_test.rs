@@ -0,0 +1,60 @@
+// Copyright (c) 2016-2017 Bruce Stenning. All rights reserved.
+//
+// Redistribution and use in source and binary forms, with or without
+// modification, are permitted provided that the following conditions
+// are met:
+//
+// 1. Redistributions of source code must retain the above copyright
+//    notice, this list of conditions and the following disclaimer.
+//
+// 2. Redistributions in binary form must reproduce the above copyright
+//    notice, this list of conditions and the following disclaimer in the
+//    documentation and/or other materials provided with the distribution.
+//
+// 3. Neither the name of the copyright holder nor the names of its
+//    contributors may be used to endorse or promote products derived
+//    from this software without specific prior written permission.
+//
+// THIS SOFTWARE IS PROVIDED BY THE COPYRIGHT HOLDERS AND CONTRIBUTORS
+// AS IS" AND ANY EXPRESS OR IMPLIED WARRANTIES, INCLUDING, BUT NOT
+// LIMITED TO, THE IMPLIED WARRANTIES OF MERCHANTABILITY AND FITNESS
+// FOR A PARTICULAR PURPOSE ARE DISCLAIMED. IN NO EVENT SHALL THE
+// COPYRIGHT HOLDER OR CONTRIBUTORS BE LIABLE FOR ANY DIRECT, INDIRECT,
+// INCIDENTAL, SPECIAL, EXEMPLARY, OR CONSEQUENTIAL DAMAGES (INCLUDING,
+// BUT NOT LIMITED TO, PROCUREMENT OF SUBSTITUTE GOODS OR SERVICES; LOSS
+// OF USE, DATA, OR PROFITS; OR BUSINESS INTERRUPTION) HOWEVER CAUSED
+// AND ON ANY THEORY OF LIABILITY, WHETHER IN CONTRACT, STRICT LIABILITY,
+// OR TORT (INCLUDING NEGLIGENCE OR OTHERWISE) ARISING IN ANY WAY OUT OF
+// THE USE OF THIS SOFTWARE, EVEN IF ADVISED OF THE POSSIBILITY OF SUCH
+// DAMAGE.
+#![allow(unused_imports)]
+
+use graphics::renderthread::*;
+
+#[test]
+fn frame_commands_execute_in_order_and_empty_the_queue() {
+    let mut frame: FrameCommands<Vec<u32>> = FrameCommands::new();
+    frame.push(|target: &mut Vec<u32>| target.push(1));
+    frame.push(|target: &mut Vec<u32>| target.push(2));
+    assert_eq!(frame.len(), 2);
+
+    let mut target = vec![];
+    frame.execute(&mut target);
+    assert_eq!(target, vec![1, 2]);
+    assert!(frame.is_empty());
+}
+
+#[test]
+fn render_thread_executes_every_frame_on_its_own_target() {
+    let mut render_thread = RenderThread::spawn(|| vec![0u32]);
+    for i in 1..5 {
+        let mut frame = render_thread.begin_frame();
+        assert!(frame.is_empty());
+        frame.push(move |target: &mut Vec<u32>| target.push(i));
+        render_thread.submit(frame);
+    }
+    assert_eq!(render_thread.frames_submitted(), 4);
+
+    let target = render_thread.finish();
+    assert_eq!(target, vec![0, 1, 2, 3, 4]);
+}
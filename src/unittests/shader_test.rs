@@ -0,0 +1,80 @@
+// Copyright (c) 2016-2017 Bruce Stenning. All rights reserved.
+//
+// Redistribution and use in source and binary forms, with or without
+// modification, are permitted provided that the following conditions
+// are met:
+//
+// 1. Redistributions of source code must retain the above copyright
+//    notice, this list of conditions and the following disclaimer.
+//
+// 2. Redistributions in binary form must reproduce the above copyright
+//    notice, this list of conditions and the following disclaimer in the
+//    documentation and/or other materials provided with the distribution.
+//
+// 3. Neither the name of the copyright holder nor the names of its
+//    contributors may be used to endorse or promote products derived
+//    from this software without specific prior written permission.
+//
+// THIS SOFTWARE IS PROVIDED BY THE COPYRIGHT HOLDERS AND CONTRIBUTORS
+// AS IS" AND ANY EXPRESS OR IMPLIED WARRANTIES, INCLUDING, BUT NOT
+// LIMITED TO, THE IMPLIED WARRANTIES OF MERCHANTABILITY AND FITNESS
+// FOR A PARTICULAR PURPOSE ARE DISCLAIMED. IN NO EVENT SHALL THE
+// COPYRIGHT HOLDER OR CONTRIBUTORS BE LIABLE FOR ANY DIRECT, INDIRECT,
+// INCIDENTAL, SPECIAL, EXEMPLARY, OR CONSEQUENTIAL DAMAGES (INCLUDING,
+// BUT NOT LIMITED TO, PROCUREMENT OF SUBSTITUTE GOODS OR SERVICES; LOSS
+// OF USE, DATA, OR PROFITS; OR BUSINESS INTERRUPTION) HOWEVER CAUSED
+// AND ON ANY THEORY OF LIABILITY, WHETHER IN CONTRACT, STRICT LIABILITY,
+// OR TORT (INCLUDING NEGLIGENCE OR OTHERWISE) ARISING IN ANY WAY OUT OF
+// THE USE OF THIS SOFTWARE, EVEN IF ADVISED OF THE POSSIBILITY OF SUCH
+// DAMAGE.
+#![allow(unused_imports)]
+
+use graphics::resources::*;
+use graphics::shader::*;
+
+#[cfg(test)]
+fn stage(filename: &'static str,
+         shader_stage: ShaderStage,
+         spirv_out: &'static str,
+         entry_point: &'static str)
+         -> ShaderFilesSpecification {
+    ShaderFilesSpecification {
+        filename: filename,
+        shader_stage: shader_stage,
+        spirv_out: spirv_out,
+        reflect_out: "unused.rfl",
+        entry_point: entry_point,
+    }
+}
+
+#[test]
+fn stages_naming_one_output_share_a_module() {
+    let files = vec![stage("uber.hlsl", ShaderStage::VertexShader, "uber.spv", "vs_main"),
+                     stage("uber.hlsl", ShaderStage::FragmentShader, "uber.spv", "ps_main")];
+    let modules = plan_spirv_modules(&files).unwrap();
+    assert_eq!(modules.len(), 1);
+    assert_eq!(modules[0].spirv_out, "uber.spv");
+    assert_eq!(modules[0].stages, vec![0, 1]);
+
+    // GLSL stages each use main, which is told apart by the stage
+    let files = vec![stage("a.vert", ShaderStage::VertexShader, "a.spv", DEFAULT_ENTRY_POINT),
+                     stage("b.frag", ShaderStage::FragmentShader, "b.spv", DEFAULT_ENTRY_POINT),
+                     stage("a.frag", ShaderStage::FragmentShader, "a.spv", DEFAULT_ENTRY_POINT)];
+    let modules = plan_spirv_modules(&files).unwrap();
+    assert_eq!(modules.iter().map(|module| module.spirv_out).collect::<Vec<_>>(), vec!["a.spv", "b.spv"]);
+    assert_eq!(modules[0].stages, vec![0, 2]);
+    assert_eq!(modules[1].stages, vec![1]);
+}
+
+#[test]
+fn a_shared_module_rejects_a_repeated_entry_point() {
+    let files = vec![stage("uber.hlsl", ShaderStage::FragmentShader, "uber.spv", "ps_main"),
+                     stage("other.hlsl", ShaderStage::FragmentShader, "uber.spv", "ps_main")];
+    assert!(plan_spirv_modules(&files).is_err());
+
+    // The same entry point is fine for another stage, or in another module
+    let files = vec![stage("uber.hlsl", ShaderStage::FragmentShader, "uber.spv", "main"),
+                     stage("uber.hlsl", ShaderStage::ComputeShader, "uber.spv", "main"),
+                     stage("other.hlsl", ShaderStage::FragmentShader, "other.spv", "main")];
+    assert_eq!(plan_spirv_modules(&files).unwrap().len(), 2);
+}
@@ -0,0 +1,87 @@
+// Copyright (c) 2016-2017 Bruce Stenning. All rights reserved.
+//
+// Redistribution and use in source and binary forms, with or without
+// modification, are permitted provided that the following conditions
+// are met:
+//
+// 1. Redistributions of source code must retain the above copyright
+//    notice, this list of conditions and the following disclaimer.
+//
+// 2. Redistributions in binary form must reproduce the above copyright
+//    notice, this list of conditions and the following disclaimer in the
+//    documentation and/or other materials provided with the distribution.
+//
+// 3. Neither the name of the copyright holder nor the names of its
+//    contributors may be used to endorse or promote products derived
+//    from this software without specific prior written permission.
+//
+// THIS SOFTWARE IS PROVIDED BY THE COPYRIGHT HOLDERS AND CONTRIBUTORS
+// AS IS" AND ANY EXPRESS OR IMPLIED WARRANTIES, INCLUDING, BUT NOT
+// LIMITED TO, THE IMPLIED WARRANTIES OF MERCHANTABILITY AND FITNESS
+// FOR A PARTICULAR PURPOSE ARE DISCLAIMED. IN NO EVENT SHALL THE
+// COPYRIGHT HOLDER OR CONTRIBUTORS BE LIABLE FOR ANY DIRECT, INDIRECT,
+// INCIDENTAL, SPECIAL, EXEMPLARY, OR CONSEQUENTIAL DAMAGES (INCLUDING,
+// BUT NOT LIMITED TO, PROCUREMENT OF SUBSTITUTE GOODS OR SERVICES; LOSS
+// OF USE, DATA, OR PROFITS; OR BUSINESS INTERRUPTION) HOWEVER CAUSED
+// AND ON ANY THEORY OF LIABILITY, WHETHER IN CONTRACT, STRICT LIABILITY,
+// OR TORT (INCLUDING NEGLIGENCE OR OTHERWISE) ARISING IN ANY WAY OUT OF
+// THE USE OF THIS SOFTWARE, EVEN IF ADVISED OF THE POSSIBILITY OF SUCH
+// DAMAGE.
+#![allow(unused_imports)]
+
+use graphics::shadervariants::*;
+
+#[test]
+fn defines_are_ordered_so_a_set_names_one_variant() {
+    let a = ShaderDefines::new().flag("SKINNING").flag("FOG");
+    let b = ShaderDefines::from_flags(&["FOG", "SKINNING"]);
+    assert_eq!(a, b);
+    assert_eq!(a.preamble(), "#define FOG 1\n#define SKINNING 1\n");
+    assert_eq!(shader_variant_name("pbr", &a), "pbr[FOG_SKINNING]");
+    assert_eq!(shader_variant_filename("pbr", &a, "pbr.frag.spv"), "pbr.FOG_SKINNING.pbr.frag.spv");
+
+    let quality = ShaderDefines::new().value("QUALITY", "1").value("QUALITY", "2");
+    assert_eq!(quality.get("QUALITY"), Some("2"));
+    assert_eq!(quality.tag(), "QUALITY-2");
+
+    assert_eq!(shader_variant_name("pbr", &ShaderDefines::new()), "pbr");
+}
+
+#[test]
+fn permutations_cover_every_combination_once() {
+    let permutations = ShaderDefines::permutations(&["FOG", "SKINNING", "SHADOWS"]);
+    assert_eq!(permutations.len(), 8);
+    assert!(permutations[0].is_empty());
+    for (i, a) in permutations.iter().enumerate() {
+        for b in permutations.iter().skip(i + 1) {
+            assert!(a != b);
+        }
+    }
+    assert!(permutations.contains(&ShaderDefines::from_flags(&["SHADOWS", "FOG"])));
+}
+
+#[test]
+fn variants_are_checked_against_the_declared_options() {
+    let mut variants = ShaderVariants::new();
+    variants.declare_options("pbr", &["FOG", "SKINNING"]).unwrap();
+
+    assert!(variants.check("pbr", &ShaderDefines::from_flags(&["FOG"])).is_ok());
+    assert_eq!(variants.check("pbr", &ShaderDefines::from_flags(&["WIND"])),
+               Err(ShaderVariantError::UnknownOption {
+                   shader: "pbr",
+                   option: "WIND",
+               }));
+    assert!(variants.check("pbr", &ShaderDefines::new().value("FOG", "a b")).is_err());
+    assert!(variants.check("sky", &ShaderDefines::new().flag("9LIVES")).is_err());
+
+    let fog = ShaderDefines::from_flags(&["FOG"]);
+    assert_eq!(variants.find("pbr", &fog), None);
+    assert_eq!(variants.find("pbr", &ShaderDefines::new()), Some("pbr"));
+    variants.insert("pbr", &fog, "pbr[FOG]");
+    assert_eq!(variants.resolve("pbr", &fog), "pbr[FOG]");
+
+    // Missing variants fall back to the base shader, and are only reported once
+    let skinning = ShaderDefines::from_flags(&["SKINNING"]);
+    assert_eq!(variants.resolve("pbr", &skinning), "pbr");
+    assert!(!variants.report_missing("pbr", &skinning));
+}
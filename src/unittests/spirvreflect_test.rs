@@ -0,0 +1,183 @@
+// Copyright (c) 2016-2017 Bruce Stenning. All rights reserved.
+//
+// Redistribution and use in source and binary forms, with or without
+// modification, are permitted provided that the following conditions
+// are met:
+//
+// 1. Redistributions of source code must retain the above copyright
+//    notice, this list of conditions and the following disclaimer.
+//
+// 2. Redistributions in binary form must reproduce the above copyright
+//    notice, this list of conditions and the following disclaimer in the
+//    documentation and/or other materials provided with the distribution.
+//
+// 3. Neither the name of the copyright holder nor the names of its
+//    contributors may be used to endorse or promote products derived
+//    from this software without specific prior written permission.
+//
+// THIS SOFTWARE IS PROVIDED BY THE COPYRIGHT HOLDERS AND CONTRIBUTORS
+// AS IS" AND ANY EXPRESS OR IMPLIED WARRANTIES, INCLUDING, BUT NOT
+// LIMITED TO, THE IMPLIED WARRANTIES OF MERCHANTABILITY AND FITNESS
+// FOR A PARTICULAR PURPOSE ARE DISCLAIMED. IN NO EVENT SHALL THE
+// COPYRIGHT HOLDER OR CONTRIBUTORS BE LIABLE FOR ANY DIRECT, INDIRECT,
+// INCIDENTAL, SPECIAL, EXEMPLARY, OR CONSEQUENTIAL DAMAGES (INCLUDING,
+// BUT NOT LIMITED TO, PROCUREMENT OF SUBSTITUTE GOODS OR SERVICES; LOSS
+// OF USE, DATA, OR PROFITS; OR BUSINESS INTERRUPTION) HOWEVER CAUSED
+// AND ON ANY THEORY OF LIABILITY, WHETHER IN CONTRACT, STRICT LIABILITY,
+// OR TORT (INCLUDING NEGLIGENCE OR OTHERWISE) ARISING IN ANY WAY OUT OF
+// THE USE OF THIS SOFTWARE, EVEN IF ADVISED OF THE POSSIBILITY OF SUCH
+// DAMAGE.
+#![allow(unused_imports)]
+
+use graphics::resources::*;
+use graphics::spirvreflect::*;
+use misc::fileutils::push_u32;
+
+/// Assembles a module of the instructions the reflection reads
+#[cfg(test)]
+struct Module {
+    words: Vec<u32>,
+}
+
+#[cfg(test)]
+impl Module {
+    fn new() -> Module {
+        Module { words: vec![SPIRV_MAGIC, 0x00010000, 0, 100, 0] }
+    }
+
+    fn op(&mut self, opcode: u32, operands: &[u32]) -> &mut Module {
+        self.words.push(((operands.len() as u32 + 1) << 16) | opcode);
+        self.words.extend_from_slice(operands);
+        self
+    }
+
+    fn named(&mut self, opcode: u32, operands: &[u32], name: &str) -> &mut Module {
+        let mut all = operands.to_vec();
+        let mut bytes = name.as_bytes().to_vec();
+        bytes.push(0);
+        while bytes.len() % 4 != 0 {
+            bytes.push(0);
+        }
+        for chunk in bytes.chunks(4) {
+            all.push(chunk[0] as u32 | (chunk[1] as u32) << 8 | (chunk[2] as u32) << 16 | (chunk[3] as u32) << 24);
+        }
+        self.op(opcode, &all)
+    }
+
+    fn bytes(&self) -> Vec<u8> {
+        let mut bytes = vec![];
+        for word in self.words.iter() {
+            push_u32(&mut bytes, *word);
+        }
+        bytes
+    }
+}
+
+/// A module with a uniform block of a float, a vec4 and a mat4, a sampler and a push constant
+#[cfg(test)]
+fn example_module() -> Module {
+    let mut module = Module::new();
+    module.named(5, &[10], "camera") // OpName of the block type
+        .named(6, &[10, 0], "camera_scale")
+        .named(6, &[10, 1], "camera_position")
+        .named(6, &[10, 2], "camera_view")
+        .named(5, &[21], "albedo")
+        .named(5, &[30], "per_draw")
+        .named(6, &[30, 0], "per_draw_index")
+        .op(71, &[10, 2]) // Block
+        .op(72, &[10, 0, 35, 0]) // Offsets
+        .op(72, &[10, 1, 35, 16])
+        .op(72, &[10, 2, 35, 32])
+        .op(72, &[10, 2, 5]) // ColMajor
+        .op(72, &[10, 2, 7, 16]) // MatrixStride
+        .op(71, &[12, 34, 0]) // DescriptorSet
+        .op(71, &[12, 33, 3]) // Binding
+        .op(71, &[21, 34, 1])
+        .op(71, &[21, 33, 2])
+        .op(71, &[30, 2])
+        .op(72, &[30, 0, 35, 0])
+        .op(22, &[1, 32]) // float
+        .op(23, &[2, 1, 4]) // vec4
+        .op(24, &[3, 2, 4]) // mat4
+        .op(21, &[4, 32, 1]) // int
+        .op(30, &[10, 1, 2, 3]) // The block
+        .op(32, &[11, 2, 10]) // Uniform pointer
+        .op(59, &[11, 12, 2])
+        .op(25, &[18, 1, 1, 0, 0, 0, 1, 0]) // 2D image
+        .op(27, &[19, 18]) // Sampled image
+        .op(32, &[20, 0, 19]) // UniformConstant pointer
+        .op(59, &[20, 21, 0])
+        .op(30, &[30, 4]) // The push constant block
+        .op(32, &[31, 9, 30])
+        .op(59, &[31, 32, 9]);
+    module
+}
+
+#[test]
+fn blocks_and_uniforms_are_reflected_from_the_decorations() {
+    let reflection = SpirvReflection::parse(&example_module().bytes()).unwrap();
+
+    let camera = reflection.block("camera").unwrap();
+    assert_eq!(camera.block_type, UniformType::UniformBuffer);
+    assert_eq!((camera.set, camera.binding), (Some(0), Some(3)));
+    assert_eq!(camera.size, 32 + 64);
+    assert_eq!(camera.member("camera_position").unwrap().offset, 16);
+    assert_eq!(camera.member("camera_view").unwrap().size, 64);
+
+    let albedo = reflection.uniform("albedo").unwrap();
+    assert_eq!(albedo.uniform_type, UniformType::CombinedImageSampler);
+    assert_eq!((albedo.set, albedo.binding), (Some(1), Some(2)));
+
+    let per_draw = reflection.block("per_draw").unwrap();
+    assert!(per_draw.push_constant);
+    assert_eq!(per_draw.set, None);
+    assert_eq!(per_draw.size, 4);
+
+    let mut truncated = example_module().bytes();
+    truncated.truncate(truncated.len() - 4);
+    assert!(SpirvReflection::parse(&truncated).is_err());
+    assert!(SpirvReflection::parse(&vec![0; 20]).is_err());
+}
+
+#[test]
+fn handwritten_specs_are_verified_and_corrected() {
+    let reflection = SpirvReflection::parse(&example_module().bytes()).unwrap();
+    let camera = reflection.block("camera").unwrap();
+
+    let uniform = |name: &'static str, offset: usize| {
+        BlockUniformSpec {
+            name: name,
+            offset: offset,
+            ..Default::default()
+        }
+    };
+    let mut spec = UniformBlockSpec {
+        set: 0,
+        block_type: UniformType::UniformBuffer,
+        uniforms: vec![uniform("camera_scale", 0), uniform("camera_position", 12), uniform("camera_view", 32)],
+        ..Default::default()
+    };
+
+    let mismatches = verify_block_spec("example", "camera", &spec, camera);
+    assert_eq!(mismatches.len(), 1);
+    assert!(mismatches[0].description.contains("camera_position"));
+
+    apply_block_reflection(&mut spec, camera);
+    assert!(verify_block_spec("example", "camera", &spec, camera).is_empty());
+    assert_eq!((spec.binding, spec.size), (3, 96));
+    assert_eq!(spec.uniforms[1].offset, 16);
+
+    // A member the specification leaves out is reported too
+    spec.uniforms.pop();
+    assert_eq!(verify_block_spec("example", "camera", &spec, camera).len(), 1);
+}
+
+#[test]
+fn modules_in_the_other_byte_order_are_read() {
+    let mut bytes = vec![];
+    for word in example_module().words.iter() {
+        push_u32(&mut bytes, word.swap_bytes());
+    }
+    let reflection = SpirvReflection::parse(&bytes).unwrap();
+    assert_eq!(reflection, SpirvReflection::parse(&example_module().bytes()).unwrap());
+}
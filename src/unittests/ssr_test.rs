@@ -0,0 +1,99 @@
+// Copyright (c) 2016-2017 Bruce Stenning. All rights reserved.
+//
+// Redistribution and use in source and binary forms, with or without
+// modification, are permitted provided that the following conditions
+// are met:
+//
+// 1. Redistributions of source code must retain the above copyright
+//    notice, this list of conditions and the following disclaimer.
+//
+// 2. Redistributions in binary form must reproduce the above copyright
+//    notice, this list of conditions and the following disclaimer in the
+//    documentation and/or other materials provided with the distribution.
+//
+// 3. Neither the name of the copyright holder nor the names of its
+//    contributors may be used to endorse or promote products derived
+//    from this software without specific prior written permission.
+//
+// THIS SOFTWARE IS PROVIDED BY THE COPYRIGHT HOLDERS AND CONTRIBUTORS
+// AS IS" AND ANY EXPRESS OR IMPLIED WARRANTIES, INCLUDING, BUT NOT
+// LIMITED TO, THE IMPLIED WARRANTIES OF MERCHANTABILITY AND FITNESS
+// FOR A PARTICULAR PURPOSE ARE DISCLAIMED. IN NO EVENT SHALL THE
+// COPYRIGHT HOLDER OR CONTRIBUTORS BE LIABLE FOR ANY DIRECT, INDIRECT,
+// INCIDENTAL, SPECIAL, EXEMPLARY, OR CONSEQUENTIAL DAMAGES (INCLUDING,
+// BUT NOT LIMITED TO, PROCUREMENT OF SUBSTITUTE GOODS OR SERVICES; LOSS
+// OF USE, DATA, OR PROFITS; OR BUSINESS INTERRUPTION) HOWEVER CAUSED
+// AND ON ANY THEORY OF LIABILITY, WHETHER IN CONTRACT, STRICT LIABILITY,
+// OR TORT (INCLUDING NEGLIGENCE OR OTHERWISE) ARISING IN ANY WAY OUT OF
+// THE USE OF THIS SOFTWARE, EVEN IF ADVISED OF THE POSSIBILITY OF SUCH
+// DAMAGE.
+#![allow(unused_imports)]
+
+use std::collections::HashMap;
+
+use graphics::renderer::*;
+use graphics::resources::*;
+use graphics::shader::*;
+use graphics::ssr::*;
+
+#[test]
+fn quality_presets_march_further_and_blur_more() {
+    let qualities = [SsrQuality::Low, SsrQuality::Medium, SsrQuality::High, SsrQuality::Ultra];
+    let settings: Vec<SsrSettings> = qualities.iter().map(|quality| SsrSettings::from_quality(*quality)).collect();
+    for pair in settings.windows(2) {
+        assert!(pair[1].max_steps > pair[0].max_steps);
+        assert!(pair[1].refinement_steps > pair[0].refinement_steps);
+        assert!(pair[1].blur_samples >= pair[0].blur_samples);
+        assert_eq!(pair[1].max_distance, pair[0].max_distance);
+    }
+
+    assert_eq!(SsrSettings::default().max_steps, SsrSettings::from_quality(SsrQuality::Medium).max_steps);
+}
+
+#[test]
+fn shader_spec_samples_the_four_inputs_in_the_second_set() {
+    let mut resource_manager = ResourceManager::new(HashMap::new(), HashMap::new());
+    register_ssr_shader(&mut resource_manager, "lighting");
+
+    let spec = &resource_manager.shader_specs[SSR_SHADER_NAME];
+    assert_eq!(spec.pass_name, "lighting");
+    assert!(spec.vertex_array_type == VertexArrayType::F2F2);
+    assert_eq!(spec.shader_files.iter().map(|file| file.shader_stage).collect::<Vec<_>>(),
+               vec![ShaderStage::VertexShader, ShaderStage::FragmentShader]);
+    assert_eq!(spec.uniform_block_names, vec![SSR_SETTINGS_BLOCK]);
+
+    let textures: Vec<(&str, u32, u32)> = spec.uniform_specs.iter().map(|u| (u.name, u.set, u.binding)).collect();
+    assert_eq!(textures,
+               vec![(SSR_COLOUR_TEXTURE, 1, 0),
+                    (SSR_DEPTH_TEXTURE, 1, 1),
+                    (SSR_NORMAL_TEXTURE, 1, 2),
+                    (SSR_ENVIRONMENT_TEXTURE, 1, 3)]);
+    assert!(spec.uniform_specs.iter().all(|u| u.uniform_type == UniformType::CombinedImageSampler));
+}
+
+#[test]
+fn settings_block_is_laid_out_by_the_std140_rules() {
+    let mut resource_manager = ResourceManager::new(HashMap::new(), HashMap::new());
+    register_ssr_shader(&mut resource_manager, "lighting");
+
+    let block = resource_manager.uniform_block_specs[SSR_SETTINGS_BLOCK].laid_out();
+    assert_eq!(block.set, 0);
+    assert_eq!(block.block_type, UniformType::UniformBuffer);
+
+    // Three matrices and the viewport, then the scalars packed four bytes apart
+    let offsets: Vec<(&str, usize)> = block.uniforms.iter().map(|u| (u.name, u.offset)).collect();
+    assert_eq!(offsets,
+               vec![("ssr_projection", 0),
+                    ("ssr_inverse_projection", 64),
+                    ("ssr_inverse_view", 128),
+                    ("ssr_viewport", 192),
+                    ("ssr_max_distance", 208),
+                    ("ssr_thickness", 212),
+                    ("ssr_edge_fade", 216),
+                    ("ssr_roughness_cutoff", 220),
+                    ("ssr_intensity", 224),
+                    ("ssr_max_steps", 228),
+                    ("ssr_refinement_steps", 232),
+                    ("ssr_blur_samples", 236)]);
+    assert_eq!(block.size, 240);
+}
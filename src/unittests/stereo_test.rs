@@ -0,0 +1,99 @@
+// Copyright (c) 2016-2017 Bruce Stenning. All rights reserved.
+//
+// Redistribution and use in source and binary forms, with or without
+// modification, are permitted provided that the following conditions
+// are met:
+//
+// 1. Redistributions of source code must retain the above copyright
+//    notice, this list of conditions and the following disclaimer.
+//
+// 2. Redistributions in binary form must reproduce the above copyright
+//    notice, this list of conditions and the following disclaimer in the
+//    documentation and/or other materials provided with the distribution.
+//
+// 3. Neither the name of the copyright holder nor the names of its
+//    contributors may be used to endorse or promote products derived
+//    from this software without specific prior written permission.
+//
+// THIS SOFTWARE IS PROVIDED BY THE COPYRIGHT HOLDERS AND CONTRIBUTORS
+// AS IS" AND ANY EXPRESS OR IMPLIED WARRANTIES, INCLUDING, BUT NOT
+// LIMITED TO, THE IMPLIED WARRANTIES OF MERCHANTABILITY AND FITNESS
+// FOR A PARTICULAR PURPOSE ARE DISCLAIMED. IN NO EVENT SHALL THE
+// COPYRIGHT HOLDER OR CONTRIBUTORS BE LIABLE FOR ANY DIRECT, INDIRECT,
+// INCIDENTAL, SPECIAL, EXEMPLARY, OR CONSEQUENTIAL DAMAGES (INCLUDING,
+// BUT NOT LIMITED TO, PROCUREMENT OF SUBSTITUTE GOODS OR SERVICES; LOSS
+// OF USE, DATA, OR PROFITS; OR BUSINESS INTERRUPTION) HOWEVER CAUSED
+// AND ON ANY THEORY OF LIABILITY, WHETHER IN CONTRACT, STRICT LIABILITY,
+// OR TORT (INCLUDING NEGLIGENCE OR OTHERWISE) ARISING IN ANY WAY OUT OF
+// THE USE OF THIS SOFTWARE, EVEN IF ADVISED OF THE POSSIBILITY OF SUCH
+// DAMAGE.
+
+#![allow(unused_imports)]
+
+use algebra::matrix::Mat4;
+use algebra::vector::Vec4;
+use graphics::stereo::*;
+
+fn stereo(mode: StereoMode) -> StereoSettings {
+    StereoSettings {
+        mode: mode,
+        eye_separation: 0.064f32,
+        convergence: 2.0f32,
+    }
+}
+
+#[test]
+fn the_eyes_are_offset_by_half_the_separation() {
+    let settings = stereo(StereoMode::SideBySide);
+    assert_eq!(settings.eyes(), vec![Eye::Left, Eye::Right]);
+    assert_eq!(settings.eye_offset(Eye::Left), -0.032f32);
+    assert_eq!(settings.eye_offset(Eye::Right), 0.032f32);
+
+    let mono = stereo(StereoMode::Mono);
+    assert_eq!(mono.eyes(), vec![Eye::Left]);
+    assert_eq!(mono.eye_offset(Eye::Left), 0.0f32);
+    assert_eq!(mono.eye_offset(Eye::Right), 0.0f32);
+}
+
+#[test]
+fn the_eye_projections_are_sheared_towards_the_convergence_distance() {
+    let settings = stereo(StereoMode::Anaglyph);
+    let projection = Mat4::perspective(90.0f32, 1.0f32, 0.1f32, 100.0f32);
+
+    // With a 90 degree field of view m[0][0] is 1, so the shear is the offset over the convergence
+    let left = settings.eye_projection(&projection, Eye::Left);
+    let right = settings.eye_projection(&projection, Eye::Right);
+    assert!((left.m[2][0] - 0.016f32).abs() < 1.0e-6f32);
+    assert!((right.m[2][0] + 0.016f32).abs() < 1.0e-6f32);
+    for column in 0..4 {
+        for row in 0..4 {
+            if (column, row) != (2, 0) {
+                assert_eq!(left.m[column][row], projection.m[column][row]);
+                assert_eq!(right.m[column][row], projection.m[column][row]);
+            }
+        }
+    }
+
+    let mono = stereo(StereoMode::Mono);
+    assert_eq!(mono.eye_projection(&projection, Eye::Right).m, projection.m);
+}
+
+#[test]
+fn points_at_the_convergence_distance_have_no_parallax() {
+    let settings = stereo(StereoMode::TopBottom);
+    let projection = Mat4::perspective(60.0f32, 1.5f32, 0.1f32, 100.0f32);
+    let point = Vec4 {
+        x: 0.25f32,
+        y: 0.5f32,
+        z: -settings.convergence,
+        w: 1.0f32,
+    };
+
+    let mut screen_x = vec![];
+    for &eye in [Eye::Left, Eye::Right].iter() {
+        let view = settings.eye_view(&Mat4::newidentity(), eye);
+        let clip = settings.eye_projection(&projection, eye).mul_by_vec4(view.mul_by_vec4(point));
+        screen_x.push(clip.x / clip.w);
+    }
+    assert!((screen_x[0] - screen_x[1]).abs() < 1.0e-6f32);
+}
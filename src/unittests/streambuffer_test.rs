@@ -0,0 +1,60 @@
+// Copyright (c) 2016-2017 Bruce Stenning. All rights reserved.
+//
+// Redistribution and use in source and binary forms, with or without
+// modification, are permitted provided that the following conditions
+// are met:
+//
+// 1. Redistributions of source code must retain the above copyright
+//    notice, this list of conditions and the following disclaimer.
+//
+// 2. Redistributions in binary form must reproduce the above copyright
+//    notice, this list of conditions and the following disclaimer in the
+//    documentation and/or other materials provided with the distribution.
+//
+// 3. Neither the name of the copyright holder nor the names of its
+//    contributors may be used to endorse or promote products derived
+//    from this software without specific prior written permission.
+//
+// THIS SOFTWARE IS PROVIDED BY THE COPYRIGHT HOLDERS AND CONTRIBUTORS
+// AS IS" AND ANY EXPRESS OR IMPLIED WARRANTIES, INCLUDING, BUT NOT
+// LIMITED TO, THE IMPLIED WARRANTIES OF MERCHANTABILITY AND FITNESS
+// FOR A PARTICULAR PURPOSE ARE DISCLAIMED. IN NO EVENT SHALL THE
+// COPYRIGHT HOLDER OR CONTRIBUTORS BE LIABLE FOR ANY DIRECT, INDIRECT,
+// INCIDENTAL, SPECIAL, EXEMPLARY, OR CONSEQUENTIAL DAMAGES (INCLUDING,
+// BUT NOT LIMITED TO, PROCUREMENT OF SUBSTITUTE GOODS OR SERVICES; LOSS
+// OF USE, DATA, OR PROFITS; OR BUSINESS INTERRUPTION) HOWEVER CAUSED
+// AND ON ANY THEORY OF LIABILITY, WHETHER IN CONTRACT, STRICT LIABILITY,
+// OR TORT (INCLUDING NEGLIGENCE OR OTHERWISE) ARISING IN ANY WAY OUT OF
+// THE USE OF THIS SOFTWARE, EVEN IF ADVISED OF THE POSSIBILITY OF SUCH
+// DAMAGE.
+#![allow(unused_imports)]
+
+use graphics::streambuffer::*;
+
+#[test]
+fn stream_ring_orphans_on_first_write_then_advances_aligned() {
+    let mut ring = StreamRing::new(1024);
+    assert_eq!(ring.write(100, 16), StreamWrite { offset: 0, orphan: Some(1024) });
+    assert_eq!(ring.write(100, 16), StreamWrite { offset: 112, orphan: None });
+    assert_eq!(ring.write(64, 32), StreamWrite { offset: 224, orphan: None });
+}
+
+#[test]
+fn stream_ring_orphans_back_to_start_when_full() {
+    let mut ring = StreamRing::new(256);
+    ring.write(200, 4);
+    assert_eq!(ring.write(56, 4), StreamWrite { offset: 200, orphan: None });
+    assert_eq!(ring.write(4, 4), StreamWrite { offset: 0, orphan: Some(256) });
+    assert_eq!(ring.write(4, 4), StreamWrite { offset: 4, orphan: None });
+}
+
+#[test]
+fn stream_ring_grows_for_oversized_uploads_and_reallocates_after_reset() {
+    let mut ring = StreamRing::new(256);
+    ring.write(16, 4);
+    assert_eq!(ring.write(1000, 4), StreamWrite { offset: 0, orphan: Some(1000) });
+    assert_eq!(ring.capacity(), 1000);
+
+    ring.reset();
+    assert_eq!(ring.write(16, 4), StreamWrite { offset: 0, orphan: Some(1000) });
+}
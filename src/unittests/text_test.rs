@@ -0,0 +1,110 @@
+// Copyright (c) 2016-2017 Bruce Stenning. All rights reserved.
+//
+// Redistribution and use in source and binary forms, with or without
+// modification, are permitted provided that the following conditions
+// are met:
+//
+// 1. Redistributions of source code must retain the above copyright
+//    notice, this list of conditions and the following disclaimer.
+//
+// 2. Redistributions in binary form must reproduce the above copyright
+//    notice, this list of conditions and the following disclaimer in the
+//    documentation and/or other materials provided with the distribution.
+//
+// 3. Neither the name of the copyright holder nor the names of its
+//    contributors may be used to endorse or promote products derived
+//    from this software without specific prior written permission.
+//
+// THIS SOFTWARE IS PROVIDED BY THE COPYRIGHT HOLDERS AND CONTRIBUTORS
+// AS IS" AND ANY EXPRESS OR IMPLIED WARRANTIES, INCLUDING, BUT NOT
+// LIMITED TO, THE IMPLIED WARRANTIES OF MERCHANTABILITY AND FITNESS
+// FOR A PARTICULAR PURPOSE ARE DISCLAIMED. IN NO EVENT SHALL THE
+// COPYRIGHT HOLDER OR CONTRIBUTORS BE LIABLE FOR ANY DIRECT, INDIRECT,
+// INCIDENTAL, SPECIAL, EXEMPLARY, OR CONSEQUENTIAL DAMAGES (INCLUDING,
+// BUT NOT LIMITED TO, PROCUREMENT OF SUBSTITUTE GOODS OR SERVICES; LOSS
+// OF USE, DATA, OR PROFITS; OR BUSINESS INTERRUPTION) HOWEVER CAUSED
+// AND ON ANY THEORY OF LIABILITY, WHETHER IN CONTRACT, STRICT LIABILITY,
+// OR TORT (INCLUDING NEGLIGENCE OR OTHERWISE) ARISING IN ANY WAY OUT OF
+// THE USE OF THIS SOFTWARE, EVEN IF ADVISED OF THE POSSIBILITY OF SUCH
+// DAMAGE.
+
+#![allow(unused_imports)]
+
+use graphics::gputext::GlyphGrid;
+use graphics::text::*;
+use algebra::vector::Vec2;
+
+#[cfg(test)]
+const TEST_FONT: &'static str = "info face=\"Test Sans\" size=16 bold=0
+common lineHeight=20 base=16 scaleW=128 scaleH=64 pages=1 packed=0
+page id=0 file=\"test sans.png\"
+distanceField fieldType=sdf distanceRange=4
+chars count=3
+char id=32   x=0     y=0     width=0     height=0     xoffset=0     yoffset=0    xadvance=4     page=0  chnl=15
+char id=65   x=0     y=0     width=10    height=12    xoffset=1     yoffset=4    xadvance=11    page=0  chnl=15
+char id=86   x=16    y=32    width=8     height=12    xoffset=0     yoffset=4    xadvance=9     page=0  chnl=15
+kernings count=1
+kerning first=65  second=86  amount=-2
+";
+
+#[test]
+fn parse_bmfont_reads_glyphs_kerning_and_distance_fields() {
+    let font = Font::parse_bmfont(TEST_FONT).unwrap();
+
+    assert_eq!(font.line_height, 20.0f32);
+    assert!(font.distance_field);
+    assert_eq!(font.page, "test sans.png");
+    assert_eq!(font.kerning('A', 'V'), -2.0f32);
+    assert_eq!(font.kerning('V', 'A'), 0.0f32);
+
+    let v = font.glyph('V').unwrap();
+    assert_eq!(v.texcoord_min, Vec2 { x: 0.125f32, y: 0.5f32 });
+    assert_eq!(v.texcoord_max, Vec2 { x: 0.1875f32, y: 0.6875f32 });
+    assert_eq!(v.advance, 9.0f32);
+    assert!(font.glyph('B').is_none());
+
+    assert!(Font::parse_bmfont("info face=\"Empty\"").is_err());
+}
+
+#[test]
+fn layout_applies_offsets_kerning_scale_and_newlines() {
+    let font = Font::parse_bmfont(TEST_FONT).unwrap();
+
+    // The space has no quad, but advances the pen
+    let quads = font.layout(100.0f32, 50.0f32, 2.0f32, "AV A\nV");
+    assert_eq!(quads.len(), 4);
+    assert_eq!(quads[0].top_left, Vec2 { x: 102.0f32, y: 58.0f32 });
+    assert_eq!(quads[0].bottom_right, Vec2 { x: 122.0f32, y: 82.0f32 });
+    assert_eq!(quads[1].top_left.x, 100.0f32 + (11.0f32 - 2.0f32) * 2.0f32);
+    assert_eq!(quads[2].top_left.x, 100.0f32 + (11.0f32 - 2.0f32 + 9.0f32 + 4.0f32 + 1.0f32) * 2.0f32);
+    assert_eq!(quads[3].top_left, Vec2 { x: 100.0f32, y: 98.0f32 });
+
+    assert_eq!(font.measure(2.0f32, "AV A\nV"), ((11.0f32 - 2.0f32 + 9.0f32 + 4.0f32 + 11.0f32) * 2.0f32, 80.0f32));
+}
+
+#[test]
+fn overlay_merges_strings_of_the_same_colour_into_one_draw() {
+    let font = Font::from_glyph_grid(&GlyphGrid {
+        columns: 16,
+        rows: 6,
+        first_character: 32,
+        glyph_width: 8.0,
+        glyph_height: 16.0,
+    });
+    let b = font.glyph('B').unwrap();
+    assert_eq!(b.texcoord_min, Vec2 { x: 2.0f32 / 16.0f32, y: 2.0f32 / 6.0f32 });
+    assert_eq!(b.advance, 8.0f32);
+
+    let mut overlay = TextOverlay::new(font);
+    let white = [1.0f32, 1.0f32, 1.0f32, 1.0f32];
+    let red = [1.0f32, 0.0f32, 0.0f32, 1.0f32];
+    assert_eq!(overlay.draw_text(0.0f32, 0.0f32, 1.0f32, white, "FPS 60"), 6);
+    assert_eq!(overlay.draw_text(0.0f32, 16.0f32, 1.0f32, white, "GPU 3ms"), 7);
+    assert_eq!(overlay.draw_text(0.0f32, 32.0f32, 1.0f32, red, "\u{2603}"), 0);
+    assert_eq!(overlay.draw_text(0.0f32, 32.0f32, 1.0f32, red, "Stall"), 5);
+    assert_eq!(overlay.len(), 18);
+    assert_eq!(overlay.draws(), 2);
+
+    overlay.clear();
+    assert!(overlay.is_empty());
+}
@@ -0,0 +1,91 @@
+// Copyright (c) 2016-2017 Bruce Stenning. All rights reserved.
+//
+// Redistribution and use in source and binary forms, with or without
+// modification, are permitted provided that the following conditions
+// are met:
+//
+// 1. Redistributions of source code must retain the above copyright
+//    notice, this list of conditions and the following disclaimer.
+//
+// 2. Redistributions in binary form must reproduce the above copyright
+//    notice, this list of conditions and the following disclaimer in the
+//    documentation and/or other materials provided with the distribution.
+//
+// 3. Neither the name of the copyright holder nor the names of its
+//    contributors may be used to endorse or promote products derived
+//    from this software without specific prior written permission.
+//
+// THIS SOFTWARE IS PROVIDED BY THE COPYRIGHT HOLDERS AND CONTRIBUTORS
+// AS IS" AND ANY EXPRESS OR IMPLIED WARRANTIES, INCLUDING, BUT NOT
+// LIMITED TO, THE IMPLIED WARRANTIES OF MERCHANTABILITY AND FITNESS
+// FOR A PARTICULAR PURPOSE ARE DISCLAIMED. IN NO EVENT SHALL THE
+// COPYRIGHT HOLDER OR CONTRIBUTORS BE LIABLE FOR ANY DIRECT, INDIRECT,
+// INCIDENTAL, SPECIAL, EXEMPLARY, OR CONSEQUENTIAL DAMAGES (INCLUDING,
+// BUT NOT LIMITED TO, PROCUREMENT OF SUBSTITUTE GOODS OR SERVICES; LOSS
+// OF USE, DATA, OR PROFITS; OR BUSINESS INTERRUPTION) HOWEVER CAUSED
+// AND ON ANY THEORY OF LIABILITY, WHETHER IN CONTRACT, STRICT LIABILITY,
+// OR TORT (INCLUDING NEGLIGENCE OR OTHERWISE) ARISING IN ANY WAY OUT OF
+// THE USE OF THIS SOFTWARE, EVEN IF ADVISED OF THE POSSIBILITY OF SUCH
+// DAMAGE.
+#![allow(unused_imports)]
+
+use std::env;
+use std::fs::remove_file;
+use std::time::UNIX_EPOCH;
+
+use graphics::texture::*;
+use misc::fileutils::*;
+
+#[test]
+fn modified_sources_are_reported_once_per_modification() {
+    let path = env::temp_dir().join("wyvern_texture_source_test.png");
+    let filename = path.to_str().unwrap();
+    write_entire_file("not really a png", filename).unwrap();
+
+    let mut source = TextureSource::new(filename, true);
+    assert!(source.loaded > UNIX_EPOCH);
+    assert!(!source.check_modified());
+
+    // As if the file had been written after it was loaded
+    source.loaded = UNIX_EPOCH;
+    assert!(source.check_modified());
+    assert!(!source.check_modified());
+
+    // A file that is missing, as while it is being replaced, is not reported until it returns
+    remove_file(filename).unwrap();
+    source.loaded = UNIX_EPOCH;
+    assert!(!source.check_modified());
+    write_entire_file("the replacement", filename).unwrap();
+    assert!(source.check_modified());
+
+    remove_file(filename).unwrap();
+}
+
+#[test]
+fn sources_missing_when_loaded_are_reported_once_they_appear() {
+    let path = env::temp_dir().join("wyvern_texture_source_missing_test.png");
+    let filename = path.to_str().unwrap();
+    let _ = remove_file(filename);
+
+    let mut source = TextureSource::new(filename, false);
+    assert_eq!(source.loaded, UNIX_EPOCH);
+    assert!(!source.check_modified());
+
+    write_entire_file("created later", filename).unwrap();
+    assert!(source.check_modified());
+    assert!(!source.generate_mipmaps);
+
+    remove_file(filename).unwrap();
+}
+
+#[test]
+fn texture_regions_must_lie_within_the_texture_and_be_filled_by_the_data() {
+    assert!(check_texture_region(64, 32, 0, 0, 64, 32, 4, 64 * 32 * 4).is_ok());
+    assert!(check_texture_region(64, 32, 60, 28, 4, 4, 16, 4 * 4 * 16).is_ok());
+
+    assert!(check_texture_region(64, 32, 0, 0, 0, 4, 4, 0).is_err());
+    assert!(check_texture_region(64, 32, 61, 0, 4, 4, 4, 4 * 4 * 4).is_err());
+    assert!(check_texture_region(64, 32, 0, 29, 4, 4, 4, 4 * 4 * 4).is_err());
+    assert!(check_texture_region(64, 32, 0, 0, 4, 4, 4, 4 * 4 * 4 - 1).is_err());
+    assert!(check_texture_region(64, 32, u32::max_value(), 0, 2, 1, 4, 8).is_err());
+}
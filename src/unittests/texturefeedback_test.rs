@@ -0,0 +1,99 @@
+// Copyright (c) 2016-2017 Bruce Stenning. All rights reserved.
+//
+// Redistribution and use in source and binary forms, with or without
+// modification, are permitted provided that the following conditions
+// are met:
+//
+// 1. Redistributions of source code must retain the above copyright
+//    notice, this list of conditions and the following disclaimer.
+//
+// 2. Redistributions in binary form must reproduce the above copyright
+//    notice, this list of conditions and the following disclaimer in the
+//    documentation and/or other materials provided with the distribution.
+//
+// 3. Neither the name of the copyright holder nor the names of its
+//    contributors may be used to endorse or promote products derived
+//    from this software without specific prior written permission.
+//
+// THIS SOFTWARE IS PROVIDED BY THE COPYRIGHT HOLDERS AND CONTRIBUTORS
+// AS IS" AND ANY EXPRESS OR IMPLIED WARRANTIES, INCLUDING, BUT NOT
+// LIMITED TO, THE IMPLIED WARRANTIES OF MERCHANTABILITY AND FITNESS
+// FOR A PARTICULAR PURPOSE ARE DISCLAIMED. IN NO EVENT SHALL THE
+// COPYRIGHT HOLDER OR CONTRIBUTORS BE LIABLE FOR ANY DIRECT, INDIRECT,
+// INCIDENTAL, SPECIAL, EXEMPLARY, OR CONSEQUENTIAL DAMAGES (INCLUDING,
+// BUT NOT LIMITED TO, PROCUREMENT OF SUBSTITUTE GOODS OR SERVICES; LOSS
+// OF USE, DATA, OR PROFITS; OR BUSINESS INTERRUPTION) HOWEVER CAUSED
+// AND ON ANY THEORY OF LIABILITY, WHETHER IN CONTRACT, STRICT LIABILITY,
+// OR TORT (INCLUDING NEGLIGENCE OR OTHERWISE) ARISING IN ANY WAY OUT OF
+// THE USE OF THIS SOFTWARE, EVEN IF ADVISED OF THE POSSIBILITY OF SUCH
+// DAMAGE.
+#![allow(unused_imports)]
+
+use graphics::image::Image;
+use graphics::texturefeedback::*;
+
+#[test]
+fn feedback_image_is_decoded_into_coverage_per_mip() {
+    // Four pixels: nothing, slot 0 at mip 2, slot 300 at mip 0 and slot 300 at mip 1
+    let data: Vec<u8> = vec![0, 0, 0, 1, 0, 2, 45, 1, 0, 45, 1, 1];
+    let feedback = TextureFeedback::from_feedback_image(&Image::create_from_raw_data(2, 2, &data));
+
+    assert_eq!(feedback.pixels, 4);
+    assert_eq!(feedback.textures.len(), 2);
+    assert_eq!(feedback.textures[&0].finest_mip(), Some(2));
+    assert_eq!(feedback.textures[&300].finest_mip(), Some(0));
+    assert_eq!(feedback.coverage(300), 0.5);
+    assert_eq!(feedback.coverage(7), 0.0);
+
+    assert_eq!(feedback_lod_bias(512, 128), -2.0);
+}
+
+#[test]
+fn uploads_favour_the_textures_covering_most_of_the_screen() {
+    let mut streamer = TextureStreamer::new();
+    streamer.register(1, 8);
+    streamer.register(2, 8);
+    streamer.register(3, 8);
+
+    let mut feedback = TextureFeedback::new();
+    for _ in 0..3 {
+        feedback.add(Some((1, 4)));
+    }
+    feedback.add(Some((2, 4)));
+    feedback.add(None);
+    streamer.apply_feedback(&feedback);
+
+    let uploads = streamer.uploads();
+    assert_eq!(uploads.len(), 2);
+    assert_eq!((uploads[0].slot, uploads[0].mip), (1, 6));
+    assert_eq!((uploads[1].slot, uploads[1].mip), (2, 6));
+
+    // Once the wanted level is resident nothing more is asked for
+    streamer.mark_resident(1, 4);
+    assert!(streamer.uploads().iter().all(|r| r.slot != 1));
+    assert!(streamer.evictions().is_empty());
+}
+
+#[test]
+fn unsampled_textures_are_evicted_once_stale() {
+    let mut streamer = TextureStreamer::new();
+    streamer.register(5, 6);
+
+    let mut feedback = TextureFeedback::new();
+    feedback.add(Some((5, 0)));
+    streamer.apply_feedback(&feedback);
+    streamer.mark_resident(5, 0);
+
+    // Briefly out of view keeps the detail, in case it comes straight back
+    let empty = TextureFeedback::new();
+    streamer.apply_feedback(&empty);
+    assert!(streamer.evictions().is_empty());
+
+    for _ in 0..TEXTURE_FEEDBACK_STALE_READBACKS {
+        streamer.apply_feedback(&empty);
+    }
+    let evictions = streamer.evictions();
+    assert_eq!(evictions.len(), 1);
+    assert_eq!((evictions[0].slot, evictions[0].mip), (5, 0));
+    assert_eq!(streamer.get(5).unwrap().wanted_mip, 5);
+}
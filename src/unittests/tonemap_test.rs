@@ -0,0 +1,64 @@
+// Copyright (c) 2016-2017 Bruce Stenning. All rights reserved.
+//
+// Redistribution and use in source and binary forms, with or without
+// modification, are permitted provided that the following conditions
+// are met:
+//
+// 1. Redistributions of source code must retain the above copyright
+//    notice, this list of conditions and the following disclaimer.
+//
+// 2. Redistributions in binary form must reproduce the above copyright
+//    notice, this list of conditions and the following disclaimer in the
+//    documentation and/or other materials provided with the distribution.
+//
+// 3. Neither the name of the copyright holder nor the names of its
+//    contributors may be used to endorse or promote products derived
+//    from this software without specific prior written permission.
+//
+// THIS SOFTWARE IS PROVIDED BY THE COPYRIGHT HOLDERS AND CONTRIBUTORS
+// AS IS" AND ANY EXPRESS OR IMPLIED WARRANTIES, INCLUDING, BUT NOT
+// LIMITED TO, THE IMPLIED WARRANTIES OF MERCHANTABILITY AND FITNESS
+// FOR A PARTICULAR PURPOSE ARE DISCLAIMED. IN NO EVENT SHALL THE
+// COPYRIGHT HOLDER OR CONTRIBUTORS BE LIABLE FOR ANY DIRECT, INDIRECT,
+// INCIDENTAL, SPECIAL, EXEMPLARY, OR CONSEQUENTIAL DAMAGES (INCLUDING,
+// BUT NOT LIMITED TO, PROCUREMENT OF SUBSTITUTE GOODS OR SERVICES; LOSS
+// OF USE, DATA, OR PROFITS; OR BUSINESS INTERRUPTION) HOWEVER CAUSED
+// AND ON ANY THEORY OF LIABILITY, WHETHER IN CONTRACT, STRICT LIABILITY,
+// OR TORT (INCLUDING NEGLIGENCE OR OTHERWISE) ARISING IN ANY WAY OUT OF
+// THE USE OF THIS SOFTWARE, EVEN IF ADVISED OF THE POSSIBILITY OF SUCH
+// DAMAGE.
+#![allow(unused_imports)]
+
+use graphics::tonemap::*;
+
+#[test]
+fn operators_map_into_the_unit_range_and_keep_order() {
+    for operator in [TonemapOperator::Reinhard, TonemapOperator::Aces].iter() {
+        assert_eq!(operator.apply(0.0, 4.0), 0.0);
+        assert_eq!(operator.apply(-1.0, 4.0), 0.0);
+
+        let mut last = 0.0f32;
+        for i in 1..200 {
+            let mapped = operator.apply(i as f32 * 0.1, 4.0);
+            assert!(mapped >= last);
+            assert!(mapped <= 1.0);
+            last = mapped;
+        }
+    }
+}
+
+#[test]
+fn reinhard_maps_the_white_point_to_white() {
+    assert!((TonemapOperator::Reinhard.apply(4.0, 4.0) - 1.0).abs() < 1.0e-6);
+    assert!((TonemapOperator::Reinhard.apply(1.0, 1.0e6) - 0.5).abs() < 1.0e-6);
+    assert!(TonemapOperator::Aces.apply(100.0, 4.0) > 0.99);
+}
+
+#[test]
+fn hdr10_output_keeps_highlights_below_the_peak() {
+    let settings = TonemapSettings::default();
+    assert!(settings.hdr10_nits(0.0) == 0.0);
+    assert!(settings.hdr10_nits(1.0) > 100.0);
+    assert!(settings.hdr10_nits(1000.0) <= settings.peak_nits);
+    assert!(settings.hdr10_nits(2.0) > settings.hdr10_nits(1.0));
+}
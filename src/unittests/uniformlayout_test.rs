@@ -0,0 +1,77 @@
+// Copyright (c) 2016-2017 Bruce Stenning. All rights reserved.
+//
+// Redistribution and use in source and binary forms, with or without
+// modification, are permitted provided that the following conditions
+// are met:
+//
+// 1. Redistributions of source code must retain the above copyright
+//    notice, this list of conditions and the following disclaimer.
+//
+// 2. Redistributions in binary form must reproduce the above copyright
+//    notice, this list of conditions and the following disclaimer in the
+//    documentation and/or other materials provided with the distribution.
+//
+// 3. Neither the name of the copyright holder nor the names of its
+//    contributors may be used to endorse or promote products derived
+//    from this software without specific prior written permission.
+//
+// THIS SOFTWARE IS PROVIDED BY THE COPYRIGHT HOLDERS AND CONTRIBUTORS
+// AS IS" AND ANY EXPRESS OR IMPLIED WARRANTIES, INCLUDING, BUT NOT
+// LIMITED TO, THE IMPLIED WARRANTIES OF MERCHANTABILITY AND FITNESS
+// FOR A PARTICULAR PURPOSE ARE DISCLAIMED. IN NO EVENT SHALL THE
+// COPYRIGHT HOLDER OR CONTRIBUTORS BE LIABLE FOR ANY DIRECT, INDIRECT,
+// INCIDENTAL, SPECIAL, EXEMPLARY, OR CONSEQUENTIAL DAMAGES (INCLUDING,
+// BUT NOT LIMITED TO, PROCUREMENT OF SUBSTITUTE GOODS OR SERVICES; LOSS
+// OF USE, DATA, OR PROFITS; OR BUSINESS INTERRUPTION) HOWEVER CAUSED
+// AND ON ANY THEORY OF LIABILITY, WHETHER IN CONTRACT, STRICT LIABILITY,
+// OR TORT (INCLUDING NEGLIGENCE OR OTHERWISE) ARISING IN ANY WAY OUT OF
+// THE USE OF THIS SOFTWARE, EVEN IF ADVISED OF THE POSSIBILITY OF SUCH
+// DAMAGE.
+#![allow(unused_imports)]
+
+use graphics::uniformlayout::*;
+
+/// Return the offset and stride of each member
+#[cfg(test)]
+fn offsets_and_strides(layout: &CalculatedBlockLayout) -> Vec<(usize, usize)> {
+    layout.members.iter().map(|m| (m.offset, m.stride)).collect()
+}
+
+#[test]
+fn std140_packs_scalars_after_vec3_and_pads_arrays_to_16_bytes() {
+    let layout = calculate_block_layout(BlockLayout::Std140,
+                                        &[(BlockMemberType::Mat4, 0),
+                                          (BlockMemberType::Vec3, 0),
+                                          (BlockMemberType::Float, 0),
+                                          (BlockMemberType::Float, 2),
+                                          (BlockMemberType::Vec2, 0),
+                                          (BlockMemberType::Int, 0)]);
+    assert_eq!(offsets_and_strides(&layout),
+               vec![(0, 0), (64, 0), (76, 0), (80, 16), (112, 0), (120, 0)]);
+    assert_eq!(layout.size, 124);
+}
+
+#[test]
+fn std430_aligns_arrays_to_their_elements() {
+    let layout = calculate_block_layout(BlockLayout::Std430,
+                                        &[(BlockMemberType::Float, 3),
+                                          (BlockMemberType::Vec3, 2),
+                                          (BlockMemberType::Mat3, 0),
+                                          (BlockMemberType::Vec2, 0)]);
+    assert_eq!(offsets_and_strides(&layout), vec![(0, 4), (16, 16), (48, 0), (96, 0)]);
+    assert_eq!(layout.members[2].size, 48);
+    assert_eq!(layout.size, 104);
+}
+
+#[test]
+fn matrix_columns_are_padded_by_std140_only() {
+    let members = [(BlockMemberType::Float, 0), (BlockMemberType::Mat2, 0), (BlockMemberType::Mat2, 2)];
+
+    let std140 = calculate_block_layout(BlockLayout::Std140, &members);
+    assert_eq!(offsets_and_strides(&std140), vec![(0, 0), (16, 0), (48, 32)]);
+    assert_eq!(std140.size, 112);
+
+    let std430 = calculate_block_layout(BlockLayout::Std430, &members);
+    assert_eq!(offsets_and_strides(&std430), vec![(0, 0), (8, 0), (24, 16)]);
+    assert_eq!(std430.size, 56);
+}
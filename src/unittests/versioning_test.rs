@@ -0,0 +1,89 @@
+// Copyright (c) 2016-2017 Bruce Stenning. All rights reserved.
+//
+// Redistribution and use in source and binary forms, with or without
+// modification, are permitted provided that the following conditions
+// are met:
+//
+// 1. Redistributions of source code must retain the above copyright
+//    notice, this list of conditions and the following disclaimer.
+//
+// 2. Redistributions in binary form must reproduce the above copyright
+//    notice, this list of conditions and the following disclaimer in the
+//    documentation and/or other materials provided with the distribution.
+//
+// 3. Neither the name of the copyright holder nor the names of its
+//    contributors may be used to endorse or promote products derived
+//    from this software without specific prior written permission.
+//
+// THIS SOFTWARE IS PROVIDED BY THE COPYRIGHT HOLDERS AND CONTRIBUTORS
+// AS IS" AND ANY EXPRESS OR IMPLIED WARRANTIES, INCLUDING, BUT NOT
+// LIMITED TO, THE IMPLIED WARRANTIES OF MERCHANTABILITY AND FITNESS
+// FOR A PARTICULAR PURPOSE ARE DISCLAIMED. IN NO EVENT SHALL THE
+// COPYRIGHT HOLDER OR CONTRIBUTORS BE LIABLE FOR ANY DIRECT, INDIRECT,
+// INCIDENTAL, SPECIAL, EXEMPLARY, OR CONSEQUENTIAL DAMAGES (INCLUDING,
+// BUT NOT LIMITED TO, PROCUREMENT OF SUBSTITUTE GOODS OR SERVICES; LOSS
+// OF USE, DATA, OR PROFITS; OR BUSINESS INTERRUPTION) HOWEVER CAUSED
+// AND ON ANY THEORY OF LIABILITY, WHETHER IN CONTRACT, STRICT LIABILITY,
+// OR TORT (INCLUDING NEGLIGENCE OR OTHERWISE) ARISING IN ANY WAY OUT OF
+// THE USE OF THIS SOFTWARE, EVEN IF ADVISED OF THE POSSIBILITY OF SUCH
+// DAMAGE.
+#![allow(unused_imports)]
+
+use graphics::versioning::*;
+
+#[test]
+fn content_version_synchronises_only_after_writes() {
+    let version = ContentVersion::new();
+    assert!(version.synchronise());
+    assert!(!version.synchronise());
+
+    let before = version.version();
+    version.bump();
+    version.bump();
+    assert!(version.version() != before);
+    assert!(version.synchronise());
+    assert!(!version.synchronise());
+}
+
+#[test]
+fn skipped_binds_report_the_last_completed_frame() {
+    let skipped = SkippedBinds::new();
+    skipped.skip();
+    skipped.skip();
+    assert_eq!(skipped.last_frame(), 0);
+
+    skipped.end_frame();
+    skipped.skip();
+    assert_eq!(skipped.last_frame(), 2);
+
+    skipped.end_frame();
+    assert_eq!(skipped.last_frame(), 1);
+}
+
+#[test]
+fn bound_state_skips_rebinding_until_reset() {
+    let mut bound = BoundState::new();
+    assert!(bound.bind_pipeline(7));
+    assert!(!bound.bind_pipeline(7));
+    assert!(bound.bind_pipeline(8));
+
+    assert!(bound.bind_vertex_buffers(0, &[(3, 0), (4, 0)]));
+    assert!(!bound.bind_vertex_buffers(1, &[(4, 0)]));
+    assert!(bound.bind_vertex_buffers(0, &[(3, 64)]));
+
+    bound.reset();
+    assert!(bound.bind_pipeline(8));
+    assert!(bound.bind_vertex_buffers(1, &[(4, 0)]));
+}
+
+#[test]
+fn bound_state_forgets_descriptor_sets_when_the_layout_changes() {
+    let mut bound = BoundState::new();
+    assert!(bound.bind_descriptor_set(1, 0, 10));
+    assert!(bound.bind_descriptor_set(1, 2, 12));
+    assert!(!bound.bind_descriptor_set(1, 2, 12));
+    assert!(!bound.bind_descriptor_set(1, 0, 10));
+
+    assert!(bound.bind_descriptor_set(2, 0, 10));
+    assert!(bound.bind_descriptor_set(2, 2, 12));
+}
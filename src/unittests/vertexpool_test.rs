@@ -0,0 +1,65 @@
+// Copyright (c) 2016-2017 Bruce Stenning. All rights reserved.
+//
+// Redistribution and use in source and binary forms, with or without
+// modification, are permitted provided that the following conditions
+// are met:
+//
+// 1. Redistributions of source code must retain the above copyright
+//    notice, this list of conditions and the following disclaimer.
+//
+// 2. Redistributions in binary form must reproduce the above copyright
+//    notice, this list of conditions and the following disclaimer in the
+//    documentation and/or other materials provided with the distribution.
+//
+// 3. Neither the name of the copyright holder nor the names of its
+//    contributors may be used to endorse or promote products derived
+//    from this software without specific prior written permission.
+//
+// THIS SOFTWARE IS PROVIDED BY THE COPYRIGHT HOLDERS AND CONTRIBUTORS
+// AS IS" AND ANY EXPRESS OR IMPLIED WARRANTIES, INCLUDING, BUT NOT
+// LIMITED TO, THE IMPLIED WARRANTIES OF MERCHANTABILITY AND FITNESS
+// FOR A PARTICULAR PURPOSE ARE DISCLAIMED. IN NO EVENT SHALL THE
+// COPYRIGHT HOLDER OR CONTRIBUTORS BE LIABLE FOR ANY DIRECT, INDIRECT,
+// INCIDENTAL, SPECIAL, EXEMPLARY, OR CONSEQUENTIAL DAMAGES (INCLUDING,
+// BUT NOT LIMITED TO, PROCUREMENT OF SUBSTITUTE GOODS OR SERVICES; LOSS
+// OF USE, DATA, OR PROFITS; OR BUSINESS INTERRUPTION) HOWEVER CAUSED
+// AND ON ANY THEORY OF LIABILITY, WHETHER IN CONTRACT, STRICT LIABILITY,
+// OR TORT (INCLUDING NEGLIGENCE OR OTHERWISE) ARISING IN ANY WAY OUT OF
+// THE USE OF THIS SOFTWARE, EVEN IF ADVISED OF THE POSSIBILITY OF SUCH
+// DAMAGE.
+#![allow(unused_imports)]
+
+use graphics::renderer::*;
+use graphics::vertexpool::*;
+
+#[test]
+fn vertex_pool_sizes_round_trip_through_text() {
+    let mut sizes = VertexBufferPoolSizes::new(2);
+    sizes.set(VertexArrayType::F3F3F3, 0, 12);
+    sizes.set(VertexArrayType::F2F2, 1, 3);
+
+    let parsed = VertexBufferPoolSizes::parse(&sizes.serialise(), 2);
+    assert_eq!(parsed, sizes);
+
+    // Malformed lines and threads beyond those known are ignored
+    let parsed = VertexBufferPoolSizes::parse("1 0 4\nnonsense\n1 5 9\n", 2);
+    assert_eq!(parsed.get(VertexArrayType::F3F3F3, 0), 4);
+    assert_eq!(parsed.get(VertexArrayType::F3F3F3, 5), 0);
+}
+
+#[test]
+fn vertex_pool_sizes_merge_to_the_larger_counts() {
+    let mut sizes = VertexBufferPoolSizes::new(2);
+    sizes.set(VertexArrayType::F3, 0, 5);
+    sizes.set(VertexArrayType::F3, 1, 1);
+
+    let mut other = VertexBufferPoolSizes::new(1);
+    other.set(VertexArrayType::F3, 0, 2);
+    other.set(VertexArrayType::F3F3, 0, 7);
+
+    sizes.merge_max(&other);
+    assert_eq!(sizes.get(VertexArrayType::F3, 0), 5);
+    assert_eq!(sizes.get(VertexArrayType::F3, 1), 1);
+    assert_eq!(sizes.get(VertexArrayType::F3F3, 0), 7);
+    assert_eq!(sizes.max_threads(), 2);
+}
@@ -0,0 +1,119 @@
+// Copyright (c) 2016-2017 Bruce Stenning. All rights reserved.
+//
+// Redistribution and use in source and binary forms, with or without
+// modification, are permitted provided that the following conditions
+// are met:
+//
+// 1. Redistributions of source code must retain the above copyright
+//    notice, this list of conditions and the following disclaimer.
+//
+// 2. Redistributions in binary form must reproduce the above copyright
+//    notice, this list of conditions and the following disclaimer in the
+//    documentation and/or other materials provided with the distribution.
+//
+// 3. Neither the name of the copyright holder nor the names of its
+//    contributors may be used to endorse or promote products derived
+//    from this software without specific prior written permission.
+//
+// THIS SOFTWARE IS PROVIDED BY THE COPYRIGHT HOLDERS AND CONTRIBUTORS
+// AS IS" AND ANY EXPRESS OR IMPLIED WARRANTIES, INCLUDING, BUT NOT
+// LIMITED TO, THE IMPLIED WARRANTIES OF MERCHANTABILITY AND FITNESS
+// FOR A PARTICULAR PURPOSE ARE DISCLAIMED. IN NO EVENT SHALL THE
+// COPYRIGHT HOLDER OR CONTRIBUTORS BE LIABLE FOR ANY DIRECT, INDIRECT,
+// INCIDENTAL, SPECIAL, EXEMPLARY, OR CONSEQUENTIAL DAMAGES (INCLUDING,
+// BUT NOT LIMITED TO, PROCUREMENT OF SUBSTITUTE GOODS OR SERVICES; LOSS
+// OF USE, DATA, OR PROFITS; OR BUSINESS INTERRUPTION) HOWEVER CAUSED
+// AND ON ANY THEORY OF LIABILITY, WHETHER IN CONTRACT, STRICT LIABILITY,
+// OR TORT (INCLUDING NEGLIGENCE OR OTHERWISE) ARISING IN ANY WAY OUT OF
+// THE USE OF THIS SOFTWARE, EVEN IF ADVISED OF THE POSSIBILITY OF SUCH
+// DAMAGE.
+#![allow(unused_imports)]
+
+use graphics::workpartition::*;
+
+// Drain a partition by letting each thread take one chunk in turn, as threads running in
+// lockstep would
+#[cfg(test)]
+fn drain_in_turn(partition: &WorkPartition) -> Vec<Vec<usize>> {
+    let mut taken = vec![vec![]; partition.thread_count()];
+    let mut finished = false;
+    while !finished {
+        finished = true;
+        for thr in 0..partition.thread_count() {
+            if let Some(chunk) = partition.next_chunk(thr) {
+                taken[thr].push(chunk);
+                finished = false;
+            }
+        }
+    }
+
+    taken
+}
+
+#[test]
+fn static_modes_hand_out_each_chunk_once() {
+    let interleaved = WorkPartitioner::new(PartitionMode::Interleaved).begin_frame(7, 3);
+    assert_eq!(drain_in_turn(&interleaved), vec![vec![0, 3, 6], vec![1, 4], vec![2, 5]]);
+
+    let fixed = WorkPartitioner::new(PartitionMode::Fixed).begin_frame(7, 3);
+    assert_eq!(drain_in_turn(&fixed), vec![vec![0, 1, 2], vec![3, 4], vec![5, 6]]);
+    assert_eq!(fixed.next_chunk(3), None);
+
+    // A seed gives the same partition on every run
+    let seeded = drain_in_turn(&WorkPartitioner::new(PartitionMode::Seeded(42)).begin_frame(20, 4));
+    assert_eq!(seeded, drain_in_turn(&WorkPartitioner::new(PartitionMode::Seeded(42)).begin_frame(20, 4)));
+    let mut chunks: Vec<usize> = seeded.iter().flat_map(|chunks| chunks.iter().cloned()).collect();
+    chunks.sort();
+    assert_eq!(chunks, (0..20).collect::<Vec<usize>>());
+}
+
+#[test]
+fn replay_repeats_the_recorded_dynamic_claims() {
+    let mut partitioner = WorkPartitioner::new(PartitionMode::Dynamic);
+    partitioner.start_recording();
+
+    // Thread 1 is busier than thread 0, claiming fewer chunks
+    let partition = partitioner.begin_frame(5, 2);
+    let claims = [0, 0, 1, 0, 1];
+    for thr in claims.iter() {
+        assert!(partition.next_chunk(*thr).is_some());
+    }
+    assert_eq!(partition.next_chunk(0), None);
+    partitioner.end_frame(&partition);
+
+    let log = partitioner.stop_recording().unwrap();
+    assert_eq!(log.frames[0].threads, vec![vec![0, 1, 3], vec![2, 4]]);
+
+    partitioner.start_replay(PartitionLog::decode(&log.encode()).unwrap());
+    let replayed = partitioner.begin_frame(5, 2);
+    assert_eq!(drain_in_turn(&replayed), vec![vec![0, 1, 3], vec![2, 4]]);
+
+    // The log is exhausted, so the dynamic claims resume
+    assert!(partitioner.is_replaying());
+    let partition = partitioner.begin_frame(5, 2);
+    assert!(!partitioner.is_replaying());
+    assert_eq!(drain_in_turn(&partition), vec![vec![0, 2, 4], vec![1, 3]]);
+}
+
+#[test]
+fn mismatched_or_corrupt_logs_are_not_replayed() {
+    let log = PartitionLog {
+        frames: vec![FramePartition {
+                         frame: 1,
+                         chunk_count: 4,
+                         threads: vec![vec![3, 2, 1, 0]],
+                     }],
+    };
+
+    let mut partitioner = WorkPartitioner::new(PartitionMode::Fixed);
+    partitioner.start_replay(log.clone());
+    let partition = partitioner.begin_frame(6, 2);
+    assert!(!partitioner.is_replaying());
+    assert_eq!(drain_in_turn(&partition), vec![vec![0, 1, 2], vec![3, 4, 5]]);
+
+    let mut bytes = log.encode();
+    let last = bytes.len() - 4;
+    bytes[last] = 9;
+    assert!(PartitionLog::decode(&bytes).is_err());
+    assert!(PartitionLog::decode(&bytes[0..bytes.len() - 2].to_vec()).is_err());
+}
@@ -0,0 +1,154 @@
+// Copyright (c) 2016-2017 Bruce Stenning. All rights reserved.
+//
+// Redistribution and use in source and binary forms, with or without
+// modification, are permitted provided that the following conditions
+// are met:
+//
+// 1. Redistributions of source code must retain the above copyright
+//    notice, this list of conditions and the following disclaimer.
+//
+// 2. Redistributions in binary form must reproduce the above copyright
+//    notice, this list of conditions and the following disclaimer in the
+//    documentation and/or other materials provided with the distribution.
+//
+// 3. Neither the name of the copyright holder nor the names of its
+//    contributors may be used to endorse or promote products derived
+//    from this software without specific prior written permission.
+//
+// THIS SOFTWARE IS PROVIDED BY THE COPYRIGHT HOLDERS AND CONTRIBUTORS
+// AS IS" AND ANY EXPRESS OR IMPLIED WARRANTIES, INCLUDING, BUT NOT
+// LIMITED TO, THE IMPLIED WARRANTIES OF MERCHANTABILITY AND FITNESS
+// FOR A PARTICULAR PURPOSE ARE DISCLAIMED. IN NO EVENT SHALL THE
+// COPYRIGHT HOLDER OR CONTRIBUTORS BE LIABLE FOR ANY DIRECT, INDIRECT,
+// INCIDENTAL, SPECIAL, EXEMPLARY, OR CONSEQUENTIAL DAMAGES (INCLUDING,
+// BUT NOT LIMITED TO, PROCUREMENT OF SUBSTITUTE GOODS OR SERVICES; LOSS
+// OF USE, DATA, OR PROFITS; OR BUSINESS INTERRUPTION) HOWEVER CAUSED
+// AND ON ANY THEORY OF LIABILITY, WHETHER IN CONTRACT, STRICT LIABILITY,
+// OR TORT (INCLUDING NEGLIGENCE OR OTHERWISE) ARISING IN ANY WAY OUT OF
+// THE USE OF THIS SOFTWARE, EVEN IF ADVISED OF THE POSSIBILITY OF SUCH
+// DAMAGE.
+#![allow(unused_imports)]
+
+use std::f32::consts::FRAC_PI_4;
+use algebra::matrix::{ClipSpaceConvention, Mat4};
+use algebra::quaternion::Quaternion;
+use algebra::vector::Vec3;
+use graphics::stereo::Eye;
+use graphics::xr::*;
+
+#[cfg(test)]
+fn approx(a: f32, b: f32) -> bool {
+    (a - b).abs() < 1.0e-5f32
+}
+
+#[cfg(test)]
+struct FakeSession {
+    running: bool,
+    located_at: Option<i64>,
+}
+
+#[cfg(test)]
+impl XrSession for FakeSession {
+    fn wait_frame(&mut self) -> Option<XrFrameState> {
+        if self.running {
+            Some(XrFrameState {
+                predicted_display_time: 1000,
+                should_render: true,
+            })
+        } else {
+            None
+        }
+    }
+
+    fn locate_views(&mut self, display_time: i64) -> [XrView; 2] {
+        self.located_at = Some(display_time);
+        let fov = XrFov {
+            angle_left: -FRAC_PI_4,
+            angle_right: FRAC_PI_4,
+            angle_up: FRAC_PI_4,
+            angle_down: -FRAC_PI_4,
+        };
+        let view = |x: f32| {
+            XrView {
+                pose: XrPose {
+                    orientation: Quaternion::identity(),
+                    position: Vec3 { x: x, y: 0.0, z: 0.0 },
+                },
+                fov: fov,
+            }
+        };
+        [view(-0.03), view(0.03)]
+    }
+
+    fn acquire_image(&mut self, _: Eye) -> XrSwapchainImage {
+        XrSwapchainImage {
+            image: 0,
+            width: 1,
+            height: 1,
+        }
+    }
+
+    fn release_image(&mut self, _: Eye) {}
+
+    fn end_frame(&mut self, _: i64, _: &[XrView; 2], _: bool) {}
+}
+
+#[test]
+fn fov_projections_follow_the_angles_and_convention() {
+    let symmetric = XrFov {
+        angle_left: -FRAC_PI_4,
+        angle_right: FRAC_PI_4,
+        angle_up: FRAC_PI_4,
+        angle_down: -FRAC_PI_4,
+    };
+    let gl = symmetric.projection(0.1, 100.0, ClipSpaceConvention::OpenGl);
+    assert!(approx(gl.m[0][0], 1.0) && approx(gl.m[1][1], 1.0) && approx(gl.m[2][0], 0.0));
+    let vk = symmetric.projection(0.1, 100.0, ClipSpaceConvention::Vulkan);
+    assert!(approx(vk.m[1][1], -1.0));
+
+    // Looking straight ahead lands on the right edge when the view only extends to the left
+    let asymmetric = XrFov { angle_right: 0.0, ..symmetric };
+    let projection = asymmetric.projection(0.1, 100.0, ClipSpaceConvention::OpenGl);
+    let clip = projection.mul_by_vec3(Vec3 { x: 0.0, y: 0.0, z: -5.0 });
+    assert!(approx(clip.x / clip.w, 1.0));
+    assert!(approx(clip.y / clip.w, 0.0));
+}
+
+#[test]
+fn pose_views_map_the_eye_to_the_origin_looking_down_negative_z() {
+    let pose = XrPose {
+        orientation: Quaternion::identity(),
+        position: Vec3 { x: 1.0, y: 2.0, z: 3.0 },
+    };
+    let eye = pose.view().mul_by_vec3(Vec3 { x: 1.0, y: 2.0, z: 3.0 });
+    assert!(approx(eye.x, 0.0) && approx(eye.y, 0.0) && approx(eye.z, 0.0));
+
+    // Turned to the left, a point to the left of the origin is straight ahead
+    let turned = XrPose {
+        orientation: Quaternion::axis_and_angle_to_quaternion(&Vec3 { x: 0.0, y: 1.0, z: 0.0 }, 90.0),
+        position: Vec3 { x: 0.0, y: 0.0, z: 0.0 },
+    };
+    let ahead = turned.view().mul_by_vec3(Vec3 { x: -1.0, y: 0.0, z: 0.0 });
+    assert!(approx(ahead.x, 0.0) && approx(ahead.y, 0.0) && approx(ahead.z, -1.0));
+}
+
+#[test]
+fn frames_are_located_at_their_display_time() {
+    let mut session = FakeSession {
+        running: false,
+        located_at: None,
+    };
+    assert!(begin_xr_frame(&mut session).is_none());
+    assert_eq!(session.located_at, None);
+
+    session.running = true;
+    let frame = begin_xr_frame(&mut session).unwrap();
+    assert_eq!(session.located_at, Some(1000));
+
+    // Each eye's view carries its own offset into eye space
+    let world = Mat4::newidentity();
+    let left = frame.eye_view(&world, Eye::Left).mul_by_vec3(Vec3 { x: 0.0, y: 0.0, z: 0.0 });
+    let right = frame.eye_view(&world, Eye::Right).mul_by_vec3(Vec3 { x: 0.0, y: 0.0, z: 0.0 });
+    assert!(approx(left.x, 0.03) && approx(right.x, -0.03));
+    assert_eq!(eye_index(Eye::Right), 1);
+}